@@ -0,0 +1,65 @@
+#!/usr/bin/env python3
+"""Example script for the "External Command" node.
+
+Reads frames from stdin and writes them unchanged to stdout. Replace `process`
+with your own processing step. Frames have the following layout:
+
+    magic   4 bytes   b"IVCK"
+    dtype   1 byte    0: u8, 1: u16, 2: u32, 3: u64, 4: f32, 5: f64
+    rows    u32 LE    Samples per A scan
+    cols    u32 LE    Number of A scans
+    payload           rows * cols values, one A scan after another, LE
+
+Processed frames must keep the samples per A scan (rows) of the input.
+"""
+
+import struct
+import sys
+
+MAGIC = b"IVCK"
+DTYPE_SIZES = [1, 2, 4, 8, 4, 8]
+
+
+def read_exact(stream, size):
+    data = b""
+    while len(data) < size:
+        chunk = stream.read(size - len(data))
+        if not chunk:
+            return None
+        data += chunk
+    return data
+
+
+def process(dtype, rows, cols, payload):
+    # With numpy, the chunk can be viewed as a matrix like this:
+    #   types = ["<u1", "<u2", "<u4", "<u8", "<f4", "<f8"]
+    #   scan = np.frombuffer(payload, types[dtype]).reshape(cols, rows)
+    return dtype, rows, cols, payload
+
+
+def main():
+    stdin = sys.stdin.buffer
+    stdout = sys.stdout.buffer
+
+    while True:
+        header = read_exact(stdin, 13)
+        if header is None:
+            break
+
+        magic, dtype, rows, cols = struct.unpack("<4sBII", header)
+        if magic != MAGIC:
+            sys.exit(f"Invalid frame magic {magic!r}")
+
+        payload = read_exact(stdin, rows * cols * DTYPE_SIZES[dtype])
+        if payload is None:
+            sys.exit("Frame payload ended early")
+
+        dtype, rows, cols, payload = process(dtype, rows, cols, payload)
+
+        stdout.write(struct.pack("<4sBII", MAGIC, dtype, rows, cols))
+        stdout.write(payload)
+        stdout.flush()
+
+
+if __name__ == "__main__":
+    main()
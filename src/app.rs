@@ -1,21 +1,75 @@
-use std::{borrow::Cow, mem};
+use std::{
+    borrow::Cow,
+    mem,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 use crate::{
     cache::Cache,
     gui::{
+        batch_window::BatchWindow,
+        compare_window::CompareWindow,
+        data_types_window::DataTypesWindow,
+        diagnostic_window::DiagnosticWindow,
         dock_state::{DockState, TabType},
-        node_graph::{NodeGraphEditState, NodeGraphEditor},
+        files_window::FilesWindow,
+        jobs_window::JobsWindow,
+        node_graph::{NodeAction, NodeGraphEditState, NodeGraphEditor, NodeWarning, Snapping},
+        parameter_sweep_window::ParameterSweepWindow,
+        pipeline::{
+            connection_preview,
+            memory_monitor::MemoryMonitor,
+            progress_monitor::{self, ProgressMonitor},
+            transfer_monitor::{self, TransferMonitor},
+        },
+        range_selector::RangeSelector,
+        report_window::ReportWindow,
+        runs_window::RunsWindow,
+        settings_window::SettingsWindow,
+        validation_window::ValidationWindow,
+        walkthrough::Walkthrough,
+        widgets::DragValueExt,
+    },
+    node_graph::{NodeId, NodeOutput},
+    pipeline::{
+        self,
+        diagnostic_link::{Diagnostic, OutputSummary},
+        execution::{flatten, Shutdown},
+        nodes::composite::{self, Library},
+        persistence::{self, Loaded, Loader, SaveTarget, Saved, Saver, Snapshot},
+        sessions,
+        suggestions::SuggestionRunner,
     },
-    node_graph::NodeId,
-    pipeline::{self, nodes},
+    recovery::{self, DeviceLoss, LossMonitor, Startup},
+    settings::{self, Settings},
     view::{
         execution::executor::ViewsExecutor,
+        live_tuning::LiveTuningRunner,
+        reattach::{self, Detached},
         views,
         views_manager::{DataViewsManager, DataViewsManagerBuilder},
         DataViewsState,
     },
 };
 
+/// How often live tuning previews are checked, while changed settings are
+/// deferred.
+const LIVE_TUNING_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often filter nodes check, whether their input can be sampled for
+/// suggested settings.
+const SUGGESTIONS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often the tasks of a replaced pipeline are checked, until they ended.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How often running saves and loads are checked, see [persistence].
+const PERSISTENCE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a [Status] is shown, in seconds.
+const STATUS_DURATION: f64 = 4.0;
+
 pub struct IVOCTApp {
     /// High level pipeline description.
     pipeline: pipeline::Pipeline,
@@ -23,6 +77,11 @@ pub struct IVOCTApp {
     pipeline_edit_state: NodeGraphEditState,
     /// System responsible for executing the pipeline, described py [pipeline].
     pipeline_executor: pipeline::PipelineExecutor,
+    /// Previews changed settings for views, before the [pipeline_executor]
+    /// runs them.
+    live_tuning: LiveTuningRunner,
+    /// Suggests settings for filter nodes from a sample of their input.
+    suggestions: SuggestionRunner,
 
     /// High level description of data views (Views that show data from the pipeline).
     data_views_state: DataViewsState,
@@ -43,15 +102,99 @@ pub struct IVOCTApp {
     /// The node that got double clicked by the User.
     interacted_node: Option<NodeId>,
 
+    /// Composites opened by double clicking them, each inside of the previous
+    /// one. The editor shows the innermost one instead of the pipeline.
+    composite_path: Vec<NodeId>,
+
+    /// Composites saved to reuse them, see [composite::Library].
+    library: Library,
+
     /// Whether and the pipeline to load (JSON). Set in [pipeline_menu_bar],
     /// used in [update].
     load_pipeline: Option<Cow<'static, str>>,
+    /// The file [load_pipeline] was read from.
+    load_path: Option<PathBuf>,
+    /// Pipeline replacing the current one.
+    replacement: Option<Replacement>,
+    /// The file, the pipeline was opened from or saved to last. Run sessions
+    /// are recorded next to it.
+    pipeline_path: Option<PathBuf>,
+    /// [persistence::hash] of the pipeline, when it was loaded or saved last.
+    saved_hash: u64,
+    /// Saves the pipeline off the UI thread.
+    saver: Saver,
+    /// Loads pipeline files off the UI thread.
+    loader: Loader,
+    /// Outcome of the last save or load, shown for [STATUS_DURATION].
+    status: Option<Status>,
+
+    /// Application wide settings. Changes are published to [Settings::current]
+    /// at the end of every frame.
+    settings: Settings,
+    /// Whether the settings window is open.
+    settings_open: bool,
+
+    /// Open parameter sweep of a node. Dropping it stops the sweep.
+    parameter_sweep: Option<ParameterSweepWindow>,
+
+    /// Open report window. Dropping it cancels the generation.
+    report: Option<ReportWindow>,
+
+    /// Open debug window listing the data types between nodes.
+    data_types: Option<DataTypesWindow>,
+
+    /// Open debug window listing the blocking computations of the nodes.
+    jobs: Option<JobsWindow>,
+
+    /// Open window checking the files the pipeline reads and writes.
+    files: Option<FilesWindow>,
+
+    /// Open window comparing two versions of the pipeline.
+    compare: Option<CompareWindow>,
+    diagnostic: Option<DiagnosticWindow>,
+
+    /// Open window listing run sessions. Closing it stops recording the
+    /// active session.
+    runs: Option<RunsWindow>,
+
+    /// Open window processing a directory of pullbacks. Closing it aborts
+    /// the batch.
+    batch: Option<BatchWindow>,
+    /// Part of the pullback the views, outputs and previews work on.
+    range_selector: RangeSelector,
+
+    /// Data flowing through the connections of the pipeline, shown in the
+    /// pipeline editor.
+    transfer_monitor: TransferMonitor,
+    /// Recent progress of the nodes, shown in the pipeline editor.
+    progress_monitor: ProgressMonitor,
+    /// Warnings about the memory the pipeline needs, shown in the pipeline
+    /// editor.
+    memory_monitor: MemoryMonitor,
+
+    /// Guides new users through a sample pipeline.
+    walkthrough: Walkthrough,
+    /// Settings, that were invalid when loading or editing the pipeline.
+    validation: ValidationWindow,
+
+    /// How the app was started. Safe mode has no data views.
+    startup: Startup,
+    /// When the app was started, see [Startup::next].
+    started: Instant,
+    /// Watches the GPU device, see [recovery].
+    device_loss: LossMonitor,
+    /// Why the GPU device got lost. The app only saves the pipeline and
+    /// relaunches then.
+    recovering: Option<DeviceLoss>,
 }
 
 impl IVOCTApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        // Whether and the pipeline the user had open in the last session (JSON)
-        let pipeline_json = cc.storage.unwrap().get_string("user_pipeline");
+    pub fn new(cc: &eframe::CreationContext<'_>, startup: Startup) -> Self {
+        // Whether and the pipeline the user had open in the last session (JSON).
+        // Older versions kept it in the storage of eframe
+        let pipeline_json = persistence::autosave_path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .or_else(|| cc.storage.unwrap().get_string("user_pipeline"));
 
         let pipeline_json: Cow<_> = match pipeline_json {
             Some(json) => json.into(),
@@ -59,62 +202,408 @@ impl IVOCTApp {
             None => pipeline::presets::PHANTOM_1_1_3.into(),
         };
 
-        let (pipeline, state) = Self::load_pipeline(&pipeline_json);
+        let Loaded {
+            mut pipeline,
+            state,
+            hash: saved_hash,
+        } = Self::load_pipeline(&pipeline_json);
+
+        // Corrections count as unsaved changes
+        let mut validation = ValidationWindow::new();
+        validation.loaded(&mut pipeline);
+
+        // Settings are persisted by eframe. Fall back to the ones loaded at
+        // startup. Without any, this is the first launch
+        let settings_json = cc.storage.unwrap().get_string(settings::STORAGE_KEY);
+        let first_run = settings_json.is_none();
+        let settings = settings_json
+            .and_then(|json| {
+                Settings::from_json(&json)
+                    .inspect_err(|e| eprintln!("Error loading settings: {}", e))
+                    .ok()
+            })
+            .unwrap_or_else(Settings::current);
+        Settings::set_current(settings);
+
+        let range_selector: RangeSelector = cc
+            .storage
+            .unwrap()
+            .get_string(RangeSelector::STORAGE_KEY)
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        range_selector.publish();
+
+        let render_state = cc.wgpu_render_state.as_ref().unwrap();
+        let mut data_views_manager = DataViewsManagerBuilder::new(render_state);
+        if !startup.safe_mode {
+            // Add all available views, so the DataViewsManager can create them
+            data_views_manager = data_views_manager
+                .with_view::<views::data_vector::View>()
+                // Before the M scan view, which shows diameters as overlay
+                .with_view::<views::diameter::View>()
+                .with_view::<views::m_scan::View>()
+                .with_view::<views::mesh::View>()
+                .with_view::<views::volume::View>()
+                .with_view::<views::histogram::View>();
+        }
 
         IVOCTApp {
             pipeline,
             pipeline_edit_state: state,
             pipeline_executor: pipeline::PipelineExecutor::new(),
+            live_tuning: LiveTuningRunner::default(),
+            suggestions: SuggestionRunner::default(),
             data_views_state: DataViewsState::new(),
-            data_views_manager: DataViewsManagerBuilder::new(
-                &cc.wgpu_render_state.as_ref().unwrap(),
-            )
-            // Add all available views, so the DataViewsManager can create them
-            .with_view::<views::data_vector::View>()
-            .with_view::<views::m_scan::View>()
-            .with_view::<views::mesh::View>()
-            .build(),
+            data_views_manager: data_views_manager.build(),
             data_views_executor: ViewsExecutor::new(),
             dock_state: DockState::new(),
             cache: Cache::new(),
             interacted_node: None,
+            composite_path: Vec::new(),
+            library: Library::load().unwrap_or_else(|e| {
+                eprintln!("Error loading the composite library: {}", e);
+                Library::default()
+            }),
             load_pipeline: None,
+            load_path: None,
+            replacement: None,
+            pipeline_path: None,
+            saved_hash,
+            saver: Saver::default(),
+            loader: Loader::default(),
+            status: None,
+            settings,
+            settings_open: false,
+            parameter_sweep: None,
+            report: None,
+            data_types: None,
+            jobs: None,
+            files: None,
+            compare: None,
+            diagnostic: None,
+            runs: None,
+            batch: None,
+            range_selector,
+            transfer_monitor: TransferMonitor::new(),
+            progress_monitor: ProgressMonitor::new(),
+            memory_monitor: MemoryMonitor::new(),
+            walkthrough: Walkthrough::new(first_run),
+            validation,
+            startup,
+            started: Instant::now(),
+            device_loss: LossMonitor::install(&render_state.device, &cc.egui_ctx),
+            recovering: None,
         }
     }
 
-    fn load_pipeline(pipeline_json: &str) -> (pipeline::Pipeline, NodeGraphEditState) {
-        let (mut pipeline, state) = serde_json::from_str(pipeline_json).unwrap_or_else(|e| {
+    fn load_pipeline(pipeline_json: &str) -> Loaded {
+        persistence::parse(pipeline_json).unwrap_or_else(|e| {
             eprintln!("Error loading pipeline: {}", e);
-            (pipeline::Pipeline::new(), NodeGraphEditState::new())
-        });
+            Loaded::empty()
+        })
+    }
+
+    /// Whether the pipeline changed since it was loaded or saved.
+    fn is_dirty(&self) -> bool {
+        persistence::hash(&self.pipeline, &self.pipeline_edit_state) != self.saved_hash
+    }
+
+    /// Stops everything working on the current pipeline and clears it. `load`
+    /// is loaded in [Self::update_replacement], once every task ended.
+    fn tear_down(&mut self, load: PipelineLoad) -> Replacement {
+        self.parameter_sweep = None;
+        self.report = None;
+        self.live_tuning = LiveTuningRunner::default();
+        self.suggestions = SuggestionRunner::default();
+        self.data_views_state.clear();
+        self.dock_state.close_all_views();
+
+        let shutdown = self
+            .pipeline_executor
+            .shutdown()
+            .and(self.data_views_executor.shutdown());
+
+        // Values still referenced by stopping tasks are dropped with them
+        self.cache.clear();
+
+        // Nothing is synced into the executors in the meantime
+        self.pipeline = pipeline::Pipeline::new();
+        self.pipeline_edit_state = NodeGraphEditState::new();
+
+        Replacement::ShuttingDown(load, shutdown)
+    }
+
+    /// Asks to save unsaved changes and loads the pipeline of
+    /// [Self::replacement], once the old one is torn down.
+    fn update_replacement(&mut self, ctx: &egui::Context) {
+        // User requested to load new pipeline in this frame, or a file got
+        // loaded
+        let load = match (self.load_pipeline.take(), self.loader.poll()) {
+            (Some(json), _) => Some(PipelineLoad {
+                loaded: Self::load_pipeline(&json),
+                path: self.load_path.take(),
+            }),
+            (None, Some((path, Ok(loaded)))) => Some(PipelineLoad {
+                loaded,
+                path: Some(path),
+            }),
+            (None, Some((path, Err(e)))) => {
+                self.status = Some(Status::error(
+                    ctx,
+                    format!("Error loading {}: {}", path.display(), e),
+                ));
+                None
+            }
+            (None, None) => None,
+        };
 
-        // Clear all paths that do not exist
-        for (_, node) in &mut pipeline.nodes {
-            if let Some(node) = node
-                .as_any_mut()
-                .downcast_mut::<nodes::binary_input::Node>()
-            {
-                if !node.path.exists() {
-                    node.path = "".into();
+        if let Some(load) = load {
+            self.replacement = Some(match self.replacement.take() {
+                // The current pipeline is gone already
+                Some(Replacement::ShuttingDown(_, shutdown)) => {
+                    Replacement::ShuttingDown(load, shutdown)
                 }
-            } else if let Some(node) = node.as_any_mut().downcast_mut::<nodes::output::Node>() {
-                if !node.path.exists() {
-                    node.path = "".into();
+                _ if self.is_dirty() => Replacement::Confirm(load),
+                _ => self.tear_down(load),
+            });
+        }
+
+        self.replacement = match self.replacement.take() {
+            None => None,
+            Some(Replacement::Confirm(load)) => {
+                let mut choice = None;
+                let mut saving = false;
+                egui::Window::new("Unsaved Changes")
+                    .collapsible(false)
+                    .resizable(false)
+                    .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+                    .show(ctx, |ui| {
+                        ui.label(
+                            "The pipeline has unsaved changes, which are lost when it is replaced.",
+                        );
+                        ui.horizontal(|ui| {
+                            if ui.button("Save").clicked() {
+                                saving = self.save_pipeline();
+                            }
+                            if ui.button("Discard").clicked() {
+                                choice = Some(true);
+                            }
+                            if ui.button("Cancel").clicked() {
+                                choice = Some(false);
+                            }
+                        });
+                    });
+
+                match choice {
+                    _ if saving => Some(Replacement::Saving(load)),
+                    Some(true) => Some(self.tear_down(load)),
+                    Some(false) => None,
+                    None => Some(Replacement::Confirm(load)),
+                }
+            }
+            // Asks again, when saving failed
+            Some(Replacement::Saving(load)) if !self.saver.is_busy() => match self.is_dirty() {
+                true => Some(Replacement::Confirm(load)),
+                false => Some(self.tear_down(load)),
+            },
+            Some(replacement @ Replacement::Saving(_)) => Some(replacement),
+            Some(Replacement::ShuttingDown(load, shutdown)) if shutdown.is_finished() => {
+                let Loaded {
+                    mut pipeline,
+                    state,
+                    hash,
+                } = load.loaded;
+                self.saved_hash = hash;
+                self.validation.loaded(&mut pipeline);
+                self.pipeline = pipeline;
+                self.pipeline_edit_state = state;
+                self.pipeline_path = load.path;
+                None
+            }
+            Some(replacement @ Replacement::ShuttingDown(..)) => {
+                ctx.request_repaint_after(SHUTDOWN_POLL_INTERVAL);
+                Some(replacement)
+            }
+        };
+    }
+
+    /// Asks for a file and saves the pipeline to it in the background.
+    /// Returns whether a file was chosen.
+    fn save_pipeline(&mut self) -> bool {
+        let file = native_dialog::FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .set_title("Save Pipeline")
+            .show_save_single_file();
+
+        let Ok(Some(file)) = file else {
+            return false;
+        };
+
+        self.saver.save(SaveTarget::File(file), self.snapshot());
+        true
+    }
+
+    /// Copy of the pipeline to save in the background.
+    fn snapshot(&self) -> Snapshot {
+        let snapshot = Snapshot::new(&self.pipeline, &self.pipeline_edit_state);
+        if snapshot.duration() > persistence::SNAPSHOT_BUDGET {
+            eprintln!(
+                "Taking a snapshot of the pipeline took {:?}",
+                snapshot.duration()
+            );
+        }
+        snapshot
+    }
+
+    /// Handles saves, that ended.
+    fn update_saves(&mut self, ctx: &egui::Context) {
+        while let Some(Saved { target, result }) = self.saver.poll() {
+            match (target, result) {
+                (SaveTarget::File(path), Ok(hash)) => {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    self.status = Some(Status::info(ctx, format!("Saved {}", name)));
+                    self.pipeline_path = Some(path);
+                    self.saved_hash = hash;
+                }
+                (SaveTarget::Autosave, Ok(_)) => {}
+                (_, Err(e)) => {
+                    self.status = Some(Status::error(ctx, format!("Error saving pipeline: {}", e)));
                 }
             }
         }
 
-        (pipeline, state)
+        if self.saver.is_busy() || self.loader.is_busy() {
+            ctx.request_repaint_after(PERSISTENCE_POLL_INTERVAL);
+        }
     }
 
-    fn set_pipeline(&mut self, pipeline: pipeline::Pipeline, state: NodeGraphEditState) {
-        self.pipeline = pipeline;
-        self.pipeline_edit_state = state;
+    /// Takes the app down, once the GPU device got lost, and relaunches it
+    /// after the pipeline is saved, see [recovery]. Returns whether it is
+    /// recovering.
+    fn update_recovery(&mut self, ctx: &egui::Context, frame: &eframe::Frame) -> bool {
+        if self.recovering.is_none() {
+            let Some(loss) = self.device_loss.lost() else {
+                return false;
+            };
 
-        self.pipeline_executor.clear();
-        self.data_views_state.clear();
+            let adapter = frame
+                .wgpu_render_state()
+                .map(|render_state| render_state.adapter.get_info());
+            recovery::log_diagnostics(self.startup, adapter.as_ref(), &loss.error());
 
-        self.dock_state.close_all_views();
+            // Resources of the views belong to the lost device
+            self.data_views_state.clear();
+            self.dock_state.close_all_views();
+            self.cache.clear();
+
+            self.saver.save(SaveTarget::Autosave, self.snapshot());
+            self.recovering = Some(loss);
+        }
+
+        let next = self.startup.next(self.started.elapsed());
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(ui.available_height() / 3.0);
+                match next {
+                    Some(_) => {
+                        ui.heading("GPU context lost — attempting recovery");
+                        ui.spinner();
+                    }
+                    None => {
+                        ui.heading("GPU context lost");
+                        ui.label("The pipeline was saved and is restored on the next start");
+                    }
+                }
+                if let Some(path) = recovery::diagnostics_path() {
+                    ui.weak(format!("Details are logged to {}", path.display()));
+                }
+            });
+        });
+
+        self.update_saves(ctx);
+        if self.saver.is_busy() {
+            return true;
+        }
+
+        match next.map(Startup::relaunch) {
+            Some(Ok(())) => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+            Some(Err(e)) => {
+                eprintln!("Error relaunching the app: {}", e);
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+            None => {}
+        }
+
+        true
+    }
+}
+
+/// Pipeline to load, see [IVOCTApp::load_pipeline].
+struct PipelineLoad {
+    loaded: Loaded,
+    /// The file it was read from.
+    path: Option<PathBuf>,
+}
+
+/// Steps of replacing the pipeline, see [IVOCTApp::update_replacement].
+enum Replacement {
+    /// Asking whether to save unsaved changes first.
+    Confirm(PipelineLoad),
+    /// Waiting for the pipeline to be saved.
+    Saving(PipelineLoad),
+    /// Waiting for the tasks of the old pipeline to end.
+    ShuttingDown(PipelineLoad, Shutdown),
+}
+
+/// Outcome of saving or loading the pipeline, shown at the bottom of the
+/// window.
+struct Status {
+    message: String,
+    error: bool,
+    /// When it happened.
+    time: f64,
+}
+
+impl Status {
+    fn info(ctx: &egui::Context, message: String) -> Self {
+        Self {
+            message,
+            error: false,
+            time: ctx.input(|i| i.time),
+        }
+    }
+
+    fn error(ctx: &egui::Context, message: String) -> Self {
+        eprintln!("{}", message);
+        Self {
+            error: true,
+            ..Self::info(ctx, message)
+        }
+    }
+
+    /// Returns false, once it was shown for [STATUS_DURATION].
+    fn show(&self, ctx: &egui::Context) -> bool {
+        let remaining = STATUS_DURATION - (ctx.input(|i| i.time) - self.time);
+        if remaining <= 0.0 {
+            return false;
+        }
+
+        egui::Area::new(egui::Id::new("status"))
+            .order(egui::Order::Foreground)
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -64.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    match self.error {
+                        true => ui.colored_label(ui.visuals().error_fg_color, &self.message),
+                        false => ui.label(&self.message),
+                    };
+                });
+            });
+
+        ctx.request_repaint_after(Duration::from_secs_f64(remaining));
+        true
     }
 }
 
@@ -122,11 +611,31 @@ impl IVOCTApp {
 
 impl eframe::App for IVOCTApp {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
-        self.interacted_node = None;
+        if self.update_recovery(ctx, frame) {
+            return;
+        }
+
+        // The walkthrough opens a view, once its pipeline got loaded
+        self.interacted_node = match self.replacement {
+            None => self.walkthrough.take_view_request(),
+            Some(_) => None,
+        };
 
         // Satisfy Borrow Checker: Move dock_state onto the current stack frame
         let mut dock_state = mem::replace(&mut self.dock_state, DockState::new());
 
+        if self.startup.safe_mode {
+            egui::TopBottomPanel::top("safe_mode").show(ctx, |ui| {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    "Safe mode: The GPU failed repeatedly, data views are disabled. The \
+                     pipeline can still be edited, run and saved.",
+                );
+            });
+        }
+
+        egui::TopBottomPanel::bottom("range_selector").show(ctx, |ui| self.range_selector.ui(ui));
+
         // Render all tabs
         egui_dock::DockArea::new(&mut dock_state)
             .style(egui_dock::Style::from_egui(ctx.style().as_ref()))
@@ -147,19 +656,146 @@ impl eframe::App for IVOCTApp {
             ctx.input(|i| i.modifiers.ctrl),
         );
 
+        // Views can ask for views, that are not opened from a node
+        if let Some((m_scan, b_scans)) = views::volume::take_open_request(ctx) {
+            if let Some(view) = views::volume::View::new(
+                m_scan,
+                b_scans,
+                &self.cache,
+                frame.wgpu_render_state().unwrap(),
+            ) {
+                self.data_views_manager.open_view(
+                    &mut self.data_views_state,
+                    &mut self.dock_state,
+                    Box::new(view),
+                );
+            }
+        }
+
+        if let Some(m_scan) = views::histogram::take_open_request(ctx) {
+            self.data_views_manager.open_view(
+                &mut self.data_views_state,
+                &mut self.dock_state,
+                Box::new(views::histogram::View::new(m_scan)),
+            );
+        }
+
+        // Thresholds picked on a histogram are applied like any other edit
+        for edit in views::histogram::take_edit_requests(ctx) {
+            pipeline::thresholds::apply(&mut self.pipeline, edit);
+        }
+
+        // Parameter sweeps might apply a value to their node
+        if let Some(window) = &mut self.parameter_sweep {
+            if !window.show(ctx, &mut self.pipeline, &self.pipeline_executor) {
+                self.parameter_sweep = None;
+            }
+        }
+
+        if let Some(window) = &mut self.report {
+            if !window.show(ctx, &self.pipeline, &self.pipeline_executor) {
+                self.report = None;
+            }
+        }
+
+        if let Some(window) = &mut self.data_types {
+            if !window.show(ctx) {
+                self.data_types = None;
+            }
+        }
+
+        if let Some(window) = &mut self.jobs {
+            if !window.show(ctx) {
+                self.jobs = None;
+            }
+        }
+
+        if let Some(window) = &mut self.files {
+            if !window.show(ctx, &self.pipeline, &mut self.pipeline_edit_state) {
+                self.files = None;
+            }
+        }
+
+        if let Some(window) = &mut self.compare {
+            if !window.show(ctx, &self.pipeline, self.pipeline_path.as_deref()) {
+                self.compare = None;
+            }
+        }
+
+        if let Some(window) = &mut self.diagnostic {
+            if !window.show(
+                ctx,
+                &mut self.pipeline,
+                &mut self.pipeline_executor,
+                &mut self.pipeline_edit_state,
+            ) {
+                self.diagnostic = None;
+            }
+        }
+
+        if let Some(window) = &mut self.runs {
+            if !window.show(
+                ctx,
+                &self.pipeline,
+                &self.pipeline_executor,
+                &mut self.load_pipeline,
+            ) {
+                self.runs = None;
+            }
+            // Runs are recorded next to the current file
+            if self.load_pipeline.is_some() {
+                self.load_path = self.pipeline_path.clone();
+            }
+        }
+
+        if let Some(window) = &mut self.batch {
+            if !window.show(ctx, &self.pipeline) {
+                self.batch = None;
+            }
+        }
+
+        if let Some(json) = self.walkthrough.show(ctx) {
+            self.load_pipeline = Some(json.into());
+        }
+
+        self.validation.show(ctx);
+
+        // Edits can make settings invalid. They are corrected, before they
+        // reach the execution system
+        self.validation.validate(&mut self.pipeline);
+
         // Merge differences between high level pipeline description and
-        // execution system
-        self.pipeline_executor.update(&mut self.pipeline);
+        // execution system. Changes previewed by live tuning views are
+        // deferred
+        let deferring = self.live_tuning.update(
+            &mut self.pipeline_executor,
+            &mut self.pipeline,
+            self.data_views_state.live_tuning(),
+        );
+        if deferring {
+            ctx.request_repaint_after(LIVE_TUNING_POLL_INTERVAL);
+        }
+
+        // Filter nodes sample inputs, that got data after they connected
+        if self
+            .suggestions
+            .update(&mut self.pipeline, &self.pipeline_executor)
+        {
+            ctx.request_repaint_after(SUGGESTIONS_POLL_INTERVAL);
+        }
 
         // Same for data views. They might connect into the pipeline_executor
         self.data_views_executor
             .update(&mut self.data_views_state, &self.pipeline_executor);
 
-        // User requested to load new pipeline in this frame
-        if let Some(json) = self.load_pipeline.take() {
-            let (pipeline, state) = Self::load_pipeline(&json);
-            self.set_pipeline(pipeline, state);
+        self.update_saves(ctx);
+        self.update_replacement(ctx);
+
+        if self.status.as_ref().is_some_and(|status| !status.show(ctx)) {
+            self.status = None;
         }
+
+        self.update_settings(ctx);
     }
 
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
@@ -167,9 +803,27 @@ impl eframe::App for IVOCTApp {
 
         println!("Saving");
 
-        let pipeline = serde_json::to_string(&(&self.pipeline, &self.pipeline_edit_state)).unwrap();
+        // Written in the background, but waited for on exit
+        self.saver.save(SaveTarget::Autosave, self.snapshot());
 
-        storage.set_string("user_pipeline", pipeline)
+        storage.set_string(settings::STORAGE_KEY, self.settings.to_json());
+        storage.set_string(
+            RangeSelector::STORAGE_KEY,
+            serde_json::to_string(&self.range_selector).unwrap(),
+        );
+        self.settings.save_startup_file();
+    }
+
+    fn auto_save_interval(&self) -> std::time::Duration {
+        self.settings.general.autosave_interval()
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        for saved in self.saver.wait() {
+            if let Err(e) = saved.result {
+                eprintln!("Error saving pipeline: {}", e);
+            }
+        }
     }
 }
 
@@ -222,18 +876,170 @@ impl egui_dock::TabViewer for IVOCTApp {
             TabType::Pipeline => {
                 self.pipeline_menu_bar(ui);
 
+                if self
+                    .transfer_monitor
+                    .update(&self.pipeline_executor, &self.pipeline)
+                {
+                    ui.ctx()
+                        .request_repaint_after(transfer_monitor::POLL_INTERVAL);
+                }
+
+                if self
+                    .progress_monitor
+                    .update(&mut self.pipeline, self.settings.display.stall_threshold())
+                {
+                    ui.ctx()
+                        .request_repaint_after(progress_monitor::SAMPLE_INTERVAL);
+                }
+
+                self.memory_monitor.update(&self.pipeline);
+                if let Some(banner) = self.memory_monitor.banner() {
+                    memory_banner(ui, banner);
+                }
+
+                // The composite got deleted or the pipeline replaced
+                if composite::at_path_mut(&mut self.pipeline, &self.composite_path).is_none() {
+                    self.composite_path.clear();
+                }
+                if !self.composite_path.is_empty() {
+                    self.composite_ui(ui);
+                    return;
+                }
+
+                // Nodes, that make a deterministic pipeline differ between runs
+                let mut warnings = self.memory_monitor.warnings().clone();
+                if self.pipeline.deterministic {
+                    for (node_id, reason) in self.pipeline.nondeterministic_nodes() {
+                        warnings.entry(node_id).or_default().push(NodeWarning {
+                            label: "Not Reproducible",
+                            description: reason.to_string(),
+                        });
+                    }
+                }
+                self.validation.warnings(&mut warnings);
+
+                let display = self.settings.display;
+                let executor = &self.pipeline_executor;
                 let _response =
                     NodeGraphEditor::new(&mut self.pipeline, &mut self.pipeline_edit_state)
+                        .scale(display.graph_scale)
+                        .activity(self.transfer_monitor.activity())
+                        .badges(self.transfer_monitor.badges())
+                        .progress(self.progress_monitor.progress())
+                        .warnings(&warnings)
+                        .preview(&|ui, node_id, input_id| {
+                            connection_preview::preview_ui(ui, executor.peek(node_id, input_id))
+                        })
+                        .snapping(Snapping {
+                            grid: display.snap_to_grid.then_some(display.grid_size),
+                            guides: display.alignment_guides,
+                        })
                         .show(ui);
 
-                // User double clicked a node
+                self.walkthrough.step_ui(
+                    ui.ctx(),
+                    &_response.node_rects,
+                    &mut self.pipeline_edit_state,
+                );
+
+                if let Some(window) = &self.compare {
+                    window.outline(ui.ctx(), &_response.node_rects);
+                }
+                if let Some(window) = &self.diagnostic {
+                    window.outline(ui.ctx(), &self.pipeline, &_response.node_rects);
+                }
+
+                // User double clicked a node. Composites are opened instead
                 if let Some(interacted_node) = _response.activated {
-                    self.interacted_node = Some(interacted_node);
+                    if self.pipeline[interacted_node]
+                        .as_any()
+                        .is::<composite::Node>()
+                    {
+                        self.composite_path.push(interacted_node);
+                    } else {
+                        self.interacted_node = Some(interacted_node);
+                    }
+                }
+
+                if let Some(restarted_node) = _response.restarted {
+                    self.pipeline_executor
+                        .recreate_node(restarted_node, &mut self.pipeline);
+                }
+
+                if let Some(cancelled_node) = _response.cancelled {
+                    self.pipeline_executor.cancel_node(cancelled_node);
+                }
+
+                match _response.action {
+                    Some((node_id, NodeAction::ParameterSweep)) => {
+                        self.parameter_sweep = ParameterSweepWindow::new(node_id, &self.pipeline);
+                    }
+                    Some((node_id, NodeAction::CopyDiagnosticLink)) => {
+                        let outputs = self
+                            .pipeline_executor
+                            .output_stats(node_id)
+                            .iter()
+                            .map(|stats| {
+                                let peek = self
+                                    .pipeline_executor
+                                    .get_output(node_id, stats.output_id)
+                                    .and_then(|output| output.peek());
+                                OutputSummary::new(stats, peek.as_deref())
+                            })
+                            .collect();
+                        if let Some(diagnostic) = Diagnostic::new(&self.pipeline, node_id, outputs)
+                        {
+                            ui.ctx().copy_text(diagnostic.encode());
+                        }
+                    }
+                    Some((node_id, action)) => self.library_action(ui.ctx(), node_id, action),
+                    None => {}
                 }
             }
             TabType::DataView(view_id) => {
-                if let Some(view) = self.data_views_state.get_mut(*view_id) {
-                    view.ui(ui);
+                let link = self.data_views_state.link().clone();
+                let live_tuning = self.data_views_state.live_tuning().clone();
+                let detached = self.data_views_state.detached(*view_id, &self.pipeline);
+
+                if let Some(failure) = self.data_views_state.failure(*view_id) {
+                    if failure_card(ui, failure) {
+                        self.data_views_state.retry(*view_id);
+                    }
+                } else if let Some(view) = self.data_views_state.get_mut(*view_id) {
+                    let action = detached.iter().find_map(|detached| {
+                        detached_card(ui, detached, &self.pipeline)
+                            .map(|action| (detached.input_id, action))
+                    });
+
+                    if let Some(node_id) = view.inputs().iter().find_map(|(_, output)| {
+                        output.and_then(|o| self.pipeline.disabled_upstream(o.node_id))
+                    }) {
+                        disabled_upstream_label(ui, self.pipeline[node_id].name());
+                    }
+
+                    let rect = ui.available_rect_before_wrap();
+                    let response = view.ui(ui, &self.pipeline, &link, &live_tuning);
+                    if response.retry_failed {
+                        self.pipeline_executor.retry_failed();
+                    }
+
+                    // Dim the last data of detached views
+                    if !detached.is_empty() {
+                        ui.painter()
+                            .rect_filled(rect, 0.0, egui::Color32::from_black_alpha(120));
+                    }
+
+                    match action {
+                        Some((_, DetachedAction::Reattach(output))) => {
+                            self.data_views_state
+                                .reattach(*view_id, output, &self.pipeline);
+                        }
+                        Some((input_id, DetachedAction::Disconnect)) => {
+                            self.data_views_state
+                                .disconnect_detached(*view_id, input_id);
+                        }
+                        None => {}
+                    }
                 } else {
                     ui.label(format!(
                         "Data View {:?} does not exist, You can close this tab.",
@@ -245,9 +1051,323 @@ impl egui_dock::TabViewer for IVOCTApp {
     }
 }
 
+/// The pipeline and layout of the innermost composite of `path`, or the ones
+/// of the pipeline itself.
+fn current_level_mut<'a>(
+    pipeline: &'a mut pipeline::Pipeline,
+    state: &'a mut NodeGraphEditState,
+    path: &[NodeId],
+) -> Option<(&'a mut pipeline::Pipeline, &'a mut NodeGraphEditState)> {
+    if path.is_empty() {
+        return Some((pipeline, state));
+    }
+
+    composite::at_path_mut(pipeline, path)
+        .map(|composite| (&mut composite.pipeline, &mut composite.state))
+}
+
+/// Shows the error of a failed data view. Returns true, when the user
+/// requested to retry.
+fn failure_card(ui: &mut egui::Ui, failure: &str) -> bool {
+    egui::Frame::popup(ui.style())
+        .fill(ui.visuals().error_fg_color.gamma_multiply(0.3))
+        .show(ui, |ui| {
+            ui.colored_label(ui.visuals().error_fg_color, "This view failed:");
+            ui.label(failure);
+            ui.button("Retry")
+                .on_hover_text("Recreate the task of this view")
+                .clicked()
+        })
+        .inner
+}
+
+/// Tells that a data view cannot show anything new, because a node it depends
+/// on is disabled.
+fn disabled_upstream_label(ui: &mut egui::Ui, node_name: &str) {
+    egui::Frame::popup(ui.style())
+        .fill(ui.visuals().warn_fg_color.gamma_multiply(0.3))
+        .show(ui, |ui| {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!("Upstream disabled: The node \"{node_name}\" is disabled"),
+            )
+            .on_hover_text("Enable it from its context menu in the pipeline editor");
+        });
+}
+
+/// Warns that running the pipeline would need more memory than available.
+fn memory_banner(ui: &mut egui::Ui, text: &str) {
+    egui::Frame::popup(ui.style())
+        .fill(ui.visuals().warn_fg_color.gamma_multiply(0.3))
+        .show(ui, |ui| {
+            ui.colored_label(ui.visuals().warn_fg_color, text)
+                .on_hover_text(
+                    "A rough estimate. Nodes needing the most are marked, consider smaller \
+                     data types, a shorter range or a smaller stream capacity",
+                );
+        });
+}
+
+enum DetachedAction {
+    Reattach(NodeOutput),
+    Disconnect,
+}
+
+/// Tells that the node of a data view got removed. The view keeps showing its
+/// last data, until it is reattached, either to the node added again or to an
+/// output picked by the user.
+fn detached_card(
+    ui: &mut egui::Ui,
+    detached: &Detached,
+    pipeline: &pipeline::Pipeline,
+) -> Option<DetachedAction> {
+    let name = detached
+        .source
+        .as_ref()
+        .map_or("unknown", |source| source.name.as_str());
+
+    egui::Frame::popup(ui.style())
+        .fill(ui.visuals().warn_fg_color.gamma_multiply(0.3))
+        .show(ui, |ui| {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!("Detached: The node \"{name}\" was removed"),
+            )
+            .on_hover_text(
+                "Showing the last data. The view reattaches on its own, when a node with the \
+                 same settings is added again",
+            );
+
+            ui.horizontal(|ui| {
+                let mut action = None;
+
+                ui.menu_button("Reattach to…", |ui| {
+                    let outputs = reattach::compatible_outputs(pipeline, detached.output.type_id);
+                    if outputs.is_empty() {
+                        ui.label("No compatible outputs");
+                    }
+                    for output in outputs {
+                        let label = format!(
+                            "{} ({})",
+                            pipeline[output.node_id].name(),
+                            Into::<usize>::into(output.node_id)
+                        );
+                        if ui.button(label).clicked() {
+                            action = Some(DetachedAction::Reattach(output));
+                            ui.close_menu();
+                        }
+                    }
+                });
+
+                if ui
+                    .button("Disconnect")
+                    .on_hover_text("Stop waiting for the node. Closes views without other inputs")
+                    .clicked()
+                {
+                    action = Some(DetachedAction::Disconnect);
+                }
+
+                action
+            })
+            .inner
+        })
+        .inner
+}
+
+// MARK: Settings
+
+impl IVOCTApp {
+    fn update_settings(&mut self, ctx: &egui::Context) {
+        SettingsWindow::new(&mut self.settings, &mut self.settings_open).show(ctx);
+
+        let previous = Settings::current();
+        if previous == self.settings {
+            return;
+        }
+
+        if previous.display.dark_mode != self.settings.display.dark_mode {
+            ctx.set_visuals(if self.settings.display.dark_mode {
+                egui::Visuals::dark()
+            } else {
+                egui::Visuals::light()
+            });
+        }
+
+        // Subsystems read the current settings when they need them
+        Settings::set_current(self.settings);
+    }
+}
+
 // MARK: Pipeline Menu Bar
 
 impl IVOCTApp {
+    /// Starts a run session in the runs window, see [sessions].
+    fn save_all_outputs(&mut self, ctx: &egui::Context) {
+        let runs_file = sessions::runs_file_path(self.pipeline_path.as_deref());
+        let window = match &mut self.runs {
+            Some(window) => {
+                window.set_runs_file(runs_file, ctx);
+                window
+            }
+            None => self.runs.insert(RunsWindow::new(runs_file, ctx)),
+        };
+
+        let snapshot = serde_json::to_value((&self.pipeline, &self.pipeline_edit_state)).unwrap();
+        window.start(&mut self.pipeline, &self.pipeline_executor, snapshot);
+    }
+
+    /// Breadcrumbs of [Self::composite_path] and the editor of the innermost
+    /// composite.
+    fn composite_ui(&mut self, ui: &mut egui::Ui) {
+        let names = (1..=self.composite_path.len())
+            .filter_map(|len| {
+                composite::at_path_mut(&mut self.pipeline, &self.composite_path[..len])
+                    .map(|composite| composite.name.clone())
+            })
+            .collect::<Vec<_>>();
+
+        // Clicking a breadcrumb closes the composites inside of it
+        let mut close = None;
+        ui.horizontal(|ui| {
+            if ui.link("Pipeline").clicked() {
+                close = Some(0);
+            }
+            for (i, name) in names.iter().enumerate() {
+                ui.label("›");
+                if i + 1 == names.len() {
+                    ui.strong(name);
+                } else if ui.link(name).clicked() {
+                    close = Some(i + 1);
+                }
+            }
+        });
+        if let Some(len) = close {
+            self.composite_path.truncate(len);
+            return;
+        }
+
+        let display = self.settings.display;
+        let Some(composite) = composite::at_path_mut(&mut self.pipeline, &self.composite_path)
+        else {
+            return;
+        };
+        let response = NodeGraphEditor::new(&mut composite.pipeline, &mut composite.state)
+            .scale(display.graph_scale)
+            .snapping(Snapping {
+                grid: display.snap_to_grid.then_some(display.grid_size),
+                guides: display.alignment_guides,
+            })
+            .show(ui);
+
+        if let Some(node_id) = response.activated {
+            if composite.pipeline[node_id].as_any().is::<composite::Node>() {
+                self.composite_path.push(node_id);
+            }
+        }
+
+        // The executor knows nodes inside of composites by their flattened id
+        let flat_id = |node_id| flatten::flat_id(&self.composite_path, node_id);
+        if let Some(node_id) = response.restarted.and_then(flat_id) {
+            self.pipeline_executor
+                .recreate_node(node_id, &mut self.pipeline);
+        }
+        if let Some(node_id) = response.cancelled.and_then(flat_id) {
+            self.pipeline_executor.cancel_node(node_id);
+        }
+
+        if let Some((node_id, action)) = response.action {
+            self.library_action(ui.ctx(), node_id, action);
+        }
+
+        // Groups collapsed inside of a composite can exceed the nesting limit
+        let depth = composite::at_path_mut(&mut self.pipeline, &self.composite_path[..1])
+            .map_or(0, |composite| composite.depth());
+        if depth > flatten::MAX_DEPTH {
+            self.status = Some(Status::error(
+                ui.ctx(),
+                "Groups can only be nested three levels deep, the innermost nodes do not run"
+                    .to_owned(),
+            ));
+        }
+    }
+
+    /// Handles the library actions of a composite, that is shown in the
+    /// editor right now.
+    fn library_action(&mut self, ctx: &egui::Context, node_id: NodeId, action: NodeAction) {
+        let Some((pipeline, _)) = current_level_mut(
+            &mut self.pipeline,
+            &mut self.pipeline_edit_state,
+            &self.composite_path,
+        ) else {
+            return;
+        };
+        let Some(composite) = pipeline
+            .nodes
+            .get_mut(&node_id)
+            .and_then(|node| node.as_any_mut().downcast_mut::<composite::Node>())
+        else {
+            return;
+        };
+
+        let status = match action {
+            NodeAction::SaveToLibrary => {
+                self.library.insert(composite);
+                composite.library = Some(composite.name.clone());
+                match self.library.save() {
+                    Ok(()) => {
+                        Status::info(ctx, format!("Saved \"{}\" to the library", composite.name))
+                    }
+                    Err(e) => Status::error(ctx, format!("Error saving the library: {}", e)),
+                }
+            }
+            NodeAction::UpdateFromLibrary => {
+                let name = composite.library.clone().unwrap_or_default();
+                match self.library.get(&name) {
+                    Some(entry) => {
+                        composite.update_from(entry);
+                        Status::info(ctx, format!("Updated from \"{}\"", name))
+                    }
+                    None => Status::error(ctx, format!("\"{}\" is not in the library", name)),
+                }
+            }
+            _ => return,
+        };
+        self.status = Some(status);
+    }
+
+    /// Adds a copy of the library entry `name` to the pipeline shown in the
+    /// editor right now.
+    fn insert_from_library(&mut self, ctx: &egui::Context, name: &str) {
+        let Some(instance) = self.library.instantiate(name) else {
+            return;
+        };
+        if self.composite_path.len() + instance.depth() > flatten::MAX_DEPTH {
+            self.status = Some(Status::error(
+                ctx,
+                "Groups can only be nested three levels deep".to_owned(),
+            ));
+            return;
+        }
+
+        let Some((pipeline, state)) = current_level_mut(
+            &mut self.pipeline,
+            &mut self.pipeline_edit_state,
+            &self.composite_path,
+        ) else {
+            return;
+        };
+        let id: usize = pipeline
+            .nodes
+            .keys()
+            .copied()
+            .max()
+            .unwrap_or(0.into())
+            .into();
+        let id = NodeId::from(id + 1);
+        pipeline.nodes.insert(id, Box::new(instance));
+        state.focus(id);
+    }
+
     fn pipeline_menu_bar(&mut self, ui: &mut egui::Ui) {
         egui::menu::bar(ui, |ui| {
             ui.menu_button("File", |ui| {
@@ -257,11 +1377,9 @@ impl IVOCTApp {
                         .set_title("Open Pipeline")
                         .show_open_single_file();
 
+                    // Read and parsed in the background, see update_replacement
                     if let Ok(Some(file)) = file {
-                        match std::fs::read_to_string(file) {
-                            Ok(json) => self.load_pipeline = Some(json.into()),
-                            Err(e) => eprintln!("Error loading pipeline: {}", e),
-                        }
+                        self.loader.load(file);
                     }
 
                     ui.close_menu();
@@ -280,25 +1398,204 @@ impl IVOCTApp {
                         self.load_pipeline = Some(pipeline::presets::CLINIC.into());
                         ui.close_menu();
                     }
+                    ui.separator();
+                    if ui.button("Catheter Mask Template").clicked() {
+                        self.load_pipeline = Some(pipeline::presets::CATHETER_MASK_TEMPLATE.into());
+                        ui.close_menu();
+                    }
                 });
 
                 if ui.button("Save").clicked() {
-                    let file = native_dialog::FileDialog::new()
-                        .add_filter("JSON", &["json"])
-                        .set_title("Save Pipeline")
-                        .show_save_single_file();
+                    self.save_pipeline();
+                    ui.close_menu();
+                }
 
-                    if let Ok(Some(file)) = file {
-                        let serialized = serde_json::to_string_pretty(&(
-                            &self.pipeline,
-                            &self.pipeline_edit_state,
-                        ))
-                        .unwrap();
-                        if let Err(e) = std::fs::write(file, serialized) {
-                            eprintln!("Error saving pipeline: {}", e);
+                if ui
+                    .button("Save All Outputs")
+                    .on_hover_text("Save every output node and record the run")
+                    .clicked()
+                {
+                    self.save_all_outputs(ui.ctx());
+                    ui.close_menu();
+                }
+
+                if ui
+                    .button("Runs…")
+                    .on_hover_text("Past runs, recorded next to the pipeline file")
+                    .clicked()
+                {
+                    let runs_file = sessions::runs_file_path(self.pipeline_path.as_deref());
+                    match &mut self.runs {
+                        Some(window) => window.set_runs_file(runs_file, ui.ctx()),
+                        None => self.runs = Some(RunsWindow::new(runs_file, ui.ctx())),
+                    }
+                    ui.close_menu();
+                }
+
+                if ui
+                    .button("Batch…")
+                    .on_hover_text("Process a directory of pullbacks with this pipeline")
+                    .clicked()
+                {
+                    self.batch.get_or_insert_with(BatchWindow::new);
+                    ui.close_menu();
+                }
+
+                if ui.button("Generate Report…").clicked() {
+                    self.report.get_or_insert_with(ReportWindow::new);
+                    ui.close_menu();
+                }
+
+                if ui
+                    .button("Files…")
+                    .on_hover_text("Check the files the pipeline reads and writes")
+                    .clicked()
+                {
+                    self.files.get_or_insert_with(FilesWindow::new);
+                    ui.close_menu();
+                }
+
+                if ui
+                    .button("Compare Pipelines…")
+                    .on_hover_text("Show what changed between two versions of a pipeline")
+                    .clicked()
+                {
+                    self.compare.get_or_insert_with(CompareWindow::new);
+                    ui.close_menu();
+                }
+
+                if ui
+                    .button("Open Diagnostic…")
+                    .on_hover_text("Show the contents of a diagnostic link from a bug report")
+                    .clicked()
+                {
+                    self.diagnostic.get_or_insert_with(DiagnosticWindow::new);
+                    ui.close_menu();
+                }
+
+                ui.separator();
+
+                if ui.button("Settings").clicked() {
+                    self.settings_open = true;
+                    ui.close_menu();
+                }
+
+                if ui
+                    .button("Data Types…")
+                    .on_hover_text("Which data flows between which nodes")
+                    .clicked()
+                {
+                    self.data_types.get_or_insert_with(DataTypesWindow::new);
+                    ui.close_menu();
+                }
+
+                if ui
+                    .button("Jobs…")
+                    .on_hover_text("Which computations run on which thread pool")
+                    .clicked()
+                {
+                    self.jobs.get_or_insert_with(JobsWindow::new);
+                    ui.close_menu();
+                }
+
+                if cfg!(debug_assertions)
+                    && ui
+                        .button("Simulate GPU Loss")
+                        .on_hover_text("Destroy the GPU device, to test the recovery")
+                        .clicked()
+                {
+                    self.device_loss.simulate_loss();
+                    ui.close_menu();
+                }
+            });
+
+            ui.menu_button("View", |ui| {
+                let display = &mut self.settings.display;
+
+                ui.checkbox(&mut display.snap_to_grid, "Snap to Grid")
+                    .on_hover_text("Hold Alt while dragging to place a node freely");
+                ui.add_enabled_ui(display.snap_to_grid, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Grid Size:");
+                        ui.add(
+                            egui::DragValue::new(&mut display.grid_size)
+                                .range(2.0..=100.0)
+                                .speed(0.5)
+                                .localized(),
+                        );
+                    });
+                });
+                ui.checkbox(&mut display.alignment_guides, "Alignment Guides")
+                    .on_hover_text("Align the edges of a dragged node with nearby nodes");
+            });
+
+            ui.menu_button("Pipeline", |ui| {
+                ui.checkbox(&mut self.pipeline.deterministic, "Deterministic Mode")
+                    .on_hover_text(
+                        "Give bit-identical results on every run of the same data, at the cost \
+                         of a few percent of speed. Nodes, that still differ between runs, \
+                         are marked",
+                    );
+
+                if ui
+                    .add_enabled(
+                        !self.validation.is_empty(),
+                        egui::Button::new("Validation Report…"),
+                    )
+                    .on_hover_text("Settings, that were invalid and got corrected")
+                    .clicked()
+                {
+                    self.validation.open();
+                    ui.close_menu();
+                }
+
+                ui.separator();
+
+                let mut insert = None;
+                let mut remove = None;
+                ui.menu_button("Insert from Library", |ui| {
+                    if self.library.names().next().is_none() {
+                        ui.weak("Save a group from its context menu to reuse it");
+                    }
+
+                    for name in self.library.names() {
+                        let response = ui
+                            .button(name)
+                            .on_hover_text("Right click to remove it from the library");
+                        if response.clicked() {
+                            insert = Some(name.to_owned());
+                            ui.close_menu();
                         }
+                        response.context_menu(|ui| {
+                            if ui.button("Remove from Library").clicked() {
+                                remove = Some(name.to_owned());
+                                ui.close_menu();
+                            }
+                        });
+                    }
+                });
+
+                if let Some(name) = insert {
+                    self.insert_from_library(ui.ctx(), &name);
+                }
+                if let Some(name) = remove {
+                    self.library.remove(&name);
+                    if let Err(e) = self.library.save() {
+                        self.status = Some(Status::error(
+                            ui.ctx(),
+                            format!("Error saving the library: {}", e),
+                        ));
                     }
+                }
+            });
 
+            ui.menu_button("Help", |ui| {
+                if ui
+                    .button("Walkthrough")
+                    .on_hover_text("Process a sample pullback step by step")
+                    .clicked()
+                {
+                    self.walkthrough.restart();
                     ui.close_menu();
                 }
             });
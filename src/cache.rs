@@ -3,7 +3,7 @@ use std::{
     any::{Any, TypeId},
     collections::HashMap,
     hash::Hash,
-    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak},
+    sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak},
 };
 
 /// Key-value store for caching purposes.
@@ -14,6 +14,20 @@ use std::{
 pub struct Cache(Arc<_Shared>);
 
 /// Reference to a cached value of type T inside a [Cache].
+///
+/// # Locking
+///
+/// Values are guarded by a synchronous [RwLock], that is shared by everyone
+/// referencing the same value, including the UI thread, which reads them
+/// every frame. Therefore:
+///
+/// - Never hold a guard across an `.await`.
+/// - Keep write sections short. Expensive work, like uploads to the GPU, is
+///   done before taking the lock, and the result is swapped in.
+/// - Values that are large or read while being written should be an [Arc]
+///   to an immutable state. Readers [Cached::load] the [Arc] and writers
+///   publish a new one, so neither waits for the other.
+/// - When holding other locks as well, take those first.
 pub struct Cached<T> {
     cache: Arc<_Shared>,
     entry: _CacheEntry,
@@ -27,13 +41,43 @@ pub struct CacheKey(usize);
 struct _CacheEntry(Arc<dyn Any + Sync + Send>);
 struct _WeakCacheEntry(Weak<dyn Any + Sync + Send>);
 
-struct _Shared(RwLock<HashMap<(CacheKey, TypeId), _WeakCacheEntry>>);
+struct _Shared {
+    entries: RwLock<HashMap<(CacheKey, TypeId), _WeakCacheEntry>>,
+    /// Entries removed by [Cache::clear], that were still referenced.
+    cleared: Mutex<Vec<_WeakCacheEntry>>,
+}
 
 // MARK: Cache
 
 impl Cache {
     pub fn new() -> Self {
-        Self(Arc::new(_Shared(RwLock::new(HashMap::new()))))
+        Self(Arc::new(_Shared {
+            entries: RwLock::new(HashMap::new()),
+            cleared: Mutex::new(Vec::new()),
+        }))
+    }
+
+    /// Removes every entry. Values still referenced stay alive until their
+    /// last [Cached] is dropped, but are not handed out again.
+    pub fn clear(&self) {
+        let entries = std::mem::take(&mut *self.0.entries.write().unwrap());
+
+        let mut cleared = self.0.cleared.lock().unwrap();
+        cleared.retain(|entry| entry.is_alive());
+        cleared.extend(entries.into_values().filter(|entry| entry.is_alive()));
+    }
+
+    /// Number of values alive, including cleared ones, that are still
+    /// referenced.
+    #[cfg(test)]
+    pub fn live_entries(&self) -> usize {
+        let entries = self.0.entries.read().unwrap();
+        let cleared = self.0.cleared.lock().unwrap();
+        entries
+            .values()
+            .chain(cleared.iter())
+            .filter(|entry| entry.is_alive())
+            .count()
     }
 
     /// Get an existing value or create a new one.
@@ -58,7 +102,7 @@ impl Cache {
 
 impl fmt::Debug for Cache {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let caches = self.0 .0.read().unwrap();
+        let caches = self.0.entries.read().unwrap();
         f.debug_struct("Cache")
             .field(
                 "Strong count",
@@ -100,6 +144,21 @@ impl<T: Send + Sync + 'static> Cached<T> {
             .unwrap()
     }
 
+    /// Clone of the cached value. The lock is only held while cloning, which
+    /// is cheap for values behind an [Arc].
+    pub fn load(&self) -> T
+    where
+        T: Clone,
+    {
+        self.read().clone()
+    }
+
+    /// Replaces the cached value. The old value is returned, so it is dropped
+    /// after the lock got released.
+    pub fn replace(&self, value: T) -> T {
+        std::mem::replace(&mut *self.write(), value)
+    }
+
     /// Changes the value being referenced, possibly deleting the old value and
     /// possibly creating a new value.
     pub fn change_target(&mut self, key: impl Hash)
@@ -143,14 +202,14 @@ impl _Shared {
         key: CacheKey,
         f: impl FnOnce() -> T,
     ) -> _CacheEntry {
-        if let Some(entry) = self.0.read().unwrap().get(&(key, TypeId::of::<T>())) {
+        if let Some(entry) = self.entries.read().unwrap().get(&(key, TypeId::of::<T>())) {
             if let Some(entry) = entry.upgrade() {
                 return entry;
             }
         }
 
         let entry = _CacheEntry::new(f());
-        self.0
+        self.entries
             .write()
             .unwrap()
             .insert((key, TypeId::of::<T>()), entry.weak());
@@ -189,6 +248,10 @@ impl _WeakCacheEntry {
     fn upgrade(&self) -> Option<_CacheEntry> {
         self.0.upgrade().map(_CacheEntry)
     }
+
+    fn is_alive(&self) -> bool {
+        self.0.strong_count() > 0
+    }
 }
 
 // MARK: Tests
@@ -250,10 +313,91 @@ mod test {
 
         assert!(cache
             .0
-             .0
+            .entries
             .read()
             .unwrap()
             .values()
             .all(|entry| entry.upgrade().is_none()));
     }
+
+    /// Readers must never see a half written value and never wait for a
+    /// writer building the next value.
+    #[test]
+    fn concurrent_publish() {
+        use std::{
+            sync::atomic::{AtomicBool, Ordering},
+            thread,
+            time::{Duration, Instant},
+        };
+
+        const BUILD_TIME: Duration = Duration::from_millis(50);
+
+        let cache = Cache::new();
+        let cached = cache.get_or_insert_with(0, || Arc::new(vec![0usize; 64]));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let writers = (0..2)
+            .map(|_| {
+                let cached = cached.clone();
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        let next = cached.load()[0] + 1;
+                        // Expensive work happens outside of the lock
+                        thread::sleep(BUILD_TIME);
+                        cached.replace(Arc::new(vec![next; 64]));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let readers = (0..4)
+            .map(|_| {
+                let cached = cached.clone();
+                let done = done.clone();
+                thread::spawn(move || {
+                    let mut max_wait = Duration::ZERO;
+                    while !done.load(Ordering::Relaxed) {
+                        let start = Instant::now();
+                        let value = cached.load();
+                        max_wait = max_wait.max(start.elapsed());
+
+                        assert!(value.iter().all(|v| *v == value[0]));
+                        thread::yield_now();
+                    }
+                    max_wait
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+
+        for reader in readers {
+            let max_wait = reader.join().unwrap();
+            assert!(max_wait < BUILD_TIME, "Reader waited {:?}", max_wait);
+        }
+
+        assert!(cached.load()[0] > 0);
+    }
+
+    #[test]
+    fn clear_detaches_entries() {
+        let cache = Cache::new();
+
+        let kept = cache.get_or_insert_with(1, || 1);
+        drop(cache.get_or_insert_with(2, || 2));
+        assert_eq!(cache.live_entries(), 1);
+
+        cache.clear();
+
+        // The kept value is alive, but not shared anymore
+        assert_eq!(cache.live_entries(), 1);
+        assert_eq!(*cache.get_or_insert_with(1, || 3).read(), 3);
+        assert_eq!(*kept.read(), 1);
+
+        drop(kept);
+        assert_eq!(cache.live_entries(), 0);
+    }
 }
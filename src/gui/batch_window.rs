@@ -0,0 +1,176 @@
+use egui::{DragValue, Grid, ProgressBar};
+
+use crate::{
+    gui::widgets::{PathInput, PathInputAction},
+    pipeline::{
+        batch::{BatchOptions, BatchPlan, BatchRun, BatchState},
+        Pipeline,
+    },
+    units::NumberFormat,
+};
+
+/// Window to process a directory of pullbacks with the current pipeline, see
+/// [crate::pipeline::batch]. Closing the window aborts the batch.
+pub struct BatchWindow {
+    options: BatchOptions,
+    run: Option<BatchRun>,
+    /// Why the batch could not start.
+    error: Option<String>,
+}
+
+impl BatchWindow {
+    pub fn new() -> Self {
+        Self {
+            options: BatchOptions::default(),
+            run: None,
+            error: None,
+        }
+    }
+
+    /// Returns false, when the window got closed.
+    pub fn show(&mut self, ctx: &egui::Context, pipeline: &Pipeline) -> bool {
+        let mut open = true;
+
+        egui::Window::new("Batch")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let state = self.run.as_ref().map(BatchRun::state);
+                let is_running = state.as_ref().is_some_and(|state| !state.finished);
+
+                ui.add_enabled_ui(!is_running, |ui| self.options_ui(ui));
+
+                ui.horizontal(|ui| {
+                    let can_start = !is_running && !self.options.input_dir.as_os_str().is_empty();
+                    if ui
+                        .add_enabled(can_start, egui::Button::new("Start"))
+                        .clicked()
+                    {
+                        match BatchPlan::new(pipeline, &self.options) {
+                            Ok(plan) => {
+                                self.error = None;
+                                self.run = Some(BatchRun::start(plan));
+                            }
+                            Err(e) => self.error = Some(e.to_string()),
+                        }
+                    }
+
+                    let stopping = self.run.as_ref().is_some_and(BatchRun::is_stopping);
+                    if ui
+                        .add_enabled(is_running && !stopping, egui::Button::new("Stop"))
+                        .on_hover_text("Finish the files being processed, then stop")
+                        .clicked()
+                    {
+                        if let Some(run) = &self.run {
+                            run.stop();
+                        }
+                    }
+
+                    if ui
+                        .add_enabled(is_running, egui::Button::new("Abort"))
+                        .on_hover_text("Stop right away, leaving the current files unfinished")
+                        .clicked()
+                    {
+                        self.run = None;
+                    }
+                });
+
+                if let Some(error) = &self.error {
+                    ui.colored_label(ui.visuals().error_fg_color, format!("Failed: {error}"));
+                }
+
+                if let Some(state) = state {
+                    ui.separator();
+                    state_ui(ui, &state);
+                }
+            });
+
+        open
+    }
+
+    fn options_ui(&mut self, ui: &mut egui::Ui) {
+        Grid::new("batch_options").num_columns(2).show(ui, |ui| {
+            ui.label("Directory:");
+            ui.add(PathInput::new(&mut self.options.input_dir).action(PathInputAction::OpenFolder))
+                .on_hover_text("The directory with the pullbacks to process");
+            ui.end_row();
+
+            ui.label("Files:");
+            ui.text_edit_singleline(&mut self.options.pattern)
+                .on_hover_text(
+                    "Names of the files to process. * matches anything, ? one character",
+                );
+            ui.end_row();
+
+            ui.label("Output Names:");
+            ui.text_edit_singleline(&mut self.options.output_template)
+                .on_hover_text(
+                    "Name of every file written by an output node, in its directory.\n\
+                     {stem}: Name of the input file without extension\n\
+                     {index}: Number of the input file\n\
+                     {output}: File name of the output node",
+                );
+            ui.end_row();
+
+            ui.label("Parallel:");
+            ui.add(
+                DragValue::new(&mut self.options.parallel).range(1..=rayon::current_num_threads()),
+            )
+            .on_hover_text("Number of files processed at once");
+            ui.end_row();
+        });
+    }
+}
+
+fn state_ui(ui: &mut egui::Ui, state: &BatchState) {
+    let format = NumberFormat::current();
+    let done = state.results.len();
+
+    if !state.finished {
+        ui.add(
+            ProgressBar::new(done as f32 / state.total.max(1) as f32)
+                .rounding(3.0)
+                .text(format!("{done} of {} files", state.total)),
+        );
+        for input in &state.running {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label(input.display().to_string());
+            });
+        }
+        ui.ctx().request_repaint();
+    } else {
+        let failed = state.results.iter().filter(|r| r.result.is_err()).count();
+        ui.label(format!(
+            "Processed {done} of {} files, {failed} failed",
+            state.total
+        ));
+        ui.label(format!("Summary written to {}", state.summary.display()));
+    }
+
+    if let Some(error) = &state.error {
+        ui.colored_label(ui.visuals().error_fg_color, format!("Failed: {error}"));
+    }
+
+    egui::ScrollArea::vertical()
+        .max_height(300.0)
+        .show(ui, |ui| {
+            Grid::new("batch_results")
+                .num_columns(3)
+                .striped(true)
+                .show(ui, |ui| {
+                    for result in &state.results {
+                        let name = result.input.file_name().unwrap_or_default();
+                        ui.label(name.to_string_lossy());
+                        match &result.result {
+                            Ok(()) => ui.label("ok"),
+                            Err(e) => ui
+                                .colored_label(ui.visuals().error_fg_color, "error")
+                                .on_hover_text(e),
+                        };
+                        ui.label(format!("{} s", format.number(result.seconds, 1..=1)));
+                        ui.end_row();
+                    }
+                });
+        });
+}
@@ -1,3 +1,6 @@
+use std::sync::OnceLock;
+
+use anyhow::anyhow;
 use nalgebra::DMatrix;
 use wgpu::util::DeviceExt;
 
@@ -44,37 +47,197 @@ import_color_maps!(
         "gist_ncar"])
 );
 
-pub fn get_color_map_names() -> &'static [(&'static str, &'static [&'static str])] {
-    COLOR_MAP_NAMES
+/// Number of entries of every color map.
+const COLOR_MAP_SIZE: usize = 256;
+
+/// The color maps, that passed validation. Indices of color maps, like the
+/// `map_idx` of views, refer to these.
+struct ColorMaps {
+    names: Vec<(&'static str, Vec<&'static str>)>,
+    /// One color map per column.
+    maps: DMatrix<[u8; 4]>,
+    /// Index among the loaded maps of every bundled map, [None] for skipped
+    /// ones.
+    loaded_indices: Vec<Option<u32>>,
+}
+
+static LOADED: OnceLock<ColorMaps> = OnceLock::new();
+
+fn loaded() -> &'static ColorMaps {
+    LOADED.get_or_init(|| load(COLOR_MAPS, COLOR_MAP_NAMES))
+}
+
+/// Names of the usable color maps by category, in the order of their indices.
+pub fn get_color_map_names() -> &'static [(&'static str, Vec<&'static str>)] {
+    &loaded().names
 }
 
-pub fn get_color_maps() -> DMatrix<[u8; 4]> {
-    let mut color_maps = DMatrix::from_fn(256, COLOR_MAPS.len(), |_, _| [0, 0, 0, 0]);
+/// One color map per column, in RGBA.
+pub fn get_color_maps() -> &'static DMatrix<[u8; 4]> {
+    &loaded().maps
+}
 
-    let mut col_idx = 0;
-    for bytes in COLOR_MAPS.iter() {
-        assert_eq!(bytes[..2], b"BM"[..]);
+/// Number of bundled color maps, including the ones that were skipped.
+pub fn bundled_color_map_count() -> u32 {
+    COLOR_MAPS.len() as u32
+}
 
-        assert!(bytes.len() >= 14);
+/// Index among the usable color maps of the bundled map at `index`, as used
+/// by [crate::settings::DisplaySettings::default_color_map]. Skipped and
+/// unknown maps fall back to the first one.
+pub fn loaded_index(index: u32) -> u32 {
+    loaded()
+        .loaded_indices
+        .get(index as usize)
+        .copied()
+        .flatten()
+        .unwrap_or(0)
+}
 
-        let offset: u32 = bytemuck::cast([bytes[10], bytes[11], bytes[12], bytes[13]]);
+/// Inverse of [loaded_index].
+pub fn bundled_index(index: u32) -> u32 {
+    loaded()
+        .loaded_indices
+        .iter()
+        .position(|i| *i == Some(index))
+        .unwrap_or(0) as u32
+}
 
-        assert!(bytes.len() as u32 == offset + 256 * 8);
+/// Parses and validates all bundled maps. Invalid maps are skipped with a
+/// warning. Without any valid map, a gray ramp is used, so views still work.
+fn load(bundled: &[&[u8]], bundled_names: &[(&'static str, &[&'static str])]) -> ColorMaps {
+    let mut names = Vec::new();
+    let mut columns = Vec::new();
+    let mut loaded_indices = Vec::new();
 
-        let bytes = &bytes[offset as usize..offset as usize + 256 * 4];
+    let mut bundled = bundled.iter();
+    for (category, maps) in bundled_names {
+        let mut valid = Vec::new();
+
+        for name in maps.iter() {
+            let Some(bytes) = bundled.next() else {
+                break;
+            };
+
+            match parse_bmp(bytes) {
+                Ok(colors) => {
+                    loaded_indices.push(Some((columns.len() / COLOR_MAP_SIZE) as u32));
+                    columns.extend(colors);
+                    valid.push(*name);
+                }
+                Err(e) => {
+                    eprintln!("Skipping color map {category}/{name}: {e}");
+                    loaded_indices.push(None);
+                }
+            }
+        }
 
-        for (mat, byte) in color_maps
-            .column_mut(col_idx)
-            .iter_mut()
-            .zip(bytes.chunks_exact(4))
-        {
-            *mat = [byte[2], byte[1], byte[0], byte[3]];
+        if !valid.is_empty() {
+            names.push((*category, valid));
         }
+    }
+
+    if columns.is_empty() {
+        names.push(("Fallback", vec!["gray"]));
+        columns.extend((0..COLOR_MAP_SIZE).map(|i| [i as u8, i as u8, i as u8, 255]));
+    }
+
+    ColorMaps {
+        names,
+        maps: DMatrix::from_vec(COLOR_MAP_SIZE, columns.len() / COLOR_MAP_SIZE, columns),
+        loaded_indices,
+    }
+}
+
+/// Reads the colors from the first row of an uncompressed BMP with 256 pixels
+/// per row and 24 or 32 bits per pixel.
+fn parse_bmp(bytes: &[u8]) -> anyhow::Result<Vec<[u8; 4]>> {
+    let u16_at = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+    let u32_at =
+        |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
 
-        col_idx += 1;
+    // File header and the fields of the info header, that all versions share
+    if bytes.len() < 34 || &bytes[..2] != b"BM" {
+        return Err(anyhow!("Not a BMP file"));
     }
 
-    color_maps
+    let offset = u32_at(10);
+    let width = u32_at(18);
+    let bits_per_pixel = u16_at(28) as usize;
+    let compression = u32_at(30);
+
+    if width != COLOR_MAP_SIZE {
+        return Err(anyhow!("Width is {width}, expected {COLOR_MAP_SIZE}"));
+    }
+
+    // Bit fields are only accepted with the default BGRA layout
+    match (bits_per_pixel, compression) {
+        (24, 0) | (32, 0) | (32, 3) => {}
+        _ => {
+            return Err(anyhow!(
+                "Unsupported format with {bits_per_pixel} bits per pixel and compression \
+                 {compression}"
+            ))
+        }
+    }
+
+    let bytes_per_pixel = bits_per_pixel / 8;
+    let row = bytes
+        .get(offset..offset + width * bytes_per_pixel)
+        .ok_or_else(|| anyhow!("File is truncated"))?;
+
+    Ok(row
+        .chunks_exact(bytes_per_pixel)
+        .map(|bgra| {
+            [
+                bgra[2],
+                bgra[1],
+                bgra[0],
+                bgra.get(3).copied().unwrap_or(255),
+            ]
+        })
+        .collect())
+}
+
+/// Menu button to choose among all color maps by category, showing the
+/// selected `map_idx`.
+pub fn color_map_menu(ui: &mut egui::Ui, map_idx: &mut u32) -> egui::Response {
+    let color_maps = get_color_map_names();
+
+    let mut start = 0;
+    let (category, map) = color_maps
+        .iter()
+        .find_map(|(category, maps)| {
+            if *map_idx < start + maps.len() as u32 {
+                Some((category, maps[(*map_idx - start) as usize]))
+            } else {
+                start += maps.len() as u32;
+                None
+            }
+        })
+        .unwrap();
+
+    ui.menu_button(format!("{category}/{map}"), |ui| {
+        let mut i = 0;
+        for (category, maps) in color_maps {
+            if !ui
+                .menu_button(*category, |ui| {
+                    for map in maps {
+                        if ui.selectable_label(*map_idx == i, *map).clicked() {
+                            *map_idx = i;
+                            ui.close_menu();
+                        }
+                        i += 1;
+                    }
+                })
+                .response
+                .context_menu_opened()
+            {
+                i += maps.len() as u32;
+            }
+        }
+    })
+    .response
 }
 
 pub fn upload_color_maps(device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::Texture {
@@ -102,3 +265,82 @@ pub fn upload_color_maps(device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::Te
 
     texture
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// BMP with two rows of the color map, as exported by Matplotlib.
+    fn bmp(bits_per_pixel: u16, colors: &[[u8; 3]]) -> Vec<u8> {
+        let bytes_per_pixel = bits_per_pixel as usize / 8;
+        let row_size = (colors.len() * bytes_per_pixel).next_multiple_of(4);
+
+        let mut bytes = vec![0; 54];
+        bytes[..2].copy_from_slice(b"BM");
+        bytes[2..6].copy_from_slice(&((54 + 2 * row_size) as u32).to_le_bytes());
+        bytes[10..14].copy_from_slice(&54u32.to_le_bytes());
+        bytes[14..18].copy_from_slice(&40u32.to_le_bytes());
+        bytes[18..22].copy_from_slice(&(colors.len() as u32).to_le_bytes());
+        bytes[22..26].copy_from_slice(&2u32.to_le_bytes());
+        bytes[26..28].copy_from_slice(&1u16.to_le_bytes());
+        bytes[28..30].copy_from_slice(&bits_per_pixel.to_le_bytes());
+
+        for _ in 0..2 {
+            let mut row = Vec::with_capacity(row_size);
+            for [r, g, b] in colors {
+                row.extend_from_slice(&[*b, *g, *r, 255][..bytes_per_pixel]);
+            }
+            row.resize(row_size, 0);
+            bytes.extend(row);
+        }
+
+        bytes
+    }
+
+    fn ramp() -> Vec<[u8; 3]> {
+        (0..=255).map(|i| [i, 255 - i, i / 2]).collect()
+    }
+
+    #[test]
+    fn bundled_maps_are_valid() {
+        let maps = load(COLOR_MAPS, COLOR_MAP_NAMES);
+
+        assert_eq!(maps.maps.ncols(), COLOR_MAPS.len());
+        assert!(maps.loaded_indices.iter().all(Option::is_some));
+
+        // First entries of viridis and Greys
+        assert_eq!(maps.maps[(0, 0)], [68, 1, 84, 255]);
+        assert_eq!(maps.maps[(0, 5)], [255, 255, 255, 255]);
+        assert_eq!(maps.names[2].1[3], "gray");
+    }
+
+    #[test]
+    fn invalid_maps_are_skipped() {
+        let bmp_32 = bmp(32, &ramp());
+        let bmp_24 = bmp(24, &ramp());
+        let truncated = &bmp_32[..54 + 100];
+        let narrow = bmp(32, &ramp()[..100]);
+
+        let bundled: [&[u8]; 5] = [&bmp_32, truncated, &bmp_24, b"BM", &narrow];
+        let names: [(&str, &[&str]); 2] = [("A", &["a", "truncated", "b"]), ("B", &["c", "d"])];
+
+        let maps = load(&bundled, &names);
+
+        assert_eq!(maps.maps.ncols(), 2);
+        assert_eq!(maps.names, vec![("A", vec!["a", "b"])]);
+        assert_eq!(
+            maps.loaded_indices,
+            vec![Some(0), None, Some(1), None, None]
+        );
+
+        for column in 0..2 {
+            let colors = maps.maps.column(column);
+            assert_eq!(colors[0], [0, 255, 0, 255]);
+            assert_eq!(colors[200], [200, 55, 100, 255]);
+        }
+
+        let maps = load(&bundled[3..], &[("B", &["c", "d"])]);
+        assert_eq!(maps.maps.ncols(), 1);
+        assert_eq!(maps.maps[(128, 0)], [128, 128, 128, 255]);
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use egui::{Color32, ComboBox, Grid, Id, LayerId, Order, Rect, ScrollArea, Stroke};
+use serde_json::Value;
+
+use crate::{
+    gui::widgets::PathInput,
+    node_graph::NodeId,
+    pipeline::{
+        compare::{DiffStatus, NodeDiff, PipelineDiff, PipelineSnapshot, Side},
+        Pipeline,
+    },
+};
+
+/// A pipeline to compare.
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    /// The pipeline open in the editor.
+    Current,
+    /// The file the current pipeline was opened from or saved to last.
+    LastSaved,
+    File(PathBuf),
+}
+
+impl Source {
+    fn text(&self) -> &'static str {
+        match self {
+            Source::Current => "Current",
+            Source::LastSaved => "Last Saved",
+            Source::File(_) => "File",
+        }
+    }
+
+    fn read(&self, current: &Value, pipeline_path: Option<&Path>) -> anyhow::Result<Value> {
+        let path = match self {
+            Source::Current => return Ok(current.clone()),
+            Source::LastSaved => {
+                pipeline_path.ok_or_else(|| anyhow::anyhow!("The pipeline was not saved yet"))?
+            }
+            Source::File(path) => path,
+        };
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+/// What a diff was computed from.
+#[derive(Debug, PartialEq)]
+struct Inputs {
+    a: Source,
+    b: Source,
+    pipeline_path: Option<PathBuf>,
+    current: Value,
+}
+
+/// Window comparing two versions of a pipeline, see
+/// [crate::pipeline::compare].
+pub struct CompareWindow {
+    a: Source,
+    b: Source,
+    /// Outline the differences in the editor, when a side is [Source::Current].
+    outline: bool,
+    inputs: Option<Inputs>,
+    diff: Result<PipelineDiff, String>,
+}
+
+impl CompareWindow {
+    pub fn new() -> Self {
+        Self {
+            a: Source::LastSaved,
+            b: Source::Current,
+            outline: true,
+            inputs: None,
+            diff: Ok(PipelineDiff::default()),
+        }
+    }
+
+    /// Returns false, when the window got closed.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        pipeline: &Pipeline,
+        pipeline_path: Option<&Path>,
+    ) -> bool {
+        self.update(pipeline, pipeline_path);
+
+        let mut open = true;
+
+        egui::Window::new("Compare Pipelines")
+            .open(&mut open)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                Grid::new("compare_sources").num_columns(2).show(ui, |ui| {
+                    ui.label("Old:");
+                    source_ui(ui, "a", &mut self.a);
+                    ui.end_row();
+
+                    ui.label("New:");
+                    source_ui(ui, "b", &mut self.b);
+                    ui.end_row();
+                });
+
+                ui.add_enabled(
+                    self.current_side().is_some(),
+                    egui::Checkbox::new(&mut self.outline, "Outline in editor"),
+                )
+                .on_hover_text("Added nodes in green, removed in red and changed in yellow");
+
+                ui.separator();
+
+                match &self.diff {
+                    Ok(diff) if diff.is_empty() => {
+                        ui.label("No differences");
+                    }
+                    Ok(diff) => {
+                        ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                            diff_ui(ui, diff);
+                        });
+                    }
+                    Err(e) => {
+                        ui.colored_label(ui.visuals().error_fg_color, e);
+                    }
+                }
+            });
+
+        open
+    }
+
+    /// Outlines the nodes of the current pipeline, that differ, in the editor.
+    pub fn outline(&self, ctx: &egui::Context, node_rects: &HashMap<NodeId, Rect>) {
+        let (Some(side), Ok(diff)) = (self.current_side(), &self.diff) else {
+            return;
+        };
+        if !self.outline {
+            return;
+        }
+
+        let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("compare")));
+        for (node_id, status) in diff.statuses(side) {
+            if let Some(rect) = node_rects.get(&node_id) {
+                painter.rect_stroke(rect.expand(4.0), 6.0, Stroke::new(2.0, color(status)));
+            }
+        }
+    }
+
+    fn current_side(&self) -> Option<Side> {
+        match (&self.a, &self.b) {
+            (_, Source::Current) => Some(Side::B),
+            (Source::Current, _) => Some(Side::A),
+            _ => None,
+        }
+    }
+
+    /// Computes the diff again, when the sources or the current pipeline
+    /// changed.
+    fn update(&mut self, pipeline: &Pipeline, pipeline_path: Option<&Path>) {
+        let inputs = Inputs {
+            a: self.a.clone(),
+            b: self.b.clone(),
+            pipeline_path: pipeline_path.map(Path::to_path_buf),
+            current: serde_json::to_value(pipeline).unwrap_or_default(),
+        };
+        if self.inputs.as_ref() == Some(&inputs) {
+            return;
+        }
+
+        let snapshot = |source: &Source| {
+            source
+                .read(&inputs.current, pipeline_path)
+                .and_then(|value| PipelineSnapshot::from_value(&value))
+                .map_err(|e| format!("{}: {e}", source.text()))
+        };
+        self.diff =
+            snapshot(&inputs.a).and_then(|a| Ok(PipelineDiff::new(&a, &snapshot(&inputs.b)?)));
+        self.inputs = Some(inputs);
+    }
+}
+
+fn source_ui(ui: &mut egui::Ui, id: &str, source: &mut Source) {
+    ui.horizontal(|ui| {
+        ComboBox::from_id_source(ui.id().with(id))
+            .selected_text(source.text())
+            .show_ui(ui, |ui| {
+                ui.selectable_value(source, Source::Current, Source::Current.text());
+                ui.selectable_value(source, Source::LastSaved, Source::LastSaved.text());
+                if ui
+                    .selectable_label(matches!(source, Source::File(_)), "File")
+                    .clicked()
+                    && !matches!(source, Source::File(_))
+                {
+                    *source = Source::File(PathBuf::new());
+                }
+            });
+
+        if let Source::File(path) = source {
+            ui.add(PathInput::new(path));
+        }
+    });
+}
+
+fn diff_ui(ui: &mut egui::Ui, diff: &PipelineDiff) {
+    Grid::new("compare_diff")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| {
+            for node in &diff.nodes {
+                match node {
+                    NodeDiff::Added { label, .. } => {
+                        ui.colored_label(color(DiffStatus::Added), "Added");
+                        ui.label(label);
+                        ui.end_row();
+                    }
+                    NodeDiff::Removed { label, .. } => {
+                        ui.colored_label(color(DiffStatus::Removed), "Removed");
+                        ui.label(label);
+                        ui.end_row();
+                    }
+                    NodeDiff::Changed {
+                        a,
+                        b,
+                        label,
+                        changes,
+                    } => {
+                        ui.colored_label(color(DiffStatus::Changed), "Changed");
+                        ui.vertical(|ui| {
+                            ui.strong(label);
+                            if a != b {
+                                let number: usize = (*a).into();
+                                ui.label(format!("Renumbered from #{}", number));
+                            }
+                            for change in changes {
+                                ui.label(change.to_string());
+                            }
+                        });
+                        ui.end_row();
+                    }
+                }
+            }
+        });
+}
+
+fn color(status: DiffStatus) -> Color32 {
+    match status {
+        DiffStatus::Added => Color32::GREEN,
+        DiffStatus::Removed => Color32::RED,
+        DiffStatus::Changed => Color32::YELLOW,
+    }
+}
@@ -0,0 +1,81 @@
+use egui::Grid;
+
+use crate::{
+    gui::pipeline::node_templates,
+    pipeline::registry::{data_type_usage, DataTypeUsage},
+};
+
+/// Debug window listing the request types flowing between nodes, with the
+/// nodes producing and consuming them. See [crate::pipeline::registry].
+pub struct DataTypesWindow {
+    usage: Vec<DataTypeUsage>,
+}
+
+impl DataTypesWindow {
+    pub fn new() -> Self {
+        Self {
+            usage: data_type_usage(node_templates()),
+        }
+    }
+
+    /// Returns false, when the window got closed.
+    pub fn show(&mut self, ctx: &egui::Context) -> bool {
+        let mut open = true;
+
+        egui::Window::new("Data Types")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    Grid::new("data_types")
+                        .num_columns(5)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.strong("Request");
+                            ui.strong("Response");
+                            ui.strong("Chunk");
+                            ui.strong("Produced by");
+                            ui.strong("Consumed by");
+                            ui.end_row();
+
+                            for usage in &self.usage {
+                                usage_row(ui, usage);
+                            }
+                        });
+                });
+
+                ui.weak("Data views are not listed as consumers.");
+            });
+
+        open
+    }
+}
+
+fn usage_row(ui: &mut egui::Ui, usage: &DataTypeUsage) {
+    let info = usage.info;
+
+    let data_type = format!("Pins of type {:?}", info.data_type);
+
+    if usage.is_orphan() {
+        let color = ui.visuals().warn_fg_color;
+        ui.colored_label(color, format!("⚠ {}", info.name))
+            .on_hover_text(format!(
+                "{data_type}\nEither no node produces or no node consumes this type"
+            ));
+    } else {
+        ui.label(info.name).on_hover_text(data_type);
+    }
+
+    ui.label(info.response);
+    ui.monospace(info.chunk);
+    nodes_label(ui, &usage.producers);
+    nodes_label(ui, &usage.consumers);
+    ui.end_row();
+}
+
+fn nodes_label(ui: &mut egui::Ui, nodes: &[&str]) {
+    match nodes {
+        [] => ui.weak("None"),
+        nodes => ui.label(nodes.join("\n")),
+    };
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use egui::{Color32, Grid, Id, LayerId, Order, Rect, ScrollArea, Stroke, TextEdit};
+
+use crate::{
+    gui::node_graph::NodeGraphEditState,
+    node_graph::NodeId,
+    pipeline::{
+        diagnostic_link::{Diagnostic, Match, MatchKind},
+        Pipeline, PipelineExecutor,
+    },
+};
+
+/// Window decoding a diagnostic link, see [crate::pipeline::diagnostic_link].
+pub struct DiagnosticWindow {
+    link: String,
+    diagnostic: Result<Diagnostic, String>,
+    /// Result of the last [Diagnostic::apply].
+    applied: Option<Result<NodeId, String>>,
+}
+
+impl DiagnosticWindow {
+    pub fn new() -> Self {
+        Self {
+            link: String::new(),
+            diagnostic: Err(String::new()),
+            applied: None,
+        }
+    }
+
+    /// Returns false, when the window got closed.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        pipeline: &mut Pipeline,
+        executor: &mut PipelineExecutor,
+        edit_state: &mut NodeGraphEditState,
+    ) -> bool {
+        let mut open = true;
+
+        egui::Window::new("Open Diagnostic")
+            .open(&mut open)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                let response = ui.add(
+                    TextEdit::multiline(&mut self.link)
+                        .hint_text("Paste a diagnostic link")
+                        .desired_rows(2)
+                        .desired_width(f32::INFINITY),
+                );
+                if response.changed() {
+                    self.diagnostic = match self.link.trim() {
+                        "" => Err(String::new()),
+                        link => Diagnostic::decode(link).map_err(|e| e.to_string()),
+                    };
+                    self.applied = None;
+                }
+
+                let diagnostic = match &self.diagnostic {
+                    Ok(diagnostic) => diagnostic,
+                    Err(e) => {
+                        ui.colored_label(ui.visuals().error_fg_color, e);
+                        return;
+                    }
+                };
+
+                ui.separator();
+
+                ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    ui.monospace(diagnostic.report());
+                });
+
+                ui.separator();
+
+                let matches = diagnostic.matches(pipeline);
+                if matches.is_empty() {
+                    ui.label("No matching nodes in the current pipeline");
+                }
+
+                let mut apply = None;
+                Grid::new("diagnostic_matches")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for Match { node_id, kind } in matches {
+                            let number: usize = node_id.into();
+                            ui.colored_label(
+                                color(kind),
+                                format!("{} #{}", pipeline[node_id].name(), number),
+                            );
+                            ui.label(match kind {
+                                MatchKind::Node {
+                                    same_settings: true,
+                                } => "Same settings",
+                                MatchKind::Node {
+                                    same_settings: false,
+                                } => "Different settings",
+                                MatchKind::Upstream => "Upstream",
+                            });
+                            ui.horizontal(|ui| {
+                                if ui.button("Show").clicked() {
+                                    edit_state.focus(node_id);
+                                }
+                                if matches!(kind, MatchKind::Node { .. })
+                                    && ui
+                                        .button("Apply Settings")
+                                        .on_hover_text(
+                                            "Replace the settings of this node with the ones \
+                                             of the link, keeping its connections",
+                                        )
+                                        .clicked()
+                                {
+                                    apply = Some(node_id);
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+
+                if let Some(node_id) = apply {
+                    self.applied = Some(
+                        diagnostic
+                            .apply(pipeline, node_id)
+                            .map(|_| {
+                                executor.recreate_node(node_id, pipeline);
+                                node_id
+                            })
+                            .map_err(|e| e.to_string()),
+                    );
+                }
+
+                match &self.applied {
+                    Some(Ok(node_id)) => {
+                        let number: usize = (*node_id).into();
+                        ui.label(format!("Applied the settings to #{}", number));
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(ui.visuals().error_fg_color, e);
+                    }
+                    None => {}
+                }
+            });
+
+        open
+    }
+
+    /// Outlines the nodes of the current pipeline, that match the link, in
+    /// the editor.
+    pub fn outline(
+        &self,
+        ctx: &egui::Context,
+        pipeline: &Pipeline,
+        node_rects: &HashMap<NodeId, Rect>,
+    ) {
+        let Ok(diagnostic) = &self.diagnostic else {
+            return;
+        };
+
+        let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("diagnostic")));
+        for Match { node_id, kind } in diagnostic.matches(pipeline) {
+            if let Some(rect) = node_rects.get(&node_id) {
+                painter.rect_stroke(rect.expand(4.0), 6.0, Stroke::new(2.0, color(kind)));
+            }
+        }
+    }
+}
+
+fn color(kind: MatchKind) -> Color32 {
+    match kind {
+        MatchKind::Node {
+            same_settings: true,
+        } => Color32::GREEN,
+        MatchKind::Node {
+            same_settings: false,
+        } => Color32::YELLOW,
+        MatchKind::Upstream => Color32::LIGHT_BLUE,
+    }
+}
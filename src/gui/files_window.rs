@@ -0,0 +1,351 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use egui::Grid;
+use tokio::sync::oneshot;
+
+use crate::{
+    gui::node_graph::NodeGraphEditState,
+    node_graph::NodeId,
+    pipeline::{nodes::PathRole, Pipeline},
+};
+
+/// Window listing every file the nodes of the pipeline read or write, see
+/// [crate::pipeline::nodes::PipelineNode::visit_paths]. Problems, that would
+/// otherwise only show up while running the pipeline, are marked.
+pub struct FilesWindow {
+    entries: Vec<PathEntry>,
+    /// Status of every entry, in the same order. None while checking.
+    statuses: Option<Vec<FileStatus>>,
+    pending: Option<oneshot::Receiver<Vec<FileStatus>>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PathEntry {
+    node_id: NodeId,
+    node_name: String,
+    role: PathRole,
+    path: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct FileStatus {
+    exists: bool,
+    size: Option<u64>,
+    modified: Option<SystemTime>,
+    problem: Option<String>,
+}
+
+impl FilesWindow {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            statuses: None,
+            pending: None,
+        }
+    }
+
+    /// Returns false, when the window got closed. Clicking an entry focuses
+    /// its node in the editor.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        pipeline: &Pipeline,
+        edit_state: &mut NodeGraphEditState,
+    ) -> bool {
+        let mut open = true;
+
+        // Paths are edited in the nodes, so they are checked again, as soon as
+        // they differ
+        let entries = collect_entries(pipeline);
+        if entries != self.entries {
+            self.entries = entries;
+            self.refresh(ctx);
+        }
+
+        if let Some(statuses) = self.pending.as_mut().and_then(|rx| rx.try_recv().ok()) {
+            self.statuses = Some(statuses);
+            self.pending = None;
+        }
+
+        egui::Window::new("Files")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(self.pending.is_none(), egui::Button::new("Refresh"))
+                        .clicked()
+                    {
+                        self.refresh(ctx);
+                    }
+                    if self.pending.is_some() {
+                        ui.spinner();
+                    }
+                });
+
+                if self.entries.is_empty() {
+                    ui.weak("No node reads or writes files.");
+                    return;
+                }
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    Grid::new("files")
+                        .num_columns(5)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.strong("Node");
+                            ui.strong("Role");
+                            ui.strong("Path");
+                            ui.strong("Size");
+                            ui.strong("Modified");
+                            ui.end_row();
+
+                            for (i, entry) in self.entries.iter().enumerate() {
+                                let status = self.statuses.as_ref().and_then(|s| s.get(i));
+                                if entry_row(ui, entry, status) {
+                                    edit_state.focus(entry.node_id);
+                                }
+                            }
+                        });
+                });
+            });
+
+        open
+    }
+
+    /// Checks all entries again on a blocking thread.
+    fn refresh(&mut self, ctx: &egui::Context) {
+        let (tx, rx) = oneshot::channel();
+
+        let paths = self
+            .entries
+            .iter()
+            .map(|entry| (entry.role, entry.path.clone()))
+            .collect::<Vec<_>>();
+        let ctx = ctx.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let statuses = paths.iter().map(|(role, path)| stat(*role, path)).collect();
+            let _ = tx.send(statuses);
+            ctx.request_repaint();
+        });
+
+        self.statuses = None;
+        self.pending = Some(rx);
+    }
+}
+
+/// Paths of all nodes, ordered by node.
+fn collect_entries(pipeline: &Pipeline) -> Vec<PathEntry> {
+    let mut entries = Vec::new();
+
+    for (node_id, node) in &pipeline.nodes {
+        node.visit_paths(&mut |role, path| {
+            entries.push(PathEntry {
+                node_id: *node_id,
+                node_name: node.name().to_string(),
+                role,
+                path: path.to_path_buf(),
+            })
+        });
+    }
+
+    entries.sort_by_key(|entry| (entry.node_id, entry.role));
+    entries
+}
+
+/// Returns true, when the row got clicked.
+fn entry_row(ui: &mut egui::Ui, entry: &PathEntry, status: Option<&FileStatus>) -> bool {
+    let problem = status.and_then(|status| status.problem.as_ref());
+
+    let (text, hover) = match problem {
+        Some(problem) => (
+            egui::RichText::new(format!("⚠ {}", entry.node_name)).color(ui.visuals().warn_fg_color),
+            format!("{problem}\nClick to show the node"),
+        ),
+        None => (
+            egui::RichText::new(&entry.node_name),
+            "Click to show the node".to_string(),
+        ),
+    };
+    let clicked = ui
+        .add(egui::Label::new(text).sense(egui::Sense::click()))
+        .on_hover_text(hover)
+        .clicked();
+
+    ui.label(match entry.role {
+        PathRole::Input => "Input",
+        PathRole::Output => "Output",
+    });
+
+    match entry.path.as_os_str().is_empty() {
+        true => ui.weak("Not set"),
+        false => ui.monospace(entry.path.display().to_string()),
+    };
+
+    match status {
+        None => {
+            ui.weak("…");
+            ui.weak("…");
+        }
+        Some(status) if !status.exists => {
+            match entry.path.as_os_str().is_empty() {
+                true => ui.label(""),
+                false => ui.weak("Missing"),
+            };
+            ui.label("");
+        }
+        Some(status) => {
+            ui.label(status.size.map(format_size).unwrap_or_default());
+            ui.label(
+                status
+                    .modified
+                    .and_then(|modified| modified.elapsed().ok())
+                    .map(format_age)
+                    .unwrap_or_default(),
+            );
+        }
+    }
+
+    ui.end_row();
+
+    clicked
+}
+
+// MARK: Checks
+
+/// Looks up the file and checks, whether the node can read or write it. This
+/// blocks on the file system.
+fn stat(role: PathRole, path: &Path) -> FileStatus {
+    if path.as_os_str().is_empty() {
+        return FileStatus {
+            problem: (role == PathRole::Input).then(|| "No file chosen".to_string()),
+            ..Default::default()
+        };
+    }
+
+    let metadata = fs::metadata(path).ok();
+    let mut status = FileStatus {
+        exists: metadata.is_some(),
+        size: metadata.as_ref().map(|m| m.len()),
+        modified: metadata.as_ref().and_then(|m| m.modified().ok()),
+        problem: None,
+    };
+
+    status.problem = match (role, &metadata) {
+        (PathRole::Input, None) => Some("Input file is missing".to_string()),
+        (PathRole::Input, Some(m)) if m.is_dir() => Some("Input is a directory".to_string()),
+        (PathRole::Input, Some(_)) => fs::File::open(path)
+            .err()
+            .map(|e| format!("Input file is not readable: {e}")),
+        (PathRole::Output, Some(m)) if m.is_dir() => Some("Output is a directory".to_string()),
+        (PathRole::Output, _) => output_problem(path, metadata.as_ref()),
+    };
+
+    status
+}
+
+fn output_problem(path: &Path, metadata: Option<&fs::Metadata>) -> Option<String> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    match fs::metadata(dir) {
+        Err(_) => return Some("Output directory is missing".to_string()),
+        Ok(m) if m.permissions().readonly() => {
+            return Some("Output directory is not writable".to_string())
+        }
+        Ok(_) => {}
+    }
+
+    match metadata {
+        Some(m) if m.permissions().readonly() => Some("Output file is not writable".to_string()),
+        Some(m) if m.len() > 0 => Some("Output would overwrite an existing file".to_string()),
+        _ => None,
+    }
+}
+
+// MARK: Formatting
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for u in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = u;
+    }
+
+    format!("{size:.1} {unit}")
+}
+
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{secs} s ago"),
+        60..=3599 => format!("{} min ago", secs / 60),
+        3600..=86399 => format!("{} h ago", secs / 3600),
+        _ => format!("{} d ago", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn problems() {
+        let dir = std::env::temp_dir().join(format!("ivoct_files_window_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let existing = dir.join("existing.bin");
+        fs::write(&existing, [1, 2, 3]).unwrap();
+        let empty = dir.join("empty.bin");
+        fs::write(&empty, []).unwrap();
+        let missing = dir.join("missing.bin");
+
+        let input = stat(PathRole::Input, &existing);
+        assert!(input.exists);
+        assert_eq!(input.size, Some(3));
+        assert_eq!(input.problem, None);
+
+        assert!(stat(PathRole::Input, &missing).problem.is_some());
+        assert!(stat(PathRole::Input, &dir).problem.is_some());
+        assert!(stat(PathRole::Input, Path::new("")).problem.is_some());
+
+        assert!(stat(PathRole::Output, &existing).problem.is_some());
+        assert_eq!(stat(PathRole::Output, &empty).problem, None);
+        assert_eq!(stat(PathRole::Output, &missing).problem, None);
+        assert_eq!(stat(PathRole::Output, Path::new("")).problem, None);
+        assert!(stat(PathRole::Output, &dir.join("missing/out.bin"))
+            .problem
+            .is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 << 30), "3.0 GiB");
+
+        assert_eq!(format_age(Duration::from_secs(5)), "5 s ago");
+        assert_eq!(format_age(Duration::from_secs(120)), "2 min ago");
+        assert_eq!(format_age(Duration::from_secs(7200)), "2 h ago");
+        assert_eq!(format_age(Duration::from_secs(3 * 86400)), "3 d ago");
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use egui::Grid;
+
+use crate::pipeline::priority::{self, Priority};
+
+/// Debug window listing the blocking computations of the nodes, with the pool
+/// they run on. See [crate::pipeline::priority].
+pub struct JobsWindow;
+
+impl JobsWindow {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Returns false, when the window got closed.
+    pub fn show(&mut self, ctx: &egui::Context) -> bool {
+        let mut open = true;
+
+        egui::Window::new("Jobs")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let jobs = priority::active_jobs();
+
+                for priority in Priority::ALL {
+                    let running = jobs
+                        .iter()
+                        .filter(|job| job.priority == priority && job.running)
+                        .count();
+                    let waiting = jobs
+                        .iter()
+                        .filter(|job| job.priority == priority && !job.running)
+                        .count();
+                    ui.label(format!(
+                        "{} pool: {} running, {} waiting",
+                        priority.pool(),
+                        running,
+                        waiting
+                    ));
+                }
+
+                ui.separator();
+
+                if jobs.is_empty() {
+                    ui.weak("No jobs");
+                    return;
+                }
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    Grid::new("jobs")
+                        .num_columns(3)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.strong("Node");
+                            ui.strong("Pool");
+                            ui.strong("State");
+                            ui.end_row();
+
+                            for job in &jobs {
+                                ui.label(job.node.as_deref().unwrap_or("None"));
+                                ui.label(job.priority.pool());
+                                let state = match job.running {
+                                    true => "Running",
+                                    false => "Waiting",
+                                };
+                                ui.label(format!(
+                                    "{} for {:.1} s",
+                                    state,
+                                    job.since.elapsed().as_secs_f32()
+                                ));
+                                ui.end_row();
+                            }
+                        });
+                });
+            });
+
+        // Jobs come and go without anything else repainting
+        ctx.request_repaint_after(Duration::from_millis(250));
+
+        open
+    }
+}
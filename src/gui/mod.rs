@@ -1,5 +1,18 @@
+pub mod batch_window;
 pub mod color_maps;
+pub mod compare_window;
+pub mod data_types_window;
+pub mod diagnostic_window;
 pub mod dock_state;
+pub mod files_window;
+pub mod jobs_window;
 pub mod node_graph;
+pub mod parameter_sweep_window;
 pub mod pipeline;
+pub mod range_selector;
+pub mod report_window;
+pub mod runs_window;
+pub mod settings_window;
+pub mod validation_window;
+pub mod walkthrough;
 pub mod widgets;
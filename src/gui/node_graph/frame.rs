@@ -1,9 +1,23 @@
 use egui::{
-    emath::TSTransform, epaint::TextShape, Color32, Id, InnerResponse, Margin, Pos2, Rect,
-    Response, Rounding, Sense, Shape, Stroke, TextStyle, Ui, Vec2, WidgetText,
+    emath::TSTransform, epaint::TextShape, Align, Color32, Id, InnerResponse, Layout, Margin, Pos2,
+    Rect, Response, Rounding, Sense, Shape, Stroke, TextStyle, Ui, Vec2, WidgetText,
 };
 use serde::{Deserialize, Serialize};
 
+use super::{snapping, NodeProgress, NodeWarning};
+
+/// Outline of nodes that made no progress for a while.
+const STALLED_COLOR: Color32 = Color32::from_rgb(255, 176, 0);
+
+/// Outline and sign of nodes with a warning.
+const WARNING_COLOR: Color32 = STALLED_COLOR;
+
+/// Title bar of disabled nodes, replacing their color.
+const DISABLED_COLOR: Color32 = Color32::from_gray(80);
+
+/// Opacity of the contents of disabled nodes.
+const DISABLED_OPACITY: f32 = 0.5;
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NodeFrameState {
     #[serde(with = "Pos2Def")]
@@ -27,6 +41,12 @@ pub struct NodeFrame<'a> {
     selected: bool,
     sense: Sense,
     follow_mouse: bool,
+    header_scale: f32,
+    progress: Option<&'a NodeProgress>,
+    cancel: Option<&'a mut bool>,
+    disabled: bool,
+    warnings: &'a [NodeWarning],
+    snap: Option<f32>,
 }
 
 impl<'a> NodeFrame<'a> {
@@ -39,6 +59,12 @@ impl<'a> NodeFrame<'a> {
             selected: false,
             sense: Sense::drag(),
             follow_mouse: false,
+            header_scale: 1.0,
+            progress: None,
+            cancel: None,
+            disabled: false,
+            warnings: &[],
+            snap: None,
         }
     }
 
@@ -67,6 +93,45 @@ impl<'a> NodeFrame<'a> {
         self
     }
 
+    /// Scales the height of the title bar.
+    pub fn header_scale(mut self, header_scale: f32) -> Self {
+        self.header_scale = header_scale;
+        self
+    }
+
+    /// Plots the recent progress at the bottom of the node.
+    pub fn progress(mut self, progress: Option<&'a NodeProgress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Shows a button next to the progress plot, which sets `cancel` when
+    /// clicked.
+    pub fn cancel(mut self, cancel: Option<&'a mut bool>) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Greys out the node. Its contents stay interactive.
+    pub fn disabled(mut self, disabled: bool) -> Self {
+        self.disabled = disabled;
+        self
+    }
+
+    /// Shows a warning sign for every warning at the bottom of the node,
+    /// explained when hovered, and outlines the node in amber.
+    pub fn warnings(mut self, warnings: &'a [NodeWarning]) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    /// Snaps the position to a grid of this spacing, while the node is
+    /// dragged.
+    pub fn snap(mut self, grid: Option<f32>) -> Self {
+        self.snap = grid;
+        self
+    }
+
     pub fn show(
         &mut self,
         ui: &mut Ui,
@@ -77,7 +142,10 @@ impl<'a> NodeFrame<'a> {
         // negative space, which would cause the origin to change for subsequent
         // frames.
 
-        let padding = Margin::symmetric(10.0, 5.0);
+        let padding = Margin {
+            top: 5.0 * self.header_scale,
+            ..Margin::symmetric(10.0, 5.0)
+        };
         let rounding = 5.0;
 
         let shadow = ui.style().visuals.popup_shadow;
@@ -169,9 +237,29 @@ impl<'a> NodeFrame<'a> {
                         ui.clip_rect(),
                         egui::UiStackInfo::default(),
                     );
+                    if self.disabled {
+                        _ui.multiply_opacity(DISABLED_OPACITY);
+                    }
                     _ui.with_layout(*ui.layout(), add_contents);
                     ui.allocate_rect(_ui.min_rect(), Sense::hover());
 
+                    for warning in self.warnings {
+                        ui.colored_label(WARNING_COLOR, format!("⚠ {}", warning.label))
+                            .on_hover_text(&warning.description);
+                    }
+
+                    if let Some(progress) = self.progress {
+                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                            if let Some(cancel) = self.cancel.as_deref_mut() {
+                                *cancel |= ui
+                                    .small_button("■")
+                                    .on_hover_text("Cancel the current run")
+                                    .clicked();
+                            }
+                            self.progress_plot(ui, progress);
+                        });
+                    }
+
                     // ui.push_id(self.id.with("content"), add_contents);
 
                     title_size
@@ -213,7 +301,10 @@ impl<'a> NodeFrame<'a> {
                     sw: 0.0,
                     se: 0.0,
                 },
-                self.color,
+                match self.disabled {
+                    true => DISABLED_COLOR,
+                    false => self.color,
+                },
                 Stroke::NONE,
             )),
         );
@@ -224,15 +315,33 @@ impl<'a> NodeFrame<'a> {
                 rect,
                 rounding,
                 Color32::TRANSPARENT,
-                match self.selected {
-                    true => Stroke::new(1.0, Color32::WHITE),
-                    false => ui.style().visuals.window_stroke(),
+                match (self.progress, self.selected) {
+                    (Some(NodeProgress { stalled: true, .. }), _) => {
+                        Stroke::new(1.5, STALLED_COLOR)
+                    }
+                    _ if !self.warnings.is_empty() => Stroke::new(1.5, WARNING_COLOR),
+                    (_, true) => Stroke::new(1.0, Color32::WHITE),
+                    (_, false) => ui.style().visuals.window_stroke(),
                 },
             )),
         );
 
+        // The position follows the pointer unsnapped, so the node does not
+        // stick to a grid point, while the pointer moves slowly
+        let unsnapped_id = self.id.with("unsnapped");
         if response.dragged() {
-            state.position += response.drag_delta();
+            let unsnapped = match response.drag_started() {
+                true => None,
+                false => ui.data(|d| d.get_temp::<Pos2>(unsnapped_id)),
+            };
+            let unsnapped = unsnapped.unwrap_or(state.position) + response.drag_delta();
+            state.position = match self.snap {
+                Some(grid) => snapping::snap_to_grid(unsnapped, grid),
+                None => unsnapped,
+            };
+            ui.data_mut(|d| d.insert_temp(unsnapped_id, unsnapped));
+        } else if response.drag_stopped() {
+            ui.data_mut(|d| d.remove::<Pos2>(unsnapped_id));
         }
 
         ui.memory_mut(|mem| mem.data.insert_temp(self.id.with("rect"), rect));
@@ -244,4 +353,34 @@ impl<'a> NodeFrame<'a> {
 
         response
     }
+
+    fn progress_plot(&self, ui: &mut Ui, progress: &NodeProgress) {
+        let (rect, response) =
+            ui.allocate_exact_size(Vec2::new(ui.available_width(), 14.0), Sense::hover());
+
+        let painter = ui.painter();
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+        let rect = rect.shrink(2.0);
+        let steps = progress.history.len().saturating_sub(1).max(1) as f32;
+        let points = progress
+            .history
+            .iter()
+            .enumerate()
+            .map(|(i, fraction)| {
+                Pos2::new(
+                    rect.left() + rect.width() * i as f32 / steps,
+                    rect.bottom() - rect.height() * fraction.clamp(0.0, 1.0),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let color = match progress.stalled {
+            true => STALLED_COLOR,
+            false => ui.visuals().text_color(),
+        };
+        painter.add(Shape::line(points, Stroke::new(1.0, color)));
+
+        response.on_hover_text(&progress.description);
+    }
 }
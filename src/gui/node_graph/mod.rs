@@ -2,6 +2,7 @@ mod add_node_popup;
 mod draw_cut;
 mod frame;
 mod node_graph_editor;
+mod snapping;
 
 use std::{
     collections::HashMap,
@@ -10,18 +11,92 @@ use std::{
 
 use frame::NodeFrameState;
 pub use node_graph_editor::*;
+pub use snapping::Snapping;
 
-use egui::{pos2, Align, Color32, InnerResponse, Label, Layout, Pos2, Response, Vec2, WidgetText};
+use egui::{
+    emath::TSTransform, pos2, Align, Color32, InnerResponse, Label, Layout, Pos2, Response, Vec2,
+    WidgetText,
+};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::node_graph::*;
 
+/// Data flowing through a connection, shown by the [NodeGraphEditor].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConnectionActivity {
+    /// How much data currently flows, from 0 (idle) to 1.
+    pub intensity: f32,
+    /// Shown when hovering the connection.
+    pub description: String,
+}
+
+/// A small label on a connection next to the input receiving it, shown by the
+/// [NodeGraphEditor].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConnectionBadge {
+    pub label: String,
+    /// Drawn in orange, like when the receiving node converts the data.
+    pub highlighted: bool,
+    /// Shown when hovering the badge.
+    pub description: String,
+}
+
+/// Recent progress of a node, shown by the [NodeGraphEditor] as a small plot
+/// at the bottom of the node.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeProgress {
+    /// Fractions done from 0 to 1, oldest first and sampled at a fixed
+    /// interval.
+    pub history: Vec<f32>,
+    /// Whether no progress has been made for a while. The node is outlined in
+    /// amber.
+    pub stalled: bool,
+    /// Shown when hovering the plot.
+    pub description: String,
+}
+
+/// A problem of a node, shown by the [NodeGraphEditor] as a warning sign at
+/// the bottom of the node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeWarning {
+    /// Shown next to the sign.
+    pub label: &'static str,
+    /// Shown when hovering the sign.
+    pub description: String,
+}
+
 /// Contains every information about nodes that is only relevant to the editing
 /// of a node graph, like node positions and their drawing order.
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NodeGraphEditState {
     node_states: HashMap<NodeId, NodeFrameState>,
     node_order: Vec<NodeId>,
+    /// Pan and zoom of the editor, so it shows the same part of the graph
+    /// after loading. Files from before do not have it.
+    #[serde(default, with = "TSTransformDef")]
+    view: TSTransform,
+    /// Node to select and move into view, the next time the editor is shown.
+    #[serde(skip)]
+    focus: Option<NodeId>,
+}
+
+/// A mirror for `egui::emath::TSTransform`, because it does not implement
+/// serde traits.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "TSTransform")]
+struct TSTransformDef {
+    scaling: f32,
+    #[serde(with = "Vec2Def")]
+    translation: Vec2,
+}
+
+/// A mirror for `egui::Vec2`, see [TSTransformDef].
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Vec2")]
+struct Vec2Def {
+    x: f32,
+    y: f32,
 }
 
 impl NodeGraphEditState {
@@ -29,6 +104,8 @@ impl NodeGraphEditState {
         Self {
             node_states: HashMap::new(),
             node_order: Vec::new(),
+            view: TSTransform::IDENTITY,
+            focus: None,
         }
     }
 
@@ -69,6 +146,50 @@ impl NodeGraphEditState {
         self.node_order.retain(|id| id != &node_id);
         self.node_order.push(node_id);
     }
+
+    /// Selects the node and moves it into the center of the editor, the next
+    /// time it is shown.
+    pub fn focus(&mut self, node_id: NodeId) {
+        self.focus = Some(node_id);
+    }
+
+    /// Copy without the pan and zoom of the editor. Moving the view is saved,
+    /// but does not change the pipeline.
+    pub fn without_view(&self) -> Self {
+        Self {
+            view: TSTransform::IDENTITY,
+            ..self.clone()
+        }
+    }
+
+    /// Copy of the state of `node_ids`, for moving them into another graph.
+    pub fn subset(&self, node_ids: &[NodeId]) -> Self {
+        Self {
+            node_states: self
+                .node_states
+                .iter()
+                .filter(|(node_id, _)| node_ids.contains(node_id))
+                .map(|(node_id, state)| (*node_id, *state))
+                .collect(),
+            node_order: self
+                .node_order
+                .iter()
+                .filter(|node_id| node_ids.contains(node_id))
+                .copied()
+                .collect(),
+            view: TSTransform::IDENTITY,
+            focus: None,
+        }
+    }
+}
+
+/// Error of [EditNodeGraph::add_node].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AddNodeError {
+    #[error("There is no node \"{0}\"")]
+    UnknownPath(String),
+    #[error("The pipeline has no node ids left")]
+    NoNodeId,
 }
 
 /// Trait describing a node graph to the [NodeGraphEditor].
@@ -79,7 +200,22 @@ pub trait EditNodeGraph {
 
     fn remove_node(&mut self, node_id: NodeId);
 
-    fn add_node(&mut self, path: &str) -> NodeId;
+    /// Adds a copy of the node with the same settings and input connections.
+    /// Returns the id of the copy.
+    fn duplicate_node(&mut self, node_id: NodeId) -> Option<NodeId>;
+
+    /// Disabled nodes are greyed out and their outgoing connections dashed.
+    fn is_disabled(&self, node_id: NodeId) -> bool;
+
+    fn set_disabled(&mut self, node_id: NodeId, disabled: bool);
+
+    /// Adds a node by one of the paths of [Self::addable_nodes].
+    fn add_node(&mut self, path: &str) -> Result<NodeId, AddNodeError>;
+
+    /// Moves the nodes into a new node grouping them, with `state` being
+    /// their layout. Returns the id of the group, or [None], if they cannot be
+    /// grouped.
+    fn collapse_nodes(&mut self, node_ids: &[NodeId], state: NodeGraphEditState) -> Option<NodeId>;
 
     fn addable_nodes(&self) -> Vec<&'static str>;
 }
@@ -98,6 +234,36 @@ pub trait EditNode {
     fn disconnect(&mut self, input: Self::InputId);
 
     fn ui(&mut self, ui: &mut NodeUi);
+
+    /// Add node specific entries to the context menu of the node. The returned
+    /// action is handed to the owner of the node graph.
+    fn context_menu(&mut self, ui: &mut egui::Ui) -> Option<NodeAction> {
+        let _ = ui;
+        None
+    }
+
+    /// Fraction of the current run that is done, from 0 to 1. [None] while
+    /// idle or when the node cannot tell.
+    fn progress(&self) -> Option<f32> {
+        None
+    }
+}
+
+/// Action requested from the context menu of a node, that needs to be handled
+/// by the owner of the node graph. See [NodeGraphResponse::action].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeAction {
+    /// Compare the results of multiple values of a setting.
+    ParameterSweep,
+    /// Copy a link describing the node to the clipboard, see
+    /// [crate::pipeline::diagnostic_link].
+    CopyDiagnosticLink,
+    /// Save the composite to the library, see
+    /// [crate::pipeline::nodes::composite::Library].
+    SaveToLibrary,
+    /// Replace the nodes of the composite with the library entry it was
+    /// created from.
+    UpdateFromLibrary,
 }
 
 /// Auto-trait
@@ -111,6 +277,10 @@ pub trait DynEditNode {
     fn disconnect(&mut self, input: InputId);
 
     fn ui(&mut self, ui: &mut NodeUi);
+
+    fn context_menu(&mut self, ui: &mut egui::Ui) -> Option<NodeAction>;
+
+    fn progress(&self) -> Option<f32>;
 }
 
 impl<T: EditNode> DynEditNode for T {
@@ -133,6 +303,14 @@ impl<T: EditNode> DynEditNode for T {
     fn ui(&mut self, ui: &mut NodeUi) {
         self.ui(ui)
     }
+
+    fn context_menu(&mut self, ui: &mut egui::Ui) -> Option<NodeAction> {
+        self.context_menu(ui)
+    }
+
+    fn progress(&self) -> Option<f32> {
+        self.progress()
+    }
 }
 
 /// Wrapper around [egui::Ui], additionally describing the node inputs and
@@ -143,10 +321,25 @@ pub struct NodeUi<'a> {
     outputs: &'a mut Vec<CollectedOutput>,
 }
 
+/// Describes how a pin of a node is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PinStyle {
+    pub color: Color32,
+    /// Drawn inside of the pin, so the color is not the only cue for the type
+    /// of the pin.
+    pub glyph: Option<char>,
+}
+
+impl From<Color32> for PinStyle {
+    fn from(color: Color32) -> Self {
+        Self { color, glyph: None }
+    }
+}
+
 pub(super) struct CollectedInput {
     pub id: InputId,
     pub pos: Pos2,
-    pub color: Color32,
+    pub style: PinStyle,
     pub connection: Option<NodeOutput>,
 }
 
@@ -154,7 +347,7 @@ pub(super) struct CollectedOutput {
     pub id: OutputId,
     pub type_: TypeId,
     pub pos: Pos2,
-    pub color: Color32,
+    pub style: PinStyle,
 }
 
 impl NodeUi<'_> {
@@ -162,7 +355,7 @@ impl NodeUi<'_> {
         &mut self,
         id: impl Into<InputId>,
         connection: Option<NodeOutput>,
-        color: impl Into<Color32>,
+        style: impl Into<PinStyle>,
         add_contents: impl FnOnce(&mut egui::Ui),
     ) {
         let InnerResponse {
@@ -177,7 +370,7 @@ impl NodeUi<'_> {
         self.inputs.push(CollectedInput {
             id: id.into(),
             pos: pin_pos,
-            color: color.into(),
+            style: style.into(),
             connection,
         });
     }
@@ -186,7 +379,7 @@ impl NodeUi<'_> {
         &mut self,
         id: impl Into<OutputId>,
         type_: impl Into<TypeId>,
-        color: impl Into<Color32>,
+        style: impl Into<PinStyle>,
         add_contents: impl FnOnce(&mut egui::Ui),
     ) {
         let rect = self
@@ -209,7 +402,7 @@ impl NodeUi<'_> {
             id: id.into(),
             type_: type_.into(),
             pos: pin_pos,
-            color: color.into(),
+            style: style.into(),
         });
     }
 }
@@ -238,3 +431,35 @@ impl UiNodeExt for egui::Ui {
         self.add(Label::new(text).selectable(false))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layout_is_restored() {
+        let [a, b, c] = [0, 1, 2].map(NodeId::from);
+
+        let mut state = NodeGraphEditState::new();
+        state.sync_state(&[a, b]);
+        state.node_states.get_mut(&b).unwrap().position = pos2(-40.0, 300.0);
+        state.view = TSTransform::new(Vec2::new(12.0, -5.0), 0.5);
+
+        let json = serde_json::to_string(&state).unwrap();
+        let mut loaded: NodeGraphEditState = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, state);
+
+        // Stored positions are kept, new nodes are placed next to them
+        loaded.sync_state(&[a, b, c]);
+        assert_eq!(loaded.node_states[&b], state.node_states[&b]);
+        assert_eq!(loaded.node_states[&c].position, pos2(230.0, 0.0));
+        assert_eq!(loaded.node_order, [a, b, c]);
+
+        // Without a view, it starts at the origin
+        let json = r#"{"node_states":{},"node_order":[]}"#;
+        let loaded: NodeGraphEditState = serde_json::from_str(json).unwrap();
+        assert_eq!(loaded.view, TSTransform::IDENTITY);
+
+        assert_eq!(state.without_view().view, TSTransform::IDENTITY);
+    }
+}
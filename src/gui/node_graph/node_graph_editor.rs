@@ -1,24 +1,51 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
 use egui::{
-    epaint::PathStroke, Color32, DragAndDrop, InnerResponse, Key, PointerButton, Pos2, Rect,
-    Response, Sense, Shape, Stroke, Vec2,
+    epaint::PathStroke, pos2, Align2, Color32, DragAndDrop, FontId, InnerResponse, LayerId, Order,
+    PointerButton, Pos2, Rect, Response, Sense, Shape, Stroke, Vec2,
 };
 
-use crate::gui::widgets::PanZoom;
+use crate::{
+    gui::widgets::PanZoom,
+    shortcuts::{self, Action, ShortcutContext},
+};
 
 use super::{
-    add_node_popup::AddNodePopup, draw_cut::DrawCut, frame::NodeFrame, EditNodeGraph, InputId,
-    NodeGraphEditState, NodeId, NodeOutput, NodeUi, OutputId, TypeId,
+    add_node_popup::AddNodePopup,
+    draw_cut::DrawCut,
+    frame::{NodeFrame, NodeFrameState},
+    snapping::{self, Guides, Snapping, GUIDE_DISTANCE, MIN_GRID_DOT_SPACING},
+    ConnectionActivity, ConnectionBadge, EditNodeGraph, InputId, NodeAction, NodeGraphEditState,
+    NodeId, NodeOutput, NodeProgress, NodeUi, NodeWarning, OutputId, PinStyle, TypeId,
 };
 
+/// Glyphs inside of pins are hidden, when they would be smaller than this on
+/// screen, in points.
+const MIN_GLYPH_SCREEN_SIZE: f32 = 6.0;
+
+/// How long errors are shown at the bottom of the editor, in seconds.
+const TOAST_DURATION: f64 = 4.0;
+
 /// Response returned to the caller from [NodeGraphEditor::show].
 pub struct NodeGraphResponse {
-    pub selected: Option<NodeId>,
     /// The node being double clicked.
     pub activated: Option<NodeId>,
+    /// The node the user requested to restart from its context menu.
+    pub restarted: Option<NodeId>,
+    /// The node, whose current run the user cancelled.
+    pub cancelled: Option<NodeId>,
+    /// Node specific action requested from the context menu of a node.
+    pub action: Option<(NodeId, NodeAction)>,
+    /// Where the nodes are on screen, to anchor popups to them.
+    pub node_rects: HashMap<NodeId, Rect>,
 }
 
+/// Shows a preview of the data flowing into an input.
+type PreviewUi<'a> = dyn Fn(&mut egui::Ui, NodeId, InputId) + 'a;
+
 /// An editor for a node graph.
 ///
 /// The node graph must implement the [EditNodeGraph] trait. Every node in the
@@ -26,43 +53,129 @@ pub struct NodeGraphResponse {
 pub struct NodeGraphEditor<'a> {
     pipeline: &'a mut dyn EditNodeGraph,
     state: &'a mut NodeGraphEditState,
+    scale: f32,
+    activity: Option<&'a HashMap<(NodeId, InputId), ConnectionActivity>>,
+    badges: Option<&'a HashMap<(NodeId, InputId), ConnectionBadge>>,
+    progress: Option<&'a HashMap<NodeId, NodeProgress>>,
+    warnings: Option<&'a HashMap<NodeId, Vec<NodeWarning>>>,
+    snapping: Snapping,
+    preview: Option<&'a PreviewUi<'a>>,
 }
 
+/// Seconds a connection has to be hovered, before its preview is shown.
+const PREVIEW_DELAY: f64 = 0.4;
+
 impl<'a> NodeGraphEditor<'a> {
     pub fn new(pipeline: &'a mut impl EditNodeGraph, state: &'a mut NodeGraphEditState) -> Self {
         Self {
             pipeline: pipeline as &mut dyn EditNodeGraph,
             state,
+            scale: 1.0,
+            activity: None,
+            badges: None,
+            progress: None,
+            warnings: None,
+            snapping: Snapping::default(),
+            preview: None,
         }
     }
 
+    /// Scales pins, connections and node headers, independent of the zoom of
+    /// the editor.
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Data flowing through the connections, keyed by the node and input
+    /// receiving it. Active connections are drawn brighter and thicker.
+    pub fn activity(
+        mut self,
+        activity: &'a HashMap<(NodeId, InputId), ConnectionActivity>,
+    ) -> Self {
+        self.activity = Some(activity);
+        self
+    }
+
+    /// Badges drawn on the connections next to the inputs receiving them,
+    /// explained when hovered.
+    pub fn badges(mut self, badges: &'a HashMap<(NodeId, InputId), ConnectionBadge>) -> Self {
+        self.badges = Some(badges);
+        self
+    }
+
+    /// Recent progress of the nodes, plotted at the bottom of each node that
+    /// is working.
+    pub fn progress(mut self, progress: &'a HashMap<NodeId, NodeProgress>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Warnings shown at the bottom of the nodes, explained when hovered.
+    pub fn warnings(mut self, warnings: &'a HashMap<NodeId, Vec<NodeWarning>>) -> Self {
+        self.warnings = Some(warnings);
+        self
+    }
+
+    /// How nodes snap to a grid and to each other, while they are dragged.
+    /// Holding Alt places them freely. The grid is drawn as dots, when zoomed
+    /// in far enough.
+    pub fn snapping(mut self, snapping: Snapping) -> Self {
+        self.snapping = snapping;
+        self
+    }
+
+    /// Shows a preview of the data flowing into an input, after its
+    /// connection has been hovered for [PREVIEW_DELAY].
+    pub fn preview(mut self, preview: &'a PreviewUi<'a>) -> Self {
+        self.preview = Some(preview);
+        self
+    }
+
     fn get_pipeline_state_mut(&mut self) -> (&mut dyn EditNodeGraph, &mut NodeGraphEditState) {
         (self.pipeline, self.state)
     }
 
-    fn sense_pin_drag(ui: &mut egui::Ui, pos: Pos2, to_left: bool) -> Response {
-        let mut rect = Rect::from_center_size(pos, Vec2::splat(18.0));
+    fn sense_pin_drag(ui: &mut egui::Ui, pos: Pos2, to_left: bool, scale: f32) -> Response {
+        // Pins are one row apart, so the hit area only grows vertically until
+        // it would cover the neighboring pins
+        let size = Vec2::new(18.0 * scale, 18.0 * scale.min(1.0));
+        let mut rect = Rect::from_center_size(pos, size);
         match to_left {
-            true => rect.min.x -= 8.0,
-            false => rect.max.x += 8.0,
+            true => rect.min.x -= 8.0 * scale,
+            false => rect.max.x += 8.0 * scale,
         }
         ui.allocate_rect(rect, Sense::click_and_drag())
     }
 
-    #[allow(non_upper_case_globals)]
     pub fn show(&mut self, ui: &mut egui::Ui) -> NodeGraphResponse {
-        const line_with: f32 = 2.0;
-        const pin_hover_point_radius: f32 = 2.0;
-        const pin_radius: f32 = 4.0;
-        const pin_stroke: Stroke = Stroke {
-            width: 0.8,
+        let scale = self.scale;
+
+        let line_with: f32 = 2.0 * scale;
+        // Width around a connection, in which it is hovered or cut
+        let connection_hit_width: f32 = 6.0 * scale;
+        let pin_hover_point_radius: f32 = 2.0 * scale;
+        let pin_radius: f32 = 4.0 * scale;
+        let pin_stroke = Stroke {
+            width: 0.8 * scale,
             color: Color32::BLACK,
         };
 
+        // Actions on a selected node apply to every selected node
         let selected_id = ui.id().with("selected");
-        let mut selected: Option<NodeId> = ui.data(|d| d.get_temp(selected_id)).unwrap_or_default();
+        let mut selected: HashSet<NodeId> =
+            ui.data(|d| d.get_temp(selected_id)).unwrap_or_default();
+        let mut collapse_failed = false;
 
         let mut activated = None;
+        let mut restarted = None;
+        let mut cancelled = None;
+        let mut action = None;
+
+        let focus = self.state.focus.take();
+        if let Some(node_id) = focus {
+            selected = HashSet::from([node_id]);
+        }
 
         let following_id = ui.id().with("following_node");
         let following_node: Option<NodeId> = ui
@@ -70,14 +183,28 @@ impl<'a> NodeGraphEditor<'a> {
             .map(|id| id.into());
 
         let anything_focused = ui.ctx().memory(|mem| mem.focused()).is_some();
+        let graph_active = !anything_focused && ui.ui_contains_pointer();
+        let shift = ui.input(|i| i.modifiers.shift);
 
+        let activity = self.activity;
+        let badges = self.badges;
+        let progress = self.progress;
+        let warnings = self.warnings;
+        let preview = self.preview;
+        let grid = self.snapping.grid;
+        let snapping = match ui.input(|i| i.modifiers.alt) {
+            true => Snapping::default(),
+            false => self.snapping,
+        };
         let (pipeline, state) = self.get_pipeline_state_mut();
+        let mut view = state.view;
+        let mut pan_zoom = PanZoom::new().transform(&mut view);
 
         let InnerResponse {
             response,
-            inner: (connections, transform),
+            inner: (connections, node_rects, transform),
             ..
-        } = PanZoom::new().show(ui, |ui, transform| {
+        } = pan_zoom.show(ui, |ui, transform| {
             let node_ids = pipeline.get_node_ids();
 
             state.sync_state(&node_ids);
@@ -86,6 +213,7 @@ impl<'a> NodeGraphEditor<'a> {
             // between drawing of nodes
             let origin = ui.min_rect().min.to_vec2();
 
+            let grid_op = ui.painter().add(Shape::Noop);
             let bg_op = ui.painter().add(Shape::Noop);
 
             let mut connections = Vec::<(Pos2, NodeOutput, NodeId, InputId)>::new();
@@ -93,26 +221,39 @@ impl<'a> NodeGraphEditor<'a> {
 
             let mut pending_connection_end = None;
 
-            let mut to_top = None;
+            let mut to_top = focus;
+            let mut toggle_disabled = None;
+            let mut focus_rect = None;
+
+            // Rects of the nodes at their new positions, for alignment guides
+            let mut node_rects = Vec::<(NodeId, Rect)>::new();
+            // The node being dragged, and whether it was released
+            let mut dragged = None;
+            // How far the dragged node moved this frame
+            let mut drag_delta = Vec2::ZERO;
 
             let to_delete_id = ui.id().with("to_delete");
 
-            let delete = !anything_focused
-                && ui
+            let pressed = match graph_active {
+                true => ui
                     .ctx()
-                    .input(|i| i.key_pressed(Key::Delete) || i.key_pressed(Key::Backspace));
-            let to_delete = if delete {
-                selected
-            } else {
-                ui.data_mut(|d| {
-                    d.get_temp::<NodeId>(to_delete_id).map(|id| {
-                        d.remove::<NodeId>(to_delete_id);
-                        id
-                    })
-                })
+                    .input(|i| shortcuts::pressed(i, ShortcutContext::Graph)),
+                false => Vec::new(),
+            };
+            let mut to_duplicate = match pressed.contains(&Action::DuplicateNode) {
+                true => in_order(&selected, &state.node_order),
+                false => Vec::new(),
+            };
+            let mut to_collapse = None;
+            let to_delete = match pressed.contains(&Action::DeleteNode) {
+                true => selected.clone(),
+                false => ui
+                    .data_mut(|d| d.remove_temp::<HashSet<NodeId>>(to_delete_id))
+                    .unwrap_or_default(),
             };
 
             for node_id in &state.node_order {
+                let disabled = pipeline.is_disabled(*node_id);
                 let node = pipeline.get_node_mut(*node_id);
                 let Some(node) = node else {
                     eprintln!("Node not found: {:?}", node_id);
@@ -121,12 +262,26 @@ impl<'a> NodeGraphEditor<'a> {
 
                 let (mut inputs, mut outputs) = (Vec::new(), Vec::new());
 
+                let working = node.progress().is_some();
+                let mut cancel = false;
+                let position = state.node_states[node_id].position;
+
                 let response = NodeFrame::new(ui.id().with(node_id), node.name())
                     .state(state.node_states.get_mut(node_id).unwrap())
                     .color(node.color())
-                    .selected(matches!(selected, Some(id) if id == *node_id))
+                    .header_scale(scale)
+                    .selected(selected.contains(node_id))
                     .sense(Sense::click_and_drag())
                     .follow_mouse(matches!(following_node, Some(id) if id == *node_id))
+                    .progress(progress.and_then(|progress| progress.get(node_id)))
+                    .cancel(working.then_some(&mut cancel))
+                    .disabled(disabled)
+                    .snap(snapping.grid)
+                    .warnings(
+                        warnings
+                            .and_then(|warnings| warnings.get(node_id))
+                            .map_or(&[], Vec::as_slice),
+                    )
                     .show(ui, origin, |ui| {
                         node.ui(&mut NodeUi {
                             ui,
@@ -139,26 +294,101 @@ impl<'a> NodeGraphEditor<'a> {
                     activated = Some(*node_id);
                 }
 
+                if focus == Some(*node_id) {
+                    focus_rect = Some(response.rect);
+                }
+
+                node_rects.push((
+                    *node_id,
+                    Rect::from_min_size(
+                        state.node_states[node_id].position + origin,
+                        response.rect.size(),
+                    ),
+                ));
+                if response.dragged() {
+                    dragged = Some((*node_id, false));
+                    drag_delta = state.node_states[node_id].position - position;
+                } else if response.drag_stopped() {
+                    dragged = Some((*node_id, true));
+                }
+
+                if cancel {
+                    cancelled = Some(*node_id);
+                }
+
                 response.context_menu(|ui| {
                     ui.label("Node");
+                    if ui
+                        .button("Restart")
+                        .on_hover_text("Recreate the task of this node, keeping its connections")
+                        .clicked()
+                    {
+                        ui.close_menu();
+                        restarted = Some(*node_id);
+                    }
+                    if ui
+                        .button(match disabled {
+                            true => "Enable",
+                            false => "Disable",
+                        })
+                        .on_hover_text(
+                            "Disabled nodes keep their settings and connections, but do not \
+                             process anything",
+                        )
+                        .clicked()
+                    {
+                        ui.close_menu();
+                        toggle_disabled = Some((*node_id, !disabled));
+                    }
+                    if ui.button("Duplicate").clicked() {
+                        ui.close_menu();
+                        to_duplicate = acted_on(*node_id, &selected, &state.node_order);
+                    }
+                    if ui
+                        .button("Collapse to Group")
+                        .on_hover_text("Select several nodes to group them with this one")
+                        .clicked()
+                    {
+                        ui.close_menu();
+                        to_collapse = Some(acted_on(*node_id, &selected, &state.node_order));
+                    }
                     if ui.button("Delete").clicked() {
                         ui.close_menu();
-                        ui.data_mut(|d| d.insert_temp(to_delete_id, *node_id))
+                        let node_ids = acted_on(*node_id, &selected, &state.node_order);
+                        ui.data_mut(|d| {
+                            d.insert_temp(
+                                to_delete_id,
+                                node_ids.into_iter().collect::<HashSet<_>>(),
+                            )
+                        })
+                    }
+                    if ui
+                        .button("Copy Diagnostic Link")
+                        .on_hover_text(
+                            "Copy the settings of this node and what it produced, to paste \
+                             them into a bug report",
+                        )
+                        .clicked()
+                    {
+                        ui.close_menu();
+                        action = Some((*node_id, NodeAction::CopyDiagnosticLink));
+                    }
+
+                    if let Some(node_action) = node.context_menu(ui) {
+                        ui.close_menu();
+                        action = Some((*node_id, node_action));
                     }
                 });
 
-                // Remove all connections to the outputs of the node that is
+                // Remove all connections to the outputs of the nodes that are
                 // being deleted
-                if let Some(to_delete) = to_delete {
-                    if to_delete == *node_id {
-                        continue;
-                    } else {
-                        for input in inputs.iter() {
-                            if let Some(connection) = input.connection {
-                                if connection.node_id == to_delete {
-                                    node.disconnect(input.id);
-                                }
-                            }
+                if to_delete.contains(node_id) {
+                    continue;
+                }
+                for input in inputs.iter() {
+                    if let Some(connection) = input.connection {
+                        if to_delete.contains(&connection.node_id) {
+                            node.disconnect(input.id);
                         }
                     }
                 }
@@ -169,11 +399,25 @@ impl<'a> NodeGraphEditor<'a> {
                     && ui.input(|i| i.pointer.primary_pressed())
                 {
                     to_top = Some(*node_id);
-                    selected = Some(*node_id);
+
+                    // Pressing a selected node keeps the selection, so it can
+                    // be dragged together
+                    if shift {
+                        if !selected.remove(node_id) {
+                            selected.insert(*node_id);
+                        }
+                    } else if !selected.contains(node_id) {
+                        selected = HashSet::from([*node_id]);
+                    }
+                }
+
+                // Clicking without dragging selects only the node
+                if response.clicked() && !shift {
+                    selected = HashSet::from([*node_id]);
                 }
 
                 for input in inputs.iter() {
-                    let response = Self::sense_pin_drag(ui, input.pos, true);
+                    let response = Self::sense_pin_drag(ui, input.pos, true, scale);
 
                     if response.dragged_by(PointerButton::Primary) {
                         response.dnd_set_drag_payload(DragPayload(
@@ -204,8 +448,14 @@ impl<'a> NodeGraphEditor<'a> {
                         connections.push((input.pos, connection, *node_id, input.id));
                     }
 
-                    ui.painter()
-                        .circle(input.pos, pin_radius, input.color, pin_stroke);
+                    draw_pin(
+                        ui.painter(),
+                        input.pos,
+                        input.style,
+                        pin_radius,
+                        pin_stroke,
+                        transform.scaling,
+                    );
                     if response.hovered() {
                         ui.painter().circle_filled(
                             input.pos,
@@ -216,7 +466,7 @@ impl<'a> NodeGraphEditor<'a> {
                 }
 
                 for output in outputs.iter() {
-                    let response = Self::sense_pin_drag(ui, output.pos, false);
+                    let response = Self::sense_pin_drag(ui, output.pos, false, scale);
 
                     if response.dragged_by(PointerButton::Primary) {
                         response.dnd_set_drag_payload(DragPayload(
@@ -251,8 +501,14 @@ impl<'a> NodeGraphEditor<'a> {
                         output.pos,
                     );
 
-                    ui.painter()
-                        .circle(output.pos, pin_radius, output.color, pin_stroke);
+                    draw_pin(
+                        ui.painter(),
+                        output.pos,
+                        output.style,
+                        pin_radius,
+                        pin_stroke,
+                        transform.scaling,
+                    );
                     if response.hovered() {
                         ui.painter().circle_filled(
                             output.pos,
@@ -267,9 +523,122 @@ impl<'a> NodeGraphEditor<'a> {
                 state.to_top(node_id);
             }
 
-            if let Some(to_delete) = to_delete {
-                pipeline.remove_node(to_delete);
-                selected = None;
+            // The other selected nodes follow the dragged one
+            let moving = match dragged {
+                Some((node_id, _)) if selected.contains(&node_id) => selected.clone(),
+                Some((node_id, _)) => HashSet::from([node_id]),
+                None => HashSet::new(),
+            };
+            if let Some((node_id, false)) = dragged {
+                for (id, node_state) in state.node_states.iter_mut() {
+                    if *id != node_id && moving.contains(id) {
+                        node_state.position += drag_delta;
+                    }
+                }
+            }
+
+            // Align the dragged node with the edges of the nodes, that are not
+            // moved along
+            if let Some((node_id, released)) = dragged.filter(|_| snapping.guides) {
+                let rect = node_rects.iter().find(|(id, _)| *id == node_id);
+                if let Some((_, rect)) = rect {
+                    let others = node_rects
+                        .iter()
+                        .filter(|(id, _)| !moving.contains(id))
+                        .map(|(_, rect)| *rect);
+                    let guides = Guides::find(*rect, others, GUIDE_DISTANCE / transform.scaling);
+
+                    if released {
+                        let offset = guides.offset(*rect);
+                        for node_id in &moving {
+                            if let Some(node_state) = state.node_states.get_mut(node_id) {
+                                node_state.position += offset;
+                            }
+                        }
+                    } else {
+                        let stroke = Stroke::new(
+                            1.0 / transform.scaling,
+                            ui.visuals().selection.stroke.color,
+                        );
+                        for line in guides.lines(*rect) {
+                            ui.painter().line_segment(line, stroke);
+                        }
+                    }
+                }
+            }
+
+            if let Some(grid) = grid.filter(|grid| grid * transform.scaling >= MIN_GRID_DOT_SPACING)
+            {
+                let visible = ui.clip_rect().translate(-origin);
+                let first = snapping::snap_to_grid(visible.min, grid);
+                let radius = 1.0 / transform.scaling;
+                let color = ui.visuals().weak_text_color().gamma_multiply(0.5);
+
+                let mut dots = Vec::new();
+                let mut y = first.y;
+                while y <= visible.max.y {
+                    let mut x = first.x;
+                    while x <= visible.max.x {
+                        dots.push(Shape::circle_filled(pos2(x, y) + origin, radius, color));
+                        x += grid;
+                    }
+                    y += grid;
+                }
+                ui.painter().set(grid_op, Shape::Vec(dots));
+            }
+
+            // Center the focused node, keeping the zoom
+            if let Some(rect) = focus_rect {
+                transform.translation +=
+                    transform.scaling * (ui.clip_rect().center() - rect.center());
+                ui.ctx().request_repaint();
+            }
+
+            if let Some((node_id, disabled)) = toggle_disabled {
+                pipeline.set_disabled(node_id, disabled);
+            }
+
+            for node_id in &to_delete {
+                pipeline.remove_node(*node_id);
+                selected.remove(node_id);
+            }
+
+            // The copies are placed next to the originals, so they are
+            // visible, and get selected instead
+            to_duplicate.retain(|node_id| !to_delete.contains(node_id));
+            if !to_duplicate.is_empty() {
+                selected.clear();
+            }
+            for original in to_duplicate {
+                if let Some(node_id) = pipeline.duplicate_node(original) {
+                    let position = state.node_states[&original].position + Vec2::splat(30.0);
+                    state
+                        .node_states
+                        .insert(node_id, NodeFrameState { position });
+                    state.node_order.push(node_id);
+                    selected.insert(node_id);
+                }
+            }
+
+            // The group takes the place of its top left node
+            if let Some(node_ids) = to_collapse {
+                let position = node_ids
+                    .iter()
+                    .filter_map(|node_id| state.node_states.get(node_id))
+                    .map(|node_state| node_state.position)
+                    .reduce(Pos2::min)
+                    .unwrap_or_default();
+
+                match pipeline.collapse_nodes(&node_ids, state.subset(&node_ids)) {
+                    Some(node_id) => {
+                        state
+                            .node_states
+                            .insert(node_id, NodeFrameState { position });
+                        state.node_order.push(node_id);
+                        selected = HashSet::from([node_id]);
+                    }
+                    None => collapse_failed = true,
+                }
             }
 
             // Draw connection that the user is currently creating
@@ -298,25 +667,149 @@ impl<'a> NodeGraphEditor<'a> {
             let connections = connections
                 .iter()
                 .filter_map(|(input_pos, output, node_id, input_id)| {
-                    output_positions
-                        .get(output)
-                        .map(|output_pos| (*input_pos, *output_pos, *node_id, *input_id))
+                    output_positions.get(output).map(|output_pos| {
+                        let disabled = pipeline.is_disabled(output.node_id);
+                        (*input_pos, *output_pos, *node_id, *input_id, disabled)
+                    })
                 })
                 .collect::<Vec<_>>();
 
-            // Draw existing connections
-            let shapes = connections
-                .iter()
-                .map(|(input_pos, output_pos, _, _)| Shape::LineSegment {
-                    points: [*input_pos, *output_pos],
-                    stroke: PathStroke::new(line_with, Color32::WHITE),
-                })
-                .collect::<Vec<_>>();
+            let hovered_connection = ui
+                .ctx()
+                .input(|i| i.pointer.hover_pos())
+                .filter(|_| DragAndDrop::payload::<DragPayload>(ui.ctx()).is_none())
+                .map(|pos| transform.inverse() * pos)
+                .and_then(|pos| {
+                    connections.iter().position(|(input_pos, output_pos, ..)| {
+                        point_segment_distance(pos, *input_pos, *output_pos)
+                            <= connection_hit_width / 2.0
+                    })
+                });
+
+            let connection_activity = |node_id: &NodeId, input_id: &InputId| {
+                activity.and_then(|activity| activity.get(&(*node_id, *input_id)))
+            };
+
+            // Draw existing connections. Connections from disabled nodes are
+            // dashed
+            let mut shapes = Vec::new();
+            for (i, (input_pos, output_pos, node_id, input_id, disabled)) in
+                connections.iter().enumerate()
+            {
+                let (width, color) = match connection_activity(node_id, input_id) {
+                    Some(activity) => connection_stroke(line_with, activity.intensity),
+                    None => (line_with, Color32::WHITE),
+                };
+                let width = match hovered_connection == Some(i) {
+                    true => width * 2.0,
+                    false => width,
+                };
+
+                match disabled {
+                    true => Shape::dashed_line_many(
+                        &[*output_pos, *input_pos],
+                        Stroke::new(width, color),
+                        4.0 * line_with,
+                        3.0 * line_with,
+                        &mut shapes,
+                    ),
+                    false => shapes.push(Shape::LineSegment {
+                        points: [*input_pos, *output_pos],
+                        stroke: PathStroke::new(width, color),
+                    }),
+                }
+            }
 
             ui.painter().set(bg_op, Shape::Vec(shapes));
 
-            (connections, *transform)
+            // Badges sit on the connections, just before the input pins
+            let pointer = ui
+                .ctx()
+                .input(|i| i.pointer.hover_pos())
+                .map(|pos| transform.inverse() * pos);
+            let mut hovered_badge = None;
+            for (input_pos, output_pos, node_id, input_id, _) in connections.iter() {
+                let Some(badge) = badges.and_then(|badges| badges.get(&(*node_id, *input_id)))
+                else {
+                    continue;
+                };
+
+                let direction = (*output_pos - *input_pos).normalized();
+                let center = *input_pos + direction * 16.0 * scale;
+                let color = match badge.highlighted {
+                    true => ui.visuals().warn_fg_color,
+                    false => Color32::LIGHT_GRAY,
+                };
+
+                let galley = ui.painter().layout_no_wrap(
+                    badge.label.clone(),
+                    FontId::monospace(7.0 * scale),
+                    Color32::BLACK,
+                );
+                let rect = Rect::from_center_size(center, galley.size() + Vec2::splat(2.0 * scale));
+                ui.painter().rect_filled(rect, 2.0 * scale, color);
+                ui.painter()
+                    .galley(rect.min + Vec2::splat(scale), galley, Color32::BLACK);
+
+                if pointer.is_some_and(|pos| rect.contains(pos)) {
+                    hovered_badge = Some(badge);
+                }
+            }
+
+            // The preview appears, once the same connection is hovered for a
+            // moment
+            let hovered = hovered_connection.map(|i| (connections[i].2, connections[i].3));
+            let hover_id = ui.id().with("hovered_connection");
+            let now = ui.input(|i| i.time);
+            let hovered_since = hovered.map(|connection| {
+                let since = ui
+                    .data(|d| d.get_temp::<((NodeId, InputId), f64)>(hover_id))
+                    .filter(|(hovered, _)| *hovered == connection)
+                    .map_or(now, |(_, since)| since);
+                ui.data_mut(|d| d.insert_temp(hover_id, (connection, since)));
+                since
+            });
+            if hovered.is_none() {
+                ui.data_mut(|d| d.remove::<((NodeId, InputId), f64)>(hover_id));
+            }
+
+            if let Some(badge) = hovered_badge {
+                egui::show_tooltip_at_pointer(
+                    ui.ctx(),
+                    ui.layer_id(),
+                    ui.id().with("connection_badge"),
+                    |ui| ui.label(&badge.description),
+                );
+            } else if let (Some((node_id, input_id)), Some(since)) = (hovered, hovered_since) {
+                let activity = connection_activity(&node_id, &input_id);
+                let due = now - since >= PREVIEW_DELAY;
+                if preview.is_some() && !due {
+                    ui.ctx().request_repaint_after(Duration::from_secs_f64(
+                        PREVIEW_DELAY - (now - since),
+                    ));
+                }
+                let preview = preview.filter(|_| due);
+
+                if activity.is_some() || preview.is_some() {
+                    egui::show_tooltip_at_pointer(
+                        ui.ctx(),
+                        ui.layer_id(),
+                        ui.id().with("connection_activity"),
+                        |ui| {
+                            if let Some(activity) = activity {
+                                ui.label(&activity.description);
+                            }
+                            if let Some(preview) = preview {
+                                preview(ui, node_id, input_id);
+                            }
+                        },
+                    );
+                }
+            }
+
+            (connections, node_rects, *transform)
         });
+        state.view = view;
 
         // Connection cutting
         if let (_, Some(line)) = DrawCut.ui(ui) {
@@ -325,9 +818,11 @@ impl<'a> NodeGraphEditor<'a> {
                 .map(|pos| transform.inverse() * *pos)
                 .collect::<Vec<_>>();
 
-            for (p1, p2, node_id, input_id) in connections.iter() {
+            for (p1, p2, node_id, input_id, _) in connections.iter() {
                 for (start, end) in line.iter().zip(line.iter().skip(1)) {
-                    if line_intersects(*p1, *p2, *start, *end).is_some() {
+                    if line_intersects(*p1, *p2, *start, *end).is_some()
+                        || segment_distance(*p1, *p2, *start, *end) <= connection_hit_width / 2.0
+                    {
                         pipeline
                             .get_node_mut(*node_id)
                             .map(|node| node.disconnect(*input_id));
@@ -337,50 +832,145 @@ impl<'a> NodeGraphEditor<'a> {
             }
         }
 
-        // Context menu to add nodes
+        // Context menu to add nodes. It stays open, when adding fails
+        let toast_id = ui.id().with("toast");
         response.context_menu(|ui| {
             if let Some(path) = AddNodePopup::new(&pipeline.addable_nodes()).show(ui) {
-                ui.close_menu();
-                let node_id = pipeline.add_node(path);
-                selected = Some(node_id);
+                match pipeline.add_node(path) {
+                    Ok(node_id) => {
+                        ui.close_menu();
+                        selected = HashSet::from([node_id]);
 
-                ui.data_mut(|d| {
-                    d.insert_temp::<usize>(following_id, node_id.into());
-                });
+                        ui.data_mut(|d| {
+                            d.insert_temp::<usize>(following_id, node_id.into());
+                        });
+                    }
+                    Err(e) => {
+                        let time = ui.input(|i| i.time);
+                        ui.data_mut(|d| d.insert_temp(toast_id, (e.to_string(), time)));
+                    }
+                }
             }
         });
 
-        // Mass select
-        // if response.dragged_by(PointerButton::Primary) {
-        //     if let (Some(start), Some(end)) = (
-        //         ui.ctx().input(|i| i.pointer.press_origin()),
-        //         ui.ctx().input(|i| i.pointer.hover_pos()),
-        //     ) {
-        //         // let start = transform.inverse() * start;
-        //         // let end = transform.inverse() * end;
-
-        //         ui.painter().circle_filled(start, 3.0, Color32::RED);
-        //         ui.painter().circle_filled(end, 3.0, Color32::RED);
-        //     }
-        // }
-
-        // User clicked on background
+        if collapse_failed {
+            let time = ui.input(|i| i.time);
+            let message = "Groups can only be nested three levels deep".to_string();
+            ui.data_mut(|d| d.insert_temp(toast_id, (message, time)));
+        }
+
+        show_toast(ui, toast_id, response.rect);
+
+        // User clicked on background. Holding shift keeps the selection
         if response.is_pointer_button_down_on()
             && ui.input(|mem| mem.pointer.primary_pressed())
             && !anything_focused
+            && !shift
         {
-            selected = None;
+            selected.clear();
+        }
+
+        // Mass select the nodes touching the rectangle dragged on the
+        // background, in addition to the selection when the drag started
+        let band_id = ui.id().with("band");
+        if response.drag_started_by(PointerButton::Primary) {
+            ui.data_mut(|d| d.insert_temp(band_id, selected.clone()));
+        }
+        if response.dragged_by(PointerButton::Primary) {
+            if let (Some(start), Some(end)) = (
+                ui.ctx().input(|i| i.pointer.press_origin()),
+                ui.ctx().input(|i| i.pointer.hover_pos()),
+            ) {
+                let band = Rect::from_two_pos(start, end);
+                let in_band = transform.inverse() * band;
+
+                selected = ui
+                    .data(|d| d.get_temp::<HashSet<NodeId>>(band_id))
+                    .unwrap_or_default();
+                selected.extend(
+                    node_rects
+                        .iter()
+                        .filter(|(_, rect)| rect.intersects(in_band))
+                        .map(|(node_id, _)| *node_id),
+                );
+
+                let selection = ui.visuals().selection;
+                ui.painter_at(response.rect)
+                    .with_layer_id(LayerId::new(Order::Foreground, band_id))
+                    .rect(
+                        band,
+                        0.0,
+                        selection.bg_fill.gamma_multiply(0.2),
+                        selection.stroke,
+                    );
+            }
+        } else if response.drag_stopped() {
+            ui.data_mut(|d| d.remove::<HashSet<NodeId>>(band_id));
         }
 
         ui.memory_mut(|mem| mem.data.insert_temp(selected_id, selected));
 
         NodeGraphResponse {
-            selected,
             activated,
+            restarted,
+            cancelled,
+            action,
+            node_rects: node_rects
+                .into_iter()
+                .map(|(node_id, rect)| (node_id, transform * rect))
+                .collect(),
         }
     }
 }
 
+/// The selected nodes in drawing order.
+fn in_order(selected: &HashSet<NodeId>, node_order: &[NodeId]) -> Vec<NodeId> {
+    node_order
+        .iter()
+        .filter(|node_id| selected.contains(node_id))
+        .copied()
+        .collect()
+}
+
+/// Nodes an action from the context menu of `node_id` applies to. These are
+/// all selected nodes, if it is selected, and only the node otherwise.
+fn acted_on(node_id: NodeId, selected: &HashSet<NodeId>, node_order: &[NodeId]) -> Vec<NodeId> {
+    match selected.contains(&node_id) {
+        true => in_order(selected, node_order),
+        false => vec![node_id],
+    }
+}
+
+/// Shows the message stored under `id` together with the time it was stored
+/// at the bottom of `rect`, until [TOAST_DURATION] passed.
+fn show_toast(ui: &egui::Ui, id: egui::Id, rect: Rect) {
+    let Some((message, time)) = ui.data(|d| d.get_temp::<(String, f64)>(id)) else {
+        return;
+    };
+
+    let remaining = TOAST_DURATION - (ui.input(|i| i.time) - time);
+    if remaining <= 0.0 {
+        ui.data_mut(|d| d.remove::<(String, f64)>(id));
+        return;
+    }
+
+    egui::Area::new(id)
+        .order(egui::Order::Foreground)
+        .pivot(Align2::CENTER_BOTTOM)
+        .fixed_pos(rect.center_bottom() - Vec2::new(0.0, 16.0))
+        .interactable(false)
+        .show(ui.ctx(), |ui| {
+            egui::Frame::popup(ui.style())
+                .fill(ui.visuals().error_fg_color.gamma_multiply(0.3))
+                .show(ui, |ui| {
+                    ui.colored_label(ui.visuals().error_fg_color, message);
+                });
+        });
+
+    ui.ctx()
+        .request_repaint_after(std::time::Duration::from_secs_f64(remaining));
+}
+
 #[derive(Debug, Clone)]
 struct DragPayload(Pos2, NodeId, PayloadPin);
 
@@ -405,3 +995,118 @@ fn line_intersects(a: Pos2, b: Pos2, c: Pos2, d: Pos2) -> Option<Pos2> {
 
     Some(a + t * (b - a))
 }
+
+/// Distance of `p` to the line segment from `a` to `b`.
+fn point_segment_distance(p: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let t = match ab.length_sq() {
+        0.0 => 0.0,
+        length_sq => ((p - a).dot(ab) / length_sq).clamp(0.0, 1.0),
+    };
+    p.distance(a + t * ab)
+}
+
+/// Smallest distance between the line segments `a`-`b` and `c`-`d`, ignoring
+/// intersections.
+fn segment_distance(a: Pos2, b: Pos2, c: Pos2, d: Pos2) -> f32 {
+    point_segment_distance(a, c, d)
+        .min(point_segment_distance(b, c, d))
+        .min(point_segment_distance(c, a, b))
+        .min(point_segment_distance(d, a, b))
+}
+
+/// Whether the glyph inside of a pin is legible at the given zoom of the
+/// editor.
+/// Width and color of a connection, with data flowing through it at the
+/// given intensity. Idle connections are dimmed slightly.
+fn connection_stroke(line_width: f32, intensity: f32) -> (f32, Color32) {
+    let intensity = intensity.clamp(0.0, 1.0);
+    let brightness = 180.0 + 75.0 * intensity;
+
+    (
+        line_width * (1.0 + intensity),
+        Color32::from_gray(brightness as u8),
+    )
+}
+
+fn glyph_visible(pin_radius: f32, zoom: f32) -> bool {
+    glyph_size(pin_radius) * zoom >= MIN_GLYPH_SCREEN_SIZE
+}
+
+fn glyph_size(pin_radius: f32) -> f32 {
+    pin_radius * 1.5
+}
+
+fn draw_pin(
+    painter: &egui::Painter,
+    pos: Pos2,
+    style: PinStyle,
+    radius: f32,
+    stroke: Stroke,
+    zoom: f32,
+) {
+    painter.circle(pos, radius, style.color, stroke);
+
+    let Some(glyph) = style.glyph else {
+        return;
+    };
+    if !glyph_visible(radius, zoom) {
+        return;
+    }
+
+    // Pick black or white, whichever contrasts more with the pin
+    let [r, g, b, _] = style.color.to_array();
+    let luminance = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+    let text_color = match luminance > 128.0 {
+        true => Color32::BLACK,
+        false => Color32::WHITE,
+    };
+
+    painter.text(
+        pos,
+        Align2::CENTER_CENTER,
+        glyph,
+        FontId::monospace(glyph_size(radius)),
+        text_color,
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use egui::pos2;
+
+    use super::*;
+
+    #[test]
+    fn distances() {
+        let (a, b) = (pos2(0.0, 0.0), pos2(10.0, 0.0));
+
+        assert_eq!(point_segment_distance(pos2(5.0, 3.0), a, b), 3.0);
+        assert_eq!(point_segment_distance(pos2(-4.0, 3.0), a, b), 5.0);
+        assert_eq!(point_segment_distance(pos2(1.0, 1.0), a, a), 2f32.sqrt());
+
+        // Cut line ending just above the connection
+        assert_eq!(segment_distance(a, b, pos2(5.0, 10.0), pos2(5.0, 2.0)), 2.0);
+    }
+
+    #[test]
+    fn actions_apply_to_selection() {
+        let [a, b, c] = [0, 1, 2].map(NodeId::from);
+        let order = [c, a, b];
+        let selected = HashSet::from([a, c]);
+
+        assert_eq!(in_order(&selected, &order), [c, a]);
+        assert_eq!(acted_on(a, &selected, &order), [c, a]);
+        assert_eq!(acted_on(b, &selected, &order), [b]);
+        assert_eq!(acted_on(b, &HashSet::new(), &order), [b]);
+    }
+
+    #[test]
+    fn glyphs_hidden_when_zoomed_out() {
+        assert!(glyph_visible(4.0, 1.0));
+        assert!(!glyph_visible(4.0, 0.5));
+
+        // Larger pins keep their glyphs further out
+        assert!(glyph_visible(8.0, 0.5));
+    }
+}
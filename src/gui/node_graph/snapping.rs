@@ -0,0 +1,137 @@
+//! Snapping of nodes to a grid and to the edges of other nodes, while they are
+//! dragged in the [super::NodeGraphEditor]. Everything is in graph space, so it
+//! is independent of the zoom of the editor.
+
+use egui::{pos2, Pos2, Rect, Vec2};
+
+/// Distance on screen, in points, within which the edges of a dragged node
+/// are aligned with the edges of other nodes.
+pub const GUIDE_DISTANCE: f32 = 6.0;
+
+/// Grid dots are drawn, when they are at least this far apart on screen, in
+/// points.
+pub const MIN_GRID_DOT_SPACING: f32 = 16.0;
+
+/// How nodes snap while they are dragged.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Snapping {
+    /// Spacing of the grid, node positions snap to. [None] to place nodes
+    /// freely.
+    pub grid: Option<f32>,
+    /// Whether guides are shown for the edges of other nodes close to the
+    /// dragged node, which it snaps to when released.
+    pub guides: bool,
+}
+
+/// The point of the grid closest to `position`.
+pub fn snap_to_grid(position: Pos2, grid: f32) -> Pos2 {
+    pos2(
+        (position.x / grid).round() * grid,
+        (position.y / grid).round() * grid,
+    )
+}
+
+/// An edge of another node, the dragged node is aligned with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Guide {
+    /// Coordinate of the edge.
+    pub position: f32,
+    /// The node the edge belongs to.
+    pub other: Rect,
+}
+
+/// Guides for the left and top edges of a dragged node.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Guides {
+    pub left: Option<Guide>,
+    pub top: Option<Guide>,
+}
+
+impl Guides {
+    /// The closest left and top edges of `others`, that are at most `distance`
+    /// away from the ones of `rect`.
+    pub fn find(rect: Rect, others: impl IntoIterator<Item = Rect>, distance: f32) -> Self {
+        let closest = |current: Option<Guide>, position: f32, edge: f32, other: Rect| {
+            let offset = (position - edge).abs();
+            match current {
+                Some(guide) if (guide.position - edge).abs() <= offset => Some(guide),
+                _ if offset <= distance => Some(Guide { position, other }),
+                _ => current,
+            }
+        };
+
+        others
+            .into_iter()
+            .fold(Self::default(), |guides, other| Self {
+                left: closest(guides.left, other.left(), rect.left(), other),
+                top: closest(guides.top, other.top(), rect.top(), other),
+            })
+    }
+
+    /// Offset moving `rect` onto the guides.
+    pub fn offset(&self, rect: Rect) -> Vec2 {
+        Vec2::new(
+            self.left.map_or(0.0, |guide| guide.position - rect.left()),
+            self.top.map_or(0.0, |guide| guide.position - rect.top()),
+        )
+    }
+
+    /// Lines to draw for the guides, spanning `rect` and the node it is
+    /// aligned with.
+    pub fn lines(&self, rect: Rect) -> impl Iterator<Item = [Pos2; 2]> {
+        let left = self.left.map(|guide| {
+            let span = rect.union(guide.other);
+            [
+                pos2(guide.position, span.top()),
+                pos2(guide.position, span.bottom()),
+            ]
+        });
+        let top = self.top.map(|guide| {
+            let span = rect.union(guide.other);
+            [
+                pos2(span.left(), guide.position),
+                pos2(span.right(), guide.position),
+            ]
+        });
+
+        left.into_iter().chain(top)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn snaps_to_closest_grid_point() {
+        assert_eq!(snap_to_grid(pos2(14.0, 16.0), 10.0), pos2(10.0, 20.0));
+        assert_eq!(snap_to_grid(pos2(-14.0, 0.0), 10.0), pos2(-10.0, 0.0));
+        assert_eq!(snap_to_grid(pos2(3.0, 7.0), 2.5), pos2(2.5, 7.5));
+    }
+
+    #[test]
+    fn finds_closest_guides() {
+        let rect = Rect::from_min_size(pos2(100.0, 100.0), Vec2::splat(50.0));
+        let others = [
+            Rect::from_min_size(pos2(104.0, 300.0), Vec2::splat(50.0)),
+            Rect::from_min_size(pos2(98.0, 400.0), Vec2::splat(50.0)),
+            Rect::from_min_size(pos2(300.0, 120.0), Vec2::splat(50.0)),
+        ];
+
+        let guides = Guides::find(rect, others, 5.0);
+        assert_eq!(
+            guides.left,
+            Some(Guide {
+                position: 98.0,
+                other: others[1]
+            })
+        );
+        assert_eq!(guides.top, None);
+        assert_eq!(guides.offset(rect), Vec2::new(-2.0, 0.0));
+
+        let lines = guides.lines(rect).collect::<Vec<_>>();
+        assert_eq!(lines, [[pos2(98.0, 100.0), pos2(98.0, 450.0)]]);
+
+        assert_eq!(Guides::find(rect, others, 1.0), Guides::default());
+    }
+}
@@ -0,0 +1,328 @@
+use egui::{load::SizedTexture, ComboBox, DragValue, Grid, ProgressBar};
+
+use crate::{
+    node_graph::NodeId,
+    pipeline::{
+        nodes::{filter, DynPipelineNode},
+        range,
+        sweep::{self, ParameterSweep, PreviewState},
+        types::{DataMatrix, DataType},
+        Pipeline, PipelineExecutor,
+    },
+};
+
+/// Size of one preview in the result grid.
+const PREVIEW_SIZE: egui::Vec2 = egui::vec2(160.0, 160.0);
+/// Number of previews per row in the result grid.
+const PREVIEWS_PER_ROW: usize = 4;
+
+/// Window to compare the results of a filter node for multiple values of one
+/// setting side by side. Clicking a result applies its value to the node.
+///
+/// The sweep runs on temporary copies of the node, see [ParameterSweep].
+/// Closing the window cancels the sweep.
+pub struct ParameterSweepWindow {
+    node_id: NodeId,
+
+    parameter: filter::SweepParameter,
+    start: f64,
+    end: f64,
+    step: f64,
+    /// Number of A scans of the input to run the sweep on.
+    a_scan_limit: usize,
+
+    sweep: Option<RunningSweep>,
+}
+
+struct RunningSweep {
+    sweep: ParameterSweep,
+    parameter: filter::SweepParameter,
+    textures: Vec<Option<egui::TextureHandle>>,
+    applied: Option<usize>,
+}
+
+impl ParameterSweepWindow {
+    /// Returns [None], if the node is not a filter node or has no setting to
+    /// sweep.
+    pub fn new(node_id: NodeId, pipeline: &Pipeline) -> Option<Self> {
+        let node = get_filter_node(pipeline.nodes.get(&node_id)?.as_ref())?;
+        let &parameter = node.sweep_parameters().first()?;
+
+        let mut window = Self {
+            node_id,
+            parameter,
+            start: 0.0,
+            end: 0.0,
+            step: 0.0,
+            a_scan_limit: 500,
+            sweep: None,
+        };
+        window.reset_range(node);
+
+        Some(window)
+    }
+
+    /// Sets the range to a few steps, starting at the current value of the
+    /// node.
+    fn reset_range(&mut self, node: &filter::Node) {
+        let range = self.parameter.range();
+
+        self.start = node.parameter(self.parameter);
+        self.step = if self.parameter.is_integer() {
+            1.0
+        } else {
+            (self.start.abs() * 0.25).max(0.01)
+        };
+        self.end = (self.start + self.step * 4.0).min(*range.end());
+    }
+
+    /// Returns false, when the window got closed.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        pipeline: &mut Pipeline,
+        executor: &PipelineExecutor,
+    ) -> bool {
+        let mut open = true;
+
+        egui::Window::new("Parameter Sweep")
+            .id(egui::Id::new("parameter_sweep").with(self.node_id))
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let Some(node) = pipeline
+                    .nodes
+                    .get_mut(&self.node_id)
+                    .and_then(|node| get_filter_node_mut(node.as_mut()))
+                else {
+                    ui.label("The node does not exist anymore.");
+                    return;
+                };
+
+                self.settings_ui(ui, node, executor);
+
+                if let Some(running) = &mut self.sweep {
+                    ui.separator();
+                    running.ui(ui, node);
+                }
+            });
+
+        open
+    }
+
+    fn settings_ui(&mut self, ui: &mut egui::Ui, node: &filter::Node, executor: &PipelineExecutor) {
+        let range = self.parameter.range();
+        let speed = if self.parameter.is_integer() {
+            0.1
+        } else {
+            0.01
+        };
+
+        Grid::new("sweep_settings").num_columns(2).show(ui, |ui| {
+            ui.label("Setting:");
+            let selected = self.parameter;
+            ComboBox::from_id_source("sweep_parameter")
+                .selected_text(format!("{}", self.parameter))
+                .show_ui(ui, |ui| {
+                    for &parameter in node.sweep_parameters() {
+                        ui.selectable_value(
+                            &mut self.parameter,
+                            parameter,
+                            format!("{}", parameter),
+                        );
+                    }
+                });
+            if selected != self.parameter {
+                self.reset_range(node);
+            }
+            ui.end_row();
+
+            ui.label("From:");
+            ui.add(
+                DragValue::new(&mut self.start)
+                    .speed(speed)
+                    .range(range.clone()),
+            );
+            ui.end_row();
+
+            ui.label("To:");
+            ui.add(DragValue::new(&mut self.end).speed(speed).range(range));
+            ui.end_row();
+
+            ui.label("Step:");
+            let min_step = if self.parameter.is_integer() {
+                1.0
+            } else {
+                0.001
+            };
+            ui.add(
+                DragValue::new(&mut self.step)
+                    .speed(speed)
+                    .range(min_step..=f64::MAX),
+            );
+            ui.end_row();
+
+            ui.label("Preview A Scans:");
+            ui.add(DragValue::new(&mut self.a_scan_limit).range(16..=4096))
+                .on_hover_text(
+                    "The sweep only processes this many A scans, from the start of the range \
+                     selector or of the input",
+                );
+            ui.end_row();
+        });
+
+        let values = sweep::sweep_values(
+            self.start..=self.end,
+            self.step,
+            self.parameter.is_integer(),
+        );
+
+        ui.horizontal(|ui| {
+            let is_running = self
+                .sweep
+                .as_ref()
+                .is_some_and(|running| !running.sweep.is_finished());
+
+            if ui
+                .add_enabled(!values.is_empty(), egui::Button::new("Run"))
+                .on_hover_text(format!(
+                    "Runs {} copies of the node (at most {})",
+                    values.len(),
+                    sweep::MAX_SWEEP_STEPS
+                ))
+                .clicked()
+            {
+                self.sweep = self.start_sweep(node, values, executor);
+            }
+
+            if ui
+                .add_enabled(is_running, egui::Button::new("Cancel"))
+                .clicked()
+            {
+                self.sweep = None;
+            }
+        });
+    }
+
+    fn start_sweep(
+        &self,
+        node: &filter::Node,
+        values: Vec<f64>,
+        executor: &PipelineExecutor,
+    ) -> Option<RunningSweep> {
+        let input = node.input.connection()?;
+        let input = executor.get_output(input.node_id, input.output_id)?;
+
+        let copies = values
+            .into_iter()
+            .map(|value| {
+                let mut copy = node.clone();
+                copy.set_parameter(self.parameter, value);
+                (value, Box::new(copy) as Box<dyn DynPipelineNode>)
+            })
+            .collect::<Vec<_>>();
+
+        let count = copies.len();
+
+        Some(RunningSweep {
+            sweep: ParameterSweep::start(
+                executor,
+                input,
+                filter::InputId::MScan.into(),
+                filter::OutputId::Filtered.into(),
+                copies,
+                range::preview_slice(&range::selected(), self.a_scan_limit),
+            ),
+            parameter: self.parameter,
+            textures: vec![None; count],
+            applied: None,
+        })
+    }
+}
+
+impl RunningSweep {
+    fn ui(&mut self, ui: &mut egui::Ui, node: &mut filter::Node) {
+        if let Some(error) = self.sweep.input_error() {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+
+        if !self.sweep.is_finished() {
+            ui.ctx().request_repaint();
+        }
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            Grid::new("sweep_results").show(ui, |ui| {
+                for (i, preview) in self.sweep.previews().iter().enumerate() {
+                    ui.vertical(|ui| {
+                        ui.label(format!("{} = {}", self.parameter, preview.value));
+
+                        match &*preview.state.borrow() {
+                            PreviewState::Running(progress) => {
+                                ui.add_sized(
+                                    PREVIEW_SIZE,
+                                    ProgressBar::new(*progress).rounding(3.0),
+                                );
+                            }
+                            PreviewState::Failed(e) => {
+                                ui.add_sized(
+                                    PREVIEW_SIZE,
+                                    egui::Label::new(
+                                        egui::RichText::new(e).color(ui.visuals().error_fg_color),
+                                    ),
+                                );
+                            }
+                            PreviewState::Done(m_scan) => {
+                                let texture = self.textures[i].get_or_insert_with(|| {
+                                    ui.ctx().load_texture(
+                                        format!("sweep_preview_{i}"),
+                                        to_color_image(m_scan),
+                                        egui::TextureOptions::LINEAR,
+                                    )
+                                });
+
+                                let response = ui
+                                    .add(
+                                        egui::ImageButton::new(SizedTexture::new(
+                                            texture.id(),
+                                            PREVIEW_SIZE,
+                                        ))
+                                        .selected(self.applied == Some(i)),
+                                    )
+                                    .on_hover_text("Apply this value to the node");
+
+                                if response.clicked() {
+                                    node.set_parameter(self.parameter, preview.value);
+                                    self.applied = Some(i);
+                                }
+                            }
+                        }
+                    });
+
+                    if (i + 1) % PREVIEWS_PER_ROW == 0 {
+                        ui.end_row();
+                    }
+                }
+            });
+        });
+    }
+}
+
+fn get_filter_node(node: &dyn DynPipelineNode) -> Option<&filter::Node> {
+    node.as_any().downcast_ref::<filter::Node>()
+}
+
+fn get_filter_node_mut(node: &mut dyn DynPipelineNode) -> Option<&mut filter::Node> {
+    node.as_any_mut().downcast_mut::<filter::Node>()
+}
+
+/// Converts an M scan to a grayscale image. A scans are columns of the image.
+pub fn to_color_image(m_scan: &DataMatrix) -> egui::ColorImage {
+    let DataMatrix::U8(m_scan) = m_scan.cast_rescale_par(DataType::U8) else {
+        unreachable!("Casted to U8");
+    };
+
+    // Matrices are column major, the image is row major
+    let pixels = m_scan.transpose();
+
+    egui::ColorImage::from_gray([m_scan.ncols(), m_scan.nrows()], pixels.as_slice())
+}
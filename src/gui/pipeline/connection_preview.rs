@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use egui::{ColorImage, Grid, TextureHandle, TextureOptions};
+use egui_plot::{Line, Plot, PlotPoints};
+
+use crate::{pipeline::execution::Peek, units::NumberFormat};
+
+/// Size of the preview of an M scan chunk, in points.
+const IMAGE_SIZE: f32 = 128.0;
+
+/// Shows the last data captured from a connection, see
+/// [crate::pipeline::execution::PeekBuffer]. Never requests any data.
+pub fn preview_ui(ui: &mut egui::Ui, peek: Option<Arc<Peek>>) {
+    let Some(peek) = peek else {
+        ui.weak("No data yet").on_hover_text(
+            "Previews show the last data sent through a connection since its last change. \
+             Previews of M scans can be enabled in the settings",
+        );
+        return;
+    };
+
+    let format = NumberFormat::current();
+
+    match peek.as_ref() {
+        Peek::Image { size, chunk, .. } => {
+            if size.contains(&0) {
+                ui.weak("Last chunk is empty");
+                return;
+            }
+
+            let texture = texture(ui, &peek);
+            ui.add(egui::Image::new(&texture).fit_to_exact_size(egui::Vec2::splat(IMAGE_SIZE)));
+            ui.weak(format!(
+                "Last chunk: {} A scans × {} samples",
+                format.count(chunk[0]),
+                format.count(chunk[1]),
+            ));
+        }
+        Peek::Line(values) => {
+            let points = PlotPoints::from_ys_f32(values);
+            Plot::new(ui.id().with("connection_preview_plot"))
+                .width(IMAGE_SIZE * 1.5)
+                .height(IMAGE_SIZE / 2.0)
+                .show_axes([false, true])
+                .show_x(false)
+                .allow_drag(false)
+                .allow_zoom(false)
+                .allow_scroll(false)
+                .allow_boxed_zoom(false)
+                .show(ui, |plot_ui| plot_ui.line(Line::new(points)));
+        }
+        Peek::Diameters(diameters) => {
+            Grid::new(ui.id().with("connection_preview_diameters")).show(ui, |ui| {
+                ui.weak("From A scan");
+                ui.weak("Min");
+                ui.weak("Max");
+                ui.end_row();
+
+                for diameter in diameters {
+                    ui.label(format.count(diameter.b_scan_start));
+                    ui.label(format.length(diameter.min, Some(3)));
+                    ui.label(format.length(diameter.max, Some(3)));
+                    ui.end_row();
+                }
+            });
+        }
+        Peek::Mesh {
+            vertices,
+            triangles,
+        } => {
+            ui.label(format!(
+                "{} vertices · {} triangles",
+                format.count(*vertices),
+                format.count(*triangles),
+            ));
+        }
+    }
+}
+
+/// Texture of a [Peek::Image], uploaded once per captured chunk.
+fn texture(ui: &egui::Ui, peek: &Arc<Peek>) -> TextureHandle {
+    let id = ui.id().with("connection_preview_texture");
+
+    if let Some((uploaded, texture)) = ui.data(|d| d.get_temp::<(Arc<Peek>, TextureHandle)>(id)) {
+        if Arc::ptr_eq(&uploaded, peek) {
+            return texture;
+        }
+    }
+
+    let Peek::Image { size, pixels, .. } = peek.as_ref() else {
+        unreachable!("Only images are uploaded");
+    };
+    let texture = ui.ctx().load_texture(
+        "connection_preview",
+        ColorImage::from_gray(*size, pixels),
+        TextureOptions::NEAREST,
+    );
+
+    ui.data_mut(|d| d.insert_temp(id, (peek.clone(), texture.clone())));
+    texture
+}
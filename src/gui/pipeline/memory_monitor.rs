@@ -0,0 +1,90 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    gui::node_graph::NodeWarning,
+    node_graph::NodeId,
+    pipeline::{
+        memory::{self, PipelineMemory},
+        Pipeline,
+    },
+    settings::Settings,
+    units::NumberFormat,
+};
+
+/// How often the memory estimate of the pipeline is renewed.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Estimates the memory the pipeline needs, see [memory], and warns when it
+/// would exceed the memory available.
+pub struct MemoryMonitor {
+    last_update: Option<Instant>,
+    /// Warnings of the nodes, whose path needs too much memory.
+    warnings: HashMap<NodeId, Vec<NodeWarning>>,
+    /// Warning about the whole pipeline.
+    banner: Option<String>,
+}
+
+impl MemoryMonitor {
+    pub fn new() -> Self {
+        Self {
+            last_update: None,
+            warnings: HashMap::new(),
+            banner: None,
+        }
+    }
+
+    /// Renews the estimate, if [UPDATE_INTERVAL] has passed since the last
+    /// one.
+    pub fn update(&mut self, pipeline: &Pipeline) {
+        let now = Instant::now();
+        if self
+            .last_update
+            .is_some_and(|last_update| now.duration_since(last_update) < UPDATE_INTERVAL)
+        {
+            return;
+        }
+        self.last_update = Some(now);
+
+        let estimate = memory::estimate(pipeline, Settings::current().performance.stream_capacity);
+        let available = memory::available_memory();
+        let format = NumberFormat::current();
+
+        self.warnings = estimate
+            .nodes_exceeding(available)
+            .map(|(node_id, node)| {
+                let warning = format!(
+                    "Needs about {} up to here, {} available. This node needs {}, {} of it \
+                     kept across chunks",
+                    format.bytes(node.path_total as f64),
+                    format.bytes(available as f64),
+                    format.bytes(node.total() as f64),
+                    format.bytes((node.estimate.buffers + node.queued) as f64),
+                );
+                let warning = NodeWarning {
+                    label: "Memory",
+                    description: warning,
+                };
+                (node_id, vec![warning])
+            })
+            .collect();
+
+        self.banner = PipelineMemory::exceeds(estimate.peak, available).then(|| {
+            format!(
+                "Running this pipeline needs about {}, but only {} are available",
+                format.bytes(estimate.peak as f64),
+                format.bytes(available as f64),
+            )
+        });
+    }
+
+    pub fn warnings(&self) -> &HashMap<NodeId, Vec<NodeWarning>> {
+        &self.warnings
+    }
+
+    pub fn banner(&self) -> Option<&str> {
+        self.banner.as_deref()
+    }
+}
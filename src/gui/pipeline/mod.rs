@@ -1,4 +1,8 @@
+pub mod connection_preview;
+pub mod memory_monitor;
 pub mod nodes;
+pub mod progress_monitor;
+pub mod transfer_monitor;
 
 use std::path::PathBuf;
 
@@ -7,7 +11,55 @@ use crate::{
     pipeline::{nodes::*, Pipeline},
 };
 
-use super::node_graph::{DynEditNode, EditNodeGraph};
+use super::node_graph::{AddNodeError, DynEditNode, EditNodeGraph, NodeGraphEditState};
+
+/// Creates a node with its default settings.
+type CreateNode = fn() -> Box<dyn DynPipelineNode>;
+
+/// Every node that can be added to a pipeline, by its path in the add node
+/// popup.
+#[rustfmt::skip]
+static NODE_TYPES: &[(&str, CreateNode)] = &[
+    ("In Out/Raw M Scan Input", || Box::new(binary_input::Node::raw_m_scan(PathBuf::new(), None))),
+    ("In Out/M Scan Input", || Box::new(binary_input::Node::m_scan(PathBuf::new(), None))),
+    ("In Out/Binary Vector Input", || Box::new(binary_input::Node::data_vector(PathBuf::new()))),
+    ("In Out/B Scan Boundaries Input", || Box::new(b_scan_boundaries_input::Node::default())),
+    ("In Out/Segmentation Input", || Box::new(segmentation_input::Node::default())),
+    ("In Out/Output", || Box::new(output::Node::default())),
+    ("In Out/Bundle", || Box::new(bundle::Node::default())),
+    ("Process/Process Raw M Scan", || Box::new(process_raw_m_scan::Node::default())),
+    ("Process/Remove Detector Defect", || Box::new(remove_detector_defect::Node::new())),
+    ("Process/Remove Catheter Region", || Box::new(remove_catheter::Node::default())),
+    ("Process/Segment B Scans", || Box::new(segment_b_scans::Node::default())),
+    ("Process/Follow Catheter", || Box::new(follow_catheter::Node::default())),
+    ("Process/Follow Lumen", || Box::new(follow_lumen::Node::default())),
+    ("Process/Diameter", || Box::new(diameter::Node::default())),
+    ("Process/Generate Mesh", || Box::new(generate_mesh::Node::default())),
+    ("Process/Lumen Volume", || Box::new(lumen_volume::Node::default())),
+    ("Process/Apply Mask", || Box::new(apply_mask::Node::default())),
+    ("Process/External Command", || Box::new(external_command::Node::default())),
+    ("Process/Rechunk by B-scan", || Box::new(rechunk::Node::default())),
+    ("Process/Flatten", || Box::new(flatten::Node::flatten())),
+    ("Process/Unflatten", || Box::new(flatten::Node::unflatten())),
+    ("Process/Resample Depth", || Box::new(resample_depth::Node::default())),
+    ("Process/Crop", || Box::new(crop::Node::default())),
+    ("Filter/Gaussian Filter", || Box::new(filter::Node::gaussian())),
+    ("Filter/Median Filter", || Box::new(filter::Node::median())),
+    ("Filter/Align Brightness", || Box::new(filter::Node::align_brightness())),
+    ("Filter/Global Normalize", || Box::new(filter::Node::global_normalize())),
+    ("Filter/Moving Average", || Box::new(filter::Node::moving_average())),
+    ("Filter/Levels", || Box::new(filter::Node::levels())),
+    ("Filter/Threshold", || Box::new(filter::Node::threshold())),
+    ("Filter/Wiener Filter", || Box::new(filter::Node::wiener())),
+    ("Filter/Prewitt Filter", || Box::new(filter::Node::prewitt())),
+    ("Filter/Widen Structures", || Box::new(filter::Node::widen_structures())),
+    ("Filter/Binary Area Opening", || Box::new(filter::Node::b_ware_open())),
+];
+
+/// A node of every type, with its path in the add node popup.
+pub fn node_templates() -> impl Iterator<Item = (&'static str, Box<dyn DynPipelineNode>)> {
+    NODE_TYPES.iter().map(|(path, create)| (*path, create()))
+}
 
 impl EditNodeGraph for Pipeline {
     fn get_node_ids(&self) -> Vec<NodeId> {
@@ -23,63 +75,96 @@ impl EditNodeGraph for Pipeline {
 
     fn remove_node(&mut self, node_id: NodeId) {
         self.nodes.remove(&node_id);
+        self.disabled.remove(&node_id);
+    }
+
+    fn duplicate_node(&mut self, node_id: NodeId) -> Option<NodeId> {
+        let node = self.nodes.get(&node_id)?.clone_boxed();
+        let id = self.next_node_id()?;
+
+        self.nodes.insert(id, node);
+        if self.disabled.contains(&node_id) {
+            self.disabled.insert(id);
+        }
+
+        Some(id)
+    }
+
+    fn is_disabled(&self, node_id: NodeId) -> bool {
+        self.disabled.contains(&node_id)
     }
 
-    fn add_node(&mut self, path: &str) -> NodeId {
-        let id: usize = self.nodes.keys().copied().max().unwrap_or(0.into()).into();
-        let id = id + 1;
-
-        let node: Box<dyn DynPipelineNode> = match path {
-            "In Out/Raw M Scan Input" => {
-                Box::new(binary_input::Node::raw_m_scan(PathBuf::new(), None))
-            }
-            "In Out/M Scan Input" => Box::new(binary_input::Node::m_scan(PathBuf::new(), None)),
-            "In Out/Binary Vector Input" => {
-                Box::new(binary_input::Node::data_vector(PathBuf::new()))
-            }
-            "In Out/Output" => Box::new(output::Node::default()),
-            "Process/Process Raw M Scan" => Box::new(process_raw_m_scan::Node::default()),
-            "Process/Remove Detector Defect" => Box::new(remove_detector_defect::Node::new()),
-            "Process/Segment B Scans" => Box::new(segment_b_scans::Node::default()),
-            "Process/Follow Catheter" => Box::new(follow_catheter::Node::default()),
-            "Process/Follow Lumen" => Box::new(follow_lumen::Node::default()),
-            "Process/Diameter" => Box::new(diameter::Node::default()),
-            "Process/Generate Mesh" => Box::new(generate_mesh::Node::default()),
-            "Filter/Gaussian Filter" => Box::new(filter::Node::gaussian()),
-            "Filter/Median Filter" => Box::new(filter::Node::median()),
-            "Filter/Align Brightness" => Box::new(filter::Node::align_brightness()),
-            "Filter/Wiener Filter" => Box::new(filter::Node::wiener()),
-            "Filter/Prewitt Filter" => Box::new(filter::Node::prewitt()),
-            "Filter/Widen Structures" => Box::new(filter::Node::widen_structures()),
-            "Filter/Binary Area Opening" => Box::new(filter::Node::b_ware_open()),
-            _ => panic!("Invalid path: {}", path),
+    fn set_disabled(&mut self, node_id: NodeId, disabled: bool) {
+        match disabled {
+            true => self.disabled.insert(node_id),
+            false => self.disabled.remove(&node_id),
         };
+    }
+
+    fn add_node(&mut self, path: &str) -> Result<NodeId, AddNodeError> {
+        let (_, create) = NODE_TYPES
+            .iter()
+            .find(|(p, _)| *p == path)
+            .ok_or_else(|| AddNodeError::UnknownPath(path.to_string()))?;
+
+        let id = self.next_node_id().ok_or(AddNodeError::NoNodeId)?;
+        self.nodes.insert(id, create());
 
-        self.nodes.insert(id.into(), node);
+        Ok(id)
+    }
 
-        id.into()
+    fn collapse_nodes(&mut self, node_ids: &[NodeId], state: NodeGraphEditState) -> Option<NodeId> {
+        composite::collapse(self, node_ids, state)
     }
 
     fn addable_nodes(&self) -> Vec<&'static str> {
-        vec![
-            "In Out/Raw M Scan Input",
-            "In Out/M Scan Input",
-            "In Out/Binary Vector Input",
-            "In Out/Output",
-            "Process/Process Raw M Scan",
-            "Process/Remove Detector Defect",
-            "Process/Segment B Scans",
-            "Process/Follow Catheter",
-            "Process/Follow Lumen",
-            "Process/Diameter",
-            "Process/Generate Mesh",
-            "Filter/Gaussian Filter",
-            "Filter/Median Filter",
-            "Filter/Align Brightness",
-            "Filter/Wiener Filter",
-            "Filter/Prewitt Filter",
-            "Filter/Widen Structures",
-            "Filter/Binary Area Opening",
-        ]
+        NODE_TYPES.iter().map(|(path, _)| *path).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::node_graph::NodeOutput;
+
+    use super::*;
+
+    #[test]
+    fn add_every_node() {
+        let mut pipeline = Pipeline::new();
+
+        let paths = pipeline.addable_nodes();
+        assert_eq!(paths.len(), NODE_TYPES.len());
+
+        for path in &paths {
+            let node_id = pipeline.add_node(path).unwrap();
+            assert!(pipeline.nodes.contains_key(&node_id), "{}", path);
+        }
+        assert_eq!(pipeline.nodes.len(), paths.len());
+
+        assert_eq!(
+            pipeline.add_node("Filter/Removed Filter"),
+            Err(AddNodeError::UnknownPath(
+                "Filter/Removed Filter".to_string()
+            ))
+        );
+        assert_eq!(pipeline.nodes.len(), paths.len());
+    }
+
+    #[test]
+    fn duplicate_node() {
+        let mut pipeline = Pipeline::new();
+        let input = pipeline.add_node(NODE_TYPES[0].0).unwrap();
+        let filter = pipeline.add_node("Filter/Gaussian Filter").unwrap();
+        let output = NodeOutput::new(input, 0.into(), 0.into());
+        pipeline[filter].connect(0.into(), output);
+        pipeline.set_disabled(filter, true);
+
+        let copy = pipeline.duplicate_node(filter).unwrap();
+        assert_ne!(copy, filter);
+        assert!(!pipeline[copy].changed(&pipeline[filter]));
+        assert_eq!(pipeline[copy].inputs(), pipeline[filter].inputs());
+        assert!(pipeline.is_disabled(copy));
+
+        assert_eq!(pipeline.duplicate_node(100.into()), None);
     }
 }
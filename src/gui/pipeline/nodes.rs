@@ -1,19 +1,31 @@
+pub mod apply_mask;
+pub mod b_scan_boundaries_input;
 pub mod binary_input;
+pub mod bundle;
+pub mod composite;
+pub mod crop;
 pub mod diameter;
+pub mod external_command;
 pub mod filter;
+pub mod flatten;
 pub mod follow_catheter;
 pub mod follow_lumen;
 pub mod generate_mesh;
+pub mod lumen_volume;
 pub mod output;
 pub mod process_raw_m_scan;
+pub mod rechunk;
+pub mod remove_catheter;
 pub mod remove_detector_defect;
+pub mod resample_depth;
 pub mod segment_b_scans;
+pub mod segmentation_input;
 
 use core::fmt;
 
 use egui::Color32;
 
-use crate::pipeline::PipelineDataType;
+use crate::{gui::node_graph::PinStyle, pipeline::PipelineDataType, settings::Settings};
 
 #[allow(unused_imports)]
 mod prelude {
@@ -51,9 +63,23 @@ mod colors {
     pub const PROCESS: Color32 = Color32::from_rgb(43, 101, 43);
     pub const FILTER: Color32 = Color32::from_rgb(131, 49, 74);
     // pub const TRANSFORM: Color32 = Color32::from_rgb(36, 98, 131);
+    pub const GROUP: Color32 = Color32::from_rgb(80, 80, 80);
 }
 
 impl PipelineDataType {
+    /// Style of pins of this type, depending on
+    /// [crate::settings::DisplaySettings::high_contrast_pins].
+    fn pin(&self) -> PinStyle {
+        if Settings::current().display.high_contrast_pins {
+            PinStyle {
+                color: self.high_contrast_color(),
+                glyph: Some(self.glyph()),
+            }
+        } else {
+            self.color().into()
+        }
+    }
+
     fn color(&self) -> Color32 {
         match self {
             PipelineDataType::RawMScan => Color32::from_rgb(121, 70, 29),
@@ -63,6 +89,35 @@ impl PipelineDataType {
             PipelineDataType::MScanSegmentation => Color32::from_rgb(128, 0, 128),
             PipelineDataType::Diameter => Color32::from_rgb(128, 128, 0),
             PipelineDataType::Mesh => Color32::from_rgb(0, 128, 0),
+            PipelineDataType::Bundle => Color32::from_rgb(128, 128, 128),
+        }
+    }
+
+    /// Colorblind safe palette by Okabe and Ito, with a distinct color for
+    /// every type.
+    fn high_contrast_color(&self) -> Color32 {
+        match self {
+            PipelineDataType::RawMScan => Color32::from_rgb(213, 94, 0),
+            PipelineDataType::DataVector => Color32::from_rgb(86, 180, 233),
+            PipelineDataType::MScan => Color32::from_rgb(230, 159, 0),
+            PipelineDataType::BScanSegmentation => Color32::from_rgb(0, 114, 178),
+            PipelineDataType::MScanSegmentation => Color32::from_rgb(204, 121, 167),
+            PipelineDataType::Diameter => Color32::from_rgb(240, 228, 66),
+            PipelineDataType::Mesh => Color32::from_rgb(0, 158, 115),
+            PipelineDataType::Bundle => Color32::from_rgb(0, 0, 0),
+        }
+    }
+
+    fn glyph(&self) -> char {
+        match self {
+            PipelineDataType::RawMScan => 'R',
+            PipelineDataType::DataVector => 'V',
+            PipelineDataType::MScan => 'M',
+            PipelineDataType::BScanSegmentation => 'B',
+            PipelineDataType::MScanSegmentation => 'S',
+            PipelineDataType::Diameter => 'D',
+            PipelineDataType::Mesh => 'G',
+            PipelineDataType::Bundle => 'J',
         }
     }
 }
@@ -77,6 +132,7 @@ impl fmt::Display for PipelineDataType {
             PipelineDataType::MScanSegmentation => write!(f, "M-scan segmentation"),
             PipelineDataType::Diameter => write!(f, "Diameter"),
             PipelineDataType::Mesh => write!(f, "Mesh"),
+            PipelineDataType::Bundle => write!(f, "Bundle"),
         }
     }
 }
@@ -0,0 +1,76 @@
+use egui::DragValue;
+
+use crate::{
+    gui::widgets::DragValueExt,
+    pipeline::nodes::apply_mask::{InputId, Node},
+};
+
+use super::prelude::*;
+
+impl EditNode for Node {
+    type OutputId = OutputIdSingle;
+    type InputId = InputId;
+
+    fn name(&self) -> &str {
+        "Apply Mask"
+    }
+
+    fn color(&self) -> egui::Color32 {
+        colors::PROCESS
+    }
+
+    fn connect(&mut self, input: Self::InputId, connection: NodeOutput) {
+        match (input, PipelineDataType::from(connection.type_id)) {
+            (InputId::MScan, PipelineDataType::MScan) => {
+                self.m_scan.connect(connection);
+            }
+            (InputId::Mask, PipelineDataType::MScan) => {
+                self.mask.connect(connection);
+            }
+            _ => {}
+        }
+    }
+
+    fn disconnect(&mut self, input: Self::InputId) {
+        match input {
+            InputId::MScan => self.m_scan.disconnect(),
+            InputId::Mask => self.mask.disconnect(),
+        }
+    }
+
+    fn ui(&mut self, ui: &mut NodeUi) {
+        ui.output(
+            OutputIdSingle,
+            PipelineDataType::MScan,
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan");
+            },
+        );
+
+        ui.input(
+            InputId::MScan,
+            self.m_scan.connection(),
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan");
+            },
+        );
+
+        ui.input(
+            InputId::Mask,
+            self.mask.connection(),
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("Mask");
+            },
+        );
+
+        ui.add(
+            DragValue::new(&mut self.fill_value)
+                .localized()
+                .speed(0.01)
+                .prefix("Fill Value: "),
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use core::fmt;
+
+use egui::ComboBox;
+
+use crate::pipeline::nodes::b_scan_boundaries_input::*;
+
+use super::prelude::*;
+
+impl fmt::Display for BoundaryFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BoundaryFormat::RawU32 => write!(f, "Raw UInt 32"),
+            BoundaryFormat::Csv => write!(f, "CSV"),
+            BoundaryFormat::Text => write!(f, "One per line"),
+        }
+    }
+}
+
+impl EditNode for Node {
+    type OutputId = OutputIdSingle;
+    type InputId = InputIdSingle;
+
+    fn name(&self) -> &str {
+        "B Scan Boundaries Input"
+    }
+
+    fn color(&self) -> egui::Color32 {
+        colors::INPUT
+    }
+
+    fn connect(&mut self, _input: Self::InputId, connection: NodeOutput) {
+        if connection.type_id == PipelineDataType::MScan.into() {
+            self.m_scan.connect(connection);
+        }
+    }
+
+    fn disconnect(&mut self, _input: Self::InputId) {
+        self.m_scan.disconnect();
+    }
+
+    fn ui(&mut self, ui: &mut NodeUi) {
+        ui.output(
+            OutputIdSingle,
+            PipelineDataType::BScanSegmentation,
+            PipelineDataType::BScanSegmentation.pin(),
+            |ui| {
+                ui.node_label("B Scan Segmentation");
+            },
+        );
+
+        ui.input(
+            InputIdSingle,
+            self.m_scan.connection(),
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan").on_hover_text(
+                    "Optional, the boundaries are checked against the number of A scans",
+                );
+            },
+        );
+
+        ui.add(PathInput::new(&mut self.path));
+
+        ComboBox::from_id_source(ui.id().with("format"))
+            .selected_text(format!("{}", self.format))
+            .show_ui(ui, |ui| {
+                for format in BoundaryFormat::VALUES {
+                    ui.selectable_value(&mut self.format, format, format!("{}", format));
+                }
+            });
+
+        ui.checkbox(&mut self.one_based, "Counting from 1")
+            .on_hover_text("The file counts A scans from 1, like MATLAB does");
+
+        let status = self.status_rx.as_ref().map(|rx| rx.borrow().clone());
+        match status {
+            Some(Status::Loaded(stats)) => {
+                ui.label(format!("{} boundaries", stats.count));
+                if let Some((min, median, max)) = stats.widths {
+                    ui.label(format!("B scan width: {} / {} / {}", min, median, max))
+                        .on_hover_text("Minimum, median and maximum number of A scans");
+                }
+            }
+            Some(Status::Failed(message)) => {
+                let color = ui.visuals().error_fg_color;
+                ui.colored_label(color, message);
+            }
+            Some(Status::Idle) | None => {}
+        }
+    }
+}
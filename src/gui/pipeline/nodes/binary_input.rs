@@ -1,10 +1,26 @@
 use core::fmt;
+use std::path::PathBuf;
 
-use egui::{ComboBox, DragValue, ProgressBar};
+use egui::{ComboBox, DragValue, ProgressBar, TextureHandle, TextureOptions};
 
 use super::prelude::*;
 
-use crate::pipeline::{nodes::binary_input::*, types::DataType};
+use crate::{
+    gui::parameter_sweep_window::to_color_image,
+    pipeline::{
+        nodes::binary_input::*,
+        raw_format::{self, Dimensions, Endianness},
+        types::DataType,
+    },
+};
+
+/// Candidates offered by [auto_configure_ui].
+const CANDIDATES: usize = 8;
+/// A scans shown in the preview of a candidate.
+const PREVIEW_A_SCANS: usize = 400;
+/// Samples of the A scans in the preview, others are skipped.
+const PREVIEW_SAMPLES: usize = 256;
+const PREVIEW_SIZE: egui::Vec2 = egui::vec2(160.0, 80.0);
 
 impl fmt::Display for InputDataType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -29,6 +45,15 @@ impl fmt::Display for DataType {
     }
 }
 
+impl fmt::Display for Endianness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Endianness::Little => write!(f, "Little Endian"),
+            Endianness::Big => write!(f, "Big Endian"),
+        }
+    }
+}
+
 impl EditNode for Node {
     type OutputId = InputDataType;
     type InputId = InputIdNone;
@@ -49,11 +74,15 @@ impl EditNode for Node {
         unreachable!()
     }
 
+    fn progress(&self) -> Option<f32> {
+        *self.progress_rx.as_ref()?.borrow()
+    }
+
     fn ui(&mut self, ui: &mut NodeUi) {
         ui.output(
             self.input_type,
             self.input_type.data_type(),
-            self.input_type.data_type().color(),
+            self.input_type.data_type().pin(),
             |ui| {
                 ui.node_label(format!("{}", self.input_type));
             },
@@ -71,23 +100,57 @@ impl EditNode for Node {
                 }
             });
 
-        ComboBox::from_id_source(ui.id().with("data_type"))
-            .selected_text(format!("{}", self.data_type))
-            .show_ui(ui, |ui| {
-                for data_type in DataType::VALUES.into_iter() {
-                    ui.selectable_value(&mut self.data_type, data_type, format!("{}", data_type));
-                }
-            });
+        let is_scan = matches!(
+            self.input_type,
+            InputDataType::RawMScan | InputDataType::MScan
+        );
 
         ui.add(PathInput::new(&mut self.path));
-
-        if let InputDataType::RawMScan | InputDataType::MScan = self.input_type {
-            ui.add(
-                DragValue::new(&mut self.a_scan_length)
-                    .speed(1)
-                    .prefix("A Scan Length: ")
-                    .range(1..=usize::MAX),
-            );
+        self.detect_header();
+
+        // The header describes the data
+        let header = self.header.filter(|_| is_scan);
+
+        ui.add_enabled_ui(header.is_none(), |ui| {
+            ComboBox::from_id_source(ui.id().with("data_type"))
+                .selected_text(format!("{}", self.data_type))
+                .show_ui(ui, |ui| {
+                    for data_type in DataType::VALUES.into_iter() {
+                        ui.selectable_value(
+                            &mut self.data_type,
+                            data_type,
+                            format!("{}", data_type),
+                        );
+                    }
+                });
+
+            ComboBox::from_id_source(ui.id().with("endianness"))
+                .selected_text(format!("{}", self.endianness))
+                .show_ui(ui, |ui| {
+                    for endianness in Endianness::VALUES {
+                        ui.selectable_value(
+                            &mut self.endianness,
+                            endianness,
+                            format!("{}", endianness),
+                        );
+                    }
+                });
+
+            if is_scan {
+                ui.add(
+                    DragValue::new(&mut self.a_scan_length)
+                        .speed(1)
+                        .prefix("A Scan Length: ")
+                        .range(1..=usize::MAX),
+                );
+
+                ui.collapsing("Auto Configure", |ui| auto_configure_ui(ui, self));
+            }
+        });
+
+        if let Some(header) = header {
+            ui.label(format!("Header: {} A scans", header.a_scan_count))
+                .on_hover_text("The file describes its data type, A scan length and endianness");
         }
 
         if let Some(progress) = self.progress_rx.as_ref().and_then(|rx| rx.borrow().clone()) {
@@ -96,3 +159,97 @@ impl EditNode for Node {
         }
     }
 }
+
+// MARK: Auto configuration
+
+/// Candidates of [Node::dimension_candidates] and the preview of the selected
+/// one, kept between frames while the file and its data type stay the same.
+#[derive(Clone)]
+struct AutoConfigure {
+    key: (PathBuf, DataType, Endianness),
+    candidates: Vec<Dimensions>,
+    selected: usize,
+    /// The candidate previewed and its image.
+    preview: Option<(usize, Result<TextureHandle, String>)>,
+}
+
+/// Offers A scan lengths, that fit the size of the file, with a preview of
+/// the first A scans. The A scan length changes only, when the user applies
+/// one.
+fn auto_configure_ui(ui: &mut egui::Ui, node: &mut Node) {
+    let id = ui.id().with("auto_configure");
+    let key = (node.path.clone(), node.data_type, node.endianness);
+
+    let mut state = ui
+        .data(|d| d.get_temp::<AutoConfigure>(id))
+        .filter(|state| state.key == key)
+        .unwrap_or_else(|| AutoConfigure {
+            key,
+            candidates: node.dimension_candidates(CANDIDATES),
+            selected: 0,
+            preview: None,
+        });
+
+    let text = |dimensions: &Dimensions| {
+        format!(
+            "{} × {} A scans",
+            dimensions.a_scan_samples, dimensions.a_scan_count
+        )
+    };
+
+    if let Some(&dimensions) = state.candidates.get(state.selected) {
+        ComboBox::from_id_source(id.with("candidates"))
+            .selected_text(text(&dimensions))
+            .show_ui(ui, |ui| {
+                for (i, candidate) in state.candidates.iter().enumerate() {
+                    ui.selectable_value(&mut state.selected, i, text(candidate));
+                }
+            });
+
+        let dimensions = state.candidates[state.selected];
+        if state.preview.as_ref().map(|(i, _)| *i) != Some(state.selected) {
+            let texture = raw_format::read_preview(
+                &node.path,
+                node.data_type,
+                node.endianness,
+                dimensions.a_scan_samples,
+                PREVIEW_A_SCANS,
+                PREVIEW_SAMPLES,
+            )
+            .map(|preview| {
+                ui.ctx().load_texture(
+                    "binary_input_preview",
+                    to_color_image(&preview),
+                    TextureOptions::LINEAR,
+                )
+            })
+            .map_err(|e| e.to_string());
+            state.preview = Some((state.selected, texture));
+        }
+
+        match &state.preview {
+            Some((_, Ok(texture))) => {
+                ui.add(egui::Image::new(texture).fit_to_exact_size(PREVIEW_SIZE))
+                    .on_hover_text("The first A scans, read with this length");
+            }
+            Some((_, Err(e))) => {
+                ui.colored_label(ui.visuals().error_fg_color, e);
+            }
+            None => {}
+        }
+
+        if ui
+            .add_enabled(
+                node.a_scan_length != dimensions.a_scan_samples,
+                egui::Button::new("Apply"),
+            )
+            .clicked()
+        {
+            node.a_scan_length = dimensions.a_scan_samples;
+        }
+    } else {
+        ui.label("No A scan length fits the size of the file");
+    }
+
+    ui.data_mut(|d| d.insert_temp(id, state));
+}
@@ -0,0 +1,91 @@
+use crate::pipeline::nodes::bundle::{InputId, Node};
+
+use super::prelude::*;
+
+impl EditNode for Node {
+    type OutputId = OutputIdSingle;
+    type InputId = InputId;
+
+    fn name(&self) -> &str {
+        "Bundle"
+    }
+
+    fn color(&self) -> egui::Color32 {
+        colors::OUTPUT
+    }
+
+    fn connect(&mut self, input: Self::InputId, connection: NodeOutput) {
+        match (input, PipelineDataType::from(connection.type_id)) {
+            (InputId::BScans, PipelineDataType::BScanSegmentation) => {
+                self.b_scans.connect(connection);
+            }
+            (InputId::Lumen, PipelineDataType::MScanSegmentation) => {
+                self.lumen.connect(connection);
+            }
+            (InputId::Diameter, PipelineDataType::Diameter) => {
+                self.diameter.connect(connection);
+            }
+            (InputId::Area, PipelineDataType::DataVector) => {
+                self.area.connect(connection);
+            }
+            _ => {}
+        }
+    }
+
+    fn disconnect(&mut self, input: Self::InputId) {
+        match input {
+            InputId::BScans => self.b_scans.disconnect(),
+            InputId::Lumen => self.lumen.disconnect(),
+            InputId::Diameter => self.diameter.disconnect(),
+            InputId::Area => self.area.disconnect(),
+        }
+    }
+
+    fn ui(&mut self, ui: &mut NodeUi) {
+        ui.output(
+            OutputIdSingle,
+            PipelineDataType::Bundle,
+            PipelineDataType::Bundle.pin(),
+            |ui| {
+                ui.node_label("Bundle");
+            },
+        );
+
+        ui.input(
+            InputId::BScans,
+            self.b_scans.connection(),
+            PipelineDataType::BScanSegmentation.pin(),
+            |ui| {
+                ui.node_label("B-Scans");
+            },
+        );
+
+        ui.input(
+            InputId::Lumen,
+            self.lumen.connection(),
+            PipelineDataType::MScanSegmentation.pin(),
+            |ui| {
+                ui.node_label("Lumen");
+            },
+        );
+
+        ui.input(
+            InputId::Diameter,
+            self.diameter.connection(),
+            PipelineDataType::Diameter.pin(),
+            |ui| {
+                ui.node_label("Diameter");
+            },
+        );
+
+        ui.input(
+            InputId::Area,
+            self.area.connection(),
+            PipelineDataType::DataVector.pin(),
+            |ui| {
+                ui.node_label("Area")
+                    .on_hover_text("Area of every B scan, from the lumen volume node");
+            },
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use egui::TextEdit;
+
+use crate::{gui::node_graph::NodeAction, pipeline::nodes::composite::Node};
+
+use super::prelude::*;
+
+impl EditNode for Node {
+    type OutputId = graph::OutputId;
+    type InputId = graph::InputId;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn color(&self) -> egui::Color32 {
+        colors::GROUP
+    }
+
+    fn connect(&mut self, input: Self::InputId, connection: NodeOutput) {
+        let index: usize = input.into();
+        if let Some(pin) = self.inputs.get_mut(index) {
+            if pin.type_id == connection.type_id {
+                pin.connection = Some(connection);
+            }
+        }
+    }
+
+    fn disconnect(&mut self, input: Self::InputId) {
+        let index: usize = input.into();
+        if let Some(pin) = self.inputs.get_mut(index) {
+            pin.connection = None;
+        }
+    }
+
+    fn ui(&mut self, ui: &mut NodeUi) {
+        for (index, pin) in self.outputs.iter().enumerate() {
+            let data_type = PipelineDataType::from(pin.output.type_id);
+            ui.output(index, data_type, data_type.pin(), |ui| {
+                ui.node_label(&pin.name);
+            });
+        }
+
+        for (index, pin) in self.inputs.iter().enumerate() {
+            let data_type = PipelineDataType::from(pin.type_id);
+            ui.input(index, pin.connection, data_type.pin(), |ui| {
+                ui.node_label(&pin.name);
+            });
+        }
+
+        ui.add(TextEdit::singleline(&mut self.name).desired_width(140.0));
+
+        let label = match &self.library {
+            Some(entry) => format!("{} nodes, from \"{}\"", self.pipeline.nodes.len(), entry),
+            None => format!("{} nodes", self.pipeline.nodes.len()),
+        };
+        ui.node_label(egui::RichText::new(label).weak())
+            .on_hover_text("Double click to open");
+    }
+
+    fn context_menu(&mut self, ui: &mut egui::Ui) -> Option<NodeAction> {
+        if ui
+            .button("Save to Library")
+            .on_hover_text("Save under the name of the group, to add it to other pipelines")
+            .clicked()
+        {
+            return Some(NodeAction::SaveToLibrary);
+        }
+
+        let clicked = ui
+            .add_enabled(
+                self.library.is_some(),
+                egui::Button::new("Update from Library"),
+            )
+            .on_hover_text("Replace the nodes with the ones of the library entry")
+            .on_disabled_hover_text("Not created from the library")
+            .clicked();
+
+        clicked.then_some(NodeAction::UpdateFromLibrary)
+    }
+
+    fn progress(&self) -> Option<f32> {
+        let progress = self
+            .pipeline
+            .nodes
+            .values()
+            .filter_map(|node| node.progress())
+            .collect::<Vec<_>>();
+
+        match progress.is_empty() {
+            true => None,
+            false => Some(progress.iter().sum::<f32>() / progress.len() as f32),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::ops::Range;
+
+use egui::DragValue;
+
+use crate::pipeline::nodes::crop::Node;
+
+use super::prelude::*;
+
+/// End set, when an end of the whole M scan is limited, after the start.
+const DEFAULT_LENGTH: usize = 1000;
+
+impl EditNode for Node {
+    type OutputId = OutputIdSingle;
+    type InputId = InputIdSingle;
+
+    fn name(&self) -> &str {
+        "Crop"
+    }
+
+    fn color(&self) -> egui::Color32 {
+        colors::PROCESS
+    }
+
+    fn connect(&mut self, _input: Self::InputId, connection: NodeOutput) {
+        if connection.type_id == PipelineDataType::MScan.into() {
+            self.m_scan.connect(connection);
+        }
+    }
+
+    fn disconnect(&mut self, _input: Self::InputId) {
+        self.m_scan.disconnect();
+    }
+
+    fn ui(&mut self, ui: &mut NodeUi) {
+        ui.output(
+            OutputIdSingle,
+            PipelineDataType::MScan,
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan");
+            },
+        );
+
+        ui.input(
+            InputIdSingle,
+            self.m_scan.connection(),
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan");
+            },
+        );
+
+        range_ui(
+            ui,
+            "A Scans:",
+            &mut self.settings.a_scan_range,
+            "A scans kept, counted from the start of the M scan",
+        );
+        range_ui(
+            ui,
+            "Samples:",
+            &mut self.settings.sample_range,
+            "Samples of every A scan kept",
+        );
+    }
+}
+
+/// Edits the start and the excluded end of `range`. An end of [usize::MAX]
+/// is shown as "End", keeping everything up to the end of the M scan.
+fn range_ui(ui: &mut egui::Ui, label: &str, range: &mut Range<usize>, description: &str) {
+    ui.horizontal(|ui| {
+        ui.label(label).on_hover_text(description);
+
+        ui.add(DragValue::new(&mut range.start).range(0..=range.end.saturating_sub(1)));
+        ui.label("–");
+
+        if range.end == usize::MAX {
+            if ui
+                .button("End")
+                .on_hover_text("Everything up to the end is kept. Click to set an end")
+                .clicked()
+            {
+                range.end = range.start.saturating_add(DEFAULT_LENGTH);
+            }
+        } else {
+            ui.add(DragValue::new(&mut range.end).range(range.start + 1..=usize::MAX))
+                .on_hover_text("First one after the range");
+            if ui
+                .small_button("⇥")
+                .on_hover_text("Keep everything up to the end")
+                .clicked()
+            {
+                range.end = usize::MAX;
+            }
+        }
+    });
+}
@@ -1,6 +1,9 @@
 use egui::{Checkbox, DragValue};
 
-use crate::pipeline::nodes::diameter::{InputId, Node};
+use crate::{
+    gui::widgets::DragValueExt,
+    pipeline::nodes::diameter::{InputId, Node},
+};
 
 use super::prelude::*;
 
@@ -43,7 +46,7 @@ impl EditNode for Node {
         ui.output(
             OutputIdSingle,
             PipelineDataType::Diameter,
-            PipelineDataType::Diameter.color(),
+            PipelineDataType::Diameter.pin(),
             |ui| {
                 ui.node_label("Diameter");
             },
@@ -52,7 +55,7 @@ impl EditNode for Node {
         ui.input(
             InputId::Lumen,
             self.lumen.connection(),
-            PipelineDataType::MScanSegmentation.color(),
+            PipelineDataType::MScanSegmentation.pin(),
             |ui| {
                 ui.node_label("Lumen");
             },
@@ -61,7 +64,7 @@ impl EditNode for Node {
         ui.input(
             InputId::Catheter,
             self.catheter.connection(),
-            PipelineDataType::MScanSegmentation.color(),
+            PipelineDataType::MScanSegmentation.pin(),
             |ui| {
                 ui.node_label("Catheter");
             },
@@ -70,7 +73,7 @@ impl EditNode for Node {
         ui.input(
             InputId::BScans,
             self.b_scans.connection(),
-            PipelineDataType::BScanSegmentation.color(),
+            PipelineDataType::BScanSegmentation.pin(),
             |ui| {
                 ui.node_label("B-Scans");
             },
@@ -78,6 +81,7 @@ impl EditNode for Node {
 
         ui.add(
             DragValue::new(&mut self.settings.mm_per_pixel)
+                .localized()
                 .range(0.0..=f32::INFINITY)
                 .speed(0.001)
                 .prefix("mm per pixel: "),
@@ -85,6 +89,7 @@ impl EditNode for Node {
 
         ui.add(
             DragValue::new(&mut self.settings.refraction_index)
+                .localized()
                 .range(0.0..=f32::INFINITY)
                 .speed(0.01)
                 .prefix("refraction index: "),
@@ -103,7 +108,7 @@ impl EditNode for Node {
                 DragValue::new(&mut self.settings.catheter_diameter)
                     .range(0.0..=f32::INFINITY)
                     .speed(0.01)
-                    .suffix(" mm"),
+                    .length(),
             );
         });
     }
@@ -0,0 +1,60 @@
+use egui::{DragValue, TextEdit};
+
+use crate::{gui::widgets::DragValueExt, pipeline::nodes::external_command::Node};
+
+use super::prelude::*;
+
+impl EditNode for Node {
+    type OutputId = OutputIdSingle;
+    type InputId = InputIdSingle;
+
+    fn name(&self) -> &str {
+        "External Command"
+    }
+
+    fn color(&self) -> egui::Color32 {
+        colors::PROCESS
+    }
+
+    fn connect(&mut self, _input: Self::InputId, connection: NodeOutput) {
+        if connection.type_id == PipelineDataType::MScan.into() {
+            self.m_scan.connect(connection);
+        }
+    }
+
+    fn disconnect(&mut self, _input: Self::InputId) {
+        self.m_scan.disconnect();
+    }
+
+    fn ui(&mut self, ui: &mut NodeUi) {
+        ui.output(
+            OutputIdSingle,
+            PipelineDataType::MScan,
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan");
+            },
+        );
+
+        ui.input(
+            InputIdSingle,
+            self.m_scan.connection(),
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan");
+            },
+        );
+
+        ui.add(PathInput::new(&mut self.command));
+        ui.add(TextEdit::singleline(&mut self.args).hint_text("Arguments"));
+        ui.add(
+            DragValue::new(&mut self.timeout)
+                .localized()
+                .range(0.1..=3600.0)
+                .speed(0.1)
+                .prefix("Timeout: ")
+                .suffix(" s"),
+        )
+        .on_hover_text("Maximum time to wait for the next processed chunk, while the command has unanswered input");
+    }
+}
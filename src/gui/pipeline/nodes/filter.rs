@@ -1,11 +1,25 @@
 use core::fmt;
 use std::ops::DerefMut;
 
-use egui::{Color32, ComboBox, DragValue, ProgressBar};
+use egui::{pos2, vec2, Color32, ComboBox, DragValue, ProgressBar, Rect, Sense, Slider, Stroke};
+use nalgebra::Vector2;
 
 use crate::{
-    gui::widgets::DragVector,
-    pipeline::nodes::filter::{AreaConnectionType, FilterType, Node},
+    gui::{
+        node_graph::NodeAction,
+        widgets::{DragValueExt, DragVector},
+    },
+    pipeline::{
+        nodes::filter::{
+            AreaConnectionType, FilterType, GatedFill, GatingStats, InputId, KernelCalibration,
+            KernelUnit, LevelsSettings, Node, OutputId, PhysicalKernel, SweepParameter,
+            ThresholdMode,
+        },
+        result_cache::{CacheStats, CacheStatus},
+        suggestions::SuggestionState,
+        two_pass::Passes,
+    },
+    units::NumberFormat,
 };
 
 use super::prelude::*;
@@ -15,11 +29,24 @@ impl fmt::Display for FilterType {
         match self {
             FilterType::Gaussian => write!(f, "Gaussian"),
             FilterType::Median => write!(f, "Median"),
+            FilterType::MovingAverage => write!(f, "Moving Average"),
             FilterType::AlignBrightness => write!(f, "Align Brightness"),
             FilterType::Wiener => write!(f, "Wiener"),
             FilterType::Prewitt => write!(f, "Prewitt"),
             FilterType::WidenStructures => write!(f, "Widen Structures"),
             FilterType::BWAreaOpen => write!(f, "Binary Area Opening"),
+            FilterType::GlobalNormalize => write!(f, "Global Normalize"),
+            FilterType::Levels => write!(f, "Levels"),
+            FilterType::Threshold => write!(f, "Threshold"),
+        }
+    }
+}
+
+impl fmt::Display for ThresholdMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ThresholdMode::Fixed => write!(f, "Fixed"),
+            ThresholdMode::Otsu => write!(f, "Otsu"),
         }
     }
 }
@@ -33,19 +60,50 @@ impl fmt::Display for AreaConnectionType {
     }
 }
 
+impl fmt::Display for GatedFill {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GatedFill::PassThrough => write!(f, "Unfiltered"),
+            GatedFill::Zero => write!(f, "Zero"),
+        }
+    }
+}
+
+impl fmt::Display for SweepParameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SweepParameter::GaussSigma => write!(f, "Sigma"),
+            SweepParameter::GaussKernelRows
+            | SweepParameter::MedianRows
+            | SweepParameter::WienerRows => write!(f, "Rows"),
+            SweepParameter::GaussKernelColumns
+            | SweepParameter::MedianColumns
+            | SweepParameter::WienerColumns => write!(f, "Columns"),
+            SweepParameter::PrewittThreshold => write!(f, "Threshold"),
+            SweepParameter::MovingAverageWidth => write!(f, "Width"),
+            SweepParameter::WidenStructuresWidth => write!(f, "Size"),
+            SweepParameter::BWAreaOpenArea => write!(f, "Area Size"),
+        }
+    }
+}
+
 impl EditNode for Node {
-    type OutputId = OutputIdSingle;
-    type InputId = InputIdSingle;
+    type OutputId = OutputId;
+    type InputId = InputId;
 
     fn name(&self) -> &str {
         match self.filter_type {
             FilterType::Gaussian => "Gaussian Filter",
             FilterType::Median => "Median Filter",
+            FilterType::MovingAverage => "Moving Average",
             FilterType::AlignBrightness => "Align Brightness",
             FilterType::Wiener => "Wiener Filter",
             FilterType::Prewitt => "Prewitt Filter",
             FilterType::WidenStructures => "Widen Structures",
             FilterType::BWAreaOpen => "Binary Area Opening",
+            FilterType::GlobalNormalize => "Global Normalize",
+            FilterType::Levels => "Levels",
+            FilterType::Threshold => "Threshold",
         }
     }
 
@@ -53,35 +111,68 @@ impl EditNode for Node {
         colors::FILTER
     }
 
-    fn connect(&mut self, _input: Self::InputId, connection: NodeOutput) {
-        if connection.type_id == PipelineDataType::MScan.into() {
-            self.input.connect(connection);
+    fn connect(&mut self, input: Self::InputId, connection: NodeOutput) {
+        match input {
+            InputId::MScan if connection.type_id == PipelineDataType::MScan.into() => {
+                self.input.connect(connection);
+            }
+            InputId::BScans if connection.type_id == PipelineDataType::BScanSegmentation.into() => {
+                self.b_scans.connect(connection);
+            }
+            _ => {}
+        }
+    }
+
+    fn disconnect(&mut self, input: Self::InputId) {
+        match input {
+            InputId::MScan => self.input.disconnect(),
+            InputId::BScans => self.b_scans.disconnect(),
         }
     }
 
-    fn disconnect(&mut self, _input: Self::InputId) {
-        self.input.disconnect();
+    fn progress(&self) -> Option<f32> {
+        *self.progress_rx.as_ref()?.borrow()
     }
 
     fn ui(&mut self, ui: &mut NodeUi) {
         ui.output(
-            OutputIdSingle,
+            OutputId::Filtered,
             PipelineDataType::MScan,
-            PipelineDataType::MScan.color(),
+            PipelineDataType::MScan.pin(),
             |ui| {
                 ui.node_label("M Scan");
             },
         );
 
+        ui.output(
+            OutputId::Original,
+            PipelineDataType::MScan,
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("Original");
+            },
+        );
+
         ui.input(
-            InputIdSingle,
+            InputId::MScan,
             self.input.connection(),
-            PipelineDataType::MScan.color(),
+            PipelineDataType::MScan.pin(),
             |ui| {
                 ui.node_label("M Scan");
             },
         );
 
+        ui.input(
+            InputId::BScans,
+            self.b_scans.connection(),
+            PipelineDataType::BScanSegmentation.pin(),
+            |ui| {
+                ui.node_label("B Scans").on_hover_text(
+                    "Calibrates kernel sizes in degrees with the mean width of the B scans",
+                );
+            },
+        );
+
         ComboBox::from_id_source(ui.id().with("filter_type"))
             .selected_text(format!("{}", self.filter_type))
             .show_ui(ui, |ui| {
@@ -98,31 +189,67 @@ impl EditNode for Node {
             FilterType::Gaussian => {
                 ui.add(
                     DragValue::new(&mut self.gauss_settings.sigma)
+                        .localized()
                         .speed(0.1)
                         .range(0.1..=50.0)
                         .prefix("Sigma: "),
                 );
 
-                let kernel_size = self.gauss_settings.kernel_size.deref_mut();
-
-                ui.node_label("Kernel Size");
-                ui.add(
-                    DragVector::new([&mut kernel_size.x, &mut kernel_size.y])
-                        .range(1..=100)
-                        .prefix(["Rows: ", "Columns: "]),
+                kernel_size_ui(
+                    ui,
+                    &mut self.gauss_settings.kernel_size,
+                    &mut self.gauss_settings.physical,
+                    self.calibration_rx.as_ref().map(|rx| *rx.borrow()),
                 );
             }
             FilterType::Median => {
-                let size = self.median_settings.size.deref_mut();
-
-                ui.node_label("Kernel Size");
+                kernel_size_ui(
+                    ui,
+                    &mut self.median_settings.size,
+                    &mut self.median_settings.physical,
+                    self.calibration_rx.as_ref().map(|rx| *rx.borrow()),
+                );
+            }
+            FilterType::MovingAverage => {
                 ui.add(
-                    DragVector::new([&mut size.x, &mut size.y])
+                    DragValue::new(&mut self.moving_average_settings.width)
                         .range(1..=100)
-                        .prefix(["Rows: ", "Columns: "]),
-                );
+                        .prefix("Width: ")
+                        .suffix(" A scans"),
+                )
+                .on_hover_text("Number of neighboring A scans averaged");
             }
             FilterType::AlignBrightness => {}
+            FilterType::GlobalNormalize => {
+                let settings = &mut self.global_normalize_settings;
+                ui.node_label("Percentiles");
+                ui.add(
+                    DragVector::new([&mut settings.low, &mut settings.high])
+                        .speed(0.1)
+                        .range(0.0..=100.0)
+                        .prefix(["Low: ", "High: "]),
+                )
+                .on_hover_text("Mapped to 0 and 1, over the whole input");
+                settings.high = settings.high.max(settings.low);
+            }
+            FilterType::Levels => {
+                let settings = &mut self.levels_settings;
+                ui.add(Slider::new(&mut settings.black_point, 0.0..=1.0).text("Black"))
+                    .on_hover_text("Mapped to 0, from 0 to 1 of the range of the data type");
+                ui.add(Slider::new(&mut settings.white_point, 0.0..=1.0).text("White"))
+                    .on_hover_text("Mapped to 1, from 0 to 1 of the range of the data type");
+                ui.add(
+                    Slider::new(&mut settings.gamma, 0.1..=10.0)
+                        .logarithmic(true)
+                        .text("Gamma"),
+                )
+                .on_hover_text("Below 1 brightens, above 1 darkens the values in between");
+                settings.white_point = settings.white_point.max(settings.black_point);
+
+                if let Some(rx) = &self.levels_histogram_rx {
+                    levels_histogram_ui(ui, &rx.borrow(), settings);
+                }
+            }
             FilterType::Wiener => {
                 let size = self.wiener_settings.neighborhood_size.deref_mut();
 
@@ -136,11 +263,43 @@ impl EditNode for Node {
             FilterType::Prewitt => {
                 ui.add(
                     DragValue::new(&mut self.prewitt_settings.threshold)
+                        .localized()
                         .speed(0.01)
                         .range(0.0..=1.0)
                         .prefix("Threshold: "),
                 );
             }
+            FilterType::Threshold => {
+                let settings = &mut self.threshold_settings;
+
+                ComboBox::from_id_source(ui.id().with("threshold_mode"))
+                    .selected_text(format!("Mode: {}", settings.mode))
+                    .show_ui(ui, |ui| {
+                        for mode in ThresholdMode::VALUES {
+                            ui.selectable_value(&mut settings.mode, mode, format!("{}", mode));
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Otsu finds the threshold best separating the values of the first chunk",
+                    );
+
+                match settings.mode {
+                    ThresholdMode::Fixed => {
+                        ui.add(Slider::new(&mut settings.threshold, 0.0..=1.0).text("Threshold"))
+                            .on_hover_text("From 0 to 1 of the range of the data type");
+                    }
+                    ThresholdMode::Otsu => {
+                        let threshold = self.otsu_threshold_rx.as_ref().and_then(|rx| *rx.borrow());
+                        if let Some(threshold) = threshold {
+                            ui.label(format!("Threshold: {threshold:.3}"));
+                        }
+                    }
+                }
+
+                ui.checkbox(&mut settings.invert, "Invert")
+                    .on_hover_text("Values below the threshold become 255");
+            }
             FilterType::WidenStructures => {
                 ui.add(
                     DragValue::new(&mut self.widen_structures_settings.width)
@@ -169,9 +328,306 @@ impl EditNode for Node {
             }
         }
 
+        suggestions_ui(ui, self);
+
+        if self.filter_type.is_global() {
+            if let Some(Passes::Single(reason)) = self.passes_rx.as_ref().map(|rx| *rx.borrow()) {
+                let color = ui.visuals().warn_fg_color;
+                ui.colored_label(color, "⚠ Single pass")
+                    .on_hover_text(format!(
+                        "{reason}, so every chunk is processed with its own statistics"
+                    ));
+            }
+        }
+
+        ui.checkbox(&mut self.gating.skip_dark_columns, "Skip Dark A Scans")
+            .on_hover_text(
+                "Do not filter A scans with a mean intensity below the threshold, like during the \
+                 flush or beyond the imaging range",
+            );
+
+        if self.gating.skip_dark_columns {
+            ui.add(
+                DragValue::new(&mut self.gating.dark_threshold)
+                    .localized()
+                    .speed(0.005)
+                    .range(0.0..=1.0)
+                    .prefix("Threshold: "),
+            )
+            .on_hover_text("Mean intensity, from 0 to 1 of the range of the data type");
+
+            ComboBox::from_id_source(ui.id().with("gated_fill"))
+                .selected_text(format!("Skipped: {}", self.gating.fill))
+                .show_ui(ui, |ui| {
+                    for fill in GatedFill::VALUES {
+                        ui.selectable_value(&mut self.gating.fill, fill, format!("{}", fill));
+                    }
+                })
+                .response
+                .on_hover_text("What skipped A scans are replaced with");
+
+            if let Some(stats) = self.gating_rx.as_ref().map(|rx| *rx.borrow()) {
+                gating_stats_ui(ui, stats);
+            }
+        }
+
+        ui.checkbox(&mut self.cache_settings.enabled, "Cache Results")
+            .on_hover_text(
+                "Keep recent results, so switching back to previous settings does not compute \
+                 them again",
+            );
+
+        if self.cache_settings.enabled {
+            ui.add(
+                DragValue::new(&mut self.cache_settings.max_entries)
+                    .range(1..=32)
+                    .prefix("Results: "),
+            );
+            ui.add(
+                DragValue::new(&mut self.cache_settings.max_megabytes)
+                    .range(1..=65536)
+                    .suffix(" MB")
+                    .prefix("Memory: "),
+            );
+
+            if let Some(stats) = self.cache_rx.as_ref().map(|rx| *rx.borrow()) {
+                cache_stats_ui(ui, stats);
+            }
+        }
+
         if let Some(progress) = self.progress_rx.as_ref().and_then(|rx| rx.borrow().clone()) {
             ui.add(ProgressBar::new(progress).rounding(3.0));
             ui.ctx().request_repaint();
         }
     }
+
+    fn context_menu(&mut self, ui: &mut egui::Ui) -> Option<NodeAction> {
+        let clicked = ui
+            .add_enabled(
+                !self.sweep_parameters().is_empty() && self.input.connection().is_some(),
+                egui::Button::new("Parameter Sweep…"),
+            )
+            .on_disabled_hover_text("Needs a connected input and numeric settings")
+            .clicked();
+
+        clicked.then_some(NodeAction::ParameterSweep)
+    }
+}
+
+/// Kernel size, with a toggle between pixels and physical units per
+/// dimension.
+fn kernel_size_ui(
+    ui: &mut NodeUi,
+    pixels: &mut Vector2<usize>,
+    physical: &mut PhysicalKernel,
+    calibration: Option<KernelCalibration>,
+) {
+    const DIMENSIONS: [(&str, &str); 2] = [("Rows: ", "µm"), ("Columns: ", "°")];
+
+    ui.node_label("Kernel Size");
+
+    for (dimension, (prefix, symbol)) in DIMENSIONS.into_iter().enumerate() {
+        ui.horizontal(|ui| {
+            match (dimension, physical.units[dimension]) {
+                (_, KernelUnit::Pixels) => ui.add(
+                    DragValue::new(&mut pixels[dimension])
+                        .range(1..=100)
+                        .prefix(prefix),
+                ),
+                (0, KernelUnit::Physical) => ui.add(
+                    DragValue::new(&mut physical.depth)
+                        .localized()
+                        .range(1.0..=5000.0)
+                        .prefix(prefix),
+                ),
+                (_, KernelUnit::Physical) => ui.add(
+                    DragValue::new(&mut physical.angle)
+                        .localized()
+                        .speed(0.05)
+                        .range(0.01..=90.0)
+                        .prefix(prefix),
+                ),
+            };
+
+            let unit = &mut physical.units[dimension];
+            ui.selectable_value(unit, KernelUnit::Pixels, "px");
+            ui.selectable_value(unit, KernelUnit::Physical, symbol);
+        });
+    }
+
+    if physical.units[0] == KernelUnit::Physical {
+        ui.add(
+            DragValue::new(&mut physical.mm_per_pixel)
+                .localized()
+                .range(0.0..=f32::INFINITY)
+                .speed(0.001)
+                .prefix("mm per pixel: "),
+        );
+    }
+
+    if !physical.is_used() {
+        return;
+    }
+
+    match calibration {
+        Some(KernelCalibration::Converted(size)) => {
+            ui.node_label(format!("{} × {} px", size.x, size.y))
+                .on_hover_text("Kernel size of the last run");
+        }
+        Some(KernelCalibration::Missing(reason)) => {
+            let color = ui.visuals().warn_fg_color;
+            ui.colored_label(color, "⚠ Using pixels")
+                .on_hover_text(format!("{reason}, so the kernel size in pixels is used"));
+        }
+        Some(KernelCalibration::Pixels) | None => {}
+    }
+}
+
+/// Settings of the current filter type suggested from a sample of the input,
+/// see [crate::pipeline::suggestions]. They are only applied on request.
+fn suggestions_ui(ui: &mut NodeUi, node: &mut Node) {
+    if !matches!(
+        node.filter_type,
+        FilterType::Gaussian | FilterType::Median | FilterType::Prewitt
+    ) {
+        return;
+    }
+
+    let suggestions = match &node.suggestions {
+        SuggestionState::None | SuggestionState::Cancelled => return,
+        SuggestionState::Waiting => {
+            ui.weak("Suggestions after the input ran").on_hover_text(
+                "Settings are suggested from the first A scans of the input, once it holds \
+                 data. View or save the input to compute it",
+            );
+            return;
+        }
+        SuggestionState::Sampling => {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.weak("Sampling input…");
+            });
+            return;
+        }
+        SuggestionState::Failed(e) => {
+            ui.weak("No suggestions").on_hover_text(e);
+            return;
+        }
+        SuggestionState::Ready(suggestions) => suggestions,
+    };
+
+    let format = NumberFormat::current();
+    let suggested = node
+        .sweep_parameters()
+        .iter()
+        .filter_map(|&parameter| Some((parameter, suggestions.value(parameter)?)))
+        .collect::<Vec<_>>();
+
+    let text = suggested
+        .iter()
+        .map(|(parameter, value)| format!("{parameter} {}", format.number(*value, 0..=3)))
+        .collect::<Vec<_>>()
+        .join(" · ");
+    let [p1, p50, p95, p99] = suggestions.percentiles;
+    ui.weak(format!("Suggested: {text}")).on_hover_text(format!(
+        "From the first A scans of the input:\n\
+         Noise: {}\n\
+         Speckle grains: {} × {} px\n\
+         Intensity percentiles 1, 50, 95, 99: {}, {}, {}, {}",
+        format.number(suggestions.noise_sigma as f64, 0..=4),
+        format.number(suggestions.grain_size.x as f64, 0..=1),
+        format.number(suggestions.grain_size.y as f64, 0..=1),
+        format.number(p1 as f64, 0..=3),
+        format.number(p50 as f64, 0..=3),
+        format.number(p95 as f64, 0..=3),
+        format.number(p99 as f64, 0..=3),
+    ));
+
+    let applied = suggested
+        .iter()
+        .all(|(parameter, value)| node.parameter(*parameter) == *value);
+    if ui
+        .add_enabled(!applied, egui::Button::new("Apply Suggested"))
+        .clicked()
+    {
+        for (parameter, value) in suggested {
+            node.set_parameter(parameter, value);
+        }
+    }
+}
+
+/// Histogram of the input values of the last chunk, with the black and the
+/// white point marked.
+fn levels_histogram_ui(ui: &mut NodeUi, counts: &[u64], settings: &LevelsSettings) {
+    let Some(max) = counts.iter().copied().max().filter(|&max| max > 0) else {
+        return;
+    };
+
+    let size = vec2(ui.available_width(), 40.0);
+    let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+    let painter = ui.painter_at(rect);
+    let visuals = ui.visuals();
+    painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+
+    // Counts on a square root scale, so small peaks stay visible
+    let width = rect.width() / counts.len() as f32;
+    for (i, &count) in counts.iter().enumerate() {
+        let height = rect.height() * (count as f32 / max as f32).sqrt();
+        let x = rect.left() + i as f32 * width;
+        painter.rect_filled(
+            Rect::from_min_max(
+                pos2(x, rect.bottom() - height),
+                pos2(x + width, rect.bottom()),
+            ),
+            0.0,
+            visuals.text_color().gamma_multiply(0.6),
+        );
+    }
+
+    for (point, color) in [
+        (settings.black_point, Color32::BLACK),
+        (settings.white_point, Color32::WHITE),
+    ] {
+        let x = rect.left() + point.clamp(0.0, 1.0) * rect.width();
+        painter.vline(x, rect.y_range(), Stroke::new(1.5, color));
+    }
+
+    response.on_hover_text("Values of the last chunk of the input, from 0 to 1");
+}
+
+fn gating_stats_ui(ui: &mut NodeUi, stats: GatingStats) {
+    let format = NumberFormat::current();
+    let total = stats.skipped + stats.processed;
+
+    if total == 0 {
+        return;
+    }
+
+    ui.node_label(format!(
+        "{} of {} A scans skipped",
+        format.count(stats.skipped),
+        format.count(total),
+    ))
+    .on_hover_text(
+        "During the current or last run. Check, that the threshold does not skip A scans \
+         showing tissue",
+    );
+}
+
+fn cache_stats_ui(ui: &mut NodeUi, stats: CacheStats) {
+    let format = NumberFormat::current();
+
+    let last_run = match stats.last_run {
+        Some(CacheStatus::Hit) => "Cache hit",
+        Some(CacheStatus::Miss) => "Cache miss",
+        None => "Cache empty",
+    };
+
+    ui.node_label(last_run).on_hover_text(format!(
+        "{} hits · {} misses · {} results · {}",
+        format.count(stats.hits),
+        format.count(stats.misses),
+        stats.entries,
+        format.bytes(stats.bytes as f64),
+    ));
 }
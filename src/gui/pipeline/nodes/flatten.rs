@@ -0,0 +1,106 @@
+use core::fmt;
+
+use egui::{ComboBox, DragValue};
+
+use crate::pipeline::nodes::flatten::{FillMode, InputId, Node};
+
+use super::prelude::*;
+
+impl fmt::Display for FillMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FillMode::Zero => write!(f, "Zero"),
+            FillMode::Edge => write!(f, "Edge"),
+        }
+    }
+}
+
+impl EditNode for Node {
+    type OutputId = OutputIdSingle;
+    type InputId = InputId;
+
+    fn name(&self) -> &str {
+        match self.settings.invert {
+            false => "Flatten",
+            true => "Unflatten",
+        }
+    }
+
+    fn color(&self) -> egui::Color32 {
+        colors::PROCESS
+    }
+
+    fn connect(&mut self, input: Self::InputId, connection: NodeOutput) {
+        match (input, PipelineDataType::from(connection.type_id)) {
+            (InputId::MScan, PipelineDataType::MScan) => {
+                self.m_scan.connect(connection);
+            }
+            (InputId::Segmentation, PipelineDataType::MScanSegmentation) => {
+                self.segmentation.connect(connection);
+            }
+            _ => {}
+        }
+    }
+
+    fn disconnect(&mut self, input: Self::InputId) {
+        match input {
+            InputId::MScan => self.m_scan.disconnect(),
+            InputId::Segmentation => self.segmentation.disconnect(),
+        }
+    }
+
+    fn ui(&mut self, ui: &mut NodeUi) {
+        ui.output(
+            OutputIdSingle,
+            PipelineDataType::MScan,
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan");
+            },
+        );
+
+        ui.input(
+            InputId::MScan,
+            self.m_scan.connection(),
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan");
+            },
+        );
+
+        ui.input(
+            InputId::Segmentation,
+            self.segmentation.connection(),
+            PipelineDataType::MScanSegmentation.pin(),
+            |ui| {
+                ui.node_label("Segmentation");
+            },
+        );
+
+        ui.add(
+            DragValue::new(&mut self.settings.target_row)
+                .range(0..=4096)
+                .prefix("Target Row: "),
+        )
+        .on_hover_text(match self.settings.invert {
+            false => "Row, every A scan is shifted to, so the segmentation lies on it",
+            true => "Target row of the flatten node to undo",
+        });
+
+        ComboBox::from_id_source(ui.id().with("fill"))
+            .selected_text(format!("Fill: {}", self.settings.fill))
+            .show_ui(ui, |ui| {
+                for fill in FillMode::VALUES {
+                    ui.selectable_value(&mut self.settings.fill, fill, format!("{}", fill));
+                }
+            })
+            .response
+            .on_hover_text("How samples shifted in from outside of the A scan are filled");
+
+        ui.checkbox(&mut self.settings.invert, "Invert")
+            .on_hover_text(
+                "Move the segmentation from the target row back to its place. Use the same \
+                 segmentation and target row as the flatten node",
+            );
+    }
+}
@@ -1,11 +1,14 @@
 use egui::DragValue;
 
-use crate::pipeline::nodes::follow_catheter::{InputId, Node};
+use crate::{
+    gui::widgets::DragValueExt,
+    pipeline::nodes::follow_catheter::{InputId, Node, OutputId},
+};
 
 use super::prelude::*;
 
 impl EditNode for Node {
-    type OutputId = OutputIdSingle;
+    type OutputId = OutputId;
     type InputId = InputId;
 
     fn name(&self) -> &str {
@@ -37,18 +40,27 @@ impl EditNode for Node {
 
     fn ui(&mut self, ui: &mut NodeUi) {
         ui.output(
-            OutputIdSingle,
+            OutputId::Segmentation,
             PipelineDataType::MScanSegmentation,
-            PipelineDataType::MScanSegmentation.color(),
+            PipelineDataType::MScanSegmentation.pin(),
             |ui| {
                 ui.node_label("Segmentation");
             },
         );
 
+        ui.output(
+            OutputId::Mask,
+            PipelineDataType::MScan,
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("Catheter Mask");
+            },
+        );
+
         ui.input(
             InputId::MScan,
             self.m_scan.connection(),
-            PipelineDataType::MScan.color(),
+            PipelineDataType::MScan.pin(),
             |ui| {
                 ui.node_label("M-Scan");
             },
@@ -57,7 +69,7 @@ impl EditNode for Node {
         ui.input(
             InputId::BScanSegmentation,
             self.b_scan_segmentation.connection(),
-            PipelineDataType::BScanSegmentation.color(),
+            PipelineDataType::BScanSegmentation.pin(),
             |ui| {
                 ui.node_label("B-Scan Segmentation");
             },
@@ -71,9 +83,12 @@ impl EditNode for Node {
 
         ui.add(
             DragValue::new(&mut self.settings.threshold)
+                .localized()
                 .speed(0.02)
                 .range(0.0..=2.0)
                 .prefix("Seg Threshold: "),
         );
+
+        ui.add(DragValue::new(&mut self.settings.mask_margin).prefix("Mask Margin: "));
     }
 }
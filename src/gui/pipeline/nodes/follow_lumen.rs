@@ -1,6 +1,9 @@
 use egui::DragValue;
 
-use crate::pipeline::nodes::follow_lumen::{InputId, Node};
+use crate::{
+    gui::widgets::DragValueExt,
+    pipeline::nodes::follow_lumen::{InputId, Node},
+};
 
 use super::prelude::*;
 
@@ -39,7 +42,7 @@ impl EditNode for Node {
         ui.output(
             OutputIdSingle,
             PipelineDataType::MScanSegmentation,
-            PipelineDataType::MScanSegmentation.color(),
+            PipelineDataType::MScanSegmentation.pin(),
             |ui| {
                 ui.node_label("Segmentation");
             },
@@ -48,7 +51,7 @@ impl EditNode for Node {
         ui.input(
             InputId::MScan,
             self.m_scan.connection(),
-            PipelineDataType::MScan.color(),
+            PipelineDataType::MScan.pin(),
             |ui| {
                 ui.node_label("M-Scan");
             },
@@ -57,7 +60,7 @@ impl EditNode for Node {
         ui.input(
             InputId::CatheterSegmentation,
             self.catheter_segmentation.connection(),
-            PipelineDataType::MScanSegmentation.color(),
+            PipelineDataType::MScanSegmentation.pin(),
             |ui| {
                 ui.node_label("Catheter Segmentation");
             },
@@ -68,6 +71,7 @@ impl EditNode for Node {
 
         ui.add(
             DragValue::new(&mut self.settings.threshold)
+                .localized()
                 .speed(0.01)
                 .range(0.0..=1.0)
                 .prefix("Threshold: "),
@@ -78,10 +82,17 @@ impl EditNode for Node {
         if self.settings.check_artifact {
             ui.add(
                 DragValue::new(&mut self.settings.artifact_threshold)
+                    .localized()
                     .speed(0.01)
                     .range(0.0..=1.0)
                     .prefix("Artifact Threshold: "),
             );
         }
+
+        ui.checkbox(&mut self.settings.parallel, "Parallel")
+            .on_hover_text(
+                "Follow blocks of A scans in parallel and join them afterwards. The result can \
+                 differ slightly, where the lumen is ambiguous",
+            );
     }
 }
@@ -1,6 +1,9 @@
 use egui::DragValue;
 
-use crate::pipeline::nodes::generate_mesh::{InputId, Node};
+use crate::{
+    gui::widgets::DragValueExt,
+    pipeline::nodes::generate_mesh::{InputId, Node},
+};
 
 use super::prelude::*;
 
@@ -39,7 +42,7 @@ impl EditNode for Node {
         ui.output(
             OutputIdSingle,
             PipelineDataType::Mesh,
-            PipelineDataType::Mesh.color(),
+            PipelineDataType::Mesh.pin(),
             |ui| {
                 ui.node_label("Mesh");
             },
@@ -48,7 +51,7 @@ impl EditNode for Node {
         ui.input(
             InputId::Lumen,
             self.lumen.connection(),
-            PipelineDataType::MScanSegmentation.color(),
+            PipelineDataType::MScanSegmentation.pin(),
             |ui| {
                 ui.node_label("Lumen");
             },
@@ -57,7 +60,7 @@ impl EditNode for Node {
         ui.input(
             InputId::BScans,
             self.b_scans.connection(),
-            PipelineDataType::BScanSegmentation.color(),
+            PipelineDataType::BScanSegmentation.pin(),
             |ui| {
                 ui.node_label("B-Scans");
             },
@@ -71,6 +74,7 @@ impl EditNode for Node {
 
         ui.add(
             DragValue::new(&mut self.settings.rotation_frequency)
+                .localized()
                 .range(0.0..=f32::INFINITY)
                 .prefix("Rotation Frequency: ")
                 .suffix(" Hz"),
@@ -78,6 +82,7 @@ impl EditNode for Node {
 
         ui.add(
             DragValue::new(&mut self.settings.pullback_speed)
+                .localized()
                 .range(0.0..=f32::INFINITY)
                 .speed(0.01)
                 .prefix("Pullback Speed: ")
@@ -86,6 +91,7 @@ impl EditNode for Node {
 
         ui.add(
             DragValue::new(&mut self.settings.mm_per_pixel)
+                .localized()
                 .range(0.0..=f32::INFINITY)
                 .speed(0.001)
                 .prefix("mm per pixel: ")
@@ -94,6 +100,7 @@ impl EditNode for Node {
 
         ui.add(
             DragValue::new(&mut self.settings.refraction_index)
+                .localized()
                 .range(0.0..=f32::INFINITY)
                 .speed(0.01)
                 .prefix("Refraction Index: "),
@@ -0,0 +1,151 @@
+use egui::{Checkbox, DragValue};
+
+use crate::{
+    gui::widgets::DragValueExt,
+    pipeline::nodes::lumen_volume::{InputId, Node, OutputId},
+};
+
+use super::prelude::*;
+
+impl EditNode for Node {
+    type OutputId = OutputId;
+    type InputId = InputId;
+
+    fn name(&self) -> &str {
+        "Lumen Volume"
+    }
+
+    fn color(&self) -> egui::Color32 {
+        colors::PROCESS
+    }
+
+    fn connect(&mut self, input: Self::InputId, connection: NodeOutput) {
+        match (input, PipelineDataType::from(connection.type_id)) {
+            (InputId::BScans, PipelineDataType::BScanSegmentation) => {
+                self.b_scans.connect(connection);
+            }
+            (InputId::Lumen, PipelineDataType::MScanSegmentation) => {
+                self.lumen.connect(connection);
+            }
+            (InputId::Catheter, PipelineDataType::MScanSegmentation) => {
+                self.catheter.connect(connection);
+            }
+            _ => {}
+        }
+    }
+
+    fn disconnect(&mut self, input: Self::InputId) {
+        match input {
+            InputId::BScans => self.b_scans.disconnect(),
+            InputId::Lumen => self.lumen.disconnect(),
+            InputId::Catheter => self.catheter.disconnect(),
+        }
+    }
+
+    fn ui(&mut self, ui: &mut NodeUi) {
+        ui.output(
+            OutputId::Area,
+            PipelineDataType::DataVector,
+            PipelineDataType::DataVector.pin(),
+            |ui| {
+                ui.node_label("Area");
+            },
+        );
+
+        ui.output(
+            OutputId::Volume,
+            PipelineDataType::DataVector,
+            PipelineDataType::DataVector.pin(),
+            |ui| {
+                ui.node_label("Volume");
+            },
+        );
+
+        ui.input(
+            InputId::Lumen,
+            self.lumen.connection(),
+            PipelineDataType::MScanSegmentation.pin(),
+            |ui| {
+                ui.node_label("Lumen");
+            },
+        );
+
+        ui.input(
+            InputId::Catheter,
+            self.catheter.connection(),
+            PipelineDataType::MScanSegmentation.pin(),
+            |ui| {
+                ui.node_label("Catheter");
+            },
+        );
+
+        ui.input(
+            InputId::BScans,
+            self.b_scans.connection(),
+            PipelineDataType::BScanSegmentation.pin(),
+            |ui| {
+                ui.node_label("B-Scans");
+            },
+        );
+
+        ui.add(
+            DragValue::new(&mut self.settings.mm_per_pixel)
+                .localized()
+                .range(0.0..=f32::INFINITY)
+                .speed(0.001)
+                .prefix("mm per pixel: "),
+        );
+
+        ui.add(
+            DragValue::new(&mut self.settings.refraction_index)
+                .localized()
+                .range(0.0..=f32::INFINITY)
+                .speed(0.01)
+                .prefix("refraction index: "),
+        );
+
+        ui.label("Catheter diameter");
+        ui.horizontal(|ui| {
+            ui.spacing_mut().item_spacing.x = 0.0;
+
+            ui.add(Checkbox::without_text(
+                &mut self.settings.use_catheter_diameter,
+            ));
+
+            ui.add_enabled(
+                self.settings.use_catheter_diameter,
+                DragValue::new(&mut self.settings.catheter_diameter)
+                    .range(0.0..=f32::INFINITY)
+                    .speed(0.01)
+                    .length(),
+            );
+        });
+
+        ui.add(
+            DragValue::new(&mut self.settings.pullback_speed)
+                .localized()
+                .range(0.0..=f32::INFINITY)
+                .speed(0.1)
+                .prefix("pullback speed: ")
+                .suffix(" mm/s"),
+        );
+
+        ui.add(
+            DragValue::new(&mut self.settings.rotation_frequency)
+                .localized()
+                .range(0.001..=f32::INFINITY)
+                .speed(1.0)
+                .prefix("rotation frequency: ")
+                .suffix(" Hz"),
+        );
+
+        ui.add(
+            DragValue::new(&mut self.settings.min_coverage)
+                .localized()
+                .range(0.0..=1.0)
+                .speed(0.01)
+                .prefix("min coverage: "),
+        )
+        .on_hover_text("B scans with fewer valid lumen points have no area");
+    }
+}
@@ -1,8 +1,9 @@
-use egui::{Color32, ComboBox, ProgressBar};
+use egui::{Color32, ComboBox, DragValue, ProgressBar};
 
 use crate::{
     gui::widgets::PathInputAction,
-    pipeline::{nodes::output::*, types::DataType},
+    pipeline::{nodes::output::*, priority::Priority, raw_format::Endianness, types::DataType},
+    units::NumberFormat,
 };
 
 use super::prelude::*;
@@ -28,13 +29,20 @@ impl EditNode for Node {
         self.input.disconnect();
     }
 
+    fn progress(&self) -> Option<f32> {
+        match *self.progress_rx.as_ref()?.borrow() {
+            Progress::Working(progress) => progress,
+            Progress::Idle | Progress::Items(_) | Progress::Waiting | Progress::Failed(_) => None,
+        }
+    }
+
     fn ui(&mut self, ui: &mut NodeUi) {
         ui.input(
             InputIdSingle,
             self.input.connection(),
             match self.input.connection() {
-                Some(_) => self.input_type.color(),
-                None => Color32::GRAY,
+                Some(_) => self.input_type.pin(),
+                None => Color32::GRAY.into(),
             },
             |ui| {
                 ui.node_label(match self.input.connection() {
@@ -56,17 +64,140 @@ impl EditNode for Node {
                         );
                     }
                 });
+
+            ComboBox::from_id_source(ui.id().with("endianness"))
+                .selected_text(format!("{}", self.endianness))
+                .show_ui(ui, |ui| {
+                    for endianness in Endianness::VALUES {
+                        ui.selectable_value(
+                            &mut self.endianness,
+                            endianness,
+                            format!("{}", endianness),
+                        );
+                    }
+                });
+
+            ui.checkbox(&mut self.header, "Header").on_hover_text(
+                "Prepend a 32 byte header with the data type, the A scan length and count and \
+                 the endianness",
+            );
+
+            ui.checkbox(&mut self.selected_range_only, "Selected Range Only")
+                .on_hover_text("Export only the A scans selected in the range selector");
+        }
+
+        let formats = ExportFormat::available(self.input_type);
+        if formats.len() > 1 {
+            ComboBox::from_id_source(ui.id().with("format"))
+                .selected_text(format!("{}", self.export_format()))
+                .show_ui(ui, |ui| {
+                    for format in formats {
+                        ui.selectable_value(&mut self.format, *format, format!("{}", format));
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "JSON writes one document with the schema version, the A scan count and \
+                     the provenance, which a \"Bundle\" node can combine several outputs into",
+                );
         }
 
-        ui.add(PathInput::new(&mut self.path).action(PathInputAction::SaveFile));
+        if let (
+            ExportFormat::Json,
+            PipelineDataType::MScanSegmentation | PipelineDataType::Bundle,
+        ) = (self.export_format(), self.input_type)
+        {
+            ui.add(
+                DragValue::new(&mut self.contour_stride)
+                    .range(1..=usize::MAX)
+                    .prefix("Contour Stride: "),
+            )
+            .on_hover_text("Write the lumen of every n-th A scan only");
+        }
+
+        ComboBox::from_id_source(ui.id().with("priority"))
+            .selected_text(format!("Priority: {}", self.priority.name()))
+            .show_ui(ui, |ui| {
+                for priority in Priority::ALL {
+                    ui.selectable_value(&mut self.priority, priority, priority.name());
+                }
+            })
+            .response
+            .on_hover_text(
+                "Background saves run on fewer threads with a lower priority and wait for \
+                 other nodes, so interactive work stays responsive",
+            );
+
+        ComboBox::from_id_source(ui.id().with("destination"))
+            .selected_text(destination_name(&self.destination))
+            .show_ui(ui, |ui| {
+                let address = self.destination.address().unwrap_or(DEFAULT_ADDRESS);
+                for destination in [
+                    Destination::File,
+                    Destination::Listen(address.to_string()),
+                    Destination::Connect(address.to_string()),
+                ] {
+                    let name = destination_name(&destination);
+                    ui.selectable_value(&mut self.destination, destination, name);
+                }
+            })
+            .response
+            .on_hover_text(
+                "Stream the export over TCP to another program instead of writing a file, see \
+                 the stream format in the technical overview",
+            );
+
+        match &mut self.destination {
+            Destination::File => {
+                ui.add(PathInput::new(&mut self.path).action(PathInputAction::SaveFile));
+
+                ui.checkbox(&mut self.separate_runs, "Separate Runs")
+                    .on_hover_text(
+                        "When saving all outputs as a run, write into a directory named after \
+                         the run, next to the file",
+                    );
+            }
+            Destination::Listen(address) | Destination::Connect(address) => {
+                ui.text_edit_singleline(address)
+                    .on_hover_text("Address and port, like 127.0.0.1:7000");
+            }
+        }
 
-        if ui.button("Save").clicked() {
+        let save_text = match self.destination {
+            Destination::Listen(_) => "Serve",
+            Destination::File | Destination::Connect(_) => "Save",
+        };
+        if ui.button(save_text).clicked() {
             self.save();
         }
 
-        if let Some(progress_rx) = &self.progress_rx {
-            match progress_rx.borrow().clone() {
+        let progress = self
+            .progress_rx
+            .as_ref()
+            .map(|progress_rx| progress_rx.borrow().clone());
+
+        if let Some(progress) = progress {
+            match progress {
                 Progress::Idle => {}
+                Progress::Waiting => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(match self.destination {
+                            Destination::Listen(_) => "Waiting for a client",
+                            Destination::File | Destination::Connect(_) => "Connecting",
+                        });
+                        if ui.button("Stop").clicked() {
+                            self.stop();
+                        }
+                    });
+                }
+                Progress::Failed(e) => {
+                    let color = ui.visuals().error_fg_color;
+                    ui.colored_label(color, e);
+                    if ui.button("Retry").clicked() {
+                        self.retry();
+                    }
+                }
                 Progress::Working(None) => {
                     ui.add(
                         ProgressBar::new(0.9999)
@@ -79,7 +210,29 @@ impl EditNode for Node {
                     ui.add(ProgressBar::new(progress).rounding(3.0));
                     ui.ctx().request_repaint();
                 }
+                Progress::Items(items) => {
+                    ui.add(
+                        ProgressBar::new(0.9999)
+                            .rounding(3.0)
+                            .text(format!(
+                                "processed {} items",
+                                NumberFormat::current().count(items)
+                            ))
+                            .animate(true),
+                    );
+                }
             }
         }
     }
 }
+
+/// Address offered, when switching from a file to a stream.
+const DEFAULT_ADDRESS: &str = "127.0.0.1:7000";
+
+fn destination_name(destination: &Destination) -> &'static str {
+    match destination {
+        Destination::File => "File",
+        Destination::Listen(_) => "Listen",
+        Destination::Connect(_) => "Connect",
+    }
+}
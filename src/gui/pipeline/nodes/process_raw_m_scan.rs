@@ -1,6 +1,6 @@
-use egui::{Color32, DragValue, ProgressBar};
+use egui::{Button, Color32, ComboBox, DragValue, ProgressBar, Ui};
 
-use crate::pipeline::nodes::process_raw_m_scan::*;
+use crate::{gui::widgets::DragValueExt, pipeline::nodes::process_raw_m_scan::*};
 
 use super::prelude::*;
 
@@ -33,11 +33,18 @@ impl EditNode for Node {
         }
     }
 
+    fn progress(&self) -> Option<f32> {
+        *self.progress_rx.as_ref()?.borrow()
+    }
+
     fn ui(&mut self, ui: &mut NodeUi) {
+        let offset_connected = self.offset.connection().is_some();
+        let chirp_connected = self.chirp.connection().is_some();
+
         ui.output(
             OutputIdSingle,
             PipelineDataType::MScan,
-            PipelineDataType::MScan.color(),
+            PipelineDataType::MScan.pin(),
             |ui| {
                 ui.node_label("M Scan");
             },
@@ -46,7 +53,7 @@ impl EditNode for Node {
         ui.input(
             InputId::RawMScan,
             self.raw_scan.connection(),
-            PipelineDataType::RawMScan.color(),
+            PipelineDataType::RawMScan.pin(),
             |ui| {
                 ui.node_label("Raw M Scan");
             },
@@ -55,32 +62,139 @@ impl EditNode for Node {
         ui.input(
             InputId::Offset,
             self.offset.connection(),
-            PipelineDataType::DataVector.color(),
+            PipelineDataType::DataVector.pin(),
             |ui| {
-                ui.node_label("Offset");
+                ui.horizontal(|ui| {
+                    ui.node_label("Offset");
+                    optional_input_status(ui, offset_connected);
+                })
+                .response
+                .on_hover_text("Detector offset, subtracted from every A scan");
             },
         );
 
         ui.input(
             InputId::Chirp,
             self.chirp.connection(),
-            PipelineDataType::DataVector.color(),
+            PipelineDataType::DataVector.pin(),
             |ui| {
-                ui.node_label("Chirp");
+                ui.horizontal(|ui| {
+                    ui.node_label("Chirp");
+                    optional_input_status(ui, chirp_connected);
+                })
+                .response
+                .on_hover_text("Sample positions of the A scans, used to resample them linearly");
             },
         );
 
-        ui.add(
-            DragValue::new(&mut self.factor)
-                .range(1.0..=f64::INFINITY)
-                .prefix("Factor: "),
-        );
+        ui.horizontal(|ui| {
+            ui.add(
+                DragValue::new(&mut self.factor)
+                    .localized()
+                    .range(1.0..=10000.0)
+                    .prefix("Factor: "),
+            )
+            .on_hover_text(
+                "Raw values and the offset are multiplied with this factor, like in the \
+                MATLAB implementation. Only affects the result with fixed rescale bounds.",
+            );
 
-        ui.add(
-            DragValue::new(&mut self.rescale_cutoff)
-                .range(1..=usize::MAX)
-                .prefix("Rescale Cutoff: "),
-        );
+            if ui
+                .add_enabled(
+                    self.factor != Node::DEFAULT_FACTOR,
+                    Button::new("⟲").small(),
+                )
+                .on_hover_text(format!("Reset to {}", Node::DEFAULT_FACTOR))
+                .clicked()
+            {
+                self.factor = Node::DEFAULT_FACTOR;
+            }
+        });
+
+        let bounds = self.bounds_rx.as_ref().and_then(|rx| *rx.borrow());
+
+        ComboBox::from_id_source(ui.id().with("rescale_mode"))
+            .selected_text(rescale_mode_name(&self.rescale_mode))
+            .show_ui(ui, |ui| {
+                let (lower, upper) = bounds.unwrap_or((0.0, 100.0));
+                for mode in [
+                    RescaleMode::FirstChunk,
+                    RescaleMode::PERCENTILE_DEFAULT,
+                    RescaleMode::Fixed { lower, upper },
+                ] {
+                    let selected =
+                        std::mem::discriminant(&self.rescale_mode) == std::mem::discriminant(&mode);
+                    if ui
+                        .selectable_label(selected, rescale_mode_name(&mode))
+                        .clicked()
+                        && !selected
+                    {
+                        self.rescale_mode = mode;
+                    }
+                }
+            })
+            .response
+            .on_hover_text("How the value range is found, that is mapped to 0 to 1");
+
+        match &mut self.rescale_mode {
+            RescaleMode::FirstChunk => {
+                ui.add(
+                    DragValue::new(&mut self.rescale_cutoff)
+                        .localized()
+                        .range(1..=usize::MAX)
+                        .prefix("Rescale Cutoff: "),
+                )
+                .on_hover_text(
+                    "Number of the lowest and highest values of the first chunk, that are \
+                    ignored when finding the value range",
+                );
+            }
+            RescaleMode::Percentile {
+                low,
+                high,
+                sample_a_scans,
+            } => {
+                ui.add(
+                    DragValue::new(low)
+                        .localized()
+                        .range(0.0..=*high)
+                        .speed(0.01)
+                        .prefix("Low: ")
+                        .suffix(" %"),
+                );
+                ui.add(
+                    DragValue::new(high)
+                        .localized()
+                        .range(*low..=100.0)
+                        .speed(0.01)
+                        .prefix("High: ")
+                        .suffix(" %"),
+                );
+                ui.add(
+                    DragValue::new(sample_a_scans)
+                        .range(1..=usize::MAX)
+                        .prefix("Sampled A Scans: "),
+                );
+            }
+            RescaleMode::Fixed { lower, upper } => {
+                ui.add(
+                    DragValue::new(lower)
+                        .localized()
+                        .speed(0.1)
+                        .prefix("Lower: "),
+                );
+                ui.add(
+                    DragValue::new(upper)
+                        .localized()
+                        .speed(0.1)
+                        .prefix("Upper: "),
+                );
+            }
+        }
+
+        if let Some((lower, upper)) = bounds {
+            ui.label(format!("Bounds: {:.2} to {:.2}", lower, upper));
+        }
 
         if let Some(progress) = self.progress_rx.as_ref().and_then(|rx| rx.borrow().clone()) {
             ui.add(ProgressBar::new(progress).rounding(3.0));
@@ -88,3 +202,21 @@ impl EditNode for Node {
         }
     }
 }
+
+/// Shows whether an optional input is connected.
+fn optional_input_status(ui: &mut Ui, connected: bool) {
+    if connected {
+        ui.colored_label(Color32::GREEN, "●")
+            .on_hover_text("Connected");
+    } else {
+        ui.weak("optional");
+    }
+}
+
+fn rescale_mode_name(mode: &RescaleMode) -> &'static str {
+    match mode {
+        RescaleMode::FirstChunk => "First Chunk",
+        RescaleMode::Percentile { .. } => "Percentile",
+        RescaleMode::Fixed { .. } => "Fixed",
+    }
+}
@@ -0,0 +1,72 @@
+use egui::DragValue;
+
+use crate::pipeline::nodes::rechunk::{InputId, Node};
+
+use super::prelude::*;
+
+impl EditNode for Node {
+    type OutputId = OutputIdSingle;
+    type InputId = InputId;
+
+    fn name(&self) -> &str {
+        "Rechunk by B-scan"
+    }
+
+    fn color(&self) -> egui::Color32 {
+        colors::PROCESS
+    }
+
+    fn connect(&mut self, input: Self::InputId, connection: NodeOutput) {
+        match (input, PipelineDataType::from(connection.type_id)) {
+            (InputId::MScan, PipelineDataType::MScan) => {
+                self.m_scan.connect(connection);
+            }
+            (InputId::BScanSegmentation, PipelineDataType::BScanSegmentation) => {
+                self.b_scan_segmentation.connect(connection);
+            }
+            _ => {}
+        }
+    }
+
+    fn disconnect(&mut self, input: Self::InputId) {
+        match input {
+            InputId::MScan => self.m_scan.disconnect(),
+            InputId::BScanSegmentation => self.b_scan_segmentation.disconnect(),
+        }
+    }
+
+    fn ui(&mut self, ui: &mut NodeUi) {
+        ui.output(
+            OutputIdSingle,
+            PipelineDataType::MScan,
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan");
+            },
+        );
+
+        ui.input(
+            InputId::MScan,
+            self.m_scan.connection(),
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan");
+            },
+        );
+
+        ui.input(
+            InputId::BScanSegmentation,
+            self.b_scan_segmentation.connection(),
+            PipelineDataType::BScanSegmentation.pin(),
+            |ui| {
+                ui.node_label("B-Scans");
+            },
+        );
+
+        ui.add(
+            DragValue::new(&mut self.b_scans_per_chunk)
+                .range(1..=usize::MAX)
+                .prefix("B scans per chunk: "),
+        );
+    }
+}
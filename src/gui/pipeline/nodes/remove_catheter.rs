@@ -0,0 +1,120 @@
+use core::fmt;
+
+use egui::{ComboBox, DragValue};
+
+use crate::pipeline::nodes::remove_catheter::{FillMode, InputId, Node};
+
+use super::prelude::*;
+
+impl fmt::Display for FillMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FillMode::Zero => write!(f, "Zero"),
+            FillMode::Background => write!(f, "Background"),
+            FillMode::Fade => write!(f, "Fade"),
+        }
+    }
+}
+
+impl EditNode for Node {
+    type OutputId = OutputIdSingle;
+    type InputId = InputId;
+
+    fn name(&self) -> &str {
+        "Remove Catheter Region"
+    }
+
+    fn color(&self) -> egui::Color32 {
+        colors::PROCESS
+    }
+
+    fn connect(&mut self, input: Self::InputId, connection: NodeOutput) {
+        match (input, PipelineDataType::from(connection.type_id)) {
+            (InputId::MScan, PipelineDataType::MScan) => {
+                self.m_scan.connect(connection);
+            }
+            (InputId::CatheterSegmentation, PipelineDataType::MScanSegmentation) => {
+                self.catheter_segmentation.connect(connection);
+            }
+            _ => {}
+        }
+    }
+
+    fn disconnect(&mut self, input: Self::InputId) {
+        match input {
+            InputId::MScan => self.m_scan.disconnect(),
+            InputId::CatheterSegmentation => self.catheter_segmentation.disconnect(),
+        }
+    }
+
+    fn ui(&mut self, ui: &mut NodeUi) {
+        ui.output(
+            OutputIdSingle,
+            PipelineDataType::MScan,
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan");
+            },
+        );
+
+        ui.input(
+            InputId::MScan,
+            self.m_scan.connection(),
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan");
+            },
+        );
+
+        ui.input(
+            InputId::CatheterSegmentation,
+            self.catheter_segmentation.connection(),
+            PipelineDataType::MScanSegmentation.pin(),
+            |ui| {
+                ui.node_label("Catheter Segmentation");
+            },
+        );
+
+        ComboBox::from_id_source(ui.id().with("mode"))
+            .selected_text(format!("{}", self.settings.mode))
+            .show_ui(ui, |ui| {
+                for mode in FillMode::VALUES {
+                    ui.selectable_value(&mut self.settings.mode, mode, format!("{}", mode));
+                }
+            })
+            .response
+            .on_hover_text("How the samples above the catheter line are replaced");
+
+        ui.add(
+            DragValue::new(&mut self.settings.margin)
+                .range(0..=200)
+                .prefix("Margin: "),
+        )
+        .on_hover_text("Samples below the catheter line, that are removed as well");
+
+        match self.settings.mode {
+            FillMode::Zero => {}
+            FillMode::Background => {
+                ui.add(
+                    DragValue::new(&mut self.settings.background_samples)
+                        .range(1..=200)
+                        .prefix("Background Samples: "),
+                )
+                .on_hover_text("Samples below the removed region, whose median is filled in");
+            }
+            FillMode::Fade => {
+                ui.add(
+                    DragValue::new(&mut self.settings.fade_length)
+                        .range(1..=200)
+                        .prefix("Fade Length: "),
+                );
+            }
+        }
+
+        ui.checkbox(&mut self.settings.flatten, "Flatten to Catheter")
+            .on_hover_text(
+                "Shift every A scan up, so the catheter line becomes the first row. Useful before \
+                 exporting aligned stacks",
+            );
+    }
+}
@@ -30,7 +30,7 @@ impl EditNode for Node {
         ui.output(
             OutputIdSingle,
             PipelineDataType::MScan,
-            PipelineDataType::MScan.color(),
+            PipelineDataType::MScan.pin(),
             |ui| {
                 ui.node_label("M Scan");
             },
@@ -39,7 +39,7 @@ impl EditNode for Node {
         ui.input(
             InputIdSingle,
             self.m_scan.connection(),
-            PipelineDataType::MScan.color(),
+            PipelineDataType::MScan.pin(),
             |ui| {
                 ui.node_label("M Scan");
             },
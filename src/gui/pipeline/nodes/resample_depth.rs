@@ -0,0 +1,90 @@
+use core::fmt;
+
+use egui::{ComboBox, DragValue};
+
+use crate::pipeline::nodes::resample_depth::{Interpolation, Node, OutputId};
+
+use super::prelude::*;
+
+impl fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Interpolation::Linear => write!(f, "Linear"),
+            Interpolation::Cubic => write!(f, "Cubic"),
+        }
+    }
+}
+
+impl EditNode for Node {
+    type OutputId = OutputId;
+    type InputId = InputIdSingle;
+
+    fn name(&self) -> &str {
+        "Resample Depth"
+    }
+
+    fn color(&self) -> egui::Color32 {
+        colors::PROCESS
+    }
+
+    fn connect(&mut self, _input: Self::InputId, connection: NodeOutput) {
+        if connection.type_id == PipelineDataType::MScan.into() {
+            self.m_scan.connect(connection);
+        }
+    }
+
+    fn disconnect(&mut self, _input: Self::InputId) {
+        self.m_scan.disconnect();
+    }
+
+    fn ui(&mut self, ui: &mut NodeUi) {
+        ui.output(
+            OutputId::MScan,
+            PipelineDataType::MScan,
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan");
+            },
+        );
+
+        ui.output(
+            OutputId::Scale,
+            PipelineDataType::DataVector,
+            PipelineDataType::DataVector.pin(),
+            |ui| {
+                ui.node_label("Scale").on_hover_text(
+                    "Samples of the input per sample of the output. Multiply rows of \
+                     results downstream with it to get rows of the input",
+                );
+            },
+        );
+
+        ui.input(
+            InputIdSingle,
+            self.m_scan.connection(),
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan");
+            },
+        );
+
+        ui.add(
+            DragValue::new(&mut self.settings.samples)
+                .range(2..=8192)
+                .prefix("Samples: "),
+        )
+        .on_hover_text("Samples of every A scan after resampling");
+
+        ComboBox::from_id_source(ui.id().with("interpolation"))
+            .selected_text(format!("Interpolation: {}", self.settings.interpolation))
+            .show_ui(ui, |ui| {
+                for interpolation in Interpolation::VALUES {
+                    ui.selectable_value(
+                        &mut self.settings.interpolation,
+                        interpolation,
+                        format!("{}", interpolation),
+                    );
+                }
+            });
+    }
+}
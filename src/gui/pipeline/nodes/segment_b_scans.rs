@@ -26,11 +26,15 @@ impl EditNode for Node {
         self.m_scan.disconnect();
     }
 
+    fn progress(&self) -> Option<f32> {
+        *self.progress_rx.as_ref()?.borrow()
+    }
+
     fn ui(&mut self, ui: &mut NodeUi) {
         ui.output(
             OutputIdSingle,
             PipelineDataType::BScanSegmentation,
-            PipelineDataType::BScanSegmentation.color(),
+            PipelineDataType::BScanSegmentation.pin(),
             |ui| {
                 ui.node_label("B Scan Segmentation");
             },
@@ -39,7 +43,7 @@ impl EditNode for Node {
         ui.input(
             InputIdSingle,
             self.m_scan.connection(),
-            PipelineDataType::MScan.color(),
+            PipelineDataType::MScan.pin(),
             |ui| {
                 ui.node_label("M Scan");
             },
@@ -0,0 +1,72 @@
+use crate::{pipeline::nodes::segmentation_input::*, units::NumberFormat};
+
+use super::prelude::*;
+
+impl EditNode for Node {
+    type OutputId = OutputIdSingle;
+    type InputId = InputIdSingle;
+
+    fn name(&self) -> &str {
+        "Segmentation Input"
+    }
+
+    fn color(&self) -> egui::Color32 {
+        colors::INPUT
+    }
+
+    fn connect(&mut self, _input: Self::InputId, connection: NodeOutput) {
+        if connection.type_id == PipelineDataType::MScan.into() {
+            self.m_scan.connect(connection);
+        }
+    }
+
+    fn disconnect(&mut self, _input: Self::InputId) {
+        self.m_scan.disconnect();
+    }
+
+    fn ui(&mut self, ui: &mut NodeUi) {
+        ui.output(
+            OutputIdSingle,
+            PipelineDataType::MScanSegmentation,
+            PipelineDataType::MScanSegmentation.pin(),
+            |ui| {
+                ui.node_label("M Scan Segmentation");
+            },
+        );
+
+        ui.input(
+            InputIdSingle,
+            self.m_scan.connection(),
+            PipelineDataType::MScan.pin(),
+            |ui| {
+                ui.node_label("M Scan").on_hover_text(
+                    "Optional, the segmentation is checked against the number of A scans and \
+                     their samples",
+                );
+            },
+        );
+
+        ui.add(PathInput::new(&mut self.path)).on_hover_text(
+            "A segmentation exported by the output node, with its sidecar file ending in .json",
+        );
+
+        let status = self.status_rx.as_ref().map(|rx| rx.borrow().clone());
+        match status {
+            Some(Status::Loaded(sidecar)) => {
+                let format = NumberFormat::current();
+                ui.label(format!("{} A scans", format.count(sidecar.a_scan_count)))
+                    .on_hover_text(format!(
+                        "{} chunks, {} samples per A scan\nProvenance: {}",
+                        format.count(sidecar.chunks.len()),
+                        format.count(sidecar.a_scan_samples),
+                        sidecar.provenance.as_deref().unwrap_or("unknown"),
+                    ));
+            }
+            Some(Status::Failed(message)) => {
+                let color = ui.visuals().error_fg_color;
+                ui.colored_label(color, message);
+            }
+            Some(Status::Idle) | None => {}
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    gui::node_graph::NodeProgress, node_graph::NodeId, pipeline::Pipeline, units::NumberFormat,
+};
+
+/// How often the progress of the nodes is sampled.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Maximum number of samples kept per node, covering the last 30 seconds.
+const HISTORY_LENGTH: usize = 120;
+
+/// Recent progress samples of a single node, as fractions from 0 to 1.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressHistory {
+    samples: VecDeque<(Instant, f32)>,
+    last_change: Option<Instant>,
+}
+
+impl ProgressHistory {
+    /// Adds a sample, dropping the oldest one if the history is full. A
+    /// fraction lower than the last one means the node started over, so the
+    /// history is reset first.
+    pub fn push(&mut self, time: Instant, fraction: f32) {
+        match self.samples.back() {
+            Some((_, last)) if fraction == *last => {}
+            Some((_, last)) if fraction < *last => {
+                self.reset();
+                self.last_change = Some(time);
+            }
+            _ => self.last_change = Some(time),
+        }
+
+        if self.samples.len() == HISTORY_LENGTH {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((time, fraction));
+    }
+
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.last_change = None;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Fraction per second between the last two samples.
+    pub fn throughput(&self) -> Option<f32> {
+        let mut samples = self.samples.iter().rev();
+        let ((time, fraction), (last_time, last_fraction)) = (samples.next()?, samples.next()?);
+
+        match time.duration_since(*last_time).as_secs_f32() {
+            secs if secs > 0.0 => Some((fraction - last_fraction) / secs),
+            _ => None,
+        }
+    }
+
+    /// Time since the fraction last changed.
+    pub fn since_change(&self, now: Instant) -> Option<Duration> {
+        self.last_change.map(|time| now.duration_since(time))
+    }
+
+    /// Whether the fraction has not changed for at least `threshold`.
+    pub fn is_stalled(&self, now: Instant, threshold: Duration) -> bool {
+        self.since_change(now)
+            .is_some_and(|duration| duration >= threshold)
+    }
+
+    fn fractions(&self) -> Vec<f32> {
+        self.samples.iter().map(|(_, fraction)| *fraction).collect()
+    }
+}
+
+/// Samples the progress of every node in the pipeline and turns it into
+/// [NodeProgress] for the pipeline editor.
+pub struct ProgressMonitor {
+    last_sample: Option<Instant>,
+    histories: HashMap<NodeId, ProgressHistory>,
+    progress: HashMap<NodeId, NodeProgress>,
+}
+
+impl ProgressMonitor {
+    pub fn new() -> Self {
+        Self {
+            last_sample: None,
+            histories: HashMap::new(),
+            progress: HashMap::new(),
+        }
+    }
+
+    /// Samples the nodes, if [SAMPLE_INTERVAL] has passed since the last
+    /// sample. Nodes without progress, because they are idle or got
+    /// invalidated, lose their history. Returns true, while any node is
+    /// working.
+    pub fn update(&mut self, pipeline: &mut Pipeline, stall_threshold: Duration) -> bool {
+        let now = Instant::now();
+
+        if let Some(last_time) = self.last_sample {
+            if now.duration_since(last_time) < SAMPLE_INTERVAL {
+                return self.is_active();
+            }
+        }
+
+        self.histories
+            .retain(|node_id, _| pipeline.nodes.contains_key(node_id));
+
+        for (node_id, node) in &mut pipeline.nodes {
+            let history = self.histories.entry(*node_id).or_default();
+            match node.as_edit_node_mut().progress() {
+                Some(fraction) => history.push(now, fraction),
+                None => history.reset(),
+            }
+        }
+
+        let format = NumberFormat::current();
+
+        self.progress = self
+            .histories
+            .iter()
+            .filter(|(_, history)| !history.is_empty())
+            .map(|(node_id, history)| {
+                let fraction = history
+                    .samples
+                    .back()
+                    .map_or(0.0, |(_, fraction)| *fraction);
+                let throughput = history.throughput().unwrap_or(0.0);
+                let since_change = history.since_change(now).unwrap_or_default();
+
+                let progress = NodeProgress {
+                    history: history.fractions(),
+                    stalled: history.is_stalled(now, stall_threshold),
+                    description: format!(
+                        "{} % · {} %/s · last change {} s ago",
+                        format.number(fraction as f64 * 100.0, 0..=1),
+                        format.number(throughput as f64 * 100.0, 0..=2),
+                        format.number(since_change.as_secs_f64(), 0..=1),
+                    ),
+                };
+
+                (*node_id, progress)
+            })
+            .collect();
+
+        self.last_sample = Some(now);
+
+        self.is_active()
+    }
+
+    pub fn progress(&self) -> &HashMap<NodeId, NodeProgress> {
+        &self.progress
+    }
+
+    fn is_active(&self) -> bool {
+        !self.progress.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn history() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        let mut history = ProgressHistory::default();
+        history.push(at(0), 0.0);
+        assert_eq!(history.throughput(), None);
+
+        history.push(at(500), 0.25);
+        history.push(at(1000), 0.5);
+        assert_eq!(history.throughput(), Some(0.5));
+        assert_eq!(history.since_change(at(1000)), Some(Duration::ZERO));
+
+        history.push(at(2000), 0.5);
+        assert_eq!(history.throughput(), Some(0.0));
+        assert_eq!(history.since_change(at(3000)), Some(Duration::from_secs(2)));
+        assert!(history.is_stalled(at(3000), Duration::from_secs(2)));
+        assert!(!history.is_stalled(at(3000), Duration::from_secs(3)));
+
+        // Started over
+        history.push(at(4000), 0.1);
+        assert_eq!(history.fractions(), vec![0.1]);
+        assert_eq!(history.since_change(at(4000)), Some(Duration::ZERO));
+
+        for i in 0..2 * HISTORY_LENGTH as u64 {
+            history.push(at(5000 + i), 0.2);
+        }
+        assert_eq!(history.fractions().len(), HISTORY_LENGTH);
+
+        history.reset();
+        assert!(history.is_empty());
+        assert_eq!(history.since_change(at(0)), None);
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    gui::node_graph::{ConnectionActivity, ConnectionBadge},
+    node_graph::{InputId, NodeId},
+    pipeline::{
+        execution::TransferSnapshot,
+        types::{DataType, TypeHandling},
+        Pipeline, PipelineExecutor,
+    },
+    units::NumberFormat,
+};
+
+/// How often the transfer statistics are polled from the executor.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Data rate in bytes per second, at which a connection is drawn at half of
+/// the full intensity.
+const HALF_INTENSITY_RATE: f64 = 50e6;
+
+type Snapshots = HashMap<(NodeId, InputId), TransferSnapshot>;
+
+/// Polls the data sent through every connection of the pipeline from the
+/// [PipelineExecutor] and turns it into [ConnectionActivity] for the pipeline
+/// editor. M scan connections get a [ConnectionBadge] with their data type.
+pub struct TransferMonitor {
+    last_poll: Option<(Instant, Snapshots)>,
+    activity: HashMap<(NodeId, InputId), ConnectionActivity>,
+    badges: HashMap<(NodeId, InputId), ConnectionBadge>,
+}
+
+impl TransferMonitor {
+    pub fn new() -> Self {
+        Self {
+            last_poll: None,
+            activity: HashMap::new(),
+            badges: HashMap::new(),
+        }
+    }
+
+    /// Polls the executor, if [POLL_INTERVAL] has passed since the last
+    /// poll. Returns true, while data is flowing through any connection.
+    pub fn update(&mut self, executor: &PipelineExecutor, pipeline: &Pipeline) -> bool {
+        let now = Instant::now();
+
+        if let Some((last_time, _)) = &self.last_poll {
+            if now.duration_since(*last_time) < POLL_INTERVAL {
+                return self.is_active();
+            }
+        }
+
+        let stats = executor.transfer_stats();
+        let format = NumberFormat::current();
+
+        self.activity = stats
+            .iter()
+            .map(|(connection, snapshot)| {
+                let rate = self.last_poll.as_ref().map_or(0.0, |(last_time, last)| {
+                    let previous = last.get(connection).copied().unwrap_or_default();
+                    rate(previous, *snapshot, now.duration_since(*last_time))
+                });
+
+                let activity = ConnectionActivity {
+                    intensity: (rate / (rate + HALF_INTENSITY_RATE)) as f32,
+                    description: format!(
+                        "{} chunks · {} · {}/s",
+                        snapshot.chunks,
+                        format.bytes(snapshot.bytes as f64),
+                        format.bytes(rate),
+                    ),
+                };
+
+                (*connection, activity)
+            })
+            .collect();
+
+        self.badges = stats
+            .iter()
+            .filter_map(|(connection @ (node_id, input_id), snapshot)| {
+                let data_type = snapshot.data_type?;
+                let handling = pipeline
+                    .nodes
+                    .get(node_id)?
+                    .type_handling(*input_id, data_type);
+
+                Some((*connection, badge(data_type, handling)))
+            })
+            .collect();
+
+        self.last_poll = Some((now, stats));
+
+        self.is_active()
+    }
+
+    pub fn activity(&self) -> &HashMap<(NodeId, InputId), ConnectionActivity> {
+        &self.activity
+    }
+
+    pub fn badges(&self) -> &HashMap<(NodeId, InputId), ConnectionBadge> {
+        &self.badges
+    }
+
+    fn is_active(&self) -> bool {
+        self.activity
+            .values()
+            .any(|activity| activity.intensity > 0.0)
+    }
+}
+
+/// Bytes per second sent between two snapshots. Zero, if the counters got
+/// reset in between.
+fn rate(previous: TransferSnapshot, current: TransferSnapshot, elapsed: Duration) -> f64 {
+    let bytes = current.bytes.saturating_sub(previous.bytes);
+
+    match elapsed.as_secs_f64() {
+        secs if secs > 0.0 => bytes as f64 / secs,
+        _ => 0.0,
+    }
+}
+
+/// Badge of an M scan connection carrying `data_type` to a node handling it
+/// like `handling`. Conversions are highlighted and explained with their
+/// effect on memory.
+fn badge(data_type: DataType, handling: TypeHandling) -> ConnectionBadge {
+    let label = format!("{data_type:?}");
+
+    let Some(target) = handling.target(data_type) else {
+        return ConnectionBadge {
+            label,
+            highlighted: false,
+            description: format!("{data_type}, used as is"),
+        };
+    };
+
+    let range = |data_type: DataType| match data_type.is_integer() {
+        true => format!("the range of {data_type}"),
+        false => "0..1".to_string(),
+    };
+    let conversion = match handling {
+        TypeHandling::Rescales(_) => format!(
+            "rescaling the values from {} to {}",
+            range(data_type),
+            range(target)
+        ),
+        _ => "keeping the values".to_string(),
+    };
+    let factor = target.size() as f32 / data_type.size() as f32;
+
+    ConnectionBadge {
+        label,
+        highlighted: true,
+        description: format!(
+            "Converted from {data_type} to {target}, {conversion}. \
+             Every chunk takes {factor}× the memory after the conversion"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rate_after_reset() {
+        let snapshot = |bytes| TransferSnapshot {
+            chunks: 1,
+            bytes,
+            data_type: None,
+        };
+
+        assert_eq!(
+            rate(snapshot(1000), snapshot(3000), Duration::from_secs(2)),
+            1000.0
+        );
+        assert_eq!(
+            rate(snapshot(3000), snapshot(10), Duration::from_secs(1)),
+            0.0
+        );
+        assert_eq!(rate(snapshot(0), snapshot(10), Duration::ZERO), 0.0);
+    }
+
+    #[test]
+    fn badge_of_conversion() {
+        let as_is = badge(DataType::F32, TypeHandling::PromotesToF32);
+        assert_eq!(as_is.label, "F32");
+        assert!(!as_is.highlighted);
+
+        let promoted = badge(DataType::U16, TypeHandling::Rescales(DataType::F32));
+        assert_eq!(promoted.label, "U16");
+        assert!(promoted.highlighted);
+        assert!(promoted.description.contains("to 0..1"));
+        assert!(promoted.description.contains("2× the memory"));
+
+        let reduced = badge(DataType::F32, TypeHandling::Rescales(DataType::U8));
+        assert!(reduced.description.contains("0.25× the memory"));
+    }
+}
@@ -0,0 +1,198 @@
+use std::ops::Range;
+
+use egui::{pos2, vec2, DragValue, Rect, Sense, Stroke};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    pipeline::range::{self, Extent},
+    units::NumberFormat,
+};
+
+/// Height of the timeline bar.
+const BAR_HEIGHT: f32 = 16.0;
+
+/// Slim timeline under the views, selecting the part of the pullback the
+/// views, outputs and previews work on, see [range]. Persisted with the
+/// session.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RangeSelector {
+    /// Selected A scans, [None] for the whole pullback.
+    selection: Option<Range<usize>>,
+    /// Whether the selection is entered in B scans, if a segmentation is
+    /// available.
+    b_scans: bool,
+}
+
+impl RangeSelector {
+    pub const STORAGE_KEY: &'static str = "range_selector";
+
+    /// Makes the selection current, see [range::publish].
+    pub fn publish(&self) {
+        range::publish(self.selection.clone());
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let extent = range::extent();
+        let count = extent.a_scan_count;
+
+        // The pullback got shorter
+        if count > 0 {
+            self.selection = range::clamp(self.selection.take(), count);
+        }
+
+        ui.horizontal(|ui| {
+            let mut enabled = self.selection.is_some();
+            if ui
+                .add_enabled(count > 0, egui::Checkbox::new(&mut enabled, "Range"))
+                .on_hover_text("Work on a part of the pullback only")
+                .on_disabled_hover_text("Open an M scan view to select a range")
+                .changed()
+            {
+                self.selection = enabled.then_some(0..count);
+            }
+
+            let has_b_scans = extent.b_scan_count() > 0;
+            let b_scans = self.b_scans && has_b_scans;
+
+            ui.add_enabled_ui(has_b_scans, |ui| {
+                ui.selectable_value(&mut self.b_scans, false, "A Scans");
+                ui.selectable_value(&mut self.b_scans, true, "B Scans")
+                    .on_disabled_hover_text("Needs a B scan segmentation");
+            });
+
+            if let (Some(selection), true) = (&mut self.selection, count > 0) {
+                let format = NumberFormat::current();
+                match b_scans {
+                    true => {
+                        let b_scan_count = extent.b_scan_count();
+                        let mut selected = range::b_scans_of(selection, &extent.b_scans);
+                        if !selected.is_empty() && range_ui(ui, &mut selected, b_scan_count) {
+                            *selection = range::a_scans_of(&selected, &extent.b_scans);
+                        }
+                        ui.label(format!("{} B scans", format.count(selected.len())));
+                    }
+                    false => {
+                        range_ui(ui, selection, count);
+                        ui.label(format!("{} A scans", format.count(selection.len())));
+                    }
+                }
+            }
+
+            if count > 0 {
+                self.bar_ui(ui, &extent, b_scans);
+            }
+        });
+
+        self.publish();
+    }
+
+    /// The timeline with B scan ticks. Dragging moves the nearest end of the
+    /// selection, or starts a new one. In B scans, the ends snap to B scans.
+    fn bar_ui(&mut self, ui: &mut egui::Ui, extent: &Extent, b_scans: bool) {
+        let count = extent.a_scan_count;
+        let (rect, response) = ui.allocate_exact_size(
+            vec2(ui.available_width(), BAR_HEIGHT),
+            Sense::click_and_drag(),
+        );
+
+        let x = |a_scan: usize| rect.left() + a_scan as f32 / count as f32 * rect.width();
+        let a_scan_at = |x: f32| {
+            let a_scan = ((x - rect.left()) / rect.width() * count as f32).round();
+            let a_scan = a_scan.clamp(0.0, count as f32) as usize;
+            match b_scans {
+                true => snap(a_scan, &extent.b_scans),
+                false => a_scan,
+            }
+        };
+
+        // Which end is dragged, true for the end
+        let dragged_id = response.id.with("dragged_end");
+        if response.drag_started() {
+            if let Some(pos) = response.interact_pointer_pos() {
+                let a_scan = a_scan_at(pos.x);
+                let is_end = match &self.selection {
+                    Some(selection) => {
+                        a_scan.abs_diff(selection.end) < a_scan.abs_diff(selection.start)
+                    }
+                    None => {
+                        self.selection = Some(a_scan..(a_scan + 1).min(count));
+                        true
+                    }
+                };
+                ui.data_mut(|d| d.insert_temp(dragged_id, is_end));
+            }
+        }
+
+        if response.dragged() {
+            let is_end = ui.data(|d| d.get_temp::<bool>(dragged_id));
+            if let (Some(pos), Some(is_end), Some(selection)) =
+                (response.interact_pointer_pos(), is_end, &mut self.selection)
+            {
+                let a_scan = a_scan_at(pos.x);
+                match is_end {
+                    true if a_scan > selection.start => selection.end = a_scan,
+                    false if a_scan < selection.end => selection.start = a_scan,
+                    _ => {}
+                }
+            }
+        }
+
+        let response = response.on_hover_cursor(egui::CursorIcon::ResizeHorizontal);
+
+        let visuals = ui.visuals();
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+
+        let tick = Stroke::new(1.0, visuals.weak_text_color());
+        for &b_scan in &extent.b_scans {
+            let x = x(b_scan);
+            painter.line_segment([pos2(x, rect.center().y), pos2(x, rect.bottom())], tick);
+        }
+
+        if let Some(selection) = &self.selection {
+            painter.rect_filled(
+                Rect::from_x_y_ranges(x(selection.start)..=x(selection.end), rect.y_range()),
+                2.0,
+                visuals.selection.bg_fill.gamma_multiply(0.6),
+            );
+
+            let handle = Stroke::new(3.0, visuals.selection.stroke.color);
+            for a_scan in [selection.start, selection.end] {
+                let x = x(a_scan);
+                painter.line_segment([pos2(x, rect.top()), pos2(x, rect.bottom())], handle);
+            }
+        }
+
+        if response.double_clicked() {
+            self.selection = None;
+        }
+        response.on_hover_text("Drag to select, double click to select everything");
+    }
+}
+
+/// Edits the non-empty `range` of `0..count` with its first and last
+/// element. Returns whether it changed.
+fn range_ui(ui: &mut egui::Ui, range: &mut Range<usize>, count: usize) -> bool {
+    let mut first = range.start;
+    let mut last = range.end - 1;
+
+    let mut changed = ui.add(DragValue::new(&mut first).range(0..=last)).changed();
+    ui.label("to");
+    changed |= ui
+        .add(DragValue::new(&mut last).range(first..=count - 1))
+        .changed();
+
+    if changed {
+        *range = first..last + 1;
+    }
+    changed
+}
+
+/// The B scan boundary closest to `a_scan`.
+fn snap(a_scan: usize, b_scans: &[usize]) -> usize {
+    b_scans
+        .iter()
+        .copied()
+        .min_by_key(|b_scan| b_scan.abs_diff(a_scan))
+        .unwrap_or(a_scan)
+}
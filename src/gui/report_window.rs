@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+
+use egui::{Grid, ProgressBar};
+
+use crate::{
+    gui::widgets::{PathInput, PathInputAction},
+    pipeline::{
+        report::{ReportGeneration, ReportOptions, ReportState},
+        Pipeline, PipelineExecutor,
+    },
+};
+
+/// Window to write a report of the pipeline and its results, see
+/// [crate::pipeline::report]. Closing the window cancels the generation.
+pub struct ReportWindow {
+    options: ReportOptions,
+    generation: Option<ReportGeneration>,
+}
+
+impl ReportWindow {
+    pub fn new() -> Self {
+        Self {
+            options: ReportOptions {
+                path: PathBuf::new(),
+                html: false,
+                compute_missing: false,
+            },
+            generation: None,
+        }
+    }
+
+    /// Returns false, when the window got closed.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        pipeline: &Pipeline,
+        executor: &PipelineExecutor,
+    ) -> bool {
+        let mut open = true;
+
+        egui::Window::new("Generate Report")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let is_running = self
+                    .generation
+                    .as_ref()
+                    .is_some_and(|generation| !generation.is_finished());
+
+                ui.add_enabled_ui(!is_running, |ui| self.options_ui(ui));
+
+                ui.horizontal(|ui| {
+                    let can_start = !is_running && !self.options.path.as_os_str().is_empty();
+                    if ui
+                        .add_enabled(can_start, egui::Button::new("Generate"))
+                        .clicked()
+                    {
+                        self.generation = Some(ReportGeneration::start(
+                            pipeline,
+                            executor,
+                            self.options.clone(),
+                        ));
+                    }
+
+                    if ui
+                        .add_enabled(is_running, egui::Button::new("Cancel"))
+                        .clicked()
+                    {
+                        self.generation = None;
+                    }
+                });
+
+                if let Some(generation) = &self.generation {
+                    ui.separator();
+                    state_ui(ui, generation.state());
+                }
+            });
+
+        open
+    }
+
+    fn options_ui(&mut self, ui: &mut egui::Ui) {
+        Grid::new("report_options").num_columns(2).show(ui, |ui| {
+            ui.label("File:");
+            ui.add(PathInput::new(&mut self.options.path).action(PathInputAction::SaveFile))
+                .on_hover_text("The Markdown file to write");
+            ui.end_row();
+
+            ui.label("HTML:");
+            ui.checkbox(&mut self.options.html, "Also write an HTML file");
+            ui.end_row();
+
+            ui.label("Missing Results:");
+            ui.checkbox(&mut self.options.compute_missing, "Compute")
+                .on_hover_text(
+                    "Process nodes, that did not run yet. Otherwise, their results are reported \
+                     as not computed",
+                );
+            ui.end_row();
+        });
+    }
+}
+
+fn state_ui(ui: &mut egui::Ui, state: ReportState) {
+    match state {
+        ReportState::Running { progress, step } => {
+            ui.add(ProgressBar::new(progress).rounding(3.0).text(step));
+            ui.ctx().request_repaint();
+        }
+        ReportState::Done(files) => {
+            ui.label("Report written to:");
+            for file in files {
+                ui.label(file.display().to_string());
+            }
+        }
+        ReportState::Failed(e) => {
+            ui.colored_label(ui.visuals().error_fg_color, format!("Failed: {e}"));
+        }
+    }
+}
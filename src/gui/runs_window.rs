@@ -0,0 +1,364 @@
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use egui::{Grid, ProgressBar};
+use futures::FutureExt;
+use tokio::{sync::oneshot, task::JoinHandle};
+
+use crate::{
+    pipeline::{
+        sessions::{self, Session, SessionRun, SettingChange},
+        Pipeline, PipelineExecutor,
+    },
+    units::NumberFormat,
+};
+
+/// How often the outputs of the active session are checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Window listing the run sessions in a `runs.json`, see
+/// [crate::pipeline::sessions]. It records the active session, when all
+/// outputs saved. Closing the window stops recording it.
+pub struct RunsWindow {
+    runs_file: PathBuf,
+    sessions: Vec<Session>,
+    loading: Option<oneshot::Receiver<anyhow::Result<Vec<Session>>>>,
+    active: Option<SessionRun>,
+    finishing: Option<JoinHandle<anyhow::Result<Session>>>,
+    error: Option<String>,
+    /// Sessions to compare, by index.
+    compare: [Option<usize>; 2],
+    /// Changes between the sessions in [Self::compare].
+    changes: Option<([usize; 2], Changes)>,
+}
+
+type Changes = Result<Vec<SettingChange>, String>;
+
+impl RunsWindow {
+    pub fn new(runs_file: PathBuf, ctx: &egui::Context) -> Self {
+        let mut window = Self {
+            runs_file,
+            sessions: Vec::new(),
+            loading: None,
+            active: None,
+            finishing: None,
+            error: None,
+            compare: [None; 2],
+            changes: None,
+        };
+        window.reload(ctx);
+        window
+    }
+
+    /// Switches to another `runs.json`, for example after saving the pipeline
+    /// to another directory.
+    pub fn set_runs_file(&mut self, runs_file: PathBuf, ctx: &egui::Context) {
+        if runs_file != self.runs_file {
+            self.runs_file = runs_file;
+            self.reload(ctx);
+        }
+    }
+
+    /// Saves all outputs as a new session. A session, that is still saving, is
+    /// recorded as it is.
+    pub fn start(
+        &mut self,
+        pipeline: &mut Pipeline,
+        executor: &PipelineExecutor,
+        snapshot: serde_json::Value,
+    ) {
+        self.finish(pipeline, executor);
+        self.active = Some(SessionRun::start(pipeline, snapshot));
+    }
+
+    /// Returns false, when the window got closed. Re-running a session loads
+    /// its pipeline into `load_pipeline`.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        pipeline: &Pipeline,
+        executor: &PipelineExecutor,
+        load_pipeline: &mut Option<Cow<'static, str>>,
+    ) -> bool {
+        let mut open = true;
+
+        self.poll(ctx, pipeline, executor);
+
+        egui::Window::new("Runs")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Sessions of:");
+                    ui.monospace(self.runs_file.display().to_string());
+                });
+
+                if let Some(error) = &self.error {
+                    ui.colored_label(ui.visuals().error_fg_color, format!("Failed: {error}"));
+                }
+
+                if let Some(active) = &self.active {
+                    let (finished, total) = active.progress();
+                    let name = active.name().to_string();
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            ProgressBar::new(finished as f32 / total.max(1) as f32)
+                                .rounding(3.0)
+                                .desired_width(200.0)
+                                .text(format!("{name}: {finished} of {total} outputs saved")),
+                        );
+                        if ui
+                            .button("Stop Waiting")
+                            .on_hover_text("Record the session with the outputs saved so far")
+                            .clicked()
+                        {
+                            self.finish(pipeline, executor);
+                        }
+                    });
+                } else if self.finishing.is_some() || self.loading.is_some() {
+                    ui.spinner();
+                }
+
+                ui.separator();
+
+                if self.sessions.is_empty() {
+                    ui.weak("No runs yet. Use File → Save All Outputs to start one.");
+                    return;
+                }
+
+                if let Some(rerun) = self.sessions_ui(ui) {
+                    match serde_json::to_string(&self.sessions[rerun].snapshot) {
+                        Ok(json) => *load_pipeline = Some(json.into()),
+                        Err(e) => self.error = Some(e.to_string()),
+                    }
+                }
+
+                self.changes_ui(ui);
+            });
+
+        open
+    }
+
+    /// Returns the session to re-run, if requested.
+    fn sessions_ui(&mut self, ui: &mut egui::Ui) -> Option<usize> {
+        let mut rerun = None;
+
+        egui::ScrollArea::vertical()
+            .max_height(400.0)
+            .show(ui, |ui| {
+                // Newest first
+                for (i, session) in self.sessions.iter().enumerate().rev() {
+                    ui.horizontal(|ui| {
+                        for (slot, label) in ["A", "B"].into_iter().enumerate() {
+                            let selected = self.compare[slot] == Some(i);
+                            if ui
+                                .selectable_label(selected, label)
+                                .on_hover_text(format!("Compare as {label}"))
+                                .clicked()
+                            {
+                                self.compare[slot] = (!selected).then_some(i);
+                            }
+                        }
+
+                        egui::CollapsingHeader::new(&session.name)
+                            .id_source(("run", i))
+                            .show(ui, |ui| {
+                                session_ui(ui, session);
+
+                                ui.horizontal(|ui| {
+                                    if ui
+                                        .add_enabled(
+                                            session.folder().is_some(),
+                                            egui::Button::new("Open Folder"),
+                                        )
+                                        .clicked()
+                                    {
+                                        if let Some(folder) = session.folder() {
+                                            if let Err(e) = open_folder(folder) {
+                                                eprintln!("Error opening folder: {}", e);
+                                            }
+                                        }
+                                    }
+
+                                    if ui
+                                        .button("Re-run")
+                                        .on_hover_text(
+                                            "Load the pipeline with the settings of this run. \
+                                             It replaces the current pipeline",
+                                        )
+                                        .clicked()
+                                    {
+                                        rerun = Some(i);
+                                    }
+                                });
+                            });
+                    });
+                }
+            });
+
+        rerun
+    }
+
+    fn changes_ui(&mut self, ui: &mut egui::Ui) {
+        let [Some(a), Some(b)] = self.compare else {
+            ui.weak("Select A and B to compare two runs.");
+            return;
+        };
+
+        if self.changes.as_ref().map(|(pair, _)| *pair) != Some([a, b]) {
+            let changes = self.sessions[a]
+                .pipeline()
+                .and_then(|pa| Ok(sessions::diff(&pa, &self.sessions[b].pipeline()?)))
+                .map_err(|e| e.to_string());
+            self.changes = Some(([a, b], changes));
+        }
+        let Some((_, changes)) = &self.changes else {
+            return;
+        };
+
+        ui.separator();
+        ui.strong(format!(
+            "From {} to {}",
+            self.sessions[a].name, self.sessions[b].name
+        ));
+
+        match changes {
+            Err(e) => {
+                ui.colored_label(ui.visuals().error_fg_color, format!("Failed: {e}"));
+            }
+            Ok(changes) if changes.is_empty() => {
+                ui.label("Same settings");
+            }
+            Ok(changes) => {
+                for change in changes {
+                    ui.label(change.to_string());
+                }
+            }
+        }
+    }
+
+    /// Records the active session, once all outputs saved, and picks up
+    /// finished background work.
+    fn poll(&mut self, ctx: &egui::Context, pipeline: &Pipeline, executor: &PipelineExecutor) {
+        if let Some(active) = &mut self.active {
+            active.update(pipeline);
+            if active.is_done() {
+                self.finish(pipeline, executor);
+            } else {
+                ctx.request_repaint_after(POLL_INTERVAL);
+            }
+        }
+
+        if let Some(result) = self.loading.as_mut().and_then(|rx| rx.try_recv().ok()) {
+            self.loading = None;
+            match result {
+                Ok(sessions) => self.sessions = sessions,
+                Err(e) => self.error = Some(e.to_string()),
+            }
+        }
+
+        if self.finishing.as_ref().is_some_and(|f| f.is_finished()) {
+            match self.finishing.take().and_then(|f| f.now_or_never()) {
+                Some(Ok(Ok(session))) => self.sessions.push(session),
+                Some(Ok(Err(e))) => self.error = Some(e.to_string()),
+                Some(Err(e)) => self.error = Some(e.to_string()),
+                None => {}
+            }
+        } else if self.finishing.is_some() {
+            ctx.request_repaint_after(POLL_INTERVAL);
+        }
+    }
+
+    fn finish(&mut self, pipeline: &Pipeline, executor: &PipelineExecutor) {
+        if let Some(active) = self.active.take() {
+            self.error = None;
+            self.finishing = Some(active.finish(pipeline, executor, self.runs_file.clone()));
+        }
+    }
+
+    fn reload(&mut self, ctx: &egui::Context) {
+        let (tx, rx) = oneshot::channel();
+
+        let path = self.runs_file.clone();
+        let ctx = ctx.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let _ = tx.send(sessions::load(&path));
+            ctx.request_repaint();
+        });
+
+        self.sessions.clear();
+        self.compare = [None; 2];
+        self.changes = None;
+        self.loading = Some(rx);
+    }
+}
+
+fn session_ui(ui: &mut egui::Ui, session: &Session) {
+    let format = NumberFormat::current();
+
+    Grid::new(("session", &session.name))
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("Settings:");
+            ui.monospace(format!("{:016x}", session.fingerprint))
+                .on_hover_text("Hash of the settings of all nodes");
+            ui.end_row();
+
+            ui.label("Duration:");
+            ui.label(format!("{} s", format.number(session.seconds, 1..=1)));
+            ui.end_row();
+        });
+
+    if !session.files.is_empty() {
+        ui.strong("Files");
+        Grid::new(("session_files", &session.name))
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                for file in &session.files {
+                    ui.label(&file.node);
+                    ui.monospace(file.path.display().to_string());
+                    ui.label(format.bytes(file.size as f64));
+                    ui.monospace(&file.checksum)
+                        .on_hover_text("FNV-1a checksum");
+                    ui.end_row();
+                }
+            });
+    }
+
+    for node in &session.missing {
+        ui.colored_label(ui.visuals().warn_fg_color, format!("⚠ {node} did not save"));
+    }
+
+    if !session.durations.is_empty() {
+        ui.strong("Durations");
+        Grid::new(("session_durations", &session.name))
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                for duration in &session.durations {
+                    ui.label(&duration.node);
+                    ui.label(format!("{} s", format.number(duration.seconds, 2..=2)));
+                    ui.end_row();
+                }
+            });
+    }
+}
+
+/// Opens the file manager of the platform.
+fn open_folder(folder: &Path) -> std::io::Result<()> {
+    let program = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+
+    std::process::Command::new(program).arg(folder).spawn()?;
+    Ok(())
+}
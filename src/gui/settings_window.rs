@@ -0,0 +1,437 @@
+use egui::{ComboBox, DragValue, Grid, Key, KeyboardShortcut};
+
+use crate::{
+    gui::{color_maps, widgets::DragValueExt},
+    settings::{MScanPooling, PowerPreference, Settings},
+    shortcuts::{self, Action, Shortcuts, ACTIONS},
+    units::{DecimalSeparator, LengthUnit},
+};
+
+/// Window to edit the application wide [Settings]. Every field can be reverted
+/// to its default value individually.
+pub struct SettingsWindow<'a> {
+    settings: &'a mut Settings,
+    open: &'a mut bool,
+}
+
+impl<'a> SettingsWindow<'a> {
+    pub fn new(settings: &'a mut Settings, open: &'a mut bool) -> Self {
+        Self { settings, open }
+    }
+
+    pub fn show(self, ctx: &egui::Context) {
+        let settings = self.settings;
+        let default = Settings::DEFAULT;
+
+        egui::Window::new("Settings")
+            .open(self.open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.heading("General");
+                Grid::new("general_settings").num_columns(3).show(ui, |ui| {
+                    let general = &mut settings.general;
+
+                    ui.label("Autosave Interval:");
+                    ui.add(
+                        DragValue::new(&mut general.autosave_interval)
+                            .range(5..=3600)
+                            .suffix(" s"),
+                    );
+                    reset_button(
+                        ui,
+                        &mut general.autosave_interval,
+                        default.general.autosave_interval,
+                    );
+                    ui.end_row();
+                });
+
+                ui.separator();
+                ui.heading("Performance");
+                Grid::new("performance_settings")
+                    .num_columns(3)
+                    .show(ui, |ui| {
+                        let performance = &mut settings.performance;
+                        let limits = &mut performance.chunk_limits;
+
+                        ui.label("Max Chunk Width:");
+                        ui.add(
+                            DragValue::new(&mut limits.max_width)
+                                .range(limits.min_width.max(1)..=1 << 20),
+                        )
+                        .on_hover_text("Wider chunks are split into multiple chunks");
+                        reset_button(
+                            ui,
+                            &mut limits.max_width,
+                            default.performance.chunk_limits.max_width,
+                        );
+                        ui.end_row();
+
+                        ui.label("Min Chunk Width:");
+                        ui.add(DragValue::new(&mut limits.min_width).range(0..=4096))
+                            .on_hover_text("Narrower chunks are merged with the following ones");
+                        reset_button(
+                            ui,
+                            &mut limits.min_width,
+                            default.performance.chunk_limits.min_width,
+                        );
+                        ui.end_row();
+
+                        ui.label("Stream Capacity:");
+                        ui.add(DragValue::new(&mut performance.stream_capacity).range(2..=10000))
+                            .on_hover_text(
+                                "How many chunks a streamed response can buffer, before slow \
+                                 receivers start lagging",
+                            );
+                        reset_button(
+                            ui,
+                            &mut performance.stream_capacity,
+                            default.performance.stream_capacity,
+                        );
+                        ui.end_row();
+
+                        ui.label("Connection Previews:");
+                        ui.checkbox(&mut performance.connection_previews, "")
+                            .on_hover_text(
+                                "Keep the last data sent through every connection, to show it \
+                                 when hovering the connection",
+                            );
+                        reset_button(
+                            ui,
+                            &mut performance.connection_previews,
+                            default.performance.connection_previews,
+                        );
+                        ui.end_row();
+
+                        ui.label("M Scan Previews:");
+                        ui.checkbox(&mut performance.m_scan_previews, "")
+                            .on_hover_text(
+                                "Also keep a small image of the last chunk of M scans. Takes \
+                                 time on every chunk",
+                            );
+                        reset_button(
+                            ui,
+                            &mut performance.m_scan_previews,
+                            default.performance.m_scan_previews,
+                        );
+                        ui.end_row();
+                    });
+
+                ui.separator();
+                ui.heading("Display");
+                Grid::new("display_settings").num_columns(3).show(ui, |ui| {
+                    let display = &mut settings.display;
+
+                    ui.label("Dark Mode:");
+                    ui.checkbox(&mut display.dark_mode, "");
+                    reset_button(ui, &mut display.dark_mode, default.display.dark_mode);
+                    ui.end_row();
+
+                    let names = color_map_names();
+
+                    ui.label("Default Color Map:");
+                    let mut selected = color_maps::loaded_index(display.default_color_map) as usize;
+                    ComboBox::from_id_source("default_color_map").show_index(
+                        ui,
+                        &mut selected,
+                        names.len(),
+                        |i| names[i].as_str(),
+                    );
+                    display.default_color_map = color_maps::bundled_index(selected as u32);
+                    reset_button(
+                        ui,
+                        &mut display.default_color_map,
+                        default.display.default_color_map,
+                    );
+                    ui.end_row();
+
+                    ui.label("Power Preference:");
+                    let mut selected = PowerPreference::VALUES
+                        .iter()
+                        .position(|p| *p == display.power_preference)
+                        .unwrap();
+                    ComboBox::from_id_source("power_preference")
+                        .show_index(ui, &mut selected, PowerPreference::VALUES.len(), |i| {
+                            match PowerPreference::VALUES[i] {
+                                PowerPreference::HighPerformance => "High Performance",
+                                PowerPreference::LowPower => "Low Power",
+                            }
+                        })
+                        .on_hover_text("Takes effect after restarting the app");
+                    display.power_preference = PowerPreference::VALUES[selected];
+                    reset_button(
+                        ui,
+                        &mut display.power_preference,
+                        default.display.power_preference,
+                    );
+                    ui.end_row();
+
+                    ui.label("Graph Scale:");
+                    ui.add(
+                        DragValue::new(&mut display.graph_scale)
+                            .range(0.5..=2.0)
+                            .speed(0.01)
+                            .localized(),
+                    )
+                    .on_hover_text("Size of pins, connections and node headers in the pipeline");
+                    reset_button(ui, &mut display.graph_scale, default.display.graph_scale);
+                    ui.end_row();
+
+                    ui.label("High Contrast Pins:");
+                    ui.checkbox(&mut display.high_contrast_pins, "")
+                        .on_hover_text(
+                            "Colorblind safe pin colors with a letter for the type of the data",
+                        );
+                    reset_button(
+                        ui,
+                        &mut display.high_contrast_pins,
+                        default.display.high_contrast_pins,
+                    );
+                    ui.end_row();
+
+                    ui.label("Stall Threshold:");
+                    ui.add(
+                        DragValue::new(&mut display.stall_threshold)
+                            .range(1..=3600)
+                            .suffix(" s"),
+                    )
+                    .on_hover_text(
+                        "Working nodes without progress for this long are outlined in amber",
+                    );
+                    reset_button(
+                        ui,
+                        &mut display.stall_threshold,
+                        default.display.stall_threshold,
+                    );
+                    ui.end_row();
+
+                    ui.label("Zoomed Out Pooling:");
+                    let mut selected = MScanPooling::VALUES
+                        .iter()
+                        .position(|p| *p == display.m_scan_pooling)
+                        .unwrap();
+                    ComboBox::from_id_source("m_scan_pooling")
+                        .show_index(ui, &mut selected, MScanPooling::VALUES.len(), |i| {
+                            match MScanPooling::VALUES[i] {
+                                MScanPooling::Maximum => "Maximum",
+                                MScanPooling::Mean => "Mean",
+                            }
+                        })
+                        .on_hover_text(
+                            "How A scans are combined when zooming out of a polar view. \
+                             Maximum keeps bright features visible. Applies to M scans \
+                             uploaded afterwards.",
+                        );
+                    display.m_scan_pooling = MScanPooling::VALUES[selected];
+                    reset_button(
+                        ui,
+                        &mut display.m_scan_pooling,
+                        default.display.m_scan_pooling,
+                    );
+                    ui.end_row();
+
+                    let format = &mut display.number_format;
+
+                    ui.label("Decimal Separator:");
+                    ComboBox::from_id_source("decimal_separator")
+                        .selected_text(decimal_separator_name(format.decimal_separator))
+                        .show_ui(ui, |ui| {
+                            for separator in DecimalSeparator::VALUES {
+                                ui.selectable_value(
+                                    &mut format.decimal_separator,
+                                    separator,
+                                    decimal_separator_name(separator),
+                                );
+                            }
+                        })
+                        .response
+                        .on_hover_text("Typed in numbers may use either separator");
+                    reset_button(
+                        ui,
+                        &mut format.decimal_separator,
+                        default.display.number_format.decimal_separator,
+                    );
+                    ui.end_row();
+
+                    ui.label("Length Unit:");
+                    ComboBox::from_id_source("length_unit")
+                        .selected_text(format.length_unit.symbol())
+                        .show_ui(ui, |ui| {
+                            for unit in LengthUnit::VALUES {
+                                ui.selectable_value(&mut format.length_unit, unit, unit.symbol());
+                            }
+                        })
+                        .response
+                        .on_hover_text("Pipelines always store lengths in millimetres");
+                    reset_button(
+                        ui,
+                        &mut format.length_unit,
+                        default.display.number_format.length_unit,
+                    );
+                    ui.end_row();
+                });
+
+                ui.separator();
+                ui.heading("Shortcuts");
+                shortcuts_ui(ui, &mut settings.shortcuts);
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Import").clicked() {
+                        import_settings(settings);
+                    }
+                    if ui.button("Export").clicked() {
+                        export_settings(settings);
+                    }
+                    if ui.button("Reset All").clicked() {
+                        *settings = Settings::DEFAULT;
+                    }
+                });
+            });
+
+        settings.validate();
+    }
+}
+
+fn reset_button<T: PartialEq>(ui: &mut egui::Ui, value: &mut T, default: T) {
+    if ui
+        .add_enabled(*value != default, egui::Button::new("⟲"))
+        .on_hover_text("Reset to default")
+        .clicked()
+    {
+        *value = default;
+    }
+}
+
+/// Action waiting for its new binding, and a pressed binding, that is already
+/// used by another action.
+#[derive(Clone, Copy)]
+struct Capture {
+    action: Action,
+    conflict: Option<(KeyboardShortcut, Action)>,
+}
+
+/// Lists all actions with their bindings. Clicking a binding waits for the
+/// new combination to be pressed, escape cancels.
+fn shortcuts_ui(ui: &mut egui::Ui, shortcuts: &mut Shortcuts) {
+    let capture_id = ui.id().with("shortcut_capture");
+    let mut capture: Option<Capture> = ui.data(|d| d.get_temp(capture_id)).flatten();
+
+    if let Some(current) = &mut capture {
+        let pressed = ui.input(|i| i.events.iter().find_map(shortcuts::pressed_shortcut));
+
+        match pressed {
+            Some(shortcut)
+                if shortcut == KeyboardShortcut::new(Default::default(), Key::Escape) =>
+            {
+                capture = None
+            }
+            Some(shortcut) => match shortcuts.conflict(current.action, shortcut) {
+                Some(other) => current.conflict = Some((shortcut, other)),
+                None => {
+                    shortcuts.set(current.action, Some(shortcut));
+                    capture = None;
+                }
+            },
+            None => {}
+        }
+    }
+
+    Grid::new("shortcut_settings")
+        .num_columns(3)
+        .show(ui, |ui| {
+            for info in &ACTIONS {
+                ui.label(info.description);
+
+                let capturing = capture.is_some_and(|c| c.action == info.action);
+                let text = match capturing {
+                    true => "Press keys…".to_string(),
+                    false => shortcuts
+                        .bindings(info.action)
+                        .iter()
+                        .map(|shortcut| ui.ctx().format_shortcut(shortcut))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                };
+                if ui
+                    .selectable_label(capturing, text)
+                    .on_hover_text("Click and press the new combination, escape cancels")
+                    .clicked()
+                {
+                    capture = Some(Capture {
+                        action: info.action,
+                        conflict: None,
+                    });
+                }
+
+                if ui
+                    .add_enabled(!shortcuts.is_default(info.action), egui::Button::new("⟲"))
+                    .on_hover_text("Reset to default")
+                    .clicked()
+                {
+                    shortcuts.set(info.action, None);
+                }
+                ui.end_row();
+            }
+        });
+
+    if let Some((shortcut, other)) = capture.and_then(|c| c.conflict) {
+        ui.colored_label(
+            ui.visuals().warn_fg_color,
+            format!(
+                "⚠ {} is already used to: {}",
+                ui.ctx().format_shortcut(&shortcut),
+                other.info().description
+            ),
+        );
+    }
+
+    ui.data_mut(|d| d.insert_temp(capture_id, capture));
+}
+
+fn decimal_separator_name(separator: DecimalSeparator) -> &'static str {
+    match separator {
+        DecimalSeparator::Dot => "Dot (1.25)",
+        DecimalSeparator::Comma => "Comma (1,25)",
+    }
+}
+
+/// Names of all color maps in the form "category/name", in the order of their
+/// indices.
+fn color_map_names() -> Vec<String> {
+    color_maps::get_color_map_names()
+        .iter()
+        .flat_map(|(category, maps)| maps.iter().map(move |map| format!("{category}/{map}")))
+        .collect()
+}
+
+fn import_settings(settings: &mut Settings) {
+    let file = native_dialog::FileDialog::new()
+        .add_filter("JSON", &["json"])
+        .set_title("Import Settings")
+        .show_open_single_file();
+
+    if let Ok(Some(file)) = file {
+        match std::fs::read_to_string(file) {
+            Ok(json) => match Settings::from_json(&json) {
+                Ok(imported) => *settings = imported,
+                Err(e) => eprintln!("Error importing settings: {}", e),
+            },
+            Err(e) => eprintln!("Error importing settings: {}", e),
+        }
+    }
+}
+
+fn export_settings(settings: &Settings) {
+    let file = native_dialog::FileDialog::new()
+        .add_filter("JSON", &["json"])
+        .set_title("Export Settings")
+        .show_save_single_file();
+
+    if let Ok(Some(file)) = file {
+        if let Err(e) = std::fs::write(file, settings.to_json()) {
+            eprintln!("Error exporting settings: {}", e);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, HashMap};
+
+use egui::{Grid, ScrollArea};
+
+use crate::{
+    gui::node_graph::NodeWarning,
+    node_graph::NodeId,
+    pipeline::{validation::ValidationIssue, Pipeline},
+};
+
+struct NodeIssues {
+    name: String,
+    issues: Vec<ValidationIssue>,
+}
+
+/// Issues found by [Pipeline::validate], kept until the user acknowledges
+/// them. Nodes with issues are marked in the editor, the window lists them.
+pub struct ValidationWindow {
+    nodes: BTreeMap<NodeId, NodeIssues>,
+    open: bool,
+}
+
+impl ValidationWindow {
+    pub fn new() -> Self {
+        Self {
+            nodes: BTreeMap::new(),
+            open: false,
+        }
+    }
+
+    /// Validates a pipeline, that was just loaded. Replaces the previous
+    /// issues and opens the window, if there are any.
+    pub fn loaded(&mut self, pipeline: &mut Pipeline) {
+        self.nodes.clear();
+        self.validate(pipeline);
+        self.open = !self.nodes.is_empty();
+    }
+
+    /// Validates the pipeline after edits. New issues are added to the ones
+    /// not acknowledged yet.
+    pub fn validate(&mut self, pipeline: &mut Pipeline) {
+        self.nodes
+            .retain(|node_id, _| pipeline.nodes.contains_key(node_id));
+
+        for (node_id, issues) in pipeline.validate() {
+            let node = self.nodes.entry(node_id).or_insert_with(|| NodeIssues {
+                name: pipeline[node_id].name().to_string(),
+                issues: Vec::new(),
+            });
+            for issue in issues {
+                if !node.issues.contains(&issue) {
+                    node.issues.push(issue);
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    /// Adds a warning to every node with issues.
+    pub fn warnings(&self, warnings: &mut HashMap<NodeId, Vec<NodeWarning>>) {
+        for (node_id, node) in &self.nodes {
+            let mut description = node
+                .issues
+                .iter()
+                .map(ValidationIssue::to_string)
+                .collect::<Vec<_>>()
+                .join("\n");
+            description.push_str("\n\nAcknowledge in Pipeline → Validation Report");
+
+            warnings.entry(*node_id).or_default().push(NodeWarning {
+                label: match node.issues.iter().all(|issue| issue.corrected) {
+                    true => "Corrected",
+                    false => "Invalid",
+                },
+                description,
+            });
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        if !self.open {
+            return;
+        }
+
+        let mut acknowledged = Vec::new();
+        let mut all = false;
+
+        egui::Window::new("Validation Report")
+            .open(&mut self.open)
+            .default_width(400.0)
+            .show(ctx, |ui| {
+                if self.nodes.is_empty() {
+                    ui.label("No issues");
+                    return;
+                }
+
+                ui.label(
+                    "Settings of these nodes were invalid. Corrected ones took the closest valid \
+                     value, the others need to be fixed.",
+                );
+                ui.separator();
+
+                ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                    Grid::new("validation_issues")
+                        .num_columns(2)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (node_id, node) in &self.nodes {
+                                ui.vertical(|ui| {
+                                    let number: usize = (*node_id).into();
+                                    ui.strong(format!("{} #{}", node.name, number));
+                                    for issue in &node.issues {
+                                        match issue.corrected {
+                                            true => ui.label(issue.to_string()),
+                                            false => ui.colored_label(
+                                                ui.visuals().warn_fg_color,
+                                                issue.to_string(),
+                                            ),
+                                        };
+                                    }
+                                });
+                                if ui.button("Acknowledge").clicked() {
+                                    acknowledged.push(*node_id);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                });
+
+                ui.separator();
+                all = ui.button("Acknowledge All").clicked();
+            });
+
+        for node_id in acknowledged {
+            self.nodes.remove(&node_id);
+        }
+        if all {
+            self.nodes.clear();
+            self.open = false;
+        }
+    }
+}
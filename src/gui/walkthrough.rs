@@ -0,0 +1,331 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use egui::{Align2, Color32, Id, LayerId, Order, ProgressBar, Rect, Stroke, Vec2};
+use tokio::sync::watch;
+
+use crate::{
+    gui::node_graph::NodeGraphEditState,
+    node_graph::NodeId,
+    pipeline::{presets, sample_data},
+    settings,
+};
+
+/// A scans of the sample pullback, 24 rotations of the catheter.
+const SAMPLE_A_SCANS: usize = 24 * sample_data::B_SCAN_PERIOD;
+
+/// Node of [presets::WALKTHROUGH], whose M scan is opened in a view.
+const VIEW_NODE: usize = 5;
+
+/// One step of the walkthrough, explaining a node of [presets::WALKTHROUGH].
+struct Step {
+    node: usize,
+    title: &'static str,
+    text: &'static str,
+}
+
+const STEPS: [Step; 5] = [
+    Step {
+        node: 1,
+        title: "Input",
+        text: "Binary Input nodes read files. This one reads the raw spectra of the sample \
+               pullback, the two below read the offset and the chirp of the spectrometer.",
+    },
+    Step {
+        node: 4,
+        title: "Process",
+        text: "Process Raw M Scan subtracts the offset, resamples every spectrum at the chirp \
+               and transforms it into an A scan. Double click a node to view its result.",
+    },
+    Step {
+        node: 5,
+        title: "Filter",
+        text: "A Gaussian filter reduces the speckle noise. Its result is shown in the view. \
+               Hover a connection to see the data flowing through it.",
+    },
+    Step {
+        node: 6,
+        title: "Segment",
+        text: "Segment B Scans finds where every rotation of the catheter starts. Follow \
+               Catheter and Follow Lumen then trace the catheter and the vessel wall.",
+    },
+    Step {
+        node: 9,
+        title: "Export",
+        text: "Output nodes write results to disk. Choose a file for the lumen and click \
+               Save, or save every output from the File menu.",
+    },
+];
+
+/// State of preparing the sample data in the background.
+#[derive(Debug, Clone)]
+enum Preparation {
+    Running(f32),
+    /// Contains the directory of the sample data.
+    Done(PathBuf),
+    Failed(String),
+}
+
+enum State {
+    Closed,
+    /// Offers to prepare the sample data and load the walkthrough pipeline.
+    Offer,
+    Preparing(watch::Receiver<Preparation>),
+    Failed(String),
+    /// Showing the step with this index.
+    Step(usize),
+}
+
+/// Guides new users through a pipeline processing a synthetic sample
+/// pullback, see [sample_data]. Offered on the first launch and from the Help
+/// menu, and can be skipped at any time.
+pub struct Walkthrough {
+    state: State,
+    /// Node, whose view to open, after the walkthrough pipeline got loaded.
+    view_request: Option<NodeId>,
+    /// Step, whose node was moved into view.
+    focused: Option<usize>,
+}
+
+impl Walkthrough {
+    /// Offers the walkthrough right away on the `first_run`.
+    pub fn new(first_run: bool) -> Self {
+        Self {
+            state: match first_run {
+                true => State::Offer,
+                false => State::Closed,
+            },
+            view_request: None,
+            focused: None,
+        }
+    }
+
+    /// Offers the walkthrough again.
+    pub fn restart(&mut self) {
+        self.state = State::Offer;
+        self.view_request = None;
+        self.focused = None;
+    }
+
+    /// The node, whose view to open, once the pipeline returned by
+    /// [Self::show] is loaded.
+    pub fn take_view_request(&mut self) -> Option<NodeId> {
+        self.view_request.take()
+    }
+
+    /// Shows the dialogs offering and preparing the sample data. Returns the
+    /// pipeline to load as JSON, once the data is ready.
+    pub fn show(&mut self, ctx: &egui::Context) -> Option<String> {
+        let mut next = None;
+
+        let dialog = |title| {
+            egui::Window::new(title)
+                .collapsible(false)
+                .resizable(false)
+                .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+        };
+
+        match &self.state {
+            State::Closed | State::Step(_) => return None,
+            State::Offer => {
+                dialog("Welcome").show(ctx, |ui| {
+                    ui.label(
+                        "Learn the pipeline on a small sample pullback: It is read, processed, \
+                         filtered, segmented and exported step by step.",
+                    );
+                    ui.weak(
+                        "The sample is synthetic and generated on this computer, so no \
+                         download is needed.",
+                    );
+                    ui.horizontal(|ui| {
+                        if ui.button("Start Walkthrough").clicked() {
+                            next = Some(State::Preparing(prepare()));
+                        }
+                        if ui.button("Skip").clicked() {
+                            next = Some(State::Closed);
+                        }
+                    });
+                });
+            }
+            State::Preparing(preparation) => {
+                let preparation = preparation.borrow().clone();
+                match preparation {
+                    Preparation::Running(progress) => {
+                        dialog("Preparing Sample Data").show(ctx, |ui| {
+                            ui.add(ProgressBar::new(progress).rounding(3.0).show_percentage());
+                            if ui.button("Cancel").clicked() {
+                                next = Some(State::Closed);
+                            }
+                        });
+                        ctx.request_repaint();
+                    }
+                    Preparation::Done(dir) => {
+                        self.state = State::Step(0);
+                        self.view_request = Some(VIEW_NODE.into());
+                        return Some(walkthrough_pipeline(&dir));
+                    }
+                    Preparation::Failed(e) => next = Some(State::Failed(e)),
+                }
+            }
+            State::Failed(e) => {
+                dialog("Walkthrough").show(ctx, |ui| {
+                    ui.colored_label(
+                        ui.visuals().error_fg_color,
+                        format!("The sample data could not be prepared: {e}"),
+                    );
+                    if ui.button("Close").clicked() {
+                        next = Some(State::Closed);
+                    }
+                });
+            }
+        }
+
+        if let Some(next) = next {
+            self.state = next;
+        }
+        None
+    }
+
+    /// Shows the current step next to its node and outlines the node. Nodes
+    /// are moved into view, when their step starts.
+    pub fn step_ui(
+        &mut self,
+        ctx: &egui::Context,
+        node_rects: &HashMap<NodeId, Rect>,
+        edit_state: &mut NodeGraphEditState,
+    ) {
+        let State::Step(index) = self.state else {
+            return;
+        };
+        let step = &STEPS[index];
+        let node_id = NodeId::from(step.node);
+
+        if self.focused != Some(index) {
+            self.focused = Some(index);
+            edit_state.focus(node_id);
+        }
+
+        let mut window =
+            egui::Window::new(format!("{} ({}/{})", step.title, index + 1, STEPS.len()))
+                .id(Id::new("walkthrough_step"))
+                .collapsible(false)
+                .resizable(false)
+                .max_width(260.0);
+
+        // Users may have removed the node in the meantime
+        window = match node_rects.get(&node_id) {
+            Some(rect) => {
+                ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("walkthrough")))
+                    .rect_stroke(rect.expand(4.0), 6.0, Stroke::new(2.0, Color32::GOLD));
+                window
+                    .pivot(Align2::LEFT_TOP)
+                    .fixed_pos(rect.right_top() + Vec2::new(12.0, 0.0))
+            }
+            None => window.anchor(Align2::CENTER_TOP, Vec2::new(0.0, 40.0)),
+        };
+
+        let mut next = Some(index);
+        window.show(ctx, |ui| {
+            ui.label(step.text);
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(index > 0, egui::Button::new("Back"))
+                    .clicked()
+                {
+                    next = Some(index - 1);
+                }
+                let last = index + 1 == STEPS.len();
+                if ui.button(if last { "Finish" } else { "Next" }).clicked() {
+                    next = (!last).then_some(index + 1);
+                }
+                if ui.button("Skip").clicked() {
+                    next = None;
+                }
+            });
+        });
+
+        self.state = match next {
+            Some(index) => State::Step(index),
+            None => State::Closed,
+        };
+    }
+}
+
+/// Writes the sample data in the background, unless it exists already.
+fn prepare() -> watch::Receiver<Preparation> {
+    let (tx, rx) = watch::channel(Preparation::Running(0.0));
+
+    tokio::task::spawn_blocking(move || {
+        let Some(dir) = eframe::storage_dir(settings::APP_NAME).map(|dir| dir.join("sample_data"))
+        else {
+            tx.send_replace(Preparation::Failed(
+                "There is no directory to store it in".to_string(),
+            ));
+            return;
+        };
+
+        let result = match sample_data::exists(&dir, SAMPLE_A_SCANS) {
+            true => Ok(()),
+            false => sample_data::write(&dir, SAMPLE_A_SCANS, |progress| {
+                tx.send_replace(Preparation::Running(progress));
+            }),
+        };
+
+        tx.send_replace(match result {
+            Ok(()) => Preparation::Done(dir),
+            Err(e) => Preparation::Failed(e.to_string()),
+        });
+    });
+
+    rx
+}
+
+/// [presets::WALKTHROUGH] reading the sample data in `dir`.
+fn walkthrough_pipeline(dir: &std::path::Path) -> String {
+    let mut pipeline: serde_json::Value = serde_json::from_str(presets::WALKTHROUGH).unwrap();
+
+    if let Some(nodes) = pipeline[0]["nodes"].as_object_mut() {
+        for node in nodes.values_mut() {
+            if node["type"] == "binary_input" {
+                let file = node["path"].as_str().unwrap_or_default().to_string();
+                node["path"] = dir.join(file).to_string_lossy().into();
+            }
+        }
+    }
+
+    pipeline.to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pipeline::Pipeline;
+
+    #[test]
+    fn pipeline_reads_sample_data() {
+        let dir = PathBuf::from("sample");
+        let (pipeline, _): (Pipeline, NodeGraphEditState) =
+            serde_json::from_str(&walkthrough_pipeline(&dir)).unwrap();
+
+        let mut paths = Vec::new();
+        for node in pipeline.nodes.values() {
+            node.visit_paths(&mut |_, path| paths.push(path.to_path_buf()));
+        }
+        for file in [
+            sample_data::RAW_FILE,
+            sample_data::OFFSET_FILE,
+            sample_data::CHIRP_FILE,
+        ] {
+            assert!(paths.contains(&dir.join(file)), "{} is not read", file);
+        }
+
+        // Every step explains a node of the pipeline
+        for step in &STEPS {
+            assert!(
+                pipeline.nodes.contains_key(&step.node.into()),
+                "{}",
+                step.title
+            );
+        }
+        assert!(pipeline.nodes.contains_key(&VIEW_NODE.into()));
+    }
+}
@@ -0,0 +1,33 @@
+use egui::DragValue;
+
+use crate::units::{self, NumberFormat};
+
+/// Display [DragValue]s according to the configured [NumberFormat].
+pub trait DragValueExt {
+    /// Use the configured decimal separator. Typed in values may use either
+    /// separator.
+    fn localized(self) -> Self;
+
+    /// The value is a length in millimetres. It is displayed and typed in
+    /// using the configured length unit, but stays in millimetres. Also sets
+    /// the suffix to the unit symbol.
+    fn length(self) -> Self;
+}
+
+impl DragValueExt for DragValue<'_> {
+    fn localized(self) -> Self {
+        let format = NumberFormat::current();
+
+        self.custom_formatter(move |value, decimals| format.number(value, decimals))
+            .custom_parser(units::parse_number)
+    }
+
+    fn length(self) -> Self {
+        let format = NumberFormat::current();
+        let unit = format.length_unit;
+
+        self.custom_formatter(move |mm, decimals| format.number(unit.to_unit(mm), decimals))
+            .custom_parser(move |text| units::parse_number(text).map(|value| unit.to_mm(value)))
+            .suffix(format!(" {}", unit.symbol()))
+    }
+}
@@ -1,9 +1,11 @@
 mod drag_vector;
+mod localized;
 mod pan_zoom;
 mod pan_zoom_rect;
 mod path_input;
 
 pub use drag_vector::*;
+pub use localized::*;
 pub use pan_zoom::*;
 pub use pan_zoom_rect::*;
 pub use path_input::*;
@@ -1,16 +1,18 @@
 use egui::{emath::TSTransform, InnerResponse, LayerId, Order, Ui};
 
-pub struct PanZoom {
+pub struct PanZoom<'a> {
     max_zoom: f32,
     min_zoom: f32,
+    transform: Option<&'a mut TSTransform>,
 }
 /// Handles panning and zooming interactions and applies them to the layer
 /// transform.
-impl PanZoom {
+impl<'a> PanZoom<'a> {
     pub fn new() -> Self {
         Self {
             max_zoom: f32::INFINITY,
             min_zoom: 0.0,
+            transform: None,
         }
     }
 
@@ -24,6 +26,13 @@ impl PanZoom {
         self
     }
 
+    /// Keeps the transform in `transform` instead of the memory of egui, so
+    /// it can be saved.
+    pub fn transform(mut self, transform: &'a mut TSTransform) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
     pub fn show<R>(
         &mut self,
         ui: &mut Ui,
@@ -31,10 +40,13 @@ impl PanZoom {
     ) -> InnerResponse<R> {
         let (id, rect) = ui.allocate_space(ui.available_size());
 
-        let mut transform: TSTransform = ui
-            .ctx()
-            .memory(|mem| mem.data.get_temp(id))
-            .unwrap_or(TSTransform::IDENTITY);
+        let mut transform: TSTransform = match self.transform {
+            Some(ref transform) => **transform,
+            None => ui
+                .ctx()
+                .memory(|mem| mem.data.get_temp(id))
+                .unwrap_or(TSTransform::IDENTITY),
+        };
 
         let response = ui.interact(rect, id, egui::Sense::click_and_drag());
 
@@ -97,9 +109,12 @@ impl PanZoom {
 
         ui.ctx().set_transform_layer(layer_id, transform);
 
-        ui.ctx().memory_mut(|mem| {
-            mem.data.insert_temp(id, transform);
-        });
+        match self.transform {
+            Some(ref mut transform_) => **transform_ = transform,
+            None => ui.ctx().memory_mut(|mem| {
+                mem.data.insert_temp(id, transform);
+            }),
+        }
 
         InnerResponse {
             response,
@@ -1,4 +1,4 @@
-use egui::{InnerResponse, Rect, Ui};
+use egui::{InnerResponse, Rect, Ui, Vec2};
 
 use super::PanZoom;
 
@@ -12,6 +12,7 @@ pub struct PanZoomRect {
     zoom_y: bool,
     max_zoom: f32,
     min_zoom: f32,
+    content_size: Option<Vec2>,
 }
 
 #[allow(unused)]
@@ -22,6 +23,7 @@ impl PanZoomRect {
             zoom_y: true,
             max_zoom: f32::INFINITY,
             min_zoom: 0.0,
+            content_size: None,
         }
     }
 
@@ -44,6 +46,14 @@ impl PanZoomRect {
         self.min_zoom = min_zoom;
         self
     }
+
+    /// Size of the content at zoom 1. Along each axis, it is centered when it
+    /// fits into the max rect and starts at its border otherwise. By default,
+    /// the content fills the max rect.
+    pub fn content_size(mut self, content_size: Option<Vec2>) -> Self {
+        self.content_size = content_size;
+        self
+    }
 }
 
 impl PanZoomRect {
@@ -63,6 +73,10 @@ impl PanZoomRect {
 
         let rect = response.rect;
 
+        if let Some(size) = self.content_size {
+            inner_rect = content_rect(inner_rect, size);
+        }
+
         let inner_viewport = transform.inverse() * rect;
 
         if !self.zoom_x {
@@ -93,3 +107,17 @@ impl PanZoomRect {
         InnerResponse { inner, response }
     }
 }
+
+fn content_rect(max_rect: Rect, size: Vec2) -> Rect {
+    let place = |min: f32, max: f32, size: f32| match max - min - size {
+        free if free >= 0.0 => min + free / 2.0,
+        _ => min,
+    };
+
+    let min = egui::pos2(
+        place(max_rect.min.x, max_rect.max.x, size.x),
+        place(max_rect.min.y, max_rect.max.y, size.y),
+    );
+
+    Rect::from_min_size(min, size)
+}
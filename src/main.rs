@@ -7,51 +7,93 @@ mod node_graph;
 mod pipeline;
 #[allow(unused)]
 mod queue_channel;
+mod recovery;
+mod settings;
+mod shortcuts;
+mod units;
 mod view;
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use app::*;
+use recovery::Startup;
+use settings::Settings;
 
 #[tokio::main]
 async fn main() {
-    eframe::run_native(
-        "IVOCT Test App",
+    // Some settings must be known before eframe is started
+    let settings = Settings::load_startup_file().unwrap_or_default();
+    Settings::set_current(settings);
+
+    let startup = Startup::current();
+    let wgpu_options = if startup.safe_mode {
+        // Only what egui needs, on any backend. Data views are disabled
+        eframe::egui_wgpu::WgpuConfiguration {
+            supported_backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::LowPower,
+            ..Default::default()
+        }
+    } else {
+        eframe::egui_wgpu::WgpuConfiguration {
+            supported_backends: wgpu::Backends::PRIMARY,
+            power_preference: settings.display.power_preference.into(),
+            device_descriptor: Arc::new(|adapter| wgpu::DeviceDescriptor {
+                label: Some("egui wgpu device"),
+                required_features: wgpu::Features::TEXTURE_BINDING_ARRAY
+                        | wgpu::Features::PARTIALLY_BOUND_BINDING_ARRAY
+                        | wgpu::Features::PUSH_CONSTANTS
+                        | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
+                        | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                        // Optional, for the performance overlay of the views
+                        | adapter.features()
+                            & (wgpu::Features::TIMESTAMP_QUERY
+                                | wgpu::Features::TIMESTAMP_QUERY_INSIDE_PASSES),
+                required_limits: wgpu::Limits {
+                    max_texture_dimension_2d: 12000,
+                    max_sampled_textures_per_shader_stage: view::views::m_scan::MAX_TEXTURES as _,
+                    // The volume view pushes an inverse matrix with its
+                    // settings
+                    max_push_constant_size: 128,
+                    ..Default::default()
+                },
+            }),
+            ..Default::default()
+        }
+    };
+
+    let result = eframe::run_native(
+        settings::APP_NAME,
         eframe::NativeOptions {
             renderer: eframe::Renderer::Wgpu,
             hardware_acceleration: eframe::HardwareAcceleration::Preferred,
             // Depth buffer needed for 3D view
             depth_buffer: 24,
-            wgpu_options: eframe::egui_wgpu::WgpuConfiguration {
-                supported_backends: wgpu::Backends::PRIMARY,
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                device_descriptor: Arc::new(|_adapter| wgpu::DeviceDescriptor {
-                    label: Some("egui wgpu device"),
-                    required_features: wgpu::Features::TEXTURE_BINDING_ARRAY
-                        | wgpu::Features::PARTIALLY_BOUND_BINDING_ARRAY
-                        | wgpu::Features::PUSH_CONSTANTS
-                        | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
-                        | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
-                    required_limits: wgpu::Limits {
-                        max_texture_dimension_2d: 12000,
-                        max_sampled_textures_per_shader_stage: view::views::m_scan::MAX_TEXTURES as _,
-                        max_push_constant_size: 64,
-                        ..Default::default()
-                    },
-                }),
-                ..Default::default()
-            },
+            wgpu_options,
             ..Default::default()
         },
-        Box::new(|cc| {
-            // Force dark mode
+        Box::new(move |cc| {
             cc.egui_ctx.set_style(egui::Style {
-                visuals: egui::Visuals::dark(),
+                visuals: if settings.display.dark_mode {
+                    egui::Visuals::dark()
+                } else {
+                    egui::Visuals::light()
+                },
                 ..egui::Style::default()
             });
 
-            Ok(Box::new(IVOCTApp::new(cc)))
+            Ok(Box::new(IVOCTApp::new(cc, startup)))
         }),
-    )
-    .unwrap();
+    );
+
+    // Most likely, no device could be requested
+    if let Err(e) = result {
+        let error = anyhow::anyhow!("{}", e).context("Starting the app failed");
+        recovery::log_diagnostics(startup, None, &error);
+
+        match startup.next(Duration::ZERO).map(Startup::relaunch) {
+            Some(Ok(())) => {}
+            Some(Err(e)) => eprintln!("Error relaunching the app: {}", e),
+            None => std::process::exit(1),
+        }
+    }
 }
@@ -0,0 +1,642 @@
+//! Processing a directory of pullbacks with one pipeline.
+//!
+//! For every file matching a pattern, the pipeline is copied, its M scan
+//! input node reads the file and every output node writes to a file named by
+//! a [Template]. The copies run in their own [PipelineExecutor], one after
+//! another, or a few at once. Every finished file adds a line to a summary
+//! CSV, failures do not stop the batch.
+
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::anyhow;
+use futures::{stream::FuturesUnordered, StreamExt};
+use tokio::{io::AsyncWriteExt, sync::watch, task::JoinHandle};
+
+use crate::node_graph::NodeId;
+
+use super::{
+    nodes::{binary_input, output},
+    sessions, Pipeline, PipelineExecutor,
+};
+
+/// Name of the summary CSV, written into the input directory.
+pub const SUMMARY_FILE_NAME: &str = "batch_summary.csv";
+
+/// How often a running pipeline is checked for finished outputs and errors.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchOptions {
+    pub input_dir: PathBuf,
+    /// File names to process, see [matches].
+    pub pattern: String,
+    /// Names of the written files, see [Template].
+    pub output_template: String,
+    /// Number of pipelines running at once. Node tasks of all pipelines share
+    /// the rayon thread pool, so it is limited to its number of threads.
+    pub parallel: usize,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            input_dir: PathBuf::new(),
+            pattern: "*.bin".to_string(),
+            output_template: "{stem}_{output}".to_string(),
+            parallel: 1,
+        }
+    }
+}
+
+// MARK: Template
+
+/// File name of an output, with placeholders:
+///
+/// - `{stem}`: The input file name without extension.
+/// - `{index}`: Position of the input file in the batch, starting at 1. It is
+///   padded with zeros, so the files sort in order.
+/// - `{output}`: The file name the output node writes to in the pipeline.
+///
+/// The file goes into the directory of the output node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template(Vec<Part>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Stem,
+    Index,
+    Output,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> anyhow::Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find(['{', '}']) {
+            if rest[start..].starts_with('}') {
+                return Err(anyhow!("Unmatched \"}}\" in \"{}\"", template));
+            }
+            let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+                return Err(anyhow!("Unmatched \"{{\" in \"{}\"", template));
+            };
+
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            parts.push(match &rest[start + 1..end] {
+                "stem" => Part::Stem,
+                "index" => Part::Index,
+                "output" => Part::Output,
+                other => {
+                    return Err(anyhow!(
+                        "Unknown placeholder {{{}}}, use {{stem}}, {{index}} or {{output}}",
+                        other
+                    ))
+                }
+            });
+            rest = &rest[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+
+        if !parts.iter().any(|p| matches!(p, Part::Stem | Part::Index)) {
+            return Err(anyhow!(
+                "The output names need {{stem}} or {{index}}, otherwise every file overwrites \
+                 the last one"
+            ));
+        }
+        if parts
+            .iter()
+            .any(|p| matches!(p, Part::Text(t) if t.contains(['/', '\\'])))
+        {
+            return Err(anyhow!("The output names must not contain directories"));
+        }
+
+        Ok(Self(parts))
+    }
+
+    /// The file name for the input `stem` at `index` of `count` files.
+    pub fn expand(&self, stem: &str, index: usize, count: usize, output: &str) -> String {
+        let width = count.to_string().len();
+
+        self.0.iter().fold(String::new(), |mut name, part| {
+            match part {
+                Part::Text(text) => name += text,
+                Part::Stem => name += stem,
+                Part::Index => {
+                    let _ = write!(name, "{:0width$}", index + 1);
+                }
+                Part::Output => name += output,
+            }
+            name
+        })
+    }
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any number of
+/// characters and `?` a single one.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+
+    // Position after the last `*` and the position in `name` it matched up to
+    let mut star = None;
+    let (mut p, mut n) = (0, 0);
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    // Let the `*` match one more character
+                    p = star_p;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// MARK: Plan
+
+/// One input file of a batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchFile {
+    pub input: PathBuf,
+    /// The files written by the output nodes.
+    pub outputs: Vec<(NodeId, PathBuf)>,
+}
+
+/// Everything needed to run a batch, checked before anything runs.
+#[derive(Debug)]
+pub struct BatchPlan {
+    pipeline: serde_json::Value,
+    input_node: NodeId,
+    pub files: Vec<BatchFile>,
+    pub summary: PathBuf,
+    pub parallel: usize,
+}
+
+impl BatchPlan {
+    /// Finds the input files and names all outputs. Fails, if the pipeline
+    /// has no single M scan input node, or outputs would collide.
+    pub fn new(pipeline: &Pipeline, options: &BatchOptions) -> anyhow::Result<Self> {
+        let template = Template::parse(&options.output_template)?;
+
+        let input_nodes = pipeline
+            .nodes
+            .iter()
+            .filter(|(_, node)| {
+                node.as_any()
+                    .downcast_ref::<binary_input::Node>()
+                    .is_some_and(|node| node.input_type != binary_input::InputDataType::DataVector)
+            })
+            .map(|(node_id, _)| *node_id)
+            .collect::<Vec<_>>();
+        let input_node = match input_nodes[..] {
+            [node_id] => node_id,
+            [] => return Err(anyhow!("The pipeline has no input node reading an M scan")),
+            _ => {
+                return Err(anyhow!(
+                    "The pipeline has {} input nodes reading an M scan, but only one can be \
+                     replaced",
+                    input_nodes.len()
+                ))
+            }
+        };
+
+        // Outputs, that can finish
+        let mut output_nodes = pipeline
+            .nodes
+            .iter()
+            .filter_map(|(node_id, node)| {
+                let node = node.as_any().downcast_ref::<output::Node>()?;
+                let runs = node.input.connection().is_some()
+                    && pipeline.disabled_upstream(*node_id).is_none();
+                runs.then(|| (*node_id, node.path.clone()))
+            })
+            .collect::<Vec<_>>();
+        output_nodes.sort_by_key(|(node_id, _)| *node_id);
+        if output_nodes.is_empty() {
+            return Err(anyhow!("The pipeline has no connected output node"));
+        }
+        if let Some((node_id, _)) = output_nodes
+            .iter()
+            .find(|(_, path)| path.file_name().is_none())
+        {
+            let name = pipeline.nodes[node_id].name();
+            return Err(anyhow!(
+                "{} has no file set",
+                sessions::label(*node_id, name)
+            ));
+        }
+
+        let inputs = find_files(&options.input_dir, &options.pattern)?;
+        if inputs.is_empty() {
+            return Err(anyhow!(
+                "No file in {} matches \"{}\"",
+                options.input_dir.display(),
+                options.pattern
+            ));
+        }
+
+        let mut written = HashSet::new();
+        let files = inputs
+            .iter()
+            .enumerate()
+            .map(|(index, input)| {
+                let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+                let outputs = output_nodes
+                    .iter()
+                    .map(|(node_id, path)| {
+                        let output = path.file_name().unwrap_or_default().to_string_lossy();
+                        let name = template.expand(&stem, index, inputs.len(), &output);
+                        (*node_id, path.with_file_name(name))
+                    })
+                    .collect::<Vec<_>>();
+
+                for (_, path) in &outputs {
+                    if inputs.contains(path) {
+                        return Err(anyhow!("{} would overwrite an input", path.display()));
+                    }
+                    if !written.insert(path.clone()) {
+                        return Err(anyhow!("{} would be written twice", path.display()));
+                    }
+                }
+
+                Ok(BatchFile {
+                    input: input.clone(),
+                    outputs,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            pipeline: serde_json::to_value(pipeline)?,
+            input_node,
+            files,
+            summary: options.input_dir.join(SUMMARY_FILE_NAME),
+            parallel: options.parallel.clamp(1, rayon::current_num_threads()),
+        })
+    }
+
+    /// A copy of the pipeline, reading and writing the files of `file`.
+    fn pipeline(&self, file: &BatchFile) -> anyhow::Result<Pipeline> {
+        let mut pipeline: Pipeline = serde_json::from_value(self.pipeline.clone())?;
+
+        if let Some(node) = pipeline
+            .nodes
+            .get_mut(&self.input_node)
+            .and_then(|node| node.as_any_mut().downcast_mut::<binary_input::Node>())
+        {
+            node.path = file.input.clone();
+        }
+
+        for (node_id, path) in &file.outputs {
+            if let Some(node) = pipeline
+                .nodes
+                .get_mut(node_id)
+                .and_then(|node| node.as_any_mut().downcast_mut::<output::Node>())
+            {
+                node.path = path.clone();
+            }
+        }
+
+        Ok(pipeline)
+    }
+}
+
+/// Files in `dir` matching `pattern`, sorted by name.
+fn find_files(dir: &Path, pattern: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if entry.file_type()?.is_file() && matches(pattern, &name.to_string_lossy()) {
+            files.push(entry.path());
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+// MARK: Run
+
+/// Outcome of one input file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileResult {
+    pub input: PathBuf,
+    pub result: Result<(), String>,
+    pub seconds: f64,
+}
+
+/// State of a [BatchRun].
+#[derive(Debug, Clone, Default)]
+pub struct BatchState {
+    pub total: usize,
+    /// See [BatchPlan::summary].
+    pub summary: PathBuf,
+    /// Finished files, in the order they finished.
+    pub results: Vec<FileResult>,
+    /// Files currently processed.
+    pub running: Vec<PathBuf>,
+    pub finished: bool,
+    /// Writing the summary failed.
+    pub error: Option<String>,
+}
+
+/// Runs a batch in the background. Dropping it aborts the batch, files
+/// being processed are left unfinished.
+pub struct BatchRun {
+    state: watch::Receiver<BatchState>,
+    stop: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+impl BatchRun {
+    pub fn start(plan: BatchPlan) -> Self {
+        let (state_tx, state) = watch::channel(BatchState {
+            total: plan.files.len(),
+            summary: plan.summary.clone(),
+            ..Default::default()
+        });
+        let (stop, stop_rx) = watch::channel(false);
+
+        let task = tokio::spawn(async move {
+            if let Err(e) = run(&plan, &state_tx, stop_rx).await {
+                state_tx.send_modify(|state| state.error = Some(e.to_string()));
+            }
+            state_tx.send_modify(|state| {
+                state.running.clear();
+                state.finished = true;
+            });
+        });
+
+        Self { state, stop, task }
+    }
+
+    pub fn state(&self) -> BatchState {
+        self.state.borrow().clone()
+    }
+
+    /// Lets the files being processed finish, but starts no more.
+    pub fn stop(&self) {
+        self.stop.send_replace(true);
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        *self.stop.borrow()
+    }
+}
+
+impl Drop for BatchRun {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn run(
+    plan: &BatchPlan,
+    state_tx: &watch::Sender<BatchState>,
+    stop: watch::Receiver<bool>,
+) -> anyhow::Result<()> {
+    let mut summary = tokio::fs::File::create(&plan.summary).await?;
+    summary.write_all(b"file,status,seconds,error\n").await?;
+
+    let mut files = plan.files.iter();
+    let mut running = FuturesUnordered::new();
+
+    loop {
+        while running.len() < plan.parallel && !*stop.borrow() {
+            let Some(file) = files.next() else {
+                break;
+            };
+            state_tx.send_modify(|state| state.running.push(file.input.clone()));
+            running.push(process(plan, file));
+        }
+
+        let Some(result) = running.next().await else {
+            break;
+        };
+
+        summary.write_all(summary_line(&result).as_bytes()).await?;
+        summary.flush().await?;
+
+        state_tx.send_modify(|state| {
+            state.running.retain(|input| *input != result.input);
+            state.results.push(result);
+        });
+    }
+
+    Ok(())
+}
+
+async fn process(plan: &BatchPlan, file: &BatchFile) -> FileResult {
+    let start = Instant::now();
+    let result = match plan.pipeline(file) {
+        Ok(pipeline) => run_file(pipeline, file).await,
+        Err(e) => Err(e),
+    };
+
+    FileResult {
+        input: file.input.clone(),
+        result: result.map_err(|e| e.to_string()),
+        seconds: start.elapsed().as_secs_f64(),
+    }
+}
+
+/// Saves all outputs of `pipeline` and waits, until they are written or a
+/// node fails.
+async fn run_file(mut pipeline: Pipeline, file: &BatchFile) -> anyhow::Result<()> {
+    let started = SystemTime::now();
+
+    let mut executor = PipelineExecutor::new();
+    executor.update(&mut pipeline);
+
+    let mut saves = Vec::new();
+    for (node_id, _) in &file.outputs {
+        let node = pipeline
+            .nodes
+            .get_mut(node_id)
+            .and_then(|node| node.as_any_mut().downcast_mut::<output::Node>())
+            .ok_or_else(|| anyhow!("Output node is missing"))?;
+        node.save();
+        saves.extend(node.saves_rx.clone());
+    }
+
+    while !saves.iter().all(|saves| *saves.borrow() > 0) {
+        if let Some(e) = failure(&pipeline, &executor) {
+            return Err(e);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    // Outputs count as saved, even if their input got no response. This
+    // happens, when a node upstream failed, which is reported shortly after.
+    for (_, path) in &file.outputs {
+        let modified = fs::metadata(path).and_then(|m| m.modified());
+        if !modified.is_ok_and(|modified| modified >= started) {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            return Err(failure(&pipeline, &executor)
+                .unwrap_or_else(|| anyhow!("Nothing was written to {}", path.display())));
+        }
+    }
+
+    Ok(())
+}
+
+/// The first node, whose last run failed.
+fn failure(pipeline: &Pipeline, executor: &PipelineExecutor) -> Option<anyhow::Error> {
+    let (node_id, error) = executor.errors().into_iter().next()?;
+    let name = pipeline.nodes.get(&node_id)?.name();
+    Some(anyhow!("{}: {}", sessions::label(node_id, name), error))
+}
+
+fn summary_line(result: &FileResult) -> String {
+    let (status, error) = match &result.result {
+        Ok(()) => ("ok", ""),
+        Err(e) => ("error", e.as_str()),
+    };
+
+    format!(
+        "{},{},{:.3},{}\n",
+        csv_field(&result.input.display().to_string()),
+        status,
+        result.seconds,
+        csv_field(error)
+    )
+}
+
+/// Quotes a field, if it contains a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let template = Template::parse("{stem}_{index}_{output}").unwrap();
+        assert_eq!(template.expand("pb", 6, 40, "lumen.csv"), "pb_07_lumen.csv");
+        assert_eq!(template.expand("pb", 0, 5, "lumen.csv"), "pb_1_lumen.csv");
+
+        assert!(Template::parse("{output}").is_err());
+        assert!(Template::parse("{stem}_{name}").is_err());
+        assert!(Template::parse("{stem").is_err());
+        assert!(Template::parse("stem}").is_err());
+        assert!(Template::parse("out/{stem}").is_err());
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(matches("*.bin", "pullback_01.bin"));
+        assert!(matches("*", "a"));
+        assert!(matches("pb_??.bin", "pb_01.bin"));
+        assert!(matches("*_*.bin", "a_b_c.bin"));
+        assert!(!matches("*.bin", "pullback.bin.txt"));
+        assert!(!matches("pb_?.bin", "pb_01.bin"));
+        assert!(!matches("a*", "ba"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn batch() {
+        let dir = std::env::temp_dir().join(format!("ivoct_batch_{}", std::process::id()));
+        let out_dir = dir.join("out");
+        fs::create_dir_all(&out_dir).unwrap();
+
+        let raw = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden/raw.bin");
+        for name in ["a.bin", "b.bin", "c.bin"] {
+            fs::copy(&raw, dir.join(name)).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        // Writing the output of b fails
+        fs::create_dir_all(out_dir.join("b_scan.bin.part")).unwrap();
+
+        let pipeline: Pipeline = serde_json::from_value(json!({ "nodes": {
+            "1": { "type": "binary_input", "path": "", "input_type": "RawMScan",
+                   "data_type": "U16", "a_scan_length": 256 },
+            "2": { "type": "output", "path": out_dir.join("scan.bin"), "input_type": "RawMScan",
+                   "scan_data_type": "U16",
+                   "input": { "value": null,
+                              "connection": { "node_id": 1, "output_id": 0, "type_id": 0 } } },
+        }}))
+        .unwrap();
+
+        let options = BatchOptions {
+            input_dir: dir.clone(),
+            parallel: 2,
+            ..Default::default()
+        };
+        let plan = BatchPlan::new(&pipeline, &options).unwrap();
+        assert_eq!(plan.files.len(), 3);
+        assert_eq!(plan.files[2].outputs[0].1, out_dir.join("c_scan.bin"));
+
+        let run = BatchRun::start(plan);
+        let mut state = run.state.clone();
+        tokio::time::timeout(Duration::from_secs(60), state.wait_for(|s| s.finished))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let state = run.state();
+        assert_eq!(state.error, None);
+        assert_eq!(state.results.len(), 3);
+        for result in &state.results {
+            let failed = result.input.ends_with("b.bin");
+            assert_eq!(result.result.is_err(), failed, "{:?}", result);
+        }
+        assert_eq!(
+            fs::read(out_dir.join("a_scan.bin")).unwrap(),
+            fs::read(&raw).unwrap()
+        );
+
+        let summary = fs::read_to_string(dir.join(SUMMARY_FILE_NAME)).unwrap();
+        assert_eq!(summary.lines().count(), 4);
+        assert_eq!(summary.matches(",ok,").count(), 2);
+        assert_eq!(summary.matches(",error,").count(), 1);
+
+        // Stopping before the first file processes nothing
+        let plan = BatchPlan::new(&pipeline, &options).unwrap();
+        let run = BatchRun::start(plan);
+        run.stop();
+        let mut state = run.state.clone();
+        let state = tokio::time::timeout(Duration::from_secs(60), state.wait_for(|s| s.finished))
+            .await
+            .unwrap()
+            .unwrap()
+            .clone();
+        assert!(state.results.len() < 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
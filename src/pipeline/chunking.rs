@@ -0,0 +1,214 @@
+// Helpers to keep the size of streamed chunks within sensible bounds.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{queue_channel, settings::Settings};
+
+use super::{determinism, types::DataMatrix};
+
+// MARK: ChunkLimits
+
+/// Bounds for the number of columns (A-scans) in one streamed chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChunkLimits {
+    /// Chunks wider than this are split into multiple chunks.
+    pub max_width: usize,
+    /// Chunks narrower than this are held back and merged with the following
+    /// chunks.
+    pub min_width: usize,
+}
+
+impl ChunkLimits {
+    pub const DEFAULT: ChunkLimits = ChunkLimits {
+        max_width: 16384,
+        min_width: 16,
+    };
+
+    /// The limits currently used by all producers of streamed chunks, as
+    /// configured in the app settings. Node tasks in deterministic mode use
+    /// [Self::DEFAULT], see [super::determinism].
+    pub fn current() -> Self {
+        match determinism::is_deterministic() {
+            true => Self::DEFAULT,
+            false => Settings::current().performance.chunk_limits,
+        }
+    }
+}
+
+impl Default for ChunkLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+// MARK: ChunkedSender
+
+/// Wraps the sender of a streamed [DataMatrix] response and enforces
+/// [ChunkLimits] on every chunk send through it, while preserving the order
+/// of all columns.
+///
+/// Chunks held back for coalescing are sent when [Self::flush] is called or
+/// the sender is dropped.
+pub struct ChunkedSender {
+    tx: queue_channel::Sender<Arc<DataMatrix>>,
+    max_width: usize,
+    min_width: usize,
+    pending: Option<DataMatrix>,
+}
+
+impl ChunkedSender {
+    pub fn new(tx: queue_channel::Sender<Arc<DataMatrix>>, limits: ChunkLimits) -> Self {
+        Self {
+            tx,
+            max_width: limits.max_width.max(limits.min_width).max(1),
+            min_width: limits.min_width,
+            pending: None,
+        }
+    }
+
+    pub fn send(&mut self, chunk: impl Into<Arc<DataMatrix>>) {
+        let mut chunk: Arc<DataMatrix> = chunk.into();
+
+        if let Some(pending) = self.pending.take() {
+            match pending.concat_horizontally(&chunk) {
+                Some(merged) => chunk = Arc::new(merged),
+                None => self.tx.send(Arc::new(pending)),
+            }
+        }
+
+        let ncols = chunk.ncols();
+
+        if ncols < self.min_width {
+            self.pending = Some(Arc::unwrap_or_clone(chunk));
+            return;
+        }
+
+        if ncols <= self.max_width {
+            self.tx.send(chunk);
+            return;
+        }
+
+        let mut start = 0;
+        while ncols - start > self.max_width {
+            self.tx.send(Arc::new(chunk.columns(start, self.max_width)));
+            start += self.max_width;
+        }
+
+        let rest = chunk.columns(start, ncols - start);
+        if rest.ncols() < self.min_width {
+            self.pending = Some(rest);
+        } else {
+            self.tx.send(Arc::new(rest));
+        }
+    }
+
+    /// Sends all chunks that were held back, regardless of their size.
+    pub fn flush(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            if pending.ncols() > 0 {
+                self.tx.send(Arc::new(pending));
+            }
+        }
+    }
+
+    /// Sends all chunks that were held back and marks the stream as complete,
+    /// see [queue_channel::Sender::finish].
+    pub fn finish(mut self) {
+        self.flush();
+        self.tx.clone().finish();
+    }
+}
+
+impl Drop for ChunkedSender {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+// MARK: Tests
+
+#[cfg(test)]
+mod test {
+    use futures::FutureExt;
+
+    use super::*;
+    use crate::pipeline::types::DataType;
+
+    fn send_all(limits: ChunkLimits, widths: &[usize]) -> Vec<usize> {
+        let (tx, mut rx) = queue_channel::channel(1000);
+
+        {
+            let mut sender = ChunkedSender::new(tx, limits);
+            for &width in widths {
+                sender.send(DataMatrix::from_data_type(DataType::U16, 4, width));
+            }
+        }
+
+        let mut result = Vec::new();
+        while let Some(Ok(chunk)) = rx.recv().now_or_never() {
+            result.push(chunk.ncols());
+        }
+        result
+    }
+
+    #[test]
+    fn test_typical_chunks_are_unchanged() {
+        let widths = [1000, 2000, 4000, 3000, 12000, 5];
+        assert_eq!(send_all(ChunkLimits::default(), &widths), widths);
+    }
+
+    #[test]
+    fn test_wide_chunks_are_split() {
+        let limits = ChunkLimits {
+            max_width: 1000,
+            min_width: 0,
+        };
+
+        assert_eq!(send_all(limits, &[1000]), [1000]);
+        assert_eq!(send_all(limits, &[1001]), [1000, 1]);
+        assert_eq!(send_all(limits, &[3500]), [1000, 1000, 1000, 500]);
+        assert_eq!(send_all(limits, &[2000, 10]), [1000, 1000, 10]);
+    }
+
+    #[test]
+    fn test_tiny_chunks_are_coalesced() {
+        let limits = ChunkLimits {
+            max_width: 1000,
+            min_width: 100,
+        };
+
+        assert_eq!(send_all(limits, &[10; 25]), [100, 100, 50]);
+        assert_eq!(send_all(limits, &[50, 2000]), [1000, 1000, 50]);
+        assert_eq!(send_all(limits, &[2050, 60]), [1000, 1000, 110]);
+    }
+
+    #[test]
+    fn test_column_order_is_preserved() {
+        let limits = ChunkLimits {
+            max_width: 3,
+            min_width: 2,
+        };
+
+        let (tx, mut rx) = queue_channel::channel(100);
+
+        {
+            let mut sender = ChunkedSender::new(tx, limits);
+            let data = nalgebra::DMatrix::from_fn(2, 8, |r, c| (c * 2 + r) as u16);
+            sender.send(DataMatrix::U16(data.columns(0, 1).into_owned()));
+            sender.send(DataMatrix::U16(data.columns(1, 7).into_owned()));
+        }
+
+        let mut values = Vec::new();
+        while let Some(Ok(chunk)) = rx.recv().now_or_never() {
+            let DataMatrix::U16(chunk) = chunk.as_ref() else {
+                unreachable!()
+            };
+            values.extend(chunk.iter().copied());
+        }
+
+        assert_eq!(values, (0..16).collect::<Vec<u16>>());
+    }
+}
@@ -0,0 +1,565 @@
+//! Differences between two versions of a pipeline file, for reviewing changes
+//! to pipelines kept under version control.
+//!
+//! Both pipelines are read as [PipelineSnapshot]s, which keep the nodes in
+//! their serialized form, so nodes of types this version does not know are
+//! compared as well. Nodes are matched by their id first. Nodes left over,
+//! like a node deleted and added again under a new id, are matched by their
+//! type and the types of the nodes they are connected to, see [Fingerprint].
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use anyhow::anyhow;
+use serde_json::Value;
+
+use crate::node_graph::NodeId;
+
+use super::{nodes::DynPipelineNode, report::flatten_settings, sessions::label};
+
+// MARK: PipelineSnapshot
+
+/// A pipeline, as read for comparing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PipelineSnapshot {
+    nodes: BTreeMap<NodeId, NodeSnapshot>,
+}
+
+/// A node in its serialized form.
+#[derive(Debug, Clone, PartialEq)]
+struct NodeSnapshot {
+    slug: String,
+    /// Name shown in the editor, or a placeholder for unknown types.
+    name: String,
+    /// Dotted keys and values, see [flatten_settings].
+    settings: Vec<(String, String)>,
+    /// Inputs by their dotted key, with the node they are connected to.
+    inputs: BTreeMap<String, Option<Source>>,
+    disabled: bool,
+}
+
+/// Output an input is connected to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Source {
+    node_id: NodeId,
+    output: u64,
+}
+
+impl PipelineSnapshot {
+    /// Reads a pipeline file, as saved by the app with its editor state, or
+    /// a bare pipeline.
+    pub fn from_value(value: &Value) -> anyhow::Result<Self> {
+        // Saved files hold the pipeline and the editor state
+        let pipeline = match value {
+            Value::Array(items) => items.first().unwrap_or(&Value::Null),
+            value => value,
+        };
+
+        let nodes = pipeline
+            .get("nodes")
+            .and_then(Value::as_object)
+            .ok_or_else(|| anyhow!("The file does not contain a pipeline"))?;
+
+        let disabled = pipeline
+            .get("disabled")
+            .and_then(Value::as_array)
+            .map(|ids| ids.iter().filter_map(Value::as_u64).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let mut snapshot = Self::default();
+        for (id, node) in nodes {
+            let id: usize = id
+                .parse()
+                .map_err(|_| anyhow!("Invalid node id {:?}", id))?;
+            let slug = node
+                .get("type")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+
+            // Unknown types and settings of other versions only lose the name
+            let name = match serde_json::from_value::<Box<dyn DynPipelineNode>>(node.clone()) {
+                Ok(node) => node.name().to_string(),
+                Err(_) => format!("Unknown {}", slug),
+            };
+
+            let mut inputs = BTreeMap::new();
+            collect_inputs("", node, &mut inputs);
+
+            snapshot.nodes.insert(
+                NodeId::from(id),
+                NodeSnapshot {
+                    slug,
+                    name,
+                    settings: flatten_settings(node),
+                    inputs,
+                    disabled: disabled.contains(&(id as u64)),
+                },
+            );
+        }
+
+        Ok(snapshot)
+    }
+}
+
+/// Collects the inputs of a serialized node, which are objects with a
+/// `value` and a `connection`.
+fn collect_inputs(prefix: &str, value: &Value, inputs: &mut BTreeMap<String, Option<Source>>) {
+    let Some(object) = value.as_object() else {
+        return;
+    };
+
+    if object.len() == 2 && object.contains_key("value") && object.contains_key("connection") {
+        let source = object["connection"].as_object().and_then(|connection| {
+            Some(Source {
+                node_id: NodeId::from(connection.get("node_id")?.as_u64()? as usize),
+                output: connection.get("output_id")?.as_u64()?,
+            })
+        });
+        inputs.insert(prefix.to_string(), source);
+        return;
+    }
+
+    for (key, value) in object {
+        let key = match prefix.is_empty() {
+            true => key.clone(),
+            false => format!("{prefix}.{key}"),
+        };
+        collect_inputs(&key, value, inputs);
+    }
+}
+
+// MARK: Matching
+
+/// Identifies a node without its id: its type and the types of the nodes
+/// connected to its inputs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Fingerprint {
+    slug: String,
+    inputs: Vec<(String, Option<String>)>,
+}
+
+impl PipelineSnapshot {
+    fn fingerprint(&self, node: &NodeSnapshot) -> Fingerprint {
+        Fingerprint {
+            slug: node.slug.clone(),
+            inputs: node
+                .inputs
+                .iter()
+                .map(|(key, source)| {
+                    let slug = source
+                        .and_then(|source| self.nodes.get(&source.node_id))
+                        .map(|node| node.slug.clone());
+                    (key.clone(), slug)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Pairs of nodes of `a` and `b`, that are the same node.
+fn match_nodes(a: &PipelineSnapshot, b: &PipelineSnapshot) -> BTreeMap<NodeId, NodeId> {
+    let mut matched = BTreeMap::new();
+
+    for (id, node) in &a.nodes {
+        if b.nodes.get(id).is_some_and(|other| other.slug == node.slug) {
+            matched.insert(*id, *id);
+        }
+    }
+
+    // Nodes of b, that are left, by their fingerprint, in the order of ids
+    let mut left: HashMap<Fingerprint, Vec<NodeId>> = HashMap::new();
+    for (id, node) in b.nodes.iter().rev() {
+        if !matched.values().any(|matched| matched == id) {
+            left.entry(b.fingerprint(node)).or_default().push(*id);
+        }
+    }
+
+    for (id, node) in &a.nodes {
+        if matched.contains_key(id) {
+            continue;
+        }
+        if let Some(other) = left.get_mut(&a.fingerprint(node)).and_then(Vec::pop) {
+            matched.insert(*id, other);
+        }
+    }
+
+    matched
+}
+
+// MARK: Diff
+
+/// How a node differs between the two pipelines.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeDiff {
+    /// Only in the second pipeline.
+    Added { node_id: NodeId, label: String },
+    /// Only in the first pipeline.
+    Removed { node_id: NodeId, label: String },
+    /// In both, possibly under different ids.
+    Changed {
+        a: NodeId,
+        b: NodeId,
+        label: String,
+        changes: Vec<Change>,
+    },
+}
+
+/// A change to a node in both pipelines.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// A setting got a new value. [None], if it does not exist on one side.
+    Setting {
+        key: String,
+        from: Option<String>,
+        to: Option<String>,
+    },
+    /// A setting with the same value under a new key, like after a setting
+    /// got renamed in a newer version.
+    Renamed {
+        from: String,
+        to: String,
+        value: String,
+    },
+    /// An input got connected to another node. The nodes are given by their
+    /// labels, [None] if not connected.
+    Connection {
+        input: String,
+        from: Option<String>,
+        to: Option<String>,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let or_nothing = |value: &Option<String>| value.clone().unwrap_or("nothing".to_string());
+
+        match self {
+            Change::Setting { key, from, to } => {
+                write!(f, "{key}: {} → {}", or_nothing(from), or_nothing(to))
+            }
+            Change::Renamed { from, to, value } => write!(f, "{from} renamed to {to} ({value})"),
+            Change::Connection { input, from, to } => {
+                write!(
+                    f,
+                    "Input {input}: {} → {}",
+                    or_nothing(from),
+                    or_nothing(to)
+                )
+            }
+        }
+    }
+}
+
+/// Which side of a comparison a node id belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    A,
+    B,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffStatus {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// Differences from pipeline `a` to `b`, ordered by node.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PipelineDiff {
+    pub nodes: Vec<NodeDiff>,
+}
+
+impl PipelineDiff {
+    pub fn new(a: &PipelineSnapshot, b: &PipelineSnapshot) -> Self {
+        let matched = match_nodes(a, b);
+        let labels = |side: &PipelineSnapshot, node_id: &NodeId| {
+            side.nodes
+                .get(node_id)
+                .map(|node| label(*node_id, &node.name))
+        };
+
+        let mut nodes = Vec::new();
+
+        for (a_id, a_node) in &a.nodes {
+            let Some(b_id) = matched.get(a_id) else {
+                nodes.push(NodeDiff::Removed {
+                    node_id: *a_id,
+                    label: label(*a_id, &a_node.name),
+                });
+                continue;
+            };
+            let b_node = &b.nodes[b_id];
+
+            let mut changes = settings_changes(&a_node.settings, &b_node.settings);
+
+            // Sources of a are named by their node in b, if it still exists
+            let source_label = |source: &Option<Source>, in_a: bool| {
+                let source = (*source)?;
+                Some(match in_a {
+                    true => match matched.get(&source.node_id) {
+                        Some(b_id) => labels(b, b_id)?,
+                        None => labels(a, &source.node_id)?,
+                    },
+                    false => labels(b, &source.node_id)?,
+                })
+            };
+            let keys = a_node.inputs.keys().chain(b_node.inputs.keys());
+            for input in keys.collect::<std::collections::BTreeSet<_>>() {
+                let from = a_node.inputs.get(input).and_then(|s| source_label(s, true));
+                let to = b_node
+                    .inputs
+                    .get(input)
+                    .and_then(|s| source_label(s, false));
+                if from != to {
+                    changes.push(Change::Connection {
+                        input: input.clone(),
+                        from,
+                        to,
+                    });
+                }
+            }
+
+            if a_node.disabled != b_node.disabled {
+                changes.push(Change::Setting {
+                    key: "disabled".to_string(),
+                    from: Some(a_node.disabled.to_string()),
+                    to: Some(b_node.disabled.to_string()),
+                });
+            }
+
+            if !changes.is_empty() || a_id != b_id {
+                nodes.push(NodeDiff::Changed {
+                    a: *a_id,
+                    b: *b_id,
+                    label: label(*b_id, &b_node.name),
+                    changes,
+                });
+            }
+        }
+
+        for (b_id, b_node) in &b.nodes {
+            if !matched.values().any(|matched| matched == b_id) {
+                nodes.push(NodeDiff::Added {
+                    node_id: *b_id,
+                    label: label(*b_id, &b_node.name),
+                });
+            }
+        }
+
+        Self { nodes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Status of the nodes of one `side`, to outline them in the editor.
+    pub fn statuses(&self, side: Side) -> HashMap<NodeId, DiffStatus> {
+        self.nodes
+            .iter()
+            .filter_map(|diff| match (diff, side) {
+                (NodeDiff::Added { node_id, .. }, Side::B) => Some((*node_id, DiffStatus::Added)),
+                (NodeDiff::Removed { node_id, .. }, Side::A) => {
+                    Some((*node_id, DiffStatus::Removed))
+                }
+                (NodeDiff::Changed { a, .. }, Side::A) => Some((*a, DiffStatus::Changed)),
+                (NodeDiff::Changed { b, .. }, Side::B) => Some((*b, DiffStatus::Changed)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Changed settings. Keys only on one side with equal values are reported as
+/// renamed.
+fn settings_changes(a: &[(String, String)], b: &[(String, String)]) -> Vec<Change> {
+    let a = a.iter().cloned().collect::<BTreeMap<_, _>>();
+    let b = b.iter().cloned().collect::<BTreeMap<_, _>>();
+
+    let mut only_b = b
+        .iter()
+        .filter(|(key, _)| !a.contains_key(*key))
+        .collect::<Vec<_>>();
+
+    let mut changes = Vec::new();
+    for (key, value) in &a {
+        match b.get(key) {
+            Some(other) if other == value => {}
+            Some(other) => changes.push(Change::Setting {
+                key: key.clone(),
+                from: Some(value.clone()),
+                to: Some(other.clone()),
+            }),
+            None => match only_b.iter().position(|(_, other)| *other == value) {
+                Some(index) => {
+                    let (to, _) = only_b.remove(index);
+                    changes.push(Change::Renamed {
+                        from: key.clone(),
+                        to: to.clone(),
+                        value: value.clone(),
+                    });
+                }
+                None => changes.push(Change::Setting {
+                    key: key.clone(),
+                    from: Some(value.clone()),
+                    to: None,
+                }),
+            },
+        }
+    }
+
+    for (key, value) in only_b {
+        changes.push(Change::Setting {
+            key: key.clone(),
+            from: None,
+            to: Some(value.clone()),
+        });
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn input(node_id: usize) -> Value {
+        json!({ "value": null, "connection": { "node_id": node_id, "output_id": 0, "type_id": 2 } })
+    }
+
+    fn gaussian(source: usize, sigma: f32) -> Value {
+        json!({ "type": "filter", "filter_type": "Gaussian",
+                "gauss_settings": { "kernel_size": [5, 5], "sigma": sigma },
+                "input": input(source) })
+    }
+
+    fn snapshot(nodes: Value) -> PipelineSnapshot {
+        PipelineSnapshot::from_value(&json!([{ "nodes": nodes }, null])).unwrap()
+    }
+
+    fn input_node() -> Value {
+        json!({ "type": "binary_input", "path": "a.bin", "input_type": "MScan",
+                "data_type": "U16", "a_scan_length": 256 })
+    }
+
+    #[test]
+    fn identical() {
+        let a = snapshot(json!({ "1": input_node(), "2": gaussian(1, 2.0) }));
+        assert!(PipelineDiff::new(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn renamed_settings() {
+        let a = snapshot(json!({ "1": input_node(), "2": gaussian(1, 2.0) }));
+        let b = snapshot(json!({
+            "1": { "type": "binary_input", "path": "a.bin", "input_type": "MScan",
+                   "data_type": "U16", "samples_per_a_scan": 256 },
+            "2": gaussian(1, 3.0),
+        }));
+
+        let diff = PipelineDiff::new(&a, &b);
+        assert_eq!(diff.nodes.len(), 2, "{:#?}", diff);
+
+        let NodeDiff::Changed { changes, .. } = &diff.nodes[0] else {
+            panic!("{:?}", diff.nodes[0]);
+        };
+        assert_eq!(
+            changes,
+            &[Change::Renamed {
+                from: "a_scan_length".to_string(),
+                to: "samples_per_a_scan".to_string(),
+                value: "256".to_string(),
+            }]
+        );
+
+        let NodeDiff::Changed { changes, .. } = &diff.nodes[1] else {
+            panic!("{:?}", diff.nodes[1]);
+        };
+        assert_eq!(
+            changes,
+            &[Change::Setting {
+                key: "gauss_settings.sigma".to_string(),
+                from: Some("2.0".to_string()),
+                to: Some("3.0".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn moved_connections() {
+        let a = snapshot(json!({
+            "1": input_node(),
+            "2": gaussian(1, 2.0),
+            "3": gaussian(2, 2.0),
+        }));
+        // The second filter reads the input directly, the first one got
+        // deleted and added again under a new id
+        let b = snapshot(json!({
+            "1": input_node(),
+            "3": gaussian(1, 2.0),
+            "4": gaussian(1, 2.0),
+        }));
+
+        let diff = PipelineDiff::new(&a, &b);
+        let changes = diff
+            .nodes
+            .iter()
+            .filter_map(|node| match node {
+                NodeDiff::Changed { a, b, changes, .. } => Some((*a, *b, changes.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // Matched by its type and source, under its new id
+        assert!(changes
+            .iter()
+            .any(|(a, b, changes)| *a == 2.into() && *b == 4.into() && changes.is_empty()));
+
+        let (_, _, moved) = changes.iter().find(|(a, ..)| *a == 3.into()).unwrap();
+        assert_eq!(moved.len(), 1);
+        assert!(matches!(
+            &moved[0],
+            Change::Connection { input, from: Some(from), to: Some(to) }
+                if input == "input" && from.ends_with("(#4)") && to.ends_with("(#1)")
+        ));
+
+        assert!(!diff
+            .nodes
+            .iter()
+            .any(|node| matches!(node, NodeDiff::Added { .. } | NodeDiff::Removed { .. })));
+
+        let statuses = diff.statuses(Side::B);
+        assert_eq!(statuses.get(&3.into()), Some(&DiffStatus::Changed));
+        assert_eq!(statuses.get(&1.into()), None);
+    }
+
+    #[test]
+    fn unknown_types() {
+        let a = snapshot(json!({ "1": input_node() }));
+        let b = snapshot(json!({
+            "1": input_node(),
+            "2": { "type": "from_the_future", "strength": 3, "m_scan": input(1) },
+        }));
+
+        let diff = PipelineDiff::new(&a, &b);
+        assert_eq!(
+            diff.nodes,
+            vec![NodeDiff::Added {
+                node_id: 2.into(),
+                label: "Unknown from_the_future (#2)".to_string(),
+            }]
+        );
+
+        let diff = PipelineDiff::new(&b, &a);
+        assert!(matches!(&diff.nodes[..], [NodeDiff::Removed { .. }]));
+        assert_eq!(diff.statuses(Side::A)[&2.into()], DiffStatus::Removed);
+
+        assert!(PipelineSnapshot::from_value(&json!({})).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! Deterministic mode of a pipeline, see [super::Pipeline::deterministic].
+//!
+//! Two runs of the same pipeline on the same data may differ in the last bits
+//! of their results: Parallel sums are added up in the order the threads
+//! finish, some algorithms split their data by the number of threads, and the
+//! size of chunks depends on the settings. In deterministic mode:
+//!
+//! - Parallel reductions collect their partial results and add them up in
+//!   order, see [par_sum].
+//! - Algorithms, whose results depend on how the data is split, use
+//!   [FIXED_BLOCKS] instead of the number of threads, see [parallel_blocks].
+//! - Chunks use [crate::pipeline::chunking::ChunkLimits::DEFAULT], regardless
+//!   of the settings.
+//! - Normalizations depending on the size of the first chunk use percentiles
+//!   instead.
+//!
+//! Nodes, that still behave differently between runs, like an external
+//! command, report it with [super::nodes::PipelineNode::nondeterminism].
+//!
+//! The mode is set per node task, when the [super::PipelineExecutor] spawns it.
+//! Blocking computations, like in [tokio::task::spawn_blocking] or on the
+//! rayon thread pool, do not know it, so tasks read it before and pass it on.
+
+use std::{future::Future, iter::Sum};
+
+use rayon::iter::IndexedParallelIterator;
+
+/// Number of blocks algorithms split their data into in deterministic mode,
+/// when their result depends on the split.
+pub const FIXED_BLOCKS: usize = 16;
+
+tokio::task_local! {
+    static DETERMINISTIC: bool;
+}
+
+/// Whether the node task calling this runs in deterministic mode. Always
+/// false outside of node tasks.
+pub fn is_deterministic() -> bool {
+    DETERMINISTIC
+        .try_with(|deterministic| *deterministic)
+        .unwrap_or(false)
+}
+
+/// Runs a node task in deterministic mode or not.
+pub(super) async fn scope<F: Future>(deterministic: bool, task: F) -> F::Output {
+    DETERMINISTIC.scope(deterministic, task).await
+}
+
+/// Number of blocks to split data into for parallel algorithms, whose result
+/// depends on the split.
+pub fn parallel_blocks(deterministic: bool) -> usize {
+    match deterministic {
+        true => FIXED_BLOCKS,
+        false => rayon::current_num_threads(),
+    }
+}
+
+/// Sum of `values`. In deterministic mode, the values are collected and added
+/// up in order, otherwise as they are computed.
+pub fn par_sum<T, I>(values: I, deterministic: bool) -> T
+where
+    T: Sum + Send,
+    I: IndexedParallelIterator<Item = T>,
+{
+    match deterministic {
+        true => values.collect::<Vec<_>>().into_iter().sum(),
+        false => values.sum(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rayon::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn mode_of_the_task() {
+        assert!(!is_deterministic());
+        assert!(scope(true, async { is_deterministic() }).await);
+        assert!(!scope(false, async { is_deterministic() }).await);
+
+        // Blocking computations do not know the mode
+        let blocking = scope(true, async {
+            tokio::task::spawn_blocking(is_deterministic).await.unwrap()
+        });
+        assert!(!blocking.await);
+    }
+
+    #[test]
+    fn sum_in_order() {
+        let values = (0..10000)
+            .map(|i| 1.0 / (i as f32 + 1.0))
+            .collect::<Vec<_>>();
+        let sequential = values.iter().sum::<f32>();
+
+        for _ in 0..10 {
+            let sum = par_sum(values.par_iter().copied(), true);
+            assert_eq!(sum.to_bits(), sequential.to_bits());
+        }
+        assert!((par_sum(values.par_iter().copied(), false) - sequential).abs() < 1e-3);
+    }
+}
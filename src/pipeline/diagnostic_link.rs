@@ -0,0 +1,498 @@
+//! Diagnostic links, to reproduce the situation of a node from a bug report.
+//!
+//! A link holds the settings of a node, the types and settings hashes of the
+//! nodes upstream of it and what its outputs sent last, but no data, so it
+//! stays short enough to paste anywhere. It looks like
+//! `ivoct-diag:1:eyJhcHBf…`, the version followed by the JSON of a
+//! [Diagnostic] in URL safe Base64.
+//!
+//! Decoding rejects links of newer versions. Older versions have to be
+//! migrated in [Diagnostic::decode], once there are any.
+
+use std::fmt::Write;
+
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::node_graph::NodeId;
+
+use super::{
+    execution::{OutputStats, Peek},
+    nodes::DynPipelineNode,
+    report::{execution_order, flatten_settings},
+    Pipeline,
+};
+
+/// Version of the links created by this version of the app.
+pub const VERSION: u32 = 1;
+
+const PREFIX: &str = "ivoct-diag";
+
+/// Everything a diagnostic link contains.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// Version of the app, that created the link.
+    pub app_version: String,
+    pub node: NodeInfo,
+    /// The node serialized like in pipeline files, without its connections.
+    pub settings: Value,
+    /// Nodes, the output of [Self::node] depends on, in execution order.
+    pub upstream: Vec<NodeInfo>,
+    pub outputs: Vec<OutputSummary>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeInfo {
+    pub id: usize,
+    /// Type of the node, like in pipeline files.
+    pub slug: String,
+    pub name: String,
+    /// See [Pipeline::settings_hash].
+    pub settings_hash: u64,
+}
+
+/// What an output of the node sent since its last invalidation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputSummary {
+    pub output_id: usize,
+    pub has_response: bool,
+    pub chunks: usize,
+    pub bytes: u64,
+    /// Type of the last M scan chunk.
+    pub data_type: Option<String>,
+    /// A scans and samples of the last M scan chunk.
+    pub last_chunk: Option<[usize; 2]>,
+}
+
+impl OutputSummary {
+    /// Summary of the `stats` of an output, with the last chunk from `peek`.
+    pub fn new(stats: &OutputStats, peek: Option<&Peek>) -> Self {
+        Self {
+            output_id: stats.output_id.into(),
+            has_response: stats.has_response,
+            chunks: stats.transfer.chunks,
+            bytes: stats.transfer.bytes,
+            data_type: stats
+                .transfer
+                .data_type
+                .map(|data_type| format!("{:?}", data_type)),
+            last_chunk: match peek {
+                Some(Peek::Image { chunk, .. }) => Some(*chunk),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// A node of the current pipeline, that matches a [Diagnostic].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pub node_id: NodeId,
+    pub kind: MatchKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// A node of the same type as [Diagnostic::node], so its settings can be
+    /// applied with [Diagnostic::apply].
+    Node { same_settings: bool },
+    /// A node with the type and settings of an upstream node.
+    Upstream,
+}
+
+impl Diagnostic {
+    /// Collects the diagnostic of `node_id`. `outputs` are the summaries of
+    /// its outputs, see [OutputSummary::new].
+    pub fn new(pipeline: &Pipeline, node_id: NodeId, outputs: Vec<OutputSummary>) -> Option<Self> {
+        let node = pipeline.nodes.get(&node_id)?;
+
+        let mut settings = serde_json::to_value(node).ok()?;
+        strip_connections(&mut settings);
+
+        let upstream = pipeline.upstream(node_id);
+        let upstream = execution_order(pipeline)
+            .into_iter()
+            .filter(|id| *id != node_id && upstream.contains(id))
+            .filter_map(|id| node_info(pipeline, id))
+            .collect();
+
+        Some(Self {
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            node: node_info(pipeline, node_id)?,
+            settings,
+            upstream,
+            outputs,
+        })
+    }
+
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        format!("{PREFIX}:{VERSION}:{}", base64_encode(&json))
+    }
+
+    /// Decodes a link created by [Self::encode]. Whitespace, like line breaks
+    /// added by mail programs, is ignored.
+    pub fn decode(link: &str) -> anyhow::Result<Self> {
+        let link = link
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>();
+
+        let mut parts = link.splitn(3, ':');
+        let (Some(PREFIX), Some(version), Some(data)) = (parts.next(), parts.next(), parts.next())
+        else {
+            bail!("Not a diagnostic link, it should start with \"{PREFIX}:\"");
+        };
+
+        match version.parse::<u32>() {
+            Ok(VERSION) => {}
+            Ok(version) if version > VERSION => {
+                bail!("The link was created by a newer version of the app (link version {version})")
+            }
+            _ => bail!("Unknown link version {version:?}"),
+        }
+
+        let json = base64_decode(data).ok_or_else(|| anyhow!("The link is damaged"))?;
+        serde_json::from_slice(&json).map_err(|e| anyhow!("The link is damaged: {e}"))
+    }
+
+    /// Human readable description of the diagnostic.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+
+        let _ = writeln!(report, "Node: {}", node_label(&self.node));
+        let _ = writeln!(report, "App version: {}", self.app_version);
+
+        let _ = writeln!(report, "\nSettings:");
+        for (key, value) in flatten_settings(&self.settings) {
+            let _ = writeln!(report, "  {key}: {value}");
+        }
+
+        let _ = writeln!(report, "\nUpstream:");
+        if self.upstream.is_empty() {
+            let _ = writeln!(report, "  None");
+        }
+        for node in &self.upstream {
+            let _ = writeln!(report, "  {}", node_label(node));
+        }
+
+        let _ = writeln!(report, "\nOutputs:");
+        if self.outputs.is_empty() {
+            let _ = writeln!(report, "  None");
+        }
+        for output in &self.outputs {
+            let _ = write!(report, "  {}: ", output.output_id);
+            if !output.has_response {
+                let _ = writeln!(report, "not computed");
+                continue;
+            }
+            let _ = write!(report, "{} chunks, {} bytes", output.chunks, output.bytes);
+            if let Some(data_type) = &output.data_type {
+                let _ = write!(report, ", {data_type}");
+            }
+            if let Some([a_scans, samples]) = output.last_chunk {
+                let _ = write!(report, ", last chunk {a_scans} A scans × {samples} samples");
+            }
+            report.push('\n');
+        }
+
+        report
+    }
+
+    /// Nodes of `pipeline` of the type of [Self::node], then the ones with
+    /// the type and settings of an upstream node.
+    pub fn matches(&self, pipeline: &Pipeline) -> Vec<Match> {
+        let mut matches = Vec::new();
+
+        for node_id in execution_order(pipeline) {
+            let Some(info) = node_info(pipeline, node_id) else {
+                continue;
+            };
+
+            if info.slug == self.node.slug {
+                matches.push(Match {
+                    node_id,
+                    kind: MatchKind::Node {
+                        same_settings: info.settings_hash == self.node.settings_hash,
+                    },
+                });
+            } else if self
+                .upstream
+                .iter()
+                .any(|u| u.slug == info.slug && u.settings_hash == info.settings_hash)
+            {
+                matches.push(Match {
+                    node_id,
+                    kind: MatchKind::Upstream,
+                });
+            }
+        }
+
+        matches.sort_by_key(|m| matches!(m.kind, MatchKind::Upstream));
+        matches
+    }
+
+    /// Replaces the settings of `node_id` with the ones of the diagnostic,
+    /// keeping its connections.
+    pub fn apply(&self, pipeline: &mut Pipeline, node_id: NodeId) -> anyhow::Result<()> {
+        let node = pipeline
+            .nodes
+            .get(&node_id)
+            .ok_or_else(|| anyhow!("The node does not exist anymore"))?;
+
+        let mut value = serde_json::to_value(node)?;
+        if value.get("type") != self.settings.get("type") {
+            bail!("The node is not a {}", self.node.name);
+        }
+
+        let (Some(object), Some(settings)) = (value.as_object_mut(), self.settings.as_object())
+        else {
+            bail!("The node has no settings");
+        };
+        for (key, setting) in settings {
+            match (is_input(setting), object.get_mut(key)) {
+                (true, Some(input)) if is_input(input) => input["value"] = setting["value"].clone(),
+                (true, _) => {}
+                (false, _) => {
+                    object.insert(key.clone(), setting.clone());
+                }
+            }
+        }
+
+        let node: Box<dyn DynPipelineNode> = serde_json::from_value(value)
+            .map_err(|e| anyhow!("The settings do not fit this version of the node: {e}"))?;
+        pipeline.nodes.insert(node_id, node);
+
+        Ok(())
+    }
+}
+
+fn node_info(pipeline: &Pipeline, node_id: NodeId) -> Option<NodeInfo> {
+    let node = pipeline.nodes.get(&node_id)?;
+    let value = serde_json::to_value(node).ok()?;
+    Some(NodeInfo {
+        id: node_id.into(),
+        slug: value["type"].as_str()?.to_string(),
+        name: node.name().to_string(),
+        settings_hash: pipeline.settings_hash(node_id)?,
+    })
+}
+
+fn node_label(node: &NodeInfo) -> String {
+    format!(
+        "{} (#{}, {}), settings {:016x}",
+        node.name, node.id, node.slug, node.settings_hash
+    )
+}
+
+/// Inputs are serialized as objects with a `value` and a `connection`.
+fn is_input(value: &Value) -> bool {
+    value.as_object().is_some_and(|object| {
+        object.len() == 2 && object.contains_key("value") && object.contains_key("connection")
+    })
+}
+
+/// Removes the connections of the inputs of a serialized node, which only
+/// make sense in its pipeline.
+fn strip_connections(value: &mut Value) {
+    let Some(object) = value.as_object_mut() else {
+        return;
+    };
+    for value in object.values_mut() {
+        if is_input(value) {
+            value["connection"] = Value::Null;
+        } else {
+            strip_connections(value);
+        }
+    }
+}
+
+// MARK: Base64
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// URL safe Base64 without padding.
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (i, b)| group | (*b as u32) << (16 - 8 * i));
+
+        for i in 0..=chunk.len() {
+            out.push(BASE64[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+
+    out
+}
+
+/// Inverse of [base64_encode]. [None] for invalid characters or lengths.
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let values = text
+        .bytes()
+        .map(|c| BASE64.iter().position(|b| *b == c).map(|v| v as u32))
+        .collect::<Option<Vec<_>>>()?;
+
+    let mut out = Vec::with_capacity(values.len() / 4 * 3 + 2);
+    for chunk in values.chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (i, v)| group | v << (18 - 6 * i));
+
+        for i in 0..chunk.len() - 1 {
+            out.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::pipeline::nodes::filter::{self, FilterType};
+
+    use super::*;
+
+    fn pipeline(prewitt_threshold: f32) -> Pipeline {
+        serde_json::from_value(json!({
+            "nodes": {
+                "1": {
+                    "type": "filter",
+                    "filter_type": "Gaussian",
+                    "input": { "value": null, "connection": null },
+                    "b_scans": { "value": null, "connection": null },
+                },
+                "2": {
+                    "type": "filter",
+                    "filter_type": "Prewitt",
+                    "prewitt_settings": { "threshold": prewitt_threshold },
+                    "input": {
+                        "value": null,
+                        "connection": { "node_id": 1, "output_id": 0, "type_id": 2 },
+                    },
+                    "b_scans": { "value": null, "connection": null },
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    fn diagnostic() -> Diagnostic {
+        let outputs = vec![OutputSummary {
+            output_id: 0,
+            has_response: true,
+            chunks: 3,
+            bytes: 1024,
+            data_type: Some("U16".to_string()),
+            last_chunk: Some([100, 512]),
+        }];
+        Diagnostic::new(&pipeline(0.25), 2.into(), outputs).unwrap()
+    }
+
+    #[test]
+    fn base64() {
+        for len in 0..8 {
+            let bytes = (0..len).map(|i| (i * 97 + 13) as u8).collect::<Vec<_>>();
+            let encoded = base64_encode(&bytes);
+            assert!(encoded
+                .bytes()
+                .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_'));
+            assert_eq!(base64_decode(&encoded).unwrap(), bytes);
+        }
+        assert_eq!(base64_encode(b"Man"), "TWFu");
+        assert_eq!(base64_encode(b"Ma"), "TWE");
+        assert_eq!(base64_decode("TWE=").as_deref(), None);
+        assert_eq!(base64_decode("TWFuT").as_deref(), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let diagnostic = diagnostic();
+        assert_eq!(diagnostic.node.slug, "filter");
+        assert_eq!(diagnostic.upstream.len(), 1);
+        assert_eq!(diagnostic.upstream[0].id, 1);
+        // Connections only make sense in the original pipeline
+        assert_eq!(diagnostic.settings["input"]["connection"], Value::Null);
+
+        let link = diagnostic.encode();
+        assert!(link.starts_with("ivoct-diag:1:"));
+        assert!(link.len() < 4096, "{} characters", link.len());
+
+        // Mail programs break long lines
+        let wrapped = link
+            .as_bytes()
+            .chunks(76)
+            .map(|line| std::str::from_utf8(line).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(Diagnostic::decode(&wrapped).unwrap(), diagnostic);
+
+        let report = diagnostic.report();
+        assert!(report.contains("Prewitt"), "{report}");
+        assert!(report.contains("last chunk 100 A scans × 512 samples"));
+    }
+
+    #[test]
+    fn rejects_other_links() {
+        let link = diagnostic().encode();
+
+        assert!(Diagnostic::decode("https://example.com").is_err());
+        let newer = link.replacen(":1:", ":2:", 1);
+        assert!(Diagnostic::decode(&newer)
+            .unwrap_err()
+            .to_string()
+            .contains("newer version"));
+        assert!(Diagnostic::decode(&link[..link.len() - 10]).is_err());
+    }
+
+    #[test]
+    fn matches_and_applies() {
+        let diagnostic = diagnostic();
+
+        let mut pipeline = pipeline(0.5);
+        let matches = diagnostic.matches(&pipeline);
+        assert_eq!(
+            matches,
+            [
+                Match {
+                    node_id: 1.into(),
+                    kind: MatchKind::Node {
+                        same_settings: false
+                    },
+                },
+                Match {
+                    node_id: 2.into(),
+                    kind: MatchKind::Node {
+                        same_settings: false
+                    },
+                },
+            ]
+        );
+
+        diagnostic.apply(&mut pipeline, 2.into()).unwrap();
+        let node = pipeline[2.into()]
+            .as_any()
+            .downcast_ref::<filter::Node>()
+            .unwrap();
+        assert_eq!(node.filter_type, FilterType::Prewitt);
+        assert_eq!(node.prewitt_settings.threshold, 0.25);
+        // The connection is kept
+        assert_eq!(pipeline.upstream(2.into()).len(), 2);
+        assert!(diagnostic.matches(&pipeline).contains(&Match {
+            node_id: 2.into(),
+            kind: MatchKind::Node {
+                same_settings: true
+            },
+        }));
+    }
+}
@@ -1,20 +1,75 @@
-use std::{any::Any, sync::Arc};
+use std::{
+    any::{Any, TypeId},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use futures::future::BoxFuture;
 use tokio::sync::{mpsc, watch};
 
+use super::{Peek, PeekBuffer, TransferSnapshot, TransferStats};
+
 /// An input to a node task. Can be connected to one [TaskOutput] with same
 /// request type `Req`.
 #[derive(Debug)]
 pub enum TaskInput<Req: Request> {
     Disconnected(Option<Req::Response>),
     Connected {
-        request_tx: mpsc::Sender<Req>,
-        response_rx: watch::Receiver<Option<Req::Response>>,
+        /// Resolves the channels of the task currently producing the output.
+        slot: watch::Receiver<Channels<Req>>,
         default_value: Option<Req::Response>,
     },
 }
 
+/// Channel ends used to send requests to a [TaskOutput] and receive its
+/// responses, plus state shared with it.
+pub struct Channels<Req: Request> {
+    request_tx: mpsc::Sender<Req>,
+    response_rx: watch::Receiver<Option<Req::Response>>,
+    /// Number of [TaskInput]s connected to the output.
+    consumers: Arc<AtomicUsize>,
+    transfer: Arc<TransferStats>,
+    peek: Arc<PeekBuffer>,
+    /// Current invalidation epoch of the output, see [TaskInput::epoch].
+    epoch: Arc<AtomicU64>,
+    /// The node producing the output is disabled. Requests resolve to [None]
+    /// immediately, instead of waiting for a task.
+    disabled: bool,
+    /// Whether the output serves the same data again, see
+    /// [TaskInput::replay]. Kept up to date by the executor.
+    replayable: Arc<AtomicBool>,
+}
+
+impl<Req: Request> Clone for Channels<Req> {
+    fn clone(&self) -> Self {
+        Self {
+            request_tx: self.request_tx.clone(),
+            response_rx: self.response_rx.clone(),
+            consumers: self.consumers.clone(),
+            transfer: self.transfer.clone(),
+            peek: self.peek.clone(),
+            epoch: self.epoch.clone(),
+            disabled: self.disabled,
+            replayable: self.replayable.clone(),
+        }
+    }
+}
+
+impl<Req: Request> std::fmt::Debug for Channels<Req> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Channels")
+            .field("consumers", &self.consumers)
+            .field("transfer", &self.transfer)
+            .field("epoch", &self.epoch)
+            .field("disabled", &self.disabled)
+            .field("replayable", &self.replayable)
+            .finish_non_exhaustive()
+    }
+}
+
 /// An output of a node task. Can be connected to multiple [TaskInput]s with
 /// same request type `Req`.
 #[derive(Debug)]
@@ -22,6 +77,17 @@ pub struct TaskOutput<Req: Request> {
     working_on: Option<Req>,
     request_rx: mpsc::Receiver<Req>,
     response_tx: watch::Sender<Option<Req::Response>>,
+    consumers: Arc<AtomicUsize>,
+    transfer: Arc<TransferStats>,
+    peek: Arc<PeekBuffer>,
+    epoch: Arc<AtomicU64>,
+}
+
+/// Returns a new invalidation epoch. Epochs are unique across all outputs, so
+/// a recreated output never repeats the epoch of the one it replaces.
+fn next_epoch() -> u64 {
+    static NEXT_EPOCH: AtomicU64 = AtomicU64::new(1);
+    NEXT_EPOCH.fetch_add(1, Ordering::Relaxed)
 }
 
 impl<Req: Request> TaskInput<Req> {
@@ -30,39 +96,57 @@ impl<Req: Request> TaskInput<Req> {
     /// If disconnected, return the default value, or [None] if not valid.
     ///
     /// If there is a valid response already available, return that.
+    ///
+    /// If the producing node is disabled, return [None] immediately, but stay
+    /// connected.
     pub async fn request(&mut self, req: Req) -> Option<Req::Response> {
         match self {
             TaskInput::Disconnected(r) => match r {
                 Some(r) if req.is_response_valid(r) => Some(r.clone()),
                 _ => None,
             },
-            TaskInput::Connected {
-                request_tx,
-                response_rx: data_rx,
-                ..
-            } => {
+            TaskInput::Connected { slot, .. } => {
+                // Resolve the current producer. It might have been replaced
+                // since the last request. Available responses, like vectors
+                // requested by every chunk, are served from the borrowed
+                // channels, without cloning them
+                let (request_tx, mut data_rx) = {
+                    let channels = slot.borrow_and_update();
+
+                    if channels.disabled {
+                        return None;
+                    }
+
+                    if let Some(res) = channels.response_rx.borrow().as_ref() {
+                        if req.is_response_valid(res) {
+                            // If available response is valid, return it
+                            return Some(res.clone());
+                        }
+                    }
+
+                    (channels.request_tx.clone(), channels.response_rx.clone())
+                };
+
+                // A response might have arrived in between
                 if let Some(res) = data_rx.borrow_and_update().as_ref() {
                     if req.is_response_valid(res) {
-                        // If available response is valid, return it
                         return Some(res.clone());
                     }
                 }
 
                 if request_tx.send(req.clone()).await.is_err() {
-                    // Partner dropped
-                    self.disconnect();
+                    self.on_partner_dropped();
                     return None;
                 }
 
                 loop {
                     if data_rx.changed().await.is_err() {
-                        // Partner dropped
-                        self.disconnect();
+                        self.on_partner_dropped();
                         break None;
                     }
 
                     if let Some(res) = data_rx.borrow().as_ref() {
-                        if req.is_response_valid(res) {
+                        if req.is_response_valid(res) || req.answers_pending(res) {
                             break Some(res.clone());
                         }
                     } else {
@@ -74,6 +158,66 @@ impl<Req: Request> TaskInput<Req> {
         }
     }
 
+    /// Requests the data received in `epoch` again, for a second pass over
+    /// it, see [crate::pipeline::two_pass]. Serves the previous response
+    /// again, if it still holds everything, and makes the producer send it
+    /// again otherwise. [None], if the output cannot replay, see
+    /// [Self::can_replay], or got invalidated since, so the data would
+    /// differ.
+    pub async fn replay(&mut self, req: Req, epoch: u64) -> Option<Req::Response> {
+        if !self.can_replay() || self.epoch() != Some(epoch) {
+            return None;
+        }
+
+        let response = self.request(req).await?;
+        (self.epoch() == Some(epoch)).then_some(response)
+    }
+
+    /// Whether the connected output serves the same data again, when
+    /// requested a second time in the same epoch, see [super::Replay].
+    pub fn can_replay(&self) -> bool {
+        match self {
+            TaskInput::Connected { slot, .. } => {
+                let channels = slot.borrow();
+                !channels.disabled && channels.replayable.load(Ordering::Relaxed)
+            }
+            TaskInput::Disconnected(_) => false,
+        }
+    }
+
+    /// The valid response, that is already available, like [Self::request]
+    /// would return it. Unlike a request, this never makes the producing task
+    /// do any work.
+    pub fn available(&mut self, req: &Req) -> Option<Req::Response> {
+        match self {
+            TaskInput::Disconnected(r) => r.clone().filter(|r| req.is_response_valid(r)),
+            TaskInput::Connected { slot, .. } => {
+                let channels = slot.borrow_and_update().clone();
+                if channels.disabled {
+                    return None;
+                }
+
+                let response = channels.response_rx.borrow().clone();
+                response.filter(|res| req.is_response_valid(res))
+            }
+        }
+    }
+
+    /// The task producing the output stopped. If it got replaced by a new
+    /// task, the request can be retried and will be served by the new task.
+    /// Otherwise, the output does not exist anymore and this input is
+    /// disconnected.
+    fn on_partner_dropped(&mut self) {
+        let replaced = match self {
+            TaskInput::Connected { slot, .. } => matches!(slot.has_changed(), Ok(true)),
+            TaskInput::Disconnected(_) => false,
+        };
+
+        if !replaced {
+            self.disconnect();
+        }
+    }
+
     /// Disconnect this input.
     ///
     /// Input will transition into [TaskInput::Disconnected] state, when not
@@ -93,13 +237,15 @@ impl<Req: Request> TaskInput<Req> {
             TaskInput::Connected { default_value, .. } => default_value.take(),
         };
 
-        let Some((request_tx, response_rx)) = connection.connection.get_channels() else {
+        let Some(slot) = connection.connection.get_slot() else {
+            connection.rejected_by = Some(TypeId::of::<Req>());
             return false;
         };
 
+        slot.borrow().consumers.fetch_add(1, Ordering::Relaxed);
+
         *self = TaskInput::Connected {
-            request_tx,
-            response_rx,
+            slot,
             default_value,
         };
 
@@ -110,6 +256,31 @@ impl<Req: Request> TaskInput<Req> {
     pub fn is_connected(&self) -> bool {
         matches!(self, TaskInput::Connected { .. })
     }
+
+    /// The invalidation epoch of the connected output. It changes every time
+    /// the output gets invalidated or its producer gets replaced, so two
+    /// responses received in the same epoch hold the same data. [None], if
+    /// disconnected.
+    pub fn epoch(&self) -> Option<u64> {
+        match self {
+            TaskInput::Connected { slot, .. } => Some(slot.borrow().epoch.load(Ordering::Relaxed)),
+            TaskInput::Disconnected(_) => None,
+        }
+    }
+}
+
+impl<Req: Request> Drop for TaskInput<Req> {
+    fn drop(&mut self) {
+        if let TaskInput::Connected { slot, .. } = self {
+            // The count moves along on redirects, so release it at the
+            // current producer
+            let _ = slot.borrow().consumers.fetch_update(
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+                |count| count.checked_sub(1),
+            );
+        }
+    }
 }
 
 impl<Req: Request> TaskOutput<Req> {
@@ -137,6 +308,7 @@ impl<Req: Request> TaskOutput<Req> {
             };
 
             self.working_on = Some(req.clone());
+            self.transfer.start();
 
             req
         }
@@ -156,6 +328,17 @@ impl<Req: Request> TaskOutput<Req> {
             return;
         }
 
+        self.publish(response);
+    }
+
+    /// Provide a response, regardless of whether it got requested. Later
+    /// requests, for which it is valid, are served with it directly.
+    ///
+    /// Answers the request being worked on, if there is one.
+    pub fn publish(&mut self, response: Req::Response) {
+        Req::track_transfer(&response, &self.transfer);
+        Req::capture(&response, &self.peek);
+
         self.response_tx
             .send(Some(response))
             .expect("Should never close");
@@ -163,8 +346,18 @@ impl<Req: Request> TaskOutput<Req> {
         self.working_on = None;
     }
 
-    /// Invalidate the current response, if not already.
+    /// Whether any [TaskInput] is connected to this output. Connected inputs
+    /// may request data at any time, even if they did not do so yet.
+    pub fn has_consumers(&self) -> bool {
+        self.consumers.load(Ordering::Relaxed) > 0
+    }
+
+    /// Invalidate the current response, if not already. Always starts a new
+    /// epoch.
     pub fn invalidate(&mut self) {
+        self.transfer.reset();
+        self.peek.clear();
+        self.epoch.store(next_epoch(), Ordering::Relaxed);
         self.response_tx.send_if_modified(|v| match v {
             Some(_) => {
                 *v = None;
@@ -176,7 +369,13 @@ impl<Req: Request> TaskOutput<Req> {
 
     pub(super) fn get_invalidator(&self) -> Invalidator {
         let response_tx = self.response_tx.clone();
+        let transfer = self.transfer.clone();
+        let peek = self.peek.clone();
+        let epoch = self.epoch.clone();
         Invalidator(Box::new(move || {
+            transfer.reset();
+            peek.clear();
+            epoch.store(next_epoch(), Ordering::Relaxed);
             response_tx.send_if_modified(|v| match v {
                 Some(_) => {
                     *v = None;
@@ -206,15 +405,41 @@ pub trait Request: Send + Sync + Clone + 'static {
     fn is_response_valid(&self, _response: &Self::Response) -> bool {
         true
     }
+
+    /// Whether `response` still answers a request, that was sent before it
+    /// arrived, even though it is not valid for later ones. For example, a
+    /// stream that ended early gives the requester the partial data, while
+    /// later requests make the node task produce it again. `false` by
+    /// default.
+    fn answers_pending(&self, _response: &Self::Response) -> bool {
+        false
+    }
+
+    /// Called with every response of a [TaskOutput]. Count the data sent
+    /// through the response into `stats`, for example by observing its
+    /// stream.
+    fn track_transfer(_response: &Self::Response, _stats: &Arc<TransferStats>) {}
+
+    /// Called with every response of a [TaskOutput], like
+    /// [Self::track_transfer]. Keep the last data sent through the response
+    /// in `peek`, if [PeekBuffer::is_enabled] for this type.
+    fn capture(_response: &Self::Response, _peek: &Arc<PeekBuffer>) {}
 }
 
 /// Handle to an output connection, hiding its concrete request type. Can be
 /// used to create a connection to a [TaskInput] from the referred [TaskOutput].
 /// This only works if both have the same request type.
+///
+/// The handle does not refer to the [TaskOutput] directly, but to a slot, that
+/// holds the channels of the current one. When the producing task is
+/// recreated, [Self::redirect] updates the slot and all connected inputs
+/// transparently continue with the new task.
 #[derive(Clone)]
 pub struct ConnectionHandle {
     connection: Arc<dyn _DynConnectionHandle>,
     did_connect: bool,
+    /// Request type of the last input, that refused to connect.
+    rejected_by: Option<TypeId>,
 }
 
 impl ConnectionHandle {
@@ -222,20 +447,38 @@ impl ConnectionHandle {
         let (request_tx, request_rx) = mpsc::channel(3);
         let (response_tx, response_rx) = watch::channel(None);
 
-        let connection = Arc::new(_SharedConnectionHandle {
+        let consumers = Arc::new(AtomicUsize::new(0));
+        let transfer = Arc::new(TransferStats::default());
+        let peek = Arc::new(PeekBuffer::default());
+        let epoch = Arc::new(AtomicU64::new(next_epoch()));
+
+        let (slot, _) = watch::channel(Channels {
             request_tx,
             response_rx,
+            consumers: consumers.clone(),
+            transfer: transfer.clone(),
+            peek: peek.clone(),
+            epoch: epoch.clone(),
+            disabled: false,
+            replayable: Arc::new(AtomicBool::new(false)),
         });
 
+        let connection = Arc::new(_SharedConnectionHandle { slot });
+
         (
             Self {
                 connection: connection.clone(),
                 did_connect: false,
+                rejected_by: None,
             },
             TaskOutput {
                 working_on: None,
                 request_rx,
                 response_tx,
+                consumers,
+                transfer,
+                peek,
+                epoch,
             },
         )
     }
@@ -244,54 +487,193 @@ impl ConnectionHandle {
         self.connection.get_invalidation_notifier()
     }
 
+    /// Data sent through the output since its last invalidation. Every
+    /// connected input receives all of it.
+    pub fn transfer(&self) -> TransferSnapshot {
+        self.connection.transfer()
+    }
+
+    /// Time from the first request to the last chunk sent since the last
+    /// invalidation of the output. [None], if nothing was sent yet.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.connection.elapsed()
+    }
+
+    /// The last data sent through the output since its last invalidation,
+    /// see [PeekBuffer]. Never makes the producing task do any work.
+    pub fn peek(&self) -> Option<Arc<Peek>> {
+        self.connection.peek()
+    }
+
+    /// Whether the output holds a response, which may still be streaming.
+    /// Requesting the output then does not start any processing.
+    pub fn has_response(&self) -> bool {
+        self.connection.has_response()
+    }
+
+    /// Redirect everything connected to this handle to the output behind
+    /// `other`. Returns false, if the request types do not match.
+    pub fn redirect(&self, other: &ConnectionHandle) -> bool {
+        self.connection.redirect(other.connection.as_ref())
+    }
+
+    /// Detach the output from its task, because its node got disabled.
+    /// Connected inputs stay connected, but their requests resolve to [None]
+    /// and they get invalidated. Use [Self::redirect] to enable it again.
+    pub fn disable(&self) {
+        self.connection.disable()
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.connection.is_disabled()
+    }
+
+    /// Declares, whether the output serves the same data again, see
+    /// [super::Replay]. Connected inputs read it with
+    /// [TaskInput::can_replay].
+    pub fn set_replayable(&self, replayable: bool) {
+        self.connection.set_replayable(replayable)
+    }
+
     pub fn reset_connection(&mut self) {
         self.did_connect = false;
+        self.rejected_by = None;
     }
 
     pub fn did_connect(&self) -> bool {
         self.did_connect
     }
+
+    /// Request type of the output, see [crate::pipeline::requests::RequestInfo].
+    pub fn request_type(&self) -> TypeId {
+        self.connection.request_type()
+    }
+
+    /// Request type of the last input, that refused to connect, because it
+    /// expects another request type than [Self::request_type].
+    pub fn rejected_by(&self) -> Option<TypeId> {
+        self.rejected_by
+    }
 }
 
 trait _DynConnectionHandle: Send + Sync + 'static {
-    fn get_channels_any(&self) -> (&dyn Any, &dyn Any);
+    fn as_any(&self) -> &dyn Any;
+
+    fn request_type(&self) -> TypeId;
 
     fn get_invalidation_notifier(&self) -> InvalidationNotifier;
+
+    fn transfer(&self) -> TransferSnapshot;
+
+    fn elapsed(&self) -> Option<Duration>;
+
+    fn peek(&self) -> Option<Arc<Peek>>;
+
+    fn has_response(&self) -> bool;
+
+    fn redirect(&self, other: &dyn _DynConnectionHandle) -> bool;
+
+    fn disable(&self);
+
+    fn is_disabled(&self) -> bool;
+
+    fn set_replayable(&self, replayable: bool);
 }
 
 struct _SharedConnectionHandle<Req: Request> {
-    request_tx: mpsc::Sender<Req>,
-    response_rx: watch::Receiver<Option<Req::Response>>,
+    slot: watch::Sender<Channels<Req>>,
 }
 
 impl<Req: Request> _DynConnectionHandle for _SharedConnectionHandle<Req> {
-    fn get_channels_any(&self) -> (&dyn Any, &dyn Any) {
-        (&self.request_tx, &self.response_rx)
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn request_type(&self) -> TypeId {
+        TypeId::of::<Req>()
     }
 
     fn get_invalidation_notifier(&self) -> InvalidationNotifier {
-        InvalidationNotifier(Box::new(InvalidationNotifierImpl {
-            channel_rx: self.response_rx.clone(),
+        let slot = self.slot.subscribe();
+        let channel_rx = slot.borrow().response_rx.clone();
+
+        InvalidationNotifier(Box::new(InvalidationNotifierImpl::<Req> {
+            slot,
+            channel_rx,
         }))
     }
+
+    fn transfer(&self) -> TransferSnapshot {
+        self.slot.borrow().transfer.snapshot()
+    }
+
+    fn elapsed(&self) -> Option<Duration> {
+        self.slot.borrow().transfer.elapsed()
+    }
+
+    fn peek(&self) -> Option<Arc<Peek>> {
+        self.slot.borrow().peek.get()
+    }
+
+    fn has_response(&self) -> bool {
+        self.slot.borrow().response_rx.borrow().is_some()
+    }
+
+    fn redirect(&self, other: &dyn _DynConnectionHandle) -> bool {
+        let Some(other) = other.as_any().downcast_ref::<Self>() else {
+            return false;
+        };
+
+        let Channels { consumers, .. } = self.slot.send_replace(other.slot.borrow().clone());
+
+        // The inputs connected to this handle now belong to the other output
+        other
+            .slot
+            .borrow()
+            .consumers
+            .fetch_add(consumers.swap(0, Ordering::Relaxed), Ordering::Relaxed);
+        true
+    }
+
+    fn disable(&self) {
+        // Both channels are closed, nothing serves them
+        let (request_tx, _) = mpsc::channel(1);
+        let (_, response_rx) = watch::channel(None);
+
+        self.slot.send_modify(|channels| {
+            channels.request_tx = request_tx;
+            channels.response_rx = response_rx;
+            channels.transfer = Arc::new(TransferStats::default());
+            channels.peek = Arc::new(PeekBuffer::default());
+            channels.epoch = Arc::new(AtomicU64::new(next_epoch()));
+            channels.disabled = true;
+            channels.replayable = Arc::new(AtomicBool::new(false));
+        });
+    }
+
+    fn is_disabled(&self) -> bool {
+        self.slot.borrow().disabled
+    }
+
+    fn set_replayable(&self, replayable: bool) {
+        self.slot
+            .borrow()
+            .replayable
+            .store(replayable, Ordering::Relaxed);
+    }
 }
 
 trait _DynConnectionHandleExt: _DynConnectionHandle {
-    fn get_channels<Req: Request>(
-        &self,
-    ) -> Option<(mpsc::Sender<Req>, watch::Receiver<Option<Req::Response>>)>;
+    fn get_slot<Req: Request>(&self) -> Option<watch::Receiver<Channels<Req>>>;
 }
 
 impl<T: ?Sized + _DynConnectionHandle> _DynConnectionHandleExt for T {
-    fn get_channels<Req: Request>(
-        &self,
-    ) -> Option<(mpsc::Sender<Req>, watch::Receiver<Option<Req::Response>>)> {
-        let (request_tx, response_rx) = self.get_channels_any();
+    fn get_slot<Req: Request>(&self) -> Option<watch::Receiver<Channels<Req>>> {
+        let connection = self
+            .as_any()
+            .downcast_ref::<_SharedConnectionHandle<Req>>()?;
 
-        let request_tx = request_tx.downcast_ref::<mpsc::Sender<Req>>()?;
-        let response_rx = response_rx.downcast_ref::<watch::Receiver<Option<Req::Response>>>()?;
-
-        Some((request_tx.clone(), response_rx.clone()))
+        Some(connection.slot.subscribe())
     }
 }
 
@@ -307,22 +689,43 @@ trait DynInvalidationNotifier: Send + Sync {
     fn on_invalidate(&mut self) -> BoxFuture<'_, bool>;
 }
 
-struct InvalidationNotifierImpl<T: Send + Sync> {
-    channel_rx: watch::Receiver<Option<T>>,
+struct InvalidationNotifierImpl<Req: Request> {
+    slot: watch::Receiver<Channels<Req>>,
+    channel_rx: watch::Receiver<Option<Req::Response>>,
 }
 
-impl<T: Send + Sync> DynInvalidationNotifier for InvalidationNotifierImpl<T> {
+impl<Req: Request> DynInvalidationNotifier for InvalidationNotifierImpl<Req> {
     fn on_invalidate(&mut self) -> BoxFuture<'_, bool> {
-        // Box::pin(async move { self.channel_rx.wait_for(Option::is_none).await.is_ok() })
         Box::pin(async move {
             loop {
-                if self.channel_rx.changed().await.is_err() {
-                    return false;
+                tokio::select! {
+                    changed = self.channel_rx.changed() => {
+                        if changed.is_err() {
+                            // Partner dropped. If it got replaced, the new
+                            // task has none of the old data. A disabled
+                            // output waits until it is enabled again
+                            if !matches!(self.slot.has_changed(), Ok(true))
+                                && (!self.slot.borrow().disabled
+                                    || self.slot.changed().await.is_err())
+                            {
+                                return false;
+                            }
+                        } else if self.channel_rx.borrow().is_none() {
+                            return true;
+                        } else {
+                            continue;
+                        }
+                    }
+                    changed = self.slot.changed() => {
+                        if changed.is_err() {
+                            return false;
+                        }
+                    }
                 }
 
-                if self.channel_rx.borrow().is_none() {
-                    return true;
-                }
+                // The producing task got replaced
+                self.channel_rx = self.slot.borrow_and_update().response_rx.clone();
+                return true;
             }
         })
     }
@@ -335,3 +738,354 @@ impl Invalidator {
         self.0()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    struct Generation;
+
+    impl Request for Generation {
+        type Response = u32;
+    }
+
+    /// Serves every request with `response`, until the output is dropped.
+    fn spawn_producer(
+        mut output: TaskOutput<Generation>,
+        response: u32,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                output.receive().await;
+                output.respond(response);
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn redirect_keeps_inputs_connected() {
+        let (mut handle, output) = ConnectionHandle::new::<Generation>();
+        let producer = spawn_producer(output, 1);
+
+        let mut input = TaskInput::<Generation>::default();
+        assert!(input.connect(&mut handle));
+        assert_eq!(input.request(Generation).await, Some(1));
+
+        // Restart the producer, like the executor does
+        let (new_handle, new_output) = ConnectionHandle::new::<Generation>();
+        assert!(handle.redirect(&new_handle));
+        producer.abort();
+        let _ = producer.await;
+        let _producer = spawn_producer(new_output, 2);
+
+        assert_eq!(input.request(Generation).await, Some(2));
+        assert!(input.is_connected());
+    }
+
+    #[tokio::test]
+    async fn in_flight_request_is_retriable() {
+        let (mut handle, mut output) = ConnectionHandle::new::<Generation>();
+
+        let mut input = TaskInput::<Generation>::default();
+        assert!(input.connect(&mut handle));
+
+        let mut notifier = handle.get_invalidation_notifier();
+
+        let (new_handle, new_output) = ConnectionHandle::new::<Generation>();
+
+        // The old producer receives the request, but gets replaced before
+        // responding
+        let restart = async {
+            output.receive().await;
+            assert!(handle.redirect(&new_handle));
+            drop(output);
+        };
+
+        let (res, ()) = futures::join!(input.request(Generation), restart);
+        assert_eq!(res, None);
+        assert!(input.is_connected());
+        assert!(notifier.on_invalidate().await);
+
+        let _producer = spawn_producer(new_output, 2);
+        assert_eq!(input.request(Generation).await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn disabled_output_resolves_immediately() {
+        let (mut handle, output) = ConnectionHandle::new::<Generation>();
+        let producer = spawn_producer(output, 1);
+
+        let mut input = TaskInput::<Generation>::default();
+        assert!(input.connect(&mut handle));
+        assert_eq!(input.request(Generation).await, Some(1));
+
+        let mut notifier = handle.get_invalidation_notifier();
+
+        // Disable the node, like the executor does
+        handle.disable();
+        producer.abort();
+        let _ = producer.await;
+
+        assert!(notifier.on_invalidate().await);
+        assert_eq!(input.request(Generation).await, None);
+        assert!(input.is_connected());
+        assert!(handle.is_disabled());
+
+        // Enabling it again invalidates the input once more
+        let (new_handle, new_output) = ConnectionHandle::new::<Generation>();
+        let enable = async {
+            assert!(handle.redirect(&new_handle));
+        };
+        let (invalidated, ()) = futures::join!(notifier.on_invalidate(), enable);
+        assert!(invalidated);
+
+        let _producer = spawn_producer(new_output, 2);
+        assert_eq!(input.request(Generation).await, Some(2));
+        assert!(!handle.is_disabled());
+    }
+
+    #[tokio::test]
+    async fn available_sends_no_request() {
+        let (mut handle, mut output) = ConnectionHandle::new::<Generation>();
+
+        let mut input = TaskInput::<Generation>::default();
+        assert!(input.connect(&mut handle));
+        assert_eq!(input.available(&Generation), None);
+        assert!(output.request_rx.try_recv().is_err());
+
+        output.publish(3);
+        assert_eq!(input.available(&Generation), Some(3));
+
+        output.invalidate();
+        assert_eq!(input.available(&Generation), None);
+        assert!(output.request_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn aborted_stream_is_requested_again() {
+        use crate::{
+            pipeline::requests::{BScanSegmentation, BScanSegmentationResponse, StreamedResponse},
+            queue_channel::error::RecvError,
+        };
+
+        /// Receives the whole stream, or the error it ended with.
+        async fn receive(res: &BScanSegmentationResponse) -> (Vec<usize>, RecvError) {
+            let mut rx = res.data.subscribe().unwrap();
+            let mut received = Vec::new();
+            loop {
+                match rx.recv().await {
+                    Ok(boundary) => received.push(boundary),
+                    Err(e) => break (received, e),
+                }
+            }
+        }
+
+        let (mut handle, mut output) = ConnectionHandle::new::<BScanSegmentation>();
+
+        // Fails after 3 chunks on the first request, and recovers after
+        let _producer = tokio::spawn(async move {
+            for chunks in [3, 10] {
+                output.receive().await;
+                let (res, tx) = StreamedResponse::new(16);
+                output.respond(BScanSegmentationResponse {
+                    data: res,
+                    a_scan_count: 10,
+                });
+                for boundary in 0..chunks {
+                    tx.send(boundary);
+                }
+                if chunks == 10 {
+                    tx.finish();
+                }
+            }
+            std::future::pending::<()>().await
+        });
+
+        let mut input = TaskInput::<BScanSegmentation>::default();
+        assert!(input.connect(&mut handle));
+
+        let res = input.request(BScanSegmentation).await.unwrap();
+        assert_eq!(receive(&res).await, (vec![0, 1, 2], RecvError::Aborted));
+        assert!(res.data.is_aborted());
+
+        // Without any invalidation, the aborted response is not served again
+        let res = input.request(BScanSegmentation).await.unwrap();
+        assert_eq!(receive(&res).await, ((0..10).collect(), RecvError::Closed));
+        assert!(!res.data.is_aborted());
+    }
+
+    fn calibration_vector() -> Arc<crate::pipeline::types::DataVector> {
+        let data = nalgebra::DVector::from_fn(1 << 20, |i, _| i as f32);
+        Arc::new(crate::pipeline::types::DataVector::F32(data))
+    }
+
+    #[tokio::test]
+    async fn vector_responses_are_shared() {
+        use crate::pipeline::{requests::VectorData, types::F32Vector};
+
+        let (mut handle, mut output) = ConnectionHandle::new::<VectorData>();
+        let vector = calibration_vector();
+        output.publish(vector.clone());
+
+        let mut input = TaskInput::<VectorData>::default();
+        assert!(input.connect(&mut handle));
+        for _ in 0..3 {
+            let response = input.request(VectorData).await.unwrap();
+            assert!(Arc::ptr_eq(&response, &vector));
+
+            // Consumers needing f32 borrow the data as well
+            let view = F32Vector::new(response);
+            let crate::pipeline::types::DataVector::F32(data) = &*vector else {
+                unreachable!()
+            };
+            assert_eq!(view.as_view().as_ptr(), data.as_ptr());
+        }
+        assert!(output.request_rx.try_recv().is_err());
+    }
+
+    /// Compares serving a calibration vector, like the chirp, to every chunk
+    /// by copying it, as done before, and by sharing it. Run with
+    /// `cargo test vector_request_latency -- --ignored --nocapture`.
+    #[tokio::test]
+    #[ignore]
+    async fn vector_request_latency() {
+        use std::time::Instant;
+
+        use crate::pipeline::{requests::VectorData, types::F32Vector};
+
+        const REQUESTS: u32 = 100;
+
+        let (mut handle, mut output) = ConnectionHandle::new::<VectorData>();
+        output.publish(calibration_vector());
+        let mut input = TaskInput::<VectorData>::default();
+        assert!(input.connect(&mut handle));
+
+        let start = Instant::now();
+        for _ in 0..REQUESTS {
+            let response = input.request(VectorData).await.unwrap();
+            std::hint::black_box((*response).clone().cast::<f32>());
+        }
+        let copied = start.elapsed() / REQUESTS;
+
+        let start = Instant::now();
+        for _ in 0..REQUESTS {
+            let response = input.request(VectorData).await.unwrap();
+            std::hint::black_box(F32Vector::new(response));
+        }
+        let shared = start.elapsed() / REQUESTS;
+
+        println!("1M element vector per request: copied {copied:?}, shared {shared:?}");
+        assert!(shared < copied);
+    }
+
+    #[test]
+    fn transfer_and_peek_until_invalidation() {
+        use crate::pipeline::requests::{
+            BScanSegmentation, BScanSegmentationResponse, StreamedResponse,
+        };
+
+        let (handle, mut output) = ConnectionHandle::new::<BScanSegmentation>();
+
+        let (res, tx) = StreamedResponse::new(4);
+        tx.send(1);
+        output.publish(BScanSegmentationResponse {
+            data: res,
+            a_scan_count: 2,
+        });
+        tx.send(2);
+
+        let size = std::mem::size_of::<usize>() as u64;
+        assert_eq!(
+            handle.transfer(),
+            TransferSnapshot {
+                chunks: 2,
+                bytes: 2 * size,
+                data_type: None,
+            }
+        );
+
+        assert!(handle.has_response());
+        assert!(handle.elapsed().is_some());
+        assert!(matches!(handle.peek().as_deref(), Some(Peek::Line(_))));
+
+        output.invalidate();
+        assert_eq!(handle.transfer(), TransferSnapshot::default());
+        assert!(!handle.has_response());
+        assert_eq!(handle.elapsed(), None);
+        assert_eq!(handle.peek(), None);
+    }
+
+    #[test]
+    fn epoch_changes_on_invalidation() {
+        let (mut handle, mut output) = ConnectionHandle::new::<Generation>();
+
+        let mut input = TaskInput::<Generation>::default();
+        assert_eq!(input.epoch(), None);
+        assert!(input.connect(&mut handle));
+
+        let epoch = input.epoch().unwrap();
+        output.respond(1);
+        assert_eq!(input.epoch(), Some(epoch));
+
+        output.invalidate();
+        let invalidated = input.epoch().unwrap();
+        assert_ne!(invalidated, epoch);
+
+        output.get_invalidator().invalidate();
+        assert_ne!(input.epoch().unwrap(), invalidated);
+
+        // A replaced producer never continues an old epoch
+        let (new_handle, _new_output) = ConnectionHandle::new::<Generation>();
+        let epoch = input.epoch().unwrap();
+        assert!(handle.redirect(&new_handle));
+        assert_ne!(input.epoch().unwrap(), epoch);
+    }
+
+    #[tokio::test]
+    async fn replay_within_epoch() {
+        let (mut handle, mut output) = ConnectionHandle::new::<Generation>();
+
+        let mut input = TaskInput::<Generation>::default();
+        assert!(input.connect(&mut handle));
+        output.publish(1);
+
+        let epoch = input.epoch().unwrap();
+        assert!(!input.can_replay());
+        assert_eq!(input.replay(Generation, epoch).await, None);
+
+        handle.set_replayable(true);
+        assert!(input.can_replay());
+        assert_eq!(input.replay(Generation, epoch).await, Some(1));
+
+        // The data of another epoch would differ
+        output.invalidate();
+        output.publish(2);
+        assert_eq!(input.replay(Generation, epoch).await, None);
+
+        handle.disable();
+        assert!(!input.can_replay());
+    }
+
+    #[test]
+    fn consumers_follow_redirect() {
+        let (mut handle, output) = ConnectionHandle::new::<Generation>();
+        assert!(!output.has_consumers());
+
+        let mut input = TaskInput::<Generation>::default();
+        assert!(input.connect(&mut handle));
+        let mut other_input = TaskInput::<Generation>::default();
+        assert!(other_input.connect(&mut handle));
+        assert!(output.has_consumers());
+
+        let (new_handle, new_output) = ConnectionHandle::new::<Generation>();
+        assert!(handle.redirect(&new_handle));
+        assert!(!output.has_consumers());
+        assert!(new_output.has_consumers());
+
+        input.disconnect();
+        assert!(new_output.has_consumers());
+        drop(other_input);
+        assert!(!new_output.has_consumers());
+    }
+}
@@ -1,5 +1,13 @@
 use core::fmt;
-use std::{collections::HashMap, panic, sync::RwLock};
+use std::{
+    collections::{HashMap, HashSet},
+    panic,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock, Weak,
+    },
+    time::Duration,
+};
 
 use futures::{future::select_all, FutureExt};
 use tokio::sync::{mpsc, watch};
@@ -8,14 +16,16 @@ use vec_collections::{AbstractVecMap, VecMap};
 use crate::{
     node_graph::{InputId, NodeId, NodeOutput, OutputId},
     pipeline::{
-        nodes::{DynPipelineNode, PipelineNode},
-        Pipeline,
+        determinism,
+        nodes::{output, DynPipelineNode, PipelineNode},
+        priority, requests, Pipeline,
     },
 };
 
 use super::{
+    flatten::{self, Flattened},
     ConnectionHandle, DynNodeTask, InvalidationCause, InvalidationNotifier, Invalidator, NodeTask,
-    NodeTaskBuilder, Request, TaskOutput,
+    NodeTaskBuilder, Peek, Request, TaskOutput, TransferSnapshot,
 };
 
 // MARK: PipelineExecutor
@@ -35,55 +45,531 @@ use super::{
 /// [Self::update] syncs the high level [Pipeline] description with the node
 /// tasks.
 ///
+/// Disabled nodes have no running task. Their outputs stay connected, but
+/// every request to them resolves to [None] immediately.
+///
 /// There is no shared state between node tasks and [PipelineExecutor]. Syncing
 /// only uses message channels from [tokio::sync] to communicate to node tasks.
+///
+/// Node tasks are spawned in the deterministic mode of the [Pipeline], see
+/// [determinism]. Changing it recreates every task.
+///
+/// Composite nodes have no task. They are expanded into the nodes inside of
+/// them, which run like any other node under a namespaced id, see [flatten].
 #[derive(Debug)]
 pub struct PipelineExecutor {
-    /// Each runner corresponds to one node in the [Pipeline].
+    /// Each runner corresponds to one node in the flattened [Pipeline].
     runners: HashMap<NodeId, RwLock<NodeTaskRunner>>,
+    /// Outputs of composites, by the output of the node inside serving them,
+    /// see [Flattened::into_outputs].
+    composite_outputs: HashMap<(NodeId, OutputId), NodeOutput>,
+    /// Mode the tasks run in, see [Pipeline::deterministic].
+    deterministic: bool,
+    /// Held by every task spawned since the last [Self::shutdown].
+    tasks: TaskToken,
 }
 
 impl PipelineExecutor {
     pub fn new() -> Self {
         Self {
             runners: HashMap::new(),
+            composite_outputs: HashMap::new(),
+            deterministic: false,
+            tasks: TaskToken::default(),
         }
     }
 
     pub fn update(&mut self, pipeline: &mut Pipeline) {
-        // Deleted nodes
-        self.runners.retain(|id, _| pipeline.nodes.contains_key(id));
-
-        // New nodes
-        for (node_id, node) in &mut pipeline.nodes {
-            if !self.runners.contains_key(node_id) {
-                self.runners.insert(
-                    *node_id,
-                    RwLock::new(NodeTaskRunner::from_node(node.as_mut())),
-                );
+        self.update_deferring(pipeline, &HashSet::new());
+    }
+
+    /// Like [Self::update], but keeps the tasks of the nodes in `deferred` on
+    /// their previous settings, so their outputs stay valid. See
+    /// [crate::pipeline::partial_run].
+    ///
+    /// Runners are created and synced from a [PipelineSnapshot] in the order
+    /// of the node ids, and only handed back to the [Pipeline] at the end.
+    pub fn update_deferring(&mut self, pipeline: &mut Pipeline, deferred: &HashSet<NodeId>) {
+        output::update_provenance(pipeline, deferred);
+
+        let flattened = Flattened::of(pipeline);
+        let mut snapshot = PipelineSnapshot::of(flattened.pipeline(pipeline));
+
+        // Deferring a composite defers everything inside
+        let deferred = snapshot
+            .nodes
+            .iter()
+            .map(|node| node.node_id)
+            .filter(|node_id| deferred.contains(&flatten::top_level(*node_id)))
+            .collect::<HashSet<_>>();
+
+        // Deleted nodes, and nodes replaced by a node of another type under
+        // the same id, like after deleting and adding a node in one frame
+        self.runners.retain(|node_id, runner| {
+            snapshot
+                .get(*node_id)
+                .is_some_and(|node| runner.get_mut().unwrap().runs(node.node.as_ref()))
+        });
+
+        // Tasks know their mode from when they were spawned. Their inputs
+        // are connected again below
+        let mode_changed = self.deterministic != snapshot.deterministic;
+        self.deterministic = snapshot.deterministic;
+
+        // New, disabled and enabled nodes
+        for node in &mut snapshot.nodes {
+            let Some(runner) = self.runners.get_mut(&node.node_id) else {
+                let runner = match node.disabled {
+                    true => NodeTaskRunner::disabled(node.node.as_mut()),
+                    false => NodeTaskRunner::from_node(
+                        node.node.as_mut(),
+                        self.deterministic,
+                        &self.tasks,
+                    ),
+                };
+                self.runners.insert(node.node_id, RwLock::new(runner));
+                node.created = true;
+                continue;
+            };
+
+            let runner = runner.get_mut().unwrap();
+            match (runner.disabled, node.disabled) {
+                (false, true) => runner.disable(node.node.as_ref()),
+                // Outputs are redirected to the new task, which invalidates
+                // everything downstream
+                (true, false) => {
+                    runner.recreate(node.node.as_mut(), self.deterministic, &self.tasks);
+                    node.created = true;
+                }
+                (false, false) if mode_changed => {
+                    runner.recreate(node.node.as_mut(), self.deterministic, &self.tasks);
+                    node.created = true;
+                }
+                _ => {}
             }
         }
 
-        // Connections and node sync
-        for (node_id, runner) in &self.runners {
-            let node = pipeline.nodes.get(node_id).expect("Nodes should be synced");
+        // Connections are resolved, before any runner is changed
+        let changes = snapshot
+            .nodes
+            .iter()
+            .filter(|node| !node.disabled)
+            .map(|node| {
+                (
+                    node.node_id,
+                    self.connection_changes(node.node_id, node.node.as_ref()),
+                )
+            })
+            .collect::<Vec<_>>();
 
-            let mut runner = runner.write().unwrap();
+        for (node_id, changes) in changes {
+            let runner = self.runners.get_mut(&node_id).unwrap().get_mut().unwrap();
+            for change in changes {
+                runner.apply(change);
+            }
+        }
+
+        for node in snapshot.nodes.iter().filter(|node| !node.disabled) {
+            if !deferred.contains(&node.node_id) {
+                let runner = self.runners.get_mut(&node.node_id).unwrap();
+                runner.get_mut().unwrap().sync_node(node.node.as_ref());
+            }
+        }
 
-            runner.sync_connections(node.as_ref(), &self.runners);
-            runner.sync_node(node.as_ref());
+        // Creating a task hands its receivers, like the progress of the
+        // task, to the node it was created from
+        snapshot.apply(pipeline);
+
+        // Tell two-pass nodes, which of their inputs can be requested again
+        let flat = flattened.pipeline(pipeline);
+        let mut replayable = HashMap::new();
+        for (node_id, runner) in &self.runners {
+            for (output_id, handle) in runner.read().unwrap().output_handles.iter() {
+                handle.set_replayable(flat.can_replay(*node_id, *output_id, &mut replayable));
+            }
         }
+
+        self.composite_outputs = flattened.into_outputs();
+    }
+
+    /// How the inputs of the task of `node_id` differ from the connections of
+    /// `node`. Inputs are connected again, when the runner of their output got
+    /// replaced.
+    fn connection_changes(
+        &self,
+        node_id: NodeId,
+        node: &dyn DynPipelineNode,
+    ) -> Vec<ConnectionChange> {
+        let runner = self.runners[&node_id].read().unwrap();
+
+        node.inputs()
+            .into_iter()
+            .filter_map(|(input_id, incoming)| {
+                let existing = runner.inputs.get(&input_id).copied();
+
+                let Some(output) = incoming else {
+                    return existing.map(|_| ConnectionChange::Disconnect(input_id));
+                };
+
+                let source = self.runners.get(&output.node_id).map(|source| {
+                    let source = source.read().unwrap();
+                    (source.serial, source.get_output(output.output_id))
+                });
+                let record = InputRecord {
+                    output,
+                    source: source.as_ref().map(|(serial, _)| *serial),
+                };
+                if existing == Some(record) {
+                    return None;
+                }
+
+                match source.and_then(|(_, connection)| connection) {
+                    Some(connection) => {
+                        Some(ConnectionChange::Connect(input_id, record, connection))
+                    }
+                    None => {
+                        eprintln!(
+                            "Failed to find output {:?} of node {:?}",
+                            output.output_id, output.node_id
+                        );
+                        Some(ConnectionChange::Dangling(input_id, record))
+                    }
+                }
+            })
+            .collect()
     }
 
+    /// Enabled nodes, whose settings differ from the ones their task runs
+    /// with. Composites are included, when a node inside of them changed.
+    pub fn changed_nodes(&self, pipeline: &Pipeline) -> HashSet<NodeId> {
+        self.runners
+            .iter()
+            .filter(|(node_id, runner)| {
+                let runner = runner.read().unwrap();
+                !runner.disabled
+                    && !flatten::is_disabled(pipeline, **node_id)
+                    && flatten::node(pipeline, **node_id)
+                        .is_some_and(|node| runner.sync_tx.borrow().changed(node))
+            })
+            .map(|(node_id, _)| flatten::top_level(*node_id))
+            .collect()
+    }
+
+    /// Outputs of composites are served by the node inside producing them.
     pub fn get_output(&self, node_id: NodeId, output_id: OutputId) -> Option<ConnectionHandle> {
+        let (node_id, output_id) = match self.composite_outputs.get(&(node_id, output_id)) {
+            Some(output) => (output.node_id, output.output_id),
+            None => (node_id, output_id),
+        };
+
         self.runners
             .get(&node_id)
             .and_then(|r| r.read().unwrap().get_output(output_id))
     }
 
-    pub fn clear(&mut self) {
+    /// Data sent through every connection since the last invalidation of its
+    /// output, keyed by the node and input receiving it. All connections of
+    /// an output carry the same data.
+    pub fn transfer_stats(&self) -> HashMap<(NodeId, InputId), TransferSnapshot> {
+        let mut stats = HashMap::new();
+
+        for (node_id, runner) in &self.runners {
+            for (input_id, record) in runner.read().unwrap().inputs.iter() {
+                let output = record.output;
+                if let Some(handle) = self.get_output(output.node_id, output.output_id) {
+                    stats.insert((*node_id, *input_id), handle.transfer());
+                }
+            }
+        }
+
+        stats
+    }
+
+    /// The last data sent to an input, see [super::PeekBuffer]. Never makes any task
+    /// do work.
+    pub fn peek(&self, node_id: NodeId, input_id: InputId) -> Option<Arc<Peek>> {
+        let output = self
+            .runners
+            .get(&node_id)?
+            .read()
+            .unwrap()
+            .inputs
+            .get(&input_id)?
+            .output;
+        self.get_output(output.node_id, output.output_id)?.peek()
+    }
+
+    /// The outputs of a node with the data sent through them since their last
+    /// invalidation.
+    pub fn output_stats(&self, node_id: NodeId) -> Vec<OutputStats> {
+        let Some(runner) = self.runners.get(&node_id) else {
+            return Vec::new();
+        };
+
+        runner
+            .read()
+            .unwrap()
+            .output_handles
+            .iter()
+            .map(|(output_id, handle)| OutputStats {
+                output_id: *output_id,
+                transfer: handle.transfer(),
+                elapsed: handle.elapsed(),
+                has_response: handle.has_response(),
+            })
+            .collect()
+    }
+
+    /// Replaces the task of a node with a newly created one, for example to
+    /// recover from a misbehaving task. Connections to other nodes are kept:
+    /// Downstream tasks are invalidated and their next requests are served by
+    /// the new task. Disabled nodes have no task to recreate. For composites,
+    /// the tasks of every node inside are recreated.
+    pub fn recreate_node(&mut self, node_id: NodeId, pipeline: &mut Pipeline) {
+        let flattened = Flattened::of(pipeline);
+        let nodes = flattened
+            .pipeline(pipeline)
+            .nodes
+            .iter()
+            .filter(|(id, _)| flatten::is_within(**id, node_id))
+            .map(|(id, node)| (*id, node.clone_boxed()))
+            .collect::<Vec<_>>();
+
+        for (node_id, mut node) in nodes {
+            let Some(runner) = self.runners.get(&node_id) else {
+                continue;
+            };
+
+            if runner.read().unwrap().disabled {
+                continue;
+            }
+
+            runner
+                .write()
+                .unwrap()
+                .recreate(node.as_mut(), self.deterministic, &self.tasks);
+
+            // Reconnect the inputs of the new task
+            for change in self.connection_changes(node_id, node.as_ref()) {
+                runner.write().unwrap().apply(change);
+            }
+
+            flatten::restore(pipeline, node_id, node);
+        }
+    }
+
+    /// Nodes, whose last run failed or panicked, with the error. The error is
+    /// cleared, when the node is invalidated or its next run succeeds. Errors
+    /// of nodes inside of composites are reported for the composite.
+    pub fn errors(&self) -> Vec<(NodeId, String)> {
+        let mut errors = self
+            .runners
+            .iter()
+            .filter_map(|(node_id, runner)| {
+                let error = runner.read().unwrap().error_rx.borrow().clone()?;
+                Some((flatten::top_level(*node_id), error))
+            })
+            .collect::<Vec<_>>();
+
+        errors.sort_by_key(|(node_id, _)| *node_id);
+        errors
+    }
+
+    /// Runs the tasks of the nodes, whose last run failed, again, without
+    /// invalidating them. Their error is cleared, when the run succeeds. Used
+    /// to retry after transient errors, like a failed read, where the data
+    /// downstream stays valid up to the error.
+    pub fn retry_failed(&self) {
+        for runner in self.runners.values() {
+            let runner = runner.read().unwrap();
+            if runner.error_rx.borrow().is_some() {
+                let _ = runner.control_tx.send(ControlMsg::Retry);
+            }
+        }
+    }
+
+    /// Abandons the current run of a node, without touching its
+    /// configuration. Downstream tasks are invalidated as usual. Does nothing,
+    /// if the run finishes before the task receives the cancellation. For
+    /// composites, the runs of every node inside are abandoned.
+    pub fn cancel_node(&self, node_id: NodeId) {
+        for (_, runner) in self
+            .runners
+            .iter()
+            .filter(|(id, _)| flatten::is_within(**id, node_id))
+        {
+            runner.read().unwrap().cancel();
+        }
+    }
+
+    /// Creates a task for a node, that is not part of the [Pipeline]. The
+    /// inputs of the task are not connected, use
+    /// [EphemeralRunner::connect_input]. The task stops, when the returned
+    /// runner is dropped. Runs in the same deterministic mode as the pipeline.
+    pub fn spawn_ephemeral(&self, node: &mut dyn DynPipelineNode) -> EphemeralRunner {
+        EphemeralRunner(NodeTaskRunner::from_node(
+            node,
+            self.deterministic,
+            &self.tasks,
+        ))
+    }
+
+    /// Stops every task, including ephemeral ones, and removes all nodes.
+    /// The tasks end at their next await, the returned [Shutdown] tells when.
+    pub fn shutdown(&mut self) -> Shutdown {
         self.runners.clear();
+        self.tasks.shutdown()
+    }
+}
+
+// MARK: Shutdown
+
+/// Held by every task an executor spawned, to know when they ended.
+#[derive(Debug, Clone, Default)]
+pub struct TaskToken(Arc<()>);
+
+impl TaskToken {
+    /// Replaces the token for new tasks. The returned [Shutdown] waits for
+    /// the tasks holding the old one.
+    pub fn shutdown(&mut self) -> Shutdown {
+        let old = std::mem::take(self);
+        Shutdown(vec![Arc::downgrade(&old.0)])
+    }
+}
+
+/// Tasks, that were told to stop, but might still be running.
+#[derive(Debug, Default)]
+pub struct Shutdown(Vec<Weak<()>>);
+
+impl Shutdown {
+    /// Number of tasks still running.
+    pub fn running(&self) -> usize {
+        self.0.iter().map(Weak::strong_count).sum()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.running() == 0
+    }
+
+    /// Waits for the tasks of both.
+    pub fn and(mut self, other: Shutdown) -> Shutdown {
+        self.0.extend(other.0);
+        self
+    }
+
+    /// Polls until every task ended.
+    #[cfg(test)]
+    pub async fn wait(&self) {
+        while !self.is_finished() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+}
+
+/// State of one output of a node, see [PipelineExecutor::output_stats].
+#[derive(Debug, Clone, Copy)]
+pub struct OutputStats {
+    pub output_id: OutputId,
+    pub transfer: TransferSnapshot,
+    /// See [ConnectionHandle::elapsed].
+    pub elapsed: Option<Duration>,
+    /// See [ConnectionHandle::has_response].
+    pub has_response: bool,
+}
+
+// MARK: EphemeralRunner
+
+/// Handle to a node task, that is not part of the [Pipeline], for example a
+/// temporary copy of a node. Owned by whoever requested it, instead of the
+/// [PipelineExecutor]. Dropping it stops the task.
+#[derive(Debug)]
+pub struct EphemeralRunner(NodeTaskRunner);
+
+impl EphemeralRunner {
+    pub fn get_output(&self, output_id: OutputId) -> Option<ConnectionHandle> {
+        self.0.get_output(output_id)
     }
+
+    /// Connects an input of the task directly to a connection.
+    pub fn connect_input(&mut self, input_id: InputId, connection: ConnectionHandle) {
+        self.0
+            .control_tx
+            .send(ControlMsg::Connect(input_id, connection))
+            .expect("Task should be running");
+    }
+}
+
+// MARK: PipelineSnapshot
+
+/// Copies of the nodes of a [Pipeline], sorted by their id, taken at the
+/// start of [PipelineExecutor::update]. Runners are created from the copies,
+/// so the live nodes are only touched by [Self::apply].
+struct PipelineSnapshot {
+    nodes: Vec<SnapshotNode>,
+    deterministic: bool,
+}
+
+struct SnapshotNode {
+    node_id: NodeId,
+    node: Box<dyn DynPipelineNode>,
+    disabled: bool,
+    /// A task was created from the copy.
+    created: bool,
+}
+
+impl PipelineSnapshot {
+    fn of(pipeline: &Pipeline) -> Self {
+        let mut nodes = pipeline
+            .nodes
+            .iter()
+            .map(|(node_id, node)| SnapshotNode {
+                node_id: *node_id,
+                node: node.clone_boxed(),
+                disabled: pipeline.disabled.contains(node_id),
+                created: false,
+            })
+            .collect::<Vec<_>>();
+        nodes.sort_by_key(|node| node.node_id);
+
+        Self {
+            nodes,
+            deterministic: pipeline.deterministic,
+        }
+    }
+
+    fn get(&self, node_id: NodeId) -> Option<&SnapshotNode> {
+        self.nodes
+            .binary_search_by_key(&node_id, |node| node.node_id)
+            .ok()
+            .map(|i| &self.nodes[i])
+    }
+
+    /// Replaces the nodes of `pipeline`, that a task was created from, with
+    /// their copies, which hold the receivers of the task. Nodes inside of
+    /// composites are put back into them, see [flatten::restore].
+    fn apply(self, pipeline: &mut Pipeline) {
+        for node in self.nodes.into_iter().filter(|node| node.created) {
+            flatten::restore(pipeline, node.node_id, node.node);
+        }
+    }
+}
+
+/// The output an input of a task is connected to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct InputRecord {
+    output: NodeOutput,
+    /// [NodeTaskRunner::serial] of the runner of the output. [None], if there
+    /// was none.
+    source: Option<u64>,
+}
+
+/// Change of the inputs of a task, see [PipelineExecutor::connection_changes].
+enum ConnectionChange {
+    Connect(InputId, InputRecord, ConnectionHandle),
+    /// The output does not exist, so the input stays disconnected, until its
+    /// runner changes.
+    Dangling(InputId, InputRecord),
+    Disconnect(InputId),
 }
 
 // MARK: NodeTaskRunner
@@ -91,43 +577,149 @@ impl PipelineExecutor {
 /// Handle to a node task, holding information about the node task and all
 /// channel ends to communicate to the node task.
 struct NodeTaskRunner {
+    /// Identifies this runner among all runners ever created. Kept, when the
+    /// task is recreated, because its outputs stay the same.
+    serial: u64,
     output_handles: VecMap<[(OutputId, ConnectionHandle); 4]>,
-    inputs: VecMap<[(InputId, NodeOutput); 4]>,
+    inputs: VecMap<[(InputId, InputRecord); 4]>,
     control_tx: mpsc::UnboundedSender<ControlMsg>,
     sync_tx: watch::Sender<Box<dyn DynPipelineNode>>,
+    /// Index of the run to cancel, see [RunningNodeTask::on_cancel].
+    cancel_tx: watch::Sender<Option<u64>>,
+    /// Number of runs the task finished.
+    runs_rx: watch::Receiver<u64>,
+    /// Error of the last run, see [PipelineExecutor::errors].
+    error_rx: watch::Receiver<Option<String>>,
+    /// No task is running, the channels above are closed.
+    disabled: bool,
 }
 
 impl NodeTaskRunner {
-    pub fn from_node(node: &mut dyn DynPipelineNode) -> Self {
+    pub fn from_node(
+        node: &mut dyn DynPipelineNode,
+        deterministic: bool,
+        tasks: &TaskToken,
+    ) -> Self {
         let (task, output_handles, invalidator) = node.create_node_task();
 
         let (control_tx, control_rx) = mpsc::unbounded_channel();
         let (sync_tx, sync_rx) = watch::channel(node.clone_boxed());
+        let (cancel_tx, cancel_rx) = watch::channel(None);
+        let (runs_tx, runs_rx) = watch::channel(0);
+        let (error_tx, error_rx) = watch::channel(None);
 
-        tokio::spawn(
-            RunningNodeTask {
-                node_task: task,
-                control_rx,
-                sync_rx,
-                input_connections: Vec::new(),
-                output_invalidator: invalidator,
-                error_on_last_run: false,
-            }
-            .run(),
-        );
+        tokio::spawn(priority::node_scope(
+            node.name().into(),
+            determinism::scope(
+                deterministic,
+                RunningNodeTask {
+                    node_task: task,
+                    control_rx,
+                    sync_rx,
+                    cancel_rx,
+                    runs_tx,
+                    error_tx,
+                    input_connections: Vec::new(),
+                    output_invalidator: invalidator,
+                    error_on_last_run: false,
+                    _token: tasks.clone(),
+                }
+                .run(),
+            ),
+        ));
 
         Self {
+            serial: next_serial(),
             output_handles,
             inputs: VecMap::empty(),
             control_tx,
             sync_tx,
+            cancel_tx,
+            runs_rx,
+            error_rx,
+            disabled: false,
         }
     }
 
+    /// Creates the outputs of a disabled node, without running its task.
+    pub fn disabled(node: &mut dyn DynPipelineNode) -> Self {
+        let (_, output_handles, _) = node.create_node_task();
+
+        let mut runner = Self {
+            serial: next_serial(),
+            output_handles,
+            inputs: VecMap::empty(),
+            control_tx: mpsc::unbounded_channel().0,
+            sync_tx: watch::channel(node.clone_boxed()).0,
+            cancel_tx: watch::channel(None).0,
+            runs_rx: watch::channel(0).1,
+            error_rx: watch::channel(None).1,
+            disabled: false,
+        };
+        runner.disable(node);
+        runner
+    }
+
+    /// Stops the running task. The output handles are kept, so connected
+    /// inputs stay connected, but get invalidated and their requests resolve
+    /// to [None]. Use [Self::recreate] to run a task again.
+    pub fn disable(&mut self, node: &dyn DynPipelineNode) {
+        for (_, handle) in self.output_handles.iter() {
+            handle.disable();
+        }
+
+        self.control_tx = mpsc::unbounded_channel().0;
+        self.sync_tx = watch::channel(node.clone_boxed()).0;
+        self.cancel_tx = watch::channel(None).0;
+        self.runs_rx = watch::channel(0).1;
+        self.error_rx = watch::channel(None).1;
+        self.inputs = VecMap::empty();
+        self.disabled = true;
+    }
+
+    /// Replaces the running task with a new one. The existing output handles
+    /// are redirected to the new task, so connected inputs keep working. The
+    /// inputs of the new task are not connected, use [Self::sync_connections]
+    /// afterwards.
+    pub fn recreate(
+        &mut self,
+        node: &mut dyn DynPipelineNode,
+        deterministic: bool,
+        tasks: &TaskToken,
+    ) {
+        let new = Self::from_node(node, deterministic, tasks);
+
+        for (output_id, handle) in new.output_handles.iter() {
+            match self.output_handles.get(output_id) {
+                Some(existing) if existing.redirect(handle) => {}
+                _ => {
+                    self.output_handles.insert(*output_id, handle.clone());
+                }
+            }
+        }
+
+        // Dropping the old channels stops the old task. The handles are
+        // redirected before, so inputs see the new task when the old one is
+        // gone.
+        self.control_tx = new.control_tx;
+        self.sync_tx = new.sync_tx;
+        self.cancel_tx = new.cancel_tx;
+        self.runs_rx = new.runs_rx;
+        self.error_rx = new.error_rx;
+        self.inputs = new.inputs;
+        self.disabled = false;
+    }
+
     pub fn get_output(&self, output_id: OutputId) -> Option<ConnectionHandle> {
         self.output_handles.get(&output_id).cloned()
     }
 
+    /// Cancels the run the task is currently working on.
+    pub fn cancel(&self) {
+        let run = *self.runs_rx.borrow();
+        let _ = self.cancel_tx.send(Some(run));
+    }
+
     pub fn sync_node(&mut self, node: &dyn DynPipelineNode) {
         self.sync_tx.send_if_modified(|v| {
             if v.changed(node) {
@@ -139,78 +731,40 @@ impl NodeTaskRunner {
         });
     }
 
-    pub fn sync_connections(
-        &mut self,
-        node: &dyn DynPipelineNode,
-        runners: &HashMap<NodeId, RwLock<NodeTaskRunner>>,
-    ) {
-        let inputs = node.inputs();
-
-        for (input_id, incoming) in inputs {
-            let existing = self
-                .inputs
-                .iter()
-                .find(|(id, _)| *id == input_id)
-                .map(|(_, o)| *o);
-
-            match (incoming, existing) {
-                (i, e) if i == e => {
-                    // Do nothing when equal
-                }
-                (Some(incoming), _) => {
-                    self.connect_input(input_id, incoming, runners);
-                }
-                (None, _) => {
-                    self.disconnect_input(input_id);
-                }
-            }
-        }
-    }
-
-    pub fn disconnect_input(&mut self, input_id: InputId) {
-        self.control_tx
-            .send(ControlMsg::Disconnect(input_id))
-            .expect("Task should be running");
-        self.inputs.retain(|(id, _)| *id != input_id);
+    /// Whether the task was created from a node of the same type as `node`.
+    pub fn runs(&self, node: &dyn DynPipelineNode) -> bool {
+        self.sync_tx.borrow().as_any().type_id() == node.as_any().type_id()
     }
 
-    pub fn connect_input(
-        &mut self,
-        input_id: InputId,
-        output: NodeOutput,
-        runners: &HashMap<NodeId, RwLock<NodeTaskRunner>>,
-    ) {
-        // Find runner that we want to connect to
-        let Some(out_runner) = runners.get(&output.node_id) else {
-            eprintln!(
-                "Failed to find runner for node with id {:?}",
-                output.node_id
-            );
-            return;
-        };
-
-        // Get read lock on runner
-        let out_runner = out_runner.read().unwrap();
-
-        // Find creator for output
-        let Some(connection) = out_runner.get_output(output.output_id) else {
-            eprintln!(
-                "Failed to find connection for output with id {:?}",
-                output.output_id
-            );
-            return;
+    fn apply(&mut self, change: ConnectionChange) {
+        let control = match change {
+            ConnectionChange::Connect(input_id, record, connection) => {
+                self.inputs.insert(input_id, record);
+                ControlMsg::Connect(input_id, connection)
+            }
+            ConnectionChange::Dangling(input_id, record) => {
+                self.inputs.insert(input_id, record);
+                ControlMsg::Disconnect(input_id)
+            }
+            ConnectionChange::Disconnect(input_id) => {
+                self.inputs.retain(|(id, _)| *id != input_id);
+                ControlMsg::Disconnect(input_id)
+            }
         };
 
-        // Send connect message to task
         self.control_tx
-            .send(ControlMsg::Connect(input_id, connection.clone()))
+            .send(control)
             .expect("Task should be running");
-
-        // Update input record
-        self.inputs.insert(input_id, output);
     }
 }
 
+/// Serial of a new [NodeTaskRunner].
+fn next_serial() -> u64 {
+    static SERIAL: AtomicU64 = AtomicU64::new(0);
+
+    SERIAL.fetch_add(1, Ordering::Relaxed)
+}
+
 impl fmt::Debug for NodeTaskRunner {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("NodeTaskRunner")
@@ -223,6 +777,7 @@ impl fmt::Debug for NodeTaskRunner {
                     .collect::<Vec<_>>(),
             )
             .field("inputs", &self.inputs)
+            .field("disabled", &self.disabled)
             .finish()
     }
 }
@@ -236,15 +791,24 @@ struct RunningNodeTask {
     node_task: Box<dyn DynNodeTask>,
     control_rx: mpsc::UnboundedReceiver<ControlMsg>,
     sync_rx: watch::Receiver<Box<dyn DynPipelineNode>>,
+    cancel_rx: watch::Receiver<Option<u64>>,
+    /// Counts finished and cancelled runs.
+    runs_tx: watch::Sender<u64>,
+    /// Error of the last run, cleared on invalidation.
+    error_tx: watch::Sender<Option<String>>,
     input_connections: Vec<(InputId, InvalidationNotifier)>,
     output_invalidator: Vec<Invalidator>,
     error_on_last_run: bool,
+    /// Dropped, when the task ends, see [Shutdown].
+    _token: TaskToken,
 }
 
 impl RunningNodeTask {
     /// Main entry point and event loop.
     pub async fn run(mut self) {
         loop {
+            let run = *self.runs_tx.borrow();
+
             tokio::select! {
                 biased;
                 msg = self.control_rx.recv() => {
@@ -256,6 +820,12 @@ impl RunningNodeTask {
                             if connection.did_connect() {
                                 self.input_connections.push((input_id, connection.get_invalidation_notifier()));
                                 self.invalidate(InvalidationCause::Connected(input_id));
+                            } else if let Some(expected) = connection.rejected_by() {
+                                eprintln!(
+                                    "Failed to connect input {:?}: {}",
+                                    input_id,
+                                    requests::describe_mismatch(expected, connection.request_type())
+                                );
                             }
                         }
                         Some(ControlMsg::Disconnect(input_id)) => {
@@ -263,9 +833,19 @@ impl RunningNodeTask {
                             self.input_connections.retain(|(id, _)| *id != input_id);
                             self.invalidate(InvalidationCause::Disconnected(input_id));
                         }
+                        Some(ControlMsg::Retry) => {
+                            // The error stays, until the next run succeeds
+                            self.error_on_last_run = false;
+                        }
                         None => break,
                     };
                 }
+                () = Self::on_cancel(&mut self.cancel_rx, run) => {
+                    self.invalidate(InvalidationCause::UserCancelled);
+                    // The cancelled run counts as finished, so the same
+                    // cancellation is not received again
+                    self.runs_tx.send_modify(|runs| *runs += 1);
+                }
                 _ = self.sync_rx.changed() => {
                     self.node_task.sync_node(self.sync_rx.borrow().as_ref());
                     self.invalidate(InvalidationCause::Synced);
@@ -274,39 +854,42 @@ impl RunningNodeTask {
                     // An input got invalidated
                     self.invalidate(InvalidationCause::InputInvalidated(input_id));
                 }
-                is_error = Self::run_task(self.error_on_last_run, self.node_task.as_mut()) => {
-                    self.error_on_last_run = is_error;
+                error = Self::run_task(self.error_on_last_run, self.node_task.as_mut()) => {
+                    self.error_on_last_run = error.is_some();
+                    self.error_tx.send_replace(error);
+                    self.runs_tx.send_modify(|runs| *runs += 1);
                 }
             }
         }
     }
 
     /// Run the [NodeTask::run] method, additionally handling panics and errors.
-    async fn run_task(error_on_last_run: bool, task: &mut dyn DynNodeTask) -> bool {
+    /// Returns the error, if the run failed.
+    async fn run_task(error_on_last_run: bool, task: &mut dyn DynNodeTask) -> Option<String> {
         if error_on_last_run {
             let () = futures::future::pending().await;
         }
 
         let result = panic::AssertUnwindSafe(task.run()).catch_unwind().await;
 
-        let is_error = !matches!(result, Ok(Ok(_)));
-
         match result {
-            Ok(Ok(_)) => {}
-            Ok(Err(e)) => eprintln!("Task failed: {:?}", e),
-            Err(e) => eprintln!(
-                "Task panicked: {}",
-                if let Some(msg) = e.downcast_ref::<&'static str>() {
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => {
+                eprintln!("Task failed: {:?}", e);
+                Some(format!("{:#}", e))
+            }
+            Err(e) => {
+                let msg = if let Some(msg) = e.downcast_ref::<&'static str>() {
                     msg.to_string()
                 } else if let Some(msg) = e.downcast_ref::<String>() {
                     msg.clone()
                 } else {
                     format!("?{:?}", e)
-                }
-            ),
+                };
+                eprintln!("Task panicked: {}", msg);
+                Some(format!("Panicked: {}", msg))
+            }
         }
-
-        is_error
     }
 
     /// Invalidates all outputs of this node task and itself.
@@ -318,6 +901,22 @@ impl RunningNodeTask {
         self.node_task.invalidate(cause);
 
         self.error_on_last_run = false;
+        self.error_tx.send_replace(None);
+    }
+
+    /// Returned future completes when cancelling the given run was requested.
+    /// Requests for earlier runs, that finished before the request arrived,
+    /// are ignored, so the current run is not interrupted by them.
+    async fn on_cancel(cancel_rx: &mut watch::Receiver<Option<u64>>, run: u64) {
+        loop {
+            if *cancel_rx.borrow_and_update() == Some(run) {
+                return;
+            }
+
+            if cancel_rx.changed().await.is_err() {
+                futures::future::pending().await
+            }
+        }
     }
 
     /// Returned future completes when any input received an invalidation
@@ -349,6 +948,9 @@ impl RunningNodeTask {
 enum ControlMsg {
     Connect(InputId, ConnectionHandle),
     Disconnect(InputId),
+    /// Runs the task again after a failed run, see
+    /// [PipelineExecutor::retry_failed].
+    Retry,
 }
 
 impl fmt::Debug for ControlMsg {
@@ -358,6 +960,7 @@ impl fmt::Debug for ControlMsg {
             ControlMsg::Disconnect(input_id) => {
                 f.debug_tuple("Disconnect").field(input_id).finish()
             }
+            ControlMsg::Retry => f.write_str("Retry"),
         }
     }
 }
@@ -418,3 +1021,434 @@ impl<T: PipelineNode> NodeTaskBuilder for NodeTaskBuilderImpl<T> {
         self.task = Some(Box::new(task));
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        path::Path,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use futures::future::BoxFuture;
+    use serde_json::json;
+    use tokio::sync::Notify;
+
+    use crate::pipeline::{execution::TaskInput, nodes, random::Lcg, requests};
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn disabled_node() {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden/raw.bin");
+        let mut pipeline: Pipeline = serde_json::from_value(json!({
+            "nodes": {
+                "1": {
+                    "type": "binary_input",
+                    "path": source,
+                    "input_type": "RawMScan",
+                    "data_type": "U16",
+                    "a_scan_length": 256,
+                },
+            },
+            "disabled": [1],
+        }))
+        .unwrap();
+        let node_id = NodeId::from(1);
+
+        let mut executor = PipelineExecutor::new();
+        executor.update(&mut pipeline);
+
+        let mut handle = executor.get_output(node_id, OutputId::from(0)).unwrap();
+        assert!(handle.is_disabled());
+
+        let mut input = TaskInput::<requests::RawMScan>::default();
+        assert!(input.connect(&mut handle));
+        assert!(input.request(requests::RawMScan).await.is_none());
+        assert!(input.is_connected());
+
+        // Enabling runs a task again, serving the same connection
+        pipeline.disabled.clear();
+        executor.update(&mut pipeline);
+        assert!(!handle.is_disabled());
+
+        let response =
+            tokio::time::timeout(Duration::from_secs(60), input.request(requests::RawMScan))
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(response.a_scan_samples, 256);
+
+        pipeline.disabled.insert(node_id);
+        executor.update(&mut pipeline);
+        assert!(input.request(requests::RawMScan).await.is_none());
+        assert!(input.is_connected());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn replayable_outputs() {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden/raw.bin");
+        let connection = |node_id: usize, output_id: usize| {
+            json!({
+                "value": null,
+                "connection": { "node_id": node_id, "output_id": output_id, "type_id": 2 },
+            })
+        };
+        let mut pipeline: Pipeline = serde_json::from_value(json!({
+            "nodes": {
+                "1": {
+                    "type": "binary_input",
+                    "path": source,
+                    "input_type": "MScan",
+                    "data_type": "U16",
+                    "a_scan_length": 256,
+                },
+                "2": { "type": "filter", "filter_type": "Median", "input": connection(1, 1) },
+                "3": { "type": "external_command", "m_scan": connection(1, 1) },
+                "4": { "type": "filter", "filter_type": "Median", "input": connection(3, 0) },
+                "5": {
+                    "type": "filter",
+                    "filter_type": "Median",
+                    "cache_settings": { "enabled": true },
+                    "input": connection(3, 0),
+                },
+            },
+        }))
+        .unwrap();
+
+        let mut executor = PipelineExecutor::new();
+        executor.update(&mut pipeline);
+
+        fn can_replay(executor: &PipelineExecutor, node_id: usize) -> bool {
+            let mut input = TaskInput::<requests::MScan>::default();
+            let mut handle = executor
+                .get_output(NodeId::from(node_id), OutputId::from(0))
+                .unwrap();
+            assert!(input.connect(&mut handle));
+            input.can_replay()
+        }
+
+        assert!(can_replay(&executor, 2));
+        assert!(!can_replay(&executor, 3));
+        assert!(!can_replay(&executor, 4));
+        // The cached result might get evicted before the second request
+        assert!(!can_replay(&executor, 5));
+
+        // Disabling the source makes everything downstream lose its data
+        pipeline.disabled.insert(NodeId::from(1));
+        executor.update(&mut pipeline);
+        assert!(!can_replay(&executor, 2));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failed_run() {
+        let mut pipeline: Pipeline = serde_json::from_value(json!({
+            "nodes": {
+                "1": {
+                    "type": "binary_input",
+                    "path": "missing.bin",
+                    "input_type": "RawMScan",
+                    "data_type": "U16",
+                    "a_scan_length": 256,
+                },
+            },
+        }))
+        .unwrap();
+        let node_id = NodeId::from(1);
+
+        let mut executor = PipelineExecutor::new();
+        executor.update(&mut pipeline);
+        assert!(executor.errors().is_empty());
+
+        let mut handle = executor.get_output(node_id, OutputId::from(0)).unwrap();
+        let mut input = TaskInput::<requests::RawMScan>::default();
+        assert!(input.connect(&mut handle));
+        // The request stays unanswered
+        let request = tokio::spawn(async move { input.request(requests::RawMScan).await });
+
+        let start = std::time::Instant::now();
+        while executor.errors().is_empty() && start.elapsed() < Duration::from_secs(10) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let errors = executor.errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, node_id);
+
+        // Pointing the node to an existing file clears the error
+        let node = pipeline.nodes.get_mut(&node_id).unwrap();
+        let node = node
+            .as_any_mut()
+            .downcast_mut::<nodes::binary_input::Node>()
+            .unwrap();
+        node.path = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden/raw.bin");
+        executor.update(&mut pipeline);
+
+        let start = std::time::Instant::now();
+        while !executor.errors().is_empty() && start.elapsed() < Duration::from_secs(10) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(executor.errors().is_empty());
+        request.abort();
+    }
+    /// Asserts, that there is a runner of the right type for every node, and
+    /// that the inputs of every task are connected to the current runners of
+    /// the outputs the pipeline describes.
+    fn assert_converged(executor: &PipelineExecutor, pipeline: &Pipeline) {
+        assert_eq!(executor.runners.len(), pipeline.nodes.len());
+
+        for (node_id, node) in &pipeline.nodes {
+            let runner = executor.runners[node_id].read().unwrap();
+            assert!(runner.runs(node.as_ref()), "Node {:?}", node_id);
+            assert_eq!(runner.disabled, pipeline.disabled.contains(node_id));
+            if runner.disabled {
+                continue;
+            }
+
+            for (input_id, incoming) in node.inputs() {
+                let expected = incoming.map(|output| InputRecord {
+                    output,
+                    source: executor
+                        .runners
+                        .get(&output.node_id)
+                        .map(|source| source.read().unwrap().serial),
+                });
+                assert_eq!(
+                    runner.inputs.get(&input_id).copied(),
+                    expected,
+                    "Input {:?} of node {:?}",
+                    input_id,
+                    node_id
+                );
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn topology_converges() {
+        use crate::{gui::node_graph::EditNodeGraph, node_graph::TypeId};
+
+        let mut pipeline = Pipeline::new();
+        let mut executor = PipelineExecutor::new();
+
+        let mut rng = Lcg::new(0x5eed);
+        let mut random = move |n: usize| rng.below(n);
+
+        let paths = [
+            "Filter/Median Filter",
+            "Process/Remove Catheter Region",
+            "In Out/M Scan Input",
+        ];
+
+        // Several edits between updates, like adding a node and connecting it
+        // in one frame. Deleting the newest node and adding another one reuses
+        // its id
+        for step in 0..300 {
+            for _ in 0..=random(4) {
+                let mut node_ids = pipeline.get_node_ids();
+                node_ids.sort();
+
+                match random(6) {
+                    0 | 1 => {
+                        pipeline.add_node(paths[random(paths.len())]).unwrap();
+                    }
+                    2 | 3 if !node_ids.is_empty() => {
+                        let target = node_ids[random(node_ids.len())];
+                        let source = node_ids[random(node_ids.len())];
+                        let output =
+                            NodeOutput::new(source, OutputId::from(random(2)), TypeId::from(2));
+
+                        if !pipeline.nodes[&target].inputs().is_empty() {
+                            pipeline
+                                .get_node_mut(target)
+                                .unwrap()
+                                .connect(InputId::from(0), output);
+                        }
+                    }
+                    4 if !node_ids.is_empty() => {
+                        pipeline.remove_node(*node_ids.last().unwrap());
+                    }
+                    5 if !node_ids.is_empty() => {
+                        let node_id = node_ids[random(node_ids.len())];
+                        let disabled = pipeline.is_disabled(node_id);
+                        pipeline.set_disabled(node_id, !disabled);
+                    }
+                    _ => {}
+                }
+            }
+
+            if step % 50 == 49 {
+                pipeline.deterministic = !pipeline.deterministic;
+            }
+
+            executor.update(&mut pipeline);
+            assert_converged(&executor, &pipeline);
+        }
+
+        assert!(!pipeline.nodes.is_empty());
+    }
+
+    /// Works until `finish` is notified, counting started runs and
+    /// cancellations.
+    #[derive(Default)]
+    struct CancelTask {
+        finish: Arc<Notify>,
+        started: Arc<AtomicUsize>,
+        cancelled: Arc<AtomicUsize>,
+    }
+
+    impl DynNodeTask for CancelTask {
+        fn sync_node(&mut self, _node: &dyn DynPipelineNode) {}
+
+        fn connect(&mut self, _input_id: InputId, _input: &mut ConnectionHandle) {}
+
+        fn disconnect(&mut self, _input_id: InputId) {}
+
+        fn invalidate(&mut self, cause: InvalidationCause) {
+            if let InvalidationCause::UserCancelled = cause {
+                self.cancelled.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        fn run(&mut self) -> BoxFuture<'_, anyhow::Result<()>> {
+            self.started.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async {
+                self.finish.notified().await;
+                Ok(())
+            })
+        }
+    }
+
+    /// Fails the first run, succeeds the second and waits afterwards.
+    #[derive(Default)]
+    struct FlakyTask {
+        started: Arc<AtomicUsize>,
+    }
+
+    impl DynNodeTask for FlakyTask {
+        fn sync_node(&mut self, _node: &dyn DynPipelineNode) {}
+
+        fn connect(&mut self, _input_id: InputId, _input: &mut ConnectionHandle) {}
+
+        fn disconnect(&mut self, _input_id: InputId) {}
+
+        fn invalidate(&mut self, _cause: InvalidationCause) {}
+
+        fn run(&mut self) -> BoxFuture<'_, anyhow::Result<()>> {
+            let run = self.started.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                match run {
+                    0 => Err(anyhow::anyhow!("Read failed")),
+                    1 => Ok(()),
+                    _ => futures::future::pending().await,
+                }
+            })
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn retry_failed_run() {
+        let task = FlakyTask::default();
+        let started = task.started.clone();
+
+        let node: Box<dyn DynPipelineNode> = Box::new(nodes::remove_catheter::Node::default());
+        let (control_tx, control_rx) = mpsc::unbounded_channel();
+        let (_sync_tx, sync_rx) = watch::channel(node);
+        let (_cancel_tx, cancel_rx) = watch::channel(None);
+        let (runs_tx, mut runs_rx) = watch::channel(0);
+        let (error_tx, error_rx) = watch::channel(None);
+
+        tokio::spawn(
+            RunningNodeTask {
+                node_task: Box::new(task),
+                control_rx,
+                sync_rx,
+                cancel_rx,
+                runs_tx,
+                error_tx,
+                input_connections: Vec::new(),
+                output_invalidator: Vec::new(),
+                error_on_last_run: false,
+                _token: TaskToken::default(),
+            }
+            .run(),
+        );
+
+        let wait = Duration::from_secs(10);
+        tokio::time::timeout(wait, runs_rx.wait_for(|runs| *runs == 1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(error_rx.borrow().is_some());
+
+        // A failed task waits for an invalidation or a retry
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+
+        control_tx.send(ControlMsg::Retry).unwrap();
+        tokio::time::timeout(wait, runs_rx.wait_for(|runs| *runs == 2))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(error_rx.borrow().is_none());
+        assert!(started.load(Ordering::SeqCst) >= 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn cancel_run() {
+        let task = CancelTask::default();
+        let (finish, started, cancelled) = (
+            task.finish.clone(),
+            task.started.clone(),
+            task.cancelled.clone(),
+        );
+
+        let node: Box<dyn DynPipelineNode> = Box::new(nodes::remove_catheter::Node::default());
+        let (_control_tx, control_rx) = mpsc::unbounded_channel();
+        let (_sync_tx, sync_rx) = watch::channel(node);
+        let (cancel_tx, cancel_rx) = watch::channel(None);
+        let (runs_tx, mut runs_rx) = watch::channel(0);
+
+        tokio::spawn(
+            RunningNodeTask {
+                node_task: Box::new(task),
+                control_rx,
+                sync_rx,
+                cancel_rx,
+                runs_tx,
+                error_tx: watch::channel(None).0,
+                input_connections: Vec::new(),
+                output_invalidator: Vec::new(),
+                error_on_last_run: false,
+                _token: TaskToken::default(),
+            }
+            .run(),
+        );
+
+        let wait = Duration::from_secs(10);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+
+        // Cancelling the current run restarts the task
+        cancel_tx.send(Some(0)).unwrap();
+        tokio::time::timeout(wait, runs_rx.wait_for(|runs| *runs == 1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cancelled.load(Ordering::SeqCst), 1);
+
+        finish.notify_one();
+        tokio::time::timeout(wait, runs_rx.wait_for(|runs| *runs == 2))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // The run finished before the cancellation arrived
+        cancel_tx.send(Some(1)).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(cancelled.load(Ordering::SeqCst), 1);
+        assert_eq!(*runs_rx.borrow(), 2);
+        assert_eq!(started.load(Ordering::SeqCst), 3);
+    }
+}
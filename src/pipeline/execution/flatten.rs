@@ -0,0 +1,511 @@
+//! Expands composite nodes into the nodes inside of them, see
+//! [composite::Node]. The executor runs the flattened pipeline, so a
+//! composite processes exactly like its nodes placed into the pipeline
+//! directly.
+//!
+//! Nodes inside of a composite get a namespaced id, see [namespaced], which
+//! stays the same as long as the composite keeps its id. Their tasks survive
+//! edits of the pipeline like the ones of any other node.
+
+use std::collections::HashMap;
+
+use crate::{
+    node_graph::{InputId, NodeId, NodeOutput, OutputId},
+    pipeline::{
+        nodes::{composite, DynPipelineNode},
+        Pipeline,
+    },
+};
+
+/// Bits of a namespaced id holding the id of the node inside of its
+/// composite.
+const INNER_BITS: u32 = 16;
+
+/// Ids of the nodes of a pipeline stay below, so they fit into a namespaced
+/// id, see [Pipeline::next_node_id].
+pub const NODE_ID_LIMIT: usize = 1 << INNER_BITS;
+
+/// How deep composites can be nested into each other, so the namespaced ids
+/// of the innermost nodes still fit.
+pub const MAX_DEPTH: usize = 3;
+
+/// Id of the node `inner` of the composite `composite` in the flattened
+/// pipeline. `composite` might be namespaced itself. [None], if it does not
+/// fit, because composites are nested too deep.
+pub fn namespaced(composite: NodeId, inner: NodeId) -> Option<NodeId> {
+    let composite: usize = composite.into();
+    let inner: usize = inner.into();
+    if inner >= NODE_ID_LIMIT {
+        return None;
+    }
+
+    let id = (composite.checked_add(1)?)
+        .checked_mul(1 << INNER_BITS)?
+        .checked_add(inner)?;
+    Some(id.into())
+}
+
+/// Id of a node in the flattened pipeline, `path` being the composites it is
+/// nested in, starting at the outermost.
+pub fn flat_id(path: &[NodeId], node_id: NodeId) -> Option<NodeId> {
+    let Some((first, rest)) = path.split_first() else {
+        return Some(node_id);
+    };
+
+    let mut id = *first;
+    for composite in rest.iter().copied().chain([node_id]) {
+        id = namespaced(id, composite)?;
+    }
+    Some(id)
+}
+
+/// The composite containing the node `node_id` of the flattened pipeline.
+/// [None] for nodes of the pipeline itself.
+pub fn parent(node_id: NodeId) -> Option<NodeId> {
+    let id: usize = node_id.into();
+    match id >> INNER_BITS {
+        0 => None,
+        composite => Some((composite - 1).into()),
+    }
+}
+
+/// The node of the pipeline itself, that contains the node `node_id` of the
+/// flattened pipeline, possibly the node itself.
+pub fn top_level(node_id: NodeId) -> NodeId {
+    match parent(node_id) {
+        Some(composite) => top_level(composite),
+        None => node_id,
+    }
+}
+
+/// Whether the node `node_id` of the flattened pipeline is `ancestor`, or
+/// inside of it.
+pub fn is_within(node_id: NodeId, ancestor: NodeId) -> bool {
+    node_id == ancestor || parent(node_id).is_some_and(|parent| is_within(parent, ancestor))
+}
+
+/// The node `node_id` of the flattened pipeline, in the scope of the
+/// pipeline containing it, with the scope and whether any composite around
+/// it is disabled.
+fn locate(pipeline: &Pipeline, node_id: NodeId) -> Option<(&Pipeline, NodeId, bool)> {
+    let Some(parent) = parent(node_id) else {
+        return Some((pipeline, node_id, false));
+    };
+
+    let (scope, composite_id, disabled) = locate(pipeline, parent)?;
+    let composite = scope
+        .nodes
+        .get(&composite_id)?
+        .as_any()
+        .downcast_ref::<composite::Node>()?;
+
+    let inner: usize = node_id.into();
+    let inner = NodeId::from(inner & ((1 << INNER_BITS) - 1));
+    Some((
+        &composite.pipeline,
+        inner,
+        disabled || scope.disabled.contains(&composite_id),
+    ))
+}
+
+fn locate_mut(pipeline: &mut Pipeline, node_id: NodeId) -> Option<(&mut Pipeline, NodeId)> {
+    let Some(parent) = parent(node_id) else {
+        return Some((pipeline, node_id));
+    };
+
+    let (scope, composite_id) = locate_mut(pipeline, parent)?;
+    let composite = scope
+        .nodes
+        .get_mut(&composite_id)?
+        .as_any_mut()
+        .downcast_mut::<composite::Node>()?;
+
+    let inner: usize = node_id.into();
+    let inner = NodeId::from(inner & ((1 << INNER_BITS) - 1));
+    Some((&mut composite.pipeline, inner))
+}
+
+/// The node `node_id` of the flattened pipeline, as it is in `pipeline`.
+pub fn node(pipeline: &Pipeline, node_id: NodeId) -> Option<&dyn DynPipelineNode> {
+    let (scope, node_id, _) = locate(pipeline, node_id)?;
+    scope.nodes.get(&node_id).map(|node| node.as_ref())
+}
+
+/// Whether the node `node_id` of the flattened pipeline or a composite around
+/// it is disabled.
+pub fn is_disabled(pipeline: &Pipeline, node_id: NodeId) -> bool {
+    locate(pipeline, node_id)
+        .is_some_and(|(scope, node_id, disabled)| disabled || scope.disabled.contains(&node_id))
+}
+
+/// Puts `node`, a copy of the node `node_id` of the flattened pipeline, back
+/// into `pipeline`, with the connections the node has there.
+pub fn restore(pipeline: &mut Pipeline, node_id: NodeId, mut node: Box<dyn DynPipelineNode>) {
+    let Some((scope, node_id)) = locate_mut(pipeline, node_id) else {
+        return;
+    };
+    let Some(original) = scope.nodes.get(&node_id) else {
+        return;
+    };
+
+    for (input_id, connection) in original.inputs() {
+        match connection {
+            Some(connection) => node.connect(input_id, connection),
+            None => node.disconnect(input_id),
+        }
+    }
+    scope.nodes.insert(node_id, node);
+}
+
+// MARK: Flattened
+
+/// A [Pipeline] with every composite expanded, see the [module
+/// docs](self).
+pub struct Flattened {
+    /// [None], if there was no composite to expand, so the pipeline is used
+    /// as it is.
+    pipeline: Option<Pipeline>,
+    /// The outputs of composites, by the output inside, that serves them.
+    outputs: HashMap<(NodeId, OutputId), NodeOutput>,
+}
+
+impl Flattened {
+    pub fn of(pipeline: &Pipeline) -> Self {
+        let mut flattened = Self {
+            pipeline: None,
+            outputs: HashMap::new(),
+        };
+
+        let has_composites = pipeline
+            .nodes
+            .values()
+            .any(|node| node.as_any().is::<composite::Node>());
+        if has_composites {
+            let mut flat = Pipeline::new();
+            flat.deterministic = pipeline.deterministic;
+            flattened.expand(pipeline, None, &HashMap::new(), false, &mut flat);
+            flattened.pipeline = Some(flat);
+        }
+
+        flattened
+    }
+
+    /// The flattened pipeline. `original` is the pipeline, this was created
+    /// from.
+    pub fn pipeline<'a>(&'a self, original: &'a Pipeline) -> &'a Pipeline {
+        self.pipeline.as_ref().unwrap_or(original)
+    }
+
+    /// The output of a node of the flattened pipeline, that serves the output
+    /// of a composite, for every output of every composite.
+    pub fn into_outputs(self) -> HashMap<(NodeId, OutputId), NodeOutput> {
+        self.outputs
+    }
+
+    /// Adds the nodes of `scope`, which are inside of the composite `prefix`,
+    /// to `flat`. `pins` are the connections of the inputs of the composite,
+    /// for the inputs inside receiving them.
+    fn expand(
+        &mut self,
+        scope: &Pipeline,
+        prefix: Option<NodeId>,
+        pins: &HashMap<(NodeId, InputId), Option<NodeOutput>>,
+        disabled: bool,
+        flat: &mut Pipeline,
+    ) {
+        for (node_id, node) in &scope.nodes {
+            let Some(id) = id(prefix, *node_id) else {
+                eprintln!(
+                    "Node id does not fit into the flattened pipeline: {:?}",
+                    node_id
+                );
+                continue;
+            };
+            let node_disabled = disabled || scope.disabled.contains(node_id);
+
+            if let Some(composite) = node.as_any().downcast_ref::<composite::Node>() {
+                let mut inner_pins = HashMap::new();
+                for pin in &composite.inputs {
+                    let connection = pin
+                        .connection
+                        .and_then(|connection| resolve(scope, prefix, connection));
+                    for target in &pin.targets {
+                        inner_pins.insert(*target, connection);
+                    }
+                }
+
+                for (index, pin) in composite.outputs.iter().enumerate() {
+                    if let Some(output) = resolve(&composite.pipeline, Some(id), pin.output) {
+                        self.outputs.insert((id, index.into()), output);
+                    }
+                }
+
+                self.expand(
+                    &composite.pipeline,
+                    Some(id),
+                    &inner_pins,
+                    node_disabled,
+                    flat,
+                );
+                continue;
+            }
+
+            let mut node = node.clone_boxed();
+            for (input_id, connection) in node.inputs() {
+                let resolved = match connection {
+                    Some(connection) => resolve(scope, prefix, connection),
+                    None => pins.get(&(*node_id, input_id)).copied().flatten(),
+                };
+
+                match resolved {
+                    Some(resolved) if Some(resolved) != connection => {
+                        node.connect(input_id, resolved)
+                    }
+                    None if connection.is_some() => node.disconnect(input_id),
+                    _ => {}
+                }
+            }
+
+            flat.nodes.insert(id, node);
+            if node_disabled {
+                flat.disabled.insert(id);
+            }
+        }
+    }
+}
+
+/// Id of the node `node_id` of the composite `prefix` in the flattened
+/// pipeline. Ids of the pipeline itself beyond [NODE_ID_LIMIT] would be taken
+/// for ones inside of a composite, so they are [None] as well.
+fn id(prefix: Option<NodeId>, node_id: NodeId) -> Option<NodeId> {
+    match prefix {
+        Some(prefix) => namespaced(prefix, node_id),
+        None => parent(node_id).is_none().then_some(node_id),
+    }
+}
+
+/// The output in the flattened pipeline, that `connection` in `scope` refers
+/// to. Outputs of composites are followed to the node inside serving them.
+fn resolve(scope: &Pipeline, prefix: Option<NodeId>, connection: NodeOutput) -> Option<NodeOutput> {
+    let node_id = id(prefix, connection.node_id)?;
+
+    let composite = scope
+        .nodes
+        .get(&connection.node_id)
+        .and_then(|node| node.as_any().downcast_ref::<composite::Node>());
+    match composite {
+        Some(composite) => {
+            let output: usize = connection.output_id.into();
+            let pin = composite.outputs.get(output)?;
+            resolve(&composite.pipeline, Some(node_id), pin.output)
+        }
+        None => Some(NodeOutput {
+            node_id,
+            ..connection
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::{
+        gui::node_graph::NodeGraphEditState,
+        pipeline::{
+            nodes::{binary_input, filter, output, remove_detector_defect},
+            PipelineDataType,
+        },
+    };
+
+    use super::*;
+
+    /// Input → Remove Detector Defect → Gaussian → Output
+    fn pipeline() -> Pipeline {
+        let mut pipeline = Pipeline::new();
+        pipeline.nodes.insert(
+            1.into(),
+            Box::new(binary_input::Node::m_scan(PathBuf::new(), None)),
+        );
+        pipeline
+            .nodes
+            .insert(2.into(), Box::new(remove_detector_defect::Node::new()));
+        pipeline
+            .nodes
+            .insert(3.into(), Box::new(filter::Node::gaussian()));
+        pipeline
+            .nodes
+            .insert(4.into(), Box::new(output::Node::default()));
+
+        let m_scan = PipelineDataType::MScan.into();
+        for id in 2..=4usize {
+            let output = NodeOutput::new((id - 1).into(), 0.into(), m_scan);
+            pipeline[id.into()].connect(0.into(), output);
+        }
+        pipeline
+    }
+
+    /// Source of every connection, by the receiving node.
+    fn connections(pipeline: &Pipeline) -> Vec<(NodeId, Option<NodeId>)> {
+        let mut connections = pipeline
+            .nodes
+            .iter()
+            .flat_map(|(node_id, node)| {
+                node.inputs()
+                    .into_iter()
+                    .map(|(_, connection)| (*node_id, connection.map(|c| c.node_id)))
+            })
+            .collect::<Vec<_>>();
+        connections.sort();
+        connections
+    }
+
+    #[test]
+    fn ids() {
+        let composite = NodeId::from(7);
+        let inner = namespaced(composite, 3.into()).unwrap();
+        let innermost = namespaced(inner, 2.into()).unwrap();
+
+        assert_eq!(parent(inner), Some(composite));
+        assert_eq!(parent(innermost), Some(inner));
+        assert_eq!(parent(composite), None);
+        assert_eq!(top_level(innermost), composite);
+        assert!(is_within(innermost, composite));
+        assert!(is_within(inner, inner));
+        assert!(!is_within(composite, inner));
+        assert_eq!(flat_id(&[composite, 3.into()], 2.into()), Some(innermost));
+        assert_eq!(flat_id(&[], 2.into()), Some(2.into()));
+
+        assert_eq!(namespaced(composite, NODE_ID_LIMIT.into()), None);
+        assert_eq!(namespaced(usize::MAX.into(), 1.into()), None);
+
+        // Larger ids of the pipeline itself would be taken for nested ones
+        assert_eq!(id(None, NODE_ID_LIMIT.into()), None);
+        let mut pipeline = Pipeline::new();
+        pipeline.nodes.insert(
+            (NODE_ID_LIMIT - 1).into(),
+            Box::new(output::Node::default()),
+        );
+        assert_eq!(pipeline.next_node_id(), None);
+    }
+
+    #[test]
+    fn pipeline_without_composites_is_used_as_is() {
+        let pipeline = pipeline();
+        let flat = Flattened::of(&pipeline);
+        assert!(std::ptr::eq(flat.pipeline(&pipeline), &pipeline));
+    }
+
+    #[test]
+    fn composites_are_expanded() {
+        let mut pipeline = pipeline();
+        pipeline.disabled.insert(3.into());
+        let id = composite::collapse(
+            &mut pipeline,
+            &[2.into(), 3.into()],
+            NodeGraphEditState::new(),
+        )
+        .unwrap();
+
+        let flattened = Flattened::of(&pipeline);
+        let flat = flattened.pipeline(&pipeline);
+
+        let filter = namespaced(id, 3.into()).unwrap();
+        let defect = namespaced(id, 2.into()).unwrap();
+        assert_eq!(flat.nodes.len(), 4);
+        assert_eq!(
+            connections(flat),
+            vec![
+                (4.into(), Some(filter)),
+                (defect, Some(1.into())),
+                (filter, None),
+                (filter, Some(defect)),
+            ]
+        );
+        assert_eq!(flat.disabled, [filter].into());
+        assert_eq!(
+            flattened.outputs.get(&(id, 0.into())).map(|o| o.node_id),
+            Some(filter)
+        );
+
+        // Disabling the composite disables everything inside
+        pipeline.disabled.insert(id);
+        let flattened = Flattened::of(&pipeline);
+        assert_eq!(
+            flattened.pipeline(&pipeline).disabled,
+            [defect, filter].into()
+        );
+        assert!(is_disabled(&pipeline, defect));
+        assert!(!is_disabled(&pipeline, 4.into()));
+    }
+
+    #[test]
+    fn nested_composites_are_expanded() {
+        let mut pipeline = pipeline();
+        let inner = composite::collapse(
+            &mut pipeline,
+            &[2.into(), 3.into()],
+            NodeGraphEditState::new(),
+        )
+        .unwrap();
+        let outer =
+            composite::collapse(&mut pipeline, &[1.into(), inner], NodeGraphEditState::new())
+                .unwrap();
+
+        let flattened = Flattened::of(&pipeline);
+        let flat = flattened.pipeline(&pipeline);
+
+        let input = namespaced(outer, 1.into()).unwrap();
+        let defect = flat_id(&[outer, inner], 2.into()).unwrap();
+        let filter = flat_id(&[outer, inner], 3.into()).unwrap();
+        assert_eq!(
+            connections(flat),
+            vec![
+                (4.into(), Some(filter)),
+                (defect, Some(input)),
+                (filter, None),
+                (filter, Some(defect)),
+            ]
+        );
+        assert!(node(&pipeline, defect).is_some_and(|node| node.name() == "Remove Detector Defect"));
+    }
+
+    #[test]
+    fn flattening_keeps_unconnected_pins_disconnected() {
+        let mut pipeline = pipeline();
+        let id = composite::collapse(
+            &mut pipeline,
+            &[2.into(), 3.into()],
+            NodeGraphEditState::new(),
+        )
+        .unwrap();
+        pipeline[id].disconnect(0.into());
+
+        let flattened = Flattened::of(&pipeline);
+        let defect = namespaced(id, 2.into()).unwrap();
+        assert_eq!(
+            flattened.pipeline(&pipeline).nodes[&defect].inputs(),
+            vec![(0.into(), None)]
+        );
+    }
+
+    #[test]
+    fn restore_keeps_connections_inside() {
+        let mut pipeline = pipeline();
+        let id = composite::collapse(
+            &mut pipeline,
+            &[2.into(), 3.into()],
+            NodeGraphEditState::new(),
+        )
+        .unwrap();
+
+        let flattened = Flattened::of(&pipeline);
+        let filter = namespaced(id, 3.into()).unwrap();
+        let copy = flattened.pipeline(&pipeline).nodes[&filter].clone_boxed();
+        restore(&mut pipeline, filter, copy);
+
+        // Still connected to the node inside, not to the namespaced one
+        let restored = node(&pipeline, filter).unwrap();
+        assert_eq!(restored.inputs()[0].1.map(|c| c.node_id), Some(2.into()));
+    }
+}
@@ -1,9 +1,14 @@
 mod connection;
 mod executor;
+pub mod flatten;
+mod peek;
+mod transfer;
 
 pub use connection::*;
 pub use executor::*;
 use futures::{future::BoxFuture, Future};
+pub use peek::*;
+pub use transfer::*;
 
 use crate::node_graph::InputId;
 
@@ -74,11 +79,27 @@ impl<T: NodeTask + Send + Sync> DynNodeTask for T {
     }
 }
 
+/// Whether an output serves the same data again, when it is requested a
+/// second time in the same epoch, see [TaskInput::replay]. Declared by
+/// [PipelineNode::replay] and resolved along the pipeline by the
+/// [PipelineExecutor].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replay {
+    /// Computed again from the inputs, so it is the same, if they all replay.
+    /// Outputs of nodes without inputs always replay. Caches do not change
+    /// this, as they may evict the result at any time.
+    Inputs,
+    /// Might differ, when computed again.
+    Never,
+}
+
 pub enum InvalidationCause {
     Connected(InputId),
     Disconnected(InputId),
     Synced,
     InputInvalidated(InputId),
+    /// The user cancelled the current run, the configuration is unchanged.
+    UserCancelled,
 }
 
 /// Passed to [PipelineNode::create_node_task]. Use [NodeTaskBuilder::output] to
@@ -0,0 +1,208 @@
+use std::sync::{Arc, Mutex};
+
+use nalgebra::{DMatrix, Scalar};
+use num_traits::ToPrimitive;
+
+use crate::{
+    pipeline::types::{BScanDiameter, DataMatrix, DataVector},
+    settings::Settings,
+};
+
+/// Maximum width and height of [Peek::Image].
+pub const PEEK_IMAGE_SIZE: usize = 128;
+
+/// Maximum number of points of [Peek::Line].
+pub const PEEK_LINE_POINTS: usize = 256;
+
+/// Number of diameters kept by [Peek::Diameters].
+pub const PEEK_DIAMETERS: usize = 5;
+
+/// The last data sent through a [super::TaskOutput], small enough to be shown
+/// when hovering a connection. Captured from the send path of responses, see
+/// [super::Request::capture].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Peek {
+    /// The last chunk of an M scan as greyscale, downsampled to at most
+    /// [PEEK_IMAGE_SIZE] pixels in both directions. Rows are the samples of the A
+    /// scans, row major.
+    Image {
+        size: [usize; 2],
+        pixels: Vec<u8>,
+        /// A scans and samples of the chunk.
+        chunk: [usize; 2],
+    },
+    /// The last vector, like the depths of a segmentation chunk, downsampled
+    /// to at most [PEEK_LINE_POINTS] points.
+    Line(Vec<f32>),
+    /// The last [PEEK_DIAMETERS] diameters, oldest first.
+    Diameters(Vec<BScanDiameter>),
+    /// Vertices and triangles sent so far.
+    Mesh { vertices: usize, triangles: usize },
+}
+
+impl Peek {
+    pub fn image(m_scan: &DataMatrix) -> Self {
+        let chunk = [m_scan.ncols(), m_scan.nrows()];
+        let size = chunk.map(|len| len.min(PEEK_IMAGE_SIZE));
+
+        let pixels = match m_scan {
+            DataMatrix::U8(matrix) => sample_image(matrix, size, Some(u8::MAX as f64)),
+            DataMatrix::U16(matrix) => sample_image(matrix, size, Some(u16::MAX as f64)),
+            DataMatrix::U32(matrix) => sample_image(matrix, size, Some(u32::MAX as f64)),
+            DataMatrix::U64(matrix) => sample_image(matrix, size, Some(u64::MAX as f64)),
+            DataMatrix::F32(matrix) => sample_image(matrix, size, None),
+            DataMatrix::F64(matrix) => sample_image(matrix, size, None),
+        };
+
+        Peek::Image {
+            size,
+            pixels,
+            chunk,
+        }
+    }
+
+    pub fn line(values: impl ExactSizeIterator<Item = f32>) -> Self {
+        let len = values.len();
+        let points = len.min(PEEK_LINE_POINTS);
+
+        let mut point = 0;
+        Peek::Line(
+            values
+                .enumerate()
+                .filter(|(i, _)| {
+                    let sampled = *i == point * len / points;
+                    point += sampled as usize;
+                    sampled
+                })
+                .map(|(_, value)| value)
+                .collect(),
+        )
+    }
+
+    pub fn vector(vector: &DataVector) -> Self {
+        match vector {
+            DataVector::U8(v) => Peek::line(v.iter().map(|&v| v as f32)),
+            DataVector::U16(v) => Peek::line(v.iter().map(|&v| v as f32)),
+            DataVector::U32(v) => Peek::line(v.iter().map(|&v| v as f32)),
+            DataVector::U64(v) => Peek::line(v.iter().map(|&v| v as f32)),
+            DataVector::F32(v) => Peek::line(v.iter().copied()),
+            DataVector::F64(v) => Peek::line(v.iter().map(|&v| v as f32)),
+        }
+    }
+}
+
+/// Samples `matrix` at `size` points, mapping values from 0 to `max` onto
+/// the greyscale. Without `max`, the range of the samples is used.
+fn sample_image<T: Scalar + ToPrimitive>(
+    matrix: &DMatrix<T>,
+    [width, height]: [usize; 2],
+    max: Option<f64>,
+) -> Vec<u8> {
+    let samples = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (y, x)))
+        .map(|(y, x)| {
+            let value = &matrix[(y * matrix.nrows() / height, x * matrix.ncols() / width)];
+            value.to_f64().filter(|v| v.is_finite()).unwrap_or(0.0)
+        })
+        .collect::<Vec<_>>();
+
+    let (min, max) = match max {
+        Some(max) => (0.0, max),
+        None => samples
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+                (min.min(v), max.max(v))
+            }),
+    };
+    let scale = match max > min {
+        true => 255.0 / (max - min),
+        false => 0.0,
+    };
+
+    samples
+        .into_iter()
+        .map(|v| ((v - min) * scale).clamp(0.0, 255.0) as u8)
+        .collect()
+}
+
+/// The last [Peek] of an output. Holds at most one entry, which every chunk
+/// replaces, and is cleared, when the output gets invalidated.
+#[derive(Debug, Default)]
+pub struct PeekBuffer(Mutex<Option<Arc<Peek>>>);
+
+impl PeekBuffer {
+    /// Whether data of M scans, or of the other, small data types is
+    /// captured, see [crate::settings::PerformanceSettings].
+    pub fn is_enabled(m_scan: bool) -> bool {
+        let performance = Settings::current().performance;
+        match m_scan {
+            true => performance.m_scan_previews,
+            false => performance.connection_previews,
+        }
+    }
+
+    pub fn store(&self, peek: Peek) {
+        *self.0.lock().unwrap() = Some(Arc::new(peek));
+    }
+
+    pub fn get(&self) -> Option<Arc<Peek>> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        *self.0.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn downsampled_image() {
+        let m_scan = DataMatrix::U8(DMatrix::from_fn(512, 64, |row, col| match col < 32 {
+            true => (row / 2) as u8,
+            false => 255,
+        }));
+
+        let Peek::Image {
+            size,
+            pixels,
+            chunk,
+        } = Peek::image(&m_scan)
+        else {
+            unreachable!()
+        };
+        assert_eq!(size, [64, PEEK_IMAGE_SIZE]);
+        assert_eq!(chunk, [64, 512]);
+        assert_eq!(pixels.len(), 64 * PEEK_IMAGE_SIZE);
+
+        // Every fourth sample of the first A scan, and a bright A scan
+        assert_eq!(
+            pixels[..3 * 64].iter().step_by(64).collect::<Vec<_>>(),
+            [&0, &2, &4]
+        );
+        assert_eq!(pixels[63], 255);
+
+        // Floating point values are stretched over their range
+        let m_scan = DataMatrix::F32(DMatrix::from_fn(2, 2, |row, _| row as f32 * 10.0 - 5.0));
+        let Peek::Image { pixels, .. } = Peek::image(&m_scan) else {
+            unreachable!()
+        };
+        assert_eq!(pixels, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn downsampled_line() {
+        assert_eq!(
+            Peek::line((0..4).map(|v| v as f32)),
+            Peek::Line(vec![0.0, 1.0, 2.0, 3.0])
+        );
+
+        let Peek::Line(points) = Peek::line((0..1024).map(|v| v as f32)) else {
+            unreachable!()
+        };
+        assert_eq!(points.len(), PEEK_LINE_POINTS);
+        assert_eq!(points[1], 4.0);
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::pipeline::types::DataType;
+
+/// Counts the data sent through a [super::TaskOutput] since its last
+/// invalidation. Updated from the send path of streamed responses, see
+/// [super::Request::track_transfer].
+#[derive(Debug, Default)]
+pub struct TransferStats {
+    chunks: AtomicUsize,
+    bytes: AtomicU64,
+    /// Time of the first request or chunk and of the last chunk, see
+    /// [timestamp]. Zero, if there was none.
+    started: AtomicU64,
+    finished: AtomicU64,
+    /// Index into [DataType::VALUES] of the last M scan chunk, plus one. Zero,
+    /// if there was none.
+    data_type: AtomicU8,
+}
+
+/// The state of a [TransferStats] at one point in time.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TransferSnapshot {
+    pub chunks: usize,
+    pub bytes: u64,
+    /// Type of the last M scan chunk, see [TransferStats::record_data_type].
+    pub data_type: Option<DataType>,
+}
+
+impl TransferStats {
+    /// Count one chunk of `bytes` bytes.
+    pub fn record(&self, bytes: usize) {
+        self.chunks.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+
+        let now = timestamp();
+        self.start_at(now);
+        self.finished.fetch_max(now, Ordering::Relaxed);
+    }
+
+    /// Remember the type of the last M scan chunk.
+    pub fn record_data_type(&self, data_type: DataType) {
+        let index = DataType::VALUES.iter().position(|t| *t == data_type);
+        self.data_type
+            .store(index.map_or(0, |i| i as u8 + 1), Ordering::Relaxed);
+    }
+
+    /// The output received a request. Starts the time of [Self::elapsed], if
+    /// not already.
+    pub fn start(&self) {
+        self.start_at(timestamp());
+    }
+
+    pub fn reset(&self) {
+        self.chunks.store(0, Ordering::Relaxed);
+        self.bytes.store(0, Ordering::Relaxed);
+        self.started.store(0, Ordering::Relaxed);
+        self.finished.store(0, Ordering::Relaxed);
+        self.data_type.store(0, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> TransferSnapshot {
+        TransferSnapshot {
+            chunks: self.chunks.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            data_type: match self.data_type.load(Ordering::Relaxed) {
+                0 => None,
+                index => DataType::VALUES.get(index as usize - 1).copied(),
+            },
+        }
+    }
+
+    /// Time from the first request to the last chunk. [None], if nothing was
+    /// sent yet.
+    pub fn elapsed(&self) -> Option<Duration> {
+        let started = self.started.load(Ordering::Relaxed);
+        let finished = self.finished.load(Ordering::Relaxed);
+
+        match (started, finished) {
+            (0, _) | (_, 0) => None,
+            _ => Some(Duration::from_nanos(finished.saturating_sub(started))),
+        }
+    }
+
+    fn start_at(&self, now: u64) {
+        let _ = self
+            .started
+            .compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed);
+    }
+}
+
+/// Nanoseconds since the first call, starting at 1, so zero can mark unset
+/// times.
+fn timestamp() -> u64 {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+    EPOCH.get_or_init(Instant::now).elapsed().as_nanos() as u64 + 1
+}
@@ -0,0 +1,493 @@
+//! Golden file regression tests for whole pipelines.
+//!
+//! Every case in [GOLDEN_DIR] is a pipeline `<case>.json`, which is run
+//! headlessly in deterministic mode, see [super::determinism], on the
+//! synthetic input fixtures in the same directory. The files
+//! written by its output nodes are compared with `<case>.golden.json`: The
+//! SHA-256 digest of the exported bytes must match, or else every sampled
+//! value must lie within the tolerance of the export. On a mismatch, the chain
+//! of nodes upstream of the output node is printed.
+//!
+//! Set [BLESS_VAR] to regenerate the goldens after an intentional change of
+//! numeric behavior. Tolerances of existing goldens are kept:
+//!
+//! ```sh
+//! GOLDEN_BLESS=1 cargo test golden
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    gui::node_graph::EditNodeGraph,
+    node_graph::NodeId,
+    pipeline::{
+        nodes::{binary_input, output},
+        sample_data,
+        types::DataType,
+        Pipeline, PipelineDataType, PipelineExecutor,
+    },
+};
+
+const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/golden");
+
+/// Environment variable, that rewrites the goldens instead of comparing them.
+const BLESS_VAR: &str = "GOLDEN_BLESS";
+
+/// Number of values sampled from each export.
+const SAMPLE_COUNT: usize = 16;
+
+/// How long a case may take to export all outputs.
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+// MARK: Golden
+
+/// How the values of an export are read.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum Format {
+    /// Little endian values of the given type.
+    Binary(DataType),
+    /// Every number in the text, for example in a CSV file. Lines starting
+    /// with `#` are comments.
+    Text,
+}
+
+impl Format {
+    fn of(node: &output::Node) -> Self {
+        match node.input_type {
+            PipelineDataType::RawMScan | PipelineDataType::MScan => {
+                Format::Binary(node.scan_data_type)
+            }
+            PipelineDataType::BScanSegmentation | PipelineDataType::MScanSegmentation => {
+                Format::Binary(DataType::U32)
+            }
+            // The vector is written in the data type it was read in
+            PipelineDataType::DataVector => Format::Binary(DataType::U8),
+            PipelineDataType::Diameter | PipelineDataType::Mesh | PipelineDataType::Bundle => {
+                Format::Text
+            }
+        }
+    }
+
+    fn default_tolerance(&self) -> f64 {
+        match self {
+            Format::Binary(data_type) if data_type.is_integer() => 1.0,
+            _ => 1e-4,
+        }
+    }
+
+    fn values(&self, bytes: &[u8]) -> Vec<f64> {
+        fn read<T: bytemuck::Pod + Into<f64>>(bytes: &[u8]) -> Vec<f64> {
+            bytes
+                .chunks_exact(std::mem::size_of::<T>())
+                .map(|b| bytemuck::pod_read_unaligned::<T>(b).into())
+                .collect()
+        }
+
+        match self {
+            Format::Binary(DataType::U8) => read::<u8>(bytes),
+            Format::Binary(DataType::U16) => read::<u16>(bytes),
+            Format::Binary(DataType::U32) => read::<u32>(bytes),
+            Format::Binary(DataType::U64) => bytes
+                .chunks_exact(8)
+                .map(|b| bytemuck::pod_read_unaligned::<u64>(b) as f64)
+                .collect(),
+            Format::Binary(DataType::F32) => read::<f32>(bytes),
+            Format::Binary(DataType::F64) => read::<f64>(bytes),
+            Format::Text => String::from_utf8_lossy(bytes)
+                .lines()
+                .filter(|line| !line.starts_with('#'))
+                .flat_map(|line| {
+                    line.split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+                        .filter_map(|s| s.parse().ok())
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Expected export of one output node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Expected {
+    sha256: String,
+    /// Length in bytes.
+    len: usize,
+    format: Format,
+    /// Maximum absolute difference of the sampled values.
+    tolerance: f64,
+    /// Evenly spaced values of the export, by index.
+    samples: BTreeMap<usize, f64>,
+}
+
+impl Expected {
+    fn new(bytes: &[u8], format: Format, tolerance: f64) -> Self {
+        let values = format.values(bytes);
+
+        // Includes the first and last value
+        let last = values.len().saturating_sub(1);
+        let samples = (0..SAMPLE_COUNT)
+            .map(|i| i * last / (SAMPLE_COUNT - 1))
+            .filter_map(|i| Some((i, *values.get(i)?)))
+            .collect();
+
+        Self {
+            sha256: sha256(bytes),
+            len: bytes.len(),
+            format,
+            tolerance,
+            samples,
+        }
+    }
+
+    /// Compares an export with this golden. Returns a note, if only the
+    /// samples matched.
+    fn compare(&self, bytes: &[u8]) -> Result<Option<String>, String> {
+        let sha256 = sha256(bytes);
+        if sha256 == self.sha256 {
+            return Ok(None);
+        }
+
+        if self.tolerance == 0.0 {
+            return Err(format!("digest {} differs from {}", sha256, self.sha256));
+        }
+
+        if bytes.len() != self.len {
+            return Err(format!(
+                "length {} differs from {} bytes",
+                bytes.len(),
+                self.len
+            ));
+        }
+
+        let values = self.format.values(bytes);
+        let mismatches = self
+            .samples
+            .iter()
+            .filter_map(|(&i, &expected)| {
+                let actual = values.get(i).copied().unwrap_or(f64::NAN);
+                // Also fails for NaN
+                match (actual - expected).abs() <= self.tolerance {
+                    true => None,
+                    false => Some(format!("[{}] = {} instead of {}", i, actual, expected)),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        match mismatches.is_empty() {
+            true => Ok(Some(format!(
+                "digest differs, but all samples are within {}",
+                self.tolerance
+            ))),
+            false => Err(format!(
+                "samples differ by more than {}: {}",
+                self.tolerance,
+                mismatches.join(", ")
+            )),
+        }
+    }
+}
+
+/// Expected exports of a case, by file name of the output node.
+type Golden = BTreeMap<String, Expected>;
+
+fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// MARK: Harness
+
+/// An output node of a case, with the file it exports to.
+struct Export {
+    node_id: NodeId,
+    file_name: String,
+    path: PathBuf,
+    format: Format,
+}
+
+/// Points the input nodes to the fixtures and the output nodes to `out_dir`.
+fn redirect_paths(pipeline: &mut Pipeline, out_dir: &Path) -> Vec<Export> {
+    let mut exports = Vec::new();
+
+    for (node_id, node) in &mut pipeline.nodes {
+        if let Some(node) = node.as_any_mut().downcast_mut::<binary_input::Node>() {
+            node.path = Path::new(GOLDEN_DIR).join(&node.path);
+        } else if let Some(node) = node.as_any_mut().downcast_mut::<output::Node>() {
+            let file_name = node.path.to_string_lossy().into_owned();
+            node.path = out_dir.join(&file_name);
+
+            exports.push(Export {
+                node_id: *node_id,
+                file_name,
+                path: node.path.clone(),
+                format: Format::of(node),
+            });
+        }
+    }
+
+    exports.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    exports
+}
+
+/// Describes the nodes upstream of `node_id`, one node per line, indented by
+/// their distance.
+fn describe_chain(pipeline: &mut Pipeline, node_id: NodeId) -> String {
+    fn describe(pipeline: &mut Pipeline, node_id: NodeId, depth: usize, out: &mut String) {
+        let Some(node) = pipeline.get_node_mut(node_id) else {
+            return;
+        };
+        let id: usize = node_id.into();
+        *out += &format!("{}{} (#{})\n", "  ".repeat(depth + 1), node.name(), id);
+
+        let inputs = pipeline.nodes[&node_id].inputs();
+        for (_, input) in inputs {
+            if let Some(input) = input {
+                describe(pipeline, input.node_id, depth + 1, out);
+            }
+        }
+    }
+
+    let mut out = String::new();
+    describe(pipeline, node_id, 0, &mut out);
+    out
+}
+
+/// Loads the pipeline of `case`.
+fn load_case(case: &str) -> Pipeline {
+    let path = Path::new(GOLDEN_DIR).join(format!("{}.json", case));
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+/// Runs `pipeline` and returns the bytes exported by every output node, as
+/// the pipeline of `case`.
+async fn export(case: &str, pipeline: &mut Pipeline) -> (Vec<Export>, Vec<Vec<u8>>) {
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    let out_dir = std::env::temp_dir().join(format!(
+        "ivoct_golden_{}_{}_{}",
+        case,
+        std::process::id(),
+        RUNS.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::create_dir_all(&out_dir).unwrap();
+
+    let exports = redirect_paths(pipeline, &out_dir);
+    assert!(!exports.is_empty(), "Case {} has no output node", case);
+
+    let mut executor = PipelineExecutor::new();
+    executor.update(pipeline);
+
+    for export in &exports {
+        let node = pipeline.nodes.get_mut(&export.node_id).unwrap();
+        let node = node.as_any_mut().downcast_mut::<output::Node>().unwrap();
+        node.save();
+
+        let mut saves_rx = node.saves_rx.clone().unwrap();
+        tokio::time::timeout(TIMEOUT, saves_rx.wait_for(|&saves| saves > 0))
+            .await
+            .unwrap_or_else(|_| panic!("Case {}: {} was not saved", case, export.file_name))
+            .unwrap();
+    }
+
+    drop(executor);
+
+    let actual = exports
+        .iter()
+        .map(|export| fs::read(&export.path).unwrap())
+        .collect::<Vec<_>>();
+
+    fs::remove_dir_all(&out_dir).unwrap();
+
+    // Usually a node task failed or panicked
+    for (export, bytes) in exports.iter().zip(&actual) {
+        assert!(
+            !bytes.is_empty(),
+            "Case {}: {} is empty, the pipeline exported nothing:\n{}",
+            case,
+            export.file_name,
+            describe_chain(pipeline, export.node_id)
+        );
+    }
+
+    (exports, actual)
+}
+
+/// Runs the pipeline of `case` and compares its exports with the golden.
+async fn run_case(case: &str) {
+    let dir = Path::new(GOLDEN_DIR);
+
+    let mut pipeline = load_case(case);
+    pipeline.deterministic = true;
+    let (exports, actual) = export(case, &mut pipeline).await;
+
+    let golden_path = dir.join(format!("{}.golden.json", case));
+    let golden: Golden = fs::read_to_string(&golden_path)
+        .map(|json| serde_json::from_str(&json).unwrap())
+        .unwrap_or_default();
+
+    if std::env::var_os(BLESS_VAR).is_some() {
+        let blessed = exports
+            .iter()
+            .zip(&actual)
+            .map(|(export, bytes)| {
+                let tolerance = golden
+                    .get(&export.file_name)
+                    .map(|e| e.tolerance)
+                    .unwrap_or(export.format.default_tolerance());
+                let expected = Expected::new(bytes, export.format, tolerance);
+                (export.file_name.clone(), expected)
+            })
+            .collect::<Golden>();
+
+        let json = serde_json::to_string_pretty(&blessed).unwrap();
+        fs::write(&golden_path, json + "\n").unwrap();
+        return;
+    }
+
+    let mut failures = Vec::new();
+
+    for (export, bytes) in exports.iter().zip(&actual) {
+        let result = match golden.get(&export.file_name) {
+            Some(expected) => expected.compare(bytes),
+            None => Err(format!("no golden, run with {}=1 to create it", BLESS_VAR)),
+        };
+
+        match result {
+            Ok(None) => {}
+            Ok(Some(note)) => println!("{}/{}: {}", case, export.file_name, note),
+            Err(e) => failures.push(format!(
+                "{}/{}: {}\n{}",
+                case,
+                export.file_name,
+                e,
+                describe_chain(&mut pipeline, export.node_id)
+            )),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "Exports differ from the golden, diverging node chains:\n{}",
+        failures.join("\n")
+    );
+}
+
+// MARK: Cases
+
+#[tokio::test(flavor = "multi_thread")]
+async fn process_raw_m_scan() {
+    run_case("process_raw_m_scan").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn gaussian_median() {
+    run_case("gaussian_median").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn follow_catheter_lumen() {
+    run_case("follow_catheter_lumen").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn diameter() {
+    run_case("diameter").await;
+}
+
+/// Runs a chain with a parallel reduction twice, see [super::determinism].
+#[tokio::test(flavor = "multi_thread")]
+async fn deterministic_runs_are_identical() {
+    let mut json = serde_json::to_value(load_case("gaussian_median")).unwrap();
+    json["nodes"]["6"]["filter_type"] = "Wiener".into();
+    json["nodes"]["7"]["scan_data_type"] = "F32".into();
+
+    let run = |deterministic: bool| {
+        let mut pipeline: Pipeline = serde_json::from_value(json.clone()).unwrap();
+        pipeline.deterministic = deterministic;
+        async move { export("deterministic", &mut pipeline).await.1.remove(0) }
+    };
+
+    let first = run(true).await;
+    assert_eq!(sha256(&first), sha256(&run(true).await));
+
+    // Parallel sums may differ in the last bits
+    let expected = Expected::new(&run(false).await, Format::Binary(DataType::F32), 1e-4);
+    assert!(expected.compare(&run(false).await).is_ok());
+}
+
+#[test]
+fn compare_within_tolerance() {
+    let values = [1.0f32, 2.0, 3.0];
+    let expected = Expected::new(
+        bytemuck::cast_slice(&values),
+        Format::Binary(DataType::F32),
+        0.01,
+    );
+    assert_eq!(expected.compare(bytemuck::cast_slice(&values)), Ok(None));
+
+    let close = [1.0f32, 2.005, 3.0];
+    assert!(matches!(
+        expected.compare(bytemuck::cast_slice(&close)),
+        Ok(Some(_))
+    ));
+
+    let far = [1.0f32, 2.1, 3.0];
+    assert!(expected.compare(bytemuck::cast_slice(&far)).is_err());
+    assert!(expected
+        .compare(bytemuck::cast_slice(&values[..2]))
+        .is_err());
+
+    let exact = Expected {
+        tolerance: 0.0,
+        ..expected
+    };
+    assert!(exact.compare(bytemuck::cast_slice(&close)).is_err());
+
+    let csv = Format::Text.values(b"# schema version 1\n1,0.5,0.75\n2,0.25,1e-3\n");
+    assert_eq!(csv, [1.0, 0.5, 0.75, 2.0, 0.25, 1e-3]);
+}
+
+// MARK: Fixtures
+
+/// Number of A scans of the fixtures.
+const A_SCAN_COUNT: usize = 768;
+
+/// Writes the synthetic raw M scan, chirp and offset, see [sample_data]. The
+/// fixtures are checked in, run with `cargo test write_fixtures -- --ignored`
+/// after changing the sample data.
+#[test]
+#[ignore]
+fn write_fixtures() {
+    sample_data::write(Path::new(GOLDEN_DIR), A_SCAN_COUNT, |_| {}).unwrap();
+}
+
+#[test]
+fn fixtures_match_sample_data() {
+    let dir = std::env::temp_dir().join(format!("ivoct_golden_fixtures_{}", std::process::id()));
+    sample_data::write(&dir, A_SCAN_COUNT, |_| {}).unwrap();
+
+    for file in [
+        sample_data::RAW_FILE,
+        sample_data::CHIRP_FILE,
+        sample_data::OFFSET_FILE,
+    ] {
+        assert!(
+            fs::read(dir.join(file)).unwrap()
+                == fs::read(Path::new(GOLDEN_DIR).join(file)).unwrap(),
+            "{} differs from the sample data",
+            file
+        );
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,386 @@
+//! Rough estimates of the memory a pipeline needs while running, to warn
+//! before a run exhausts the memory of the machine.
+//!
+//! Every node estimates its own needs with
+//! [PipelineNode::estimate_memory](super::nodes::PipelineNode::estimate_memory)
+//! from the stream it receives, described by [UpstreamStats]. Input nodes
+//! derive the stream from their files. [estimate] walks the pipeline from its
+//! inputs and adds up the estimates. The numbers are meant to be within a
+//! factor of two, not exact.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::node_graph::NodeId;
+
+use super::{types::DataType, Pipeline};
+
+/// Share of the available memory, above which a projected peak is warned
+/// about.
+pub const WARN_FRACTION: f64 = 0.8;
+
+// MARK: UpstreamStats
+
+/// Shape of the stream a node receives.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UpstreamStats {
+    pub a_scan_count: usize,
+    /// Samples per A scan.
+    pub samples: usize,
+    pub data_type: DataType,
+    /// A scans per chunk.
+    pub chunk_size: usize,
+}
+
+impl UpstreamStats {
+    /// No data at all, received by nodes without inputs.
+    pub const NONE: UpstreamStats = UpstreamStats {
+        a_scan_count: 0,
+        samples: 0,
+        data_type: DataType::U8,
+        chunk_size: 0,
+    };
+
+    /// A stream of one value per A scan, like a segmentation.
+    pub fn per_a_scan(&self, data_type: DataType) -> Self {
+        Self {
+            samples: 1,
+            data_type,
+            ..*self
+        }
+    }
+
+    /// Size of one full chunk.
+    pub fn chunk_bytes(&self) -> u64 {
+        self.chunk_size.min(self.a_scan_count) as u64 * self.a_scan_bytes()
+    }
+
+    /// Size of the whole stream.
+    pub fn stream_bytes(&self) -> u64 {
+        self.a_scan_count as u64 * self.a_scan_bytes()
+    }
+
+    /// Number of chunks in the stream.
+    pub fn chunk_count(&self) -> usize {
+        match self.chunk_size {
+            0 => 0,
+            chunk_size => self.a_scan_count.div_ceil(chunk_size),
+        }
+    }
+
+    fn a_scan_bytes(&self) -> u64 {
+        (self.samples * self.data_type.size()) as u64
+    }
+}
+
+// MARK: MemoryEstimate
+
+/// Memory a node needs while running.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryEstimate {
+    /// Peak working set while processing a chunk, including the chunk
+    /// received and the one produced.
+    pub per_chunk: u64,
+    /// Buffers kept across chunks, like held back chunks or a whole cached
+    /// result.
+    pub buffers: u64,
+    /// The stream the node sends downstream.
+    pub output: UpstreamStats,
+}
+
+impl MemoryEstimate {
+    /// A node transforming every chunk into one of `output`, without keeping
+    /// anything.
+    pub fn streaming(upstream: &UpstreamStats, output: UpstreamStats) -> Self {
+        Self {
+            per_chunk: upstream.chunk_bytes() + output.chunk_bytes(),
+            buffers: 0,
+            output,
+        }
+    }
+
+    /// Adds buffers kept across chunks.
+    pub fn with_buffers(mut self, buffers: u64) -> Self {
+        self.buffers += buffers;
+        self
+    }
+
+    pub fn total(&self) -> u64 {
+        self.per_chunk + self.buffers
+    }
+}
+
+// MARK: Estimation
+
+/// Estimate of a single node within a pipeline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeMemory {
+    pub estimate: MemoryEstimate,
+    /// Chunks of the output waiting for slow receivers, at most the capacity
+    /// of a stream.
+    pub queued: u64,
+    /// Sum of the nodes along the most demanding path from an input node up
+    /// to and including this node.
+    pub path_total: u64,
+}
+
+impl NodeMemory {
+    pub fn total(&self) -> u64 {
+        self.estimate.total() + self.queued
+    }
+}
+
+/// Estimate of a whole pipeline.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PipelineMemory {
+    pub nodes: HashMap<NodeId, NodeMemory>,
+    /// All nodes stream at the same time, so the projected peak is the sum
+    /// of all of them.
+    pub peak: u64,
+}
+
+impl PipelineMemory {
+    /// Whether `bytes` is more than [WARN_FRACTION] of `available`.
+    pub fn exceeds(bytes: u64, available: u64) -> bool {
+        bytes as f64 > available as f64 * WARN_FRACTION
+    }
+
+    /// Nodes whose path exceeds the threshold for `available` memory.
+    pub fn nodes_exceeding(&self, available: u64) -> impl Iterator<Item = (NodeId, &NodeMemory)> {
+        self.nodes
+            .iter()
+            .filter(move |(_, node)| Self::exceeds(node.path_total, available))
+            .map(|(node_id, node)| (*node_id, node))
+    }
+}
+
+/// Estimates every node of `pipeline`, with streams holding up to
+/// `stream_capacity` chunks. Nodes with several inputs are estimated from the
+/// largest one.
+pub fn estimate(pipeline: &Pipeline, stream_capacity: usize) -> PipelineMemory {
+    let mut memory = PipelineMemory::default();
+    let mut visiting = HashSet::new();
+
+    for node_id in pipeline.nodes.keys() {
+        estimate_node(
+            pipeline,
+            *node_id,
+            stream_capacity,
+            &mut memory,
+            &mut visiting,
+        );
+    }
+
+    memory.peak = memory.nodes.values().map(NodeMemory::total).sum();
+    memory
+}
+
+fn estimate_node(
+    pipeline: &Pipeline,
+    node_id: NodeId,
+    stream_capacity: usize,
+    memory: &mut PipelineMemory,
+    visiting: &mut HashSet<NodeId>,
+) -> Option<NodeMemory> {
+    if let Some(node) = memory.nodes.get(&node_id) {
+        return Some(*node);
+    }
+    let node = pipeline.nodes.get(&node_id)?;
+
+    // Cycles cannot be run anyway
+    if !visiting.insert(node_id) {
+        return None;
+    }
+
+    let inputs = node
+        .inputs()
+        .into_iter()
+        .filter_map(|(_, output)| output)
+        .filter_map(|output| {
+            estimate_node(pipeline, output.node_id, stream_capacity, memory, visiting)
+        })
+        .collect::<Vec<_>>();
+
+    let upstream = inputs
+        .iter()
+        .map(|input| input.estimate.output)
+        .max_by_key(UpstreamStats::stream_bytes)
+        .unwrap_or(UpstreamStats::NONE);
+
+    let estimate = node.estimate_memory(&upstream);
+    let queued =
+        estimate.output.chunk_count().min(stream_capacity) as u64 * estimate.output.chunk_bytes();
+
+    let mut node_memory = NodeMemory {
+        estimate,
+        queued,
+        path_total: 0,
+    };
+    node_memory.path_total = node_memory.total()
+        + inputs
+            .iter()
+            .map(|input| input.path_total)
+            .max()
+            .unwrap_or(0);
+
+    visiting.remove(&node_id);
+    memory.nodes.insert(node_id, node_memory);
+    Some(node_memory)
+}
+
+/// Memory the system can still hand out, in bytes.
+pub fn available_memory() -> u64 {
+    let mut system = sysinfo::System::new();
+    system.refresh_memory();
+    system.available_memory()
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use crate::pipeline::nodes::rechunk::B_SCAN_WIDTH;
+
+    use super::*;
+
+    const MB: u64 = 1_000_000;
+
+    fn stats(a_scan_count: usize, samples: usize, data_type: DataType) -> UpstreamStats {
+        UpstreamStats {
+            a_scan_count,
+            samples,
+            data_type,
+            chunk_size: 1000,
+        }
+    }
+
+    #[test]
+    fn stream_arithmetic() {
+        let stats = stats(10_500, 1024, DataType::U16);
+
+        assert_eq!(stats.chunk_bytes(), 1000 * 1024 * 2);
+        assert_eq!(stats.stream_bytes(), 10_500 * 1024 * 2);
+        assert_eq!(stats.chunk_count(), 11);
+        assert_eq!(stats.per_a_scan(DataType::F32).stream_bytes(), 10_500 * 4);
+
+        // Streams shorter than a chunk
+        let short = UpstreamStats {
+            a_scan_count: 10,
+            ..stats
+        };
+        assert_eq!(short.chunk_bytes(), 10 * 1024 * 2);
+        assert_eq!(UpstreamStats::NONE.chunk_count(), 0);
+
+        let estimate = MemoryEstimate::streaming(&stats, stats.per_a_scan(DataType::F32));
+        assert_eq!(estimate.per_chunk, 1000 * 1024 * 2 + 1000 * 4);
+        assert_eq!(estimate.with_buffers(5).total(), estimate.per_chunk + 5);
+    }
+
+    fn pipeline(nodes: serde_json::Value) -> Pipeline {
+        serde_json::from_value(json!({ "nodes": nodes })).unwrap()
+    }
+
+    fn input(node_id: usize, output_id: usize, type_id: usize) -> serde_json::Value {
+        json!({ "value": null, "connection": {
+            "node_id": node_id, "output_id": output_id, "type_id": type_id,
+        }})
+    }
+
+    /// A raw file of 12000 A scans of 1024 U16 samples, 24.6 MB.
+    fn raw_input() -> serde_json::Value {
+        let raw = std::env::temp_dir().join("ivoct_memory_estimate.bin");
+        std::fs::write(&raw, vec![0u8; 12_000 * 1024 * 2]).unwrap();
+        binary_input(&raw)
+    }
+
+    fn binary_input(path: &Path) -> serde_json::Value {
+        json!({ "type": "binary_input", "path": path, "input_type": "RawMScan",
+                "data_type": "U16", "a_scan_length": 1024 })
+    }
+
+    #[test]
+    fn input_from_file_size() {
+        let memory = estimate(&pipeline(json!({ "1": raw_input() })), 100);
+        let node = memory.nodes[&NodeId::from(1)];
+
+        assert_eq!(node.estimate.output.a_scan_count, 12_000);
+        assert_eq!(node.estimate.output.samples, 1024);
+        // The whole file fits into the queue
+        assert_eq!(node.queued, 12_000 * 1024 * 2);
+        assert_eq!(memory.peak, node.total());
+
+        // Missing files stream nothing
+        let missing = Path::new("/nonexistent/ivoct.bin");
+        let memory = estimate(&pipeline(json!({ "1": binary_input(missing) })), 100);
+        assert_eq!(memory.peak, 0);
+    }
+
+    #[test]
+    fn chain_of_nodes() {
+        let memory = estimate(
+            &pipeline(json!({
+                "1": raw_input(),
+                "2": { "type": "process_raw_m_scan", "raw_scan": input(1, 0, 0) },
+                "3": { "type": "filter", "filter_type": "Median", "input": input(2, 0, 2),
+                       "cache_settings": { "enabled": true } },
+            })),
+            2,
+        );
+
+        let raw = &memory.nodes[&NodeId::from(1)];
+        let processed = &memory.nodes[&NodeId::from(2)];
+        let filtered = &memory.nodes[&NodeId::from(3)];
+
+        // Processing halves the samples and works in F32
+        let output = processed.estimate.output;
+        assert_eq!((output.samples, output.data_type), (512, DataType::F32));
+        assert_eq!(output.stream_bytes(), 12_000 * 512 * 4);
+
+        // The median keeps the data type, the cache keeps the whole result
+        assert_eq!(filtered.estimate.output.data_type, DataType::F32);
+        assert_eq!(filtered.estimate.buffers, 12_000 * 512 * 4);
+
+        // Paths add up from the input
+        assert_eq!(raw.path_total, raw.total());
+        assert_eq!(processed.path_total, raw.total() + processed.total());
+        assert_eq!(filtered.path_total, processed.path_total + filtered.total());
+        assert_eq!(memory.peak, filtered.path_total);
+
+        // Within 2x of the 24.6 MB input, the 24.6 MB processed and the 24.6
+        // MB cached result, plus working sets and queues
+        assert!(
+            (70 * MB..300 * MB).contains(&memory.peak),
+            "{}",
+            memory.peak
+        );
+
+        assert_eq!(memory.nodes_exceeding(2 * memory.peak).count(), 0);
+        assert_eq!(memory.nodes_exceeding(0).count(), 3);
+        assert!(PipelineMemory::exceeds(memory.peak, memory.peak / 2));
+    }
+
+    #[test]
+    fn promotion_and_rechunking() {
+        let memory = estimate(
+            &pipeline(json!({
+                "1": raw_input(),
+                "2": { "type": "filter", "filter_type": "Gaussian", "input": input(1, 0, 0) },
+                "3": { "type": "rechunk", "b_scans_per_chunk": 4,
+                       "m_scan": input(2, 0, 2),
+                       "b_scan_segmentation": { "value": null, "connection": null } },
+            })),
+            100,
+        );
+
+        // Integer data is promoted to F32
+        let gaussian = &memory.nodes[&NodeId::from(2)];
+        assert_eq!(gaussian.estimate.output.data_type, DataType::F32);
+        assert_eq!(gaussian.estimate.buffers, 0);
+
+        // Rechunking buffers the B scans of a chunk
+        let rechunk = &memory.nodes[&NodeId::from(3)];
+        assert_eq!(rechunk.estimate.output.chunk_size, 4 * B_SCAN_WIDTH);
+        assert!(rechunk.estimate.buffers >= rechunk.estimate.output.chunk_bytes());
+    }
+}
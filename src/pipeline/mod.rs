@@ -1,20 +1,48 @@
+pub mod batch;
+pub mod chunking;
+pub mod compare;
+pub mod determinism;
+pub mod diagnostic_link;
 pub mod execution;
+#[cfg(test)]
+mod golden;
+pub mod memory;
 pub mod nodes;
+pub mod partial_run;
+pub mod persistence;
 pub mod presets;
+pub mod priority;
+pub mod pullback_format;
+pub mod random;
+pub mod range;
+pub mod raw_format;
+pub mod registry;
+pub mod report;
 pub mod requests;
+pub mod result_cache;
+pub mod sample_data;
+pub mod segmentation_format;
+pub mod sessions;
+pub mod stream_format;
+pub mod suggestions;
+pub mod sweep;
+pub mod thresholds;
+pub mod two_pass;
 pub mod types;
+pub mod validation;
 
-pub use execution::PipelineExecutor;
+use execution::flatten;
+pub use execution::{PipelineExecutor, Replay};
 use nodes::DynPipelineNode;
 use serde::{Deserialize, Serialize};
 
 use core::fmt;
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap, HashSet},
     ops::{Index, IndexMut},
 };
 
-use crate::node_graph::{impl_enum_from_into_id_types, NodeId, TypeId};
+use crate::node_graph::{impl_enum_from_into_id_types, NodeId, NodeOutput, OutputId, TypeId};
 
 /// Enum defining all high level data types that are used in the pipeline
 /// description, to determine if pins are able to connect.
@@ -27,6 +55,9 @@ pub enum PipelineDataType {
     MScanSegmentation,
     Diameter,
     Mesh,
+    /// Segmentations and diameters merged by the bundle node, see
+    /// [requests::BundleResponse].
+    Bundle,
 }
 
 impl_enum_from_into_id_types!(PipelineDataType, [TypeId], {
@@ -37,10 +68,11 @@ impl_enum_from_into_id_types!(PipelineDataType, [TypeId], {
     4 => MScanSegmentation,
     5 => Diameter,
     6 => Mesh,
+    7 => Bundle,
 });
 
 impl PipelineDataType {
-    pub const VALUES: [PipelineDataType; 7] = [
+    pub const VALUES: [PipelineDataType; 8] = [
         PipelineDataType::RawMScan,
         PipelineDataType::DataVector,
         PipelineDataType::MScan,
@@ -48,6 +80,7 @@ impl PipelineDataType {
         PipelineDataType::MScanSegmentation,
         PipelineDataType::Diameter,
         PipelineDataType::Mesh,
+        PipelineDataType::Bundle,
     ];
 }
 
@@ -55,14 +88,242 @@ impl PipelineDataType {
 #[derive(Serialize, Deserialize)]
 pub struct Pipeline {
     pub nodes: HashMap<NodeId, Box<dyn DynPipelineNode>>,
+    /// Nodes that are switched off. They keep their settings and connections,
+    /// but do not process anything, see [PipelineExecutor].
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub disabled: HashSet<NodeId>,
+    /// Runs the pipeline in deterministic mode, so two runs on the same data
+    /// give bit-identical results, see [determinism]. Costs a few percent of
+    /// speed, more on machines with many cores.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deterministic: bool,
 }
 
 impl Pipeline {
     pub fn new() -> Self {
         Self {
             nodes: HashMap::new(),
+            disabled: HashSet::new(),
+            deterministic: false,
         }
     }
+
+    /// Copy of the pipeline, cloning every node with
+    /// [DynPipelineNode::clone_boxed].
+    pub fn snapshot(&self) -> Pipeline {
+        Pipeline {
+            nodes: self
+                .nodes
+                .iter()
+                .map(|(node_id, node)| (*node_id, node.clone_boxed()))
+                .collect(),
+            disabled: self.disabled.clone(),
+            deterministic: self.deterministic,
+        }
+    }
+
+    /// Id for a new node, one above the highest id in use. [None], if the ids
+    /// are used up, see [flatten::NODE_ID_LIMIT].
+    pub fn next_node_id(&self) -> Option<NodeId> {
+        let id: usize = self.nodes.keys().copied().max().unwrap_or(0.into()).into();
+        (id + 1 < flatten::NODE_ID_LIMIT).then(|| NodeId::from(id + 1))
+    }
+
+    /// Whether `other` differs in its nodes, their settings, see
+    /// [DynPipelineNode::changed], or their connections.
+    pub fn changed(&self, other: &Pipeline) -> bool {
+        self.disabled != other.disabled
+            || self.deterministic != other.deterministic
+            || self.nodes.len() != other.nodes.len()
+            || self
+                .nodes
+                .iter()
+                .any(|(node_id, node)| match other.nodes.get(node_id) {
+                    Some(other) => {
+                        node.as_any().type_id() != other.as_any().type_id()
+                            || node.changed(other.as_ref())
+                            || node.inputs() != other.inputs()
+                    }
+                    None => true,
+                })
+    }
+
+    /// A disabled node, that the output of `node_id` depends on, possibly the
+    /// node itself.
+    pub fn disabled_upstream(&self, node_id: NodeId) -> Option<NodeId> {
+        let mut visited = HashSet::new();
+        let mut pending = vec![node_id];
+
+        while let Some(node_id) = pending.pop() {
+            if !visited.insert(node_id) {
+                continue;
+            }
+            if self.disabled.contains(&node_id) {
+                return Some(node_id);
+            }
+            if let Some(node) = self.nodes.get(&node_id) {
+                pending.extend(
+                    node.inputs()
+                        .into_iter()
+                        .filter_map(|(_, output)| output.map(|o| o.node_id)),
+                );
+            }
+        }
+
+        None
+    }
+
+    /// `node_id` and every node its output depends on.
+    pub fn upstream(&self, node_id: NodeId) -> BTreeSet<NodeId> {
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![node_id];
+
+        while let Some(node_id) = pending.pop() {
+            if !visited.insert(node_id) {
+                continue;
+            }
+            if let Some(node) = self.nodes.get(&node_id) {
+                pending.extend(
+                    node.inputs()
+                        .into_iter()
+                        .filter_map(|(_, output)| output.map(|o| o.node_id)),
+                );
+            }
+        }
+
+        visited
+    }
+
+    /// Whether the output serves the same data again, when requested a second
+    /// time, see [Replay]. Outputs of disabled nodes never do. `known` keeps
+    /// the answers across calls.
+    pub fn can_replay(
+        &self,
+        node_id: NodeId,
+        output_id: OutputId,
+        known: &mut HashMap<(NodeId, OutputId), bool>,
+    ) -> bool {
+        if let Some(replay) = known.get(&(node_id, output_id)) {
+            return *replay;
+        }
+        // Guards against cycles
+        known.insert((node_id, output_id), false);
+
+        let replay = match self.nodes.get(&node_id) {
+            Some(node) if !self.disabled.contains(&node_id) => match node.replay(output_id) {
+                Replay::Inputs => node
+                    .inputs()
+                    .into_iter()
+                    .filter_map(|(_, output)| output)
+                    .all(|output| self.can_replay(output.node_id, output.output_id, known)),
+                Replay::Never => false,
+            },
+            _ => false,
+        };
+
+        known.insert((node_id, output_id), replay);
+        replay
+    }
+
+    /// Enabled nodes, whose output may differ between runs even in
+    /// deterministic mode, with the reason, see
+    /// [nodes::PipelineNode::nondeterminism].
+    pub fn nondeterministic_nodes(&self) -> Vec<(NodeId, &'static str)> {
+        let mut nodes = self
+            .nodes
+            .iter()
+            .filter(|(node_id, _)| !self.disabled.contains(node_id))
+            .filter_map(|(node_id, node)| Some((*node_id, node.nondeterminism()?)))
+            .collect::<Vec<_>>();
+
+        nodes.sort_by_key(|(node_id, _)| *node_id);
+        nodes
+    }
+
+    /// Checks the settings of every node, correcting invalid ones, see
+    /// [nodes::PipelineNode::validate]. Returns the issues of every node, that
+    /// had any.
+    pub fn validate(&mut self) -> Vec<(NodeId, Vec<validation::ValidationIssue>)> {
+        let mut nodes = self
+            .nodes
+            .iter_mut()
+            .map(|(node_id, node)| (*node_id, node.validate()))
+            .filter(|(_, issues)| !issues.is_empty())
+            .collect::<Vec<_>>();
+
+        nodes.sort_by_key(|(node_id, _)| *node_id);
+        nodes
+    }
+
+    /// Hash of the settings and connections of every node `output` depends
+    /// on, identifying the processing that produced it. Uses FNV-1a over the
+    /// serialized nodes, so it is stable between builds.
+    pub fn provenance(&self, output: NodeOutput) -> u64 {
+        let mut hash = Fnv1a::new();
+
+        hash.write(&serde_json::to_vec(&output).unwrap_or_default());
+        self.hash_nodes(self.upstream(output.node_id), &mut hash);
+
+        hash.finish()
+    }
+
+    /// Hash of the settings and connections of every node, like
+    /// [Self::provenance] of the whole pipeline.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash = Fnv1a::new();
+        self.hash_nodes(self.nodes.keys().copied().collect(), &mut hash);
+        hash.finish()
+    }
+
+    /// Hash of the type and settings of a node, leaving out its connections
+    /// and id, so an identical node added again hashes the same.
+    pub fn settings_hash(&self, node_id: NodeId) -> Option<u64> {
+        let value = serde_json::to_value(self.nodes.get(&node_id)?).ok()?;
+
+        let mut hash = Fnv1a::new();
+        hash.write(value["type"].to_string().as_bytes());
+        for (key, value) in report::flatten_settings(&value) {
+            hash.write(key.as_bytes());
+            hash.write(value.as_bytes());
+        }
+
+        Some(hash.finish())
+    }
+
+    fn hash_nodes(&self, node_ids: BTreeSet<NodeId>, hash: &mut Fnv1a) {
+        // Only written when set, so hashes of older pipelines stay the same
+        if self.deterministic {
+            hash.write(b"deterministic");
+        }
+        for node_id in node_ids {
+            if let Some(node) = self.nodes.get(&node_id) {
+                hash.write(&serde_json::to_vec(&node_id).unwrap_or_default());
+                hash.write(&serde_json::to_vec(node).unwrap_or_default());
+            }
+        }
+    }
+}
+
+/// 64 bit FNV-1a hash, which is stable between builds, unlike the hashers of
+/// the standard library.
+pub struct Fnv1a(u64);
+
+impl Fnv1a {
+    const PRIME: u64 = 0x100_0000_01b3;
+
+    pub fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(Self::PRIME);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
 }
 
 impl Index<NodeId> for Pipeline {
@@ -93,6 +354,8 @@ impl fmt::Debug for Pipeline {
 
         f.debug_struct("Pipeline")
             .field("nodes", &Helper(self))
+            .field("disabled", &self.disabled)
+            .field("deterministic", &self.deterministic)
             .finish()
     }
 }
@@ -0,0 +1,327 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use futures::FutureExt;
+use nalgebra::{DMatrix, DMatrixView, Scalar};
+use num_traits::Zero;
+
+use crate::{pipeline::types::DataMatrix, queue_channel::error::RecvError};
+
+use super::prelude::*;
+
+pub enum InputId {
+    MScan,
+    Mask,
+}
+
+impl_enum_from_into_id_types!(InputId, [graph::InputId], {
+    0 => MScan,
+    1 => Mask,
+});
+
+// MARK: Node
+
+/// Sets every pixel of an M scan to a fill value, where the mask is not zero.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Node {
+    /// The value masked pixels are set to. For integer data, this is the raw
+    /// value, for floating point data, values usually range from 0.0 to 1.0.
+    pub fill_value: f64,
+
+    pub m_scan: NodeInput<()>,
+    pub mask: NodeInput<()>,
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self {
+            fill_value: 0.0,
+            m_scan: NodeInput::default(),
+            mask: NodeInput::default(),
+        }
+    }
+}
+
+deserialize_node!(Node, "apply_mask");
+
+impl PipelineNode for Node {
+    type InputId = InputId;
+    type OutputId = OutputIdSingle;
+
+    fn slug() -> &'static str {
+        "apply_mask"
+    }
+
+    fn inputs(&self) -> impl Iterator<Item = (InputId, Option<NodeOutput>)> {
+        [
+            (InputId::MScan, self.m_scan.connection()),
+            (InputId::Mask, self.mask.connection()),
+        ]
+        .into_iter()
+    }
+
+    fn changed(&self, other: &Self) -> bool {
+        self.fill_value != other.fill_value
+    }
+
+    fn get_output_id_for_view_request(&self) -> Option<(OutputIdSingle, impl Into<TypeId>)> {
+        Some((OutputIdSingle, PipelineDataType::MScan))
+    }
+
+    fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
+        let m_scan_out = builder.output(OutputIdSingle);
+
+        builder.task(Task {
+            fill_value: self.fill_value,
+            m_scan_out,
+            m_scan_in: TaskInput::default(),
+            mask_in: TaskInput::default(),
+        });
+    }
+}
+
+// MARK: Task
+
+struct Task {
+    fill_value: f64,
+
+    m_scan_out: TaskOutput<requests::MScan>,
+    m_scan_in: TaskInput<requests::MScan>,
+    mask_in: TaskInput<requests::MScan>,
+}
+
+impl NodeTask for Task {
+    type InputId = InputId;
+    type PipelineNode = Node;
+
+    fn connect(&mut self, input_id: Self::InputId, input: &mut ConnectionHandle) {
+        match input_id {
+            InputId::MScan => self.m_scan_in.connect(input),
+            InputId::Mask => self.mask_in.connect(input),
+        };
+    }
+
+    fn disconnect(&mut self, input_id: Self::InputId) {
+        match input_id {
+            InputId::MScan => self.m_scan_in.disconnect(),
+            InputId::Mask => self.mask_in.disconnect(),
+        };
+    }
+
+    fn sync_node(&mut self, node: &Self::PipelineNode) {
+        self.fill_value = node.fill_value;
+    }
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        let _req = self.m_scan_out.receive().await;
+
+        let (Some(m_scan_res), Some(mask_res)) = futures::join!(
+            self.m_scan_in.request(requests::MScan),
+            self.mask_in.request(requests::MScan),
+        ) else {
+            return Ok(());
+        };
+
+        let (Some(mut m_scan), Some(mut mask)) =
+            (m_scan_res.data.subscribe(), mask_res.data.subscribe())
+        else {
+            return Ok(());
+        };
+
+        let (res, tx) = requests::StreamedResponse::with_default_capacity();
+
+        self.m_scan_out.respond(requests::MScanResponse {
+            data: res,
+            a_scan_count: m_scan_res.a_scan_count,
+            a_scan_samples: m_scan_res.a_scan_samples,
+        });
+        self.m_scan_out.receive().now_or_never();
+
+        let fill_value = self.fill_value;
+
+        let mut pending_mask = PendingMask::default();
+
+        loop {
+            let m_scan = match m_scan.recv().await {
+                Ok(m_scan) => m_scan,
+                Err(RecvError::Closed) => break,
+                Err(e) => Err(e)?,
+            };
+
+            let ncols = m_scan.ncols();
+
+            while pending_mask.len() < ncols {
+                let mask = match mask.recv().await {
+                    Ok(mask) => mask,
+                    Err(RecvError::Closed) => break,
+                    Err(e) => Err(e)?,
+                };
+
+                pending_mask.push(&mask)?;
+            }
+
+            let chunk_mask = pending_mask.take(ncols, m_scan.nrows())?;
+
+            let m_scan =
+                priority::spawn_blocking(move || mask_m_scan(&m_scan, &chunk_mask, fill_value))
+                    .await?;
+
+            tx.send(Arc::new(m_scan));
+        }
+
+        tx.finish();
+
+        Ok(())
+    }
+}
+
+// MARK: Pending mask
+
+/// Columns of the mask, that have been received, but not used yet. The mask
+/// may be chunked differently than the M scan.
+#[derive(Default)]
+struct PendingMask(Option<DataMatrix>);
+
+impl PendingMask {
+    fn len(&self) -> usize {
+        self.0.as_ref().map_or(0, DataMatrix::ncols)
+    }
+
+    fn push(&mut self, mask: &DataMatrix) -> anyhow::Result<()> {
+        self.0 = match self.0.take() {
+            Some(pending) => Some(
+                pending
+                    .concat_horizontally(mask)
+                    .ok_or_else(|| anyhow!("Mask chunks do not match each other"))?,
+            ),
+            None => Some(mask.clone()),
+        };
+
+        Ok(())
+    }
+
+    /// Takes the mask for the next M scan chunk of `ncols` A scans with `nrows`
+    /// samples each.
+    fn take(&mut self, ncols: usize, nrows: usize) -> anyhow::Result<DataMatrix> {
+        let available = match self.0.take() {
+            Some(available) if available.ncols() >= ncols => available,
+            _ => return Err(anyhow!("Mask is shorter than the M scan")),
+        };
+
+        if available.nrows() != nrows {
+            return Err(anyhow!(
+                "Mask has {} rows, but the M scan has {} rows",
+                available.nrows(),
+                nrows
+            ));
+        }
+
+        if available.ncols() > ncols {
+            self.0 = Some(available.columns(ncols, available.ncols() - ncols));
+        }
+
+        Ok(available.columns(0, ncols))
+    }
+}
+
+// MARK: Algorithm
+
+/// Sets every pixel of `m_scan` to `fill_value`, where `mask` is not zero.
+fn mask_m_scan(m_scan: &DataMatrix, mask: &DataMatrix, fill_value: f64) -> DataMatrix {
+    let mask = mask_to_bool(mask);
+
+    match m_scan {
+        DataMatrix::U8(m_scan) => apply_mask(m_scan.as_view(), &mask, fill_value).into(),
+        DataMatrix::U16(m_scan) => apply_mask(m_scan.as_view(), &mask, fill_value).into(),
+        DataMatrix::U32(m_scan) => apply_mask(m_scan.as_view(), &mask, fill_value).into(),
+        DataMatrix::U64(m_scan) => apply_mask(m_scan.as_view(), &mask, fill_value).into(),
+        DataMatrix::F32(m_scan) => apply_mask(m_scan.as_view(), &mask, fill_value).into(),
+        DataMatrix::F64(m_scan) => apply_mask(m_scan.as_view(), &mask, fill_value).into(),
+    }
+}
+
+/// Converts a mask of any data type into a boolean mask, which is true for
+/// every non-zero value.
+fn mask_to_bool(mask: &DataMatrix) -> DMatrix<bool> {
+    fn non_zero<T: Scalar + Zero>(mask: &DMatrix<T>) -> DMatrix<bool> {
+        mask.map(|v| !v.is_zero())
+    }
+
+    match mask {
+        DataMatrix::U8(mask) => non_zero(mask),
+        DataMatrix::U16(mask) => non_zero(mask),
+        DataMatrix::U32(mask) => non_zero(mask),
+        DataMatrix::U64(mask) => non_zero(mask),
+        DataMatrix::F32(mask) => non_zero(mask),
+        DataMatrix::F64(mask) => non_zero(mask),
+    }
+}
+
+fn apply_mask<T>(m_scan: DMatrixView<T>, mask: &DMatrix<bool>, fill_value: f64) -> DMatrix<T>
+where
+    T: Scalar + Copy + Zero + num_traits::NumCast,
+{
+    let fill_value: T = num_traits::cast(fill_value).unwrap_or(T::zero());
+
+    m_scan.zip_map(mask, |v, masked| if masked { fill_value } else { v })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn masked_values() {
+        let m_scan: DataMatrix = DMatrix::from_fn(3, 4, |r, c| (r * 4 + c + 1) as u16).into();
+        let mask: DataMatrix = DMatrix::from_fn(3, 4, |r, c| (r == c) as u8).into();
+
+        let expected: DataMatrix =
+            DMatrix::from_fn(3, 4, |r, c| if r == c { 7 } else { (r * 4 + c + 1) as u16 }).into();
+        assert_eq!(mask_m_scan(&m_scan, &mask, 7.0), expected);
+
+        // Any non-zero value masks, the fill value is cast to the data type
+        let m_scan: DataMatrix = DMatrix::from_element(2, 2, 0.5f32).into();
+        let mask: DataMatrix = DMatrix::from_row_slice(2, 2, &[0.0f32, 0.1, -1.0, 0.0]).into();
+
+        let expected: DataMatrix = DMatrix::from_row_slice(2, 2, &[0.5f32, 1.0, 1.0, 0.5]).into();
+        assert_eq!(mask_m_scan(&m_scan, &mask, 1.0), expected);
+    }
+
+    #[test]
+    fn mask_chunked_differently() {
+        let mask: DataMatrix = DMatrix::from_fn(2, 10, |r, c| (r + c) as u8).into();
+
+        let mut pending = PendingMask::default();
+        pending.push(&mask.columns(0, 3)).unwrap();
+        pending.push(&mask.columns(3, 4)).unwrap();
+        assert_eq!(pending.len(), 7);
+
+        assert_eq!(pending.take(5, 2).unwrap(), mask.columns(0, 5));
+        assert_eq!(pending.len(), 2);
+
+        pending.push(&mask.columns(7, 3)).unwrap();
+        assert_eq!(pending.take(5, 2).unwrap(), mask.columns(5, 5));
+        assert_eq!(pending.len(), 0);
+    }
+
+    #[test]
+    fn size_mismatch() {
+        let mask: DataMatrix = DMatrix::<u8>::zeros(4, 6).into();
+
+        // Fewer rows than the M scan
+        let mut pending = PendingMask::default();
+        pending.push(&mask).unwrap();
+        assert!(pending.take(6, 5).is_err());
+
+        // Mask ends before the M scan
+        let mut pending = PendingMask::default();
+        pending.push(&mask).unwrap();
+        assert!(pending.take(8, 4).is_err());
+        assert!(PendingMask::default().take(1, 4).is_err());
+
+        // Chunks of the mask with a different number of rows
+        let mut pending = PendingMask::default();
+        pending.push(&mask).unwrap();
+        assert!(pending.push(&DMatrix::<u8>::zeros(3, 6).into()).is_err());
+    }
+}
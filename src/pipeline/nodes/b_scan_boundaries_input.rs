@@ -0,0 +1,401 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail};
+use futures::FutureExt;
+use tokio::sync::watch;
+
+use super::prelude::*;
+
+/// Maximum number of offending entries listed in validation errors.
+const MAX_LISTED: usize = 10;
+
+/// Format of a file with B scan boundaries.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BoundaryFormat {
+    /// Little endian `u32` values, as written by the output node.
+    #[default]
+    RawU32,
+    /// Values separated by commas, semicolons or whitespace.
+    Csv,
+    /// One value per line.
+    Text,
+}
+
+impl BoundaryFormat {
+    pub const VALUES: [BoundaryFormat; 3] = [
+        BoundaryFormat::RawU32,
+        BoundaryFormat::Csv,
+        BoundaryFormat::Text,
+    ];
+}
+
+/// Reported to the [Node] after every run.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum Status {
+    #[default]
+    Idle,
+    Loaded(BoundaryStats),
+    Failed(String),
+}
+
+/// Sanity readout of loaded boundaries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundaryStats {
+    pub count: usize,
+    /// Minimum, median and maximum number of A scans per B scan. [None] with
+    /// less than two boundaries.
+    pub widths: Option<(usize, usize, usize)>,
+}
+
+impl BoundaryStats {
+    pub fn of(boundaries: &[usize]) -> Self {
+        let mut widths: Vec<usize> = boundaries.windows(2).map(|w| w[1] - w[0]).collect();
+        widths.sort_unstable();
+
+        Self {
+            count: boundaries.len(),
+            widths: match (widths.first(), widths.last()) {
+                (Some(min), Some(max)) => Some((*min, widths[widths.len() / 2], *max)),
+                _ => None,
+            },
+        }
+    }
+}
+
+// MARK: Node
+
+/// Reads B scan boundaries from a file, for example ones found by other tools,
+/// instead of segmenting the M scan.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Node {
+    pub path: PathBuf,
+    pub format: BoundaryFormat,
+    /// The file counts A scans from 1, like MATLAB.
+    #[serde(default)]
+    pub one_based: bool,
+
+    /// Optional, the boundaries are checked against its A scan count.
+    pub m_scan: NodeInput<()>,
+
+    #[serde(skip)]
+    pub status_rx: Option<watch::Receiver<Status>>,
+}
+
+deserialize_node!(Node, "b_scan_boundaries_input");
+
+impl PipelineNode for Node {
+    type InputId = InputIdSingle;
+    type OutputId = OutputIdSingle;
+
+    fn slug() -> &'static str {
+        "b_scan_boundaries_input"
+    }
+
+    fn inputs(
+        &self,
+    ) -> impl Iterator<Item = (<Self as PipelineNode>::InputId, Option<NodeOutput>)> {
+        std::iter::once((InputIdSingle, self.m_scan.connection()))
+    }
+
+    fn changed(&self, other: &Self) -> bool {
+        self.path != other.path || self.format != other.format || self.one_based != other.one_based
+    }
+
+    fn get_output_id_for_view_request(&self) -> Option<(OutputIdSingle, impl Into<TypeId>)> {
+        Some((OutputIdSingle, PipelineDataType::BScanSegmentation))
+    }
+
+    fn visit_paths(&self, visitor: &mut dyn FnMut(PathRole, &Path)) {
+        visitor(PathRole::Input, &self.path);
+    }
+
+    fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
+        let b_scans_out = builder.output(OutputIdSingle);
+
+        let (status_tx, status_rx) = watch::channel(Status::Idle);
+
+        self.status_rx = Some(status_rx);
+
+        builder.task(Task {
+            path: self.path.clone(),
+            format: self.format,
+            one_based: self.one_based,
+            status_tx,
+            b_scans_out,
+            m_scan_in: TaskInput::default(),
+        });
+    }
+}
+
+// MARK: Task
+
+struct Task {
+    path: PathBuf,
+    format: BoundaryFormat,
+    one_based: bool,
+
+    status_tx: watch::Sender<Status>,
+
+    b_scans_out: TaskOutput<requests::BScanSegmentation>,
+    m_scan_in: TaskInput<requests::MScan>,
+}
+
+impl NodeTask for Task {
+    type InputId = InputIdSingle;
+    type PipelineNode = Node;
+
+    fn connect(&mut self, _input_id: Self::InputId, input: &mut ConnectionHandle) {
+        self.m_scan_in.connect(input);
+    }
+
+    fn disconnect(&mut self, _input_id: Self::InputId) {
+        self.m_scan_in.disconnect();
+    }
+
+    fn sync_node(&mut self, node: &Self::PipelineNode) {
+        self.path = node.path.clone();
+        self.format = node.format;
+        self.one_based = node.one_based;
+    }
+
+    fn invalidate(&mut self, _cause: InvalidationCause) {
+        let _ = self.status_tx.send(Status::Idle);
+    }
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        let _req = self.b_scans_out.receive().await;
+
+        let a_scan_count = match self.m_scan_in.is_connected() {
+            true => match self.m_scan_in.request(requests::MScan).await {
+                Some(res) => Some(res.a_scan_count),
+                None => return Ok(()),
+            },
+            false => None,
+        };
+
+        let boundaries = match self.load(a_scan_count).await {
+            Ok(boundaries) => boundaries,
+            Err(e) => {
+                let _ = self.status_tx.send(Status::Failed(format!("{:#}", e)));
+                return Err(e);
+            }
+        };
+
+        let _ = self
+            .status_tx
+            .send(Status::Loaded(BoundaryStats::of(&boundaries)));
+
+        let (res, tx) = requests::StreamedResponse::with_default_capacity();
+
+        self.b_scans_out
+            .respond(requests::BScanSegmentationResponse {
+                data: res,
+                a_scan_count: a_scan_count.unwrap_or(boundaries.last().copied().unwrap_or(0)),
+            });
+        self.b_scans_out.receive().now_or_never();
+
+        for boundary in boundaries {
+            tx.send(boundary);
+        }
+
+        tx.finish();
+
+        Ok(())
+    }
+}
+
+impl Task {
+    async fn load(&self, a_scan_count: Option<usize>) -> anyhow::Result<Vec<usize>> {
+        let data = tokio::fs::read(&self.path).await?;
+
+        let boundaries = parse(&data, self.format, self.one_based)?;
+        validate(&boundaries, a_scan_count)?;
+
+        Ok(boundaries)
+    }
+}
+
+// MARK: Parsing
+
+/// Reads the boundaries of a file in `format`, converted to 0 based A scan
+/// indices.
+fn parse(data: &[u8], format: BoundaryFormat, one_based: bool) -> anyhow::Result<Vec<usize>> {
+    let values: Vec<u64> = match format {
+        BoundaryFormat::RawU32 => {
+            let chunks = data.chunks_exact(4);
+            if !chunks.remainder().is_empty() {
+                bail!("File size of {} bytes is not a multiple of 4", data.len());
+            }
+
+            chunks
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as u64)
+                .collect()
+        }
+        BoundaryFormat::Csv => parse_text(data, |line| {
+            line.split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+                .filter(|value| !value.is_empty())
+                .collect()
+        })?,
+        BoundaryFormat::Text => parse_text(data, |line| {
+            let line = line.trim();
+            match line.is_empty() {
+                true => vec![],
+                false => vec![line],
+            }
+        })?,
+    };
+
+    if !one_based {
+        return Ok(values.into_iter().map(|v| v as usize).collect());
+    }
+
+    let zeros = offending(values.iter().map(|v| *v == 0));
+    if !zeros.is_empty() {
+        bail!("Index 0 in a file counting from 1 at entries {}", zeros);
+    }
+
+    Ok(values.into_iter().map(|v| v as usize - 1).collect())
+}
+
+/// Parses the values `split` finds in every line of a text file.
+fn parse_text<'a>(
+    data: &'a [u8],
+    split: impl Fn(&'a str) -> Vec<&'a str>,
+) -> anyhow::Result<Vec<u64>> {
+    let text = std::str::from_utf8(data).map_err(|e| anyhow!("File is not UTF-8: {}", e))?;
+
+    let mut values = Vec::new();
+
+    for (line_number, line) in text.lines().enumerate() {
+        for value in split(line) {
+            let value = value
+                .parse()
+                .map_err(|_| anyhow!("Invalid index {:?} in line {}", value, line_number + 1))?;
+            values.push(value);
+        }
+    }
+
+    Ok(values)
+}
+
+// MARK: Validation
+
+/// Checks, that the boundaries are increasing and do not exceed the A scans of
+/// the segmented M scan, if known.
+fn validate(boundaries: &[usize], a_scan_count: Option<usize>) -> anyhow::Result<()> {
+    if boundaries.is_empty() {
+        bail!("File contains no boundaries");
+    }
+
+    let not_increasing =
+        offending(std::iter::once(false).chain(boundaries.windows(2).map(|w| w[1] <= w[0])));
+    if !not_increasing.is_empty() {
+        bail!(
+            "Boundaries are not increasing at entries {}",
+            not_increasing
+        );
+    }
+
+    if let Some(a_scan_count) = a_scan_count {
+        let out_of_range = offending(boundaries.iter().map(|b| *b > a_scan_count));
+        if !out_of_range.is_empty() {
+            bail!(
+                "Boundaries exceed the {} A scans of the M scan at entries {}",
+                a_scan_count,
+                out_of_range
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists the indices, where `is_offending` is true, for error messages.
+fn offending(is_offending: impl Iterator<Item = bool>) -> String {
+    let indices: Vec<usize> = is_offending
+        .enumerate()
+        .filter(|(_, is_offending)| *is_offending)
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut list = indices
+        .iter()
+        .take(MAX_LISTED)
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if indices.len() > MAX_LISTED {
+        list += &format!(" and {} more", indices.len() - MAX_LISTED);
+    }
+
+    list
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    const EXPECTED: [usize; 5] = [0, 120, 245, 366, 490];
+
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/boundaries")
+            .join(name);
+        std::fs::read(path).unwrap()
+    }
+
+    #[test]
+    fn parses_formats() {
+        let raw = parse(&fixture("boundaries.bin"), BoundaryFormat::RawU32, false).unwrap();
+        let csv = parse(&fixture("boundaries.csv"), BoundaryFormat::Csv, false).unwrap();
+        let text = parse(&fixture("boundaries.txt"), BoundaryFormat::Text, false).unwrap();
+        let matlab = parse(
+            &fixture("boundaries_matlab.txt"),
+            BoundaryFormat::Text,
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(raw, EXPECTED);
+        assert_eq!(csv, EXPECTED);
+        assert_eq!(text, EXPECTED);
+        assert_eq!(matlab, EXPECTED);
+
+        let stats = BoundaryStats::of(&text);
+        assert_eq!(stats.count, 5);
+        assert_eq!(stats.widths, Some((120, 124, 125)));
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert!(parse(b"1\n2\nthree\n", BoundaryFormat::Text, false)
+            .unwrap_err()
+            .to_string()
+            .contains("line 3"));
+        assert!(parse(&[0; 6], BoundaryFormat::RawU32, false).is_err());
+        assert!(parse(b"0,1,2", BoundaryFormat::Csv, true).is_err());
+    }
+
+    #[test]
+    fn validation_lists_offending_entries() {
+        let boundaries = parse(&fixture("boundaries.csv"), BoundaryFormat::Csv, false).unwrap();
+
+        assert!(validate(&boundaries, None).is_ok());
+        assert!(validate(&boundaries, Some(490)).is_ok());
+
+        let message = validate(&boundaries, Some(300)).unwrap_err().to_string();
+        assert!(message.ends_with("entries 3, 4"), "{}", message);
+
+        let message = validate(&[0, 10, 10, 5, 20], None).unwrap_err().to_string();
+        assert!(message.ends_with("entries 2, 3"), "{}", message);
+
+        let decreasing: Vec<usize> = (0..20).rev().collect();
+        let message = validate(&decreasing, None).unwrap_err().to_string();
+        assert!(message.ends_with("10 and 9 more"), "{}", message);
+
+        assert!(validate(&[], None).is_err());
+    }
+}
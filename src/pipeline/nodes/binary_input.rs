@@ -3,10 +3,18 @@ use std::{
     sync::Arc,
 };
 
+use anyhow::anyhow;
 use futures::FutureExt;
 use tokio::{fs, io::AsyncReadExt, sync::watch};
 
-use crate::pipeline::types::{DataMatrix, DataType, DataVector};
+use crate::{
+    pipeline::{
+        chunking::{ChunkLimits, ChunkedSender},
+        raw_format::{Dimensions, Endianness, RawHeader},
+        types::{DataMatrix, DataType, DataVector},
+    },
+    settings::Settings,
+};
 
 use super::prelude::*;
 
@@ -43,6 +51,9 @@ impl InputDataType {
     }
 }
 
+/// A scans read from the file at once.
+const CHUNK_SIZE: usize = 12000;
+
 // MARK: Node
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,6 +64,16 @@ pub struct Node {
     /// The data type of each value in the input data.
     pub data_type: DataType,
     pub a_scan_length: usize,
+    /// Byte order of the input data.
+    #[serde(default)]
+    pub endianness: Endianness,
+    /// The header of M scan files, its fields take precedence over the fields
+    /// above. See [Self::detect_header].
+    #[serde(skip)]
+    pub header: Option<RawHeader>,
+    /// The path [Self::header] was detected for.
+    #[serde(skip)]
+    pub header_path: Option<PathBuf>,
 
     /// Used to report the progress from the [NodeTask] to the [Node].
     #[serde(skip)]
@@ -66,6 +87,9 @@ impl Node {
             input_type: InputDataType::RawMScan,
             data_type: DataType::U16,
             a_scan_length: a_scan_length.unwrap_or(1024),
+            endianness: Endianness::default(),
+            header: None,
+            header_path: None,
             progress_rx: None,
         }
     }
@@ -76,6 +100,9 @@ impl Node {
             input_type: InputDataType::MScan,
             data_type: DataType::U16,
             a_scan_length: a_scan_length.unwrap_or(512),
+            endianness: Endianness::default(),
+            header: None,
+            header_path: None,
             progress_rx: None,
         }
     }
@@ -86,6 +113,9 @@ impl Node {
             input_type: InputDataType::DataVector,
             data_type: DataType::F64,
             a_scan_length: 1024,
+            endianness: Endianness::default(),
+            header: None,
+            header_path: None,
             progress_rx: None,
         }
     }
@@ -98,11 +128,46 @@ impl Default for Node {
             input_type: InputDataType::RawMScan,
             data_type: DataType::U16,
             a_scan_length: 1024,
+            endianness: Endianness::default(),
+            header: None,
+            header_path: None,
             progress_rx: None,
         }
     }
 }
 
+impl Node {
+    /// Reads the [RawHeader] of the file at [Self::path], if the path changed
+    /// since the last call, and fills in the fields it describes.
+    pub fn detect_header(&mut self) {
+        if self.header_path.as_ref() == Some(&self.path) {
+            return;
+        }
+
+        self.header = RawHeader::peek(&self.path);
+        self.header_path = Some(self.path.clone());
+
+        if let Some(header) = self.header {
+            self.data_type = header.data_type;
+            self.a_scan_length = header.a_scan_samples;
+            self.endianness = header.endianness;
+        }
+    }
+
+    /// The most plausible A scan lengths of the file at [Self::path], from its
+    /// size and [Self::data_type], at most `limit`. Picking one is left to
+    /// the user.
+    pub fn dimension_candidates(&self, limit: usize) -> Vec<Dimensions> {
+        let Ok(metadata) = std::fs::metadata(&self.path) else {
+            return Vec::new();
+        };
+        let file_len =
+            (metadata.len() as usize).saturating_sub(self.header.map_or(0, |_| RawHeader::SIZE));
+
+        Dimensions::candidates(file_len, self.data_type, limit)
+    }
+}
+
 deserialize_node!(Node, "binary_input");
 
 impl PipelineNode for Node {
@@ -124,12 +189,46 @@ impl PipelineNode for Node {
             || self.input_type != other.input_type
             || self.a_scan_length != other.a_scan_length
             || self.data_type != other.data_type
+            || self.endianness != other.endianness
     }
 
     fn get_output_id_for_view_request(&self) -> Option<(InputDataType, impl Into<TypeId>)> {
         Some((self.input_type, self.input_type.data_type()))
     }
 
+    fn visit_paths(&self, visitor: &mut dyn FnMut(PathRole, &Path)) {
+        visitor(PathRole::Input, &self.path);
+    }
+
+    /// Derives the stream from the size of the file. Missing files stream
+    /// nothing.
+    fn estimate_memory(&self, _upstream: &UpstreamStats) -> MemoryEstimate {
+        let Ok(metadata) = std::fs::metadata(&self.path) else {
+            return MemoryEstimate::streaming(&UpstreamStats::NONE, UpstreamStats::NONE);
+        };
+        let file_len =
+            (metadata.len() as usize).saturating_sub(self.header.map_or(0, |_| RawHeader::SIZE));
+
+        let output = match self.input_type {
+            InputDataType::RawMScan | InputDataType::MScan => UpstreamStats {
+                a_scan_count: Dimensions::of_file(file_len, self.data_type, self.a_scan_length)
+                    .a_scan_count,
+                samples: self.a_scan_length,
+                data_type: self.data_type,
+                chunk_size: CHUNK_SIZE.min(ChunkLimits::current().max_width),
+            },
+            // Read as a whole
+            InputDataType::DataVector => UpstreamStats {
+                a_scan_count: 1,
+                samples: file_len / self.data_type.size(),
+                data_type: self.data_type,
+                chunk_size: 1,
+            },
+        };
+
+        MemoryEstimate::streaming(&UpstreamStats::NONE, output)
+    }
+
     fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
         let raw_scan_out = builder.output(InputDataType::RawMScan);
         let m_scan_out = builder.output(InputDataType::MScan);
@@ -147,6 +246,7 @@ impl PipelineNode for Node {
             input_type: self.input_type,
             data_type: self.data_type,
             a_scan_length: self.a_scan_length,
+            endianness: self.endianness,
             progress_tx,
         });
     }
@@ -164,6 +264,7 @@ struct Task {
     input_type: InputDataType,
     data_type: DataType,
     a_scan_length: usize,
+    endianness: Endianness,
 
     progress_tx: watch::Sender<Option<f32>>,
 }
@@ -181,6 +282,7 @@ impl NodeTask for Task {
         self.input_type = node.input_type;
         self.data_type = node.data_type;
         self.a_scan_length = node.a_scan_length;
+        self.endianness = node.endianness;
     }
 
     fn invalidate(&mut self, _cause: InvalidationCause) {
@@ -227,6 +329,8 @@ impl Task {
             DataVector::from_data_type(self.data_type, buf.len() / self.data_type.size());
 
         data.as_mut_u8_slice().copy_from_slice(&buf);
+        self.endianness
+            .convert(data.as_mut_u8_slice(), self.data_type);
 
         self.data_vector_out.respond(Arc::new(data));
 
@@ -239,10 +343,12 @@ impl Task {
             &self.path,
             self.data_type,
             self.a_scan_length,
-            |resp, a_scan_count| {
+            self.endianness,
+            CHUNK_SIZE,
+            |resp, a_scan_samples, a_scan_count| {
                 self.raw_scan_out.respond(requests::RawMScanResponse {
                     data: resp,
-                    a_scan_samples: self.a_scan_length,
+                    a_scan_samples,
                     a_scan_count,
                 });
                 self.raw_scan_out.receive().now_or_never();
@@ -257,10 +363,12 @@ impl Task {
             &self.path,
             self.data_type,
             self.a_scan_length,
-            |resp, a_scan_count| {
+            self.endianness,
+            CHUNK_SIZE,
+            |resp, a_scan_samples, a_scan_count| {
                 self.m_scan_out.respond(requests::MScanResponse {
                     data: resp,
-                    a_scan_samples: self.a_scan_length,
+                    a_scan_samples,
                     a_scan_count,
                 });
                 self.m_scan_out.receive().now_or_never();
@@ -269,57 +377,300 @@ impl Task {
         .await
     }
 
+    /// Streams the M scan in the file at `path`, reading `chunk_size` A scans
+    /// at a time. A [RawHeader] in the file overrides `data_type`,
+    /// `a_scan_length` and `endianness`. `respond` gets the A scan samples
+    /// and count.
     async fn respond_streamed(
         progress_tx: &mut watch::Sender<Option<f32>>,
         path: &Path,
         data_type: DataType,
         a_scan_length: usize,
-        respond: impl FnOnce(requests::StreamedResponse<Arc<DataMatrix>>, usize),
+        endianness: Endianness,
+        chunk_size: usize,
+        respond: impl FnOnce(requests::StreamedResponse<Arc<DataMatrix>>, usize, usize),
     ) -> anyhow::Result<()> {
-        const CHUNK_SIZE: usize = 12000;
-
         let mut file = fs::File::open(path).await?;
 
-        let (output, tx) = requests::StreamedResponse::new(200);
+        let header = RawHeader::read(&mut file).await?;
+        let (data_type, a_scan_length, endianness) = match header {
+            Some(header) => (header.data_type, header.a_scan_samples, header.endianness),
+            None => (data_type, a_scan_length, endianness),
+        };
+        if a_scan_length == 0 {
+            return Err(anyhow!("A scans must have at least one sample"));
+        }
+
+        // Reading from disk is fast, give slow receivers more headroom
+        let capacity = 2 * Settings::current().performance.stream_capacity;
+        let (output, tx) = requests::StreamedResponse::new(capacity);
+        let mut tx = ChunkedSender::new(tx, ChunkLimits::current());
 
         let _ = progress_tx.send(Some(0.0));
 
-        let file_len = file.metadata().await?.len() as usize;
+        let file_len =
+            file.metadata().await?.len() as usize - header.map_or(0, |_| RawHeader::SIZE);
 
-        respond(output, file_len / a_scan_length / data_type.size());
+        let dimensions = Dimensions::of_file(file_len, data_type, a_scan_length);
+        respond(output, dimensions.a_scan_samples, dimensions.a_scan_count);
 
         let mut bytes_read = 0;
 
         loop {
-            let mut data = DataMatrix::from_data_type(data_type, a_scan_length, CHUNK_SIZE);
-
-            let mut index = 0;
+            let mut data = DataMatrix::from_data_type(data_type, a_scan_length, chunk_size);
+            let capacity = data.as_u8_slice().len();
 
+            let mut len = 0;
             loop {
-                match file.read(&mut data.as_mut_u8_slice()[index..]).await? {
+                match file.read(&mut data.as_mut_u8_slice()[len..]).await? {
                     0 => break,
-                    len => index += len,
+                    read => len += read,
                 }
             }
-            let ncols = index / a_scan_length / data_type.size();
 
-            if ncols < CHUNK_SIZE {
-                if index == 0 {
-                    break;
-                }
-                let data = data.resize_horizontally(ncols);
-                tx.send(Arc::new(data));
+            if let Some(chunk) = decode_chunk(data, len, endianness) {
+                tx.send(chunk);
+            }
+
+            // Only the last chunk is not filled
+            if len < capacity {
                 break;
             }
 
-            bytes_read += index;
+            bytes_read += len;
             let _ = progress_tx.send(Some(bytes_read as f32 / file_len as f32));
-
-            tx.send(Arc::new(data));
         }
+        tx.finish();
 
         let _ = progress_tx.send(None);
 
         Ok(())
     }
 }
+
+/// The whole A scans in the first `len` bytes read into `data`, converted
+/// from `endianness`. [None], if not even one A scan was read.
+fn decode_chunk(mut data: DataMatrix, len: usize, endianness: Endianness) -> Option<DataMatrix> {
+    let data_type = data.data_type();
+    let ncols = Dimensions::of_file(len, data_type, data.nrows()).a_scan_count;
+    if ncols == 0 {
+        return None;
+    }
+
+    let bytes = ncols * data.nrows() * data_type.size();
+    endianness.convert(&mut data.as_mut_u8_slice()[..bytes], data_type);
+
+    Some(match ncols == data.ncols() {
+        true => data,
+        false => data.resize_horizontally(ncols),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{pipeline::random::Lcg, queue_channel::error::RecvError};
+
+    use super::*;
+
+    /// A raw file, whose value at position `i` is `i`, and the M scan it
+    /// holds.
+    struct Case {
+        data_type: DataType,
+        endianness: Endianness,
+        a_scan_samples: usize,
+        a_scan_count: usize,
+        /// Bytes of an incomplete A scan at the end of the file.
+        trailing: usize,
+        chunk_size: usize,
+    }
+
+    impl Case {
+        fn random(rng: &mut Lcg) -> Self {
+            let data_type = DataType::VALUES[rng.below(DataType::VALUES.len())];
+            let a_scan_samples = 1 + rng.below(33);
+            Self {
+                data_type,
+                endianness: Endianness::VALUES[rng.below(2)],
+                a_scan_samples,
+                // Often none or exactly one chunk of A scans
+                a_scan_count: match rng.below(4) {
+                    0 => 0,
+                    _ => rng.below(60),
+                },
+                trailing: rng.below(a_scan_samples * data_type.size()),
+                chunk_size: 1 + rng.below(9),
+            }
+        }
+
+        /// Values `0..len` in native byte order.
+        fn values(&self, len: usize) -> Vec<u8> {
+            (0..len)
+                .flat_map(|i| match self.data_type {
+                    DataType::U8 => vec![i as u8],
+                    DataType::U16 => (i as u16).to_ne_bytes().to_vec(),
+                    DataType::U32 => (i as u32).to_ne_bytes().to_vec(),
+                    DataType::U64 => (i as u64).to_ne_bytes().to_vec(),
+                    DataType::F32 => (i as f32).to_ne_bytes().to_vec(),
+                    DataType::F64 => (i as f64).to_ne_bytes().to_vec(),
+                })
+                .collect()
+        }
+
+        fn file(&self) -> Vec<u8> {
+            let size = self.data_type.size();
+            let values = self.a_scan_count * self.a_scan_samples + self.trailing.div_ceil(size);
+
+            let mut bytes = self.values(values);
+            self.endianness.convert(&mut bytes, self.data_type);
+            bytes.truncate(self.a_scan_count * self.a_scan_samples * size + self.trailing);
+            bytes
+        }
+
+        fn m_scan(&self) -> DataMatrix {
+            let mut m_scan =
+                DataMatrix::from_data_type(self.data_type, self.a_scan_samples, self.a_scan_count);
+            m_scan
+                .as_mut_u8_slice()
+                .copy_from_slice(&self.values(self.a_scan_count * self.a_scan_samples));
+            m_scan
+        }
+    }
+
+    /// The chunks put back together, or an empty M scan without chunks.
+    fn reassemble(case: &Case, chunks: &[DataMatrix]) -> DataMatrix {
+        chunks.iter().fold(
+            DataMatrix::from_data_type(case.data_type, case.a_scan_samples, 0),
+            |m_scan, chunk| m_scan.concat_horizontally(chunk).unwrap(),
+        )
+    }
+
+    #[test]
+    fn chunks_reassemble_to_file() {
+        let mut rng = Lcg::new(0x5eed);
+
+        for _ in 0..500 {
+            let case = Case::random(&mut rng);
+            let file = case.file();
+            let (size, samples) = (case.data_type.size(), case.a_scan_samples);
+
+            assert_eq!(
+                Dimensions::of_file(file.len(), case.data_type, samples).a_scan_count,
+                case.a_scan_count
+            );
+
+            // Reads like the task, with reads filling the whole chunk
+            let mut chunks = Vec::new();
+            let mut offset = 0;
+            loop {
+                let mut data = DataMatrix::from_data_type(case.data_type, samples, case.chunk_size);
+                let capacity = data.as_u8_slice().len();
+                let len = capacity.min(file.len() - offset);
+                data.as_mut_u8_slice()[..len].copy_from_slice(&file[offset..offset + len]);
+                offset += len;
+
+                if let Some(chunk) = decode_chunk(data, len, case.endianness) {
+                    assert!(chunk.ncols() > 0);
+                    assert_eq!(chunk.nrows(), samples);
+                    chunks.push(chunk);
+                }
+                if len < capacity {
+                    break;
+                }
+            }
+
+            assert_eq!(
+                chunks.iter().map(|c| c.ncols()).sum::<usize>(),
+                case.a_scan_count,
+            );
+            assert_eq!(
+                reassemble(&case, &chunks),
+                case.m_scan(),
+                "{:?} {:?}, {} samples, {} A scans, {} trailing bytes of {}, chunks of {}",
+                case.data_type,
+                case.endianness,
+                samples,
+                case.a_scan_count,
+                case.trailing,
+                size,
+                case.chunk_size,
+            );
+        }
+
+        // Not even one sample
+        let data = DataMatrix::from_data_type(DataType::U16, 0, 4);
+        assert!(decode_chunk(data, 0, Endianness::Little).is_none());
+        assert_eq!(Dimensions::of_file(100, DataType::U16, 0).a_scan_count, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn streams_files() {
+        let dir = std::env::temp_dir().join("ivoct_binary_input_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("m_scan.bin");
+
+        let mut rng = Lcg::new(0xf11e);
+
+        for i in 0..40 {
+            let case = Case::random(&mut rng);
+
+            // With a header, the settings of the node are ignored
+            let mut file = Vec::new();
+            let with_header = i % 2 == 0;
+            if with_header {
+                let header = RawHeader {
+                    endianness: case.endianness,
+                    data_type: case.data_type,
+                    a_scan_samples: case.a_scan_samples,
+                    a_scan_count: case.a_scan_count,
+                };
+                file.extend(header.to_bytes());
+            }
+            file.extend(case.file());
+            std::fs::write(&path, &file).unwrap();
+
+            let (mut progress_tx, _progress_rx) = watch::channel(None);
+            let mut rx = None;
+            let mut dimensions = None;
+            Task::respond_streamed(
+                &mut progress_tx,
+                &path,
+                match with_header {
+                    true => DataType::U8,
+                    false => case.data_type,
+                },
+                match with_header {
+                    true => case.a_scan_samples + 1,
+                    false => case.a_scan_samples,
+                },
+                case.endianness,
+                case.chunk_size,
+                |res, a_scan_samples, a_scan_count| {
+                    rx = res.subscribe();
+                    dimensions = Some((a_scan_samples, a_scan_count));
+                },
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(
+                dimensions,
+                Some((case.a_scan_samples, case.a_scan_count)),
+                "Header: {with_header}"
+            );
+
+            let mut rx = rx.unwrap();
+            let mut chunks = Vec::new();
+            loop {
+                match rx.recv().await {
+                    Ok(chunk) => chunks.push(Arc::unwrap_or_clone(chunk)),
+                    Err(RecvError::Closed) => break,
+                    Err(e) => panic!("{e:?}"),
+                }
+            }
+
+            assert_eq!(reassemble(&case, &chunks), case.m_scan());
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+use futures::FutureExt;
+
+use crate::pipeline::execution::Request;
+
+use super::prelude::*;
+
+pub enum InputId {
+    BScans,
+    Lumen,
+    Diameter,
+    Area,
+}
+
+impl_enum_from_into_id_types!(InputId, [graph::InputId], {
+    0 => BScans,
+    1 => Lumen,
+    2 => Diameter,
+    3 => Area,
+});
+
+// MARK: Node
+
+/// Merges segmentations and diameters into one output, so they can be
+/// exported together, see [crate::pipeline::pullback_format]. Every input is
+/// optional.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Node {
+    pub b_scans: NodeInput<()>,
+    pub lumen: NodeInput<()>,
+    pub diameter: NodeInput<()>,
+    /// Area of every B scan, as computed by the lumen volume node.
+    #[serde(default)]
+    pub area: NodeInput<()>,
+}
+
+deserialize_node!(Node, "bundle");
+
+impl PipelineNode for Node {
+    type InputId = InputId;
+    type OutputId = OutputIdSingle;
+
+    fn slug() -> &'static str {
+        "bundle"
+    }
+
+    fn inputs(
+        &self,
+    ) -> impl Iterator<Item = (<Self as PipelineNode>::InputId, Option<NodeOutput>)> {
+        [
+            (InputId::BScans, self.b_scans.connection()),
+            (InputId::Lumen, self.lumen.connection()),
+            (InputId::Diameter, self.diameter.connection()),
+            (InputId::Area, self.area.connection()),
+        ]
+        .into_iter()
+    }
+
+    fn changed(&self, _other: &Self) -> bool {
+        false
+    }
+
+    fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
+        let bundle_out = builder.output(OutputIdSingle);
+
+        builder.task(Task {
+            bundle_out,
+            b_scans_in: TaskInput::default(),
+            lumen_in: TaskInput::default(),
+            diameter_in: TaskInput::default(),
+            area_in: TaskInput::default(),
+        });
+    }
+}
+
+// MARK: Task
+
+struct Task {
+    bundle_out: TaskOutput<requests::Bundle>,
+    b_scans_in: TaskInput<requests::BScanSegmentation>,
+    lumen_in: TaskInput<requests::MScanSegmentation>,
+    diameter_in: TaskInput<requests::Diameter>,
+    area_in: TaskInput<requests::VectorData>,
+}
+
+/// Requests `input`, if it is connected. [None], if it is connected, but
+/// gave no response.
+async fn request_connected<Req: Request>(
+    input: &mut TaskInput<Req>,
+    req: Req,
+) -> Option<Option<Req::Response>> {
+    match input.is_connected() {
+        true => input.request(req).await.map(Some),
+        false => Some(None),
+    }
+}
+
+impl NodeTask for Task {
+    type InputId = InputId;
+    type PipelineNode = Node;
+
+    fn connect(&mut self, input_id: Self::InputId, input: &mut ConnectionHandle) {
+        match input_id {
+            InputId::BScans => self.b_scans_in.connect(input),
+            InputId::Lumen => self.lumen_in.connect(input),
+            InputId::Diameter => self.diameter_in.connect(input),
+            InputId::Area => self.area_in.connect(input),
+        };
+    }
+
+    fn disconnect(&mut self, input_id: Self::InputId) {
+        match input_id {
+            InputId::BScans => self.b_scans_in.disconnect(),
+            InputId::Lumen => self.lumen_in.disconnect(),
+            InputId::Diameter => self.diameter_in.disconnect(),
+            InputId::Area => self.area_in.disconnect(),
+        }
+    }
+
+    fn sync_node(&mut self, _node: &Self::PipelineNode) {}
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        let _req = self.bundle_out.receive().await;
+
+        let (Some(b_scan_segmentation), Some(lumen), Some(diameter), Some(area)) = futures::join!(
+            request_connected(&mut self.b_scans_in, requests::BScanSegmentation),
+            request_connected(&mut self.lumen_in, requests::MScanSegmentation),
+            request_connected(&mut self.diameter_in, requests::Diameter),
+            request_connected(&mut self.area_in, requests::VectorData),
+        ) else {
+            return Ok(());
+        };
+
+        let a_scan_count = [
+            b_scan_segmentation.as_ref().map(|res| res.a_scan_count),
+            lumen.as_ref().map(|res| res.a_scan_count),
+            diameter.as_ref().map(|res| res.a_scan_count),
+        ]
+        .into_iter()
+        .flatten()
+        .next()
+        .unwrap_or(0);
+
+        self.bundle_out.respond(requests::BundleResponse {
+            b_scan_segmentation,
+            lumen,
+            diameter,
+            area,
+            a_scan_count,
+        });
+        self.bundle_out.receive().now_or_never();
+
+        Ok(())
+    }
+}
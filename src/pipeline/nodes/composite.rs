@@ -0,0 +1,573 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    gui::node_graph::NodeGraphEditState,
+    pipeline::{execution::flatten, Pipeline},
+    settings,
+};
+
+use super::prelude::*;
+
+/// Name of the file in [eframe::storage_dir], the [Library] is stored in.
+const LIBRARY_FILE_NAME: &str = "composites.json";
+
+/// An input of a composite, passing the data it receives to nodes inside.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputPin {
+    pub name: String,
+    pub type_id: TypeId,
+    /// Inputs of the nodes inside, that receive the data. Only while they are
+    /// not connected inside.
+    pub targets: Vec<(NodeId, InputId)>,
+    pub connection: Option<NodeOutput>,
+}
+
+/// An output of a composite, serving the data of a node inside.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputPin {
+    pub name: String,
+    /// Output of a node inside.
+    pub output: NodeOutput,
+}
+
+// MARK: Node
+
+/// A group of nodes, shown as a single node. The executor expands it into the
+/// nodes inside, see [flatten], so it processes exactly like them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Node {
+    pub name: String,
+    /// Nodes inside of the composite. Their ids are only unique inside.
+    pub pipeline: Pipeline,
+    /// Layout of the nodes inside, when the composite is opened.
+    pub state: NodeGraphEditState,
+    pub inputs: Vec<InputPin>,
+    pub outputs: Vec<OutputPin>,
+    /// Entry of the [Library], the composite was created from. Changing the
+    /// entry does not change the composite, until it is updated from it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+}
+
+impl Clone for Node {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            pipeline: self.pipeline.snapshot(),
+            state: self.state.clone(),
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+            library: self.library.clone(),
+        }
+    }
+}
+
+impl Node {
+    /// Number of composites nested into each other, including this one.
+    pub fn depth(&self) -> usize {
+        1 + self
+            .pipeline
+            .nodes
+            .values()
+            .filter_map(|node| node.as_any().downcast_ref::<Node>())
+            .map(Node::depth)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Replaces the nodes and pins with the ones of `entry`, keeping the name
+    /// and the connections of pins, that still accept them.
+    pub fn update_from(&mut self, entry: &Node) {
+        let connections = self
+            .inputs
+            .iter()
+            .map(|pin| pin.connection)
+            .collect::<Vec<_>>();
+
+        let updated = entry.clone();
+        self.pipeline = updated.pipeline;
+        self.state = updated.state;
+        self.outputs = updated.outputs;
+        self.inputs = updated.inputs;
+
+        for (pin, connection) in self.inputs.iter_mut().zip(connections) {
+            pin.connection = connection.filter(|c| c.type_id == pin.type_id);
+        }
+    }
+}
+
+deserialize_node!(Node, "composite");
+
+impl PipelineNode for Node {
+    type InputId = InputId;
+    type OutputId = OutputId;
+
+    fn slug() -> &'static str {
+        "composite"
+    }
+
+    fn inputs(&self) -> impl Iterator<Item = (InputId, Option<NodeOutput>)> {
+        self.inputs
+            .iter()
+            .enumerate()
+            .map(|(i, pin)| (i.into(), pin.connection))
+    }
+
+    fn changed(&self, other: &Self) -> bool {
+        self.inputs != other.inputs
+            || self.outputs != other.outputs
+            || self.pipeline.changed(&other.pipeline)
+    }
+
+    fn get_output_id_for_view_request(&self) -> Option<(OutputId, impl Into<TypeId>)> {
+        self.outputs
+            .first()
+            .map(|pin| (0.into(), pin.output.type_id))
+    }
+
+    fn visit_paths(&self, visitor: &mut dyn FnMut(PathRole, &Path)) {
+        for node in self.pipeline.nodes.values() {
+            node.visit_paths(visitor);
+        }
+    }
+
+    fn nondeterminism(&self) -> Option<&'static str> {
+        self.pipeline
+            .nondeterministic_nodes()
+            .first()
+            .map(|(_, reason)| *reason)
+    }
+
+    fn validate(&mut self) -> Vec<ValidationIssue> {
+        let issues = self.pipeline.validate();
+
+        issues
+            .into_iter()
+            .flat_map(|(node_id, issues)| {
+                let name = self.pipeline[node_id].name().to_owned();
+                issues.into_iter().map(move |issue| ValidationIssue {
+                    description: format!("In {}: {}", name, issue.description),
+                    ..issue
+                })
+            })
+            .collect()
+    }
+
+    fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
+        builder.task(Task);
+    }
+}
+
+// MARK: Task
+
+/// Composites never run themselves, the executor runs the nodes inside
+/// instead, see [flatten].
+struct Task;
+
+impl NodeTask for Task {
+    type InputId = InputId;
+    type PipelineNode = Node;
+
+    fn connect(&mut self, _input_id: Self::InputId, _input: &mut ConnectionHandle) {}
+
+    fn disconnect(&mut self, _input_id: Self::InputId) {}
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        futures::future::pending().await
+    }
+}
+
+// MARK: Collapse
+
+/// Moves the nodes `node_ids` into a new composite, returning its id. Every
+/// connection crossing the group becomes a pin of the composite, as well as
+/// the outputs of nodes, that nothing inside is connected to. [None], if none
+/// of the nodes exist, the composites would be nested too deep, see
+/// [flatten::MAX_DEPTH], or the node ids are used up.
+pub fn collapse(
+    pipeline: &mut Pipeline,
+    node_ids: &[NodeId],
+    state: NodeGraphEditState,
+) -> Option<NodeId> {
+    let group = node_ids
+        .iter()
+        .copied()
+        .filter(|node_id| pipeline.nodes.contains_key(node_id))
+        .collect::<BTreeSet<_>>();
+
+    let depth = group
+        .iter()
+        .filter_map(|node_id| pipeline.nodes[node_id].as_any().downcast_ref::<Node>())
+        .map(Node::depth)
+        .max()
+        .unwrap_or(0);
+    if group.is_empty() || depth >= flatten::MAX_DEPTH {
+        return None;
+    }
+
+    let id = pipeline.next_node_id()?;
+
+    let mut inner = Pipeline::new();
+    for node_id in &group {
+        inner
+            .nodes
+            .insert(*node_id, pipeline.nodes.remove(node_id).unwrap());
+        if pipeline.disabled.remove(node_id) {
+            inner.disabled.insert(*node_id);
+        }
+    }
+
+    // Connections from outside. Inputs receiving the same output share a pin
+    let mut inputs = Vec::<InputPin>::new();
+    for node_id in &group {
+        for (input_id, connection) in inner.nodes[node_id].inputs() {
+            let Some(connection) = connection.filter(|c| !group.contains(&c.node_id)) else {
+                continue;
+            };
+
+            match inputs
+                .iter_mut()
+                .find(|pin| pin.connection == Some(connection))
+            {
+                Some(pin) => pin.targets.push((*node_id, input_id)),
+                None => inputs.push(InputPin {
+                    name: inner[*node_id].name().to_owned(),
+                    type_id: connection.type_id,
+                    targets: vec![(*node_id, input_id)],
+                    connection: Some(connection),
+                }),
+            }
+            inner[*node_id].disconnect(input_id);
+        }
+    }
+
+    // Connections to outside
+    let mut outputs = Vec::<OutputPin>::new();
+    let mut outer_ids = pipeline.nodes.keys().copied().collect::<Vec<_>>();
+    outer_ids.sort();
+    for node_id in outer_ids {
+        for (input_id, connection) in pipeline.nodes[&node_id].inputs() {
+            let Some(connection) = connection.filter(|c| group.contains(&c.node_id)) else {
+                continue;
+            };
+
+            let index = output_pin(&mut outputs, &inner, connection);
+            let output = NodeOutput::new(id, index.into(), connection.type_id);
+            pipeline[node_id].connect(input_id, output);
+        }
+    }
+
+    // Results of the group, that nothing is connected to yet
+    let consumed = group
+        .iter()
+        .flat_map(|node_id| inner.nodes[node_id].inputs())
+        .filter_map(|(_, connection)| connection)
+        .collect::<Vec<_>>();
+    for node_id in &group {
+        let Some((output_id, type_id)) = inner.nodes[node_id].get_output_for_view_request() else {
+            continue;
+        };
+        let output = NodeOutput::new(*node_id, output_id, type_id);
+        if !consumed.contains(&output) {
+            output_pin(&mut outputs, &inner, output);
+        }
+    }
+
+    pipeline.nodes.insert(
+        id,
+        Box::new(Node {
+            name: "Group".to_owned(),
+            pipeline: inner,
+            state,
+            inputs,
+            outputs,
+            library: None,
+        }),
+    );
+
+    Some(id)
+}
+
+/// Index of the pin serving `output`, adding it if there is none.
+fn output_pin(outputs: &mut Vec<OutputPin>, inner: &Pipeline, output: NodeOutput) -> usize {
+    if let Some(index) = outputs.iter().position(|pin| pin.output == output) {
+        return index;
+    }
+
+    outputs.push(OutputPin {
+        name: inner[output.node_id].name().to_owned(),
+        output,
+    });
+    outputs.len() - 1
+}
+
+/// The composite at `path`, which starts with a composite of `pipeline`,
+/// followed by composites inside of the previous one.
+pub fn at_path_mut<'a>(pipeline: &'a mut Pipeline, path: &[NodeId]) -> Option<&'a mut Node> {
+    let (first, rest) = path.split_first()?;
+    let composite = pipeline
+        .nodes
+        .get_mut(first)?
+        .as_any_mut()
+        .downcast_mut::<Node>()?;
+
+    match rest.is_empty() {
+        true => Some(composite),
+        false => at_path_mut(&mut composite.pipeline, rest),
+    }
+}
+
+// MARK: Library
+
+/// Composites saved to reuse them in other pipelines, by their name. Stored
+/// in the storage directory of the app, see [Self::load].
+///
+/// Pipelines contain copies of the entries, so changing an entry does not
+/// change them, until they are updated explicitly, see [Node::update_from].
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Library {
+    entries: BTreeMap<String, Node>,
+}
+
+impl Library {
+    pub fn path() -> Option<PathBuf> {
+        eframe::storage_dir(settings::APP_NAME).map(|dir| dir.join(LIBRARY_FILE_NAME))
+    }
+
+    /// Loads the library, or an empty one, if it does not exist yet.
+    pub fn load() -> anyhow::Result<Self> {
+        match Self::path() {
+            Some(path) if path.exists() => Self::load_from(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path().ok_or_else(|| anyhow::anyhow!("No storage directory"))?;
+        self.save_to(&path)
+    }
+
+    pub fn save_to(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Node> {
+        self.entries.get(name)
+    }
+
+    /// Saves a copy of `composite` under its name, replacing an entry with the
+    /// same name. The connections of its pins are left out.
+    pub fn insert(&mut self, composite: &Node) {
+        let mut entry = composite.clone();
+        entry.library = Some(entry.name.clone());
+        for pin in &mut entry.inputs {
+            pin.connection = None;
+        }
+
+        self.entries.insert(entry.name.clone(), entry);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.entries.remove(name);
+    }
+
+    /// A new composite from the entry `name`.
+    pub fn instantiate(&self, name: &str) -> Option<Node> {
+        self.entries.get(name).cloned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pipeline::nodes::{filter, remove_detector_defect};
+
+    use super::*;
+
+    /// Input → Remove Detector Defect → Gaussian → Align Brightness → Output
+    fn preprocessing() -> (Pipeline, [NodeId; 5]) {
+        let ids = [1, 2, 3, 4, 5].map(NodeId::from);
+        let mut pipeline = Pipeline::new();
+        pipeline.nodes.insert(
+            ids[0],
+            Box::new(super::super::binary_input::Node::m_scan(
+                PathBuf::new(),
+                None,
+            )),
+        );
+        pipeline
+            .nodes
+            .insert(ids[1], Box::new(remove_detector_defect::Node::new()));
+        pipeline
+            .nodes
+            .insert(ids[2], Box::new(filter::Node::gaussian()));
+        pipeline
+            .nodes
+            .insert(ids[3], Box::new(filter::Node::align_brightness()));
+        pipeline
+            .nodes
+            .insert(ids[4], Box::new(super::super::output::Node::default()));
+
+        let m_scan = PipelineDataType::MScan.into();
+        for (from, to) in ids.iter().zip(ids.iter().skip(1)) {
+            pipeline[*to].connect(0.into(), NodeOutput::new(*from, 0.into(), m_scan));
+        }
+
+        (pipeline, ids)
+    }
+
+    #[test]
+    fn collapse_group() {
+        let (mut pipeline, ids) = preprocessing();
+        pipeline.disabled.insert(ids[2]);
+
+        let id = collapse(&mut pipeline, &ids[1..4], NodeGraphEditState::new()).unwrap();
+        assert_eq!(id, NodeId::from(6));
+        assert_eq!(pipeline.nodes.len(), 3);
+        assert!(pipeline.disabled.is_empty());
+
+        let composite = pipeline[id].as_any().downcast_ref::<Node>().unwrap();
+        assert_eq!(composite.pipeline.nodes.len(), 3);
+        assert!(composite.pipeline.disabled.contains(&ids[2]));
+
+        // The input became a pin, disconnected inside
+        assert_eq!(composite.inputs.len(), 1);
+        assert_eq!(composite.inputs[0].targets, vec![(ids[1], 0.into())]);
+        assert_eq!(composite.pipeline[ids[1]].inputs(), vec![(0.into(), None)]);
+        assert_eq!(
+            pipeline[id].inputs(),
+            vec![(
+                0.into(),
+                Some(NodeOutput::new(
+                    ids[0],
+                    0.into(),
+                    PipelineDataType::MScan.into()
+                ))
+            )]
+        );
+
+        // Connections inside stay
+        assert_eq!(
+            composite.pipeline[ids[3]].inputs()[0].1.map(|c| c.node_id),
+            Some(ids[2])
+        );
+
+        // The output now receives the pin
+        assert_eq!(composite.outputs.len(), 1);
+        assert_eq!(composite.outputs[0].output.node_id, ids[3]);
+        assert_eq!(
+            pipeline[ids[4]].inputs()[0]
+                .1
+                .map(|c| (c.node_id, c.output_id)),
+            Some((id, 0.into()))
+        );
+
+        assert_eq!(
+            collapse(&mut pipeline, &[100.into()], NodeGraphEditState::new()),
+            None
+        );
+    }
+
+    #[test]
+    fn unconnected_results_become_pins() {
+        let (mut pipeline, ids) = preprocessing();
+        pipeline.nodes.remove(&ids[4]);
+
+        let id = collapse(&mut pipeline, &ids[1..4], NodeGraphEditState::new()).unwrap();
+        let composite = pipeline[id].as_any().downcast_ref::<Node>().unwrap();
+        assert_eq!(composite.outputs.len(), 1);
+        assert_eq!(composite.outputs[0].output.node_id, ids[3]);
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let (mut pipeline, _) = preprocessing();
+
+        let mut nested = Vec::new();
+        loop {
+            let ids = pipeline.nodes.keys().copied().collect::<Vec<_>>();
+            match collapse(&mut pipeline, &ids, NodeGraphEditState::new()) {
+                Some(id) => nested.push(id),
+                None => break,
+            }
+        }
+
+        assert_eq!(nested.len(), flatten::MAX_DEPTH);
+        let composite = pipeline[*nested.last().unwrap()]
+            .as_any()
+            .downcast_ref::<Node>()
+            .unwrap();
+        assert_eq!(composite.depth(), flatten::MAX_DEPTH);
+    }
+
+    #[test]
+    fn changes_inside() {
+        let (mut pipeline, ids) = preprocessing();
+        let id = collapse(&mut pipeline, &ids[1..4], NodeGraphEditState::new()).unwrap();
+        let composite = pipeline[id].as_any().downcast_ref::<Node>().unwrap();
+        assert!(!PipelineNode::changed(&composite.clone(), composite));
+
+        // A node of another type with the same id
+        let mut copy = composite.clone();
+        copy.pipeline
+            .nodes
+            .insert(ids[2], Box::new(remove_detector_defect::Node::new()));
+        assert!(PipelineNode::changed(&copy, composite));
+
+        let mut copy = composite.clone();
+        copy.pipeline.disabled.insert(ids[1]);
+        assert!(PipelineNode::changed(&copy, composite));
+
+        let mut copy = composite.clone();
+        copy.pipeline[ids[3]].disconnect(0.into());
+        assert!(PipelineNode::changed(&copy, composite));
+    }
+
+    #[test]
+    fn library_instances_are_copies() {
+        let (mut pipeline, ids) = preprocessing();
+        let id = collapse(&mut pipeline, &ids[1..4], NodeGraphEditState::new()).unwrap();
+
+        let composite = at_path_mut(&mut pipeline, &[id]).unwrap();
+        composite.name = "Preprocessing".to_owned();
+
+        let mut library = Library::default();
+        library.insert(composite);
+
+        let dir = std::env::temp_dir().join("ivoct_composite_test");
+        let path = dir.join(LIBRARY_FILE_NAME);
+        library.save_to(&path).unwrap();
+        let mut library = Library::load_from(&path).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let mut instance = library.instantiate("Preprocessing").unwrap();
+        assert_eq!(instance.library.as_deref(), Some("Preprocessing"));
+        assert_eq!(instance.inputs[0].connection, None);
+
+        // Changing the entry leaves the instance alone, until it is updated
+        let mut entry = library.instantiate("Preprocessing").unwrap();
+        entry.pipeline.nodes.remove(&ids[2]);
+        library.insert(&entry);
+        assert_eq!(instance.pipeline.nodes.len(), 3);
+
+        let connection = NodeOutput::new(ids[0], 0.into(), PipelineDataType::MScan.into());
+        instance.inputs[0].connection = Some(connection);
+        instance.update_from(library.get("Preprocessing").unwrap());
+        assert_eq!(instance.pipeline.nodes.len(), 2);
+        assert_eq!(instance.inputs[0].connection, Some(connection));
+    }
+}
@@ -0,0 +1,244 @@
+use std::{ops::Range, sync::Arc};
+
+use futures::FutureExt;
+
+use crate::{pipeline::types::DataMatrix, queue_channel::error::RecvError};
+
+use super::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    /// A scans kept, counted from the start of the M scan. Ends past the M
+    /// scan keep everything up to its end.
+    pub a_scan_range: Range<usize>,
+    /// Samples of every A scan kept.
+    pub sample_range: Range<usize>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            a_scan_range: 0..usize::MAX,
+            sample_range: 0..usize::MAX,
+        }
+    }
+}
+
+// MARK: Node
+
+/// Keeps a region of interest of the M scan, a range of A scans and a range
+/// of samples of each of them.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Node {
+    pub settings: Settings,
+
+    pub m_scan: NodeInput<()>,
+}
+
+deserialize_node!(Node, "crop");
+
+impl PipelineNode for Node {
+    type InputId = InputIdSingle;
+    type OutputId = OutputIdSingle;
+
+    fn slug() -> &'static str {
+        "crop"
+    }
+
+    fn inputs(
+        &self,
+    ) -> impl Iterator<Item = (<Self as PipelineNode>::InputId, Option<NodeOutput>)> {
+        [(InputIdSingle, self.m_scan.connection())].into_iter()
+    }
+
+    fn changed(&self, other: &Self) -> bool {
+        self.settings != other.settings
+    }
+
+    fn get_output_id_for_view_request(&self) -> Option<(OutputIdSingle, impl Into<TypeId>)> {
+        Some((OutputIdSingle, PipelineDataType::MScan))
+    }
+
+    fn estimate_memory(&self, upstream: &UpstreamStats) -> MemoryEstimate {
+        let output = UpstreamStats {
+            a_scan_count: clamp(&self.settings.a_scan_range, upstream.a_scan_count).len(),
+            samples: clamp(&self.settings.sample_range, upstream.samples).len(),
+            ..*upstream
+        };
+
+        MemoryEstimate::streaming(upstream, output)
+    }
+
+    fn validate(&mut self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+
+        for (setting, range) in [
+            ("A Scans", &mut self.settings.a_scan_range),
+            ("Samples", &mut self.settings.sample_range),
+        ] {
+            if range.end <= range.start {
+                range.end = range.start.saturating_add(1);
+                issues.push(ValidationIssue::corrected(
+                    setting,
+                    format!("The range was empty, it now ends at {}", range.end),
+                ));
+            }
+        }
+
+        issues
+    }
+
+    fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
+        let m_scan_out = builder.output(OutputIdSingle);
+
+        builder.task(Task {
+            settings: self.settings.clone(),
+            m_scan_out,
+            m_scan_in: TaskInput::default(),
+        });
+    }
+}
+
+// MARK: Task
+
+struct Task {
+    settings: Settings,
+
+    m_scan_out: TaskOutput<requests::MScan>,
+    m_scan_in: TaskInput<requests::MScan>,
+}
+
+impl NodeTask for Task {
+    type InputId = InputIdSingle;
+    type PipelineNode = Node;
+
+    fn connect(&mut self, _input_id: Self::InputId, input: &mut ConnectionHandle) {
+        self.m_scan_in.connect(input);
+    }
+
+    fn disconnect(&mut self, _input_id: Self::InputId) {
+        self.m_scan_in.disconnect();
+    }
+
+    fn sync_node(&mut self, node: &Self::PipelineNode) {
+        self.settings = node.settings.clone();
+    }
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        let _req = self.m_scan_out.receive().await;
+
+        let Some(m_scan_res) = self.m_scan_in.request(requests::MScan).await else {
+            return Ok(());
+        };
+        let Some(mut m_scan) = m_scan_res.data.subscribe() else {
+            return Ok(());
+        };
+
+        let a_scans = clamp(&self.settings.a_scan_range, m_scan_res.a_scan_count);
+        let samples = clamp(&self.settings.sample_range, m_scan_res.a_scan_samples);
+
+        let (res, tx) = requests::StreamedResponse::with_default_capacity();
+
+        self.m_scan_out.respond(requests::MScanResponse {
+            data: res,
+            a_scan_count: a_scans.len(),
+            a_scan_samples: samples.len(),
+        });
+        self.m_scan_out.receive().now_or_never();
+
+        // Index of the first A scan of the next chunk
+        let mut offset = 0;
+
+        loop {
+            let chunk = match m_scan.recv().await {
+                Ok(chunk) => chunk,
+                Err(RecvError::Closed) => break,
+                Err(e) => Err(e)?,
+            };
+
+            let start = offset;
+            offset += chunk.ncols();
+
+            if let Some(chunk) = crop(&chunk, start, &a_scans, &samples) {
+                tx.send(Arc::new(chunk));
+            }
+
+            // Everything after the range is not needed
+            if offset >= a_scans.end {
+                break;
+            }
+        }
+
+        tx.finish();
+
+        Ok(())
+    }
+}
+
+// MARK: Algorithm
+
+/// The part of `range` inside of `0..len`.
+fn clamp(range: &Range<usize>, len: usize) -> Range<usize> {
+    let end = range.end.min(len);
+    range.start.min(end)..end
+}
+
+/// The part of `chunk`, whose first A scan is A scan `offset` of the M scan,
+/// that is inside of `a_scans` and `samples`. [None], if the chunk is outside
+/// of the A scans.
+fn crop(
+    chunk: &DataMatrix,
+    offset: usize,
+    a_scans: &Range<usize>,
+    samples: &Range<usize>,
+) -> Option<DataMatrix> {
+    let a_scans = clamp(
+        &(a_scans.start.saturating_sub(offset)..a_scans.end.saturating_sub(offset)),
+        chunk.ncols(),
+    );
+    if a_scans.is_empty() {
+        return None;
+    }
+
+    let samples = clamp(samples, chunk.nrows());
+    Some(chunk.block(
+        (samples.start, a_scans.start),
+        (samples.len(), a_scans.len()),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::DMatrix;
+
+    use super::*;
+
+    #[test]
+    fn clamped_ranges() {
+        assert_eq!(clamp(&(2..5), 10), 2..5);
+        assert_eq!(clamp(&(2..usize::MAX), 10), 2..10);
+        assert_eq!(clamp(&(12..20), 10), 10..10);
+    }
+
+    #[test]
+    fn chunks_straddling_the_range() {
+        // 4 samples per A scan, whose values are the index of their A scan
+        let chunk = |offset: usize, len: usize| {
+            DataMatrix::U16(DMatrix::from_fn(4, len, |_, col| (offset + col) as u16))
+        };
+        let (a_scans, samples) = (5..12, 1..3);
+
+        let cropped = [(0, 4), (4, 4), (8, 4), (12, 4)]
+            .into_iter()
+            .filter_map(|(offset, len)| crop(&chunk(offset, len), offset, &a_scans, &samples))
+            .collect::<Vec<_>>();
+
+        assert_eq!(cropped.len(), 2);
+        assert_eq!(cropped[0], chunk(5, 3).block((0, 0), (2, 3)));
+        assert_eq!(cropped[1], chunk(8, 4).block((0, 0), (2, 4)));
+
+        // Samples past the end of the A scans are left out
+        let cropped = crop(&chunk(0, 2), 0, &(0..usize::MAX), &(2..usize::MAX)).unwrap();
+        assert_eq!((cropped.nrows(), cropped.ncols()), (2, 2));
+    }
+}
@@ -145,16 +145,19 @@ impl NodeTask for Task {
 
         // Get a receiver for all inputs
         let (Some(mut b_scans), Some(mut catheter), Some(mut lumen)) = (
-            b_scans_res.subscribe(),
-            catheter_res.subscribe(),
-            lumen_res.subscribe(),
+            b_scans_res.data.subscribe(),
+            catheter_res.data.subscribe(),
+            lumen_res.data.subscribe(),
         ) else {
             return Ok(());
         };
 
-        let (res, tx) = requests::StreamedResponse::new(100);
+        let (res, tx) = requests::StreamedResponse::with_default_capacity();
 
-        self.diameter_out.respond(res);
+        self.diameter_out.respond(requests::DiameterResponse {
+            data: res,
+            a_scan_count: lumen_res.a_scan_count,
+        });
         self.diameter_out.receive().now_or_never();
 
         let settings = self.settings;
@@ -218,14 +221,29 @@ impl NodeTask for Task {
             }
         }
 
+        tx.finish();
+
         Ok(())
     }
 }
 
 // MARK: Calculate diameter
 
+/// Diameter of the catheter in one B scan in mm. Unless
+/// [Settings::use_catheter_diameter] is set, it is measured from the mean depth
+/// of the catheter segmentation.
+pub(super) fn catheter_diameter(catheter: &[u32], st: &Settings) -> f32 {
+    let diameter = if st.use_catheter_diameter {
+        st.catheter_diameter
+    } else {
+        (catheter.iter().sum::<u32>() as f32 / catheter.len() as f32) * 2.0 * st.mm_per_pixel
+    };
+
+    diameter / st.refraction_index
+}
+
 /// Calculates the diameters for one B scan.
-fn calculate_diameter(
+pub(super) fn calculate_diameter(
     b_scan_start: usize,
     b_scan_end: usize,
     catheter: &[u32],
@@ -236,11 +254,7 @@ fn calculate_diameter(
     let catheter = &catheter[b_scan_start..b_scan_end];
     let lumen = &lumen[b_scan_start..b_scan_end];
 
-    let catheter_diameter = if st.use_catheter_diameter {
-        st.catheter_diameter
-    } else {
-        (catheter.iter().sum::<u32>() as f32 / catheter.len() as f32) * 2.0 * st.mm_per_pixel
-    } / st.refraction_index;
+    let catheter_diameter = catheter_diameter(catheter, st);
 
     let diameters = (0..catheter.len() / 2)
         .map(|i| calc_diameter(catheter, lumen, i, catheter_diameter, st))
@@ -0,0 +1,623 @@
+//! Runs an external command on streamed M scan data.
+//!
+//! Chunks are written to the stdin of the command and processed chunks are
+//! read from its stdout. Both directions use the same framing:
+//!
+//! | Field   | Size           | Description                                         |
+//! |---------|----------------|-----------------------------------------------------|
+//! | magic   | 4              | `b"IVCK"`                                           |
+//! | dtype   | 1              | 0: u8, 1: u16, 2: u32, 3: u64, 4: f32, 5: f64       |
+//! | rows    | 4              | Samples per A scan, u32 little endian               |
+//! | cols    | 4              | Number of A scans, u32 little endian                |
+//! | payload | rows·cols·size | Column major (one A scan after another), little endian |
+//!
+//! Frames of the command must keep the samples per A scan of the input and
+//! may not be larger than [MAX_FRAME_LEN] bytes. The command signals the end of
+//! its output by closing stdout after the last frame. See `doc/scripts/external_command_echo.py` for an example.
+
+use std::{
+    path::{Path, PathBuf},
+    pin::pin,
+    process::{ExitStatus, Stdio},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+use futures::FutureExt;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    process::{Child, ChildStderr, Command},
+    sync::watch,
+    task::JoinHandle,
+};
+
+use crate::{
+    pipeline::types::{DataMatrix, DataType},
+    queue_channel::{self, error::RecvError},
+};
+
+use super::prelude::*;
+
+/// Magic bytes at the start of every frame.
+pub const FRAME_MAGIC: [u8; 4] = *b"IVCK";
+
+/// At most this many bytes of the commands stderr are kept, to attach them to
+/// errors.
+const MAX_STDERR_LEN: usize = 8 * 1024;
+
+/// Upper bound for the payload of a frame read from the command.
+pub const MAX_FRAME_LEN: usize = 1 << 30;
+
+// MARK: Node
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Node {
+    /// Path to the executable.
+    pub command: PathBuf,
+    /// Arguments passed to the executable, separated by whitespace.
+    pub args: String,
+    /// Maximum time in seconds to wait for the next processed chunk, while
+    /// the command has unanswered input.
+    pub timeout: f64,
+
+    pub m_scan: NodeInput<()>,
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self {
+            command: PathBuf::new(),
+            args: String::new(),
+            timeout: 10.0,
+            m_scan: NodeInput::default(),
+        }
+    }
+}
+
+deserialize_node!(Node, "external_command");
+
+impl PipelineNode for Node {
+    type InputId = InputIdSingle;
+    type OutputId = OutputIdSingle;
+
+    fn slug() -> &'static str {
+        "external_command"
+    }
+
+    fn inputs(&self) -> impl Iterator<Item = (InputIdSingle, Option<NodeOutput>)> {
+        std::iter::once((InputIdSingle, self.m_scan.connection()))
+    }
+
+    fn changed(&self, other: &Self) -> bool {
+        self.command != other.command || self.args != other.args || self.timeout != other.timeout
+    }
+
+    fn get_output_id_for_view_request(&self) -> Option<(OutputIdSingle, impl Into<TypeId>)> {
+        Some((OutputIdSingle, PipelineDataType::MScan))
+    }
+
+    fn visit_paths(&self, visitor: &mut dyn FnMut(PathRole, &Path)) {
+        // Commands without a directory are looked up in PATH
+        if self
+            .command
+            .parent()
+            .is_some_and(|dir| !dir.as_os_str().is_empty())
+        {
+            visitor(PathRole::Input, &self.command);
+        }
+    }
+
+    fn replay(&self, _output_id: OutputIdSingle) -> Replay {
+        // The command might answer differently, when run again
+        Replay::Never
+    }
+
+    fn nondeterminism(&self) -> Option<&'static str> {
+        Some("The external command might answer differently, when run again")
+    }
+
+    fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
+        let m_scan_out = builder.output(OutputIdSingle);
+
+        builder.task(Task {
+            config: CommandConfig::from_node(self),
+            m_scan_out,
+            m_scan_in: TaskInput::default(),
+        });
+    }
+}
+
+// MARK: Task
+
+struct Task {
+    config: CommandConfig,
+
+    m_scan_out: TaskOutput<requests::MScan>,
+    m_scan_in: TaskInput<requests::MScan>,
+}
+
+impl NodeTask for Task {
+    type InputId = InputIdSingle;
+    type PipelineNode = Node;
+
+    fn connect(&mut self, _input_id: Self::InputId, input: &mut ConnectionHandle) {
+        self.m_scan_in.connect(input);
+    }
+
+    fn disconnect(&mut self, _input_id: Self::InputId) {
+        self.m_scan_in.disconnect();
+    }
+
+    fn sync_node(&mut self, node: &Self::PipelineNode) {
+        self.config = CommandConfig::from_node(node);
+    }
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        let _req = self.m_scan_out.receive().await;
+
+        let Some(m_scan_res) = self.m_scan_in.request(requests::MScan).await else {
+            return Ok(());
+        };
+
+        let Some(m_scan) = m_scan_res.data.subscribe() else {
+            return Ok(());
+        };
+
+        if self.config.command.as_os_str().is_empty() {
+            return Ok(());
+        }
+
+        let (res, tx) = requests::StreamedResponse::with_default_capacity();
+
+        self.m_scan_out.respond(requests::MScanResponse {
+            data: res,
+            a_scan_count: m_scan_res.a_scan_count,
+            a_scan_samples: m_scan_res.a_scan_samples,
+        });
+        self.m_scan_out.receive().now_or_never();
+
+        // When the node is invalidated, this future is dropped, which kills
+        // the command
+        run_command(&self.config, m_scan, m_scan_res.a_scan_samples, &tx).await?;
+        tx.finish();
+
+        Ok(())
+    }
+}
+
+// MARK: Command
+
+/// Everything needed to spawn the external command.
+#[derive(Debug, Clone)]
+pub struct CommandConfig {
+    pub command: PathBuf,
+    pub args: Vec<String>,
+    /// Maximum time to wait for the next processed chunk, while the command
+    /// has unanswered input.
+    pub timeout: Duration,
+}
+
+impl CommandConfig {
+    fn from_node(node: &Node) -> Self {
+        Self {
+            command: node.command.clone(),
+            args: node.args.split_whitespace().map(String::from).collect(),
+            timeout: Duration::from_secs_f64(node.timeout.max(0.001)),
+        }
+    }
+}
+
+/// A scans written to the command, that it did not answer yet.
+#[derive(Debug, Clone, Copy, Default)]
+struct Outstanding {
+    a_scans: usize,
+    input_closed: bool,
+}
+
+impl Outstanding {
+    /// Whether the command should be working on an answer. Otherwise, it
+    /// waits for its input, which may take arbitrarily long.
+    fn expects_output(&self) -> bool {
+        self.a_scans > 0 || self.input_closed
+    }
+}
+
+/// Spawns the command, streams all chunks of `input` through it and sends the
+/// processed chunks to `output`. Every chunk has `a_scan_samples` rows.
+///
+/// Input is only read as fast as the command consumes it, so nothing is
+/// buffered without bounds. The command is killed when the returned future is
+/// dropped.
+pub async fn run_command(
+    config: &CommandConfig,
+    mut input: queue_channel::Receiver<Arc<DataMatrix>>,
+    a_scan_samples: usize,
+    output: &queue_channel::Sender<Arc<DataMatrix>>,
+) -> anyhow::Result<()> {
+    let mut child = Command::new(&config.command)
+        .args(&config.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to start {}", config.command.display()))?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let stderr = capture_stderr(child.stderr.take().unwrap());
+
+    // The timeout only runs, while the command has something to answer. A
+    // slow upstream is not the fault of the command
+    let (outstanding, _) = watch::channel(Outstanding::default());
+    let outstanding = &outstanding;
+
+    let write = async move {
+        loop {
+            let m_scan = match input.recv().await {
+                Ok(m_scan) => m_scan,
+                Err(RecvError::Closed) => break,
+                Err(e) => Err(e)?,
+            };
+
+            outstanding.send_modify(|o| o.a_scans += m_scan.ncols());
+            write_frame(&mut stdin, &m_scan).await?;
+        }
+
+        // Closing stdin signals the end of the input
+        drop(stdin);
+        outstanding.send_modify(|o| o.input_closed = true);
+
+        anyhow::Ok(())
+    };
+
+    let read = async {
+        let mut outstanding_rx = outstanding.subscribe();
+
+        loop {
+            let mut frame = pin!(read_frame(&mut stdout, a_scan_samples));
+
+            let frame = loop {
+                if outstanding_rx.borrow_and_update().expects_output() {
+                    break tokio::time::timeout(config.timeout, &mut frame)
+                        .await
+                        .map_err(|_| anyhow!("No output for {:?}", config.timeout))?;
+                }
+
+                tokio::select! {
+                    frame = &mut frame => break frame,
+                    _ = outstanding_rx.changed() => {}
+                }
+            }?;
+
+            match frame {
+                Some(m_scan) => {
+                    outstanding
+                        .send_modify(|o| o.a_scans = o.a_scans.saturating_sub(m_scan.ncols()));
+                    output.send(Arc::new(m_scan));
+                }
+                None => break,
+            }
+        }
+
+        anyhow::Ok(())
+    };
+
+    let result = futures::try_join!(write, read);
+
+    let result = match result {
+        Ok(_) => wait_for_exit(&mut child, config.timeout).await,
+        Err(e) => {
+            let _ = child.kill().await;
+            Err(e)
+        }
+    };
+
+    let Err(e) = result else {
+        return Ok(());
+    };
+
+    // The command exited or got killed, so stderr is closed soon
+    let stderr = tokio::time::timeout(Duration::from_secs(1), stderr)
+        .await
+        .ok()
+        .and_then(Result::ok)
+        .unwrap_or_default();
+
+    match stderr.trim() {
+        "" => Err(e),
+        stderr => Err(e.context(format!(
+            "Stderr of {}:\n{}",
+            config.command.display(),
+            stderr
+        ))),
+    }
+}
+
+async fn wait_for_exit(child: &mut Child, timeout: Duration) -> anyhow::Result<()> {
+    let status: ExitStatus = match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => status?,
+        Err(_) => {
+            let _ = child.kill().await;
+            bail!("Command did not exit after closing its output");
+        }
+    };
+
+    if !status.success() {
+        bail!("Command failed with {}", status);
+    }
+
+    Ok(())
+}
+
+/// Reads stderr in the background, keeping the last [MAX_STDERR_LEN] bytes.
+fn capture_stderr(mut stderr: ChildStderr) -> JoinHandle<String> {
+    tokio::spawn(async move {
+        let mut captured = Vec::new();
+        let mut buf = [0; 1024];
+
+        while let Ok(n @ 1..) = stderr.read(&mut buf).await {
+            captured.extend_from_slice(&buf[..n]);
+            if captured.len() > MAX_STDERR_LEN {
+                captured.drain(..captured.len() - MAX_STDERR_LEN);
+            }
+        }
+
+        String::from_utf8_lossy(&captured).into_owned()
+    })
+}
+
+// MARK: Protocol
+
+fn data_type_to_byte(data_type: DataType) -> u8 {
+    DataType::VALUES
+        .iter()
+        .position(|t| *t == data_type)
+        .unwrap() as u8
+}
+
+/// Writes one frame, containing the whole matrix.
+pub async fn write_frame(
+    writer: &mut (impl AsyncWrite + Unpin),
+    m_scan: &DataMatrix,
+) -> anyhow::Result<()> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&FRAME_MAGIC);
+    header.push(data_type_to_byte(m_scan.data_type()));
+    header.extend_from_slice(&(m_scan.nrows() as u32).to_le_bytes());
+    header.extend_from_slice(&(m_scan.ncols() as u32).to_le_bytes());
+
+    writer.write_all(&header).await?;
+    writer.write_all(m_scan.as_u8_slice()).await?;
+    writer.flush().await?;
+
+    Ok(())
+}
+
+/// Reads one frame with `a_scan_samples` rows. Returns [None], if the stream
+/// ended before a new frame.
+pub async fn read_frame(
+    reader: &mut (impl AsyncRead + Unpin),
+    a_scan_samples: usize,
+) -> anyhow::Result<Option<DataMatrix>> {
+    let mut magic = [0; 4];
+    match reader.read(&mut magic[..1]).await? {
+        0 => return Ok(None),
+        _ => reader.read_exact(&mut magic[1..]).await?,
+    };
+
+    if magic != FRAME_MAGIC {
+        bail!("Protocol violation: Invalid frame magic {:?}", magic);
+    }
+
+    let data_type = reader.read_u8().await?;
+    let data_type = *DataType::VALUES
+        .get(data_type as usize)
+        .ok_or_else(|| anyhow!("Protocol violation: Invalid data type {}", data_type))?;
+
+    let rows = reader.read_u32_le().await? as usize;
+    let cols = reader.read_u32_le().await? as usize;
+
+    if rows != a_scan_samples {
+        bail!(
+            "Protocol violation: Frame has {} samples per A scan instead of {}",
+            rows,
+            a_scan_samples
+        );
+    }
+
+    let len = rows
+        .checked_mul(cols)
+        .and_then(|len| len.checked_mul(data_type.size()))
+        .filter(|&len| len <= MAX_FRAME_LEN);
+    if len.is_none() {
+        bail!(
+            "Protocol violation: Frame of {}x{} values exceeds {} bytes",
+            rows,
+            cols,
+            MAX_FRAME_LEN
+        );
+    }
+
+    let mut m_scan = DataMatrix::from_data_type(data_type, rows, cols);
+    reader
+        .read_exact(m_scan.as_mut_u8_slice())
+        .await
+        .context("Protocol violation: Frame payload ended early")?;
+
+    Ok(Some(m_scan))
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::DMatrix;
+
+    use super::*;
+
+    fn test_chunks() -> Vec<DataMatrix> {
+        vec![
+            DMatrix::from_fn(4, 3, |r, c| (r * 3 + c) as u16).into(),
+            DMatrix::from_fn(4, 1, |r, _| r as f32 * 0.5).into(),
+            DMatrix::from_fn(4, 2, |r, c| (r + c) as u8).into(),
+        ]
+    }
+
+    #[tokio::test]
+    async fn frame_roundtrip() {
+        let (mut writer, mut reader) = tokio::io::duplex(64);
+
+        let chunks = test_chunks();
+        let expected = chunks.clone();
+
+        let write = async move {
+            for chunk in &chunks {
+                write_frame(&mut writer, chunk).await.unwrap();
+            }
+        };
+
+        let read = async {
+            let mut received = Vec::new();
+            while let Some(chunk) = read_frame(&mut reader, 4).await.unwrap() {
+                received.push(chunk);
+            }
+            received
+        };
+
+        let ((), received) = futures::join!(write, read);
+
+        assert_eq!(received, expected);
+    }
+
+    #[tokio::test]
+    async fn protocol_violation() {
+        let mut data: &[u8] = b"NOPE\x00\x01\x00\x00\x00\x01\x00\x00\x00\x00";
+        assert!(read_frame(&mut data, 1).await.is_err());
+
+        // Payload too short
+        let mut data: &[u8] = b"IVCK\x00\x02\x00\x00\x00\x01\x00\x00\x00\x00";
+        assert!(read_frame(&mut data, 2).await.is_err());
+
+        let mut data: &[u8] = b"IVCK\x09\x01\x00\x00\x00\x01\x00\x00\x00\x00";
+        assert!(read_frame(&mut data, 1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn oversized_frame() {
+        // 4 samples per A scan, 2^32 - 1 A scans of f64, without a payload
+        let mut data: &[u8] = b"IVCK\x05\x04\x00\x00\x00\xff\xff\xff\xff";
+        let error = read_frame(&mut data, 4).await.unwrap_err();
+        assert!(error.to_string().contains("exceeds"), "{}", error);
+
+        // Other samples per A scan than the input
+        let mut data: &[u8] = b"IVCK\x00\xff\xff\xff\xff\x01\x00\x00\x00";
+        let error = read_frame(&mut data, 4).await.unwrap_err();
+        assert!(
+            error.to_string().contains("samples per A scan"),
+            "{}",
+            error
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn echo_command() {
+        let (in_tx, in_rx) = queue_channel::channel(10);
+        let (out_tx, mut out_rx) = queue_channel::channel(10);
+
+        for chunk in test_chunks() {
+            in_tx.send(Arc::new(chunk));
+        }
+        in_tx.finish();
+
+        // `cat` echoes every frame unchanged
+        let config = CommandConfig {
+            command: "cat".into(),
+            args: Vec::new(),
+            timeout: Duration::from_secs(5),
+        };
+        run_command(&config, in_rx, 4, &out_tx).await.unwrap();
+        out_tx.finish();
+
+        let mut received = Vec::new();
+        while let Ok(chunk) = out_rx.recv().await {
+            received.push(chunk.as_ref().clone());
+        }
+
+        assert_eq!(received, test_chunks());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn slow_upstream() {
+        let (in_tx, in_rx) = queue_channel::channel(10);
+        let (out_tx, mut out_rx) = queue_channel::channel(10);
+
+        // The input takes longer than the timeout, while the command has
+        // answered everything
+        let upstream = tokio::spawn(async move {
+            for chunk in test_chunks() {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                in_tx.send(Arc::new(chunk));
+            }
+            in_tx.finish();
+        });
+
+        let config = CommandConfig {
+            command: "cat".into(),
+            args: Vec::new(),
+            timeout: Duration::from_millis(100),
+        };
+        run_command(&config, in_rx, 4, &out_tx).await.unwrap();
+        out_tx.finish();
+        upstream.await.unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(chunk) = out_rx.recv().await {
+            received.push(chunk.as_ref().clone());
+        }
+
+        assert_eq!(received, test_chunks());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn failing_command() {
+        let (in_tx, in_rx) = queue_channel::channel::<Arc<DataMatrix>>(10);
+        let (out_tx, _out_rx) = queue_channel::channel(10);
+        in_tx.finish();
+
+        let config = CommandConfig {
+            command: "sh".into(),
+            args: vec!["-c".into(), "echo broken script >&2; exit 3".into()],
+            timeout: Duration::from_secs(5),
+        };
+        let error = run_command(&config, in_rx, 4, &out_tx).await.unwrap_err();
+
+        let message = format!("{:#}", error);
+        assert!(message.contains("broken script"), "{}", message);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn hung_command() {
+        let (in_tx, in_rx) = queue_channel::channel::<Arc<DataMatrix>>(10);
+        let (out_tx, _out_rx) = queue_channel::channel(10);
+        in_tx.finish();
+
+        let config = CommandConfig {
+            command: "sleep".into(),
+            args: vec!["10".into()],
+            timeout: Duration::from_millis(100),
+        };
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            run_command(&config, in_rx, 4, &out_tx),
+        )
+        .await;
+
+        assert!(matches!(result, Ok(Err(_))));
+    }
+}
@@ -1,25 +1,57 @@
 use std::{
     borrow::Cow,
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
     iter::Sum,
-    ops::{AddAssign, Div, MulAssign},
-    sync::Arc,
+    ops::{AddAssign, Div, MulAssign, RangeInclusive},
+    sync::{Arc, OnceLock},
 };
 
 use futures::FutureExt;
 use nalgebra::{DMatrix, DMatrixView, Matrix3, Scalar, Vector2};
-use num_traits::{Float, Zero};
+use num_traits::{Float, FromPrimitive, ToPrimitive, Zero};
 use simba::scalar::SupersetOf;
 use tokio::sync::watch;
 
 use crate::{
     convolution::convolve_par,
-    pipeline::types::{self, DataMatrix},
+    pipeline::{
+        chunking::{ChunkLimits, ChunkedSender},
+        determinism,
+        result_cache::{CacheKey, CacheSettings, CacheStats, CachedResult, ResultCache},
+        suggestions::SuggestionState,
+        two_pass::{self, Histogram, Passes, Statistics, StatisticsPass},
+        types::{self, DataMatrix},
+    },
     queue_channel::error::RecvError,
+    settings::Settings,
 };
 
 use super::prelude::*;
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputId {
+    MScan,
+    /// Calibrates kernel sizes given in degrees.
+    BScans,
+}
+
+impl_enum_from_into_id_types!(InputId, [graph::InputId], {
+    0 => MScan,
+    1 => BScans,
+});
+
+pub enum OutputId {
+    Filtered,
+    /// The unfiltered input, re-streamed from the same upstream request.
+    Original,
+}
+
+impl_enum_from_into_id_types!(OutputId, [graph::OutputId], {
+    0 => Filtered,
+    1 => Original,
+});
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum FilterType {
     #[default]
     Gaussian,
@@ -29,21 +61,65 @@ pub enum FilterType {
     Prewitt,
     WidenStructures,
     BWAreaOpen,
+    /// Stretches the values between two percentiles of the whole input to the
+    /// range from 0 to 1.
+    GlobalNormalize,
+    /// Stretches the values between a black and a white point to the range
+    /// from 0 to 1 and applies a gamma curve.
+    Levels,
+    /// Mean of neighboring A scans.
+    MovingAverage,
+    /// Binary mask of the values above a threshold.
+    Threshold,
 }
 
 impl FilterType {
-    pub const VALUES: [FilterType; 7] = [
+    pub const VALUES: [FilterType; 11] = [
         FilterType::Gaussian,
         FilterType::Median,
+        FilterType::MovingAverage,
         FilterType::AlignBrightness,
+        FilterType::GlobalNormalize,
+        FilterType::Levels,
         FilterType::Wiener,
         FilterType::Prewitt,
+        FilterType::Threshold,
         FilterType::WidenStructures,
         FilterType::BWAreaOpen,
     ];
+
+    /// The data type of the result for an input of `data_type`. Some filters
+    /// work on floating point values only.
+    pub fn output_type(self, data_type: types::DataType) -> types::DataType {
+        match self {
+            // Masks of 0 and 255, ready for the binary filters
+            FilterType::Threshold => types::DataType::U8,
+            FilterType::Gaussian
+            | FilterType::MovingAverage
+            | FilterType::AlignBrightness
+            | FilterType::GlobalNormalize
+            | FilterType::Levels
+            | FilterType::Wiener
+            | FilterType::Prewitt
+                if data_type.is_integer() =>
+            {
+                types::DataType::F32
+            }
+            _ => data_type,
+        }
+    }
+
+    /// Whether the filter needs statistics of the whole input, which are
+    /// gathered in a first pass, see [two_pass].
+    pub fn is_global(self) -> bool {
+        matches!(
+            self,
+            FilterType::AlignBrightness | FilterType::GlobalNormalize
+        )
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum AreaConnectionType {
     Star4,
     Circle8,
@@ -54,15 +130,51 @@ impl AreaConnectionType {
         [AreaConnectionType::Star4, AreaConnectionType::Circle8];
 }
 
+/// Unit of one dimension of a kernel size.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum KernelUnit {
+    #[default]
+    Pixels,
+    /// Micrometres for rows, which run along the A scans, and degrees for
+    /// columns, which run around the catheter.
+    Physical,
+}
+
+/// Kernel size in physical units, for the dimensions not given in pixels.
+/// Speckle is elongated along the A scans, so the kernel can match its
+/// extent in depth and angle, independent of the sampling of the scan.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicalKernel {
+    /// Unit of the rows and of the columns.
+    pub units: [KernelUnit; 2],
+    /// Extent along the A scans in µm.
+    pub depth: f32,
+    /// Extent around the catheter in degrees.
+    pub angle: f32,
+    /// Distance of the samples along the A scans in mm.
+    pub mm_per_pixel: f32,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct GaussSettings {
     pub kernel_size: Vector2<usize>,
     pub sigma: f32,
+    #[serde(default)]
+    pub physical: PhysicalKernel,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct MedianSettings {
     pub size: Vector2<usize>,
+    #[serde(default)]
+    pub physical: PhysicalKernel,
+}
+
+/// Number of A scans averaged.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MovingAverageSettings {
+    pub width: usize,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -80,12 +192,107 @@ pub struct WidenStructuresSettings {
     pub width: usize,
 }
 
+/// Percentiles of the input, from 0 to 100, that are mapped to 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GlobalNormalizeSettings {
+    pub low: f32,
+    pub high: f32,
+}
+
+/// Values mapped to 0 and 1, from 0 to 1 of the range of the data type, and
+/// the exponent applied to the result.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LevelsSettings {
+    pub black_point: f32,
+    pub white_point: f32,
+    pub gamma: f32,
+}
+
+/// Number of bins of the histogram of the input of a levels filter, over the
+/// range from 0 to 1.
+pub const LEVELS_BINS: usize = 64;
+
+/// How the threshold of a [FilterType::Threshold] is determined.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ThresholdMode {
+    #[default]
+    Fixed,
+    /// Otsu's method on the histogram of the first chunk. The threshold is
+    /// reused for the following chunks, so they are split alike.
+    Otsu,
+}
+
+impl ThresholdMode {
+    pub const VALUES: [ThresholdMode; 2] = [ThresholdMode::Fixed, ThresholdMode::Otsu];
+}
+
+/// Values above the threshold, from 0 to 1 of the range of the data type,
+/// become 255 and the others 0, or the other way around, if inverted.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThresholdSettings {
+    pub mode: ThresholdMode,
+    /// Used in [ThresholdMode::Fixed].
+    pub threshold: f32,
+    pub invert: bool,
+}
+
+/// Number of bins of the histogram Otsu's method is computed on, over the
+/// range from 0 to 1.
+const OTSU_BINS: usize = 256;
+
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct BWareOpenSettings {
     pub area: usize,
     pub connection_type: AreaConnectionType,
 }
 
+/// What skipped A scans are replaced with, see [GatingSettings].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GatedFill {
+    /// The unfiltered A scan.
+    #[default]
+    PassThrough,
+    Zero,
+}
+
+impl GatedFill {
+    pub const VALUES: [GatedFill; 2] = [GatedFill::PassThrough, GatedFill::Zero];
+}
+
+/// Skips A scans, that are too dark to contain anything, like during the
+/// flush or beyond the imaging range. Only the remaining A scans are filtered,
+/// moved next to each other, and put back into place afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GatingSettings {
+    pub skip_dark_columns: bool,
+    /// A scans with a mean intensity below are skipped, from 0 to 1 of the
+    /// range of the data type.
+    pub dark_threshold: f32,
+    pub fill: GatedFill,
+}
+
+impl Default for GatingSettings {
+    fn default() -> Self {
+        Self {
+            skip_dark_columns: false,
+            dark_threshold: 0.05,
+            fill: GatedFill::PassThrough,
+        }
+    }
+}
+
+/// A scans skipped and filtered during the current or last run, see
+/// [GatingSettings].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GatingStats {
+    pub skipped: usize,
+    pub processed: usize,
+}
+
 // MARK: Node
 
 /// This node implements all image filters.
@@ -98,6 +305,8 @@ pub struct Node {
     #[serde(default)]
     pub median_settings: MedianSettings,
     #[serde(default)]
+    pub moving_average_settings: MovingAverageSettings,
+    #[serde(default)]
     pub wiener_settings: WienerSettings,
     #[serde(default)]
     pub prewitt_settings: PrewittSettings,
@@ -105,11 +314,48 @@ pub struct Node {
     pub widen_structures_settings: WidenStructuresSettings,
     #[serde(default)]
     pub b_w_area_open_settings: BWareOpenSettings,
+    #[serde(default)]
+    pub global_normalize_settings: GlobalNormalizeSettings,
+    #[serde(default)]
+    pub levels_settings: LevelsSettings,
+    #[serde(default)]
+    pub threshold_settings: ThresholdSettings,
+
+    #[serde(default)]
+    pub gating: GatingSettings,
+
+    /// Keep recent results, to switch back to previous settings instantly.
+    #[serde(default)]
+    pub cache_settings: CacheSettings,
 
     #[serde(skip)]
     pub progress_rx: Option<watch::Receiver<Option<f32>>>,
+    #[serde(skip)]
+    pub cache_rx: Option<watch::Receiver<CacheStats>>,
+    /// How the task determined the kernel size of its last run.
+    #[serde(skip)]
+    pub calibration_rx: Option<watch::Receiver<KernelCalibration>>,
+    #[serde(skip)]
+    pub gating_rx: Option<watch::Receiver<GatingStats>>,
+    /// Whether the last run of a global filter could make two passes over
+    /// its input.
+    #[serde(skip)]
+    pub passes_rx: Option<watch::Receiver<Passes>>,
+    /// Counts of the input values of the last chunk of a levels filter, in
+    /// [LEVELS_BINS] bins.
+    #[serde(skip)]
+    pub levels_histogram_rx: Option<watch::Receiver<Vec<u64>>>,
+    /// Threshold Otsu's method found for the last run of a threshold filter.
+    #[serde(skip)]
+    pub otsu_threshold_rx: Option<watch::Receiver<Option<f32>>>,
+    /// Settings suggested from a sample of the input, kept up to date by the
+    /// [SuggestionRunner](crate::pipeline::suggestions::SuggestionRunner).
+    #[serde(skip)]
+    pub suggestions: SuggestionState,
 
     pub input: NodeInput<()>,
+    #[serde(default)]
+    pub b_scans: NodeInput<()>,
 }
 
 impl Node {
@@ -121,6 +367,10 @@ impl Node {
         Self::new(FilterType::Median)
     }
 
+    pub fn moving_average() -> Self {
+        Self::new(FilterType::MovingAverage)
+    }
+
     pub fn align_brightness() -> Self {
         Self::new(FilterType::AlignBrightness)
     }
@@ -141,6 +391,18 @@ impl Node {
         Self::new(FilterType::BWAreaOpen)
     }
 
+    pub fn global_normalize() -> Self {
+        Self::new(FilterType::GlobalNormalize)
+    }
+
+    pub fn levels() -> Self {
+        Self::new(FilterType::Levels)
+    }
+
+    pub fn threshold() -> Self {
+        Self::new(FilterType::Threshold)
+    }
+
     pub fn new(filter_type: FilterType) -> Self {
         Self {
             filter_type,
@@ -149,11 +411,286 @@ impl Node {
     }
 }
 
+/// A numeric setting of a filter, that can be varied in a parameter sweep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SweepParameter {
+    GaussSigma,
+    GaussKernelRows,
+    GaussKernelColumns,
+    MedianRows,
+    MedianColumns,
+    MovingAverageWidth,
+    WienerRows,
+    WienerColumns,
+    PrewittThreshold,
+    WidenStructuresWidth,
+    BWAreaOpenArea,
+}
+
+impl SweepParameter {
+    /// Whether the setting only accepts whole numbers.
+    pub fn is_integer(self) -> bool {
+        !matches!(
+            self,
+            SweepParameter::GaussSigma | SweepParameter::PrewittThreshold
+        )
+    }
+
+    /// The values the setting accepts.
+    pub fn range(self) -> RangeInclusive<f64> {
+        match self {
+            SweepParameter::GaussSigma => 0.1..=50.0,
+            SweepParameter::GaussKernelRows
+            | SweepParameter::GaussKernelColumns
+            | SweepParameter::MedianRows
+            | SweepParameter::MedianColumns
+            | SweepParameter::MovingAverageWidth
+            | SweepParameter::WienerRows
+            | SweepParameter::WienerColumns => 1.0..=100.0,
+            SweepParameter::PrewittThreshold => 0.0..=1.0,
+            SweepParameter::WidenStructuresWidth => 0.0..=300.0,
+            SweepParameter::BWAreaOpenArea => 0.0..=1000.0,
+        }
+    }
+}
+
+impl Node {
+    /// The settings of the current filter type, that can be varied in a
+    /// parameter sweep.
+    pub fn sweep_parameters(&self) -> &'static [SweepParameter] {
+        match self.filter_type {
+            FilterType::Gaussian => &[
+                SweepParameter::GaussSigma,
+                SweepParameter::GaussKernelRows,
+                SweepParameter::GaussKernelColumns,
+            ],
+            FilterType::Median => &[SweepParameter::MedianRows, SweepParameter::MedianColumns],
+            FilterType::MovingAverage => &[SweepParameter::MovingAverageWidth],
+            FilterType::AlignBrightness => &[],
+            FilterType::Wiener => &[SweepParameter::WienerRows, SweepParameter::WienerColumns],
+            FilterType::Prewitt => &[SweepParameter::PrewittThreshold],
+            FilterType::WidenStructures => &[SweepParameter::WidenStructuresWidth],
+            FilterType::BWAreaOpen => &[SweepParameter::BWAreaOpenArea],
+            FilterType::GlobalNormalize | FilterType::Levels | FilterType::Threshold => &[],
+        }
+    }
+
+    pub fn parameter(&self, parameter: SweepParameter) -> f64 {
+        match parameter {
+            SweepParameter::GaussSigma => self.gauss_settings.sigma as f64,
+            SweepParameter::GaussKernelRows => self.gauss_settings.kernel_size.x as f64,
+            SweepParameter::GaussKernelColumns => self.gauss_settings.kernel_size.y as f64,
+            SweepParameter::MedianRows => self.median_settings.size.x as f64,
+            SweepParameter::MedianColumns => self.median_settings.size.y as f64,
+            SweepParameter::MovingAverageWidth => self.moving_average_settings.width as f64,
+            SweepParameter::WienerRows => self.wiener_settings.neighborhood_size.x as f64,
+            SweepParameter::WienerColumns => self.wiener_settings.neighborhood_size.y as f64,
+            SweepParameter::PrewittThreshold => self.prewitt_settings.threshold as f64,
+            SweepParameter::WidenStructuresWidth => self.widen_structures_settings.width as f64,
+            SweepParameter::BWAreaOpenArea => self.b_w_area_open_settings.area as f64,
+        }
+    }
+
+    /// Sets a setting, clamping the value to [SweepParameter::range].
+    pub fn set_parameter(&mut self, parameter: SweepParameter, value: f64) {
+        let range = parameter.range();
+        let value = value.clamp(*range.start(), *range.end());
+        let int = value.round() as usize;
+
+        match parameter {
+            SweepParameter::GaussSigma => self.gauss_settings.sigma = value as f32,
+            SweepParameter::GaussKernelRows => self.gauss_settings.kernel_size.x = int,
+            SweepParameter::GaussKernelColumns => self.gauss_settings.kernel_size.y = int,
+            SweepParameter::MedianRows => self.median_settings.size.x = int,
+            SweepParameter::MedianColumns => self.median_settings.size.y = int,
+            SweepParameter::MovingAverageWidth => self.moving_average_settings.width = int,
+            SweepParameter::WienerRows => self.wiener_settings.neighborhood_size.x = int,
+            SweepParameter::WienerColumns => self.wiener_settings.neighborhood_size.y = int,
+            SweepParameter::PrewittThreshold => self.prewitt_settings.threshold = value as f32,
+            SweepParameter::WidenStructuresWidth => self.widen_structures_settings.width = int,
+            SweepParameter::BWAreaOpenArea => self.b_w_area_open_settings.area = int,
+        }
+    }
+}
+
+impl ThresholdTarget for Node {
+    fn thresholds(&self) -> Vec<Threshold> {
+        match self.filter_type {
+            FilterType::Prewitt => vec![Threshold {
+                name: "Threshold",
+                value: self.prewitt_settings.threshold,
+                scale: ThresholdScale::Value,
+            }],
+            FilterType::GlobalNormalize => vec![
+                Threshold {
+                    name: "Low",
+                    value: self.global_normalize_settings.low,
+                    scale: ThresholdScale::Percentile,
+                },
+                Threshold {
+                    name: "High",
+                    value: self.global_normalize_settings.high,
+                    scale: ThresholdScale::Percentile,
+                },
+            ],
+            FilterType::Levels => vec![
+                Threshold {
+                    name: "Black Point",
+                    value: self.levels_settings.black_point,
+                    scale: ThresholdScale::Value,
+                },
+                Threshold {
+                    name: "White Point",
+                    value: self.levels_settings.white_point,
+                    scale: ThresholdScale::Value,
+                },
+            ],
+            FilterType::Threshold if self.threshold_settings.mode == ThresholdMode::Fixed => {
+                vec![Threshold {
+                    name: "Threshold",
+                    value: self.threshold_settings.threshold,
+                    scale: ThresholdScale::Value,
+                }]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn set_threshold(&mut self, index: usize, value: f32) {
+        match (self.filter_type, index) {
+            (FilterType::Prewitt, 0) => {
+                self.set_parameter(SweepParameter::PrewittThreshold, value as f64);
+            }
+            // Validation orders low and high
+            (FilterType::GlobalNormalize, 0) => {
+                self.global_normalize_settings.low = value.clamp(0.0, 100.0);
+            }
+            (FilterType::GlobalNormalize, 1) => {
+                self.global_normalize_settings.high = value.clamp(0.0, 100.0);
+            }
+            (FilterType::Levels, 0) => self.levels_settings.black_point = value.clamp(0.0, 1.0),
+            (FilterType::Levels, 1) => self.levels_settings.white_point = value.clamp(0.0, 1.0),
+            (FilterType::Threshold, 0) => {
+                self.threshold_settings.threshold = value.clamp(0.0, 1.0);
+            }
+            _ => {}
+        }
+    }
+
+    fn window(&self) -> Option<[usize; 2]> {
+        matches!(
+            self.filter_type,
+            FilterType::GlobalNormalize | FilterType::Levels
+        )
+        .then_some([0, 1])
+    }
+}
+
+/// How the size of a kernel was determined for a run.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum KernelCalibration {
+    /// The size is given in pixels, or nothing ran yet.
+    #[default]
+    Pixels,
+    /// The physical units got converted into this size in pixels.
+    Converted(Vector2<usize>),
+    /// Physical units are used, but the calibration is missing, so the size in
+    /// pixels was used instead. Contains the reason.
+    Missing(&'static str),
+}
+
+impl PhysicalKernel {
+    /// Whether any dimension is given in physical units.
+    pub fn is_used(&self) -> bool {
+        self.units.contains(&KernelUnit::Physical)
+    }
+
+    /// Whether the columns are given in degrees, which needs the width of the
+    /// B scans.
+    pub fn needs_b_scans(&self) -> bool {
+        self.units[1] == KernelUnit::Physical
+    }
+
+    /// Converts the dimensions in physical units into pixels and takes the
+    /// others from `pixels`. `b_scan_width` is the mean number of A scans per
+    /// B scan, which covers 360°.
+    pub fn pixel_size(
+        &self,
+        pixels: Vector2<usize>,
+        b_scan_width: Option<f32>,
+    ) -> Result<Vector2<usize>, &'static str> {
+        let to_pixels = |size: f32| (size.round() as usize).max(1);
+
+        let rows = match self.units[0] {
+            KernelUnit::Pixels => pixels.x,
+            KernelUnit::Physical if self.mm_per_pixel > 0.0 => {
+                to_pixels(self.depth / (self.mm_per_pixel * 1000.0))
+            }
+            KernelUnit::Physical => return Err("The depth pitch is not set"),
+        };
+
+        let columns = match (self.units[1], b_scan_width) {
+            (KernelUnit::Pixels, _) => pixels.y,
+            (KernelUnit::Physical, Some(width)) if width > 0.0 => {
+                to_pixels(self.angle * width / 360.0)
+            }
+            (KernelUnit::Physical, _) => return Err("No B scans connected"),
+        };
+
+        Ok(Vector2::new(rows, columns))
+    }
+}
+
+/// Checks the kernel size of a Gaussian or median filter, in the unit used
+/// for each dimension.
+fn validate_kernel(
+    issues: &mut Vec<ValidationIssue>,
+    pixels: &mut Vector2<usize>,
+    physical: &mut PhysicalKernel,
+) {
+    match physical.units[0] {
+        KernelUnit::Pixels => validation::at_least(issues, "Kernel Rows", &mut pixels.x, 1),
+        KernelUnit::Physical => {
+            validation::clamp(issues, "Kernel Depth", &mut physical.depth, 1.0..=5000.0);
+            validation::at_least(issues, "mm per Pixel", &mut physical.mm_per_pixel, 0.0);
+        }
+    }
+    match physical.units[1] {
+        KernelUnit::Pixels => validation::at_least(issues, "Kernel Columns", &mut pixels.y, 1),
+        KernelUnit::Physical => {
+            validation::clamp(issues, "Kernel Angle", &mut physical.angle, 0.01..=90.0);
+        }
+    }
+}
+
+/// Mean number of A scans per B scan of a B scan segmentation, see
+/// [requests::BScanSegmentationResponse]. [None], if it has no B scans.
+pub fn mean_b_scan_width(boundaries: &[usize]) -> Option<f32> {
+    match boundaries {
+        [first, .., last] if last > first => {
+            Some((last - first) as f32 / (boundaries.len() - 1) as f32)
+        }
+        _ => None,
+    }
+}
+
+impl Default for PhysicalKernel {
+    fn default() -> Self {
+        Self {
+            units: [KernelUnit::Pixels; 2],
+            depth: 20.0,
+            angle: 1.0,
+            mm_per_pixel: 0.0055,
+        }
+    }
+}
+
 impl Default for GaussSettings {
     fn default() -> Self {
         Self {
             kernel_size: Vector2::new(3, 3),
             sigma: 1.0,
+            physical: PhysicalKernel::default(),
         }
     }
 }
@@ -162,10 +699,17 @@ impl Default for MedianSettings {
     fn default() -> Self {
         Self {
             size: Vector2::new(3, 3),
+            physical: PhysicalKernel::default(),
         }
     }
 }
 
+impl Default for MovingAverageSettings {
+    fn default() -> Self {
+        Self { width: 5 }
+    }
+}
+
 impl Default for WienerSettings {
     fn default() -> Self {
         Self {
@@ -186,6 +730,35 @@ impl Default for WidenStructuresSettings {
     }
 }
 
+impl Default for GlobalNormalizeSettings {
+    fn default() -> Self {
+        Self {
+            low: 1.0,
+            high: 99.0,
+        }
+    }
+}
+
+impl Default for LevelsSettings {
+    fn default() -> Self {
+        Self {
+            black_point: 0.0,
+            white_point: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl Default for ThresholdSettings {
+    fn default() -> Self {
+        Self {
+            mode: ThresholdMode::Fixed,
+            threshold: 0.5,
+            invert: false,
+        }
+    }
+}
+
 impl Default for BWareOpenSettings {
     fn default() -> Self {
         Self {
@@ -198,8 +771,8 @@ impl Default for BWareOpenSettings {
 deserialize_node!(Node, "filter");
 
 impl PipelineNode for Node {
-    type InputId = InputIdSingle;
-    type OutputId = OutputIdSingle;
+    type InputId = InputId;
+    type OutputId = OutputId;
 
     fn slug() -> &'static str {
         "filter"
@@ -208,14 +781,23 @@ impl PipelineNode for Node {
     fn inputs(
         &self,
     ) -> impl Iterator<Item = (<Self as PipelineNode>::InputId, Option<NodeOutput>)> {
-        std::iter::once((InputIdSingle, self.input.connection()))
+        [
+            (InputId::MScan, self.input.connection()),
+            (InputId::BScans, self.b_scans.connection()),
+        ]
+        .into_iter()
     }
 
     fn changed(&self, other: &Self) -> bool {
         self.filter_type != other.filter_type
+            || self.cache_settings != other.cache_settings
+            || self.gating != other.gating
             || match self.filter_type {
                 FilterType::Gaussian => self.gauss_settings != other.gauss_settings,
                 FilterType::Median => self.median_settings != other.median_settings,
+                FilterType::MovingAverage => {
+                    self.moving_average_settings != other.moving_average_settings
+                }
                 FilterType::AlignBrightness => false,
                 FilterType::Wiener => self.wiener_settings != other.wiener_settings,
                 FilterType::Prewitt => self.prewitt_settings != other.prewitt_settings,
@@ -225,31 +807,210 @@ impl PipelineNode for Node {
                 FilterType::BWAreaOpen => {
                     self.b_w_area_open_settings != other.b_w_area_open_settings
                 }
+                FilterType::GlobalNormalize => {
+                    self.global_normalize_settings != other.global_normalize_settings
+                }
+                FilterType::Levels => self.levels_settings != other.levels_settings,
+                FilterType::Threshold => self.threshold_settings != other.threshold_settings,
+            }
+    }
+
+    fn get_output_id_for_view_request(&self) -> Option<(OutputId, impl Into<TypeId>)> {
+        Some((OutputId::Filtered, PipelineDataType::MScan))
+    }
+
+    fn type_handling(&self, input_id: InputId, data_type: types::DataType) -> TypeHandling {
+        // Integers are rescaled to 0..1 for filters working on floating point
+        // values, and for thresholds relative to the range of the data type
+        if self.filter_type == FilterType::Threshold {
+            return match matches!(input_id, InputId::MScan) && data_type.is_integer() {
+                true => TypeHandling::Rescales(types::DataType::F32),
+                false => TypeHandling::AsIs,
+            };
+        }
+
+        match self.filter_type.output_type(data_type) {
+            output_type if matches!(input_id, InputId::MScan) && output_type != data_type => {
+                TypeHandling::Rescales(output_type)
             }
+            _ => TypeHandling::AsIs,
+        }
+    }
+
+    fn estimate_memory(&self, upstream: &UpstreamStats) -> MemoryEstimate {
+        let output = UpstreamStats {
+            data_type: self.filter_type.output_type(upstream.data_type),
+            ..*upstream
+        };
+
+        let mut estimate = MemoryEstimate::streaming(upstream, output);
+        // Integer chunks are promoted before filtering
+        if output.data_type != upstream.data_type {
+            estimate.per_chunk += output.chunk_bytes();
+        }
+        // The result is collected for the cache while streaming
+        if self.cache_settings.enabled {
+            estimate = estimate.with_buffers(output.stream_bytes());
+        }
+        estimate
+    }
+
+    fn threshold_target(&self) -> Option<&dyn ThresholdTarget> {
+        Some(self)
     }
 
-    fn get_output_id_for_view_request(&self) -> Option<(OutputIdSingle, impl Into<TypeId>)> {
-        Some((OutputIdSingle, PipelineDataType::MScan))
+    fn threshold_target_mut(&mut self) -> Option<&mut dyn ThresholdTarget> {
+        Some(self)
+    }
+
+    fn validate(&mut self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+
+        // Settings of other filter types are checked, once they are selected
+        match self.filter_type {
+            FilterType::Gaussian => {
+                let settings = &mut self.gauss_settings;
+                validation::clamp(&mut issues, "Sigma", &mut settings.sigma, 0.1..=50.0);
+                validate_kernel(
+                    &mut issues,
+                    &mut settings.kernel_size,
+                    &mut settings.physical,
+                );
+            }
+            FilterType::Median => {
+                let settings = &mut self.median_settings;
+                validate_kernel(&mut issues, &mut settings.size, &mut settings.physical);
+            }
+            FilterType::MovingAverage => {
+                let width = &mut self.moving_average_settings.width;
+                validation::at_least(&mut issues, "Width", width, 1);
+            }
+            FilterType::AlignBrightness => {}
+            FilterType::GlobalNormalize => {
+                let settings = &mut self.global_normalize_settings;
+                validation::clamp(
+                    &mut issues,
+                    "Low Percentile",
+                    &mut settings.low,
+                    0.0..=100.0,
+                );
+                validation::clamp(
+                    &mut issues,
+                    "High Percentile",
+                    &mut settings.high,
+                    0.0..=100.0,
+                );
+                if settings.high < settings.low {
+                    issues.push(ValidationIssue::corrected(
+                        "High Percentile",
+                        format!(
+                            "{} is below the low one, set to {}",
+                            settings.high, settings.low
+                        ),
+                    ));
+                    settings.high = settings.low;
+                }
+            }
+            FilterType::Levels => {
+                let settings = &mut self.levels_settings;
+                validation::clamp(
+                    &mut issues,
+                    "Black Point",
+                    &mut settings.black_point,
+                    0.0..=1.0,
+                );
+                validation::clamp(
+                    &mut issues,
+                    "White Point",
+                    &mut settings.white_point,
+                    0.0..=1.0,
+                );
+                validation::clamp(&mut issues, "Gamma", &mut settings.gamma, 0.1..=10.0);
+                if settings.white_point < settings.black_point {
+                    issues.push(ValidationIssue::corrected(
+                        "White Point",
+                        format!(
+                            "{} is below the black point, set to {}",
+                            settings.white_point, settings.black_point
+                        ),
+                    ));
+                    settings.white_point = settings.black_point;
+                }
+            }
+            FilterType::Wiener => {
+                let size = &mut self.wiener_settings.neighborhood_size;
+                validation::at_least(&mut issues, "Neighborhood Rows", &mut size.x, 1);
+                validation::at_least(&mut issues, "Neighborhood Columns", &mut size.y, 1);
+            }
+            FilterType::Prewitt => {
+                let threshold = &mut self.prewitt_settings.threshold;
+                validation::clamp(&mut issues, "Threshold", threshold, 0.0..=1.0);
+            }
+            FilterType::Threshold => {
+                let threshold = &mut self.threshold_settings.threshold;
+                validation::clamp(&mut issues, "Threshold", threshold, 0.0..=1.0);
+            }
+            FilterType::WidenStructures | FilterType::BWAreaOpen => {}
+        }
+
+        if self.gating.skip_dark_columns {
+            let threshold = &mut self.gating.dark_threshold;
+            validation::clamp(&mut issues, "Dark Threshold", threshold, 0.0..=1.0);
+        }
+
+        if self.cache_settings.enabled {
+            let settings = &mut self.cache_settings;
+            validation::at_least(&mut issues, "Cached Results", &mut settings.max_entries, 1);
+            validation::at_least(&mut issues, "Cache Memory", &mut settings.max_megabytes, 1);
+        }
+
+        issues
     }
 
     fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
-        let m_scan_out = builder.output(OutputIdSingle);
+        let m_scan_out = builder.output(OutputId::Filtered);
+        let original_out = builder.output(OutputId::Original);
 
         let (progress_tx, progress_rx) = watch::channel(None);
+        let (cache_tx, cache_rx) = watch::channel(CacheStats::default());
+        let (calibration_tx, calibration_rx) = watch::channel(KernelCalibration::default());
+        let (gating_tx, gating_rx) = watch::channel(GatingStats::default());
+        let (passes_tx, passes_rx) = watch::channel(Passes::default());
+        let (levels_histogram_tx, levels_histogram_rx) = watch::channel(Vec::new());
+        let (otsu_threshold_tx, otsu_threshold_rx) = watch::channel(None);
 
         self.progress_rx = Some(progress_rx);
+        self.cache_rx = Some(cache_rx);
+        self.calibration_rx = Some(calibration_rx);
+        self.gating_rx = Some(gating_rx);
+        self.passes_rx = Some(passes_rx);
+        self.levels_histogram_rx = Some(levels_histogram_rx);
+        self.otsu_threshold_rx = Some(otsu_threshold_rx);
 
         builder.task(Task {
             filter_type: self.filter_type,
             gauss_settings: self.gauss_settings,
             median_settings: self.median_settings,
+            moving_average_settings: self.moving_average_settings,
             wiener_settings: self.wiener_settings,
             prewitt_settings: self.prewitt_settings,
             widen_structures_settings: self.widen_structures_settings,
             b_ware_open_settings: self.b_w_area_open_settings,
+            global_normalize_settings: self.global_normalize_settings,
+            levels_settings: self.levels_settings,
+            threshold_settings: self.threshold_settings,
+            gating: self.gating,
             progress_tx: progress_tx,
+            calibration_tx,
+            gating_tx,
+            passes_tx,
+            levels_histogram_tx,
+            otsu_threshold_tx,
+            cache: ResultCache::new(self.cache_settings, cache_tx),
             m_scan_out,
+            original_out,
             m_scan_in: TaskInput::default(),
+            b_scans_in: TaskInput::default(),
         });
     }
 }
@@ -261,62 +1022,167 @@ struct Task {
 
     gauss_settings: GaussSettings,
     median_settings: MedianSettings,
+    moving_average_settings: MovingAverageSettings,
     wiener_settings: WienerSettings,
     prewitt_settings: PrewittSettings,
     widen_structures_settings: WidenStructuresSettings,
     b_ware_open_settings: BWareOpenSettings,
+    global_normalize_settings: GlobalNormalizeSettings,
+    levels_settings: LevelsSettings,
+    threshold_settings: ThresholdSettings,
+    gating: GatingSettings,
 
     progress_tx: watch::Sender<Option<f32>>,
+    calibration_tx: watch::Sender<KernelCalibration>,
+    gating_tx: watch::Sender<GatingStats>,
+    passes_tx: watch::Sender<Passes>,
+    levels_histogram_tx: watch::Sender<Vec<u64>>,
+    otsu_threshold_tx: watch::Sender<Option<f32>>,
+    cache: ResultCache,
 
     m_scan_out: TaskOutput<requests::MScan>,
+    original_out: TaskOutput<requests::MScan>,
     m_scan_in: TaskInput<requests::MScan>,
+    b_scans_in: TaskInput<requests::BScanSegmentation>,
 }
 
 impl NodeTask for Task {
-    type InputId = InputIdSingle;
+    type InputId = InputId;
     type PipelineNode = Node;
 
-    fn connect(&mut self, _input_id: Self::InputId, input: &mut ConnectionHandle) {
-        self.m_scan_in.connect(input);
+    fn connect(&mut self, input_id: Self::InputId, input: &mut ConnectionHandle) {
+        match input_id {
+            InputId::MScan => self.m_scan_in.connect(input),
+            InputId::BScans => self.b_scans_in.connect(input),
+        };
     }
 
-    fn disconnect(&mut self, _input_id: Self::InputId) {
-        self.m_scan_in.disconnect();
+    fn disconnect(&mut self, input_id: Self::InputId) {
+        match input_id {
+            InputId::MScan => self.m_scan_in.disconnect(),
+            InputId::BScans => self.b_scans_in.disconnect(),
+        }
     }
 
     fn sync_node(&mut self, node: &Self::PipelineNode) {
         self.filter_type = node.filter_type;
         self.gauss_settings = node.gauss_settings;
         self.median_settings = node.median_settings;
+        self.moving_average_settings = node.moving_average_settings;
         self.wiener_settings = node.wiener_settings;
         self.prewitt_settings = node.prewitt_settings;
         self.widen_structures_settings = node.widen_structures_settings;
         self.b_ware_open_settings = node.b_w_area_open_settings;
+        self.global_normalize_settings = node.global_normalize_settings;
+        self.levels_settings = node.levels_settings;
+        self.threshold_settings = node.threshold_settings;
+        self.gating = node.gating;
+        self.cache.set_settings(node.cache_settings);
     }
 
-    fn invalidate(&mut self, _cause: InvalidationCause) {
+    fn invalidate(&mut self, cause: InvalidationCause) {
         let _ = self.progress_tx.send(None);
+
+        // Results of other settings stay valid, as long as the input does
+        if !matches!(cause, InvalidationCause::Synced) {
+            self.cache.clear();
+        }
     }
 
     async fn run(&mut self) -> anyhow::Result<()> {
-        let _req = self.m_scan_out.receive().await;
+        tokio::select! {
+            _req = self.m_scan_out.receive() => {}
+            _req = self.original_out.receive() => {}
+        }
+
+        // Both outputs may have been requested at the same time
+        let filtered_requested = self.m_scan_out.receive().now_or_never().is_some();
+        let original_requested = self.original_out.receive().now_or_never().is_some();
+
+        // The epoch is taken before requesting, so a result is never stored
+        // under an epoch newer than its input
+        let cache_key = self.cache_key();
+        let input_epoch = self.m_scan_in.epoch();
+
+        let from_cache = match cache_key {
+            Some(key) if filtered_requested => match self.cache.get(key) {
+                Some(cached) => {
+                    self.respond_cached(cached);
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        };
+
+        if from_cache && !original_requested {
+            return Ok(());
+        }
+
+        // Determined before requesting the input, which may be the M scan the
+        // B scans got segmented from
+        let kernel_size = match filtered_requested && !from_cache {
+            true => self.kernel_size().await?,
+            false => Vector2::new(1, 1),
+        };
 
         let Some(m_scan_res) = self.m_scan_in.request(requests::MScan).await else {
             return Ok(());
         };
 
+        if !filtered_requested || from_cache {
+            // Nothing to filter, pass the input through
+            self.original_out.respond(m_scan_res);
+            self.original_out.receive().now_or_never();
+            return Ok(());
+        }
+
+        let _ = self.progress_tx.send(Some(0.0));
+
+        // Global filters gather their statistics in a first pass and filter
+        // in the second one. Progress is split evenly between both passes
+        let (m_scan_res, reference, progress_start) = match self.global_statistics() {
+            Some(statistics) => {
+                let progress_tx = self.progress_tx.clone();
+                let a_scan_count = m_scan_res.a_scan_count as f32;
+                let pass = two_pass::statistics_pass(
+                    &mut self.m_scan_in,
+                    &m_scan_res,
+                    input_epoch,
+                    statistics,
+                    |a_scans| {
+                        let _ = progress_tx.send(Some(0.5 * a_scans as f32 / a_scan_count));
+                    },
+                )
+                .await?;
+
+                match pass {
+                    StatisticsPass::Done(statistics, second) => {
+                        self.passes_tx.send_replace(Passes::Two);
+                        let reference = statistics.reference(&self.global_normalize_settings);
+                        (second, reference, 0.5)
+                    }
+                    StatisticsPass::Unavailable(reason) => {
+                        self.passes_tx.send_replace(Passes::Single(reason));
+                        (m_scan_res, None, 0.0)
+                    }
+                    StatisticsPass::Aborted => {
+                        let _ = self.progress_tx.send(None);
+                        return Ok(());
+                    }
+                }
+            }
+            None => (m_scan_res, None, 0.0),
+        };
+
         if let Some(mut m_scan) = m_scan_res.data.subscribe() {
-            let _ = self.progress_tx.send(Some(0.0));
+            self.otsu_threshold_tx.send_replace(None);
 
-            let gauss_settings = self.gauss_settings;
-            let median_settings = self.median_settings;
-            let wiener_settings = self.wiener_settings;
-            let prewitt_settings = self.prewitt_settings;
-            let widen_structures_settings = self.widen_structures_settings;
-            let b_ware_open_settings = self.b_ware_open_settings;
-            let filter_type = self.filter_type;
+            let mut gating_stats = GatingStats::default();
+            self.gating_tx.send_replace(gating_stats);
 
-            let (res, tx) = requests::StreamedResponse::new(100);
+            let (res, tx) = requests::StreamedResponse::with_default_capacity();
+            let mut tx = ChunkedSender::new(tx, ChunkLimits::current());
 
             self.m_scan_out.respond(requests::MScanResponse {
                 data: res,
@@ -325,170 +1191,889 @@ impl NodeTask for Task {
             });
             self.m_scan_out.receive().now_or_never();
 
-            let kernel = gauss_kernel(gauss_settings.sigma, gauss_settings.kernel_size);
+            // Tee the input, if anything might request the original, so it
+            // does not get requested from upstream a second time
+            let original_tx = if original_requested || self.original_out.has_consumers() {
+                let (res, tx) = requests::StreamedResponse::with_default_capacity();
+
+                self.original_out.publish(requests::MScanResponse {
+                    data: res,
+                    a_scan_count: m_scan_res.a_scan_count,
+                    a_scan_samples: m_scan_res.a_scan_samples,
+                });
+                self.original_out.receive().now_or_never();
+
+                Some(tx)
+            } else {
+                None
+            };
+
+            let filter = ChunkFilter {
+                filter_type: self.filter_type,
+                kernel: gauss_kernel(self.gauss_settings.sigma, kernel_size),
+                kernel_size,
+                moving_average_settings: self.moving_average_settings,
+                wiener_settings: self.wiener_settings,
+                prewitt_settings: self.prewitt_settings,
+                widen_structures_settings: self.widen_structures_settings,
+                b_ware_open_settings: self.b_ware_open_settings,
+                global_normalize_settings: self.global_normalize_settings,
+                levels_settings: self.levels_settings,
+                threshold_settings: self.threshold_settings,
+                otsu_threshold: Arc::default(),
+                reference,
+                deterministic: determinism::is_deterministic(),
+            };
 
             let mut processed_a_scans = 0;
+            let mut cached_chunks = cache_key.map(|_| Vec::new());
+            let mut overlap = ChunkOverlap::new(filter.column_radius());
+            let otsu_threshold = filter.otsu_threshold.clone();
 
             loop {
                 let m_scan = match m_scan.recv().await {
-                    Ok(m_scan) => m_scan,
-                    Err(RecvError::Closed) => break,
+                    Ok(m_scan) => Some(m_scan),
+                    Err(RecvError::Closed) => None,
                     Err(e) => Err(e)?,
                 };
+                let closed = m_scan.is_none();
 
-                let kernel = kernel.clone();
+                if let (Some(original_tx), Some(m_scan)) = (&original_tx, &m_scan) {
+                    original_tx.send(m_scan.clone());
+                }
 
-                let m_scan: DataMatrix = tokio::task::spawn_blocking(move || match filter_type {
-                    FilterType::Gaussian => {
-                        let m_scan = if m_scan.data_type().is_integer() {
-                            m_scan.cast_rescale_par(types::DataType::F32)
-                        } else {
-                            m_scan.as_ref().clone()
-                        };
+                let filter = filter.clone();
+                let gating = self.gating;
 
-                        match m_scan {
-                            DataMatrix::F32(matrix) => convolve_par(&matrix, &kernel).into(),
-                            DataMatrix::F64(matrix) => convolve_par(&matrix, &kernel.cast()).into(),
-                            _ => unreachable!(),
-                        }
-                    }
-                    FilterType::Median => match m_scan.as_ref() {
-                        DataMatrix::U8(matrix) => {
-                            compute_median_par(matrix.as_view(), median_settings.size).into()
-                        }
-                        DataMatrix::U16(matrix) => {
-                            compute_median_par(matrix.as_view(), median_settings.size).into()
-                        }
-                        DataMatrix::U32(matrix) => {
-                            compute_median_par(matrix.as_view(), median_settings.size).into()
-                        }
-                        DataMatrix::U64(matrix) => {
-                            compute_median_par(matrix.as_view(), median_settings.size).into()
-                        }
-                        DataMatrix::F32(matrix) => {
-                            compute_median_par(matrix.as_view(), median_settings.size).into()
-                        }
-                        DataMatrix::F64(matrix) => {
-                            compute_median_par(matrix.as_view(), median_settings.size).into()
-                        }
-                    },
-                    FilterType::AlignBrightness => {
-                        let m_scan: Cow<DataMatrix> = if m_scan.data_type().is_integer() {
-                            Cow::Owned(m_scan.cast_rescale_par(types::DataType::F32))
-                        } else {
-                            Cow::Borrowed(m_scan.as_ref())
-                        };
+                // Held back A scans are filtered, once the stream is closed
+                let (filtered, gated, histogram, returned) = priority::spawn_blocking(move || {
+                    let Some(m_scan) = m_scan else {
+                        let filtered =
+                            overlap.finish(|window| filter.apply_gated(window, &gating).0);
+                        return (filtered, GatingStats::default(), None, overlap);
+                    };
 
-                        match m_scan.as_ref() {
-                            DataMatrix::F32(matrix) => {
-                                compute_align_brightness_par(matrix.as_view()).into()
-                            }
-                            DataMatrix::F64(matrix) => {
-                                compute_align_brightness_par(matrix.as_view()).into()
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                    FilterType::Wiener => {
-                        let m_scan: Cow<DataMatrix> = if m_scan.data_type().is_integer() {
-                            Cow::Owned(m_scan.cast_rescale_par(types::DataType::F32))
-                        } else {
-                            Cow::Borrowed(m_scan.as_ref())
-                        };
+                    let histogram = (filter.filter_type == FilterType::Levels)
+                        .then(|| levels_histogram(&m_scan));
+                    let skipped = match gating.skip_dark_columns {
+                        true => dark_columns(&m_scan, gating.dark_threshold)
+                            .into_iter()
+                            .filter(|&dark| dark)
+                            .count(),
+                        false => 0,
+                    };
+                    let gated = GatingStats {
+                        skipped,
+                        processed: m_scan.ncols() - skipped,
+                    };
+                    let filtered =
+                        overlap.push(&m_scan, |window| filter.apply_gated(window, &gating).0);
+                    (filtered, gated, histogram, overlap)
+                })
+                .await?;
+                overlap = returned;
 
-                        match m_scan.as_ref() {
-                            DataMatrix::F32(matrix) => {
-                                compute_wiener_par(matrix.as_view(), &wiener_settings).into()
-                            }
-                            DataMatrix::F64(matrix) => {
-                                compute_wiener_par(matrix.as_view(), &wiener_settings).into()
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                    FilterType::Prewitt => {
-                        let m_scan: Cow<DataMatrix> = if m_scan.data_type().is_integer() {
-                            Cow::Owned(m_scan.cast_rescale_par(types::DataType::F32))
-                        } else {
-                            Cow::Borrowed(m_scan.as_ref())
-                        };
+                if let Some(histogram) = histogram {
+                    self.levels_histogram_tx.send_replace(histogram);
+                }
+                if let Some(&threshold) = otsu_threshold.get() {
+                    self.otsu_threshold_tx.send_replace(Some(threshold));
+                }
 
-                        match m_scan.as_ref() {
-                            DataMatrix::F32(matrix) => {
-                                compute_prewitt_par(matrix.as_view(), &prewitt_settings).into()
-                            }
-                            DataMatrix::F64(matrix) => {
-                                compute_prewitt_par(matrix.as_view(), &prewitt_settings).into()
-                            }
-                            _ => unreachable!(),
-                        }
+                // Counted for the received A scans, held back ones included
+                gating_stats.skipped += gated.skipped;
+                gating_stats.processed += gated.processed;
+                self.gating_tx.send_replace(gating_stats);
+
+                if let Some(filtered) = filtered {
+                    // Skipped A scans are done instantly, so they simply count
+                    processed_a_scans += filtered.ncols();
+                    let _ = self.progress_tx.send(Some(
+                        progress_start
+                            + (1.0 - progress_start) * processed_a_scans as f32
+                                / m_scan_res.a_scan_count as f32,
+                    ));
+
+                    let filtered = Arc::new(filtered);
+                    if let Some(chunks) = &mut cached_chunks {
+                        chunks.push(filtered.clone());
                     }
-                    FilterType::WidenStructures => match m_scan.as_ref() {
-                        DataMatrix::U8(matrix) => {
-                            widen_structures_par(matrix.as_view(), widen_structures_settings.width)
-                                .into()
-                        }
-                        DataMatrix::U16(matrix) => {
-                            widen_structures_par(matrix.as_view(), widen_structures_settings.width)
-                                .into()
-                        }
-                        DataMatrix::U32(matrix) => {
-                            widen_structures_par(matrix.as_view(), widen_structures_settings.width)
-                                .into()
-                        }
-                        DataMatrix::U64(matrix) => {
-                            widen_structures_par(matrix.as_view(), widen_structures_settings.width)
-                                .into()
-                        }
-                        DataMatrix::F32(matrix) => {
-                            widen_structures_par(matrix.as_view(), widen_structures_settings.width)
-                                .into()
-                        }
-                        DataMatrix::F64(matrix) => {
-                            widen_structures_par(matrix.as_view(), widen_structures_settings.width)
-                                .into()
-                        }
-                    },
-                    FilterType::BWAreaOpen => match m_scan.as_ref() {
-                        DataMatrix::U8(matrix) => {
-                            bw_area_open_par(matrix.as_view(), &b_ware_open_settings).into()
-                        }
-                        DataMatrix::U16(matrix) => {
-                            bw_area_open_par(matrix.as_view(), &b_ware_open_settings).into()
-                        }
-                        DataMatrix::U32(matrix) => {
-                            bw_area_open_par(matrix.as_view(), &b_ware_open_settings).into()
-                        }
-                        DataMatrix::U64(matrix) => {
-                            bw_area_open_par(matrix.as_view(), &b_ware_open_settings).into()
-                        }
-                        DataMatrix::F32(matrix) => {
-                            bw_area_open_par(matrix.as_view(), &b_ware_open_settings).into()
-                        }
-                        DataMatrix::F64(matrix) => {
-                            bw_area_open_par(matrix.as_view(), &b_ware_open_settings).into()
-                        }
+                    tx.send(filtered);
+                }
+
+                if closed {
+                    break;
+                }
+            }
+            tx.finish();
+            if let Some(original_tx) = original_tx {
+                original_tx.finish();
+            }
+
+            if let (Some(key), Some(chunks)) = (cache_key, cached_chunks) {
+                self.cache.insert(
+                    key,
+                    CachedResult {
+                        chunks,
+                        a_scan_count: m_scan_res.a_scan_count,
+                        a_scan_samples: m_scan_res.a_scan_samples,
                     },
-                })
-                .await?;
+                );
+            }
+        }
+        let _ = self.progress_tx.send(None);
+
+        Ok(())
+    }
+}
+
+impl Task {
+    /// Empty statistics for the first pass of a global filter, see
+    /// [FilterType::is_global]. [None] for other filters.
+    fn global_statistics(&self) -> Option<GlobalStatistics> {
+        self.filter_type.is_global().then(|| GlobalStatistics {
+            filter_type: self.filter_type,
+            gating: self.gating,
+            sum: 0.0,
+            count: 0,
+            histogram: Histogram::new(GlobalStatistics::BINS),
+        })
+    }
 
-                processed_a_scans += m_scan.ncols();
-                let _ = self.progress_tx.send(Some(
-                    processed_a_scans as f32 / m_scan_res.a_scan_count as f32,
-                ));
+    /// Key of the result for the current settings and input. [None], if the
+    /// cache is disabled or there is no input.
+    fn cache_key(&self) -> Option<CacheKey> {
+        if !self.cache.is_enabled() {
+            return None;
+        }
 
-                tx.send(Arc::new(m_scan));
+        Some(CacheKey {
+            settings: self.settings_hash(),
+            epoch: self.m_scan_in.epoch()?,
+        })
+    }
+
+    /// Hashes the settings relevant for the current filter type, like
+    /// [Node::changed] compares them.
+    fn settings_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+
+        self.filter_type.hash(&mut hasher);
+        match self.filter_type {
+            FilterType::Gaussian => {
+                self.gauss_settings.kernel_size.hash(&mut hasher);
+                self.gauss_settings.sigma.to_bits().hash(&mut hasher);
+                self.hash_physical(&self.gauss_settings.physical, &mut hasher);
+            }
+            FilterType::Median => {
+                self.median_settings.size.hash(&mut hasher);
+                self.hash_physical(&self.median_settings.physical, &mut hasher);
+            }
+            FilterType::MovingAverage => self.moving_average_settings.width.hash(&mut hasher),
+            FilterType::AlignBrightness => {}
+            FilterType::Wiener => self.wiener_settings.neighborhood_size.hash(&mut hasher),
+            FilterType::Prewitt => self.prewitt_settings.threshold.to_bits().hash(&mut hasher),
+            FilterType::WidenStructures => self.widen_structures_settings.width.hash(&mut hasher),
+            FilterType::BWAreaOpen => {
+                self.b_ware_open_settings.area.hash(&mut hasher);
+                self.b_ware_open_settings.connection_type.hash(&mut hasher);
             }
+            FilterType::GlobalNormalize => {
+                self.global_normalize_settings
+                    .low
+                    .to_bits()
+                    .hash(&mut hasher);
+                self.global_normalize_settings
+                    .high
+                    .to_bits()
+                    .hash(&mut hasher);
+            }
+            FilterType::Levels => {
+                let settings = &self.levels_settings;
+                settings.black_point.to_bits().hash(&mut hasher);
+                settings.white_point.to_bits().hash(&mut hasher);
+                settings.gamma.to_bits().hash(&mut hasher);
+            }
+            FilterType::Threshold => {
+                let settings = &self.threshold_settings;
+                settings.mode.hash(&mut hasher);
+                settings.threshold.to_bits().hash(&mut hasher);
+                settings.invert.hash(&mut hasher);
+            }
+        }
 
-            let _ = self.progress_tx.send(None);
+        if self.gating.skip_dark_columns {
+            self.gating.dark_threshold.to_bits().hash(&mut hasher);
+            self.gating.fill.hash(&mut hasher);
         }
 
-        Ok(())
+        hasher.finish()
+    }
+
+    /// Hashes a physical kernel size together with the calibration it depends
+    /// on, so a result is not reused for other B scans.
+    fn hash_physical(&self, physical: &PhysicalKernel, hasher: &mut DefaultHasher) {
+        if !physical.is_used() {
+            return;
+        }
+
+        physical.units.hash(hasher);
+        physical.depth.to_bits().hash(hasher);
+        physical.angle.to_bits().hash(hasher);
+        physical.mm_per_pixel.to_bits().hash(hasher);
+        if physical.needs_b_scans() {
+            self.b_scans_in.epoch().hash(hasher);
+        }
+    }
+
+    /// Size in pixels of the kernel of the Gaussian and median filter, with
+    /// physical units converted using the current calibration. Reports the
+    /// conversion to the node. Other filters get a size of 1×1.
+    async fn kernel_size(&mut self) -> anyhow::Result<Vector2<usize>> {
+        let (pixels, physical) = match self.filter_type {
+            FilterType::Gaussian => (
+                self.gauss_settings.kernel_size,
+                self.gauss_settings.physical,
+            ),
+            FilterType::Median => (self.median_settings.size, self.median_settings.physical),
+            _ => return Ok(Vector2::new(1, 1)),
+        };
+
+        if !physical.is_used() {
+            self.calibration_tx.send_replace(KernelCalibration::Pixels);
+            return Ok(pixels);
+        }
+
+        let b_scan_width = match physical.needs_b_scans() {
+            true => self.b_scan_width().await?,
+            false => None,
+        };
+
+        let (size, calibration) = match physical.pixel_size(pixels, b_scan_width) {
+            Ok(size) => (size, KernelCalibration::Converted(size)),
+            Err(reason) => (pixels, KernelCalibration::Missing(reason)),
+        };
+        self.calibration_tx.send_replace(calibration);
+
+        Ok(size)
+    }
+
+    /// Mean number of A scans per B scan of the connected B scans. Waits for
+    /// the whole segmentation.
+    async fn b_scan_width(&mut self) -> anyhow::Result<Option<f32>> {
+        let Some(b_scans_res) = self.b_scans_in.request(requests::BScanSegmentation).await else {
+            return Ok(None);
+        };
+        let Some(mut b_scans) = b_scans_res.data.subscribe() else {
+            return Ok(None);
+        };
+
+        let mut boundaries = Vec::new();
+        loop {
+            match b_scans.recv().await {
+                Ok(boundary) => boundaries.push(boundary),
+                Err(RecvError::Closed) => break,
+                Err(e) => Err(e)?,
+            }
+        }
+
+        Ok(mean_b_scan_width(&boundaries))
+    }
+
+    /// Streams a cached result, instead of filtering the input again.
+    fn respond_cached(&mut self, cached: CachedResult) {
+        // Everything is sent at once, so the queue must hold all chunks
+        let capacity = Settings::current()
+            .performance
+            .stream_capacity
+            .max(cached.chunks.len());
+        let (res, tx) = requests::StreamedResponse::new(capacity);
+
+        self.m_scan_out.respond(requests::MScanResponse {
+            data: res,
+            a_scan_count: cached.a_scan_count,
+            a_scan_samples: cached.a_scan_samples,
+        });
+        self.m_scan_out.receive().now_or_never();
+
+        let mut tx = ChunkedSender::new(tx, ChunkLimits::current());
+        for chunk in cached.chunks {
+            tx.send(chunk);
+        }
+        tx.finish();
     }
 }
 
 // MARK: Implementations
 
+/// Everything needed to filter a chunk, independent of the task.
+#[derive(Debug, Clone)]
+struct ChunkFilter {
+    filter_type: FilterType,
+    kernel: DMatrix<f32>,
+    kernel_size: Vector2<usize>,
+    moving_average_settings: MovingAverageSettings,
+    wiener_settings: WienerSettings,
+    prewitt_settings: PrewittSettings,
+    widen_structures_settings: WidenStructuresSettings,
+    b_ware_open_settings: BWareOpenSettings,
+    global_normalize_settings: GlobalNormalizeSettings,
+    levels_settings: LevelsSettings,
+    threshold_settings: ThresholdSettings,
+    /// Threshold found by Otsu's method on the first chunk, shared by the
+    /// clones filtering the following chunks.
+    otsu_threshold: Arc<OnceLock<f32>>,
+    /// Reference of the global filters from the whole input. Without it,
+    /// every chunk is its own reference.
+    reference: Option<GlobalReference>,
+    /// See [determinism].
+    deterministic: bool,
+}
+
+impl ChunkFilter {
+    fn apply(&self, m_scan: &DataMatrix) -> DataMatrix {
+        match self.filter_type {
+            FilterType::Gaussian => {
+                let m_scan = if m_scan.data_type().is_integer() {
+                    m_scan.cast_rescale_par(types::DataType::F32)
+                } else {
+                    m_scan.clone()
+                };
+
+                match m_scan {
+                    DataMatrix::F32(matrix) => convolve_par(&matrix, &self.kernel).into(),
+                    DataMatrix::F64(matrix) => {
+                        convolve_par(&matrix, &self.kernel.clone().cast()).into()
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            FilterType::Median => match m_scan {
+                DataMatrix::U8(matrix) => {
+                    compute_median_histogram_par(matrix.as_view(), self.kernel_size, 8).into()
+                }
+                DataMatrix::U16(matrix) => {
+                    compute_median_histogram_par(matrix.as_view(), self.kernel_size, 16).into()
+                }
+                DataMatrix::U32(matrix) => {
+                    compute_median_par(matrix.as_view(), self.kernel_size).into()
+                }
+                DataMatrix::U64(matrix) => {
+                    compute_median_par(matrix.as_view(), self.kernel_size).into()
+                }
+                DataMatrix::F32(matrix) => {
+                    compute_median_par(matrix.as_view(), self.kernel_size).into()
+                }
+                DataMatrix::F64(matrix) => {
+                    compute_median_par(matrix.as_view(), self.kernel_size).into()
+                }
+            },
+            FilterType::MovingAverage => {
+                let m_scan: Cow<DataMatrix> = if m_scan.data_type().is_integer() {
+                    Cow::Owned(m_scan.cast_rescale_par(types::DataType::F32))
+                } else {
+                    Cow::Borrowed(m_scan)
+                };
+
+                let width = self
+                    .moving_average_settings
+                    .width
+                    .clamp(1, m_scan.ncols().max(1));
+                let kernel = DMatrix::from_element(1, width, 1.0 / width as f32);
+
+                match m_scan.as_ref() {
+                    DataMatrix::F32(matrix) => convolve_par(matrix, &kernel).into(),
+                    DataMatrix::F64(matrix) => convolve_par(matrix, &kernel.cast()).into(),
+                    _ => unreachable!(),
+                }
+            }
+            FilterType::AlignBrightness => {
+                let m_scan: Cow<DataMatrix> = if m_scan.data_type().is_integer() {
+                    Cow::Owned(m_scan.cast_rescale_par(types::DataType::F32))
+                } else {
+                    Cow::Borrowed(m_scan)
+                };
+
+                let mean = match self.reference {
+                    Some(GlobalReference::Mean(mean)) => Some(mean),
+                    _ => None,
+                };
+
+                match m_scan.as_ref() {
+                    DataMatrix::F32(matrix) => {
+                        compute_align_brightness_par(matrix.as_view(), mean.map(|m| m as f32))
+                            .into()
+                    }
+                    DataMatrix::F64(matrix) => {
+                        compute_align_brightness_par(matrix.as_view(), mean).into()
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            FilterType::GlobalNormalize => {
+                let m_scan: Cow<DataMatrix> = if m_scan.data_type().is_integer() {
+                    Cow::Owned(m_scan.cast_rescale_par(types::DataType::F32))
+                } else {
+                    Cow::Borrowed(m_scan)
+                };
+
+                let (low, high) = match self.reference {
+                    Some(GlobalReference::Range(low, high)) => (low, high),
+                    _ => {
+                        let mut histogram = Histogram::new(GlobalStatistics::BINS);
+                        add_values(&mut histogram, &m_scan);
+                        percentile_range(&histogram, &self.global_normalize_settings)
+                            .unwrap_or((0.0, 1.0))
+                    }
+                };
+
+                match m_scan.as_ref() {
+                    DataMatrix::F32(matrix) => {
+                        compute_normalize_par(matrix.as_view(), low as f32, high as f32).into()
+                    }
+                    DataMatrix::F64(matrix) => {
+                        compute_normalize_par(matrix.as_view(), low, high).into()
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            FilterType::Levels => {
+                let m_scan: Cow<DataMatrix> = if m_scan.data_type().is_integer() {
+                    Cow::Owned(m_scan.cast_rescale_par(types::DataType::F32))
+                } else {
+                    Cow::Borrowed(m_scan)
+                };
+
+                let settings = &self.levels_settings;
+                match m_scan.as_ref() {
+                    DataMatrix::F32(matrix) => compute_levels_par(
+                        matrix.as_view(),
+                        settings.black_point,
+                        settings.white_point,
+                        settings.gamma,
+                    )
+                    .into(),
+                    DataMatrix::F64(matrix) => compute_levels_par(
+                        matrix.as_view(),
+                        settings.black_point as f64,
+                        settings.white_point as f64,
+                        settings.gamma as f64,
+                    )
+                    .into(),
+                    _ => unreachable!(),
+                }
+            }
+            FilterType::Threshold => {
+                let m_scan: Cow<DataMatrix> = if m_scan.data_type().is_integer() {
+                    Cow::Owned(m_scan.cast_rescale_par(types::DataType::F32))
+                } else {
+                    Cow::Borrowed(m_scan)
+                };
+
+                let settings = &self.threshold_settings;
+                let threshold = match settings.mode {
+                    ThresholdMode::Fixed => settings.threshold,
+                    ThresholdMode::Otsu => match self.otsu_threshold.get() {
+                        Some(&threshold) => threshold,
+                        // Decided by the first chunk with any values
+                        None => match otsu_threshold(&unit_histogram(&m_scan, OTSU_BINS)) {
+                            Some(threshold) => *self.otsu_threshold.get_or_init(|| threshold),
+                            None => settings.threshold,
+                        },
+                    },
+                };
+
+                match m_scan.as_ref() {
+                    DataMatrix::F32(matrix) => {
+                        compute_threshold_par(matrix.as_view(), threshold, settings.invert)
+                    }
+                    DataMatrix::F64(matrix) => {
+                        compute_threshold_par(matrix.as_view(), threshold as f64, settings.invert)
+                    }
+                    _ => unreachable!(),
+                }
+                .into()
+            }
+            FilterType::Wiener => {
+                let m_scan: Cow<DataMatrix> = if m_scan.data_type().is_integer() {
+                    Cow::Owned(m_scan.cast_rescale_par(types::DataType::F32))
+                } else {
+                    Cow::Borrowed(m_scan)
+                };
+
+                match m_scan.as_ref() {
+                    DataMatrix::F32(matrix) => compute_wiener_par(
+                        matrix.as_view(),
+                        &self.wiener_settings,
+                        self.deterministic,
+                    )
+                    .into(),
+                    DataMatrix::F64(matrix) => compute_wiener_par(
+                        matrix.as_view(),
+                        &self.wiener_settings,
+                        self.deterministic,
+                    )
+                    .into(),
+                    _ => unreachable!(),
+                }
+            }
+            FilterType::Prewitt => {
+                let m_scan: Cow<DataMatrix> = if m_scan.data_type().is_integer() {
+                    Cow::Owned(m_scan.cast_rescale_par(types::DataType::F32))
+                } else {
+                    Cow::Borrowed(m_scan)
+                };
+
+                match m_scan.as_ref() {
+                    DataMatrix::F32(matrix) => {
+                        compute_prewitt_par(matrix.as_view(), &self.prewitt_settings).into()
+                    }
+                    DataMatrix::F64(matrix) => {
+                        compute_prewitt_par(matrix.as_view(), &self.prewitt_settings).into()
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            FilterType::WidenStructures => match m_scan {
+                DataMatrix::U8(matrix) => {
+                    widen_structures_par(matrix.as_view(), self.widen_structures_settings.width)
+                        .into()
+                }
+                DataMatrix::U16(matrix) => {
+                    widen_structures_par(matrix.as_view(), self.widen_structures_settings.width)
+                        .into()
+                }
+                DataMatrix::U32(matrix) => {
+                    widen_structures_par(matrix.as_view(), self.widen_structures_settings.width)
+                        .into()
+                }
+                DataMatrix::U64(matrix) => {
+                    widen_structures_par(matrix.as_view(), self.widen_structures_settings.width)
+                        .into()
+                }
+                DataMatrix::F32(matrix) => {
+                    widen_structures_par(matrix.as_view(), self.widen_structures_settings.width)
+                        .into()
+                }
+                DataMatrix::F64(matrix) => {
+                    widen_structures_par(matrix.as_view(), self.widen_structures_settings.width)
+                        .into()
+                }
+            },
+            FilterType::BWAreaOpen => match m_scan {
+                DataMatrix::U8(matrix) => bw_area_open_par(
+                    matrix.as_view(),
+                    &self.b_ware_open_settings,
+                    determinism::parallel_blocks(self.deterministic),
+                )
+                .into(),
+                DataMatrix::U16(matrix) => bw_area_open_par(
+                    matrix.as_view(),
+                    &self.b_ware_open_settings,
+                    determinism::parallel_blocks(self.deterministic),
+                )
+                .into(),
+                DataMatrix::U32(matrix) => bw_area_open_par(
+                    matrix.as_view(),
+                    &self.b_ware_open_settings,
+                    determinism::parallel_blocks(self.deterministic),
+                )
+                .into(),
+                DataMatrix::U64(matrix) => bw_area_open_par(
+                    matrix.as_view(),
+                    &self.b_ware_open_settings,
+                    determinism::parallel_blocks(self.deterministic),
+                )
+                .into(),
+                DataMatrix::F32(matrix) => bw_area_open_par(
+                    matrix.as_view(),
+                    &self.b_ware_open_settings,
+                    determinism::parallel_blocks(self.deterministic),
+                )
+                .into(),
+                DataMatrix::F64(matrix) => bw_area_open_par(
+                    matrix.as_view(),
+                    &self.b_ware_open_settings,
+                    determinism::parallel_blocks(self.deterministic),
+                )
+                .into(),
+            },
+        }
+    }
+
+    fn output_type(&self, data_type: types::DataType) -> types::DataType {
+        self.filter_type.output_type(data_type)
+    }
+
+    /// How many A scans to each side the filter looks at. Filters with a
+    /// radius get the neighboring A scans of other chunks, see
+    /// [ChunkOverlap]. The other filters process each A scan on its own, or
+    /// look at a whole chunk.
+    fn column_radius(&self) -> usize {
+        match self.filter_type {
+            FilterType::Gaussian => self.kernel.ncols() / 2,
+            FilterType::Median => self.kernel_size.x.max(self.kernel_size.y) / 2,
+            FilterType::MovingAverage => self.moving_average_settings.width / 2,
+            _ => 0,
+        }
+    }
+
+    /// Filters only the A scans, that are not too dark, see [GatingSettings].
+    /// Returns the result and the number of skipped A scans.
+    fn apply_gated(&self, m_scan: &DataMatrix, gating: &GatingSettings) -> (DataMatrix, usize) {
+        if !gating.skip_dark_columns {
+            return (self.apply(m_scan), 0);
+        }
+
+        let dark = dark_columns(m_scan, gating.dark_threshold);
+        let skipped = dark.iter().filter(|&&dark| dark).count();
+
+        if skipped == 0 {
+            return (self.apply(m_scan), 0);
+        }
+
+        let bright = dark.iter().map(|&dark| !dark).collect::<Vec<_>>();
+        let output_type = self.output_type(m_scan.data_type());
+
+        let filtered = match skipped == dark.len() {
+            true => DataMatrix::from_data_type(output_type, m_scan.nrows(), 0),
+            false => self.apply(&m_scan.select_columns(&bright)),
+        };
+
+        let skipped_columns = match gating.fill {
+            GatedFill::PassThrough => {
+                let columns = m_scan.select_columns(&dark);
+                match columns.data_type() == output_type {
+                    true => columns,
+                    false => columns.cast_rescale_par(output_type),
+                }
+            }
+            GatedFill::Zero => DataMatrix::from_data_type(output_type, m_scan.nrows(), skipped),
+        };
+
+        let result = filtered
+            .interleave_columns(&skipped_columns, &dark)
+            .expect("Filtered and skipped A scans have the same type and rows");
+
+        (result, skipped)
+    }
+}
+
+// MARK: Chunk Overlap
+
+/// Filters chunks of a stream like the whole M scan at once, for filters
+/// looking at most `radius` A scans to each side. Each chunk is filtered
+/// together with the last A scans before it, and its own last A scans are
+/// held back, until the next chunk arrives or the stream ends.
+///
+/// With dark A scans skipped, see [GatingSettings], the neighbors of the
+/// remaining A scans can still differ at the border of a chunk.
+#[derive(Debug, Clone)]
+struct ChunkOverlap {
+    radius: usize,
+    /// The last A scans already filtered, as neighbors of the held back ones.
+    /// Twice the radius, so the A scans filtered last still cover a kernel.
+    context: Option<DataMatrix>,
+    /// A scans received, but not filtered yet.
+    held: Option<DataMatrix>,
+}
+
+impl ChunkOverlap {
+    fn new(radius: usize) -> Self {
+        Self {
+            radius,
+            context: None,
+            held: None,
+        }
+    }
+
+    /// Adds a chunk, returning the A scans, whose neighbors are all known
+    /// now, filtered by `filter`. [None], while everything is held back.
+    fn push(
+        &mut self,
+        chunk: &DataMatrix,
+        filter: impl FnOnce(&DataMatrix) -> DataMatrix,
+    ) -> Option<DataMatrix> {
+        if self.radius == 0 {
+            return Some(filter(chunk));
+        }
+
+        let held = match self.held.take() {
+            Some(held) => held
+                .concat_horizontally(chunk)
+                .expect("Chunks of an M scan have the same type and rows"),
+            None => chunk.clone(),
+        };
+
+        // Filters need at least their kernel inside of the chunk
+        if held.ncols() <= 2 * self.radius {
+            self.held = Some(held);
+            return None;
+        }
+
+        let ready = held.ncols() - self.radius;
+        let filtered = self.filter_ready(&held, ready, filter);
+        self.held = Some(held.columns(ready, self.radius));
+        Some(filtered)
+    }
+
+    /// Filters the A scans held back at the end of the stream.
+    fn finish(&mut self, filter: impl FnOnce(&DataMatrix) -> DataMatrix) -> Option<DataMatrix> {
+        let held = self.held.take()?;
+        let ready = held.ncols();
+        Some(self.filter_ready(&held, ready, filter))
+    }
+
+    /// Filters the first `ready` A scans of `held`, together with the A scans
+    /// before and after them.
+    fn filter_ready(
+        &mut self,
+        held: &DataMatrix,
+        ready: usize,
+        filter: impl FnOnce(&DataMatrix) -> DataMatrix,
+    ) -> DataMatrix {
+        let (window, offset) = match self.context.take() {
+            Some(context) => (
+                context
+                    .concat_horizontally(held)
+                    .expect("Chunks of an M scan have the same type and rows"),
+                context.ncols(),
+            ),
+            None => (held.clone(), 0),
+        };
+
+        let end = offset + ready;
+        let start = end.saturating_sub(2 * self.radius);
+        self.context = Some(window.columns(start, end - start));
+
+        filter(&window).columns(offset, ready)
+    }
+}
+
+// MARK: Global Statistics
+
+/// Reference of the global filters, see [FilterType::is_global].
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GlobalReference {
+    /// Mean of all values, see [compute_align_brightness_par].
+    Mean(f64),
+    /// Values mapped to 0 and 1, see [compute_normalize_par].
+    Range(f64, f64),
+}
+
+/// Statistics of the whole input of a global filter. A scans skipped by the
+/// gating do not count.
+#[derive(Debug, Clone)]
+struct GlobalStatistics {
+    filter_type: FilterType,
+    gating: GatingSettings,
+    sum: f64,
+    count: u64,
+    histogram: Histogram,
+}
+
+impl GlobalStatistics {
+    const BINS: usize = 4096;
+
+    fn reference(&self, settings: &GlobalNormalizeSettings) -> Option<GlobalReference> {
+        match self.filter_type {
+            FilterType::AlignBrightness if self.count > 0 => {
+                Some(GlobalReference::Mean(self.sum / self.count as f64))
+            }
+            FilterType::GlobalNormalize => percentile_range(&self.histogram, settings)
+                .map(|(low, high)| GlobalReference::Range(low, high)),
+            _ => None,
+        }
+    }
+}
+
+impl Statistics for GlobalStatistics {
+    fn accumulate(&mut self, chunk: &DataMatrix) {
+        let chunk = match self.gating.skip_dark_columns {
+            true => {
+                let bright = dark_columns(chunk, self.gating.dark_threshold)
+                    .into_iter()
+                    .map(|dark| !dark)
+                    .collect::<Vec<_>>();
+                Cow::Owned(chunk.select_columns(&bright))
+            }
+            false => Cow::Borrowed(chunk),
+        };
+        let chunk: Cow<DataMatrix> = if chunk.data_type().is_integer() {
+            Cow::Owned(chunk.cast_rescale_par(types::DataType::F32))
+        } else {
+            chunk
+        };
+
+        match self.filter_type {
+            FilterType::AlignBrightness => {
+                let (sum, count) = match chunk.as_ref() {
+                    DataMatrix::F32(matrix) => {
+                        (matrix.iter().map(|&v| v as f64).sum(), matrix.len())
+                    }
+                    DataMatrix::F64(matrix) => (matrix.sum(), matrix.len()),
+                    _ => unreachable!(),
+                };
+                self.sum += sum;
+                self.count += count as u64;
+            }
+            FilterType::GlobalNormalize => add_values(&mut self.histogram, &chunk),
+            _ => {}
+        }
+    }
+}
+
+/// Adds the values of a floating point matrix to `histogram`.
+fn add_values(histogram: &mut Histogram, matrix: &DataMatrix) {
+    match matrix {
+        DataMatrix::F32(matrix) => histogram.extend(matrix.iter().map(|&v| v as f64)),
+        DataMatrix::F64(matrix) => histogram.extend(matrix.iter().copied()),
+        _ => unreachable!(),
+    }
+}
+
+/// The values at the percentiles of `settings`. [None], if the histogram is
+/// empty.
+fn percentile_range(
+    histogram: &Histogram,
+    settings: &GlobalNormalizeSettings,
+) -> Option<(f64, f64)> {
+    Some((
+        histogram.quantile(settings.low as f64 / 100.0)?,
+        histogram.quantile(settings.high as f64 / 100.0)?,
+    ))
+}
+
+// MARK: Gating
+
+/// Which columns have a mean intensity below `threshold`, relative to the
+/// range of the data type, see [DataMatrix::cast_rescale_par].
+fn dark_columns(m_scan: &DataMatrix, threshold: f32) -> Vec<bool> {
+    fn dark<T: Scalar + ToPrimitive + Send + Sync>(
+        matrix: &DMatrix<T>,
+        max: f64,
+        threshold: f32,
+    ) -> Vec<bool> {
+        use rayon::prelude::*;
+
+        let threshold = threshold as f64 * max * matrix.nrows() as f64;
+
+        matrix
+            .par_column_iter()
+            .map(|column| column.iter().filter_map(|v| v.to_f64()).sum::<f64>() < threshold)
+            .collect()
+    }
+
+    match m_scan {
+        DataMatrix::U8(matrix) => dark(matrix, u8::MAX as f64, threshold),
+        DataMatrix::U16(matrix) => dark(matrix, u16::MAX as f64, threshold),
+        DataMatrix::U32(matrix) => dark(matrix, u32::MAX as f64, threshold),
+        DataMatrix::U64(matrix) => dark(matrix, u64::MAX as f64, threshold),
+        DataMatrix::F32(matrix) => dark(matrix, 1.0, threshold),
+        DataMatrix::F64(matrix) => dark(matrix, 1.0, threshold),
+    }
+}
+
 // MARK: Gaussian
 
 fn gauss_kernel(sigma: f32, kernel_size: Vector2<usize>) -> DMatrix<f32> {
@@ -523,7 +2108,7 @@ fn gauss_kernel(sigma: f32, kernel_size: Vector2<usize>) -> DMatrix<f32> {
 
 // MARK: Median
 
-fn compute_median_par<T>(matrix: DMatrixView<T>, size: Vector2<usize>) -> DMatrix<T>
+pub(super) fn compute_median_par<T>(matrix: DMatrixView<T>, size: Vector2<usize>) -> DMatrix<T>
 where
     T: Scalar + Send + Sync + Copy + PartialOrd + Zero + 'static,
 {
@@ -581,18 +2166,144 @@ where
                     })
                 });
 
-                *value = bucket[bucket_center];
+                *value = bucket[bucket_center];
+            }
+        });
+
+    result
+}
+
+/// Median like [compute_median_par], for integers of up to `bits` bits. A
+/// histogram of the neighborhood slides down each column, adding the row
+/// entering and removing the row leaving it, instead of sorting the whole
+/// neighborhood for every value (Huang et al., 1979).
+fn compute_median_histogram_par<T>(
+    matrix: DMatrixView<T>,
+    size: Vector2<usize>,
+    bits: u32,
+) -> DMatrix<T>
+where
+    T: Scalar + Send + Sync + Copy + Into<usize> + FromPrimitive,
+{
+    use rayon::prelude::*;
+
+    assert!(size.x <= matrix.nrows());
+    assert!(size.y <= matrix.ncols());
+
+    let count = size.x * size.y;
+    let rank = (count / 2 + count % 2).min(count - 1);
+
+    // Mirrored at the borders, like compute_median_par
+    let mirror = |index: isize, len: usize| {
+        let index = index.unsigned_abs();
+        match index >= len {
+            true => len - (index - len) - 2,
+            false => index,
+        }
+    };
+
+    let mut result = matrix.clone_owned();
+
+    result.par_column_iter_mut().enumerate().for_each_init(
+        || SlidingHistogram::new(bits),
+        |histogram, (col, mut col_data)| {
+            let start_col = col as isize - (size.x / 2) as isize;
+            let columns = (0..size.x as isize)
+                .map(|k_col| mirror(start_col + k_col, matrix.ncols()))
+                .collect::<Vec<_>>();
+
+            // Values of the neighborhood in the row `row` relative to the
+            // first row
+            let values = |row: isize| {
+                let row = mirror(row, matrix.nrows());
+                columns.iter().map(move |&col| matrix[(row, col)].into())
+            };
+
+            let first_row = -((size.y / 2) as isize);
+            for k_row in 0..size.y as isize {
+                values(first_row + k_row).for_each(|value| histogram.add(value));
+            }
+
+            for (row, value) in col_data.iter_mut().enumerate() {
+                if row > 0 {
+                    let start_row = first_row + row as isize;
+                    values(start_row - 1).for_each(|value| histogram.remove(value));
+                    values(start_row + size.y as isize - 1).for_each(|value| histogram.add(value));
+                }
+
+                *value =
+                    T::from_usize(histogram.nth(rank)).expect("Bins hold values of the data type");
+            }
+
+            // Empty the histogram for the next column
+            let last_row = first_row + matrix.nrows() as isize - 1;
+            for k_row in 0..size.y as isize {
+                values(last_row + k_row).for_each(|value| histogram.remove(value));
+            }
+        },
+    );
+
+    result
+}
+
+/// Counts of integers, see [compute_median_histogram_par]. Values are also
+/// counted in groups, so finding the nth value only visits the groups before
+/// it and the values of its group.
+#[derive(Debug, Clone)]
+struct SlidingHistogram {
+    counts: Vec<u32>,
+    group_counts: Vec<u32>,
+    /// Values `v` are in the group `v >> group_bits`.
+    group_bits: u32,
+}
+
+impl SlidingHistogram {
+    fn new(bits: u32) -> Self {
+        let group_bits = bits / 2;
+        Self {
+            counts: vec![0; 1 << bits],
+            group_counts: vec![0; 1 << (bits - group_bits)],
+            group_bits,
+        }
+    }
+
+    fn add(&mut self, value: usize) {
+        self.counts[value] += 1;
+        self.group_counts[value >> self.group_bits] += 1;
+    }
+
+    fn remove(&mut self, value: usize) {
+        self.counts[value] -= 1;
+        self.group_counts[value >> self.group_bits] -= 1;
+    }
+
+    /// The value at the position `n` of the sorted values.
+    fn nth(&self, n: usize) -> usize {
+        let mut n = n as u32;
+        for (group, &group_count) in self.group_counts.iter().enumerate() {
+            if n >= group_count {
+                n -= group_count;
+                continue;
             }
-        });
 
-    result
+            let start = group << self.group_bits;
+            for (value, &count) in self.counts[start..].iter().enumerate() {
+                if n < count {
+                    return start + value;
+                }
+                n -= count;
+            }
+        }
+
+        panic!("The histogram holds fewer values than {}", n)
+    }
 }
 
 // MARK: Align Brightness
 
 /// Aligns the brightness of each A Scan, so that the mean value of every A scan
-/// is equal.
-fn compute_align_brightness_par<T>(matrix: DMatrixView<T>) -> DMatrix<T>
+/// is equal to `reference`, or to the mean of the matrix without one.
+fn compute_align_brightness_par<T>(matrix: DMatrixView<T>, reference: Option<T>) -> DMatrix<T>
 where
     T: Scalar
         + Float
@@ -614,7 +2325,9 @@ where
         .map(|col| col.iter().copied().sum::<T>() / num_traits::cast(col.len()).unwrap())
         .collect::<Vec<_>>();
 
-    let mean = means.iter().copied().sum::<T>() / num_traits::cast(means.len()).unwrap();
+    let mean = reference.unwrap_or_else(|| {
+        means.iter().copied().sum::<T>() / num_traits::cast(means.len()).unwrap()
+    });
 
     result
         .par_column_iter_mut()
@@ -628,10 +2341,164 @@ where
     result
 }
 
+// MARK: Global Normalize
+
+/// Maps `low` to 0 and `high` to 1, clamping everything outside.
+fn compute_normalize_par<T>(matrix: DMatrixView<T>, low: T, high: T) -> DMatrix<T>
+where
+    T: Scalar + Float + Send + Sync,
+{
+    use rayon::prelude::*;
+
+    let scale = match high > low {
+        true => T::one() / (high - low),
+        false => T::zero(),
+    };
+
+    let mut result = matrix.clone_owned();
+    result.par_column_iter_mut().for_each(|mut col| {
+        col.iter_mut().for_each(|value| {
+            *value = ((*value - low) * scale).max(T::zero()).min(T::one());
+        });
+    });
+    result
+}
+
+// MARK: Levels
+
+/// Maps `black` to 0 and `white` to 1, clamping everything outside, and raises
+/// the result to the power of `gamma`.
+fn compute_levels_par<T>(matrix: DMatrixView<T>, black: T, white: T, gamma: T) -> DMatrix<T>
+where
+    T: Scalar + Float + Send + Sync,
+{
+    use rayon::prelude::*;
+
+    let mut result = compute_normalize_par(matrix, black, white);
+    if gamma != T::one() {
+        result.par_column_iter_mut().for_each(|mut col| {
+            col.iter_mut().for_each(|value| *value = value.powf(gamma));
+        });
+    }
+    result
+}
+
+/// Counts of the values of `m_scan` in [LEVELS_BINS] bins over the range from
+/// 0 to 1. Integers are rescaled to it, values outside go to the first or last
+/// bin.
+fn levels_histogram(m_scan: &DataMatrix) -> Vec<u64> {
+    let m_scan: Cow<DataMatrix> = if m_scan.data_type().is_integer() {
+        Cow::Owned(m_scan.cast_rescale_par(types::DataType::F32))
+    } else {
+        Cow::Borrowed(m_scan)
+    };
+
+    unit_histogram(&m_scan, LEVELS_BINS)
+}
+
+/// Counts of the floating point values of `m_scan` in `bins` bins over the
+/// range from 0 to 1. Values outside of the range are counted in the first
+/// and the last bin.
+fn unit_histogram(m_scan: &DataMatrix, bins: usize) -> Vec<u64> {
+    let mut counts = vec![0; bins];
+    let mut add = |value: f64| {
+        if !value.is_nan() {
+            let bin = (value.clamp(0.0, 1.0) * bins as f64) as usize;
+            counts[bin.min(bins - 1)] += 1;
+        }
+    };
+
+    match m_scan {
+        DataMatrix::F32(matrix) => matrix.iter().for_each(|&value| add(value as f64)),
+        DataMatrix::F64(matrix) => matrix.iter().for_each(|&value| add(value)),
+        _ => unreachable!(),
+    }
+    counts
+}
+
+// MARK: Threshold
+
+/// 255 for values above `threshold` and 0 for the others, or the other way
+/// around, if `invert`ed. NaN counts as below.
+fn compute_threshold_par<T>(matrix: DMatrixView<T>, threshold: T, invert: bool) -> DMatrix<u8>
+where
+    T: Scalar + PartialOrd + Copy + Send + Sync,
+{
+    use rayon::prelude::*;
+
+    let mut result = DMatrix::zeros(matrix.nrows(), matrix.ncols());
+
+    result
+        .par_column_iter_mut()
+        .zip(matrix.par_column_iter())
+        .for_each(|(mut col, m_col)| {
+            for (value, &m_value) in col.iter_mut().zip(m_col.iter()) {
+                *value = match (m_value > threshold) != invert {
+                    true => u8::MAX,
+                    false => 0,
+                };
+            }
+        });
+
+    result
+}
+
+/// Otsu's method: the threshold between two bins of a histogram over the
+/// range from 0 to 1, that maximizes the variance between the classes below
+/// and above it. Of multiple equally good thresholds, like in an empty gap
+/// between two peaks, the one in the middle is taken. [None], if the
+/// histogram is empty.
+fn otsu_threshold(counts: &[u64]) -> Option<f32> {
+    let total = counts.iter().sum::<u64>() as f64;
+    let total_sum = counts
+        .iter()
+        .enumerate()
+        .map(|(bin, &count)| bin as f64 * count as f64)
+        .sum::<f64>();
+
+    if total == 0.0 {
+        return None;
+    }
+
+    let mut below = 0.0;
+    let mut below_sum = 0.0;
+    let mut best = (f64::NEG_INFINITY, 0, 0);
+
+    // Splitting after each bin, but the last
+    for (bin, &count) in counts.iter().enumerate().take(counts.len() - 1) {
+        below += count as f64;
+        below_sum += bin as f64 * count as f64;
+        let above = total - below;
+
+        let variance = match below > 0.0 && above > 0.0 {
+            true => {
+                let mean_below = below_sum / below;
+                let mean_above = (total_sum - below_sum) / above;
+                below * above * (mean_below - mean_above).powi(2)
+            }
+            false => 0.0,
+        };
+
+        // Tolerates rounding errors of equally good splits
+        if variance > best.0 * (1.0 + 1e-9) {
+            best = (variance, bin, bin);
+        } else if variance >= best.0 * (1.0 - 1e-9) {
+            best.2 = bin;
+        }
+    }
+
+    let (_, first, last) = best;
+    Some((first + last + 2) as f32 / 2.0 / counts.len() as f32)
+}
+
 // MARK: Wiener
 
 /// See https://mathworks.com/help/images/ref/wiener2.html#d126e348493
-fn compute_wiener_par<T>(matrix: DMatrixView<T>, settings: &WienerSettings) -> DMatrix<T>
+fn compute_wiener_par<T>(
+    matrix: DMatrixView<T>,
+    settings: &WienerSettings,
+    deterministic: bool,
+) -> DMatrix<T>
 where
     T: Scalar
         + Float
@@ -716,10 +2583,10 @@ where
         });
 
     // Use mean of all local variances as noise variance
-    let noise_variance = mean_variance
+    let column_variances = mean_variance
         .par_column_iter()
-        .map(|col| col.iter().map(|(_, lv)| *lv).sum::<T>() / num_traits::cast(col.len()).unwrap())
-        .sum::<T>()
+        .map(|col| col.iter().map(|(_, lv)| *lv).sum::<T>() / num_traits::cast(col.len()).unwrap());
+    let noise_variance = determinism::par_sum(column_variances, deterministic)
         / num_traits::cast(mean_variance.ncols()).unwrap();
 
     result
@@ -832,20 +2699,26 @@ where
 
 // MARK: BW Area Open
 
-fn bw_area_open_par<T>(matrix: DMatrixView<T>, settings: &BWareOpenSettings) -> DMatrix<T>
+/// Areas are labeled within `blocks` blocks of A scans, which are processed in
+/// parallel, and merged where they touch across the borders of the blocks.
+fn bw_area_open_par<T>(
+    matrix: DMatrixView<T>,
+    settings: &BWareOpenSettings,
+    blocks: usize,
+) -> DMatrix<T>
 where
     T: Scalar + Zero + PartialOrd + Send + Sync + Copy + 'static,
 {
     use rayon::prelude::*;
 
-    const HAVE_SEEN: usize = usize::MAX;
+    /// Label of pixels not belonging to any area.
+    const NO_LABEL: usize = usize::MAX;
 
     let mut result = matrix.clone_owned();
 
     let max_area = settings.area;
 
-    let thread_count = rayon::current_num_threads();
-    let block_size = matrix.ncols() / thread_count + 1;
+    let block_size = matrix.ncols() / blocks + 1;
 
     fn for_neighbors_star4(
         row: usize,
@@ -890,85 +2763,140 @@ where
         AreaConnectionType::Circle8 => for_neighbors_circle8,
     };
 
-    let area_counters = (0..thread_count)
+    // Label the areas of every block, numbered from 0 within the block, and
+    // count their pixels
+    let labeled = (0..blocks)
         .into_par_iter()
         .map(|i| {
-            let start_idx = i * block_size;
+            let start_idx = (i * block_size).min(matrix.ncols());
             let end_idx = ((i + 1) * block_size).min(matrix.ncols());
 
-            let mut area_counter = DMatrix::<usize>::zeros(matrix.nrows(), end_idx - start_idx);
-            let max_row = area_counter.nrows() - 1;
-            let max_col = area_counter.ncols() - 1;
+            let mut labels = DMatrix::from_element(matrix.nrows(), end_idx - start_idx, NO_LABEL);
+            let mut areas = Vec::new();
 
-            let mut queue = Vec::with_capacity(max_area);
+            if labels.is_empty() {
+                return (labels, areas);
+            }
+
+            let max_row = labels.nrows() - 1;
+            let max_col = labels.ncols() - 1;
 
-            for col in 0..area_counter.ncols() {
-                for row in 0..area_counter.nrows() {
-                    if area_counter[(row, col)] > 0 || matrix[(row, col + start_idx)] <= T::zero() {
+            let mut queue = Vec::new();
+
+            for col in 0..labels.ncols() {
+                for row in 0..labels.nrows() {
+                    if labels[(row, col)] != NO_LABEL || matrix[(row, col + start_idx)] <= T::zero()
+                    {
                         continue;
                     }
 
+                    let label = areas.len();
                     let mut area_count = 0;
-                    let mut queue_cur = 0;
-                    queue.clear();
+                    labels[(row, col)] = label;
                     queue.push((row, col));
 
-                    while let Some((row, col)) = queue.get(queue_cur).copied() {
-                        queue_cur += 1;
-
-                        if matrix[(row, col + start_idx)] <= T::zero() {
-                            continue;
-                        }
-
-                        let counter = &mut area_counter[(row, col)];
-
-                        if *counter == HAVE_SEEN {
-                            continue;
-                        }
-
-                        if *counter > 0 {
-                            area_count = *counter;
-                        }
-
+                    while let Some((row, col)) = queue.pop() {
                         area_count += 1;
 
-                        if area_count >= max_area {
-                            break;
-                        }
-
-                        *counter = HAVE_SEEN;
-
                         for_neighbors(row, col, max_row, max_col, &mut |n_row, n_col| {
-                            if area_counter[(n_row, n_col)] == 0
+                            if labels[(n_row, n_col)] == NO_LABEL
                                 && matrix[(n_row, n_col + start_idx)] > T::zero()
                             {
+                                labels[(n_row, n_col)] = label;
                                 queue.push((n_row, n_col));
                             }
                         });
                     }
 
-                    for (row, col) in queue.iter().take(queue_cur).copied() {
-                        area_counter[(row, col)] = area_count;
-                    }
+                    areas.push(area_count);
                 }
             }
 
-            area_counter
+            (labels, areas)
+        })
+        .collect::<Vec<_>>();
+
+    // Give every area a label unique over all blocks
+    let offsets = labeled
+        .iter()
+        .scan(0, |offset, (_, areas)| {
+            let block_offset = *offset;
+            *offset += areas.len();
+            Some(block_offset)
         })
         .collect::<Vec<_>>();
 
-    // TODO: Merge area counters
+    let mut areas = labeled
+        .iter()
+        .flat_map(|(_, areas)| areas.iter().copied())
+        .collect::<Vec<_>>();
+
+    // Union-find over the labels, merging areas touching across the border
+    // of two blocks
+    let mut parents = (0..areas.len()).collect::<Vec<_>>();
+
+    fn find(parents: &mut [usize], mut label: usize) -> usize {
+        while parents[label] != label {
+            parents[label] = parents[parents[label]];
+            label = parents[label];
+        }
+        label
+    }
+
+    for (i, pair) in labeled.windows(2).enumerate() {
+        let (left, right) = (&pair[0].0, &pair[1].0);
+        if left.is_empty() || right.is_empty() {
+            continue;
+        }
+
+        let max_row = left.nrows() - 1;
+        let border_col = left.ncols() - 1;
+
+        for row in 0..left.nrows() {
+            let label = left[(row, border_col)];
+            if label == NO_LABEL {
+                continue;
+            }
+
+            let rows = match settings.connection_type {
+                AreaConnectionType::Star4 => row..=row,
+                AreaConnectionType::Circle8 => row.saturating_sub(1)..=(row + 1).min(max_row),
+            };
+
+            for n_row in rows {
+                let n_label = right[(n_row, 0)];
+                if n_label == NO_LABEL {
+                    continue;
+                }
+
+                let a = find(&mut parents, offsets[i] + label);
+                let b = find(&mut parents, offsets[i + 1] + n_label);
+                if a != b {
+                    parents[b] = a;
+                    areas[a] += areas[b];
+                }
+            }
+        }
+    }
+
+    let merged_areas = (0..areas.len())
+        .map(|label| areas[find(&mut parents, label)])
+        .collect::<Vec<_>>();
 
     result
         .par_column_iter_mut()
         .enumerate()
         .for_each(|(col, mut col_data)| {
-            let counter_idx = col / block_size;
-            let counter_col = col % block_size;
+            let block_idx = col / block_size;
+            let block_col = col % block_size;
+            let labels = &labeled[block_idx].0;
             for (row, value) in col_data.iter_mut().enumerate() {
-                let local_area = area_counters[counter_idx][(row, counter_col)];
+                let area = match labels[(row, block_col)] {
+                    NO_LABEL => 0,
+                    label => merged_areas[offsets[block_idx] + label],
+                };
 
-                *value = if local_area >= max_area {
+                *value = if area >= max_area {
                     matrix[(row, col)]
                 } else {
                     T::zero()
@@ -978,3 +2906,752 @@ where
 
     result
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use crate::{
+        node_graph::NodeId,
+        pipeline::{random::Lcg, Pipeline, PipelineExecutor},
+    };
+
+    use super::*;
+
+    /// Serves an M scan of 12 A scans in chunks of 4 A scans and counts the
+    /// requests.
+    fn spawn_m_scan_producer(requests: Arc<AtomicUsize>) -> ConnectionHandle {
+        let (handle, mut output) = ConnectionHandle::new::<requests::MScan>();
+
+        tokio::spawn(async move {
+            loop {
+                let _req = output.receive().await;
+                requests.fetch_add(1, Ordering::Relaxed);
+
+                let (res, tx) = requests::StreamedResponse::new(64);
+                output.respond(requests::MScanResponse {
+                    data: res,
+                    a_scan_count: 12,
+                    a_scan_samples: 8,
+                });
+
+                for start in (0..12).step_by(4) {
+                    let chunk = DMatrix::from_fn(8, 4, |r, c| (r + start + c) as u8);
+                    tx.send(Arc::new(chunk.into()));
+                }
+                tx.finish();
+            }
+        });
+
+        handle
+    }
+
+    async fn collect(res: requests::MScanResponse) -> Vec<Arc<DataMatrix>> {
+        let mut rx = res.data.subscribe().unwrap();
+        let mut chunks = Vec::new();
+        loop {
+            match rx.recv().await {
+                Ok(chunk) => chunks.push(chunk),
+                Err(RecvError::Closed) => break chunks,
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn original_uses_single_upstream_request() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let executor = PipelineExecutor::new();
+
+        let mut runner = executor.spawn_ephemeral(&mut Node::gaussian());
+        runner.connect_input(
+            InputId::MScan.into(),
+            spawn_m_scan_producer(requests.clone()),
+        );
+
+        let mut filtered = TaskInput::<requests::MScan>::default();
+        let mut original = TaskInput::<requests::MScan>::default();
+        assert!(filtered.connect(&mut runner.get_output(OutputId::Filtered.into()).unwrap()));
+        assert!(original.connect(&mut runner.get_output(OutputId::Original.into()).unwrap()));
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            let filtered = collect(filtered.request(requests::MScan).await.unwrap()).await;
+            let original = collect(original.request(requests::MScan).await.unwrap()).await;
+
+            assert_eq!(filtered.iter().map(|c| c.ncols()).sum::<usize>(), 12);
+            assert_eq!(original.len(), 3);
+            for (start, chunk) in (0..12).step_by(4).zip(&original) {
+                let expected = DMatrix::from_fn(8, 4, |r, c| (r + start + c) as u8);
+                assert_eq!(**chunk, DataMatrix::from(expected));
+            }
+        })
+        .await
+        .expect("Filter should finish");
+
+        assert_eq!(requests.load(Ordering::Relaxed), 1);
+
+        // Only the original requested, the input is passed through
+        let requests = Arc::new(AtomicUsize::new(0));
+        let mut runner = executor.spawn_ephemeral(&mut Node::gaussian());
+        runner.connect_input(
+            InputId::MScan.into(),
+            spawn_m_scan_producer(requests.clone()),
+        );
+
+        let mut original = TaskInput::<requests::MScan>::default();
+        assert!(original.connect(&mut runner.get_output(OutputId::Original.into()).unwrap()));
+
+        let original = tokio::time::timeout(Duration::from_secs(10), async {
+            collect(original.request(requests::MScan).await.unwrap()).await
+        })
+        .await
+        .expect("Pass through should finish");
+
+        assert_eq!(original.len(), 3);
+        assert_eq!(requests.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn cached_results_replayed() {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden/raw.bin");
+        let mut pipeline: Pipeline = serde_json::from_value(json!({
+            "nodes": {
+                "1": {
+                    "type": "binary_input",
+                    "path": source,
+                    "input_type": "MScan",
+                    "data_type": "U16",
+                    "a_scan_length": 256,
+                },
+                "2": {
+                    "type": "filter",
+                    "filter_type": "Gaussian",
+                    "cache_settings": { "enabled": true },
+                    "input": {
+                        "value": null,
+                        "connection": { "node_id": 1, "output_id": 1, "type_id": 2 },
+                    },
+                },
+            },
+        }))
+        .unwrap();
+        let node_id = NodeId::from(2);
+
+        let mut executor = PipelineExecutor::new();
+        executor.update(&mut pipeline);
+
+        fn node(pipeline: &mut Pipeline) -> &mut Node {
+            let node = pipeline.nodes.get_mut(&NodeId::from(2)).unwrap();
+            node.as_any_mut().downcast_mut().unwrap()
+        }
+        let cache_rx = node(&mut pipeline).cache_rx.clone().unwrap();
+
+        let mut handle = executor
+            .get_output(node_id, OutputId::Filtered.into())
+            .unwrap();
+        let mut notifier = handle.get_invalidation_notifier();
+        let mut filtered = TaskInput::<requests::MScan>::default();
+        assert!(filtered.connect(&mut handle));
+
+        let mut results = Vec::new();
+        for (run, sigma) in [1.0, 2.0, 1.0, 2.0].into_iter().enumerate() {
+            if run > 0 {
+                node(&mut pipeline).gauss_settings.sigma = sigma;
+                executor.update(&mut pipeline);
+                assert!(notifier.on_invalidate().await);
+            }
+
+            let result = tokio::time::timeout(Duration::from_secs(60), async {
+                collect(filtered.request(requests::MScan).await.unwrap()).await
+            })
+            .await
+            .expect("Filter should finish");
+            results.push(result);
+
+            // The second run of each sigma streams from the cache
+            let stats = *cache_rx.borrow();
+            let hits = run.saturating_sub(1);
+            assert_eq!(stats.hits, hits, "run {}", run);
+            assert_eq!(stats.misses, run + 1 - hits, "run {}", run);
+        }
+
+        assert_eq!(cache_rx.borrow().entries, 2);
+        assert_eq!(results[0], results[2]);
+        assert_eq!(results[1], results[3]);
+        assert_ne!(results[0], results[1]);
+    }
+
+    /// Runs `node` on the output of [spawn_m_scan_producer]. Returns the
+    /// filtered A scans, how the node passed over its input and the number
+    /// of upstream requests.
+    async fn run_global(mut node: Node, replayable: bool) -> (DMatrix<f32>, Passes, usize) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let executor = PipelineExecutor::new();
+
+        let producer = spawn_m_scan_producer(requests.clone());
+        producer.set_replayable(replayable);
+
+        let mut runner = executor.spawn_ephemeral(&mut node);
+        runner.connect_input(InputId::MScan.into(), producer);
+
+        let mut filtered = TaskInput::<requests::MScan>::default();
+        assert!(filtered.connect(&mut runner.get_output(OutputId::Filtered.into()).unwrap()));
+
+        let chunks = tokio::time::timeout(Duration::from_secs(10), async {
+            collect(filtered.request(requests::MScan).await.unwrap()).await
+        })
+        .await
+        .expect("Filter should finish");
+
+        let columns = chunks
+            .iter()
+            .flat_map(|chunk| {
+                let DataMatrix::F32(chunk) = chunk.as_ref() else {
+                    panic!("Global filters work on floating point values");
+                };
+                chunk
+                    .column_iter()
+                    .map(|c| c.into_owned())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let passes = *node.passes_rx.unwrap().borrow();
+        (
+            DMatrix::from_columns(&columns),
+            passes,
+            requests.load(Ordering::Relaxed),
+        )
+    }
+
+    /// The whole M scan of [spawn_m_scan_producer], like the global filters
+    /// see it.
+    fn whole_m_scan() -> DMatrix<f32> {
+        let m_scan = DataMatrix::from(DMatrix::from_fn(8, 12, |r, c| (r + c) as u8));
+        let DataMatrix::F32(m_scan) = m_scan.cast_rescale_par(types::DataType::F32) else {
+            unreachable!()
+        };
+        m_scan
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn global_filters_match_whole_input() {
+        let m_scan = whole_m_scan();
+
+        let (result, passes, requests) = run_global(Node::align_brightness(), true).await;
+        let expected = compute_align_brightness_par(m_scan.as_view(), None);
+        assert_eq!(passes, Passes::Two);
+        // The second pass is served from the same response
+        assert_eq!(requests, 1);
+        assert!((result - expected).amax() < 1e-5);
+
+        let (result, passes, _) = run_global(Node::global_normalize(), true).await;
+        let mut values = m_scan.iter().copied().collect::<Vec<_>>();
+        values.sort_by(f32::total_cmp);
+        let percentile = |p: f32| values[(p / 100.0 * values.len() as f32) as usize];
+        let expected = compute_normalize_par(m_scan.as_view(), percentile(1.0), percentile(99.0));
+        assert_eq!(passes, Passes::Two);
+        assert!((result - expected).amax() < 1e-3);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn global_filters_fall_back_to_single_pass() {
+        let m_scan = whole_m_scan();
+
+        let (result, passes, requests) = run_global(Node::align_brightness(), false).await;
+        let expected = compute_align_brightness_par(m_scan.as_view(), None);
+        assert!(matches!(passes, Passes::Single(_)));
+        assert_eq!(requests, 1);
+        assert_eq!(result.shape(), expected.shape());
+
+        // Every chunk of 4 A scans is aligned with its own mean
+        for start in (0..12).step_by(4) {
+            let chunk = compute_align_brightness_par(m_scan.columns(start, 4), None);
+            assert!((result.columns(start, 4) - chunk).amax() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn physical_kernel_size() {
+        // 1000 A scans per B scan, so 0.36° per A scan
+        let boundaries = [100, 1100, 2100, 3100];
+        let width = mean_b_scan_width(&boundaries);
+        assert_eq!(width, Some(1000.0));
+        assert_eq!(mean_b_scan_width(&[100]), None);
+
+        let pixels = Vector2::new(3, 5);
+        let mut physical = PhysicalKernel {
+            units: [KernelUnit::Physical; 2],
+            depth: 55.0,
+            angle: 3.6,
+            mm_per_pixel: 0.0055,
+        };
+        assert_eq!(physical.pixel_size(pixels, width), Ok(Vector2::new(10, 10)));
+
+        // Never smaller than a pixel
+        physical.depth = 1.0;
+        physical.angle = 0.01;
+        assert_eq!(physical.pixel_size(pixels, width), Ok(Vector2::new(1, 1)));
+
+        // Only the columns in degrees
+        physical.units[0] = KernelUnit::Pixels;
+        physical.angle = 1.0;
+        assert_eq!(physical.pixel_size(pixels, width), Ok(Vector2::new(3, 3)));
+        assert!(physical.pixel_size(pixels, None).is_err());
+
+        // Only the rows in µm, which needs no B scans
+        physical.units = [KernelUnit::Physical, KernelUnit::Pixels];
+        physical.depth = 22.0;
+        assert_eq!(physical.pixel_size(pixels, None), Ok(Vector2::new(4, 5)));
+        physical.mm_per_pixel = 0.0;
+        assert!(physical.pixel_size(pixels, None).is_err());
+    }
+
+    fn chunk_filter(filter_type: FilterType) -> ChunkFilter {
+        ChunkFilter {
+            filter_type,
+            kernel: gauss_kernel(1.0, Vector2::new(3, 3)),
+            kernel_size: Vector2::new(3, 3),
+            moving_average_settings: MovingAverageSettings::default(),
+            wiener_settings: WienerSettings::default(),
+            prewitt_settings: PrewittSettings::default(),
+            widen_structures_settings: WidenStructuresSettings::default(),
+            b_ware_open_settings: BWareOpenSettings::default(),
+            global_normalize_settings: GlobalNormalizeSettings::default(),
+            levels_settings: LevelsSettings::default(),
+            threshold_settings: ThresholdSettings::default(),
+            otsu_threshold: Arc::default(),
+            reference: None,
+            deterministic: false,
+        }
+    }
+
+    #[test]
+    fn overlapping_chunks_match_whole_m_scan() {
+        let m_scan = DataMatrix::F32(DMatrix::from_fn(12, 23, |row, col| {
+            ((row * 7 + col * 13) % 17) as f32 / 17.0
+        }));
+
+        let mut filters = [
+            FilterType::Gaussian,
+            FilterType::Median,
+            FilterType::MovingAverage,
+        ]
+        .map(chunk_filter);
+        filters[0].kernel = gauss_kernel(1.5, Vector2::new(5, 5));
+        filters[1].kernel_size = Vector2::new(5, 5);
+
+        for filter in filters {
+            let expected = filter.apply(&m_scan);
+            let mut overlap = ChunkOverlap::new(filter.column_radius());
+            assert!(overlap.radius > 0);
+
+            let mut chunks = Vec::new();
+            let mut start = 0;
+            for len in [1, 4, 2, 7, 3, 6] {
+                chunks.extend(overlap.push(&m_scan.columns(start, len), |w| filter.apply(w)));
+                start += len;
+            }
+            chunks.extend(overlap.finish(|w| filter.apply(w)));
+
+            let result = chunks
+                .into_iter()
+                .reduce(|a, b| a.concat_horizontally(&b).unwrap())
+                .unwrap();
+            assert_eq!(result, expected, "{:?}", filter.filter_type);
+        }
+    }
+
+    /// Matrix of pseudo random values up to `max`.
+    fn random_matrix<T: Scalar + FromPrimitive>(
+        rows: usize,
+        cols: usize,
+        max: u64,
+        seed: u64,
+    ) -> DMatrix<T> {
+        let mut rng = Lcg::new(seed);
+        DMatrix::from_fn(rows, cols, |_, _| {
+            T::from_usize(rng.below(max as usize + 1)).unwrap()
+        })
+    }
+
+    #[test]
+    fn median_histogram_matches_sorting() {
+        let sizes = [(1, 1), (3, 3), (2, 2), (4, 3), (3, 6), (9, 9), (1, 7)];
+
+        for (seed, (x, y)) in sizes.into_iter().enumerate() {
+            let size = Vector2::new(x, y);
+            let seed = seed as u64 + 1;
+
+            let matrix = random_matrix::<u8>(17, 23, u8::MAX as u64, seed);
+            assert_eq!(
+                compute_median_histogram_par(matrix.as_view(), size, 8),
+                compute_median_par(matrix.as_view(), size),
+                "{x}×{y}"
+            );
+
+            // Few distinct values, so many are equal
+            let matrix = random_matrix::<u16>(23, 17, 3, seed);
+            assert_eq!(
+                compute_median_histogram_par(matrix.as_view(), size, 16),
+                compute_median_par(matrix.as_view(), size),
+                "{x}×{y}"
+            );
+
+            let matrix = random_matrix::<u16>(20, 20, u16::MAX as u64, seed);
+            assert_eq!(
+                compute_median_histogram_par(matrix.as_view(), size, 16),
+                compute_median_par(matrix.as_view(), size),
+                "{x}×{y}"
+            );
+        }
+    }
+
+    /// Compares the median by sorting and with a sliding histogram. Run with
+    /// `cargo test median_speed --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn median_speed() {
+        use std::time::Instant;
+
+        let matrix = random_matrix::<u16>(1024, 512, u16::MAX as u64, 1);
+        let size = Vector2::new(9, 9);
+
+        let start = Instant::now();
+        let sorted = compute_median_par(matrix.as_view(), size);
+        let sorting = start.elapsed();
+
+        let start = Instant::now();
+        let histogram = compute_median_histogram_par(matrix.as_view(), size, 16);
+        let sliding = start.elapsed();
+
+        println!("9×9 median of 1024×512 u16: sorting {sorting:?}, histogram {sliding:?}");
+        assert_eq!(sorted, histogram);
+        assert!(sliding < sorting);
+    }
+
+    #[test]
+    fn levels() {
+        let mut filter = chunk_filter(FilterType::Levels);
+        filter.levels_settings = LevelsSettings {
+            black_point: 0.2,
+            white_point: 0.6,
+            gamma: 2.0,
+        };
+
+        let values = [0.0, 0.2, 0.4, 0.5, 0.6, 1.0];
+        let DataMatrix::F32(result) =
+            filter.apply(&DataMatrix::F32(DMatrix::from_row_slice(1, 6, &values)))
+        else {
+            panic!("Levels keep floating point values");
+        };
+        let expected = [0.0, 0.0, 0.25, 0.5625, 1.0, 1.0];
+        for (value, expected) in result.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-6, "{value} != {expected}");
+        }
+
+        // Integers are rescaled to 0..1 first
+        let m_scan = DataMatrix::U8(DMatrix::from_row_slice(1, 3, &[0, 102, 255]));
+        let DataMatrix::F32(result) = filter.apply(&m_scan) else {
+            panic!("Levels work on floating point values");
+        };
+        assert_eq!(result[0], 0.0);
+        assert!((result[1] - 0.25).abs() < 1e-6);
+        assert_eq!(result[2], 1.0);
+
+        let histogram = levels_histogram(&m_scan);
+        assert_eq!(histogram.len(), LEVELS_BINS);
+        assert_eq!(histogram[0], 1);
+        assert_eq!(histogram[(0.4 * LEVELS_BINS as f64) as usize], 1);
+        assert_eq!(histogram[LEVELS_BINS - 1], 1);
+
+        // Every setting reruns the filter
+        let node = Node::levels();
+        for change in [
+            |s: &mut LevelsSettings| s.black_point = 0.1,
+            |s: &mut LevelsSettings| s.white_point = 0.9,
+            |s: &mut LevelsSettings| s.gamma = 0.5,
+        ] {
+            let mut other = node.clone();
+            change(&mut other.levels_settings);
+            assert!(PipelineNode::changed(&node, &other));
+        }
+    }
+
+    #[test]
+    fn otsu_threshold_of_bimodal_image() {
+        // Dark background and bright structures, with a gap in between
+        let image = DMatrix::<u8>::from_fn(30, 40, |row, col| match (row + col) % 3 {
+            0 => 180 + ((row * 7 + col * 3) % 41) as u8,
+            _ => 40 + ((row * 5 + col * 11) % 21) as u8,
+        });
+        let m_scan = DataMatrix::U8(image.clone());
+
+        let mut filter = chunk_filter(FilterType::Threshold);
+        filter.threshold_settings.mode = ThresholdMode::Otsu;
+
+        // Split in the middle of the gap
+        let DataMatrix::U8(mask) = filter.apply(&m_scan) else {
+            panic!("Thresholds produce masks of u8");
+        };
+        let threshold = *filter.otsu_threshold.get().unwrap();
+        assert!((threshold - 120.0 / 255.0).abs() < 0.01, "{threshold}");
+        assert_eq!(mask, image.map(|v| if v >= 180 { 255 } else { 0 }));
+
+        // Overlapping classes are split, where the variance within them is
+        // smallest. The values lie in the middle of the bins, so the
+        // histogram is exact.
+        let values = (0..1000)
+            .map(|i| match i % 4 {
+                0 => 128 + (i % 37) * 3,
+                _ => 26 + (i % 53) * 3,
+            })
+            .map(|bin| (bin as f64 + 0.5) / OTSU_BINS as f64)
+            .collect::<Vec<_>>();
+        let histogram = unit_histogram(
+            &DataMatrix::F64(DMatrix::from_vec(1, 1000, values.clone())),
+            OTSU_BINS,
+        );
+        let within_variance = |threshold: f64| {
+            let (below, above): (Vec<f64>, Vec<f64>) =
+                values.iter().partition(|&&value| value <= threshold);
+            [below, above]
+                .iter()
+                .map(|class| {
+                    let mean = class.iter().sum::<f64>() / class.len() as f64;
+                    class
+                        .iter()
+                        .map(|value| (value - mean).powi(2))
+                        .sum::<f64>()
+                })
+                .sum::<f64>()
+        };
+        let best = (1..OTSU_BINS)
+            .map(|bin| bin as f64 / OTSU_BINS as f64)
+            .min_by(|a, b| within_variance(*a).total_cmp(&within_variance(*b)))
+            .unwrap();
+        let threshold = otsu_threshold(&histogram).unwrap() as f64;
+        assert!(
+            within_variance(threshold) <= within_variance(best) + 1e-9,
+            "{threshold} != {best}"
+        );
+
+        assert_eq!(otsu_threshold(&[0; OTSU_BINS]), None);
+    }
+
+    #[test]
+    fn threshold() {
+        let mut filter = chunk_filter(FilterType::Threshold);
+        filter.threshold_settings.threshold = 0.4;
+
+        let m_scan = DataMatrix::F32(DMatrix::from_row_slice(
+            1,
+            5,
+            &[0.0, 0.4, 0.5, 1.0, f32::NAN],
+        ));
+        assert_eq!(
+            filter.apply(&m_scan),
+            DataMatrix::U8(DMatrix::from_row_slice(1, 5, &[0, 0, 255, 255, 0]))
+        );
+
+        filter.threshold_settings.invert = true;
+        assert_eq!(
+            filter.apply(&m_scan),
+            DataMatrix::U8(DMatrix::from_row_slice(1, 5, &[255, 255, 0, 0, 255]))
+        );
+
+        // Integers are compared relative to their range
+        let m_scan = DataMatrix::U16(DMatrix::from_row_slice(1, 2, &[20000, 30000]));
+        assert_eq!(
+            filter.apply(&m_scan),
+            DataMatrix::U8(DMatrix::from_row_slice(1, 2, &[255, 0]))
+        );
+
+        // Otsu's method decides on the first chunk with values and keeps the
+        // threshold for the following ones
+        let mut filter = chunk_filter(FilterType::Threshold);
+        filter.threshold_settings.mode = ThresholdMode::Otsu;
+        filter.apply(&DataMatrix::F32(DMatrix::zeros(0, 0)));
+        assert!(filter.otsu_threshold.get().is_none());
+
+        let first = DataMatrix::F32(DMatrix::from_row_slice(1, 4, &[0.1, 0.1, 0.3, 0.3]));
+        filter.apply(&first);
+        let threshold = *filter.otsu_threshold.get().unwrap();
+        assert!(0.1 < threshold && threshold < 0.3, "{threshold}");
+
+        let second = DataMatrix::F32(DMatrix::from_row_slice(1, 2, &[0.8, 0.9]));
+        assert_eq!(
+            filter.clone().apply(&second),
+            DataMatrix::U8(DMatrix::from_row_slice(1, 2, &[255, 255]))
+        );
+
+        let node = Node::threshold();
+        assert_eq!(
+            node.filter_type.output_type(types::DataType::F64),
+            types::DataType::U8
+        );
+        for change in [
+            |s: &mut ThresholdSettings| s.mode = ThresholdMode::Otsu,
+            |s: &mut ThresholdSettings| s.threshold = 0.2,
+            |s: &mut ThresholdSettings| s.invert = true,
+        ] {
+            let mut other = node.clone();
+            change(&mut other.threshold_settings);
+            assert!(PipelineNode::changed(&node, &other));
+        }
+    }
+
+    /// Bright speckle, with dark A scans at `dark`.
+    fn m_scan_with_dark_columns(dark: &[usize]) -> DataMatrix {
+        DataMatrix::U16(DMatrix::from_fn(16, 10, |row, col| {
+            match dark.contains(&col) {
+                true => 100 + row as u16,
+                false => 30000 + ((row * 7 + col * 13) % 50) as u16 * 100,
+            }
+        }))
+    }
+
+    #[test]
+    fn gating_bright_data() {
+        let m_scan = m_scan_with_dark_columns(&[]);
+        let gating = GatingSettings {
+            skip_dark_columns: true,
+            ..Default::default()
+        };
+
+        for filter_type in FilterType::VALUES {
+            let filter = chunk_filter(filter_type);
+            let (gated, skipped) = filter.apply_gated(&m_scan, &gating);
+
+            assert_eq!(skipped, 0);
+            assert_eq!(gated, filter.apply(&m_scan), "{:?}", filter_type);
+        }
+    }
+
+    #[test]
+    fn gated_columns_keep_their_place() {
+        let dark = [0, 3, 4, 9];
+        let m_scan = m_scan_with_dark_columns(&dark);
+        let bright = (0..10).map(|col| !dark.contains(&col)).collect::<Vec<_>>();
+
+        let filter = chunk_filter(FilterType::Median);
+        let DataMatrix::U16(expected) = filter.apply(&m_scan.select_columns(&bright)) else {
+            panic!("The median keeps the data type");
+        };
+        let DataMatrix::U16(input) = &m_scan else {
+            unreachable!()
+        };
+
+        for fill in GatedFill::VALUES {
+            let gating = GatingSettings {
+                skip_dark_columns: true,
+                dark_threshold: 0.05,
+                fill,
+            };
+            let (result, skipped) = filter.apply_gated(&m_scan, &gating);
+            assert_eq!(skipped, dark.len());
+
+            let DataMatrix::U16(result) = result else {
+                panic!("The median keeps the data type");
+            };
+
+            let mut filtered = expected.column_iter();
+            for col in 0..10 {
+                match (dark.contains(&col), fill) {
+                    (true, GatedFill::PassThrough) => {
+                        assert_eq!(result.column(col), input.column(col))
+                    }
+                    (true, GatedFill::Zero) => assert!(result.column(col).iter().all(|&v| v == 0)),
+                    (false, _) => assert_eq!(result.column(col), filtered.next().unwrap()),
+                }
+            }
+        }
+
+        // Filters working on floating point values get the skipped A scans
+        // rescaled
+        let gating = GatingSettings {
+            skip_dark_columns: true,
+            ..Default::default()
+        };
+        let (result, _) = chunk_filter(FilterType::Gaussian).apply_gated(&m_scan, &gating);
+        let DataMatrix::F32(result) = result else {
+            panic!("The Gaussian filter works on floating point values");
+        };
+        assert_eq!(result[(5, 3)], 105.0 / u16::MAX as f32);
+
+        // Nothing left to filter
+        let m_scan = m_scan_with_dark_columns(&(0..10).collect::<Vec<_>>());
+        assert_eq!(filter.apply_gated(&m_scan, &gating), (m_scan.clone(), 10));
+    }
+
+    #[test]
+    fn bw_area_open_merges_blocks() {
+        // A line through all 4 blocks of 11 A scans, with a gap in the middle
+        // of a block, and a dot. The left part of the line has an area of 25.
+        let mut matrix = DMatrix::<u8>::zeros(6, 40);
+        for col in (0..40).filter(|&col| col != 25) {
+            matrix[(2, col)] = 1;
+        }
+        matrix[(4, 5)] = 1;
+
+        for connection_type in AreaConnectionType::VALUES {
+            let settings = BWareOpenSettings {
+                area: 20,
+                connection_type,
+            };
+            let result = bw_area_open_par(matrix.as_view(), &settings, 4);
+
+            let mut expected = matrix.clone();
+            expected[(4, 5)] = 0;
+            for col in 26..40 {
+                expected[(2, col)] = 0;
+            }
+            assert_eq!(result, expected, "{connection_type:?}");
+            assert_eq!(result, bw_area_open_par(matrix.as_view(), &settings, 1));
+        }
+
+        // A diagonal zigzag is only connected with Circle8, also across
+        // blocks. The blocks split it at each possible row offset. Only the
+        // two pixels at the step in the middle touch with Star4.
+        let mut matrix = DMatrix::<u8>::zeros(3, 40);
+        for col in 0..40 {
+            matrix[(col % 2 + col / 20, col)] = 1;
+        }
+
+        let settings = BWareOpenSettings {
+            area: 40,
+            connection_type: AreaConnectionType::Circle8,
+        };
+        for blocks in [1, 4, 7, 39] {
+            assert_eq!(
+                bw_area_open_par(matrix.as_view(), &settings, blocks),
+                matrix
+            );
+        }
+
+        let settings = BWareOpenSettings {
+            area: 3,
+            connection_type: AreaConnectionType::Star4,
+        };
+        let result = bw_area_open_par(matrix.as_view(), &settings, 4);
+        assert_eq!(result, DMatrix::zeros(3, 40));
+
+        // More blocks than A scans
+        let settings = BWareOpenSettings {
+            area: 3,
+            connection_type: AreaConnectionType::Star4,
+        };
+        let matrix = DMatrix::<f32>::from_element(1, 3, 1.0);
+        assert_eq!(bw_area_open_par(matrix.as_view(), &settings, 8), matrix);
+    }
+}
@@ -0,0 +1,392 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use futures::FutureExt;
+use nalgebra::{DMatrix, DMatrixView, Scalar};
+use num_traits::Zero;
+use rayon::prelude::*;
+
+use crate::{pipeline::types::DataMatrix, queue_channel::error::RecvError};
+
+use super::prelude::*;
+
+/// How samples shifted in from outside of an A scan are filled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FillMode {
+    #[default]
+    Zero,
+    /// Repeats the first or last sample of the A scan.
+    Edge,
+}
+
+impl FillMode {
+    pub const VALUES: [FillMode; 2] = [FillMode::Zero, FillMode::Edge];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    /// Row, the segmentation is moved to.
+    pub target_row: usize,
+    pub fill: FillMode,
+    /// Moves the segmentation from the target row back to its place, undoing
+    /// a flatten node with the same segmentation and target row.
+    pub invert: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            target_row: 50,
+            fill: FillMode::Zero,
+            invert: false,
+        }
+    }
+}
+
+pub enum InputId {
+    MScan,
+    Segmentation,
+}
+
+impl_enum_from_into_id_types!(InputId, [graph::InputId], {
+    0 => MScan,
+    1 => Segmentation,
+});
+
+// MARK: Node
+
+/// Shifts every A scan, so the segmentation, like the catheter or lumen
+/// surface, lies on one row. Filters then work along the surface instead of
+/// across it. The inverted node moves the A scans back.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Node {
+    pub settings: Settings,
+
+    pub m_scan: NodeInput<()>,
+    pub segmentation: NodeInput<()>,
+}
+
+deserialize_node!(Node, "flatten");
+
+impl Node {
+    pub fn flatten() -> Self {
+        Self::default()
+    }
+
+    pub fn unflatten() -> Self {
+        Self {
+            settings: Settings {
+                invert: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl PipelineNode for Node {
+    type InputId = InputId;
+    type OutputId = OutputIdSingle;
+
+    fn slug() -> &'static str {
+        "flatten"
+    }
+
+    fn inputs(
+        &self,
+    ) -> impl Iterator<Item = (<Self as PipelineNode>::InputId, Option<NodeOutput>)> {
+        [
+            (InputId::MScan, self.m_scan.connection()),
+            (InputId::Segmentation, self.segmentation.connection()),
+        ]
+        .into_iter()
+    }
+
+    fn changed(&self, other: &Self) -> bool {
+        self.settings != other.settings
+    }
+
+    fn get_output_id_for_view_request(&self) -> Option<(OutputIdSingle, impl Into<TypeId>)> {
+        Some((OutputIdSingle, PipelineDataType::MScan))
+    }
+
+    fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
+        let m_scan_out = builder.output(OutputIdSingle);
+
+        builder.task(Task {
+            settings: self.settings,
+            m_scan_out,
+            m_scan_in: TaskInput::default(),
+            segmentation_in: TaskInput::default(),
+        });
+    }
+}
+
+// MARK: Task
+
+struct Task {
+    settings: Settings,
+
+    m_scan_out: TaskOutput<requests::MScan>,
+    m_scan_in: TaskInput<requests::MScan>,
+    segmentation_in: TaskInput<requests::MScanSegmentation>,
+}
+
+impl NodeTask for Task {
+    type InputId = InputId;
+    type PipelineNode = Node;
+
+    fn connect(&mut self, input_id: Self::InputId, input: &mut ConnectionHandle) {
+        match input_id {
+            InputId::MScan => self.m_scan_in.connect(input),
+            InputId::Segmentation => self.segmentation_in.connect(input),
+        };
+    }
+
+    fn disconnect(&mut self, input_id: Self::InputId) {
+        match input_id {
+            InputId::MScan => self.m_scan_in.disconnect(),
+            InputId::Segmentation => self.segmentation_in.disconnect(),
+        };
+    }
+
+    fn sync_node(&mut self, node: &Self::PipelineNode) {
+        self.settings = node.settings;
+    }
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        let _req = self.m_scan_out.receive().await;
+
+        let (Some(m_scan_res), Some(segmentation_res)) = futures::join!(
+            self.m_scan_in.request(requests::MScan),
+            self.segmentation_in.request(requests::MScanSegmentation),
+        ) else {
+            return Ok(());
+        };
+
+        let (Some(mut m_scan), Some(mut segmentation)) = (
+            m_scan_res.data.subscribe(),
+            segmentation_res.data.subscribe(),
+        ) else {
+            return Ok(());
+        };
+
+        let (res, tx) = requests::StreamedResponse::with_default_capacity();
+
+        self.m_scan_out.respond(requests::MScanResponse {
+            data: res,
+            a_scan_count: m_scan_res.a_scan_count,
+            a_scan_samples: m_scan_res.a_scan_samples,
+        });
+        self.m_scan_out.receive().now_or_never();
+
+        let settings = self.settings;
+
+        // Segmentation of A scans, that have been received, but not used yet.
+        // The segmentation may be chunked differently than the M scan.
+        let mut pending_segmentation: Vec<u32> = Vec::new();
+
+        loop {
+            let m_scan = match m_scan.recv().await {
+                Ok(m_scan) => m_scan,
+                Err(RecvError::Closed) => break,
+                Err(e) => Err(e)?,
+            };
+
+            let ncols = m_scan.ncols();
+
+            while pending_segmentation.len() < ncols {
+                let segmentation = match segmentation.recv().await {
+                    Ok(segmentation) => segmentation,
+                    Err(RecvError::Closed) => break,
+                    Err(e) => Err(e)?,
+                };
+
+                pending_segmentation.extend(segmentation.iter().copied());
+            }
+
+            if pending_segmentation.len() < ncols {
+                return Err(anyhow!("Segmentation is shorter than the M scan"));
+            }
+
+            let segmentation: Vec<u32> = pending_segmentation.drain(..ncols).collect();
+
+            let m_scan: DataMatrix = priority::spawn_blocking(move || match m_scan.as_ref() {
+                DataMatrix::U8(m_scan) => {
+                    flatten(m_scan.as_view(), &segmentation, &settings).into()
+                }
+                DataMatrix::U16(m_scan) => {
+                    flatten(m_scan.as_view(), &segmentation, &settings).into()
+                }
+                DataMatrix::U32(m_scan) => {
+                    flatten(m_scan.as_view(), &segmentation, &settings).into()
+                }
+                DataMatrix::U64(m_scan) => {
+                    flatten(m_scan.as_view(), &segmentation, &settings).into()
+                }
+                DataMatrix::F32(m_scan) => {
+                    flatten(m_scan.as_view(), &segmentation, &settings).into()
+                }
+                DataMatrix::F64(m_scan) => {
+                    flatten(m_scan.as_view(), &segmentation, &settings).into()
+                }
+            })
+            .await?;
+
+            tx.send(Arc::new(m_scan));
+        }
+
+        tx.finish();
+
+        Ok(())
+    }
+}
+
+// MARK: Algorithm
+
+/// Number of rows an A scan moves down, so `segmentation` lands on
+/// `target_row`. Both directions use the same integer shift, so inverting is
+/// exact.
+fn shift(segmentation: u32, target_row: usize) -> isize {
+    target_row as isize - segmentation as isize
+}
+
+fn flatten<T>(m_scan: DMatrixView<T>, segmentation: &[u32], st: &Settings) -> DMatrix<T>
+where
+    T: Scalar + Copy + Zero + Send + Sync,
+{
+    let mut result = m_scan.clone_owned();
+    let nrows = m_scan.nrows() as isize;
+
+    result
+        .par_column_iter_mut()
+        .zip(m_scan.par_column_iter())
+        .zip(segmentation.par_iter())
+        .for_each(|((mut a_scan, source), &segmentation)| {
+            let shift = match st.invert {
+                false => shift(segmentation, st.target_row),
+                true => -shift(segmentation, st.target_row),
+            };
+
+            for (row, value) in a_scan.iter_mut().enumerate() {
+                let from = row as isize - shift;
+                *value = match (from, st.fill) {
+                    (0.., _) if from < nrows => source[from as usize],
+                    (_, FillMode::Zero) => T::zero(),
+                    (..0, FillMode::Edge) => source[0],
+                    (_, FillMode::Edge) => source[nrows as usize - 1],
+                };
+            }
+        });
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::Vector2;
+
+    use crate::pipeline::nodes::filter;
+
+    use super::*;
+
+    /// Bright tissue below a curved surface.
+    fn synthetic_m_scan() -> (DMatrix<f32>, Vec<u32>) {
+        let surface: Vec<u32> = (0..32)
+            .map(|col| (20.0 + 12.0 * (col as f32 * 0.4).sin()) as u32)
+            .collect();
+
+        let m_scan = DMatrix::from_fn(64, surface.len(), |row, col| {
+            if row < surface[col] as usize {
+                0.0
+            } else {
+                1.0 - (row - surface[col] as usize) as f32 * 0.01
+            }
+        });
+
+        (m_scan, surface)
+    }
+
+    /// Rows of every column, that flattening keeps inside the A scan.
+    fn kept_rows(surface: &[u32], st: &Settings, nrows: usize) -> Vec<Vec<usize>> {
+        surface
+            .iter()
+            .map(|&segmentation| {
+                let shift = shift(segmentation, st.target_row);
+                (0..nrows)
+                    .filter(|&row| (0..nrows as isize).contains(&(row as isize + shift)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unflatten_restores() {
+        let (m_scan, surface) = synthetic_m_scan();
+
+        for fill in FillMode::VALUES {
+            let st = Settings {
+                target_row: 10,
+                fill,
+                invert: false,
+            };
+            let inverse = Settings { invert: true, ..st };
+
+            let flat = flatten(m_scan.as_view(), &surface, &st);
+            for col in 0..surface.len() {
+                assert_eq!(flat[(10, col)], 1.0);
+                assert_eq!(flat[(9, col)], 0.0);
+            }
+
+            let restored = flatten(flat.as_view(), &surface, &inverse);
+            for (col, rows) in kept_rows(&surface, &st, 64).iter().enumerate() {
+                for &row in rows {
+                    assert_eq!(restored[(row, col)], m_scan[(row, col)]);
+                }
+            }
+        }
+
+        // Edge replication keeps the lumen dark
+        let st = Settings {
+            target_row: 40,
+            fill: FillMode::Edge,
+            invert: false,
+        };
+        let flat = flatten(m_scan.as_view(), &surface, &st);
+        assert!(flat.rows(0, 40).iter().all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn median_does_not_bleed_across_surface() {
+        let (m_scan, surface) = synthetic_m_scan();
+        let st = Settings {
+            target_row: 10,
+            ..Default::default()
+        };
+        let inverse = Settings { invert: true, ..st };
+
+        // Across 5 A scans, along the rows
+        let median = |m: DMatrixView<f32>| filter::compute_median_par(m, Vector2::new(5, 1));
+
+        // Directly, the curved surface gets smeared
+        let direct = median(m_scan.as_view());
+        assert_ne!(direct, m_scan);
+
+        let flat = flatten(m_scan.as_view(), &surface, &st);
+        let restored = flatten(median(flat.as_view()).as_view(), &surface, &inverse);
+
+        // Below this row of the flattened M scan, some A scans are filled
+        // with zeros, which the median mixes in
+        let fill_start = 64 + 10 - *surface.iter().max().unwrap() as isize;
+
+        for (col, rows) in kept_rows(&surface, &st, 64).iter().enumerate() {
+            let shift = shift(surface[col], st.target_row);
+            for &row in rows
+                .iter()
+                .filter(|&&row| (row as isize + shift) < fill_start)
+            {
+                assert_eq!(restored[(row, col)], m_scan[(row, col)]);
+            }
+        }
+    }
+}
@@ -5,14 +5,18 @@ use std::{
 };
 
 use futures::FutureExt;
-use nalgebra::{DMatrixView, DVector};
+use nalgebra::{DMatrix, DMatrixView, DVector};
 use num_traits::Zero;
 
-use crate::{pipeline::types::DataMatrix, queue_channel::error::RecvError};
+use crate::{
+    pipeline::types::{DataMatrix, DataType},
+    queue_channel::error::RecvError,
+};
 
 use super::prelude::*;
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     /// A point somewhere above the catheter. This point is the starting point
     /// to find the catheter.
@@ -23,6 +27,9 @@ pub struct Settings {
     pub smoothing_window: usize,
     /// Used to determine which pixel is considered to be white.
     pub threshold: f64,
+    /// How many rows below the catheter line are still part of the catheter
+    /// mask.
+    pub mask_margin: u32,
 }
 
 impl Default for Settings {
@@ -32,6 +39,7 @@ impl Default for Settings {
             window_extend: 7,
             smoothing_window: 1000,
             threshold: 0.2,
+            mask_margin: 10,
         }
     }
 }
@@ -46,6 +54,16 @@ impl_enum_from_into_id_types!(InputId, [graph::InputId], {
     1 => BScanSegmentation,
 });
 
+pub enum OutputId {
+    Segmentation,
+    Mask,
+}
+
+impl_enum_from_into_id_types!(OutputId, [graph::OutputId], {
+    0 => Segmentation,
+    1 => Mask,
+});
+
 // MARK: Node
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -58,9 +76,25 @@ pub struct Node {
 
 deserialize_node!(Node, "follow_catheter");
 
+impl ThresholdTarget for Node {
+    fn thresholds(&self) -> Vec<Threshold> {
+        vec![Threshold {
+            name: "Seg Threshold",
+            value: self.settings.threshold as f32,
+            scale: ThresholdScale::Value,
+        }]
+    }
+
+    fn set_threshold(&mut self, index: usize, value: f32) {
+        if index == 0 {
+            self.settings.threshold = (value as f64).clamp(0.0, 2.0);
+        }
+    }
+}
+
 impl PipelineNode for Node {
     type InputId = InputId;
-    type OutputId = OutputIdSingle;
+    type OutputId = OutputId;
 
     fn slug() -> &'static str {
         "follow_catheter"
@@ -83,18 +117,57 @@ impl PipelineNode for Node {
         self.settings != other.settings
     }
 
-    fn get_output_id_for_view_request(&self) -> Option<(OutputIdSingle, impl Into<TypeId>)> {
-        Some((OutputIdSingle, PipelineDataType::MScanSegmentation))
+    fn get_output_id_for_view_request(&self) -> Option<(OutputId, impl Into<TypeId>)> {
+        Some((OutputId::Segmentation, PipelineDataType::MScanSegmentation))
+    }
+
+    fn estimate_memory(&self, upstream: &UpstreamStats) -> MemoryEstimate {
+        let mut estimate = MemoryEstimate::streaming(upstream, upstream.per_a_scan(DataType::F32));
+        // The mask has the size of the M scan
+        estimate.per_chunk +=
+            upstream.per_a_scan(DataType::U8).chunk_bytes() * upstream.samples as u64;
+        estimate
+    }
+
+    fn threshold_target(&self) -> Option<&dyn ThresholdTarget> {
+        Some(self)
+    }
+
+    fn threshold_target_mut(&mut self) -> Option<&mut dyn ThresholdTarget> {
+        Some(self)
+    }
+
+    fn validate(&mut self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        let settings = &mut self.settings;
+
+        validation::at_least(
+            &mut issues,
+            "Smoothing Size",
+            &mut settings.smoothing_window,
+            1,
+        );
+        validation::clamp(
+            &mut issues,
+            "Seg Threshold",
+            &mut settings.threshold,
+            0.0..=2.0,
+        );
+
+        issues
     }
 
     fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
-        let segmentation_out = builder.output(OutputIdSingle);
+        let segmentation_out = builder.output(OutputId::Segmentation);
+        let mask_out = builder.output(OutputId::Mask);
 
         builder.task(Task {
             settings: self.settings,
             segmentation_out,
+            mask_out,
             m_scan_in: TaskInput::default(),
             b_scan_segmentation_in: TaskInput::default(),
+            catheter_lines: None,
         });
     }
 }
@@ -105,8 +178,19 @@ struct Task {
     settings: Settings,
 
     segmentation_out: TaskOutput<requests::MScanSegmentation>,
+    mask_out: TaskOutput<requests::MScan>,
     m_scan_in: TaskInput<requests::MScan>,
     b_scan_segmentation_in: TaskInput<requests::BScanSegmentation>,
+
+    /// Catheter lines of the last complete run, a later request for the mask
+    /// is answered from.
+    catheter_lines: Option<CatheterLines>,
+}
+
+struct CatheterLines {
+    chunks: Vec<Arc<DVector<u32>>>,
+    a_scan_count: usize,
+    a_scan_samples: usize,
 }
 
 impl NodeTask for Task {
@@ -131,8 +215,27 @@ impl NodeTask for Task {
         self.settings = node.settings;
     }
 
+    fn invalidate(&mut self, _cause: InvalidationCause) {
+        self.catheter_lines = None;
+    }
+
     async fn run(&mut self) -> anyhow::Result<()> {
-        let _req = self.segmentation_out.receive().await;
+        tokio::select! {
+            _req = self.segmentation_out.receive() => {}
+            _req = self.mask_out.receive() => {}
+        }
+
+        // Both outputs may have been requested at the same time
+        let segmentation_requested = self.segmentation_out.receive().now_or_never().is_some();
+        let mask_requested = self.mask_out.receive().now_or_never().is_some();
+
+        if mask_requested && !segmentation_requested {
+            if let Some(catheter_lines) = self.catheter_lines.take() {
+                let res = self.respond_mask(&catheter_lines).await;
+                self.catheter_lines = Some(catheter_lines);
+                return res;
+            }
+        }
 
         let (Some(m_scan_res), Some(b_scan_segmentation_res)) = futures::join!(
             self.m_scan_in.request(requests::MScan),
@@ -144,15 +247,32 @@ impl NodeTask for Task {
 
         let (Some(mut m_scan), Some(mut b_scan_segmentation)) = (
             m_scan_res.data.subscribe(),
-            b_scan_segmentation_res.subscribe(),
+            b_scan_segmentation_res.data.subscribe(),
         ) else {
             return Ok(());
         };
 
-        let (res, tx) = requests::StreamedResponse::new(100);
+        let (res, tx) = requests::StreamedResponse::with_default_capacity();
+        let (mask_res, mask_tx) = requests::StreamedResponse::with_default_capacity();
+
+        if segmentation_requested {
+            self.segmentation_out
+                .respond(requests::MScanSegmentationResponse {
+                    data: res,
+                    a_scan_count: m_scan_res.a_scan_count,
+                    a_scan_samples: m_scan_res.a_scan_samples,
+                });
+            self.segmentation_out.receive().now_or_never();
+        }
 
-        self.segmentation_out.respond(res);
-        self.segmentation_out.receive().now_or_never();
+        if mask_requested {
+            self.mask_out.respond(requests::MScanResponse {
+                data: mask_res,
+                a_scan_samples: m_scan_res.a_scan_samples,
+                a_scan_count: m_scan_res.a_scan_count,
+            });
+            self.mask_out.receive().now_or_never();
+        }
 
         let settings = self.settings;
 
@@ -164,6 +284,8 @@ impl NodeTask for Task {
 
         let segmentation = Arc::new(Mutex::new(Vec::new()));
 
+        let mut catheter_lines = Vec::new();
+
         loop {
             let m_scan = match m_scan.recv().await {
                 Ok(m_scan) => m_scan,
@@ -211,10 +333,10 @@ impl NodeTask for Task {
 
             let segmentation = segmentation.clone();
 
-            let catheter_line = tokio::task::spawn_blocking(move || {
+            let (catheter_line, mask) = priority::spawn_blocking(move || {
                 let mut segmentation = segmentation.lock().unwrap();
 
-                match m_scan.as_ref() {
+                let catheter_line = match m_scan.as_ref() {
                     DataMatrix::U8(m_scan) => follow_catheter(
                         m_scan.as_view(),
                         segmentation.as_mut(),
@@ -263,7 +385,12 @@ impl NodeTask for Task {
                         &b_scans,
                         &settings,
                     ),
-                }
+                };
+
+                let mask = mask_requested
+                    .then(|| catheter_mask(&catheter_line, m_scan.nrows(), settings.mask_margin));
+
+                (catheter_line, mask)
             })
             .await?;
 
@@ -271,9 +398,55 @@ impl NodeTask for Task {
 
             processed_a_scans += m_scan_count;
 
-            tx.send(Arc::new(catheter_line));
+            if let Some(mask) = mask {
+                mask_tx.send(Arc::new(DataMatrix::U8(mask)));
+            }
+            let catheter_line = Arc::new(catheter_line);
+            catheter_lines.push(catheter_line.clone());
+            tx.send(catheter_line);
+        }
+
+        tx.finish();
+        mask_tx.finish();
+
+        self.catheter_lines = Some(CatheterLines {
+            chunks: catheter_lines,
+            a_scan_count: m_scan_res.a_scan_count,
+            a_scan_samples: m_scan_res.a_scan_samples,
+        });
+
+        Ok(())
+    }
+}
+
+impl Task {
+    /// Answers a request for the mask from the catheter lines of an earlier
+    /// run, instead of following the catheter again.
+    async fn respond_mask(&mut self, catheter_lines: &CatheterLines) -> anyhow::Result<()> {
+        let (mask_res, mask_tx) = requests::StreamedResponse::with_default_capacity();
+
+        self.mask_out.respond(requests::MScanResponse {
+            data: mask_res,
+            a_scan_samples: catheter_lines.a_scan_samples,
+            a_scan_count: catheter_lines.a_scan_count,
+        });
+        self.mask_out.receive().now_or_never();
+
+        let nrows = catheter_lines.a_scan_samples;
+        let margin = self.settings.mask_margin;
+
+        for catheter_line in &catheter_lines.chunks {
+            let catheter_line = catheter_line.clone();
+
+            let mask =
+                priority::spawn_blocking(move || catheter_mask(&catheter_line, nrows, margin))
+                    .await?;
+
+            mask_tx.send(Arc::new(DataMatrix::U8(mask)));
         }
 
+        mask_tx.finish();
+
         Ok(())
     }
 }
@@ -458,6 +631,183 @@ where
     catheter_line
 }
 
+// MARK: Catheter mask
+
+/// Creates a mask of the same size as the M scan chunk the `catheter_line`
+/// was found in. Every pixel above the catheter line plus `margin` is 1, every
+/// other pixel is 0.
+fn catheter_mask(catheter_line: &DVector<u32>, nrows: usize, margin: u32) -> DMatrix<u8> {
+    DMatrix::from_fn(nrows, catheter_line.len(), |row, col| {
+        (row <= catheter_line[col].saturating_add(margin) as usize) as u8
+    })
+}
+
 fn hann(x: f64) -> f64 {
     0.5 * (1.0 - (2.0 * std::f64::consts::PI * x).cos())
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use tokio::sync::Semaphore;
+
+    use crate::pipeline::PipelineExecutor;
+
+    use super::*;
+
+    /// Serves an M scan of 40 A scans with a bright catheter at row 20, in
+    /// chunks of 10 A scans, and counts the requests. The stream holds only 2
+    /// chunks, so the response can not be replayed. Every further chunk is
+    /// sent, once a permit is added to `consumed`.
+    fn spawn_m_scan_producer(
+        requests: Arc<AtomicUsize>,
+        consumed: Arc<Semaphore>,
+    ) -> ConnectionHandle {
+        let (handle, mut output) = ConnectionHandle::new::<requests::MScan>();
+
+        tokio::spawn(async move {
+            loop {
+                let _req = output.receive().await;
+                requests.fetch_add(1, Ordering::Relaxed);
+
+                let (res, tx) = requests::StreamedResponse::new(2);
+                output.respond(requests::MScanResponse {
+                    data: res,
+                    a_scan_count: 40,
+                    a_scan_samples: 64,
+                });
+
+                for i in 0..4 {
+                    if i >= 2 {
+                        consumed.acquire().await.unwrap().forget();
+                    }
+                    let chunk = DMatrix::from_fn(64, 10, |r, _| (r == 20) as u8 as f32);
+                    tx.send(Arc::new(chunk.into()));
+                }
+                tx.finish();
+            }
+        });
+
+        handle
+    }
+
+    fn spawn_b_scan_producer() -> ConnectionHandle {
+        let (handle, mut output) = ConnectionHandle::new::<requests::BScanSegmentation>();
+
+        tokio::spawn(async move {
+            loop {
+                let _req = output.receive().await;
+
+                let (res, tx) = requests::StreamedResponse::new(64);
+                output.respond(requests::BScanSegmentationResponse {
+                    data: res,
+                    a_scan_count: 40,
+                });
+
+                for b_scan in [0, 20, 40] {
+                    tx.send(b_scan);
+                }
+                tx.finish();
+            }
+        });
+
+        handle
+    }
+
+    async fn collect<T: Clone>(data: &requests::StreamedResponse<T>) -> Vec<T> {
+        let mut rx = data.subscribe().unwrap();
+        let mut chunks = Vec::new();
+        loop {
+            match rx.recv().await {
+                Ok(chunk) => chunks.push(chunk),
+                Err(RecvError::Closed) => break chunks,
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn mask_from_segmentation() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let consumed = Arc::new(Semaphore::new(0));
+        let executor = PipelineExecutor::new();
+
+        let mut node = Node::default();
+        let margin = node.settings.mask_margin;
+
+        let mut runner = executor.spawn_ephemeral(&mut node);
+        runner.connect_input(
+            InputId::MScan.into(),
+            spawn_m_scan_producer(requests.clone(), consumed.clone()),
+        );
+        runner.connect_input(InputId::BScanSegmentation.into(), spawn_b_scan_producer());
+
+        let mut segmentation = TaskInput::<requests::MScanSegmentation>::default();
+        let mut mask = TaskInput::<requests::MScan>::default();
+        assert!(
+            segmentation.connect(&mut runner.get_output(OutputId::Segmentation.into()).unwrap())
+        );
+        assert!(mask.connect(&mut runner.get_output(OutputId::Mask.into()).unwrap()));
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            let segmentation = segmentation
+                .request(requests::MScanSegmentation)
+                .await
+                .unwrap();
+
+            // Every catheter line is sent after its M scan chunk was received
+            let mut rx = segmentation.data.subscribe().unwrap();
+            let mut catheter_lines = Vec::new();
+            loop {
+                match rx.recv().await {
+                    Ok(catheter_line) => catheter_lines.push(catheter_line),
+                    Err(RecvError::Closed) => break,
+                    Err(e) => panic!("{:?}", e),
+                }
+                consumed.add_permits(1);
+            }
+
+            let mask = mask.request(requests::MScan).await.unwrap();
+            assert_eq!((mask.a_scan_count, mask.a_scan_samples), (40, 64));
+            let masks = collect(&mask.data).await;
+
+            assert_eq!(catheter_lines.len(), 4);
+            assert_eq!(masks.len(), 4);
+            for (catheter_line, mask) in catheter_lines.iter().zip(&masks) {
+                assert!(catheter_line.iter().all(|&row| row == 20));
+                assert_eq!(
+                    **mask,
+                    DataMatrix::U8(catheter_mask(catheter_line, 64, margin))
+                );
+            }
+        })
+        .await
+        .expect("Follow catheter should finish");
+
+        // The mask was answered from the segmentation
+        assert_eq!(requests.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn mask_below_catheter_line() {
+        let mask = catheter_mask(&DVector::from_vec(vec![2, 0, 4]), 6, 1);
+
+        let expected = DMatrix::from_row_slice(
+            6,
+            3,
+            &[
+                1, 1, 1, //
+                1, 1, 1, //
+                1, 0, 1, //
+                1, 0, 1, //
+                0, 0, 1, //
+                0, 0, 1, //
+            ],
+        );
+        assert_eq!(mask, expected);
+    }
+}
@@ -7,8 +7,12 @@ use std::{
 use futures::FutureExt;
 use nalgebra::{DMatrixView, DVector};
 use num_traits::Zero;
+use rayon::prelude::*;
 
-use crate::{pipeline::types::DataMatrix, queue_channel::error::RecvError};
+use crate::{
+    pipeline::types::{DataMatrix, DataType},
+    queue_channel::error::RecvError,
+};
 
 use super::prelude::*;
 
@@ -21,6 +25,11 @@ pub struct Settings {
     /// regions.
     pub check_artifact: bool,
     pub artifact_threshold: f64,
+    /// Whether to follow blocks of columns in parallel, see
+    /// [follow_columns_parallel]. The result can differ slightly from the
+    /// sequential one.
+    #[serde(default)]
+    pub parallel: bool,
 }
 
 impl Default for Settings {
@@ -31,6 +40,7 @@ impl Default for Settings {
             threshold: 0.2,
             check_artifact: true,
             artifact_threshold: 0.4,
+            parallel: false,
         }
     }
 }
@@ -57,6 +67,33 @@ pub struct Node {
 
 deserialize_node!(Node, "follow_lumen");
 
+impl ThresholdTarget for Node {
+    fn thresholds(&self) -> Vec<Threshold> {
+        let mut thresholds = vec![Threshold {
+            name: "Threshold",
+            value: self.settings.threshold as f32,
+            scale: ThresholdScale::Value,
+        }];
+        if self.settings.check_artifact {
+            thresholds.push(Threshold {
+                name: "Artifact Threshold",
+                value: self.settings.artifact_threshold as f32,
+                scale: ThresholdScale::Value,
+            });
+        }
+        thresholds
+    }
+
+    fn set_threshold(&mut self, index: usize, value: f32) {
+        let value = (value as f64).clamp(0.0, 1.0);
+        match index {
+            0 => self.settings.threshold = value,
+            1 if self.settings.check_artifact => self.settings.artifact_threshold = value,
+            _ => {}
+        }
+    }
+}
+
 impl PipelineNode for Node {
     type InputId = InputId;
     type OutputId = OutputIdSingle;
@@ -86,6 +123,31 @@ impl PipelineNode for Node {
         Some((OutputIdSingle, PipelineDataType::MScanSegmentation))
     }
 
+    fn estimate_memory(&self, upstream: &UpstreamStats) -> MemoryEstimate {
+        MemoryEstimate::streaming(upstream, upstream.per_a_scan(DataType::F32))
+    }
+
+    fn threshold_target(&self) -> Option<&dyn ThresholdTarget> {
+        Some(self)
+    }
+
+    fn threshold_target_mut(&mut self) -> Option<&mut dyn ThresholdTarget> {
+        Some(self)
+    }
+
+    fn validate(&mut self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        let settings = &mut self.settings;
+
+        validation::clamp(&mut issues, "Threshold", &mut settings.threshold, 0.0..=1.0);
+        if settings.check_artifact {
+            let threshold = &mut settings.artifact_threshold;
+            validation::clamp(&mut issues, "Artifact Threshold", threshold, 0.0..=1.0);
+        }
+
+        issues
+    }
+
     fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
         let segmentation_out = builder.output(OutputIdSingle);
 
@@ -143,14 +205,19 @@ impl NodeTask for Task {
 
         let (Some(mut m_scan), Some(mut catheter_segmentation)) = (
             m_scan_res.data.subscribe(),
-            catheter_segmentation_res.subscribe(),
+            catheter_segmentation_res.data.subscribe(),
         ) else {
             return Ok(());
         };
 
-        let (res, tx) = requests::StreamedResponse::new(100);
+        let (res, tx) = requests::StreamedResponse::with_default_capacity();
 
-        self.segmentation_out.respond(res);
+        self.segmentation_out
+            .respond(requests::MScanSegmentationResponse {
+                data: res,
+                a_scan_count: m_scan_res.a_scan_count,
+                a_scan_samples: m_scan_res.a_scan_samples,
+            });
         self.segmentation_out.receive().now_or_never();
 
         let settings = self.settings;
@@ -220,7 +287,7 @@ impl NodeTask for Task {
 
             let catheter_seg = catheter_seg.clone();
 
-            let end_height = tokio::task::spawn_blocking({
+            let end_height = priority::spawn_blocking({
                 let shared = shared.clone();
 
                 move || {
@@ -299,7 +366,9 @@ impl NodeTask for Task {
                 .get(chunks_send)
                 .map_or(false, |&end| shared.interpolated_til >= end)
             {
-                let chunk_start = chunk_ends.get(chunks_send - 1).copied().unwrap_or(0);
+                let chunk_start = chunks_send
+                    .checked_sub(1)
+                    .map_or(0, |previous| chunk_ends[previous]);
                 let lumen_line = &shared.lumen_from_start[chunk_start..chunk_ends[chunks_send]];
 
                 tx.send(Arc::new(DVector::from_column_slice(lumen_line)));
@@ -316,12 +385,16 @@ impl NodeTask for Task {
         let shared = shared.deref_mut();
 
         if shared.interpolated_til < processed_a_scans {
-            let chunk_start = chunk_ends.get(chunks_send - 1).copied().unwrap_or(0);
+            let chunk_start = chunks_send
+                .checked_sub(1)
+                .map_or(0, |previous| chunk_ends[previous]);
             let lumen_line = &shared.lumen_from_start[chunk_start..];
 
             tx.send(Arc::new(DVector::from_column_slice(lumen_line)));
         }
 
+        tx.finish();
+
         Ok(())
     }
 }
@@ -368,6 +441,16 @@ where
 
 // MARK: Follow lumen
 
+/// Minimum number of columns per block, when following in parallel.
+const BLOCK_SIZE: usize = 1024;
+
+/// Maximum number of columns after a seam, that are followed again to join
+/// the blocks.
+const SEAM_WINDOW: usize = 128;
+
+/// Number of columns averaged to seed the start height of a block.
+const SEED_COLUMNS: usize = 8;
+
 fn follow_lumen<T>(
     m_scan: DMatrixView<T>,
     catheter_seg: Vec<u32>,
@@ -384,71 +467,250 @@ where
         + Sum
         + Sub<Output = T>
         + Mul<Output = T>
-        + num_traits::NumCast,
+        + num_traits::NumCast
+        + Send
+        + Sync,
 {
+    let catheter_seg = &catheter_seg[m_scan_offset..];
+
     let mut lumen_line = DVector::zeros(m_scan.ncols());
 
+    let end_height = if st.parallel {
+        follow_columns_parallel(
+            m_scan,
+            catheter_seg,
+            start_height,
+            lumen_line.as_mut_slice(),
+            st,
+        )
+    } else {
+        follow_columns(
+            m_scan,
+            catheter_seg,
+            start_height,
+            0,
+            lumen_line.as_mut_slice(),
+            st,
+        )
+    };
+
+    if st.check_artifact {
+        remove_artifacts(m_scan, catheter_seg, &mut lumen_line, st);
+    }
+
+    (lumen_line, end_height)
+}
+
+/// Follows the lumen through the columns starting at `first_column`, writing
+/// one height per column into `lumen_line`. Each search window depends on the
+/// height found in the previous column. Columns without a hit get
+/// [u32::MAX] and are interpolated later. Returns the height after the last
+/// column.
+fn follow_columns<T>(
+    m_scan: DMatrixView<T>,
+    catheter_seg: &[u32],
+    start_height: u32,
+    first_column: usize,
+    lumen_line: &mut [u32],
+    st: &Settings,
+) -> u32
+where
+    T: nalgebra::Scalar + Copy + num_traits::NumCast,
+{
     let mut height = start_height as usize;
 
-    for i in 0..m_scan.ncols() {
-        // window_start is clamped with the catheter
-        let window_start = height
-            .saturating_sub(st.window_extend_up)
-            .max(catheter_seg[m_scan_offset + i] as usize);
-        let window_end = (height + st.window_extend_down).min(m_scan.nrows() - 1);
-
-        let window = m_scan.get((window_start..=window_end, i)).unwrap();
-
-        // Search for the next pixel upwards. The search window is multiplied
-        // with a hann window
-        let mut max_index = usize::MAX;
-        for (i, value) in window.iter().copied().enumerate() {
-            let value = value.to_f64().unwrap()
-                * hann(i as f64 / ((st.window_extend_up + st.window_extend_down + 1) as f64));
-            if value > st.threshold {
-                max_index = i;
-                break;
+    for (offset, lumen) in lumen_line.iter_mut().enumerate() {
+        match follow_column(m_scan, catheter_seg, height, first_column + offset, st) {
+            Some(found) => {
+                height = found;
+                *lumen = height as u32;
             }
+            None => *lumen = u32::MAX,
         }
+    }
 
-        if max_index != usize::MAX {
-            height = (window_start + max_index).min(m_scan.nrows() - 1);
+    height as u32
+}
 
-            lumen_line[i] = height as u32;
-        } else {
-            // Will be interpolated later
-            lumen_line[i] = u32::MAX;
-        }
-    }
+/// Searches the lumen in `column`, in a window around the height of the
+/// previous column.
+fn follow_column<T>(
+    m_scan: DMatrixView<T>,
+    catheter_seg: &[u32],
+    height: usize,
+    column: usize,
+    st: &Settings,
+) -> Option<usize>
+where
+    T: nalgebra::Scalar + Copy + num_traits::NumCast,
+{
+    // window_start is clamped with the catheter
+    let window_start = height
+        .saturating_sub(st.window_extend_up)
+        .max(catheter_seg[column] as usize);
+    let window_end = (height + st.window_extend_down).min(m_scan.nrows() - 1);
 
-    let end_height = height as u32;
+    let window = m_scan.get((window_start..=window_end, column))?;
 
-    if st.check_artifact {
-        // Remove where there is an artifact above
-        for i in 0..m_scan.ncols() {
-            if lumen_line[i] == u32::MAX {
-                continue;
-            }
+    // Search for the next pixel upwards. The search window is multiplied
+    // with a hann window
+    window
+        .iter()
+        .copied()
+        .enumerate()
+        .find(|(i, value)| {
+            let value = value.to_f64().unwrap()
+                * hann(*i as f64 / ((st.window_extend_up + st.window_extend_down + 1) as f64));
+            value > st.threshold
+        })
+        .map(|(i, _)| (window_start + i).min(m_scan.nrows() - 1))
+}
 
-            let window_start = (catheter_seg[m_scan_offset + i] + 10).min(lumen_line[i]) as usize;
-            let Some(window) = m_scan.get((window_start..lumen_line[i] as usize, i)) else {
-                continue;
+/// Parallel version of [follow_columns]:
+///
+/// 1. The columns are split into blocks. Every block but the first is seeded
+///    with [seed_height], a coarse guess independent of the previous block.
+/// 2. The blocks are followed in parallel.
+/// 3. Sequentially, the columns after each seam are followed again, starting
+///    from the end of the previous block, until both paths meet. From there
+///    on, both paths are identical, as the search only depends on the
+///    previous height. Paths, that do not meet within [SEAM_WINDOW] columns,
+///    keep the result of their block.
+///
+/// Blocks, that are joined within the window, give the same result as the
+/// sequential version.
+fn follow_columns_parallel<T>(
+    m_scan: DMatrixView<T>,
+    catheter_seg: &[u32],
+    start_height: u32,
+    lumen_line: &mut [u32],
+    st: &Settings,
+) -> u32
+where
+    T: nalgebra::Scalar + Copy + num_traits::NumCast + Send + Sync,
+{
+    let ncols = lumen_line.len();
+    let block_count = (ncols / BLOCK_SIZE).max(1);
+    let block_size = ncols.div_ceil(block_count).max(1);
+
+    let end_heights = lumen_line
+        .par_chunks_mut(block_size)
+        .enumerate()
+        .map(|(block, lumen_line)| {
+            let first_column = block * block_size;
+            let start_height = match block {
+                0 => start_height,
+                _ => seed_height(m_scan, catheter_seg, first_column),
             };
 
-            if window.len() < 5 {
-                continue;
+            follow_columns(
+                m_scan,
+                catheter_seg,
+                start_height,
+                first_column,
+                lumen_line,
+                st,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut end_height = end_heights[0];
+
+    for (block, &block_end_height) in end_heights.iter().enumerate().skip(1) {
+        let first_column = block * block_size;
+        let block_end = (first_column + block_size).min(ncols);
+
+        let mut height = end_height as usize;
+        let mut joined = false;
+
+        let seam = first_column..(first_column + SEAM_WINDOW).min(block_end);
+        for (lumen, column) in lumen_line[seam.clone()].iter_mut().zip(seam) {
+            let found = follow_column(m_scan, catheter_seg, height, column, st);
+
+            // The block found the same height, so it continues identically
+            if found.is_some_and(|found| *lumen == found as u32) {
+                joined = true;
+                break;
             }
 
-            for &value in window.iter() {
-                if value.to_f64().unwrap() > st.artifact_threshold {
-                    lumen_line[i] = u32::MAX;
-                    break;
+            match found {
+                Some(found) => {
+                    height = found;
+                    *lumen = found as u32;
                 }
+                None => *lumen = u32::MAX,
             }
         }
+
+        end_height = match joined || block_end - first_column > SEAM_WINDOW {
+            true => block_end_height,
+            // The whole block was followed again
+            false => height as u32,
+        };
     }
 
-    (lumen_line, end_height)
+    end_height
+}
+
+/// Coarse start height for a block starting at `column`: The row with the
+/// largest increase of the values averaged over [SEED_COLUMNS] columns,
+/// between the catheter and the bottom.
+fn seed_height<T>(m_scan: DMatrixView<T>, catheter_seg: &[u32], column: usize) -> u32
+where
+    T: nalgebra::Scalar + Copy + num_traits::NumCast,
+{
+    let columns = column..(column + SEED_COLUMNS).min(m_scan.ncols());
+    let top = catheter_seg[column] as usize;
+
+    let mean = |row: usize| {
+        columns
+            .clone()
+            .map(|c| m_scan[(row, c)].to_f64().unwrap())
+            .sum::<f64>()
+    };
+
+    (top..m_scan.nrows().saturating_sub(1))
+        .map(|row| (row + 1, mean(row + 1) - mean(row)))
+        .fold((top, f64::NEG_INFINITY), |max, (row, gradient)| {
+            if gradient > max.1 {
+                (row, gradient)
+            } else {
+                max
+            }
+        })
+        .0 as u32
+}
+
+/// Removes heights with an artifact above them, so they get interpolated.
+fn remove_artifacts<T>(
+    m_scan: DMatrixView<T>,
+    catheter_seg: &[u32],
+    lumen_line: &mut DVector<u32>,
+    st: &Settings,
+) where
+    T: nalgebra::Scalar + Copy + num_traits::NumCast,
+{
+    for i in 0..m_scan.ncols() {
+        if lumen_line[i] == u32::MAX {
+            continue;
+        }
+
+        let window_start = (catheter_seg[i] + 10).min(lumen_line[i]) as usize;
+        let Some(window) = m_scan.get((window_start..lumen_line[i] as usize, i)) else {
+            continue;
+        };
+
+        if window.len() < 5 {
+            continue;
+        }
+
+        for &value in window.iter() {
+            if value.to_f64().unwrap() > st.artifact_threshold {
+                lumen_line[i] = u32::MAX;
+                break;
+            }
+        }
+    }
 }
 
 fn hann(x: f64) -> f64 {
@@ -499,3 +761,106 @@ fn interpolate_lumen(lumen: &mut Vec<u32>, til: &mut usize) {
 
     *til = lumen.len();
 }
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::TAU;
+
+    use nalgebra::DMatrix;
+
+    use crate::pipeline::random::Lcg;
+
+    use super::*;
+
+    /// Columns without any tissue, so every start height is as good as any
+    /// other.
+    const GAP: std::ops::Range<usize> = 3550..3650;
+    /// Columns with a thin bright layer above the lumen border.
+    const LAYER: std::ops::Range<usize> = 4700..5000;
+
+    /// A lumen border following a sine, with noise, a gap and a layer around
+    /// the seams of the blocks.
+    fn synthetic_m_scan() -> DMatrix<f64> {
+        let mut rng = Lcg::new(0x5eed);
+        let mut noise = move || rng.uniform();
+
+        DMatrix::from_fn(256, 6000, |row, column| {
+            let border = (120.0 + 40.0 * (column as f64 * TAU / 1500.0).sin()) as usize;
+
+            let tissue = row >= border && !GAP.contains(&column);
+            let layer = LAYER.contains(&column) && (border - 30..border - 27).contains(&row);
+
+            match tissue || layer {
+                true => 0.8 + noise() * 0.1,
+                // Below the threshold of the test, even at the peak of the
+                // hann window
+                false => noise() * 0.01,
+            }
+        })
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let m_scan = synthetic_m_scan();
+        let catheter_seg = vec![20; m_scan.ncols()];
+        let start_height = find_start_height(m_scan.as_view(), catheter_seg[0]);
+
+        let follow = |parallel| {
+            // Low enough, that the border is found close to the top of the
+            // hann weighted window
+            let settings = Settings {
+                threshold: 0.02,
+                parallel,
+                ..Default::default()
+            };
+            follow_lumen(
+                m_scan.as_view(),
+                catheter_seg.clone(),
+                start_height,
+                0,
+                &settings,
+            )
+        };
+
+        let (sequential, sequential_end) = follow(false);
+        let (parallel, parallel_end) = follow(true);
+
+        assert_eq!(parallel.len(), sequential.len());
+        assert_eq!(parallel_end, sequential_end);
+
+        let ambiguous = |column: usize| {
+            [&GAP, &LAYER]
+                .iter()
+                .any(|range| (range.start..range.end + SEAM_WINDOW).contains(&column))
+        };
+
+        let mut differing = 0;
+        for (column, (&p, &s)) in parallel.iter().zip(sequential.iter()).enumerate() {
+            let close = match (p, s) {
+                (u32::MAX, u32::MAX) => true,
+                (u32::MAX, _) | (_, u32::MAX) => false,
+                (p, s) => p.abs_diff(s) <= 2,
+            };
+
+            if !close {
+                differing += 1;
+                assert!(
+                    ambiguous(column),
+                    "Column {} differs: parallel {}, sequential {}",
+                    column,
+                    p,
+                    s
+                );
+            }
+        }
+
+        assert!(
+            differing < parallel.len() / 100,
+            "{} columns differ",
+            differing
+        );
+
+        // The gap is not found by either
+        assert!(GAP.clone().all(|column| sequential[column] == u32::MAX));
+    }
+}
@@ -134,14 +134,18 @@ impl NodeTask for Task {
             return Ok(());
         };
 
-        let (Some(mut b_scans), Some(mut lumen)) = (b_scans_res.subscribe(), lumen_res.subscribe())
+        let (Some(mut b_scans), Some(mut lumen)) =
+            (b_scans_res.data.subscribe(), lumen_res.data.subscribe())
         else {
             return Ok(());
         };
 
-        let (res, tx) = requests::StreamedResponse::new(100);
+        let (res, tx) = requests::StreamedResponse::with_default_capacity();
 
-        self.mesh_out.respond(res);
+        self.mesh_out.respond(requests::MeshResponse {
+            data: res,
+            a_scan_count: lumen_res.a_scan_count,
+        });
         self.mesh_out.receive().now_or_never();
 
         let settings = self.settings;
@@ -193,15 +197,18 @@ impl NodeTask for Task {
                 }
 
                 if processed_b_scans > 1 {
-                    let mesh = generate_mesh(
-                        (processed_b_scans - 2) * settings.rotational_samples as usize,
-                        (processed_b_scans - 1) * settings.rotational_samples as usize,
-                        (processed_b_scans - 0) * settings.rotational_samples as usize,
-                        processed_b_scans as f32 * settings.pullback_speed
-                            / settings.rotation_frequency,
-                        &resampled_lumen,
-                        &settings,
-                    );
+                    let mesh = LumenMesh {
+                        a_scan_end: received_b_scans[processed_b_scans],
+                        ..generate_mesh(
+                            (processed_b_scans - 2) * settings.rotational_samples as usize,
+                            (processed_b_scans - 1) * settings.rotational_samples as usize,
+                            (processed_b_scans - 0) * settings.rotational_samples as usize,
+                            processed_b_scans as f32 * settings.pullback_speed
+                                / settings.rotation_frequency,
+                            &resampled_lumen,
+                            &settings,
+                        )
+                    };
 
                     tx.send(mesh);
                 }
@@ -210,6 +217,8 @@ impl NodeTask for Task {
             }
         }
 
+        tx.finish();
+
         Ok(())
     }
 }
@@ -306,5 +315,11 @@ fn generate_mesh(
     // Last 6 indices are too much
     indices.resize(indices.len() - 6, 0);
 
-    LumenMesh { vertices, indices }
+    LumenMesh {
+        vertices,
+        indices,
+        stitched_vertices: 0,
+        // The B scans are resampled, the caller knows the A scans
+        a_scan_end: 0,
+    }
 }
@@ -0,0 +1,481 @@
+use std::sync::Arc;
+
+use futures::FutureExt;
+use nalgebra::{DVector, Vector2};
+
+use crate::{
+    pipeline::types::DataVector,
+    queue_channel::{self, error::RecvError},
+};
+
+use super::{diameter, prelude::*};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub mm_per_pixel: f32,
+    pub refraction_index: f32,
+    /// Like the settings of the same name of the Diameter node, so both
+    /// nodes measure the same lumen.
+    pub catheter_diameter: f32,
+    pub use_catheter_diameter: bool,
+    /// Speed of the catheter pullback in mm/s.
+    pub pullback_speed: f32,
+    /// Rotations (B scans) per second.
+    pub rotation_frequency: f32,
+    /// Fraction of A scans with a valid lumen, below which the area of a B
+    /// scan is not computed (NaN).
+    pub min_coverage: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            mm_per_pixel: 0.0055,
+            refraction_index: 1.33,
+            catheter_diameter: 0.9,
+            use_catheter_diameter: false,
+            pullback_speed: 18.0,
+            rotation_frequency: 180.0,
+            min_coverage: 0.5,
+        }
+    }
+}
+
+impl Settings {
+    /// Distance between two B scans in mm.
+    pub fn frame_spacing(&self) -> f32 {
+        self.pullback_speed / self.rotation_frequency
+    }
+
+    fn diameter_settings(&self) -> diameter::Settings {
+        diameter::Settings {
+            mm_per_pixel: self.mm_per_pixel,
+            refraction_index: self.refraction_index,
+            catheter_diameter: self.catheter_diameter,
+            use_catheter_diameter: self.use_catheter_diameter,
+        }
+    }
+}
+
+pub enum InputId {
+    BScans,
+    Lumen,
+    Catheter,
+}
+
+impl_enum_from_into_id_types!(InputId, [graph::InputId], {
+    0 => BScans,
+    1 => Lumen,
+    2 => Catheter,
+});
+
+pub enum OutputId {
+    Area,
+    Volume,
+}
+
+impl_enum_from_into_id_types!(OutputId, [graph::OutputId], {
+    0 => Area,
+    1 => Volume,
+});
+
+// MARK: Node
+
+/// Computes the cross-sectional area of the lumen in every B scan in mm² and
+/// the accumulated volume up to every B scan in mm³. Like the Diameter node,
+/// the lumen is measured from the catheter.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Node {
+    pub settings: Settings,
+
+    pub b_scans: NodeInput<()>,
+    pub lumen: NodeInput<()>,
+    #[serde(default)]
+    pub catheter: NodeInput<()>,
+}
+
+deserialize_node!(Node, "lumen_volume");
+
+impl PipelineNode for Node {
+    type InputId = InputId;
+    type OutputId = OutputId;
+
+    fn slug() -> &'static str {
+        "lumen_volume"
+    }
+
+    fn inputs(
+        &self,
+    ) -> impl Iterator<Item = (<Self as PipelineNode>::InputId, Option<NodeOutput>)> {
+        [
+            (InputId::BScans, self.b_scans.connection()),
+            (InputId::Lumen, self.lumen.connection()),
+            (InputId::Catheter, self.catheter.connection()),
+        ]
+        .into_iter()
+    }
+
+    fn changed(&self, other: &Self) -> bool {
+        self.settings != other.settings
+    }
+
+    fn get_output_id_for_view_request(&self) -> Option<(OutputId, impl Into<TypeId>)> {
+        Some((OutputId::Area, PipelineDataType::DataVector))
+    }
+
+    fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
+        let area_out = builder.output(OutputId::Area);
+        let volume_out = builder.output(OutputId::Volume);
+
+        builder.task(Task {
+            settings: self.settings,
+            area_out,
+            volume_out,
+            b_scans_in: TaskInput::default(),
+            lumen_in: TaskInput::default(),
+            catheter_in: TaskInput::default(),
+        });
+    }
+}
+
+// MARK: Task
+
+struct Task {
+    settings: Settings,
+
+    area_out: TaskOutput<requests::VectorData>,
+    volume_out: TaskOutput<requests::VectorData>,
+    b_scans_in: TaskInput<requests::BScanSegmentation>,
+    lumen_in: TaskInput<requests::MScanSegmentation>,
+    catheter_in: TaskInput<requests::MScanSegmentation>,
+}
+
+impl NodeTask for Task {
+    type InputId = InputId;
+    type PipelineNode = Node;
+
+    fn connect(&mut self, input_id: Self::InputId, input: &mut ConnectionHandle) {
+        match input_id {
+            InputId::BScans => self.b_scans_in.connect(input),
+            InputId::Lumen => self.lumen_in.connect(input),
+            InputId::Catheter => self.catheter_in.connect(input),
+        };
+    }
+
+    fn disconnect(&mut self, input_id: Self::InputId) {
+        match input_id {
+            InputId::BScans => self.b_scans_in.disconnect(),
+            InputId::Lumen => self.lumen_in.disconnect(),
+            InputId::Catheter => self.catheter_in.disconnect(),
+        }
+    }
+
+    fn sync_node(&mut self, node: &Self::PipelineNode) {
+        self.settings = node.settings;
+    }
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        tokio::select! {
+            _req = self.area_out.receive() => {}
+            _req = self.volume_out.receive() => {}
+        }
+
+        // Both outputs may have been requested at the same time
+        let area_requested = self.area_out.receive().now_or_never().is_some();
+        let volume_requested = self.volume_out.receive().now_or_never().is_some();
+
+        let (Some(b_scans_res), Some(lumen_res), Some(catheter_res)) = futures::join!(
+            self.b_scans_in.request(requests::BScanSegmentation),
+            self.lumen_in.request(requests::MScanSegmentation),
+            self.catheter_in.request(requests::MScanSegmentation),
+        ) else {
+            return Ok(());
+        };
+
+        let (Some(mut b_scans), Some(mut lumen), Some(mut catheter)) = (
+            b_scans_res.data.subscribe(),
+            lumen_res.data.subscribe(),
+            catheter_res.data.subscribe(),
+        ) else {
+            return Ok(());
+        };
+
+        // Both outputs are whole vectors, so collect everything first. The
+        // inputs are received at the same time, so none of them lags behind
+        let collect_b_scans = async {
+            let mut received_b_scans = Vec::new();
+            loop {
+                match b_scans.recv().await {
+                    Ok(b_scan) => received_b_scans.push(b_scan),
+                    Err(RecvError::Closed) => break,
+                    Err(e) => Err(e)?,
+                }
+            }
+            anyhow::Ok(received_b_scans)
+        };
+
+        let (received_b_scans, received_lumen, received_catheter) = futures::try_join!(
+            collect_b_scans,
+            collect_segmentation(&mut lumen),
+            collect_segmentation(&mut catheter),
+        )?;
+
+        let settings = self.settings;
+
+        let (areas, volumes) = priority::spawn_blocking(move || {
+            let areas = b_scan_areas(
+                &received_b_scans,
+                &received_lumen,
+                &received_catheter,
+                &settings,
+            );
+
+            let volumes = cumulative_volume(&areas, settings.frame_spacing());
+
+            (areas, volumes)
+        })
+        .await?;
+
+        if area_requested {
+            self.area_out
+                .respond(Arc::new(DataVector::F32(DVector::from_vec(areas))));
+            self.area_out.receive().now_or_never();
+        }
+
+        if volume_requested {
+            self.volume_out
+                .respond(Arc::new(DataVector::F32(DVector::from_vec(volumes))));
+            self.volume_out.receive().now_or_never();
+        }
+
+        Ok(())
+    }
+}
+
+/// Receives a whole M scan segmentation.
+async fn collect_segmentation(
+    segmentation: &mut queue_channel::Receiver<Arc<DVector<u32>>>,
+) -> anyhow::Result<Vec<u32>> {
+    let mut received = Vec::new();
+    loop {
+        match segmentation.recv().await {
+            Ok(chunk) => received.extend(chunk.iter()),
+            Err(RecvError::Closed) => break,
+            Err(e) => Err(e)?,
+        }
+    }
+    Ok(received)
+}
+
+// MARK: Algorithm
+
+/// Whether an entry of a lumen segmentation holds a lumen depth.
+fn is_valid(depth: u32) -> bool {
+    depth != 0 && depth != u32::MAX
+}
+
+/// Fills invalid entries of the lumen contour of one B scan by interpolating
+/// linearly between the nearest valid entries around the circle. Returns the
+/// contour and the fraction of valid entries.
+fn interpolate_contour(lumen: &[u32]) -> (Vec<f32>, f32) {
+    let n = lumen.len();
+    let valid: Vec<usize> = (0..n).filter(|&i| is_valid(lumen[i])).collect();
+
+    let coverage = valid.len() as f32 / n.max(1) as f32;
+
+    let Some(&last) = valid.last() else {
+        return (vec![f32::NAN; n], coverage);
+    };
+
+    let mut contour: Vec<f32> = lumen.iter().map(|&v| v as f32).collect();
+
+    // Walk from every valid entry to the next one, wrapping around the end
+    let mut prev = last;
+    for &next in &valid {
+        let gap = (next + n - prev) % n;
+        let gap = if gap == 0 { n } else { gap };
+
+        for step in 1..gap {
+            let t = step as f32 / gap as f32;
+            contour[(prev + step) % n] = lumen[prev] as f32 * (1.0 - t) + lumen[next] as f32 * t;
+        }
+
+        prev = next;
+    }
+
+    (contour, coverage)
+}
+
+/// Area of every B scan, see [lumen_area]. B scans, whose boundaries are
+/// invalid or outside of the segmentations, have no area (NaN), so area i
+/// stays B scan i.
+fn b_scan_areas(b_scans: &[usize], lumen: &[u32], catheter: &[u32], st: &Settings) -> Vec<f32> {
+    let a_scan_count = lumen.len().min(catheter.len());
+
+    b_scans
+        .windows(2)
+        .map(
+            |b_scan| match b_scan[0] < b_scan[1] && b_scan[1] <= a_scan_count {
+                true => {
+                    let range = b_scan[0]..b_scan[1];
+                    lumen_area(&lumen[range.clone()], &catheter[range], st)
+                }
+                false => f32::NAN,
+            },
+        )
+        .collect()
+}
+
+/// Cross-sectional area of the lumen of one B scan in mm², using the shoelace
+/// formula. NaN, if too few A scans have a valid lumen.
+///
+/// Radii are measured like in the Diameter node: From the catheter surface to
+/// the lumen, plus the radius of the catheter.
+fn lumen_area(lumen: &[u32], catheter: &[u32], st: &Settings) -> f32 {
+    let (contour, coverage) = interpolate_contour(lumen);
+
+    if lumen.len() < 3 || coverage < st.min_coverage {
+        return f32::NAN;
+    }
+
+    let mm_per_pixel = st.mm_per_pixel / st.refraction_index;
+    let catheter_radius = diameter::catheter_diameter(catheter, &st.diameter_settings()) / 2.0;
+
+    let points: Vec<Vector2<f32>> = contour
+        .iter()
+        .zip(catheter)
+        .enumerate()
+        .map(|(i, (&depth, &catheter))| {
+            let rot = i as f32 / contour.len() as f32 * std::f32::consts::TAU;
+            let r = (depth - catheter as f32) * mm_per_pixel + catheter_radius;
+            Vector2::new(rot.cos(), rot.sin()) * r
+        })
+        .collect();
+
+    let twice_area: f32 = points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum();
+
+    twice_area.abs() / 2.0
+}
+
+/// Accumulated volume in mm³ up to every B scan. B scans without an area do
+/// not contribute.
+fn cumulative_volume(areas: &[f32], frame_spacing: f32) -> Vec<f32> {
+    areas
+        .iter()
+        .scan(0.0, |volume, &area| {
+            if !area.is_nan() {
+                *volume += area * frame_spacing;
+            }
+            Some(*volume)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    fn settings() -> Settings {
+        Settings {
+            mm_per_pixel: 0.01,
+            refraction_index: 1.0,
+            ..Default::default()
+        }
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        let error = (actual - expected).abs() / expected;
+        assert!(error < 0.01, "{} is not within 1% of {}", actual, expected);
+    }
+
+    #[test]
+    fn circle_area() {
+        // Radius of 150 pixels, 1.5 mm
+        let lumen = vec![150; 500];
+
+        assert_close(lumen_area(&lumen, &[0; 500], &settings()), PI * 1.5 * 1.5);
+    }
+
+    #[test]
+    fn circle_area_with_gaps() {
+        let mut lumen = vec![150; 500];
+        lumen[0..40].fill(u32::MAX);
+        lumen[200..260].fill(0);
+        lumen[499] = u32::MAX;
+
+        assert_close(lumen_area(&lumen, &[0; 500], &settings()), PI * 1.5 * 1.5);
+
+        // Below the coverage threshold
+        lumen[0..300].fill(u32::MAX);
+        assert!(lumen_area(&lumen, &[0; 500], &settings()).is_nan());
+    }
+
+    #[test]
+    fn agrees_with_diameter_node() {
+        let lumen = vec![150; 500];
+        let catheter = vec![20; 500];
+
+        for use_catheter_diameter in [false, true] {
+            let st = Settings {
+                catheter_diameter: 0.5,
+                use_catheter_diameter,
+                ..settings()
+            };
+
+            let diameter =
+                diameter::calculate_diameter(0, 500, &catheter, &lumen, &st.diameter_settings());
+            let radius = diameter.mean / 2.0;
+
+            assert_close(lumen_area(&lumen, &catheter, &st), PI * radius * radius);
+        }
+    }
+
+    #[test]
+    fn invalid_b_scans_keep_their_index() {
+        let lumen = vec![150; 2000];
+        let catheter = vec![0; 2000];
+
+        // An empty B scan, a reversed one and one past the end
+        let b_scans = [0, 500, 500, 1000, 900, 1500, 2500];
+        let areas = b_scan_areas(&b_scans, &lumen, &catheter, &settings());
+
+        assert_eq!(areas.len(), 6);
+        assert_close(areas[0], PI * 1.5 * 1.5);
+        assert!(areas[1].is_nan());
+        assert_close(areas[2], PI * 1.5 * 1.5);
+        assert!(areas[3].is_nan());
+        assert_close(areas[4], PI * 1.5 * 1.5);
+        assert!(areas[5].is_nan());
+    }
+
+    #[test]
+    fn interpolation_wraps_around() {
+        let (contour, coverage) = interpolate_contour(&[u32::MAX, 20, u32::MAX, 40]);
+
+        assert_eq!(contour, [30.0, 20.0, 30.0, 40.0]);
+        assert_eq!(coverage, 0.5);
+    }
+
+    #[test]
+    fn volume_accumulation() {
+        let st = settings();
+        let area = PI * 1.5 * 1.5;
+
+        let areas = [area, area, f32::NAN, area];
+        let volumes = cumulative_volume(&areas, st.frame_spacing());
+
+        let slice = area * 0.1;
+        assert_eq!(volumes.len(), 4);
+        assert_close(volumes[0], slice);
+        assert_close(volumes[1], 2.0 * slice);
+        assert_close(volumes[2], 2.0 * slice);
+        assert_close(volumes[3], 3.0 * slice);
+    }
+}
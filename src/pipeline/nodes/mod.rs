@@ -1,16 +1,28 @@
+pub mod apply_mask;
+pub mod b_scan_boundaries_input;
 pub mod binary_input;
+pub mod bundle;
+pub mod composite;
+pub mod crop;
 pub mod diameter;
+pub mod external_command;
 pub mod filter;
+pub mod flatten;
 pub mod follow_catheter;
 pub mod follow_lumen;
 pub mod generate_mesh;
+pub mod lumen_volume;
 pub mod output;
 pub mod process_raw_m_scan;
+pub mod rechunk;
+pub mod remove_catheter;
 pub mod remove_detector_defect;
+pub mod resample_depth;
 pub mod segment_b_scans;
+pub mod segmentation_input;
 
 use core::fmt;
-use std::any;
+use std::{any, path::Path};
 
 use vec_collections::VecMap;
 
@@ -19,8 +31,14 @@ use crate::{
     node_graph::{InputId, NodeOutput, OutputId, TypeId},
 };
 
-use super::execution::{
-    ConnectionHandle, DynNodeTask, Invalidator, NodeTaskBuilder, NodeTaskBuilderImpl,
+use super::{
+    execution::{
+        ConnectionHandle, DynNodeTask, Invalidator, NodeTaskBuilder, NodeTaskBuilderImpl, Replay,
+    },
+    memory::{MemoryEstimate, UpstreamStats},
+    thresholds::ThresholdTarget,
+    types::{DataType, TypeHandling},
+    validation::ValidationIssue,
 };
 
 /// Important types and traits for pipeline nodes.
@@ -28,12 +46,18 @@ use super::execution::{
 mod prelude {
     pub(crate) use crate::pipeline::{
         execution::{
-            ConnectionHandle, InvalidationCause, NodeTask, NodeTaskBuilder, TaskInput, TaskOutput,
+            ConnectionHandle, InvalidationCause, NodeTask, NodeTaskBuilder, Replay, TaskInput,
+            TaskOutput,
         },
-        requests, PipelineDataType,
+        memory::{MemoryEstimate, UpstreamStats},
+        priority, requests,
+        thresholds::{Threshold, ThresholdScale, ThresholdTarget},
+        types::TypeHandling,
+        validation::{self, ValidationIssue},
+        PipelineDataType,
     };
 
-    pub(crate) use super::{deserialize_node, DynPipelineNode, PipelineNode};
+    pub(crate) use super::{deserialize_node, DynPipelineNode, PathRole, PipelineNode};
 
     pub(crate) use graph::*;
 
@@ -47,6 +71,13 @@ mod prelude {
     pub(crate) use serde::{Deserialize, Serialize};
 }
 
+/// Whether a node reads or writes a file, see [PipelineNode::visit_paths].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PathRole {
+    Input,
+    Output,
+}
+
 /// Trait describing a node in a high level node graph.
 pub trait PipelineNode: erased_serde::Serialize
     + fmt::Debug
@@ -81,6 +112,60 @@ pub trait PipelineNode: erased_serde::Serialize
         None as Option<(_, TypeId)>
     }
 
+    /// Calls `visitor` with every file this node reads or writes.
+    fn visit_paths(&self, _visitor: &mut dyn FnMut(PathRole, &Path)) {}
+
+    /// Estimates the memory this node needs for the stream it receives, see
+    /// [super::memory]. Nodes without inputs receive [UpstreamStats::NONE]. By
+    /// default, every chunk is passed on unchanged.
+    fn estimate_memory(&self, upstream: &UpstreamStats) -> MemoryEstimate {
+        MemoryEstimate::streaming(upstream, *upstream)
+    }
+
+    /// Whether `output_id` serves the same data again, when requested a
+    /// second time, see [Replay]. By default, it is computed again from the
+    /// inputs.
+    fn replay(&self, _output_id: <Self as PipelineNode>::OutputId) -> Replay {
+        Replay::Inputs
+    }
+
+    /// Why the output of this node may differ between two runs on the same
+    /// data, even in deterministic mode, see [super::determinism]. [None] by
+    /// default.
+    fn nondeterminism(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// What this node does with M scans of `data_type` it receives on
+    /// `input_id`. Conversions are highlighted on the connections in the
+    /// pipeline editor. By default, they are used as is.
+    fn type_handling(
+        &self,
+        _input_id: <Self as PipelineNode>::InputId,
+        _data_type: DataType,
+    ) -> TypeHandling {
+        TypeHandling::AsIs
+    }
+
+    /// Corrects settings, that are out of range or inconsistent, and describes
+    /// every issue, see [super::validation]. Called after loading a pipeline
+    /// and after every edit, so settings are valid when the task is created.
+    /// No issues by default.
+    fn validate(&mut self) -> Vec<ValidationIssue> {
+        Vec::new()
+    }
+
+    /// The thresholds in the settings of this node, that can be picked on a
+    /// histogram, see [super::thresholds]. [None] by default.
+    fn threshold_target(&self) -> Option<&dyn ThresholdTarget> {
+        None
+    }
+
+    /// Mutable version of [Self::threshold_target].
+    fn threshold_target_mut(&mut self) -> Option<&mut dyn ThresholdTarget> {
+        None
+    }
+
     /// Creates the task that becomes part of the execution system and
     /// responsible for executing this node.
     fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>);
@@ -106,6 +191,22 @@ pub trait DynPipelineNode: DynEditNode + Send + Sync + 'static {
 
     fn get_output_for_view_request(&self) -> Option<(OutputId, TypeId)>;
 
+    fn visit_paths(&self, visitor: &mut dyn FnMut(PathRole, &Path));
+
+    fn estimate_memory(&self, upstream: &UpstreamStats) -> MemoryEstimate;
+
+    fn replay(&self, output_id: OutputId) -> Replay;
+
+    fn nondeterminism(&self) -> Option<&'static str>;
+
+    fn type_handling(&self, input_id: InputId, data_type: DataType) -> TypeHandling;
+
+    fn validate(&mut self) -> Vec<ValidationIssue>;
+
+    fn threshold_target(&self) -> Option<&dyn ThresholdTarget>;
+
+    fn threshold_target_mut(&mut self) -> Option<&mut dyn ThresholdTarget>;
+
     fn create_node_task(
         &mut self,
     ) -> (
@@ -152,6 +253,38 @@ impl<T: PipelineNode> DynPipelineNode for T {
             .map(|(id, ty)| (id.into(), ty.into()))
     }
 
+    fn visit_paths(&self, visitor: &mut dyn FnMut(PathRole, &Path)) {
+        PipelineNode::visit_paths(self, visitor)
+    }
+
+    fn estimate_memory(&self, upstream: &UpstreamStats) -> MemoryEstimate {
+        PipelineNode::estimate_memory(self, upstream)
+    }
+
+    fn replay(&self, output_id: OutputId) -> Replay {
+        PipelineNode::replay(self, output_id.into())
+    }
+
+    fn nondeterminism(&self) -> Option<&'static str> {
+        PipelineNode::nondeterminism(self)
+    }
+
+    fn type_handling(&self, input_id: InputId, data_type: DataType) -> TypeHandling {
+        PipelineNode::type_handling(self, input_id.into(), data_type)
+    }
+
+    fn validate(&mut self) -> Vec<ValidationIssue> {
+        PipelineNode::validate(self)
+    }
+
+    fn threshold_target(&self) -> Option<&dyn ThresholdTarget> {
+        PipelineNode::threshold_target(self)
+    }
+
+    fn threshold_target_mut(&mut self) -> Option<&mut dyn ThresholdTarget> {
+        PipelineNode::threshold_target_mut(self)
+    }
+
     fn create_node_task(
         &mut self,
     ) -> (
@@ -1,23 +1,128 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    collections::HashSet,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
 use anyhow::anyhow;
 use tokio::{
     fs,
-    io::AsyncWriteExt,
+    io::{AsyncSeekExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
     sync::{watch, Notify},
 };
 
 use crate::{
-    pipeline::types::{DataType, LumenVertex},
-    queue_channel::error::RecvError,
+    pipeline::{
+        priority::Priority,
+        pullback_format::{self, ContourSampler, PullbackDocument},
+        range,
+        raw_format::{Endianness, RawHeader},
+        requests::StreamedResponse,
+        segmentation_format::SegmentationSidecar,
+        stream_format::FrameWriter,
+        types::{DataMatrix, DataType, LumenMesh, LumenVertex, SCHEMA_VERSION},
+        Pipeline,
+    },
+    queue_channel::{self, error::RecvError},
+    units::NumberFormat,
 };
 
 use super::prelude::*;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Progress {
     Idle,
     Working(Option<f32>),
+    /// Working, with the number of items written so far, because their total
+    /// is unknown.
+    Items(usize),
+    /// Waiting for a client to connect or connecting to one, see
+    /// [Destination].
+    Waiting,
+    /// Streaming failed with this error. The save is retried by
+    /// [Node::retry].
+    Failed(String),
+}
+
+impl Progress {
+    /// Progress after writing `done` of `total` A scans. Without a total, the
+    /// written `items` are counted instead.
+    fn of(done: usize, total: usize, items: usize) -> Self {
+        match total {
+            0 => Progress::Items(items),
+            total => Progress::Working(Some((done as f32 / total as f32).min(1.0))),
+        }
+    }
+}
+
+// MARK: ExportFormat
+
+/// How an export is laid out.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    /// Binary values, text for diameters and OBJ for meshes.
+    #[default]
+    Native,
+    /// One [PullbackDocument], see [crate::pipeline::pullback_format].
+    Json,
+}
+
+impl ExportFormat {
+    /// Formats an input of `input_type` can be exported in. The first one is
+    /// used, when the chosen one is not available.
+    pub fn available(input_type: PipelineDataType) -> &'static [ExportFormat] {
+        match input_type {
+            PipelineDataType::BScanSegmentation
+            | PipelineDataType::MScanSegmentation
+            | PipelineDataType::Diameter => &[ExportFormat::Native, ExportFormat::Json],
+            PipelineDataType::Bundle => &[ExportFormat::Json],
+            _ => &[ExportFormat::Native],
+        }
+    }
+
+    /// This format, if available for `input_type`, the default one otherwise.
+    pub fn resolve(self, input_type: PipelineDataType) -> Self {
+        let available = Self::available(input_type);
+        match available.contains(&self) {
+            true => self,
+            false => available[0],
+        }
+    }
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExportFormat::Native => write!(f, "Native"),
+            ExportFormat::Json => write!(f, "JSON"),
+        }
+    }
+}
+
+// MARK: Destination
+
+/// Where a save writes to. Streams use the layout of [crate::pipeline::stream_format].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Destination {
+    #[default]
+    File,
+    /// Listens on this address and streams to every client connecting, until
+    /// stopped by [Node::stop]. Every client gets a fresh export.
+    Listen(String),
+    /// Connects to this address and streams to it.
+    Connect(String),
+}
+
+impl Destination {
+    /// The address to listen on or to connect to.
+    pub fn address(&self) -> Option<&str> {
+        match self {
+            Destination::File => None,
+            Destination::Listen(address) | Destination::Connect(address) => Some(address),
+        }
+    }
 }
 
 // MARK: Node
@@ -27,13 +132,58 @@ pub struct Node {
     pub path: PathBuf,
     pub input_type: PipelineDataType,
     pub scan_data_type: DataType,
+    /// Byte order of exported M scans.
+    #[serde(default)]
+    pub endianness: Endianness,
+    /// Whether exported M scans start with a [RawHeader].
+    #[serde(default)]
+    pub header: bool,
+    /// Files saved as part of a run session go into a subdirectory named after
+    /// the session, see [run_path].
+    #[serde(default)]
+    pub separate_runs: bool,
+    /// Exported M scans only contain the A scans selected in the range
+    /// selector, see [range].
+    #[serde(default)]
+    pub selected_range_only: bool,
+    /// Chosen format, see [ExportFormat::resolve].
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// Only every `contour_stride`th A scan of the lumen is written in
+    /// [ExportFormat::Json], to keep the files small.
+    #[serde(default = "default_contour_stride")]
+    pub contour_stride: usize,
+    #[serde(default)]
+    pub destination: Destination,
+    /// Priority of the conversions while saving, see [priority].
+    #[serde(default)]
+    pub priority: Priority,
     #[serde(skip)]
     pub notify: Arc<Notify>,
+    /// Stops listening for clients. Shared with the task like [Self::notify].
+    #[serde(skip)]
+    pub stop: Arc<Notify>,
+    /// Counts the requests to retry a failed stream. The task is synced, when
+    /// it changes.
+    #[serde(skip)]
+    pub retries: usize,
+    /// Name of the run session, the requested save belongs to. Shared with the
+    /// task like [Self::notify].
+    #[serde(skip)]
+    pub session: Arc<Mutex<Option<String>>>,
+    /// [Pipeline::provenance] of the input, recorded in the sidecar of
+    /// exported segmentations and in JSON exports. Kept up to date by
+    /// [update_provenance].
+    #[serde(skip)]
+    pub provenance: Option<u64>,
 
     pub input: NodeInput<()>,
 
     #[serde(skip)]
     pub progress_rx: Option<watch::Receiver<Progress>>,
+    /// Number of saves finished without error.
+    #[serde(skip)]
+    pub saves_rx: Option<watch::Receiver<usize>>,
 }
 
 impl Default for Node {
@@ -42,16 +192,75 @@ impl Default for Node {
             path: PathBuf::new(),
             input_type: PipelineDataType::RawMScan,
             scan_data_type: DataType::U16,
+            endianness: Endianness::default(),
+            header: false,
+            separate_runs: false,
+            selected_range_only: false,
+            format: ExportFormat::default(),
+            contour_stride: default_contour_stride(),
+            destination: Destination::File,
+            priority: Priority::default(),
             input: NodeInput::default(),
             notify: Arc::new(Notify::new()),
+            stop: Arc::new(Notify::new()),
+            retries: 0,
+            session: Arc::default(),
+            provenance: None,
             progress_rx: None,
+            saves_rx: None,
         }
     }
 }
 
+fn default_contour_stride() -> usize {
+    1
+}
+
 impl Node {
+    /// The format exports are written in.
+    pub fn export_format(&self) -> ExportFormat {
+        self.format.resolve(self.input_type)
+    }
+
+    /// Requests the task to save its input. The request is kept, if the task
+    /// is not waiting for it right now.
     pub fn save(&mut self) {
-        self.notify.notify_waiters();
+        *self.session.lock().unwrap() = None;
+        self.notify.notify_one();
+    }
+
+    /// Requests the task to save its input as part of the run session
+    /// `session`.
+    pub fn save_in_session(&mut self, session: &str) {
+        *self.session.lock().unwrap() = Some(session.to_string());
+        self.notify.notify_one();
+    }
+
+    /// Stops listening for clients, see [Destination::Listen].
+    pub fn stop(&mut self) {
+        self.stop.notify_one();
+    }
+
+    /// Retries the save, after streaming failed.
+    pub fn retry(&mut self) {
+        self.retries += 1;
+    }
+
+    /// The file a save as part of `session` writes.
+    pub fn session_path(&self, session: &str) -> PathBuf {
+        match self.separate_runs {
+            true => run_path(&self.path, session),
+            false => self.path.clone(),
+        }
+    }
+}
+
+/// `path` moved into a subdirectory named `run` next to it.
+pub fn run_path(path: &Path, run: &str) -> PathBuf {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    match path.file_name() {
+        Some(file_name) => dir.join(run).join(file_name),
+        None => dir.join(run),
     }
 }
 
@@ -69,6 +278,16 @@ impl PipelineNode for Node {
         self.path != other.path
             || self.input_type != other.input_type
             || self.scan_data_type != other.scan_data_type
+            || self.endianness != other.endianness
+            || self.header != other.header
+            || self.separate_runs != other.separate_runs
+            || self.selected_range_only != other.selected_range_only
+            || self.format != other.format
+            || self.contour_stride != other.contour_stride
+            || self.destination != other.destination
+            || self.priority != other.priority
+            || self.retries != other.retries
+            || self.provenance != other.provenance
     }
 
     fn inputs(
@@ -77,16 +296,97 @@ impl PipelineNode for Node {
         std::iter::once((InputIdSingle, self.input.connection()))
     }
 
+    fn visit_paths(&self, visitor: &mut dyn FnMut(PathRole, &Path)) {
+        visitor(PathRole::Output, &self.path);
+    }
+
+    fn type_handling(&self, _input_id: InputIdSingle, data_type: DataType) -> TypeHandling {
+        // M scans are written in the type chosen for the file
+        match self.input_type {
+            PipelineDataType::RawMScan | PipelineDataType::MScan
+                if data_type != self.scan_data_type =>
+            {
+                TypeHandling::Rescales(self.scan_data_type)
+            }
+            _ => TypeHandling::AsIs,
+        }
+    }
+
+    fn validate(&mut self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+
+        if let Some(path) = self.path.to_str() {
+            let mut trimmed = path.to_string();
+            validation::trim(&mut issues, "Path", &mut trimmed);
+            self.path = trimmed.into();
+        }
+        if matches!(self.destination, Destination::File)
+            && !self.path.as_os_str().is_empty()
+            && self.path.file_name().is_none()
+        {
+            issues.push(ValidationIssue::rejected(
+                "Path",
+                format!("{} names no file", self.path.display()),
+            ));
+        }
+
+        // The type is taken from the connection, see EditNode::connect
+        if let Some(connection) = self.input.connection() {
+            let input_type = connection.type_id.into();
+            if self.input_type != input_type {
+                issues.push(ValidationIssue::corrected(
+                    "Type",
+                    format!(
+                        "Saved as {}, but the input is {}, changed to it",
+                        self.input_type, input_type
+                    ),
+                ));
+                self.input_type = input_type;
+            }
+        }
+
+        validation::at_least(&mut issues, "Contour Stride", &mut self.contour_stride, 1);
+
+        match &mut self.destination {
+            Destination::File => {}
+            Destination::Listen(address) | Destination::Connect(address) => {
+                validation::trim(&mut issues, "Address", address);
+                if address.is_empty() {
+                    issues.push(ValidationIssue::rejected("Address", "No address is set"));
+                }
+            }
+        }
+
+        issues
+    }
+
     fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
         let (progress_tx, progress_rx) = watch::channel(Progress::Idle);
+        let (saves_tx, saves_rx) = watch::channel(0);
 
         self.progress_rx = Some(progress_rx);
+        self.saves_rx = Some(saves_rx);
 
         builder.task(Task {
             path: self.path.clone(),
             scan_data_type: self.scan_data_type,
+            endianness: self.endianness,
+            header: self.header,
+            separate_runs: self.separate_runs,
+            selected_range_only: self.selected_range_only,
+            format: self.format,
+            contour_stride: self.contour_stride,
+            destination: self.destination.clone(),
+            priority: self.priority,
+            listener: None,
+            provenance: self.provenance,
             notifier: self.notify.clone(),
+            stop: self.stop.clone(),
+            session_slot: self.session.clone(),
+            session: None,
+            save_requested: false,
             progress_tx,
+            saves_tx,
             input: match self.input_type {
                 PipelineDataType::RawMScan => TaskInputType::RawMScan(TaskInput::default()),
                 PipelineDataType::DataVector => TaskInputType::DataVector(TaskInput::default()),
@@ -99,6 +399,7 @@ impl PipelineNode for Node {
                 }
                 PipelineDataType::Diameter => TaskInputType::Diameter(TaskInput::default()),
                 PipelineDataType::Mesh => TaskInputType::Mesh(TaskInput::default()),
+                PipelineDataType::Bundle => TaskInputType::Bundle(TaskInput::default()),
             },
         });
     }
@@ -115,9 +416,23 @@ enum TaskInputType {
     MScanSegmentation(TaskInput<requests::MScanSegmentation>),
     Diameter(TaskInput<requests::Diameter>),
     Mesh(TaskInput<requests::Mesh>),
+    Bundle(TaskInput<requests::Bundle>),
 }
 
 impl TaskInputType {
+    fn pipeline_type(&self) -> PipelineDataType {
+        match self {
+            TaskInputType::RawMScan(_) => PipelineDataType::RawMScan,
+            TaskInputType::DataVector(_) => PipelineDataType::DataVector,
+            TaskInputType::MScan(_) => PipelineDataType::MScan,
+            TaskInputType::BScanSegmentation(_) => PipelineDataType::BScanSegmentation,
+            TaskInputType::MScanSegmentation(_) => PipelineDataType::MScanSegmentation,
+            TaskInputType::Diameter(_) => PipelineDataType::Diameter,
+            TaskInputType::Mesh(_) => PipelineDataType::Mesh,
+            TaskInputType::Bundle(_) => PipelineDataType::Bundle,
+        }
+    }
+
     pub fn disconnect(&mut self) {
         match self {
             TaskInputType::RawMScan(input) => input.disconnect(),
@@ -127,6 +442,7 @@ impl TaskInputType {
             TaskInputType::MScanSegmentation(input) => input.disconnect(),
             TaskInputType::Diameter(input) => input.disconnect(),
             TaskInputType::Mesh(input) => input.disconnect(),
+            TaskInputType::Bundle(input) => input.disconnect(),
         }
     }
 }
@@ -134,18 +450,45 @@ impl TaskInputType {
 struct Task {
     path: PathBuf,
     scan_data_type: DataType,
+    endianness: Endianness,
+    header: bool,
+    separate_runs: bool,
+    selected_range_only: bool,
+    format: ExportFormat,
+    contour_stride: usize,
+    destination: Destination,
+    priority: Priority,
+    /// Listener of [Destination::Listen] and its address. Kept between
+    /// clients, so they can connect while the previous one is served.
+    listener: Option<(String, TcpListener)>,
+    provenance: Option<u64>,
     notifier: Arc<Notify>,
+    stop: Arc<Notify>,
+    session_slot: Arc<Mutex<Option<String>>>,
+    /// The run session of the requested save, see [Node::save_in_session].
+    session: Option<String>,
+    /// A save was requested, but did not finish yet. Runs are canceled by
+    /// invalidations, the save is retried by the next run.
+    save_requested: bool,
 
     input: TaskInputType,
 
     progress_tx: watch::Sender<Progress>,
+    saves_tx: watch::Sender<usize>,
 }
 
 impl NodeTask for Task {
     type InputId = InputIdSingle;
     type PipelineNode = Node;
 
-    fn invalidate(&mut self, _cause: InvalidationCause) {
+    fn invalidate(&mut self, cause: InvalidationCause) {
+        if let InvalidationCause::UserCancelled = cause {
+            // The save is abandoned instead of retried by the next run
+            self.save_requested = false;
+            self.listener = None;
+            let _ = std::fs::remove_file(self.part_path());
+        }
+
         let _ = self.progress_tx.send(Progress::Idle);
     }
 
@@ -203,6 +546,13 @@ impl NodeTask for Task {
                         break;
                     }
                 }
+                PipelineDataType::Bundle => {
+                    let mut task_input = TaskInput::<requests::Bundle>::default();
+                    if task_input.connect(input) {
+                        resulting = Some(TaskInputType::Bundle(task_input));
+                        break;
+                    }
+                }
             }
         }
 
@@ -220,99 +570,221 @@ impl NodeTask for Task {
     fn sync_node(&mut self, node: &Self::PipelineNode) {
         self.path = node.path.clone();
         self.scan_data_type = node.scan_data_type;
+        self.endianness = node.endianness;
+        self.header = node.header;
+        self.separate_runs = node.separate_runs;
+        self.selected_range_only = node.selected_range_only;
+        self.format = node.format;
+        self.contour_stride = node.contour_stride;
+        self.provenance = node.provenance;
+        self.priority = node.priority;
+
+        if self.destination != node.destination {
+            // A save requested for another destination is dropped
+            self.destination = node.destination.clone();
+            self.save_requested = false;
+            self.listener = None;
+        }
     }
 
     async fn run(&mut self) -> anyhow::Result<()> {
-        self.notifier.notified().await;
+        if !self.save_requested {
+            self.notifier.notified().await;
+            self.save_requested = true;
+            self.session = self.session_slot.lock().unwrap().take();
+        }
 
-        match &mut self.input {
-            TaskInputType::RawMScan(input) => {
-                let mut file = fs::File::create(&self.path).await?;
+        let result = priority::scope(self.priority, async {
+            match self.destination {
+                Destination::File => self.save_file().await,
+                Destination::Listen(_) | Destination::Connect(_) => self.save_stream().await,
+            }
+        })
+        .await;
 
-                let Some(res) = input.request(requests::RawMScan).await else {
-                    return Ok(());
-                };
+        if result.is_ok() {
+            self.saves_tx.send_modify(|saves| *saves += 1);
+        }
 
-                let Some(mut rx) = res.data.subscribe() else {
-                    return Err(anyhow!("Failed to subscribe to RawMScan"));
-                };
+        result
+    }
+}
 
-                let _ = self.progress_tx.send(Progress::Working(None));
+impl Task {
+    /// The file to write, which depends on the run session.
+    fn target(&self) -> PathBuf {
+        match (&self.session, self.separate_runs) {
+            (Some(session), true) => run_path(&self.path, session),
+            _ => self.path.clone(),
+        }
+    }
 
-                let mut a_scan_count = 0;
+    /// The file written during an export. It replaces [Self::target] only when
+    /// the export finished, so canceled exports leave no truncated files.
+    fn part_path(&self) -> PathBuf {
+        let mut path = self.target().into_os_string();
+        path.push(".part");
+        path.into()
+    }
 
-                loop {
-                    let scan = match rx.recv().await {
-                        Err(RecvError::Closed) => break,
-                        Err(e) => Err(e)?,
-                        Ok(scan) => scan,
-                    };
+    /// Moves the written [Self::part_path] to [Self::target].
+    async fn finish_part(&self) -> anyhow::Result<()> {
+        match fs::rename(self.part_path(), self.target()).await {
+            // Nothing was written, because the input was not available
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    async fn save_file(&mut self) -> anyhow::Result<()> {
+        if self.session.is_some() && self.separate_runs {
+            if let Some(dir) = self.target().parent() {
+                fs::create_dir_all(dir).await?;
+            }
+        }
 
-                    let scan = scan.cast_rescale_par(self.scan_data_type);
+        let result = match fs::File::create(self.part_path()).await {
+            Ok(file) => match self.export(Sink::File(file)).await {
+                Ok(()) => self.finish_part().await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e.into()),
+        };
+        self.save_requested = false;
 
-                    file.write_all(scan.as_u8_slice()).await?;
+        result
+    }
 
-                    a_scan_count += scan.ncols();
-                    let _ = self.progress_tx.send(Progress::Working(Some(
-                        a_scan_count as f32 / res.a_scan_count as f32,
-                    )));
-                }
+    /// Streams the export to the client of [Self::destination]. Failed
+    /// streams keep the save requested, so it is retried after the next sync.
+    /// Listening keeps it requested, so the next client is served by the next
+    /// run.
+    async fn save_stream(&mut self) -> anyhow::Result<()> {
+        let _ = self.progress_tx.send(Progress::Waiting);
+
+        let stop = self.stop.clone();
+        let stream = tokio::select! {
+            stream = self.open_stream() => stream,
+            () = stop.notified() => {
+                self.save_requested = false;
+                self.listener = None;
                 let _ = self.progress_tx.send(Progress::Idle);
+                return Ok(());
+            }
+        };
+
+        let pipeline_type = self.input.pipeline_type();
+        let result = match stream {
+            Ok(stream) => {
+                self.export(Sink::Stream(FrameWriter::new(stream, pipeline_type)))
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+
+        match &result {
+            Ok(()) => {
+                if let Destination::Connect(_) = self.destination {
+                    self.save_requested = false;
+                }
+            }
+            Err(e) => {
+                let _ = self.progress_tx.send(Progress::Failed(format!("{:#}", e)));
+            }
+        }
+
+        result
+    }
+
+    /// Connects to the address of [Self::destination], or waits for the next
+    /// client on it.
+    async fn open_stream(&mut self) -> anyhow::Result<TcpStream> {
+        match &self.destination {
+            Destination::File => Err(anyhow!("The output does not stream")),
+            Destination::Connect(address) => TcpStream::connect(address)
+                .await
+                .map_err(|e| anyhow!("Failed to connect to {}: {}", address, e)),
+            Destination::Listen(address) => {
+                if !matches!(&self.listener, Some((bound, _)) if bound == address) {
+                    self.listener = None;
+                    let listener = TcpListener::bind(address)
+                        .await
+                        .map_err(|e| anyhow!("Failed to listen on {}: {}", address, e))?;
+                    self.listener = Some((address.clone(), listener));
+                }
+
+                let (_, listener) = self.listener.as_ref().unwrap();
+                let (stream, _) = listener.accept().await?;
+                Ok(stream)
+            }
+        }
+    }
+
+    /// Writes the input into `sink`, see [Sink::write].
+    async fn export(&mut self, mut sink: Sink) -> anyhow::Result<()> {
+        let target = self.target();
+        // The selection at the time of the save
+        let a_scans = match self.selected_range_only {
+            true => range::selected(),
+            false => None,
+        };
+
+        if self.format.resolve(self.input.pipeline_type()) == ExportFormat::Json {
+            let Some(document) = self.collect_document().await? else {
+                return Ok(());
+            };
+            sink.write_text(&serde_json::to_string_pretty(&document)?)
+                .await?;
+            sink.finish().await?;
+            let _ = self.progress_tx.send(Progress::Idle);
+            return Ok(());
+        }
+
+        match &mut self.input {
+            TaskInputType::RawMScan(input) => {
+                let Some(res) = input.request(requests::RawMScan).await else {
+                    return Ok(());
+                };
+
+                let Some(rx) = res.data.subscribe() else {
+                    return Err(anyhow!("Failed to subscribe to RawMScan"));
+                };
+
+                self.write_scans(&mut sink, rx, res.a_scan_samples, res.a_scan_count, a_scans)
+                    .await?;
             }
             TaskInputType::DataVector(input) => {
                 let Some(data) = input.request(requests::VectorData).await else {
                     return Ok(());
                 };
 
-                let mut file = fs::File::create(&self.path).await?;
-
-                file.write_all(data.as_u8_slice()).await?;
+                sink.write(data.data_type(), data.len(), 1, data.as_u8_slice())
+                    .await?;
             }
             TaskInputType::MScan(input) => {
-                let mut file = fs::File::create(&self.path).await?;
-
                 let Some(res) = input.request(requests::MScan).await else {
                     return Ok(());
                 };
 
-                let Some(mut rx) = res.data.subscribe() else {
+                let Some(rx) = res.data.subscribe() else {
                     return Err(anyhow!("Failed to subscribe to MScan"));
                 };
 
-                let _ = self.progress_tx.send(Progress::Working(None));
-
-                let mut a_scan_count = 0;
-
-                loop {
-                    let scan = match rx.recv().await {
-                        Err(RecvError::Closed) => break,
-                        Err(e) => Err(e)?,
-                        Ok(scan) => scan,
-                    };
-
-                    let scan = scan.cast_rescale_par(self.scan_data_type);
-
-                    file.write_all(scan.as_u8_slice()).await?;
-
-                    a_scan_count += scan.ncols();
-                    let _ = self.progress_tx.send(Progress::Working(Some(
-                        a_scan_count as f32 / res.a_scan_count as f32,
-                    )));
-                }
-                let _ = self.progress_tx.send(Progress::Idle);
+                self.write_scans(&mut sink, rx, res.a_scan_samples, res.a_scan_count, a_scans)
+                    .await?;
             }
             TaskInputType::BScanSegmentation(input) => {
-                let mut file = fs::File::create(&self.path).await?;
-
                 let Some(res) = input.request(requests::BScanSegmentation).await else {
                     return Ok(());
                 };
 
-                let Some(mut rx) = res.subscribe() else {
+                let Some(mut rx) = res.data.subscribe() else {
                     return Err(anyhow!("Failed to subscribe to BScanSegmentation"));
                 };
 
-                let _ = self.progress_tx.send(Progress::Working(None));
+                let _ = self.progress_tx.send(Progress::of(0, res.a_scan_count, 0));
+
+                let mut items = 0;
 
                 loop {
                     let value = match rx.recv().await {
@@ -321,23 +793,28 @@ impl NodeTask for Task {
                         Ok(scan) => scan,
                     };
 
-                    file.write_all(bytemuck::cast_slice(&[value as u32]))
+                    sink.write(DataType::U32, 1, 1, bytemuck::cast_slice(&[value as u32]))
                         .await?;
+
+                    items += 1;
+                    let _ = self
+                        .progress_tx
+                        .send(Progress::of(value, res.a_scan_count, items));
                 }
-                let _ = self.progress_tx.send(Progress::Idle);
             }
             TaskInputType::MScanSegmentation(input) => {
-                let mut file = fs::File::create(&self.path).await?;
-
                 let Some(res) = input.request(requests::MScanSegmentation).await else {
                     return Ok(());
                 };
 
-                let Some(mut rx) = res.subscribe() else {
+                let Some(mut rx) = res.data.subscribe() else {
                     return Err(anyhow!("Failed to subscribe to MScanSegmentation"));
                 };
 
-                let _ = self.progress_tx.send(Progress::Working(None));
+                let _ = self.progress_tx.send(Progress::of(0, res.a_scan_count, 0));
+
+                let mut a_scans = 0;
+                let mut chunks = Vec::new();
 
                 loop {
                     let value = match rx.recv().await {
@@ -346,25 +823,52 @@ impl NodeTask for Task {
                         Ok(scan) => scan,
                     };
 
-                    file.write_all(bytemuck::cast_slice(value.as_slice()))
-                        .await?;
+                    let bytes: Vec<u8> = value.iter().flat_map(|v| v.to_le_bytes()).collect();
+                    sink.write(DataType::U32, 1, value.len(), &bytes).await?;
+
+                    a_scans += value.len();
+                    chunks.push(value.len());
+                    let _ = self
+                        .progress_tx
+                        .send(Progress::of(a_scans, res.a_scan_count, a_scans));
+                }
+
+                // Records the streamed A scans, which may differ from the
+                // announced count
+                if let Sink::File(_) = sink {
+                    let sidecar = SegmentationSidecar::new(
+                        a_scans,
+                        res.a_scan_samples,
+                        self.provenance,
+                        chunks,
+                    );
+                    fs::write(
+                        SegmentationSidecar::path(&target),
+                        serde_json::to_string_pretty(&sidecar)?,
+                    )
+                    .await?;
                 }
-                let _ = self.progress_tx.send(Progress::Idle);
             }
             TaskInputType::Diameter(input) => {
-                let mut file = fs::File::create(&self.path).await?;
-
                 let Some(res) = input.request(requests::Diameter).await else {
                     return Ok(());
                 };
 
-                let Some(mut rx) = res.subscribe() else {
+                let Some(mut rx) = res.data.subscribe() else {
                     return Err(anyhow!("Failed to subscribe to Diameter"));
                 };
 
-                let _ = self.progress_tx.send(Progress::Working(None));
+                let _ = self.progress_tx.send(Progress::of(0, res.a_scan_count, 0));
+
+                let format = NumberFormat::current();
+                let separator = format.field_separator();
 
-                let mut output = String::new();
+                // Lets scripts detect changes of the columns
+                sink.write_text(&format!(
+                    "# IVOCT diameters, schema version {SCHEMA_VERSION}: \
+                     B scan{separator}min{separator}max\n"
+                ))
+                .await?;
 
                 let mut scan_number = 1;
 
@@ -375,35 +879,42 @@ impl NodeTask for Task {
                         Ok(scan) => scan,
                     };
 
-                    output += &format!(
-                        "{}, {} mm, {} mm\n",
-                        scan_number, diameter.min, diameter.max
-                    );
+                    sink.write_text(&format!(
+                        "{}{separator}{}{separator}{}\n",
+                        scan_number,
+                        format.length(diameter.min, None),
+                        format.length(diameter.max, None),
+                    ))
+                    .await?;
+
+                    let _ = self.progress_tx.send(Progress::of(
+                        diameter.b_scan_end,
+                        res.a_scan_count,
+                        scan_number,
+                    ));
 
                     scan_number += 1;
                 }
-
-                file.write_all(output.as_bytes()).await?;
-
-                let _ = self.progress_tx.send(Progress::Idle);
             }
+            // Only exported as JSON
+            TaskInputType::Bundle(_) => unreachable!(),
             TaskInputType::Mesh(mesh) => {
                 // Save in OBJ format
-                let mut file = fs::File::create(&self.path).await?;
-
                 let Some(res) = mesh.request(requests::Mesh).await else {
                     return Ok(());
                 };
 
-                let Some(mut rx) = res.subscribe() else {
+                let Some(mut rx) = res.data.subscribe() else {
                     return Err(anyhow!("Failed to subscribe to Mesh"));
                 };
 
-                let _ = self.progress_tx.send(Progress::Working(None));
+                let _ = self.progress_tx.send(Progress::of(0, res.a_scan_count, 0));
 
-                file.write_all(b"o Lumen\n").await?;
+                let mut chunks = 0;
 
-                let mut mesh_number = 0;
+                sink.write_text(ObjWriter::HEADER).await?;
+
+                let mut writer = ObjWriter::new();
 
                 loop {
                     let mesh = match rx.recv().await {
@@ -412,40 +923,935 @@ impl NodeTask for Task {
                         Ok(mesh) => mesh,
                     };
 
-                    let mut output = String::new();
-
-                    for LumenVertex {
-                        position: pos,
-                        normal,
-                    } in mesh.vertices.iter()
-                    {
-                        output += &format!(
-                            "v {} {} {}\nvn {} {} {}\n",
-                            pos.x, pos.y, pos.z, normal.x, normal.y, normal.z
-                        );
-                    }
+                    let output = writer.write_chunk(&mesh)?;
 
-                    for face in mesh.indices.chunks_exact(3) {
-                        let face = [
-                            face[0] + mesh_number + 1,
-                            face[1] + mesh_number + 1,
-                            face[2] + mesh_number + 1,
-                        ];
-                        output += &format!(
-                            "f {}//{} {}//{} {}//{}\n",
-                            face[0], face[0], face[1], face[1], face[2], face[2]
-                        );
-                    }
+                    sink.write_text(&output).await?;
+
+                    chunks += 1;
+                    let _ = self.progress_tx.send(Progress::of(
+                        mesh.a_scan_end,
+                        res.a_scan_count,
+                        chunks,
+                    ));
+                }
+            }
+        }
+
+        sink.finish().await?;
+        let _ = self.progress_tx.send(Progress::Idle);
+
+        Ok(())
+    }
 
-                    file.write_all(output.as_bytes()).await?;
+    /// Receives the input and collects it into one [PullbackDocument].
+    /// [None], if the input is not available.
+    async fn collect_document(&mut self) -> anyhow::Result<Option<PullbackDocument>> {
+        let bundle = match &mut self.input {
+            TaskInputType::BScanSegmentation(input) => input
+                .request(requests::BScanSegmentation)
+                .await
+                .map(|res| requests::BundleResponse {
+                    a_scan_count: res.a_scan_count,
+                    b_scan_segmentation: Some(res),
+                    lumen: None,
+                    diameter: None,
+                    area: None,
+                }),
+            TaskInputType::MScanSegmentation(input) => input
+                .request(requests::MScanSegmentation)
+                .await
+                .map(|res| requests::BundleResponse {
+                    a_scan_count: res.a_scan_count,
+                    b_scan_segmentation: None,
+                    lumen: Some(res),
+                    diameter: None,
+                    area: None,
+                }),
+            TaskInputType::Diameter(input) => {
+                input
+                    .request(requests::Diameter)
+                    .await
+                    .map(|res| requests::BundleResponse {
+                        a_scan_count: res.a_scan_count,
+                        b_scan_segmentation: None,
+                        lumen: None,
+                        diameter: Some(res),
+                        area: None,
+                    })
+            }
+            TaskInputType::Bundle(input) => input.request(requests::Bundle).await,
+            input => {
+                return Err(anyhow!(
+                    "{} can not be exported as JSON",
+                    input.pipeline_type()
+                ))
+            }
+        };
+        let Some(bundle) = bundle else {
+            return Ok(None);
+        };
+
+        let _ = self.progress_tx.send(Progress::Working(None));
+
+        // The streams are received at the same time, so none of them lags
+        let boundaries = async {
+            let Some(res) = &bundle.b_scan_segmentation else {
+                return Ok(None);
+            };
+            let mut boundaries = Vec::new();
+            receive_all(&res.data, "BScanSegmentation", |b_scan| {
+                boundaries.push(b_scan)
+            })
+            .await?;
+            anyhow::Ok(Some(boundaries))
+        };
+        let contour = async {
+            let Some(res) = &bundle.lumen else {
+                return Ok(None);
+            };
+            let mut sampler = ContourSampler::new(self.contour_stride, res.a_scan_samples);
+            receive_all(&res.data, "MScanSegmentation", |chunk| {
+                sampler.push(chunk.as_slice())
+            })
+            .await?;
+            anyhow::Ok(Some(sampler.finish()))
+        };
+        let diameters = async {
+            let Some(res) = &bundle.diameter else {
+                return Ok(None);
+            };
+            let mut diameters = Vec::new();
+            receive_all(&res.data, "Diameter", |diameter| diameters.push(diameter)).await?;
+            anyhow::Ok(Some(diameters))
+        };
+        let (boundaries, contour, diameters) = futures::try_join!(boundaries, contour, diameters)?;
+
+        let areas = bundle.area.map(|area| area.as_ref().clone().cast::<f32>());
+
+        let mut document = PullbackDocument::new(bundle.a_scan_count, self.provenance);
+        document.diameters = diameters.map(|diameters| {
+            pullback_format::diameter_entries(
+                &diameters,
+                boundaries.as_deref(),
+                areas.as_ref().map(|areas| areas.as_slice()),
+            )
+        });
+        document.b_scan_boundaries = boundaries;
+        document.lumen_contour = contour;
+
+        Ok(Some(document))
+    }
 
-                    mesh_number += mesh.vertices.len() as u32;
+    /// Writes raw or processed M scans in [Self::scan_data_type] and
+    /// [Self::endianness], see [crate::pipeline::raw_format]. With `a_scans`,
+    /// only these A scans are written.
+    async fn write_scans(
+        &self,
+        sink: &mut Sink,
+        mut rx: queue_channel::Receiver<Arc<DataMatrix>>,
+        a_scan_samples: usize,
+        expected_a_scan_count: usize,
+        a_scans: Option<Range<usize>>,
+    ) -> anyhow::Result<()> {
+        let expected_a_scan_count = match &a_scans {
+            Some(a_scans) => a_scans
+                .end
+                .min(expected_a_scan_count)
+                .saturating_sub(a_scans.start),
+            None => expected_a_scan_count,
+        };
+
+        let header = |a_scan_count| RawHeader {
+            endianness: self.endianness,
+            data_type: self.scan_data_type,
+            a_scan_samples,
+            a_scan_count,
+        };
+
+        if let (true, Sink::File(file)) = (self.header, &mut *sink) {
+            file.write_all(&header(expected_a_scan_count).to_bytes())
+                .await?;
+        }
+
+        let _ = self
+            .progress_tx
+            .send(Progress::of(0, expected_a_scan_count, 0));
+
+        let mut a_scan_count = 0;
+        // A scans received, including the ones not selected
+        let mut received = 0;
+
+        loop {
+            let scan = match rx.recv().await {
+                Err(RecvError::Closed) => break,
+                // The .part file is not renamed, so it stays marked incomplete
+                Err(RecvError::Aborted) => Err(anyhow!(
+                    "The input ended early at A scan {}, the export is incomplete with {} of \
+                     {} A scans",
+                    received,
+                    a_scan_count,
+                    expected_a_scan_count
+                ))?,
+                Err(e) => Err(e)?,
+                Ok(scan) => scan,
+            };
+
+            let chunk = received..received + scan.ncols();
+            received = chunk.end;
+
+            let columns = match &a_scans {
+                Some(a_scans) => {
+                    let start = chunk.start.max(a_scans.start);
+                    let end = chunk.end.min(a_scans.end);
+                    if start >= end {
+                        // Keeps receiving, so the sender is not cut off
+                        continue;
+                    }
+                    Some((start - chunk.start, end - start))
                 }
+                None => None,
+            };
+
+            let (data_type, endianness) = (self.scan_data_type, self.endianness);
+            let scan = priority::spawn_blocking(move || {
+                let mut scan = match columns {
+                    Some((start, count)) => scan.columns(start, count).cast_rescale_par(data_type),
+                    None => scan.cast_rescale_par(data_type),
+                };
+                endianness.convert(scan.as_mut_u8_slice(), data_type);
+                scan
+            })
+            .await?;
+
+            sink.write(
+                self.scan_data_type,
+                scan.nrows(),
+                scan.ncols(),
+                scan.as_u8_slice(),
+            )
+            .await?;
+
+            a_scan_count += scan.ncols();
+            let _ = self.progress_tx.send(Progress::of(
+                a_scan_count,
+                expected_a_scan_count,
+                a_scan_count,
+            ));
+        }
 
-                let _ = self.progress_tx.send(Progress::Idle);
+        // The response may announce a different count than what was streamed
+        if let (true, Sink::File(file)) = (self.header, sink) {
+            if a_scan_count != expected_a_scan_count {
+                file.rewind().await?;
+                file.write_all(&header(a_scan_count).to_bytes()).await?;
             }
         }
 
         Ok(())
     }
 }
+
+/// Receives every item of `data`, passing it to `f`. `name` is the request
+/// type, for errors.
+async fn receive_all<T: Clone>(
+    data: &StreamedResponse<T>,
+    name: &str,
+    mut f: impl FnMut(T),
+) -> anyhow::Result<()> {
+    let Some(mut rx) = data.subscribe() else {
+        return Err(anyhow!("Failed to subscribe to {}", name));
+    };
+
+    loop {
+        match rx.recv().await {
+            Err(RecvError::Closed) => break,
+            Err(e) => Err(e)?,
+            Ok(item) => f(item),
+        }
+    }
+
+    Ok(())
+}
+
+// MARK: Sink
+
+/// Where an export writes to.
+enum Sink {
+    File(fs::File),
+    /// Every write is sent as one frame, see [crate::pipeline::stream_format].
+    Stream(FrameWriter),
+}
+
+impl Sink {
+    /// Writes `bytes` of `rows` times `cols` values of `data_type`.
+    async fn write(
+        &mut self,
+        data_type: DataType,
+        rows: usize,
+        cols: usize,
+        bytes: &[u8],
+    ) -> anyhow::Result<()> {
+        match self {
+            Sink::File(file) => Ok(file.write_all(bytes).await?),
+            Sink::Stream(writer) => writer.write(data_type, rows, cols, bytes).await,
+        }
+    }
+
+    /// Writes text in a single row of bytes.
+    async fn write_text(&mut self, text: &str) -> anyhow::Result<()> {
+        self.write(DataType::U8, 1, text.len(), text.as_bytes())
+            .await
+    }
+
+    /// Waits until everything is written. File writes only finish in the
+    /// background otherwise.
+    async fn finish(&mut self) -> anyhow::Result<()> {
+        match self {
+            Sink::File(file) => Ok(file.flush().await?),
+            Sink::Stream(writer) => writer.finish().await,
+        }
+    }
+}
+
+// MARK: Provenance
+
+/// Updates [Node::provenance] of the output nodes exporting segmentations or
+/// [ExportFormat::Json].
+/// Outputs depending on `deferred` nodes keep theirs, because the tasks of
+/// these nodes still run with their previous settings.
+pub fn update_provenance(pipeline: &mut Pipeline, deferred: &HashSet<NodeId>) {
+    let mut provenances = Vec::new();
+
+    for (node_id, node) in &pipeline.nodes {
+        let Some(node) = node.as_any().downcast_ref::<Node>() else {
+            continue;
+        };
+        if node.input_type != PipelineDataType::MScanSegmentation
+            && node.export_format() != ExportFormat::Json
+        {
+            continue;
+        }
+
+        let provenance = match node.input.connection() {
+            Some(input) => {
+                let upstream = pipeline.upstream(input.node_id);
+                if upstream.iter().any(|node_id| deferred.contains(node_id)) {
+                    continue;
+                }
+                Some(pipeline.provenance(input))
+            }
+            None => None,
+        };
+        provenances.push((*node_id, provenance));
+    }
+
+    for (node_id, provenance) in provenances {
+        if let Some(node) = pipeline.nodes.get_mut(&node_id) {
+            if let Some(node) = node.as_any_mut().downcast_mut::<Node>() {
+                node.provenance = provenance;
+            }
+        }
+    }
+}
+
+// MARK: ObjWriter
+
+/// Converts a streamed [LumenMesh] into the OBJ format, chunk by chunk.
+///
+/// Indices in OBJ files are global and start at 1, so this keeps track of the
+/// number of vertices and normals written so far. Chunks may be stitched to
+/// their previous chunk, see [LumenMesh::stitched_vertices].
+struct ObjWriter {
+    vertex_count: u32,
+    normal_count: u32,
+    /// Vertices of the previous chunk, that can be referenced by stitching.
+    previous_chunk_vertices: u32,
+    chunk_number: usize,
+}
+
+impl ObjWriter {
+    const HEADER: &'static str = "o Lumen\n";
+
+    fn new() -> Self {
+        Self {
+            vertex_count: 0,
+            normal_count: 0,
+            previous_chunk_vertices: 0,
+            chunk_number: 0,
+        }
+    }
+
+    /// Returns the OBJ statements of one chunk. Fails, if the chunk references
+    /// vertices that do not exist.
+    fn write_chunk(&mut self, mesh: &LumenMesh) -> anyhow::Result<String> {
+        self.chunk_number += 1;
+
+        let stitched = mesh.stitched_vertices;
+        if stitched > self.previous_chunk_vertices {
+            return Err(anyhow!(
+                "Mesh chunk {} stitches {} vertices, but the previous chunk has only {}",
+                self.chunk_number,
+                stitched,
+                self.previous_chunk_vertices
+            ));
+        }
+
+        if !mesh.indices.chunks_exact(3).remainder().is_empty() {
+            return Err(anyhow!(
+                "Mesh chunk {} has {} indices, which do not form triangles",
+                self.chunk_number,
+                mesh.indices.len()
+            ));
+        }
+
+        let referenced = stitched + mesh.vertices.len() as u32;
+        if let Some(index) = mesh.indices.iter().find(|&&i| i >= referenced) {
+            return Err(anyhow!(
+                "Mesh chunk {} has index {} out of range, it only references {} vertices",
+                self.chunk_number,
+                index,
+                referenced
+            ));
+        }
+
+        let mut output = String::new();
+
+        for LumenVertex {
+            position: pos,
+            normal,
+        } in mesh.vertices.iter()
+        {
+            output += &format!(
+                "v {} {} {}\nvn {} {} {}\n",
+                pos.x, pos.y, pos.z, normal.x, normal.y, normal.z
+            );
+        }
+
+        // Index 0 of this chunk is the first stitched vertex, which was
+        // written last by the previous chunk
+        let vertex_base = self.vertex_count - stitched + 1;
+        let normal_base = self.normal_count - stitched + 1;
+
+        for face in mesh.indices.chunks_exact(3) {
+            output += "f";
+            for &i in face {
+                output += &format!(" {}//{}", vertex_base + i, normal_base + i);
+            }
+            output += "\n";
+        }
+
+        self.vertex_count += mesh.vertices.len() as u32;
+        self.normal_count += mesh.vertices.len() as u32;
+        self.previous_chunk_vertices = mesh.vertices.len() as u32;
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, time::Duration};
+
+    use nalgebra::Vector3;
+    use serde_json::json;
+
+    use crate::pipeline::{stream_format, Pipeline, PipelineExecutor};
+
+    use super::*;
+
+    fn vertex(x: f32, z: f32) -> LumenVertex {
+        LumenVertex {
+            position: Vector3::new(x, 0.0, z),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+        }
+    }
+
+    /// A strip of quads along x, with 2 vertices at each x.
+    fn strip(start: f32, quads: u32, stitched: bool) -> LumenMesh {
+        let columns = if stitched { quads } else { quads + 1 };
+        let first = if stitched { 1.0 } else { 0.0 };
+
+        let vertices = (0..columns)
+            .flat_map(|c| {
+                let x = start + first + c as f32;
+                [vertex(x, 0.0), vertex(x, 1.0)]
+            })
+            .collect();
+
+        let indices = (0..quads)
+            .flat_map(|q| {
+                let i = q * 2;
+                [i, i + 1, i + 2, i + 2, i + 1, i + 3]
+            })
+            .collect();
+
+        LumenMesh {
+            vertices,
+            indices,
+            stitched_vertices: if stitched { 2 } else { 0 },
+            a_scan_end: 0,
+        }
+    }
+
+    /// Minimal OBJ reader, returning the number of vertices, normals and
+    /// the faces as pairs of vertex and normal indices.
+    fn read_obj(obj: &str) -> (usize, usize, Vec<[(usize, usize); 3]>) {
+        let (mut vertices, mut normals, mut faces) = (0, 0, Vec::new());
+
+        for line in obj.lines() {
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Some("v") => vertices += 1,
+                Some("vn") => normals += 1,
+                Some("f") => {
+                    let face: Vec<_> = parts
+                        .map(|p| {
+                            let (v, n) = p.split_once("//").unwrap();
+                            (v.parse().unwrap(), n.parse().unwrap())
+                        })
+                        .collect();
+                    faces.push(face.try_into().unwrap());
+                }
+                _ => {}
+            }
+        }
+
+        (vertices, normals, faces)
+    }
+
+    #[test]
+    fn obj_roundtrip() {
+        let chunks = [
+            strip(0.0, 3, false),
+            strip(3.0, 2, true),
+            strip(10.0, 1, false),
+        ];
+
+        let mut writer = ObjWriter::new();
+        let mut obj = ObjWriter::HEADER.to_string();
+        for chunk in &chunks {
+            obj += &writer.write_chunk(chunk).unwrap();
+        }
+
+        let (vertices, normals, faces) = read_obj(&obj);
+
+        assert_eq!(vertices, 8 + 4 + 4);
+        assert_eq!(normals, vertices);
+        assert_eq!(faces.len(), 2 * (3 + 2 + 1));
+
+        for &(v, n) in faces.iter().flatten() {
+            assert!((1..=vertices).contains(&v));
+            assert!((1..=normals).contains(&n));
+        }
+
+        // The first face of the stitched chunk starts at the last two
+        // vertices of the first chunk
+        assert_eq!(faces[6][0], (7, 7));
+        assert_eq!(faces[6][1], (8, 8));
+        assert_eq!(faces[6][2], (9, 9));
+    }
+
+    #[test]
+    fn progress_of() {
+        assert_eq!(Progress::of(0, 200, 0), Progress::Working(Some(0.0)));
+        assert_eq!(Progress::of(50, 200, 1), Progress::Working(Some(0.25)));
+        // Segmentations may overshoot slightly
+        assert_eq!(Progress::of(210, 200, 9), Progress::Working(Some(1.0)));
+        assert_eq!(Progress::of(50, 0, 3), Progress::Items(3));
+    }
+
+    #[test]
+    fn invalid_chunks() {
+        let mut writer = ObjWriter::new();
+
+        let error = writer
+            .write_chunk(&strip(0.0, 1, true))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("chunk 1"), "{}", error);
+
+        let mut chunk = strip(0.0, 1, false);
+        chunk.indices[2] = 4;
+        let error = writer.write_chunk(&chunk).unwrap_err().to_string();
+        assert!(error.contains("chunk 2"), "{}", error);
+    }
+
+    /// Runs the pipeline described by `nodes` and waits until every output
+    /// node saved its input.
+    async fn export(nodes: serde_json::Value) {
+        let mut pipeline: Pipeline = serde_json::from_value(json!({ "nodes": nodes })).unwrap();
+
+        let mut executor = PipelineExecutor::new();
+        executor.update(&mut pipeline);
+
+        for node in pipeline.nodes.values_mut() {
+            if let Some(node) = node.as_any_mut().downcast_mut::<Node>() {
+                node.save();
+
+                let mut saves_rx = node.saves_rx.clone().unwrap();
+                tokio::time::timeout(
+                    Duration::from_secs(60),
+                    saves_rx.wait_for(|&saves| saves > 0),
+                )
+                .await
+                .unwrap()
+                .unwrap();
+            }
+        }
+    }
+
+    fn binary_input(path: &Path, data_type: DataType, endianness: Endianness) -> serde_json::Value {
+        json!({
+            "type": "binary_input",
+            "path": path,
+            "input_type": "RawMScan",
+            "data_type": data_type,
+            "a_scan_length": 256,
+            "endianness": endianness,
+        })
+    }
+
+    fn output(
+        path: &Path,
+        data_type: DataType,
+        endianness: Endianness,
+        header: bool,
+    ) -> serde_json::Value {
+        json!({
+            "type": "output",
+            "path": path,
+            "input_type": "RawMScan",
+            "scan_data_type": data_type,
+            "endianness": endianness,
+            "header": header,
+            "input": {
+                "value": null,
+                "connection": { "node_id": 1, "output_id": 0, "type_id": 0 },
+            },
+        })
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn raw_roundtrip() {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden/raw.bin");
+        let dir = std::env::temp_dir().join(format!("ivoct_raw_roundtrip_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for data_type in DataType::VALUES {
+            for endianness in Endianness::VALUES {
+                for header in [false, true] {
+                    let exported = dir.join("exported.bin");
+                    let reference = dir.join("reference.bin");
+                    let reimported = dir.join("reimported.bin");
+
+                    export(json!({
+                        "1": binary_input(&source, DataType::U16, Endianness::Little),
+                        "2": output(&exported, data_type, endianness, header),
+                        "3": output(&reference, data_type, Endianness::Little, false),
+                    }))
+                    .await;
+
+                    // With a header, the settings of the input are ignored
+                    let input = match header {
+                        true => binary_input(&exported, DataType::U8, Endianness::Little),
+                        false => binary_input(&exported, data_type, endianness),
+                    };
+                    export(json!({
+                        "1": input,
+                        "2": output(&reimported, data_type, Endianness::Little, false),
+                    }))
+                    .await;
+
+                    let case = format!("{:?} {:?} header: {}", data_type, endianness, header);
+
+                    let exported = std::fs::read(&exported).unwrap();
+                    let reference = std::fs::read(&reference).unwrap();
+                    let reimported = std::fs::read(&reimported).unwrap();
+
+                    assert!(!reference.is_empty(), "{}", case);
+                    assert_eq!(reimported, reference, "{}", case);
+
+                    let data = match header {
+                        true => {
+                            let header = RawHeader::parse(&exported).unwrap().unwrap();
+                            assert_eq!(header.data_type, data_type, "{}", case);
+                            assert_eq!(header.endianness, endianness, "{}", case);
+                            assert_eq!(header.a_scan_samples, 256, "{}", case);
+                            assert_eq!(
+                                header.a_scan_count,
+                                reference.len() / 256 / data_type.size(),
+                                "{}",
+                                case
+                            );
+                            &exported[RawHeader::SIZE..]
+                        }
+                        false => &exported[..],
+                    };
+
+                    let mut swapped = reference.clone();
+                    endianness.convert(&mut swapped, data_type);
+                    assert_eq!(data, swapped, "{}", case);
+                }
+            }
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn json_bundle() {
+        let dir = std::env::temp_dir().join(format!("ivoct_json_bundle_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let boundaries = dir.join("boundaries.txt");
+        std::fs::write(&boundaries, "0\n10\n20\n30\n").unwrap();
+        let lumen = dir.join("lumen.bin");
+        let depths = (0..30).map(|i| 100 + i).collect::<Vec<u32>>();
+        std::fs::write(&lumen, bytemuck::cast_slice(&depths)).unwrap();
+        std::fs::write(
+            SegmentationSidecar::path(&lumen),
+            serde_json::to_string(&SegmentationSidecar::new(30, 512, None, vec![7, 23])).unwrap(),
+        )
+        .unwrap();
+
+        let output = |path: &Path, node_id: usize, type_id: usize| {
+            json!({
+                "type": "output",
+                "path": path,
+                "input_type": PipelineDataType::from(TypeId::from(type_id)),
+                "scan_data_type": "U8",
+                "format": "Json",
+                "contour_stride": 4,
+                "input": {
+                    "value": null,
+                    "connection": { "node_id": node_id, "output_id": 0, "type_id": type_id },
+                },
+            })
+        };
+        let bundled = dir.join("bundled.json");
+        let single = dir.join("single.json");
+
+        export(json!({
+            "1": {
+                "type": "b_scan_boundaries_input",
+                "path": boundaries,
+                "format": "Text",
+                "m_scan": { "value": null, "connection": null },
+            },
+            "2": {
+                "type": "segmentation_input",
+                "path": lumen,
+                "m_scan": { "value": null, "connection": null },
+            },
+            "3": {
+                "type": "bundle",
+                "b_scans": {
+                    "value": null,
+                    "connection": { "node_id": 1, "output_id": 0, "type_id": 3 },
+                },
+                "lumen": {
+                    "value": null,
+                    "connection": { "node_id": 2, "output_id": 0, "type_id": 4 },
+                },
+                "diameter": { "value": null, "connection": null },
+            },
+            "4": output(&bundled, 3, 7),
+            "5": output(&single, 1, 3),
+        }))
+        .await;
+
+        let document: PullbackDocument =
+            serde_json::from_slice(&std::fs::read(&bundled).unwrap()).unwrap();
+        assert_eq!(document.a_scan_count, 30);
+        assert_eq!(document.b_scan_boundaries, Some(vec![0, 10, 20, 30]));
+        let contour = document.lumen_contour.unwrap();
+        assert_eq!(contour.depths, [100, 104, 108, 112, 116, 120, 124, 128]);
+        assert_eq!(contour.a_scan_samples, 512);
+        assert_eq!(document.diameters, None);
+
+        let document: PullbackDocument =
+            serde_json::from_slice(&std::fs::read(&single).unwrap()).unwrap();
+        assert_eq!(document.b_scan_boundaries, Some(vec![0, 10, 20, 30]));
+        assert_eq!(document.lumen_contour, None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn separate_runs() {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden/raw.bin");
+        let dir = std::env::temp_dir().join(format!("ivoct_separate_runs_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let exported = dir.join("exported.bin");
+        let mut output = output(&exported, DataType::U16, Endianness::Little, false);
+        output["separate_runs"] = json!(true);
+
+        let mut pipeline: Pipeline = serde_json::from_value(json!({ "nodes": {
+            "1": binary_input(&source, DataType::U16, Endianness::Little),
+            "2": output,
+        }}))
+        .unwrap();
+
+        let mut executor = PipelineExecutor::new();
+        executor.update(&mut pipeline);
+
+        let node = pipeline
+            .nodes
+            .values_mut()
+            .find_map(|node| node.as_any_mut().downcast_mut::<Node>())
+            .unwrap();
+        node.save_in_session("run_test");
+
+        let mut saves_rx = node.saves_rx.clone().unwrap();
+        tokio::time::timeout(
+            Duration::from_secs(60),
+            saves_rx.wait_for(|&saves| saves > 0),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        let target = dir.join("run_test/exported.bin");
+        assert_eq!(node.session_path("run_test"), target);
+        assert_eq!(
+            std::fs::read(&target).unwrap(),
+            std::fs::read(&source).unwrap()
+        );
+        assert!(!exported.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A local address, that nothing listens on.
+    fn free_address() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    /// Receives every frame of a streamed raw M scan and returns their
+    /// payloads.
+    async fn receive_scans(stream: &mut TcpStream) -> Vec<u8> {
+        let mut received = Vec::new();
+
+        while let Some((header, payload)) =
+            tokio::time::timeout(Duration::from_secs(60), stream_format::read_frame(stream))
+                .await
+                .unwrap()
+                .unwrap()
+        {
+            assert_eq!(header.pipeline_type, PipelineDataType::RawMScan);
+            assert_eq!(header.data_type, DataType::U16);
+            assert_eq!(header.rows, 256);
+            assert_eq!(payload.len(), header.rows * header.cols * 2);
+            received.extend(payload);
+        }
+
+        received
+    }
+
+    fn output_node(pipeline: &mut Pipeline) -> &mut Node {
+        pipeline
+            .nodes
+            .values_mut()
+            .find_map(|node| node.as_any_mut().downcast_mut::<Node>())
+            .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stream_to_clients() {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden/raw.bin");
+        let address = free_address();
+
+        // The header of files is not streamed
+        let mut output = output(Path::new(""), DataType::U16, Endianness::Little, true);
+        output["destination"] = json!({ "Listen": address });
+
+        let mut pipeline: Pipeline = serde_json::from_value(json!({ "nodes": {
+            "1": binary_input(&source, DataType::U16, Endianness::Little),
+            "2": output,
+        }}))
+        .unwrap();
+
+        let mut executor = PipelineExecutor::new();
+        executor.update(&mut pipeline);
+
+        let node = output_node(&mut pipeline);
+        node.save();
+        let mut saves_rx = node.saves_rx.clone().unwrap();
+
+        let expected = std::fs::read(&source).unwrap();
+
+        // Every client gets a fresh export
+        for client in 1..=2 {
+            let mut stream = loop {
+                match TcpStream::connect(&address).await {
+                    Ok(stream) => break stream,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+                }
+            };
+
+            assert_eq!(receive_scans(&mut stream).await, expected);
+
+            tokio::time::timeout(
+                Duration::from_secs(60),
+                saves_rx.wait_for(|&saves| saves >= client),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        }
+
+        let node = output_node(&mut pipeline);
+        node.stop();
+        let mut progress_rx = node.progress_rx.clone().unwrap();
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            progress_rx.wait_for(|progress| *progress == Progress::Idle),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn retry_failed_stream() {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden/raw.bin");
+        let address = free_address();
+
+        let mut output = output(Path::new(""), DataType::U16, Endianness::Little, false);
+        output["destination"] = json!({ "Connect": address });
+
+        let mut pipeline: Pipeline = serde_json::from_value(json!({ "nodes": {
+            "1": binary_input(&source, DataType::U16, Endianness::Little),
+            "2": output,
+        }}))
+        .unwrap();
+
+        let mut executor = PipelineExecutor::new();
+        executor.update(&mut pipeline);
+
+        // Nothing listens yet
+        let node = output_node(&mut pipeline);
+        node.save();
+        let mut progress_rx = node.progress_rx.clone().unwrap();
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            progress_rx.wait_for(|progress| matches!(progress, Progress::Failed(_))),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        let listener = TcpListener::bind(&address).await.unwrap();
+
+        output_node(&mut pipeline).retry();
+        executor.update(&mut pipeline);
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        assert_eq!(
+            receive_scans(&mut stream).await,
+            std::fs::read(&source).unwrap()
+        );
+    }
+}
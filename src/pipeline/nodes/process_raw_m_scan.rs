@@ -11,7 +11,11 @@ use rustfft::{num_complex::Complex32, FftPlanner};
 use tokio::sync::watch;
 
 use crate::{
-    pipeline::types::{DataMatrix, DataType},
+    pipeline::{
+        chunking::{ChunkLimits, ChunkedSender},
+        determinism,
+        types::{DataMatrix, DataType, F32Vector},
+    },
     queue_channel::error::RecvError,
 };
 
@@ -29,29 +33,74 @@ impl_enum_from_into_id_types!(InputId, [graph::InputId], {
     2 => Chirp,
 });
 
+/// How the value range of the processed M scan is found, that gets rescaled to
+/// `0..1`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RescaleMode {
+    /// Bounds are found in the first chunk, ignoring [Node::rescale_cutoff]
+    /// values at the top and bottom. Depends on the size of the first chunk,
+    /// so [RescaleMode::PERCENTILE_DEFAULT] is used in deterministic mode, see
+    /// [crate::pipeline::determinism].
+    #[default]
+    FirstChunk,
+    /// Bounds are percentiles of a random sample of the values of the first
+    /// `sample_a_scans` A scans. Chunks are held back until the bounds are
+    /// known.
+    Percentile {
+        /// Lower percentile, in `0..=100`.
+        low: f32,
+        /// Upper percentile, in `0..=100`.
+        high: f32,
+        sample_a_scans: usize,
+    },
+    /// Bounds are given, for reproducible results.
+    Fixed { lower: f32, upper: f32 },
+}
+
+impl RescaleMode {
+    pub const PERCENTILE_DEFAULT: RescaleMode = RescaleMode::Percentile {
+        low: 0.01,
+        high: 99.99,
+        sample_a_scans: 5000,
+    };
+}
+
 // MARK: Node
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+// Pipelines saved by older versions lack newer fields
+#[serde(default)]
 pub struct Node {
     /// Factor every value is multiplied with bevor processing.
     pub factor: f64,
     /// How many values to ignore when finding the value range of the data.
     pub rescale_cutoff: usize,
+    pub rescale_mode: RescaleMode,
 
     #[serde(skip)]
     pub progress_rx: Option<watch::Receiver<Option<f32>>>,
+    /// Bounds of the value range the last M scan was rescaled with.
+    #[serde(skip)]
+    pub bounds_rx: Option<watch::Receiver<Option<(f32, f32)>>>,
 
     pub raw_scan: NodeInput<()>,
     pub offset: NodeInput<()>,
     pub chirp: NodeInput<()>,
 }
 
+impl Node {
+    /// Factor used by the MATLAB implementation.
+    pub const DEFAULT_FACTOR: f64 = 540.0;
+}
+
 impl Default for Node {
     fn default() -> Self {
         Self {
-            factor: 540.0,
+            factor: Self::DEFAULT_FACTOR,
             rescale_cutoff: 100,
+            rescale_mode: RescaleMode::default(),
             progress_rx: None,
+            bounds_rx: None,
             raw_scan: NodeInput::default(),
             offset: NodeInput::default(),
             chirp: NodeInput::default(),
@@ -79,24 +128,110 @@ impl PipelineNode for Node {
     }
 
     fn changed(&self, other: &Self) -> bool {
-        self.factor != other.factor || self.rescale_cutoff != other.rescale_cutoff
+        self.factor != other.factor
+            || self.rescale_cutoff != other.rescale_cutoff
+            || self.rescale_mode != other.rescale_mode
     }
 
     fn get_output_id_for_view_request(&self) -> Option<(OutputIdSingle, impl Into<TypeId>)> {
         Some((OutputIdSingle, PipelineDataType::MScan))
     }
 
+    fn type_handling(&self, input_id: InputId, data_type: DataType) -> TypeHandling {
+        // The FFT works on floating point values
+        match input_id {
+            InputId::RawMScan if data_type != DataType::F32 => TypeHandling::PromotesToF32,
+            _ => TypeHandling::AsIs,
+        }
+    }
+
+    fn estimate_memory(&self, upstream: &UpstreamStats) -> MemoryEstimate {
+        // The FFT keeps half of the samples
+        let output = UpstreamStats {
+            samples: upstream.samples / 2,
+            data_type: DataType::F32,
+            ..*upstream
+        };
+
+        let mut estimate = MemoryEstimate::streaming(upstream, output);
+        // The raw chunk is cast to F32 first
+        estimate.per_chunk += UpstreamStats {
+            data_type: DataType::F32,
+            ..*upstream
+        }
+        .chunk_bytes();
+
+        // Chunks are held back, until the percentiles are sampled
+        if let RescaleMode::Percentile { sample_a_scans, .. } = self.rescale_mode {
+            let held_back = UpstreamStats {
+                a_scan_count: sample_a_scans.min(output.a_scan_count),
+                ..output
+            };
+            estimate = estimate.with_buffers(held_back.stream_bytes());
+        }
+        estimate
+    }
+
+    fn validate(&mut self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+
+        validation::clamp(&mut issues, "Factor", &mut self.factor, 1.0..=10000.0);
+
+        match &mut self.rescale_mode {
+            RescaleMode::FirstChunk => {
+                validation::at_least(&mut issues, "Rescale Cutoff", &mut self.rescale_cutoff, 1);
+            }
+            RescaleMode::Percentile {
+                low,
+                high,
+                sample_a_scans,
+            } => {
+                validation::clamp(&mut issues, "Low", low, 0.0..=100.0);
+                validation::clamp(&mut issues, "High", high, 0.0..=100.0);
+                if high < low {
+                    issues.push(ValidationIssue::corrected(
+                        "High",
+                        format!("{} is below the low percentile, set to {}", high, low),
+                    ));
+                    *high = *low;
+                }
+                validation::at_least(&mut issues, "Sampled A Scans", sample_a_scans, 1);
+            }
+            RescaleMode::Fixed { lower, upper } => {
+                if lower > upper {
+                    issues.push(ValidationIssue::corrected(
+                        "Bounds",
+                        format!("Lower bound {} is above the upper one, swapped them", lower),
+                    ));
+                    std::mem::swap(lower, upper);
+                } else if lower == upper {
+                    issues.push(ValidationIssue::rejected(
+                        "Bounds",
+                        format!("Both bounds are {}, so nothing can be rescaled", lower),
+                    ));
+                }
+            }
+        }
+
+        issues
+    }
+
     fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
         let m_scan_out = builder.output(OutputIdSingle);
 
         let (progress_tx, progress_rx) = watch::channel(None);
 
+        let (bounds_tx, bounds_rx) = watch::channel(None);
+
         self.progress_rx = Some(progress_rx);
+        self.bounds_rx = Some(bounds_rx);
 
         builder.task(Task {
             factor: self.factor,
             rescale_cutoff: self.rescale_cutoff,
+            rescale_mode: self.rescale_mode,
             progress_tx,
+            bounds_tx,
             m_scan_out,
             raw_scan_in: TaskInput::default(),
             offset_in: TaskInput::default(),
@@ -110,8 +245,10 @@ impl PipelineNode for Node {
 struct Task {
     factor: f64,
     rescale_cutoff: usize,
+    rescale_mode: RescaleMode,
 
     progress_tx: watch::Sender<Option<f32>>,
+    bounds_tx: watch::Sender<Option<(f32, f32)>>,
 
     m_scan_out: TaskOutput<requests::MScan>,
 
@@ -143,10 +280,12 @@ impl NodeTask for Task {
     fn sync_node(&mut self, node: &Self::PipelineNode) {
         self.factor = node.factor;
         self.rescale_cutoff = node.rescale_cutoff;
+        self.rescale_mode = node.rescale_mode;
     }
 
     fn invalidate(&mut self, _cause: InvalidationCause) {
         let _ = self.progress_tx.send(None);
+        let _ = self.bounds_tx.send(None);
     }
 
     async fn run(&mut self) -> anyhow::Result<()> {
@@ -166,8 +305,15 @@ impl NodeTask for Task {
 
             let factor = self.factor as f32;
             let rescale_cutoff = self.rescale_cutoff;
+            let rescale_mode = match self.rescale_mode {
+                RescaleMode::FirstChunk if determinism::is_deterministic() => {
+                    RescaleMode::PERCENTILE_DEFAULT
+                }
+                rescale_mode => rescale_mode,
+            };
 
-            let (res, tx) = requests::StreamedResponse::new(100);
+            let (res, tx) = requests::StreamedResponse::with_default_capacity();
+            let mut tx = ChunkedSender::new(tx, ChunkLimits::current());
 
             self.m_scan_out.respond(requests::MScanResponse {
                 data: res,
@@ -179,19 +325,27 @@ impl NodeTask for Task {
             let mut processed_a_scans = 0;
 
             struct Shared {
-                offset: Option<DVector<f32>>,
-                chirp: Option<DVector<f32>>,
-                lower: Option<f32>,
-                upper: Option<f32>,
+                offset: Option<F32Vector>,
+                chirp: Option<F32Vector>,
+                bounds: Option<(f32, f32)>,
+                sampler: Option<PercentileSampler>,
             }
 
             let shared = Arc::new(Mutex::new(Shared {
-                offset: offset.map(|o| (*o).clone().cast::<f32>() * factor),
-                chirp: chirp.map(|c| (*c).clone().cast::<f32>()),
-                lower: None,
-                upper: None,
+                // Calibration vectors are shared with their producer, instead
+                // of being copied for every M scan
+                offset: offset.map(|o| match factor == 1.0 {
+                    true => F32Vector::new(o),
+                    false => (o.as_f32().into_owned() * factor).into(),
+                }),
+                chirp: chirp.map(F32Vector::new),
+                bounds: None,
+                sampler: None,
             }));
 
+            // Chunks processed before the bounds are known
+            let mut held_back = Vec::new();
+
             loop {
                 let raw_scan = match raw_scan.recv().await {
                     Ok(raw_scan) => raw_scan,
@@ -201,7 +355,7 @@ impl NodeTask for Task {
 
                 let shared = shared.clone();
 
-                let m_scan = tokio::task::spawn_blocking(move || {
+                let (m_scan, bounds) = priority::spawn_blocking(move || {
                     let mut shared = shared.lock().unwrap();
 
                     let DataMatrix::F32(raw_scan) = raw_scan.cast_par(DataType::F32) else {
@@ -210,27 +364,41 @@ impl NodeTask for Task {
 
                     let mut m_scan = pre_process_raw_m_scan(
                         raw_scan,
-                        shared.offset.as_ref().map(DVector::as_view),
-                        shared.chirp.as_ref().map(DVector::as_view),
+                        shared.offset.as_ref().map(F32Vector::as_view),
+                        shared.chirp.as_ref().map(F32Vector::as_view),
                         factor,
                     );
 
-                    if shared.lower.is_none() || shared.upper.is_none() {
-                        let (l_lower, l_upper) = find_bounds_par(m_scan.as_slice(), rescale_cutoff);
-                        shared.lower = l_lower.last().copied();
-                        shared.upper = l_upper.last().copied();
+                    if shared.bounds.is_none() {
+                        shared.bounds = match rescale_mode {
+                            RescaleMode::FirstChunk => {
+                                let (l_lower, l_upper) =
+                                    find_bounds_par(m_scan.as_slice(), rescale_cutoff);
+                                l_lower.last().copied().zip(l_upper.last().copied())
+                            }
+                            RescaleMode::Percentile {
+                                low,
+                                high,
+                                sample_a_scans,
+                            } => {
+                                let sampler = shared
+                                    .sampler
+                                    .get_or_insert_with(|| PercentileSampler::new(sample_a_scans));
+                                sampler.add(&m_scan);
+                                match sampler.is_complete() {
+                                    true => sampler.bounds(low, high),
+                                    false => None,
+                                }
+                            }
+                            RescaleMode::Fixed { lower, upper } => Some((lower, upper)),
+                        };
                     }
 
-                    // Rescale every value
-                    if let (Some(lower), Some(upper)) = (shared.lower, shared.upper) {
-                        m_scan.par_column_iter_mut().for_each(|mut c| {
-                            for x in c.iter_mut() {
-                                *x = (*x - lower) / (upper - lower);
-                            }
-                        });
+                    if let Some(bounds) = shared.bounds {
+                        rescale(&mut m_scan, bounds);
                     }
 
-                    m_scan
+                    (m_scan, shared.bounds)
                 })
                 .await?;
 
@@ -239,8 +407,48 @@ impl NodeTask for Task {
                     .progress_tx
                     .send(Some(processed_a_scans as f32 / raw_res.a_scan_count as f32));
 
-                tx.send(Arc::new(DataMatrix::F32(m_scan)));
+                let Some(bounds) = bounds else {
+                    held_back.push(m_scan);
+                    continue;
+                };
+
+                if self.bounds_tx.borrow().is_none() {
+                    let _ = self.bounds_tx.send(Some(bounds));
+                }
+
+                if !held_back.is_empty() {
+                    let held_back = std::mem::take(&mut held_back);
+                    for m_scan in rescale_all(held_back, bounds).await? {
+                        tx.send(DataMatrix::F32(m_scan));
+                    }
+                }
+
+                tx.send(DataMatrix::F32(m_scan));
+            }
+
+            // The input had less A scans than should be sampled
+            if !held_back.is_empty() {
+                let bounds =
+                    shared.lock().unwrap().sampler.as_ref().and_then(
+                        |sampler| match rescale_mode {
+                            RescaleMode::Percentile { low, high, .. } => sampler.bounds(low, high),
+                            _ => None,
+                        },
+                    );
+
+                let held_back = match bounds {
+                    Some(bounds) => {
+                        let _ = self.bounds_tx.send(Some(bounds));
+                        rescale_all(held_back, bounds).await?
+                    }
+                    None => held_back,
+                };
+
+                for m_scan in held_back {
+                    tx.send(DataMatrix::F32(m_scan));
+                }
             }
+            tx.finish();
 
             let _ = self.progress_tx.send(None);
         }
@@ -251,6 +459,108 @@ impl NodeTask for Task {
 
 // MARK: Rescaling
 
+/// Maps the values in `bounds` to `0..1`.
+fn rescale(m_scan: &mut DMatrix<f32>, (lower, upper): (f32, f32)) {
+    m_scan.par_column_iter_mut().for_each(|mut c| {
+        for x in c.iter_mut() {
+            *x = (*x - lower) / (upper - lower);
+        }
+    });
+}
+
+async fn rescale_all(
+    mut m_scans: Vec<DMatrix<f32>>,
+    bounds: (f32, f32),
+) -> anyhow::Result<Vec<DMatrix<f32>>> {
+    Ok(priority::spawn_blocking(move || {
+        for m_scan in &mut m_scans {
+            rescale(m_scan, bounds);
+        }
+        m_scans
+    })
+    .await?)
+}
+
+/// Maximum number of values [PercentileSampler] keeps.
+const PERCENTILE_SAMPLE_SIZE: usize = 1 << 16;
+
+/// Collects a uniform random sample of the values of the first A scans of an M
+/// scan, using reservoir sampling. The sample only depends on the order of the
+/// values, not on how the M scan is split into chunks.
+struct PercentileSampler {
+    remaining_a_scans: usize,
+    sample: Vec<f32>,
+    /// Number of values offered to the sample.
+    seen: u64,
+    rng_state: u64,
+}
+
+impl PercentileSampler {
+    fn new(a_scans: usize) -> Self {
+        Self {
+            remaining_a_scans: a_scans,
+            sample: Vec::new(),
+            seen: 0,
+            rng_state: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.remaining_a_scans == 0
+    }
+
+    /// Adds the values of the A scans of `m_scan`, until enough A scans were
+    /// added.
+    fn add(&mut self, m_scan: &DMatrix<f32>) {
+        let a_scans = m_scan.ncols().min(self.remaining_a_scans);
+        self.remaining_a_scans -= a_scans;
+
+        for &v in m_scan.columns(0, a_scans).iter() {
+            if !v.is_finite() {
+                continue;
+            }
+
+            if self.sample.len() < PERCENTILE_SAMPLE_SIZE {
+                self.sample.push(v);
+            } else {
+                let j = self.next_random() % (self.seen + 1);
+                if let Some(slot) = self.sample.get_mut(j as usize) {
+                    *slot = v;
+                }
+            }
+            self.seen += 1;
+        }
+    }
+
+    /// Xorshift, so the sample is reproducible.
+    fn next_random(&mut self) -> u64 {
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng_state = x;
+        x
+    }
+
+    /// Percentiles `low` and `high` of the sampled values, [None] if nothing
+    /// was sampled.
+    fn bounds(&self, low: f32, high: f32) -> Option<(f32, f32)> {
+        if self.sample.is_empty() {
+            return None;
+        }
+
+        let mut sorted = self.sample.clone();
+        sorted.sort_by(f32::total_cmp);
+
+        let percentile = |p: f32| {
+            let i = (p.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f32).round();
+            sorted[i as usize]
+        };
+
+        Some((percentile(low), percentile(high)))
+    }
+}
+
 /// Finds the bounds of the value range the dataset has. It sorts every value
 /// and skips a set number of values at the top and bottom of the range to find
 /// the bounds. This ensures a well distributed range, while sacrificing a few
@@ -381,6 +691,8 @@ fn find_bounds(data: &[f32], nth: usize) -> (Vec<f32>, Vec<f32>) {
 
 #[cfg(test)]
 mod test {
+    use crate::pipeline::random::Lcg;
+
     use super::*;
 
     #[test]
@@ -392,6 +704,17 @@ mod test {
         assert_eq!(upper, vec![5.0, 4.0]);
     }
 
+    #[test]
+    fn deserialize_missing_fields() {
+        let node: Node = serde_json::from_str(r#"{ "factor": 600.0 }"#).unwrap();
+
+        assert_eq!(node.factor, 600.0);
+        assert_eq!(node.rescale_cutoff, 100);
+        assert_eq!(node.rescale_mode, RescaleMode::FirstChunk);
+        assert!(node.raw_scan.connection().is_none());
+        assert!(node.offset.connection().is_none());
+    }
+
     #[test]
     fn test_find_bounds_2() {
         let data = vec![3.0, 2.0, 3.0, 4.0, 3.0];
@@ -459,6 +782,36 @@ mod test {
         );
     }
 
+    #[test]
+    fn percentile_bounds_independent_of_chunk_size() {
+        let mut rng = Lcg::new(532);
+        let m_scan = DMatrix::from_fn(256, 1200, |_, c| {
+            // Outliers after the sampled A scans must not matter
+            match c < 1000 {
+                true => rng.uniform() as f32,
+                false => 1000.0,
+            }
+        });
+
+        let bounds_with_chunk_size = |chunk_size: usize| {
+            let mut sampler = PercentileSampler::new(1000);
+            for start in (0..m_scan.ncols()).step_by(chunk_size) {
+                let width = chunk_size.min(m_scan.ncols() - start);
+                sampler.add(&m_scan.columns(start, width).into_owned());
+            }
+            assert!(sampler.is_complete());
+            sampler.bounds(1.0, 99.0).unwrap()
+        };
+
+        let (lower, upper) = bounds_with_chunk_size(1200);
+        assert!((lower - 0.01).abs() < 0.005, "{}", lower);
+        assert!((upper - 0.99).abs() < 0.005, "{}", upper);
+
+        for chunk_size in [1, 7, 100, 999] {
+            assert_eq!(bounds_with_chunk_size(chunk_size), (lower, upper));
+        }
+    }
+
     fn pseudo_rand(last: f32) -> f32 {
         let a = 1664525.0;
         let c = 1013904223.0;
@@ -0,0 +1,414 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use anyhow::anyhow;
+use futures::FutureExt;
+
+use crate::{pipeline::types::DataMatrix, queue_channel::error::RecvError};
+
+use super::prelude::*;
+
+pub enum InputId {
+    MScan,
+    BScanSegmentation,
+}
+
+impl_enum_from_into_id_types!(InputId, [graph::InputId], {
+    0 => MScan,
+    1 => BScanSegmentation,
+});
+
+/// Typical number of A scans in a B scan, to estimate the size of chunks
+/// before the B scans are known.
+pub const B_SCAN_WIDTH: usize = 500;
+
+// MARK: Node
+
+/// Re-chunks an M scan, so that every chunk contains exactly
+/// [Self::b_scans_per_chunk] B scans. A scans before the first and after the
+/// last B scan are emitted as chunks of their own, the last group of B scans
+/// may contain fewer B scans. No A scan is dropped.
+///
+/// Chunks carry no metadata, the B scans of a chunk follow from the order of
+/// the chunks and the B scan segmentation. Nodes that need B scan aligned
+/// chunks should document to insert this node upstream.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Node {
+    pub b_scans_per_chunk: usize,
+
+    pub m_scan: NodeInput<()>,
+    pub b_scan_segmentation: NodeInput<()>,
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self {
+            b_scans_per_chunk: 1,
+            m_scan: NodeInput::default(),
+            b_scan_segmentation: NodeInput::default(),
+        }
+    }
+}
+
+deserialize_node!(Node, "rechunk");
+
+impl PipelineNode for Node {
+    type InputId = InputId;
+    type OutputId = OutputIdSingle;
+
+    fn slug() -> &'static str {
+        "rechunk"
+    }
+
+    fn inputs(&self) -> impl Iterator<Item = (InputId, Option<NodeOutput>)> {
+        [
+            (InputId::MScan, self.m_scan.connection()),
+            (
+                InputId::BScanSegmentation,
+                self.b_scan_segmentation.connection(),
+            ),
+        ]
+        .into_iter()
+    }
+
+    fn changed(&self, other: &Self) -> bool {
+        self.b_scans_per_chunk != other.b_scans_per_chunk
+    }
+
+    fn get_output_id_for_view_request(&self) -> Option<(OutputIdSingle, impl Into<TypeId>)> {
+        Some((OutputIdSingle, PipelineDataType::MScan))
+    }
+
+    fn estimate_memory(&self, upstream: &UpstreamStats) -> MemoryEstimate {
+        let output = UpstreamStats {
+            chunk_size: self.b_scans_per_chunk.max(1) * B_SCAN_WIDTH,
+            ..*upstream
+        };
+
+        // A scans are merged, until all B scans of a chunk are complete
+        MemoryEstimate::streaming(upstream, output)
+            .with_buffers(upstream.chunk_bytes() + output.chunk_bytes())
+    }
+
+    fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
+        let m_scan_out = builder.output(OutputIdSingle);
+
+        builder.task(Task {
+            b_scans_per_chunk: self.b_scans_per_chunk,
+            m_scan_out,
+            m_scan_in: TaskInput::default(),
+            b_scan_segmentation_in: TaskInput::default(),
+        });
+    }
+}
+
+// MARK: Task
+
+struct Task {
+    b_scans_per_chunk: usize,
+
+    m_scan_out: TaskOutput<requests::MScan>,
+    m_scan_in: TaskInput<requests::MScan>,
+    b_scan_segmentation_in: TaskInput<requests::BScanSegmentation>,
+}
+
+impl NodeTask for Task {
+    type InputId = InputId;
+    type PipelineNode = Node;
+
+    fn connect(&mut self, input_id: Self::InputId, input: &mut ConnectionHandle) {
+        match input_id {
+            InputId::MScan => self.m_scan_in.connect(input),
+            InputId::BScanSegmentation => self.b_scan_segmentation_in.connect(input),
+        };
+    }
+
+    fn disconnect(&mut self, input_id: Self::InputId) {
+        match input_id {
+            InputId::MScan => self.m_scan_in.disconnect(),
+            InputId::BScanSegmentation => self.b_scan_segmentation_in.disconnect(),
+        };
+    }
+
+    fn sync_node(&mut self, node: &Self::PipelineNode) {
+        self.b_scans_per_chunk = node.b_scans_per_chunk;
+    }
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        let _req = self.m_scan_out.receive().await;
+
+        let (Some(m_scan_res), Some(b_scans_res)) = futures::join!(
+            self.m_scan_in.request(requests::MScan),
+            self.b_scan_segmentation_in
+                .request(requests::BScanSegmentation),
+        ) else {
+            return Ok(());
+        };
+
+        let (Some(mut m_scan), Some(mut b_scans)) =
+            (m_scan_res.data.subscribe(), b_scans_res.data.subscribe())
+        else {
+            return Ok(());
+        };
+
+        let (res, tx) = requests::StreamedResponse::with_default_capacity();
+
+        self.m_scan_out.respond(requests::MScanResponse {
+            data: res,
+            a_scan_count: m_scan_res.a_scan_count,
+            a_scan_samples: m_scan_res.a_scan_samples,
+        });
+        self.m_scan_out.receive().now_or_never();
+
+        let mut rechunker = Rechunker::new(self.b_scans_per_chunk);
+
+        let mut b_scans_closed = false;
+
+        loop {
+            let chunk = match m_scan.recv().await {
+                Ok(chunk) => chunk,
+                Err(RecvError::Closed) => break,
+                Err(e) => Err(e)?,
+            };
+
+            rechunker.push_chunk(&chunk)?;
+
+            // Only receive the B scans needed to cut the received A scans, so
+            // the buffered A scans stay bounded
+            while !b_scans_closed && !rechunker.has_cut_after_pending() {
+                match b_scans.recv().await {
+                    Ok(b_scan) => rechunker.push_boundary(b_scan),
+                    Err(RecvError::Closed) => b_scans_closed = true,
+                    Err(e) => Err(e)?,
+                }
+            }
+
+            for chunk in rechunker.take_ready() {
+                tx.send(Arc::new(chunk));
+            }
+        }
+
+        loop {
+            match b_scans.recv().await {
+                Ok(b_scan) => rechunker.push_boundary(b_scan),
+                Err(RecvError::Closed) => break,
+                Err(e) => Err(e)?,
+            }
+        }
+
+        for chunk in rechunker.finish() {
+            tx.send(Arc::new(chunk));
+        }
+
+        tx.finish();
+
+        Ok(())
+    }
+}
+
+// MARK: Algorithm
+
+/// Splits a stream of A scans at B scan boundaries.
+///
+/// The boundaries are the elements of a [requests::BScanSegmentation]: B scan
+/// `i` contains the A scans from boundary `i` to boundary `i + 1`. Chunks are
+/// cut at every `b_scans_per_chunk`th boundary and at the last one.
+struct Rechunker {
+    b_scans_per_chunk: usize,
+
+    /// A scans received, but not emitted yet.
+    pending: Option<DataMatrix>,
+    /// Index of the first A scan in [Self::pending].
+    pending_start: usize,
+
+    /// A scan indices, at which chunks end.
+    cuts: VecDeque<usize>,
+    /// Number of boundaries to skip until the next cut.
+    boundaries_until_cut: usize,
+    last_boundary: Option<usize>,
+
+    ready: Vec<DataMatrix>,
+}
+
+impl Rechunker {
+    fn new(b_scans_per_chunk: usize) -> Self {
+        Self {
+            b_scans_per_chunk: b_scans_per_chunk.max(1),
+            pending: None,
+            pending_start: 0,
+            cuts: VecDeque::new(),
+            boundaries_until_cut: 0,
+            last_boundary: None,
+            ready: Vec::new(),
+        }
+    }
+
+    fn pending_end(&self) -> usize {
+        self.pending_start + self.pending.as_ref().map_or(0, DataMatrix::ncols)
+    }
+
+    /// Whether a cut at or after the last received A scan is known, so all
+    /// pending A scans can be assigned to a chunk.
+    fn has_cut_after_pending(&self) -> bool {
+        self.last_boundary
+            .is_some_and(|boundary| boundary >= self.pending_end())
+    }
+
+    fn push_chunk(&mut self, chunk: &DataMatrix) -> anyhow::Result<()> {
+        self.pending = Some(match self.pending.take() {
+            Some(pending) => pending
+                .concat_horizontally(chunk)
+                .ok_or_else(|| anyhow!("M scan chunks do not match each other"))?,
+            None => chunk.clone(),
+        });
+
+        self.cut();
+        Ok(())
+    }
+
+    fn push_boundary(&mut self, boundary: usize) {
+        if self.boundaries_until_cut == 0 {
+            self.cuts.push_back(boundary);
+            self.boundaries_until_cut = self.b_scans_per_chunk;
+        }
+        self.boundaries_until_cut -= 1;
+        self.last_boundary = Some(boundary);
+
+        self.cut();
+    }
+
+    /// Moves all complete chunks from the pending A scans to the ready ones.
+    fn cut(&mut self) {
+        while let Some(&cut) = self.cuts.front() {
+            if cut > self.pending_end() {
+                break;
+            }
+            self.cuts.pop_front();
+
+            if cut <= self.pending_start {
+                continue;
+            }
+
+            let pending = self.pending.take().expect("Pending A scans exist");
+            let ncols = cut - self.pending_start;
+
+            self.ready.push(pending.columns(0, ncols));
+            if pending.ncols() > ncols {
+                self.pending = Some(pending.columns(ncols, pending.ncols() - ncols));
+            }
+            self.pending_start = cut;
+        }
+    }
+
+    fn take_ready(&mut self) -> Vec<DataMatrix> {
+        std::mem::take(&mut self.ready)
+    }
+
+    /// Emits the remaining A scans. Call after both streams are closed.
+    fn finish(mut self) -> Vec<DataMatrix> {
+        if let Some(last) = self.last_boundary {
+            if self.cuts.back() != Some(&last) {
+                self.cuts.push_back(last);
+            }
+        }
+        self.cut();
+
+        let mut ready = self.take_ready();
+        ready.extend(self.pending.take());
+        ready
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::DMatrix;
+
+    use super::*;
+
+    /// Rechunks A scans `0..a_scans`, received in chunks of `chunk_width`.
+    /// Boundaries are pushed lazily, like in the task. Returns the widths of
+    /// the emitted chunks and checks that the A scans stay in order.
+    fn rechunk(
+        a_scans: usize,
+        chunk_width: usize,
+        boundaries: &[usize],
+        b_scans_per_chunk: usize,
+    ) -> Vec<usize> {
+        let m_scan: DataMatrix = DMatrix::from_fn(2, a_scans, |_, c| c as u32).into();
+
+        let mut rechunker = Rechunker::new(b_scans_per_chunk);
+        let mut boundaries = boundaries.iter().copied();
+        let mut emitted = Vec::new();
+
+        for start in (0..a_scans).step_by(chunk_width) {
+            let width = chunk_width.min(a_scans - start);
+            rechunker.push_chunk(&m_scan.columns(start, width)).unwrap();
+
+            while !rechunker.has_cut_after_pending() {
+                let Some(boundary) = boundaries.next() else {
+                    break;
+                };
+                rechunker.push_boundary(boundary);
+            }
+
+            emitted.extend(rechunker.take_ready());
+        }
+        boundaries.for_each(|b| rechunker.push_boundary(b));
+        emitted.extend(rechunker.finish());
+
+        let mut next = 0;
+        for chunk in &emitted {
+            let DataMatrix::U32(chunk) = chunk else {
+                panic!("Data type changed");
+            };
+            for &a_scan in chunk.row(0).iter() {
+                assert_eq!(a_scan as usize, next);
+                next += 1;
+            }
+        }
+        assert_eq!(next, a_scans);
+
+        emitted.iter().map(DataMatrix::ncols).collect()
+    }
+
+    #[test]
+    fn chunks_match_b_scans() {
+        let boundaries = [3, 10, 12, 25, 26, 40];
+
+        // Leading A scans, every B scan, trailing A scans
+        let expected = [3, 7, 2, 13, 1, 14, 5];
+
+        for chunk_width in [1, 4, 7, 16, 45] {
+            assert_eq!(rechunk(45, chunk_width, &boundaries, 1), expected);
+        }
+    }
+
+    #[test]
+    fn multiple_b_scans_per_chunk() {
+        let boundaries = [0, 10, 12, 25, 26, 40];
+
+        assert_eq!(rechunk(40, 6, &boundaries, 2), [12, 14, 14]);
+        assert_eq!(rechunk(40, 6, &boundaries, 3), [25, 15]);
+    }
+
+    #[test]
+    fn bounded_buffer() {
+        let boundaries: Vec<usize> = (0..=100).map(|i| i * 10).collect();
+
+        let m_scan: DataMatrix = DMatrix::<u8>::zeros(1, 1000).into();
+        let mut rechunker = Rechunker::new(1);
+        let mut boundaries = boundaries.into_iter();
+
+        for start in (0..1000).step_by(3) {
+            let width = 3.min(1000 - start);
+            rechunker.push_chunk(&m_scan.columns(start, width)).unwrap();
+
+            while !rechunker.has_cut_after_pending() {
+                rechunker.push_boundary(boundaries.next().unwrap());
+            }
+            rechunker.take_ready();
+
+            // At most one B scan is buffered
+            assert!(rechunker.pending.as_ref().map_or(0, DataMatrix::ncols) < 10);
+        }
+    }
+}
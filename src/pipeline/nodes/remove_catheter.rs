@@ -0,0 +1,400 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use futures::FutureExt;
+use nalgebra::{DMatrix, DMatrixView, DVectorViewMut, Scalar};
+use num_traits::Zero;
+use rayon::prelude::*;
+
+use crate::{pipeline::types::DataMatrix, queue_channel::error::RecvError};
+
+use super::prelude::*;
+
+/// How the samples above the catheter line are replaced.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FillMode {
+    #[default]
+    Zero,
+    /// The median of the samples right below the removed region.
+    Background,
+    /// Fades the samples out towards the top.
+    Fade,
+}
+
+impl FillMode {
+    pub const VALUES: [FillMode; 3] = [FillMode::Zero, FillMode::Background, FillMode::Fade];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    pub mode: FillMode,
+    /// Number of samples below the catheter line, that are removed as well.
+    pub margin: usize,
+    /// Number of samples below the removed region, that estimate the
+    /// background in [FillMode::Background].
+    pub background_samples: usize,
+    /// Number of samples, over which [FillMode::Fade] fades to zero.
+    pub fade_length: usize,
+    /// Whether to shift every A scan up, so the catheter line becomes the
+    /// first row. The freed rows at the bottom are filled with zeros.
+    pub flatten: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            mode: FillMode::Zero,
+            margin: 5,
+            background_samples: 20,
+            fade_length: 20,
+            flatten: false,
+        }
+    }
+}
+
+pub enum InputId {
+    MScan,
+    CatheterSegmentation,
+}
+
+impl_enum_from_into_id_types!(InputId, [graph::InputId], {
+    0 => MScan,
+    1 => CatheterSegmentation,
+});
+
+// MARK: Node
+
+/// Removes the bright catheter sheath above the catheter segmentation.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Node {
+    pub settings: Settings,
+
+    pub m_scan: NodeInput<()>,
+    pub catheter_segmentation: NodeInput<()>,
+}
+
+deserialize_node!(Node, "remove_catheter");
+
+impl PipelineNode for Node {
+    type InputId = InputId;
+    type OutputId = OutputIdSingle;
+
+    fn slug() -> &'static str {
+        "remove_catheter"
+    }
+
+    fn inputs(
+        &self,
+    ) -> impl Iterator<Item = (<Self as PipelineNode>::InputId, Option<NodeOutput>)> {
+        [
+            (InputId::MScan, self.m_scan.connection()),
+            (
+                InputId::CatheterSegmentation,
+                self.catheter_segmentation.connection(),
+            ),
+        ]
+        .into_iter()
+    }
+
+    fn changed(&self, other: &Self) -> bool {
+        self.settings != other.settings
+    }
+
+    fn get_output_id_for_view_request(&self) -> Option<(OutputIdSingle, impl Into<TypeId>)> {
+        Some((OutputIdSingle, PipelineDataType::MScan))
+    }
+
+    fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
+        let m_scan_out = builder.output(OutputIdSingle);
+
+        builder.task(Task {
+            settings: self.settings,
+            m_scan_out,
+            m_scan_in: TaskInput::default(),
+            catheter_segmentation_in: TaskInput::default(),
+        });
+    }
+}
+
+// MARK: Task
+
+struct Task {
+    settings: Settings,
+
+    m_scan_out: TaskOutput<requests::MScan>,
+    m_scan_in: TaskInput<requests::MScan>,
+    catheter_segmentation_in: TaskInput<requests::MScanSegmentation>,
+}
+
+impl NodeTask for Task {
+    type InputId = InputId;
+    type PipelineNode = Node;
+
+    fn connect(&mut self, input_id: Self::InputId, input: &mut ConnectionHandle) {
+        match input_id {
+            InputId::MScan => self.m_scan_in.connect(input),
+            InputId::CatheterSegmentation => self.catheter_segmentation_in.connect(input),
+        };
+    }
+
+    fn disconnect(&mut self, input_id: Self::InputId) {
+        match input_id {
+            InputId::MScan => self.m_scan_in.disconnect(),
+            InputId::CatheterSegmentation => self.catheter_segmentation_in.disconnect(),
+        };
+    }
+
+    fn sync_node(&mut self, node: &Self::PipelineNode) {
+        self.settings = node.settings;
+    }
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        let _req = self.m_scan_out.receive().await;
+
+        let (Some(m_scan_res), Some(catheter_segmentation_res)) = futures::join!(
+            self.m_scan_in.request(requests::MScan),
+            self.catheter_segmentation_in
+                .request(requests::MScanSegmentation),
+        ) else {
+            return Ok(());
+        };
+
+        let (Some(mut m_scan), Some(mut catheter_segmentation)) = (
+            m_scan_res.data.subscribe(),
+            catheter_segmentation_res.data.subscribe(),
+        ) else {
+            return Ok(());
+        };
+
+        let (res, tx) = requests::StreamedResponse::with_default_capacity();
+
+        self.m_scan_out.respond(requests::MScanResponse {
+            data: res,
+            a_scan_count: m_scan_res.a_scan_count,
+            a_scan_samples: m_scan_res.a_scan_samples,
+        });
+        self.m_scan_out.receive().now_or_never();
+
+        let settings = self.settings;
+
+        // Catheter line of A scans, that have been received, but not used
+        // yet. The segmentation may be chunked differently than the M scan.
+        let mut pending_catheter: Vec<u32> = Vec::new();
+
+        loop {
+            let m_scan = match m_scan.recv().await {
+                Ok(m_scan) => m_scan,
+                Err(RecvError::Closed) => break,
+                Err(e) => Err(e)?,
+            };
+
+            let ncols = m_scan.ncols();
+
+            while pending_catheter.len() < ncols {
+                let catheter_segmentation = match catheter_segmentation.recv().await {
+                    Ok(catheter_segmentation) => catheter_segmentation,
+                    Err(RecvError::Closed) => break,
+                    Err(e) => Err(e)?,
+                };
+
+                pending_catheter.extend(catheter_segmentation.iter().copied());
+            }
+
+            if pending_catheter.len() < ncols {
+                return Err(anyhow!("Catheter segmentation is shorter than the M scan"));
+            }
+
+            let catheter: Vec<u32> = pending_catheter.drain(..ncols).collect();
+
+            let m_scan: DataMatrix = priority::spawn_blocking(move || match m_scan.as_ref() {
+                DataMatrix::U8(m_scan) => {
+                    remove_catheter(m_scan.as_view(), &catheter, &settings).into()
+                }
+                DataMatrix::U16(m_scan) => {
+                    remove_catheter(m_scan.as_view(), &catheter, &settings).into()
+                }
+                DataMatrix::U32(m_scan) => {
+                    remove_catheter(m_scan.as_view(), &catheter, &settings).into()
+                }
+                DataMatrix::U64(m_scan) => {
+                    remove_catheter(m_scan.as_view(), &catheter, &settings).into()
+                }
+                DataMatrix::F32(m_scan) => {
+                    remove_catheter(m_scan.as_view(), &catheter, &settings).into()
+                }
+                DataMatrix::F64(m_scan) => {
+                    remove_catheter(m_scan.as_view(), &catheter, &settings).into()
+                }
+            })
+            .await?;
+
+            tx.send(Arc::new(m_scan));
+        }
+
+        tx.finish();
+
+        Ok(())
+    }
+}
+
+// MARK: Algorithm
+
+fn remove_catheter<T>(m_scan: DMatrixView<T>, catheter: &[u32], st: &Settings) -> DMatrix<T>
+where
+    T: Scalar + Copy + Zero + PartialOrd + num_traits::NumCast + Send + Sync,
+{
+    let mut result = m_scan.clone_owned();
+
+    result
+        .par_column_iter_mut()
+        .zip(catheter.par_iter())
+        .for_each(|(mut a_scan, &catheter)| {
+            let catheter = (catheter as usize).min(a_scan.nrows());
+            let boundary = (catheter + st.margin).min(a_scan.nrows());
+
+            remove_region(&mut a_scan, boundary, st);
+
+            if st.flatten {
+                flatten(&mut a_scan, catheter);
+            }
+        });
+
+    result
+}
+
+/// Replaces the first `boundary` samples of an A scan.
+fn remove_region<T>(a_scan: &mut DVectorViewMut<T>, boundary: usize, st: &Settings)
+where
+    T: Scalar + Copy + Zero + PartialOrd + num_traits::NumCast,
+{
+    match st.mode {
+        FillMode::Zero => a_scan.rows_mut(0, boundary).fill(T::zero()),
+        FillMode::Background => {
+            let end = (boundary + st.background_samples).min(a_scan.nrows());
+
+            let mut below = a_scan
+                .rows(boundary, end - boundary)
+                .iter()
+                .copied()
+                .collect::<Vec<_>>();
+            below.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+            let background = below.get(below.len() / 2).copied().unwrap_or(T::zero());
+            a_scan.rows_mut(0, boundary).fill(background);
+        }
+        FillMode::Fade => {
+            let length = st.fade_length.max(1) as f64;
+
+            for i in 0..boundary {
+                // Smoothstep from 1 at the boundary to 0 after fade_length
+                let x = (1.0 - (boundary - i) as f64 / length).max(0.0);
+                let weight = x * x * (3.0 - 2.0 * x);
+
+                let value = a_scan[i].to_f64().unwrap_or(0.0) * weight;
+                a_scan[i] = num_traits::cast(value).unwrap_or(T::zero());
+            }
+        }
+    }
+}
+
+/// Shifts an A scan up by `offset` samples and fills the bottom with zeros.
+fn flatten<T>(a_scan: &mut DVectorViewMut<T>, offset: usize)
+where
+    T: Scalar + Copy + Zero,
+{
+    let nrows = a_scan.nrows();
+
+    for i in 0..nrows - offset {
+        a_scan[i] = a_scan[i + offset];
+    }
+    a_scan.rows_mut(nrows - offset, offset).fill(T::zero());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A bright sheath above row 10 and a bright structure at rows 30 to 35.
+    fn synthetic_m_scan() -> (DMatrix<f32>, Vec<u32>) {
+        let catheter: Vec<u32> = (0..8).map(|i| 8 + i % 3).collect();
+
+        let m_scan = DMatrix::from_fn(64, catheter.len(), |row, col| {
+            if row < catheter[col] as usize {
+                1.0
+            } else if (30..36).contains(&row) {
+                0.8
+            } else {
+                0.1
+            }
+        });
+
+        (m_scan, catheter)
+    }
+
+    #[test]
+    fn removes_catheter_region() {
+        let (m_scan, catheter) = synthetic_m_scan();
+
+        for mode in FillMode::VALUES {
+            let st = Settings {
+                mode,
+                margin: 2,
+                background_samples: 10,
+                fade_length: 4,
+                flatten: false,
+            };
+
+            let result = remove_catheter(m_scan.as_view(), &catheter, &st);
+
+            for (col, &catheter) in catheter.iter().enumerate() {
+                let boundary = catheter as usize + 2;
+                let removed = result.view((0, col), (boundary, 1));
+
+                match mode {
+                    FillMode::Zero => assert!(removed.iter().all(|&v| v == 0.0)),
+                    FillMode::Background => assert!(removed.iter().all(|&v| v == 0.1)),
+                    FillMode::Fade => {
+                        assert!(removed.iter().all(|&v| v < 1.0));
+                        assert!(removed.rows(0, boundary - 4).iter().all(|&v| v == 0.0));
+                    }
+                }
+
+                assert_eq!(
+                    result.view((boundary, col), (64 - boundary, 1)),
+                    m_scan.view((boundary, col), (64 - boundary, 1))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn flattens_to_catheter() {
+        let (m_scan, catheter) = synthetic_m_scan();
+
+        let st = Settings {
+            flatten: true,
+            margin: 0,
+            ..Default::default()
+        };
+
+        let result = remove_catheter(m_scan.as_view(), &catheter, &st);
+
+        for (col, &catheter) in catheter.iter().enumerate() {
+            let catheter = catheter as usize;
+
+            // The structure is moved up, but stays intact
+            assert_eq!(
+                result.view((0, col), (64 - catheter, 1)),
+                m_scan.view((catheter, col), (64 - catheter, 1))
+            );
+            assert!(result
+                .view((30 - catheter, col), (6, 1))
+                .iter()
+                .all(|&v| v == 0.8));
+            assert!(result
+                .view((64 - catheter, col), (catheter, 1))
+                .iter()
+                .all(|&v| v == 0.0));
+        }
+    }
+}
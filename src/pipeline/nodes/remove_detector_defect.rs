@@ -101,7 +101,7 @@ impl NodeTask for Task {
         };
 
         if let Some(mut m_scan) = m_scan_res.data.subscribe() {
-            let (res, tx) = requests::StreamedResponse::new(100);
+            let (res, tx) = requests::StreamedResponse::with_default_capacity();
 
             let upper = self.upper;
             let lower = self.lower;
@@ -120,31 +120,32 @@ impl NodeTask for Task {
                     Err(e) => Err(e)?,
                 };
 
-                let m_scan: DataMatrix =
-                    tokio::task::spawn_blocking(move || match m_scan.as_ref() {
-                        DataMatrix::U8(m_scan) => {
-                            remove_detector_defect(m_scan.as_view(), upper, lower).into()
-                        }
-                        DataMatrix::U16(m_scan) => {
-                            remove_detector_defect(m_scan.as_view(), upper, lower).into()
-                        }
-                        DataMatrix::U32(m_scan) => {
-                            remove_detector_defect(m_scan.as_view(), upper, lower).into()
-                        }
-                        DataMatrix::U64(m_scan) => {
-                            remove_detector_defect(m_scan.as_view(), upper, lower).into()
-                        }
-                        DataMatrix::F32(m_scan) => {
-                            remove_detector_defect(m_scan.as_view(), upper, lower).into()
-                        }
-                        DataMatrix::F64(m_scan) => {
-                            remove_detector_defect(m_scan.as_view(), upper, lower).into()
-                        }
-                    })
-                    .await?;
+                let m_scan: DataMatrix = priority::spawn_blocking(move || match m_scan.as_ref() {
+                    DataMatrix::U8(m_scan) => {
+                        remove_detector_defect(m_scan.as_view(), upper, lower).into()
+                    }
+                    DataMatrix::U16(m_scan) => {
+                        remove_detector_defect(m_scan.as_view(), upper, lower).into()
+                    }
+                    DataMatrix::U32(m_scan) => {
+                        remove_detector_defect(m_scan.as_view(), upper, lower).into()
+                    }
+                    DataMatrix::U64(m_scan) => {
+                        remove_detector_defect(m_scan.as_view(), upper, lower).into()
+                    }
+                    DataMatrix::F32(m_scan) => {
+                        remove_detector_defect(m_scan.as_view(), upper, lower).into()
+                    }
+                    DataMatrix::F64(m_scan) => {
+                        remove_detector_defect(m_scan.as_view(), upper, lower).into()
+                    }
+                })
+                .await?;
 
                 tx.send(Arc::new(m_scan));
             }
+
+            tx.finish();
         }
 
         Ok(())
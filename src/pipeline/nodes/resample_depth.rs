@@ -0,0 +1,413 @@
+use std::sync::Arc;
+
+use futures::FutureExt;
+use nalgebra::{DMatrix, DMatrixView, DVector, Scalar};
+use num_traits::Float;
+use rayon::prelude::*;
+
+use crate::{
+    pipeline::types::{DataMatrix, DataVector},
+    queue_channel::error::RecvError,
+};
+
+use super::prelude::*;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// Catmull-Rom spline through the four nearest samples.
+    Cubic,
+}
+
+impl Interpolation {
+    pub const VALUES: [Interpolation; 2] = [Interpolation::Linear, Interpolation::Cubic];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    /// Samples of every A scan after resampling.
+    pub samples: usize,
+    pub interpolation: Interpolation,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            samples: 1024,
+            interpolation: Interpolation::Linear,
+        }
+    }
+}
+
+pub enum OutputId {
+    MScan,
+    Scale,
+}
+
+impl_enum_from_into_id_types!(OutputId, [graph::OutputId], {
+    0 => MScan,
+    1 => Scale,
+});
+
+// MARK: Node
+
+/// Resamples the depth axis of every A scan to a fixed number of samples, so
+/// pullbacks acquired with different A scan lengths work with the same
+/// settings of downstream nodes, which are given in samples.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Node {
+    pub settings: Settings,
+
+    pub m_scan: NodeInput<()>,
+}
+
+deserialize_node!(Node, "resample_depth");
+
+impl PipelineNode for Node {
+    type InputId = InputIdSingle;
+    type OutputId = OutputId;
+
+    fn slug() -> &'static str {
+        "resample_depth"
+    }
+
+    fn inputs(
+        &self,
+    ) -> impl Iterator<Item = (<Self as PipelineNode>::InputId, Option<NodeOutput>)> {
+        [(InputIdSingle, self.m_scan.connection())].into_iter()
+    }
+
+    fn changed(&self, other: &Self) -> bool {
+        self.settings != other.settings
+    }
+
+    fn get_output_id_for_view_request(&self) -> Option<(OutputId, impl Into<TypeId>)> {
+        Some((OutputId::MScan, PipelineDataType::MScan))
+    }
+
+    fn estimate_memory(&self, upstream: &UpstreamStats) -> MemoryEstimate {
+        let output = UpstreamStats {
+            samples: self.settings.samples.max(1),
+            ..*upstream
+        };
+
+        MemoryEstimate::streaming(upstream, output)
+    }
+
+    fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
+        let m_scan_out = builder.output(OutputId::MScan);
+        let scale_out = builder.output(OutputId::Scale);
+
+        builder.task(Task {
+            settings: self.settings,
+            m_scan_out,
+            scale_out,
+            m_scan_in: TaskInput::default(),
+        });
+    }
+}
+
+// MARK: Task
+
+struct Task {
+    settings: Settings,
+
+    m_scan_out: TaskOutput<requests::MScan>,
+    scale_out: TaskOutput<requests::VectorData>,
+    m_scan_in: TaskInput<requests::MScan>,
+}
+
+impl NodeTask for Task {
+    type InputId = InputIdSingle;
+    type PipelineNode = Node;
+
+    fn connect(&mut self, _input_id: Self::InputId, input: &mut ConnectionHandle) {
+        self.m_scan_in.connect(input);
+    }
+
+    fn disconnect(&mut self, _input_id: Self::InputId) {
+        self.m_scan_in.disconnect();
+    }
+
+    fn sync_node(&mut self, node: &Self::PipelineNode) {
+        self.settings = node.settings;
+    }
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        tokio::select! {
+            _req = self.m_scan_out.receive() => {}
+            _req = self.scale_out.receive() => {}
+        }
+
+        // Both outputs may have been requested at the same time
+        let m_scan_requested = self.m_scan_out.receive().now_or_never().is_some();
+        let scale_requested = self.scale_out.receive().now_or_never().is_some();
+
+        let Some(m_scan_res) = self.m_scan_in.request(requests::MScan).await else {
+            return Ok(());
+        };
+
+        let settings = self.settings;
+        let samples = settings.samples.max(1);
+
+        if scale_requested {
+            let scale = m_scan_res.a_scan_samples as f32 / samples as f32;
+            self.scale_out
+                .respond(Arc::new(DataVector::F32(DVector::from_element(1, scale))));
+            self.scale_out.receive().now_or_never();
+        }
+
+        if !m_scan_requested {
+            return Ok(());
+        }
+
+        let Some(mut m_scan) = m_scan_res.data.subscribe() else {
+            return Ok(());
+        };
+
+        let (res, tx) = requests::StreamedResponse::with_default_capacity();
+
+        self.m_scan_out.respond(requests::MScanResponse {
+            data: res,
+            a_scan_count: m_scan_res.a_scan_count,
+            a_scan_samples: samples,
+        });
+        self.m_scan_out.receive().now_or_never();
+
+        loop {
+            let m_scan = match m_scan.recv().await {
+                Ok(m_scan) => m_scan,
+                Err(RecvError::Closed) => break,
+                Err(e) => Err(e)?,
+            };
+
+            if m_scan.nrows() == samples {
+                tx.send(m_scan);
+                continue;
+            }
+
+            let interpolation = settings.interpolation;
+            let m_scan: DataMatrix = priority::spawn_blocking(move || match m_scan.as_ref() {
+                DataMatrix::U8(m_scan) => {
+                    resample::<_, f32>(m_scan.as_view(), samples, interpolation).into()
+                }
+                DataMatrix::U16(m_scan) => {
+                    resample::<_, f32>(m_scan.as_view(), samples, interpolation).into()
+                }
+                DataMatrix::U32(m_scan) => {
+                    resample::<_, f32>(m_scan.as_view(), samples, interpolation).into()
+                }
+                DataMatrix::U64(m_scan) => {
+                    resample::<_, f32>(m_scan.as_view(), samples, interpolation).into()
+                }
+                DataMatrix::F32(m_scan) => {
+                    resample::<_, f32>(m_scan.as_view(), samples, interpolation).into()
+                }
+                DataMatrix::F64(m_scan) => {
+                    resample::<_, f64>(m_scan.as_view(), samples, interpolation).into()
+                }
+            })
+            .await?;
+
+            tx.send(Arc::new(m_scan));
+        }
+
+        tx.finish();
+
+        Ok(())
+    }
+}
+
+// MARK: Algorithm
+
+/// Value of an M scan, interpolated in the float type `F`.
+trait Sample<F>: Scalar + Copy {
+    fn to_float(self) -> F;
+
+    /// Rounds for integer types. Out of range values saturate.
+    fn from_float(value: F) -> Self;
+}
+
+macro_rules! impl_sample {
+    (@int $float:ty, $($ty:ty),*) => {$(
+        impl Sample<$float> for $ty {
+            fn to_float(self) -> $float {
+                self as $float
+            }
+
+            fn from_float(value: $float) -> Self {
+                value.round() as $ty
+            }
+        }
+    )*};
+    (@float $float:ty, $ty:ty) => {
+        impl Sample<$float> for $ty {
+            fn to_float(self) -> $float {
+                self as $float
+            }
+
+            fn from_float(value: $float) -> Self {
+                value as $ty
+            }
+        }
+    };
+}
+
+impl_sample!(@int f32, u8, u16, u32, u64);
+impl_sample!(@float f32, f32);
+impl_sample!(@float f64, f64);
+
+/// Position in a source A scan of `len` samples, that sample `index` of
+/// `samples` is taken from. The first and last samples stay in place.
+fn source_position<F: Float>(index: usize, samples: usize, len: usize) -> F {
+    if samples < 2 {
+        return F::zero();
+    }
+    F::from(index * (len - 1)).unwrap() / F::from(samples - 1).unwrap()
+}
+
+fn interpolate<F: Float>(a_scan: &[F], position: F, interpolation: Interpolation) -> F {
+    let last = a_scan.len() - 1;
+    let i = position.floor().to_usize().unwrap_or(0).min(last);
+    let t = position - F::from(i).unwrap();
+
+    let at = |i: usize| a_scan[i.min(last)];
+
+    match interpolation {
+        Interpolation::Linear => at(i) + (at(i + 1) - at(i)) * t,
+        Interpolation::Cubic => {
+            let (p1, p2) = (at(i), at(i + 1));
+            let two = F::from(2.0).unwrap();
+            let three = F::from(3.0).unwrap();
+
+            // Samples outside of the A scan are extrapolated linearly, so
+            // ramps stay straight up to the edges
+            let p0 = match i {
+                0 => two * p1 - p2,
+                _ => at(i - 1),
+            };
+            let p3 = match i + 2 > last {
+                true => two * p2 - p1,
+                false => at(i + 2),
+            };
+
+            let a = three * (p1 - p2) + p3 - p0;
+            let b = two * p0 - F::from(5.0).unwrap() * p1 + F::from(4.0).unwrap() * p2 - p3;
+            let c = p2 - p0;
+            p1 + F::from(0.5).unwrap() * t * (c + t * (b + t * a))
+        }
+    }
+}
+
+/// Resamples every A scan of `m_scan` to `samples` samples.
+fn resample<T, F>(
+    m_scan: DMatrixView<T>,
+    samples: usize,
+    interpolation: Interpolation,
+) -> DMatrix<T>
+where
+    T: Sample<F> + Send + Sync,
+    F: Float + Send + Sync,
+{
+    let len = m_scan.nrows();
+    let mut result = DMatrix::from_element(samples, m_scan.ncols(), T::from_float(F::zero()));
+    if len == 0 {
+        return result;
+    }
+
+    result
+        .par_column_iter_mut()
+        .zip(m_scan.par_column_iter())
+        .for_each(|(mut a_scan, source)| {
+            let source: Vec<F> = source.iter().map(|v| v.to_float()).collect();
+
+            for (index, value) in a_scan.iter_mut().enumerate() {
+                let position = source_position(index, samples, len);
+                *value = T::from_float(interpolate(&source, position, interpolation));
+            }
+        });
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// `a_scans` A scans of `len` samples, with `f` of the sample index.
+    fn m_scan(len: usize, a_scans: usize, f: impl Fn(f32) -> f32) -> DMatrix<f32> {
+        DMatrix::from_fn(len, a_scans, |row, _| f(row as f32))
+    }
+
+    /// Position in the source, where sample `row` of `samples` is taken from.
+    fn position(row: usize, samples: usize, len: usize) -> f32 {
+        row as f32 * (len - 1) as f32 / (samples - 1) as f32
+    }
+
+    #[test]
+    fn ramp_matches_reference() {
+        let ramp = |x: f32| 5.0 + 0.5 * x;
+
+        for (len, samples) in [(512, 2048), (2048, 512), (1024, 1024), (7, 1000)] {
+            for interpolation in Interpolation::VALUES {
+                let resampled = resample(m_scan(len, 3, ramp).as_view(), samples, interpolation);
+
+                assert_eq!(resampled.shape(), (samples, 3));
+                for a_scan in resampled.column_iter() {
+                    for (row, &value) in a_scan.iter().enumerate() {
+                        let reference = ramp(position(row, samples, len));
+                        assert!(
+                            (value - reference).abs() < 1e-3,
+                            "{:?}, {} to {}: {} instead of {}",
+                            interpolation,
+                            len,
+                            samples,
+                            value,
+                            reference
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn integers_round() {
+        let ramp = DMatrix::from_fn(512, 2, |row, _| (row * 100) as u16);
+
+        for samples in [2048, 256] {
+            let resampled = resample::<_, f32>(ramp.as_view(), samples, Interpolation::Cubic);
+            assert_eq!(resampled.shape(), (samples, 2));
+
+            for (row, &value) in resampled.column(1).iter().enumerate() {
+                let reference = position(row, samples, 512) * 100.0;
+                assert!((value as f32 - reference).abs() <= 0.5 + 1e-2);
+            }
+            assert_eq!(resampled[(samples - 1, 0)], 51100);
+        }
+
+        // Overshooting the range of the type saturates
+        let step = DMatrix::from_fn(8, 1, |row, _| if row < 4 { 0u8 } else { 255 });
+        let resampled = resample::<_, f32>(step.as_view(), 64, Interpolation::Cubic);
+        assert_eq!(resampled.min(), 0);
+        assert_eq!(resampled.max(), 255);
+    }
+
+    #[test]
+    fn cubic_follows_curves() {
+        let wave = |x: f32| (x * 0.2).sin();
+        let (len, samples) = (64, 512);
+
+        let error = |interpolation| {
+            let resampled = resample(m_scan(len, 1, wave).as_view(), samples, interpolation);
+            resampled
+                .iter()
+                .enumerate()
+                .map(|(row, value)| (value - wave(position(row, samples, len))).abs())
+                .fold(0.0, f32::max)
+        };
+
+        assert!(error(Interpolation::Cubic) < error(Interpolation::Linear) / 4.0);
+    }
+}
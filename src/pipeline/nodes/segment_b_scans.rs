@@ -80,6 +80,17 @@ impl PipelineNode for Node {
         Some((OutputIdSingle, PipelineDataType::BScanSegmentation))
     }
 
+    fn estimate_memory(&self, upstream: &UpstreamStats) -> MemoryEstimate {
+        let m_scan = UpstreamStats {
+            data_type: DataType::F32,
+            ..*upstream
+        };
+
+        // The whole M scan is kept as F32, to search B scans across chunks
+        MemoryEstimate::streaming(upstream, upstream.per_a_scan(DataType::U64))
+            .with_buffers(m_scan.stream_bytes())
+    }
+
     fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
         let m_scan_out = builder.output(OutputIdSingle);
 
@@ -137,9 +148,13 @@ impl NodeTask for Task {
         if let Some(mut m_scan_rx) = m_scan_res.data.subscribe() {
             let _ = self.progress_tx.send(Some(0.0));
 
-            let (res, tx) = requests::StreamedResponse::new(100);
+            let (res, tx) = requests::StreamedResponse::with_default_capacity();
 
-            self.m_scan_out.respond(res);
+            self.m_scan_out
+                .respond(requests::BScanSegmentationResponse {
+                    data: res,
+                    a_scan_count: m_scan_res.a_scan_count,
+                });
             self.m_scan_out.receive().now_or_never();
 
             struct Shared {
@@ -166,7 +181,7 @@ impl NodeTask for Task {
                 let settings = self.settings.clone();
                 let shared = shared.clone();
 
-                let borders = tokio::task::spawn_blocking(move || {
+                let borders = priority::spawn_blocking(move || {
                     let mut shared = shared.lock().unwrap();
                     let shared = &mut *shared;
 
@@ -224,6 +239,7 @@ impl NodeTask for Task {
                 }
             }
 
+            tx.finish();
             let _ = self.progress_tx.send(None);
         }
 
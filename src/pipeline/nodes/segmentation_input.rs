@@ -0,0 +1,342 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::anyhow;
+use futures::FutureExt;
+use nalgebra::DVector;
+use tokio::sync::watch;
+
+use crate::pipeline::segmentation_format::{self, SegmentationSidecar};
+
+use super::prelude::*;
+
+/// Reported to the [Node] after every run.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum Status {
+    #[default]
+    Idle,
+    Loaded(SegmentationSidecar),
+    Failed(String),
+}
+
+// MARK: Node
+
+/// Reads an M scan segmentation exported by the output node, for example
+/// after correcting it with other tools. See
+/// [crate::pipeline::segmentation_format].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Node {
+    pub path: PathBuf,
+
+    /// Optional, the segmentation is checked against its A scans.
+    pub m_scan: NodeInput<()>,
+
+    #[serde(skip)]
+    pub status_rx: Option<watch::Receiver<Status>>,
+}
+
+deserialize_node!(Node, "segmentation_input");
+
+impl PipelineNode for Node {
+    type InputId = InputIdSingle;
+    type OutputId = OutputIdSingle;
+
+    fn slug() -> &'static str {
+        "segmentation_input"
+    }
+
+    fn inputs(
+        &self,
+    ) -> impl Iterator<Item = (<Self as PipelineNode>::InputId, Option<NodeOutput>)> {
+        std::iter::once((InputIdSingle, self.m_scan.connection()))
+    }
+
+    fn changed(&self, other: &Self) -> bool {
+        self.path != other.path
+    }
+
+    fn get_output_id_for_view_request(&self) -> Option<(OutputIdSingle, impl Into<TypeId>)> {
+        Some((OutputIdSingle, PipelineDataType::MScanSegmentation))
+    }
+
+    fn visit_paths(&self, visitor: &mut dyn FnMut(PathRole, &Path)) {
+        visitor(PathRole::Input, &self.path);
+    }
+
+    fn create_node_task(&mut self, builder: &mut impl NodeTaskBuilder<PipelineNode = Self>) {
+        let segmentation_out = builder.output(OutputIdSingle);
+
+        let (status_tx, status_rx) = watch::channel(Status::Idle);
+
+        self.status_rx = Some(status_rx);
+
+        builder.task(Task {
+            path: self.path.clone(),
+            status_tx,
+            segmentation_out,
+            m_scan_in: TaskInput::default(),
+        });
+    }
+}
+
+// MARK: Task
+
+struct Task {
+    path: PathBuf,
+
+    status_tx: watch::Sender<Status>,
+
+    segmentation_out: TaskOutput<requests::MScanSegmentation>,
+    m_scan_in: TaskInput<requests::MScan>,
+}
+
+impl NodeTask for Task {
+    type InputId = InputIdSingle;
+    type PipelineNode = Node;
+
+    fn connect(&mut self, _input_id: Self::InputId, input: &mut ConnectionHandle) {
+        self.m_scan_in.connect(input);
+    }
+
+    fn disconnect(&mut self, _input_id: Self::InputId) {
+        self.m_scan_in.disconnect();
+    }
+
+    fn sync_node(&mut self, node: &Self::PipelineNode) {
+        self.path = node.path.clone();
+    }
+
+    fn invalidate(&mut self, _cause: InvalidationCause) {
+        let _ = self.status_tx.send(Status::Idle);
+    }
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        let _req = self.segmentation_out.receive().await;
+
+        let m_scan = match self.m_scan_in.is_connected() {
+            true => match self.m_scan_in.request(requests::MScan).await {
+                Some(res) => Some((res.a_scan_count, res.a_scan_samples)),
+                None => return Ok(()),
+            },
+            false => None,
+        };
+
+        let (sidecar, values) = match self.load(m_scan).await {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                let _ = self.status_tx.send(Status::Failed(format!("{:#}", e)));
+                return Err(e);
+            }
+        };
+
+        let _ = self.status_tx.send(Status::Loaded(sidecar.clone()));
+
+        // Everything is sent at once, so the queue must hold all chunks
+        let (res, tx) = requests::StreamedResponse::new(sidecar.chunks.len().max(1));
+
+        self.segmentation_out
+            .respond(requests::MScanSegmentationResponse {
+                data: res,
+                a_scan_count: sidecar.a_scan_count,
+                a_scan_samples: sidecar.a_scan_samples,
+            });
+        self.segmentation_out.receive().now_or_never();
+
+        let mut start = 0;
+        for len in sidecar.chunks {
+            let chunk = DVector::from_column_slice(&values[start..start + len]);
+            tx.send(Arc::new(chunk));
+            start += len;
+        }
+
+        tx.finish();
+
+        Ok(())
+    }
+}
+
+impl Task {
+    /// Reads the segmentation and its sidecar and checks them against the
+    /// A scan count and samples of the connected M scan.
+    async fn load(
+        &self,
+        m_scan: Option<(usize, usize)>,
+    ) -> anyhow::Result<(SegmentationSidecar, Vec<u32>)> {
+        let sidecar_path = SegmentationSidecar::path(&self.path);
+
+        let sidecar = tokio::fs::read(&sidecar_path).await.map_err(|e| {
+            anyhow!(
+                "Cannot read the sidecar {}, which the output node writes next to \
+                 segmentations: {}",
+                sidecar_path.display(),
+                e
+            )
+        })?;
+        let sidecar = SegmentationSidecar::parse(&sidecar)?;
+
+        let values = segmentation_format::parse_values(&tokio::fs::read(&self.path).await?)?;
+        sidecar.validate(values.len(), m_scan)?;
+
+        Ok((sidecar, values))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, time::Duration};
+
+    use serde_json::json;
+
+    use crate::{
+        node_graph::NodeId,
+        pipeline::{nodes::output, Pipeline, PipelineExecutor},
+        queue_channel::error::RecvError,
+    };
+
+    use super::*;
+
+    /// Chunks of the segmentation served by `node_id`.
+    async fn collect(executor: &PipelineExecutor, node_id: usize) -> Vec<Arc<DVector<u32>>> {
+        let mut input = TaskInput::<requests::MScanSegmentation>::default();
+        let mut handle = executor
+            .get_output(NodeId::from(node_id), OutputIdSingle.into())
+            .unwrap();
+        assert!(input.connect(&mut handle));
+
+        let res = input.request(requests::MScanSegmentation).await.unwrap();
+        let mut rx = res.data.subscribe().unwrap();
+
+        let mut chunks = Vec::new();
+        loop {
+            match rx.recv().await {
+                Ok(chunk) => chunks.push(chunk),
+                Err(RecvError::Closed) => break chunks,
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+    }
+
+    fn output(node: &mut dyn DynPipelineNode) -> &mut output::Node {
+        node.as_any_mut().downcast_mut().unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn roundtrip_follow_lumen() {
+        let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden");
+        let dir = std::env::temp_dir().join(format!(
+            "ivoct_segmentation_roundtrip_{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let exported = dir.join("lumen.bin");
+
+        // The follow lumen case of the golden tests, exporting the lumen
+        let mut nodes: serde_json::Value = serde_json::from_slice(
+            &std::fs::read(golden.join("follow_catheter_lumen.json")).unwrap(),
+        )
+        .unwrap();
+        for (id, file) in [("1", "raw.bin"), ("2", "offset.bin"), ("3", "chirp.bin")] {
+            nodes["nodes"][id]["path"] = json!(golden.join(file));
+        }
+        nodes["nodes"]["10"]["path"] = json!(exported);
+        nodes["nodes"].as_object_mut().unwrap().remove("9");
+        nodes["nodes"]["11"] = json!({
+            "type": "segmentation_input",
+            "path": exported,
+            "m_scan": {
+                "value": null,
+                "connection": { "node_id": 5, "output_id": 0, "type_id": 2 },
+            },
+        });
+
+        let mut pipeline: Pipeline = serde_json::from_value(nodes).unwrap();
+        let mut executor = PipelineExecutor::new();
+        executor.update(&mut pipeline);
+
+        let provenance = output(&mut pipeline[NodeId::from(10)]).provenance;
+        assert!(provenance.is_some());
+
+        let node = output(&mut pipeline[NodeId::from(10)]);
+        node.save();
+        let mut saves_rx = node.saves_rx.clone().unwrap();
+        tokio::time::timeout(
+            Duration::from_secs(60),
+            saves_rx.wait_for(|&saves| saves > 0),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        let sidecar = SegmentationSidecar::parse(
+            &std::fs::read(SegmentationSidecar::path(&exported)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            sidecar.provenance,
+            provenance.map(|p| format!("{:016x}", p))
+        );
+
+        let (original, reimported) = tokio::time::timeout(Duration::from_secs(60), async {
+            (collect(&executor, 8).await, collect(&executor, 11).await)
+        })
+        .await
+        .expect("Segmentations should finish");
+
+        assert!(!original.is_empty());
+        assert_eq!(original, reimported);
+        assert_eq!(
+            sidecar.a_scan_count,
+            original.iter().map(|c| c.len()).sum::<usize>()
+        );
+
+        // Changing the settings of the segmentation changes the provenance
+        pipeline
+            .nodes
+            .get_mut(&NodeId::from(8))
+            .unwrap()
+            .as_any_mut()
+            .downcast_mut::<crate::pipeline::nodes::follow_lumen::Node>()
+            .unwrap()
+            .settings
+            .threshold = 0.5;
+        executor.update(&mut pipeline);
+        assert_ne!(
+            output(&mut pipeline[NodeId::from(10)]).provenance,
+            provenance
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn missing_sidecar() {
+        let dir =
+            std::env::temp_dir().join(format!("ivoct_segmentation_sidecar_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("segmentation.bin");
+        std::fs::write(&path, [0u8; 16]).unwrap();
+
+        let task = Task {
+            path: path.clone(),
+            status_tx: watch::channel(Status::Idle).0,
+            segmentation_out: ConnectionHandle::new::<requests::MScanSegmentation>().1,
+            m_scan_in: TaskInput::default(),
+        };
+
+        let message = task.load(None).await.unwrap_err().to_string();
+        assert!(message.contains("sidecar"), "{}", message);
+
+        std::fs::write(
+            SegmentationSidecar::path(&path),
+            serde_json::to_vec(&SegmentationSidecar::new(4, 8, None, vec![4])).unwrap(),
+        )
+        .unwrap();
+        assert!(task.load(None).await.is_ok());
+        let message = task.load(Some((5, 8))).await.unwrap_err().to_string();
+        assert!(message.contains("reports 5 A scans"), "{}", message);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,350 @@
+use std::{collections::HashSet, ops::Range, sync::Arc};
+
+use tokio::{sync::watch, task::JoinHandle};
+
+use crate::node_graph::{InputId, NodeId, NodeOutput, OutputId};
+
+use super::{
+    execution::{ConnectionHandle, EphemeralRunner, TaskInput},
+    nodes::DynPipelineNode,
+    requests,
+    sweep::{collect_preview, serve_preview_slice, PreviewState},
+    types::DataMatrix,
+    Pipeline, PipelineDataType, PipelineExecutor,
+};
+
+/// Number of extra A scans run on both sides of a [PartialRun], so filters
+/// spanning multiple A scans show no edge effects at its boundaries.
+const MARGIN: usize = 32;
+
+/// A node of a [PartialRun] chain. `input_id` receives the previous node,
+/// `output_id` feeds the next one.
+pub struct ChainLink {
+    /// The node in the pipeline, [Self::node] is a copy of.
+    pub node_id: NodeId,
+    pub node: Box<dyn DynPipelineNode>,
+    pub input_id: InputId,
+    pub output_id: OutputId,
+}
+
+/// The nodes to run again for `m_scan`, when the nodes in `changed` change.
+/// Walks upstream through nodes with a single connected input, which receives
+/// an M scan. Returns the output feeding the chain and the chain, starting at
+/// its most upstream changed node. [None], if no changed node is reachable
+/// this way.
+pub fn partial_chain(
+    pipeline: &Pipeline,
+    m_scan: NodeOutput,
+    changed: &HashSet<NodeId>,
+) -> Option<(NodeOutput, Vec<ChainLink>)> {
+    let mut links = Vec::new();
+    let mut source = None;
+    let mut seen_nodes = HashSet::new();
+
+    let mut output = m_scan;
+
+    while seen_nodes.insert(output.node_id) && !pipeline.disabled.contains(&output.node_id) {
+        let Some(node) = pipeline.nodes.get(&output.node_id) else {
+            break;
+        };
+
+        let mut inputs = node
+            .inputs()
+            .into_iter()
+            .filter_map(|(input_id, input)| input.map(|input| (input_id, input)));
+
+        let (Some((input_id, input)), None) = (inputs.next(), inputs.next()) else {
+            break;
+        };
+        if input.type_id != PipelineDataType::MScan.into() {
+            break;
+        }
+
+        links.push(ChainLink {
+            node_id: output.node_id,
+            node: node.clone_boxed(),
+            input_id,
+            output_id: output.output_id,
+        });
+
+        if changed.contains(&output.node_id) {
+            source = Some((input, links.len()));
+        }
+
+        output = input;
+    }
+
+    let (source, len) = source?;
+    links.truncate(len);
+    links.reverse();
+
+    Some((source, links))
+}
+
+/// Runs temporary copies of a chain of nodes on a slice of the chain's input,
+/// to preview their settings on a few A scans. Like [super::sweep], only the
+/// slice is kept in memory. Dropping the run stops all of its tasks.
+pub struct PartialRun {
+    /// The A scans being run.
+    a_scans: Range<usize>,
+    state: watch::Receiver<PreviewState>,
+    input_error: watch::Receiver<Option<String>>,
+    /// Keeps the input of the first copy alive, see ParameterSweep.
+    _slice: ConnectionHandle,
+    runners: Vec<EphemeralRunner>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl PartialRun {
+    /// Starts running the A scans in `a_scans` through copies of the nodes in
+    /// `chain`, see [partial_chain]. The first copy is connected to `input`.
+    pub fn start(
+        executor: &PipelineExecutor,
+        mut input: ConnectionHandle,
+        chain: Vec<ChainLink>,
+        a_scans: Range<usize>,
+    ) -> Self {
+        let extended = a_scans.start.saturating_sub(MARGIN)..a_scans.end + MARGIN;
+        let offset = a_scans.start - extended.start;
+        let len = a_scans.len();
+
+        let (slice, mut slice_out) = ConnectionHandle::new::<requests::MScan>();
+        let mut slice_in = TaskInput::default();
+        slice_in.connect(&mut input);
+
+        let (input_error_tx, input_error) = watch::channel(None);
+
+        let mut tasks = vec![tokio::spawn(async move {
+            let result = serve_preview_slice(&mut slice_in, &mut slice_out, extended).await;
+
+            if let Err(e) = result {
+                input_error_tx.send_replace(Some(e.to_string()));
+
+                // Keep the output open, see ParameterSweep::start
+                futures::future::pending::<()>().await;
+            }
+        })];
+
+        let (state_tx, state) = watch::channel(PreviewState::Running(0.0));
+
+        let mut runners = Vec::with_capacity(chain.len());
+        let mut previous = Some(slice.clone());
+
+        for mut link in chain {
+            let mut runner = executor.spawn_ephemeral(link.node.as_mut());
+            if let Some(previous) = previous {
+                runner.connect_input(link.input_id, previous);
+            }
+
+            previous = runner.get_output(link.output_id);
+            runners.push(runner);
+        }
+
+        let mut m_scan_in = TaskInput::default();
+        let connected = previous.is_some_and(|mut output| m_scan_in.connect(&mut output));
+
+        if connected {
+            tasks.push(tokio::spawn(async move {
+                let (collected_tx, collected) = watch::channel(PreviewState::Running(0.0));
+
+                let state = match collect_preview(&mut m_scan_in, &collected_tx).await {
+                    Ok(()) => match &*collected.borrow() {
+                        PreviewState::Done(m_scan) => crop(m_scan, offset, len),
+                        state => state.clone(),
+                    },
+                    Err(e) => PreviewState::Failed(e.to_string()),
+                };
+
+                state_tx.send_replace(state);
+            }));
+        } else {
+            state_tx.send_replace(PreviewState::Failed(
+                "Node has no M scan output".to_string(),
+            ));
+        }
+
+        Self {
+            a_scans,
+            state,
+            input_error,
+            _slice: slice,
+            runners,
+            tasks,
+        }
+    }
+
+    /// The A scans requested in [Self::start]. The result may be shorter at
+    /// the end of the M scan.
+    pub fn a_scans(&self) -> Range<usize> {
+        self.a_scans.clone()
+    }
+
+    pub fn state(&self) -> PreviewState {
+        match self.input_error.borrow().clone() {
+            Some(e) => PreviewState::Failed(e),
+            None => self.state.borrow().clone(),
+        }
+    }
+}
+
+impl Drop for PartialRun {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        // Dropping the runners stops the node copies
+        self.runners.clear();
+    }
+}
+
+/// Removes the margin around the result of a [PartialRun].
+fn crop(m_scan: &DataMatrix, offset: usize, len: usize) -> PreviewState {
+    if offset >= m_scan.ncols() {
+        return PreviewState::Failed("The A scans are outside of the M scan".to_string());
+    }
+
+    let len = len.min(m_scan.ncols() - offset);
+    PreviewState::Done(Arc::new(m_scan.columns(offset, len)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use nalgebra::DMatrix;
+    use serde_json::json;
+
+    use crate::{
+        node_graph::InputIdSingle,
+        pipeline::{execution::TaskOutput, nodes::filter},
+    };
+
+    use super::*;
+
+    /// Serves an M scan, whose values are the index of their A scan, in
+    /// chunks of 7 A scans.
+    fn spawn_m_scan_producer(a_scan_count: usize) -> (ConnectionHandle, JoinHandle<()>) {
+        let (handle, mut output): (_, TaskOutput<requests::MScan>) =
+            ConnectionHandle::new::<requests::MScan>();
+
+        let producer = tokio::spawn(async move {
+            loop {
+                let _req = output.receive().await;
+
+                let (res, tx) = requests::StreamedResponse::new(64);
+                output.respond(requests::MScanResponse {
+                    data: res,
+                    a_scan_count,
+                    a_scan_samples: 4,
+                });
+
+                for start in (0..a_scan_count).step_by(7) {
+                    let ncols = (a_scan_count - start).min(7);
+                    let chunk = DMatrix::from_fn(4, ncols, |_, c| (start + c) as f32);
+                    tx.send(Arc::new(chunk.into()));
+                }
+                tx.finish();
+            }
+        });
+
+        (handle, producer)
+    }
+
+    fn pipeline() -> Pipeline {
+        // The input node serves M scans on output 1
+        let filter = |input: u32, output: u32| {
+            json!({
+                "type": "filter",
+                "filter_type": "Gaussian",
+                "input": {
+                    "value": null,
+                    "connection": { "node_id": input, "output_id": output, "type_id": 2 },
+                },
+            })
+        };
+
+        serde_json::from_value(json!({
+            "nodes": {
+                "1": {
+                    "type": "binary_input",
+                    "path": "",
+                    "input_type": "MScan",
+                    "data_type": "U16",
+                    "a_scan_length": 4,
+                },
+                "2": filter(1, 1),
+                "3": filter(2, 0),
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn chain_of_changed_nodes() {
+        let mut pipeline = pipeline();
+        let m_scan = NodeOutput::new(
+            3.into(),
+            filter::OutputId::Filtered.into(),
+            PipelineDataType::MScan.into(),
+        );
+
+        let (source, chain) = partial_chain(&pipeline, m_scan, &HashSet::from([2.into()])).unwrap();
+        assert_eq!(source.node_id, 1.into());
+        assert_eq!(chain.len(), 2);
+
+        let (source, chain) = partial_chain(&pipeline, m_scan, &HashSet::from([3.into()])).unwrap();
+        assert_eq!(source.node_id, 2.into());
+        assert_eq!(chain.len(), 1);
+
+        // The input node has no input, that could feed the chain
+        assert!(partial_chain(&pipeline, m_scan, &HashSet::from([1.into()])).is_none());
+        assert!(partial_chain(&pipeline, m_scan, &HashSet::new()).is_none());
+
+        pipeline.disabled.insert(2.into());
+        assert!(partial_chain(&pipeline, m_scan, &HashSet::from([2.into()])).is_none());
+    }
+
+    /// Collects the result of a [PartialRun] of a filter passing its input
+    /// through.
+    async fn run_pass_through(a_scans: Range<usize>) -> Arc<DataMatrix> {
+        let (input, _producer) = spawn_m_scan_producer(100);
+        let chain = vec![ChainLink {
+            node_id: 1.into(),
+            node: Box::new(filter::Node::gaussian()),
+            input_id: InputIdSingle.into(),
+            output_id: filter::OutputId::Original.into(),
+        }];
+
+        let executor = PipelineExecutor::new();
+        let run = PartialRun::start(&executor, input, chain, a_scans.clone());
+        assert_eq!(run.a_scans(), a_scans);
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                match run.state() {
+                    PreviewState::Running(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+                    PreviewState::Done(m_scan) => break m_scan,
+                    PreviewState::Failed(e) => panic!("Run failed: {}", e),
+                }
+            }
+        })
+        .await
+        .expect("Run should finish")
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn runs_slice() {
+        let m_scan = run_pass_through(40..90).await;
+        let DataMatrix::F32(m_scan) = m_scan.as_ref() else {
+            panic!("Unexpected data type");
+        };
+        assert_eq!(m_scan.shape(), (4, 50));
+        for (i, a_scan) in m_scan.column_iter().enumerate() {
+            assert!(a_scan.iter().all(|v| *v == (40 + i) as f32));
+        }
+
+        // The margin is cut off at the end of the M scan
+        let m_scan = run_pass_through(90..120).await;
+        assert_eq!(m_scan.ncols(), 10);
+    }
+}
@@ -0,0 +1,357 @@
+//! Saving and loading pipeline files off the UI thread.
+//!
+//! Serializing a large pipeline stalls the UI for a noticeable time. The UI
+//! thread only takes a [Snapshot], cloning the nodes. Serializing and writing,
+//! as well as reading and parsing, run on the blocking threads of the tokio
+//! runtime and report back through a channel, see [Saver] and [Loader].
+
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+
+use crate::{gui::node_graph::NodeGraphEditState, settings};
+
+use super::{nodes, Pipeline};
+
+/// Time taking a [Snapshot] may take on the UI thread, one frame at 60 Hz.
+pub const SNAPSHOT_BUDGET: Duration = Duration::from_millis(16);
+
+/// Name of the file in [eframe::storage_dir], the pipeline is autosaved to.
+const AUTOSAVE_FILE_NAME: &str = "pipeline.json";
+
+/// The file the pipeline is saved to in regular intervals and on exit.
+pub fn autosave_path() -> Option<PathBuf> {
+    eframe::storage_dir(settings::APP_NAME).map(|dir| dir.join(AUTOSAVE_FILE_NAME))
+}
+
+/// Hash of the serialized pipeline, to tell whether it changed. Panning and
+/// zooming the editor is no change.
+pub fn hash(pipeline: &Pipeline, state: &NodeGraphEditState) -> u64 {
+    // Values have sorted keys, unlike the maps of the pipeline
+    let json = serde_json::to_value((pipeline, state.without_view()))
+        .unwrap()
+        .to_string();
+    let mut hasher = DefaultHasher::new();
+    json.hash(&mut hasher);
+    hasher.finish()
+}
+
+// MARK: Snapshot
+
+/// Copy of a pipeline and its editor state, taken on the UI thread and
+/// serialized on another one.
+pub struct Snapshot {
+    pipeline: Pipeline,
+    state: NodeGraphEditState,
+    /// Time taking the snapshot took.
+    duration: Duration,
+}
+
+impl Snapshot {
+    pub fn new(pipeline: &Pipeline, state: &NodeGraphEditState) -> Self {
+        let start = Instant::now();
+        let pipeline = pipeline.snapshot();
+        let state = state.clone();
+
+        Self {
+            pipeline,
+            state,
+            duration: start.elapsed(),
+        }
+    }
+
+    /// Time taking the snapshot took, compare with [SNAPSHOT_BUDGET].
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The pipeline file, pretty printed.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&(&self.pipeline, &self.state))
+    }
+
+    /// See [hash].
+    pub fn hash(&self) -> u64 {
+        hash(&self.pipeline, &self.state)
+    }
+}
+
+/// Writes `contents` next to `path` first and renames it, so an interrupted
+/// save does not leave a truncated file.
+fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    std::fs::write(&part, contents)?;
+    std::fs::rename(&part, path)
+}
+
+// MARK: Saver
+
+/// Where a [Saver] writes to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SaveTarget {
+    File(PathBuf),
+    /// The file at [autosave_path].
+    Autosave,
+}
+
+/// A save, that ended, see [Saver::poll].
+pub struct Saved {
+    pub target: SaveTarget,
+    /// [Snapshot::hash] of the saved pipeline.
+    pub result: anyhow::Result<u64>,
+}
+
+/// Saves [Snapshot]s on the tokio runtime, one after another.
+#[derive(Default)]
+pub struct Saver {
+    running: Option<(SaveTarget, mpsc::Receiver<anyhow::Result<u64>>)>,
+    /// Save to a file, requested while another save was running.
+    queued: Option<(PathBuf, Snapshot)>,
+}
+
+impl Saver {
+    /// Saves `snapshot` to `target`. While another save is running, a save to
+    /// a file waits for it, replacing one waiting already. Autosaves are
+    /// skipped then, the next one catches up.
+    pub fn save(&mut self, target: SaveTarget, snapshot: Snapshot) {
+        match (&self.running, target) {
+            (None, target) => self.start(target, snapshot),
+            (Some(_), SaveTarget::File(path)) => self.queued = Some((path, snapshot)),
+            (Some(_), SaveTarget::Autosave) => {}
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// The save, that ended since the last call. Starts the waiting one.
+    pub fn poll(&mut self) -> Option<Saved> {
+        let result = match self.running.as_ref()?.1.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => Err(anyhow!("The save was aborted")),
+        };
+        Some(self.finish(result))
+    }
+
+    /// Blocks, until every save ended, like before the app exits.
+    pub fn wait(&mut self) -> Vec<Saved> {
+        let mut saved = Vec::new();
+        while let Some((_, rx)) = &self.running {
+            let result = rx
+                .recv()
+                .unwrap_or_else(|_| Err(anyhow!("The save was aborted")));
+            saved.push(self.finish(result));
+        }
+        saved
+    }
+
+    fn finish(&mut self, result: anyhow::Result<u64>) -> Saved {
+        let (target, _) = self.running.take().unwrap();
+        if let Some((path, snapshot)) = self.queued.take() {
+            self.start(SaveTarget::File(path), snapshot);
+        }
+        Saved { target, result }
+    }
+
+    fn start(&mut self, target: SaveTarget, snapshot: Snapshot) {
+        let path = match &target {
+            SaveTarget::File(path) => Some(path.clone()),
+            SaveTarget::Autosave => autosave_path(),
+        };
+
+        let (tx, rx) = mpsc::channel();
+        tokio::task::spawn_blocking(move || {
+            let save = || {
+                let path = path.ok_or_else(|| anyhow!("No directory to autosave to"))?;
+                write_atomic(&path, &snapshot.to_json()?)?;
+                Ok(snapshot.hash())
+            };
+            // The app might not wait for the result
+            let _ = tx.send(save());
+        });
+
+        self.running = Some((target, rx));
+    }
+}
+
+// MARK: Loader
+
+/// A parsed pipeline file, see [parse].
+pub struct Loaded {
+    pub pipeline: Pipeline,
+    pub state: NodeGraphEditState,
+    /// [hash] of the pipeline, as loaded.
+    pub hash: u64,
+}
+
+impl Loaded {
+    pub fn empty() -> Self {
+        let pipeline = Pipeline::new();
+        let state = NodeGraphEditState::new();
+        Self {
+            hash: hash(&pipeline, &state),
+            pipeline,
+            state,
+        }
+    }
+}
+
+/// Parses a pipeline file. Paths of inputs and outputs, that do not exist,
+/// are cleared.
+pub fn parse(json: &str) -> serde_json::Result<Loaded> {
+    let (mut pipeline, state): (Pipeline, NodeGraphEditState) = serde_json::from_str(json)?;
+
+    for node in pipeline.nodes.values_mut() {
+        if let Some(node) = node
+            .as_any_mut()
+            .downcast_mut::<nodes::binary_input::Node>()
+        {
+            if !node.path.exists() {
+                node.path = "".into();
+            }
+        } else if let Some(node) = node.as_any_mut().downcast_mut::<nodes::output::Node>() {
+            if !node.path.exists() {
+                node.path = "".into();
+            }
+        }
+    }
+
+    Ok(Loaded {
+        hash: hash(&pipeline, &state),
+        pipeline,
+        state,
+    })
+}
+
+/// Reads and parses pipeline files on the tokio runtime.
+#[derive(Default)]
+pub struct Loader {
+    running: Option<(PathBuf, mpsc::Receiver<anyhow::Result<Loaded>>)>,
+}
+
+impl Loader {
+    /// Loads the pipeline file at `path`, abandoning a load still running.
+    pub fn load(&mut self, path: PathBuf) {
+        let (tx, rx) = mpsc::channel();
+        let file = path.clone();
+        tokio::task::spawn_blocking(move || {
+            let load = || Ok(parse(&std::fs::read_to_string(file)?)?);
+            let _ = tx.send(load());
+        });
+
+        self.running = Some((path, rx));
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// The pipeline loaded since the last call, with the file it was read
+    /// from.
+    pub fn poll(&mut self) -> Option<(PathBuf, anyhow::Result<Loaded>)> {
+        let result = match self.running.as_ref()?.1.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => Err(anyhow!("Loading was aborted")),
+        };
+        let (path, _) = self.running.take().unwrap();
+        Some((path, result))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{node_graph::NodeId, pipeline::nodes::external_command};
+
+    use super::*;
+
+    /// Pipeline with `count` nodes, each with `blob_len` bytes of arguments.
+    fn large_pipeline(count: usize, blob_len: usize) -> Pipeline {
+        let mut pipeline = Pipeline::new();
+        for i in 0..count {
+            let node = external_command::Node {
+                args: "-x ".repeat(blob_len / 3),
+                ..Default::default()
+            };
+            pipeline.nodes.insert(NodeId::from(i), Box::new(node));
+        }
+        pipeline
+    }
+
+    #[test]
+    fn snapshot_within_budget() {
+        let pipeline = large_pipeline(64, 64 * 1024);
+        let state = NodeGraphEditState::new();
+
+        let snapshot = Snapshot::new(&pipeline, &state);
+        assert!(
+            snapshot.duration() < SNAPSHOT_BUDGET,
+            "Snapshot took {:?}",
+            snapshot.duration()
+        );
+
+        // Serializing is left to another thread
+        let json = snapshot.to_json().unwrap();
+        assert!(json.len() > 64 * 64 * 1024);
+        assert_eq!(snapshot.hash(), hash(&pipeline, &state));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join("ivoct_persistence_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pipeline.json");
+
+        let pipeline = large_pipeline(4, 1024);
+        let state = NodeGraphEditState::new();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+
+        let mut saver = Saver::default();
+        saver.save(
+            SaveTarget::File(path.clone()),
+            Snapshot::new(&pipeline, &state),
+        );
+        // Waits for the first one
+        saver.save(
+            SaveTarget::File(path.clone()),
+            Snapshot::new(&pipeline, &state),
+        );
+        assert!(saver.is_busy());
+
+        let saved = saver.wait();
+        assert_eq!(saved.len(), 2);
+        for saved in saved {
+            assert_eq!(saved.target, SaveTarget::File(path.clone()));
+            assert_eq!(saved.result.unwrap(), hash(&pipeline, &state));
+        }
+        assert!(!dir.join("pipeline.json.part").exists());
+
+        let mut loader = Loader::default();
+        loader.load(path.clone());
+        let loaded = loop {
+            if let Some((loaded_path, loaded)) = loader.poll() {
+                assert_eq!(loaded_path, path);
+                break loaded.unwrap();
+            }
+            std::thread::yield_now();
+        };
+        assert_eq!(loaded.pipeline.nodes.len(), 4);
+        assert_eq!(loaded.hash, hash(&pipeline, &state));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
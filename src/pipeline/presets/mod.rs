@@ -1,3 +1,11 @@
 pub const PHANTOM_1_1_3: &str = include_str!("phantom1_1_3.json");
 pub const PHANTOM_1_2_4: &str = include_str!("phantom1_2_4.json");
 pub const CLINIC: &str = include_str!("clinic.json");
+/// Phantom 1.1.3 extended with a template for excluding everything above the
+/// catheter: The catheter mask of "Follow Catheter" is applied to the M scan
+/// using "Apply Mask", before it is passed to "Align Brightness".
+pub const CATHETER_MASK_TEMPLATE: &str = include_str!("catheter_mask.json");
+/// Pipeline of the first-run walkthrough, tuned for the synthetic pullback of
+/// [super::sample_data]. The paths of its inputs are relative to the directory
+/// of the sample data.
+pub const WALKTHROUGH: &str = include_str!("walkthrough.json");
@@ -0,0 +1,372 @@
+//! Priority of the blocking work of a node, see [Priority].
+//!
+//! Blocking computations of nodes go through [spawn_blocking], which runs them
+//! on the thread pool of the priority of the calling node task, and at most
+//! as many at once, as there are threads in the global rayon pool. When
+//! computations of both priorities wait for their turn, [Priority::Normal]
+//! ones go first, see [PrioritySemaphore].
+//!
+//! The priority is set by the node task for its run, see [scope]. Tasks not
+//! setting it run with [Priority::Normal].
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{sync::oneshot, task::JoinHandle};
+
+/// Priority of the blocking work of a node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Priority {
+    #[default]
+    Normal,
+    /// Runs on a smaller pool of threads with a lower scheduling priority,
+    /// and waits for normal work, when both compete. Meant for exports, that
+    /// should not slow down interactive work.
+    Background,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 2] = [Priority::Normal, Priority::Background];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Priority::Normal => "Normal",
+            Priority::Background => "Background",
+        }
+    }
+
+    /// Name of the pool, work of this priority runs on.
+    pub fn pool(self) -> &'static str {
+        match self {
+            Priority::Normal => "Blocking",
+            Priority::Background => "Background",
+        }
+    }
+}
+
+tokio::task_local! {
+    static PRIORITY: Priority;
+    static NODE: Arc<str>;
+}
+
+/// Priority of the node task calling this. [Priority::Normal] outside of
+/// [scope].
+pub fn current() -> Priority {
+    PRIORITY.try_with(|priority| *priority).unwrap_or_default()
+}
+
+/// Runs `task` with `priority`. Used by node tasks for their runs.
+pub async fn scope<F: Future>(priority: Priority, task: F) -> F::Output {
+    PRIORITY.scope(priority, task).await
+}
+
+/// Runs a node task, labeling its jobs with the name of the node, see
+/// [active_jobs].
+pub(super) async fn node_scope<F: Future>(node: Arc<str>, task: F) -> F::Output {
+    NODE.scope(node, task).await
+}
+
+// MARK: Pools
+
+/// Runs `f` on the pool of [current], once [LIMITER] lets it. Like
+/// [tokio::task::spawn_blocking], the computation keeps running, when the
+/// handle is dropped.
+pub fn spawn_blocking<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let priority = current();
+    let node = NODE.try_with(Arc::clone).ok();
+
+    tokio::spawn(async move {
+        let mut job = Jobs::register(node, priority);
+        let _permit = LIMITER.acquire(priority).await;
+        job.start();
+
+        match priority {
+            Priority::Normal => match tokio::task::spawn_blocking(f).await {
+                Ok(result) => result,
+                Err(e) => panic::resume_unwind(e.into_panic()),
+            },
+            Priority::Background => {
+                let (tx, rx) = oneshot::channel();
+                BACKGROUND_POOL.spawn(move || {
+                    let _ = tx.send(panic::catch_unwind(AssertUnwindSafe(f)));
+                });
+                match rx.await.expect("Background job should send its result") {
+                    Ok(result) => result,
+                    Err(e) => panic::resume_unwind(e),
+                }
+            }
+        }
+    })
+}
+
+/// Limits the blocking computations running at once, see [spawn_blocking].
+static LIMITER: LazyLock<PrioritySemaphore> =
+    LazyLock::new(|| PrioritySemaphore::new(rayon::current_num_threads()));
+
+/// Pool for [Priority::Background]. Parallel iterators inside its jobs use
+/// it as well.
+static BACKGROUND_POOL: LazyLock<rayon::ThreadPool> = LazyLock::new(|| {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads((rayon::current_num_threads() / 4).max(1))
+        .thread_name(|i| format!("background-{i}"))
+        .start_handler(|_| lower_thread_priority())
+        .build()
+        .expect("Failed to create the background thread pool")
+});
+
+/// Raises the niceness of the calling thread. Only Linux schedules threads
+/// by their own niceness, elsewhere this does nothing.
+fn lower_thread_priority() {
+    #[cfg(target_os = "linux")]
+    // SAFETY: Only changes the scheduling priority of the calling thread
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, 0, 10);
+    }
+}
+
+// MARK: PrioritySemaphore
+
+/// Semaphore with two tiers of waiters. A released permit goes to the longest
+/// waiting [Priority::Normal] acquirer, and only if there is none, to a
+/// [Priority::Background] one.
+#[derive(Clone)]
+pub struct PrioritySemaphore(Arc<Mutex<SemaphoreState>>);
+
+struct SemaphoreState {
+    available: usize,
+    /// Waiters of [Priority::Normal] and [Priority::Background].
+    waiting: [VecDeque<oneshot::Sender<Permit>>; 2],
+}
+
+/// Permit of a [PrioritySemaphore], released when dropped.
+pub struct Permit {
+    /// [None], when it was not handed over.
+    semaphore: Option<PrioritySemaphore>,
+}
+
+impl PrioritySemaphore {
+    pub fn new(permits: usize) -> Self {
+        Self(Arc::new(Mutex::new(SemaphoreState {
+            available: permits,
+            waiting: Default::default(),
+        })))
+    }
+
+    /// Waits for a permit. Dropping the future gives up the place in the
+    /// queue.
+    pub async fn acquire(&self, priority: Priority) -> Permit {
+        let rx = {
+            let mut state = self.0.lock().unwrap();
+            if state.available > 0 {
+                state.available -= 1;
+                return Permit {
+                    semaphore: Some(self.clone()),
+                };
+            }
+
+            let (tx, rx) = oneshot::channel();
+            state.waiting[priority as usize].push_back(tx);
+            rx
+        };
+
+        rx.await.expect("Semaphore should hand over a permit")
+    }
+
+    /// Number of permits not taken.
+    #[cfg(test)]
+    fn available(&self) -> usize {
+        self.0.lock().unwrap().available
+    }
+
+    fn release(&self) {
+        loop {
+            let next = {
+                let mut state = self.0.lock().unwrap();
+                let [normal, background] = &mut state.waiting;
+                match normal.pop_front().or_else(|| background.pop_front()) {
+                    Some(next) => next,
+                    None => {
+                        state.available += 1;
+                        return;
+                    }
+                }
+            };
+
+            let permit = Permit {
+                semaphore: Some(self.clone()),
+            };
+            match next.send(permit) {
+                Ok(()) => return,
+                // The waiter is gone, the permit goes to the next one
+                Err(mut permit) => permit.semaphore = None,
+            }
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(semaphore) = self.semaphore.take() {
+            semaphore.release();
+        }
+    }
+}
+
+// MARK: Jobs
+
+/// A computation started with [spawn_blocking], that did not finish yet.
+#[derive(Debug, Clone)]
+pub struct Job {
+    /// Name of the node, [None] outside of node tasks.
+    pub node: Option<Arc<str>>,
+    pub priority: Priority,
+    /// Whether it runs or still waits for its turn.
+    pub running: bool,
+    /// When it was spawned or started running.
+    pub since: Instant,
+}
+
+static JOBS: LazyLock<Mutex<HashMap<u64, Job>>> = LazyLock::new(Mutex::default);
+
+/// Jobs started with [spawn_blocking], that did not finish yet, in the order
+/// they were spawned.
+pub fn active_jobs() -> Vec<Job> {
+    let jobs = JOBS.lock().unwrap();
+    let mut ids = jobs.keys().copied().collect::<Vec<_>>();
+    ids.sort_unstable();
+    ids.iter().map(|id| jobs[id].clone()).collect()
+}
+
+/// Entry of a job in [JOBS], removed when dropped.
+struct Jobs(u64);
+
+impl Jobs {
+    fn register(node: Option<Arc<str>>, priority: Priority) -> Self {
+        static ID: AtomicU64 = AtomicU64::new(0);
+
+        let id = ID.fetch_add(1, Ordering::Relaxed);
+        JOBS.lock().unwrap().insert(
+            id,
+            Job {
+                node,
+                priority,
+                running: false,
+                since: Instant::now(),
+            },
+        );
+        Self(id)
+    }
+
+    fn start(&mut self) {
+        if let Some(job) = JOBS.lock().unwrap().get_mut(&self.0) {
+            job.running = true;
+            job.since = Instant::now();
+        }
+    }
+}
+
+impl Drop for Jobs {
+    fn drop(&mut self) {
+        JOBS.lock().unwrap().remove(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::pin::pin;
+
+    use futures::poll;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn normal_waiters_go_first() {
+        let semaphore = PrioritySemaphore::new(1);
+        let permit = semaphore.acquire(Priority::Background).await;
+
+        let mut background = pin!(semaphore.acquire(Priority::Background));
+        let mut normal = pin!(semaphore.acquire(Priority::Normal));
+        assert!(poll!(background.as_mut()).is_pending());
+        assert!(poll!(normal.as_mut()).is_pending());
+
+        // The normal waiter gets the permit, though it came later
+        drop(permit);
+        assert!(poll!(background.as_mut()).is_pending());
+        let permit = normal.await;
+
+        drop(permit);
+        let permit = background.await;
+        assert_eq!(semaphore.available(), 0);
+
+        drop(permit);
+        assert_eq!(semaphore.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn waiters_in_order() {
+        let semaphore = PrioritySemaphore::new(1);
+        let permit = semaphore.acquire(Priority::Normal).await;
+
+        let mut first = pin!(semaphore.acquire(Priority::Normal));
+        let mut second = pin!(semaphore.acquire(Priority::Normal));
+        assert!(poll!(first.as_mut()).is_pending());
+        assert!(poll!(second.as_mut()).is_pending());
+
+        drop(permit);
+        assert!(poll!(second.as_mut()).is_pending());
+        drop(first.await);
+        drop(second.await);
+        assert_eq!(semaphore.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn dropped_waiters_give_up() {
+        let semaphore = PrioritySemaphore::new(1);
+        let permit = semaphore.acquire(Priority::Normal).await;
+
+        let mut gone = Box::pin(semaphore.acquire(Priority::Normal));
+        let mut background = pin!(semaphore.acquire(Priority::Background));
+        assert!(poll!(gone.as_mut()).is_pending());
+        assert!(poll!(background.as_mut()).is_pending());
+        drop(gone);
+
+        // The permit skips the dropped waiter
+        drop(permit);
+        drop(background.await);
+        assert_eq!(semaphore.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn background_jobs_run_on_their_pool() {
+        let thread = || std::thread::current().name().map(str::to_string);
+
+        let normal = spawn_blocking(thread).await.unwrap();
+        assert!(!normal.unwrap_or_default().starts_with("background"));
+
+        let background = scope(Priority::Background, async {
+            spawn_blocking(thread).await.unwrap()
+        })
+        .await;
+        assert!(background.unwrap().starts_with("background"));
+
+        // Panics reach the awaiting task
+        let result = scope(Priority::Background, async {
+            spawn_blocking(|| panic!("Failed")).await
+        })
+        .await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,245 @@
+//! Layout of the JSON export of a pullback, as written by the output node in
+//! [crate::pipeline::nodes::output::ExportFormat::Json].
+//!
+//! One [PullbackDocument] holds the B scan boundaries, the lumen contour and
+//! the diameters, whichever the exported output provides. Bundling them with
+//! the bundle node writes all of them into one document, so viewers outside
+//! of this program, like the web viewer, read a single file. An example is
+//! `doc/pullback_example.json`.
+
+use serde::{Deserialize, Serialize};
+
+use super::types::{BScanDiameter, SCHEMA_VERSION};
+
+/// A pullback, as exported. Parts the output did not provide are left out of
+/// the JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullbackDocument {
+    /// [SCHEMA_VERSION] of the document.
+    pub schema_version: u32,
+    /// Number of A scans of the M scan, as announced by the exported output.
+    pub a_scan_count: usize,
+    /// [crate::pipeline::Pipeline::provenance] of the exported output, as hex.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<String>,
+    /// Index of the first A scan of every B scan, followed by the index after
+    /// the last one, like [crate::pipeline::requests::BScanSegmentationResponse].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub b_scan_boundaries: Option<Vec<usize>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lumen_contour: Option<LumenContour>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diameters: Option<Vec<DiameterEntry>>,
+}
+
+impl PullbackDocument {
+    pub fn new(a_scan_count: usize, provenance: Option<u64>) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            a_scan_count,
+            provenance: provenance.map(|provenance| format!("{:016x}", provenance)),
+            b_scan_boundaries: None,
+            lumen_contour: None,
+            diameters: None,
+        }
+    }
+}
+
+/// Depth of the lumen border in every [Self::stride]th A scan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LumenContour {
+    /// Distance of the A scans of [Self::depths]. The first one is A scan 0.
+    pub stride: usize,
+    /// Number of samples per A scan of the segmented M scan.
+    pub a_scan_samples: usize,
+    /// Depth in samples, the `i`th one of A scan `i * stride`.
+    pub depths: Vec<u32>,
+}
+
+/// Diameters of one B scan in millimetres.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiameterEntry {
+    /// Index of the B scan in [PullbackDocument::b_scan_boundaries], if
+    /// exported, or the number of the diameter otherwise.
+    pub b_scan: usize,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    /// Cross-sectional area of the lumen in mm², if bundled with the B scan
+    /// boundaries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub area: Option<f32>,
+}
+
+/// Collects a streamed segmentation into a [LumenContour], keeping only every
+/// [LumenContour::stride]th A scan.
+pub struct ContourSampler {
+    contour: LumenContour,
+    /// A scans received so far.
+    a_scans: usize,
+}
+
+impl ContourSampler {
+    /// A `stride` of 0 is treated as 1.
+    pub fn new(stride: usize, a_scan_samples: usize) -> Self {
+        Self {
+            contour: LumenContour {
+                stride: stride.max(1),
+                a_scan_samples,
+                depths: Vec::new(),
+            },
+            a_scans: 0,
+        }
+    }
+
+    /// Adds the next chunk of A scans.
+    pub fn push(&mut self, depths: &[u32]) {
+        let stride = self.contour.stride;
+        // First A scan of the chunk, that lies on the stride
+        let first = self.a_scans.next_multiple_of(stride) - self.a_scans;
+        self.contour
+            .depths
+            .extend(depths.iter().skip(first).step_by(stride));
+        self.a_scans += depths.len();
+    }
+
+    pub fn finish(self) -> LumenContour {
+        self.contour
+    }
+}
+
+/// Entries of `diameters`. Their B scans are looked up in `boundaries`, if
+/// given, which also assigns them their `areas`, one per B scan.
+pub fn diameter_entries(
+    diameters: &[BScanDiameter],
+    boundaries: Option<&[usize]>,
+    areas: Option<&[f32]>,
+) -> Vec<DiameterEntry> {
+    diameters
+        .iter()
+        .enumerate()
+        .map(|(i, diameter)| {
+            let b_scan = boundaries
+                .and_then(|boundaries| boundaries.binary_search(&diameter.b_scan_start).ok());
+            DiameterEntry {
+                b_scan: b_scan.unwrap_or(i),
+                min: diameter.min,
+                max: diameter.max,
+                mean: diameter.mean,
+                area: b_scan.and_then(|b_scan| areas?.get(b_scan).copied()),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::Vector2;
+    use serde_json::json;
+
+    use super::*;
+
+    fn diameter(b_scan_start: usize, b_scan_end: usize, min: f32) -> BScanDiameter {
+        BScanDiameter {
+            b_scan_start,
+            b_scan_end,
+            min,
+            max: min + 1.0,
+            mean: min + 0.5,
+            min_points: [Vector2::zeros(); 2],
+            max_points: [Vector2::zeros(); 2],
+        }
+    }
+
+    #[test]
+    fn samples_contour() {
+        let mut sampler = ContourSampler::new(3, 512);
+        // Chunks not aligned with the stride
+        sampler.push(&[0, 1, 2, 3]);
+        sampler.push(&[4]);
+        sampler.push(&[5, 6, 7, 8, 9, 10]);
+
+        let contour = sampler.finish();
+        assert_eq!(contour.depths, [0, 3, 6, 9]);
+        assert_eq!(contour.a_scan_samples, 512);
+
+        let mut sampler = ContourSampler::new(0, 512);
+        sampler.push(&[7, 8]);
+        assert_eq!(sampler.finish().depths, [7, 8]);
+    }
+
+    #[test]
+    fn entries_of_diameters() {
+        let diameters = [diameter(100, 200, 2.0), diameter(200, 300, 3.0)];
+
+        // The diameter node skips the first B scan
+        let entries = diameter_entries(
+            &diameters,
+            Some(&[0, 100, 200, 300]),
+            Some(&[1.0, 2.0, 3.0]),
+        );
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (e.b_scan, e.area))
+                .collect::<Vec<_>>(),
+            [(1, Some(2.0)), (2, Some(3.0))]
+        );
+
+        let entries = diameter_entries(&diameters, None, Some(&[1.0, 2.0, 3.0]));
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (e.b_scan, e.area))
+                .collect::<Vec<_>>(),
+            [(0, None), (1, None)]
+        );
+    }
+
+    #[test]
+    fn schema() {
+        let mut document = PullbackDocument::new(300, Some(0xabc));
+        document.b_scan_boundaries = Some(vec![0, 100, 200, 300]);
+        document.diameters = Some(diameter_entries(
+            &[diameter(100, 200, 2.0)],
+            document.b_scan_boundaries.as_deref(),
+            None,
+        ));
+
+        let value = serde_json::to_value(&document).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "schema_version": SCHEMA_VERSION,
+                "a_scan_count": 300,
+                "provenance": "0000000000000abc",
+                "b_scan_boundaries": [0, 100, 200, 300],
+                "diameters": [{ "b_scan": 1, "min": 2.0, "max": 3.0, "mean": 2.5 }],
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<PullbackDocument>(value).unwrap(),
+            document
+        );
+    }
+
+    #[test]
+    fn example_parses() {
+        let json = include_str!("../../doc/pullback_example.json");
+        let document: PullbackDocument = serde_json::from_str(json).unwrap();
+
+        assert_eq!(document.schema_version, SCHEMA_VERSION);
+        let boundaries = document.b_scan_boundaries.unwrap();
+        assert_eq!(boundaries.last(), Some(&document.a_scan_count));
+
+        let contour = document.lumen_contour.unwrap();
+        assert_eq!(
+            contour.depths.len(),
+            document.a_scan_count.div_ceil(contour.stride)
+        );
+        for entry in document.diameters.unwrap() {
+            assert!(entry.b_scan + 1 < boundaries.len());
+            assert!(entry.min <= entry.mean && entry.mean <= entry.max);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Seeded pseudo random numbers for the sample data and for tests, so their
+//! data does not depend on a random number crate and a failing case can be
+//! reproduced from its seed.
+
+/// Linear congruential generator with the multiplier and increment of PCG.
+#[derive(Debug, Clone)]
+pub struct Lcg(u64);
+
+impl Lcg {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0
+    }
+
+    /// Uniform in `0..1`.
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `0..n`.
+    #[cfg(test)]
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() >> 33) as usize % n
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reproducible_from_seed() {
+        let mut a = Lcg::new(3);
+        let mut b = Lcg::new(3);
+
+        for _ in 0..1000 {
+            let value = a.uniform();
+            assert_eq!(value, b.uniform());
+            assert!((0.0..1.0).contains(&value));
+
+            let index = a.below(7);
+            assert_eq!(index, b.below(7));
+            assert!(index < 7);
+        }
+
+        assert_ne!(Lcg::new(3).uniform(), Lcg::new(4).uniform());
+    }
+}
@@ -0,0 +1,176 @@
+//! The part of the pullback the user works on, selected once for the whole
+//! app with the range selector under the views.
+//!
+//! The selection is published through a [watch] channel, see [selected].
+//! Views restrict what they show to it, output nodes can export only the
+//! selected A scans and previews read it instead of their own limits. M scan
+//! views report the size of the pullback with [report_extent], so the
+//! selector can clamp the selection and offer B scans.
+
+use std::{
+    ops::Range,
+    sync::{LazyLock, RwLock},
+};
+
+use tokio::sync::watch;
+
+/// Selected A scans. [None] selects the whole pullback.
+pub type Selection = Option<Range<usize>>;
+
+static SELECTION: LazyLock<watch::Sender<Selection>> = LazyLock::new(|| watch::Sender::new(None));
+
+static EXTENT: RwLock<Extent> = RwLock::new(Extent {
+    a_scan_count: 0,
+    b_scans: Vec::new(),
+});
+
+/// Size of the pullback, as seen by the M scan views.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Extent {
+    pub a_scan_count: usize,
+    /// B scan boundaries as A scan indices, when a segmentation is
+    /// available. Contains one more entry than there are B scans.
+    pub b_scans: Vec<usize>,
+}
+
+impl Extent {
+    pub fn b_scan_count(&self) -> usize {
+        self.b_scans.len().saturating_sub(1)
+    }
+}
+
+// MARK: Channel
+
+/// The selection currently in effect.
+pub fn selected() -> Selection {
+    SELECTION.borrow().clone()
+}
+
+/// Replaces the selection. Receivers are only notified, if it differs.
+pub fn publish(selection: Selection) {
+    SELECTION.send_if_modified(|current| {
+        let modified = *current != selection;
+        *current = selection;
+        modified
+    });
+}
+
+/// The size of the pullback last reported.
+pub fn extent() -> Extent {
+    EXTENT.read().unwrap().clone()
+}
+
+/// Reports the size of the pullback shown by a view. Cheap, when nothing
+/// changed.
+pub fn report_extent(a_scan_count: usize, b_scans: &[usize]) {
+    {
+        let extent = EXTENT.read().unwrap();
+        if extent.a_scan_count == a_scan_count && extent.b_scans == b_scans {
+            return;
+        }
+    }
+
+    *EXTENT.write().unwrap() = Extent {
+        a_scan_count,
+        b_scans: b_scans.to_vec(),
+    };
+}
+
+// MARK: Helpers
+
+/// Limits `selection` to `a_scan_count` A scans. Selections left empty
+/// become [None].
+pub fn clamp(selection: Selection, a_scan_count: usize) -> Selection {
+    let selection = selection?;
+    let start = selection.start.min(a_scan_count);
+    let end = selection.end.min(a_scan_count);
+
+    (start < end).then_some(start..end)
+}
+
+/// The B scans overlapping `a_scans`, given the B scan boundaries.
+pub fn b_scans_of(a_scans: &Range<usize>, b_scans: &[usize]) -> Range<usize> {
+    let count = b_scans.len().saturating_sub(1);
+    let first = b_scans[..count].partition_point(|&start| start <= a_scans.start);
+    let last = b_scans[..count].partition_point(|&start| start < a_scans.end);
+
+    first.saturating_sub(1)..last.max(first.saturating_sub(1))
+}
+
+/// The A scans of the B scans in `b_scan_range`, given the B scan boundaries.
+pub fn a_scans_of(b_scan_range: &Range<usize>, b_scans: &[usize]) -> Range<usize> {
+    let count = b_scans.len().saturating_sub(1);
+    let start = b_scan_range.start.min(count);
+    let end = b_scan_range.end.clamp(start, count);
+
+    match b_scans.is_empty() {
+        true => 0..0,
+        false => b_scans[start]..b_scans[end],
+    }
+}
+
+/// A scans a preview processes: the start of the selection, or of the
+/// pullback, at most `limit` A scans long.
+pub fn preview_slice(selection: &Selection, limit: usize) -> Range<usize> {
+    match selection {
+        Some(selection) => selection.start..selection.end.min(selection.start + limit),
+        None => 0..limit,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clamping() {
+        assert_eq!(clamp(Some(10..20), 100), Some(10..20));
+        assert_eq!(clamp(Some(10..200), 100), Some(10..100));
+        assert_eq!(clamp(Some(0..100), 100), Some(0..100));
+        assert_eq!(clamp(Some(150..200), 100), None);
+        assert_eq!(clamp(None, 100), None);
+    }
+
+    #[test]
+    fn b_scans() {
+        // 3 B scans of 10 A scans
+        let boundaries = [0, 10, 20, 30];
+
+        assert_eq!(b_scans_of(&(0..30), &boundaries), 0..3);
+        assert_eq!(b_scans_of(&(10..20), &boundaries), 1..2);
+        assert_eq!(b_scans_of(&(15..21), &boundaries), 1..3);
+        assert_eq!(b_scans_of(&(25..30), &boundaries), 2..3);
+
+        assert_eq!(a_scans_of(&(1..3), &boundaries), 10..30);
+        assert_eq!(a_scans_of(&(2..9), &boundaries), 20..30);
+        assert_eq!(a_scans_of(&(0..1), &[]), 0..0);
+
+        for b_scan_range in [0..1, 0..3, 1..2, 2..3] {
+            let a_scans = a_scans_of(&b_scan_range, &boundaries);
+            assert_eq!(b_scans_of(&a_scans, &boundaries), b_scan_range);
+        }
+    }
+
+    #[test]
+    fn previews() {
+        assert_eq!(preview_slice(&None, 256), 0..256);
+        assert_eq!(preview_slice(&Some(1000..5000), 256), 1000..1256);
+        assert_eq!(preview_slice(&Some(1000..1100), 256), 1000..1100);
+    }
+
+    #[test]
+    fn channel() {
+        let mut rx = SELECTION.subscribe();
+        rx.mark_unchanged();
+
+        publish(Some(5..10));
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(*rx.borrow_and_update(), Some(5..10));
+
+        publish(Some(5..10));
+        assert!(!rx.has_changed().unwrap());
+
+        publish(None);
+        assert_eq!(selected(), None);
+    }
+}
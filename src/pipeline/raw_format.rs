@@ -0,0 +1,455 @@
+//! Layout of raw binary scan files, as written by the output node and read by
+//! the binary input node.
+//!
+//! The values are stored A scan by A scan in the chosen [Endianness].
+//! Optionally, a [RawHeader] precedes them, so the files describe themselves.
+
+use std::{io::Read, path::Path};
+
+use anyhow::anyhow;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+use super::types::{DataMatrix, DataType};
+
+// MARK: Endianness
+
+/// Byte order of the values in a file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endianness {
+    pub const VALUES: [Endianness; 2] = [Endianness::Little, Endianness::Big];
+
+    pub const NATIVE: Endianness = if cfg!(target_endian = "big") {
+        Endianness::Big
+    } else {
+        Endianness::Little
+    };
+
+    /// Converts the values in `bytes` between the native byte order and
+    /// `self` in parallel. Converting twice restores the values.
+    pub fn convert(&self, bytes: &mut [u8], data_type: DataType) {
+        if *self == Self::NATIVE || data_type.size() == 1 {
+            return;
+        }
+
+        bytes
+            .par_chunks_exact_mut(data_type.size())
+            .with_min_len(4096)
+            .for_each(|value| value.reverse());
+    }
+}
+
+// MARK: RawHeader
+
+/// Optional header of raw scan files, 32 bytes long:
+///
+/// | Offset | Size | Content                                              |
+/// |--------|------|------------------------------------------------------|
+/// | 0      | 8    | Magic `IVOCTRAW`                                     |
+/// | 8      | 1    | Version, currently 1                                 |
+/// | 9      | 1    | Endianness of the data and the counts, 0 little, 1 big |
+/// | 10     | 1    | Data type, 0 to 5 for U8, U16, U32, U64, F32 and F64 |
+/// | 11     | 5    | Reserved, zero                                       |
+/// | 16     | 8    | A scan samples                                       |
+/// | 24     | 8    | A scan count                                         |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawHeader {
+    pub endianness: Endianness,
+    pub data_type: DataType,
+    pub a_scan_samples: usize,
+    pub a_scan_count: usize,
+}
+
+impl RawHeader {
+    pub const SIZE: usize = 32;
+
+    const MAGIC: &'static [u8; 8] = b"IVOCTRAW";
+    const VERSION: u8 = 1;
+
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+
+        bytes[..8].copy_from_slice(Self::MAGIC);
+        bytes[8] = Self::VERSION;
+        bytes[9] = match self.endianness {
+            Endianness::Little => 0,
+            Endianness::Big => 1,
+        };
+        bytes[10] = DataType::VALUES
+            .iter()
+            .position(|t| *t == self.data_type)
+            .unwrap() as u8;
+
+        for (range, value) in [
+            (16..24, self.a_scan_samples as u64),
+            (24..32, self.a_scan_count as u64),
+        ] {
+            bytes[range].copy_from_slice(&match self.endianness {
+                Endianness::Little => value.to_le_bytes(),
+                Endianness::Big => value.to_be_bytes(),
+            });
+        }
+
+        bytes
+    }
+
+    /// Returns [None], if `bytes` do not start with a header. Fails, if the
+    /// header is not supported.
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Option<Self>> {
+        if bytes.len() < Self::SIZE || &bytes[..8] != Self::MAGIC {
+            return Ok(None);
+        }
+
+        if bytes[8] != Self::VERSION {
+            return Err(anyhow!("Unsupported raw header version {}", bytes[8]));
+        }
+
+        let endianness = match bytes[9] {
+            0 => Endianness::Little,
+            1 => Endianness::Big,
+            flag => return Err(anyhow!("Invalid endianness {} in raw header", flag)),
+        };
+
+        let data_type = *DataType::VALUES
+            .get(bytes[10] as usize)
+            .ok_or_else(|| anyhow!("Invalid data type {} in raw header", bytes[10]))?;
+
+        let field = |offset: usize| {
+            let value = bytes[offset..offset + 8].try_into().unwrap();
+            let value = match endianness {
+                Endianness::Little => u64::from_le_bytes(value),
+                Endianness::Big => u64::from_be_bytes(value),
+            };
+            value as usize
+        };
+
+        let a_scan_samples = field(16);
+        if a_scan_samples == 0 {
+            return Err(anyhow!("Raw header has no A scan samples"));
+        }
+
+        Ok(Some(Self {
+            endianness,
+            data_type,
+            a_scan_samples,
+            a_scan_count: field(24),
+        }))
+    }
+
+    /// Reads the header at the start of `file`. Without a header, the file is
+    /// rewound, so the data can be read from the start.
+    pub async fn read(file: &mut fs::File) -> anyhow::Result<Option<Self>> {
+        let mut bytes = [0; Self::SIZE];
+        let mut len = 0;
+
+        while len < Self::SIZE {
+            match file.read(&mut bytes[len..]).await? {
+                0 => break,
+                read => len += read,
+            }
+        }
+
+        let header = Self::parse(&bytes[..len])?;
+        if header.is_none() {
+            file.rewind().await?;
+        }
+
+        Ok(header)
+    }
+
+    /// Blocking version of [Self::read], that ignores errors. For quickly
+    /// inspecting a file from the UI.
+    pub fn peek(path: &Path) -> Option<Self> {
+        let mut bytes = Vec::with_capacity(Self::SIZE);
+        std::fs::File::open(path)
+            .ok()?
+            .take(Self::SIZE as u64)
+            .read_to_end(&mut bytes)
+            .ok()?;
+
+        Self::parse(&bytes).ok().flatten()
+    }
+}
+
+// MARK: Dimensions
+
+/// Samples per A scan in raw files, that are considered typical for OCT.
+pub const TYPICAL_SAMPLES: std::ops::RangeInclusive<usize> = 256..=4096;
+
+/// A scan samples considered at all, when guessing the dimensions of a file.
+const MAX_SAMPLES: usize = 1 << 16;
+
+/// The most common A scan samples.
+const COMMON_SAMPLES: f32 = 1024.0;
+
+/// A way to split the values of a raw file into A scans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dimensions {
+    pub a_scan_samples: usize,
+    pub a_scan_count: usize,
+}
+
+impl Dimensions {
+    /// The whole A scans of `a_scan_samples` samples in `data_len` bytes of
+    /// `data_type` values, without header. Trailing bytes of a partial A scan
+    /// are left out.
+    pub fn of_file(data_len: usize, data_type: DataType, a_scan_samples: usize) -> Self {
+        let a_scan_bytes = a_scan_samples * data_type.size();
+        Self {
+            a_scan_samples,
+            a_scan_count: data_len.checked_div(a_scan_bytes).unwrap_or(0),
+        }
+    }
+
+    /// All ways to split `values` into A scans of 2 to [MAX_SAMPLES] samples,
+    /// ordered by the samples.
+    pub fn factorizations(values: usize) -> Vec<Self> {
+        let mut samples = Vec::new();
+
+        let mut divisor = 1;
+        while divisor * divisor <= values {
+            if values.is_multiple_of(divisor) {
+                samples.push(divisor);
+                samples.push(values / divisor);
+            }
+            divisor += 1;
+        }
+
+        samples.sort_unstable();
+        samples.dedup();
+        samples
+            .into_iter()
+            .filter(|samples| (2..=MAX_SAMPLES).contains(samples))
+            .map(|a_scan_samples| Self {
+                a_scan_samples,
+                a_scan_count: values / a_scan_samples,
+            })
+            .collect()
+    }
+
+    /// How likely a scan has these dimensions, higher is more likely. Powers
+    /// of two in [TYPICAL_SAMPLES] score best, every octave outside of it
+    /// costs a point. Ties go to the samples closest to [COMMON_SAMPLES].
+    pub fn plausibility(&self) -> f32 {
+        let samples = self.a_scan_samples as f32;
+        let (min, max) = (
+            *TYPICAL_SAMPLES.start() as f32,
+            *TYPICAL_SAMPLES.end() as f32,
+        );
+
+        let outside = if samples < min {
+            (min / samples).log2()
+        } else if samples > max {
+            (samples / max).log2()
+        } else {
+            0.0
+        };
+
+        let roundness = if self.a_scan_samples.is_power_of_two() {
+            1.0
+        } else if self.a_scan_samples.is_multiple_of(64) {
+            0.5
+        } else if self.a_scan_samples.is_multiple_of(8) {
+            0.25
+        } else {
+            0.0
+        };
+
+        // Pullbacks have far more A scans than samples
+        let too_short = match self.a_scan_count < self.a_scan_samples {
+            true => 1.0,
+            false => 0.0,
+        };
+
+        let distance = (samples / COMMON_SAMPLES).log2().abs();
+
+        roundness - outside - too_short - 0.1 * distance
+    }
+
+    /// The most plausible dimensions of a raw file of `file_len` bytes
+    /// without header, at most `limit`. Empty, if the file does not hold
+    /// whole values of `data_type`.
+    pub fn candidates(file_len: usize, data_type: DataType, limit: usize) -> Vec<Self> {
+        if file_len == 0 || !file_len.is_multiple_of(data_type.size()) {
+            return Vec::new();
+        }
+
+        let mut candidates = Self::factorizations(file_len / data_type.size());
+        // Stable, so equally plausible dimensions stay ordered by samples
+        candidates.sort_by(|a, b| b.plausibility().total_cmp(&a.plausibility()));
+        candidates.truncate(limit);
+        candidates
+    }
+}
+
+/// Reads the first `a_scans` A scans of the raw file at `path` with the
+/// given dimensions, for previewing them. Only every n-th sample is read, so
+/// A scans have at most `max_samples` samples.
+pub fn read_preview(
+    path: &Path,
+    data_type: DataType,
+    endianness: Endianness,
+    a_scan_samples: usize,
+    a_scans: usize,
+    max_samples: usize,
+) -> anyhow::Result<DataMatrix> {
+    let offset = RawHeader::peek(path).map_or(0, |_| RawHeader::SIZE);
+    let size = data_type.size();
+
+    let mut bytes = Vec::new();
+    let mut file = std::fs::File::open(path)?;
+    std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(offset as u64))?;
+    file.take((a_scans * a_scan_samples * size) as u64)
+        .read_to_end(&mut bytes)?;
+
+    let step = a_scan_samples.div_ceil(max_samples.max(1));
+    let rows = a_scan_samples.div_ceil(step);
+    let cols = bytes.len() / (a_scan_samples * size);
+    if cols == 0 {
+        return Err(anyhow!("The file holds no complete A scan"));
+    }
+
+    let mut preview = DataMatrix::from_data_type(data_type, rows, cols);
+    let target = preview.as_mut_u8_slice();
+
+    // Matrices are column major, like the file
+    for col in 0..cols {
+        for row in 0..rows {
+            let source = (col * a_scan_samples + row * step) * size;
+            let index = (col * rows + row) * size;
+            target[index..index + size].copy_from_slice(&bytes[source..source + size]);
+        }
+    }
+    endianness.convert(target, data_type);
+
+    Ok(preview)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn header() {
+        for endianness in Endianness::VALUES {
+            for data_type in DataType::VALUES {
+                let header = RawHeader {
+                    endianness,
+                    data_type,
+                    a_scan_samples: 512,
+                    a_scan_count: 1 << 40,
+                };
+
+                let bytes = header.to_bytes();
+                assert_eq!(RawHeader::parse(&bytes).unwrap(), Some(header));
+                assert_eq!(RawHeader::parse(&bytes[..31]).unwrap(), None);
+            }
+        }
+
+        let header = RawHeader {
+            endianness: Endianness::Big,
+            data_type: DataType::F32,
+            a_scan_samples: 256,
+            a_scan_count: 3,
+        };
+        let bytes = header.to_bytes();
+        assert_eq!(&bytes[9..11], &[1, 4]);
+        assert_eq!(&bytes[16..24], &256u64.to_be_bytes());
+
+        assert_eq!(RawHeader::parse(&[0; 64]).unwrap(), None);
+
+        let mut invalid = bytes;
+        invalid[10] = 6;
+        assert!(RawHeader::parse(&invalid).is_err());
+
+        let mut invalid = bytes;
+        invalid[8] = 2;
+        assert!(RawHeader::parse(&invalid).is_err());
+    }
+
+    #[test]
+    fn factorizations() {
+        let dimensions = Dimensions::factorizations(12)
+            .into_iter()
+            .map(|d| (d.a_scan_samples, d.a_scan_count))
+            .collect::<Vec<_>>();
+        assert_eq!(dimensions, vec![(2, 6), (3, 4), (4, 3), (6, 2), (12, 1)]);
+
+        // Prime numbers of values only split into a single A scan
+        assert_eq!(Dimensions::factorizations(7919).len(), 1);
+        assert!(Dimensions::factorizations(1).is_empty());
+    }
+
+    #[test]
+    fn plausible_dimensions() {
+        let score = |a_scan_samples| {
+            Dimensions {
+                a_scan_samples,
+                a_scan_count: 10_000,
+            }
+            .plausibility()
+        };
+        assert!(score(1024) > score(960));
+        assert!(score(960) > score(1000));
+        assert!(score(1000) > score(1001));
+        assert!(score(512) > score(128));
+        assert!(score(4096) > score(16384));
+
+        // 5000 A scans of 1024 samples, 2 bytes each
+        let candidates = Dimensions::candidates(1024 * 5000 * 2, DataType::U16, 5);
+        assert_eq!(candidates.len(), 5);
+        assert_eq!(
+            candidates[0],
+            Dimensions {
+                a_scan_samples: 1024,
+                a_scan_count: 5000,
+            }
+        );
+        assert!(candidates
+            .iter()
+            .all(|d| d.a_scan_samples * d.a_scan_count == 1024 * 5000));
+
+        assert!(Dimensions::candidates(1001, DataType::U16, 5).is_empty());
+    }
+
+    #[test]
+    fn convert_endianness() {
+        let values: Vec<u32> = (0..10_000u32).map(|i| i.wrapping_mul(0x01020304)).collect();
+        let mut bytes = bytemuck::cast_slice::<_, u8>(&values).to_vec();
+
+        let read = |bytes: &[u8]| {
+            bytes
+                .chunks_exact(4)
+                .map(|v| u32::from_ne_bytes(v.try_into().unwrap()))
+                .collect::<Vec<_>>()
+        };
+
+        let other = match Endianness::NATIVE {
+            Endianness::Little => Endianness::Big,
+            Endianness::Big => Endianness::Little,
+        };
+
+        other.convert(&mut bytes, DataType::U32);
+        let swapped = read(&bytes);
+        assert!(values
+            .iter()
+            .zip(&swapped)
+            .all(|(a, b)| a.swap_bytes() == *b));
+
+        other.convert(&mut bytes, DataType::U32);
+        assert_eq!(read(&bytes), values);
+
+        Endianness::NATIVE.convert(&mut bytes, DataType::U32);
+        assert_eq!(read(&bytes), values);
+    }
+}
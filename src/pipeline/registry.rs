@@ -0,0 +1,170 @@
+//! Which request types the nodes produce and consume. Derived at runtime from
+//! the [TaskInput](super::execution::TaskInput)s and
+//! [TaskOutput](super::execution::TaskOutput)s of their tasks, so it cannot
+//! get out of sync with the nodes.
+
+use vec_collections::AbstractVecMap;
+
+use crate::node_graph::{InputId, OutputId};
+
+use super::{
+    nodes::DynPipelineNode,
+    requests::{RequestInfo, REQUESTS},
+};
+
+/// Request types spoken by the inputs and outputs of a node. Inputs accepting
+/// multiple request types are listed once per request type.
+#[derive(Debug)]
+pub struct NodeRequests {
+    pub inputs: Vec<(InputId, &'static RequestInfo)>,
+    pub outputs: Vec<(OutputId, &'static RequestInfo)>,
+}
+
+impl NodeRequests {
+    /// Creates a task of `node`, which is not run. Outputs are identified by
+    /// their handles, inputs by offering them a connection of every request
+    /// type.
+    pub fn of(node: &mut dyn DynPipelineNode) -> Self {
+        let (mut task, output_handles, _) = node.create_node_task();
+
+        let outputs = output_handles
+            .iter()
+            .filter_map(|(output_id, handle)| {
+                Some((*output_id, RequestInfo::of(handle.request_type())?))
+            })
+            .collect();
+
+        let mut inputs = Vec::new();
+
+        // Inputs may accept multiple request types, like the output node
+        for (input_id, _) in node.inputs() {
+            for info in &REQUESTS {
+                let mut connection = info.connection();
+                task.connect(input_id, &mut connection);
+
+                if connection.did_connect() {
+                    inputs.push((input_id, info));
+                    task.disconnect(input_id);
+                }
+            }
+        }
+
+        Self { inputs, outputs }
+    }
+}
+
+/// The nodes producing and consuming one request type.
+#[derive(Debug)]
+pub struct DataTypeUsage {
+    pub info: &'static RequestInfo,
+    pub producers: Vec<&'static str>,
+    pub consumers: Vec<&'static str>,
+}
+
+impl DataTypeUsage {
+    /// Whether no node produces or no node consumes the request type.
+    pub fn is_orphan(&self) -> bool {
+        self.producers.is_empty() || self.consumers.is_empty()
+    }
+}
+
+/// Usage of every request type by `nodes`, which are named by the first
+/// element. Data views are not included.
+pub fn data_type_usage(
+    nodes: impl IntoIterator<Item = (&'static str, Box<dyn DynPipelineNode>)>,
+) -> Vec<DataTypeUsage> {
+    let mut usage: Vec<_> = REQUESTS
+        .iter()
+        .map(|info| DataTypeUsage {
+            info,
+            producers: Vec::new(),
+            consumers: Vec::new(),
+        })
+        .collect();
+
+    for (name, mut node) in nodes {
+        let requests = NodeRequests::of(node.as_mut());
+
+        for usage in &mut usage {
+            let is = |info: &RequestInfo| std::ptr::eq(info, usage.info);
+
+            if requests.outputs.iter().any(|(_, info)| is(info)) {
+                usage.producers.push(name);
+            }
+            if requests.inputs.iter().any(|(_, info)| is(info)) {
+                usage.consumers.push(name);
+            }
+        }
+    }
+
+    usage
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        gui::pipeline::node_templates,
+        pipeline::{nodes::filter, requests, PipelineDataType},
+    };
+
+    use super::*;
+
+    #[test]
+    fn registry_covers_data_types() {
+        for (info, data_type) in REQUESTS.iter().zip(PipelineDataType::VALUES) {
+            assert_eq!(info.data_type, data_type);
+        }
+    }
+
+    #[tokio::test]
+    async fn filter_requests() {
+        let requests = NodeRequests::of(&mut filter::Node::gaussian());
+
+        assert_eq!(requests.inputs.len(), 2);
+        assert_eq!(requests.inputs[0].1.name, "MScan");
+        assert_eq!(requests.inputs[1].1.name, "BScanSegmentation");
+        assert_eq!(requests.outputs.len(), 2);
+        assert!(requests
+            .outputs
+            .iter()
+            .all(|(_, info)| info.name == "MScan"));
+    }
+
+    #[tokio::test]
+    async fn every_data_type_is_produced_and_consumed() {
+        for usage in data_type_usage(node_templates()) {
+            assert!(
+                !usage.producers.is_empty(),
+                "No node produces {}",
+                usage.info.name
+            );
+            assert!(
+                !usage.consumers.is_empty(),
+                "No node consumes {}",
+                usage.info.name
+            );
+        }
+    }
+
+    #[test]
+    fn mismatch_messages() {
+        let expected = REQUESTS
+            .iter()
+            .find(|info| info.name == "MScan")
+            .unwrap()
+            .connection();
+        let mut input =
+            crate::pipeline::execution::TaskInput::<requests::BScanSegmentation>::default();
+        let mut connection = expected.clone();
+
+        assert!(!input.connect(&mut connection));
+        assert_eq!(
+            requests::describe_mismatch(
+                connection.rejected_by().unwrap(),
+                connection.request_type()
+            ),
+            "expected BScanSegmentation (streamed usize boundaries), \
+             got MScan (streamed DataMatrix chunks)"
+        );
+    }
+}
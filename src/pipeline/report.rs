@@ -0,0 +1,697 @@
+//! Human readable report of a pipeline: the processed files, every node with
+//! its settings in execution order, statistics of the outputs and the
+//! diameters of every B scan.
+//!
+//! Results are only requested from outputs, that already hold a response,
+//! unless [ReportOptions::compute_missing] is set. Everything else is marked
+//! as not computed.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write,
+    path::PathBuf,
+};
+
+use anyhow::anyhow;
+use tokio::{sync::watch, task::JoinHandle};
+
+use crate::{
+    node_graph::{NodeId, OutputIdSingle},
+    queue_channel::error::RecvError,
+    units::NumberFormat,
+};
+
+use super::{
+    execution::{ConnectionHandle, OutputStats, Request, TaskInput},
+    nodes::{binary_input, diameter},
+    requests,
+    types::BScanDiameter,
+    Pipeline, PipelineDataType, PipelineExecutor,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportOptions {
+    /// The Markdown file to write.
+    pub path: PathBuf,
+    /// Additionally write an HTML file next to the Markdown file.
+    pub html: bool,
+    /// Request results, that were not computed yet, which may start the
+    /// processing of large parts of the pipeline.
+    pub compute_missing: bool,
+}
+
+/// State of a [ReportGeneration].
+#[derive(Debug, Clone)]
+pub enum ReportState {
+    Running {
+        progress: f32,
+        step: String,
+    },
+    /// Contains the written files.
+    Done(Vec<PathBuf>),
+    Failed(String),
+}
+
+/// Generates a report in the background. Dropping it cancels the generation.
+pub struct ReportGeneration {
+    state: watch::Receiver<ReportState>,
+    task: JoinHandle<()>,
+}
+
+impl ReportGeneration {
+    /// Takes everything needed from the pipeline and the executor and starts
+    /// requesting the results.
+    pub fn start(pipeline: &Pipeline, executor: &PipelineExecutor, options: ReportOptions) -> Self {
+        let source = ReportSource::collect(pipeline, executor);
+
+        let (state_tx, state) = watch::channel(ReportState::Running {
+            progress: 0.0,
+            step: "Starting".to_string(),
+        });
+
+        let task = tokio::spawn(async move {
+            let result = generate(source, &options, &state_tx).await;
+
+            state_tx.send_replace(match result {
+                Ok(files) => ReportState::Done(files),
+                Err(e) => ReportState::Failed(e.to_string()),
+            });
+        });
+
+        Self { state, task }
+    }
+
+    pub fn state(&self) -> ReportState {
+        self.state.borrow().clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(*self.state.borrow(), ReportState::Running { .. })
+    }
+}
+
+impl Drop for ReportGeneration {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// MARK: Execution order
+
+/// Orders the nodes, so every node comes after the nodes connected to its
+/// inputs. Otherwise, nodes are ordered by their id. Nodes in cycles come
+/// last.
+pub fn execution_order(pipeline: &Pipeline) -> Vec<NodeId> {
+    let upstream = pipeline
+        .nodes
+        .iter()
+        .map(|(id, node)| {
+            let upstream = node
+                .inputs()
+                .into_iter()
+                .filter_map(|(_, output)| output)
+                .map(|output| output.node_id)
+                .filter(|upstream| pipeline.nodes.contains_key(upstream))
+                .collect::<BTreeSet<_>>();
+            (*id, upstream)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut order = Vec::with_capacity(upstream.len());
+    let mut remaining = upstream.keys().copied().collect::<BTreeSet<_>>();
+
+    while let Some(next) = remaining
+        .iter()
+        .copied()
+        .find(|id| upstream[id].iter().all(|u| !remaining.contains(u)))
+    {
+        remaining.remove(&next);
+        order.push(next);
+    }
+
+    order.extend(remaining);
+    order
+}
+
+// MARK: ReportSource
+
+/// Everything the report needs from the pipeline, taken on the UI thread.
+struct ReportSource {
+    nodes: Vec<NodeSource>,
+    /// Files read by input nodes.
+    files: Vec<PathBuf>,
+    /// The most upstream M scan, to tell the number of A scans.
+    m_scan: Option<(String, ConnectionHandle)>,
+    format: NumberFormat,
+}
+
+struct NodeSource {
+    name: String,
+    settings: Vec<(String, String)>,
+    /// Names of the nodes connected to the inputs.
+    inputs: Vec<String>,
+    outputs: Vec<OutputStats>,
+    /// The output of a diameter node.
+    diameter: Option<ConnectionHandle>,
+}
+
+impl ReportSource {
+    fn collect(pipeline: &Pipeline, executor: &PipelineExecutor) -> Self {
+        let order = execution_order(pipeline);
+        let label = |id: NodeId| {
+            let number: usize = id.into();
+            format!("{} (#{})", pipeline[id].name(), number)
+        };
+
+        let mut files = Vec::new();
+        let mut m_scan = None;
+
+        let nodes = order
+            .iter()
+            .map(|&id| {
+                let node = &pipeline[id];
+
+                if let Some(input) = node.as_any().downcast_ref::<binary_input::Node>() {
+                    if !input.path.as_os_str().is_empty() {
+                        files.push(input.path.clone());
+                    }
+                }
+
+                if m_scan.is_none() {
+                    m_scan = node
+                        .get_output_for_view_request()
+                        .filter(|(_, type_id)| *type_id == PipelineDataType::MScan.into())
+                        .and_then(|(output_id, _)| executor.get_output(id, output_id))
+                        .map(|handle| (label(id), handle));
+                }
+
+                let diameter = match node.as_any().is::<diameter::Node>() {
+                    true => executor.get_output(id, OutputIdSingle.into()),
+                    false => None,
+                };
+
+                let settings = serde_json::to_value(node)
+                    .map(|value| flatten_settings(&value))
+                    .unwrap_or_default();
+
+                NodeSource {
+                    name: label(id),
+                    settings,
+                    inputs: node
+                        .inputs()
+                        .into_iter()
+                        .filter_map(|(_, output)| output)
+                        .filter(|output| pipeline.nodes.contains_key(&output.node_id))
+                        .map(|output| label(output.node_id))
+                        .collect(),
+                    outputs: executor.output_stats(id),
+                    diameter,
+                }
+            })
+            .collect();
+
+        Self {
+            nodes,
+            files,
+            m_scan,
+            format: NumberFormat::current(),
+        }
+    }
+}
+
+/// Turns the serialized node into `key: value` pairs. Nested settings get
+/// dotted keys. Inputs and the type tag are left out, they are reported
+/// separately.
+pub(super) fn flatten_settings(value: &serde_json::Value) -> Vec<(String, String)> {
+    fn is_input(value: &serde_json::Value) -> bool {
+        value.as_object().is_some_and(|object| {
+            object.len() == 2 && object.contains_key("value") && object.contains_key("connection")
+        })
+    }
+
+    fn flatten(prefix: &str, value: &serde_json::Value, out: &mut Vec<(String, String)>) {
+        let Some(object) = value.as_object() else {
+            out.push((prefix.to_string(), value.to_string()));
+            return;
+        };
+
+        for (key, value) in object {
+            if key == "type" && prefix.is_empty() || is_input(value) {
+                continue;
+            }
+            let key = match prefix.is_empty() {
+                true => key.clone(),
+                false => format!("{prefix}.{key}"),
+            };
+            flatten(&key, value, out);
+        }
+    }
+
+    let mut settings = Vec::new();
+    flatten("", value, &mut settings);
+    settings
+}
+
+// MARK: Generation
+
+/// A result, that was requested from an output.
+enum Fetched<T> {
+    Available(T),
+    NotComputed,
+    Failed(String),
+}
+
+async fn generate(
+    source: ReportSource,
+    options: &ReportOptions,
+    state: &watch::Sender<ReportState>,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let diameter_count = source.nodes.iter().filter(|n| n.diameter.is_some()).count();
+    let steps = (diameter_count + 2) as f32;
+    let report_step = |done: usize, step: String| {
+        state.send_replace(ReportState::Running {
+            progress: done as f32 / steps,
+            step,
+        });
+    };
+
+    let a_scans = match &source.m_scan {
+        Some((name, handle)) => {
+            report_step(0, format!("Requesting the M scan of {name}"));
+            let response = fetch(handle, requests::MScan, options.compute_missing).await;
+            Some((name.as_str(), map_fetched(response, |r| r.a_scan_count)))
+        }
+        None => None,
+    };
+
+    let mut diameters = Vec::with_capacity(source.nodes.len());
+    for node in &source.nodes {
+        let Some(handle) = &node.diameter else {
+            diameters.push(None);
+            continue;
+        };
+
+        report_step(
+            diameters.len() + 1,
+            format!("Requesting diameters of {}", node.name),
+        );
+
+        let collected = match fetch(handle, requests::Diameter, options.compute_missing).await {
+            Fetched::Available(response) => match collect_diameters(response).await {
+                Ok(diameters) => Fetched::Available(diameters),
+                Err(e) => Fetched::Failed(e.to_string()),
+            },
+            Fetched::NotComputed => Fetched::NotComputed,
+            Fetched::Failed(e) => Fetched::Failed(e),
+        };
+        diameters.push(Some(collected));
+    }
+
+    report_step(diameter_count + 1, "Writing".to_string());
+
+    let document = build_document(&source, a_scans, &diameters);
+
+    let mut files = vec![options.path.clone()];
+    tokio::fs::write(&options.path, document.to_markdown()).await?;
+
+    if options.html {
+        let path = options.path.with_extension("html");
+        tokio::fs::write(&path, document.to_html("Pipeline Report")).await?;
+        files.push(path);
+    }
+
+    Ok(files)
+}
+
+/// Requests the response of an output, if it already has one or `compute` is
+/// set.
+async fn fetch<Req: Request>(
+    handle: &ConnectionHandle,
+    request: Req,
+    compute: bool,
+) -> Fetched<Req::Response> {
+    if !compute && !handle.has_response() {
+        return Fetched::NotComputed;
+    }
+
+    let mut input = TaskInput::<Req>::default();
+    if !input.connect(&mut handle.clone()) {
+        return Fetched::Failed("The output has an unexpected type".to_string());
+    }
+
+    match input.request(request).await {
+        Some(response) => Fetched::Available(response),
+        None => Fetched::Failed("The node stopped without a result".to_string()),
+    }
+}
+
+fn map_fetched<T, U>(fetched: Fetched<T>, f: impl FnOnce(T) -> U) -> Fetched<U> {
+    match fetched {
+        Fetched::Available(value) => Fetched::Available(f(value)),
+        Fetched::NotComputed => Fetched::NotComputed,
+        Fetched::Failed(e) => Fetched::Failed(e),
+    }
+}
+
+async fn collect_diameters(
+    response: requests::DiameterResponse,
+) -> anyhow::Result<Vec<BScanDiameter>> {
+    let mut rx = response
+        .data
+        .subscribe()
+        .ok_or_else(|| anyhow!("The result got lost"))?;
+
+    let mut diameters = Vec::new();
+    loop {
+        match rx.recv().await {
+            Ok(diameter) => diameters.push(diameter),
+            Err(RecvError::Closed) => break,
+            Err(e) => Err(e)?,
+        }
+    }
+
+    Ok(diameters)
+}
+
+// MARK: Content
+
+const NOT_COMPUTED: &str = "Not computed";
+
+fn build_document(
+    source: &ReportSource,
+    a_scans: Option<(&str, Fetched<usize>)>,
+    diameters: &[Option<Fetched<Vec<BScanDiameter>>>],
+) -> Document {
+    let format = &source.format;
+    let mut doc = Document::default();
+
+    doc.heading(1, "Pipeline Report");
+
+    doc.heading(2, "Dataset");
+    let mut dataset = source
+        .files
+        .iter()
+        .map(|file| format!("File: {}", file.display()))
+        .collect::<Vec<_>>();
+    if dataset.is_empty() {
+        dataset.push("File: None".to_string());
+    }
+    if let Some((name, a_scans)) = a_scans {
+        let count = match a_scans {
+            Fetched::Available(count) => count.to_string(),
+            Fetched::NotComputed => NOT_COMPUTED.to_string(),
+            Fetched::Failed(e) => format!("Failed: {e}"),
+        };
+        dataset.push(format!("A scans: {count} (from {name})"));
+    }
+    doc.0.push(Block::List(dataset));
+
+    doc.heading(2, "Nodes");
+    for (node, diameters) in source.nodes.iter().zip(diameters) {
+        doc.heading(3, &node.name);
+
+        let mut facts = Vec::new();
+        if !node.inputs.is_empty() {
+            facts.push(format!("Inputs: {}", node.inputs.join(", ")));
+        }
+        facts.extend(
+            node.settings
+                .iter()
+                .map(|(key, value)| format!("{key}: {value}")),
+        );
+        if !facts.is_empty() {
+            doc.0.push(Block::List(facts));
+        }
+
+        if !node.outputs.is_empty() {
+            doc.0.push(Block::Table {
+                header: ["Output", "Chunks", "Data", "Time"]
+                    .map(String::from)
+                    .to_vec(),
+                rows: node
+                    .outputs
+                    .iter()
+                    .map(|output| output_row(output, format))
+                    .collect(),
+            });
+        }
+
+        match diameters {
+            Some(Fetched::Available(diameters)) => diameter_blocks(&mut doc, diameters, format),
+            Some(Fetched::NotComputed) => doc.paragraph(format!("Diameters: {NOT_COMPUTED}")),
+            Some(Fetched::Failed(e)) => doc.paragraph(format!("Diameters: Failed: {e}")),
+            None => {}
+        }
+    }
+
+    doc
+}
+
+fn output_row(output: &OutputStats, format: &NumberFormat) -> Vec<String> {
+    let id: usize = output.output_id.into();
+    let id = id.to_string();
+
+    if !output.has_response {
+        return vec![id, NOT_COMPUTED.to_string(), String::new(), String::new()];
+    }
+
+    vec![
+        id,
+        output.transfer.chunks.to_string(),
+        format.bytes(output.transfer.bytes as f64),
+        output.elapsed.map_or(String::new(), |elapsed| {
+            format!("{} s", format.number(elapsed.as_secs_f64(), 0..=2))
+        }),
+    ]
+}
+
+fn diameter_blocks(doc: &mut Document, diameters: &[BScanDiameter], format: &NumberFormat) {
+    let finite = diameters
+        .iter()
+        .filter(|d| d.is_finite())
+        .collect::<Vec<_>>();
+    let length = |mm: f32| format.length(mm, Some(3));
+
+    let mut summary = vec![format!("B scans: {}", diameters.len())];
+    if finite.len() < diameters.len() {
+        summary.push(format!(
+            "Without valid diameter: {}",
+            diameters.len() - finite.len()
+        ));
+    }
+    if !finite.is_empty() {
+        let min = finite.iter().map(|d| d.min).fold(f32::INFINITY, f32::min);
+        let max = finite
+            .iter()
+            .map(|d| d.max)
+            .fold(f32::NEG_INFINITY, f32::max);
+        let mean = finite.iter().map(|d| d.mean as f64).sum::<f64>() / finite.len() as f64;
+
+        summary.push(format!("Smallest diameter: {}", length(min)));
+        summary.push(format!("Largest diameter: {}", length(max)));
+        summary.push(format!("Average mean diameter: {}", length(mean as f32)));
+    }
+    doc.0.push(Block::List(summary));
+
+    doc.0.push(Block::Table {
+        header: ["B scan", "A scans", "Min", "Mean", "Max"]
+            .map(String::from)
+            .to_vec(),
+        rows: diameters
+            .iter()
+            .enumerate()
+            .map(|(i, d)| {
+                vec![
+                    i.to_string(),
+                    format!("{}..{}", d.b_scan_start, d.b_scan_end),
+                    length(d.min),
+                    length(d.mean),
+                    length(d.max),
+                ]
+            })
+            .collect(),
+    });
+}
+
+// MARK: Document
+
+/// Minimal document model, that renders to Markdown and HTML.
+#[derive(Debug, Default)]
+struct Document(Vec<Block>);
+
+#[derive(Debug)]
+enum Block {
+    Heading(usize, String),
+    Paragraph(String),
+    List(Vec<String>),
+    Table {
+        header: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+}
+
+impl Document {
+    fn heading(&mut self, level: usize, text: &str) {
+        self.0.push(Block::Heading(level, text.to_string()));
+    }
+
+    fn paragraph(&mut self, text: String) {
+        self.0.push(Block::Paragraph(text));
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut out = String::new();
+
+        for block in &self.0 {
+            match block {
+                Block::Heading(level, text) => {
+                    let _ = writeln!(out, "{} {}", "#".repeat(*level), text);
+                }
+                Block::Paragraph(text) => {
+                    let _ = writeln!(out, "{text}");
+                }
+                Block::List(items) => {
+                    for item in items {
+                        let _ = writeln!(out, "- {item}");
+                    }
+                }
+                Block::Table { header, rows } => {
+                    let row = |cells: &[String]| {
+                        let cells = cells
+                            .iter()
+                            .map(|cell| cell.replace('|', "\\|"))
+                            .collect::<Vec<_>>();
+                        format!("| {} |", cells.join(" | "))
+                    };
+                    let _ = writeln!(out, "{}", row(header));
+                    let _ = writeln!(out, "|{}", " --- |".repeat(header.len()));
+                    for cells in rows {
+                        let _ = writeln!(out, "{}", row(cells));
+                    }
+                }
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+
+    fn to_html(&self, title: &str) -> String {
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>table {{ border-collapse: collapse; }} \
+             th, td {{ border: 1px solid #888; padding: 2px 8px; }}</style>\n\
+             </head>\n<body>\n",
+            escape_html(title)
+        );
+
+        for block in &self.0 {
+            match block {
+                Block::Heading(level, text) => {
+                    let _ = writeln!(out, "<h{level}>{}</h{level}>", escape_html(text));
+                }
+                Block::Paragraph(text) => {
+                    let _ = writeln!(out, "<p>{}</p>", escape_html(text));
+                }
+                Block::List(items) => {
+                    out.push_str("<ul>\n");
+                    for item in items {
+                        let _ = writeln!(out, "<li>{}</li>", escape_html(item));
+                    }
+                    out.push_str("</ul>\n");
+                }
+                Block::Table { header, rows } => {
+                    let row = |cells: &[String], tag: &str| {
+                        let cells = cells
+                            .iter()
+                            .map(|cell| format!("<{tag}>{}</{tag}>", escape_html(cell)))
+                            .collect::<String>();
+                        format!("<tr>{cells}</tr>\n")
+                    };
+                    out.push_str("<table>\n");
+                    out.push_str(&row(header, "th"));
+                    for cells in rows {
+                        out.push_str(&row(cells, "td"));
+                    }
+                    out.push_str("</table>\n");
+                }
+            }
+        }
+
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pipeline::nodes::{process_raw_m_scan, DynPipelineNode};
+
+    use super::*;
+
+    #[test]
+    fn order_follows_connections() {
+        let json = r#"{ "nodes": {
+            "1": { "type": "diameter", "b_scans": { "value": null, "connection": null },
+                   "catheter": { "value": null, "connection": null },
+                   "lumen": { "value": null, "connection": { "node_id": 2, "output_id": 0, "type_id": 4 } } },
+            "2": { "type": "process_raw_m_scan",
+                   "raw_scan": { "value": null, "connection": { "node_id": 3, "output_id": 0, "type_id": 0 } },
+                   "offset": { "value": null, "connection": null },
+                   "chirp": { "value": null, "connection": null } },
+            "3": { "type": "binary_input", "path": "", "input_type": "RawMScan",
+                   "data_type": "U16", "a_scan_length": 1024 },
+            "4": { "type": "binary_input", "path": "", "input_type": "DataVector",
+                   "data_type": "F64", "a_scan_length": 1024 }
+        } }"#;
+        let pipeline: Pipeline = serde_json::from_str(json).unwrap();
+
+        let order = execution_order(&pipeline)
+            .into_iter()
+            .map(Into::<usize>::into)
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![3, 2, 1, 4]);
+    }
+
+    #[test]
+    fn settings_without_inputs() {
+        let node: Box<dyn DynPipelineNode> = Box::new(process_raw_m_scan::Node::default());
+        let settings = flatten_settings(&serde_json::to_value(&node).unwrap());
+
+        assert!(settings.iter().any(|(key, _)| key == "factor"));
+        assert!(settings
+            .iter()
+            .all(|(key, _)| key != "type" && key != "raw_scan"));
+
+        let node = diameter::Node::default();
+        let settings = flatten_settings(&serde_json::to_value(&node).unwrap());
+        assert!(settings
+            .iter()
+            .any(|(key, _)| key == "settings.mm_per_pixel"));
+    }
+
+    #[test]
+    fn markdown_and_html() {
+        let mut doc = Document::default();
+        doc.heading(2, "Nodes");
+        doc.0.push(Block::Table {
+            header: vec!["a|b".to_string(), "<c>".to_string()],
+            rows: vec![vec!["1".to_string(), "2".to_string()]],
+        });
+
+        assert_eq!(
+            doc.to_markdown(),
+            "## Nodes\n\n| a\\|b | <c> |\n| --- | --- |\n| 1 | 2 |\n\n"
+        );
+        assert!(doc
+            .to_html("Report")
+            .contains("<tr><th>a|b</th><th>&lt;c&gt;</th></tr>"));
+    }
+}
@@ -1,14 +1,22 @@
 // Definition of all request types, send between node tasks.
 
-use std::sync::Arc;
+use std::{
+    any::TypeId,
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
 
 use nalgebra::DVector;
 
-use crate::queue_channel;
+use crate::{queue_channel, settings::Settings};
 
 use super::{
-    execution::Request,
+    execution::{
+        ConnectionHandle, Peek, PeekBuffer, Request, TransferStats, PEEK_DIAMETERS,
+        PEEK_LINE_POINTS,
+    },
     types::{self, *},
+    PipelineDataType,
 };
 
 //MARK: Structures
@@ -34,60 +42,205 @@ pub struct Diameter;
 #[derive(Debug, Clone, Copy)]
 pub struct Mesh;
 
+#[derive(Debug, Clone, Copy)]
+pub struct Bundle;
+
 //MARK: Implementations
 
 impl Request for RawMScan {
     type Response = RawMScanResponse;
 
     fn is_response_valid(&self, response: &Self::Response) -> bool {
-        !response.data.is_lagged()
+        response.data.is_valid()
+    }
+
+    fn answers_pending(&self, response: &Self::Response) -> bool {
+        response.data.is_aborted() && !response.data.is_lagged()
+    }
+
+    fn track_transfer(response: &Self::Response, stats: &Arc<TransferStats>) {
+        response.data.track_transfer(stats);
+        response.data.track_data_type(stats);
+    }
+
+    fn capture(response: &Self::Response, peek: &Arc<PeekBuffer>) {
+        if PeekBuffer::is_enabled(true) {
+            response.data.capture(peek, |chunk| Peek::image(chunk));
+        }
     }
 }
 
 impl Request for VectorData {
     type Response = Arc<DataVector>;
+
+    fn track_transfer(response: &Self::Response, stats: &Arc<TransferStats>) {
+        stats.record(response.byte_size());
+    }
+
+    fn capture(response: &Self::Response, peek: &Arc<PeekBuffer>) {
+        if PeekBuffer::is_enabled(false) {
+            peek.store(Peek::vector(response));
+        }
+    }
 }
 
 impl Request for MScan {
     type Response = MScanResponse;
 
     fn is_response_valid(&self, response: &Self::Response) -> bool {
-        !response.data.is_lagged()
+        response.data.is_valid()
+    }
+
+    fn answers_pending(&self, response: &Self::Response) -> bool {
+        response.data.is_aborted() && !response.data.is_lagged()
+    }
+
+    fn track_transfer(response: &Self::Response, stats: &Arc<TransferStats>) {
+        response.data.track_transfer(stats);
+        response.data.track_data_type(stats);
+    }
+
+    fn capture(response: &Self::Response, peek: &Arc<PeekBuffer>) {
+        if PeekBuffer::is_enabled(true) {
+            response.data.capture(peek, |chunk| Peek::image(chunk));
+        }
     }
 }
 
 impl Request for BScanSegmentation {
-    // Every element is the index of the first a-scan in the next b-scan. The
-    // last element is the index of the first a-scan after the last b-scan. (The
-    // number of b-scans is len-1)
-    type Response = StreamedResponse<usize>;
+    type Response = BScanSegmentationResponse;
 
     fn is_response_valid(&self, response: &Self::Response) -> bool {
-        !response.is_lagged()
+        response.data.is_valid()
+    }
+
+    fn answers_pending(&self, response: &Self::Response) -> bool {
+        response.data.is_aborted() && !response.data.is_lagged()
+    }
+
+    fn track_transfer(response: &Self::Response, stats: &Arc<TransferStats>) {
+        response.data.track_transfer(stats);
+    }
+
+    /// Widths of the last B scans.
+    fn capture(response: &Self::Response, peek: &Arc<PeekBuffer>) {
+        if PeekBuffer::is_enabled(false) {
+            let boundaries = Mutex::new(VecDeque::new());
+            response.data.capture(peek, move |&boundary| {
+                let mut boundaries = boundaries.lock().unwrap();
+                if boundaries.len() > PEEK_LINE_POINTS {
+                    boundaries.pop_front();
+                }
+                boundaries.push_back(boundary);
+
+                let widths = boundaries.iter().zip(boundaries.iter().skip(1));
+                Peek::line(widths.map(|(start, end)| end.saturating_sub(*start) as f32))
+            });
+        }
     }
 }
 
 impl Request for MScanSegmentation {
-    type Response = StreamedResponse<Arc<DVector<u32>>>;
+    type Response = MScanSegmentationResponse;
 
     fn is_response_valid(&self, response: &Self::Response) -> bool {
-        !response.is_lagged()
+        response.data.is_valid()
+    }
+
+    fn answers_pending(&self, response: &Self::Response) -> bool {
+        response.data.is_aborted() && !response.data.is_lagged()
+    }
+
+    fn track_transfer(response: &Self::Response, stats: &Arc<TransferStats>) {
+        response.data.track_transfer(stats);
+    }
+
+    fn capture(response: &Self::Response, peek: &Arc<PeekBuffer>) {
+        if PeekBuffer::is_enabled(false) {
+            response
+                .data
+                .capture(peek, |chunk| Peek::line(chunk.iter().map(|&v| v as f32)));
+        }
     }
 }
 
 impl Request for Diameter {
-    type Response = StreamedResponse<types::BScanDiameter>;
+    type Response = DiameterResponse;
 
     fn is_response_valid(&self, response: &Self::Response) -> bool {
-        !response.is_lagged()
+        response.data.is_valid()
+    }
+
+    fn answers_pending(&self, response: &Self::Response) -> bool {
+        response.data.is_aborted() && !response.data.is_lagged()
+    }
+
+    fn track_transfer(response: &Self::Response, stats: &Arc<TransferStats>) {
+        response.data.track_transfer(stats);
+    }
+
+    fn capture(response: &Self::Response, peek: &Arc<PeekBuffer>) {
+        if PeekBuffer::is_enabled(false) {
+            let diameters = Mutex::new(VecDeque::new());
+            response.data.capture(peek, move |diameter| {
+                let mut diameters = diameters.lock().unwrap();
+                if diameters.len() == PEEK_DIAMETERS {
+                    diameters.pop_front();
+                }
+                diameters.push_back(*diameter);
+                Peek::Diameters(diameters.iter().copied().collect())
+            });
+        }
     }
 }
 
 impl Request for Mesh {
-    type Response = StreamedResponse<types::LumenMesh>;
+    type Response = MeshResponse;
 
     fn is_response_valid(&self, response: &Self::Response) -> bool {
-        !response.is_lagged()
+        response.data.is_valid()
+    }
+
+    fn answers_pending(&self, response: &Self::Response) -> bool {
+        response.data.is_aborted() && !response.data.is_lagged()
+    }
+
+    fn track_transfer(response: &Self::Response, stats: &Arc<TransferStats>) {
+        response.data.track_transfer(stats);
+    }
+
+    fn capture(response: &Self::Response, peek: &Arc<PeekBuffer>) {
+        if PeekBuffer::is_enabled(false) {
+            let counts = Mutex::new((0, 0));
+            response.data.capture(peek, move |mesh| {
+                let mut counts = counts.lock().unwrap();
+                counts.0 += mesh.vertices.len();
+                counts.1 += mesh.indices.len() / 3;
+                Peek::Mesh {
+                    vertices: counts.0,
+                    triangles: counts.1,
+                }
+            });
+        }
+    }
+}
+
+impl Request for Bundle {
+    type Response = BundleResponse;
+
+    fn is_response_valid(&self, response: &Self::Response) -> bool {
+        response
+            .b_scan_segmentation
+            .as_ref()
+            .is_none_or(|res| BScanSegmentation.is_response_valid(res))
+            && response
+                .lumen
+                .as_ref()
+                .is_none_or(|res| MScanSegmentation.is_response_valid(res))
+            && response
+                .diameter
+                .as_ref()
+                .is_none_or(|res| Diameter.is_response_valid(res))
     }
 }
 
@@ -107,6 +260,126 @@ pub struct MScanResponse {
     pub a_scan_count: usize,
 }
 
+/// The `a_scan_count` of the segmentation responses below is the number of A
+/// scans of the segmented M scan. The data is complete, once it covers all of
+/// them, so consumers can tell how far along it is.
+#[derive(Debug, Clone)]
+pub struct BScanSegmentationResponse {
+    /// Every element is the index of the first a-scan in the next b-scan. The
+    /// last element is the index of the first a-scan after the last b-scan.
+    /// (The number of b-scans is len-1)
+    pub data: StreamedResponse<usize>,
+    pub a_scan_count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct MScanSegmentationResponse {
+    /// One value per A scan, in chunks of A scans.
+    pub data: StreamedResponse<Arc<DVector<u32>>>,
+    pub a_scan_count: usize,
+    /// Number of samples per A scan of the segmented M scan.
+    pub a_scan_samples: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiameterResponse {
+    /// One diameter per B scan, ending at [types::BScanDiameter::b_scan_end].
+    pub data: StreamedResponse<types::BScanDiameter>,
+    pub a_scan_count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct MeshResponse {
+    /// Chunks of rings, ending at [types::LumenMesh::a_scan_end].
+    pub data: StreamedResponse<types::LumenMesh>,
+    pub a_scan_count: usize,
+}
+
+/// Responses of the outputs merged by the bundle node, each [None], if not
+/// connected.
+#[derive(Debug, Clone)]
+pub struct BundleResponse {
+    pub b_scan_segmentation: Option<BScanSegmentationResponse>,
+    pub lumen: Option<MScanSegmentationResponse>,
+    pub diameter: Option<DiameterResponse>,
+    /// Cross-sectional area of every B scan in mm².
+    pub area: Option<Arc<DataVector>>,
+    /// A scan count of the first response, that announces one.
+    pub a_scan_count: usize,
+}
+
+// MARK: Registry
+
+/// Description of a request type, for developers and error messages. See
+/// [REQUESTS].
+#[derive(Debug)]
+pub struct RequestInfo {
+    pub name: &'static str,
+    pub data_type: PipelineDataType,
+    /// What a response carries.
+    pub response: &'static str,
+    /// Type of one chunk of a streamed response, or of the whole response.
+    pub chunk: &'static str,
+    request_type: fn() -> TypeId,
+    connection: fn() -> ConnectionHandle,
+}
+
+impl RequestInfo {
+    pub fn of(request_type: TypeId) -> Option<&'static RequestInfo> {
+        REQUESTS
+            .iter()
+            .find(|info| (info.request_type)() == request_type)
+    }
+
+    /// A connection handle of this request type, without anything serving it.
+    pub fn connection(&self) -> ConnectionHandle {
+        (self.connection)()
+    }
+}
+
+impl std::fmt::Display for RequestInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.response)
+    }
+}
+
+macro_rules! request_info {
+    ($request:ident, $data_type:ident, $response:literal, $chunk:ty) => {
+        RequestInfo {
+            name: stringify!($request),
+            data_type: PipelineDataType::$data_type,
+            response: $response,
+            chunk: stringify!($chunk),
+            request_type: TypeId::of::<$request>,
+            connection: || ConnectionHandle::new::<$request>().0,
+        }
+    };
+}
+
+/// Every request type, in the order of [PipelineDataType::VALUES].
+#[rustfmt::skip]
+pub static REQUESTS: [RequestInfo; 8] = [
+    request_info!(RawMScan, RawMScan, "streamed DataMatrix chunks", Arc<DataMatrix>),
+    request_info!(VectorData, DataVector, "a single DataVector", Arc<DataVector>),
+    request_info!(MScan, MScan, "streamed DataMatrix chunks", Arc<DataMatrix>),
+    request_info!(BScanSegmentation, BScanSegmentation, "streamed usize boundaries", usize),
+    request_info!(MScanSegmentation, MScanSegmentation, "streamed u32 depth chunks", Arc<DVector<u32>>),
+    request_info!(Diameter, Diameter, "streamed B scan diameters", BScanDiameter),
+    request_info!(Mesh, Mesh, "streamed lumen mesh rings", LumenMesh),
+    request_info!(Bundle, Bundle, "optional segmentation and diameter responses", BundleResponse),
+];
+
+/// Explains why an input expecting `expected` cannot connect to an output
+/// of request type `got`.
+pub fn describe_mismatch(expected: TypeId, got: TypeId) -> String {
+    let describe = |request_type| match RequestInfo::of(request_type) {
+        Some(info) => info.to_string(),
+        None => "an unknown request type".to_string(),
+    };
+
+    format!("expected {}, got {}", describe(expected), describe(got))
+}
+
 // MARK: StreamedResponse
 
 /// A response containing a [queue_channel::Receiver] used to receive the data
@@ -122,6 +395,12 @@ impl<T: Clone> StreamedResponse<T> {
         (Self(rx), tx)
     }
 
+    /// Creates a response with the channel capacity configured in the app
+    /// settings.
+    pub fn with_default_capacity() -> (Self, queue_channel::Sender<T>) {
+        Self::new(Settings::current().performance.stream_capacity)
+    }
+
     pub fn subscribe(&self) -> Option<queue_channel::Receiver<T>> {
         match self {
             StreamedResponse(rx) if !rx.is_lagged() => Some(rx.clone()),
@@ -132,4 +411,44 @@ impl<T: Clone> StreamedResponse<T> {
     pub fn is_lagged(&self) -> bool {
         self.0.is_lagged()
     }
+
+    /// Whether the producer stopped before the stream was complete, see
+    /// [queue_channel::Sender::finish]. A new request gets a fresh response.
+    pub fn is_aborted(&self) -> bool {
+        self.0.is_aborted()
+    }
+
+    /// Whether new subscribers can still receive the whole stream.
+    pub fn is_valid(&self) -> bool {
+        !self.is_lagged() && !self.is_aborted()
+    }
+
+    /// Keep a [Peek] of every chunk sent through this response in `buffer`,
+    /// replacing the previous one.
+    pub fn capture(
+        &self,
+        buffer: &Arc<PeekBuffer>,
+        peek: impl Fn(&T) -> Peek + Send + Sync + 'static,
+    ) {
+        let buffer = buffer.clone();
+        self.0.observe(move |chunk| buffer.store(peek(chunk)));
+    }
+}
+
+impl<T: Clone + ByteSize + 'static> StreamedResponse<T> {
+    /// Count every chunk sent through this response into `stats`.
+    pub fn track_transfer(&self, stats: &Arc<TransferStats>) {
+        let stats = stats.clone();
+        self.0.observe(move |chunk| stats.record(chunk.byte_size()));
+    }
+}
+
+impl StreamedResponse<Arc<DataMatrix>> {
+    /// Record the type of every M scan chunk sent through this response in
+    /// `stats`.
+    pub fn track_data_type(&self, stats: &Arc<TransferStats>) {
+        let stats = stats.clone();
+        self.0
+            .observe(move |chunk| stats.record_data_type(chunk.data_type()));
+    }
 }
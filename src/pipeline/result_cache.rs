@@ -0,0 +1,249 @@
+//! Cache of completed streamed results, keyed by the settings that produced
+//! them and the invalidation epoch of their input. Switching a node back to
+//! recent settings then replays the cached chunks, instead of computing them
+//! again.
+
+use std::{collections::VecDeque, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use super::types::{ByteSize, DataMatrix};
+
+// MARK: CacheSettings
+
+/// Bounds of a [ResultCache]. Caching is opt-in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheSettings {
+    pub enabled: bool,
+    /// Maximum number of cached results.
+    pub max_entries: usize,
+    /// Maximum size of all cached results together.
+    pub max_megabytes: usize,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_entries: 4,
+            max_megabytes: 512,
+        }
+    }
+}
+
+// MARK: CacheKey
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    /// Hash of the settings relevant for the result.
+    pub settings: u64,
+    /// See [crate::pipeline::execution::TaskInput::epoch].
+    pub epoch: u64,
+}
+
+/// All chunks of a completed M scan response.
+#[derive(Debug, Clone)]
+pub struct CachedResult {
+    pub chunks: Vec<Arc<DataMatrix>>,
+    pub a_scan_count: usize,
+    pub a_scan_samples: usize,
+}
+
+impl ByteSize for CachedResult {
+    fn byte_size(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.byte_size()).sum()
+    }
+}
+
+// MARK: CacheStats
+
+/// Whether a run was served from the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Status of the most recent run. [None], if there was none since the
+    /// cache got cleared.
+    pub last_run: Option<CacheStatus>,
+    pub hits: usize,
+    pub misses: usize,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+// MARK: ResultCache
+
+/// Least recently used cache of [CachedResult]s, bounded by
+/// [CacheSettings]. Publishes its [CacheStats] after every change.
+pub struct ResultCache {
+    settings: CacheSettings,
+    /// Least recently used first.
+    entries: VecDeque<(CacheKey, CachedResult)>,
+    stats: CacheStats,
+    stats_tx: watch::Sender<CacheStats>,
+}
+
+impl ResultCache {
+    pub fn new(settings: CacheSettings, stats_tx: watch::Sender<CacheStats>) -> Self {
+        Self {
+            settings,
+            entries: VecDeque::new(),
+            stats: CacheStats::default(),
+            stats_tx,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    /// Applies new bounds, evicting results if they got smaller. Disabling
+    /// the cache drops all results.
+    pub fn set_settings(&mut self, settings: CacheSettings) {
+        self.settings = settings;
+
+        if !settings.enabled {
+            self.entries.clear();
+        }
+        self.evict();
+    }
+
+    /// Looks up a result and counts the run as hit or miss. A hit becomes the
+    /// most recently used result.
+    pub fn get(&mut self, key: CacheKey) -> Option<CachedResult> {
+        let index = self.entries.iter().position(|(k, _)| *k == key);
+
+        let result = index
+            .and_then(|index| self.entries.remove(index))
+            .map(|entry| {
+                let result = entry.1.clone();
+                self.entries.push_back(entry);
+                result
+            });
+
+        if result.is_some() {
+            self.stats.hits += 1;
+            self.stats.last_run = Some(CacheStatus::Hit);
+        } else {
+            self.stats.misses += 1;
+            self.stats.last_run = Some(CacheStatus::Miss);
+        }
+        self.publish();
+
+        result
+    }
+
+    /// Stores a completed result, if the cache is enabled.
+    pub fn insert(&mut self, key: CacheKey, result: CachedResult) {
+        if !self.settings.enabled {
+            return;
+        }
+
+        self.entries.retain(|(k, _)| *k != key);
+        self.entries.push_back((key, result));
+        self.evict();
+    }
+
+    /// Drops all results, because the input changed.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.stats.last_run = None;
+        self.publish();
+    }
+
+    fn evict(&mut self) {
+        let max_bytes = self.settings.max_megabytes * 1024 * 1024;
+
+        while self.entries.len() > self.settings.max_entries || self.bytes() > max_bytes {
+            self.entries.pop_front();
+        }
+        self.publish();
+    }
+
+    fn bytes(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|(_, result)| result.byte_size())
+            .sum()
+    }
+
+    fn publish(&mut self) {
+        self.stats.entries = self.entries.len();
+        self.stats.bytes = self.bytes();
+        let _ = self.stats_tx.send(self.stats);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::DMatrix;
+
+    use super::*;
+
+    fn result(ncols: usize) -> CachedResult {
+        CachedResult {
+            chunks: vec![Arc::new(DMatrix::<u8>::zeros(1024, ncols).into())],
+            a_scan_count: ncols,
+            a_scan_samples: 1024,
+        }
+    }
+
+    fn key(settings: u64) -> CacheKey {
+        CacheKey { settings, epoch: 1 }
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let (stats_tx, stats_rx) = watch::channel(CacheStats::default());
+        let mut cache = ResultCache::new(
+            CacheSettings {
+                enabled: true,
+                max_entries: 2,
+                max_megabytes: 1,
+            },
+            stats_tx,
+        );
+
+        cache.insert(key(1), result(256));
+        cache.insert(key(2), result(256));
+        assert!(cache.get(key(1)).is_some());
+
+        // Too many results, 2 was used least recently
+        cache.insert(key(3), result(256));
+        assert!(cache.get(key(2)).is_none());
+        assert!(cache.get(key(1)).is_some());
+        assert!(cache
+            .get(CacheKey {
+                settings: 3,
+                epoch: 2
+            })
+            .is_none());
+
+        // Too many bytes
+        cache.insert(key(4), result(800));
+        assert!(cache.get(key(1)).is_none());
+        assert!(cache.get(key(3)).is_none());
+        assert!(cache.get(key(4)).is_some());
+
+        let stats = *stats_rx.borrow();
+        assert_eq!(stats.hits, 3);
+        assert_eq!(stats.misses, 4);
+        assert_eq!(stats.last_run, Some(CacheStatus::Hit));
+        assert_eq!(stats.entries, 1);
+        assert_eq!(stats.bytes, 800 * 1024);
+
+        cache.clear();
+        assert_eq!(stats_rx.borrow().entries, 0);
+        assert_eq!(stats_rx.borrow().last_run, None);
+
+        cache.set_settings(CacheSettings::default());
+        cache.insert(key(1), result(1));
+        assert_eq!(stats_rx.borrow().entries, 0);
+    }
+}
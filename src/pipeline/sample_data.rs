@@ -0,0 +1,117 @@
+//! A small synthetic pullback, used by the first-run walkthrough and as the
+//! input fixtures of the golden tests.
+//!
+//! Every A scan is the offset plus a cosine for every reflector, sampled at the
+//! chirp. Reflectors are the catheter sheath at a fixed depth, the lumen wall
+//! at a depth varying with the angle of the A scan and tissue decaying below
+//! the wall, with random phases like speckle. The data only depends on the
+//! number of A scans, so it can be generated instead of shipped.
+
+use std::{f64::consts::PI, fs, io, path::Path};
+
+use super::random::Lcg;
+
+/// Number of samples of each raw A scan.
+pub const A_SCAN_LENGTH: usize = 256;
+/// A scans per rotation of the catheter.
+pub const B_SCAN_PERIOD: usize = 128;
+
+/// Raw M scan of [A_SCAN_LENGTH] U16 samples per A scan.
+pub const RAW_FILE: &str = "raw.bin";
+/// Chirp of [A_SCAN_LENGTH] F64 values.
+pub const CHIRP_FILE: &str = "chirp.bin";
+/// Offset of [A_SCAN_LENGTH] F64 values.
+pub const OFFSET_FILE: &str = "offset.bin";
+
+/// Writes the raw M scan of `a_scan_count` A scans, the chirp and the offset
+/// into `dir`. Calls `progress` with the fraction of A scans generated.
+pub fn write(dir: &Path, a_scan_count: usize, mut progress: impl FnMut(f32)) -> io::Result<()> {
+    let mut rng = Lcg::new(0x5eed);
+
+    let chirp = (0..A_SCAN_LENGTH)
+        .map(|k| k as f64 + 4.0 * (PI * k as f64 / (A_SCAN_LENGTH - 1) as f64).sin())
+        .collect::<Vec<_>>();
+    let offset = (0..A_SCAN_LENGTH)
+        .map(|k| 30000.0 + 2000.0 * (2.0 * PI * k as f64 / A_SCAN_LENGTH as f64).cos())
+        .collect::<Vec<_>>();
+
+    let mut raw = Vec::with_capacity(A_SCAN_LENGTH * a_scan_count);
+
+    for i in 0..a_scan_count {
+        let angle = 2.0 * PI * (i % B_SCAN_PERIOD) as f64 / B_SCAN_PERIOD as f64;
+        let pullback = 2.0 * PI * i as f64 / a_scan_count as f64;
+        let wall = 32.0 + 8.0 * angle.cos() + 3.0 * (2.0 * angle).sin() + 2.0 * pullback.sin();
+
+        // Depth and amplitude
+        let mut reflectors = vec![(6.0, 3000.0), (8.0, 2000.0), (wall, 2500.0)];
+        reflectors.extend((1..30).map(|t| (wall + t as f64, 600.0 * (-t as f64 / 10.0).exp())));
+
+        let phases = reflectors
+            .iter()
+            .map(|_| 2.0 * PI * rng.uniform())
+            .collect::<Vec<_>>();
+
+        for k in 0..A_SCAN_LENGTH {
+            let signal = reflectors
+                .iter()
+                .zip(&phases)
+                .map(|(&(depth, amplitude), phase)| {
+                    amplitude * (2.0 * PI * depth * chirp[k] / A_SCAN_LENGTH as f64 + phase).cos()
+                })
+                .sum::<f64>();
+            let noise = 80.0 * (rng.uniform() - 0.5);
+
+            let value = offset[k] + signal + noise;
+            raw.push(value.round().clamp(0.0, u16::MAX as f64) as u16);
+        }
+
+        if i % B_SCAN_PERIOD == 0 {
+            progress(i as f32 / a_scan_count as f32);
+        }
+    }
+
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(RAW_FILE), bytemuck::cast_slice(&raw))?;
+    fs::write(dir.join(CHIRP_FILE), bytemuck::cast_slice(&chirp))?;
+    fs::write(dir.join(OFFSET_FILE), bytemuck::cast_slice(&offset))?;
+    progress(1.0);
+
+    Ok(())
+}
+
+/// Whether `dir` contains the data of `a_scan_count` A scans, so it does not
+/// need to be written again.
+pub fn exists(dir: &Path, a_scan_count: usize) -> bool {
+    let vector_size = (A_SCAN_LENGTH * std::mem::size_of::<f64>()) as u64;
+    let sizes = [
+        (RAW_FILE, (A_SCAN_LENGTH * a_scan_count * 2) as u64),
+        (CHIRP_FILE, vector_size),
+        (OFFSET_FILE, vector_size),
+    ];
+
+    sizes.iter().all(|(file, size)| {
+        fs::metadata(dir.join(file)).is_ok_and(|metadata| metadata.len() == *size)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_and_detect() {
+        let dir = std::env::temp_dir().join(format!("ivoct_sample_data_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        assert!(!exists(&dir, 256));
+
+        let mut fractions = Vec::new();
+        write(&dir, 256, |fraction| fractions.push(fraction)).unwrap();
+        assert_eq!(fractions.last(), Some(&1.0));
+        assert!(fractions.windows(2).all(|w| w[0] <= w[1]));
+
+        assert!(exists(&dir, 256));
+        assert!(!exists(&dir, 512));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
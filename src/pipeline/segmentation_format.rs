@@ -0,0 +1,164 @@
+//! Layout of exported M scan segmentations, as written by the output node and
+//! read by the segmentation input node.
+//!
+//! The values are stored as little endian `u32`, one per A scan. A
+//! [SegmentationSidecar] in a JSON file next to them records, which M scan
+//! they belong to, so a segmentation can be corrected by other tools and
+//! imported again without getting misaligned.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::units::NumberFormat;
+
+/// Describes an exported segmentation. Stored next to it, see
+/// [SegmentationSidecar::path].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentationSidecar {
+    pub version: u32,
+    /// Number of A scans of the segmented M scan.
+    pub a_scan_count: usize,
+    /// Number of samples per A scan of the segmented M scan.
+    pub a_scan_samples: usize,
+    /// [crate::pipeline::Pipeline::provenance] of the exported output, as hex.
+    /// [None], if unknown.
+    pub provenance: Option<String>,
+    /// Number of A scans of every chunk, as streamed by the producing node.
+    pub chunks: Vec<usize>,
+}
+
+impl SegmentationSidecar {
+    pub const VERSION: u32 = 1;
+
+    pub fn new(
+        a_scan_count: usize,
+        a_scan_samples: usize,
+        provenance: Option<u64>,
+        chunks: Vec<usize>,
+    ) -> Self {
+        Self {
+            version: Self::VERSION,
+            a_scan_count,
+            a_scan_samples,
+            provenance: provenance.map(|provenance| format!("{:016x}", provenance)),
+            chunks,
+        }
+    }
+
+    /// The sidecar of the segmentation at `path`, which has `.json` appended.
+    pub fn path(path: &Path) -> PathBuf {
+        let mut path = path.to_path_buf().into_os_string();
+        path.push(".json");
+        path.into()
+    }
+
+    pub fn parse(json: &[u8]) -> anyhow::Result<Self> {
+        let sidecar: Self = serde_json::from_slice(json)?;
+
+        if sidecar.version > Self::VERSION {
+            bail!(
+                "Sidecar version {} is newer than the supported version {}",
+                sidecar.version,
+                Self::VERSION
+            );
+        }
+
+        Ok(sidecar)
+    }
+
+    /// Checks the sidecar against the `entries` of its segmentation and the
+    /// A scan count and samples of the M scan it gets applied to, if known.
+    pub fn validate(&self, entries: usize, m_scan: Option<(usize, usize)>) -> anyhow::Result<()> {
+        let format = NumberFormat::current();
+
+        if entries != self.a_scan_count {
+            bail!(
+                "File has {} entries, but its sidecar records {} A scans",
+                format.count(entries),
+                format.count(self.a_scan_count)
+            );
+        }
+
+        let chunked: usize = self.chunks.iter().sum();
+        if chunked != self.a_scan_count {
+            bail!(
+                "Chunks of the sidecar cover {} A scans, but it records {}",
+                format.count(chunked),
+                format.count(self.a_scan_count)
+            );
+        }
+
+        if let Some((a_scan_count, a_scan_samples)) = m_scan {
+            if entries != a_scan_count {
+                bail!(
+                    "File has {} entries, but the connected M scan reports {} A scans",
+                    format.count(entries),
+                    format.count(a_scan_count)
+                );
+            }
+            if self.a_scan_samples != a_scan_samples {
+                bail!(
+                    "File segments A scans of {} samples, but the connected M scan has {}",
+                    format.count(self.a_scan_samples),
+                    format.count(a_scan_samples)
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the values of an exported segmentation.
+pub fn parse_values(data: &[u8]) -> anyhow::Result<Vec<u32>> {
+    let chunks = data.chunks_exact(4);
+    if !chunks.remainder().is_empty() {
+        return Err(anyhow!(
+            "File size of {} bytes is not a multiple of 4",
+            data.len()
+        ));
+    }
+
+    Ok(chunks
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validation_messages() {
+        let sidecar = SegmentationSidecar::new(98_304, 512, Some(0xab), vec![65_536, 32_768]);
+        assert_eq!(sidecar.provenance.as_deref(), Some("00000000000000ab"));
+
+        assert!(sidecar.validate(98_304, None).is_ok());
+        assert!(sidecar.validate(98_304, Some((98_304, 512))).is_ok());
+
+        let message = sidecar
+            .validate(98_304, Some((102_400, 512)))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            message,
+            "File has 98,304 entries, but the connected M scan reports 102,400 A scans"
+        );
+
+        assert!(sidecar.validate(98_303, None).is_err());
+        assert!(sidecar.validate(98_304, Some((98_304, 256))).is_err());
+
+        let mut chunks = sidecar.clone();
+        chunks.chunks.pop();
+        assert!(chunks.validate(98_304, None).is_err());
+
+        let json = serde_json::to_vec(&SegmentationSidecar {
+            version: 2,
+            ..sidecar
+        })
+        .unwrap();
+        assert!(SegmentationSidecar::parse(&json).is_err());
+    }
+}
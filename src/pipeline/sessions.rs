@@ -0,0 +1,583 @@
+//! Run sessions: saving all outputs at once appends a [Session] to a
+//! `runs.json`. It records the produced files with their sizes and checksums,
+//! how long the nodes took, and a snapshot of the pipeline, so a session can
+//! be compared to others and run again.
+
+use std::{
+    collections::BTreeSet,
+    fmt, fs,
+    io::Read,
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{sync::watch, task::JoinHandle};
+
+use crate::{node_graph::NodeId, settings::APP_NAME};
+
+use super::{
+    nodes::output,
+    report::{execution_order, flatten_settings},
+    Fnv1a, Pipeline, PipelineExecutor,
+};
+
+pub const RUNS_FILE_NAME: &str = "runs.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    /// See [session_name].
+    pub name: String,
+    /// Seconds since the unix epoch.
+    pub started: u64,
+    /// Seconds until the last output finished saving.
+    pub seconds: f64,
+    /// [Pipeline::fingerprint] at the start.
+    pub fingerprint: u64,
+    pub files: Vec<SessionFile>,
+    /// Outputs, that did not save before the session was finished, or whose
+    /// file could not be read afterwards.
+    #[serde(default)]
+    pub missing: Vec<String>,
+    pub durations: Vec<NodeDuration>,
+    /// The pipeline and its edit state, like a saved pipeline file.
+    pub snapshot: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionFile {
+    pub node: String,
+    pub path: PathBuf,
+    pub size: u64,
+    /// FNV-1a of the contents, see [checksum].
+    pub checksum: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeDuration {
+    pub node: String,
+    pub seconds: f64,
+}
+
+impl Session {
+    /// The pipeline of [Self::snapshot].
+    pub fn pipeline(&self) -> anyhow::Result<Pipeline> {
+        let (pipeline, _): (Pipeline, serde_json::Value) =
+            serde_json::from_value(self.snapshot.clone())?;
+        Ok(pipeline)
+    }
+
+    /// Directory of the first produced file.
+    pub fn folder(&self) -> Option<&Path> {
+        self.files.first().and_then(|file| file.path.parent())
+    }
+}
+
+/// Name of a session started at `time`, like `run_2024-06-01T12-00-00`.
+pub fn session_name(time: SystemTime) -> String {
+    format!("run_{}", utc_timestamp(time))
+}
+
+/// `time` in UTC, like `2024-06-01T12-00-00`. It contains no colons, so it
+/// can name a directory.
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs = secs % 86400;
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}-{:02}-{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Date of the day `days` after 1970-01-01, see
+/// <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    (year, month, day)
+}
+
+// MARK: runs.json
+
+/// The `runs.json` next to the pipeline file. Pipelines, that were not saved,
+/// share one in the storage directory of the app.
+pub fn runs_file_path(pipeline_file: Option<&Path>) -> PathBuf {
+    let dir = match pipeline_file {
+        Some(file) => file.parent().map(Path::to_path_buf),
+        None => eframe::storage_dir(APP_NAME),
+    };
+    dir.unwrap_or_default().join(RUNS_FILE_NAME)
+}
+
+/// All sessions in the file, oldest first. A missing file holds no sessions.
+/// This blocks on the file system.
+pub fn load(path: &Path) -> anyhow::Result<Vec<Session>> {
+    match fs::read_to_string(path) {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Adds `session` to the end of the file. This blocks on the file system.
+pub fn append(path: &Path, session: &Session) -> anyhow::Result<()> {
+    let mut sessions = load(path)?;
+    sessions.push(session.clone());
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&sessions)?)?;
+
+    Ok(())
+}
+
+/// FNV-1a of the contents of the file as hex. This blocks on the file system.
+pub fn checksum(path: &Path) -> std::io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut buffer = vec![0; 1 << 16];
+    let mut hash = Fnv1a::new();
+
+    loop {
+        match file.read(&mut buffer)? {
+            0 => break,
+            n => hash.write(&buffer[..n]),
+        }
+    }
+
+    Ok(format!("{:016x}", hash.finish()))
+}
+
+// MARK: SessionRun
+
+/// A session, whose outputs are still saving.
+pub struct SessionRun {
+    name: String,
+    started: SystemTime,
+    start: Instant,
+    fingerprint: u64,
+    snapshot: serde_json::Value,
+    outputs: Vec<PendingOutput>,
+}
+
+struct PendingOutput {
+    node_id: NodeId,
+    label: String,
+    path: PathBuf,
+    saves_rx: watch::Receiver<usize>,
+    /// Saves, that happened before the session.
+    baseline: usize,
+    finished: Option<f64>,
+}
+
+impl SessionRun {
+    /// Requests every output node, that has an input and a path, to save as
+    /// part of a new session. `snapshot` is stored in the [Session].
+    pub fn start(pipeline: &mut Pipeline, snapshot: serde_json::Value) -> Self {
+        let started = SystemTime::now();
+        let name = session_name(started);
+        let fingerprint = pipeline.fingerprint();
+
+        let mut outputs = Vec::new();
+        for (node_id, node) in pipeline.nodes.iter_mut() {
+            if pipeline.disabled.contains(node_id) {
+                continue;
+            }
+            let label = label(*node_id, node.name());
+            let Some(node) = node.as_any_mut().downcast_mut::<output::Node>() else {
+                continue;
+            };
+            let Some(saves_rx) = node.saves_rx.clone() else {
+                continue;
+            };
+            if node.input.connection().is_none() || node.path.as_os_str().is_empty() {
+                continue;
+            }
+
+            node.save_in_session(&name);
+            let baseline = *saves_rx.borrow();
+
+            outputs.push(PendingOutput {
+                node_id: *node_id,
+                label,
+                path: node.session_path(&name),
+                baseline,
+                saves_rx,
+                finished: None,
+            });
+        }
+        outputs.sort_by_key(|output| output.node_id);
+
+        Self {
+            name,
+            started,
+            start: Instant::now(),
+            fingerprint,
+            snapshot,
+            outputs,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Checks, which outputs finished saving. Nodes, whose task got recreated,
+    /// are tracked through their new task.
+    pub fn update(&mut self, pipeline: &Pipeline) {
+        let elapsed = self.start.elapsed().as_secs_f64();
+
+        for output in self.outputs.iter_mut().filter(|o| o.finished.is_none()) {
+            let saves_rx = pipeline
+                .nodes
+                .get(&output.node_id)
+                .and_then(|node| node.as_any().downcast_ref::<output::Node>())
+                .and_then(|node| node.saves_rx.as_ref());
+
+            if let Some(saves_rx) = saves_rx.filter(|rx| !rx.same_channel(&output.saves_rx)) {
+                output.saves_rx = saves_rx.clone();
+                output.baseline = 0;
+            }
+
+            if *output.saves_rx.borrow() > output.baseline {
+                output.finished = Some(elapsed);
+            }
+        }
+    }
+
+    /// Number of outputs, that finished saving, and of all outputs.
+    pub fn progress(&self) -> (usize, usize) {
+        let finished = self.outputs.iter().filter(|o| o.finished.is_some()).count();
+        (finished, self.outputs.len())
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.outputs.iter().all(|o| o.finished.is_some())
+    }
+
+    /// Records the session in the `runs.json` at `runs_file`. Outputs, that
+    /// did not finish yet, are listed as missing. The files are hashed on a
+    /// blocking thread.
+    pub fn finish(
+        self,
+        pipeline: &Pipeline,
+        executor: &PipelineExecutor,
+        runs_file: PathBuf,
+    ) -> JoinHandle<anyhow::Result<Session>> {
+        let durations = execution_order(pipeline)
+            .into_iter()
+            .filter_map(|node_id| {
+                let elapsed = executor
+                    .output_stats(node_id)
+                    .into_iter()
+                    .filter_map(|stats| stats.elapsed)
+                    .max()?;
+                Some(NodeDuration {
+                    node: label(node_id, pipeline[node_id].name()),
+                    seconds: elapsed.as_secs_f64(),
+                })
+            })
+            .collect();
+
+        let seconds = self
+            .outputs
+            .iter()
+            .filter_map(|o| o.finished)
+            .fold(0.0, f64::max);
+        let (finished, missing): (Vec<_>, Vec<_>) =
+            self.outputs.into_iter().partition(|o| o.finished.is_some());
+
+        let mut session = Session {
+            name: self.name,
+            started: self
+                .started
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            seconds,
+            fingerprint: self.fingerprint,
+            files: Vec::new(),
+            missing: missing.into_iter().map(|o| o.label).collect(),
+            durations,
+            snapshot: self.snapshot,
+        };
+
+        tokio::task::spawn_blocking(move || {
+            for output in finished {
+                // The file might have been moved away in the meantime
+                match (fs::metadata(&output.path), checksum(&output.path)) {
+                    (Ok(metadata), Ok(checksum)) => session.files.push(SessionFile {
+                        node: output.label,
+                        path: output.path,
+                        size: metadata.len(),
+                        checksum,
+                    }),
+                    _ => session.missing.push(output.label),
+                }
+            }
+
+            append(&runs_file, &session)?;
+            Ok(session)
+        })
+    }
+}
+
+pub(super) fn label(node_id: NodeId, name: &str) -> String {
+    let number: usize = node_id.into();
+    format!("{} (#{})", name, number)
+}
+
+// MARK: Diff
+
+/// A difference between the pipelines of two sessions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingChange {
+    Added {
+        node: String,
+    },
+    Removed {
+        node: String,
+    },
+    Changed {
+        node: String,
+        key: String,
+        from: String,
+        to: String,
+    },
+}
+
+impl fmt::Display for SettingChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingChange::Added { node } => write!(f, "{node} was added"),
+            SettingChange::Removed { node } => write!(f, "{node} was removed"),
+            SettingChange::Changed {
+                node,
+                key,
+                from,
+                to,
+            } => write!(f, "{node}: {key} changed from {from} to {to}"),
+        }
+    }
+}
+
+/// Settings and connections, that changed from pipeline `a` to `b`, ordered
+/// by node.
+pub fn diff(a: &Pipeline, b: &Pipeline) -> Vec<SettingChange> {
+    let node_ids = a
+        .nodes
+        .keys()
+        .chain(b.nodes.keys())
+        .copied()
+        .collect::<BTreeSet<_>>();
+
+    let mut changes = Vec::new();
+
+    for node_id in node_ids {
+        let values = [a, b].map(|pipeline| {
+            let node = pipeline.nodes.get(&node_id)?;
+            let value = serde_json::to_value(node).ok()?;
+            Some((label(node_id, node.name()), value))
+        });
+
+        let (node, value_a, value_b) = match values {
+            [Some((_, a)), Some((node, b))] if a.get("type") == b.get("type") => (node, a, b),
+            [a, b] => {
+                if let Some((node, _)) = a {
+                    changes.push(SettingChange::Removed { node });
+                }
+                if let Some((node, _)) = b {
+                    changes.push(SettingChange::Added { node });
+                }
+                continue;
+            }
+        };
+
+        let settings_a = flatten_settings(&value_a);
+        let settings_b = flatten_settings(&value_b);
+        let keys = settings_a
+            .iter()
+            .chain(settings_b.iter())
+            .map(|(key, _)| key.as_str())
+            .collect::<BTreeSet<_>>();
+
+        let get = |settings: &[(String, String)], key: &str| {
+            settings
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value.clone())
+                .unwrap_or_else(|| "nothing".to_string())
+        };
+
+        for key in keys {
+            let (from, to) = (get(&settings_a, key), get(&settings_b, key));
+            if from != to {
+                changes.push(SettingChange::Changed {
+                    node: node.clone(),
+                    key: key.to_string(),
+                    from,
+                    to,
+                });
+            }
+        }
+
+        let inputs = |pipeline: &Pipeline| {
+            let inputs = pipeline.nodes[&node_id]
+                .inputs()
+                .into_iter()
+                .filter_map(|(_, output)| output)
+                .map(|output| {
+                    let number: usize = output.node_id.into();
+                    format!("#{number}")
+                })
+                .collect::<Vec<_>>();
+            match inputs.is_empty() {
+                true => "nothing".to_string(),
+                false => inputs.join(", "),
+            }
+        };
+        let (from, to) = (inputs(a), inputs(b));
+        if from != to {
+            changes.push(SettingChange::Changed {
+                node: node.clone(),
+                key: "inputs".to_string(),
+                from,
+                to,
+            });
+        }
+
+        let (from, to) = (a.disabled.contains(&node_id), b.disabled.contains(&node_id));
+        if from != to {
+            changes.push(SettingChange::Changed {
+                node,
+                key: "disabled".to_string(),
+                from: from.to_string(),
+                to: to.to_string(),
+            });
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn names() {
+        let time = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+
+        assert_eq!(session_name(time(0)), "run_1970-01-01T00-00-00");
+        assert_eq!(session_name(time(1717243205)), "run_2024-06-01T12-00-05");
+        assert_eq!(session_name(time(951825599)), "run_2000-02-29T11-59-59");
+        assert_eq!(
+            output::run_path(Path::new("out/scan.bin"), "run_x"),
+            Path::new("out/run_x/scan.bin")
+        );
+    }
+
+    fn pipeline(nodes: serde_json::Value) -> Pipeline {
+        serde_json::from_value(json!({ "nodes": nodes })).unwrap()
+    }
+
+    fn output() -> serde_json::Value {
+        json!({
+            "type": "output",
+            "path": "out.bin",
+            "input_type": "RawMScan",
+            "scan_data_type": "U16",
+            "input": {
+                "value": null,
+                "connection": { "node_id": 1, "output_id": 0, "type_id": 0 },
+            },
+        })
+    }
+
+    #[test]
+    fn changes() {
+        let a = pipeline(json!({
+            "1": { "type": "binary_input", "path": "a.bin", "input_type": "RawMScan",
+                   "data_type": "U16", "a_scan_length": 256, "endianness": "Little" },
+            "2": output(),
+        }));
+        let b = pipeline(json!({
+            "1": { "type": "binary_input", "path": "a.bin", "input_type": "RawMScan",
+                   "data_type": "U16", "a_scan_length": 512, "endianness": "Little" },
+            "3": output(),
+        }));
+
+        assert!(diff(&a, &a).is_empty());
+        let reloaded: Pipeline = serde_json::from_value(serde_json::to_value(&a).unwrap()).unwrap();
+        assert_eq!(a.fingerprint(), reloaded.fingerprint());
+        assert_ne!(a.fingerprint(), b.fingerprint());
+
+        let changes = diff(&a, &b);
+        assert_eq!(changes.len(), 3, "{changes:#?}");
+        assert!(matches!(
+            &changes[0],
+            SettingChange::Changed { key, from, to, .. }
+                if key == "a_scan_length" && from == "256" && to == "512"
+        ));
+        assert!(matches!(&changes[1], SettingChange::Removed { node } if node.ends_with("(#2)")));
+        assert!(matches!(&changes[2], SettingChange::Added { node } if node.ends_with("(#3)")));
+    }
+
+    #[test]
+    fn runs_file() {
+        let dir = std::env::temp_dir().join(format!("ivoct_sessions_{}", std::process::id()));
+        let path = dir.join(RUNS_FILE_NAME);
+        let file = dir.join("out.bin");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&file, b"abc").unwrap();
+
+        assert!(load(&path).unwrap().is_empty());
+
+        let session = |name: &str| Session {
+            name: name.to_string(),
+            started: 0,
+            seconds: 1.5,
+            fingerprint: 42,
+            files: vec![SessionFile {
+                node: "Output (#2)".to_string(),
+                path: file.clone(),
+                size: 3,
+                checksum: checksum(&file).unwrap(),
+            }],
+            missing: Vec::new(),
+            durations: Vec::new(),
+            snapshot: json!([{ "nodes": {} }, null]),
+        };
+
+        append(&path, &session("first")).unwrap();
+        append(&path, &session("second")).unwrap();
+
+        let sessions = load(&path).unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[1].name, "second");
+        assert_eq!(sessions[0].files[0].checksum, "e71fa2190541574b");
+        assert_eq!(sessions[0].folder(), Some(dir.as_path()));
+        assert!(sessions[0].pipeline().unwrap().nodes.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
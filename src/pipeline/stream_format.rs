@@ -0,0 +1,208 @@
+//! Layout of exports streamed over TCP by the output node, see
+//! [super::nodes::output::Destination].
+//!
+//! The bytes are the same as in an exported file, but split into frames, one
+//! per chunk the output node received. Every frame starts with a
+//! [FrameHeader] describing its payload. The stream ends, when the output node
+//! closes the connection. The [super::raw_format::RawHeader] and the
+//! segmentation sidecar of files are not streamed, because every frame already
+//! describes itself.
+
+use anyhow::anyhow;
+use tokio::{io::AsyncWriteExt, net::TcpStream, sync::mpsc, task::JoinHandle};
+
+use super::{types::DataType, PipelineDataType};
+
+// MARK: FrameHeader
+
+/// Header of every frame, 16 bytes long, little endian:
+///
+/// | Offset | Size | Content                                                  |
+/// |--------|------|----------------------------------------------------------|
+/// | 0      | 4    | Length of the payload in bytes                           |
+/// | 4      | 1    | Type code, 0 to 7 in the order of [PipelineDataType::VALUES] |
+/// | 5      | 1    | Data type, 0 to 5 for U8, U16, U32, U64, F32 and F64     |
+/// | 6      | 2    | Reserved, zero                                           |
+/// | 8      | 4    | Rows, the A scan length of M scans                       |
+/// | 12     | 4    | Columns, the A scans of M scans                          |
+///
+/// Diameters and meshes are streamed as text, with [DataType::U8] and a
+/// single row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub length: usize,
+    pub pipeline_type: PipelineDataType,
+    pub data_type: DataType,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl FrameHeader {
+    pub const SIZE: usize = 16;
+
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+
+        bytes[..4].copy_from_slice(&(self.length as u32).to_le_bytes());
+        bytes[4] = PipelineDataType::VALUES
+            .iter()
+            .position(|t| *t == self.pipeline_type)
+            .unwrap() as u8;
+        bytes[5] = DataType::VALUES
+            .iter()
+            .position(|t| *t == self.data_type)
+            .unwrap() as u8;
+        bytes[8..12].copy_from_slice(&(self.rows as u32).to_le_bytes());
+        bytes[12..16].copy_from_slice(&(self.cols as u32).to_le_bytes());
+
+        bytes
+    }
+
+    /// Only the tests read streams, clients are other programs.
+    #[cfg(test)]
+    pub fn parse(bytes: &[u8; Self::SIZE]) -> anyhow::Result<Self> {
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+        };
+
+        let pipeline_type = *PipelineDataType::VALUES
+            .get(bytes[4] as usize)
+            .ok_or_else(|| anyhow!("Invalid type code {} in frame header", bytes[4]))?;
+        let data_type = *DataType::VALUES
+            .get(bytes[5] as usize)
+            .ok_or_else(|| anyhow!("Invalid data type {} in frame header", bytes[5]))?;
+
+        Ok(Self {
+            length: u32_at(0),
+            pipeline_type,
+            data_type,
+            rows: u32_at(8),
+            cols: u32_at(12),
+        })
+    }
+}
+
+/// Reads the next frame from `reader`. Returns [None] at the end of the
+/// stream.
+#[cfg(test)]
+pub async fn read_frame(
+    reader: &mut (impl tokio::io::AsyncRead + Unpin),
+) -> anyhow::Result<Option<(FrameHeader, Vec<u8>)>> {
+    use tokio::io::AsyncReadExt;
+
+    let mut bytes = [0; FrameHeader::SIZE];
+    match reader.read_exact(&mut bytes).await {
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    };
+
+    let header = FrameHeader::parse(&bytes)?;
+    let mut payload = vec![0; header.length];
+    reader.read_exact(&mut payload).await?;
+
+    Ok(Some((header, payload)))
+}
+
+// MARK: FrameWriter
+
+/// Writes frames to a [TcpStream] in the background.
+///
+/// Output nodes receive chunks from a [crate::queue_channel], which drops
+/// chunks for receivers falling behind. So frames are queued here instead of
+/// waiting for the socket, which takes them at its own pace.
+pub struct FrameWriter {
+    pipeline_type: PipelineDataType,
+    tx: Option<mpsc::UnboundedSender<Vec<u8>>>,
+    /// [None] after [Self::finish].
+    writer: Option<JoinHandle<std::io::Result<()>>>,
+}
+
+impl FrameWriter {
+    pub fn new(mut stream: TcpStream, pipeline_type: PipelineDataType) -> Self {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Vec<u8>>();
+
+        let writer = tokio::spawn(async move {
+            while let Some(frame) = rx.recv().await {
+                stream.write_all(&frame).await?;
+            }
+            stream.shutdown().await
+        });
+
+        Self {
+            pipeline_type,
+            tx: Some(tx),
+            writer: Some(writer),
+        }
+    }
+
+    /// Queues a frame with `payload` of `rows` times `cols` values of
+    /// `data_type`. Fails, if the connection was lost.
+    pub async fn write(
+        &mut self,
+        data_type: DataType,
+        rows: usize,
+        cols: usize,
+        payload: &[u8],
+    ) -> anyhow::Result<()> {
+        let header = FrameHeader {
+            length: payload.len(),
+            pipeline_type: self.pipeline_type,
+            data_type,
+            rows,
+            cols,
+        };
+
+        let mut frame = Vec::with_capacity(FrameHeader::SIZE + payload.len());
+        frame.extend_from_slice(&header.to_bytes());
+        frame.extend_from_slice(payload);
+
+        let sent = self.tx.as_ref().is_some_and(|tx| tx.send(frame).is_ok());
+        if !sent {
+            // The writer stopped, because writing failed
+            self.finish().await?;
+            return Err(anyhow!("Connection closed"));
+        }
+        Ok(())
+    }
+
+    /// Waits until every frame is written and closes the connection.
+    pub async fn finish(&mut self) -> anyhow::Result<()> {
+        self.tx = None;
+        let writer = self.writer.take().ok_or(anyhow!("Connection closed"))?;
+        writer.await?.map_err(|e| anyhow!("Connection lost: {}", e))
+    }
+}
+
+impl Drop for FrameWriter {
+    fn drop(&mut self) {
+        // Canceled exports do not finish writing
+        if let Some(writer) = &self.writer {
+            writer.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn header_roundtrip() {
+        for pipeline_type in PipelineDataType::VALUES {
+            for data_type in DataType::VALUES {
+                let header = FrameHeader {
+                    length: 1024,
+                    pipeline_type,
+                    data_type,
+                    rows: 256,
+                    cols: 2,
+                };
+                assert_eq!(FrameHeader::parse(&header.to_bytes()).unwrap(), header);
+            }
+        }
+
+        let mut bytes = [0; FrameHeader::SIZE];
+        bytes[4] = 8;
+        assert!(FrameHeader::parse(&bytes).is_err());
+    }
+}
@@ -0,0 +1,533 @@
+//! Settings suggested for filter nodes, derived from a small sample of their
+//! input: the noise level suggests the sigma of a Gaussian filter, the size of
+//! the speckle grains the window of a median filter, and the intensity
+//! percentiles the threshold of a Prewitt filter.
+//!
+//! The sample is only taken from a response the input already holds, so
+//! suggestions never make upstream nodes process anything. Nothing is applied
+//! automatically, the node UI offers the suggestions.
+
+use std::collections::HashMap;
+
+use nalgebra::{DMatrix, DMatrixView, Vector2};
+use tokio::{sync::watch, task::JoinHandle};
+
+use crate::{
+    node_graph::{NodeId, NodeOutput},
+    queue_channel::error::RecvError,
+};
+
+use super::{
+    execution::TaskInput,
+    nodes::filter::{self, SweepParameter},
+    requests,
+    types::{DataMatrix, DataType},
+    Pipeline, PipelineDataType, PipelineExecutor,
+};
+
+/// Number of A scans sampled from the start of the input.
+pub const SAMPLE_A_SCANS: usize = 256;
+
+/// Largest distance in pixels, at which speckle grains are measured. Larger
+/// structures are considered tissue, not speckle.
+const MAX_GRAIN_LAG: usize = 16;
+
+/// Noise, that should remain after a Gaussian filter, relative to the
+/// contrast of the data.
+const TARGET_NOISE: f32 = 0.02;
+
+/// What is known about the suggestions of a filter node.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum SuggestionState {
+    /// The input is not connected.
+    #[default]
+    None,
+    /// Waiting for the input to hold a response, which can be sampled.
+    Waiting,
+    Sampling,
+    Ready(Suggestions),
+    /// The node started to run, before the sample was taken.
+    Cancelled,
+    Failed(String),
+}
+
+/// Measurements of a sample and the settings derived from them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestions {
+    /// Standard deviation of the noise, from 0 to 1 of the range of the data
+    /// type.
+    pub noise_sigma: f32,
+    /// Size of the speckle grains in pixels, along the rows and the columns.
+    pub grain_size: Vector2<f32>,
+    /// 1st, 50th, 95th and 99th percentile of the intensity, from 0 to 1.
+    pub percentiles: [f32; 4],
+    pub values: Vec<(SweepParameter, f64)>,
+}
+
+impl Suggestions {
+    /// The suggested value of `parameter`, if any.
+    pub fn value(&self, parameter: SweepParameter) -> Option<f64> {
+        self.values
+            .iter()
+            .find(|(p, _)| *p == parameter)
+            .map(|(_, value)| *value)
+    }
+}
+
+// MARK: Heuristics
+
+/// Computes the suggestions for a sample of A scans, scaled to 0 to 1.
+pub fn suggest(sample: DMatrixView<f32>) -> Suggestions {
+    let noise_sigma = noise_sigma(sample);
+    let grain_size = grain_size(sample);
+    let percentiles = percentiles(sample, [0.01, 0.5, 0.95, 0.99]);
+
+    let contrast = (percentiles[3] - percentiles[0]).max(f32::EPSILON);
+    let gauss_sigma = gauss_sigma(noise_sigma, contrast);
+    let gauss_size = (2.0 * (3.0 * gauss_sigma).ceil() + 1.0) as f64;
+
+    let values = vec![
+        (SweepParameter::GaussSigma, round(gauss_sigma as f64, 1)),
+        (SweepParameter::GaussKernelRows, gauss_size),
+        (SweepParameter::GaussKernelColumns, gauss_size),
+        (
+            SweepParameter::MedianRows,
+            median_window(grain_size.x) as f64,
+        ),
+        (
+            SweepParameter::MedianColumns,
+            median_window(grain_size.y) as f64,
+        ),
+        (
+            SweepParameter::PrewittThreshold,
+            round(prewitt_threshold(percentiles[1], percentiles[2]) as f64, 3),
+        ),
+    ];
+
+    Suggestions {
+        noise_sigma,
+        grain_size,
+        percentiles,
+        values,
+    }
+}
+
+/// Robust estimate of the standard deviation of white noise, from the median
+/// absolute deviation of the differences between neighboring samples of the A
+/// scans. Structures change slowly compared to the noise, so they barely
+/// affect the median.
+pub fn noise_sigma(sample: DMatrixView<f32>) -> f32 {
+    if sample.nrows() < 2 {
+        return 0.0;
+    }
+
+    let mut differences = sample
+        .column_iter()
+        .flat_map(|a_scan| {
+            a_scan
+                .iter()
+                .zip(a_scan.iter().skip(1))
+                .map(|(a, b)| b - a)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let center = median(&mut differences);
+    let mut deviations = differences
+        .iter()
+        .map(|d| (d - center).abs())
+        .collect::<Vec<_>>();
+
+    // The MAD of a normal distribution is 0.6745 sigma. Differences of two
+    // samples have sqrt(2) times the sigma of one
+    median(&mut deviations) / 0.6745 / std::f32::consts::SQRT_2
+}
+
+/// Full width at half maximum of the autocorrelation of the speckle, along
+/// the rows and along the columns. It is measured in tiles, with their mean
+/// removed, and the median of all tiles is used, so the few tiles crossing
+/// edges of structures do not count.
+pub fn grain_size(sample: DMatrixView<f32>) -> Vector2<f32> {
+    const TILE: usize = 2 * MAX_GRAIN_LAG;
+
+    let tiles = |len: usize| match len / TILE {
+        0 => vec![(0, len)],
+        count => (0..count).map(|i| (i * TILE, TILE)).collect(),
+    };
+
+    let mut widths = [Vec::new(), Vec::new()];
+    for &(row, nrows) in &tiles(sample.nrows()) {
+        for &(col, ncols) in &tiles(sample.ncols()) {
+            let tile = sample.view((row, col), (nrows, ncols));
+            let tile = tile.add_scalar(-tile.mean());
+
+            widths[0].push(half_width(&tile, |r, c, lag| (r + lag, c)));
+            widths[1].push(half_width(&tile, |r, c, lag| (r, c + lag)));
+        }
+    }
+
+    Vector2::new(median(&mut widths[0]), median(&mut widths[1]))
+}
+
+/// Twice the shift, at which the autocorrelation of `tile` drops below one
+/// half. `shift` moves a position by a lag.
+fn half_width(tile: &DMatrix<f32>, shift: fn(usize, usize, usize) -> (usize, usize)) -> f32 {
+    let mut previous = 1.0;
+
+    for lag in 1..=MAX_GRAIN_LAG {
+        let correlation = autocorrelation(tile, |r, c| shift(r, c, lag));
+        if correlation < 0.5 {
+            // Interpolate, where the correlation crosses one half
+            let fraction = (previous - 0.5) / (previous - correlation);
+            return 2.0 * (lag as f32 - 1.0 + fraction);
+        }
+        previous = correlation;
+    }
+
+    2.0 * MAX_GRAIN_LAG as f32
+}
+
+/// The intensities at the `fractions` of the sorted samples.
+pub fn percentiles<const N: usize>(sample: DMatrixView<f32>, fractions: [f32; N]) -> [f32; N] {
+    let mut values = sample
+        .iter()
+        .copied()
+        .filter(|v| v.is_finite())
+        .collect::<Vec<_>>();
+    values.sort_unstable_by(f32::total_cmp);
+
+    fractions.map(|fraction| match values.len() {
+        0 => 0.0,
+        len => values[((len - 1) as f32 * fraction).round() as usize],
+    })
+}
+
+/// Sigma in pixels of a Gaussian filter, that reduces noise with
+/// `noise_sigma` to [TARGET_NOISE] of `contrast`. Averaging white noise with
+/// a Gaussian divides its standard deviation by about 2 sqrt(pi) sigma.
+pub fn gauss_sigma(noise_sigma: f32, contrast: f32) -> f32 {
+    let reduction = noise_sigma / (TARGET_NOISE * contrast);
+    (reduction / (2.0 * std::f32::consts::PI.sqrt())).clamp(0.5, 10.0)
+}
+
+/// The smallest odd window covering a speckle grain, at least 3 pixels.
+pub fn median_window(grain_size: f32) -> usize {
+    let size = (grain_size.ceil() as usize).clamp(3, 31);
+    size | 1
+}
+
+/// Threshold of a Prewitt filter, that keeps edges with at least half the
+/// contrast between the background (`median`) and bright tissue (`bright`).
+/// An edge of height h has a Prewitt magnitude of 3 h.
+pub fn prewitt_threshold(median: f32, bright: f32) -> f32 {
+    (1.5 * (bright - median)).clamp(0.0, 1.0)
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let middle = values.len() / 2;
+    *values.select_nth_unstable_by(middle, f32::total_cmp).1
+}
+
+fn round(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value * factor).round() / factor
+}
+
+/// Autocorrelation of `matrix` shifted by `offset`, normalized by the energy
+/// of the whole matrix. Pairs shifted outside are missing, which keeps the
+/// estimate from growing towards the edges of small tiles.
+fn autocorrelation(matrix: &DMatrix<f32>, offset: impl Fn(usize, usize) -> (usize, usize)) -> f32 {
+    let energy = matrix.iter().map(|&v| v as f64 * v as f64).sum::<f64>();
+    if energy <= 0.0 {
+        return 0.0;
+    }
+
+    let mut sum = 0.0f64;
+    for c in 0..matrix.ncols() {
+        for r in 0..matrix.nrows() {
+            if let Some(&b) = matrix.get(offset(r, c)) {
+                sum += matrix[(r, c)] as f64 * b as f64;
+            }
+        }
+    }
+
+    (sum / energy) as f32
+}
+
+// MARK: SuggestionRunner
+
+/// Samples the inputs of filter nodes and publishes the suggestions to
+/// [filter::Node::suggestions]. A sample is taken once per connection.
+#[derive(Default)]
+pub struct SuggestionRunner {
+    samplers: HashMap<NodeId, Sampler>,
+}
+
+struct Sampler {
+    source: NodeOutput,
+    state: SuggestionState,
+    task: Option<(JoinHandle<()>, watch::Receiver<SuggestionState>)>,
+}
+
+impl SuggestionRunner {
+    /// Starts, cancels and collects samples. Returns true, while a sample is
+    /// pending, so the caller keeps repainting.
+    pub fn update(&mut self, pipeline: &mut Pipeline, executor: &PipelineExecutor) -> bool {
+        let mut pending = false;
+
+        self.samplers
+            .retain(|node_id, _| pipeline.nodes.contains_key(node_id));
+
+        for (node_id, node) in pipeline.nodes.iter_mut() {
+            let Some(node) = node.as_any_mut().downcast_mut::<filter::Node>() else {
+                continue;
+            };
+
+            let source = node
+                .input
+                .connection()
+                .filter(|source| source.type_id == PipelineDataType::MScan.into());
+            let Some(source) = source.filter(|_| !pipeline.disabled.contains(node_id)) else {
+                self.samplers.remove(node_id);
+                node.suggestions = SuggestionState::None;
+                continue;
+            };
+
+            // A new connection target gets sampled again
+            let sampler = self
+                .samplers
+                .entry(*node_id)
+                .and_modify(|sampler| {
+                    if sampler.source != source {
+                        *sampler = Sampler::new(source);
+                    }
+                })
+                .or_insert_with(|| Sampler::new(source));
+
+            // A real run of the node takes precedence
+            let running = executor
+                .get_output(*node_id, filter::OutputId::Filtered.into())
+                .is_some_and(|output| output.has_response());
+
+            sampler.update(executor, running);
+            pending |= matches!(
+                sampler.state,
+                SuggestionState::Waiting | SuggestionState::Sampling
+            );
+
+            if node.suggestions != sampler.state {
+                node.suggestions = sampler.state.clone();
+            }
+        }
+
+        pending
+    }
+}
+
+impl Sampler {
+    fn new(source: NodeOutput) -> Self {
+        Self {
+            source,
+            state: SuggestionState::Waiting,
+            task: None,
+        }
+    }
+
+    fn update(&mut self, executor: &PipelineExecutor, running: bool) {
+        match self.state {
+            SuggestionState::Waiting | SuggestionState::Sampling if running => {
+                self.cancel();
+                self.state = SuggestionState::Cancelled;
+            }
+            SuggestionState::Waiting => {
+                let Some(mut output) =
+                    executor.get_output(self.source.node_id, self.source.output_id)
+                else {
+                    return;
+                };
+                let mut input = TaskInput::<requests::MScan>::default();
+                input.connect(&mut output);
+
+                if let Some(response) = input.available(&requests::MScan) {
+                    let (state_tx, state_rx) = watch::channel(SuggestionState::Sampling);
+                    let task = tokio::spawn(async move {
+                        let state = match sample(response).await {
+                            Ok(suggestions) => SuggestionState::Ready(suggestions),
+                            Err(e) => SuggestionState::Failed(e.to_string()),
+                        };
+                        state_tx.send_replace(state);
+                    });
+
+                    self.task = Some((task, state_rx));
+                    self.state = SuggestionState::Sampling;
+                }
+            }
+            SuggestionState::Sampling => {
+                if let Some((_, state_rx)) = &self.task {
+                    let state = state_rx.borrow().clone();
+                    if state != SuggestionState::Sampling {
+                        self.task = None;
+                        self.state = state;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn cancel(&mut self) {
+        if let Some((task, _)) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Collects the first [SAMPLE_A_SCANS] of `response` and computes the
+/// suggestions on a blocking thread.
+async fn sample(response: requests::MScanResponse) -> anyhow::Result<Suggestions> {
+    let mut m_scan = response
+        .data
+        .subscribe()
+        .ok_or_else(|| anyhow::anyhow!("The input is not available anymore"))?;
+
+    let mut collected: Option<DataMatrix> = None;
+
+    while collected.as_ref().map_or(0, |c| c.ncols()) < SAMPLE_A_SCANS {
+        let chunk = match m_scan.recv().await {
+            Ok(chunk) => chunk,
+            Err(RecvError::Closed) => break,
+            Err(e) => Err(e)?,
+        };
+
+        collected = Some(match collected.take() {
+            Some(collected) => collected
+                .concat_horizontally(&chunk)
+                .ok_or_else(|| anyhow::anyhow!("Chunks do not match each other"))?,
+            None => chunk.as_ref().clone(),
+        });
+    }
+    drop(m_scan);
+
+    let collected = collected.ok_or_else(|| anyhow::anyhow!("The input is empty"))?;
+
+    let suggestions = tokio::task::spawn_blocking(move || {
+        let ncols = collected.ncols().min(SAMPLE_A_SCANS);
+        let DataMatrix::F32(sample) = collected.cast_rescale_par(DataType::F32) else {
+            unreachable!("Cast to f32");
+        };
+        suggest(sample.columns(0, ncols))
+    })
+    .await?;
+
+    Ok(suggestions)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pipeline::random::Lcg;
+
+    use super::*;
+
+    /// Fixture: a dark lumen above bright tissue at row 40, with normally
+    /// distributed noise of standard deviation `sigma`.
+    fn tissue(sigma: f32) -> DMatrix<f32> {
+        let mut rng = Lcg::new(7);
+        DMatrix::from_fn(128, 96, |r, _| {
+            let value = if r < 40 { 0.1 } else { 0.7 };
+            // Box-Muller transform, with u above 0 for the logarithm
+            let (u, v) = (1.0 - rng.uniform(), rng.uniform());
+            let normal = (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos();
+            value + sigma * normal as f32
+        })
+    }
+
+    /// Fixture: speckle with grains of `size` pixels, made of blocks of
+    /// constant random brightness.
+    fn speckle(size: Vector2<usize>) -> DMatrix<f32> {
+        let mut rng = Lcg::new(3);
+        let blocks = DMatrix::from_fn(128 / size.x + 1, 128 / size.y + 1, |_, _| {
+            0.1 + 0.8 * rng.uniform() as f32
+        });
+        DMatrix::from_fn(128, 128, |r, c| blocks[(r / size.x, c / size.y)])
+    }
+
+    #[test]
+    fn noise_level() {
+        for sigma in [0.01, 0.05, 0.1] {
+            let estimate = noise_sigma(tissue(sigma).as_view());
+            assert!(
+                (estimate - sigma).abs() < 0.15 * sigma,
+                "{estimate} for {sigma}"
+            );
+        }
+
+        assert!(noise_sigma(DMatrix::from_element(10, 10, 0.3f32).as_view()) < 1e-6);
+        assert_eq!(noise_sigma(DMatrix::<f32>::zeros(1, 10).as_view()), 0.0);
+    }
+
+    #[test]
+    fn grains() {
+        let fine = grain_size(tissue(0.05).as_view());
+        assert!(fine.x < 2.0 && fine.y < 2.0, "{fine}");
+
+        let coarse = grain_size(speckle(Vector2::new(8, 3)).as_view());
+        assert!((5.0..11.0).contains(&coarse.x), "{coarse}");
+        assert!((2.0..5.0).contains(&coarse.y), "{coarse}");
+
+        assert_eq!(median_window(1.0), 3);
+        assert_eq!(median_window(4.2), 5);
+        assert_eq!(median_window(8.0), 9);
+        assert_eq!(median_window(100.0), 31);
+    }
+
+    #[test]
+    fn suggestions() {
+        let quiet = suggest(tissue(0.01).as_view());
+        let noisy = suggest(tissue(0.1).as_view());
+
+        let sigma = |s: &Suggestions| s.value(SweepParameter::GaussSigma).unwrap();
+        assert!(sigma(&noisy) > sigma(&quiet), "{noisy:?} {quiet:?}");
+        assert_eq!(sigma(&quiet), 0.5);
+
+        let [low, median, bright, high] = noisy.percentiles;
+        assert!(low < median && median < bright && bright <= high);
+        assert!((median - 0.7).abs() < 0.1, "{median}");
+
+        let threshold = quiet.value(SweepParameter::PrewittThreshold).unwrap();
+        assert!((0.0..=1.0).contains(&threshold));
+        assert!((prewitt_threshold(0.2, 0.6) - 0.6).abs() < 1e-6);
+
+        let coarse = suggest(speckle(Vector2::new(8, 3)).as_view());
+        assert_eq!(coarse.value(SweepParameter::MedianRows), Some(9.0));
+        assert_eq!(coarse.value(SweepParameter::MedianColumns), Some(3.0));
+    }
+
+    #[tokio::test]
+    async fn samples_first_a_scans() {
+        let (res, tx) = requests::StreamedResponse::new(64);
+        for _ in 0..(SAMPLE_A_SCANS / 32 + 2) {
+            tx.send(std::sync::Arc::new(
+                tissue(0.05).columns(0, 32).into_owned().into(),
+            ));
+        }
+        tx.finish();
+
+        let suggestions = sample(requests::MScanResponse {
+            data: res,
+            a_scan_count: SAMPLE_A_SCANS + 64,
+            a_scan_samples: 128,
+        })
+        .await
+        .unwrap();
+        assert!((suggestions.noise_sigma - 0.05).abs() < 0.01);
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    ops::{Range, RangeInclusive},
+    sync::Arc,
+};
+
+use anyhow::anyhow;
+use futures::FutureExt;
+use tokio::{sync::watch, task::JoinHandle};
+
+use crate::{
+    node_graph::{InputId, OutputId},
+    queue_channel::error::RecvError,
+};
+
+use super::{
+    chunking::{ChunkLimits, ChunkedSender},
+    execution::{ConnectionHandle, EphemeralRunner, TaskInput, TaskOutput},
+    nodes::DynPipelineNode,
+    requests,
+    types::DataMatrix,
+    PipelineExecutor,
+};
+
+/// Upper bound for the number of node copies in a single sweep.
+pub const MAX_SWEEP_STEPS: usize = 16;
+
+/// The values of a sweep from the start to the end of `range` (inclusive) in
+/// steps of `step`. Yields at most [MAX_SWEEP_STEPS] values. For integer
+/// settings, the values are rounded and duplicates are skipped.
+pub fn sweep_values(range: RangeInclusive<f64>, step: f64, integer: bool) -> Vec<f64> {
+    let (start, end) = (*range.start(), *range.end());
+
+    if !(step > 0.0 && start.is_finite() && end.is_finite() && start <= end) {
+        return Vec::new();
+    }
+
+    let mut values = Vec::new();
+
+    for i in 0.. {
+        let value = start + step * i as f64;
+
+        // Tolerate rounding errors of the last step
+        if value > end + step * 1e-6 || values.len() == MAX_SWEEP_STEPS {
+            break;
+        }
+
+        let value = if integer { value.round() } else { value };
+
+        if values.last() != Some(&value) {
+            values.push(value);
+        }
+    }
+
+    values
+}
+
+/// State of the preview of one node copy.
+#[derive(Debug, Clone)]
+pub enum PreviewState {
+    /// Still receiving data. Contains the fraction of received A scans.
+    Running(f32),
+    Done(Arc<DataMatrix>),
+    Failed(String),
+}
+
+pub struct SweepPreview {
+    /// The value of the swept setting in this copy.
+    pub value: f64,
+    pub state: watch::Receiver<PreviewState>,
+}
+
+/// Runs temporary copies of a node, each with a different value of one setting,
+/// on a preview slice of the node's input, which contains only its first A
+/// scans. The copies are not part of the pipeline and only the preview slices
+/// are kept in memory. Dropping the sweep stops all of its tasks.
+pub struct ParameterSweep {
+    previews: Vec<SweepPreview>,
+    input_error: watch::Receiver<Option<String>>,
+    /// The copies only keep notifiers of their input. Once the last handle
+    /// is dropped, they would see it as invalidated and restart.
+    _preview_slice: ConnectionHandle,
+    runners: Vec<EphemeralRunner>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl ParameterSweep {
+    /// Starts a sweep. `copies` contains the node copies together with their
+    /// value of the swept setting. Input `input_id` of every copy gets
+    /// connected to the A scans `a_scans` of `input`, output `output_id` is
+    /// collected into the previews.
+    pub fn start(
+        executor: &PipelineExecutor,
+        mut input: ConnectionHandle,
+        input_id: InputId,
+        output_id: OutputId,
+        copies: Vec<(f64, Box<dyn DynPipelineNode>)>,
+        a_scans: Range<usize>,
+    ) -> Self {
+        let (preview_slice, mut preview_out) = ConnectionHandle::new::<requests::MScan>();
+        let mut preview_in = TaskInput::default();
+        preview_in.connect(&mut input);
+
+        let (input_error_tx, input_error) = watch::channel(None);
+
+        let mut tasks = vec![tokio::spawn(async move {
+            let result = serve_preview_slice(&mut preview_in, &mut preview_out, a_scans).await;
+
+            if let Err(e) = result {
+                input_error_tx.send_replace(Some(e.to_string()));
+
+                // Keep the output open. Otherwise, the copies lose their
+                // input and keep retrying until the sweep is dropped.
+                futures::future::pending::<()>().await;
+            }
+        })];
+
+        let mut runners = Vec::with_capacity(copies.len());
+        let mut previews = Vec::with_capacity(copies.len());
+
+        for (value, mut node) in copies {
+            let mut runner = executor.spawn_ephemeral(node.as_mut());
+            runner.connect_input(input_id, preview_slice.clone());
+
+            let (state_tx, state) = watch::channel(PreviewState::Running(0.0));
+
+            let mut m_scan_in = TaskInput::default();
+            let connected = runner
+                .get_output(output_id)
+                .is_some_and(|mut output| m_scan_in.connect(&mut output));
+
+            if connected {
+                tasks.push(tokio::spawn(async move {
+                    if let Err(e) = collect_preview(&mut m_scan_in, &state_tx).await {
+                        state_tx.send_replace(PreviewState::Failed(e.to_string()));
+                    }
+                }));
+            } else {
+                state_tx.send_replace(PreviewState::Failed(
+                    "Node has no M scan output".to_string(),
+                ));
+            }
+
+            runners.push(runner);
+            previews.push(SweepPreview { value, state });
+        }
+
+        Self {
+            previews,
+            input_error,
+            _preview_slice: preview_slice,
+            runners,
+            tasks,
+        }
+    }
+
+    pub fn previews(&self) -> &[SweepPreview] {
+        &self.previews
+    }
+
+    /// Error of the preview slice, if the input of the node failed.
+    pub fn input_error(&self) -> Option<String> {
+        self.input_error.borrow().clone()
+    }
+
+    /// Whether every preview is either done or failed.
+    pub fn is_finished(&self) -> bool {
+        self.previews
+            .iter()
+            .all(|p| !matches!(*p.state.borrow(), PreviewState::Running(_)))
+    }
+}
+
+impl Drop for ParameterSweep {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        // Dropping the runners stops the node copies
+        self.runners.clear();
+    }
+}
+
+/// Serves the A scans in `a_scans` of `input` on `output`.
+pub(super) async fn serve_preview_slice(
+    input: &mut TaskInput<requests::MScan>,
+    output: &mut TaskOutput<requests::MScan>,
+    a_scans: Range<usize>,
+) -> anyhow::Result<()> {
+    loop {
+        let _req = output.receive().await;
+
+        let Some(m_scan_res) = input.request(requests::MScan).await else {
+            if input.is_connected() {
+                // The node got replaced, try again
+                continue;
+            }
+            return Err(anyhow!("The input of the node is not available"));
+        };
+
+        let Some(mut m_scan) = m_scan_res.data.subscribe() else {
+            continue;
+        };
+
+        let (res, tx) = requests::StreamedResponse::with_default_capacity();
+        let mut tx = ChunkedSender::new(tx, ChunkLimits::current());
+
+        output.respond(requests::MScanResponse {
+            data: res,
+            a_scan_count: m_scan_res
+                .a_scan_count
+                .min(a_scans.end)
+                .saturating_sub(a_scans.start),
+            a_scan_samples: m_scan_res.a_scan_samples,
+        });
+        output.receive().now_or_never();
+
+        // Index of the first A scan of the next chunk
+        let mut position = 0;
+
+        while position < a_scans.end {
+            let chunk = match m_scan.recv().await {
+                Ok(chunk) => chunk,
+                Err(RecvError::Closed) => break,
+                Err(e) => Err(e)?,
+            };
+
+            let chunk_start = position;
+            position += chunk.ncols();
+
+            let start = a_scans.start.max(chunk_start);
+            let end = a_scans.end.min(position);
+            if start >= end {
+                continue;
+            }
+
+            let chunk = if end - start < chunk.ncols() {
+                Arc::new(chunk.columns(start - chunk_start, end - start))
+            } else {
+                chunk
+            };
+
+            tx.send(chunk);
+        }
+
+        tx.finish();
+    }
+}
+
+/// Requests the output of a node copy and collects it into one matrix.
+pub(super) async fn collect_preview(
+    input: &mut TaskInput<requests::MScan>,
+    state: &watch::Sender<PreviewState>,
+) -> anyhow::Result<()> {
+    let m_scan_res = input
+        .request(requests::MScan)
+        .await
+        .ok_or_else(|| anyhow!("Node stopped without a result"))?;
+
+    let mut m_scan = m_scan_res
+        .data
+        .subscribe()
+        .ok_or_else(|| anyhow!("Result got lost"))?;
+
+    let mut collected: Option<DataMatrix> = None;
+
+    loop {
+        let chunk = match m_scan.recv().await {
+            Ok(chunk) => chunk,
+            Err(RecvError::Closed) => break,
+            Err(e) => Err(e)?,
+        };
+
+        let merged = match collected.take() {
+            Some(collected) => collected
+                .concat_horizontally(&chunk)
+                .ok_or_else(|| anyhow!("Chunks do not match each other"))?,
+            None => chunk.as_ref().clone(),
+        };
+        let collected = collected.insert(merged);
+
+        state.send_replace(PreviewState::Running(
+            collected.ncols() as f32 / m_scan_res.a_scan_count.max(1) as f32,
+        ));
+    }
+
+    let collected = collected.ok_or_else(|| anyhow!("Result is empty"))?;
+    state.send_replace(PreviewState::Done(Arc::new(collected)));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use nalgebra::DMatrix;
+
+    use crate::pipeline::nodes::filter::{self, SweepParameter};
+
+    use super::*;
+
+    #[test]
+    fn values_of_range() {
+        assert_eq!(sweep_values(0.5..=2.0, 0.5, false), [0.5, 1.0, 1.5, 2.0]);
+        assert_eq!(sweep_values(1.0..=3.0, 0.75, true), [1.0, 2.0, 3.0]);
+        assert_eq!(sweep_values(0.0..=1000.0, 1.0, true).len(), MAX_SWEEP_STEPS);
+        assert!(sweep_values(2.0..=1.0, 0.5, false).is_empty());
+        assert!(sweep_values(1.0..=2.0, 0.0, false).is_empty());
+    }
+
+    /// Serves an M scan with `a_scan_count` A scans in chunks of 4 A scans.
+    fn spawn_m_scan_producer(a_scan_count: usize) -> (ConnectionHandle, JoinHandle<()>) {
+        let (handle, mut output) = ConnectionHandle::new::<requests::MScan>();
+
+        let producer = tokio::spawn(async move {
+            loop {
+                let _req = output.receive().await;
+
+                let (res, tx) = requests::StreamedResponse::new(64);
+                output.respond(requests::MScanResponse {
+                    data: res,
+                    a_scan_count,
+                    a_scan_samples: 8,
+                });
+
+                for start in (0..a_scan_count).step_by(4) {
+                    let ncols = (a_scan_count - start).min(4);
+                    let chunk = DMatrix::from_fn(8, ncols, |r, c| ((r + start + c) * 10) as u8);
+                    tx.send(Arc::new(chunk.into()));
+                }
+                tx.finish();
+            }
+        });
+
+        (handle, producer)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sweep_previews_and_cleanup() {
+        let (input, _producer) = spawn_m_scan_producer(30);
+
+        let node = filter::Node::gaussian();
+        let copies = sweep_values(1.0..=2.0, 0.5, false)
+            .into_iter()
+            .map(|value| {
+                let mut copy = node.clone();
+                copy.set_parameter(SweepParameter::GaussSigma, value);
+                (value, Box::new(copy) as Box<dyn DynPipelineNode>)
+            })
+            .collect();
+
+        let executor = PipelineExecutor::new();
+        let sweep = ParameterSweep::start(
+            &executor,
+            input,
+            filter::InputId::MScan.into(),
+            filter::OutputId::Filtered.into(),
+            copies,
+            0..10,
+        );
+
+        assert_eq!(sweep.runners.len(), 3);
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !sweep.is_finished() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Sweep should finish");
+
+        for preview in sweep.previews() {
+            match &*preview.state.borrow() {
+                PreviewState::Done(m_scan) => {
+                    assert_eq!((m_scan.nrows(), m_scan.ncols()), (8, 10));
+                }
+                state => panic!("Unexpected state {:?}", state),
+            }
+        }
+
+        // Dropping the sweep stops the node copies
+        let copy_output = sweep.runners[0]
+            .get_output(filter::OutputId::Filtered.into())
+            .unwrap();
+        let mut notifier = copy_output.get_invalidation_notifier();
+        drop(sweep);
+
+        let stopped = tokio::time::timeout(Duration::from_secs(10), async {
+            // Returns false, once the output does not exist anymore
+            while notifier.on_invalidate().await {}
+        })
+        .await;
+
+        assert!(stopped.is_ok());
+    }
+}
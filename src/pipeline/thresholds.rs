@@ -0,0 +1,182 @@
+//! Settings of nodes, that are thresholds on the values of an M scan, so they
+//! can be picked on its histogram, see [crate::view::views::histogram].
+//!
+//! Nodes expose them with [super::nodes::PipelineNode::threshold_target]. The
+//! view asks the app for a [ThresholdEdit], which [apply]s it to the node in
+//! the pipeline, like every other edit, so the node reprocesses.
+
+use crate::node_graph::NodeId;
+
+use super::Pipeline;
+
+/// How the value of a [Threshold] relates to the values of the M scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThresholdScale {
+    /// A value of the M scan, rescaled to the range from 0 to 1.
+    Value,
+    /// A percentile of the values of the M scan, from 0 to 100.
+    Percentile,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Threshold {
+    pub name: &'static str,
+    pub value: f32,
+    pub scale: ThresholdScale,
+}
+
+/// Nodes, whose settings contain thresholds on the values of an M scan.
+pub trait ThresholdTarget {
+    /// Thresholds of the current settings. Empty, if the node uses none with
+    /// its current settings.
+    fn thresholds(&self) -> Vec<Threshold>;
+
+    /// Sets the `index`th of [Self::thresholds], clamping `value` to its
+    /// range.
+    fn set_threshold(&mut self, index: usize, value: f32);
+
+    /// Indices of a low and a high threshold in [Self::thresholds], that
+    /// are set together, like the range of a normalization.
+    fn window(&self) -> Option<[usize; 2]> {
+        None
+    }
+}
+
+/// A threshold picked in a view, to set on a node of the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThresholdEdit {
+    pub node_id: NodeId,
+    /// Index in [ThresholdTarget::thresholds].
+    pub index: usize,
+    pub value: f32,
+}
+
+/// A node of [targets].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetNode {
+    pub node_id: NodeId,
+    /// Whether the node processes the M scan, whose histogram is shown.
+    pub downstream: bool,
+}
+
+/// Nodes with thresholds, the ones processing the output of `m_scan` first.
+pub fn targets(pipeline: &Pipeline, m_scan: NodeId) -> Vec<TargetNode> {
+    let mut targets = pipeline
+        .nodes
+        .iter()
+        .filter(|(_, node)| {
+            node.threshold_target()
+                .is_some_and(|target| !target.thresholds().is_empty())
+        })
+        .map(|(node_id, _)| TargetNode {
+            node_id: *node_id,
+            downstream: *node_id != m_scan && pipeline.upstream(*node_id).contains(&m_scan),
+        })
+        .collect::<Vec<_>>();
+
+    targets.sort_by_key(|target| (!target.downstream, target.node_id));
+    targets
+}
+
+/// Sets the threshold of `edit` on its node. Returns whether the node has
+/// this threshold.
+pub fn apply(pipeline: &mut Pipeline, edit: ThresholdEdit) -> bool {
+    let Some(target) = pipeline
+        .nodes
+        .get_mut(&edit.node_id)
+        .and_then(|node| node.threshold_target_mut())
+    else {
+        return false;
+    };
+    if edit.index >= target.thresholds().len() {
+        return false;
+    }
+
+    target.set_threshold(edit.index, edit.value);
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::pipeline::nodes::filter::{self, FilterType};
+
+    use super::*;
+
+    fn pipeline() -> Pipeline {
+        let filter = |filter_type: &str, input: u32| {
+            json!({
+                "type": "filter",
+                "filter_type": filter_type,
+                "input": {
+                    "value": null,
+                    "connection": { "node_id": input, "output_id": 0, "type_id": 2 },
+                },
+            })
+        };
+
+        serde_json::from_value(json!({
+            "nodes": {
+                "1": filter("Gaussian", 0),
+                "2": filter("Prewitt", 1),
+                "3": filter("GlobalNormalize", 0),
+                "4": filter("Median", 1),
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn downstream_targets_first() {
+        let pipeline = pipeline();
+
+        let targets = targets(&pipeline, 1.into());
+        assert_eq!(
+            targets,
+            [
+                TargetNode {
+                    node_id: 2.into(),
+                    downstream: true,
+                },
+                TargetNode {
+                    node_id: 3.into(),
+                    downstream: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_to_node() {
+        let mut pipeline = pipeline();
+        let before = pipeline[3.into()].clone_boxed();
+
+        let edit = |node_id: usize, index, value| ThresholdEdit {
+            node_id: node_id.into(),
+            index,
+            value,
+        };
+        assert!(apply(&mut pipeline, edit(3, 1, 150.0)));
+        assert!(apply(&mut pipeline, edit(2, 0, 0.3)));
+        // No such threshold
+        assert!(!apply(&mut pipeline, edit(2, 1, 0.3)));
+        assert!(!apply(&mut pipeline, edit(4, 0, 0.3)));
+
+        let node = pipeline[3.into()]
+            .as_any()
+            .downcast_ref::<filter::Node>()
+            .unwrap();
+        assert_eq!(node.filter_type, FilterType::GlobalNormalize);
+        assert_eq!(node.global_normalize_settings.high, 100.0);
+        assert_eq!(node.window(), Some([0, 1]));
+        // The executor picks the edit up like any other
+        assert!(pipeline[3.into()].changed(before.as_ref()));
+
+        let node = pipeline[2.into()]
+            .as_any()
+            .downcast_ref::<filter::Node>()
+            .unwrap();
+        assert_eq!(node.prewitt_settings.threshold, 0.3);
+    }
+}
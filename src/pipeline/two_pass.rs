@@ -0,0 +1,276 @@
+//! Streaming nodes, that need statistics of their whole input before they can
+//! process any of it, like a normalization against the whole pullback.
+//!
+//! Such a node requests its input twice per run. The first pass accumulates
+//! [Statistics], whose size does not depend on the length of the scan. The
+//! second pass streams the input again and transforms it chunk by chunk, so
+//! the pullback is never held in memory as a whole. This needs the producer to
+//! serve the same data again, see [super::Replay]. Otherwise, nodes fall back
+//! to the statistics of every chunk on its own and report [Passes::Single].
+
+use crate::queue_channel::error::RecvError;
+
+use super::{execution::TaskInput, requests, types::DataMatrix};
+
+/// Statistics of a whole M scan, accumulated chunk by chunk in the first pass.
+pub trait Statistics: Send + 'static {
+    fn accumulate(&mut self, chunk: &DataMatrix);
+}
+
+/// How a two-pass node processed its input in its last run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Passes {
+    /// Nothing ran yet.
+    #[default]
+    None,
+    Two,
+    /// The input cannot be requested twice, so every chunk was processed with
+    /// its own statistics. Contains the reason.
+    Single(&'static str),
+}
+
+/// Outcome of [statistics_pass].
+pub enum StatisticsPass<S> {
+    /// The statistics of the whole input and the response to stream the
+    /// second pass from.
+    Done(S, requests::MScanResponse),
+    /// The input cannot be requested twice. Contains the reason.
+    Unavailable(&'static str),
+    /// The input got invalidated in between. The run starts again.
+    Aborted,
+}
+
+/// Accumulates `statistics` over all of `first`, the response of `input`
+/// received in `epoch`, and requests it again for the second pass. `progress`
+/// is called with the number of A scans done.
+pub async fn statistics_pass<S: Statistics>(
+    input: &mut TaskInput<requests::MScan>,
+    first: &requests::MScanResponse,
+    epoch: Option<u64>,
+    mut statistics: S,
+    mut progress: impl FnMut(usize),
+) -> anyhow::Result<StatisticsPass<S>> {
+    if !input.can_replay() {
+        return Ok(StatisticsPass::Unavailable(
+            "The input cannot be requested twice, like the output of an external command",
+        ));
+    }
+    let (Some(epoch), Some(mut m_scan)) = (epoch, first.data.subscribe()) else {
+        return Ok(StatisticsPass::Aborted);
+    };
+
+    let mut a_scans = 0;
+    loop {
+        let chunk = match m_scan.recv().await {
+            Ok(chunk) => chunk,
+            Err(RecvError::Closed) => break,
+            Err(e) => Err(e)?,
+        };
+        a_scans += chunk.ncols();
+
+        statistics = tokio::task::spawn_blocking(move || {
+            statistics.accumulate(&chunk);
+            statistics
+        })
+        .await?;
+        progress(a_scans);
+    }
+
+    Ok(match input.replay(requests::MScan, epoch).await {
+        Some(second) => StatisticsPass::Done(statistics, second),
+        None => StatisticsPass::Aborted,
+    })
+}
+
+// MARK: Histogram
+
+/// Histogram with a fixed number of bins, whose range grows with the values
+/// added, so it needs no range up front. Quantiles are accurate to the width
+/// of one bin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    bins: Vec<u64>,
+    /// Lower bound of the first bin.
+    start: f64,
+    /// Width of every bin. Zero, while all values added are equal.
+    width: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new(bins: usize) -> Self {
+        Self {
+            bins: vec![0; bins.max(2)],
+            start: 0.0,
+            width: 0.0,
+            count: 0,
+        }
+    }
+
+    /// Adds every finite value.
+    pub fn extend(&mut self, values: impl Iterator<Item = f64> + Clone) {
+        let finite = values.filter(|value| value.is_finite());
+
+        let Some((min, max)) = finite.clone().fold(None, |range, value| match range {
+            None => Some((value, value)),
+            Some((min, max)) => Some((value.min(min), value.max(max))),
+        }) else {
+            return;
+        };
+
+        self.cover(min, max);
+        for value in finite {
+            let bin = self.bin(value);
+            self.bins[bin] += 1;
+            self.count += 1;
+        }
+    }
+
+    /// The value, below which the fraction `q` of the values lies. [None], if
+    /// nothing was added.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        if self.width == 0.0 {
+            return Some(self.start);
+        }
+
+        let target = q.clamp(0.0, 1.0) * self.count as f64;
+        let mut below = 0.0;
+        for (bin, &count) in self.bins.iter().enumerate() {
+            let count = count as f64;
+            if count > 0.0 && below + count >= target {
+                let fraction = (target - below) / count;
+                return Some(self.start + (bin as f64 + fraction) * self.width);
+            }
+            below += count;
+        }
+
+        Some(self.start + self.bins.len() as f64 * self.width)
+    }
+
+    fn bin(&self, value: f64) -> usize {
+        match self.width > 0.0 {
+            true => {
+                (((value - self.start) / self.width).max(0.0) as usize).min(self.bins.len() - 1)
+            }
+            false => 0,
+        }
+    }
+
+    fn end(&self) -> f64 {
+        self.start + self.bins.len() as f64 * self.width
+    }
+
+    /// Grows the range, so it includes `min` to `max`, merging bins.
+    fn cover(&mut self, min: f64, max: f64) {
+        let len = self.bins.len();
+
+        if self.count == 0 {
+            self.start = min;
+            self.width = (max - min) / len as f64;
+            return;
+        }
+
+        if self.width == 0.0 {
+            if min == self.start && max == self.start {
+                return;
+            }
+            // All values so far are equal, so they move to a single bin
+            let value = self.start;
+            self.start = min.min(value);
+            self.width = (max.max(value) - self.start) / len as f64;
+            self.bins.fill(0);
+            let bin = self.bin(value);
+            self.bins[bin] = self.count;
+            return;
+        }
+
+        // Shift the bins by whole bins, if the values fit into the bins left
+        // empty at the end. Otherwise, doubling the width merges pairs of bins
+        loop {
+            let shift = match min < self.start {
+                true => ((self.start - min) / self.width).ceil() as usize,
+                false => 0,
+            };
+            let used = self
+                .bins
+                .iter()
+                .rposition(|&count| count > 0)
+                .map_or(0, |bin| bin + 1);
+
+            if shift + used <= len && max <= self.end() - shift as f64 * self.width {
+                self.bins.rotate_right(shift);
+                self.start -= shift as f64 * self.width;
+                return;
+            }
+
+            for bin in 0..len {
+                let count = self.bins[bin];
+                self.bins[bin] = 0;
+                self.bins[bin / 2] += count;
+            }
+            self.width *= 2.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The value at `q` of sorted `values`.
+    fn exact_quantile(values: &[f64], q: f64) -> f64 {
+        let mut values = values.to_vec();
+        values.sort_by(f64::total_cmp);
+        values[((q * values.len() as f64) as usize).min(values.len() - 1)]
+    }
+
+    #[test]
+    fn histogram_quantiles_within_one_bin() {
+        let mut histogram = Histogram::new(1000);
+        let mut values = Vec::new();
+
+        // The range grows in both directions over the chunks
+        for chunk in 0..10 {
+            let chunk = (0..500)
+                .map(|i| {
+                    ((i * 7919 + chunk * 31) % 500) as f64 * (chunk + 1) as f64
+                        - 100.0 * chunk as f64
+                })
+                .collect::<Vec<_>>();
+            histogram.extend(chunk.iter().copied());
+            values.extend(chunk);
+        }
+        histogram.extend([f64::NAN, f64::INFINITY].into_iter());
+
+        let (min, max) = (exact_quantile(&values, 0.0), exact_quantile(&values, 1.0));
+        assert!(histogram.width <= 4.0 * (max - min) / 1000.0);
+
+        for q in [0.0, 0.01, 0.25, 0.5, 0.9, 0.99, 1.0] {
+            let quantile = histogram.quantile(q).unwrap();
+            let exact = exact_quantile(&values, q);
+            assert!(
+                (quantile - exact).abs() <= histogram.width,
+                "q {}: {} != {}",
+                q,
+                quantile,
+                exact
+            );
+        }
+    }
+
+    #[test]
+    fn histogram_of_equal_values() {
+        let mut histogram = Histogram::new(16);
+        assert_eq!(histogram.quantile(0.5), None);
+
+        histogram.extend([3.0; 10].into_iter());
+        assert_eq!(histogram.quantile(0.5), Some(3.0));
+
+        histogram.extend([1.0; 10].into_iter());
+        assert!((histogram.quantile(0.25).unwrap() - 1.0).abs() <= 2.0 / 16.0);
+        assert!((histogram.quantile(0.75).unwrap() - 3.0).abs() <= 2.0 / 16.0);
+    }
+}
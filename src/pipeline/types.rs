@@ -1,15 +1,58 @@
-use std::mem;
+use std::{borrow::Cow, mem, sync::Arc};
 
-use nalgebra::{DMatrix, DMatrixView, DVector, Scalar, Vector2, Vector3};
+use anyhow::bail;
+use nalgebra::{DMatrix, DMatrixView, DVector, DVectorView, Scalar, Vector2, Vector3};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use simba::scalar::SubsetOf;
 
+use crate::units::LengthUnit;
+
+/// Version of the serialized analysis types, like [BScanDiameter]. Increased,
+/// when a field is removed or changes its meaning. New fields get a serde
+/// default instead, so data written before can still be read.
+pub const SCHEMA_VERSION: u32 = 1;
+
 /// A 3D Mesh of a lumen that can be send to the GPU for rendering.
+///
+/// Meshes are streamed in chunks. A chunk may be stitched to the previous one
+/// by referencing its trailing vertices, see [Self::stitched_vertices].
 #[derive(Debug, Clone)]
 pub struct LumenMesh {
     pub vertices: Vec<LumenVertex>,
+    /// Triangles. Index `i` refers to vertex `i` of
+    /// [Self::referenced_vertices].
     pub indices: Vec<u32>,
+    /// Number of trailing vertices of the previous chunk, that come before
+    /// [Self::vertices] in the index space of this chunk. Zero for chunks
+    /// that only reference their own vertices.
+    pub stitched_vertices: u32,
+    /// Index of the first A scan after the B scans of this chunk.
+    pub a_scan_end: usize,
+}
+
+impl LumenMesh {
+    /// All vertices the indices of this chunk refer to: The stitched vertices
+    /// of the `previous` chunk followed by [Self::vertices].
+    ///
+    /// Returns [None] if the previous chunk does not have enough vertices.
+    pub fn referenced_vertices<'a>(
+        &'a self,
+        previous: Option<&LumenMesh>,
+    ) -> Option<Cow<'a, [LumenVertex]>> {
+        let stitched = self.stitched_vertices as usize;
+
+        if stitched == 0 {
+            return Some(Cow::Borrowed(&self.vertices));
+        }
+
+        let previous = &previous?.vertices;
+        let start = previous.len().checked_sub(stitched)?;
+
+        Some(Cow::Owned(
+            [&previous[start..], &self.vertices[..]].concat(),
+        ))
+    }
 }
 
 /// One vertex of a [LumenMesh].
@@ -42,19 +85,111 @@ impl LumenVertex {
 }
 
 /// Structure containing information about the diameters in one BScan.
+///
+/// Serialized with [SCHEMA_VERSION] and the unit of the diameters, see
+/// [BScanDiameterRecord].
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(into = "BScanDiameterRecord", try_from = "BScanDiameterRecord")]
 pub struct BScanDiameter {
+    /// First A scan of the B scan.
     pub b_scan_start: usize,
+    /// A scan after the B scan.
     pub b_scan_end: usize,
 
+    /// Diameters in millimetres.
     pub min: f32,
     pub max: f32,
     pub mean: f32,
 
+    /// Ends of the diameters in samples of the A scans, relative to the
+    /// center of the catheter.
     pub min_points: [Vector2<f32>; 2],
     pub max_points: [Vector2<f32>; 2],
 }
 
+impl BScanDiameter {
+    /// Whether all diameters and points are finite numbers.
+    pub fn is_finite(&self) -> bool {
+        [self.min, self.max, self.mean]
+            .iter()
+            .all(|v| v.is_finite())
+            && self
+                .min_points
+                .iter()
+                .chain(&self.max_points)
+                .all(|p| p.iter().all(|v| v.is_finite()))
+    }
+}
+
+/// Serialized form of [BScanDiameter]. Data written before versioning has no
+/// version and no unit, which default to 0 and millimetres.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BScanDiameterRecord {
+    #[serde(default)]
+    schema_version: u32,
+    b_scan_start: usize,
+    b_scan_end: usize,
+
+    /// Unit of [Self::min], [Self::max] and [Self::mean].
+    #[serde(default)]
+    unit: LengthUnit,
+    min: f32,
+    max: f32,
+    mean: f32,
+
+    min_points: [Vector2<f32>; 2],
+    max_points: [Vector2<f32>; 2],
+}
+
+impl From<BScanDiameter> for BScanDiameterRecord {
+    fn from(diameter: BScanDiameter) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            b_scan_start: diameter.b_scan_start,
+            b_scan_end: diameter.b_scan_end,
+            unit: LengthUnit::Millimetre,
+            min: diameter.min,
+            max: diameter.max,
+            mean: diameter.mean,
+            min_points: diameter.min_points,
+            max_points: diameter.max_points,
+        }
+    }
+}
+
+impl TryFrom<BScanDiameterRecord> for BScanDiameter {
+    type Error = anyhow::Error;
+
+    fn try_from(record: BScanDiameterRecord) -> anyhow::Result<Self> {
+        if record.schema_version > SCHEMA_VERSION {
+            bail!(
+                "Schema version {} is newer than the supported version {}",
+                record.schema_version,
+                SCHEMA_VERSION
+            );
+        }
+        if record.b_scan_start > record.b_scan_end {
+            bail!(
+                "B scan starts at A scan {} after its end {}",
+                record.b_scan_start,
+                record.b_scan_end
+            );
+        }
+
+        let mm = |value: f32| record.unit.to_mm(value as f64) as f32;
+
+        Ok(Self {
+            b_scan_start: record.b_scan_start,
+            b_scan_end: record.b_scan_end,
+            min: mm(record.min),
+            max: mm(record.max),
+            mean: mm(record.mean),
+            min_points: record.min_points,
+            max_points: record.max_points,
+        })
+    }
+}
+
 // MARK: DataType
 
 /// The data type of every value in a set of data.
@@ -97,6 +232,33 @@ impl DataType {
     }
 }
 
+/// What a node does with the [DataType] of the M scans it receives, see
+/// [super::nodes::PipelineNode::type_handling].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeHandling {
+    /// The data is used in the type it is received in.
+    AsIs,
+    /// Cast to [DataType::F32], keeping the values, see [DataMatrix::cast_par].
+    PromotesToF32,
+    /// Cast to another type, mapping the range of the values onto the range
+    /// of that type, see [DataMatrix::cast_rescale_par].
+    Rescales(DataType),
+}
+
+impl TypeHandling {
+    /// The type data of `data_type` is converted to. [None], if it is used
+    /// as is.
+    pub fn target(self, data_type: DataType) -> Option<DataType> {
+        let target = match self {
+            TypeHandling::AsIs => return None,
+            TypeHandling::PromotesToF32 => DataType::F32,
+            TypeHandling::Rescales(target) => target,
+        };
+
+        (target != data_type).then_some(target)
+    }
+}
+
 // MARK: DataVector
 
 /// A union of [DVector] with types according to [DataType].
@@ -185,12 +347,70 @@ impl DataVector {
             DataVector::F64(data) => data.cast(),
         }
     }
+
+    /// Like [Self::cast], but reads the vector in place, so only the result
+    /// is allocated.
+    pub fn cast_ref<T>(&self) -> DVector<T>
+    where
+        T: Scalar,
+        u8: SubsetOf<T>,
+        u16: SubsetOf<T>,
+        u32: SubsetOf<T>,
+        u64: SubsetOf<T>,
+        f32: SubsetOf<T>,
+        f64: SubsetOf<T>,
+    {
+        match self {
+            DataVector::U8(data) => data.map(|v| v.to_superset()),
+            DataVector::U16(data) => data.map(|v| v.to_superset()),
+            DataVector::U32(data) => data.map(|v| v.to_superset()),
+            DataVector::U64(data) => data.map(|v| v.to_superset()),
+            DataVector::F32(data) => data.map(|v| v.to_superset()),
+            DataVector::F64(data) => data.map(|v| v.to_superset()),
+        }
+    }
+
+    /// The vector as `f32`. Borrowed, if it already is one.
+    pub fn as_f32(&self) -> Cow<'_, DVector<f32>> {
+        match self {
+            DataVector::F32(data) => Cow::Borrowed(data),
+            data => Cow::Owned(data.cast_ref()),
+        }
+    }
+}
+
+/// A shared [DataVector] as `f32`, for tasks holding on to a
+/// [crate::pipeline::requests::VectorData] response. Shares the response, if
+/// it already is `f32`, and converts it once otherwise.
+#[derive(Debug, Clone)]
+pub struct F32Vector(Arc<DataVector>);
+
+impl F32Vector {
+    pub fn new(data: Arc<DataVector>) -> Self {
+        match *data {
+            DataVector::F32(_) => Self(data),
+            _ => Self(Arc::new(DataVector::F32(data.cast_ref()))),
+        }
+    }
+
+    pub fn as_view(&self) -> DVectorView<'_, f32> {
+        match &*self.0 {
+            DataVector::F32(data) => data.as_view(),
+            _ => unreachable!("F32Vector only holds f32 data"),
+        }
+    }
+}
+
+impl From<DVector<f32>> for F32Vector {
+    fn from(data: DVector<f32>) -> Self {
+        Self(Arc::new(DataVector::F32(data)))
+    }
 }
 
 // MARK: DataMatrix
 
 /// A union of [DMatrix] with types according to [DataType].
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum DataMatrix {
     U8(DMatrix<u8>),
     U16(DMatrix<u16>),
@@ -256,6 +476,146 @@ impl DataMatrix {
         }
     }
 
+    pub fn nrows(&self) -> usize {
+        match self {
+            DataMatrix::U8(data) => data.nrows(),
+            DataMatrix::U16(data) => data.nrows(),
+            DataMatrix::U32(data) => data.nrows(),
+            DataMatrix::U64(data) => data.nrows(),
+            DataMatrix::F32(data) => data.nrows(),
+            DataMatrix::F64(data) => data.nrows(),
+        }
+    }
+
+    /// Copies `ncols` columns starting at column `start` into a new matrix.
+    pub fn columns(&self, start: usize, ncols: usize) -> Self {
+        match self {
+            DataMatrix::U8(data) => DataMatrix::U8(data.columns(start, ncols).into_owned()),
+            DataMatrix::U16(data) => DataMatrix::U16(data.columns(start, ncols).into_owned()),
+            DataMatrix::U32(data) => DataMatrix::U32(data.columns(start, ncols).into_owned()),
+            DataMatrix::U64(data) => DataMatrix::U64(data.columns(start, ncols).into_owned()),
+            DataMatrix::F32(data) => DataMatrix::F32(data.columns(start, ncols).into_owned()),
+            DataMatrix::F64(data) => DataMatrix::F64(data.columns(start, ncols).into_owned()),
+        }
+    }
+
+    /// Copies the block of `shape` rows and columns starting at row and
+    /// column `start` into a new matrix.
+    pub fn block(&self, start: (usize, usize), shape: (usize, usize)) -> Self {
+        match self {
+            DataMatrix::U8(data) => DataMatrix::U8(data.view(start, shape).into_owned()),
+            DataMatrix::U16(data) => DataMatrix::U16(data.view(start, shape).into_owned()),
+            DataMatrix::U32(data) => DataMatrix::U32(data.view(start, shape).into_owned()),
+            DataMatrix::U64(data) => DataMatrix::U64(data.view(start, shape).into_owned()),
+            DataMatrix::F32(data) => DataMatrix::F32(data.view(start, shape).into_owned()),
+            DataMatrix::F64(data) => DataMatrix::F64(data.view(start, shape).into_owned()),
+        }
+    }
+
+    /// Appends the columns of `other` to the columns of `self`. Returns `None`
+    /// if the data types or the number of rows do not match.
+    pub fn concat_horizontally(&self, other: &DataMatrix) -> Option<Self> {
+        fn concat<T: Scalar + Copy>(a: &DMatrix<T>, b: &DMatrix<T>) -> DMatrix<T> {
+            // Matrices are stored column major, so the columns of b simply
+            // follow the columns of a
+            DMatrix::from_iterator(
+                a.nrows(),
+                a.ncols() + b.ncols(),
+                a.iter().chain(b.iter()).copied(),
+            )
+        }
+
+        if self.nrows() != other.nrows() {
+            return None;
+        }
+
+        match (self, other) {
+            (DataMatrix::U8(a), DataMatrix::U8(b)) => Some(DataMatrix::U8(concat(a, b))),
+            (DataMatrix::U16(a), DataMatrix::U16(b)) => Some(DataMatrix::U16(concat(a, b))),
+            (DataMatrix::U32(a), DataMatrix::U32(b)) => Some(DataMatrix::U32(concat(a, b))),
+            (DataMatrix::U64(a), DataMatrix::U64(b)) => Some(DataMatrix::U64(concat(a, b))),
+            (DataMatrix::F32(a), DataMatrix::F32(b)) => Some(DataMatrix::F32(concat(a, b))),
+            (DataMatrix::F64(a), DataMatrix::F64(b)) => Some(DataMatrix::F64(concat(a, b))),
+            _ => None,
+        }
+    }
+
+    /// Copies the columns, where `mask` is true, into a new matrix.
+    pub fn select_columns(&self, mask: &[bool]) -> Self {
+        fn select<T: Scalar + Copy>(a: &DMatrix<T>, mask: &[bool]) -> DMatrix<T> {
+            let mut data = Vec::new();
+            let mut ncols = 0;
+            for (column, _) in a.column_iter().zip(mask).filter(|(_, &keep)| keep) {
+                data.extend(column.iter().copied());
+                ncols += 1;
+            }
+
+            DMatrix::from_vec(a.nrows(), ncols, data)
+        }
+
+        match self {
+            DataMatrix::U8(a) => DataMatrix::U8(select(a, mask)),
+            DataMatrix::U16(a) => DataMatrix::U16(select(a, mask)),
+            DataMatrix::U32(a) => DataMatrix::U32(select(a, mask)),
+            DataMatrix::U64(a) => DataMatrix::U64(select(a, mask)),
+            DataMatrix::F32(a) => DataMatrix::F32(select(a, mask)),
+            DataMatrix::F64(a) => DataMatrix::F64(select(a, mask)),
+        }
+    }
+
+    /// Merges the columns of both matrices in order, taking the next column of
+    /// `other`, where `from_other` is true, and of `self` otherwise. Inverse of
+    /// [Self::select_columns]. Returns `None` if the data types or the number
+    /// of rows or columns do not match.
+    pub fn interleave_columns(&self, other: &DataMatrix, from_other: &[bool]) -> Option<Self> {
+        fn interleave<T: Scalar + Copy>(
+            a: &DMatrix<T>,
+            b: &DMatrix<T>,
+            from_other: &[bool],
+        ) -> DMatrix<T> {
+            let mut data = Vec::with_capacity(a.len() + b.len());
+            let (mut a_columns, mut b_columns) = (a.column_iter(), b.column_iter());
+            for &other in from_other {
+                let column = match other {
+                    true => b_columns.next(),
+                    false => a_columns.next(),
+                };
+                data.extend(column.unwrap().iter().copied());
+            }
+
+            DMatrix::from_vec(a.nrows(), from_other.len(), data)
+        }
+
+        if self.nrows() != other.nrows()
+            || self.ncols() + other.ncols() != from_other.len()
+            || other.ncols() != from_other.iter().filter(|&&other| other).count()
+        {
+            return None;
+        }
+
+        match (self, other) {
+            (DataMatrix::U8(a), DataMatrix::U8(b)) => {
+                Some(DataMatrix::U8(interleave(a, b, from_other)))
+            }
+            (DataMatrix::U16(a), DataMatrix::U16(b)) => {
+                Some(DataMatrix::U16(interleave(a, b, from_other)))
+            }
+            (DataMatrix::U32(a), DataMatrix::U32(b)) => {
+                Some(DataMatrix::U32(interleave(a, b, from_other)))
+            }
+            (DataMatrix::U64(a), DataMatrix::U64(b)) => {
+                Some(DataMatrix::U64(interleave(a, b, from_other)))
+            }
+            (DataMatrix::F32(a), DataMatrix::F32(b)) => {
+                Some(DataMatrix::F32(interleave(a, b, from_other)))
+            }
+            (DataMatrix::F64(a), DataMatrix::F64(b)) => {
+                Some(DataMatrix::F64(interleave(a, b, from_other)))
+            }
+            _ => None,
+        }
+    }
+
     pub fn resize_horizontally(self, rows: usize) -> Self {
         match self {
             DataMatrix::U8(data) => DataMatrix::U8(data.resize_horizontally(rows, 0)),
@@ -342,6 +702,56 @@ impl_from_data_matrix!(
     (DMatrix<f64>, F64)
 );
 
+// MARK: ByteSize
+
+/// Approximate size of data in memory, used to measure the data sent between
+/// node tasks.
+pub trait ByteSize {
+    fn byte_size(&self) -> usize;
+}
+
+impl ByteSize for DataMatrix {
+    fn byte_size(&self) -> usize {
+        self.nrows() * self.ncols() * self.data_type().size()
+    }
+}
+
+impl ByteSize for DataVector {
+    fn byte_size(&self) -> usize {
+        self.len() * self.data_type().size()
+    }
+}
+
+impl ByteSize for DVector<u32> {
+    fn byte_size(&self) -> usize {
+        mem::size_of_val(self.as_slice())
+    }
+}
+
+impl ByteSize for LumenMesh {
+    fn byte_size(&self) -> usize {
+        mem::size_of_val(self.vertices.as_slice()) + mem::size_of_val(self.indices.as_slice())
+    }
+}
+
+impl ByteSize for BScanDiameter {
+    fn byte_size(&self) -> usize {
+        mem::size_of::<Self>()
+    }
+}
+
+impl ByteSize for usize {
+    fn byte_size(&self) -> usize {
+        mem::size_of::<Self>()
+    }
+}
+
+impl<T: ByteSize> ByteSize for Arc<T> {
+    fn byte_size(&self) -> usize {
+        self.as_ref().byte_size()
+    }
+}
+
 // MARK: Helper functions
 
 fn cast_from_matrix_par<T>(data_type: DataType, matrix: DMatrixView<T>) -> DataMatrix
@@ -473,8 +883,75 @@ where
 
 #[cfg(test)]
 mod test {
+    use serde_json::json;
+
     use super::*;
 
+    #[test]
+    fn data_vector_as_f32() {
+        let data = DataVector::U16(DVector::from_vec(vec![1, 2, 65535]));
+        assert_eq!(data.cast_ref::<f64>(), data.clone().cast::<f64>());
+        assert!(matches!(data.as_f32(), Cow::Owned(_)));
+
+        let converted = F32Vector::new(Arc::new(data));
+        assert_eq!(converted.as_view().as_slice(), [1.0, 2.0, 65535.0]);
+
+        let data = Arc::new(DataVector::F32(DVector::from_vec(vec![0.5, 1.5])));
+        assert!(matches!(data.as_f32(), Cow::Borrowed(_)));
+        let shared = F32Vector::new(data.clone());
+        assert_eq!(shared.as_view().as_ptr(), data.as_f32().as_ptr());
+    }
+
+    #[test]
+    fn diameter_schema() {
+        let diameter = BScanDiameter {
+            b_scan_start: 128,
+            b_scan_end: 256,
+            min: 2.5,
+            max: 3.0,
+            mean: 2.75,
+            min_points: [Vector2::new(-1.0, 0.0), Vector2::new(1.5, 0.0)],
+            max_points: [Vector2::new(0.0, -1.0), Vector2::new(0.0, 2.0)],
+        };
+
+        let value = serde_json::to_value(diameter).unwrap();
+        assert_eq!(value["schema_version"], json!(SCHEMA_VERSION));
+        assert_eq!(value["unit"], json!("Millimetre"));
+        assert_eq!(
+            serde_json::from_value::<BScanDiameter>(value).unwrap(),
+            diameter
+        );
+
+        // As written before versioning
+        let unversioned = json!({
+            "b_scan_start": 128,
+            "b_scan_end": 256,
+            "min": 2.5,
+            "max": 3.0,
+            "mean": 2.75,
+            "min_points": [[-1.0, 0.0], [1.5, 0.0]],
+            "max_points": [[0.0, -1.0], [0.0, 2.0]],
+        });
+        assert_eq!(
+            serde_json::from_value::<BScanDiameter>(unversioned.clone()).unwrap(),
+            diameter
+        );
+
+        let mut micrometres = unversioned.clone();
+        micrometres["unit"] = json!("Micrometre");
+        micrometres["min"] = json!(2500.0);
+        let converted: BScanDiameter = serde_json::from_value(micrometres).unwrap();
+        assert!((converted.min - 2.5).abs() < 1e-6);
+
+        let mut newer = unversioned.clone();
+        newer["schema_version"] = json!(SCHEMA_VERSION + 1);
+        assert!(serde_json::from_value::<BScanDiameter>(newer).is_err());
+
+        let mut reversed = unversioned;
+        reversed["b_scan_end"] = json!(64);
+        assert!(serde_json::from_value::<BScanDiameter>(reversed).is_err());
+    }
+
     #[test]
     fn test_data_matrix_to_type_matrix_f_to_i_par() {
         let data = DMatrix::from_row_slice(2, 2, &[0.0, 0.5, 1.0, 1.5]);
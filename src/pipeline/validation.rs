@@ -0,0 +1,275 @@
+//! Checks of the settings of nodes, see
+//! [super::nodes::PipelineNode::validate].
+//!
+//! Pipeline files are edited by hand or written by older versions, so they can
+//! contain values the editor does not allow. Nodes correct them where possible
+//! and describe every issue, so the user knows what changed.
+
+use core::fmt;
+use std::ops::RangeInclusive;
+
+/// A setting of a node, that was invalid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    /// Name of the setting, as shown in the node.
+    pub setting: &'static str,
+    /// What was wrong and how it was corrected.
+    pub description: String,
+    /// Whether the setting got corrected. Otherwise, the user has to fix it.
+    pub corrected: bool,
+}
+
+impl ValidationIssue {
+    pub fn corrected(setting: &'static str, description: impl Into<String>) -> Self {
+        Self {
+            setting,
+            description: description.into(),
+            corrected: true,
+        }
+    }
+
+    pub fn rejected(setting: &'static str, description: impl Into<String>) -> Self {
+        Self {
+            setting,
+            description: description.into(),
+            corrected: false,
+        }
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.setting, self.description)
+    }
+}
+
+/// Clamps `value` into `range`, adding an issue when it was outside.
+pub fn clamp<T: PartialOrd + Copy + fmt::Display>(
+    issues: &mut Vec<ValidationIssue>,
+    setting: &'static str,
+    value: &mut T,
+    range: RangeInclusive<T>,
+) {
+    let (min, max) = range.into_inner();
+    let clamped = if *value < min {
+        min
+    } else if *value > max {
+        max
+    } else {
+        return;
+    };
+
+    issues.push(ValidationIssue::corrected(
+        setting,
+        format!(
+            "{} is outside of {} to {}, set to {}",
+            value, min, max, clamped
+        ),
+    ));
+    *value = clamped;
+}
+
+/// Raises `value` to `min`, adding an issue when it was below.
+pub fn at_least<T: PartialOrd + Copy + fmt::Display>(
+    issues: &mut Vec<ValidationIssue>,
+    setting: &'static str,
+    value: &mut T,
+    min: T,
+) {
+    if *value < min {
+        issues.push(ValidationIssue::corrected(
+            setting,
+            format!("{} is below the minimum of {}, set to {}", value, min, min),
+        ));
+        *value = min;
+    }
+}
+
+/// Removes whitespace around `text`, adding an issue when there was some.
+pub fn trim(issues: &mut Vec<ValidationIssue>, setting: &'static str, text: &mut String) {
+    let trimmed = text.trim();
+    if trimmed.len() != text.len() {
+        issues.push(ValidationIssue::corrected(
+            setting,
+            format!("Removed whitespace around \"{}\"", trimmed),
+        ));
+        *text = trimmed.to_string();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::{
+        node_graph::NodeId,
+        pipeline::{
+            nodes::{
+                filter, follow_catheter, follow_lumen, output, process_raw_m_scan, DynPipelineNode,
+                PipelineNode,
+            },
+            presets, Pipeline,
+        },
+    };
+
+    use super::*;
+
+    fn validate<T: PipelineNode>(value: serde_json::Value) -> (T, Vec<ValidationIssue>) {
+        let mut node: Box<dyn DynPipelineNode> = serde_json::from_value(value).unwrap();
+        let issues = node.validate();
+        let node = node.as_any().downcast_ref::<T>().unwrap().clone();
+        (node, issues)
+    }
+
+    fn settings(issues: &[ValidationIssue]) -> Vec<&'static str> {
+        issues.iter().map(|issue| issue.setting).collect()
+    }
+
+    #[test]
+    fn clamps() {
+        let mut issues = Vec::new();
+
+        let mut value = 5;
+        clamp(&mut issues, "Value", &mut value, 1..=3);
+        assert_eq!(value, 3);
+        clamp(&mut issues, "Value", &mut value, 1..=3);
+
+        let mut value = -0.5;
+        at_least(&mut issues, "Other", &mut value, 0.0);
+        assert_eq!(value, 0.0);
+
+        let mut text = " 127.0.0.1:4000\n".to_string();
+        trim(&mut issues, "Address", &mut text);
+        assert_eq!(text, "127.0.0.1:4000");
+
+        assert_eq!(settings(&issues), ["Value", "Other", "Address"]);
+        assert!(issues.iter().all(|issue| issue.corrected));
+    }
+
+    #[test]
+    fn filter() {
+        let (node, issues) = validate::<filter::Node>(json!({
+            "type": "filter",
+            "filter_type": "Gaussian",
+            "gauss_settings": { "kernel_size": [0, 5], "sigma": -1.0 },
+            "gating": { "skip_dark_columns": true, "dark_threshold": 1.5 },
+            "input": { "value": null, "connection": null },
+        }));
+
+        assert_eq!(node.gauss_settings.kernel_size.x, 1);
+        assert_eq!(node.gauss_settings.kernel_size.y, 5);
+        assert_eq!(node.gauss_settings.sigma, 0.1);
+        assert_eq!(node.gating.dark_threshold, 1.0);
+        assert_eq!(
+            settings(&issues),
+            ["Sigma", "Kernel Rows", "Dark Threshold"]
+        );
+
+        // Only the settings of the selected filter type are checked
+        let (node, issues) = validate::<filter::Node>(json!({
+            "type": "filter",
+            "filter_type": "GlobalNormalize",
+            "gauss_settings": { "kernel_size": [0, 0], "sigma": 1.0 },
+            "global_normalize_settings": { "low": 80.0, "high": 120.0 },
+            "input": { "value": null, "connection": null },
+        }));
+        assert_eq!(node.global_normalize_settings.high, 100.0);
+        assert_eq!(settings(&issues), ["High Percentile"]);
+    }
+
+    #[test]
+    fn follow_catheter_and_lumen() {
+        let (node, issues) = validate::<follow_catheter::Node>(json!({
+            "type": "follow_catheter",
+            "settings": { "smoothing_window": 0, "threshold": 3.0 },
+            "m_scan": { "value": null, "connection": null },
+            "b_scan_segmentation": { "value": null, "connection": null },
+        }));
+        assert_eq!(node.settings.smoothing_window, 1);
+        assert_eq!(node.settings.threshold, 2.0);
+        assert_eq!(settings(&issues), ["Smoothing Size", "Seg Threshold"]);
+
+        let (node, issues) = validate::<follow_lumen::Node>(json!({
+            "type": "follow_lumen",
+            "settings": {
+                "window_extend_up": 7,
+                "window_extend_down": 100,
+                "threshold": -0.2,
+                "check_artifact": true,
+                "artifact_threshold": 0.4,
+            },
+            "m_scan": { "value": null, "connection": null },
+            "catheter_segmentation": { "value": null, "connection": null },
+        }));
+        assert_eq!(node.settings.threshold, 0.0);
+        assert_eq!(settings(&issues), ["Threshold"]);
+    }
+
+    #[test]
+    fn process_raw_m_scan() {
+        let (node, issues) = validate::<process_raw_m_scan::Node>(json!({
+            "type": "process_raw_m_scan",
+            "factor": 0.0,
+            "rescale_cutoff": 0,
+        }));
+        assert_eq!(node.factor, 1.0);
+        assert_eq!(node.rescale_cutoff, 1);
+        assert_eq!(settings(&issues), ["Factor", "Rescale Cutoff"]);
+
+        let (node, issues) = validate::<process_raw_m_scan::Node>(json!({
+            "type": "process_raw_m_scan",
+            "rescale_mode": { "Fixed": { "lower": 30.0, "upper": 10.0 } },
+        }));
+        assert_eq!(
+            node.rescale_mode,
+            process_raw_m_scan::RescaleMode::Fixed {
+                lower: 10.0,
+                upper: 30.0
+            }
+        );
+        assert_eq!(settings(&issues), ["Bounds"]);
+
+        let (_, issues) = validate::<process_raw_m_scan::Node>(json!({
+            "type": "process_raw_m_scan",
+            "rescale_mode": { "Fixed": { "lower": 10.0, "upper": 10.0 } },
+        }));
+        assert!(!issues[0].corrected);
+    }
+
+    #[test]
+    fn output() {
+        let (node, issues) = validate::<output::Node>(json!({
+            "type": "output",
+            "path": "  export/m_scan.bin ",
+            "input_type": "MScan",
+            "scan_data_type": "U8",
+            "destination": { "Connect": "" },
+            "input": {
+                "value": null,
+                "connection": { "node_id": 1, "output_id": 0, "type_id": 4 },
+            },
+        }));
+        assert_eq!(node.path, std::path::Path::new("export/m_scan.bin"));
+        assert_eq!(
+            node.input_type,
+            crate::pipeline::PipelineDataType::MScanSegmentation
+        );
+        assert_eq!(settings(&issues), ["Path", "Type", "Address"]);
+        assert!(!issues[2].corrected);
+    }
+
+    #[test]
+    fn presets_are_valid() {
+        for preset in [
+            presets::PHANTOM_1_1_3,
+            presets::PHANTOM_1_2_4,
+            presets::CLINIC,
+            presets::CATHETER_MASK_TEMPLATE,
+            presets::WALKTHROUGH,
+        ] {
+            let value: serde_json::Value = serde_json::from_str(preset).unwrap();
+            let mut pipeline: Pipeline = serde_json::from_value(value[0].clone()).unwrap();
+            assert_eq!(pipeline.validate(), Vec::<(NodeId, Vec<_>)>::new());
+        }
+    }
+}
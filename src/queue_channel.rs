@@ -1,35 +1,78 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
 use tokio::sync::watch;
 
 /// A channel using a queue with specified capacity, where every new receiver
 /// starts receiving the oldest value, that is still applicable.
+///
+/// A stream is complete, once [Sender::finish] is called. When all senders are
+/// dropped without it, e.g. because the producer failed, receivers get
+/// [error::RecvError::Aborted] instead of [error::RecvError::Closed].
 pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
     let (tx, rx) = watch::channel(Queue::new(capacity));
+    let observers = Observers::default();
 
-    (Sender { tx }, Receiver { rx, pos: 0 })
+    (
+        Sender {
+            tx,
+            observers: observers.clone(),
+        },
+        Receiver {
+            rx,
+            pos: 0,
+            observers,
+        },
+    )
 }
 
 #[derive(Debug, Clone)]
 pub struct Sender<T: Clone> {
     tx: watch::Sender<Queue<T>>,
+    observers: Observers<T>,
 }
 
 #[derive(Debug)]
 pub struct Receiver<T: Clone> {
     rx: watch::Receiver<Queue<T>>,
     pos: usize,
+    observers: Observers<T>,
 }
 
+type Observer<T> = Box<dyn Fn(&T) + Send + Sync>;
+
+/// Functions called with every item sent through a channel.
+struct Observers<T>(Arc<Mutex<Vec<Observer<T>>>>);
+
 impl<T: Clone> Sender<T> {
     pub fn send(&self, item: T) {
         self.tx.send_modify(|queue| {
+            // Called while the queue is locked, see Receiver::observe
+            for observer in self.observers.0.lock().unwrap().iter() {
+                observer(&item);
+            }
+
             queue.push(item);
         });
     }
 
+    /// Marks the stream as complete. Receivers get
+    /// [error::RecvError::Closed], once all senders are dropped.
+    pub fn finish(self) {
+        // Only the status changes, so receivers are not woken up
+        self.tx.send_if_modified(|queue| {
+            queue.finished = true;
+            false
+        });
+    }
+
     pub fn subscribe(&self) -> Receiver<T> {
         Receiver {
             rx: self.tx.subscribe(),
             pos: 0,
+            observers: self.observers.clone(),
         }
     }
 
@@ -56,10 +99,12 @@ impl<T: Clone> Receiver<T> {
                 Err(error::RecvError::Lagged)
             }
             Err(error::GetError::TooNew) => {
-                self.rx
-                    .changed()
-                    .await
-                    .map_err(|_| error::RecvError::Closed)?;
+                if self.rx.changed().await.is_err() {
+                    return Err(match self.rx.borrow().finished {
+                        true => error::RecvError::Closed,
+                        false => error::RecvError::Aborted,
+                    });
+                }
 
                 let queue = self.rx.borrow_and_update();
 
@@ -82,6 +127,28 @@ impl<T: Clone> Receiver<T> {
     pub fn is_lagged(&self) -> bool {
         self.rx.borrow().tail > self.pos
     }
+
+    /// Whether all senders were dropped without finishing the stream, see
+    /// [Sender::finish].
+    pub fn is_aborted(&self) -> bool {
+        self.rx.has_changed().is_err() && !self.rx.borrow().finished
+    }
+
+    /// Calls `observer` with every item sent through the channel from now
+    /// on, and immediately with the items still held by the queue.
+    pub fn observe(&self, observer: impl Fn(&T) + Send + Sync + 'static) {
+        // Lock the queue first, like the sender does
+        let queue = self.rx.borrow();
+        let mut observers = self.observers.0.lock().unwrap();
+
+        for index in queue.tail..queue.head {
+            if let Ok(item) = queue.get(index) {
+                observer(&item);
+            }
+        }
+
+        observers.push(Box::new(observer));
+    }
 }
 
 impl<T: Clone> Clone for Receiver<T> {
@@ -89,15 +156,38 @@ impl<T: Clone> Clone for Receiver<T> {
         Self {
             rx: self.rx.clone(),
             pos: self.pos,
+            observers: self.observers.clone(),
         }
     }
 }
 
+impl<T> Default for Observers<T> {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(Vec::new())))
+    }
+}
+
+impl<T> Clone for Observers<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> fmt::Debug for Observers<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Observers")
+            .field(&self.0.lock().unwrap().len())
+            .finish()
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Queue<T: Clone> {
     buffer: Box<[Option<T>]>,
     head: usize,
     tail: usize,
+    /// See [Sender::finish].
+    finished: bool,
 }
 
 impl<T: Clone> Queue<T> {
@@ -112,6 +202,7 @@ impl<T: Clone> Queue<T> {
             buffer: buffer.into_boxed_slice(),
             head: 0,
             tail: 0,
+            finished: false,
         }
     }
 
@@ -145,6 +236,10 @@ pub mod error {
         Lagged,
         #[error("The queue has been closed")]
         Closed,
+        /// The senders were dropped without finishing the stream, see
+        /// [super::Sender::finish].
+        #[error("The stream ended before it was complete")]
+        Aborted,
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
@@ -239,4 +334,62 @@ mod test {
         assert_eq!(rx.recv().await, Ok(2));
         assert_eq!(rx.recv().await, Ok(3));
     }
+
+    #[tokio::test]
+    async fn end_of_stream_status() {
+        let (tx, mut rx) = channel(4);
+        let mut rx2 = tx.subscribe();
+
+        tx.send(1);
+        assert!(!rx.is_aborted());
+        tx.finish();
+        assert!(!rx.is_aborted());
+        assert_eq!(rx.recv().await, Ok(1));
+        assert_eq!(rx.recv().await, Err(error::RecvError::Closed));
+
+        let (tx, mut rx) = channel(4);
+        tx.send(1);
+        let tx2 = tx.clone();
+        tx.finish();
+        drop(tx2);
+        assert_eq!(rx.recv().await, Ok(1));
+        assert_eq!(rx.recv().await, Err(error::RecvError::Closed));
+
+        // The first channel is already done, the status stays
+        assert_eq!(rx2.recv().await, Ok(1));
+        assert_eq!(rx2.recv().await, Err(error::RecvError::Closed));
+    }
+
+    #[tokio::test]
+    async fn dropped_sender_aborts() {
+        let (tx, mut rx) = channel(4);
+
+        tx.send(1);
+        drop(tx);
+        assert!(rx.is_aborted());
+        assert_eq!(rx.recv().await, Ok(1));
+        assert_eq!(rx.recv().await, Err(error::RecvError::Aborted));
+    }
+
+    #[test]
+    fn observers_see_every_item() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let (tx, rx) = channel(2);
+
+        tx.send(1);
+        tx.send(2);
+        tx.send(3);
+
+        // Only the items still in the queue are replayed
+        let sum = Arc::new(AtomicUsize::new(0));
+        let observed = sum.clone();
+        rx.observe(move |item| {
+            observed.fetch_add(*item, Ordering::Relaxed);
+        });
+        assert_eq!(sum.load(Ordering::Relaxed), 5);
+
+        tx.send(4);
+        assert_eq!(sum.load(Ordering::Relaxed), 9);
+    }
 }
@@ -0,0 +1,312 @@
+//! Recovery from a lost GPU device.
+//!
+//! eframe owns the wgpu device and cannot request a new one. Recovering
+//! restarts the app instead: The pipeline is autosaved and the app relaunched,
+//! which requests a new device and initializes the resources of every view
+//! again with [crate::view::views::DataView::init_wgpu]. Caches start empty, so
+//! views upload their data again.
+//!
+//! When the device is lost again shortly after a recovery, or the app does not
+//! start at all, it gives up after [MAX_ATTEMPTS] and starts in safe mode. Safe
+//! mode only requests a basic device and disables all data views, so the
+//! pipeline can still be edited, run and saved.
+//!
+//! Every failure is logged with the adapter to [diagnostics_path].
+
+use std::{
+    fs,
+    io::Write,
+    path::PathBuf,
+    process::Command,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::{pipeline::sessions, settings};
+
+/// Argument starting the app in safe mode.
+pub const SAFE_MODE_ARG: &str = "--safe-mode";
+
+/// Environment variable holding [Startup::attempt] of a relaunched app.
+const ATTEMPT_VAR: &str = "IVOCT_GPU_RECOVERY_ATTEMPT";
+
+/// Relaunches, before the app starts in safe mode.
+pub const MAX_ATTEMPTS: u32 = 2;
+
+/// Time after a recovery, after which a lost device counts as new failure,
+/// not as failed recovery.
+pub const RECOVERY_WINDOW: Duration = Duration::from_secs(60);
+
+/// Name of the file in [eframe::storage_dir], failures are logged to.
+const DIAGNOSTICS_FILE_NAME: &str = "gpu_diagnostics.log";
+
+// MARK: Startup
+
+/// How the app was started.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Startup {
+    pub safe_mode: bool,
+    /// Recoveries in a row, that lead to this start.
+    pub attempt: u32,
+}
+
+impl Startup {
+    /// The startup of this process.
+    pub fn current() -> Self {
+        Self {
+            safe_mode: std::env::args().any(|arg| arg == SAFE_MODE_ARG),
+            attempt: std::env::var(ATTEMPT_VAR)
+                .ok()
+                .and_then(|attempt| attempt.parse().ok())
+                .unwrap_or(0),
+        }
+    }
+
+    /// How to start again, after the GPU failed `uptime` after this start.
+    /// [None] in safe mode, there is nothing left to fall back to.
+    pub fn next(self, uptime: Duration) -> Option<Startup> {
+        if self.safe_mode {
+            return None;
+        }
+
+        // A failure long after the recovery is not the same one
+        let attempt = match uptime < RECOVERY_WINDOW {
+            true => self.attempt + 1,
+            false => 1,
+        };
+        Some(Startup {
+            safe_mode: attempt > MAX_ATTEMPTS,
+            attempt,
+        })
+    }
+
+    /// Starts a new process of the app like this.
+    pub fn relaunch(self) -> std::io::Result<()> {
+        Command::new(std::env::current_exe()?)
+            .args(self.args(std::env::args().skip(1)))
+            .env(ATTEMPT_VAR, self.attempt.to_string())
+            .spawn()?;
+        Ok(())
+    }
+
+    /// The arguments `args` of this process, for a start like this.
+    fn args(self, args: impl Iterator<Item = String>) -> Vec<String> {
+        args.filter(|arg| arg != SAFE_MODE_ARG)
+            .chain(self.safe_mode.then(|| SAFE_MODE_ARG.to_string()))
+            .collect()
+    }
+}
+
+// MARK: Device loss
+
+/// Why the device got lost, as reported by wgpu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceLoss {
+    pub reason: String,
+    pub message: String,
+}
+
+impl DeviceLoss {
+    pub fn error(&self) -> anyhow::Error {
+        anyhow::anyhow!("{}", self.message).context(format!("GPU device lost ({})", self.reason))
+    }
+}
+
+/// Watches a device for getting lost.
+#[derive(Debug, Clone)]
+pub struct LossMonitor {
+    device: Arc<wgpu::Device>,
+    lost: Arc<Mutex<Option<DeviceLoss>>>,
+}
+
+impl LossMonitor {
+    /// Installs the callbacks on `device`. `ctx` is repainted, once it got
+    /// lost.
+    pub fn install(device: &Arc<wgpu::Device>, ctx: &egui::Context) -> Self {
+        let monitor = Self {
+            device: device.clone(),
+            lost: Arc::default(),
+        };
+
+        let lost = monitor.lost.clone();
+        let ctx = ctx.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            // Dropping the device on exit reports it as lost, too
+            if matches!(
+                reason,
+                wgpu::DeviceLostReason::Dropped | wgpu::DeviceLostReason::ReplacedCallback
+            ) {
+                return;
+            }
+            *lost.lock().unwrap() = Some(DeviceLoss {
+                reason: format!("{:?}", reason),
+                message,
+            });
+            ctx.request_repaint();
+        });
+
+        // Everything done with the lost device fails, until the app restarts.
+        // Other errors panic, like by default
+        let lost = monitor.lost.clone();
+        device.on_uncaptured_error(Box::new(move |error| {
+            if lost.lock().unwrap().is_none() {
+                panic!("wgpu error: {}", error);
+            }
+        }));
+
+        monitor
+    }
+
+    pub fn lost(&self) -> Option<DeviceLoss> {
+        self.lost.lock().unwrap().clone()
+    }
+
+    /// Destroys the device, to test the recovery.
+    pub fn simulate_loss(&self) {
+        self.device.destroy();
+    }
+}
+
+// MARK: Diagnostics
+
+/// The file failures of the GPU are logged to.
+pub fn diagnostics_path() -> Option<PathBuf> {
+    eframe::storage_dir(settings::APP_NAME).map(|dir| dir.join(DIAGNOSTICS_FILE_NAME))
+}
+
+/// Entry of the diagnostics file about `error`.
+pub fn diagnostics_entry(
+    time: SystemTime,
+    startup: Startup,
+    adapter: Option<&wgpu::AdapterInfo>,
+    error: &anyhow::Error,
+) -> String {
+    let mut entry = format!(
+        "[{}] {}, attempt {}\n",
+        sessions::utc_timestamp(time),
+        match startup.safe_mode {
+            true => "safe mode",
+            false => "normal start",
+        },
+        startup.attempt,
+    );
+
+    match adapter {
+        Some(adapter) => entry.push_str(&format!(
+            "Adapter: {} ({:?}, {:?}), driver {} {}\n",
+            adapter.name, adapter.device_type, adapter.backend, adapter.driver, adapter.driver_info
+        )),
+        None => entry.push_str("Adapter: unknown\n"),
+    }
+
+    for (i, cause) in error.chain().enumerate() {
+        let prefix = if i == 0 { "Error" } else { "Caused by" };
+        entry.push_str(&format!("{}: {}\n", prefix, cause));
+    }
+
+    entry
+}
+
+/// Appends an entry about `error` to the file at [diagnostics_path].
+pub fn log_diagnostics(
+    startup: Startup,
+    adapter: Option<&wgpu::AdapterInfo>,
+    error: &anyhow::Error,
+) {
+    let entry = diagnostics_entry(SystemTime::now(), startup, adapter, error);
+    eprint!("{}", entry);
+
+    let Some(path) = diagnostics_path() else {
+        return;
+    };
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| {
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)?
+                .write_all(entry.as_bytes())
+        });
+    if let Err(e) = result {
+        eprintln!("Error writing {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    #[test]
+    fn falls_back_to_safe_mode() {
+        let soon = Duration::from_secs(5);
+
+        let first = Startup::default().next(soon).unwrap();
+        assert_eq!(first.attempt, 1);
+        assert!(!first.safe_mode);
+
+        let second = first.next(soon).unwrap();
+        assert_eq!(second.attempt, 2);
+        assert!(!second.safe_mode);
+
+        // Recovery failed twice
+        let third = second.next(soon).unwrap();
+        assert!(third.safe_mode);
+        assert_eq!(third.next(soon), None);
+
+        // Running for a while, counts as recovered
+        assert_eq!(second.next(RECOVERY_WINDOW * 2), Some(first));
+    }
+
+    #[test]
+    fn relaunch_arguments() {
+        let args = || {
+            ["pipeline.json", SAFE_MODE_ARG]
+                .map(String::from)
+                .into_iter()
+        };
+
+        let normal = Startup::default();
+        assert_eq!(normal.args(args()), ["pipeline.json"]);
+
+        let safe = Startup {
+            safe_mode: true,
+            attempt: 3,
+        };
+        assert_eq!(safe.args(args()), ["pipeline.json", SAFE_MODE_ARG]);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let adapter = wgpu::AdapterInfo {
+            name: "Test GPU".into(),
+            vendor: 0,
+            device: 0,
+            device_type: wgpu::DeviceType::DiscreteGpu,
+            driver: "test".into(),
+            driver_info: "1.0".into(),
+            backend: wgpu::Backend::Vulkan,
+        };
+        let loss = DeviceLoss {
+            reason: "Unknown".into(),
+            message: "Driver reset".into(),
+        };
+
+        let entry = diagnostics_entry(
+            UNIX_EPOCH + Duration::from_secs(1717243205),
+            Startup::default(),
+            Some(&adapter),
+            &loss.error(),
+        );
+        assert_eq!(
+            entry,
+            "[2024-06-01T12-00-05] normal start, attempt 0\n\
+             Adapter: Test GPU (DiscreteGpu, Vulkan), driver test 1.0\n\
+             Error: GPU device lost (Unknown)\n\
+             Caused by: Driver reset\n"
+        );
+    }
+}
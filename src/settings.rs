@@ -0,0 +1,312 @@
+// Application wide settings, that are persisted between sessions.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{pipeline::chunking::ChunkLimits, shortcuts::Shortcuts, units::NumberFormat};
+
+/// Name of the app, also used to find the storage directory.
+pub const APP_NAME: &str = "IVOCT Test App";
+
+/// Key under which the settings are stored in the eframe storage.
+pub const STORAGE_KEY: &str = "settings";
+
+/// The settings are additionally mirrored to this file in the eframe storage
+/// directory, because some settings are needed before eframe is started.
+const STARTUP_FILE_NAME: &str = "settings.json";
+
+static SETTINGS: RwLock<Settings> = RwLock::new(Settings::DEFAULT);
+
+// MARK: Settings
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub general: GeneralSettings,
+    pub performance: PerformanceSettings,
+    pub display: DisplaySettings,
+    pub shortcuts: Shortcuts,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeneralSettings {
+    /// How often the pipeline and settings are saved, in seconds.
+    pub autosave_interval: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PerformanceSettings {
+    /// Bounds for the width of chunks streamed through the pipeline.
+    pub chunk_limits: ChunkLimits,
+    /// How many chunks a streamed response can hold, before slow receivers
+    /// start lagging.
+    pub stream_capacity: usize,
+    /// Keep the last data sent through every connection, except M scans, to
+    /// preview it when hovering the connection.
+    pub connection_previews: bool,
+    /// Keep a downsampled image of the last chunk of every M scan connection.
+    /// Costs time on every chunk sent.
+    pub m_scan_previews: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplaySettings {
+    pub dark_mode: bool,
+    /// Color map new M scan views start with, as index among all bundled
+    /// color maps, see [crate::gui::color_maps::loaded_index].
+    pub default_color_map: u32,
+    /// Which GPU to prefer. Only applied on the next start of the app.
+    pub power_preference: PowerPreference,
+    /// Scale of pins, connections and node headers in the pipeline editor,
+    /// independent of the zoom.
+    pub graph_scale: f32,
+    /// Use a colorblind safe palette for pins and draw a letter for the data
+    /// type into every pin.
+    pub high_contrast_pins: bool,
+    /// Decimal separator and length unit of displayed numbers. Stored values
+    /// are not affected.
+    pub number_format: NumberFormat,
+    /// Seconds without progress, after which a working node is outlined in
+    /// the pipeline editor.
+    pub stall_threshold: u64,
+    /// How A scans are combined into the downsampled textures drawn when
+    /// zoomed out of a polar view. Applies to M scans uploaded afterwards.
+    pub m_scan_pooling: MScanPooling,
+    /// Snap nodes to a grid, while they are dragged in the pipeline editor.
+    pub snap_to_grid: bool,
+    /// Spacing of that grid, in points at a zoom of 1.
+    pub grid_size: f32,
+    /// Align dragged nodes with the edges of other nodes in the pipeline
+    /// editor.
+    pub alignment_guides: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerPreference {
+    HighPerformance,
+    LowPower,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MScanPooling {
+    /// Keeps bright features, like the catheter and the lumen border, visible.
+    Maximum,
+    Mean,
+}
+
+impl Settings {
+    pub const DEFAULT: Settings = Settings {
+        general: GeneralSettings::DEFAULT,
+        performance: PerformanceSettings::DEFAULT,
+        display: DisplaySettings::DEFAULT,
+        shortcuts: Shortcuts::DEFAULT,
+    };
+
+    /// The settings currently in effect.
+    pub fn current() -> Self {
+        *SETTINGS.read().unwrap()
+    }
+
+    /// Replace the settings currently in effect. Subsystems read the settings
+    /// when they need them, so new values are picked up without a restart.
+    pub fn set_current(settings: Settings) {
+        *SETTINGS.write().unwrap() = settings;
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let mut settings: Settings = serde_json::from_str(json)?;
+        settings.validate();
+        Ok(settings)
+    }
+
+    pub fn to_json(self) -> String {
+        serde_json::to_string_pretty(&self).unwrap()
+    }
+
+    /// Clamp all values into their valid ranges.
+    pub fn validate(&mut self) {
+        let general = &mut self.general;
+        general.autosave_interval = general.autosave_interval.clamp(5, 3600);
+
+        let performance = &mut self.performance;
+        performance.stream_capacity = performance.stream_capacity.clamp(2, 10000);
+        let limits = &mut performance.chunk_limits;
+        limits.min_width = limits.min_width.min(4096);
+        limits.max_width = limits.max_width.clamp(limits.min_width.max(1), 1 << 20);
+
+        let display = &mut self.display;
+        if display.default_color_map >= crate::gui::color_maps::bundled_color_map_count() {
+            display.default_color_map = DisplaySettings::DEFAULT.default_color_map;
+        }
+        display.graph_scale = match display.graph_scale.is_finite() {
+            true => display.graph_scale.clamp(0.5, 2.0),
+            false => DisplaySettings::DEFAULT.graph_scale,
+        };
+        display.stall_threshold = display.stall_threshold.clamp(1, 3600);
+        display.grid_size = match display.grid_size.is_finite() {
+            true => display.grid_size.clamp(2.0, 100.0),
+            false => DisplaySettings::DEFAULT.grid_size,
+        };
+    }
+
+    /// Loads the settings from the file mirrored into the storage directory.
+    /// Used before eframe is started.
+    pub fn load_startup_file() -> Option<Self> {
+        let json = std::fs::read_to_string(startup_file_path()?).ok()?;
+
+        Settings::from_json(&json)
+            .inspect_err(|e| eprintln!("Error loading settings: {}", e))
+            .ok()
+    }
+
+    pub fn save_startup_file(&self) {
+        let Some(path) = startup_file_path() else {
+            return;
+        };
+
+        if let Err(e) = write_file(&path, &self.to_json()) {
+            eprintln!("Error saving settings to {}: {}", path.display(), e);
+        }
+    }
+}
+
+impl GeneralSettings {
+    pub const DEFAULT: GeneralSettings = GeneralSettings {
+        autosave_interval: 30,
+    };
+
+    pub fn autosave_interval(&self) -> Duration {
+        Duration::from_secs(self.autosave_interval)
+    }
+}
+
+impl PerformanceSettings {
+    pub const DEFAULT: PerformanceSettings = PerformanceSettings {
+        chunk_limits: ChunkLimits::DEFAULT,
+        stream_capacity: 100,
+        connection_previews: true,
+        m_scan_previews: false,
+    };
+}
+
+impl DisplaySettings {
+    pub const DEFAULT: DisplaySettings = DisplaySettings {
+        dark_mode: true,
+        default_color_map: 26,
+        power_preference: PowerPreference::HighPerformance,
+        graph_scale: 1.0,
+        high_contrast_pins: false,
+        number_format: NumberFormat::DEFAULT,
+        stall_threshold: 10,
+        m_scan_pooling: MScanPooling::Maximum,
+        snap_to_grid: false,
+        grid_size: 10.0,
+        alignment_guides: true,
+    };
+
+    pub fn stall_threshold(&self) -> Duration {
+        Duration::from_secs(self.stall_threshold)
+    }
+}
+
+impl PowerPreference {
+    pub const VALUES: [PowerPreference; 2] =
+        [PowerPreference::HighPerformance, PowerPreference::LowPower];
+}
+
+impl MScanPooling {
+    pub const VALUES: [MScanPooling; 2] = [MScanPooling::Maximum, MScanPooling::Mean];
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(value: PowerPreference) -> Self {
+        match value {
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+        }
+    }
+}
+
+macro_rules! impl_default {
+    ($($t:ty),*) => {
+        $(impl Default for $t {
+            fn default() -> Self {
+                Self::DEFAULT
+            }
+        })*
+    };
+}
+
+impl_default!(
+    Settings,
+    GeneralSettings,
+    PerformanceSettings,
+    DisplaySettings
+);
+
+impl Default for PowerPreference {
+    fn default() -> Self {
+        DisplaySettings::DEFAULT.power_preference
+    }
+}
+
+impl Default for MScanPooling {
+    fn default() -> Self {
+        DisplaySettings::DEFAULT.m_scan_pooling
+    }
+}
+
+// MARK: Helper functions
+
+fn startup_file_path() -> Option<PathBuf> {
+    eframe::storage_dir(APP_NAME).map(|dir| dir.join(STARTUP_FILE_NAME))
+}
+
+fn write_file(path: &Path, contents: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn json_roundtrip() {
+        let mut settings = Settings::DEFAULT;
+        settings.general.autosave_interval = 120;
+        settings.display.power_preference = PowerPreference::LowPower;
+        settings.display.m_scan_pooling = MScanPooling::Mean;
+        settings.display.number_format.length_unit = crate::units::LengthUnit::Mil;
+
+        assert_eq!(Settings::from_json(&settings.to_json()).unwrap(), settings);
+    }
+
+    #[test]
+    fn missing_and_invalid_values() {
+        let settings = Settings::from_json(
+            r#"{ "general": { "autosave_interval": 0 }, "display": { "default_color_map": 100000, "graph_scale": 10.0, "stall_threshold": 0, "grid_size": 0.0 } }"#,
+        )
+        .unwrap();
+
+        assert_eq!(settings.general.autosave_interval, 5);
+        assert_eq!(settings.performance, PerformanceSettings::DEFAULT);
+        assert_eq!(
+            settings.display.default_color_map,
+            DisplaySettings::DEFAULT.default_color_map
+        );
+        assert_eq!(settings.display.graph_scale, 2.0);
+        assert_eq!(settings.display.stall_threshold, 1);
+        assert_eq!(settings.display.grid_size, 2.0);
+    }
+}
@@ -0,0 +1,334 @@
+// Keyboard shortcuts of the app. Every action is registered in [ACTIONS] with
+// its default bindings, which can be changed in the settings.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, RwLock},
+};
+
+use egui::{Key, KeyboardShortcut, Modifiers};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+/// Where the shortcut of an action fires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShortcutContext {
+    /// While the pipeline editor is hovered and no text field is focused.
+    Graph,
+    /// While a data view is hovered.
+    View,
+}
+
+/// Actions, that can be triggered by a shortcut. Indices into [ACTIONS].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    DeleteNode,
+    DuplicateNode,
+    MeshForward,
+    MeshBackward,
+    MeshLeft,
+    MeshRight,
+    MeshUp,
+    MeshDown,
+}
+
+pub struct ActionInfo {
+    pub action: Action,
+    /// Stable identifier, under which changed bindings are stored.
+    pub id: &'static str,
+    pub description: &'static str,
+    pub context: ShortcutContext,
+    pub defaults: &'static [KeyboardShortcut],
+}
+
+const fn key(key: Key) -> KeyboardShortcut {
+    KeyboardShortcut::new(Modifiers::NONE, key)
+}
+
+/// All actions, in the order of [Action].
+pub const ACTIONS: [ActionInfo; 8] = [
+    ActionInfo {
+        action: Action::DeleteNode,
+        id: "graph.delete_node",
+        description: "Delete the selected nodes",
+        context: ShortcutContext::Graph,
+        defaults: &[key(Key::Delete), key(Key::Backspace)],
+    },
+    ActionInfo {
+        action: Action::DuplicateNode,
+        id: "graph.duplicate_node",
+        description: "Duplicate the selected nodes, keeping their inputs connected",
+        context: ShortcutContext::Graph,
+        defaults: &[KeyboardShortcut::new(Modifiers::CTRL, Key::D)],
+    },
+    ActionInfo {
+        action: Action::MeshForward,
+        id: "mesh.forward",
+        description: "Move the 3D camera forward, while dragging",
+        context: ShortcutContext::View,
+        defaults: &[key(Key::W)],
+    },
+    ActionInfo {
+        action: Action::MeshBackward,
+        id: "mesh.backward",
+        description: "Move the 3D camera backward, while dragging",
+        context: ShortcutContext::View,
+        defaults: &[key(Key::S)],
+    },
+    ActionInfo {
+        action: Action::MeshLeft,
+        id: "mesh.left",
+        description: "Move the 3D camera left, while dragging",
+        context: ShortcutContext::View,
+        defaults: &[key(Key::A)],
+    },
+    ActionInfo {
+        action: Action::MeshRight,
+        id: "mesh.right",
+        description: "Move the 3D camera right, while dragging",
+        context: ShortcutContext::View,
+        defaults: &[key(Key::D)],
+    },
+    ActionInfo {
+        action: Action::MeshUp,
+        id: "mesh.up",
+        description: "Move the 3D camera up, while dragging",
+        context: ShortcutContext::View,
+        defaults: &[key(Key::Space), key(Key::E)],
+    },
+    ActionInfo {
+        action: Action::MeshDown,
+        id: "mesh.down",
+        description: "Move the 3D camera down, while dragging",
+        context: ShortcutContext::View,
+        defaults: &[key(Key::Q)],
+    },
+];
+
+impl Action {
+    pub fn info(self) -> &'static ActionInfo {
+        &ACTIONS[self as usize]
+    }
+}
+
+// MARK: Shortcuts
+
+/// The bindings of all actions. Only bindings changed by the user are stored,
+/// by the id of their action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "BTreeMap<String, KeyboardShortcut>")]
+#[serde(into = "BTreeMap<String, KeyboardShortcut>")]
+pub struct Shortcuts {
+    /// Replaces the defaults of the action with the same index.
+    changed: [Option<KeyboardShortcut>; ACTIONS.len()],
+}
+
+impl Shortcuts {
+    pub const DEFAULT: Shortcuts = Shortcuts {
+        changed: [None; ACTIONS.len()],
+    };
+
+    pub fn bindings(&self, action: Action) -> &[KeyboardShortcut] {
+        match &self.changed[action as usize] {
+            Some(shortcut) => std::slice::from_ref(shortcut),
+            None => action.info().defaults,
+        }
+    }
+
+    /// Binds `shortcut` to `action`. [None] restores the defaults.
+    pub fn set(&mut self, action: Action, shortcut: Option<KeyboardShortcut>) {
+        self.changed[action as usize] = shortcut.map(normalize);
+    }
+
+    pub fn is_default(&self, action: Action) -> bool {
+        self.changed[action as usize].is_none()
+    }
+
+    /// Another action of the same context, which `shortcut` is bound to.
+    pub fn conflict(&self, action: Action, shortcut: KeyboardShortcut) -> Option<Action> {
+        let shortcut = normalize(shortcut);
+
+        ACTIONS
+            .iter()
+            .filter(|info| info.action != action && info.context == action.info().context)
+            .find(|info| self.bindings(info.action).contains(&shortcut))
+            .map(|info| info.action)
+    }
+
+    fn map(&self) -> ShortcutMap {
+        let mut map = ShortcutMap::new();
+        for info in &ACTIONS {
+            for shortcut in self.bindings(info.action) {
+                map.entry((info.context, *shortcut))
+                    .or_default()
+                    .push(info.action);
+            }
+        }
+        map
+    }
+}
+
+impl From<BTreeMap<String, KeyboardShortcut>> for Shortcuts {
+    fn from(stored: BTreeMap<String, KeyboardShortcut>) -> Self {
+        let mut shortcuts = Shortcuts::DEFAULT;
+        // Bindings of removed actions are dropped
+        for info in &ACTIONS {
+            shortcuts.set(info.action, stored.get(info.id).copied());
+        }
+        shortcuts
+    }
+}
+
+impl From<Shortcuts> for BTreeMap<String, KeyboardShortcut> {
+    fn from(shortcuts: Shortcuts) -> Self {
+        ACTIONS
+            .iter()
+            .zip(shortcuts.changed)
+            .filter_map(|(info, shortcut)| Some((info.id.to_string(), shortcut?)))
+            .collect()
+    }
+}
+
+impl Default for Shortcuts {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+// MARK: Lookup
+
+/// Actions by their context and binding.
+type ShortcutMap = HashMap<(ShortcutContext, KeyboardShortcut), Vec<Action>>;
+
+/// Built from the current settings, when they changed.
+static MAP: RwLock<Option<(Shortcuts, Arc<ShortcutMap>)>> = RwLock::new(None);
+
+fn current_map() -> Arc<ShortcutMap> {
+    let shortcuts = Settings::current().shortcuts;
+
+    if let Some((built, map)) = &*MAP.read().unwrap() {
+        if *built == shortcuts {
+            return map.clone();
+        }
+    }
+
+    let map = Arc::new(shortcuts.map());
+    *MAP.write().unwrap() = Some((shortcuts, map.clone()));
+    map
+}
+
+/// `command` mirrors ctrl or the mac command key, depending on the platform,
+/// so it is ignored when comparing shortcuts.
+fn normalize(shortcut: KeyboardShortcut) -> KeyboardShortcut {
+    KeyboardShortcut {
+        modifiers: Modifiers {
+            command: false,
+            ..shortcut.modifiers
+        },
+        ..shortcut
+    }
+}
+
+/// Shortcut of a key press in `event`.
+pub fn pressed_shortcut(event: &egui::Event) -> Option<KeyboardShortcut> {
+    match event {
+        egui::Event::Key {
+            key,
+            pressed: true,
+            modifiers,
+            ..
+        } => Some(normalize(KeyboardShortcut::new(*modifiers, *key))),
+        _ => None,
+    }
+}
+
+/// Actions of `context`, whose shortcut was pressed this frame. The caller
+/// decides, whether the context is active.
+pub fn pressed(input: &egui::InputState, context: ShortcutContext) -> Vec<Action> {
+    let map = current_map();
+
+    input
+        .events
+        .iter()
+        .filter_map(pressed_shortcut)
+        .filter_map(|shortcut| map.get(&(context, shortcut)))
+        .flatten()
+        .copied()
+        .collect()
+}
+
+/// Whether a key bound to `action` is held down. Modifiers are ignored, so
+/// they can change how the action behaves.
+pub fn down(input: &egui::InputState, action: Action) -> bool {
+    Settings::current()
+        .shortcuts
+        .bindings(action)
+        .iter()
+        .any(|shortcut| input.key_down(shortcut.logical_key))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn registry() {
+        for (i, info) in ACTIONS.iter().enumerate() {
+            assert_eq!(info.action as usize, i, "{} is out of order", info.id);
+            assert!(!info.defaults.is_empty());
+        }
+        let ids: HashSet<_> = ACTIONS.iter().map(|info| info.id).collect();
+        assert_eq!(ids.len(), ACTIONS.len());
+
+        // Defaults do not conflict
+        let map = Shortcuts::DEFAULT.map();
+        assert!(map.values().all(|actions| actions.len() == 1));
+        assert_eq!(
+            map[&(ShortcutContext::Graph, key(Key::Backspace))],
+            [Action::DeleteNode]
+        );
+    }
+
+    #[test]
+    fn rebinding() {
+        let ctrl_d = KeyboardShortcut::new(Modifiers::CTRL | Modifiers::COMMAND, Key::D);
+
+        let mut shortcuts = Shortcuts::DEFAULT;
+        shortcuts.set(Action::DeleteNode, Some(ctrl_d));
+        assert_eq!(
+            shortcuts.bindings(Action::DeleteNode),
+            [KeyboardShortcut::new(Modifiers::CTRL, Key::D)]
+        );
+        assert!(!shortcuts.is_default(Action::DeleteNode));
+
+        assert_eq!(
+            shortcuts.conflict(Action::DeleteNode, ctrl_d),
+            Some(Action::DuplicateNode)
+        );
+        // D moves the camera in views, which does not clash with the graph
+        assert_eq!(shortcuts.conflict(Action::DeleteNode, key(Key::D)), None);
+        assert_eq!(
+            shortcuts.conflict(Action::MeshUp, key(Key::D)),
+            Some(Action::MeshRight)
+        );
+        assert_eq!(shortcuts.conflict(Action::MeshRight, key(Key::D)), None);
+
+        let json = serde_json::to_string(&shortcuts).unwrap();
+        assert!(json.contains("graph.delete_node"), "{}", json);
+        assert!(!json.contains("mesh.up"), "{}", json);
+        assert_eq!(serde_json::from_str::<Shortcuts>(&json).unwrap(), shortcuts);
+
+        // Unknown actions are ignored
+        let json = r#"{ "graph.removed": { "modifiers": { "alt": false, "ctrl": false, "shift": false, "mac_cmd": false, "command": false }, "logical_key": "A" } }"#;
+        assert_eq!(
+            serde_json::from_str::<Shortcuts>(json).unwrap(),
+            Shortcuts::DEFAULT
+        );
+
+        shortcuts.set(Action::DeleteNode, None);
+        assert_eq!(shortcuts, Shortcuts::DEFAULT);
+    }
+}
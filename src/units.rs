@@ -0,0 +1,245 @@
+// Presentation of numbers and lengths to the user.
+//
+// Values in pipelines and settings are always stored canonically, with a dot
+// as decimal separator and lengths in millimetres. Only what is displayed and
+// typed in by the user is converted.
+
+use std::ops::RangeInclusive;
+
+use egui::emath;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+/// Number of millimetres in one mil (a thousandth of an inch).
+const MM_PER_MIL: f64 = 0.0254;
+
+// MARK: NumberFormat
+
+/// How numbers are displayed to the user.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NumberFormat {
+    pub decimal_separator: DecimalSeparator,
+    pub length_unit: LengthUnit,
+}
+
+impl NumberFormat {
+    pub const DEFAULT: NumberFormat = NumberFormat {
+        decimal_separator: DecimalSeparator::Dot,
+        length_unit: LengthUnit::Millimetre,
+    };
+
+    /// The number format currently in effect.
+    pub fn current() -> Self {
+        Settings::current().display.number_format
+    }
+
+    /// Formats a number with as few decimals in the range as possible,
+    /// without losing precision.
+    pub fn number(&self, value: f64, decimals: RangeInclusive<usize>) -> String {
+        self.decimal_separator
+            .localize(emath::format_with_decimals_in_range(value, decimals))
+    }
+
+    /// Formats a length in millimetres in the configured unit, including the
+    /// unit symbol.
+    pub fn length(&self, mm: f32, decimals: Option<usize>) -> String {
+        // Convert in f32, so the shortest representation is not cluttered
+        // with digits below the precision of the value
+        let value = self.length_unit.to_unit(mm as f64) as f32;
+        let text = match decimals {
+            Some(decimals) => format!("{:.*}", decimals, value),
+            None => format!("{}", value),
+        };
+        format!(
+            "{} {}",
+            self.decimal_separator.localize(text),
+            self.length_unit.symbol()
+        )
+    }
+
+    /// Formats an amount of bytes with a decimal prefix, like "1.4 GB".
+    pub fn bytes(&self, bytes: f64) -> String {
+        const PREFIXES: [&str; 5] = ["", "K", "M", "G", "T"];
+
+        let mut value = bytes;
+        let mut prefix = 0;
+        while value.abs() >= 1000.0 && prefix < PREFIXES.len() - 1 {
+            value /= 1000.0;
+            prefix += 1;
+        }
+
+        let decimals = match prefix > 0 && value.abs() < 10.0 {
+            true => 1,
+            false => 0,
+        };
+
+        format!(
+            "{} {}B",
+            self.decimal_separator
+                .localize(format!("{:.*}", decimals, value)),
+            PREFIXES[prefix]
+        )
+    }
+
+    /// Formats a count with grouped thousands, like "1,234". The groups are
+    /// separated by whichever of dot and comma is not the decimal separator.
+    pub fn count(&self, count: usize) -> String {
+        let separator = match self.decimal_separator {
+            DecimalSeparator::Dot => ",",
+            DecimalSeparator::Comma => ".",
+        };
+
+        let digits = count.to_string();
+        digits
+            .as_bytes()
+            .rchunks(3)
+            .rev()
+            .map(|group| std::str::from_utf8(group).unwrap())
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    /// Separator between the fields of a line in exported text files. A comma
+    /// is ambiguous next to a decimal comma.
+    pub fn field_separator(&self) -> &'static str {
+        match self.decimal_separator {
+            DecimalSeparator::Dot => ", ",
+            DecimalSeparator::Comma => "; ",
+        }
+    }
+}
+
+// MARK: DecimalSeparator
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecimalSeparator {
+    #[default]
+    Dot,
+    Comma,
+}
+
+impl DecimalSeparator {
+    pub const VALUES: [DecimalSeparator; 2] = [DecimalSeparator::Dot, DecimalSeparator::Comma];
+
+    /// Replaces the dot of a number formatted by Rust with this separator.
+    pub fn localize(self, formatted: String) -> String {
+        match self {
+            DecimalSeparator::Dot => formatted,
+            DecimalSeparator::Comma => formatted.replace('.', ","),
+        }
+    }
+}
+
+/// Parses a number typed in by the user. Both a dot and a comma are accepted
+/// as decimal separator, independent of the configured one. Returns [None]
+/// for ambiguous input with both separators, like "1.000,5".
+pub fn parse_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+
+    if text.contains('.') && text.contains(',') {
+        return None;
+    }
+
+    text.replacen(',', ".", 1).parse().ok()
+}
+
+// MARK: LengthUnit
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LengthUnit {
+    #[default]
+    Millimetre,
+    Micrometre,
+    Mil,
+}
+
+impl LengthUnit {
+    pub const VALUES: [LengthUnit; 3] = [
+        LengthUnit::Millimetre,
+        LengthUnit::Micrometre,
+        LengthUnit::Mil,
+    ];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            LengthUnit::Millimetre => "mm",
+            LengthUnit::Micrometre => "µm",
+            LengthUnit::Mil => "mil",
+        }
+    }
+
+    fn mm_per_unit(self) -> f64 {
+        match self {
+            LengthUnit::Millimetre => 1.0,
+            LengthUnit::Micrometre => 0.001,
+            LengthUnit::Mil => MM_PER_MIL,
+        }
+    }
+
+    /// Converts a length in millimetres into this unit.
+    pub fn to_unit(self, mm: f64) -> f64 {
+        mm / self.mm_per_unit()
+    }
+
+    /// Converts a length in this unit into millimetres.
+    pub fn to_mm(self, value: f64) -> f64 {
+        value * self.mm_per_unit()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unit_roundtrip() {
+        for unit in LengthUnit::VALUES {
+            for mm in [0.0, 0.0055, 1.0, 2.1791291, 1234.5] {
+                let back = unit.to_mm(unit.to_unit(mm));
+                assert!((back - mm).abs() < 1e-12, "{:?}: {} != {}", unit, back, mm);
+            }
+        }
+
+        assert!((LengthUnit::Micrometre.to_unit(1.25) - 1250.0).abs() < 1e-9);
+        assert!((LengthUnit::Mil.to_unit(0.0254) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn parse_both_separators() {
+        assert_eq!(parse_number("1.25"), Some(1.25));
+        assert_eq!(parse_number("1,25"), Some(1.25));
+        assert_eq!(parse_number(" -0,5 "), Some(-0.5));
+        assert_eq!(parse_number("3"), Some(3.0));
+
+        assert_eq!(parse_number("1.000,5"), None);
+        assert_eq!(parse_number("1,2,3"), None);
+        assert_eq!(parse_number(""), None);
+    }
+
+    #[test]
+    fn format_roundtrip() {
+        let format = NumberFormat {
+            decimal_separator: DecimalSeparator::Comma,
+            length_unit: LengthUnit::Micrometre,
+        };
+
+        assert_eq!(format.number(1.25, 0..=6), "1,25");
+        assert_eq!(format.number(1.26, 1..=1), "1,3");
+        assert_eq!(format.length(2.5, Some(0)), "2500 µm");
+
+        assert_eq!(format.bytes(512.0), "512 B");
+        assert_eq!(format.bytes(1.4e9), "1,4 GB");
+
+        assert_eq!(format.count(1234567), "1.234.567");
+        assert_eq!(NumberFormat::DEFAULT.count(1234), "1,234");
+        assert_eq!(NumberFormat::DEFAULT.count(123), "123");
+        assert_eq!(NumberFormat::DEFAULT.count(0), "0");
+        assert_eq!(format.bytes(210e6), "210 MB");
+
+        for value in [0.1, 1.25, -3.75, 1e-4] {
+            assert_eq!(parse_number(&format.number(value, 0..=15)), Some(value));
+        }
+    }
+}
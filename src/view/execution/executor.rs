@@ -1,5 +1,5 @@
 use core::fmt;
-use std::{collections::HashMap, panic};
+use std::{any::Any, collections::HashMap, panic};
 
 use futures::{future::select_all, FutureExt};
 use tokio::sync::{mpsc, watch};
@@ -8,8 +8,10 @@ use vec_collections::{AbstractVecMap, VecMap};
 use crate::{
     node_graph::{InputId, NodeOutput},
     pipeline::{
-        execution::{ConnectionHandle, InvalidationCause, InvalidationNotifier},
-        PipelineExecutor,
+        execution::{
+            ConnectionHandle, InvalidationCause, InvalidationNotifier, Shutdown, TaskToken,
+        },
+        requests, PipelineExecutor,
     },
     view::{views::DynDataView, DataViewsState, ViewId},
 };
@@ -24,15 +26,24 @@ use super::DynDataViewTask;
 /// to tasks in the pipelines execution system.
 pub struct ViewsExecutor {
     runners: HashMap<ViewId, ViewTaskRunner>,
+    /// Held by every task spawned since the last [Self::shutdown].
+    tasks: TaskToken,
 }
 
 impl ViewsExecutor {
     pub fn new() -> Self {
         Self {
             runners: HashMap::new(),
+            tasks: TaskToken::default(),
         }
     }
 
+    /// Stops every view task. See [PipelineExecutor::shutdown].
+    pub fn shutdown(&mut self) -> Shutdown {
+        self.runners.clear();
+        self.tasks.shutdown()
+    }
+
     pub fn update(
         &mut self,
         views_state: &mut DataViewsState,
@@ -42,11 +53,18 @@ impl ViewsExecutor {
         self.runners
             .retain(|id, _| views_state.views.contains_key(id));
 
+        // Views to retry get a new runner below
+        for view_id in views_state.to_recreate.drain() {
+            self.runners.remove(&view_id);
+        }
+
         // New views
         for (view_id, view) in &mut views_state.views {
             if !self.runners.contains_key(view_id) {
-                self.runners
-                    .insert(*view_id, ViewTaskRunner::from_view(view.as_mut()));
+                self.runners.insert(
+                    *view_id,
+                    ViewTaskRunner::from_view(view.as_mut(), &self.tasks),
+                );
             }
         }
 
@@ -60,6 +78,19 @@ impl ViewsExecutor {
             runner.sync_connections(view.as_ref(), pipeline_executor);
             runner.sync_view(view.as_ref());
         }
+
+        // Failures
+        views_state
+            .failures
+            .retain(|id, _| self.runners.contains_key(id));
+        for (view_id, runner) in &mut self.runners {
+            if let Ok(true) = runner.failure_rx.has_changed() {
+                match runner.failure_rx.borrow_and_update().clone() {
+                    Some(failure) => views_state.failures.insert(*view_id, failure),
+                    None => views_state.failures.remove(view_id),
+                };
+            }
+        }
     }
 }
 
@@ -77,22 +108,27 @@ struct ViewTaskRunner {
     inputs: VecMap<[(InputId, NodeOutput); 4]>,
     control_tx: mpsc::UnboundedSender<ControlMsg>,
     sync_tx: watch::Sender<Box<dyn DynDataView>>,
+    /// Error message of the last run of the task, if it failed.
+    failure_rx: watch::Receiver<Option<String>>,
 }
 
 impl ViewTaskRunner {
-    pub fn from_view(view: &mut dyn DynDataView) -> Self {
+    pub fn from_view(view: &mut dyn DynDataView, tasks: &TaskToken) -> Self {
         let task = view.create_view_task();
 
         let (control_tx, control_rx) = mpsc::unbounded_channel();
         let (sync_tx, sync_rx) = watch::channel(view.clone_boxed());
+        let (failure_tx, failure_rx) = watch::channel(None);
 
         tokio::spawn(
             RunningViewTask {
                 task,
                 control_rx,
                 sync_rx,
+                failure_tx,
                 input_connections: Vec::new(),
                 error_on_last_run: false,
+                _token: tasks.clone(),
             }
             .run(),
         );
@@ -101,6 +137,7 @@ impl ViewTaskRunner {
             inputs: VecMap::empty(),
             control_tx,
             sync_tx,
+            failure_rx,
         }
     }
 
@@ -177,8 +214,11 @@ struct RunningViewTask {
     task: Box<dyn DynDataViewTask>,
     control_rx: mpsc::UnboundedReceiver<ControlMsg>,
     sync_rx: watch::Receiver<Box<dyn DynDataView>>,
+    failure_tx: watch::Sender<Option<String>>,
     input_connections: Vec<(InputId, InvalidationNotifier)>,
     error_on_last_run: bool,
+    /// Dropped, when the task ends.
+    _token: TaskToken,
 }
 
 impl RunningViewTask {
@@ -195,6 +235,12 @@ impl RunningViewTask {
                             if connection.did_connect() {
                                 self.input_connections.push((input_id, connection.get_invalidation_notifier()));
                                 self.invalidate(InvalidationCause::Connected(input_id));
+                            } else if let Some(expected) = connection.rejected_by() {
+                                eprintln!(
+                                    "Failed to connect view input {:?}: {}",
+                                    input_id,
+                                    requests::describe_mismatch(expected, connection.request_type())
+                                );
                             }
                         }
                         Some(ControlMsg::Disconnect(input_id)) => {
@@ -212,44 +258,44 @@ impl RunningViewTask {
                 input_id = Self::on_invalidation(&mut self.input_connections) => {
                     self.invalidate(InvalidationCause::InputInvalidated(input_id));
                 }
-                is_error = Self::run_task(self.error_on_last_run, self.task.as_mut()) => {
-                    self.error_on_last_run = is_error;
+                failure = Self::run_task(self.error_on_last_run, self.task.as_mut()) => {
+                    self.error_on_last_run = failure.is_some();
+                    if failure.is_some() {
+                        let _ = self.failure_tx.send(failure);
+                    }
                 }
             }
         }
     }
 
-    async fn run_task(error_on_last_run: bool, task: &mut dyn DynDataViewTask) -> bool {
+    /// Runs the task, returning an error message, if it failed or panicked.
+    async fn run_task(error_on_last_run: bool, task: &mut dyn DynDataViewTask) -> Option<String> {
         if error_on_last_run {
             let () = futures::future::pending().await;
         }
 
         let result = panic::AssertUnwindSafe(task.run()).catch_unwind().await;
 
-        let is_error = !matches!(result, Ok(Ok(_)));
-
         match result {
-            Ok(Ok(_)) => {}
-            Ok(Err(e)) => eprintln!("Task failed: {:?}", e),
-            Err(e) => eprintln!(
-                "Task panicked: {}",
-                if let Some(msg) = e.downcast_ref::<&'static str>() {
-                    msg.to_string()
-                } else if let Some(msg) = e.downcast_ref::<String>() {
-                    msg.clone()
-                } else {
-                    format!("?{:?}", e)
-                }
-            ),
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => {
+                eprintln!("Task failed: {:?}", e);
+                Some(format!("{:#}", e))
+            }
+            Err(e) => {
+                let msg = panic_message(e.as_ref());
+                eprintln!("Task panicked: {}", msg);
+                Some(format!("The view panicked: {}", msg))
+            }
         }
-
-        is_error
     }
 
     fn invalidate(&mut self, cause: InvalidationCause) {
         self.task.invalidate(cause);
 
         self.error_on_last_run = false;
+        self.failure_tx
+            .send_if_modified(|failure| failure.take().is_some());
     }
 
     async fn on_invalidation(notifiers: &mut Vec<(InputId, InvalidationNotifier)>) -> InputId {
@@ -272,9 +318,297 @@ impl RunningViewTask {
     }
 }
 
+fn panic_message(e: &(dyn Any + Send)) -> String {
+    if let Some(msg) = e.downcast_ref::<&'static str>() {
+        msg.to_string()
+    } else if let Some(msg) = e.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        format!("?{:?}", e)
+    }
+}
+
 // MARK: ControlMsg
 
 enum ControlMsg {
     Connect(InputId, ConnectionHandle),
     Disconnect(InputId),
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        path::Path,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use serde_json::json;
+
+    use crate::{
+        cache::{Cache, Cached},
+        node_graph::{InputIdNone, InputIdSingle, NodeId},
+        pipeline::{execution::TaskInput, Pipeline},
+        view::{
+            execution::DataViewTask,
+            link::SharedLinkState,
+            live_tuning::SharedLiveTuning,
+            views::{DataView, Existence, ViewResponse},
+        },
+    };
+
+    use super::*;
+
+    /// View, whose first task panics.
+    #[derive(Clone)]
+    struct FakeView {
+        created_tasks: Arc<AtomicUsize>,
+    }
+
+    struct FakeTask {
+        generation: usize,
+    }
+
+    impl DataView for FakeView {
+        type InputId = InputIdNone;
+
+        fn from_node_output(
+            _node_output: &NodeOutput,
+            _pipeline: &Pipeline,
+            _cache: &Cache,
+            _render_state: &eframe::egui_wgpu::RenderState,
+        ) -> Option<Self> {
+            None
+        }
+
+        fn inputs(&self) -> impl Iterator<Item = (Self::InputId, Option<NodeOutput>)> {
+            std::iter::empty()
+        }
+
+        fn changed(&self, _other: &Self) -> bool {
+            false
+        }
+
+        fn connect(&mut self, _node_output: NodeOutput, _pipeline: &Pipeline) -> bool {
+            false
+        }
+
+        fn disconnect(&mut self, _input_id: Self::InputId) -> Existence {
+            Existence::Keep
+        }
+
+        fn create_view_task(
+            &mut self,
+        ) -> impl DataViewTask<InputId = Self::InputId, DataView = Self> {
+            FakeTask {
+                generation: self.created_tasks.fetch_add(1, Ordering::SeqCst) + 1,
+            }
+        }
+
+        fn ui(
+            &mut self,
+            _ui: &mut egui::Ui,
+            _pipeline: &Pipeline,
+            _link: &SharedLinkState,
+            _live_tuning: &SharedLiveTuning,
+        ) -> ViewResponse {
+            ViewResponse::default()
+        }
+    }
+
+    impl DataViewTask for FakeTask {
+        type InputId = InputIdNone;
+        type DataView = FakeView;
+
+        fn connect(&mut self, _input_id: Self::InputId, _input: &mut ConnectionHandle) {}
+
+        fn disconnect(&mut self, _input_id: Self::InputId) {}
+
+        async fn run(&mut self) -> anyhow::Result<()> {
+            if self.generation == 1 {
+                panic!("Fake task panicked");
+            }
+            futures::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn panicking_task_is_retryable() {
+        let created_tasks = Arc::new(AtomicUsize::new(0));
+
+        let mut views_state = DataViewsState::new();
+        let view_id = views_state.add_view(Box::new(FakeView {
+            created_tasks: created_tasks.clone(),
+        }));
+
+        let pipeline_executor = PipelineExecutor::new();
+        let mut executor = ViewsExecutor::new();
+
+        let mut failure = None;
+        for _ in 0..100 {
+            executor.update(&mut views_state, &pipeline_executor);
+            failure = views_state.failure(view_id).map(str::to_string);
+            if failure.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let failure = failure.expect("Panic should be recorded");
+        assert!(failure.contains("Fake task panicked"), "{}", failure);
+
+        views_state.retry(view_id);
+        assert!(views_state.failure(view_id).is_none());
+
+        executor.update(&mut views_state, &pipeline_executor);
+        assert_eq!(created_tasks.load(Ordering::SeqCst), 2);
+
+        // The new task keeps running
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        executor.update(&mut views_state, &pipeline_executor);
+        assert!(views_state.failure(view_id).is_none());
+    }
+
+    /// View of a raw M scan, whose task keeps the M scan in the cache, like
+    /// views uploading textures.
+    #[derive(Clone)]
+    struct CachedView {
+        raw_m_scan: NodeOutput,
+        cached: Cached<usize>,
+    }
+
+    struct CachedTask {
+        raw_m_scan: TaskInput<requests::RawMScan>,
+        cached: Cached<usize>,
+    }
+
+    impl DataView for CachedView {
+        type InputId = InputIdSingle;
+
+        fn from_node_output(
+            _node_output: &NodeOutput,
+            _pipeline: &Pipeline,
+            _cache: &Cache,
+            _render_state: &eframe::egui_wgpu::RenderState,
+        ) -> Option<Self> {
+            None
+        }
+
+        fn inputs(&self) -> impl Iterator<Item = (Self::InputId, Option<NodeOutput>)> {
+            std::iter::once((InputIdSingle, Some(self.raw_m_scan)))
+        }
+
+        fn changed(&self, _other: &Self) -> bool {
+            false
+        }
+
+        fn connect(&mut self, node_output: NodeOutput, _pipeline: &Pipeline) -> bool {
+            self.raw_m_scan = node_output;
+            true
+        }
+
+        fn disconnect(&mut self, _input_id: Self::InputId) -> Existence {
+            Existence::Destroy
+        }
+
+        fn create_view_task(
+            &mut self,
+        ) -> impl DataViewTask<InputId = Self::InputId, DataView = Self> {
+            CachedTask {
+                raw_m_scan: TaskInput::default(),
+                cached: self.cached.clone(),
+            }
+        }
+
+        fn ui(
+            &mut self,
+            _ui: &mut egui::Ui,
+            _pipeline: &Pipeline,
+            _link: &SharedLinkState,
+            _live_tuning: &SharedLiveTuning,
+        ) -> ViewResponse {
+            ViewResponse::default()
+        }
+    }
+
+    impl DataViewTask for CachedTask {
+        type InputId = InputIdSingle;
+        type DataView = CachedView;
+
+        fn connect(&mut self, _input_id: Self::InputId, input: &mut ConnectionHandle) {
+            self.raw_m_scan.connect(input);
+        }
+
+        fn disconnect(&mut self, _input_id: Self::InputId) {
+            self.raw_m_scan.disconnect();
+        }
+
+        async fn run(&mut self) -> anyhow::Result<()> {
+            if let Some(m_scan) = self.raw_m_scan.request(requests::RawMScan).await {
+                self.cached.replace(m_scan.a_scan_samples);
+            }
+            futures::future::pending().await
+        }
+    }
+
+    fn input_pipeline() -> Pipeline {
+        let raw = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden/raw.bin");
+        serde_json::from_value(json!({ "nodes": { "1": {
+            "type": "binary_input", "path": raw, "input_type": "RawMScan",
+            "data_type": "U16", "a_scan_length": 256 } } }))
+        .unwrap()
+    }
+
+    /// Loading a pipeline over a running one, like the app does.
+    #[tokio::test]
+    async fn replace_running_pipeline() {
+        let cache = Cache::new();
+        let baseline = cache.live_entries();
+
+        let mut pipeline = input_pipeline();
+        let cached = cache.get::<usize>("raw");
+        let mut views_state = DataViewsState::new();
+        views_state.add_view(Box::new(CachedView {
+            raw_m_scan: NodeOutput {
+                node_id: NodeId::from(1),
+                output_id: 0.into(),
+                type_id: 0.into(),
+            },
+            cached: cached.clone(),
+        }));
+
+        let mut pipeline_executor = PipelineExecutor::new();
+        let mut executor = ViewsExecutor::new();
+        for _ in 0..200 {
+            pipeline_executor.update(&mut pipeline);
+            executor.update(&mut views_state, &pipeline_executor);
+            if *cached.read() == 256 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(*cached.read(), 256, "The view should have received data");
+        drop(cached);
+        assert_eq!(cache.live_entries(), baseline + 1);
+
+        // Tear down the old pipeline, before loading the new one
+        views_state.clear();
+        let shutdown = pipeline_executor.shutdown().and(executor.shutdown());
+        cache.clear();
+        tokio::time::timeout(Duration::from_secs(5), shutdown.wait())
+            .await
+            .expect("Old tasks should stop");
+
+        assert_eq!(cache.live_entries(), baseline);
+
+        // The new pipeline only runs its own tasks
+        let mut pipeline = input_pipeline();
+        pipeline_executor.update(&mut pipeline);
+        executor.update(&mut views_state, &pipeline_executor);
+        assert!(shutdown.is_finished());
+        assert_eq!(pipeline_executor.shutdown().running(), 1);
+    }
+}
@@ -0,0 +1,131 @@
+//! Selection shared between data views, so reviewing one view moves the
+//! others along.
+//!
+//! Every view opts in with its own [ViewLink]. Unlinked views neither publish
+//! nor receive selections.
+
+use std::sync::{Arc, RwLock};
+
+/// Shared by all data views, see [super::DataViewsState::link].
+pub type SharedLinkState = Arc<RwLock<LinkState>>;
+
+#[derive(Debug, Default)]
+pub struct LinkState {
+    /// Index of the selected B scan.
+    b_scan: Option<usize>,
+    /// Incremented on every selection, so views can tell new selections
+    /// apart from the ones they have seen already.
+    generation: u64,
+}
+
+// MARK: ViewLink
+
+/// Participation of a single view in the [LinkState].
+#[derive(Debug, Default, Clone)]
+pub struct ViewLink {
+    enabled: bool,
+    seen_generation: u64,
+    /// The B scan last passed to [Self::track].
+    tracked: Option<usize>,
+}
+
+impl ViewLink {
+    /// Toggle for the toolbar of a view.
+    pub fn toggle_ui(&mut self, ui: &mut egui::Ui) -> egui::Response {
+        ui.toggle_value(&mut self.enabled, "🔗")
+            .on_hover_text("Link the selected B scan with other linked views")
+    }
+
+    /// Returns the B scan, if another view selected one since the last call.
+    pub fn receive(&mut self, link: &SharedLinkState) -> Option<usize> {
+        if !self.enabled {
+            return None;
+        }
+
+        let state = link.read().unwrap();
+        if state.generation == self.seen_generation {
+            return None;
+        }
+        self.seen_generation = state.generation;
+
+        // The view moves to the received B scan, so it is not a change by
+        // the user
+        self.tracked = None;
+
+        state.b_scan
+    }
+
+    /// The currently selected B scan, while linked.
+    pub fn b_scan(&self, link: &SharedLinkState) -> Option<usize> {
+        match self.enabled {
+            true => link.read().unwrap().b_scan,
+            false => None,
+        }
+    }
+
+    /// Selects a B scan in all linked views, e.g. after a click.
+    pub fn select(&mut self, link: &SharedLinkState, b_scan: usize) {
+        self.tracked = Some(b_scan);
+
+        if !self.enabled {
+            return;
+        }
+
+        let mut state = link.write().unwrap();
+        state.b_scan = Some(b_scan);
+        state.generation += 1;
+        self.seen_generation = state.generation;
+    }
+
+    /// Selects the B scan shown by a view, that is scrolled continuously. Only
+    /// changes since the last call are published.
+    pub fn track(&mut self, link: &SharedLinkState, b_scan: usize) {
+        match self.tracked {
+            Some(tracked) if tracked != b_scan => self.select(link, b_scan),
+            _ => self.tracked = Some(b_scan),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn linked() -> ViewLink {
+        ViewLink {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn views_follow_selection() {
+        let state = SharedLinkState::default();
+
+        let mut plot = linked();
+        let mut m_scan = linked();
+        let mut unlinked = ViewLink::default();
+
+        plot.select(&state, 12);
+        assert_eq!(plot.receive(&state), None);
+        assert_eq!(m_scan.receive(&state), Some(12));
+        assert_eq!(m_scan.receive(&state), None);
+        assert_eq!(unlinked.receive(&state), None);
+        assert_eq!(unlinked.b_scan(&state), None);
+
+        // Moving to the received B scan, or clamping it, is not published
+        m_scan.track(&state, 10);
+        m_scan.track(&state, 10);
+        assert_eq!(plot.receive(&state), None);
+        assert_eq!(plot.b_scan(&state), Some(12));
+
+        // Scrolling is published
+        m_scan.track(&state, 11);
+        assert_eq!(plot.receive(&state), Some(11));
+        assert_eq!(state.read().unwrap().generation, 2);
+
+        unlinked.select(&state, 3);
+        unlinked.track(&state, 4);
+        assert_eq!(state.read().unwrap().b_scan, Some(11));
+    }
+}
@@ -0,0 +1,330 @@
+//! Live tuning: While a view asks for it, changed settings of the nodes
+//! producing its M scan are first applied to the A scans the view shows. The
+//! full pipeline runs with the new settings only once the user stopped
+//! editing for [DEBOUNCE], or live tuning gets switched off.
+//!
+//! Views register the shown A scans every frame with
+//! [LiveTuningState::request]. The [LiveTuningRunner] defers syncing the
+//! changed nodes, runs them on the requested A scans with a [PartialRun] and
+//! publishes the results.
+
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    ops::Range,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    node_graph::{NodeId, NodeOutput},
+    pipeline::{
+        nodes::DynPipelineNode,
+        partial_run::{partial_chain, PartialRun},
+        sweep::PreviewState,
+        types::DataMatrix,
+        Pipeline, PipelineExecutor,
+    },
+};
+
+/// How long settings have to stay unchanged, before the full pipeline runs
+/// with them.
+pub const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Shared by all data views, see [super::DataViewsState::live_tuning].
+pub type SharedLiveTuning = Arc<RwLock<LiveTuningState>>;
+
+#[derive(Debug, Default)]
+pub struct LiveTuningState {
+    requests: HashMap<NodeOutput, LiveRequest>,
+    /// Whether changed settings are waiting for the full pipeline run.
+    deferring: bool,
+    /// Id of the next published [PartialResult].
+    next_id: u64,
+}
+
+#[derive(Debug)]
+struct LiveRequest {
+    a_scans: Range<usize>,
+    /// Renewed since the last [LiveTuningRunner::update].
+    touched: bool,
+    result: Option<PartialResult>,
+}
+
+/// Result of running changed settings on some A scans of an M scan.
+#[derive(Debug, Clone)]
+pub struct PartialResult {
+    /// Increasing with every published result, so views can tell new results
+    /// apart from the ones they applied already.
+    pub id: u64,
+    pub a_scans: Range<usize>,
+    pub data: Arc<DataMatrix>,
+}
+
+impl LiveTuningState {
+    /// Asks to preview changed settings on `a_scans` of `m_scan`. Has to be
+    /// called every frame, otherwise the request is dropped. Returns the
+    /// latest result for `m_scan`.
+    pub fn request(&mut self, m_scan: NodeOutput, a_scans: Range<usize>) -> Option<PartialResult> {
+        let request = self.requests.entry(m_scan).or_insert_with(|| LiveRequest {
+            a_scans: a_scans.clone(),
+            touched: true,
+            result: None,
+        });
+        request.a_scans = a_scans;
+        request.touched = true;
+
+        request.result.clone()
+    }
+
+    /// Whether changed settings are only previewed so far.
+    pub fn is_deferring(&self) -> bool {
+        self.deferring
+    }
+}
+
+// MARK: LiveTuningRunner
+
+/// Runs the requests of the [LiveTuningState]. Replaces
+/// [PipelineExecutor::update] while any view requests live tuning.
+#[derive(Default)]
+pub struct LiveTuningRunner {
+    /// Copies of the deferred nodes, to tell further edits apart.
+    deferred: HashMap<NodeId, Box<dyn DynPipelineNode>>,
+    /// Last edit of a deferred node.
+    last_edit: Option<Instant>,
+    runs: HashMap<NodeOutput, RunningPreview>,
+}
+
+struct RunningPreview {
+    run: PartialRun,
+    published: bool,
+}
+
+impl LiveTuningRunner {
+    /// Syncs the pipeline with `executor`, except for changed nodes, that a
+    /// live tuning request depends on. Returns true, while such nodes are
+    /// deferred, so the caller keeps repainting.
+    pub fn update(
+        &mut self,
+        executor: &mut PipelineExecutor,
+        pipeline: &mut Pipeline,
+        state: &SharedLiveTuning,
+    ) -> bool {
+        let mut state = state.write().unwrap();
+
+        // Views, that closed or switched live tuning off, stop renewing their
+        // requests
+        state
+            .requests
+            .retain(|_, request| mem::take(&mut request.touched));
+        self.runs
+            .retain(|m_scan, _| state.requests.contains_key(m_scan));
+
+        let changed = match state.requests.is_empty() {
+            true => HashSet::new(),
+            false => executor.changed_nodes(pipeline),
+        };
+
+        let chains: HashMap<_, _> = state
+            .requests
+            .keys()
+            .filter_map(|m_scan| Some((*m_scan, partial_chain(pipeline, *m_scan, &changed)?)))
+            .collect();
+
+        let deferred: HashSet<NodeId> = chains
+            .values()
+            .flat_map(|(_, chain)| chain.iter().map(|link| link.node_id))
+            .filter(|node_id| changed.contains(node_id))
+            .collect();
+
+        let now = Instant::now();
+        let settled = self
+            .last_edit
+            .is_some_and(|last_edit| now - last_edit >= DEBOUNCE);
+
+        if deferred.is_empty() || settled {
+            // The full run replaces all previews
+            let changed = match self.deferred.is_empty() {
+                true => changed,
+                false => executor.changed_nodes(pipeline),
+            };
+            executor.update(pipeline);
+
+            // Nodes edited back to the settings their task runs with are not
+            // invalidated by the update, so views would keep their previews
+            for node_id in self.deferred.keys() {
+                if !changed.contains(node_id) {
+                    executor.recreate_node(*node_id, pipeline);
+                }
+            }
+
+            self.deferred.clear();
+            self.last_edit = None;
+            self.runs.clear();
+            for request in state.requests.values_mut() {
+                request.result = None;
+            }
+            state.deferring = false;
+
+            return false;
+        }
+
+        executor.update_deferring(pipeline, &deferred);
+        state.deferring = true;
+
+        let edited = deferred.len() != self.deferred.len()
+            || deferred
+                .iter()
+                .any(|node_id| match self.deferred.get(node_id) {
+                    Some(copy) => copy.changed(&pipeline[*node_id]),
+                    None => true,
+                });
+
+        if edited {
+            self.deferred = deferred
+                .iter()
+                .map(|node_id| (*node_id, pipeline[*node_id].clone_boxed()))
+                .collect();
+            self.last_edit = Some(now);
+            self.runs.clear();
+        }
+
+        for (m_scan, (source, chain)) in chains {
+            let a_scans = state.requests[&m_scan].a_scans.clone();
+
+            let current = self.runs.get(&m_scan).map(|r| r.run.a_scans());
+            if current == Some(a_scans.clone()) || a_scans.is_empty() {
+                continue;
+            }
+
+            let Some(input) = executor.get_output(source.node_id, source.output_id) else {
+                continue;
+            };
+
+            self.runs.insert(
+                m_scan,
+                RunningPreview {
+                    run: PartialRun::start(executor, input, chain, a_scans),
+                    published: false,
+                },
+            );
+        }
+
+        for (m_scan, preview) in &mut self.runs {
+            if preview.published {
+                continue;
+            }
+
+            match preview.run.state() {
+                PreviewState::Running(_) => {}
+                PreviewState::Done(data) => {
+                    preview.published = true;
+
+                    let start = preview.run.a_scans().start;
+                    let result = PartialResult {
+                        id: state.next_id,
+                        a_scans: start..start + data.ncols(),
+                        data,
+                    };
+                    state.next_id += 1;
+
+                    if let Some(request) = state.requests.get_mut(m_scan) {
+                        request.result = Some(result);
+                    }
+                }
+                PreviewState::Failed(e) => {
+                    preview.published = true;
+                    eprintln!("Live tuning preview failed: {}", e);
+                }
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use crate::pipeline::{nodes::filter, PipelineDataType};
+
+    use super::*;
+
+    fn filter(pipeline: &mut Pipeline, node_id: usize) -> &mut filter::Node {
+        let node = pipeline.nodes.get_mut(&NodeId::from(node_id)).unwrap();
+        node.as_any_mut().downcast_mut().unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn defers_changed_nodes() {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden/raw.bin");
+        let mut pipeline: Pipeline = serde_json::from_value(json!({
+            "nodes": {
+                "1": {
+                    "type": "binary_input",
+                    "path": source,
+                    "input_type": "MScan",
+                    "data_type": "U16",
+                    "a_scan_length": 256,
+                },
+                "2": {
+                    "type": "filter",
+                    "filter_type": "Gaussian",
+                    "input": {
+                        "value": null,
+                        "connection": { "node_id": 1, "output_id": 1, "type_id": 2 },
+                    },
+                },
+            },
+        }))
+        .unwrap();
+        let m_scan = NodeOutput::new(
+            2.into(),
+            filter::OutputId::Filtered.into(),
+            PipelineDataType::MScan.into(),
+        );
+
+        let mut executor = PipelineExecutor::new();
+        let mut runner = LiveTuningRunner::default();
+        let state = SharedLiveTuning::default();
+
+        // Without requests, everything is synced immediately
+        assert!(!runner.update(&mut executor, &mut pipeline, &state));
+        filter(&mut pipeline, 2).gauss_settings.sigma = 3.0;
+        assert!(!runner.update(&mut executor, &mut pipeline, &state));
+        assert!(executor.changed_nodes(&pipeline).is_empty());
+
+        // With a request, the change is previewed on the requested A scans
+        filter(&mut pipeline, 2).gauss_settings.sigma = 2.0;
+        state.write().unwrap().request(m_scan, 10..20);
+        assert!(runner.update(&mut executor, &mut pipeline, &state));
+        assert!(state.read().unwrap().is_deferring());
+        assert_eq!(executor.changed_nodes(&pipeline), HashSet::from([2.into()]));
+
+        let result = tokio::time::timeout(Duration::from_secs(60), async {
+            loop {
+                let result = state.write().unwrap().request(m_scan, 10..20);
+                runner.update(&mut executor, &mut pipeline, &state);
+                if let Some(result) = result {
+                    break result;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Preview should finish");
+
+        assert_eq!(result.a_scans, 10..20);
+        assert_eq!(result.data.ncols(), 10);
+        assert_eq!(result.data.nrows(), 256);
+
+        // Without renewed request, the full run takes over
+        assert!(!runner.update(&mut executor, &mut pipeline, &state));
+        assert!(!state.read().unwrap().is_deferring());
+        assert!(executor.changed_nodes(&pipeline).is_empty());
+    }
+}
@@ -1,10 +1,18 @@
 pub mod execution;
+pub mod link;
+pub mod live_tuning;
+pub mod reattach;
 pub mod views;
 pub mod views_manager;
 
 use core::fmt;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use crate::node_graph::InputId;
+
+use link::SharedLinkState;
+use live_tuning::SharedLiveTuning;
+use reattach::Source;
 use views::{DataView, DynDataView};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -31,12 +39,29 @@ impl Into<usize> for ViewId {
 /// pipeline.
 pub struct DataViewsState {
     views: HashMap<ViewId, Box<dyn DynDataView>>,
+    /// Error messages of view tasks, that failed or panicked. Maintained by
+    /// the [execution::executor::ViewsExecutor].
+    failures: HashMap<ViewId, String>,
+    /// Views, whose task should be recreated on the next update.
+    to_recreate: HashSet<ViewId>,
+    /// Selection shared between linked views.
+    link: SharedLinkState,
+    /// Previews of changed settings requested by views.
+    live_tuning: SharedLiveTuning,
+    /// Nodes the view inputs are connected to, to reattach them, see
+    /// [reattach].
+    sources: HashMap<(ViewId, InputId), Source>,
 }
 
 impl DataViewsState {
     pub fn new() -> Self {
         Self {
             views: HashMap::new(),
+            failures: HashMap::new(),
+            to_recreate: HashSet::new(),
+            link: SharedLinkState::default(),
+            live_tuning: SharedLiveTuning::default(),
+            sources: HashMap::new(),
         }
     }
 
@@ -61,8 +86,30 @@ impl DataViewsState {
         self.views.get_mut(&view_id).map(|v| v.as_mut())
     }
 
+    pub fn link(&self) -> &SharedLinkState {
+        &self.link
+    }
+
+    pub fn live_tuning(&self) -> &SharedLiveTuning {
+        &self.live_tuning
+    }
+
+    /// Error message, if the task of the view failed or panicked.
+    pub fn failure(&self, view_id: ViewId) -> Option<&str> {
+        self.failures.get(&view_id).map(String::as_str)
+    }
+
+    /// Recreates the task of a failed view on the next update.
+    pub fn retry(&mut self, view_id: ViewId) {
+        self.failures.remove(&view_id);
+        self.to_recreate.insert(view_id);
+    }
+
     pub fn clear(&mut self) {
         self.views.clear();
+        self.failures.clear();
+        self.to_recreate.clear();
+        self.sources.clear();
     }
 }
 
@@ -0,0 +1,415 @@
+//! Keeps data views, whose node got removed, instead of destroying them.
+//!
+//! While a view input is connected, the type and settings of its node are
+//! recorded as a [Source]. When the node disappears, the input stays connected
+//! to the removed output and the view is detached, showing its last data. It
+//! reattaches on its own, as soon as a node with the same type and settings
+//! appears, like after undoing the removal. Otherwise, the user can pick a
+//! compatible output, see [compatible_outputs].
+
+use std::collections::BTreeSet;
+
+use crate::{
+    node_graph::{InputId, NodeOutput, TypeId},
+    pipeline::Pipeline,
+};
+
+use super::{views::Existence, DataViewsState, ViewId};
+
+/// The node a view input was connected to, recorded while it exists.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub output: NodeOutput,
+    /// Name of the node, shown while the view is detached.
+    pub name: String,
+    /// [Pipeline::settings_hash] of the node. Covers the type of the node, so
+    /// only nodes of the same kind match.
+    pub settings_hash: u64,
+}
+
+impl Source {
+    fn of(pipeline: &Pipeline, output: NodeOutput) -> Option<Self> {
+        Some(Self {
+            output,
+            name: pipeline.nodes.get(&output.node_id)?.name().to_string(),
+            settings_hash: pipeline.settings_hash(output.node_id)?,
+        })
+    }
+
+    /// The same output of the most recently added node, that has the same
+    /// type and settings.
+    fn find_match(&self, pipeline: &Pipeline) -> Option<NodeOutput> {
+        let node_id = pipeline
+            .nodes
+            .keys()
+            .copied()
+            .filter(|node_id| pipeline.settings_hash(*node_id) == Some(self.settings_hash))
+            .max()?;
+
+        Some(NodeOutput {
+            node_id,
+            ..self.output
+        })
+    }
+}
+
+/// An input of a view, whose node got removed.
+#[derive(Debug, Clone)]
+pub struct Detached {
+    pub input_id: InputId,
+    pub output: NodeOutput,
+    /// [None], if the node was removed before it could be recorded.
+    pub source: Option<Source>,
+}
+
+/// Outputs of type `type_id`, that are requested by views or connected to
+/// other nodes. Sorted by node.
+pub fn compatible_outputs(pipeline: &Pipeline, type_id: TypeId) -> Vec<NodeOutput> {
+    let mut outputs = BTreeSet::new();
+
+    for (node_id, node) in &pipeline.nodes {
+        if let Some((output_id, output_type)) = node.get_output_for_view_request() {
+            outputs.insert((*node_id, output_id, output_type));
+        }
+        for output in node.inputs().into_iter().filter_map(|(_, output)| output) {
+            if pipeline.nodes.contains_key(&output.node_id) {
+                outputs.insert((output.node_id, output.output_id, output.type_id));
+            }
+        }
+    }
+
+    outputs
+        .into_iter()
+        .filter(|(_, _, output_type)| *output_type == type_id)
+        .map(|(node_id, output_id, type_id)| NodeOutput {
+            node_id,
+            output_id,
+            type_id,
+        })
+        .collect()
+}
+
+impl DataViewsState {
+    /// Records the sources of connected view inputs and reattaches detached
+    /// inputs to matching nodes. Called every update, before the views are
+    /// executed.
+    pub fn update_sources(&mut self, pipeline: &Pipeline) {
+        self.sources
+            .retain(|(view_id, _), _| self.views.contains_key(view_id));
+
+        for (view_id, view) in &mut self.views {
+            for (input_id, output) in view.inputs() {
+                let key = (*view_id, input_id);
+                let Some(output) = output else {
+                    self.sources.remove(&key);
+                    continue;
+                };
+
+                if pipeline.nodes.contains_key(&output.node_id) {
+                    match Source::of(pipeline, output) {
+                        Some(source) => self.sources.insert(key, source),
+                        None => self.sources.remove(&key),
+                    };
+                    continue;
+                }
+
+                let Some(source) = self.sources.get(&key).filter(|s| s.output == output) else {
+                    continue;
+                };
+                if let Some(output) = source.find_match(pipeline) {
+                    view.connect(output, pipeline);
+                }
+            }
+        }
+    }
+
+    /// Inputs of the view, whose node got removed.
+    pub fn detached(&self, view_id: ViewId, pipeline: &Pipeline) -> Vec<Detached> {
+        let Some(view) = self.views.get(&view_id) else {
+            return Vec::new();
+        };
+
+        view.inputs()
+            .into_iter()
+            .filter_map(|(input_id, output)| {
+                let output = output?;
+                (!pipeline.nodes.contains_key(&output.node_id)).then(|| Detached {
+                    input_id,
+                    output,
+                    source: self.sources.get(&(view_id, input_id)).cloned(),
+                })
+            })
+            .collect()
+    }
+
+    /// Connects a detached view to `output`, picked by the user.
+    pub fn reattach(&mut self, view_id: ViewId, output: NodeOutput, pipeline: &Pipeline) -> bool {
+        self.views
+            .get_mut(&view_id)
+            .is_some_and(|view| view.connect(output, pipeline))
+    }
+
+    /// Disconnects a detached input for good. Views, that cannot do without
+    /// it, are removed.
+    pub fn disconnect_detached(&mut self, view_id: ViewId, input_id: InputId) {
+        let Some(view) = self.views.get_mut(&view_id) else {
+            return;
+        };
+
+        self.sources.remove(&(view_id, input_id));
+        if let Existence::Destroy = view.disconnect(input_id) {
+            self.views.remove(&view_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        path::Path,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use serde_json::json;
+
+    use crate::{
+        cache::Cache,
+        node_graph::{InputIdSingle, NodeId},
+        pipeline::{
+            execution::{ConnectionHandle, TaskInput},
+            requests, PipelineExecutor,
+        },
+        view::{
+            execution::{executor::ViewsExecutor, DataViewTask},
+            link::SharedLinkState,
+            live_tuning::SharedLiveTuning,
+            views::{DataView, ViewResponse},
+        },
+    };
+
+    use super::*;
+
+    /// View of a raw M scan, counting the responses of its task.
+    #[derive(Clone)]
+    struct CountingView {
+        raw_m_scan: NodeOutput,
+        responses: Arc<AtomicUsize>,
+    }
+
+    struct CountingTask {
+        raw_m_scan: TaskInput<requests::RawMScan>,
+        responses: Arc<AtomicUsize>,
+    }
+
+    impl DataView for CountingView {
+        type InputId = InputIdSingle;
+
+        fn from_node_output(
+            _node_output: &NodeOutput,
+            _pipeline: &Pipeline,
+            _cache: &Cache,
+            _render_state: &eframe::egui_wgpu::RenderState,
+        ) -> Option<Self> {
+            None
+        }
+
+        fn inputs(&self) -> impl Iterator<Item = (Self::InputId, Option<NodeOutput>)> {
+            std::iter::once((InputIdSingle, Some(self.raw_m_scan)))
+        }
+
+        fn changed(&self, _other: &Self) -> bool {
+            false
+        }
+
+        fn connect(&mut self, node_output: NodeOutput, _pipeline: &Pipeline) -> bool {
+            self.raw_m_scan = node_output;
+            true
+        }
+
+        fn disconnect(&mut self, _input_id: Self::InputId) -> Existence {
+            Existence::Destroy
+        }
+
+        fn create_view_task(
+            &mut self,
+        ) -> impl DataViewTask<InputId = Self::InputId, DataView = Self> {
+            CountingTask {
+                raw_m_scan: TaskInput::default(),
+                responses: self.responses.clone(),
+            }
+        }
+
+        fn ui(
+            &mut self,
+            _ui: &mut egui::Ui,
+            _pipeline: &Pipeline,
+            _link: &SharedLinkState,
+            _live_tuning: &SharedLiveTuning,
+        ) -> ViewResponse {
+            ViewResponse::default()
+        }
+    }
+
+    impl DataViewTask for CountingTask {
+        type InputId = InputIdSingle;
+        type DataView = CountingView;
+
+        fn connect(&mut self, _input_id: Self::InputId, input: &mut ConnectionHandle) {
+            self.raw_m_scan.connect(input);
+        }
+
+        fn disconnect(&mut self, _input_id: Self::InputId) {
+            self.raw_m_scan.disconnect();
+        }
+
+        async fn run(&mut self) -> anyhow::Result<()> {
+            if self.raw_m_scan.request(requests::RawMScan).await.is_some() {
+                self.responses.fetch_add(1, Ordering::SeqCst);
+            }
+            futures::future::pending().await
+        }
+    }
+
+    fn input_node(a_scan_length: usize) -> serde_json::Value {
+        let raw = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden/raw.bin");
+        json!({ "type": "binary_input", "path": raw, "input_type": "RawMScan",
+                "data_type": "U16", "a_scan_length": a_scan_length })
+    }
+
+    /// Updates everything like the app, until `done` or a timeout.
+    async fn update_until(
+        pipeline: &mut Pipeline,
+        executor: &mut PipelineExecutor,
+        views_state: &mut DataViewsState,
+        views_executor: &mut ViewsExecutor,
+        done: impl Fn(&DataViewsState) -> bool,
+    ) -> bool {
+        for _ in 0..200 {
+            executor.update(pipeline);
+            views_state.update_sources(pipeline);
+            views_executor.update(views_state, executor);
+            if done(views_state) {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn reattach_recreated_node() {
+        let mut pipeline: Pipeline =
+            serde_json::from_value(json!({ "nodes": { "1": input_node(256) } })).unwrap();
+        let output = NodeOutput {
+            node_id: NodeId::from(1),
+            output_id: 0.into(),
+            type_id: 0.into(),
+        };
+
+        let responses = Arc::new(AtomicUsize::new(0));
+        let mut views_state = DataViewsState::new();
+        let view_id = views_state.add_view(Box::new(CountingView {
+            raw_m_scan: output,
+            responses: responses.clone(),
+        }));
+
+        let mut executor = PipelineExecutor::new();
+        let mut views_executor = ViewsExecutor::new();
+        let count = |n: usize| {
+            let responses = responses.clone();
+            move |_: &DataViewsState| responses.load(Ordering::SeqCst) >= n
+        };
+
+        assert!(
+            update_until(
+                &mut pipeline,
+                &mut executor,
+                &mut views_state,
+                &mut views_executor,
+                count(1)
+            )
+            .await
+        );
+
+        // Removing the node keeps the view, detached
+        let node = pipeline.nodes.remove(&NodeId::from(1)).unwrap();
+        update_until(
+            &mut pipeline,
+            &mut executor,
+            &mut views_state,
+            &mut views_executor,
+            |_| true,
+        )
+        .await;
+        assert!(views_state.get(view_id).is_some());
+        let detached = views_state.detached(view_id, &pipeline);
+        assert_eq!(detached.len(), 1);
+        assert_eq!(detached[0].source.as_ref().unwrap().output, output);
+
+        // A node with other settings does not match
+        let other: Pipeline =
+            serde_json::from_value(json!({ "nodes": { "2": input_node(128) } })).unwrap();
+        pipeline.nodes.extend(other.nodes);
+        update_until(
+            &mut pipeline,
+            &mut executor,
+            &mut views_state,
+            &mut views_executor,
+            |_| true,
+        )
+        .await;
+        assert_eq!(views_state.detached(view_id, &pipeline).len(), 1);
+        assert_eq!(
+            compatible_outputs(&pipeline, output.type_id),
+            [NodeOutput {
+                node_id: NodeId::from(2),
+                ..output
+            }]
+        );
+
+        // Adding it again resumes streaming without user interaction
+        pipeline.nodes.insert(NodeId::from(3), node);
+        assert!(
+            update_until(
+                &mut pipeline,
+                &mut executor,
+                &mut views_state,
+                &mut views_executor,
+                count(2)
+            )
+            .await
+        );
+        assert!(views_state.detached(view_id, &pipeline).is_empty());
+        assert_eq!(
+            views_state.get(view_id).unwrap().inputs()[0].1,
+            Some(NodeOutput {
+                node_id: NodeId::from(3),
+                ..output
+            })
+        );
+    }
+
+    #[test]
+    fn disconnect_detached() {
+        let mut views_state = DataViewsState::new();
+        let view_id = views_state.add_view(Box::new(CountingView {
+            raw_m_scan: NodeOutput {
+                node_id: NodeId::from(1),
+                output_id: 0.into(),
+                type_id: 0.into(),
+            },
+            responses: Arc::default(),
+        }));
+
+        let pipeline = Pipeline::new();
+        assert_eq!(views_state.detached(view_id, &pipeline).len(), 1);
+        assert!(views_state.detached(view_id, &pipeline)[0].source.is_none());
+
+        views_state.disconnect_detached(view_id, InputIdSingle.into());
+        assert!(views_state.get(view_id).is_none());
+    }
+}
@@ -0,0 +1,313 @@
+//! Camera of the 3D views. Both orbit it around their content: the mesh
+//! view with an [Orbit], the volume view with [Camera::orbit].
+
+use std::f32::consts::PI;
+
+use egui::PointerButton;
+use nalgebra::{Matrix4, Perspective3, Rotation3, Unit, Vector3};
+
+use crate::shortcuts::{self, Action};
+
+/// Vertical field of view in radians.
+const FOV: f32 = PI * 0.5;
+
+/// Largest elevation of an [Orbit], just below the poles, where the view
+/// would flip.
+const MAX_PITCH: f32 = 89.0 * PI / 180.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub position: Vector3<f32>,
+    dir: Unit<Vector3<f32>>,
+    up: Unit<Vector3<f32>>,
+}
+
+impl Camera {
+    pub fn new() -> Self {
+        Self {
+            position: Vector3::new(0.0, 0.0, 0.0),
+            dir: Unit::new_normalize(Vector3::new(1.0, 0.0, 0.0)),
+            up: Unit::new_normalize(Vector3::new(0.0, 1.0, 0.0)),
+        }
+    }
+
+    /// Camera at `position`, looking at `target`.
+    pub fn looking_at(position: Vector3<f32>, target: Vector3<f32>) -> Self {
+        Self {
+            position,
+            dir: Unit::new_normalize(target - position),
+            ..Self::new()
+        }
+    }
+
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        nalgebra::Matrix4::look_at_rh(
+            &(self.position).into(),
+            &(self.position + self.dir.into_inner()).into(),
+            &self.up,
+        )
+    }
+
+    /// Projection and view matrix for drawing into `rect`.
+    pub fn mvp_matrix(&self, rect: egui::Rect) -> Matrix4<f32> {
+        self.mvp_matrix_with_depth(rect, 0.001, 100.0)
+    }
+
+    /// Like [Self::mvp_matrix], drawing from `near` to `far`.
+    fn mvp_matrix_with_depth(&self, rect: egui::Rect, near: f32, far: f32) -> Matrix4<f32> {
+        Perspective3::new(rect.width() / rect.height(), FOV, near, far).as_matrix()
+            * self.view_matrix()
+    }
+
+    /// Turns the camera by a pointer movement of `delta`. It does not tilt
+    /// over the up direction.
+    fn look(&mut self, delta: egui::Vec2) {
+        let delta = Vector3::new(-delta.x, -delta.y, 0.0) * 0.01;
+
+        let right = Unit::new_normalize(self.dir.cross(&self.up));
+        let tilted = Rotation3::from_axis_angle(&right, delta.y) * self.dir;
+        if tilted.dot(&self.up).abs() < 0.99 {
+            self.dir = tilted;
+        }
+
+        self.dir = Rotation3::from_axis_angle(&self.up, delta.x) * self.dir;
+    }
+
+    /// Circles around `target` while dragging, keeping it in the center.
+    /// Scrolling moves closer. Returns, whether the camera moved.
+    pub fn orbit(
+        &mut self,
+        ctx: &egui::Context,
+        response: &egui::Response,
+        target: Vector3<f32>,
+    ) -> bool {
+        let mut distance = (self.position - target).norm();
+        let mut moved = false;
+
+        if response.dragged() {
+            self.look(ctx.input(|r| r.pointer.delta()));
+            moved = true;
+        }
+
+        if let Some(pos) = response.hover_pos() {
+            let scroll = ctx.input(|r| r.smooth_scroll_delta.x + r.smooth_scroll_delta.y);
+            if response.rect.contains(pos) && scroll.abs() > 0.0 {
+                distance = (distance * (-scroll * 0.003).exp()).clamp(0.1, 50.0);
+                moved = true;
+            }
+        }
+
+        if moved {
+            self.position = target - self.dir.into_inner() * distance;
+            ctx.request_repaint();
+        }
+
+        moved
+    }
+}
+
+// MARK: Orbit
+
+/// Camera circling around a target, with the y axis up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Orbit {
+    pub target: Vector3<f32>,
+    /// Angle around the y axis in radians.
+    pub yaw: f32,
+    /// Elevation over the target in radians, within [MAX_PITCH].
+    pub pitch: f32,
+    pub distance: f32,
+}
+
+impl Default for Orbit {
+    fn default() -> Self {
+        Self {
+            target: Vector3::zeros(),
+            yaw: PI * 0.25,
+            pitch: PI * 0.125,
+            distance: 3.0,
+        }
+    }
+}
+
+impl Orbit {
+    /// Looks at the sphere around `center` with `radius` from the default
+    /// direction, so all of it is visible.
+    pub fn fit(center: Vector3<f32>, radius: f32) -> Self {
+        Self {
+            target: center,
+            distance: radius.max(1e-3) / (FOV * 0.5).sin() * 1.1,
+            ..Self::default()
+        }
+    }
+
+    pub fn camera(&self) -> Camera {
+        let offset = Vector3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        );
+        Camera::looking_at(self.target + offset * self.distance, self.target)
+    }
+
+    /// Projection and view matrix for drawing into `rect`. The depth range
+    /// follows the distance, so small and large meshes are not clipped.
+    pub fn mvp_matrix(&self, rect: egui::Rect) -> Matrix4<f32> {
+        self.camera()
+            .mvp_matrix_with_depth(rect, self.distance * 0.001, self.distance * 100.0)
+    }
+
+    /// Turns around the target by a pointer movement of `delta`.
+    fn rotate(&mut self, delta: egui::Vec2) {
+        self.yaw = (self.yaw - delta.x * 0.01) % (2.0 * PI);
+        self.pitch = (self.pitch + delta.y * 0.01).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Moves the target by `delta` points, in the plane of the view. Objects
+    /// at the target follow the pointer in a view `height` points high.
+    fn pan(&mut self, delta: egui::Vec2, height: f32) {
+        let camera = self.camera();
+        let right = camera.dir.cross(&camera.up).normalize();
+        let up = right.cross(&camera.dir).normalize();
+
+        let scale = 2.0 * self.distance * (FOV * 0.5).tan() / height.max(1.0);
+        self.target += (-right * delta.x + up * delta.y) * scale;
+    }
+
+    /// Moves closer for positive `scroll`, by the same factor for every
+    /// step.
+    fn zoom(&mut self, scroll: f32) {
+        self.distance = (self.distance * (-scroll * 0.003).exp()).clamp(1e-4, 1e6);
+    }
+
+    /// Rotates while dragging with the primary button, pans while dragging
+    /// with the middle button or holding shift, and zooms when scrolling.
+    /// The keys of the mesh actions move the target while dragging. Returns,
+    /// whether the camera moved.
+    pub fn update(&mut self, ctx: &egui::Context, response: &egui::Response) -> bool {
+        let before = *self;
+        let delta = ctx.input(|r| r.pointer.delta());
+        let shift = ctx.input(|r| r.modifiers.shift);
+
+        if response.dragged_by(PointerButton::Middle)
+            || (shift && response.dragged_by(PointerButton::Primary))
+        {
+            self.pan(delta, response.rect.height());
+        } else if response.dragged_by(PointerButton::Primary) {
+            self.rotate(delta);
+        }
+
+        if response.dragged() {
+            let (forward, backward, left, right, up, down) = ctx.input(|r| {
+                (
+                    shortcuts::down(r, Action::MeshForward),
+                    shortcuts::down(r, Action::MeshBackward),
+                    shortcuts::down(r, Action::MeshLeft),
+                    shortcuts::down(r, Action::MeshRight),
+                    shortcuts::down(r, Action::MeshUp),
+                    shortcuts::down(r, Action::MeshDown),
+                )
+            });
+
+            let speed = match ctx.input(|r| r.modifiers.ctrl) {
+                true => 0.025,
+                false => 0.01,
+            } * self.distance;
+
+            let camera = self.camera();
+            let forward_dir = Vector3::new(camera.dir.x, 0.0, camera.dir.z).normalize();
+            let right_dir = camera.dir.cross(&camera.up).normalize();
+            let up_dir = camera.up.into_inner();
+
+            for (pressed, dir) in [
+                (forward, forward_dir),
+                (backward, -forward_dir),
+                (left, -right_dir),
+                (right, right_dir),
+                (up, up_dir),
+                (down, -up_dir),
+            ] {
+                if pressed {
+                    self.target += dir * speed;
+                }
+            }
+        }
+
+        if let Some(pos) = response.hover_pos() {
+            let scroll = ctx.input(|r| r.smooth_scroll_delta.x + r.smooth_scroll_delta.y);
+            if response.rect.contains(pos) && scroll.abs() > 0.0 {
+                self.zoom(scroll);
+            }
+        }
+
+        let moved = *self != before;
+        if moved {
+            ctx.request_repaint();
+        }
+        moved
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn look_does_not_tilt_over() {
+        let mut camera = Camera::looking_at(Vector3::new(0.0, 0.0, 3.0), Vector3::zeros());
+
+        for _ in 0..100 {
+            camera.look(egui::vec2(3.0, 20.0));
+            assert!(camera.dir.dot(&camera.up).abs() < 0.99);
+            assert!(camera.view_matrix().iter().all(|v| v.is_finite()));
+        }
+    }
+
+    #[test]
+    fn orbit_does_not_flip() {
+        let mut orbit = Orbit::default();
+
+        for delta in [egui::vec2(5.0, 500.0), egui::vec2(-5.0, -500.0)] {
+            for _ in 0..10 {
+                orbit.rotate(delta);
+                assert!(orbit.pitch.abs() <= MAX_PITCH);
+
+                let camera = orbit.camera();
+                assert!(camera.dir.dot(&camera.up).abs() < 0.9999);
+                assert!(orbit.mvp_matrix(rect()).iter().all(|v| v.is_finite()));
+            }
+        }
+    }
+
+    #[test]
+    fn orbit_fits_and_zooms() {
+        let center = Vector3::new(1.0, 2.0, 30.0);
+        let orbit = Orbit::fit(center, 10.0);
+
+        let camera = orbit.camera();
+        assert!(((camera.position - center).norm() - orbit.distance).abs() < 1e-3);
+        assert!((camera.dir.into_inner() - (center - camera.position).normalize()).norm() < 1e-5);
+        // The whole sphere is in the field of view
+        assert!(orbit.distance * (FOV * 0.5).sin() > 10.0);
+
+        let mut zoomed = orbit;
+        zoomed.zoom(100.0);
+        assert!(zoomed.distance < orbit.distance);
+        zoomed.zoom(-100.0);
+        assert!((zoomed.distance - orbit.distance).abs() < 1e-3);
+    }
+
+    #[test]
+    fn orbit_pans_in_view_plane() {
+        let mut orbit = Orbit::fit(Vector3::zeros(), 1.0);
+        let dir = orbit.camera().dir.into_inner();
+
+        orbit.pan(egui::vec2(40.0, -25.0), 400.0);
+        assert!(orbit.target.norm() > 0.0);
+        assert!(orbit.target.dot(&dir).abs() < 1e-5);
+    }
+
+    fn rect() -> egui::Rect {
+        egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(400.0, 300.0))
+    }
+}
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use anyhow::anyhow;
-use egui_plot::{Line, Plot, PlotPoints};
+use egui_plot::{Line, Plot, PlotPoints, VLine};
 use tokio::sync::watch;
 
 use super::prelude::*;
@@ -12,6 +12,10 @@ pub struct View {
     input: NodeOutput,
 
     data_rx: Option<watch::Receiver<Option<Arc<DataVector>>>>,
+
+    /// Values are usually given per B scan, so the index is linked as B
+    /// scan.
+    link: ViewLink,
 }
 
 /// Renders a plot of a [DataVector].
@@ -28,6 +32,7 @@ impl DataView for View {
             Some(Self {
                 input: *node_output,
                 data_rx: None,
+                link: ViewLink::default(),
             })
         } else {
             None
@@ -66,28 +71,53 @@ impl DataView for View {
         }
     }
 
-    fn ui(&mut self, ui: &mut egui::Ui) {
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        _pipeline: &Pipeline,
+        link: &SharedLinkState,
+        _live_tuning: &SharedLiveTuning,
+    ) -> ViewResponse {
         if let Some(data_rx) = &mut self.data_rx {
             let changed = data_rx.has_changed().unwrap_or(false);
 
             if let Some(data) = data_rx.borrow_and_update().as_ref() {
+                ui.horizontal(|ui| {
+                    self.link.toggle_ui(ui);
+                });
+
                 let mut plot = Plot::new("Data Vector").allow_scroll(false);
 
                 if changed {
                     plot = plot.reset();
                 }
 
-                plot.show(ui, |plot_ui| {
-                    plot_ui.line(Line::new(PlotPoints::from_ys_f32(
-                        data.as_ref().clone().cast().as_slice(),
-                    )));
+                // Only the cursor follows other views
+                self.link.receive(link);
+                let cursor = self.link.b_scan(link);
+
+                let response = plot.show(ui, |plot_ui| {
+                    plot_ui.line(Line::new(PlotPoints::from_ys_f32(data.as_f32().as_slice())));
+
+                    if let Some(cursor) = cursor {
+                        plot_ui.vline(VLine::new(cursor as f64).color(egui::Color32::BLUE));
+                    }
+
+                    plot_ui.pointer_coordinate()
                 });
+
+                if let (true, Some(pointer)) = (response.response.clicked(), response.inner) {
+                    let index = pointer.x.round().clamp(0.0, (data.len().max(1) - 1) as f64);
+                    self.link.select(link, index as usize);
+                }
             } else {
                 ui.label("No data available");
             }
         } else {
             ui.label("No data receiver available");
         }
+
+        ViewResponse::default()
     }
 }
 
@@ -0,0 +1,401 @@
+//! Plot of the minimal, mean and maximal diameter of the lumen over the
+//! pullback, with one value per B scan.
+
+use egui::{pos2, vec2, Align2, Color32, FontId, Rect, Sense, Shape, Stroke};
+use futures::future;
+use tokio::sync::watch;
+
+use crate::{queue_channel::error::RecvError, units::NumberFormat};
+
+use super::prelude::*;
+use types::BScanDiameter;
+
+/// Space around the plot for the axis labels.
+const MARGIN_LEFT: f32 = 64.0;
+const MARGIN_BOTTOM: f32 = 20.0;
+
+/// One of the diameters of a B scan.
+type Value = fn(&BScanDiameter) -> f32;
+
+/// Name, value and color of every line.
+const SERIES: [(&str, Value, Color32); 3] = [
+    ("Max", |d| d.max, Color32::from_rgb(230, 120, 60)),
+    ("Mean", |d| d.mean, Color32::from_rgb(90, 170, 90)),
+    ("Min", |d| d.min, Color32::from_rgb(70, 130, 230)),
+];
+
+/// Diameters received so far, in the order of the B scans.
+#[derive(Debug, Default)]
+struct Diameters {
+    values: Vec<BScanDiameter>,
+    /// Whether the stream is finished.
+    complete: bool,
+}
+
+impl Diameters {
+    /// Range of the y axis, from 0 to a bit above the largest finite
+    /// diameter.
+    fn value_range(&self) -> f32 {
+        let max = self
+            .values
+            .iter()
+            .flat_map(|d| [d.min, d.mean, d.max])
+            .filter(|v| v.is_finite())
+            .fold(0.0, f32::max);
+
+        match max > 0.0 {
+            true => max * 1.1,
+            false => 1.0,
+        }
+    }
+}
+
+/// The B scan closest to `x`, on an axis with `count` B scans spread evenly
+/// from 0 to 1.
+fn nearest_b_scan(x: f32, count: usize) -> Option<usize> {
+    match count {
+        0 => None,
+        _ => Some(((x.clamp(0.0, 1.0) * (count - 1) as f32).round() as usize).min(count - 1)),
+    }
+}
+
+#[derive(Clone)]
+pub struct View {
+    diameter: NodeOutput,
+
+    diameters_rx: Option<watch::Receiver<Diameters>>,
+
+    /// The B scan clicked last.
+    selected: Option<usize>,
+    link: ViewLink,
+}
+
+impl View {
+    /// Draws the plot into the remaining space and handles hovering and
+    /// clicking.
+    fn plot_ui(&mut self, ui: &mut egui::Ui, diameters: &Diameters, link: &SharedLinkState) {
+        let (response, painter) = ui.allocate_painter(ui.available_size(), Sense::click());
+        let plot = Rect::from_min_max(
+            response.rect.min + vec2(MARGIN_LEFT, 8.0),
+            response.rect.max - vec2(8.0, MARGIN_BOTTOM),
+        );
+        if plot.width() <= 0.0 || plot.height() <= 0.0 {
+            return;
+        }
+
+        let visuals = ui.visuals();
+        let format = NumberFormat::current();
+        let font = FontId::proportional(11.0);
+        let count = diameters.values.len();
+        let max = diameters.value_range();
+
+        let x_of = |b_scan: usize| match count {
+            0 | 1 => plot.center().x,
+            _ => plot.left() + plot.width() * b_scan as f32 / (count - 1) as f32,
+        };
+        let y_of = |mm: f32| plot.bottom() - plot.height() * mm / max;
+
+        painter.rect_filled(plot, 2.0, visuals.extreme_bg_color);
+
+        // Horizontal grid lines with labels
+        let grid = Stroke::new(1.0, visuals.weak_text_color().gamma_multiply(0.3));
+        for i in 0..=4 {
+            let mm = max * i as f32 / 4.0;
+            let y = y_of(mm);
+            painter.hline(plot.x_range(), y, grid);
+            painter.text(
+                pos2(plot.left() - 4.0, y),
+                Align2::RIGHT_CENTER,
+                format.length(mm, Some(2)),
+                font.clone(),
+                visuals.text_color(),
+            );
+        }
+        if count > 0 {
+            for (b_scan, align) in [(0, Align2::LEFT_TOP), (count - 1, Align2::RIGHT_TOP)] {
+                painter.text(
+                    pos2(x_of(b_scan), plot.bottom() + 4.0),
+                    align,
+                    format!("B scan {}", format.count(b_scan)),
+                    font.clone(),
+                    visuals.text_color(),
+                );
+            }
+        }
+
+        // Lines are interrupted at B scans without a diameter
+        for (_, value, color) in SERIES {
+            let mut points = Vec::new();
+            for (b_scan, diameter) in diameters.values.iter().enumerate() {
+                let mm = value(diameter);
+                if mm.is_finite() {
+                    points.push(pos2(x_of(b_scan), y_of(mm)));
+                } else if !points.is_empty() {
+                    painter.add(Shape::line(std::mem::take(&mut points), (1.5, color)));
+                }
+            }
+            if points.len() == 1 {
+                painter.circle_filled(points[0], 1.5, color);
+            } else if !points.is_empty() {
+                painter.add(Shape::line(points, (1.5, color)));
+            }
+        }
+
+        // The B scan selected here, or by another linked view
+        if let Some(b_scan) = self.link.b_scan(link).or(self.selected) {
+            if b_scan < count {
+                painter.vline(
+                    x_of(b_scan),
+                    plot.y_range(),
+                    Stroke::new(1.5, Color32::BLUE),
+                );
+            }
+        }
+
+        let hovered = response
+            .hover_pos()
+            .filter(|pos| plot.x_range().contains(pos.x))
+            .and_then(|pos| nearest_b_scan((pos.x - plot.left()) / plot.width(), count));
+
+        if let Some(b_scan) = hovered {
+            painter.vline(
+                x_of(b_scan),
+                plot.y_range(),
+                visuals.widgets.hovered.fg_stroke,
+            );
+
+            let diameter = diameters.values[b_scan];
+            let response = response.clone().on_hover_ui_at_pointer(|ui| {
+                ui.label(format!("B scan {}", format.count(b_scan)));
+                for (name, value, _) in SERIES {
+                    let mm = value(&diameter);
+                    let text = match mm.is_finite() {
+                        true => format.length(mm, Some(3)),
+                        false => "–".to_string(),
+                    };
+                    ui.label(format!("{name}: {text}"));
+                }
+            });
+
+            if response.clicked() {
+                self.selected = Some(b_scan);
+                self.link.select(link, b_scan);
+            }
+        }
+    }
+}
+
+/// Renders the diameters of a [requests::Diameter] over the B scans.
+impl DataView for View {
+    type InputId = InputIdSingle;
+
+    fn from_node_output(
+        node_output: &NodeOutput,
+        _pipeline: &Pipeline,
+        _cache: &Cache,
+        _render_state: &RenderState,
+    ) -> Option<Self> {
+        if node_output.type_id == PipelineDataType::Diameter.into() {
+            Some(Self {
+                diameter: *node_output,
+                diameters_rx: None,
+                selected: None,
+                link: ViewLink::default(),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn inputs(&self) -> impl Iterator<Item = (Self::InputId, Option<NodeOutput>)> {
+        std::iter::once((InputIdSingle, Some(self.diameter)))
+    }
+
+    fn changed(&self, other: &Self) -> bool {
+        self.diameter != other.diameter
+    }
+
+    fn connect(&mut self, node_output: NodeOutput, _pipeline: &Pipeline) -> bool {
+        if node_output.type_id == PipelineDataType::Diameter.into() {
+            self.diameter = node_output;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn disconnect(&mut self, _input_id: Self::InputId) -> Existence {
+        Existence::Destroy
+    }
+
+    fn create_view_task(&mut self) -> impl DataViewTask<InputId = Self::InputId, DataView = Self> {
+        let (diameters_tx, diameters_rx) = watch::channel(Diameters::default());
+
+        self.diameters_rx = Some(diameters_rx);
+
+        Task {
+            diameter_in: TaskInput::default(),
+            diameters_tx,
+            done: false,
+        }
+    }
+
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        _pipeline: &Pipeline,
+        link: &SharedLinkState,
+        _live_tuning: &SharedLiveTuning,
+    ) -> ViewResponse {
+        let Some(diameters_rx) = self.diameters_rx.clone() else {
+            ui.label("No data receiver available");
+            return ViewResponse::default();
+        };
+        let diameters = diameters_rx.borrow();
+
+        if !diameters.complete {
+            ui.ctx().request_repaint();
+        }
+
+        if let Some(b_scan) = self.link.receive(link) {
+            self.selected = Some(b_scan);
+        }
+
+        ui.horizontal(|ui| {
+            self.link.toggle_ui(ui);
+
+            for (name, _, color) in SERIES {
+                ui.colored_label(color, format!("━ {name}"));
+            }
+
+            if let Some(b_scan) = self.selected {
+                ui.separator();
+                ui.label(format!(
+                    "Selected: B scan {}",
+                    NumberFormat::current().count(b_scan)
+                ));
+            }
+
+            if !diameters.complete {
+                ui.spinner();
+            }
+        });
+
+        if diameters.values.is_empty() && diameters.complete {
+            ui.label("No diameters");
+            return ViewResponse::default();
+        }
+
+        self.plot_ui(ui, &diameters, link);
+
+        ViewResponse::default()
+    }
+}
+
+// MARK: Task
+
+struct Task {
+    diameter_in: TaskInput<requests::Diameter>,
+
+    diameters_tx: watch::Sender<Diameters>,
+    /// Whether every diameter got received, so there is nothing to do until
+    /// the input changes.
+    done: bool,
+}
+
+impl DataViewTask for Task {
+    type InputId = InputIdSingle;
+    type DataView = View;
+
+    fn connect(&mut self, _input_id: Self::InputId, input: &mut ConnectionHandle) {
+        self.diameter_in.connect(input);
+    }
+
+    fn disconnect(&mut self, _input_id: Self::InputId) {
+        self.diameter_in.disconnect();
+    }
+
+    fn invalidate(&mut self, cause: InvalidationCause) {
+        match cause {
+            // Views are not cancelled
+            InvalidationCause::UserCancelled => {}
+            _ => {
+                self.done = false;
+                self.diameters_tx.send_replace(Diameters::default());
+            }
+        }
+    }
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        if self.done {
+            return future::pending().await;
+        }
+
+        let Some(res) = self.diameter_in.request(requests::Diameter).await else {
+            return future::pending().await;
+        };
+        let Some(mut rx) = res.data.subscribe() else {
+            return future::pending().await;
+        };
+
+        self.diameters_tx.send_replace(Diameters::default());
+
+        loop {
+            match rx.recv().await {
+                Ok(diameter) => {
+                    self.diameters_tx.send_modify(|d| d.values.push(diameter));
+                }
+                Err(RecvError::Closed) => break,
+                _ => return Ok(()),
+            }
+        }
+
+        self.diameters_tx.send_modify(|d| d.complete = true);
+        self.done = true;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::Vector2;
+
+    use super::*;
+
+    fn diameter(min: f32, mean: f32, max: f32) -> BScanDiameter {
+        BScanDiameter {
+            b_scan_start: 0,
+            b_scan_end: 0,
+            min,
+            max,
+            mean,
+            min_points: [Vector2::zeros(); 2],
+            max_points: [Vector2::zeros(); 2],
+        }
+    }
+
+    #[test]
+    fn value_range() {
+        assert_eq!(Diameters::default().value_range(), 1.0);
+
+        let diameters = Diameters {
+            values: vec![
+                diameter(1.0, 2.0, 3.0),
+                diameter(f32::NAN, 4.0, f32::INFINITY),
+            ],
+            complete: true,
+        };
+        assert!((diameters.value_range() - 4.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn nearest_b_scans() {
+        assert_eq!(nearest_b_scan(0.5, 0), None);
+        assert_eq!(nearest_b_scan(0.7, 1), Some(0));
+        assert_eq!(nearest_b_scan(-1.0, 5), Some(0));
+        assert_eq!(nearest_b_scan(0.3, 5), Some(1));
+        assert_eq!(nearest_b_scan(0.4, 5), Some(2));
+        assert_eq!(nearest_b_scan(2.0, 5), Some(4));
+    }
+}
@@ -0,0 +1,480 @@
+//! Histogram of the values of an M scan, to pick thresholds on.
+//!
+//! Markers are dragged over the histogram and applied to nodes with
+//! thresholds, see [crate::pipeline::thresholds]. Applying asks the app for
+//! the edit, see [request_edit], as views only see the pipeline.
+//!
+//! The view is opened from the M scan view, see [request_open].
+
+use std::sync::Arc;
+
+use egui_plot::{Line, Plot, PlotPoints, VLine};
+use futures::future;
+use tokio::sync::watch;
+
+use crate::{
+    pipeline::{
+        thresholds::{self, ThresholdEdit, ThresholdScale},
+        types::{DataMatrix, DataType},
+    },
+    queue_channel::error::RecvError,
+};
+
+use super::prelude::*;
+
+/// Number of bins over the range from 0 to 1.
+pub const BINS: usize = 256;
+
+// MARK: Requests
+
+fn open_request_id() -> egui::Id {
+    egui::Id::new("open_histogram_view")
+}
+
+/// Asks the app to open a histogram view of `m_scan`. See
+/// [take_open_request].
+pub fn request_open(ctx: &egui::Context, m_scan: NodeOutput) {
+    ctx.data_mut(|data| data.insert_temp(open_request_id(), Some(m_scan)));
+}
+
+/// The M scan of a requested histogram view.
+pub fn take_open_request(ctx: &egui::Context) -> Option<NodeOutput> {
+    ctx.data_mut(|data| data.remove_temp::<Option<NodeOutput>>(open_request_id()))
+        .flatten()
+}
+
+fn edit_request_id() -> egui::Id {
+    egui::Id::new("threshold_edits")
+}
+
+/// Asks the app to set a threshold of a node, see [take_edit_requests].
+pub fn request_edit(ctx: &egui::Context, edit: ThresholdEdit) {
+    ctx.data_mut(|data| {
+        data.get_temp_mut_or_default::<Vec<ThresholdEdit>>(edit_request_id())
+            .push(edit)
+    });
+}
+
+/// Thresholds to set, since the last call, see [thresholds::apply].
+pub fn take_edit_requests(ctx: &egui::Context) -> Vec<ThresholdEdit> {
+    ctx.data_mut(|data| data.remove_temp::<Vec<ThresholdEdit>>(edit_request_id()))
+        .unwrap_or_default()
+}
+
+// MARK: Histogram
+
+/// Counts of the values of an M scan, rescaled to the range from 0 to 1, in
+/// [BINS] bins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    /// Whether every A scan is counted.
+    complete: bool,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; BINS],
+            total: 0,
+            complete: false,
+        }
+    }
+}
+
+impl Histogram {
+    /// Counts `values`. NaNs are skipped, values out of range go to the first
+    /// or last bin.
+    pub fn extend(&mut self, values: impl Iterator<Item = f32>) {
+        for value in values.filter(|v| !v.is_nan()) {
+            let bin = (value.clamp(0.0, 1.0) * BINS as f32) as usize;
+            self.counts[bin.min(BINS - 1)] += 1;
+            self.total += 1;
+        }
+    }
+
+    /// Percentile of `value`, from 0 to 100, the share of values below it.
+    /// Values are assumed to be spread evenly within a bin.
+    pub fn percentile(&self, value: f32) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+
+        let position = value.clamp(0.0, 1.0) * BINS as f32;
+        let bin = (position as usize).min(BINS);
+        let mut below = self.counts[..bin].iter().sum::<u64>() as f32;
+        if bin < BINS {
+            below += self.counts[bin] as f32 * position.fract();
+        }
+
+        100.0 * below / self.total as f32
+    }
+
+    /// Outline of the bars, as share of all values.
+    fn outline(&self) -> Vec<[f64; 2]> {
+        let total = self.total.max(1) as f64;
+        let width = 1.0 / BINS as f64;
+        self.counts
+            .iter()
+            .enumerate()
+            .flat_map(|(i, count)| {
+                let share = *count as f64 / total;
+                [[i as f64 * width, share], [(i + 1) as f64 * width, share]]
+            })
+            .collect()
+    }
+}
+
+// MARK: View
+
+#[derive(Clone)]
+pub struct View {
+    m_scan: NodeOutput,
+
+    histogram_rx: Option<watch::Receiver<Option<Arc<Histogram>>>>,
+
+    /// Positions of the markers, from 0 to 1. One, or a low and a high one.
+    markers: Vec<f32>,
+    /// Marker applied to single thresholds, the last one moved.
+    selected: usize,
+    /// Marker being dragged.
+    dragged: Option<usize>,
+}
+
+impl View {
+    pub fn new(m_scan: NodeOutput) -> Self {
+        Self {
+            m_scan,
+            histogram_rx: None,
+            markers: vec![0.2],
+            selected: 0,
+            dragged: None,
+        }
+    }
+
+    /// Lists the nodes with thresholds, to apply the markers to.
+    fn apply_menu(&self, ui: &mut egui::Ui, pipeline: &Pipeline, histogram: &Histogram) {
+        let targets = thresholds::targets(pipeline, self.m_scan.node_id);
+        if targets.is_empty() {
+            ui.label("No node with thresholds");
+            return;
+        }
+
+        let on_scale = |marker: f32, scale| match scale {
+            ThresholdScale::Value => marker,
+            ThresholdScale::Percentile => histogram.percentile(marker),
+        };
+        let marker = self.markers[self.selected];
+
+        for (i, target) in targets.iter().enumerate() {
+            if i == 0 && target.downstream {
+                ui.weak("Processing this M scan");
+            } else if !target.downstream && (i == 0 || targets[i - 1].downstream) {
+                ui.weak("Other nodes");
+            }
+
+            let node = &pipeline[target.node_id];
+            let Some(threshold_target) = node.threshold_target() else {
+                continue;
+            };
+            let node_thresholds = threshold_target.thresholds();
+
+            ui.label(node.name());
+            ui.indent(target.node_id, |ui| {
+                for (index, threshold) in node_thresholds.iter().enumerate() {
+                    let value = on_scale(marker, threshold.scale);
+                    if ui
+                        .button(format!("{}: {:.3}", threshold.name, value))
+                        .on_hover_text(format!("Currently {:.3}", threshold.value))
+                        .clicked()
+                    {
+                        request_edit(
+                            ui.ctx(),
+                            ThresholdEdit {
+                                node_id: target.node_id,
+                                index,
+                                value,
+                            },
+                        );
+                        ui.close_menu();
+                    }
+                }
+
+                if let (Some(window), &[a, b]) = (threshold_target.window(), &self.markers[..]) {
+                    let (low, high) = (a.min(b), a.max(b));
+                    if ui
+                        .button("Low and high")
+                        .on_hover_text("Apply both markers")
+                        .clicked()
+                    {
+                        for (index, marker) in window.into_iter().zip([low, high]) {
+                            request_edit(
+                                ui.ctx(),
+                                ThresholdEdit {
+                                    node_id: target.node_id,
+                                    index,
+                                    value: on_scale(marker, node_thresholds[index].scale),
+                                },
+                            );
+                        }
+                        ui.close_menu();
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Renders the histogram of a [requests::MScan], with markers to pick
+/// thresholds.
+impl DataView for View {
+    type InputId = InputIdSingle;
+
+    /// Histogram views are only opened on request, see [request_open].
+    fn from_node_output(
+        _node_output: &NodeOutput,
+        _pipeline: &Pipeline,
+        _cache: &Cache,
+        _render_state: &RenderState,
+    ) -> Option<Self> {
+        None
+    }
+
+    fn inputs(&self) -> impl Iterator<Item = (Self::InputId, Option<NodeOutput>)> {
+        std::iter::once((InputIdSingle, Some(self.m_scan)))
+    }
+
+    fn changed(&self, other: &Self) -> bool {
+        self.m_scan != other.m_scan
+    }
+
+    fn connect(&mut self, node_output: NodeOutput, _pipeline: &Pipeline) -> bool {
+        if node_output.type_id == PipelineDataType::MScan.into() {
+            self.m_scan = node_output;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn disconnect(&mut self, _input_id: Self::InputId) -> Existence {
+        Existence::Destroy
+    }
+
+    fn create_view_task(&mut self) -> impl DataViewTask<InputId = Self::InputId, DataView = Self> {
+        let (histogram_tx, histogram_rx) = watch::channel(None);
+
+        self.histogram_rx = Some(histogram_rx);
+
+        Task {
+            m_scan_in: TaskInput::default(),
+            histogram_tx,
+        }
+    }
+
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        pipeline: &Pipeline,
+        _link: &SharedLinkState,
+        _live_tuning: &SharedLiveTuning,
+    ) -> ViewResponse {
+        let histogram = self
+            .histogram_rx
+            .as_mut()
+            .and_then(|rx| rx.borrow_and_update().clone());
+        let Some(histogram) = histogram else {
+            ui.ctx().request_repaint();
+            ui.label("Data should be here soon");
+            return ViewResponse::default();
+        };
+        if !histogram.complete {
+            ui.ctx().request_repaint();
+        }
+
+        ui.horizontal(|ui| {
+            let mut two = self.markers.len() == 2;
+            if ui
+                .checkbox(&mut two, "Low and high")
+                .on_hover_text("Two markers, for a range of values")
+                .changed()
+            {
+                if two {
+                    let low = self.markers[0].min(0.8);
+                    self.markers = vec![low, low + 0.2];
+                } else {
+                    self.markers.truncate(1);
+                    self.selected = 0;
+                }
+            }
+
+            for (i, marker) in self.markers.iter().enumerate() {
+                let text = format!("{:.3} ({:.1} %)", marker, histogram.percentile(*marker));
+                if ui
+                    .selectable_label(i == self.selected, text)
+                    .on_hover_text("Marker applied to single thresholds")
+                    .clicked()
+                {
+                    self.selected = i;
+                }
+            }
+
+            ui.menu_button("Apply to node…", |ui| {
+                self.apply_menu(ui, pipeline, &histogram);
+            });
+
+            if !histogram.complete {
+                ui.spinner();
+            }
+        });
+
+        let response = Plot::new(ui.id().with("histogram"))
+            .allow_drag(false)
+            .allow_scroll(false)
+            .allow_zoom(false)
+            .allow_boxed_zoom(false)
+            .x_axis_label("Value")
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(histogram.outline())));
+
+                for (i, marker) in self.markers.iter().enumerate() {
+                    let color = if i == self.selected {
+                        egui::Color32::BLUE
+                    } else {
+                        egui::Color32::GRAY
+                    };
+                    plot_ui.vline(VLine::new(*marker).color(color).width(2.0));
+                }
+
+                plot_ui.pointer_coordinate()
+            });
+
+        // Dragging moves the marker closest to where it started
+        let plot = &response.response;
+        if let Some(pointer) = response.inner {
+            let x = pointer.x.clamp(0.0, 1.0) as f32;
+            if plot.drag_started() || plot.clicked() {
+                let closest = (0..self.markers.len())
+                    .min_by(|a, b| {
+                        let distance = |i: &usize| (self.markers[*i] - x).abs();
+                        distance(a).total_cmp(&distance(b))
+                    })
+                    .unwrap_or(0);
+                self.selected = closest;
+                self.dragged = Some(closest);
+            }
+            if let Some(i) = self.dragged {
+                self.markers[i] = x;
+            }
+        }
+        if !plot.dragged() {
+            self.dragged = None;
+        }
+
+        ViewResponse::default()
+    }
+}
+
+// MARK: Task
+
+struct Task {
+    m_scan_in: TaskInput<requests::MScan>,
+
+    histogram_tx: watch::Sender<Option<Arc<Histogram>>>,
+}
+
+impl DataViewTask for Task {
+    type InputId = InputIdSingle;
+    type DataView = View;
+
+    fn connect(&mut self, _input_id: Self::InputId, input: &mut ConnectionHandle) {
+        self.m_scan_in.connect(input);
+    }
+
+    fn disconnect(&mut self, _input_id: Self::InputId) {
+        self.m_scan_in.disconnect();
+    }
+
+    fn invalidate(&mut self, cause: InvalidationCause) {
+        match cause {
+            // Views are not cancelled
+            InvalidationCause::UserCancelled => {}
+            _ => {
+                self.histogram_tx.send_replace(None);
+            }
+        }
+    }
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        if self.histogram_tx.borrow().is_some() {
+            let () = future::pending().await;
+        }
+
+        let Some(m_scan) = self.m_scan_in.request(requests::MScan).await else {
+            return future::pending().await;
+        };
+        let Some(mut m_scan_rx) = m_scan.data.subscribe() else {
+            return future::pending().await;
+        };
+
+        let mut histogram = Histogram::default();
+        loop {
+            match m_scan_rx.recv().await {
+                Ok(data) => {
+                    histogram = tokio::task::spawn_blocking(move || {
+                        let DataMatrix::F32(a_scans) = data.cast_rescale_par(DataType::F32) else {
+                            unreachable!("Data should be cast to f32");
+                        };
+                        histogram.extend(a_scans.iter().copied());
+                        histogram
+                    })
+                    .await?;
+                    self.histogram_tx
+                        .send_replace(Some(Arc::new(histogram.clone())));
+                }
+                Err(RecvError::Closed) => break,
+                _ => return Ok(()),
+            }
+        }
+
+        histogram.complete = true;
+        self.histogram_tx.send_replace(Some(Arc::new(histogram)));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_values() {
+        let mut histogram = Histogram::default();
+        histogram.extend([0.0, 0.5, 1.0, 2.0, -1.0, f32::NAN].into_iter());
+
+        assert_eq!(histogram.total, 5);
+        assert_eq!(histogram.counts[0], 2);
+        assert_eq!(histogram.counts[BINS / 2], 1);
+        assert_eq!(histogram.counts[BINS - 1], 2);
+    }
+
+    #[test]
+    fn percentiles() {
+        assert_eq!(Histogram::default().percentile(0.5), 0.0);
+
+        let mut histogram = Histogram::default();
+        histogram.extend((0..BINS * 4).map(|i| (i as f32 + 0.5) / (BINS * 4) as f32));
+
+        for value in [0.0, 0.1, 0.25, 0.5, 0.9, 1.0] {
+            let percentile = histogram.percentile(value);
+            assert!(
+                (percentile - value * 100.0).abs() < 0.5,
+                "{} is at {} %",
+                value,
+                percentile
+            );
+        }
+    }
+}
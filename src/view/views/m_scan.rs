@@ -1,28 +1,69 @@
+mod animation;
+mod display;
 mod gpu;
+mod image_export;
+mod jump_list;
+mod overlay;
+mod polyline;
+mod pyramid;
 mod uis;
 
+use animation::{AnimationDialog, AnimationSource};
+use display::{ColorMapping, ValueHistogram};
 use gpu::{upload_b_scan_segmentation, SharedResources};
-use uis::{cartesian_m_scan_ui, polar_m_scan_ui, side_m_scan_ui};
-
-use std::{collections::HashSet, sync::Arc};
+use image_export::{ImageDialog, ImageView};
+use jump_list::{EventInput, JumpList};
+use overlay::Overlays;
+use polyline::OverlayLines;
+use pyramid::Pyramid;
+use uis::{cartesian_m_scan_ui, polar_m_scan_ui, side_m_scan_ui, AspectMode, BScanSpacing};
+
+use std::{
+    collections::HashSet,
+    mem,
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use crate::{
-    cache::Cached, gui::color_maps, pipeline::nodes::diameter, queue_channel::error::RecvError,
+    cache::Cached,
+    gui::color_maps,
+    pipeline::{nodes::diameter, range},
+    queue_channel::error::RecvError,
+    settings::{MScanPooling, Settings},
+    units::NumberFormat,
+    view::live_tuning::DEBOUNCE,
 };
 
-use super::prelude::*;
-use egui::{ComboBox, Layout};
+use super::{perf::PerfOverlay, prelude::*};
+use anyhow::anyhow;
+use egui::{ComboBox, Layout, RichText};
 use futures::future;
-use nalgebra::DVector;
-use tokio::sync::{watch, RwLock};
+use tokio::sync::{watch, Mutex};
 use types::BScanDiameter;
-use wgpu::util::DeviceExt;
 
 /// WGPU requires to specify a maximum number of textures we can bind in a
-/// texture array. When we exceed this number, the remaining textures are not
-/// rendered.
+/// texture array. When we would exceed this number, pairs of adjacent textures
+/// are merged into wider ones. Only when the merged textures would exceed the
+/// maximum texture size, the remaining A scans are not rendered. A scans split
+/// into row tiles take one texture per tile, see [TilePlan].
 pub const MAX_TEXTURES: usize = 100;
 
+/// Number of A scans of the first chunk, which are converted and uploaded at
+/// once, so the first A scans show up before the whole chunk is uploaded.
+const FIRST_CHUNK_SLICE: usize = 256;
+
+/// How long a B scan selected in another linked view is highlighted in the
+/// polar view.
+const FLASH_SECONDS: f64 = 1.0;
+
+/// Maximum number of A scans previewed with live tuning, so previews stay
+/// fast when the whole M scan is visible.
+const MAX_LIVE_A_SCANS: usize = 2048;
+
 pub enum InputId {
     MScan,
     BScanSegmentation,
@@ -45,44 +86,82 @@ impl_enum_from_into_id_types!(InputId, [graph::InputId], {
 /// achieved by sampling the scan data in a specific way on the GPU.
 pub struct View {
     m_scan: NodeOutput,
+    /// The most downstream M scan of the chain, [Self::m_scan] was selected
+    /// from. See [m_scan_chain].
+    m_scan_head: NodeOutput,
     b_scan_segmentation: Option<NodeOutput>,
     m_scan_segmentation: Option<NodeOutput>,
     diameter: Option<NodeOutput>,
 
-    textures_state: Cached<Option<TexturesState>>,
+    textures_state: Cached<Option<Arc<TexturesState>>>,
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>,
+    target_format: wgpu::TextureFormat,
     bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    pyramid: Arc<Pyramid>,
 
-    m_scan_segmentation_rx: Option<watch::Receiver<Vec<usize>>>,
+    m_scan_segmentation_rx: Option<watch::Receiver<Overlay<usize>>>,
 
-    b_scan_segmentation_rx: Option<watch::Receiver<Vec<usize>>>,
+    b_scan_segmentation_rx: Option<watch::Receiver<Overlay<usize>>>,
     b_scan_segmentation_buffer: Option<(wgpu::Buffer, Arc<wgpu::BindGroup>)>,
     b_scan_segmentation_bind_group_layout: Arc<wgpu::BindGroupLayout>,
 
-    diameter_rx: Option<watch::Receiver<Vec<BScanDiameter>>>,
+    diameter_rx: Option<watch::Receiver<Overlay<BScanDiameter>>>,
+    overlay_lines: OverlayLines,
 
     show_side_view: bool,
-    map_idx: u32,
+    /// Half width of the angular neighborhood averaged in the side view, as
+    /// fraction of a B scan. At 0, a single A scan is used.
+    side_view_neighborhood: f32,
+    aspect_mode: AspectMode,
+    b_scan_spacing: BScanSpacing,
+    /// Color map and value range of all perspectives.
+    color_mapping: ColorMapping,
+    merge_notice_dismissed: bool,
+    /// Counts the requests to retry a stream, that ended early. The task is
+    /// synced, when it changes.
+    retries: usize,
+    animation_dialog: Option<AnimationDialog>,
+    image_dialog: Option<ImageDialog>,
+    jump_list: JumpList,
+    /// B scan shown in the cartesian view in the last frame.
+    current_b_scan: Option<usize>,
+    link: ViewLink,
+    /// B scan selected in another linked view and the time it was selected.
+    flash: Option<(usize, f64)>,
+    /// Preview changed settings on the shown A scans, see
+    /// [crate::view::live_tuning].
+    live_tuning: bool,
+    /// A scans of the textures of an upload, that were overwritten by a live
+    /// tuning preview.
+    live_region: Option<(Arc<Mutex<Upload>>, Range<usize>)>,
+    /// Id of the last applied live tuning preview.
+    live_result: Option<u64>,
+    perf: PerfOverlay,
 }
 
 impl View {
-    fn new(node_output: NodeOutput, cache: &Cache, render_state: &RenderState) -> Self {
+    /// Returns [None], if the wgpu resources of this view were not
+    /// initialized.
+    fn new(node_output: NodeOutput, cache: &Cache, render_state: &RenderState) -> Option<Self> {
         let renderer = render_state.renderer.read();
-        let resources = renderer
-            .callback_resources
-            .get::<SharedResources>()
-            .unwrap();
+        let Some(resources) = renderer.callback_resources.get::<SharedResources>() else {
+            eprintln!("M scan view resources are missing");
+            return None;
+        };
 
-        Self {
+        Some(Self {
             m_scan: node_output,
+            m_scan_head: node_output,
             b_scan_segmentation: None,
             m_scan_segmentation: None,
             diameter: None,
             textures_state: cache.get((node_output.node_id, node_output.output_id)),
             device: render_state.device.clone(),
             queue: render_state.queue.clone(),
+            target_format: render_state.target_format,
             bind_group_layout: resources.scan_bind_group_layout.clone(),
+            pyramid: resources.pyramid.clone(),
             m_scan_segmentation_rx: None,
             b_scan_segmentation_rx: None,
             b_scan_segmentation_buffer: None,
@@ -90,9 +169,27 @@ impl View {
                 .b_scan_segmentation_bind_group_layout
                 .clone(),
             diameter_rx: None,
+            overlay_lines: OverlayLines::default(),
             show_side_view: false,
-            map_idx: 26,
-        }
+            side_view_neighborhood: 0.0,
+            aspect_mode: AspectMode::default(),
+            b_scan_spacing: BScanSpacing::default(),
+            color_mapping: ColorMapping::new(color_maps::loaded_index(
+                Settings::current().display.default_color_map,
+            )),
+            merge_notice_dismissed: false,
+            retries: 0,
+            animation_dialog: None,
+            image_dialog: None,
+            jump_list: JumpList::default(),
+            current_b_scan: None,
+            link: ViewLink::default(),
+            flash: None,
+            live_tuning: false,
+            live_region: None,
+            live_result: None,
+            perf: PerfOverlay::new(resources.timer.as_ref().map(|timer| timer.timers())),
+        })
     }
 }
 
@@ -100,13 +197,16 @@ impl Clone for View {
     fn clone(&self) -> Self {
         Self {
             m_scan: self.m_scan.clone(),
+            m_scan_head: self.m_scan_head,
             b_scan_segmentation: self.b_scan_segmentation.clone(),
             m_scan_segmentation: self.m_scan_segmentation.clone(),
             diameter: self.diameter.clone(),
             textures_state: self.textures_state.clone(),
             device: self.device.clone(),
             queue: self.queue.clone(),
+            target_format: self.target_format,
             bind_group_layout: self.bind_group_layout.clone(),
+            pyramid: self.pyramid.clone(),
             m_scan_segmentation_rx: None,
             b_scan_segmentation_rx: None,
             b_scan_segmentation_buffer: None,
@@ -114,8 +214,24 @@ impl Clone for View {
                 .b_scan_segmentation_bind_group_layout
                 .clone(),
             diameter_rx: None,
+            overlay_lines: OverlayLines::default(),
             show_side_view: self.show_side_view.clone(),
-            map_idx: self.map_idx.clone(),
+            side_view_neighborhood: self.side_view_neighborhood,
+            aspect_mode: self.aspect_mode,
+            b_scan_spacing: self.b_scan_spacing,
+            color_mapping: self.color_mapping,
+            merge_notice_dismissed: self.merge_notice_dismissed,
+            retries: self.retries,
+            animation_dialog: None,
+            image_dialog: None,
+            jump_list: self.jump_list.clone(),
+            current_b_scan: None,
+            link: self.link.clone(),
+            flash: None,
+            live_tuning: self.live_tuning,
+            live_region: None,
+            live_result: None,
+            perf: self.perf.clone(),
         }
     }
 }
@@ -141,19 +257,19 @@ impl DataView for View {
         Self: Sized,
     {
         match PipelineDataType::from(node_output.type_id) {
-            PipelineDataType::MScan => Some(Self::new(*node_output, cache, render_state)),
+            PipelineDataType::MScan => Self::new(*node_output, cache, render_state),
             PipelineDataType::BScanSegmentation => {
                 let m_scan = find_m_scan_input(pipeline, node_output.node_id)?;
                 Some(Self {
                     b_scan_segmentation: Some(*node_output),
-                    ..Self::new(m_scan, cache, render_state)
+                    ..Self::new(m_scan, cache, render_state)?
                 })
             }
             PipelineDataType::MScanSegmentation => {
                 let m_scan = find_m_scan_input(pipeline, node_output.node_id)?;
                 Some(Self {
                     m_scan_segmentation: Some(*node_output),
-                    ..Self::new(m_scan, cache, render_state)
+                    ..Self::new(m_scan, cache, render_state)?
                 })
             }
             PipelineDataType::Diameter => {
@@ -165,7 +281,7 @@ impl DataView for View {
                     diameter: Some(*node_output),
                     b_scan_segmentation: b_scans,
                     m_scan_segmentation,
-                    ..Self::new(m_scan, cache, render_state)
+                    ..Self::new(m_scan, cache, render_state)?
                 })
             }
             _ => None,
@@ -183,12 +299,16 @@ impl DataView for View {
     }
 
     fn changed(&self, other: &Self) -> bool {
-        self.m_scan != other.m_scan
+        self.m_scan != other.m_scan || self.retries != other.retries
     }
 
-    fn connect(&mut self, node_output: NodeOutput, _pipeline: &Pipeline) -> bool {
+    fn connect(&mut self, node_output: NodeOutput, pipeline: &Pipeline) -> bool {
         match PipelineDataType::from(node_output.type_id) {
             PipelineDataType::MScan => {
+                // Stay in the chain, so the user can switch back
+                if !m_scan_chain(pipeline, self.m_scan_head).contains(&node_output) {
+                    self.m_scan_head = node_output;
+                }
                 self.m_scan = node_output;
                 self.textures_state
                     .change_target((node_output.node_id, node_output.output_id));
@@ -229,9 +349,9 @@ impl DataView for View {
     }
 
     fn create_view_task(&mut self) -> impl DataViewTask<InputId = Self::InputId, DataView = Self> {
-        let (b_scan_tx, b_scan_rx) = watch::channel(Vec::new());
-        let (m_scan_tx, m_scan_rx) = watch::channel(Vec::new());
-        let (diameter_tx, diameter_rx) = watch::channel(Vec::new());
+        let (b_scan_tx, b_scan_rx) = watch::channel(Overlay::default());
+        let (m_scan_tx, m_scan_rx) = watch::channel(Overlay::default());
+        let (diameter_tx, diameter_rx) = watch::channel(Overlay::default());
 
         self.b_scan_segmentation_rx = Some(b_scan_rx);
         self.m_scan_segmentation_rx = Some(m_scan_rx);
@@ -246,53 +366,155 @@ impl DataView for View {
             device: self.device.clone(),
             queue: self.queue.clone(),
             bind_group_layout: self.bind_group_layout.clone(),
+            pyramid: self.pyramid.clone(),
             b_scan_segmentation_tx: b_scan_tx,
             m_scan_segmentation_tx: m_scan_tx,
             diameter_tx,
         }
     }
 
-    fn ui(&mut self, ui: &mut egui::Ui) {
-        let textures_state = self.textures_state.read();
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        pipeline: &Pipeline,
+        link: &SharedLinkState,
+        live_tuning: &SharedLiveTuning,
+    ) -> ViewResponse {
+        let mut m_scan_chain = m_scan_chain(pipeline, self.m_scan_head);
+        if !m_scan_chain.contains(&self.m_scan) {
+            // The pipeline was changed, so the head does not lead to the
+            // selected M scan anymore
+            self.m_scan_head = self.m_scan;
+            m_scan_chain = self::m_scan_chain(pipeline, self.m_scan);
+        }
 
-        let Some(textures_state) = textures_state.as_ref() else {
+        let rect = ui.max_rect();
+        self.perf.begin(ui);
+
+        let retries = self.retries;
+        let selected_m_scan = self.m_scan_ui(ui, pipeline, &m_scan_chain, link, live_tuning);
+
+        self.perf.end(ui, rect);
+
+        if let Some(m_scan) = selected_m_scan.filter(|o| *o != self.m_scan) {
+            self.connect(m_scan, pipeline);
+        }
+
+        ViewResponse {
+            // The view requests the M scan again, once its task is recreated
+            retry_failed: self.retries != retries,
+        }
+    }
+}
+
+impl View {
+    /// Renders the M scan with its overlays and the toolbar. Returns the M
+    /// scan, the user selected from `m_scan_chain`.
+    fn m_scan_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        pipeline: &Pipeline,
+        m_scan_chain: &[NodeOutput],
+        link: &SharedLinkState,
+        live_tuning: &SharedLiveTuning,
+    ) -> Option<NodeOutput> {
+        let mut selected_m_scan = None;
+
+        let Some(textures_state) = self.textures_state.load() else {
             ui.ctx().request_repaint();
             ui.label("Data should be here soon");
-            return;
+            return None;
         };
 
+        if let Some(error) = &textures_state.error {
+            egui::Frame::popup(ui.style())
+                .fill(ui.visuals().error_fg_color.gamma_multiply(0.3))
+                .show(ui, |ui| {
+                    ui.colored_label(ui.visuals().error_fg_color, error)
+                });
+            return None;
+        }
+
         let Some(texture_bind_group) = textures_state.bind_group.as_ref() else {
             ui.ctx().request_repaint();
             ui.label("Data should be here soon");
-            return;
+            return None;
         };
 
+        // A new upload replaces the preview
+        if let Some((upload, _)) = &self.live_region {
+            if !Arc::ptr_eq(upload, &textures_state.upload) {
+                self.live_region = None;
+            }
+        }
+
+        // The range selector clamps to the shown pullback
+        match self.b_scan_segmentation_rx.as_ref().map(|rx| rx.borrow()) {
+            Some(b_scans) if b_scans.data.len() > 1 => {
+                range::report_extent(textures_state.a_scan_count, &b_scans.data)
+            }
+            _ => range::report_extent(textures_state.a_scan_count, &[]),
+        }
+
         if let Some(b_scan_segmentation) = self.b_scan_segmentation_rx.as_mut() {
             if let Ok(true) = b_scan_segmentation.has_changed() {
                 let b_scan_segmentation = b_scan_segmentation.borrow_and_update();
-                if b_scan_segmentation.len() > 1 {
+                if b_scan_segmentation.data.len() > 1 {
                     upload_b_scan_segmentation(
                         &self.device,
                         &mut self.b_scan_segmentation_buffer,
                         &self.b_scan_segmentation_bind_group_layout,
-                        b_scan_segmentation.as_slice(),
+                        b_scan_segmentation.data.as_slice(),
                     );
                 }
             }
         }
 
-        let diameters = self.diameter_rx.as_ref().map(|rx| rx.borrow());
-        let diameters = diameters.as_deref().and_then(|v| match v.len() {
-            0 => None,
-            _ => Some(&v[..]),
-        });
+        let generation = |rx: &Option<watch::Receiver<Overlay<usize>>>| {
+            rx.as_ref().map_or(0, |rx| rx.borrow().generation)
+        };
+        self.overlay_lines.begin_frame(
+            (
+                generation(&self.b_scan_segmentation_rx),
+                generation(&self.m_scan_segmentation_rx),
+            ),
+            ui.ctx().pixels_per_point(),
+        );
+
+        let jump = self.jump_list_ui(ui, &textures_state);
+
+        let diameter_overlay = self.diameter_rx.as_ref().map(|rx| rx.borrow());
+        let diameters = diameter_overlay
+            .as_deref()
+            .and_then(|v| match v.data.len() {
+                0 => None,
+                _ => Some(&v.data[..]),
+            });
 
         let layout = Layout {
             main_dir: egui::Direction::RightToLeft,
             cross_justify: true,
             ..*ui.layout()
         };
-        let response = ui
+        let now = ui.input(|i| i.time);
+
+        let linked_b_scan = self.link.receive(link).or(jump);
+        if let Some(b_scan) = linked_b_scan {
+            self.flash = Some((b_scan, now));
+        }
+
+        let highlight = self.flash.and_then(|(b_scan, time)| {
+            let opacity = 1.0 - (now - time) / FLASH_SECONDS;
+            (opacity > 0.0).then_some((b_scan, opacity as f32))
+        });
+        match highlight {
+            Some(_) => ui.ctx().request_repaint(),
+            None => self.flash = None,
+        }
+
+        let mut rotation = 0.0;
+        let mut current_b_scan = None;
+        let (response, polar) = ui
             .with_layout(layout, |ui| {
                 let b_scan_segmentation =
                     self.b_scan_segmentation_rx.as_ref().map(|rx| rx.borrow());
@@ -302,70 +524,148 @@ impl DataView for View {
 
                 let m_scan_segmentation = m_scan_segmentation
                     .as_deref()
-                    .map(|v| v.as_slice())
+                    .map(|v| v.data.as_slice())
                     .and_then(|v| match v.len() {
                         0..=2 => None,
                         _ => Some(v),
                     });
 
-                if let Some(b_scan_segmentation) = b_scan_segmentation {
-                    if b_scan_segmentation.len() > 1 {
-                        cartesian_m_scan_ui(
-                            ui,
-                            textures_state,
-                            texture_bind_group.clone(),
-                            b_scan_segmentation.as_slice(),
-                            m_scan_segmentation,
-                            diameters,
-                            self.map_idx,
-                        )
-                    }
-                }
-
-                let b_scan_segmentation = self
+                let side_b_scan_segmentation = self
                     .b_scan_segmentation_rx
                     .as_ref()
                     .map(|rx| rx.borrow())
-                    .and_then(|b| match b.len() {
+                    .and_then(|b| match b.data.len() {
                         0..=2 => None,
                         _ => Some(b),
                     });
 
+                let overlays = Overlays::of_scan(
+                    side_b_scan_segmentation
+                        .as_deref()
+                        .map(|b| b.data.as_slice()),
+                    m_scan_segmentation,
+                    diameters,
+                    Some(&mut self.overlay_lines),
+                );
+
+                if let Some(b_scan_segmentation) = b_scan_segmentation {
+                    if b_scan_segmentation.data.len() > 1 {
+                        let (b_scan, side_rotation) = cartesian_m_scan_ui(
+                            ui,
+                            &textures_state,
+                            texture_bind_group.clone(),
+                            b_scan_segmentation.data.as_slice(),
+                            &overlays,
+                            self.color_mapping,
+                            linked_b_scan,
+                            self.perf.timing(0),
+                        );
+                        current_b_scan = Some(b_scan);
+                        rotation = side_rotation;
+                    }
+                }
+
+                let b_scan_segmentation = &side_b_scan_segmentation;
+
                 if let (Some(b_scan_segmentation), Some((_, bind_group)), true) = (
                     &b_scan_segmentation,
                     &self.b_scan_segmentation_buffer,
                     self.show_side_view,
                 ) {
-                    side_m_scan_ui(
+                    let response = side_m_scan_ui(
                         ui,
-                        textures_state,
+                        &textures_state,
                         texture_bind_group.clone(),
                         bind_group.clone(),
-                        b_scan_segmentation,
-                        m_scan_segmentation,
-                        self.map_idx,
-                    )
+                        &b_scan_segmentation.data,
+                        &overlays,
+                        self.side_view_neighborhood,
+                        self.color_mapping,
+                        self.perf.timing(1),
+                    );
+                    (response, None)
                 } else {
-                    polar_m_scan_ui(
+                    let response = polar_m_scan_ui(
                         ui,
-                        textures_state,
+                        &textures_state,
                         texture_bind_group.clone(),
-                        b_scan_segmentation.as_deref().map(|rx| rx.as_slice()),
-                        m_scan_segmentation,
-                        self.map_idx,
-                    )
+                        b_scan_segmentation.as_deref().map(|b| b.data.as_slice()),
+                        &overlays,
+                        highlight,
+                        self.live_region
+                            .as_ref()
+                            .map(|(_, a_scans)| a_scans.clone()),
+                        range::clamp(range::selected(), textures_state.a_scan_count),
+                        self.aspect_mode,
+                        self.b_scan_spacing,
+                        self.b_scan_segmentation_buffer
+                            .as_ref()
+                            .map(|(_, bind_group)| bind_group.clone()),
+                        self.color_mapping,
+                        self.perf.timing(1),
+                    );
+                    (response.response, Some(response.inner))
                 }
             })
             .inner;
 
+        if let Some(b_scan) = current_b_scan {
+            self.link.track(link, b_scan);
+        }
+        self.current_b_scan = current_b_scan;
+
+        let mut open_animation_dialog = false;
+        let mut open_image_dialog = false;
         ui.allocate_ui_at_rect(response.rect.expand(-5.0), |ui| {
             ui.horizontal(|ui| {
+                if m_scan_chain.len() > 1 {
+                    let label = |i: usize, output: &NodeOutput| {
+                        format!("{}. {}", i + 1, pipeline[output.node_id].name())
+                    };
+
+                    let current = m_scan_chain.iter().position(|o| *o == self.m_scan);
+                    ComboBox::from_id_source(ui.id().with("m_scan_selector"))
+                        .selected_text(
+                            current.map_or(String::new(), |i| label(i, &m_scan_chain[i])),
+                        )
+                        .show_ui(ui, |ui| {
+                            for (i, output) in m_scan_chain.iter().enumerate() {
+                                if ui
+                                    .selectable_label(Some(i) == current, label(i, output))
+                                    .clicked()
+                                {
+                                    selected_m_scan = Some(*output);
+                                }
+                            }
+                        })
+                        .response
+                        .on_hover_text("Show the M scan of a node further upstream");
+                }
+
+                ui.toggle_value(&mut self.live_tuning, "⚡ Live")
+                    .on_hover_text(format!(
+                        "Live tuning: Changed settings of upstream nodes are applied to the \
+                         shown A scans first. The whole M scan is processed after {} s \
+                         without changes, or when switching this off.",
+                        DEBOUNCE.as_secs()
+                    ));
+
+                if self.live_tuning && live_tuning.read().unwrap().is_deferring() {
+                    ui.weak("Full update pending")
+                        .on_hover_text("Only the marked A scans show the changed settings yet");
+                }
+
                 // If there is BScanSegmentation input
                 if let Some(true) = self
                     .b_scan_segmentation_rx
                     .as_ref()
-                    .map(|rx| rx.borrow().len() > 1)
+                    .map(|rx| rx.borrow().data.len() > 1)
                 {
+                    self.link.toggle_ui(ui);
+
+                    ui.toggle_value(&mut self.jump_list.open, "☰ Jump List")
+                        .on_hover_text("Bookmarks and flagged B scans of the pullback");
+
                     let mut selected = if self.show_side_view { 1 } else { 0 };
                     ComboBox::from_id_source(ui.id().with("view_selector")).show_index(
                         ui,
@@ -378,54 +678,459 @@ impl DataView for View {
                         },
                     );
                     self.show_side_view = selected == 1;
-                }
 
-                let color_maps = color_maps::get_color_map_names();
-
-                let mut map_idx = 0;
-                let (category, map) = color_maps
-                    .iter()
-                    .find_map(|(category, maps)| {
-                        if self.map_idx < map_idx + maps.len() as u32 {
-                            return Some((category, maps[(self.map_idx - map_idx) as usize]));
-                        } else {
-                            map_idx += maps.len() as u32;
-                            None
-                        }
-                    })
-                    .unwrap();
-
-                ui.menu_button(format!("{category}/{map}"), |ui| {
-                    let mut i = 0;
-                    for (category, maps) in color_maps {
-                        if !ui
-                            .menu_button(*category, |ui| {
-                                for map in *maps {
-                                    if ui.selectable_label(self.map_idx == i, *map).clicked() {
-                                        self.map_idx = i;
-                                        ui.close_menu();
-                                    }
-                                    i += 1;
+                    if self.show_side_view {
+                        ui.add(
+                            egui::DragValue::new(&mut self.side_view_neighborhood)
+                                .range(0.0..=0.25)
+                                .speed(0.001)
+                                .prefix("± ")
+                                .custom_formatter(|v, _| format!("{:.1} %", v * 100.0))
+                                .custom_parser(|s| {
+                                    s.trim_end_matches('%')
+                                        .trim()
+                                        .parse::<f64>()
+                                        .ok()
+                                        .map(|v| v / 100.0)
+                                }),
+                        )
+                        .on_hover_text(
+                            "Angular neighborhood averaged per B scan, as fraction of a B scan",
+                        );
+                    } else {
+                        ComboBox::from_id_source(ui.id().with("b_scan_spacing"))
+                            .selected_text(self.b_scan_spacing.name())
+                            .show_ui(ui, |ui| {
+                                for spacing in BScanSpacing::ALL {
+                                    ui.selectable_value(
+                                        &mut self.b_scan_spacing,
+                                        spacing,
+                                        spacing.name(),
+                                    );
                                 }
                             })
                             .response
-                            .context_menu_opened()
+                            .on_hover_text("Give every B scan the same width in the polar view");
+                    }
+
+                    if ui
+                        .button("Export animation…")
+                        .on_hover_text("Export the cartesian view of every B scan as animation")
+                        .clicked()
+                    {
+                        open_animation_dialog = true;
+                    }
+
+                    if ui
+                        .button("Histogram…")
+                        .on_hover_text("Open a histogram of the values, to pick thresholds on")
+                        .clicked()
+                    {
+                        super::histogram::request_open(ui.ctx(), self.m_scan);
+                    }
+
+                    if let Some(b_scans) = self.b_scan_segmentation {
+                        if ui
+                            .button("3D Volume…")
+                            .on_hover_text("Open a volume rendering of the whole pullback")
+                            .clicked()
                         {
-                            i += maps.len() as u32;
+                            super::volume::request_open(ui.ctx(), self.m_scan, b_scans);
                         }
                     }
-                })
-                .response
-                .on_hover_text("All color maps from Matplotlib");
+                }
+
+                // Only the polar view returns a scale
+                if let Some((scale, _)) = polar {
+                    ComboBox::from_id_source(ui.id().with("aspect_mode"))
+                        .selected_text(self.aspect_mode.name())
+                        .show_ui(ui, |ui| {
+                            for mode in AspectMode::ALL {
+                                ui.selectable_value(&mut self.aspect_mode, mode, mode.name());
+                            }
+                        })
+                        .response
+                        .on_hover_text("How the M scan fills the view");
+
+                    if self.aspect_mode != AspectMode::Stretch {
+                        ui.label(format!("{:.0} %", scale * 100.0))
+                            .on_hover_text("Zoom, at 100 % one texel is one screen pixel");
+                    }
+                }
+
+                color_maps::color_map_menu(ui, &mut self.color_mapping.map_idx)
+                    .on_hover_text("All color maps from Matplotlib");
+
+                // The histogram is complete, once the M scan is uploaded
+                let upload = match textures_state.working {
+                    true => None,
+                    false => textures_state.upload.try_lock().ok(),
+                };
+                display::color_mapping_ui(
+                    ui,
+                    &mut self.color_mapping,
+                    upload.as_ref().map(|upload| &upload.histogram),
+                );
+                drop(upload);
+
+                if ui
+                    .button("Export image…")
+                    .on_hover_text("Export the polar or cartesian view as PNG at any resolution")
+                    .clicked()
+                {
+                    open_image_dialog = true;
+                }
+
+                let invalid = [
+                    (
+                        "B scan segmentation",
+                        overlay_invalid(&self.b_scan_segmentation_rx),
+                    ),
+                    (
+                        "M scan segmentation",
+                        overlay_invalid(&self.m_scan_segmentation_rx),
+                    ),
+                    ("diameter", overlay_invalid(&self.diameter_rx)),
+                ];
+
+                if invalid.iter().any(|(_, count)| *count > 0) {
+                    let details = invalid
+                        .iter()
+                        .filter(|(_, count)| *count > 0)
+                        .map(|(name, count)| format!("{count} invalid {name} entries"))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+
+                    ui.colored_label(ui.visuals().warn_fg_color, "⚠ Invalid overlay data")
+                        .on_hover_text(format!(
+                            "{details}\n\nOut of range values were clamped or are not drawn."
+                        ));
+                }
+
+                self.perf.menu_ui(ui);
+
+                if cfg!(debug_assertions) {
+                    ui.weak(format!("{} points", self.overlay_lines.painted()))
+                        .on_hover_text("Points of the segmentation lines painted in this frame");
+                }
             });
+
+            if let Some(received) = textures_state.ended_early {
+                egui::Frame::popup(ui.style())
+                    .fill(ui.visuals().error_fg_color.gamma_multiply(0.3))
+                    .show(ui, |ui| {
+                        let format = NumberFormat::current();
+                        let text = RichText::new(format!(
+                            "Stream ended early at A scan {} of {} — click to retry",
+                            format.count(received),
+                            format.count(textures_state.a_scan_count)
+                        ))
+                        .color(ui.visuals().error_fg_color);
+                        if ui
+                            .add(egui::Label::new(text).sense(egui::Sense::click()))
+                            .on_hover_cursor(egui::CursorIcon::PointingHand)
+                            .on_hover_text("Request the M scan again from the nodes that failed")
+                            .clicked()
+                        {
+                            self.retries += 1;
+                        }
+                    });
+            } else if textures_state.dropped_a_scans > 0 {
+                egui::Frame::popup(ui.style())
+                    .fill(ui.visuals().error_fg_color.gamma_multiply(0.3))
+                    .show(ui, |ui| {
+                        ui.colored_label(
+                            ui.visuals().error_fg_color,
+                            format!(
+                                "{} A scans are not displayed, because they do not fit into {} \
+                                 textures. Try a larger input chunk size.",
+                                textures_state.dropped_a_scans, MAX_TEXTURES
+                            ),
+                        );
+                    });
+            } else if textures_state.merged && !self.merge_notice_dismissed {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "Textures were merged to stay below {} textures.",
+                            MAX_TEXTURES
+                        ));
+                        if ui.small_button("Ok").clicked() {
+                            self.merge_notice_dismissed = true;
+                        }
+                    });
+                });
+            }
         });
 
         if textures_state.working {
             ui.ctx().request_repaint();
         }
+
+        drop(diameter_overlay);
+
+        if self.live_tuning {
+            let visible_a_scans = polar
+                .as_ref()
+                .and_then(|(_, visible)| visible.clone())
+                .or_else(|| {
+                    let b_scan_segmentation = self.b_scan_segmentation_rx.as_ref()?.borrow();
+                    let b_scan = current_b_scan?;
+                    Some(
+                        *b_scan_segmentation.data.get(b_scan)?
+                            ..*b_scan_segmentation.data.get(b_scan + 1)?,
+                    )
+                })
+                .unwrap_or(0..textures_state.a_scan_count);
+
+            self.live_tuning_ui(&textures_state, live_tuning, visible_a_scans);
+        }
+
+        if open_animation_dialog && self.animation_dialog.is_none() {
+            self.animation_dialog = Some(AnimationDialog::new(self.selected_b_scans()));
+        }
+
+        if let Some(mut dialog) = self.animation_dialog.take() {
+            let open = dialog.show(
+                ui.ctx(),
+                ui.id().with("animation_dialog"),
+                self.b_scan_count(),
+                self.selected_b_scans(),
+                || self.animation_source(ui.ctx(), &textures_state, rotation),
+            );
+            if open {
+                self.animation_dialog = Some(dialog);
+            }
+        }
+
+        if open_image_dialog && self.image_dialog.is_none() {
+            let view = match current_b_scan {
+                Some(_) => ImageView::Cartesian,
+                None => ImageView::Polar,
+            };
+            self.image_dialog = Some(ImageDialog::new(view, current_b_scan.unwrap_or(0)));
+        }
+
+        if let Some(mut dialog) = self.image_dialog.take() {
+            let open = dialog.show(
+                ui.ctx(),
+                ui.id().with("image_dialog"),
+                current_b_scan,
+                || self.animation_source(ui.ctx(), &textures_state, rotation),
+            );
+            if open {
+                self.image_dialog = Some(dialog);
+            }
+        }
+
+        selected_m_scan
+    }
+
+    /// Requests a live tuning preview of `visible_a_scans` and writes new
+    /// previews into the textures, unless they are being uploaded.
+    fn live_tuning_ui(
+        &mut self,
+        textures_state: &TexturesState,
+        live_tuning: &SharedLiveTuning,
+        visible_a_scans: Range<usize>,
+    ) {
+        let a_scans = visible_a_scans.start
+            ..visible_a_scans
+                .end
+                .min(visible_a_scans.start + MAX_LIVE_A_SCANS);
+
+        let result = live_tuning.write().unwrap().request(self.m_scan, a_scans);
+
+        let Some(result) = result.filter(|r| Some(r.id) != self.live_result) else {
+            return;
+        };
+        if textures_state.working {
+            return;
+        }
+        let Ok(mut upload) = textures_state.upload.try_lock() else {
+            return;
+        };
+
+        write_a_scans(
+            &self.queue,
+            &mut upload,
+            &result.data,
+            result.a_scans.start,
+            textures_state.a_scan_samples,
+        );
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("MScan Pyramid Encoder"),
+            });
+        upload.update_pyramid(&self.device, &mut encoder, &self.pyramid);
+        self.queue.submit([encoder.finish()]);
+        drop(upload);
+
+        self.live_result = Some(result.id);
+        self.live_region = Some((textures_state.upload.clone(), result.a_scans));
+    }
+
+    /// Shows the [JumpList] in a side panel, if it is open. Returns the B
+    /// scan to jump to.
+    fn jump_list_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        textures_state: &Arc<TexturesState>,
+    ) -> Option<usize> {
+        if !self.jump_list.open || self.b_scan_count() == 0 {
+            return None;
+        }
+
+        let b_scans = self.b_scan_segmentation_rx.as_ref()?.borrow();
+        let m_scan_segmentation = self.m_scan_segmentation_rx.as_ref().map(|rx| rx.borrow());
+        let diameters = self.diameter_rx.as_ref().map(|rx| rx.borrow());
+
+        let generation = |overlay: Option<u64>| overlay.unwrap_or(0);
+        let generations = [
+            b_scans.generation,
+            generation(m_scan_segmentation.as_ref().map(|o| o.generation)),
+            generation(diameters.as_ref().map(|o| o.generation)),
+        ];
+        let input = EventInput {
+            b_scans: &b_scans.data,
+            m_scan_segmentation: m_scan_segmentation
+                .as_ref()
+                .map(|o| o.data.as_slice())
+                .filter(|data| data.len() > 2),
+            diameters: diameters
+                .as_ref()
+                .map(|o| o.data.as_slice())
+                .filter(|data| !data.is_empty()),
+        };
+
+        // Thumbnails are rendered, once the M scan and its overlays are
+        // complete, and are rendered again, when any of them change
+        let thumbnails = (!textures_state.working).then(|| {
+            let upload = Arc::as_ptr(&textures_state.upload) as usize;
+            let mut hasher = std::hash::DefaultHasher::new();
+            std::hash::Hash::hash(
+                &(upload, textures_state.a_scan_count, generations),
+                &mut hasher,
+            );
+            std::hash::Hash::hash(&self.color_mapping, &mut hasher);
+            std::hash::Hasher::finish(&hasher)
+        });
+
+        let ctx = ui.ctx().clone();
+        let mut jump_list = mem::take(&mut self.jump_list);
+        let jump = egui::SidePanel::right(ui.id().with("jump_list"))
+            .resizable(true)
+            .default_width(240.0)
+            .show_inside(ui, |ui| {
+                jump_list.ui(
+                    ui,
+                    &input,
+                    generations,
+                    thumbnails,
+                    self.current_b_scan,
+                    || self.animation_source(&ctx, textures_state, 0.0),
+                )
+            })
+            .inner;
+
+        drop((b_scans, m_scan_segmentation, diameters));
+        self.jump_list = jump_list;
+
+        jump
+    }
+
+    fn b_scan_count(&self) -> usize {
+        self.b_scan_segmentation_rx
+            .as_ref()
+            .map_or(0, |rx| rx.borrow().data.len().saturating_sub(1))
+    }
+
+    /// The B scans in the range selector, all without a selection.
+    fn selected_b_scans(&self) -> Range<usize> {
+        let Some(rx) = self.b_scan_segmentation_rx.as_ref() else {
+            return 0..0;
+        };
+        let b_scans = &rx.borrow().data;
+
+        match range::selected() {
+            Some(a_scans) => range::b_scans_of(&a_scans, b_scans),
+            None => 0..b_scans.len().saturating_sub(1),
+        }
+    }
+
+    /// Copies the current overlays, so the exported frames look like the
+    /// view. Without B scan segmentation, only the polar view can be
+    /// exported.
+    fn animation_source(
+        &self,
+        ctx: &egui::Context,
+        textures_state: &Arc<TexturesState>,
+        rotation: f32,
+    ) -> Option<AnimationSource> {
+        let b_scan_segmentation = self
+            .b_scan_segmentation_rx
+            .as_ref()
+            .map(|rx| rx.borrow().data.clone())
+            .unwrap_or_default();
+        let m_scan_segmentation = self
+            .m_scan_segmentation_rx
+            .as_ref()
+            .map(|rx| rx.borrow().data.clone())
+            .filter(|data| data.len() > 2);
+        let diameters = self
+            .diameter_rx
+            .as_ref()
+            .map(|rx| rx.borrow().data.clone())
+            .filter(|data| !data.is_empty());
+
+        Some(AnimationSource {
+            device: self.device.clone(),
+            queue: self.queue.clone(),
+            target_format: self.target_format,
+            scan_bind_group_layout: self.bind_group_layout.clone(),
+            b_scan_segmentation_bind_group_layout: self
+                .b_scan_segmentation_bind_group_layout
+                .clone(),
+            textures_state: textures_state.clone(),
+            texture_bind_group: textures_state.bind_group.clone()?,
+            b_scan_segmentation,
+            m_scan_segmentation,
+            diameters,
+            rotation,
+            color_mapping: self.color_mapping,
+            style: ctx.style(),
+            pixels_per_point: ctx.pixels_per_point(),
+        })
     }
 }
 
+/// The M scans, the view can switch between: `head` and the M scans upstream
+/// of it, found like in [find_m_scan_input]. Starts at the most upstream one.
+fn m_scan_chain(pipeline: &Pipeline, head: NodeOutput) -> Vec<NodeOutput> {
+    if !pipeline.nodes.contains_key(&head.node_id) {
+        return Vec::new();
+    }
+
+    let mut chain = vec![head];
+    let mut seen_nodes = HashSet::from([head.node_id]);
+
+    while let Some(output) = find_m_scan_input(pipeline, chain[chain.len() - 1].node_id) {
+        if !seen_nodes.insert(output.node_id) {
+            break;
+        }
+        chain.push(output);
+    }
+
+    chain.reverse();
+    chain
+}
+
+fn overlay_invalid<T>(rx: &Option<watch::Receiver<Overlay<T>>>) -> usize {
+    rx.as_ref().map_or(0, |rx| rx.borrow().invalid)
+}
+
 fn find_m_scan_input(pipeline: &Pipeline, node_id: NodeId) -> Option<NodeOutput> {
     let mut seen_nodes = HashSet::new();
 
@@ -508,14 +1213,15 @@ struct Task {
     m_scan_segmentation_in: TaskInput<requests::MScanSegmentation>,
     diameter_in: TaskInput<requests::Diameter>,
 
-    textures_state: Cached<Option<TexturesState>>,
+    textures_state: Cached<Option<Arc<TexturesState>>>,
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>,
     bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    pyramid: Arc<Pyramid>,
 
-    b_scan_segmentation_tx: watch::Sender<Vec<usize>>,
-    m_scan_segmentation_tx: watch::Sender<Vec<usize>>,
-    diameter_tx: watch::Sender<Vec<BScanDiameter>>,
+    b_scan_segmentation_tx: watch::Sender<Overlay<usize>>,
+    m_scan_segmentation_tx: watch::Sender<Overlay<usize>>,
+    diameter_tx: watch::Sender<Overlay<BScanDiameter>>,
 }
 
 impl DataViewTask for Task {
@@ -547,12 +1253,10 @@ impl DataViewTask for Task {
 
     fn invalidate(&mut self, cause: InvalidationCause) {
         fn invalidate_m_scan(slf: &mut Task) {
-            *slf.textures_state.write() = None;
+            slf.textures_state.replace(None);
         }
-        fn invalidate_sender<T>(tx: &watch::Sender<Vec<T>>) {
-            let _ = tx.send_modify(|d| {
-                d.clear();
-            });
+        fn invalidate_sender<T>(tx: &watch::Sender<Overlay<T>>) {
+            tx.send_modify(Overlay::clear);
         }
 
         match cause {
@@ -565,10 +1269,16 @@ impl DataViewTask for Task {
                 InputId::MScanSegmentation => invalidate_sender(&self.m_scan_segmentation_tx),
                 InputId::Diameter => invalidate_sender(&self.diameter_tx),
             },
+            // Views are not cancelled
+            InvalidationCause::UserCancelled => {}
         }
     }
 
     async fn run(&mut self) -> anyhow::Result<()> {
+        // Segmentations are validated against the dimensions of the M scan,
+        // so they are only received after it
+        let has_dimensions = self.dimensions().is_some();
+
         tokio::select! {
             biased;
             Some(res) = async {
@@ -581,8 +1291,8 @@ impl DataViewTask for Task {
                 self.get_m_scan(res).await?;
             }
             Some(res) = async {
-                let is_empty = self.b_scan_segmentation_tx.borrow().is_empty();
-                match is_empty {
+                let is_empty = self.b_scan_segmentation_tx.borrow().data.is_empty();
+                match is_empty && has_dimensions {
                     false => None,
                     _ => {
                         self.b_scan_segmentation_in
@@ -594,8 +1304,8 @@ impl DataViewTask for Task {
                 self.get_b_scan_segmentation(res).await?;
             }
             Some(res) = async {
-                let is_empty = self.m_scan_segmentation_tx.borrow().is_empty();
-                match is_empty {
+                let is_empty = self.m_scan_segmentation_tx.borrow().data.is_empty();
+                match is_empty && has_dimensions {
                     false => None,
                     _ => {
                         self.m_scan_segmentation_in
@@ -607,7 +1317,7 @@ impl DataViewTask for Task {
                 self.get_m_scan_segmentation(res).await?;
             }
             Some(res) = async {
-                let is_empty = self.diameter_tx.borrow().is_empty();
+                let is_empty = self.diameter_tx.borrow().data.is_empty();
                 match is_empty {
                     false => None,
                     _ => self.diameter_in.request(requests::Diameter).await
@@ -623,17 +1333,24 @@ impl DataViewTask for Task {
 }
 
 impl Task {
+    /// The A scan count and samples per A scan of the received M scan.
+    fn dimensions(&self) -> Option<(usize, usize)> {
+        self.textures_state
+            .read()
+            .as_ref()
+            .map(|state| (state.a_scan_count, state.a_scan_samples))
+    }
+
     async fn get_b_scan_segmentation(
         &mut self,
-        res: requests::StreamedResponse<usize>,
+        res: requests::BScanSegmentationResponse,
     ) -> anyhow::Result<()> {
-        let Some(mut rx) = res.subscribe() else {
+        let (Some(mut rx), Some((a_scan_count, _))) = (res.data.subscribe(), self.dimensions())
+        else {
             return Ok(());
         };
 
-        self.b_scan_segmentation_tx.send_modify(|d| {
-            d.clear();
-        });
+        self.b_scan_segmentation_tx.send_modify(Overlay::clear);
 
         loop {
             let data = match rx.recv().await {
@@ -643,7 +1360,7 @@ impl Task {
             };
 
             self.b_scan_segmentation_tx.send_modify(|d| {
-                d.push(data);
+                d.push_b_scan_boundary(data, a_scan_count);
             });
         }
 
@@ -652,15 +1369,14 @@ impl Task {
 
     async fn get_m_scan_segmentation(
         &mut self,
-        res: requests::StreamedResponse<Arc<DVector<u32>>>,
+        res: requests::MScanSegmentationResponse,
     ) -> anyhow::Result<()> {
-        let Some(mut rx) = res.subscribe() else {
+        let (Some(mut rx), Some((_, a_scan_samples))) = (res.data.subscribe(), self.dimensions())
+        else {
             return Ok(());
         };
 
-        self.m_scan_segmentation_tx.send_modify(|d| {
-            d.clear();
-        });
+        self.m_scan_segmentation_tx.send_modify(Overlay::clear);
 
         loop {
             let data = match rx.recv().await {
@@ -670,24 +1386,19 @@ impl Task {
             };
 
             self.m_scan_segmentation_tx.send_modify(|d| {
-                d.extend(data.iter().map(|d| *d as usize));
+                d.extend_m_scan_segmentation(data.as_slice(), a_scan_samples);
             });
         }
 
         Ok(())
     }
 
-    async fn get_diameter(
-        &mut self,
-        res: requests::StreamedResponse<BScanDiameter>,
-    ) -> anyhow::Result<()> {
-        let Some(mut rx) = res.subscribe() else {
+    async fn get_diameter(&mut self, res: requests::DiameterResponse) -> anyhow::Result<()> {
+        let Some(mut rx) = res.data.subscribe() else {
             return Ok(());
         };
 
-        self.diameter_tx.send_modify(|d| {
-            d.clear();
-        });
+        self.diameter_tx.send_modify(Overlay::clear);
 
         loop {
             let data = match rx.recv().await {
@@ -697,7 +1408,7 @@ impl Task {
             };
 
             self.diameter_tx.send_modify(|d| {
-                d.push(data);
+                d.push_diameter(data);
             });
         }
 
@@ -705,119 +1416,1025 @@ impl Task {
     }
 
     async fn get_m_scan(&mut self, res: requests::MScanResponse) -> anyhow::Result<()> {
-        {
-            let mut state = self.textures_state.write();
-            if state.is_none() {
-                state.replace(TexturesState {
-                    uploaded: Arc::new(RwLock::new(0)),
-                    textures: Vec::new(),
+        // Views of the same M scan share the state. The first one creates it
+        let upload = self
+            .textures_state
+            .write()
+            .get_or_insert_with(|| {
+                Arc::new(TexturesState {
+                    upload: Arc::new(Mutex::new(Upload {
+                        pooling: Settings::current().display.m_scan_pooling,
+                        ..Default::default()
+                    })),
                     bind_group: None,
+                    level_bind_groups: Vec::new(),
+                    capacity: 0,
+                    texture_count: 0,
                     working: true,
                     a_scan_count: res.a_scan_count,
                     a_scan_samples: res.a_scan_samples,
-                });
-            }
-        }
+                    merged: false,
+                    dropped_a_scans: 0,
+                    error: None,
+                    ended_early: None,
+                })
+            })
+            .upload
+            .clone();
 
         let Some(mut rx) = res.data.subscribe() else {
             return Ok(());
         };
 
-        let uploaded = {
-            let state = self.textures_state.read();
-            state.as_ref().unwrap().uploaded.clone()
-        };
-
         let mut my_uploaded = 0;
+        let mut received_a_scans = 0;
 
         loop {
             let data = match rx.recv().await {
                 Ok(data) => data,
                 Err(RecvError::Closed) => break,
+                Err(RecvError::Aborted) => {
+                    // The textures keep what was received, until the retry
+                    self.publish(&upload, |state| {
+                        state.ended_early = Some(received_a_scans);
+                        state.working = false;
+                    });
+                    return Ok(());
+                }
                 _ => return Ok(()),
             };
+            received_a_scans += data.ncols();
 
-            let mut uploaded = uploaded.write().await;
+            let mut uploading = upload.lock().await;
 
             my_uploaded += 1;
-            if my_uploaded <= *uploaded {
+            if my_uploaded <= uploading.chunks {
                 continue;
             }
 
-            // Upload data to GPU
-            let device = self.device.clone();
-            let queue = self.queue.clone();
-            let texture = tokio::task::spawn_blocking(move || {
-                let data = data.cast_rescale_par(types::DataType::U16);
-
-                device.create_texture_with_data(
-                    &queue,
-                    &wgpu::TextureDescriptor {
-                        label: Some("MScan Texture"),
-                        dimension: wgpu::TextureDimension::D2,
-                        format: wgpu::TextureFormat::R16Uint,
-                        mip_level_count: 1,
-                        sample_count: 1,
-                        usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
-                        view_formats: &[],
-                        size: wgpu::Extent3d {
-                            width: res.a_scan_samples as u32,
-                            height: data.ncols() as u32,
-                            depth_or_array_layers: 1,
-                        },
-                    },
-                    wgpu::util::TextureDataOrder::LayerMajor,
-                    data.as_u8_slice(),
-                )
-            })
-            .await?;
+            let max_size = self.device.limits().max_texture_dimension_2d;
+            let Some(plan) = TilePlan::new(res.a_scan_samples, max_size) else {
+                return self.fail(
+                    &upload,
+                    format!(
+                        "A scans with {} samples do not fit into {} textures of at most {} \
+                         samples",
+                        res.a_scan_samples, MAX_TEXTURES, max_size
+                    ),
+                );
+            };
+            if data.ncols() > max_size as usize {
+                return self.fail(
+                    &upload,
+                    format!(
+                        "Chunk of {} A scans exceeds the maximum texture size of {}. Try a \
+                         smaller input chunk size.",
+                        data.ncols(),
+                        max_size
+                    ),
+                );
+            }
 
-            let mut texture_state = self.textures_state.write();
-            let Some(texture_state) = texture_state.as_mut() else {
-                return Ok(());
+            if my_uploaded == 1 {
+                // A canceled upload of the first chunk may have left a texture
+                uploading.textures.clear();
+                uploading.histogram = ValueHistogram::default();
+            }
+
+            let published = if my_uploaded == 1 && data.ncols() > FIRST_CHUNK_SLICE {
+                self.upload_first_chunk(&upload, &mut uploading, data, plan)
+                    .await?
+            } else {
+                self.upload_chunk(&upload, &mut uploading, data, plan)
+                    .await?
             };
+            if !published {
+                return Ok(());
+            }
 
-            let textures = &mut texture_state.textures;
+            uploading.chunks = my_uploaded;
+        }
 
-            textures.push(texture.create_view(&wgpu::TextureViewDescriptor::default()));
+        self.publish(&upload, |state| state.working = false);
 
-            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        Ok(())
+    }
+
+    /// Shows `message` instead of the M scan and fails with it.
+    fn fail(&self, upload: &Arc<Mutex<Upload>>, message: String) -> anyhow::Result<()> {
+        self.publish(upload, |state| {
+            state.error = Some(message.clone());
+            state.working = false;
+        });
+        Err(anyhow!(message))
+    }
+
+    /// Uploads a chunk as its own textures, appending them to the textures.
+    /// Returns false, if the upload got abandoned. See [Self::publish].
+    async fn upload_chunk(
+        &self,
+        upload: &Arc<Mutex<Upload>>,
+        uploading: &mut Upload,
+        data: Arc<types::DataMatrix>,
+        plan: TilePlan,
+    ) -> anyhow::Result<bool> {
+        let device = self.device.clone();
+        let queue = self.queue.clone();
+        let (tiles, histogram) = tokio::task::spawn_blocking(move || {
+            let data = data.cast_rescale_par(types::DataType::U16);
+
+            let tiles = create_m_scan_tiles(&device, plan.widths(), data.ncols() as u32);
+            write_tiles(
+                &queue,
+                &tiles,
+                0,
+                data.as_u8_slice(),
+                plan.a_scan_samples as usize,
+                data.ncols() as u32,
+            );
+            (
+                tiles,
+                ValueHistogram::of(bytemuck::cast_slice(data.as_u8_slice())),
+            )
+        })
+        .await?;
+        uploading.histogram.merge(&histogram);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("MScan Merge Encoder"),
+            });
+        uploading.append(&self.device, &mut encoder, tiles);
+        uploading.update_pyramid(&self.device, &mut encoder, &self.pyramid);
+        self.queue.submit([encoder.finish()]);
+
+        Ok(self.publish_textures(upload, uploading))
+    }
+
+    /// Converting and uploading a whole chunk takes a while, during which the
+    /// view would stay empty. The first chunk is therefore uploaded in slices
+    /// of [FIRST_CHUNK_SLICE] A scans, publishing after each one. The slices
+    /// are written into one texture of the size of the chunk, so its A scans
+    /// are at their final position and later chunks are appended as usual.
+    async fn upload_first_chunk(
+        &self,
+        upload: &Arc<Mutex<Upload>>,
+        uploading: &mut Upload,
+        data: Arc<types::DataMatrix>,
+        plan: TilePlan,
+    ) -> anyhow::Result<bool> {
+        let tiles = create_m_scan_tiles(&self.device, plan.widths(), data.ncols() as u32);
+        uploading.textures.push(MScanTexture::new(tiles, 0));
+
+        for slice in first_chunk_slices(data.ncols()) {
+            let data = data.clone();
+            let len = slice.len();
+            let (converted, histogram) = tokio::task::spawn_blocking(move || {
+                let converted = data
+                    .columns(slice.start, len)
+                    .cast_rescale_par(types::DataType::U16);
+                let histogram = ValueHistogram::of(bytemuck::cast_slice(converted.as_u8_slice()));
+                (converted, histogram)
+            })
+            .await?;
+            uploading.histogram.merge(&histogram);
+
+            let texture = &mut uploading.textures[0];
+            write_tiles(
+                &self.queue,
+                texture.textures(),
+                texture.a_scans,
+                converted.as_u8_slice(),
+                plan.a_scan_samples as usize,
+                len as u32,
+            );
+            texture.a_scans += len as u32;
+            texture.pyramid_stale = true;
+
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("MScan Pyramid Encoder"),
+                });
+            uploading.update_pyramid(&self.device, &mut encoder, &self.pyramid);
+            self.queue.submit([encoder.finish()]);
+
+            if !self.publish_textures(upload, uploading) {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Publishes bind groups of the uploaded textures and their pyramid
+    /// levels. The tiles of every texture are bound one after the other.
+    fn publish_textures(&self, upload: &Arc<Mutex<Upload>>, uploading: &Upload) -> bool {
+        let bind_group = |views: &[&wgpu::TextureView]| {
+            Arc::new(self.device.create_bind_group(&wgpu::BindGroupDescriptor {
                 label: Some("MScan Bind Group"),
                 layout: &self.bind_group_layout,
                 entries: &[wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureViewArray(
-                        &textures
-                            .iter()
-                            .take(MAX_TEXTURES)
-                            .map(|t| t)
-                            .collect::<Vec<_>>(),
-                    ),
+                    resource: wgpu::BindingResource::TextureViewArray(views),
                 }],
-            });
+            }))
+        };
+        let textures = || {
+            uploading
+                .textures
+                .iter()
+                .flat_map(|t| &t.tiles)
+                .take(MAX_TEXTURES)
+        };
+
+        let full = bind_group(&textures().map(|t| &t.view).collect::<Vec<_>>());
+        // Empty, if a texture has no levels yet
+        let levels = (0..pyramid::LEVELS)
+            .map_while(|level| {
+                let views = textures()
+                    .map(|t| t.levels.get(level).map(|(_, view)| view))
+                    .collect::<Option<Vec<_>>>()?;
+                Some(bind_group(&views))
+            })
+            .collect::<Vec<_>>();
+        let levels = match levels.len() {
+            pyramid::LEVELS => levels,
+            _ => Vec::new(),
+        };
+
+        self.publish(upload, |state| {
+            state.bind_group = Some(full);
+            state.level_bind_groups = levels;
+            state.capacity = uploading.textures.first().map_or(0, |t| t.capacity());
+            state.texture_count = textures().count();
+            state.merged = uploading.merged;
+            state.dropped_a_scans = uploading.dropped_a_scans;
+        })
+    }
+
+    /// Replaces the state drawn by the view with an updated copy. Returns
+    /// false, if the state got invalidated or belongs to another upload by
+    /// now.
+    fn publish(&self, upload: &Arc<Mutex<Upload>>, f: impl FnOnce(&mut TexturesState)) -> bool {
+        let mut textures_state = self.textures_state.write();
+
+        let mut state = match textures_state.as_deref() {
+            Some(state) if Arc::ptr_eq(&state.upload, upload) => state.clone(),
+            _ => return false,
+        };
+        f(&mut state);
+
+        let old = textures_state.replace(Arc::new(state));
+        // The last reference to the old textures may be dropped here, which
+        // should not happen while holding the lock
+        drop(textures_state);
+        drop(old);
+
+        true
+    }
+}
+
+// MARK: Overlay
 
-            texture_state.bind_group = Some(Arc::new(bind_group));
+/// Data drawn on top of the M scan, as received from an input of the view.
+///
+/// Upstream nodes may produce garbage, like NaN cast to integers. Entries are
+/// validated when they are received, so drawing does not have to deal with
+/// them. Invalid entries are counted, to warn the user.
+#[derive(Debug)]
+struct Overlay<T> {
+    data: Vec<T>,
+    /// Number of entries, that were out of range and got clamped or are not
+    /// drawn.
+    invalid: usize,
+    /// Changes with every modification of [Self::data] and is unique among
+    /// all overlays, so lines built from the data can be cached, see
+    /// [OverlayLines].
+    generation: u64,
+}
 
-            *uploaded = my_uploaded;
+impl<T> Default for Overlay<T> {
+    fn default() -> Self {
+        Self {
+            data: Vec::new(),
+            invalid: 0,
+            generation: next_generation(),
         }
+    }
+}
 
-        self.textures_state
-            .write()
-            .as_mut()
-            .map(|state| state.working = false);
+impl<T> Overlay<T> {
+    fn clear(&mut self) {
+        self.data.clear();
+        self.invalid = 0;
+        self.generation = next_generation();
+    }
+}
 
-        Ok(())
+fn next_generation() -> u64 {
+    static GENERATION: AtomicU64 = AtomicU64::new(0);
+    GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Overlay<usize> {
+    /// Clamps the boundary into the M scan and to be not before the previous
+    /// boundary, so every B scan is a valid range of A scans.
+    fn push_b_scan_boundary(&mut self, boundary: usize, a_scan_count: usize) {
+        let min = self.data.last().copied().unwrap_or(0).min(a_scan_count);
+        let clamped = boundary.clamp(min, a_scan_count);
+        self.generation = next_generation();
+
+        if clamped != boundary {
+            self.invalid += 1;
+        }
+        self.data.push(clamped);
+    }
+
+    /// Depths outside of the A scans are replaced by [INVALID_DEPTH], which
+    /// is not drawn. [INVALID_DEPTH] itself marks A scans without a
+    /// segmentation and is not counted.
+    fn extend_m_scan_segmentation(&mut self, depths: &[u32], a_scan_samples: usize) {
+        self.data.reserve(depths.len());
+        self.generation = next_generation();
+
+        for &depth in depths {
+            let depth = depth as usize;
+            if depth < a_scan_samples || depth == INVALID_DEPTH {
+                self.data.push(depth);
+            } else {
+                self.invalid += 1;
+                self.data.push(INVALID_DEPTH);
+            }
+        }
     }
 }
 
+impl Overlay<BScanDiameter> {
+    /// Diameters with non-finite values are kept, so the diameters stay
+    /// aligned with the B scans, but are not drawn.
+    fn push_diameter(&mut self, diameter: BScanDiameter) {
+        self.generation = next_generation();
+        if !diameter.is_finite() {
+            self.invalid += 1;
+        }
+        self.data.push(diameter);
+    }
+}
+
+/// Marks an A scan without a valid M scan segmentation.
+const INVALID_DEPTH: usize = u32::MAX as usize;
+
 // MARK: TexturesState
 
-#[derive(Default)]
+/// What the view draws. Tasks publish a new state after every uploaded
+/// chunk, so the view never waits for an upload. See [Cached].
+#[derive(Clone)]
 struct TexturesState {
-    uploaded: Arc<RwLock<usize>>,
-    textures: Vec<wgpu::TextureView>,
+    upload: Arc<Mutex<Upload>>,
     bind_group: Option<Arc<wgpu::BindGroup>>,
+    /// Bind groups of the downsampled levels of the textures, see [pyramid].
+    /// Empty, while they are not available.
+    level_bind_groups: Vec<Arc<wgpu::BindGroup>>,
+    /// Number of A scans every texture can hold.
+    capacity: u32,
+    /// Number of textures in [Self::bind_group].
+    texture_count: usize,
     working: bool,
     a_scan_count: usize,
     a_scan_samples: usize,
+    /// Whether textures had to be merged to stay below [MAX_TEXTURES].
+    merged: bool,
+    /// Number of A scans, that did not fit into [MAX_TEXTURES] textures.
+    dropped_a_scans: usize,
+    /// Why the M scan can not be shown at all.
+    error: Option<String>,
+    /// Number of A scans received, when the stream ended before it was
+    /// complete, see [crate::queue_channel::Sender::finish].
+    ended_early: Option<usize>,
+}
+
+/// The textures being uploaded. Tasks of views showing the same M scan take
+/// turns uploading its chunks.
+#[derive(Default)]
+struct Upload {
+    /// Number of chunks uploaded so far.
+    chunks: usize,
+    textures: Vec<MScanTexture>,
+    merged: bool,
+    dropped_a_scans: usize,
+    /// How the pyramid levels of the textures are rendered.
+    pooling: MScanPooling,
+    /// Histogram of the uploaded chunks, without live tuning previews.
+    histogram: ValueHistogram,
+}
+
+/// A texture holding consecutive A scans. All textures of an M scan have the
+/// same capacity (height), so the shader can compute which texture holds an A
+/// scan. Only the last texture may not be full.
+///
+/// The samples of the A scans are split into one or more row tiles, see
+/// [TilePlan].
+struct MScanTexture {
+    tiles: Vec<MScanTile>,
+    /// Number of A scans written to this texture.
+    a_scans: u32,
+    /// Whether A scans were written since the levels were rendered.
+    pyramid_stale: bool,
+}
+
+/// The samples of a [TilePlan::widths] range of every A scan of an
+/// [MScanTexture].
+struct MScanTile {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    /// Downsampled levels, see [pyramid]. Created on the first
+    /// [Upload::update_pyramid].
+    levels: Vec<(wgpu::Texture, wgpu::TextureView)>,
+}
+
+impl MScanTexture {
+    fn new(tiles: Vec<wgpu::Texture>, a_scans: u32) -> Self {
+        let tiles = tiles
+            .into_iter()
+            .map(|texture| MScanTile {
+                view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                texture,
+                levels: Vec::new(),
+            })
+            .collect();
+        Self {
+            tiles,
+            a_scans,
+            pyramid_stale: true,
+        }
+    }
+
+    fn capacity(&self) -> u32 {
+        self.tiles[0].texture.height()
+    }
+
+    fn textures(&self) -> impl Iterator<Item = &wgpu::Texture> {
+        self.tiles.iter().map(|tile| &tile.texture)
+    }
+
+    fn widths(&self) -> impl Iterator<Item = u32> + '_ {
+        self.tiles.iter().map(|tile| tile.texture.width())
+    }
+}
+
+impl Upload {
+    /// Textures, whose tiles fit into [MAX_TEXTURES].
+    fn max_textures(&self) -> usize {
+        MAX_TEXTURES / self.textures.first().map_or(1, |t| t.tiles.len())
+    }
+
+    /// Appends the A scans of the tiles of `chunk` to the textures. When the
+    /// chunk does not fit, its A scans are copied into the last texture or
+    /// into new ones.
+    fn append(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        chunk: Vec<wgpu::Texture>,
+    ) {
+        let height = chunk[0].height();
+
+        // Usual case: The chunk gets its own texture
+        let fits = match self.textures.last() {
+            None => true,
+            Some(last) => {
+                last.a_scans == last.capacity()
+                    && height == last.capacity()
+                    && self.textures.len() < self.max_textures()
+            }
+        };
+        if fits {
+            self.textures.push(MScanTexture::new(chunk, height));
+            return;
+        }
+
+        let mut offset = 0;
+        while offset < height {
+            let capacity = self.textures[0].capacity();
+            let last = self.textures.last_mut().unwrap();
+
+            if last.a_scans < capacity {
+                let count = (height - offset).min(capacity - last.a_scans);
+                for (src, dst) in chunk.iter().zip(last.textures()) {
+                    copy_a_scans(encoder, src, offset, dst, last.a_scans, count);
+                }
+                last.a_scans += count;
+                last.pyramid_stale = true;
+                offset += count;
+            } else if self.textures.len() < self.max_textures() {
+                let widths = chunk.iter().map(|tile| tile.width());
+                let tiles = create_m_scan_tiles(device, widths, capacity);
+                self.textures.push(MScanTexture::new(tiles, 0));
+            } else if !self.merge(device, encoder) {
+                self.dropped_a_scans += (height - offset) as usize;
+                break;
+            }
+        }
+    }
+
+    /// Renders the pyramid levels of the textures, that changed since.
+    fn update_pyramid(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        pyramid: &Pyramid,
+    ) {
+        for texture in self.textures.iter_mut().filter(|t| t.pyramid_stale) {
+            let capacity = texture.capacity();
+
+            for tile in &mut texture.tiles {
+                if tile.levels.is_empty() {
+                    tile.levels = (1..=pyramid::LEVELS)
+                        .map(|level| {
+                            let capacity = pyramid::level_capacity(capacity, level);
+                            let level =
+                                create_m_scan_level_texture(device, tile.texture.width(), capacity);
+                            let view = level.create_view(&wgpu::TextureViewDescriptor::default());
+                            (level, view)
+                        })
+                        .collect();
+                }
+
+                let mut source = &tile.view;
+                let mut rows = texture.a_scans;
+                for (_, level) in &tile.levels {
+                    pyramid.downsample(device, encoder, source, rows, level, self.pooling);
+                    source = level;
+                    rows = rows.div_ceil(pyramid::LEVEL_FACTOR);
+                }
+            }
+
+            texture.pyramid_stale = false;
+        }
+    }
+
+    /// Merges pairs of adjacent textures into textures of twice the capacity.
+    /// Returns false, if the merged textures would be too large.
+    fn merge(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) -> bool {
+        let Some((capacity, groups)) = plan_merge(
+            self.textures.len(),
+            self.textures[0].capacity(),
+            device.limits().max_texture_dimension_2d,
+        ) else {
+            return false;
+        };
+
+        let widths = self.textures[0].widths().collect::<Vec<_>>();
+        let old = mem::take(&mut self.textures);
+
+        self.textures = groups
+            .into_iter()
+            .map(|group| {
+                let merged = create_m_scan_tiles(device, widths.iter().copied(), capacity);
+                let mut a_scans = 0;
+                for texture in &old[group] {
+                    for (src, dst) in texture.textures().zip(&merged) {
+                        copy_a_scans(encoder, src, 0, dst, a_scans, texture.a_scans);
+                    }
+                    a_scans += texture.a_scans;
+                }
+                MScanTexture::new(merged, a_scans)
+            })
+            .collect();
+
+        self.merged = true;
+
+        true
+    }
+}
+
+/// Ranges of A scans, in which the first chunk of an M scan is uploaded. See
+/// [Task::upload_first_chunk].
+fn first_chunk_slices(a_scans: usize) -> impl Iterator<Item = Range<usize>> {
+    (0..a_scans)
+        .step_by(FIRST_CHUNK_SLICE)
+        .map(move |start| start..(start + FIRST_CHUNK_SLICE).min(a_scans))
+}
+
+/// Plans merging pairs of adjacent textures into textures of twice the
+/// capacity. Returns the new capacity and which of the old textures make up
+/// each new texture, or [None], if the new capacity exceeds `max_capacity`.
+fn plan_merge(
+    texture_count: usize,
+    capacity: u32,
+    max_capacity: u32,
+) -> Option<(u32, Vec<Range<usize>>)> {
+    let capacity = capacity.checked_mul(2).filter(|c| *c <= max_capacity)?;
+
+    let groups = (0..texture_count)
+        .step_by(2)
+        .map(|start| start..(start + 2).min(texture_count))
+        .collect();
+
+    Some((capacity, groups))
+}
+
+/// How the samples of every A scan are split into row tiles, when there are
+/// more than the maximum texture size. Every tile is a texture of its own, the
+/// shaders pick the tile of a sample. Overlays do not know about the tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TilePlan {
+    a_scan_samples: u32,
+    /// Samples per tile. Only the last tile may have fewer.
+    tile_samples: u32,
+}
+
+impl TilePlan {
+    /// [None], if there are no samples or the tiles of a single texture would
+    /// exceed [MAX_TEXTURES].
+    fn new(a_scan_samples: usize, max_size: u32) -> Option<Self> {
+        let a_scan_samples = u32::try_from(a_scan_samples).ok().filter(|s| *s > 0)?;
+        let plan = Self {
+            a_scan_samples,
+            tile_samples: a_scan_samples.min(max_size.max(1)),
+        };
+        (plan.count() <= MAX_TEXTURES).then_some(plan)
+    }
+
+    fn count(&self) -> usize {
+        self.a_scan_samples.div_ceil(self.tile_samples) as usize
+    }
+
+    /// Number of samples of every tile.
+    fn widths(&self) -> impl Iterator<Item = u32> {
+        let Self {
+            a_scan_samples,
+            tile_samples,
+        } = *self;
+        (0..a_scan_samples)
+            .step_by(tile_samples as usize)
+            .map(move |start| tile_samples.min(a_scan_samples - start))
+    }
+}
+
+fn m_scan_texture_descriptor() -> wgpu::TextureDescriptor<'static> {
+    wgpu::TextureDescriptor {
+        label: Some("MScan Texture"),
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R16Uint,
+        mip_level_count: 1,
+        sample_count: 1,
+        usage: wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+        size: wgpu::Extent3d::default(),
+    }
+}
+
+/// Creates a texture for each tile with the samples of `widths`.
+fn create_m_scan_tiles(
+    device: &wgpu::Device,
+    widths: impl Iterator<Item = u32>,
+    capacity: u32,
+) -> Vec<wgpu::Texture> {
+    widths
+        .map(|samples| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width: samples,
+                    height: capacity,
+                    depth_or_array_layers: 1,
+                },
+                ..m_scan_texture_descriptor()
+            })
+        })
+        .collect()
+}
+
+/// Texture of a downsampled level, see [pyramid].
+fn create_m_scan_level_texture(
+    device: &wgpu::Device,
+    samples: u32,
+    capacity: u32,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("MScan Level Texture"),
+        size: wgpu::Extent3d {
+            width: samples,
+            height: capacity,
+            depth_or_array_layers: 1,
+        },
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        ..m_scan_texture_descriptor()
+    })
+}
+
+/// Overwrites the uploaded A scans starting at `start` with the A scans of
+/// `data`. A scans, that were not uploaded, are skipped.
+fn write_a_scans(
+    queue: &wgpu::Queue,
+    upload: &mut Upload,
+    data: &types::DataMatrix,
+    start: usize,
+    a_scan_samples: usize,
+) {
+    let Some(capacity) = upload.textures.first().map(|t| t.capacity() as usize) else {
+        return;
+    };
+    if data.nrows() != a_scan_samples {
+        return;
+    }
+
+    let data = data.cast_rescale_par(types::DataType::U16);
+    let bytes = data.as_u8_slice();
+    let row_bytes = a_scan_samples * mem::size_of::<u16>();
+
+    let mut a_scan = start;
+    let end = start + data.ncols();
+
+    while a_scan < end {
+        let offset = a_scan % capacity;
+        let Some(texture) = upload.textures.get_mut(a_scan / capacity) else {
+            break;
+        };
+        let count = (end - a_scan).min((texture.a_scans as usize).saturating_sub(offset));
+        if count == 0 {
+            break;
+        }
+
+        let bytes_start = (a_scan - start) * row_bytes;
+        write_tiles(
+            queue,
+            texture.textures(),
+            offset as u32,
+            &bytes[bytes_start..bytes_start + count * row_bytes],
+            a_scan_samples,
+            count as u32,
+        );
+        texture.pyramid_stale = true;
+
+        a_scan += count;
+    }
+}
+
+/// Writes `count` A scans of `bytes` with `a_scan_samples` 16 bit samples each
+/// into `tiles`, starting at A scan `offset`. Every tile gets its range of
+/// samples, see [TilePlan].
+fn write_tiles<'a>(
+    queue: &wgpu::Queue,
+    tiles: impl IntoIterator<Item = &'a wgpu::Texture>,
+    offset: u32,
+    bytes: &[u8],
+    a_scan_samples: usize,
+    count: u32,
+) {
+    let sample_bytes = mem::size_of::<u16>() as u32;
+
+    let mut sample = 0;
+    for tile in tiles {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: tile,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: offset,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytes,
+            wgpu::ImageDataLayout {
+                offset: (sample * sample_bytes) as u64,
+                bytes_per_row: Some(a_scan_samples as u32 * sample_bytes),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: tile.width(),
+                height: count,
+                depth_or_array_layers: 1,
+            },
+        );
+        sample += tile.width();
+    }
+}
+
+/// Copies `count` A scans (rows) from one texture to another.
+fn copy_a_scans(
+    encoder: &mut wgpu::CommandEncoder,
+    src: &wgpu::Texture,
+    src_offset: u32,
+    dst: &wgpu::Texture,
+    dst_offset: u32,
+    count: u32,
+) {
+    if count == 0 {
+        return;
+    }
+
+    let copy_texture = |texture, offset| wgpu::ImageCopyTexture {
+        texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d {
+            x: 0,
+            y: offset,
+            z: 0,
+        },
+        aspect: wgpu::TextureAspect::All,
+    };
+
+    encoder.copy_texture_to_texture(
+        copy_texture(src, src_offset),
+        copy_texture(dst, dst_offset),
+        wgpu::Extent3d {
+            width: src.width(),
+            height: count,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::Vector2;
+
+    use crate::gui::node_graph::EditNodeGraph;
+
+    use super::*;
+
+    #[test]
+    fn merge_plan() {
+        let (capacity, groups) = plan_merge(MAX_TEXTURES, 1000, 12000).unwrap();
+        assert_eq!(capacity, 2000);
+        assert_eq!(groups.len(), MAX_TEXTURES / 2);
+        assert!(groups.iter().all(|g| g.len() == 2));
+        assert_eq!(groups.last(), Some(&(98..100)));
+
+        let (capacity, groups) = plan_merge(7, 16, 12000).unwrap();
+        assert_eq!(capacity, 32);
+        assert_eq!(groups, vec![0..2, 2..4, 4..6, 6..7]);
+
+        let (capacity, groups) = plan_merge(1, 6000, 12000).unwrap();
+        assert_eq!(capacity, 12000);
+        assert_eq!(groups, vec![0..1]);
+
+        assert_eq!(plan_merge(MAX_TEXTURES, 6001, 12000), None);
+        assert_eq!(plan_merge(MAX_TEXTURES, u32::MAX, u32::MAX), None);
+    }
+
+    #[test]
+    fn tile_plan() {
+        let plan = TilePlan::new(1000, 12000).unwrap();
+        assert_eq!(plan.count(), 1);
+        assert_eq!(plan.widths().collect::<Vec<_>>(), [1000]);
+
+        let plan = TilePlan::new(30000, 12000).unwrap();
+        assert_eq!(plan.count(), 3);
+        assert_eq!(plan.widths().collect::<Vec<_>>(), [12000, 12000, 6000]);
+
+        assert_eq!(TilePlan::new(0, 12000), None);
+        assert_eq!(TilePlan::new(MAX_TEXTURES * 100 + 1, 100), None);
+        assert!(TilePlan::new(MAX_TEXTURES * 100, 100).is_some());
+
+        // Texture and sample in it, like load_tile in shader.wgsl
+        let texel = |texture: usize, sample: u32| {
+            let tiles = plan.a_scan_samples.div_ceil(plan.tile_samples) as usize;
+            (
+                texture * tiles + (sample / plan.tile_samples) as usize,
+                sample % plan.tile_samples,
+            )
+        };
+
+        let mut start = 0;
+        for (tile, width) in plan.widths().enumerate() {
+            for sample in [start, start + width / 2, start + width - 1] {
+                assert_eq!(texel(0, sample), (tile, sample - start));
+                assert_eq!(texel(5, sample), (5 * plan.count() + tile, sample - start));
+            }
+            start += width;
+        }
+        assert_eq!(start, plan.a_scan_samples);
+    }
+
+    #[test]
+    fn first_chunk_in_slices() {
+        // The first bind group is published after the first slice, long
+        // before the whole chunk is converted
+        let slices: Vec<_> = first_chunk_slices(5000).collect();
+        assert_eq!(slices.first(), Some(&(0..FIRST_CHUNK_SLICE)));
+        assert!(slices.len() > 1);
+
+        // The slices cover the chunk in order, so every A scan ends up at its
+        // position in the M scan
+        assert!(slices.windows(2).all(|w| w[0].end == w[1].start));
+        assert_eq!(slices.last().map(|s| s.end), Some(5000));
+        assert!(slices.iter().all(|s| s.len() <= FIRST_CHUNK_SLICE));
+    }
+
+    #[test]
+    fn merge_plan_repeated() {
+        // Merging repeatedly keeps every A scan, until the size limit is hit
+        let mut count = MAX_TEXTURES;
+        let mut capacity = 16;
+        let a_scans = count as u32 * capacity;
+
+        while let Some((new_capacity, groups)) = plan_merge(count, capacity, 12000) {
+            assert!(groups.len() <= MAX_TEXTURES / 2);
+            assert_eq!(groups.iter().map(|g| g.len()).sum::<usize>(), count);
+
+            count = groups.len();
+            capacity = new_capacity;
+            assert!(count as u32 * capacity >= a_scans);
+        }
+
+        assert_eq!(capacity, 8192);
+    }
+
+    #[test]
+    fn m_scan_chain_walks_upstream() {
+        let mut pipeline = Pipeline::new();
+        let input = pipeline.add_node("In Out/M Scan Input").unwrap();
+        let gaussian = pipeline.add_node("Filter/Gaussian Filter").unwrap();
+        let median = pipeline.add_node("Filter/Median Filter").unwrap();
+
+        let output = |pipeline: &Pipeline, node_id| {
+            let (output_id, type_id) = pipeline[node_id].get_output_for_view_request().unwrap();
+            NodeOutput::new(node_id, output_id, type_id)
+        };
+        let input_out = output(&pipeline, input);
+        let gaussian_out = output(&pipeline, gaussian);
+        let median_out = output(&pipeline, median);
+
+        let mut connect = |node_id, output| {
+            pipeline
+                .get_node_mut(node_id)
+                .unwrap()
+                .connect(InputIdSingle.into(), output);
+        };
+        connect(gaussian, input_out);
+        connect(median, gaussian_out);
+
+        assert_eq!(
+            m_scan_chain(&pipeline, median_out),
+            [input_out, gaussian_out, median_out]
+        );
+        assert_eq!(
+            m_scan_chain(&pipeline, gaussian_out),
+            [input_out, gaussian_out]
+        );
+        assert_eq!(m_scan_chain(&pipeline, input_out), [input_out]);
+
+        pipeline.remove_node(median);
+        assert!(m_scan_chain(&pipeline, median_out).is_empty());
+    }
+
+    #[test]
+    fn overlay_validation() {
+        // Boundaries are clamped into the M scan and kept in order
+        let mut b_scans = Overlay::default();
+        for boundary in [0, 10, 5, 30, usize::MAX, 20, 40] {
+            b_scans.push_b_scan_boundary(boundary, 35);
+        }
+        assert_eq!(b_scans.data, [0, 10, 10, 30, 35, 35, 35]);
+        assert_eq!(b_scans.invalid, 4);
+        assert!(b_scans
+            .data
+            .iter()
+            .all(|&b| u32::try_from(b).is_ok() && b <= 35));
+
+        // Infinity cast to u32 is u32::MAX, which marks A scans without
+        // segmentation
+        let depths = [0, 12, 600, f32::INFINITY as u32, 511, 512];
+        let mut m_scan = Overlay::default();
+        m_scan.extend_m_scan_segmentation(&depths, 512);
+        assert_eq!(
+            m_scan.data,
+            [0, 12, INVALID_DEPTH, INVALID_DEPTH, 511, INVALID_DEPTH]
+        );
+        assert_eq!(m_scan.invalid, 2);
+
+        let valid = BScanDiameter {
+            b_scan_start: 0,
+            b_scan_end: 10,
+            min: 1.0,
+            max: 2.0,
+            mean: 1.5,
+            min_points: [Vector2::new(0.0, 1.0), Vector2::new(0.0, -1.0)],
+            max_points: [Vector2::new(2.0, 0.0), Vector2::new(-2.0, 0.0)],
+        };
+        let mut diameters = Overlay::default();
+        diameters.push_diameter(valid);
+        diameters.push_diameter(BScanDiameter {
+            mean: f32::NAN,
+            ..valid
+        });
+        diameters.push_diameter(BScanDiameter {
+            max_points: [Vector2::new(f32::INFINITY, 0.0), Vector2::zeros()],
+            ..valid
+        });
+        assert_eq!(diameters.data.len(), 3);
+        assert_eq!(diameters.invalid, 2);
+
+        diameters.clear();
+        assert!(diameters.data.is_empty());
+        assert_eq!(diameters.invalid, 0);
+    }
 }
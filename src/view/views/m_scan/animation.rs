@@ -0,0 +1,779 @@
+//! Export of the cartesian view as an animation, one B scan per frame.
+//!
+//! The frames are rendered offscreen with the same pipelines and overlays as
+//! the view, see [CartesianBScan]. Animated GIFs are encoded directly. For
+//! videos, export a PNG sequence and encode it with ffmpeg, for example
+//! `ffmpeg -framerate 10 -i pullback_%04d.png pullback.mp4`.
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::anyhow;
+use egui::{Grid, ProgressBar};
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, ImageFormat, RgbaImage,
+};
+use tokio::sync::watch;
+
+use crate::gui::widgets::{PathInput, PathInputAction};
+
+use super::{
+    display::ColorMapping, gpu::SharedResources, overlay::Overlays, types::BScanDiameter,
+    uis::CartesianBScan, TexturesState,
+};
+
+// MARK: AnimationSettings
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    /// One PNG file per frame, numbered from 1.
+    PngSequence,
+}
+
+impl AnimationFormat {
+    pub const VALUES: [AnimationFormat; 2] = [AnimationFormat::Gif, AnimationFormat::PngSequence];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "Animated GIF",
+            AnimationFormat::PngSequence => "PNG Sequence",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationSettings {
+    /// The GIF file, or the name of the PNG files, which get the frame number
+    /// appended.
+    pub path: PathBuf,
+    /// The exported B scans, selected with the range selector, see
+    /// [crate::pipeline::range].
+    pub b_scans: Range<usize>,
+    /// Export every n-th B scan.
+    pub stride: usize,
+    /// Width and height of the frames in pixels.
+    pub resolution: u32,
+    /// Frames per second.
+    pub frame_rate: u32,
+    pub format: AnimationFormat,
+}
+
+impl AnimationSettings {
+    pub fn new(b_scans: Range<usize>) -> Self {
+        Self {
+            path: PathBuf::new(),
+            b_scans,
+            stride: 1,
+            resolution: 512,
+            frame_rate: 10,
+            format: AnimationFormat::Gif,
+        }
+    }
+
+    /// Indices of the exported B scans, limited to the `b_scan_count` B scans
+    /// available.
+    pub fn frames(&self, b_scan_count: usize) -> Vec<usize> {
+        (self.b_scans.start..self.b_scans.end.min(b_scan_count))
+            .step_by(self.stride.max(1))
+            .collect()
+    }
+}
+
+// MARK: AnimationSource
+
+/// Everything needed to render the frames, taken from the view when the
+/// export starts.
+pub struct AnimationSource {
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
+    pub target_format: wgpu::TextureFormat,
+    pub scan_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    pub b_scan_segmentation_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    pub textures_state: Arc<TexturesState>,
+    pub texture_bind_group: Arc<wgpu::BindGroup>,
+    pub b_scan_segmentation: Vec<usize>,
+    pub m_scan_segmentation: Option<Vec<usize>>,
+    pub diameters: Option<Vec<BScanDiameter>>,
+    pub rotation: f32,
+    pub color_mapping: ColorMapping,
+    /// Style and scale of the view, so text and lines look the same.
+    pub style: Arc<egui::Style>,
+    pub pixels_per_point: f32,
+}
+
+impl AnimationSource {
+    fn b_scan_count(&self) -> usize {
+        self.b_scan_segmentation.len().saturating_sub(1)
+    }
+
+    /// The overlays of the view, without the cached lines.
+    pub(super) fn overlays(&self) -> Overlays<'_> {
+        Overlays::of_scan(
+            Some(&self.b_scan_segmentation),
+            self.m_scan_segmentation.as_deref(),
+            self.diameters.as_deref(),
+            None,
+        )
+    }
+
+    /// Paints `b_scan` like the cartesian view.
+    pub(super) fn paint_b_scan(&self, painter: &egui::Painter, rect: egui::Rect, b_scan: usize) {
+        CartesianBScan {
+            textures_state: &self.textures_state,
+            texture_bind_group: self.texture_bind_group.clone(),
+            b_scan_segmentation: &self.b_scan_segmentation,
+            b_scan,
+            rotation: self.rotation,
+            color_mapping: self.color_mapping,
+            overlays: &self.overlays(),
+            timing: None,
+        }
+        .paint(painter, rect)
+    }
+}
+
+// MARK: AnimationExport
+
+/// State of an [AnimationExport].
+#[derive(Debug, Clone)]
+pub enum AnimationState {
+    Running { frame: usize, frames: usize },
+    Done(PathBuf),
+    Failed(String),
+}
+
+/// Renders and writes an animation in the background. Dropping it cancels the
+/// export after the current frame.
+pub struct AnimationExport {
+    state: watch::Receiver<AnimationState>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl AnimationExport {
+    pub fn start(source: AnimationSource, settings: AnimationSettings) -> Self {
+        let frames = settings.frames(source.b_scan_count());
+
+        let (state_tx, state) = watch::channel(AnimationState::Running {
+            frame: 0,
+            frames: frames.len(),
+        });
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let task_cancel = cancel.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = export(&source, &settings, &frames, &state_tx, &task_cancel);
+
+            state_tx.send_replace(match result {
+                Ok(path) => AnimationState::Done(path),
+                Err(e) => AnimationState::Failed(e.to_string()),
+            });
+        });
+
+        Self { state, cancel }
+    }
+
+    pub fn state(&self) -> AnimationState {
+        self.state.borrow().clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(*self.state.borrow(), AnimationState::Running { .. })
+    }
+}
+
+impl Drop for AnimationExport {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Renders the `frames` one after another and writes each one immediately,
+/// so only a single frame is held in memory. Returns the written file, or the
+/// first file of a sequence.
+fn export(
+    source: &AnimationSource,
+    settings: &AnimationSettings,
+    frames: &[usize],
+    state: &watch::Sender<AnimationState>,
+    cancel: &AtomicBool,
+) -> anyhow::Result<PathBuf> {
+    if frames.is_empty() {
+        return Err(anyhow!("There are no B scans in the selected range"));
+    }
+
+    let mut renderer = FrameRenderer::new(source, [settings.resolution; 2]);
+    let mut writer = FrameWriter::create(settings)?;
+
+    for (index, &b_scan) in frames.iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(anyhow!("Cancelled"));
+        }
+
+        state.send_replace(AnimationState::Running {
+            frame: index,
+            frames: frames.len(),
+        });
+
+        let frame = renderer.render(|painter, rect| source.paint_b_scan(painter, rect, b_scan))?;
+
+        writer.write(frame)?;
+    }
+
+    Ok(writer.finish())
+}
+
+// MARK: FrameRenderer
+
+/// Renders egui shapes, including the paint callbacks of the M scan views,
+/// into an offscreen texture and reads it back. Also renders the thumbnails
+/// of the [super::jump_list] and the images of [super::image_export].
+pub(super) struct FrameRenderer {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    ctx: egui::Context,
+    renderer: eframe::egui_wgpu::Renderer,
+    format: wgpu::TextureFormat,
+    texture: wgpu::Texture,
+    depth_texture: wgpu::Texture,
+    readback: wgpu::Buffer,
+    /// Width and height in pixels.
+    size: [u32; 2],
+    pixels_per_point: f32,
+    /// Bytes per row of [Self::readback], aligned as wgpu requires.
+    padded_row: u32,
+}
+
+impl FrameRenderer {
+    pub(super) fn new(source: &AnimationSource, size: [u32; 2]) -> Self {
+        let device = source.device.clone();
+
+        // The frames are read back as RGBA or BGRA
+        let format = match source.target_format {
+            format @ (wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Bgra8Unorm) => format,
+            _ => wgpu::TextureFormat::Rgba8Unorm,
+        };
+
+        let mut renderer = eframe::egui_wgpu::Renderer::new(
+            &device,
+            format,
+            Some(wgpu::TextureFormat::Depth24Plus),
+            1,
+        );
+        renderer
+            .callback_resources
+            .insert(SharedResources::with_layouts(
+                &device,
+                &source.queue,
+                &format,
+                source.scan_bind_group_layout.clone(),
+                source.b_scan_segmentation_bind_group_layout.clone(),
+            ));
+
+        let texture = |label, format, usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size[0],
+                    height: size[1],
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            })
+        };
+
+        let padded_row = (size[0] * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let ctx = egui::Context::default();
+        ctx.set_style(source.style.clone());
+
+        Self {
+            texture: texture(
+                "Animation Frame Texture",
+                format,
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            ),
+            depth_texture: texture(
+                "Animation Depth Texture",
+                wgpu::TextureFormat::Depth24Plus,
+                wgpu::TextureUsages::RENDER_ATTACHMENT,
+            ),
+            readback: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Animation Readback Buffer"),
+                size: (padded_row * size[1]) as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            queue: source.queue.clone(),
+            device,
+            ctx,
+            renderer,
+            format,
+            size,
+            pixels_per_point: source.pixels_per_point,
+            padded_row,
+        }
+    }
+
+    pub(super) fn render(
+        &mut self,
+        paint: impl FnOnce(&egui::Painter, egui::Rect),
+    ) -> anyhow::Result<RgbaImage> {
+        let screen_rect = egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(self.size[0] as f32, self.size[1] as f32) / self.pixels_per_point,
+        );
+
+        let mut input = egui::RawInput {
+            screen_rect: Some(screen_rect),
+            ..Default::default()
+        };
+        input
+            .viewports
+            .entry(egui::ViewportId::ROOT)
+            .or_default()
+            .native_pixels_per_point = Some(self.pixels_per_point);
+
+        let output = self.ctx.run(input, |ctx| {
+            let painter = ctx.layer_painter(egui::LayerId::background());
+            paint(&painter, screen_rect);
+        });
+
+        let paint_jobs = self.ctx.tessellate(output.shapes, output.pixels_per_point);
+        let screen = eframe::egui_wgpu::ScreenDescriptor {
+            size_in_pixels: self.size,
+            pixels_per_point: output.pixels_per_point,
+        };
+
+        for (id, delta) in &output.textures_delta.set {
+            self.renderer
+                .update_texture(&self.device, &self.queue, *id, delta);
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Animation Frame Encoder"),
+            });
+
+        let commands = self.renderer.update_buffers(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            &paint_jobs,
+            &screen,
+        );
+
+        let view = self
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = self
+            .depth_texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let background = self.ctx.style().visuals.panel_fill;
+        let [r, g, b, _] = background.to_normalized_gamma_f32();
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Animation Frame Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: r as f64,
+                            g: g as f64,
+                            b: b as f64,
+                            a: 1.0,
+                        }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            self.renderer.render(&mut render_pass, &paint_jobs, &screen);
+        }
+
+        for id in &output.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: self.size[0],
+                height: self.size[1],
+                depth_or_array_layers: 1,
+            },
+        );
+
+        self.queue
+            .submit(commands.into_iter().chain([encoder.finish()]));
+
+        self.read_back()
+    }
+
+    fn read_back(&self) -> anyhow::Result<RgbaImage> {
+        let slice = self.readback.slice(..);
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        rx.recv()??;
+
+        let row = self.size[0] as usize * 4;
+        let mut image = RgbaImage::new(self.size[0], self.size[1]);
+        {
+            let data = slice.get_mapped_range();
+            for (src, dst) in data
+                .chunks(self.padded_row as usize)
+                .zip(image.chunks_mut(row))
+            {
+                dst.copy_from_slice(&src[..row]);
+            }
+        }
+        self.readback.unmap();
+
+        if self.format == wgpu::TextureFormat::Bgra8Unorm {
+            image.pixels_mut().for_each(|pixel| pixel.0.swap(0, 2));
+        }
+
+        Ok(image)
+    }
+}
+
+// MARK: FrameWriter
+
+/// Writes frames as soon as they are rendered.
+enum FrameWriter {
+    Gif {
+        path: PathBuf,
+        encoder: GifEncoder<BufWriter<File>>,
+        delay: Delay,
+    },
+    PngSequence {
+        first: PathBuf,
+        /// The path of the sequence, without extension.
+        base: PathBuf,
+        count: usize,
+    },
+}
+
+impl FrameWriter {
+    fn create(settings: &AnimationSettings) -> anyhow::Result<Self> {
+        match settings.format {
+            AnimationFormat::Gif => {
+                let path = settings.path.with_extension("gif");
+                let file = BufWriter::new(File::create(&path)?);
+
+                let mut encoder = GifEncoder::new_with_speed(file, 10);
+                encoder.set_repeat(Repeat::Infinite)?;
+
+                Ok(Self::Gif {
+                    path,
+                    encoder,
+                    delay: Delay::from_numer_denom_ms(1000, settings.frame_rate.max(1)),
+                })
+            }
+            AnimationFormat::PngSequence => {
+                let base = settings.path.with_extension("");
+
+                Ok(Self::PngSequence {
+                    first: sequence_path(&base, 1),
+                    base,
+                    count: 0,
+                })
+            }
+        }
+    }
+
+    fn write(&mut self, frame: RgbaImage) -> anyhow::Result<()> {
+        match self {
+            Self::Gif { encoder, delay, .. } => {
+                encoder.encode_frame(image::Frame::from_parts(frame, 0, 0, *delay))?;
+            }
+            Self::PngSequence { base, count, .. } => {
+                *count += 1;
+                frame.save_with_format(sequence_path(base, *count), ImageFormat::Png)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the written file, or the first file of a sequence.
+    fn finish(self) -> PathBuf {
+        match self {
+            // The encoder flushes, when dropped
+            Self::Gif { path, .. } => path,
+            Self::PngSequence { first, .. } => first,
+        }
+    }
+}
+
+/// `base` with the frame number appended, like `pullback_0001.png`.
+fn sequence_path(base: &Path, number: usize) -> PathBuf {
+    let mut name = base.file_name().unwrap_or_default().to_os_string();
+    name.push(format!("_{:04}.png", number));
+    base.with_file_name(name)
+}
+
+// MARK: AnimationDialog
+
+/// Window to set up and run an [AnimationExport]. Closing the window cancels
+/// the export.
+pub struct AnimationDialog {
+    settings: AnimationSettings,
+    export: Option<AnimationExport>,
+}
+
+impl AnimationDialog {
+    pub fn new(b_scans: Range<usize>) -> Self {
+        Self {
+            settings: AnimationSettings::new(b_scans),
+            export: None,
+        }
+    }
+
+    /// Returns false, when the window got closed. `b_scans` follows the range
+    /// selector, until the export is started. `source` is called, when the
+    /// export is started.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        id: egui::Id,
+        b_scan_count: usize,
+        b_scans: Range<usize>,
+        source: impl FnOnce() -> Option<AnimationSource>,
+    ) -> bool {
+        let mut open = true;
+
+        egui::Window::new("Export Animation")
+            .id(id)
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let is_running = self
+                    .export
+                    .as_ref()
+                    .is_some_and(|export| !export.is_finished());
+
+                if !is_running {
+                    self.settings.b_scans = b_scans;
+                }
+
+                ui.add_enabled_ui(!is_running, |ui| self.settings_ui(ui, b_scan_count));
+
+                ui.horizontal(|ui| {
+                    let can_start = !is_running && !self.settings.path.as_os_str().is_empty();
+                    if ui
+                        .add_enabled(can_start, egui::Button::new("Export"))
+                        .clicked()
+                    {
+                        self.export = source()
+                            .map(|source| AnimationExport::start(source, self.settings.clone()));
+                    }
+
+                    if ui
+                        .add_enabled(is_running, egui::Button::new("Cancel"))
+                        .clicked()
+                    {
+                        self.export = None;
+                    }
+                });
+
+                if let Some(export) = &self.export {
+                    ui.separator();
+                    state_ui(ui, export.state());
+                }
+            });
+
+        open
+    }
+
+    fn settings_ui(&mut self, ui: &mut egui::Ui, b_scan_count: usize) {
+        let settings = &mut self.settings;
+        let last = b_scan_count.saturating_sub(1);
+
+        Grid::new("animation_settings")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Format:");
+                egui::ComboBox::from_id_source("animation_format")
+                    .selected_text(settings.format.name())
+                    .show_ui(ui, |ui| {
+                        for format in AnimationFormat::VALUES {
+                            ui.selectable_value(&mut settings.format, format, format.name());
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "For videos, export a PNG sequence and encode it with ffmpeg:\n\
+                         ffmpeg -framerate 10 -i name_%04d.png name.mp4",
+                    );
+                ui.end_row();
+
+                ui.label("File:");
+                ui.add(PathInput::new(&mut settings.path).action(PathInputAction::SaveFile))
+                    .on_hover_text("PNG sequences get the frame number appended to the name");
+                ui.end_row();
+
+                ui.label("B Scans:");
+                let b_scans = &settings.b_scans;
+                match b_scans.is_empty() {
+                    true => ui.label("None"),
+                    false => ui.label(format!("{} to {}", b_scans.start, b_scans.end - 1)),
+                }
+                .on_hover_text("Select the B scans with the range selector under the views");
+                ui.end_row();
+
+                ui.label("Stride:");
+                ui.add(egui::DragValue::new(&mut settings.stride).range(1..=last.max(1)))
+                    .on_hover_text("Export every n-th B scan");
+                ui.end_row();
+
+                ui.label("Resolution:");
+                ui.add(
+                    egui::DragValue::new(&mut settings.resolution)
+                        .range(64..=4096)
+                        .suffix(" px"),
+                );
+                ui.end_row();
+
+                ui.label("Frame Rate:");
+                ui.add(
+                    egui::DragValue::new(&mut settings.frame_rate)
+                        .range(1..=60)
+                        .suffix(" fps"),
+                );
+                ui.end_row();
+            });
+
+        ui.label(format!("{} frames", settings.frames(b_scan_count).len()));
+    }
+}
+
+fn state_ui(ui: &mut egui::Ui, state: AnimationState) {
+    match state {
+        AnimationState::Running { frame, frames } => {
+            ui.add(
+                ProgressBar::new(frame as f32 / frames.max(1) as f32)
+                    .rounding(3.0)
+                    .text(format!("Frame {} of {}", frame + 1, frames)),
+            );
+            ui.ctx().request_repaint();
+        }
+        AnimationState::Done(path) => {
+            ui.label(format!("Animation written to {}", path.display()));
+        }
+        AnimationState::Failed(e) => {
+            ui.colored_label(ui.visuals().error_fg_color, format!("Failed: {e}"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::BufReader;
+
+    use image::{codecs::gif::GifDecoder, AnimationDecoder, Rgba};
+
+    use super::*;
+
+    #[test]
+    fn frame_selection() {
+        let mut settings = AnimationSettings::new(0..10);
+        assert_eq!(settings.frames(10), (0..10).collect::<Vec<_>>());
+
+        settings.b_scans = 2..10;
+        settings.stride = 3;
+        assert_eq!(settings.frames(10), vec![2, 5, 8]);
+
+        // The segmentation got shorter
+        assert_eq!(settings.frames(6), vec![2, 5]);
+        assert!(settings.frames(0).is_empty());
+    }
+
+    #[test]
+    fn write_frames() {
+        let dir = std::env::temp_dir().join(format!("ivoct_animation_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let frame = |value| RgbaImage::from_pixel(16, 16, Rgba([value, 0, 255 - value, 255]));
+
+        for format in AnimationFormat::VALUES {
+            let settings = AnimationSettings {
+                path: dir.join("pullback"),
+                format,
+                ..AnimationSettings::new(0..3)
+            };
+
+            let mut writer = FrameWriter::create(&settings).unwrap();
+            for value in [0, 128, 255] {
+                writer.write(frame(value)).unwrap();
+            }
+            let path = writer.finish();
+
+            match format {
+                AnimationFormat::Gif => {
+                    assert_eq!(path, dir.join("pullback.gif"));
+
+                    let decoder = GifDecoder::new(BufReader::new(File::open(&path).unwrap()));
+                    let frames = decoder.unwrap().into_frames().collect_frames().unwrap();
+                    assert_eq!(frames.len(), 3);
+                    assert_eq!(frames[0].buffer().dimensions(), (16, 16));
+                    assert_eq!(frames[0].delay(), Delay::from_numer_denom_ms(100, 1));
+                }
+                AnimationFormat::PngSequence => {
+                    assert_eq!(path, dir.join("pullback_0001.png"));
+
+                    let last = image::open(dir.join("pullback_0003.png")).unwrap();
+                    assert_eq!(last.to_rgba8(), frame(255));
+                    assert!(!dir.join("pullback_0004.png").exists());
+                }
+            }
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,338 @@
+//! How the values of the M scan are mapped to colors. The mapping is applied
+//! in the shaders, so changing it does not upload the textures again.
+
+use std::hash::{Hash, Hasher};
+
+use egui::{ComboBox, DragValue};
+
+/// Number of bins of a [ValueHistogram].
+const HISTOGRAM_BINS: usize = 1024;
+
+/// Smallest width of a [ValueRange].
+const MIN_WIDTH: f32 = 0.001;
+
+/// Smallest value taken into a logarithm, so zeros do not become infinite.
+/// The same as `LOG_EPSILON` in shader.wgsl.
+const LOG_EPSILON: f32 = 1e-7;
+
+/// Values of [DisplayTransform::Log] are compressed like `log(1 + LOG_GAIN
+/// v)`. The same as `LOG_GAIN` in shader.wgsl.
+const LOG_GAIN: f32 = 1000.0;
+
+/// How values are transformed, before the [ValueRange] is applied. Linear
+/// M scans, like from binary input, are dark without.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DisplayTransform {
+    #[default]
+    Linear,
+    /// Logarithmic compression, keeping 0 and 1.
+    Log,
+    /// Decibels of the intensity, showing the dynamic range below the
+    /// maximum.
+    Decibel,
+}
+
+impl DisplayTransform {
+    pub const VALUES: [Self; 3] = [Self::Linear, Self::Log, Self::Decibel];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::Log => "Log",
+            Self::Decibel => "dB",
+        }
+    }
+
+    /// Index of the transform in the shader.
+    pub fn index(&self) -> u32 {
+        match self {
+            Self::Linear => 0,
+            Self::Log => 1,
+            Self::Decibel => 2,
+        }
+    }
+
+    /// Transforms `value` from 0 to 1, like `apply_transform` in shader.wgsl.
+    /// `dynamic_range` in dB is shown with [Self::Decibel].
+    pub fn apply(&self, value: f32, dynamic_range: f32) -> f32 {
+        let value = value.max(0.0);
+        match self {
+            Self::Linear => value,
+            Self::Log => (1.0 + LOG_GAIN * value).ln() / (1.0 + LOG_GAIN).ln(),
+            Self::Decibel => {
+                let db = 10.0 * value.max(LOG_EPSILON).log10();
+                (1.0 + db / dynamic_range.max(1.0)).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+/// Values from 0 to 1 of the M scan, that are stretched over the whole color
+/// map. Values outside are clamped to its ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Default for ValueRange {
+    fn default() -> Self {
+        Self { min: 0.0, max: 1.0 }
+    }
+}
+
+impl ValueRange {
+    /// Keeps both ends inside 0 to 1 and `min` below `max`.
+    pub fn validate(&mut self) {
+        self.max = self.max.clamp(MIN_WIDTH, 1.0);
+        self.min = self.min.clamp(0.0, self.max - MIN_WIDTH);
+    }
+}
+
+/// Everything the shaders need to color a value, the same in every
+/// perspective.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorMapping {
+    pub map_idx: u32,
+    pub transform: DisplayTransform,
+    /// Decibels below the maximum shown with [DisplayTransform::Decibel].
+    pub dynamic_range: f32,
+    /// Applied to the transformed values.
+    pub range: ValueRange,
+}
+
+impl ColorMapping {
+    pub fn new(map_idx: u32) -> Self {
+        Self {
+            map_idx,
+            transform: DisplayTransform::default(),
+            dynamic_range: 50.0,
+            range: ValueRange::default(),
+        }
+    }
+}
+
+impl Hash for ColorMapping {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.map_idx.hash(state);
+        self.transform.hash(state);
+        self.dynamic_range.to_bits().hash(state);
+        self.range.min.to_bits().hash(state);
+        self.range.max.to_bits().hash(state);
+    }
+}
+
+/// Histogram of the uploaded values, to find a [ValueRange] without keeping
+/// the M scan in memory.
+#[derive(Debug, Clone)]
+pub struct ValueHistogram {
+    counts: Vec<u64>,
+}
+
+impl Default for ValueHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; HISTOGRAM_BINS],
+        }
+    }
+}
+
+impl ValueHistogram {
+    pub fn of(values: &[u16]) -> Self {
+        let mut histogram = Self::default();
+        for &value in values {
+            histogram.counts[value as usize * HISTOGRAM_BINS / (u16::MAX as usize + 1)] += 1;
+        }
+        histogram
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+    }
+
+    /// The range from the `low` to the `high` fraction of the values, like
+    /// the 1st to the 99th percentile. [None] without values.
+    pub fn percentiles(&self, low: f32, high: f32) -> Option<ValueRange> {
+        let total = self.counts.iter().sum::<u64>();
+        if total == 0 {
+            return None;
+        }
+
+        // The bin holding the value at `fraction` of the sorted values
+        let bin = |fraction: f32| {
+            let target = ((total - 1) as f64 * fraction as f64).round() as u64;
+            let mut seen = 0;
+            self.counts
+                .iter()
+                .position(|count| {
+                    seen += count;
+                    seen > target
+                })
+                .unwrap_or(HISTOGRAM_BINS - 1)
+        };
+
+        let mut range = ValueRange {
+            min: bin(low) as f32 / HISTOGRAM_BINS as f32,
+            max: (bin(high) + 1) as f32 / HISTOGRAM_BINS as f32,
+        };
+        range.validate();
+        Some(range)
+    }
+}
+
+/// Edits the transform and the range of `mapping` next to the color map
+/// menu. "Auto" sets the 1st to 99th percentile of `histogram`, which is
+/// [None] while the M scan is uploaded.
+pub fn color_mapping_ui(
+    ui: &mut egui::Ui,
+    mapping: &mut ColorMapping,
+    histogram: Option<&ValueHistogram>,
+) {
+    let transform = mapping.transform;
+    ComboBox::from_id_source(ui.id().with("display_transform"))
+        .selected_text(mapping.transform.name())
+        .width(60.0)
+        .show_ui(ui, |ui| {
+            for transform in DisplayTransform::VALUES {
+                ui.selectable_value(&mut mapping.transform, transform, transform.name());
+            }
+        })
+        .response
+        .on_hover_text("How the values are transformed, before they are colored");
+
+    if mapping.transform == DisplayTransform::Decibel {
+        ui.add(
+            DragValue::new(&mut mapping.dynamic_range)
+                .range(10.0..=100.0)
+                .speed(0.5)
+                .suffix(" dB"),
+        )
+        .on_hover_text("Dynamic range, shown below the maximum value");
+    }
+
+    // The range of other transforms does not fit
+    if mapping.transform != transform {
+        mapping.range = ValueRange::default();
+    }
+
+    value_range_ui(ui, mapping, histogram);
+}
+
+fn value_range_ui(
+    ui: &mut egui::Ui,
+    mapping: &mut ColorMapping,
+    histogram: Option<&ValueHistogram>,
+) {
+    let range = &mut mapping.range;
+
+    fn value(value: &mut f32) -> DragValue<'_> {
+        DragValue::new(value)
+            .range(0.0..=1.0)
+            .speed(0.002)
+            .fixed_decimals(3)
+    }
+
+    ui.add(value(&mut range.min))
+        .on_hover_text("Value shown with the start of the color map, from 0 to 1");
+    ui.label("–");
+    ui.add(value(&mut range.max))
+        .on_hover_text("Value shown with the end of the color map, from 0 to 1");
+
+    if ui
+        .add_enabled(histogram.is_some(), egui::Button::new("Auto"))
+        .on_hover_text("Stretch the 1st to the 99th percentile of the values over the color map")
+        .on_disabled_hover_text("Available, once the M scan is uploaded")
+        .clicked()
+    {
+        // The transforms keep the order, so they keep the percentiles
+        if let Some(auto) = histogram.and_then(|h| h.percentiles(0.01, 0.99)) {
+            let transform = |v| mapping.transform.apply(v, mapping.dynamic_range);
+            *range = ValueRange {
+                min: transform(auto.min),
+                max: transform(auto.max),
+            };
+        }
+    }
+
+    if *range != ValueRange::default()
+        && ui
+            .small_button("↺")
+            .on_hover_text("Show the full range")
+            .clicked()
+    {
+        *range = ValueRange::default();
+    }
+
+    range.validate();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validate_value_range() {
+        let mut range = ValueRange { min: 0.8, max: 0.2 };
+        range.validate();
+        assert_eq!(range.max, 0.2);
+        assert!((range.min - (0.2 - MIN_WIDTH)).abs() < 1e-6);
+
+        let mut range = ValueRange {
+            min: -1.0,
+            max: 0.0,
+        };
+        range.validate();
+        assert_eq!(
+            range,
+            ValueRange {
+                min: 0.0,
+                max: MIN_WIDTH
+            }
+        );
+    }
+
+    #[test]
+    fn transforms() {
+        for transform in DisplayTransform::VALUES {
+            let values = [-1.0, 0.0, 1e-12, 0.01, 0.5, 1.0].map(|v| transform.apply(v, 50.0));
+            assert!(values.iter().all(|v| v.is_finite()), "{transform:?}");
+            assert!(values.windows(2).all(|w| w[0] <= w[1]), "{transform:?}");
+            assert_eq!(values[1], 0.0, "{transform:?}");
+            assert!((values[5] - 1.0).abs() < 1e-6, "{transform:?}");
+        }
+
+        // 10 dB below the maximum, with a dynamic range of 40 dB
+        let db = DisplayTransform::Decibel;
+        assert!((db.apply(0.1, 40.0) - 0.75).abs() < 1e-6);
+        assert_eq!(db.apply(1e-5, 40.0), 0.0);
+
+        // Dark values are brightened
+        assert!(DisplayTransform::Log.apply(0.01, 0.0) > 0.3);
+    }
+
+    #[test]
+    fn histogram_percentiles() {
+        assert_eq!(ValueHistogram::default().percentiles(0.01, 0.99), None);
+
+        // 98 values in the middle and an outlier at each end
+        let mut values = vec![u16::MAX / 2; 98];
+        values.extend([0, u16::MAX]);
+        let mut histogram = ValueHistogram::of(&values);
+
+        let range = histogram.percentiles(0.01, 0.99).unwrap();
+        assert!((range.min - 0.5).abs() < 2.0 / HISTOGRAM_BINS as f32);
+        assert!((range.max - 0.5).abs() < 2.0 / HISTOGRAM_BINS as f32);
+        assert!(range.min < range.max);
+
+        assert_eq!(
+            histogram.percentiles(0.0, 1.0),
+            Some(ValueRange { min: 0.0, max: 1.0 })
+        );
+
+        histogram.merge(&ValueHistogram::of(&[u16::MAX; 200]));
+        assert_eq!(histogram.percentiles(0.99, 1.0).unwrap().max, 1.0);
+        assert!(histogram.percentiles(0.5, 1.0).unwrap().min > 0.99);
+    }
+}
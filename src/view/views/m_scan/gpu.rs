@@ -6,7 +6,12 @@ use wgpu::util::DeviceExt;
 
 use crate::gui::color_maps;
 
-use super::MAX_TEXTURES;
+use super::{
+    super::perf::{self, GpuTimer, Timing},
+    display::ColorMapping,
+    pyramid::Pyramid,
+    MAX_TEXTURES,
+};
 
 pub fn upload_b_scan_segmentation(
     device: &wgpu::Device,
@@ -14,7 +19,11 @@ pub fn upload_b_scan_segmentation(
     bind_group_layout: &wgpu::BindGroupLayout,
     data: &[usize],
 ) {
-    let data = data.iter().map(|d| *d as u32).collect::<Vec<_>>();
+    // Saturate instead of wrapping around
+    let data = data
+        .iter()
+        .map(|d| u32::try_from(*d).unwrap_or(u32::MAX))
+        .collect::<Vec<_>>();
 
     let buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("BScan Segmentation Buffer"),
@@ -42,18 +51,41 @@ pub(super) struct PolarViewPaintCallback {
     pub texture_bind_group: Arc<wgpu::BindGroup>,
     pub texture_count: usize,
     pub a_scan_count: usize,
+    pub a_scan_samples: usize,
     pub rect: egui::Rect,
-    pub map_idx: u32,
+    pub color_mapping: ColorMapping,
+    /// Pyramid level of [Self::texture_bind_group].
+    pub level: usize,
+    /// Number of A scans of the full resolution textures.
+    pub capacity: u32,
+    /// B scan segmentation, to give every B scan the same width, see
+    /// [super::uis::BScanSpacing::Uniform]. Native spacing without.
+    pub b_scan_bind_group: Option<Arc<wgpu::BindGroup>>,
+    pub timing: Option<Timing>,
 }
 
 impl eframe::egui_wgpu::CallbackTrait for PolarViewPaintCallback {
+    fn prepare(
+        &self,
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _screen_descriptor: &eframe::egui_wgpu::ScreenDescriptor,
+        egui_encoder: &mut wgpu::CommandEncoder,
+        callback_resources: &mut eframe::egui_wgpu::CallbackResources,
+    ) -> Vec<wgpu::CommandBuffer> {
+        prepare_timer(self.timing, device, egui_encoder, callback_resources);
+        Vec::new()
+    }
+
     fn paint<'a>(
         &'a self,
         _info: egui::PaintCallbackInfo,
         render_pass: &mut wgpu::RenderPass<'a>,
         callback_resources: &'a eframe::egui_wgpu::CallbackResources,
     ) {
-        let resources = callback_resources.get::<SharedResources>().unwrap();
+        let Some(resources) = callback_resources.get::<SharedResources>() else {
+            return;
+        };
 
         #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
         #[repr(C)]
@@ -61,9 +93,22 @@ impl eframe::egui_wgpu::CallbackTrait for PolarViewPaintCallback {
             tex_count: u32,
             map_idx: u32,
             a_scan_count: u32,
+            level: u32,
+            capacity: u32,
+            samples: u32,
+            transform: u32,
+            dynamic_range: f32,
+            range_min: f32,
+            range_max: f32,
         }
 
-        render_pass.set_pipeline(&resources.polar_view_pipeline);
+        match &self.b_scan_bind_group {
+            Some(b_scan_bind_group) => {
+                render_pass.set_pipeline(&resources.polar_uniform_view_pipeline);
+                render_pass.set_bind_group(2, b_scan_bind_group, &[]);
+            }
+            None => render_pass.set_pipeline(&resources.polar_view_pipeline),
+        }
         render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
         render_pass.set_bind_group(1, &resources.color_maps_bind_group, &[]);
         render_pass.set_push_constants(
@@ -81,11 +126,23 @@ impl eframe::egui_wgpu::CallbackTrait for PolarViewPaintCallback {
             16,
             bytemuck::cast_slice(&[Constants {
                 tex_count: self.texture_count.min(MAX_TEXTURES) as u32,
-                map_idx: self.map_idx,
+                map_idx: self.color_mapping.map_idx,
                 a_scan_count: self.a_scan_count as u32,
+                level: self.level as u32,
+                capacity: self.capacity,
+                samples: self.a_scan_samples as u32,
+                transform: self.color_mapping.transform.index(),
+                dynamic_range: self.color_mapping.dynamic_range,
+                range_min: self.color_mapping.range.min,
+                range_max: self.color_mapping.range.max,
             }]),
         );
-        render_pass.draw(0..6, 0..1);
+        perf::measure(
+            resources.timer.as_ref(),
+            self.timing,
+            render_pass,
+            |render_pass| render_pass.draw(0..6, 0..1),
+        );
     }
 }
 
@@ -96,18 +153,34 @@ pub(super) struct CartesianViewPaintCallback {
     pub texture_count: usize,
     pub b_scan_start: usize,
     pub b_scan_end: usize,
+    pub a_scan_samples: usize,
     pub rect: egui::Rect,
-    pub map_idx: u32,
+    pub color_mapping: ColorMapping,
+    pub timing: Option<Timing>,
 }
 
 impl eframe::egui_wgpu::CallbackTrait for CartesianViewPaintCallback {
+    fn prepare(
+        &self,
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _screen_descriptor: &eframe::egui_wgpu::ScreenDescriptor,
+        egui_encoder: &mut wgpu::CommandEncoder,
+        callback_resources: &mut eframe::egui_wgpu::CallbackResources,
+    ) -> Vec<wgpu::CommandBuffer> {
+        prepare_timer(self.timing, device, egui_encoder, callback_resources);
+        Vec::new()
+    }
+
     fn paint<'a>(
         &'a self,
         _info: egui::PaintCallbackInfo,
         render_pass: &mut wgpu::RenderPass<'a>,
         callback_resources: &'a eframe::egui_wgpu::CallbackResources,
     ) {
-        let resources = callback_resources.get::<SharedResources>().unwrap();
+        let Some(resources) = callback_resources.get::<SharedResources>() else {
+            return;
+        };
 
         #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
         #[repr(C)]
@@ -116,6 +189,11 @@ impl eframe::egui_wgpu::CallbackTrait for CartesianViewPaintCallback {
             map_idx: u32,
             b_scan_start: u32,
             b_scan_end: u32,
+            samples: u32,
+            transform: u32,
+            dynamic_range: f32,
+            range_min: f32,
+            range_max: f32,
         }
 
         render_pass.set_pipeline(&resources.cartesian_view_pipeline);
@@ -136,12 +214,22 @@ impl eframe::egui_wgpu::CallbackTrait for CartesianViewPaintCallback {
             16,
             bytemuck::cast_slice(&[Constants {
                 tex_count: self.texture_count.min(MAX_TEXTURES) as u32,
-                map_idx: self.map_idx,
+                map_idx: self.color_mapping.map_idx,
                 b_scan_start: self.b_scan_start as u32,
                 b_scan_end: self.b_scan_end as u32,
+                samples: self.a_scan_samples as u32,
+                transform: self.color_mapping.transform.index(),
+                dynamic_range: self.color_mapping.dynamic_range,
+                range_min: self.color_mapping.range.min,
+                range_max: self.color_mapping.range.max,
             }]),
         );
-        render_pass.draw(0..6, 0..1);
+        perf::measure(
+            resources.timer.as_ref(),
+            self.timing,
+            render_pass,
+            |render_pass| render_pass.draw(0..6, 0..1),
+        );
     }
 }
 
@@ -151,19 +239,36 @@ pub(super) struct SideViewPaintCallback {
     pub b_scan_bind_group: Arc<wgpu::BindGroup>,
     pub texture_bind_group: Arc<wgpu::BindGroup>,
     pub texture_count: usize,
+    pub a_scan_samples: usize,
     pub view_rotation: f32,
+    pub neighborhood: f32,
     pub rect: egui::Rect,
-    pub map_idx: u32,
+    pub color_mapping: ColorMapping,
+    pub timing: Option<Timing>,
 }
 
 impl eframe::egui_wgpu::CallbackTrait for SideViewPaintCallback {
+    fn prepare(
+        &self,
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _screen_descriptor: &eframe::egui_wgpu::ScreenDescriptor,
+        egui_encoder: &mut wgpu::CommandEncoder,
+        callback_resources: &mut eframe::egui_wgpu::CallbackResources,
+    ) -> Vec<wgpu::CommandBuffer> {
+        prepare_timer(self.timing, device, egui_encoder, callback_resources);
+        Vec::new()
+    }
+
     fn paint<'a>(
         &'a self,
         _info: egui::PaintCallbackInfo,
         render_pass: &mut wgpu::RenderPass<'a>,
         callback_resources: &'a eframe::egui_wgpu::CallbackResources,
     ) {
-        let resources = callback_resources.get::<SharedResources>().unwrap();
+        let Some(resources) = callback_resources.get::<SharedResources>() else {
+            return;
+        };
 
         #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
         #[repr(C)]
@@ -171,6 +276,12 @@ impl eframe::egui_wgpu::CallbackTrait for SideViewPaintCallback {
             tex_count: u32,
             map_idx: u32,
             view_rot: f32,
+            neighborhood: f32,
+            samples: u32,
+            transform: u32,
+            dynamic_range: f32,
+            range_min: f32,
+            range_max: f32,
         }
 
         render_pass.set_pipeline(&resources.side_view_pipeline);
@@ -192,11 +303,38 @@ impl eframe::egui_wgpu::CallbackTrait for SideViewPaintCallback {
             16,
             bytemuck::cast_slice(&[Constants {
                 tex_count: self.texture_count.min(MAX_TEXTURES) as u32,
-                map_idx: self.map_idx,
+                map_idx: self.color_mapping.map_idx,
                 view_rot: self.view_rotation,
+                neighborhood: self.neighborhood,
+                samples: self.a_scan_samples as u32,
+                transform: self.color_mapping.transform.index(),
+                dynamic_range: self.color_mapping.dynamic_range,
+                range_min: self.color_mapping.range.min,
+                range_max: self.color_mapping.range.max,
             }]),
         );
-        render_pass.draw(0..6, 0..1);
+        perf::measure(
+            resources.timer.as_ref(),
+            self.timing,
+            render_pass,
+            |render_pass| render_pass.draw(0..6, 0..1),
+        );
+    }
+}
+
+/// Advances the [GpuTimer] of measured paint callbacks.
+fn prepare_timer(
+    timing: Option<Timing>,
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    callback_resources: &mut eframe::egui_wgpu::CallbackResources,
+) {
+    let timer = callback_resources
+        .get_mut::<SharedResources>()
+        .and_then(|resources| resources.timer.as_mut());
+
+    if let (Some(timing), Some(timer)) = (timing, timer) {
+        timer.prepare(timing, device, encoder);
     }
 }
 
@@ -204,11 +342,17 @@ impl eframe::egui_wgpu::CallbackTrait for SideViewPaintCallback {
 
 pub(super) struct SharedResources {
     pub polar_view_pipeline: wgpu::RenderPipeline,
+    /// The polar view with every B scan at the same width.
+    pub polar_uniform_view_pipeline: wgpu::RenderPipeline,
     pub cartesian_view_pipeline: wgpu::RenderPipeline,
     pub side_view_pipeline: wgpu::RenderPipeline,
     pub scan_bind_group_layout: Arc<wgpu::BindGroupLayout>,
     pub color_maps_bind_group: Arc<wgpu::BindGroup>,
     pub b_scan_segmentation_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    pub pyramid: Arc<Pyramid>,
+    /// Measures the paint callbacks for the performance overlay. Not
+    /// available for offscreen rendering.
+    pub timer: Option<GpuTimer>,
 }
 
 impl SharedResources {
@@ -217,9 +361,6 @@ impl SharedResources {
         queue: &wgpu::Queue,
         target_format: &wgpu::TextureFormat,
     ) -> Self {
-        let (color_maps_bind_group_layout, color_maps_bind_group) =
-            Self::create_color_map_bind_group(device, queue);
-
         let scan_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("MScan Bind Group Layout"),
@@ -250,15 +391,52 @@ impl SharedResources {
                 }],
             });
 
+        Self {
+            timer: GpuTimer::new(device, queue),
+            ..Self::with_layouts(
+                device,
+                queue,
+                target_format,
+                Arc::new(scan_bind_group_layout),
+                Arc::new(b_scan_bind_group_layout),
+            )
+        }
+    }
+
+    /// Creates the resources for another target format, sharing the bind
+    /// group layouts, so the bind groups of existing views stay compatible.
+    pub fn with_layouts(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target_format: &wgpu::TextureFormat,
+        scan_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+        b_scan_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    ) -> Self {
+        let (color_maps_bind_group_layout, color_maps_bind_group) =
+            Self::create_color_map_bind_group(device, queue);
+
         let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
 
         let polar_view_pipeline = Self::create_polar_view_pipeline(
             device,
             target_format,
             &shader,
+            "polar_fs_main",
             &[&scan_bind_group_layout, &color_maps_bind_group_layout],
         );
 
+        let polar_uniform_view_pipeline = Self::create_polar_view_pipeline(
+            device,
+            target_format,
+            &shader,
+            "polar_uniform_fs_main",
+            &[
+                &scan_bind_group_layout,
+                &color_maps_bind_group_layout,
+                &b_scan_bind_group_layout,
+            ],
+        );
+
         let cartesian_view_pipeline = Self::create_cartesian_view_pipeline(
             device,
             target_format,
@@ -279,11 +457,14 @@ impl SharedResources {
 
         Self {
             polar_view_pipeline,
+            polar_uniform_view_pipeline,
             cartesian_view_pipeline,
             side_view_pipeline,
-            scan_bind_group_layout: Arc::new(scan_bind_group_layout),
+            scan_bind_group_layout,
             color_maps_bind_group: Arc::new(color_maps_bind_group),
-            b_scan_segmentation_bind_group_layout: Arc::new(b_scan_bind_group_layout),
+            b_scan_segmentation_bind_group_layout: b_scan_bind_group_layout,
+            pyramid: Arc::new(Pyramid::new(device)),
+            timer: None,
         }
     }
 
@@ -291,6 +472,7 @@ impl SharedResources {
         device: &wgpu::Device,
         target_format: &wgpu::TextureFormat,
         shader: &wgpu::ShaderModule,
+        fragment_entry_point: &str,
         bind_group_layouts: &[&wgpu::BindGroupLayout],
     ) -> wgpu::RenderPipeline {
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -303,7 +485,7 @@ impl SharedResources {
                 },
                 wgpu::PushConstantRange {
                     stages: wgpu::ShaderStages::FRAGMENT,
-                    range: 16..28,
+                    range: 16..56,
                 },
             ],
         });
@@ -320,7 +502,7 @@ impl SharedResources {
             },
             fragment: Some(wgpu::FragmentState {
                 module: shader,
-                entry_point: "polar_fs_main",
+                entry_point: fragment_entry_point,
                 targets: &[Some((*target_format).into())],
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
             }),
@@ -355,7 +537,7 @@ impl SharedResources {
                 },
                 wgpu::PushConstantRange {
                     stages: wgpu::ShaderStages::FRAGMENT,
-                    range: 16..32,
+                    range: 16..52,
                 },
             ],
         });
@@ -407,7 +589,7 @@ impl SharedResources {
                 },
                 wgpu::PushConstantRange {
                     stages: wgpu::ShaderStages::FRAGMENT,
-                    range: 16..28,
+                    range: 16..52,
                 },
             ],
         });
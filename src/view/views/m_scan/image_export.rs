@@ -0,0 +1,324 @@
+//! Export of the polar or cartesian view as a PNG image, for figures.
+//!
+//! The image is rendered offscreen at the chosen resolution with the same
+//! pipelines, color map and overlays as the view, see [FrameRenderer], and
+//! read back and written in the background.
+
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use egui::Grid;
+use image::ImageFormat;
+use tokio::sync::watch;
+
+use crate::gui::widgets::{PathInput, PathInputAction};
+
+use super::{
+    animation::{AnimationSource, FrameRenderer},
+    overlay::Overlays,
+    uis::{CartesianBScan, PolarMScan},
+};
+
+// MARK: ImageSettings
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageView {
+    /// The whole M scan, unwrapped.
+    Polar,
+    /// The shown B scan.
+    Cartesian,
+}
+
+impl ImageView {
+    pub const VALUES: [ImageView; 2] = [ImageView::Polar, ImageView::Cartesian];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageView::Polar => "Polar View",
+            ImageView::Cartesian => "Cartesian View",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageSettings {
+    /// The PNG file.
+    pub path: PathBuf,
+    pub view: ImageView,
+    /// The B scan of [ImageView::Cartesian].
+    pub b_scan: usize,
+    /// Width of the image in pixels. The cartesian view is square.
+    pub width: u32,
+    /// Height of the polar view in pixels.
+    pub height: u32,
+    /// Whether to draw the segmentation and the other overlays of the view.
+    pub overlays: bool,
+}
+
+impl ImageSettings {
+    pub fn new(view: ImageView, b_scan: usize) -> Self {
+        Self {
+            path: PathBuf::new(),
+            view,
+            b_scan,
+            width: 2048,
+            height: 1024,
+            overlays: true,
+        }
+    }
+
+    /// Width and height of the image, limited to `max_size`, the largest
+    /// texture of the device.
+    pub fn size(&self, max_size: u32) -> [u32; 2] {
+        let size = match self.view {
+            ImageView::Polar => [self.width, self.height],
+            ImageView::Cartesian => [self.width; 2],
+        };
+        size.map(|s| s.clamp(1, max_size))
+    }
+
+    pub fn file(&self) -> PathBuf {
+        self.path.with_extension("png")
+    }
+}
+
+// MARK: ImageExport
+
+/// State of an [ImageExport].
+#[derive(Debug, Clone)]
+pub enum ImageState {
+    Running,
+    Done(PathBuf),
+    Failed(String),
+}
+
+/// Renders and writes an image in the background, so the view stays
+/// responsive while the GPU renders and the image is read back.
+pub struct ImageExport {
+    state: watch::Receiver<ImageState>,
+}
+
+impl ImageExport {
+    pub fn start(source: AnimationSource, settings: ImageSettings) -> Self {
+        let (state_tx, state) = watch::channel(ImageState::Running);
+
+        tokio::task::spawn_blocking(move || {
+            state_tx.send_replace(match export(&source, &settings) {
+                Ok(path) => ImageState::Done(path),
+                Err(e) => ImageState::Failed(e.to_string()),
+            });
+        });
+
+        Self { state }
+    }
+
+    pub fn state(&self) -> ImageState {
+        self.state.borrow().clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(*self.state.borrow(), ImageState::Running)
+    }
+}
+
+fn export(source: &AnimationSource, settings: &ImageSettings) -> anyhow::Result<PathBuf> {
+    let b_scans = &source.b_scan_segmentation;
+    if settings.view == ImageView::Cartesian && settings.b_scan + 1 >= b_scans.len() {
+        return Err(anyhow!("There is no B scan {}", settings.b_scan));
+    }
+
+    let size = settings.size(source.device.limits().max_texture_dimension_2d);
+    let mut renderer = FrameRenderer::new(source, size);
+    let overlays = match settings.overlays {
+        true => source.overlays(),
+        false => Overlays::default(),
+    };
+
+    let image = renderer.render(|painter, rect| match settings.view {
+        ImageView::Polar => PolarMScan {
+            textures_state: &source.textures_state,
+            texture_bind_group: source.texture_bind_group.clone(),
+            color_mapping: source.color_mapping,
+            overlays: &overlays,
+        }
+        .paint(painter, rect),
+        ImageView::Cartesian => CartesianBScan {
+            textures_state: &source.textures_state,
+            texture_bind_group: source.texture_bind_group.clone(),
+            b_scan_segmentation: b_scans,
+            b_scan: settings.b_scan,
+            rotation: source.rotation,
+            color_mapping: source.color_mapping,
+            overlays: &overlays,
+            timing: None,
+        }
+        .paint(painter, rect),
+    })?;
+
+    let path = settings.file();
+    image.save_with_format(&path, ImageFormat::Png)?;
+
+    Ok(path)
+}
+
+// MARK: ImageDialog
+
+/// Window to set up and run an [ImageExport].
+pub struct ImageDialog {
+    settings: ImageSettings,
+    export: Option<ImageExport>,
+}
+
+impl ImageDialog {
+    pub fn new(view: ImageView, b_scan: usize) -> Self {
+        Self {
+            settings: ImageSettings::new(view, b_scan),
+            export: None,
+        }
+    }
+
+    /// Returns false, when the window got closed. `b_scan` follows the
+    /// cartesian view, until the export is started, and is [None] without B
+    /// scans. `source` is called, when the export is started.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        id: egui::Id,
+        b_scan: Option<usize>,
+        source: impl FnOnce() -> Option<AnimationSource>,
+    ) -> bool {
+        let mut open = true;
+
+        egui::Window::new("Export Image")
+            .id(id)
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let is_running = self
+                    .export
+                    .as_ref()
+                    .is_some_and(|export| !export.is_finished());
+
+                if !is_running {
+                    if let Some(b_scan) = b_scan {
+                        self.settings.b_scan = b_scan;
+                    } else {
+                        self.settings.view = ImageView::Polar;
+                    }
+                }
+
+                ui.add_enabled_ui(!is_running, |ui| self.settings_ui(ui, b_scan.is_some()));
+
+                let can_start = !is_running && !self.settings.path.as_os_str().is_empty();
+                if ui
+                    .add_enabled(can_start, egui::Button::new("Export"))
+                    .clicked()
+                {
+                    self.export =
+                        source().map(|source| ImageExport::start(source, self.settings.clone()));
+                }
+
+                if let Some(export) = &self.export {
+                    ui.separator();
+                    match export.state() {
+                        ImageState::Running => {
+                            ui.horizontal(|ui| {
+                                ui.spinner();
+                                ui.label("Rendering…");
+                            });
+                            ui.ctx().request_repaint();
+                        }
+                        ImageState::Done(path) => {
+                            ui.label(format!("Image written to {}", path.display()));
+                        }
+                        ImageState::Failed(e) => {
+                            ui.colored_label(ui.visuals().error_fg_color, format!("Failed: {e}"));
+                        }
+                    }
+                }
+            });
+
+        open
+    }
+
+    fn settings_ui(&mut self, ui: &mut egui::Ui, has_b_scans: bool) {
+        let settings = &mut self.settings;
+
+        Grid::new("image_settings").num_columns(2).show(ui, |ui| {
+            ui.label("View:");
+            egui::ComboBox::from_id_source("image_view")
+                .selected_text(settings.view.name())
+                .show_ui(ui, |ui| {
+                    for view in ImageView::VALUES {
+                        let enabled = view == ImageView::Polar || has_b_scans;
+                        ui.add_enabled_ui(enabled, |ui| {
+                            ui.selectable_value(&mut settings.view, view, view.name());
+                        });
+                    }
+                });
+            ui.end_row();
+
+            ui.label("File:");
+            ui.add(PathInput::new(&mut settings.path).action(PathInputAction::SaveFile));
+            ui.end_row();
+
+            if settings.view == ImageView::Cartesian {
+                ui.label("B Scan:");
+                ui.label(settings.b_scan.to_string())
+                    .on_hover_text("Scroll in the cartesian view to change the B scan");
+                ui.end_row();
+            }
+
+            let resolution = egui::DragValue::new(&mut settings.width)
+                .range(64..=8192)
+                .suffix(" px");
+            match settings.view {
+                ImageView::Polar => {
+                    ui.label("Width:");
+                    ui.add(resolution);
+                    ui.end_row();
+
+                    ui.label("Height:");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.height)
+                            .range(64..=8192)
+                            .suffix(" px"),
+                    );
+                    ui.end_row();
+                }
+                ImageView::Cartesian => {
+                    ui.label("Resolution:");
+                    ui.add(resolution);
+                    ui.end_row();
+                }
+            }
+
+            ui.label("Overlays:");
+            ui.checkbox(&mut settings.overlays, "")
+                .on_hover_text("Draw the segmentation and the other overlays of the view");
+            ui.end_row();
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn image_size() {
+        let mut settings = ImageSettings {
+            path: PathBuf::from("figure.jpg"),
+            width: 3000,
+            height: 800,
+            ..ImageSettings::new(ImageView::Polar, 0)
+        };
+        assert_eq!(settings.size(8192), [3000, 800]);
+        assert_eq!(settings.size(2048), [2048, 800]);
+        assert_eq!(settings.file(), PathBuf::from("figure.png"));
+
+        settings.view = ImageView::Cartesian;
+        assert_eq!(settings.size(8192), [3000, 3000]);
+        assert_eq!(settings.size(1024), [1024, 1024]);
+    }
+}
@@ -0,0 +1,698 @@
+//! Jump list of the M scan view, to navigate long pullbacks. Lists bookmarks
+//! and events flagged by [EventProvider]s, each with a thumbnail of the
+//! cartesian view at its B scan.
+//!
+//! Thumbnails are rendered offscreen like the frames of an animation export,
+//! see [super::animation], only for the rows that are visible. Each one is
+//! rendered once and kept until it is evicted by [THUMBNAIL_BUDGET] or the
+//! M scan changes.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use egui::{ColorImage, ScrollArea, TextureHandle, TextureOptions, Vec2};
+use image::RgbaImage;
+use tokio::sync::mpsc;
+
+use super::{
+    animation::{AnimationSource, FrameRenderer},
+    types::BScanDiameter,
+    INVALID_DEPTH,
+};
+
+/// Size of the thumbnails in pixels.
+const THUMBNAIL_SIZE: u32 = 64;
+
+/// Memory all thumbnails of a view may take together, in bytes.
+const THUMBNAIL_BUDGET: usize = 8 * 1024 * 1024;
+
+const ROW_HEIGHT: f32 = 52.0;
+
+// MARK: Events
+
+/// What the events are computed from. The data of B scans, that are not
+/// complete yet, may be missing.
+pub struct EventInput<'a> {
+    pub b_scans: &'a [usize],
+    pub m_scan_segmentation: Option<&'a [usize]>,
+    pub diameters: Option<&'a [BScanDiameter]>,
+}
+
+/// A B scan an [EventProvider] flagged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub b_scan: usize,
+    pub label: String,
+}
+
+/// Finds B scans worth a look in a pullback. Providers are registered in
+/// [EVENT_PROVIDERS].
+pub trait EventProvider: Sync {
+    fn name(&self) -> &'static str;
+
+    fn events(&self, input: &EventInput) -> Vec<Event>;
+}
+
+/// All providers, whose events are listed in the jump list.
+pub static EVENT_PROVIDERS: &[&dyn EventProvider] =
+    &[&BScanWidthOutliers, &CoverageDrops, &DiameterMinima];
+
+/// B scans, that are much narrower or wider than the median B scan, which
+/// hints at a missed or extra boundary.
+pub struct BScanWidthOutliers;
+
+impl BScanWidthOutliers {
+    /// Relative deviation from the median width, that is flagged.
+    const TOLERANCE: f32 = 0.25;
+}
+
+impl EventProvider for BScanWidthOutliers {
+    fn name(&self) -> &'static str {
+        "B scan width"
+    }
+
+    fn events(&self, input: &EventInput) -> Vec<Event> {
+        let widths = input
+            .b_scans
+            .windows(2)
+            .map(|b| b[1] - b[0])
+            .collect::<Vec<_>>();
+        if widths.len() < 3 {
+            return Vec::new();
+        }
+
+        let mut sorted = widths.clone();
+        sorted.sort_unstable();
+        let median = sorted[sorted.len() / 2] as f32;
+
+        widths
+            .iter()
+            .enumerate()
+            .filter(|(_, width)| (**width as f32 - median).abs() > median * Self::TOLERANCE)
+            .map(|(b_scan, width)| Event {
+                b_scan,
+                label: format!("{width} A scans wide, median {median}"),
+            })
+            .collect()
+    }
+}
+
+/// Where the share of A scans with an M scan segmentation drops below
+/// [Self::THRESHOLD]. Only the first B scan of a drop is flagged.
+pub struct CoverageDrops;
+
+impl CoverageDrops {
+    const THRESHOLD: f32 = 0.8;
+}
+
+impl EventProvider for CoverageDrops {
+    fn name(&self) -> &'static str {
+        "Segmentation coverage"
+    }
+
+    fn events(&self, input: &EventInput) -> Vec<Event> {
+        let Some(depths) = input.m_scan_segmentation else {
+            return Vec::new();
+        };
+
+        let mut events = Vec::new();
+        let mut covered = true;
+        for (b_scan, b) in input.b_scans.windows(2).enumerate() {
+            let Some(a_scans) = depths.get(b[0]..b[1]).filter(|a| !a.is_empty()) else {
+                break;
+            };
+
+            let valid = a_scans.iter().filter(|d| **d != INVALID_DEPTH).count();
+            let coverage = valid as f32 / a_scans.len() as f32;
+
+            if coverage < Self::THRESHOLD && covered {
+                events.push(Event {
+                    b_scan,
+                    label: format!("{:.0} % segmented", coverage * 100.0),
+                });
+            }
+            covered = coverage >= Self::THRESHOLD;
+        }
+        events
+    }
+}
+
+/// B scans with the smallest mean diameter among their neighbors, like
+/// stenoses.
+pub struct DiameterMinima;
+
+impl DiameterMinima {
+    /// Number of B scans on each side, a minimum is smaller than.
+    const NEIGHBORS: usize = 10;
+}
+
+impl EventProvider for DiameterMinima {
+    fn name(&self) -> &'static str {
+        "Diameter minimum"
+    }
+
+    fn events(&self, input: &EventInput) -> Vec<Event> {
+        let Some(diameters) = input.diameters else {
+            return Vec::new();
+        };
+        let means = diameters
+            .iter()
+            .map(|d| match d.is_finite() {
+                true => d.mean,
+                false => f32::INFINITY,
+            })
+            .collect::<Vec<_>>();
+
+        (0..means.len())
+            .filter(|&b_scan| {
+                let mean = means[b_scan];
+                let before = b_scan.saturating_sub(Self::NEIGHBORS)..b_scan;
+                let after = b_scan + 1..(b_scan + 1 + Self::NEIGHBORS).min(means.len());
+
+                // On plateaus, only the first B scan is a minimum
+                mean.is_finite()
+                    && means[before].iter().all(|m| *m > mean)
+                    && means[after].iter().all(|m| *m >= mean)
+            })
+            .map(|b_scan| Event {
+                b_scan,
+                label: format!("{:.2} mm mean diameter", means[b_scan]),
+            })
+            .collect()
+    }
+}
+
+// MARK: JumpList
+
+/// A B scan marked by the user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bookmark {
+    pub b_scan: usize,
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EntryKind {
+    /// Index into [JumpList::bookmarks].
+    Bookmark(usize),
+    Event(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    b_scan: usize,
+    kind: EntryKind,
+    label: String,
+}
+
+type NamedEvents = Vec<(&'static str, Event)>;
+
+#[derive(Default)]
+pub struct JumpList {
+    pub open: bool,
+    bookmarks: Vec<Bookmark>,
+    /// Events by the name of their provider, and the generations of the
+    /// overlays they were computed from.
+    events: Option<([u64; 3], NamedEvents)>,
+    thumbnails: Thumbnails,
+}
+
+impl Clone for JumpList {
+    fn clone(&self) -> Self {
+        Self {
+            open: self.open,
+            bookmarks: self.bookmarks.clone(),
+            events: None,
+            thumbnails: Thumbnails::default(),
+        }
+    }
+}
+
+impl JumpList {
+    /// Shows the list. `generations` identify the data of `input`, so events
+    /// are only searched again, when it changed. `thumbnails` identifies what
+    /// the thumbnails show, [None] while it is not complete. Returns the B
+    /// scan to jump to.
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        input: &EventInput,
+        generations: [u64; 3],
+        thumbnails: Option<u64>,
+        current_b_scan: Option<usize>,
+        source: impl FnOnce() -> Option<AnimationSource>,
+    ) -> Option<usize> {
+        if self.events.as_ref().map(|(g, _)| *g) != Some(generations) {
+            let events = EVENT_PROVIDERS
+                .iter()
+                .flat_map(|provider| {
+                    provider
+                        .events(input)
+                        .into_iter()
+                        .map(|event| (provider.name(), event))
+                })
+                .collect();
+            self.events = Some((generations, events));
+        }
+
+        let b_scan_count = input.b_scans.len().saturating_sub(1);
+        let entries = self.entries(b_scan_count);
+
+        self.thumbnails.begin_frame(ui.ctx(), thumbnails);
+
+        let mut jump = None;
+        let mut remove = None;
+
+        ui.horizontal(|ui| {
+            ui.strong("Jump List");
+            if let Some(b_scan) = current_b_scan {
+                if ui
+                    .button("🔖 Bookmark")
+                    .on_hover_text("Bookmark the B scan shown in the cartesian view")
+                    .clicked()
+                {
+                    self.bookmarks.push(Bookmark {
+                        b_scan,
+                        label: format!("Bookmark {}", self.bookmarks.len() + 1),
+                    });
+                }
+            }
+        });
+        ui.weak(format!("{} entries", entries.len()));
+        ui.separator();
+
+        let width = ui.available_width();
+        ScrollArea::vertical().auto_shrink([false; 2]).show_rows(
+            ui,
+            ROW_HEIGHT,
+            entries.len(),
+            |ui, rows| {
+                for entry in &entries[rows] {
+                    let thumbnail = thumbnails.and_then(|_| self.thumbnails.get(entry.b_scan));
+
+                    ui.allocate_ui(Vec2::new(width, ROW_HEIGHT), |ui| {
+                        ui.horizontal(|ui| {
+                            let size = Vec2::splat(ROW_HEIGHT - 4.0);
+                            match thumbnail {
+                                Some(texture) => {
+                                    ui.image((texture.id(), size));
+                                }
+                                None => {
+                                    let (rect, _) =
+                                        ui.allocate_exact_size(size, egui::Sense::hover());
+                                    ui.painter().rect_filled(
+                                        rect,
+                                        2.0,
+                                        ui.visuals().faint_bg_color,
+                                    );
+                                }
+                            }
+
+                            ui.vertical(|ui| {
+                                let selected = current_b_scan == Some(entry.b_scan);
+                                if ui
+                                    .selectable_label(selected, format!("B scan {}", entry.b_scan))
+                                    .on_hover_text("Show this B scan")
+                                    .clicked()
+                                {
+                                    jump = Some(entry.b_scan);
+                                }
+
+                                match entry.kind {
+                                    EntryKind::Bookmark(index) => {
+                                        ui.horizontal(|ui| {
+                                            ui.add(
+                                                egui::TextEdit::singleline(
+                                                    &mut self.bookmarks[index].label,
+                                                )
+                                                .desired_width(width - ROW_HEIGHT - 40.0),
+                                            );
+                                            if ui.small_button("🗑").clicked() {
+                                                remove = Some(index);
+                                            }
+                                        });
+                                    }
+                                    EntryKind::Event(name) => {
+                                        ui.weak(format!("{name}: {}", entry.label));
+                                    }
+                                }
+                            });
+                        });
+                    });
+                }
+            },
+        );
+
+        if let Some(index) = remove {
+            self.bookmarks.remove(index);
+        }
+
+        if thumbnails.is_some() {
+            self.thumbnails.end_frame(source);
+        }
+
+        jump
+    }
+
+    /// Bookmarks and events, ordered by their B scan. Entries of B scans not
+    /// received yet are left out.
+    fn entries(&self, b_scan_count: usize) -> Vec<Entry> {
+        let bookmarks = self
+            .bookmarks
+            .iter()
+            .enumerate()
+            .map(|(index, bookmark)| Entry {
+                b_scan: bookmark.b_scan,
+                kind: EntryKind::Bookmark(index),
+                label: bookmark.label.clone(),
+            });
+        let events = self.events.iter().flat_map(|(_, events)| {
+            events.iter().map(|(name, event)| Entry {
+                b_scan: event.b_scan,
+                kind: EntryKind::Event(name),
+                label: event.label.clone(),
+            })
+        });
+
+        let mut entries = bookmarks
+            .chain(events)
+            .filter(|entry| entry.b_scan < b_scan_count)
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|entry| (entry.b_scan, matches!(entry.kind, EntryKind::Event(_))));
+        entries
+    }
+}
+
+// MARK: Thumbnails
+
+/// Least recently used thumbnails by their B scan, rendered in the
+/// background.
+struct Thumbnails {
+    /// Identifies what the thumbnails show.
+    key: Option<u64>,
+    /// Least recently used first.
+    textures: VecDeque<(usize, TextureHandle)>,
+    /// B scans, that were cached or requested already. Evicted B scans are
+    /// removed, so they are rendered again, when they become visible.
+    requested: HashSet<usize>,
+    /// B scans to render, requested in this frame.
+    queued: Vec<usize>,
+    worker: Option<Worker>,
+    budget: usize,
+}
+
+impl Default for Thumbnails {
+    fn default() -> Self {
+        Self::new(THUMBNAIL_BUDGET)
+    }
+}
+
+/// Renders thumbnails on a blocking thread. Dropping it stops rendering after
+/// the current thumbnail.
+struct Worker {
+    rx: mpsc::UnboundedReceiver<(usize, RgbaImage)>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Thumbnails {
+    fn new(budget: usize) -> Self {
+        Self {
+            key: None,
+            textures: VecDeque::new(),
+            requested: HashSet::new(),
+            queued: Vec::new(),
+            worker: None,
+            budget,
+        }
+    }
+
+    /// Drops all thumbnails, if `key` changed, and takes the ones rendered
+    /// since the last frame.
+    fn begin_frame(&mut self, ctx: &egui::Context, key: Option<u64>) {
+        if key.is_some() && key != self.key {
+            self.key = key;
+            self.textures.clear();
+            self.requested.clear();
+            self.worker = None;
+        }
+        self.queued.clear();
+
+        let Some(worker) = &mut self.worker else {
+            return;
+        };
+
+        let mut finished = false;
+        let mut rendered = Vec::new();
+        loop {
+            match worker.rx.try_recv() {
+                Ok(thumbnail) => rendered.push(thumbnail),
+                Err(mpsc::error::TryRecvError::Empty) => break,
+                Err(mpsc::error::TryRecvError::Disconnected) => {
+                    finished = true;
+                    break;
+                }
+            }
+        }
+
+        for (b_scan, image) in rendered {
+            let image = ColorImage::from_rgba_unmultiplied(
+                [image.width() as usize, image.height() as usize],
+                image.as_raw(),
+            );
+            let texture = ctx.load_texture(
+                format!("b_scan_thumbnail_{b_scan}"),
+                image,
+                TextureOptions::LINEAR,
+            );
+            self.insert(b_scan, texture);
+        }
+
+        match finished {
+            true => self.worker = None,
+            false => ctx.request_repaint(),
+        }
+    }
+
+    /// The thumbnail of `b_scan`, which is rendered, if it is missing.
+    fn get(&mut self, b_scan: usize) -> Option<&TextureHandle> {
+        if let Some(index) = self.textures.iter().position(|(b, _)| *b == b_scan) {
+            let entry = self.textures.remove(index)?;
+            self.textures.push_back(entry);
+            return self.textures.back().map(|(_, texture)| texture);
+        }
+
+        if self.requested.insert(b_scan) {
+            self.queued.push(b_scan);
+        }
+        None
+    }
+
+    fn insert(&mut self, b_scan: usize, texture: TextureHandle) {
+        self.textures.push_back((b_scan, texture));
+
+        let size = |texture: &TextureHandle| texture.byte_size();
+        while self.textures.iter().map(|(_, t)| size(t)).sum::<usize>() > self.budget {
+            let Some((evicted, _)) = self.textures.pop_front() else {
+                break;
+            };
+            self.requested.remove(&evicted);
+        }
+    }
+
+    /// Starts rendering the thumbnails requested in this frame, once the
+    /// previous ones are done. Requests made while rendering are forgotten,
+    /// so they are made again, if the rows are still visible.
+    fn end_frame(&mut self, source: impl FnOnce() -> Option<AnimationSource>) {
+        if self.queued.is_empty() {
+            return;
+        }
+
+        let queued = std::mem::take(&mut self.queued);
+        let source = match self.worker {
+            None => source(),
+            Some(_) => None,
+        };
+        let Some(source) = source else {
+            for b_scan in queued {
+                self.requested.remove(&b_scan);
+            }
+            return;
+        };
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let task_cancel = cancel.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut renderer = FrameRenderer::new(&source, [THUMBNAIL_SIZE; 2]);
+            for b_scan in queued {
+                if task_cancel.load(Ordering::Relaxed) {
+                    return;
+                }
+                let Ok(image) =
+                    renderer.render(|painter, rect| source.paint_b_scan(painter, rect, b_scan))
+                else {
+                    return;
+                };
+                if tx.send((b_scan, image)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        self.worker = Some(Worker { rx, cancel });
+    }
+
+    #[cfg(test)]
+    fn cached(&self) -> Vec<usize> {
+        let mut b_scans = self.textures.iter().map(|(b, _)| *b).collect::<Vec<_>>();
+        b_scans.sort_unstable();
+        b_scans
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::Vector2;
+
+    use super::*;
+
+    fn diameter(mean: f32) -> BScanDiameter {
+        BScanDiameter {
+            b_scan_start: 0,
+            b_scan_end: 0,
+            min: mean,
+            max: mean,
+            mean,
+            min_points: [Vector2::zeros(); 2],
+            max_points: [Vector2::zeros(); 2],
+        }
+    }
+
+    #[test]
+    fn event_providers() {
+        let b_scans = [0, 10, 20, 30, 35, 45, 65, 75];
+        let mut depths = vec![5; 75];
+        // B scan 1 is barely segmented, B scans 5 and 6 not at all
+        depths[10..18].fill(INVALID_DEPTH);
+        depths[45..].fill(INVALID_DEPTH);
+
+        let mut means = [3.0; 30];
+        means[4] = 2.0;
+        means[20] = 2.5;
+        means[21] = 2.5;
+        means[25] = f32::NAN;
+        let diameters = means.iter().map(|m| diameter(*m)).collect::<Vec<_>>();
+
+        let input = EventInput {
+            b_scans: &b_scans,
+            m_scan_segmentation: Some(&depths),
+            diameters: Some(&diameters),
+        };
+
+        let b_scans_of = |provider: &dyn EventProvider| {
+            provider
+                .events(&input)
+                .iter()
+                .map(|e| e.b_scan)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(b_scans_of(&BScanWidthOutliers), [3, 5]);
+        assert_eq!(b_scans_of(&CoverageDrops), [1, 5]);
+        assert_eq!(b_scans_of(&DiameterMinima), [4, 20]);
+
+        // Streamed data may end before the B scans
+        let input = EventInput {
+            b_scans: &b_scans,
+            m_scan_segmentation: Some(&depths[..40]),
+            diameters: None,
+        };
+        assert_eq!(CoverageDrops.events(&input).len(), 1);
+        assert!(DiameterMinima.events(&input).is_empty());
+    }
+
+    #[test]
+    fn entries_are_ordered() {
+        let b_scans = [0, 10, 20, 30, 35, 45];
+        let input = EventInput {
+            b_scans: &b_scans,
+            m_scan_segmentation: None,
+            diameters: None,
+        };
+
+        let mut list = JumpList::default();
+        list.bookmarks.push(Bookmark {
+            b_scan: 3,
+            label: "Stent".to_string(),
+        });
+        list.bookmarks.push(Bookmark {
+            b_scan: 9,
+            label: "Not received yet".to_string(),
+        });
+        list.bookmarks.push(Bookmark {
+            b_scan: 1,
+            label: "Side branch".to_string(),
+        });
+
+        let ctx = egui::Context::default();
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                list.ui(ui, &input, [0; 3], None, None, || None);
+            });
+        });
+
+        let entries = list.entries(5);
+        let b_scans = entries.iter().map(|e| e.b_scan).collect::<Vec<_>>();
+        assert_eq!(b_scans, [1, 3, 3]);
+        assert_eq!(entries[1].kind, EntryKind::Bookmark(0));
+        assert_eq!(entries[2].kind, EntryKind::Event("B scan width"));
+    }
+
+    #[test]
+    fn thumbnails_within_budget() {
+        let ctx = egui::Context::default();
+        let texture = |b_scan: usize| {
+            let image = ColorImage::new([THUMBNAIL_SIZE as usize; 2], egui::Color32::BLACK);
+            ctx.load_texture(format!("{b_scan}"), image, TextureOptions::LINEAR)
+        };
+        let size = texture(0).byte_size();
+
+        let mut thumbnails = Thumbnails::new(size * 4);
+        thumbnails.begin_frame(&ctx, Some(1));
+
+        // Requested once, until rendered
+        assert!(thumbnails.get(0).is_none());
+        assert!(thumbnails.get(0).is_none());
+        assert_eq!(thumbnails.queued, [0]);
+
+        for b_scan in 0..6 {
+            thumbnails.requested.insert(b_scan);
+            thumbnails.insert(b_scan, texture(b_scan));
+        }
+        assert_eq!(thumbnails.cached(), [2, 3, 4, 5]);
+
+        // Using a thumbnail keeps it
+        thumbnails.begin_frame(&ctx, Some(1));
+        assert!(thumbnails.get(2).is_some());
+        thumbnails.insert(6, texture(6));
+        assert!(thumbnails.get(2).is_some());
+        assert!(thumbnails.get(3).is_none());
+        assert_eq!(thumbnails.queued, [3]);
+
+        // Another M scan
+        thumbnails.begin_frame(&ctx, Some(2));
+        assert!(thumbnails.get(2).is_none());
+        assert!(thumbnails.cached().is_empty());
+    }
+}
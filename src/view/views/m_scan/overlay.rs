@@ -0,0 +1,611 @@
+//! Overlays drawn on top of the M scan. Every overlay is drawn through a
+//! [ScanViewMapping], which places A scans and samples on the screen in the
+//! polar, cartesian or side view, so an overlay is written once for all of
+//! them.
+
+use std::{cell::RefCell, f32::consts::TAU, ops::Range};
+
+use egui::*;
+use nalgebra::Vector2;
+
+use crate::units::NumberFormat;
+
+use super::{
+    polyline::{self, Line, LineKey, OverlayLines},
+    types::BScanDiameter,
+    uis::{
+        cartesian_segmentation_points, diameter_of_b_scan, polar_segmentation_points,
+        side_segmentation_points, PolarMapping,
+    },
+};
+
+// MARK: ScanViewMapping
+
+/// Where the A scans and samples of the M scan are on the screen in one of
+/// the views.
+#[derive(Debug, Clone)]
+pub enum ScanViewMapping<'a> {
+    /// The whole M scan unwrapped, panned and zoomed inside `rect`.
+    Polar {
+        mapping: PolarMapping<'a>,
+        rect: Rect,
+    },
+    /// One B scan around the center of `rect`, filling its width.
+    Cartesian {
+        rect: Rect,
+        /// The A scans of the B scan.
+        b_scan: Range<usize>,
+        a_scan_samples: usize,
+        /// Rotation shown in the side view, as fraction of a full turn.
+        rotation: f32,
+    },
+    /// A cut through the pullback at `rotation`, with one column per B scan.
+    /// The upper half shows the rotation, the lower half the opposite side.
+    Side {
+        rect: Rect,
+        b_scan_segmentation: &'a [usize],
+        a_scan_samples: usize,
+        rotation: f32,
+        /// Fraction of a B scan averaged on each side of the rotation.
+        neighborhood: f32,
+        /// The B scan shown in the cartesian view.
+        current_b_scan: usize,
+    },
+}
+
+impl ScanViewMapping<'_> {
+    /// Screen position of `sample` of `a_scan`. [None], if the A scan is not
+    /// shown, like A scans outside the B scan of the cartesian view or away
+    /// from the rotation of the side view.
+    pub fn to_screen(&self, a_scan: f32, sample: f32) -> Option<Pos2> {
+        match *self {
+            Self::Polar { mapping, .. } => Some(pos2(mapping.x(a_scan), mapping.y(sample))),
+            Self::Cartesian {
+                rect,
+                ref b_scan,
+                a_scan_samples,
+                ..
+            } => {
+                let a_scans = b_scan.start as f32..b_scan.end as f32;
+                if !a_scans.contains(&a_scan) || a_scan_samples == 0 {
+                    return None;
+                }
+
+                let alpha = (a_scan - a_scans.start) / b_scan.len() as f32 * TAU;
+                let vec =
+                    sample / a_scan_samples as f32 * Vec2::angled(alpha) * (rect.width() / 2.0);
+
+                Some(rect.center() + vec2(-vec.y, -vec.x))
+            }
+            Self::Side {
+                rect,
+                b_scan_segmentation,
+                a_scan_samples,
+                rotation,
+                neighborhood,
+                ..
+            } => {
+                let b_scan = b_scan_segmentation
+                    .partition_point(|&start| start as f32 <= a_scan)
+                    .checked_sub(1)?;
+                let start = b_scan_segmentation[b_scan];
+                let end = *b_scan_segmentation.get(b_scan + 1)?;
+                if end <= start || a_scan_samples == 0 {
+                    return None;
+                }
+                let len = (end - start) as f32;
+
+                // Distance around the B scan, as fraction of a full turn
+                let turn = (a_scan - start as f32) / len;
+                let distance = |rotation: f32| {
+                    let d = (turn - rotation).rem_euclid(1.0);
+                    d.min(1.0 - d)
+                };
+
+                // The nearer of the rotation and its opposite side. Shown
+                // within the neighborhood and the A scan closest to it.
+                let (distance, direction) =
+                    [(distance(rotation), -1.0), (distance(rotation + 0.5), 1.0)]
+                        .into_iter()
+                        .min_by(|(a, _), (b, _)| a.total_cmp(b))?;
+                if distance > neighborhood + 1.0 / len {
+                    return None;
+                }
+
+                let x = (b_scan as f32 + 0.5) / (b_scan_segmentation.len() - 1) as f32;
+                let y = sample / a_scan_samples as f32;
+
+                Some(pos2(
+                    rect.left() + x * rect.width(),
+                    rect.center().y + direction * y * rect.height() * 0.5,
+                ))
+            }
+        }
+    }
+}
+
+// MARK: ScanOverlay
+
+/// Something drawn on top of the M scan.
+pub trait ScanOverlay {
+    /// Draws the overlay into the view of `mapping`. Overlays without meaning
+    /// in a view draw nothing there.
+    fn draw(&self, painter: &Painter, mapping: &ScanViewMapping);
+}
+
+/// The overlays of the M scan view, drawn in the order they were added. The
+/// view, the exported images and the animations draw the same ones.
+#[derive(Default)]
+pub struct Overlays<'a> {
+    overlays: Vec<Box<dyn ScanOverlay + 'a>>,
+}
+
+impl<'a> Overlays<'a> {
+    /// The overlays registered with the view. `b_scan_segmentation` gets its
+    /// bounds drawn. `lines` caches the lumen line between frames.
+    pub fn of_scan(
+        b_scan_segmentation: Option<&'a [usize]>,
+        m_scan_segmentation: Option<&'a [usize]>,
+        diameters: Option<&'a [BScanDiameter]>,
+        lines: Option<&'a mut OverlayLines>,
+    ) -> Self {
+        let mut overlays = Self::default();
+
+        if let Some(b_scan_segmentation) = b_scan_segmentation {
+            overlays.add(BScanBounds(b_scan_segmentation));
+        }
+        if let Some(m_scan_segmentation) = m_scan_segmentation {
+            overlays.add(LumenLine {
+                m_scan_segmentation,
+                lines: lines.map(RefCell::new),
+            });
+        }
+        if let Some(diameters) = diameters {
+            overlays.add(Diameters(diameters));
+        }
+        overlays.add(RotationMarker);
+        overlays.add(CurrentBScan);
+
+        overlays
+    }
+
+    pub fn add(&mut self, overlay: impl ScanOverlay + 'a) {
+        self.overlays.push(Box::new(overlay));
+    }
+}
+
+impl ScanOverlay for Overlays<'_> {
+    fn draw(&self, painter: &Painter, mapping: &ScanViewMapping) {
+        for overlay in &self.overlays {
+            overlay.draw(painter, mapping);
+        }
+    }
+}
+
+// MARK: Overlays
+
+/// Bounds of the B scans in the polar view.
+pub struct BScanBounds<'a>(pub &'a [usize]);
+
+impl ScanOverlay for BScanBounds<'_> {
+    fn draw(&self, painter: &Painter, mapping: &ScanViewMapping) {
+        let ScanViewMapping::Polar { mapping, .. } = mapping else {
+            return;
+        };
+
+        for b_scan in self.0 {
+            let x = mapping.x(*b_scan as f32);
+
+            painter.line_segment(
+                [
+                    pos2(x, mapping.viewport.min.y),
+                    pos2(x, mapping.viewport.max.y),
+                ],
+                Stroke::new(1.0, Color32::BLUE),
+            );
+        }
+    }
+}
+
+/// The segmentation of the lumen, one sample per A scan. The side view draws
+/// it averaged around its rotation, on both sides.
+pub struct LumenLine<'a> {
+    pub m_scan_segmentation: &'a [usize],
+    /// Caches the lines of the view. Without, they are built for every draw.
+    pub lines: Option<RefCell<&'a mut OverlayLines>>,
+}
+
+impl ScanOverlay for LumenLine<'_> {
+    fn draw(&self, painter: &Painter, mapping: &ScanViewMapping) {
+        let stroke = Stroke::new(2.0, Color32::RED);
+        let mut lines = self.lines.as_ref().map(|lines| lines.borrow_mut());
+
+        match *mapping {
+            ScanViewMapping::Polar { ref mapping, rect } => {
+                let build = || {
+                    polar_segmentation_points(
+                        self.m_scan_segmentation,
+                        rect.intersect(mapping.viewport).x_range(),
+                        mapping,
+                        painter.ctx().pixels_per_point(),
+                    )
+                };
+                let key = LineKey {
+                    rect,
+                    viewport: mapping.viewport,
+                    params: [mapping.uniform_b_scans.is_some() as u8 as f32, 0.0, 0.0],
+                };
+                let points = match lines.as_mut() {
+                    Some(lines) => lines.get(Line::Polar, key, build),
+                    None => build(),
+                };
+
+                painter.add(Shape::line(points, stroke));
+            }
+            ScanViewMapping::Cartesian {
+                rect,
+                ref b_scan,
+                a_scan_samples,
+                ..
+            } => {
+                let build = || {
+                    cartesian_segmentation_points(
+                        self.m_scan_segmentation,
+                        b_scan.clone(),
+                        a_scan_samples,
+                        rect,
+                    )
+                };
+                let key = LineKey {
+                    rect,
+                    viewport: rect,
+                    params: [b_scan.start as f32, b_scan.end as f32, 0.0],
+                };
+                let points = match lines.as_mut() {
+                    Some(lines) => lines.get(Line::Cartesian, key, build),
+                    None => polyline::simplify(&build(), polyline::TOLERANCE),
+                };
+
+                painter.add(Shape::closed_line(points, stroke));
+            }
+            ScanViewMapping::Side {
+                rect,
+                rotation,
+                neighborhood,
+                ..
+            } => {
+                for (index, opposite) in [false, true].into_iter().enumerate() {
+                    let build =
+                        || side_segmentation_points(self.m_scan_segmentation, mapping, opposite);
+                    let key = LineKey {
+                        rect,
+                        viewport: rect,
+                        params: [rotation, neighborhood, 0.0],
+                    };
+                    let points = match lines.as_mut() {
+                        Some(lines) => lines.get(Line::Side(index), key, build),
+                        None => build(),
+                    };
+
+                    painter.add(Shape::line(points, stroke));
+                }
+            }
+        }
+    }
+}
+
+/// The smallest and the largest diameter of the B scan in the cartesian view,
+/// labeled with their lengths.
+pub struct Diameters<'a>(pub &'a [BScanDiameter]);
+
+impl ScanOverlay for Diameters<'_> {
+    fn draw(&self, painter: &Painter, mapping: &ScanViewMapping) {
+        let ScanViewMapping::Cartesian { ref b_scan, .. } = *mapping else {
+            return;
+        };
+        let Some(diameter) = diameter_of_b_scan(self.0, b_scan.start).filter(|d| d.is_finite())
+        else {
+            return;
+        };
+
+        let format = NumberFormat::current();
+
+        // The points are in samples around the center of the B scan
+        let to_screen = |p: Vector2<f32>| {
+            let turn = (p.y.atan2(p.x) / TAU).rem_euclid(1.0);
+            let a_scan = b_scan.start as f32 + turn * b_scan.len() as f32;
+            // Rounding may reach the end of the B scan, which is its start
+            let a_scan = match a_scan < b_scan.end as f32 {
+                true => a_scan,
+                false => b_scan.start as f32,
+            };
+            mapping.to_screen(a_scan, p.norm())
+        };
+
+        let line_at_rot = |[p1, p2]: [Vector2<f32>; 2], diameter, stroke: Stroke| {
+            let (Some(p1), Some(p2)) = (to_screen(p1), to_screen(p2)) else {
+                return;
+            };
+            // Labeled close to the upper end
+            let text_pos = p1.lerp(p2, if p1.y < p2.y { 0.1 } else { 0.9 });
+
+            painter.line_segment([p1, p2], stroke);
+
+            painter.text(
+                text_pos,
+                Align2::LEFT_BOTTOM,
+                format.length(diameter, Some(2)),
+                FontId::default(),
+                stroke.color,
+            );
+        };
+
+        line_at_rot(
+            diameter.max_points,
+            diameter.max,
+            Stroke::new(2.0, Color32::GREEN),
+        );
+        line_at_rot(
+            diameter.min_points,
+            diameter.min,
+            Stroke::new(2.0, Color32::YELLOW),
+        );
+    }
+}
+
+/// Marks the rotation of the side view at the edge of the cartesian view.
+pub struct RotationMarker;
+
+impl ScanOverlay for RotationMarker {
+    fn draw(&self, painter: &Painter, mapping: &ScanViewMapping) {
+        let ScanViewMapping::Cartesian {
+            ref b_scan,
+            a_scan_samples,
+            rotation,
+            ..
+        } = *mapping
+        else {
+            return;
+        };
+
+        mark_edges(
+            painter,
+            mapping,
+            b_scan.clone(),
+            a_scan_samples,
+            [(rotation + 0.5) % 1.0, rotation],
+        );
+    }
+}
+
+/// Marks the B scan of the cartesian view at the top and the bottom of the
+/// side view.
+pub struct CurrentBScan;
+
+impl ScanOverlay for CurrentBScan {
+    fn draw(&self, painter: &Painter, mapping: &ScanViewMapping) {
+        let ScanViewMapping::Side {
+            b_scan_segmentation,
+            a_scan_samples,
+            rotation,
+            current_b_scan,
+            ..
+        } = *mapping
+        else {
+            return;
+        };
+        let (Some(&start), Some(&end)) = (
+            b_scan_segmentation.get(current_b_scan),
+            b_scan_segmentation.get(current_b_scan + 1),
+        ) else {
+            return;
+        };
+
+        mark_edges(
+            painter,
+            mapping,
+            start..end,
+            a_scan_samples,
+            [rotation, (rotation + 0.5) % 1.0],
+        );
+    }
+}
+
+/// Draws a tick from the last sample a fifth inwards, at the A scans of
+/// `b_scan` at the given `turns`.
+fn mark_edges(
+    painter: &Painter,
+    mapping: &ScanViewMapping,
+    b_scan: Range<usize>,
+    a_scan_samples: usize,
+    turns: [f32; 2],
+) {
+    let samples = a_scan_samples as f32;
+
+    for turn in turns {
+        let a_scan = b_scan.start as f32 + turn * b_scan.len() as f32;
+
+        if let (Some(edge), Some(end)) = (
+            mapping.to_screen(a_scan, samples),
+            mapping.to_screen(a_scan, 0.8 * samples),
+        ) {
+            painter.line_segment([edge, end], Stroke::new(2.0, Color32::BLUE));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The lines `overlay` draws into the view of `mapping`, without labels.
+    /// Points are rounded to 0.01, to compare them to written down positions.
+    fn shapes(overlay: &impl ScanOverlay, mapping: &ScanViewMapping) -> Vec<Shape> {
+        let round = |p: Pos2| pos2((p.x * 100.0).round() / 100.0, (p.y * 100.0).round() / 100.0);
+
+        let ctx = Context::default();
+        let output = ctx.run(RawInput::default(), |ctx| {
+            overlay.draw(&ctx.layer_painter(LayerId::background()), mapping);
+        });
+        output
+            .shapes
+            .into_iter()
+            .filter_map(|s| match s.shape {
+                Shape::LineSegment { points, stroke } => Some(Shape::LineSegment {
+                    points: points.map(round),
+                    stroke,
+                }),
+                Shape::Path(mut path) => {
+                    path.points.iter_mut().for_each(|p| *p = round(*p));
+                    Some(Shape::Path(path))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn square() -> Rect {
+        Rect::from_min_size(Pos2::ZERO, Vec2::splat(100.0))
+    }
+
+    fn segment(a: [f32; 2], b: [f32; 2], stroke: Stroke) -> Shape {
+        Shape::line_segment([a.into(), b.into()], stroke)
+    }
+
+    fn points(points: &[[f32; 2]]) -> Vec<Pos2> {
+        points.iter().map(|&p| p.into()).collect()
+    }
+
+    /// Positions drawn by the views before they shared [ScanViewMapping].
+    #[test]
+    fn shapes_of_the_views() {
+        let b_scans = [0, 4, 8, 12];
+        let segmentation = [10, 20, 30, 40, 50, 60, 70, 80, 90, 80, 70, 60];
+        let diameter = |b_scan_start, b_scan_end| BScanDiameter {
+            b_scan_start,
+            b_scan_end,
+            min: 1.0,
+            max: 2.0,
+            mean: 1.5,
+            min_points: [Vector2::new(-20.0, 10.0), Vector2::new(25.0, -5.0)],
+            max_points: [Vector2::new(0.0, -40.0), Vector2::new(0.0, 45.0)],
+        };
+        let diameters = [diameter(0, 4), diameter(4, 8)];
+        let overlays =
+            Overlays::of_scan(Some(&b_scans), Some(&segmentation), Some(&diameters), None);
+        let blue = |width| Stroke::new(width, Color32::BLUE);
+        let red = Stroke::new(2.0, Color32::RED);
+
+        let polar = PolarMapping {
+            viewport: Rect::from_min_size(Pos2::ZERO, vec2(120.0, 100.0)),
+            a_scan_count: 12,
+            a_scan_samples: 100,
+            uniform_b_scans: None,
+        };
+        let mut expected = [0.0, 40.0, 80.0, 120.0]
+            .map(|x| segment([x, 0.0], [x, 100.0], blue(1.0)))
+            .to_vec();
+        expected.push(Shape::line(
+            points(&[
+                [0.0, 10.0],
+                [10.0, 20.0],
+                [20.0, 30.0],
+                [30.0, 40.0],
+                [40.0, 50.0],
+                [50.0, 60.0],
+                [60.0, 70.0],
+                [70.0, 80.0],
+                [80.0, 90.0],
+                [90.0, 80.0],
+                [100.0, 70.0],
+                [110.0, 60.0],
+            ]),
+            red,
+        ));
+        let mapping = ScanViewMapping::Polar {
+            mapping: polar,
+            rect: polar.viewport,
+        };
+        assert_eq!(shapes(&overlays, &mapping), expected);
+
+        let mapping = ScanViewMapping::Cartesian {
+            rect: square(),
+            b_scan: 4..8,
+            a_scan_samples: 100,
+            rotation: 0.0,
+        };
+        let expected = vec![
+            Shape::closed_line(
+                points(&[[50.0, 25.0], [20.0, 50.0], [50.0, 85.0], [90.0, 50.0]]),
+                red,
+            ),
+            segment([70.0, 50.0], [27.5, 50.0], Stroke::new(2.0, Color32::GREEN)),
+            segment(
+                [45.0, 60.0],
+                [52.5, 37.5],
+                Stroke::new(2.0, Color32::YELLOW),
+            ),
+            segment([50.0, 100.0], [50.0, 90.0], blue(2.0)),
+            segment([50.0, 0.0], [50.0, 10.0], blue(2.0)),
+        ];
+        assert_eq!(shapes(&overlays, &mapping), expected);
+
+        let mapping = ScanViewMapping::Side {
+            rect: square(),
+            b_scan_segmentation: &b_scans,
+            a_scan_samples: 100,
+            rotation: 0.25,
+            neighborhood: 0.1,
+            current_b_scan: 1,
+        };
+        let expected = vec![
+            Shape::line(points(&[[16.67, 45.0], [50.0, 25.0], [83.33, 5.0]]), red),
+            Shape::line(points(&[[16.67, 65.0], [50.0, 85.0], [83.33, 85.0]]), red),
+            segment([50.0, 0.0], [50.0, 10.0], blue(2.0)),
+            segment([50.0, 100.0], [50.0, 90.0], blue(2.0)),
+        ];
+        assert_eq!(shapes(&overlays, &mapping), expected);
+
+        // Without averaging, only the A scans at the rotation are drawn
+        let mapping = ScanViewMapping::Side {
+            rect: square(),
+            b_scan_segmentation: &b_scans,
+            a_scan_samples: 100,
+            rotation: 0.25,
+            neighborhood: 0.0,
+            current_b_scan: 1,
+        };
+        assert_eq!(shapes(&overlays, &mapping), expected);
+    }
+
+    #[test]
+    fn positions_in_the_views() {
+        let b_scans = [0, 8, 16];
+
+        let cartesian = ScanViewMapping::Cartesian {
+            rect: square(),
+            b_scan: 8..16,
+            a_scan_samples: 100,
+            rotation: 0.0,
+        };
+        // The B scan starts at the top and turns counterclockwise
+        assert_eq!(cartesian.to_screen(8.0, 50.0), Some(pos2(50.0, 25.0)));
+        let left = cartesian.to_screen(10.0, 100.0).unwrap();
+        assert!((left - pos2(0.0, 50.0)).length() < 1e-4);
+        assert_eq!(cartesian.to_screen(16.0, 50.0), None);
+
+        let side = ScanViewMapping::Side {
+            rect: square(),
+            b_scan_segmentation: &b_scans,
+            a_scan_samples: 100,
+            rotation: 0.25,
+            neighborhood: 0.1,
+            current_b_scan: 0,
+        };
+        // The rotation is above the center, the opposite side below
+        assert_eq!(side.to_screen(10.0, 50.0), Some(pos2(75.0, 25.0)));
+        assert_eq!(side.to_screen(14.0, 50.0), Some(pos2(75.0, 75.0)));
+        assert_eq!(side.to_screen(8.0, 50.0), None);
+        assert_eq!(side.to_screen(16.0, 50.0), None);
+    }
+}
@@ -0,0 +1,280 @@
+//! Level of detail of the segmentation overlays. Their lines are built from
+//! every A scan in view, reduced to what can be seen at the current zoom and
+//! cached, until the view or the data changes.
+
+use egui::{Pos2, Rect};
+
+/// Maximum distance of removed points from the simplified line, in pixels.
+pub const TOLERANCE: f32 = 0.25;
+
+/// Reduces points with increasing x to at most four per column of
+/// `column_width`: the first, the last, the highest and the lowest. The line
+/// through them covers the same pixels as the line through all points.
+pub fn column_extremes(points: impl IntoIterator<Item = Pos2>, column_width: f32) -> Vec<Pos2> {
+    let mut result = Vec::new();
+    // The column and its first, highest, lowest and last point, with their
+    // index
+    let mut column: Option<(i64, [(usize, Pos2); 4])> = None;
+
+    for (i, point) in points.into_iter().enumerate() {
+        let index = (point.x / column_width).floor() as i64;
+
+        match &mut column {
+            Some((current, [_, min, max, last])) if *current == index => {
+                if point.y < min.1.y {
+                    *min = (i, point);
+                }
+                if point.y > max.1.y {
+                    *max = (i, point);
+                }
+                *last = (i, point);
+            }
+            _ => {
+                if let Some((_, extremes)) = column.take() {
+                    push_in_order(extremes, &mut result);
+                }
+                column = Some((index, [(i, point); 4]));
+            }
+        }
+    }
+
+    if let Some((_, extremes)) = column {
+        push_in_order(extremes, &mut result);
+    }
+
+    result
+}
+
+/// Pushes the points in the order they were drawn, each once.
+fn push_in_order(mut points: [(usize, Pos2); 4], result: &mut Vec<Pos2>) {
+    points.sort_by_key(|(i, _)| *i);
+
+    let mut previous = None;
+    for (i, point) in points {
+        if previous != Some(i) {
+            result.push(point);
+            previous = Some(i);
+        }
+    }
+}
+
+/// Removes points closer than `tolerance` to the line through the remaining
+/// ones, using the Ramer–Douglas–Peucker algorithm.
+pub fn simplify(points: &[Pos2], tolerance: f32) -> Vec<Pos2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+
+    // Without recursion, long lines do not overflow the stack
+    let mut ranges = vec![(0, points.len() - 1)];
+
+    while let Some((start, end)) = ranges.pop() {
+        let (a, b) = (points[start], points[end]);
+
+        let farthest = (start + 1..end)
+            .map(|i| (i, distance_to_segment(points[i], a, b)))
+            .max_by(|(_, d1), (_, d2)| d1.total_cmp(d2));
+
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance {
+                keep[i] = true;
+                ranges.push((start, i));
+                ranges.push((i, end));
+            }
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(point, keep)| keep.then_some(*point))
+        .collect()
+}
+
+fn distance_to_segment(point: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let length_sq = ab.length_sq();
+    if length_sq == 0.0 {
+        return point.distance(a);
+    }
+
+    let t = ((point - a).dot(ab) / length_sq).clamp(0.0, 1.0);
+    point.distance(a + t * ab)
+}
+
+// MARK: OverlayLines
+
+/// What a line depends on besides the data: The rect it is drawn in, the
+/// visible part of the scan and further parameters of the view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineKey {
+    pub rect: Rect,
+    pub viewport: Rect,
+    pub params: [f32; 3],
+}
+
+/// Simplified line, rebuilt only when its [LineKey] changes.
+#[derive(Debug, Default)]
+struct CachedLine {
+    key: Option<LineKey>,
+    points: Vec<Pos2>,
+}
+
+/// The overlay lines of one M scan view.
+#[derive(Debug, Default)]
+pub struct OverlayLines {
+    polar: CachedLine,
+    cartesian: CachedLine,
+    side: [CachedLine; 2],
+    /// Versions of the overlay data, the lines were built from.
+    generation: (u64, u64),
+    pixels_per_point: f32,
+    /// Points of the lines painted since [Self::begin_frame], for debugging.
+    painted: usize,
+}
+
+/// A line of [OverlayLines].
+#[derive(Debug, Clone, Copy)]
+pub enum Line {
+    Polar,
+    Cartesian,
+    /// The upper and the lower curve of the side view.
+    Side(usize),
+}
+
+impl OverlayLines {
+    /// Drops the cached lines, when the overlay data changed, see
+    /// [super::Overlay::generation].
+    pub fn begin_frame(&mut self, generation: (u64, u64), pixels_per_point: f32) {
+        if self.generation != generation || self.pixels_per_point != pixels_per_point {
+            *self = Self {
+                generation,
+                pixels_per_point,
+                ..Self::default()
+            };
+        }
+        self.painted = 0;
+    }
+
+    /// Points of the overlay lines painted in this frame.
+    pub fn painted(&self) -> usize {
+        self.painted
+    }
+
+    /// The simplified `line`. `build` returns its points and is only called,
+    /// when `key` changed.
+    pub fn get(
+        &mut self,
+        line: Line,
+        key: LineKey,
+        build: impl FnOnce() -> Vec<Pos2>,
+    ) -> Vec<Pos2> {
+        let tolerance = TOLERANCE / self.pixels_per_point.max(f32::EPSILON);
+
+        let cached = match line {
+            Line::Polar => &mut self.polar,
+            Line::Cartesian => &mut self.cartesian,
+            Line::Side(index) => &mut self.side[index.min(1)],
+        };
+
+        if cached.key != Some(key) {
+            cached.key = Some(key);
+            cached.points = simplify(&build(), tolerance);
+        }
+
+        self.painted += cached.points.len();
+        cached.points.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use egui::pos2;
+
+    use super::*;
+
+    #[test]
+    fn simplify_within_tolerance() {
+        // A straight line collapses to its ends
+        let line = (0..100)
+            .map(|i| pos2(i as f32, 2.0 * i as f32))
+            .collect::<Vec<_>>();
+        assert_eq!(simplify(&line, 0.1), vec![line[0], line[99]]);
+
+        // Every removed point stays close to the simplified line, spikes
+        // are kept
+        let mut wave = (0..1000)
+            .map(|i| pos2(i as f32 * 0.1, (i as f32 * 0.02).sin() * 20.0))
+            .collect::<Vec<_>>();
+        wave[500].y += 5.0;
+
+        let simplified = simplify(&wave, 0.25);
+        assert!(simplified.len() < wave.len() / 4);
+        assert!(simplified.contains(&wave[500]));
+
+        for point in &wave {
+            let distance = simplified
+                .windows(2)
+                .map(|segment| distance_to_segment(*point, segment[0], segment[1]))
+                .fold(f32::INFINITY, f32::min);
+            assert!(distance <= 0.25, "{:?} is {} away", point, distance);
+        }
+    }
+
+    #[test]
+    fn extremes_of_columns() {
+        // Ten points per column, alternating up and down
+        let points = (0..100)
+            .map(|i| pos2(i as f32 * 0.1, if i % 2 == 0 { 0.0 } else { 10.0 }))
+            .collect::<Vec<_>>();
+
+        let reduced = column_extremes(points.iter().copied(), 1.0);
+        assert!(reduced.len() <= 4 * 10);
+        for column in 0..10 {
+            let in_column = reduced
+                .iter()
+                .filter(|p| (p.x as usize) == column)
+                .collect::<Vec<_>>();
+            assert!(in_column.iter().any(|p| p.y == 0.0));
+            assert!(in_column.iter().any(|p| p.y == 10.0));
+        }
+        assert_eq!(reduced.first(), points.first());
+        assert_eq!(reduced.last(), points.last());
+    }
+
+    #[test]
+    fn rebuilt_on_change() {
+        let mut lines = OverlayLines::default();
+        let key = LineKey {
+            rect: Rect::from_min_size(Pos2::ZERO, egui::vec2(100.0, 100.0)),
+            viewport: Rect::from_min_size(Pos2::ZERO, egui::vec2(100.0, 100.0)),
+            params: [0.0; 3],
+        };
+        let line = || vec![pos2(0.0, 0.0), pos2(1.0, 1.0), pos2(2.0, 0.0)];
+
+        let mut builds = 0;
+        for generation in [0, 0, 1] {
+            lines.begin_frame((generation, 0), 1.0);
+            lines.get(Line::Polar, key, || {
+                builds += 1;
+                line()
+            });
+        }
+        assert_eq!(builds, 2);
+        assert_eq!(lines.painted(), 3);
+
+        let moved = LineKey {
+            params: [1.0, 0.0, 0.0],
+            ..key
+        };
+        lines.get(Line::Polar, moved, || {
+            builds += 1;
+            line()
+        });
+        assert_eq!(builds, 3);
+    }
+}
@@ -0,0 +1,182 @@
+//! Downsampled copies of the M scan textures, so the polar view does not
+//! alias when zoomed out.
+//!
+//! The textures hold integers, which cannot be filtered, so every M scan
+//! texture gets [LEVELS] additional textures, each pooling [LEVEL_FACTOR]
+//! consecutive A scans of the previous level. Samples of an A scan are not
+//! pooled. Each level has its own bind group with the same number of textures
+//! as the full resolution one, so the [super::MAX_TEXTURES] budget applies to
+//! every level alike. The polar view binds the level matching its zoom.
+
+use crate::settings::MScanPooling;
+
+/// Number of downsampled levels in addition to the full resolution textures.
+pub const LEVELS: usize = 3;
+
+/// Number of A scans of a level pooled into one texel of the next level. The
+/// shaders assume 4.
+pub const LEVEL_FACTOR: u32 = 4;
+
+/// Number of A scans a texture of `capacity` A scans holds at `level`.
+pub fn level_capacity(capacity: u32, level: usize) -> u32 {
+    capacity.div_ceil(LEVEL_FACTOR.pow(level as u32))
+}
+
+/// Level to draw, when `a_scans_per_pixel` A scans fall onto one screen
+/// pixel. Chooses the coarsest level, whose texels are not smaller than a
+/// pixel, so the view looks unchanged at 1:1 and closer.
+pub fn select_level(a_scans_per_pixel: f32) -> usize {
+    (1..=LEVELS)
+        .take_while(|&level| a_scans_per_pixel >= LEVEL_FACTOR.pow(level as u32) as f32)
+        .last()
+        .unwrap_or(0)
+}
+
+// MARK: Pyramid
+
+/// Renders the levels of M scan textures.
+pub struct Pyramid {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl Pyramid {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("pyramid.wgsl"));
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("MScan Pyramid Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Uint,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("MScan Pyramid Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::FRAGMENT,
+                range: 0..8,
+            }],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("MScan Pyramid Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            multisample: wgpu::MultisampleState::default(),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::TextureFormat::R16Uint.into())],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            depth_stencil: None,
+            multiview: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+        }
+    }
+
+    /// Renders `target` from `source`, of which the first `source_rows` rows
+    /// hold data.
+    pub fn downsample(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        source_rows: u32,
+        target: &wgpu::TextureView,
+        pooling: MScanPooling,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("MScan Pyramid Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(source),
+            }],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("MScan Pyramid Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        let pooling = match pooling {
+            MScanPooling::Maximum => 0u32,
+            MScanPooling::Mean => 1,
+        };
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.set_push_constants(
+            wgpu::ShaderStages::FRAGMENT,
+            0,
+            bytemuck::cast_slice(&[source_rows, pooling]),
+        );
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn level_selection() {
+        // Unchanged at 1:1 and when zoomed in
+        assert_eq!(select_level(0.25), 0);
+        assert_eq!(select_level(1.0), 0);
+        assert_eq!(select_level(3.9), 0);
+        assert_eq!(select_level(f32::NAN), 0);
+
+        assert_eq!(select_level(4.0), 1);
+        assert_eq!(select_level(20.0), 2);
+        assert_eq!(select_level(64.0), 3);
+        assert_eq!(select_level(500_000.0), LEVELS);
+    }
+
+    #[test]
+    fn memory_overhead() {
+        for capacity in [256, 1000, 4096, 6001, 12000] {
+            let levels: u32 = (1..=LEVELS).map(|l| level_capacity(capacity, l)).sum();
+            let overhead = levels as f32 / capacity as f32;
+            assert!(overhead < 0.35, "{} for capacity {}", overhead, capacity);
+        }
+
+        assert_eq!(level_capacity(1000, 0), 1000);
+        assert_eq!(level_capacity(1000, 1), 250);
+        assert_eq!(level_capacity(1000, 3), 16);
+        assert_eq!(level_capacity(1, 3), 1);
+    }
+}
@@ -0,0 +1,41 @@
+// Renders a level of the texture pyramid from the previous level, see
+// pyramid.rs. Every texel pools 4 consecutive A scans of the source.
+
+@group(0) @binding(0)
+var source: texture_2d<u32>;
+
+struct Constants {
+    // Number of rows (A scans) of the source, that hold data
+    source_rows: u32,
+    // 0: Maximum, 1: Mean
+    pooling: u32,
+};
+
+var<push_constant> consts: Constants;
+
+@vertex
+fn vs_main(@builtin(vertex_index) idx: u32) -> @builtin(position) vec4<f32> {
+    // A triangle covering the whole target
+    let uv = vec2<f32>(f32((idx << 1u) & 2u), f32(idx & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<u32> {
+    let sample_idx = u32(position.x);
+    let start = u32(position.y) * 4u;
+    let end = min(start + 4u, consts.source_rows);
+
+    var max_value = 0u;
+    var sum = 0u;
+    for (var row = start; row < end; row++) {
+        let value = textureLoad(source, vec2<u32>(sample_idx, row), 0).r;
+        max_value = max(max_value, value);
+        sum += value;
+    }
+
+    if (consts.pooling == 0u) {
+        return vec4<u32>(max_value, 0u, 0u, 0u);
+    }
+    return vec4<u32>(sum / max(end - start, 1u), 0u, 0u, 0u);
+}
@@ -16,6 +16,16 @@ struct PolarConstants {
     tex_count: u32,
     map_idx: u32,
     a_scan_count: u32,
+    // Pyramid level bound to the texture array, see pyramid.rs
+    level: u32,
+    // Number of A scans of the full resolution textures
+    capacity: u32,
+    samples: u32,
+    // Transform and values stretched over the color map, see display.rs
+    transform: u32,
+    dynamic_range: f32,
+    range_min: f32,
+    range_max: f32,
 };
 
 struct CartesianConstants {
@@ -24,6 +34,11 @@ struct CartesianConstants {
     map_idx: u32,
     b_scan_start: u32,
     b_scan_end: u32,
+    samples: u32,
+    transform: u32,
+    dynamic_range: f32,
+    range_min: f32,
+    range_max: f32,
 };
 
 struct SideConstants {
@@ -31,8 +46,21 @@ struct SideConstants {
     tex_count: u32,
     map_idx: u32,
     view_rot: f32,
+    // Half width of the averaged neighborhood, as fraction of a B scan
+    neighborhood: f32,
+    samples: u32,
+    transform: u32,
+    dynamic_range: f32,
+    range_min: f32,
+    range_max: f32,
 };
 
+const MAX_NEIGHBORHOOD_SAMPLES: i32 = 32;
+
+// See display.rs
+const LOG_EPSILON: f32 = 1e-7;
+const LOG_GAIN: f32 = 1000.0;
+
 var<push_constant> vert_consts: VertexConstants;
 var<push_constant> polar_consts: PolarConstants;
 var<push_constant> cart_consts: CartesianConstants;
@@ -78,16 +106,63 @@ fn polar_fs_main(in: VertexOut) -> @location(0) vec4<f32>{
     if (polar_consts.tex_count == 0) {
         discard;
     }
-    let tex_dim = textureDimensions(m_scan_texture_array[0]);
-
-    let pixel = load_m_scan(
+    let pixel = load_m_scan_level(
         u32(in.uv.x * f32(polar_consts.a_scan_count)),
-        u32(in.uv.y * f32(tex_dim.x)),
-        polar_consts.tex_count,
-        tex_dim
+        u32(in.uv.y * f32(polar_consts.samples)),
+        polar_consts.samples,
+        polar_consts.level,
+        polar_consts.capacity,
+        polar_consts.tex_count
     );
 
-    return sample_color_map(pixel, polar_consts.map_idx);
+    return sample_color_map(
+        apply_range(
+            apply_transform(pixel, polar_consts.transform, polar_consts.dynamic_range),
+            polar_consts.range_min,
+            polar_consts.range_max
+        ),
+        polar_consts.map_idx
+    );
+}
+
+// Like polar_fs_main, but every B scan of b_scan_segments gets the same width
+@fragment
+fn polar_uniform_fs_main(in: VertexOut) -> @location(0) vec4<f32>{
+    if (polar_consts.tex_count == 0) {
+        discard;
+    }
+    let pixel = load_m_scan_level(
+        u32(uniform_a_scan(in.uv.x)),
+        u32(in.uv.y * f32(polar_consts.samples)),
+        polar_consts.samples,
+        polar_consts.level,
+        polar_consts.capacity,
+        polar_consts.tex_count
+    );
+
+    return sample_color_map(
+        apply_range(
+            apply_transform(pixel, polar_consts.transform, polar_consts.dynamic_range),
+            polar_consts.range_min,
+            polar_consts.range_max
+        ),
+        polar_consts.map_idx
+    );
+}
+
+/// The A scan at horizontal position x from 0 to 1, when every B scan of
+/// b_scan_segments has the same width. Keep in sync with uniform_a_scan in
+/// uis.rs.
+fn uniform_a_scan(x: f32) -> f32 {
+    let count = arrayLength(&b_scan_segments) - 1u;
+
+    let pos = clamp(x, 0.0, 1.0) * f32(count);
+    let b_scan_idx = min(u32(floor(pos)), count - 1u);
+
+    let b_scan_start = f32(b_scan_segments[b_scan_idx]);
+    let b_scan_end = f32(b_scan_segments[b_scan_idx + 1u]);
+
+    return b_scan_start + (pos - f32(b_scan_idx)) * (b_scan_end - b_scan_start);
 }
 
 // Concept:
@@ -119,12 +194,20 @@ fn cartesian_fs_main(in: VertexOut) -> @location(0) vec4<f32>{
 
     let pixel = load_m_scan(
         cart_consts.b_scan_start + u32(alpha * f32(cart_consts.b_scan_end - cart_consts.b_scan_start)),
-        u32(distance * f32(tex_dim.x)),
+        u32(distance * f32(cart_consts.samples)),
+        cart_consts.samples,
         cart_consts.tex_count,
         tex_dim
     );
 
-    return sample_color_map(pixel, cart_consts.map_idx);
+    return sample_color_map(
+        apply_range(
+            apply_transform(pixel, cart_consts.transform, cart_consts.dynamic_range),
+            cart_consts.range_min,
+            cart_consts.range_max
+        ),
+        cart_consts.map_idx
+    );
 }
 
 @fragment
@@ -143,32 +226,97 @@ fn side_fs_main(in: VertexOut) -> @location(0) vec4<f32>{
 
     let a_scan_idx = u32(floor(rot * f32(b_scan_len - 1)));
 
-    let tex_row = u32(floor(f32(tex_dim.x) * abs(in.uv.y - 0.5) * 2.0));
+    let tex_row = u32(floor(f32(side_consts.samples) * abs(in.uv.y - 0.5) * 2.0));
+
+    // Average the A scans in the neighborhood, wrapping around inside the
+    // B scan. Wide neighborhoods are subsampled.
+    let half_width = i32(round(side_consts.neighborhood * f32(b_scan_len)));
+    let stride = max(1, (2 * half_width + 1) / MAX_NEIGHBORHOOD_SAMPLES);
+
+    var sum = 0.0;
+    var count = 0.0;
+    for (var offset = -half_width; offset <= half_width; offset += stride) {
+        let idx = (i32(a_scan_idx) + offset + i32(b_scan_len)) % i32(b_scan_len);
+
+        sum += load_m_scan(
+            b_scan_start + u32(idx),
+            tex_row,
+            side_consts.samples,
+            side_consts.tex_count,
+            tex_dim
+        );
+        count += 1.0;
+    }
 
-    let pixel = load_m_scan(
-        b_scan_start + a_scan_idx,
-        tex_row,
-        side_consts.tex_count,
-        tex_dim
+    return sample_color_map(
+        apply_range(
+            apply_transform(sum / count, side_consts.transform, side_consts.dynamic_range),
+            side_consts.range_min,
+            side_consts.range_max
+        ),
+        side_consts.map_idx
     );
-
-    return sample_color_map(pixel, side_consts.map_idx);
 }
 
 /// Load a sample from the m-scan texture array.
-fn load_m_scan(a_scan_idx: u32, sample_idx: u32, tex_count: u32, tex_dim: vec2<u32>) -> f32 {
+fn load_m_scan(a_scan_idx: u32, sample_idx: u32, samples: u32, tex_count: u32, tex_dim: vec2<u32>) -> f32 {
     let tex_idx = a_scan_idx / tex_dim.y;
     let tex_column = a_scan_idx % tex_dim.y;
 
-    if (tex_idx >= tex_count) {
+    return load_tile(tex_idx, tex_column, sample_idx, samples, tex_count);
+}
+
+/// Load a sample from a level of the m-scan texture pyramid, where every
+/// texel pools 4^level A scans of a full resolution texture.
+fn load_m_scan_level(a_scan_idx: u32, sample_idx: u32, samples: u32, level: u32, capacity: u32, tex_count: u32) -> f32 {
+    let tex_idx = a_scan_idx / capacity;
+    let tex_column = (a_scan_idx % capacity) >> (2u * level);
+
+    return load_tile(tex_idx, tex_column, sample_idx, samples, tex_count);
+}
+
+/// Load a sample from the row tile of texture tex_idx holding it. Every
+/// texture of A scans is split into tiles of the width of the first one,
+/// which follow each other in the texture array. See TilePlan in m_scan.rs.
+fn load_tile(tex_idx: u32, tex_column: u32, sample_idx: u32, samples: u32, tex_count: u32) -> f32 {
+    let tile_samples = textureDimensions(m_scan_texture_array[0]).x;
+    let tiles = (samples + tile_samples - 1u) / tile_samples;
+    let sample = min(sample_idx, samples - 1u);
+
+    let idx = tex_idx * tiles + sample / tile_samples;
+    if (idx >= tex_count) {
         discard;
     }
 
-    let pixel = textureLoad(m_scan_texture_array[tex_idx], vec2<u32>(sample_idx, tex_column), 0);
+    let pixel = textureLoad(m_scan_texture_array[idx], vec2<u32>(sample % tile_samples, tex_column), 0);
 
     return f32(pixel.r) / 65535.0;
 }
 
+/// Transform a value from 0 to 1 linearly (0), logarithmically (1) or to
+/// decibels of dynamic_range below the maximum (2). Keep in sync with
+/// DisplayTransform::apply in display.rs.
+fn apply_transform(value: f32, transform: u32, dynamic_range: f32) -> f32 {
+    let v = max(value, 0.0);
+    switch transform {
+        case 1u: {
+            return log(1.0 + LOG_GAIN * v) / log(1.0 + LOG_GAIN);
+        }
+        case 2u: {
+            let db = 10.0 * log2(max(v, LOG_EPSILON)) / log2(10.0);
+            return clamp(1.0 + db / max(dynamic_range, 1.0), 0.0, 1.0);
+        }
+        default: {
+            return v;
+        }
+    }
+}
+
+/// Stretch the values from range_min to range_max over 0 to 1.
+fn apply_range(value: f32, range_min: f32, range_max: f32) -> f32 {
+    return (value - range_min) / max(range_max - range_min, 1e-6);
+}
+
 fn sample_color_map(value: f32, map_idx: u32) -> vec4<f32> {
     let dims = textureDimensions(color_maps);
 
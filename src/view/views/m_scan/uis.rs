@@ -1,114 +1,359 @@
-use std::sync::Arc;
+use std::{ops::Range, sync::Arc};
 
 use egui::*;
-use nalgebra::Vector2;
 
 use crate::gui::widgets::PanZoomRect;
 
 use super::{
+    super::perf::Timing,
+    display::ColorMapping,
     gpu::{CartesianViewPaintCallback, PolarViewPaintCallback, SideViewPaintCallback},
+    overlay::{Overlays, ScanOverlay, ScanViewMapping},
+    polyline, pyramid,
     types::BScanDiameter,
     TexturesState,
 };
 
+/// How the polar view maps the M scan onto the available space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AspectMode {
+    /// Fills the available space, zooming only horizontally.
+    #[default]
+    Stretch,
+    /// Preserves the aspect ratio of A scan count to A scan samples.
+    Fit,
+    /// One texel per screen pixel at zoom 1.
+    Actual,
+}
+
+impl AspectMode {
+    pub const ALL: [Self; 3] = [Self::Stretch, Self::Fit, Self::Actual];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Stretch => "Stretch",
+            Self::Fit => "Fit",
+            Self::Actual => "1:1",
+        }
+    }
+
+    /// Size of the scan at zoom 1, [None] when it fills `available`.
+    fn content_size(
+        &self,
+        available: Vec2,
+        a_scan_count: usize,
+        a_scan_samples: usize,
+        pixels_per_point: f32,
+    ) -> Option<Vec2> {
+        let size = vec2(a_scan_count as f32, a_scan_samples as f32);
+        if size.min_elem() <= 0.0 {
+            return None;
+        }
+
+        match self {
+            Self::Stretch => None,
+            Self::Fit => Some(size * (available / size).min_elem()),
+            Self::Actual => Some(size / pixels_per_point),
+        }
+    }
+}
+
+/// How the polar view spaces the B scans horizontally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BScanSpacing {
+    /// Every A scan has the same width.
+    #[default]
+    Native,
+    /// Every B scan has the same width, no matter how many A scans it has.
+    Uniform,
+}
+
+impl BScanSpacing {
+    pub const ALL: [Self; 2] = [Self::Native, Self::Uniform];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Native => "Native spacing",
+            Self::Uniform => "Uniform B scans",
+        }
+    }
+}
+
+/// Horizontal position from 0 to 1 of `a_scan`, when every B scan of
+/// `b_scans` has the same width. A scans outside of the B scans are clamped.
+/// `b_scans` needs at least two boundaries.
+fn uniform_x(b_scans: &[usize], a_scan: f32) -> f32 {
+    let count = b_scans.len() - 1;
+    let a_scan = a_scan.clamp(b_scans[0] as f32, b_scans[count] as f32);
+
+    // The last B scan starting at or before the A scan
+    let b_scan = b_scans
+        .partition_point(|&start| start as f32 <= a_scan)
+        .saturating_sub(1)
+        .min(count - 1);
+
+    let start = b_scans[b_scan] as f32;
+    let end = b_scans[b_scan + 1] as f32;
+    let t = match end > start {
+        true => (a_scan - start) / (end - start),
+        false => 0.0,
+    };
+
+    (b_scan as f32 + t) / count as f32
+}
+
+/// The A scan at horizontal position `x` from 0 to 1, the inverse of
+/// [uniform_x]. The same as `uniform_a_scan` in shader.wgsl, which draws the
+/// polar view with [BScanSpacing::Uniform].
+fn uniform_a_scan(b_scans: &[usize], x: f32) -> f32 {
+    let count = b_scans.len() - 1;
+
+    let pos = x.clamp(0.0, 1.0) * count as f32;
+    let b_scan = (pos.floor() as usize).min(count - 1);
+
+    let start = b_scans[b_scan] as f32;
+    let end = b_scans[b_scan + 1] as f32;
+
+    start + (pos - b_scan as f32) * (end - start)
+}
+
+/// Maps A scans and samples of the M scan to screen positions in the polar
+/// view. The paint callback and all overlays use it, so they stay registered
+/// in every [AspectMode] and [BScanSpacing].
+#[derive(Debug, Clone, Copy)]
+pub struct PolarMapping<'a> {
+    /// The whole scan in screen coordinates.
+    pub viewport: Rect,
+    pub a_scan_count: usize,
+    pub a_scan_samples: usize,
+    /// The B scan segmentation with [BScanSpacing::Uniform].
+    pub uniform_b_scans: Option<&'a [usize]>,
+}
+
+impl PolarMapping<'_> {
+    pub fn x(&self, a_scan: f32) -> f32 {
+        let x = match self.uniform_b_scans {
+            Some(b_scans) => uniform_x(b_scans, a_scan),
+            None => a_scan / self.a_scan_count as f32,
+        };
+        x * self.viewport.width() + self.viewport.min.x
+    }
+
+    pub fn y(&self, sample: f32) -> f32 {
+        sample / self.a_scan_samples as f32 * self.viewport.height() + self.viewport.min.y
+    }
+
+    /// The A scan at screen position `x`, outside the scan or with degenerate
+    /// dimensions [None].
+    fn a_scan_at(&self, x: f32) -> Option<usize> {
+        let x = (x - self.viewport.min.x) / self.viewport.width();
+        if !(0.0..=1.0).contains(&x) || self.a_scan_count == 0 {
+            return None;
+        }
+
+        let a_scan = match self.uniform_b_scans {
+            Some(b_scans) => uniform_a_scan(b_scans, x) as usize,
+            None => (x * (self.a_scan_count - 1) as f32) as usize,
+        };
+        Some(a_scan.min(self.a_scan_count - 1))
+    }
+
+    /// The viewport in clip space of the paint callback covering `rect`.
+    /// Clip space points upwards, while sample 0 is at the top.
+    fn gpu_rect(&self, rect: Rect) -> Rect {
+        let min = (self.viewport.min - rect.min) / rect.size();
+        let max = (self.viewport.max - rect.min) / rect.size();
+
+        Rect::from_min_max(
+            pos2(min.x * 2.0 - 1.0, 1.0 - max.y * 2.0),
+            pos2(max.x * 2.0 - 1.0, 1.0 - min.y * 2.0),
+        )
+    }
+
+    /// Screen pixels per texel horizontally.
+    fn scale(&self, pixels_per_point: f32) -> f32 {
+        self.viewport.width() * pixels_per_point / self.a_scan_count as f32
+    }
+}
+
+/// Draws `overlays`, highlights the B scan of `highlight` with the given
+/// opacity, marks the bounds of `live_region` and dims the A scans outside of
+/// `selection`, see [crate::pipeline::range]. With [BScanSpacing::Uniform], `b_scan_bind_group`
+/// holds `b_scan_segmentation` on the GPU. Returns the zoom as screen pixels per texel, see
+/// [AspectMode::Actual], and the visible A scans.
+#[allow(clippy::too_many_arguments)]
 pub fn polar_m_scan_ui(
     ui: &mut egui::Ui,
     textures_state: &TexturesState,
     texture_bind_group: Arc<wgpu::BindGroup>,
     b_scan_segmentation: Option<&[usize]>,
-    m_scan_segmentation: Option<&[usize]>,
-    map_idx: u32,
-) -> egui::Response {
-    PanZoomRect::new()
-        .zoom_y(false)
-        .min_zoom(1.0)
-        .show(ui, |ui, viewport, n_viewport| {
-            let response = ui.allocate_rect(ui.max_rect(), Sense::hover());
-            let rect = response.rect;
-
-            let gpu_viewport = Rect::from_min_max(
-                n_viewport.min * 2.0 - Vec2::splat(1.0),
-                n_viewport.max * 2.0 - Vec2::splat(1.0),
-            );
-
-            ui.painter()
-                .add(eframe::egui_wgpu::Callback::new_paint_callback(
-                    response.rect,
-                    PolarViewPaintCallback {
-                        texture_bind_group,
-                        texture_count: textures_state.textures.len(),
-                        a_scan_count: textures_state.a_scan_count,
-                        rect: gpu_viewport,
-                        map_idx,
-                    },
-                ));
-
-            if let Some(b_scan_segmentation) = b_scan_segmentation {
-                for b_scan in b_scan_segmentation {
-                    let x = (*b_scan as f32) / (textures_state.a_scan_count as f32);
-                    let x = x * viewport.width() + viewport.min.x;
-
-                    ui.painter().line_segment(
-                        [pos2(x, viewport.min.y), pos2(x, viewport.max.y)],
-                        Stroke::new(1.0, egui::Color32::BLUE),
-                    );
-                }
-            }
-
-            if let Some(m_scan_segmentation) = m_scan_segmentation {
-                let points = (rect.left() as usize..=rect.right() as usize)
-                    .filter_map(|global_x| {
-                        let viewport_x = (global_x as f32 - viewport.min.x) / viewport.width();
-
-                        if viewport_x < 0.0 {
-                            return None;
-                        }
+    overlays: &Overlays,
+    highlight: Option<(usize, f32)>,
+    live_region: Option<Range<usize>>,
+    selection: Option<Range<usize>>,
+    aspect_mode: AspectMode,
+    b_scan_spacing: BScanSpacing,
+    b_scan_bind_group: Option<Arc<wgpu::BindGroup>>,
+    color_mapping: ColorMapping,
+    timing: Option<Timing>,
+) -> InnerResponse<(f32, Option<Range<usize>>)> {
+    let pixels_per_point = ui.ctx().pixels_per_point();
+    let available = ui.available_size();
+
+    let content_size = aspect_mode.content_size(
+        available,
+        textures_state.a_scan_count,
+        textures_state.a_scan_samples,
+        pixels_per_point,
+    );
 
-                        let scan_idx =
-                            (viewport_x * (textures_state.a_scan_count - 1) as f32) as usize;
+    let pan_zoom = match (aspect_mode, content_size) {
+        (AspectMode::Stretch, _) | (_, None) => PanZoomRect::new().zoom_y(false).min_zoom(1.0),
+        (AspectMode::Fit, _) => PanZoomRect::new().min_zoom(1.0),
+        // Allow zooming out until the scan fits
+        (AspectMode::Actual, Some(size)) => PanZoomRect::new()
+            .min_zoom((available / size).min_elem().min(1.0))
+            .max_zoom(32.0),
+    };
+
+    // Every mode keeps its own pan and zoom, so 1:1 starts at actual size
+    ui.push_id(aspect_mode, |ui| {
+        pan_zoom
+            .content_size(content_size)
+            .show(ui, |ui, viewport, _| {
+                let response = ui.allocate_rect(ui.max_rect(), Sense::hover());
+                let rect = response.rect;
+
+                // Native spacing, until the segmentation is uploaded
+                let uniform = match (b_scan_spacing, b_scan_segmentation, &b_scan_bind_group) {
+                    (BScanSpacing::Uniform, Some(b_scans), Some(bind_group))
+                        if b_scans.len() > 1 =>
+                    {
+                        Some((b_scans, bind_group.clone()))
+                    }
+                    _ => None,
+                };
+
+                let mapping = PolarMapping {
+                    viewport,
+                    a_scan_count: textures_state.a_scan_count,
+                    a_scan_samples: textures_state.a_scan_samples,
+                    uniform_b_scans: uniform.as_ref().map(|(b_scans, _)| *b_scans),
+                };
+
+                // Zoomed out, the downsampled levels avoid aliasing
+                let level = pyramid::select_level(1.0 / mapping.scale(pixels_per_point))
+                    .min(textures_state.level_bind_groups.len());
+                let texture_bind_group = match level {
+                    0 => texture_bind_group,
+                    level => textures_state.level_bind_groups[level - 1].clone(),
+                };
 
-                        if scan_idx >= m_scan_segmentation.len() {
-                            return None;
+                ui.painter()
+                    .add(eframe::egui_wgpu::Callback::new_paint_callback(
+                        response.rect,
+                        PolarViewPaintCallback {
+                            texture_bind_group,
+                            texture_count: textures_state.texture_count,
+                            a_scan_count: textures_state.a_scan_count,
+                            a_scan_samples: textures_state.a_scan_samples,
+                            rect: mapping.gpu_rect(rect),
+                            color_mapping,
+                            level,
+                            capacity: textures_state.capacity,
+                            b_scan_bind_group: uniform.map(|(_, bind_group)| bind_group),
+                            timing,
+                        },
+                    ));
+
+                if let Some(b_scan_segmentation) = b_scan_segmentation {
+                    if let Some((b_scan, opacity)) = highlight {
+                        if let (Some(&start), Some(&end)) = (
+                            b_scan_segmentation.get(b_scan),
+                            b_scan_segmentation.get(b_scan + 1),
+                        ) {
+                            ui.painter().rect_filled(
+                                Rect::from_x_y_ranges(
+                                    mapping.x(start as f32)..=mapping.x(end as f32),
+                                    viewport.y_range(),
+                                ),
+                                0.0,
+                                Color32::YELLOW.gamma_multiply(0.4 * opacity),
+                            );
                         }
+                    }
+                }
 
-                        let scan_idx = scan_idx.min(textures_state.a_scan_count - 1);
+                overlays.draw(ui.painter(), &ScanViewMapping::Polar { mapping, rect });
 
-                        let seg = m_scan_segmentation[scan_idx];
-                        if seg >= textures_state.a_scan_samples {
-                            return None;
-                        }
+                if let Some(live_region) = live_region {
+                    let stroke = Stroke::new(1.0, ui.visuals().weak_text_color());
 
-                        let y = seg as f32 / textures_state.a_scan_samples as f32;
-                        let y = y * viewport.height() + viewport.min.y;
+                    for a_scan in [live_region.start, live_region.end] {
+                        let x = mapping.x(a_scan as f32);
+                        ui.painter().extend(Shape::dashed_line(
+                            &[pos2(x, viewport.min.y), pos2(x, viewport.max.y)],
+                            stroke,
+                            4.0,
+                            4.0,
+                        ));
+                    }
+                }
 
-                        let x = (scan_idx as f32) / (textures_state.a_scan_count as f32);
-                        let x = x * viewport.width() + viewport.min.x;
+                if let Some(selection) = selection {
+                    let outside = [
+                        0..selection.start,
+                        selection.end..textures_state.a_scan_count,
+                    ];
+                    for a_scans in outside.into_iter().filter(|r| !r.is_empty()) {
+                        ui.painter().rect_filled(
+                            Rect::from_x_y_ranges(
+                                mapping.x(a_scans.start as f32)..=mapping.x(a_scans.end as f32),
+                                viewport.y_range(),
+                            ),
+                            0.0,
+                            Color32::from_black_alpha(160),
+                        );
+                    }
+                }
 
-                        Some(pos2(x as f32, y))
-                    })
-                    .collect::<Vec<_>>();
+                let visible = rect.intersect(viewport);
+                let visible_a_scans = match (
+                    mapping.a_scan_at(visible.left()),
+                    mapping.a_scan_at(visible.right()),
+                ) {
+                    (Some(start), Some(end)) => Some(start..end + 1),
+                    _ => None,
+                };
 
-                ui.painter()
-                    .add(Shape::line(points, Stroke::new(2.0, Color32::RED)));
-            }
-        })
-        .response
+                (mapping.scale(pixels_per_point), visible_a_scans)
+            })
+    })
+    .inner
 }
 
+/// Scrolls to `select_b_scan`, if given. Returns the shown B scan and the
+/// rotation of the side view, which is marked in the view.
+#[allow(clippy::too_many_arguments)]
 pub fn cartesian_m_scan_ui(
     ui: &mut egui::Ui,
     textures_state: &TexturesState,
     texture_bind_group: Arc<wgpu::BindGroup>,
     b_scan_segmentation: &[usize],
-    m_scan_segmentation: Option<&[usize]>,
-    diameters: Option<&[BScanDiameter]>,
-    map_idx: u32,
-) {
+    overlays: &Overlays,
+    color_mapping: ColorMapping,
+    select_b_scan: Option<usize>,
+    timing: Option<Timing>,
+) -> (usize, f32) {
     let (rect, response) = ui.allocate_exact_size(
         Vec2::splat(ui.available_height().min(ui.available_width())),
         Sense::hover(),
     );
 
+    if let Some(b_scan) = select_b_scan {
+        ui.data_mut(|d| d.insert_temp(ui.id().with("current_b_scan"), b_scan as isize));
+    }
+
     let current_b_scan = get_scroll_value::<true>(
         ui,
         "current_b_scan",
@@ -116,113 +361,148 @@ pub fn cartesian_m_scan_ui(
         b_scan_segmentation.len() as isize,
     );
 
-    ui.painter()
-        .add(eframe::egui_wgpu::Callback::new_paint_callback(
+    let current_rotation = ui
+        .data(|d| d.get_temp::<isize>(ui.id().with("current_rotation")))
+        .unwrap_or(0) as f32
+        / 100.0;
+
+    CartesianBScan {
+        textures_state,
+        texture_bind_group,
+        b_scan_segmentation,
+        b_scan: current_b_scan,
+        rotation: current_rotation,
+        color_mapping,
+        overlays,
+        timing,
+    }
+    .paint(ui.painter(), rect);
+
+    (current_b_scan, current_rotation)
+}
+
+/// One B scan in the cartesian view with all its overlays. The view, the
+/// exported animations and images paint it the same way.
+pub struct CartesianBScan<'a> {
+    pub textures_state: &'a TexturesState,
+    pub texture_bind_group: Arc<wgpu::BindGroup>,
+    pub b_scan_segmentation: &'a [usize],
+    /// Index of the B scan in [Self::b_scan_segmentation].
+    pub b_scan: usize,
+    /// Rotation shown in the side view, as fraction of a full turn.
+    pub rotation: f32,
+    pub color_mapping: ColorMapping,
+    /// Drawn on top of the scan.
+    pub overlays: &'a Overlays<'a>,
+    /// Measures the paint callback for the performance overlay.
+    pub timing: Option<Timing>,
+}
+
+impl CartesianBScan<'_> {
+    pub fn paint(self, painter: &Painter, rect: Rect) {
+        let b_scan =
+            self.b_scan_segmentation[self.b_scan]..self.b_scan_segmentation[self.b_scan + 1];
+        let a_scan_samples = self.textures_state.a_scan_samples;
+
+        painter.add(eframe::egui_wgpu::Callback::new_paint_callback(
             rect,
             CartesianViewPaintCallback {
-                texture_bind_group,
-                texture_count: textures_state.textures.len(),
-                b_scan_start: b_scan_segmentation[current_b_scan],
-                b_scan_end: b_scan_segmentation[current_b_scan + 1],
+                texture_bind_group: self.texture_bind_group,
+                texture_count: self.textures_state.texture_count,
+                b_scan_start: b_scan.start,
+                b_scan_end: b_scan.end,
+                a_scan_samples,
                 rect: Rect::from_min_max(Vec2::splat(-1.0).to_pos2(), Vec2::splat(1.0).to_pos2()),
-                map_idx,
+                color_mapping: self.color_mapping,
+                timing: self.timing,
             },
         ));
 
-    if let Some(m_scan_segmentation) = m_scan_segmentation {
-        let b_scan_start = b_scan_segmentation[current_b_scan];
-        let b_scan_end = b_scan_segmentation[current_b_scan + 1];
-        let b_scan_size = b_scan_end - b_scan_start;
-        let radius = rect.width() / 2.0;
-        let step_size = (b_scan_size / 200).max(1);
-
-        let points = (b_scan_start..b_scan_end)
-            .step_by(step_size)
-            .filter_map(|i| {
-                m_scan_segmentation.get(i).and_then(|&seg| {
-                    if seg >= textures_state.a_scan_samples {
-                        return None;
-                    }
-                    let alpha = (i - b_scan_start) as f32 / b_scan_size as f32;
-                    let alpha = alpha * std::f32::consts::TAU;
-
-                    let vec = seg as f32 / textures_state.a_scan_samples as f32
-                        * Vec2::angled(alpha)
-                        * radius;
-
-                    Some(rect.center() + vec2(-vec.y, -vec.x))
-                })
-            })
-            .collect::<Vec<_>>();
-
-        ui.painter()
-            .add(Shape::closed_line(points, Stroke::new(2.0, Color32::RED)));
+        self.overlays.draw(
+            painter,
+            &ScanViewMapping::Cartesian {
+                rect,
+                b_scan,
+                a_scan_samples,
+                rotation: self.rotation,
+            },
+        );
     }
+}
 
-    if let Some(diameter) = diameters.and_then(|d| d.get(current_b_scan)) {
-        let line_at_rot = |[p1, p2]: [Vector2<f32>; 2], diameter, stroke: Stroke| {
-            let factor = rect.width() / 2.0 / textures_state.a_scan_samples as f32;
-            let center = rect.center();
-            let p1 = center + vec2(-p1.y, -p1.x) * factor;
-            let p2 = center + vec2(-p2.y, -p2.x) * factor;
-            let text_pos = p1.lerp(p2, if p1.y < p2.y { 0.1 } else { 0.9 });
-            if p1.y < p2.y {
-                p1.lerp(p2, 0.1)
-            } else {
-                p2.lerp(p1, 0.1)
-            };
-
-            ui.painter().line_segment([p1, p2], stroke);
-
-            ui.painter().text(
-                text_pos,
-                Align2::LEFT_BOTTOM,
-                format!("{:.2} mm", diameter),
-                FontId::default(),
-                stroke.color,
-            );
+/// The whole M scan in the polar view, filling the rect, as exported by
+/// [super::image_export]. Uses native B scan spacing and draws the overlays
+/// like [polar_m_scan_ui].
+pub struct PolarMScan<'a> {
+    pub textures_state: &'a TexturesState,
+    pub texture_bind_group: Arc<wgpu::BindGroup>,
+    pub color_mapping: ColorMapping,
+    /// Drawn on top of the scan.
+    pub overlays: &'a Overlays<'a>,
+}
+
+impl PolarMScan<'_> {
+    pub fn paint(self, painter: &Painter, rect: Rect) {
+        let pixels_per_point = painter.ctx().pixels_per_point();
+        let mapping = PolarMapping {
+            viewport: rect,
+            a_scan_count: self.textures_state.a_scan_count,
+            a_scan_samples: self.textures_state.a_scan_samples,
+            uniform_b_scans: None,
         };
 
-        line_at_rot(
-            diameter.max_points,
-            diameter.max,
-            Stroke::new(2.0, Color32::GREEN),
-        );
-        line_at_rot(
-            diameter.min_points,
-            diameter.min,
-            Stroke::new(2.0, Color32::YELLOW),
-        );
-    }
+        let level = pyramid::select_level(1.0 / mapping.scale(pixels_per_point))
+            .min(self.textures_state.level_bind_groups.len());
+        let texture_bind_group = match level {
+            0 => self.texture_bind_group,
+            level => self.textures_state.level_bind_groups[level - 1].clone(),
+        };
 
-    // Draw current_rotation line
-    let current_rotation = ui
-        .data(|d| d.get_temp::<isize>(ui.id().with("current_rotation")))
-        .unwrap_or(0) as f32
-        / 100.0;
+        painter.add(eframe::egui_wgpu::Callback::new_paint_callback(
+            rect,
+            PolarViewPaintCallback {
+                texture_bind_group,
+                texture_count: self.textures_state.texture_count,
+                a_scan_count: self.textures_state.a_scan_count,
+                a_scan_samples: self.textures_state.a_scan_samples,
+                rect: mapping.gpu_rect(rect),
+                color_mapping: self.color_mapping,
+                level,
+                capacity: self.textures_state.capacity,
+                b_scan_bind_group: None,
+                timing: None,
+            },
+        ));
 
-    let center = rect.center();
-    let vec = rect.width() / 2.0 * Vec2::angled(current_rotation * std::f32::consts::TAU);
-    let vec = vec2(vec.y, vec.x);
+        self.overlays
+            .draw(painter, &ScanViewMapping::Polar { mapping, rect });
+    }
+}
 
-    ui.painter().line_segment(
-        [center + vec, center + 0.8 * vec],
-        Stroke::new(2.0, Color32::BLUE),
-    );
-    ui.painter().line_segment(
-        [center - vec, center - 0.8 * vec],
-        Stroke::new(2.0, Color32::BLUE),
-    );
+/// The diameter of the B scan starting at A scan `b_scan_start`. Diameters
+/// are ordered by their B scans, but do not need to start at the first one,
+/// so their index is not the one of the B scan.
+pub fn diameter_of_b_scan(
+    diameters: &[BScanDiameter],
+    b_scan_start: usize,
+) -> Option<&BScanDiameter> {
+    diameters
+        .binary_search_by_key(&b_scan_start, |d| d.b_scan_start)
+        .ok()
+        .map(|i| &diameters[i])
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn side_m_scan_ui(
     ui: &mut egui::Ui,
     textures_state: &TexturesState,
     texture_bind_group: Arc<wgpu::BindGroup>,
     b_scan_bind_group: Arc<wgpu::BindGroup>,
     b_scan_segmentation: &[usize],
-    m_scan_segmentation: Option<&[usize]>,
-    map_idx: u32,
+    overlays: &Overlays,
+    neighborhood: f32,
+    color_mapping: ColorMapping,
+    timing: Option<Timing>,
 ) -> egui::Response {
     let response = ui.allocate_response(ui.available_size(), Sense::hover());
 
@@ -235,97 +515,177 @@ pub fn side_m_scan_ui(
             SideViewPaintCallback {
                 b_scan_bind_group,
                 texture_bind_group,
-                texture_count: textures_state.textures.len(),
+                texture_count: textures_state.texture_count,
+                a_scan_samples: textures_state.a_scan_samples,
                 rect: Rect::from_min_max(Vec2::splat(-1.0).to_pos2(), Vec2::splat(1.0).to_pos2()),
                 view_rotation: current_rotation,
-                map_idx,
+                neighborhood,
+                color_mapping,
+                timing,
             },
         ));
 
-    if let Some(m_scan_segmentation) = m_scan_segmentation {
-        let rect = response.rect;
-        let points1 = b_scan_segmentation
-            .windows(2)
-            .enumerate()
-            .filter_map(|(i, seg)| match seg {
-                &[start, end] => {
-                    let scan_idx = start + ((end - start) as f32 * current_rotation) as usize;
-
-                    m_scan_segmentation.get(scan_idx).and_then(|&seg| {
-                        if seg >= textures_state.a_scan_samples {
-                            return None;
-                        }
+    let current_b_scan = ui
+        .data(|d| d.get_temp::<isize>(ui.id().with("current_b_scan")))
+        .unwrap_or(0) as usize;
+
+    overlays.draw(
+        ui.painter(),
+        &ScanViewMapping::Side {
+            rect: response.rect,
+            b_scan_segmentation,
+            a_scan_samples: textures_state.a_scan_samples,
+            rotation: current_rotation,
+            neighborhood,
+            current_b_scan,
+        },
+    );
 
-                        let y = seg as f32 / textures_state.a_scan_samples as f32;
-                        let y = rect.center().y - y * rect.height() * 0.5;
+    response
+}
 
-                        let x = (i as f32 + 0.5) / (b_scan_segmentation.len() - 1) as f32;
-                        let x = rect.left() + x * rect.width();
+/// Points of the M scan segmentation in the polar view, of every A scan in
+/// `x_range` and one beyond on each side, reduced to the extremes of every
+/// pixel column, see [polyline::column_extremes]. Invalid entries are skipped.
+pub fn polar_segmentation_points(
+    m_scan_segmentation: &[usize],
+    x_range: Rangef,
+    mapping: &PolarMapping,
+    pixels_per_point: f32,
+) -> Vec<Pos2> {
+    if mapping.a_scan_count == 0 || mapping.a_scan_samples == 0 {
+        return Vec::new();
+    }
 
-                        Some(pos2(x, y))
-                    })
-                }
-                _ => None,
-            })
-            .collect::<Vec<_>>();
+    let (Some(start), Some(end)) = (
+        mapping.a_scan_at(x_range.min.max(mapping.viewport.min.x)),
+        mapping.a_scan_at(x_range.max.min(mapping.viewport.max.x)),
+    ) else {
+        return Vec::new();
+    };
+
+    let a_scans = start.saturating_sub(1)..(end + 2).min(mapping.a_scan_count);
+    let points = a_scans
+        .filter_map(|scan_idx| {
+            let seg = *m_scan_segmentation.get(scan_idx)?;
+            if seg >= mapping.a_scan_samples {
+                return None;
+            }
 
-        let points2 = b_scan_segmentation
-            .windows(2)
-            .enumerate()
-            .filter_map(|(i, seg)| match seg {
-                &[start, end] => {
-                    let scan_idx =
-                        start + ((end - start) as f32 * ((current_rotation + 0.5) % 1.0)) as usize;
-
-                    m_scan_segmentation.get(scan_idx).and_then(|&seg| {
-                        if seg >= textures_state.a_scan_samples {
-                            return None;
-                        }
+            Some(pos2(mapping.x(scan_idx as f32), mapping.y(seg as f32)))
+        })
+        .filter(|p| p.is_finite());
 
-                        let y = seg as f32 / textures_state.a_scan_samples as f32;
-                        let y = rect.center().y + y * rect.height() * 0.5;
+    polyline::column_extremes(points, 1.0 / pixels_per_point)
+}
 
-                        let x = (i as f32 + 0.5) / (b_scan_segmentation.len() - 1) as f32;
-                        let x = rect.left() + x * rect.width();
+/// Points of the M scan segmentation of one B scan in the cartesian view in
+/// `rect`, one per A scan. Invalid entries are skipped.
+pub fn cartesian_segmentation_points(
+    m_scan_segmentation: &[usize],
+    b_scan: Range<usize>,
+    a_scan_samples: usize,
+    rect: Rect,
+) -> Vec<Pos2> {
+    let mapping = ScanViewMapping::Cartesian {
+        rect,
+        b_scan: b_scan.clone(),
+        a_scan_samples,
+        rotation: 0.0,
+    };
+
+    b_scan
+        .filter_map(|i| {
+            let seg = *m_scan_segmentation.get(i)?;
+            if seg >= a_scan_samples {
+                return None;
+            }
 
-                        Some(pos2(x, y))
-                    })
-                }
-                _ => None,
-            })
-            .collect::<Vec<_>>();
+            mapping.to_screen(i as f32, seg as f32)
+        })
+        .filter(|p| p.is_finite())
+        .collect()
+}
 
-        ui.painter()
-            .add(Shape::line(points1, Stroke::new(2.0, Color32::RED)));
+/// Points of the averaged M scan segmentation of every B scan in the side view
+/// of `mapping`, at its rotation, or half a turn from it if `opposite`. The
+/// curve goes up from the center of the view at the rotation and down
+/// opposite of it.
+pub fn side_segmentation_points(
+    m_scan_segmentation: &[usize],
+    mapping: &ScanViewMapping,
+    opposite: bool,
+) -> Vec<Pos2> {
+    let ScanViewMapping::Side {
+        b_scan_segmentation,
+        a_scan_samples,
+        rotation,
+        neighborhood,
+        ..
+    } = *mapping
+    else {
+        return Vec::new();
+    };
+    let rotation = match opposite {
+        true => (rotation + 0.5) % 1.0,
+        false => rotation,
+    };
+
+    b_scan_segmentation
+        .windows(2)
+        .filter_map(|b_scan| match *b_scan {
+            [start, end] => {
+                let seg = average_segmentation(
+                    m_scan_segmentation,
+                    start..end,
+                    rotation,
+                    neighborhood,
+                    a_scan_samples,
+                )?;
+
+                // The A scan at the rotation
+                let a_scan = start as f32 + rotation * (end - start) as f32;
+
+                mapping.to_screen(a_scan, seg)
+            }
+            _ => None,
+        })
+        .filter(|p| p.is_finite())
+        .collect()
+}
 
-        ui.painter()
-            .add(Shape::line(points2, Stroke::new(2.0, Color32::RED)));
+/// Averages the segmentation of the A scans around `rotation` inside a B scan,
+/// the same way the side view shader averages the A scans themselves. Invalid
+/// entries are skipped.
+fn average_segmentation(
+    m_scan_segmentation: &[usize],
+    b_scan: Range<usize>,
+    rotation: f32,
+    neighborhood: f32,
+    a_scan_samples: usize,
+) -> Option<f32> {
+    const MAX_SAMPLES: isize = 32;
+
+    let len = b_scan.len() as isize;
+    if len == 0 {
+        return None;
     }
 
-    // Draw current_b_scan line
-    let current_b_scan = ui
-        .data(|d| d.get_temp::<isize>(ui.id().with("current_b_scan")))
-        .unwrap_or(0) as f32;
-
-    let rect = response.rect;
-    let x = rect.left()
-        + rect.width() * (current_b_scan + 0.5) / (b_scan_segmentation.len() - 1) as f32;
-    ui.painter().line_segment(
-        [
-            pos2(x, rect.top()),
-            pos2(x, rect.top() + rect.height() * 0.1),
-        ],
-        Stroke::new(2.0, Color32::BLUE),
-    );
-    ui.painter().line_segment(
-        [
-            pos2(x, rect.bottom()),
-            pos2(x, rect.bottom() - rect.height() * 0.1),
-        ],
-        Stroke::new(2.0, Color32::BLUE),
-    );
+    let center = (rotation * (len - 1) as f32).floor() as isize;
+    let half_width = (neighborhood * len as f32).round() as isize;
+    let stride = ((2 * half_width + 1) / MAX_SAMPLES).max(1);
+
+    let (sum, count) = (-half_width..=half_width)
+        .step_by(stride as usize)
+        .filter_map(|offset| {
+            let idx = b_scan.start + (center + offset).rem_euclid(len) as usize;
+            m_scan_segmentation
+                .get(idx)
+                .filter(|&&seg| seg < a_scan_samples)
+        })
+        .fold((0, 0), |(sum, count), &seg| (sum + seg, count + 1));
 
-    response
+    (count > 0).then(|| sum as f32 / count as f32)
 }
 
 fn get_scroll_value<const CLAMP: bool>(
@@ -366,3 +726,204 @@ fn get_scroll_value<const CLAMP: bool>(
 
     current as usize
 }
+
+#[cfg(test)]
+mod test {
+    use nalgebra::Vector2;
+
+    use super::*;
+
+    #[test]
+    fn segmentation_points_are_finite() {
+        let segmentation = [10, usize::MAX, 99, 100, 0, u32::MAX as usize, 50, 1 << 40];
+
+        let viewport = Rect::from_min_size(pos2(-20.0, 5.0), vec2(400.0, 300.0));
+        let mapping = |viewport, a_scan_count, a_scan_samples| PolarMapping {
+            viewport,
+            a_scan_count,
+            a_scan_samples,
+            uniform_b_scans: None,
+        };
+        let points = polar_segmentation_points(
+            &segmentation,
+            Rangef::new(0.0, 300.0),
+            &mapping(viewport, 8, 100),
+            1.0,
+        );
+        assert!(!points.is_empty());
+        assert!(points.iter().all(|p| p.is_finite()));
+        assert!(points.iter().all(|p| p.y < viewport.max.y));
+
+        let points = cartesian_segmentation_points(
+            &segmentation,
+            0..8,
+            100,
+            Rect::from_x_y_ranges(0.0..=100.0, 0.0..=100.0),
+        );
+        assert_eq!(points.len(), 4);
+        assert!(points.iter().all(|p| p.is_finite()));
+
+        // Degenerate dimensions produce no points instead of NaN
+        let nan_viewport = Rect::from_min_size(Pos2::ZERO, Vec2::ZERO);
+        assert!(polar_segmentation_points(
+            &segmentation,
+            Rangef::new(0.0, 10.0),
+            &mapping(nan_viewport, 8, 100),
+            1.0,
+        )
+        .iter()
+        .all(|p| p.is_finite()));
+        assert!(polar_segmentation_points(
+            &segmentation,
+            Rangef::new(0.0, 10.0),
+            &mapping(viewport, 0, 0),
+            1.0,
+        )
+        .is_empty());
+        assert!(cartesian_segmentation_points(&segmentation, 3..3, 100, Rect::ZERO).is_empty());
+        assert!(cartesian_segmentation_points(&segmentation, 0..8, 0, Rect::ZERO).is_empty());
+    }
+
+    #[test]
+    fn diameters_of_b_scans() {
+        let diameter = |b_scan_start, b_scan_end| BScanDiameter {
+            b_scan_start,
+            b_scan_end,
+            min: 1.0,
+            max: 2.0,
+            mean: 1.5,
+            min_points: [Vector2::zeros(); 2],
+            max_points: [Vector2::zeros(); 2],
+        };
+        // The diameter node starts at the second B scan
+        let b_scans = [0, 100, 200, 300];
+        let diameters = [diameter(100, 200), diameter(200, 300)];
+
+        assert_eq!(diameter_of_b_scan(&diameters, b_scans[0]), None);
+        assert_eq!(
+            diameter_of_b_scan(&diameters, b_scans[1]),
+            Some(&diameters[0])
+        );
+        assert_eq!(
+            diameter_of_b_scan(&diameters, b_scans[2]),
+            Some(&diameters[1])
+        );
+        assert_eq!(diameter_of_b_scan(&diameters, 150), None);
+        assert_eq!(diameter_of_b_scan(&[], 0), None);
+    }
+
+    #[test]
+    fn fewer_points_than_pixels() {
+        // A smooth lumen with many A scans per pixel column
+        let segmentation = (0..20000)
+            .map(|i| (50.0 + 10.0 * (i as f32 / 5000.0).sin()) as usize)
+            .collect::<Vec<_>>();
+        let mapping = PolarMapping {
+            viewport: Rect::from_min_size(Pos2::ZERO, vec2(400.0, 300.0)),
+            a_scan_count: 20000,
+            a_scan_samples: 100,
+            uniform_b_scans: None,
+        };
+
+        let points =
+            polar_segmentation_points(&segmentation, Rangef::new(0.0, 400.0), &mapping, 1.0);
+        let simplified = polyline::simplify(&points, polyline::TOLERANCE);
+
+        // Previously, there was one point per pixel column
+        assert!(simplified.len() < 400 / 2, "{} points", simplified.len());
+        assert_eq!(simplified.first(), points.first());
+        assert_eq!(simplified.last(), points.last());
+    }
+
+    #[test]
+    fn polar_mapping() {
+        let available = vec2(400.0, 300.0);
+
+        assert_eq!(
+            AspectMode::Stretch.content_size(available, 1000, 100, 1.0),
+            None
+        );
+        assert_eq!(
+            AspectMode::Fit.content_size(available, 1000, 100, 1.0),
+            Some(vec2(400.0, 40.0))
+        );
+        assert_eq!(
+            AspectMode::Fit.content_size(available, 150, 600, 1.0),
+            Some(vec2(75.0, 300.0))
+        );
+        assert_eq!(
+            AspectMode::Actual.content_size(available, 1000, 100, 2.0),
+            Some(vec2(500.0, 50.0))
+        );
+        assert_eq!(AspectMode::Fit.content_size(available, 0, 100, 1.0), None);
+
+        // Letterboxed inside the callback rect
+        let rect = Rect::from_min_size(pos2(100.0, 100.0), available);
+        let mapping = PolarMapping {
+            viewport: Rect::from_min_size(pos2(100.0, 230.0), vec2(400.0, 40.0)),
+            a_scan_count: 1000,
+            a_scan_samples: 100,
+            uniform_b_scans: None,
+        };
+
+        assert_eq!(mapping.x(500.0), 300.0);
+        assert_eq!(mapping.y(50.0), 250.0);
+        assert_eq!(mapping.a_scan_at(300.0), Some(499));
+        assert_eq!(mapping.a_scan_at(99.0), None);
+        assert_eq!(mapping.scale(2.0), 0.8);
+
+        let gpu_rect = mapping.gpu_rect(rect);
+        assert_eq!(gpu_rect.x_range(), Rangef::new(-1.0, 1.0));
+        assert!((gpu_rect.min.y + 0.1333).abs() < 1e-3);
+        assert!((gpu_rect.max.y - 0.1333).abs() < 1e-3);
+
+        // Overlays land where the texels are drawn
+        let to_screen = |clip: Pos2| {
+            pos2(
+                rect.min.x + (clip.x + 1.0) / 2.0 * rect.width(),
+                rect.min.y + (1.0 - clip.y) / 2.0 * rect.height(),
+            )
+        };
+        assert!((to_screen(gpu_rect.left_top()).y - mapping.y(100.0)).abs() < 1e-3);
+        assert!((to_screen(gpu_rect.left_bottom()).y - mapping.y(0.0)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn uniform_b_scan_spacing() {
+        // Uneven B scans, an empty one and A scans before the first
+        let b_scans = [4, 10, 30, 30, 34, 100];
+
+        assert_eq!(uniform_x(&b_scans, 4.0), 0.0);
+        assert_eq!(uniform_x(&b_scans, 20.0), 1.5 / 5.0);
+        assert_eq!(uniform_x(&b_scans, 100.0), 1.0);
+        // Clamped to the B scans
+        assert_eq!(uniform_x(&b_scans, 0.0), 0.0);
+        assert_eq!(uniform_x(&b_scans, 120.0), 1.0);
+
+        // The shader finds the A scans, the overlays are drawn at
+        for a_scan in 4..=100 {
+            let x = uniform_x(&b_scans, a_scan as f32);
+            let found = uniform_a_scan(&b_scans, x);
+            assert!((found - a_scan as f32).abs() < 1e-3, "{a_scan}: {found}");
+        }
+        // Every position of the view shows an A scan of its B scan
+        for i in 0..=500 {
+            let x = i as f32 / 500.0;
+            let a_scan = uniform_a_scan(&b_scans, x);
+            assert!((uniform_x(&b_scans, a_scan) - x).abs() < 1e-4 || a_scan == 30.0);
+        }
+        // The empty B scan has no width on its own
+        assert_eq!(uniform_a_scan(&b_scans, 2.5 / 5.0), 30.0);
+
+        let mapping = PolarMapping {
+            viewport: Rect::from_min_size(pos2(100.0, 0.0), vec2(500.0, 100.0)),
+            a_scan_count: 110,
+            a_scan_samples: 100,
+            uniform_b_scans: Some(&b_scans),
+        };
+        assert_eq!(mapping.x(10.0), 200.0);
+        assert_eq!(mapping.a_scan_at(200.0), Some(10));
+        assert_eq!(mapping.a_scan_at(450.0), Some(32));
+        assert_eq!(mapping.a_scan_at(600.0), Some(100));
+    }
+}
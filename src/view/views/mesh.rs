@@ -1,14 +1,19 @@
 use std::sync::Arc;
 
+use anyhow::anyhow;
 use egui::Sense;
 use futures::future;
-use nalgebra::{Matrix4, Perspective3, Unit, Vector3};
-use tokio::sync::RwLock;
+use nalgebra::{Matrix4, Vector3};
+use tokio::sync::Mutex;
 use types::LumenVertex;
 
 use crate::{cache::Cached, queue_channel::error::RecvError};
 
-use super::prelude::*;
+use super::{
+    camera::Orbit,
+    perf::{self, GpuTimer, PerfOverlay, Timing},
+    prelude::*,
+};
 
 // MARK: View
 
@@ -17,10 +22,14 @@ use super::prelude::*;
 pub struct View {
     mesh: NodeOutput,
 
-    mesh_state: Cached<Option<MeshState>>,
+    mesh_state: Cached<Option<Arc<MeshState>>>,
     device: Arc<wgpu::Device>,
 
-    camera: Camera,
+    /// Kept, when only the mesh data is refreshed.
+    orbit: Orbit,
+    /// Whether [Self::orbit] got fitted to the first mesh chunk.
+    fitted: bool,
+    perf: PerfOverlay,
 }
 
 impl DataView for View {
@@ -44,11 +53,20 @@ impl DataView for View {
         Self: Sized,
     {
         if node_output.type_id == PipelineDataType::Mesh.into() {
+            let timers = render_state
+                .renderer
+                .read()
+                .callback_resources
+                .get::<SharedResources>()
+                .and_then(|resources| Some(resources.timer.as_ref()?.timers()));
+
             Some(Self {
                 mesh: node_output.clone(),
                 mesh_state: cache.get(node_output),
                 device: render_state.device.clone(),
-                camera: Camera::new(),
+                orbit: Orbit::default(),
+                fitted: false,
+                perf: PerfOverlay::new(timers),
             })
         } else {
             None
@@ -84,126 +102,61 @@ impl DataView for View {
         }
     }
 
-    fn ui(&mut self, ui: &mut egui::Ui) {
-        let mesh_state = self.mesh_state.read();
-
-        let Some(mesh_state) = mesh_state.as_ref() else {
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        _pipeline: &Pipeline,
+        _link: &SharedLinkState,
+        _live_tuning: &SharedLiveTuning,
+    ) -> ViewResponse {
+        let Some(mesh_state) = self.mesh_state.load() else {
             ui.ctx().request_repaint();
             ui.label("Data should be here soon");
-            return;
+            return ViewResponse::default();
         };
 
+        self.perf.begin(ui);
+
+        if let (false, Some(bounds)) = (self.fitted, mesh_state.bounds) {
+            self.orbit = bounds.orbit();
+            self.fitted = true;
+        }
+
         let (rect, response) =
             ui.allocate_exact_size(ui.available_size_before_wrap(), Sense::drag());
 
-        self.camera.update(ui.ctx(), response);
+        self.orbit.update(ui.ctx(), &response);
 
         ui.painter()
             .add(eframe::egui_wgpu::Callback::new_paint_callback(
                 rect,
                 PaintCallback {
                     buffers: mesh_state.meshes.clone(),
-                    mvp_matrix: Perspective3::new(
-                        rect.width() / rect.height(),
-                        std::f32::consts::PI * 0.5,
-                        0.001,
-                        100.0,
-                    )
-                    .as_matrix()
-                        * self.camera.view_matrix(),
+                    mvp_matrix: self.orbit.mvp_matrix(rect),
+                    timing: self.perf.timing(0),
                 },
             ));
-    }
-}
-
-// MARK: Camera
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-struct Camera {
-    position: Vector3<f32>,
-    dir: Unit<Vector3<f32>>,
-    up: Unit<Vector3<f32>>,
-}
-
-impl Camera {
-    fn new() -> Self {
-        Self {
-            position: Vector3::new(0.0, 0.0, 0.0),
-            dir: Unit::new_normalize(Vector3::new(1.0, 0.0, 0.0)),
-            up: Unit::new_normalize(Vector3::new(0.0, 1.0, 0.0)),
-        }
-    }
 
-    fn view_matrix(&self) -> Matrix4<f32> {
-        nalgebra::Matrix4::look_at_rh(
-            &(self.position).into(),
-            &(self.position + self.dir.into_inner()).into(),
-            &self.up,
-        )
-    }
+        ui.allocate_ui_at_rect(rect.expand(-5.0), |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Reset view")
+                    .on_hover_text(
+                        "Show the whole mesh. Drag to rotate, drag with the middle button or \
+                         shift to pan, scroll to zoom.",
+                    )
+                    .clicked()
+                {
+                    self.orbit = mesh_state.bounds.map_or_else(Orbit::default, |b| b.orbit());
+                }
 
-    fn update(&mut self, ctx: &egui::Context, response: egui::Response) {
-        if response.dragged() {
-            let delta = ctx.input(|r| r.pointer.delta());
-
-            let delta = Vector3::new(-delta.x, -delta.y, 0.0) * 0.01;
-
-            self.dir = nalgebra::Rotation3::from_axis_angle(&self.up, delta.x)
-                * nalgebra::Rotation3::from_axis_angle(
-                    &Unit::new_normalize(self.dir.into_inner().cross(&self.up.into_inner())),
-                    delta.y,
-                )
-                * self.dir;
-
-            let (forward, backward, left, right, up, down) = ctx.input(|r| {
-                (
-                    r.key_down(egui::Key::W),
-                    r.key_down(egui::Key::S),
-                    r.key_down(egui::Key::A),
-                    r.key_down(egui::Key::D),
-                    r.key_down(egui::Key::Space) || r.key_down(egui::Key::E),
-                    r.modifiers.shift || r.key_down(egui::Key::Q),
-                )
+                self.perf.menu_ui(ui);
             });
+        });
 
-            let speed = if ctx.input(|r| r.modifiers.ctrl) {
-                0.05
-            } else {
-                0.02
-            };
-
-            if forward {
-                let dir = Vector3::new(self.dir.x, 0.0, self.dir.z).normalize();
-                self.position += dir * speed;
-            }
-            if backward {
-                let dir = Vector3::new(self.dir.x, 0.0, self.dir.z).normalize();
-                self.position -= dir * speed;
-            }
-            if left {
-                self.position -= self.dir.cross(&self.up).normalize() * speed;
-            }
-            if right {
-                self.position += self.dir.cross(&self.up).normalize() * speed;
-            }
-            if up {
-                self.position += self.up.into_inner() * speed;
-            }
-            if down {
-                self.position -= self.up.into_inner() * speed;
-            }
-
-            ctx.request_repaint();
-        }
+        self.perf.end(ui, rect);
 
-        if let Some(pos) = response.hover_pos() {
-            let scroll = ctx.input(|r| r.smooth_scroll_delta.x + r.smooth_scroll_delta.y);
-            if response.rect.contains(pos) && scroll.abs() > 0.0 {
-                let dir = self.dir.into_inner();
-                self.position += dir * scroll * 0.03;
-                ctx.request_repaint();
-            }
-        }
+        ViewResponse::default()
     }
 }
 
@@ -212,30 +165,59 @@ impl Camera {
 struct PaintCallback {
     buffers: Vec<Arc<(wgpu::Buffer, wgpu::Buffer)>>,
     mvp_matrix: Matrix4<f32>,
+    timing: Option<Timing>,
 }
 
 impl eframe::egui_wgpu::CallbackTrait for PaintCallback {
+    fn prepare(
+        &self,
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _screen_descriptor: &eframe::egui_wgpu::ScreenDescriptor,
+        egui_encoder: &mut wgpu::CommandEncoder,
+        callback_resources: &mut eframe::egui_wgpu::CallbackResources,
+    ) -> Vec<wgpu::CommandBuffer> {
+        let timer = callback_resources
+            .get_mut::<SharedResources>()
+            .and_then(|resources| resources.timer.as_mut());
+
+        if let (Some(timing), Some(timer)) = (self.timing, timer) {
+            timer.prepare(timing, device, egui_encoder);
+        }
+        Vec::new()
+    }
+
     fn paint<'a>(
         &'a self,
         _info: egui::PaintCallbackInfo,
         render_pass: &mut wgpu::RenderPass<'a>,
         callback_resources: &'a eframe::egui_wgpu::CallbackResources,
     ) {
-        let resources = &callback_resources.get::<SharedResources>().unwrap();
+        let Some(resources) = callback_resources.get::<SharedResources>() else {
+            return;
+        };
 
         render_pass.set_pipeline(&resources.pipeline);
 
-        for (vertex_buffer, index_buffer) in self.buffers.iter().map(Arc::as_ref) {
-            let index_count = index_buffer.size() as u32 / std::mem::size_of::<u32>() as u32;
-            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            render_pass.set_push_constants(
-                wgpu::ShaderStages::VERTEX,
-                0,
-                bytemuck::cast_slice(self.mvp_matrix.as_slice()),
-            );
-            render_pass.draw_indexed(0..index_count, 0, 0..1);
-        }
+        perf::measure(
+            resources.timer.as_ref(),
+            self.timing,
+            render_pass,
+            |render_pass| {
+                for (vertex_buffer, index_buffer) in self.buffers.iter().map(Arc::as_ref) {
+                    let index_count =
+                        index_buffer.size() as u32 / std::mem::size_of::<u32>() as u32;
+                    render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                    render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    render_pass.set_push_constants(
+                        wgpu::ShaderStages::VERTEX,
+                        0,
+                        bytemuck::cast_slice(self.mvp_matrix.as_slice()),
+                    );
+                    render_pass.draw_indexed(0..index_count, 0, 0..1);
+                }
+            },
+        );
     }
 }
 
@@ -244,7 +226,7 @@ impl eframe::egui_wgpu::CallbackTrait for PaintCallback {
 struct Task {
     mesh: TaskInput<requests::Mesh>,
 
-    mesh_state: Cached<Option<MeshState>>,
+    mesh_state: Cached<Option<Arc<MeshState>>>,
     device: Arc<wgpu::Device>,
 }
 
@@ -266,7 +248,7 @@ impl DataViewTask for Task {
     }
 
     fn invalidate(&mut self, _cause: InvalidationCause) {
-        *self.mesh_state.write() = None;
+        self.mesh_state.replace(None);
     }
 
     async fn run(&mut self) -> anyhow::Result<()> {
@@ -280,28 +262,30 @@ impl DataViewTask for Task {
             return future::pending().await;
         };
 
-        {
-            let mut state = self.mesh_state.write();
-            if state.is_none() {
-                state.replace(MeshState {
-                    uploaded: Arc::new(RwLock::new(0)),
+        // Views of the same mesh share the state. The first one creates it
+        let uploaded = self
+            .mesh_state
+            .write()
+            .get_or_insert_with(|| {
+                Arc::new(MeshState {
+                    uploaded: Arc::new(Mutex::new(0)),
                     meshes: Vec::new(),
+                    bounds: None,
                     working: true,
-                });
-            }
-        }
+                })
+            })
+            .uploaded
+            .clone();
 
-        let Some(mut rx) = res.subscribe() else {
+        let Some(mut rx) = res.data.subscribe() else {
             return future::pending().await;
         };
 
-        let uploaded = {
-            let state = self.mesh_state.read();
-            state.as_ref().unwrap().uploaded.clone()
-        };
-
         let mut my_uploaded = 0;
 
+        // Chunks may reference vertices of their previous chunk
+        let mut previous: Option<types::LumenMesh> = None;
+
         loop {
             let data = match rx.recv().await {
                 Ok(data) => data,
@@ -309,20 +293,40 @@ impl DataViewTask for Task {
                 _ => return Ok(()),
             };
 
-            let mut uploaded = uploaded.write().await;
+            let previous = previous.replace(data.clone());
+
+            let mut uploaded_chunks = uploaded.lock().await;
 
             my_uploaded += 1;
-            if my_uploaded <= *uploaded {
+            if my_uploaded <= *uploaded_chunks {
                 continue;
             }
 
             // Upload data to GPU
             let device = self.device.clone();
 
-            let (vertex_buffer, index_buffer) = tokio::task::spawn_blocking(move || {
+            let (vertex_buffer, index_buffer, bounds) = tokio::task::spawn_blocking(move || {
+                let Some(vertices) = data.referenced_vertices(previous.as_ref()) else {
+                    return Err(anyhow!(
+                        "Mesh chunk {} references more vertices than its previous chunk has",
+                        my_uploaded
+                    ));
+                };
+
+                let max_size = device.limits().max_buffer_size;
+                let vertex_size = std::mem::size_of_val(vertices.as_ref()) as u64;
+                let index_size = std::mem::size_of_val(data.indices.as_slice()) as u64;
+                if vertex_size > max_size || index_size > max_size {
+                    return Err(anyhow!(
+                        "Mesh chunk {} exceeds the maximum buffer size of {} bytes",
+                        my_uploaded,
+                        max_size
+                    ));
+                }
+
                 let vertex = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: Some("Mesh Vertex Buffer"),
-                    contents: bytemuck::cast_slice(&data.vertices),
+                    contents: bytemuck::cast_slice(&vertices),
                     usage: wgpu::BufferUsages::VERTEX,
                 });
 
@@ -332,51 +336,113 @@ impl DataViewTask for Task {
                     usage: wgpu::BufferUsages::INDEX,
                 });
 
-                (vertex, index)
+                Ok((vertex, index, Bounds::of(&data.vertices)))
             })
-            .await?;
-
-            let mut mesh_state = self.mesh_state.write();
-            let Some(mesh_state) = mesh_state.as_mut() else {
+            .await??;
+
+            let mesh = Arc::new((vertex_buffer, index_buffer));
+            if !self.publish(&uploaded, |state| {
+                state.meshes.push(mesh);
+                state.bounds = match (state.bounds, bounds) {
+                    (Some(a), Some(b)) => Some(a.union(&b)),
+                    (a, b) => a.or(b),
+                };
+            }) {
                 return Ok(());
-            };
-
-            let meshes = &mut mesh_state.meshes;
-
-            meshes.push(Arc::new((vertex_buffer, index_buffer)));
+            }
 
-            *uploaded = my_uploaded;
+            *uploaded_chunks = my_uploaded;
         }
 
-        self.mesh_state
-            .write()
-            .as_mut()
-            .map(|state| state.working = false);
+        self.publish(&uploaded, |state| state.working = false);
 
         Ok(())
     }
 }
 
+impl Task {
+    /// Replaces the state drawn by the view with an updated copy. Returns
+    /// false, if the state got invalidated or belongs to another upload by
+    /// now.
+    fn publish(&self, uploaded: &Arc<Mutex<usize>>, f: impl FnOnce(&mut MeshState)) -> bool {
+        let mut mesh_state = self.mesh_state.write();
+
+        let mut state = match mesh_state.as_deref() {
+            Some(state) if Arc::ptr_eq(&state.uploaded, uploaded) => state.clone(),
+            _ => return false,
+        };
+        f(&mut state);
+
+        let old = mesh_state.replace(Arc::new(state));
+        drop(mesh_state);
+        drop(old);
+
+        true
+    }
+}
+
 // MARK: MeshState
 
-#[derive(Debug, Default)]
+/// What the view draws. Tasks publish a new state after every uploaded
+/// chunk, so the view never waits for an upload. See [Cached].
+#[derive(Debug, Default, Clone)]
 struct MeshState {
-    uploaded: Arc<RwLock<usize>>,
+    /// Number of chunks uploaded so far. Tasks of views showing the same mesh
+    /// take turns uploading its chunks.
+    uploaded: Arc<Mutex<usize>>,
     meshes: Vec<Arc<(wgpu::Buffer, wgpu::Buffer)>>,
+    /// Bounds of the vertices uploaded so far.
+    bounds: Option<Bounds>,
     working: bool,
 }
 
+/// Axis aligned bounding box of mesh vertices.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bounds {
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+}
+
+impl Bounds {
+    /// [None] without finite vertices.
+    fn of(vertices: &[LumenVertex]) -> Option<Self> {
+        vertices
+            .iter()
+            .map(|v| v.position)
+            .filter(|p| p.iter().all(|c| c.is_finite()))
+            .map(|p| Self { min: p, max: p })
+            .reduce(|a, b| a.union(&b))
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    /// Orbit showing the whole box.
+    fn orbit(&self) -> Orbit {
+        Orbit::fit(
+            (self.min + self.max) / 2.0,
+            (self.max - self.min).norm() / 2.0,
+        )
+    }
+}
+
 // MARK: SharedResources
 
 #[derive(Debug)]
 struct SharedResources {
     pipeline: wgpu::RenderPipeline,
+    /// Measures the paint callback for the performance overlay.
+    timer: Option<GpuTimer>,
 }
 
 impl SharedResources {
     fn new(
         device: &wgpu::Device,
-        _queue: &wgpu::Queue,
+        queue: &wgpu::Queue,
         target_format: &wgpu::TextureFormat,
     ) -> Self {
         let shader = device.create_shader_module(wgpu::include_wgsl!("mesh.wgsl"));
@@ -421,6 +487,36 @@ impl SharedResources {
             multisample: wgpu::MultisampleState::default(),
         });
 
-        Self { pipeline }
+        Self {
+            pipeline,
+            timer: GpuTimer::new(device, queue),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn vertex(x: f32, y: f32, z: f32) -> LumenVertex {
+        LumenVertex {
+            position: Vector3::new(x, y, z),
+            normal: Vector3::zeros(),
+        }
+    }
+
+    #[test]
+    fn bounds_of_vertices() {
+        assert_eq!(Bounds::of(&[]), None);
+        assert_eq!(Bounds::of(&[vertex(f32::NAN, 0.0, 0.0)]), None);
+
+        let a = Bounds::of(&[vertex(1.0, -2.0, 0.0), vertex(-1.0, 2.0, 5.0)]).unwrap();
+        assert_eq!(a.min, Vector3::new(-1.0, -2.0, 0.0));
+        assert_eq!(a.max, Vector3::new(1.0, 2.0, 5.0));
+
+        let b = Bounds::of(&[vertex(0.0, 0.0, 9.0), vertex(f32::INFINITY, 0.0, 0.0)]).unwrap();
+        let union = a.union(&b);
+        assert_eq!(union.max, Vector3::new(1.0, 2.0, 9.0));
+        assert_eq!(union.orbit().target, Vector3::new(0.0, 0.0, 4.5));
     }
 }
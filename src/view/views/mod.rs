@@ -1,6 +1,11 @@
+mod camera;
 pub mod data_vector;
+pub mod diameter;
+pub mod histogram;
 pub mod m_scan;
 pub mod mesh;
+mod perf;
+pub mod volume;
 
 use std::any::{self, Any};
 
@@ -12,7 +17,11 @@ use crate::{
     pipeline::Pipeline,
 };
 
-use super::execution::{DataViewTask, DynDataViewTask};
+use super::{
+    execution::{DataViewTask, DynDataViewTask},
+    link::SharedLinkState,
+    live_tuning::SharedLiveTuning,
+};
 
 #[allow(unused_imports)]
 mod prelude {
@@ -23,12 +32,16 @@ mod prelude {
             execution::{ConnectionHandle, InvalidationCause, Request, TaskInput},
             requests, types, Pipeline, PipelineDataType,
         },
-        view::execution::DataViewTask,
+        view::{
+            execution::DataViewTask,
+            link::{SharedLinkState, ViewLink},
+            live_tuning::SharedLiveTuning,
+        },
     };
 
     pub(crate) use eframe::egui_wgpu::RenderState;
 
-    pub(crate) use super::{DataView, Existence};
+    pub(crate) use super::{DataView, Existence, ViewResponse};
 
     pub(crate) use graph::*;
 
@@ -45,6 +58,14 @@ pub enum Existence {
     Keep,
 }
 
+/// Requests of a view to the app, returned by [DataView::ui].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ViewResponse {
+    /// Run the tasks of the pipeline, whose last run failed, again, see
+    /// [crate::pipeline::PipelineExecutor::retry_failed].
+    pub retry_failed: bool,
+}
+
 /// Describes a data view.
 ///
 /// Data views are rendered in their own tab and can connect to multiple nodes
@@ -81,7 +102,18 @@ pub trait DataView: Send + Sync + Clone + 'static {
 
     fn create_view_task(&mut self) -> impl DataViewTask<InputId = Self::InputId, DataView = Self>;
 
-    fn ui(&mut self, ui: &mut egui::Ui);
+    /// Renders the view. The pipeline can be used to find related nodes, to
+    /// [Self::connect] to. Views can take part in the selection shared by
+    /// `link` with a [ViewLink] and preview changed settings with
+    /// `live_tuning`. Everything else they need from the app is requested in
+    /// the returned [ViewResponse].
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        pipeline: &Pipeline,
+        link: &SharedLinkState,
+        live_tuning: &SharedLiveTuning,
+    ) -> ViewResponse;
 }
 
 /// Dynamic version of [DataView]. This trait is implemented automatically for
@@ -101,7 +133,13 @@ pub trait DynDataView: Send + Sync + 'static {
 
     fn create_view_task(&mut self) -> Box<dyn DynDataViewTask>;
 
-    fn ui(&mut self, ui: &mut egui::Ui);
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        pipeline: &Pipeline,
+        link: &SharedLinkState,
+        live_tuning: &SharedLiveTuning,
+    ) -> ViewResponse;
 }
 
 impl<T: DataView> DynDataView for T {
@@ -136,7 +174,13 @@ impl<T: DataView> DynDataView for T {
         Box::new(self.create_view_task())
     }
 
-    fn ui(&mut self, ui: &mut egui::Ui) {
-        self.ui(ui)
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        pipeline: &Pipeline,
+        link: &SharedLinkState,
+        live_tuning: &SharedLiveTuning,
+    ) -> ViewResponse {
+        self.ui(ui, pipeline, link, live_tuning)
     }
 }
@@ -0,0 +1,432 @@
+//! Performance overlay of data views. It shows how long the `ui` body of a
+//! view takes on the CPU, how many shapes it paints, and how long its paint
+//! callbacks take on the GPU, where the device supports timestamp queries
+//! inside render passes.
+//!
+//! GPU times are measured by a [GpuTimer] in the shared resources of a view
+//! type. Measured paint callbacks write a timestamp before and after their
+//! draw. The queries are resolved in the next frame and read back a few
+//! frames later, with only one readback in flight at a time.
+
+use std::{
+    sync::{Arc, Mutex, OnceLock},
+    time::Instant,
+};
+
+use egui::{Align2, Color32, FontId, Rect, Shape};
+
+/// Paint callbacks measured at once, over all views of a type.
+const MAX_SLOTS: usize = 32;
+
+/// Weight of a new sample in the rolling averages.
+const SMOOTHING: f32 = 0.05;
+
+// MARK: GpuTimer
+
+/// Slots of a [GpuTimer] and their latest results, shared with the views.
+#[derive(Debug, Default)]
+struct Slots {
+    allocated: Mutex<[bool; MAX_SLOTS]>,
+    /// GPU time of the slots in milliseconds, until it is taken.
+    results: Mutex<[Option<f32>; MAX_SLOTS]>,
+}
+
+/// Handle to allocate [TimerSlot]s of a [GpuTimer] from.
+#[derive(Debug, Clone)]
+pub struct GpuTimers(Arc<Slots>);
+
+impl GpuTimers {
+    fn allocate(&self) -> Option<TimerSlot> {
+        let mut allocated = self.0.allocated.lock().unwrap();
+        let index = allocated.iter().position(|allocated| !allocated)?;
+        allocated[index] = true;
+        self.0.results.lock().unwrap()[index] = None;
+
+        Some(TimerSlot {
+            index,
+            slots: self.0.clone(),
+        })
+    }
+}
+
+/// A pair of timestamp queries, free again when dropped.
+#[derive(Debug)]
+struct TimerSlot {
+    index: usize,
+    slots: Arc<Slots>,
+}
+
+impl TimerSlot {
+    fn take_result(&self) -> Option<f32> {
+        self.slots.results.lock().unwrap()[self.index].take()
+    }
+}
+
+impl Drop for TimerSlot {
+    fn drop(&mut self) {
+        self.slots.allocated.lock().unwrap()[self.index] = false;
+    }
+}
+
+/// Measurement of a paint callback in a frame, see [PerfOverlay::timing].
+#[derive(Debug, Clone, Copy)]
+pub struct Timing {
+    slot: usize,
+    frame: u64,
+}
+
+#[derive(Debug, Default)]
+enum Readback {
+    #[default]
+    Idle,
+    /// The slots were resolved and copied in a submitted frame.
+    Copied(Vec<usize>),
+    /// The result of mapping the readback buffer, once it is known.
+    Mapping(Vec<usize>, Arc<OnceLock<bool>>),
+}
+
+/// Timestamp queries for the paint callbacks of a view type. [None], unless
+/// the device supports [wgpu::Features::TIMESTAMP_QUERY_INSIDE_PASSES].
+#[derive(Debug)]
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    slots: Arc<Slots>,
+    /// Frame of the last [Self::prepare].
+    frame: u64,
+    /// Slots written since the last frame.
+    written: Mutex<Vec<usize>>,
+    readback: Readback,
+}
+
+impl GpuTimer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        let features =
+            wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_PASSES;
+        if !device.features().contains(features) {
+            return None;
+        }
+
+        let size = MAX_SLOTS as u64 * wgpu::QUERY_RESOLVE_BUFFER_ALIGNMENT;
+        let buffer = |label, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+
+        Some(Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Perf Query Set"),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2 * MAX_SLOTS as u32,
+            }),
+            resolve_buffer: buffer(
+                "Perf Resolve Buffer",
+                wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            ),
+            readback_buffer: buffer(
+                "Perf Readback Buffer",
+                wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            ),
+            period: queue.get_timestamp_period(),
+            slots: Arc::default(),
+            frame: 0,
+            written: Mutex::default(),
+            readback: Readback::Idle,
+        })
+    }
+
+    pub fn timers(&self) -> GpuTimers {
+        GpuTimers(self.slots.clone())
+    }
+
+    /// Advances the readback of the previous frames, once per frame. Call
+    /// from [eframe::egui_wgpu::CallbackTrait::prepare] of measured paint
+    /// callbacks.
+    pub fn prepare(
+        &mut self,
+        timing: Timing,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        if timing.frame == self.frame {
+            return;
+        }
+        self.frame = timing.frame;
+
+        // Written in the render pass of the last frame, which got submitted
+        let written = std::mem::take(&mut *self.written.lock().unwrap());
+
+        self.readback = match std::mem::take(&mut self.readback) {
+            Readback::Idle if written.is_empty() => Readback::Idle,
+            Readback::Idle => {
+                for &slot in &written {
+                    let queries = 2 * slot as u32;
+                    encoder.resolve_query_set(
+                        &self.query_set,
+                        queries..queries + 2,
+                        &self.resolve_buffer,
+                        slot as u64 * wgpu::QUERY_RESOLVE_BUFFER_ALIGNMENT,
+                    );
+                }
+                encoder.copy_buffer_to_buffer(
+                    &self.resolve_buffer,
+                    0,
+                    &self.readback_buffer,
+                    0,
+                    self.resolve_buffer.size(),
+                );
+                Readback::Copied(written)
+            }
+            Readback::Copied(slots) => {
+                let mapped = Arc::new(OnceLock::new());
+                let result = mapped.clone();
+                self.readback_buffer
+                    .slice(..)
+                    .map_async(wgpu::MapMode::Read, move |r| {
+                        let _ = result.set(r.is_ok());
+                    });
+                Readback::Mapping(slots, mapped)
+            }
+            Readback::Mapping(slots, mapped) => match mapped.get() {
+                None => {
+                    device.poll(wgpu::Maintain::Poll);
+                    Readback::Mapping(slots, mapped)
+                }
+                Some(false) => Readback::Idle,
+                Some(true) => {
+                    self.read_results(&slots);
+                    self.readback_buffer.unmap();
+                    Readback::Idle
+                }
+            },
+        };
+    }
+
+    fn read_results(&self, slots: &[usize]) {
+        let data = self.readback_buffer.slice(..).get_mapped_range();
+        let timestamp =
+            |offset: usize| u64::from_ne_bytes(data[offset..offset + 8].try_into().unwrap());
+
+        let mut results = self.slots.results.lock().unwrap();
+        for &slot in slots {
+            let offset = slot * wgpu::QUERY_RESOLVE_BUFFER_ALIGNMENT as usize;
+            let ticks = timestamp(offset + 8).wrapping_sub(timestamp(offset));
+            results[slot] = Some(ticks as f32 * self.period / 1e6);
+        }
+    }
+
+    fn begin(&self, render_pass: &mut wgpu::RenderPass, timing: Timing) {
+        render_pass.write_timestamp(&self.query_set, 2 * timing.slot as u32);
+    }
+
+    fn end(&self, render_pass: &mut wgpu::RenderPass, timing: Timing) {
+        render_pass.write_timestamp(&self.query_set, 2 * timing.slot as u32 + 1);
+        self.written.lock().unwrap().push(timing.slot);
+    }
+}
+
+/// Runs `draw` of a paint callback, surrounded by timestamps, if it is
+/// measured.
+pub fn measure<'a>(
+    timer: Option<&GpuTimer>,
+    timing: Option<Timing>,
+    render_pass: &mut wgpu::RenderPass<'a>,
+    draw: impl FnOnce(&mut wgpu::RenderPass<'a>),
+) {
+    let (Some(timer), Some(timing)) = (timer, timing) else {
+        draw(render_pass);
+        return;
+    };
+
+    timer.begin(render_pass, timing);
+    draw(render_pass);
+    timer.end(render_pass, timing);
+}
+
+// MARK: PerfOverlay
+
+/// Exponential moving average.
+#[derive(Debug, Default, Clone, Copy)]
+struct RollingAverage(Option<f32>);
+
+impl RollingAverage {
+    fn add(&mut self, sample: f32) {
+        self.0 = Some(match self.0 {
+            Some(average) => average + SMOOTHING * (sample - average),
+            None => sample,
+        });
+    }
+}
+
+/// Measures a view and shows the results in a corner of it, when enabled.
+#[derive(Debug)]
+pub struct PerfOverlay {
+    pub enabled: bool,
+    timers: Option<GpuTimers>,
+    /// Slots of the paint callbacks of the view, allocated when first used.
+    slots: Vec<Option<TimerSlot>>,
+    /// Start of the `ui` body and the index of a marker shape painted there.
+    started: Option<(Instant, usize)>,
+    frame: u64,
+    ui_time: RollingAverage,
+    shapes: RollingAverage,
+    gpu_time: RollingAverage,
+}
+
+impl PerfOverlay {
+    /// Without `timers`, GPU times are not measured.
+    pub fn new(timers: Option<GpuTimers>) -> Self {
+        Self {
+            enabled: false,
+            timers,
+            slots: Vec::new(),
+            started: None,
+            frame: 0,
+            ui_time: RollingAverage::default(),
+            shapes: RollingAverage::default(),
+            gpu_time: RollingAverage::default(),
+        }
+    }
+
+    /// Call at the start of the `ui` body of the view.
+    pub fn begin(&mut self, ui: &egui::Ui) {
+        if !self.enabled {
+            return;
+        }
+        self.frame = ui.ctx().frame_nr();
+        self.started = Some((Instant::now(), ui.painter().add(Shape::Noop).0));
+    }
+
+    /// Measurement for the `index`-th paint callback of the view in this
+    /// frame. [None], when disabled or not supported.
+    pub fn timing(&mut self, index: usize) -> Option<Timing> {
+        if !self.enabled {
+            return None;
+        }
+        let timers = self.timers.as_ref()?;
+
+        if self.slots.len() <= index {
+            self.slots.resize_with(index + 1, || None);
+        }
+        let slot = match &mut self.slots[index] {
+            Some(slot) => slot,
+            slot => slot.insert(timers.allocate()?),
+        };
+
+        Some(Timing {
+            slot: slot.index,
+            frame: self.frame,
+        })
+    }
+
+    /// Call at the end of the `ui` body of the view. Paints the overlay into
+    /// `rect`.
+    pub fn end(&mut self, ui: &egui::Ui, rect: Rect) {
+        if !self.enabled {
+            // Free the slots for other views
+            self.slots.clear();
+            return;
+        }
+        let Some((start, marker)) = self.started.take() else {
+            return;
+        };
+
+        self.ui_time.add(start.elapsed().as_secs_f32() * 1000.0);
+        let end = ui.painter().add(Shape::Noop).0;
+        self.shapes.add(end.saturating_sub(marker + 1) as f32);
+
+        let gpu_times = self
+            .slots
+            .iter()
+            .flatten()
+            .filter_map(TimerSlot::take_result)
+            .collect::<Vec<_>>();
+        if !gpu_times.is_empty() {
+            self.gpu_time.add(gpu_times.iter().sum());
+        }
+
+        let gpu_time = match (&self.timers, self.gpu_time.0) {
+            (None, _) => "not supported".to_string(),
+            (Some(_), None) => "–".to_string(),
+            (Some(_), Some(time)) => format!("{time:.2} ms"),
+        };
+        let text = format!(
+            "UI {:.2} ms\nShapes {:.0}\nGPU {}",
+            self.ui_time.0.unwrap_or_default(),
+            self.shapes.0.unwrap_or_default(),
+            gpu_time,
+        );
+
+        let painter = ui.painter_at(rect);
+        let galley = painter.layout_no_wrap(text, FontId::monospace(11.0), Color32::WHITE);
+        let text_rect = Align2::LEFT_BOTTOM
+            .anchor_size(rect.left_bottom() + egui::vec2(8.0, -8.0), galley.size());
+        painter.rect_filled(text_rect.expand(4.0), 3.0, Color32::from_black_alpha(180));
+        painter.galley(text_rect.min, galley, Color32::WHITE);
+
+        ui.ctx().request_repaint();
+    }
+
+    /// Debug section of the toolbar of a view.
+    pub fn menu_ui(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("🐛", |ui| {
+            ui.checkbox(&mut self.enabled, "Performance overlay")
+                .on_hover_text(
+                    "Time of the view on the CPU and the GPU and the shapes it paints, \
+                     averaged over recent frames",
+                );
+        })
+        .response
+        .on_hover_text("Debug");
+    }
+}
+
+impl Clone for PerfOverlay {
+    fn clone(&self) -> Self {
+        Self {
+            enabled: self.enabled,
+            ..Self::new(self.timers.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slots_are_reused() {
+        let timers = GpuTimers(Arc::default());
+
+        let slots = (0..MAX_SLOTS)
+            .map(|_| timers.allocate().unwrap())
+            .collect::<Vec<_>>();
+        assert!(timers.allocate().is_none());
+
+        timers.0.results.lock().unwrap()[3] = Some(1.5);
+        assert_eq!(slots[3].take_result(), Some(1.5));
+        assert_eq!(slots[3].take_result(), None);
+
+        drop(slots);
+        assert_eq!(timers.allocate().unwrap().index, 0);
+    }
+
+    #[test]
+    fn rolling_average() {
+        let mut average = RollingAverage::default();
+        average.add(10.0);
+        assert_eq!(average.0, Some(10.0));
+
+        for _ in 0..200 {
+            average.add(2.0);
+        }
+        assert!((average.0.unwrap() - 2.0).abs() < 0.01);
+    }
+}
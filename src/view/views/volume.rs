@@ -0,0 +1,974 @@
+//! Volume rendering of a whole pullback.
+//!
+//! Every B scan is resampled onto a cartesian slice, like the cartesian view
+//! shows it, and the slices are stacked into a 3D texture of a fixed
+//! resolution, which is raycast. Only the A scans of the B scan being
+//! received are kept, so the memory used depends on the resolution, not on
+//! the length of the pullback. When there are more B scans than layers, every
+//! layer shows the first of its B scans.
+//!
+//! The view is opened from the M scan view, see [request_open].
+
+use std::{collections::VecDeque, ops::Range, sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use egui::{ComboBox, Sense};
+use futures::future;
+use nalgebra::{DMatrix, DMatrixView, Matrix4, Vector3};
+use rayon::prelude::*;
+
+use crate::{
+    cache::Cached,
+    gui::color_maps,
+    pipeline::types::{DataMatrix, DataType},
+    queue_channel::error::RecvError,
+    settings::Settings,
+};
+
+use super::{camera::Camera, prelude::*};
+
+/// Edge lengths of the volume to choose from.
+const RESOLUTIONS: [u32; 4] = [128, 192, 256, 384];
+const DEFAULT_RESOLUTION: u32 = 256;
+
+/// Time without camera movement, after which the volume is rendered with
+/// more steps.
+const REFINE_DELAY: f64 = 0.3;
+
+pub enum InputId {
+    MScan,
+    BScanSegmentation,
+}
+
+impl_enum_from_into_id_types!(InputId, [graph::InputId], {
+    0 => MScan,
+    1 => BScanSegmentation,
+});
+
+// MARK: Open requests
+
+fn open_request_id() -> egui::Id {
+    egui::Id::new("open_volume_view")
+}
+
+/// Asks the app to open a volume view of `m_scan`, split into B scans by
+/// `b_scans`. See [take_open_request].
+pub fn request_open(ctx: &egui::Context, m_scan: NodeOutput, b_scans: NodeOutput) {
+    ctx.data_mut(|data| data.insert_temp(open_request_id(), Some((m_scan, b_scans))));
+}
+
+/// The M scan and B scan segmentation of a requested volume view.
+pub fn take_open_request(ctx: &egui::Context) -> Option<(NodeOutput, NodeOutput)> {
+    ctx.data_mut(|data| data.remove_temp::<Option<(NodeOutput, NodeOutput)>>(open_request_id()))
+        .flatten()
+}
+
+// MARK: View
+
+/// Renders a [requests::MScan] as volume, split into B scans by a
+/// [requests::BScanSegmentation].
+#[derive(Clone, Debug)]
+pub struct View {
+    m_scan: NodeOutput,
+    b_scans: NodeOutput,
+    resolution: u32,
+
+    volume_state: Cached<Option<Arc<VolumeState>>>,
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    bind_group_layout: Arc<wgpu::BindGroupLayout>,
+
+    camera: Camera,
+    /// Time of the last camera movement, in seconds of [egui::InputState::time].
+    last_moved: f64,
+
+    map_idx: u32,
+    /// Opacity of the brightest value along the diagonal of the volume.
+    opacity: f32,
+    /// Values below are transparent.
+    threshold: f32,
+    /// Length of the volume relative to its width.
+    length: f32,
+}
+
+impl View {
+    /// Returns [None], if the wgpu resources of this view were not
+    /// initialized.
+    pub fn new(
+        m_scan: NodeOutput,
+        b_scans: NodeOutput,
+        cache: &Cache,
+        render_state: &RenderState,
+    ) -> Option<Self> {
+        let renderer = render_state.renderer.read();
+        let Some(resources) = renderer.callback_resources.get::<SharedResources>() else {
+            eprintln!("Volume view resources are missing");
+            return None;
+        };
+
+        let mut view = Self {
+            m_scan,
+            b_scans,
+            resolution: DEFAULT_RESOLUTION,
+            volume_state: cache.get(()),
+            device: render_state.device.clone(),
+            queue: render_state.queue.clone(),
+            bind_group_layout: resources.bind_group_layout.clone(),
+            camera: Camera::looking_at(Vector3::new(3.0, 1.5, 3.5), Vector3::zeros()),
+            last_moved: f64::NEG_INFINITY,
+            map_idx: color_maps::loaded_index(Settings::current().display.default_color_map),
+            opacity: 0.9,
+            threshold: 0.2,
+            length: 2.0,
+        };
+        view.volume_state = cache.get(view.cache_key());
+
+        Some(view)
+    }
+
+    /// Volumes are shared by views of the same inputs and resolution.
+    fn cache_key(&self) -> impl std::hash::Hash {
+        (
+            (self.m_scan.node_id, self.m_scan.output_id),
+            (self.b_scans.node_id, self.b_scans.output_id),
+            self.resolution,
+        )
+    }
+}
+
+impl DataView for View {
+    type InputId = InputId;
+
+    fn init_wgpu(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target_format: &wgpu::TextureFormat,
+    ) -> impl std::any::Any + Send + Sync + 'static {
+        SharedResources::new(device, queue, target_format)
+    }
+
+    /// Volume views are only opened on request, see [request_open].
+    fn from_node_output(
+        _node_output: &NodeOutput,
+        _pipeline: &Pipeline,
+        _cache: &Cache,
+        _render_state: &RenderState,
+    ) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    fn inputs(&self) -> impl Iterator<Item = (Self::InputId, Option<NodeOutput>)> {
+        [
+            (InputId::MScan, Some(self.m_scan)),
+            (InputId::BScanSegmentation, Some(self.b_scans)),
+        ]
+        .into_iter()
+    }
+
+    fn changed(&self, other: &Self) -> bool {
+        self.m_scan != other.m_scan
+            || self.b_scans != other.b_scans
+            || self.resolution != other.resolution
+    }
+
+    fn connect(&mut self, node_output: NodeOutput, _pipeline: &Pipeline) -> bool {
+        match PipelineDataType::from(node_output.type_id) {
+            PipelineDataType::MScan => self.m_scan = node_output,
+            PipelineDataType::BScanSegmentation => self.b_scans = node_output,
+            _ => return false,
+        }
+        self.volume_state.change_target(self.cache_key());
+        true
+    }
+
+    fn disconnect(&mut self, _input_id: Self::InputId) -> Existence {
+        Existence::Destroy
+    }
+
+    fn create_view_task(&mut self) -> impl DataViewTask<InputId = Self::InputId, DataView = Self> {
+        Task {
+            m_scan_in: TaskInput::default(),
+            b_scans_in: TaskInput::default(),
+            resolution: self.resolution,
+            volume_state: self.volume_state.clone(),
+            device: self.device.clone(),
+            queue: self.queue.clone(),
+            bind_group_layout: self.bind_group_layout.clone(),
+        }
+    }
+
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        _pipeline: &Pipeline,
+        _link: &SharedLinkState,
+        _live_tuning: &SharedLiveTuning,
+    ) -> ViewResponse {
+        let Some(volume_state) = self.volume_state.load() else {
+            ui.ctx().request_repaint();
+            ui.label("Data should be here soon");
+            return ViewResponse::default();
+        };
+
+        let (rect, response) =
+            ui.allocate_exact_size(ui.available_size_before_wrap(), Sense::drag());
+
+        let now = ui.input(|i| i.time);
+        if self.camera.orbit(ui.ctx(), &response, Vector3::zeros()) {
+            self.last_moved = now;
+        }
+
+        // Few steps while the volume fills or the camera moves
+        let idle = !volume_state.working && now - self.last_moved > REFINE_DELAY;
+        let steps = match idle {
+            true => self.resolution * 2,
+            false => self.resolution / 2,
+        };
+
+        if volume_state.working {
+            ui.ctx().request_repaint();
+        } else if !idle {
+            ui.ctx()
+                .request_repaint_after(Duration::from_secs_f64(REFINE_DELAY));
+        }
+
+        let mvp = self.camera.mvp_matrix(rect);
+        ui.painter()
+            .add(eframe::egui_wgpu::Callback::new_paint_callback(
+                rect,
+                PaintCallback {
+                    bind_group: volume_state.bind_group.clone(),
+                    constants: Constants {
+                        inverse_mvp: mvp.try_inverse().unwrap_or_else(Matrix4::identity).into(),
+                        camera: self.camera.position.into(),
+                        half_length: self.length,
+                        steps,
+                        map_idx: self.map_idx,
+                        opacity: self.opacity,
+                        threshold: self.threshold,
+                    },
+                },
+            ));
+
+        ui.allocate_ui_at_rect(rect.expand(-5.0), |ui| {
+            ui.horizontal(|ui| {
+                let mut resolution = self.resolution;
+                ComboBox::from_id_source(ui.id().with("resolution"))
+                    .selected_text(format!("{}³", resolution))
+                    .show_ui(ui, |ui| {
+                        for r in RESOLUTIONS {
+                            let mib = (r as usize).pow(3) / (1024 * 1024);
+                            ui.selectable_value(&mut resolution, r, format!("{r}³ ({mib} MiB)"));
+                        }
+                    })
+                    .response
+                    .on_hover_text("Resolution of the volume. Higher ones take more GPU memory");
+                if resolution != self.resolution {
+                    self.resolution = resolution;
+                    self.volume_state.change_target(self.cache_key());
+                }
+
+                color_maps::color_map_menu(ui, &mut self.map_idx)
+                    .on_hover_text("Transfer function: Values are colored by this map");
+
+                ui.add(
+                    egui::Slider::new(&mut self.opacity, 0.0..=1.0)
+                        .text("Opacity")
+                        .fixed_decimals(2),
+                )
+                .on_hover_text("Opacity of the brightest value across the whole volume");
+                ui.add(
+                    egui::Slider::new(&mut self.threshold, 0.0..=1.0)
+                        .text("Threshold")
+                        .fixed_decimals(2),
+                )
+                .on_hover_text("Values below are transparent, opacity rises above it");
+                ui.add(
+                    egui::DragValue::new(&mut self.length)
+                        .range(0.25..=20.0)
+                        .speed(0.05)
+                        .prefix("Length: "),
+                )
+                .on_hover_text("Length of the pullback relative to the diameter of the volume");
+
+                if volume_state.working {
+                    ui.spinner();
+                    ui.label(format!(
+                        "{:.0} %",
+                        volume_state.layers as f32 / self.resolution as f32 * 100.0
+                    ))
+                    .on_hover_text("Layers of the volume filled so far");
+                }
+            });
+        });
+
+        ViewResponse::default()
+    }
+}
+
+// MARK: PaintCallback
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Constants {
+    inverse_mvp: [[f32; 4]; 4],
+    camera: [f32; 3],
+    half_length: f32,
+    steps: u32,
+    map_idx: u32,
+    opacity: f32,
+    threshold: f32,
+}
+
+struct PaintCallback {
+    bind_group: Arc<wgpu::BindGroup>,
+    constants: Constants,
+}
+
+impl eframe::egui_wgpu::CallbackTrait for PaintCallback {
+    fn paint<'a>(
+        &'a self,
+        _info: egui::PaintCallbackInfo,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        callback_resources: &'a eframe::egui_wgpu::CallbackResources,
+    ) {
+        let Some(resources) = callback_resources.get::<SharedResources>() else {
+            return;
+        };
+
+        render_pass.set_pipeline(&resources.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, &resources.color_maps_bind_group, &[]);
+        render_pass.set_push_constants(
+            wgpu::ShaderStages::FRAGMENT,
+            0,
+            bytemuck::cast_slice(&[self.constants]),
+        );
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// MARK: Task
+
+struct Task {
+    m_scan_in: TaskInput<requests::MScan>,
+    b_scans_in: TaskInput<requests::BScanSegmentation>,
+    resolution: u32,
+
+    volume_state: Cached<Option<Arc<VolumeState>>>,
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    bind_group_layout: Arc<wgpu::BindGroupLayout>,
+}
+
+impl DataViewTask for Task {
+    type InputId = InputId;
+    type DataView = View;
+
+    fn sync_view(&mut self, view: &Self::DataView) {
+        self.resolution = view.resolution;
+        self.volume_state.change_target(view.cache_key());
+    }
+
+    fn connect(&mut self, input_id: Self::InputId, input: &mut ConnectionHandle) {
+        match input_id {
+            InputId::MScan => self.m_scan_in.connect(input),
+            InputId::BScanSegmentation => self.b_scans_in.connect(input),
+        };
+    }
+
+    fn disconnect(&mut self, input_id: Self::InputId) {
+        match input_id {
+            InputId::MScan => self.m_scan_in.disconnect(),
+            InputId::BScanSegmentation => self.b_scans_in.disconnect(),
+        };
+    }
+
+    fn invalidate(&mut self, cause: InvalidationCause) {
+        match cause {
+            // Views are not cancelled
+            InvalidationCause::UserCancelled => {}
+            _ => {
+                self.volume_state.replace(None);
+            }
+        }
+    }
+
+    async fn run(&mut self) -> anyhow::Result<()> {
+        if self.volume_state.read().is_some() {
+            let () = future::pending().await;
+        }
+
+        let (Some(m_scan), Some(b_scans)) = tokio::join!(
+            self.m_scan_in.request(requests::MScan),
+            self.b_scans_in.request(requests::BScanSegmentation),
+        ) else {
+            return future::pending().await;
+        };
+
+        if b_scans.a_scan_count != m_scan.a_scan_count {
+            return Err(anyhow!(
+                "The B scan segmentation covers {} A scans, but the M scan has {}",
+                b_scans.a_scan_count,
+                m_scan.a_scan_count
+            ));
+        }
+
+        let (Some(mut m_scan_rx), Some(mut b_scans_rx)) =
+            (m_scan.data.subscribe(), b_scans.data.subscribe())
+        else {
+            return future::pending().await;
+        };
+
+        let resolution = self.resolution as usize;
+        let texture = self.create_volume_texture();
+
+        // Views of the same volume share the state. The first one fills it
+        let token = Arc::new(());
+        let first = {
+            let mut volume_state = self.volume_state.write();
+            let first = volume_state.is_none();
+            if first {
+                *volume_state = Some(Arc::new(VolumeState {
+                    token: token.clone(),
+                    bind_group: Arc::new(self.create_bind_group(&texture)),
+                    layers: 0,
+                    working: true,
+                }));
+            }
+            first
+        };
+        if !first {
+            return future::pending().await;
+        }
+
+        let mut builder = VolumeBuilder::new(resolution, m_scan.a_scan_count);
+        let mut m_scan_done = false;
+        let mut b_scans_done = false;
+
+        while !(m_scan_done && b_scans_done) {
+            tokio::select! {
+                data = m_scan_rx.recv(), if !m_scan_done => match data {
+                    Ok(data) => {
+                        let data = tokio::task::spawn_blocking(move || {
+                            data.cast_rescale_par(DataType::U8)
+                        })
+                        .await?;
+                        let DataMatrix::U8(a_scans) = data else {
+                            unreachable!("Data should be cast to u8");
+                        };
+                        builder.push_a_scans(a_scans)?;
+                    }
+                    Err(RecvError::Closed) => m_scan_done = true,
+                    _ => {
+                        self.abandon(&token);
+                        return Ok(());
+                    }
+                },
+                boundary = b_scans_rx.recv(), if !b_scans_done => match boundary {
+                    Ok(boundary) => builder.push_boundary(boundary),
+                    Err(RecvError::Closed) => b_scans_done = true,
+                    _ => {
+                        self.abandon(&token);
+                        return Ok(());
+                    }
+                },
+            }
+
+            let b_scans = builder.take_b_scans();
+            let Some((last_layers, _)) = b_scans.last() else {
+                continue;
+            };
+            let layers = last_layers.end;
+
+            let slices = tokio::task::spawn_blocking(move || {
+                b_scans
+                    .into_iter()
+                    .map(|(layers, b_scan)| (layers, resample_b_scan(b_scan.as_view(), resolution)))
+                    .collect::<Vec<_>>()
+            })
+            .await?;
+
+            for (layers, slice) in slices {
+                for layer in layers {
+                    self.write_layer(&texture, layer as u32, &slice);
+                }
+            }
+
+            if !self.publish(&token, |state| state.layers = layers) {
+                return Ok(());
+            }
+        }
+
+        self.publish(&token, |state| state.working = false);
+
+        Ok(())
+    }
+}
+
+impl Task {
+    fn create_volume_texture(&self) -> wgpu::Texture {
+        self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Volume Texture"),
+            size: wgpu::Extent3d {
+                width: self.resolution,
+                height: self.resolution,
+                depth_or_array_layers: self.resolution,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+
+    fn create_bind_group(&self, texture: &wgpu::Texture) -> wgpu::BindGroup {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler = self.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Volume Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Volume Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        })
+    }
+
+    fn write_layer(&self, texture: &wgpu::Texture, layer: u32, slice: &[u8]) {
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            slice,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(self.resolution),
+                rows_per_image: Some(self.resolution),
+            },
+            wgpu::Extent3d {
+                width: self.resolution,
+                height: self.resolution,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Replaces the state drawn by the view with an updated copy. Returns
+    /// false, if the state got invalidated or belongs to another task by now.
+    fn publish(&self, token: &Arc<()>, f: impl FnOnce(&mut VolumeState)) -> bool {
+        let mut volume_state = self.volume_state.write();
+
+        let mut state = match volume_state.as_deref() {
+            Some(state) if Arc::ptr_eq(&state.token, token) => state.clone(),
+            _ => return false,
+        };
+        f(&mut state);
+
+        let old = volume_state.replace(Arc::new(state));
+        drop(volume_state);
+        drop(old);
+
+        true
+    }
+
+    /// Drops the state, if it belongs to this task, so the next run starts
+    /// over. Used, when the task lagged behind its inputs.
+    fn abandon(&self, token: &Arc<()>) {
+        let mut volume_state = self.volume_state.write();
+
+        let old = match volume_state.as_deref() {
+            Some(state) if Arc::ptr_eq(&state.token, token) => volume_state.take(),
+            _ => None,
+        };
+        drop(volume_state);
+        drop(old);
+    }
+}
+
+// MARK: VolumeState
+
+/// What the view draws. See [Cached].
+#[derive(Debug, Clone)]
+struct VolumeState {
+    /// Identifies the task filling the volume.
+    token: Arc<()>,
+    bind_group: Arc<wgpu::BindGroup>,
+    /// Number of layers filled, from the start of the pullback.
+    layers: usize,
+    working: bool,
+}
+
+// MARK: VolumeBuilder
+
+/// Collects the streamed A scans and B scan boundaries and hands out every B
+/// scan, once all of its A scans were received.
+struct VolumeBuilder {
+    resolution: usize,
+    a_scan_count: usize,
+    /// Boundaries of the B scans, that were not handed out yet. The first one
+    /// starts the next B scan.
+    boundaries: VecDeque<usize>,
+    /// Received A scans, starting at A scan [Self::pending_start].
+    pending: DMatrix<u8>,
+    pending_start: usize,
+    /// Layers below are taken by previous B scans.
+    next_layer: usize,
+}
+
+impl VolumeBuilder {
+    fn new(resolution: usize, a_scan_count: usize) -> Self {
+        Self {
+            resolution,
+            a_scan_count,
+            boundaries: VecDeque::new(),
+            pending: DMatrix::zeros(0, 0),
+            pending_start: 0,
+            next_layer: 0,
+        }
+    }
+
+    fn push_a_scans(&mut self, a_scans: DMatrix<u8>) -> anyhow::Result<()> {
+        if self.pending.shape() == (0, 0) {
+            self.pending = a_scans;
+            return Ok(());
+        }
+
+        self.pending = DataMatrix::U8(std::mem::replace(&mut self.pending, DMatrix::zeros(0, 0)))
+            .concat_horizontally(&DataMatrix::U8(a_scans))
+            .and_then(|pending| match pending {
+                DataMatrix::U8(pending) => Some(pending),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow!("A scans of the M scan changed their number of samples"))?;
+
+        Ok(())
+    }
+
+    fn push_boundary(&mut self, boundary: usize) {
+        self.boundaries.push_back(boundary);
+    }
+
+    /// Layers of the volume, that B scans starting at `a_scan` fall onto.
+    fn layer(&self, a_scan: usize) -> usize {
+        (a_scan * self.resolution / self.a_scan_count.max(1)).min(self.resolution)
+    }
+
+    /// B scans, whose A scans were all received, with the layers of the
+    /// volume they fill. B scans on layers already filled are skipped.
+    fn take_b_scans(&mut self) -> Vec<(Range<usize>, DMatrix<u8>)> {
+        let received = self.pending_start + self.pending.ncols();
+
+        let mut b_scans = Vec::new();
+        while let (Some(&start), Some(&end)) = (self.boundaries.front(), self.boundaries.get(1)) {
+            if end > received {
+                break;
+            }
+            self.boundaries.pop_front();
+
+            let layers = self.next_layer.max(self.layer(start))
+                ..(self.layer(start) + 1)
+                    .max(self.layer(end))
+                    .min(self.resolution);
+            if layers.is_empty() || end <= start || start < self.pending_start {
+                continue;
+            }
+
+            let b_scan = self
+                .pending
+                .columns(start - self.pending_start, end - start)
+                .into_owned();
+            self.next_layer = layers.end;
+            b_scans.push((layers, b_scan));
+        }
+
+        // Only A scans from the next B scan on are kept
+        if let Some(&next) = self.boundaries.front() {
+            let done = next
+                .saturating_sub(self.pending_start)
+                .min(self.pending.ncols());
+            if done > 0 {
+                self.pending = self
+                    .pending
+                    .columns(done, self.pending.ncols() - done)
+                    .into_owned();
+                self.pending_start += done;
+            }
+        }
+
+        b_scans
+    }
+}
+
+/// Resamples a B scan onto a `resolution`² cartesian slice, in the same
+/// orientation as the cartesian view. Outside of the A scans is zero.
+fn resample_b_scan(b_scan: DMatrixView<u8>, resolution: usize) -> Vec<u8> {
+    let (samples, a_scans) = b_scan.shape();
+    let mut slice = vec![0; resolution * resolution];
+    if samples == 0 || a_scans == 0 {
+        return slice;
+    }
+
+    let coord = |i: usize| (i as f32 + 0.5) / resolution as f32 * 2.0 - 1.0;
+
+    slice
+        .par_chunks_mut(resolution)
+        .enumerate()
+        .for_each(|(row, pixels)| {
+            let y = coord(row);
+            for (column, pixel) in pixels.iter_mut().enumerate() {
+                let x = coord(column);
+
+                let distance = (x * x + y * y).sqrt();
+                if distance >= 1.0 {
+                    continue;
+                }
+                let alpha = (x.atan2(y) + std::f32::consts::PI) / std::f32::consts::TAU;
+
+                let a_scan = ((alpha * a_scans as f32) as usize).min(a_scans - 1);
+                let sample = ((distance * samples as f32) as usize).min(samples - 1);
+                *pixel = b_scan[(sample, a_scan)];
+            }
+        });
+
+    slice
+}
+
+// MARK: SharedResources
+
+#[derive(Debug)]
+struct SharedResources {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    color_maps_bind_group: wgpu::BindGroup,
+}
+
+impl SharedResources {
+    fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target_format: &wgpu::TextureFormat,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("volume.wgsl"));
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Volume Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let color_maps_view = color_maps::upload_color_maps(device, queue)
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let color_maps_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Volume Color Maps Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::ReadOnly,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                }],
+            });
+
+        let color_maps_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Volume Color Maps Bind Group"),
+            layout: &color_maps_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&color_maps_view),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Volume Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout, &color_maps_bind_group_layout],
+            push_constant_ranges: &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::FRAGMENT,
+                range: 0..std::mem::size_of::<Constants>() as u32,
+            }],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Volume Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: *target_format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth24Plus,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multiview: None,
+            multisample: wgpu::MultisampleState::default(),
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout: Arc::new(bind_group_layout),
+            color_maps_bind_group,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resampling() {
+        // Four A scans of eight samples, each with its own value
+        let b_scan = DMatrix::from_fn(8, 4, |_, a_scan| (a_scan as u8 + 1) * 10);
+        let slice = resample_b_scan(b_scan.as_view(), 16);
+        let pixel = |x: usize, y: usize| slice[y * 16 + x];
+
+        // Outside of the A scans
+        assert_eq!(pixel(0, 0), 0);
+        assert_eq!(pixel(15, 15), 0);
+
+        // The A scans go around, like in the cartesian view
+        assert_eq!(pixel(4, 4), 10);
+        assert_eq!(pixel(4, 11), 20);
+        assert_eq!(pixel(11, 11), 30);
+        assert_eq!(pixel(11, 4), 40);
+    }
+
+    /// Pushes an M scan of `a_scan_count` A scans, whose values are their
+    /// index, in `chunk` sized pieces, with boundaries every `b_scan` A
+    /// scans. Returns the first A scan of every B scan handed out with its
+    /// layers and the most A scans kept at a time.
+    fn build(
+        resolution: usize,
+        a_scan_count: usize,
+        b_scan: usize,
+        chunk: usize,
+    ) -> (Vec<(Range<usize>, u8)>, usize) {
+        let mut builder = VolumeBuilder::new(resolution, a_scan_count);
+        for boundary in (0..=a_scan_count).step_by(b_scan) {
+            builder.push_boundary(boundary);
+        }
+
+        let mut b_scans = Vec::new();
+        let mut most_pending = 0;
+        for start in (0..a_scan_count).step_by(chunk) {
+            let len = chunk.min(a_scan_count - start);
+            builder
+                .push_a_scans(DMatrix::from_fn(2, len, |_, i| (start + i) as u8))
+                .unwrap();
+            most_pending = most_pending.max(builder.pending.ncols());
+
+            for (layers, b_scan) in builder.take_b_scans() {
+                assert_eq!(b_scan.shape(), (2, 10));
+                b_scans.push((layers, b_scan[(0, 0)]));
+            }
+        }
+        (b_scans, most_pending)
+    }
+
+    #[test]
+    fn layers_of_b_scans() {
+        // More B scans than layers: Every layer gets its first B scan
+        let (b_scans, most_pending) = build(4, 100, 10, 7);
+        assert_eq!(b_scans, [(0..1, 0), (1..2, 30), (2..3, 50), (3..4, 80)],);
+        assert!(most_pending <= 10 + 7, "{} A scans kept", most_pending);
+
+        // Fewer B scans than layers: B scans fill several layers
+        let (b_scans, _) = build(8, 30, 10, 30);
+        assert_eq!(b_scans, [(0..2, 0), (2..5, 10), (5..8, 20)]);
+    }
+
+    #[test]
+    fn boundaries_after_a_scans() {
+        let mut builder = VolumeBuilder::new(2, 20);
+        builder
+            .push_a_scans(DMatrix::from_fn(2, 20, |_, i| i as u8))
+            .unwrap();
+        assert!(builder.take_b_scans().is_empty());
+
+        builder.push_boundary(0);
+        builder.push_boundary(10);
+        let b_scans = builder.take_b_scans();
+        assert_eq!(b_scans.len(), 1);
+        assert_eq!(b_scans[0].0, 0..1);
+        assert_eq!(builder.pending_start, 10);
+        assert_eq!(builder.pending.ncols(), 10);
+
+        builder.push_boundary(20);
+        let b_scans = builder.take_b_scans();
+        assert_eq!(b_scans[0].0, 1..2);
+        assert_eq!(b_scans[0].1[(1, 0)], 10);
+
+        assert!(builder
+            .push_a_scans(DMatrix::from_fn(3, 1, |_, _| 0))
+            .is_err());
+    }
+}
@@ -0,0 +1,99 @@
+@group(0) @binding(0)
+var volume: texture_3d<f32>;
+
+@group(0) @binding(1)
+var volume_sampler: sampler;
+
+@group(1) @binding(0)
+var color_maps: texture_storage_2d<rgba8unorm, read>;
+
+struct Constants {
+    inverse_mvp: mat4x4<f32>,
+    camera: vec3<f32>,
+    /// The volume spans -1..1 across and -half_length..half_length along the
+    /// pullback.
+    half_length: f32,
+    /// Samples along the diagonal of the volume.
+    steps: u32,
+    map_idx: u32,
+    /// Opacity of the brightest value for a ray crossing the whole volume.
+    opacity: f32,
+    /// Values below are transparent.
+    threshold: f32,
+};
+
+var<push_constant> consts: Constants;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// Fullscreen triangle
+@vertex
+fn vs_main(@builtin(vertex_index) idx: u32) -> VertexOut {
+    let ndc = vec2<f32>(f32(idx / 2u) * 4.0 - 1.0, f32(idx % 2u) * 4.0 - 1.0);
+
+    var out: VertexOut;
+    out.position = vec4<f32>(ndc, 0.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    let far = consts.inverse_mvp * vec4<f32>(in.ndc, 1.0, 1.0);
+    let origin = consts.camera;
+    let dir = normalize(far.xyz / far.w - origin);
+
+    let box_max = vec3<f32>(1.0, 1.0, consts.half_length);
+    let box_min = -box_max;
+
+    // Slab intersection with the volume
+    let t0 = (box_min - origin) / dir;
+    let t1 = (box_max - origin) / dir;
+    let t_enter = max(max(min(t0.x, t1.x), min(t0.y, t1.y)), max(min(t0.z, t1.z), 0.0));
+    let t_exit = min(min(max(t0.x, t1.x), max(t0.y, t1.y)), max(t0.z, t1.z));
+
+    if (t_exit <= t_enter) {
+        discard;
+    }
+
+    let step = length(box_max - box_min) / f32(consts.steps);
+    // Keeps the opacity of a ray independent of the step count
+    let exponent = 1.0 / f32(consts.steps);
+
+    var color = vec3<f32>(0.0);
+    var alpha = 0.0;
+
+    // Front to back compositing
+    for (var t = t_enter + 0.5 * step; t < t_exit && alpha < 0.99; t += step) {
+        let pos = origin + dir * t;
+        let uvw = (pos - box_min) / (box_max - box_min);
+        let value = textureSampleLevel(volume, volume_sampler, uvw, 0.0).r;
+
+        let opacity = consts.opacity * smoothstep(consts.threshold, 1.0, value);
+        let sample_alpha = 1.0 - pow(1.0 - min(opacity, 0.999), exponent);
+
+        color += (1.0 - alpha) * sample_alpha * sample_color_map(value, consts.map_idx).rgb;
+        alpha += (1.0 - alpha) * sample_alpha;
+    }
+
+    // Premultiplied
+    return vec4<f32>(color, alpha);
+}
+
+fn sample_color_map(value: f32, map_idx: u32) -> vec4<f32> {
+    let dims = textureDimensions(color_maps);
+
+    if (map_idx >= dims.y) {
+        return vec4<f32>(1.0, 0.0, 1.0, 1.0);
+    }
+
+    let col_idx = clamp(value, 0.0, 1.0) * f32(dims.x - 1);
+
+    let lower = textureLoad(color_maps, vec2<u32>(u32(floor(col_idx)), map_idx));
+    let upper = textureLoad(color_maps, vec2<u32>(u32(ceil(col_idx)), map_idx));
+
+    return mix(lower, upper, fract(col_idx));
+}
@@ -10,10 +10,7 @@ use crate::{
     pipeline::{self, Pipeline},
 };
 
-use super::{
-    views::{DynDataView, Existence},
-    DataView, DataViewsState, ViewId,
-};
+use super::{views::DynDataView, DataView, DataViewsState, ViewId};
 
 pub struct DataViewsManagerBuilder<'a> {
     view_factories: Vec<
@@ -87,25 +84,9 @@ impl DataViewsManager {
             })
         });
 
-        // Disconnect from removed nodes
-        let mut to_destroy = Vec::new();
-        for (view_id, view) in state.views.iter_mut() {
-            for (input_id, node_id) in view
-                .inputs()
-                .iter()
-                .filter_map(|(id, out)| out.map(|o| (*id, o.node_id)))
-            {
-                if !pipeline.nodes.contains_key(&node_id) {
-                    if let Existence::Destroy = view.disconnect(input_id) {
-                        to_destroy.push(*view_id);
-                        break;
-                    }
-                }
-            }
-        }
-        for view_id in to_destroy {
-            state.views.remove(&view_id);
-        }
+        // Keep views of removed nodes detached and reattach them to recreated
+        // nodes
+        state.update_sources(pipeline);
 
         // Track last focused view
         if let Some((_, TabType::DataView(view_id))) = dock_state.find_active_focused() {
@@ -161,12 +142,22 @@ impl DataViewsManager {
         render_state: &RenderState,
     ) {
         if let Some(view) = self.create_view(node_output, pipeline, cache, render_state) {
-            let view_id = state.add_view(view);
-
-            dock_state.add_view_tab(view_id);
+            self.open_view(state, dock_state, view);
         }
     }
 
+    /// Adds `view` in a new tab.
+    pub fn open_view(
+        &self,
+        state: &mut DataViewsState,
+        dock_state: &mut DockState,
+        view: Box<dyn DynDataView>,
+    ) {
+        let view_id = state.add_view(view);
+
+        dock_state.add_view_tab(view_id);
+    }
+
     fn create_view(
         &self,
         node_output: &NodeOutput,